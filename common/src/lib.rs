@@ -1,10 +1,16 @@
 use serde::{Deserialize, Serialize};
 
+use std::str::FromStr;
+
 /// The maximum allowed length of a title, in UTF-16 code units
 pub const MAX_TITLE_UTF16_CODEUNITS: usize = 300;
 
+/// The maximum length of an article body that's converted as a single article, in bytes. Longer
+/// articles can be split into a group of parts.
+pub const MAX_ARTICLE_LEN: usize = 100_000;
+
 /// Contains all the metadata about an article
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct ArticleMetadata {
     /// The ID of the article
     pub id: String,
@@ -14,30 +20,1832 @@ pub struct ArticleMetadata {
     pub datetime_added: Option<u64>,
     /// The URL this article was sourced from, if any
     pub source_url: Option<String>,
+    /// The article's author, if known
+    #[serde(default)]
+    pub author: Option<String>,
+    /// The site or publication the article appeared in, if known
+    #[serde(default)]
+    pub publication: Option<String>,
+    /// Whether synthesis failed partway through, so the audio only covers the beginning of the
+    /// article
+    #[serde(default)]
+    pub incomplete: bool,
+    /// The approximate length of the article's audio, in seconds
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+    /// If this article is one part of a longer article that was split up, this says which
+    #[serde(default)]
+    pub group: Option<ArticleGroup>,
+    /// What the article's text is like, if it was analyzed
+    #[serde(default)]
+    pub reading_profile: Option<ReadingProfile>,
+    /// The title of the feed the article was added from, if it was added by a feed subscription
+    #[serde(default)]
+    pub feed: Option<String>,
+    /// The name of the voice the article was read in, if it was recorded
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Where the article's text came from, if it was recorded
+    #[serde(default)]
+    pub source_type: Option<SourceType>,
+    /// The path on this server of the article's lead image, e.g., the photo at the top of a news
+    /// story, if it had one
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Whether the listener thought the article was worth it, if they said
+    #[serde(default)]
+    pub rating: Option<Rating>,
+    /// A hash of the article's words, for recognizing the article when it's added again from
+    /// somewhere else, if it was recorded
+    #[serde(default)]
+    pub text_hash: Option<String>,
+}
+
+/// Whether an article was worth listening to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    /// Thumbs up
+    Up,
+    /// Thumbs down
+    Down,
+}
+
+impl Rating {
+    /// The identifier of this rating. This is also its serialized form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rating::Up => "up",
+            Rating::Down => "down",
+        }
+    }
+
+    /// The emoji this rating is shown as
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            Rating::Up => "👍",
+            Rating::Down => "👎",
+        }
+    }
+}
+
+impl FromStr for Rating {
+    type Err = String;
+
+    /// Parses a rating from its identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Rating::Up, Rating::Down]
+            .into_iter()
+            .find(|r| r.as_str() == s)
+            .ok_or_else(|| format!("unknown rating '{s}'"))
+    }
+}
+
+/// Where an article's text came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceType {
+    /// A web page, fetched from its URL
+    Web,
+    /// Text that was pasted in
+    Pasted,
+    /// An uploaded plain text file
+    Txt,
+    /// A chapter of an uploaded EPUB
+    Epub,
+    /// An uploaded PDF's text layer
+    Pdf,
+    /// An uploaded Word document
+    Docx,
+}
+
+impl SourceType {
+    /// All the source types
+    pub const ALL: [SourceType; 6] = [
+        SourceType::Web,
+        SourceType::Pasted,
+        SourceType::Txt,
+        SourceType::Epub,
+        SourceType::Pdf,
+        SourceType::Docx,
+    ];
+
+    /// The identifier of this source type. This is also its serialized form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceType::Web => "web",
+            SourceType::Pasted => "pasted",
+            SourceType::Txt => "txt",
+            SourceType::Epub => "epub",
+            SourceType::Pdf => "pdf",
+            SourceType::Docx => "docx",
+        }
+    }
+
+    /// A human-readable description of this source type
+    pub fn description(&self) -> &'static str {
+        match self {
+            SourceType::Web => "Web page",
+            SourceType::Pasted => "Pasted text",
+            SourceType::Txt => "Text file",
+            SourceType::Epub => "EPUB chapter",
+            SourceType::Pdf => "PDF",
+            SourceType::Docx => "Word document",
+        }
+    }
+}
+
+impl FromStr for SourceType {
+    type Err = String;
+
+    /// Parses a source type from its identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SourceType::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| format!("unknown source type '{s}'"))
+    }
+}
+
+/// The text of an uploaded document, split into the sections it can be added as, e.g., the chapters
+/// of an EPUB. Most documents have one section.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParsedDocument {
+    /// What kind of document it is
+    pub source_type: SourceType,
+    pub sections: Vec<DocumentSection>,
+}
+
+/// A section of an uploaded document
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DocumentSection {
+    pub title: String,
+    /// The section's text, one paragraph per line
+    pub body: String,
+}
+
+/// What an article's text is like to listen to. Articles that are alike are likely listened to at
+/// alike speeds, so this is used to suggest a playback speed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReadingProfile {
+    /// The language the article is in, as an ISO 639-1 code like `en`, if it could be detected
+    pub language: Option<String>,
+    /// How hard the text is to follow, from 0 (plain) to 1 (dense). It's based on the lengths of
+    /// the words and sentences.
+    pub complexity: f32,
+}
+
+/// Identifies the group an article belongs to, and where in the group it goes
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArticleGroup {
+    /// The ID of the group. Every part of the same article has the same group ID.
+    pub id: String,
+    /// The title of the whole article
+    pub title: String,
+    /// The number of this part, starting at 1
+    pub part: u32,
+    /// The number of parts in the group
+    pub parts: u32,
 }
 
 /// A library catalog is a list of article metadata
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LibraryCatalog(pub Vec<ArticleMetadata>);
 
-/// The request type for when the client sends the raw text of the article they want converted
+/// What changed in the library since a given time, so a saved copy of the catalog can be brought up
+/// to date without fetching all of it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LibraryDelta {
+    /// The articles that were added or changed since then
+    pub changed: Vec<ArticleMetadata>,
+    /// The ID of every article in the library. Saved articles that aren't in here were deleted.
+    pub ids: Vec<String>,
+    /// When this was put together, in seconds since the Unix epoch. This is the time to ask for the
+    /// next changes since.
+    pub as_of: u64,
+}
+
+/// An article whose text matches a full-text search
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    /// The ID of the article
+    pub id: String,
+    /// The title of the article
+    pub title: String,
+    /// Passages of the article's body that contain matches, in the order they appear
+    pub snippets: Vec<SearchSnippet>,
+}
+
+/// A passage of an article's body around one or more matches. All offsets are in characters
+/// (Unicode scalar values) from the start of the body.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchSnippet {
+    /// The text of the passage
+    pub text: String,
+    /// Where the passage starts in the body
+    pub start: usize,
+    /// The start and end of every match in the passage
+    pub matches: Vec<(usize, usize)>,
+}
+
+/// The results of a full-text search, best match first
 #[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResults(pub Vec<SearchHit>);
+
+/// The point in an article's audio where a passage of its text starts being spoken
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Timepoint {
+    /// Where the passage starts in the text, in characters (Unicode scalar values)
+    pub offset: usize,
+    /// When the passage starts in the audio, in seconds
+    pub secs: f64,
+    /// Whether the passage is a heading, i.e., starts a section of the article
+    #[serde(default)]
+    pub heading: bool,
+}
+
+/// The text of an article that's been spoken, and where in the audio it's spoken
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArticleTranscript {
+    /// The text that the audio covers. This is the title and body, or the beginning of them if the
+    /// article is incomplete.
+    pub text: String,
+    /// The start of every paragraph of the text, in order
+    pub timepoints: Vec<Timepoint>,
+    /// The length of the audio, in seconds
+    pub duration_secs: f64,
+    /// How loud the audio is over time, for drawing its waveform: one level from 0 (silence) to
+    /// 255 for every `LOUDNESS_STEP_SECS` of audio. Empty for articles converted before it was
+    /// kept.
+    #[serde(default)]
+    pub loudness: Vec<u8>,
+}
+
+/// How many seconds of audio each level of an `ArticleTranscript`'s loudness covers
+pub const LOUDNESS_STEP_SECS: f64 = 0.5;
+
+/// A format the text of an article can be exported in, for quoting it elsewhere
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextExportFormat {
+    /// Markdown, with the title and section headings as headings
+    #[default]
+    Markdown,
+    /// A standalone HTML page
+    Html,
+}
+
+impl TextExportFormat {
+    /// All the formats, in the order they should be displayed
+    pub const ALL: [TextExportFormat; 2] = [TextExportFormat::Markdown, TextExportFormat::Html];
+
+    /// The identifier of this format. This is also its serialized form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextExportFormat::Markdown => "markdown",
+            TextExportFormat::Html => "html",
+        }
+    }
+
+    /// A human-readable description of this format
+    pub fn description(&self) -> &'static str {
+        match self {
+            TextExportFormat::Markdown => "Markdown",
+            TextExportFormat::Html => "HTML",
+        }
+    }
+
+    /// The file extension of this format, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TextExportFormat::Markdown => "md",
+            TextExportFormat::Html => "html",
+        }
+    }
+
+    /// The MIME type of this format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            TextExportFormat::Markdown => "text/markdown; charset=utf-8",
+            TextExportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
+impl FromStr for TextExportFormat {
+    type Err = String;
+
+    /// Parses a format from its identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TextExportFormat::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| format!("unknown export format '{s}'"))
+    }
+}
+
+/// A format the library's catalog can be exported in. A catalog lists every article's URL, title,
+/// playlists, and how far it's been listened to, without the audio, so it's small enough to keep as
+/// a backup, and importing it converts the articles again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CatalogFormat {
+    /// A JSON object with the list of articles under `articles`
+    #[default]
+    Json,
+    /// A CSV file with a header row, e.g., for spreadsheets
+    Csv,
+}
+
+impl CatalogFormat {
+    /// All the formats, in the order they should be displayed
+    pub const ALL: [CatalogFormat; 2] = [CatalogFormat::Json, CatalogFormat::Csv];
+
+    /// The identifier of this format. This is also its serialized form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CatalogFormat::Json => "json",
+            CatalogFormat::Csv => "csv",
+        }
+    }
+
+    /// A human-readable description of this format
+    pub fn description(&self) -> &'static str {
+        match self {
+            CatalogFormat::Json => "JSON",
+            CatalogFormat::Csv => "CSV",
+        }
+    }
+
+    /// The MIME type of this format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            CatalogFormat::Json => "application/json",
+            CatalogFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+impl FromStr for CatalogFormat {
+    type Err = String;
+
+    /// Parses a format from its identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CatalogFormat::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| format!("unknown catalog format '{s}'"))
+    }
+}
+
+/// Escapes the given text for HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escapes the given text so Markdown shows it as it is
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    // Things that only mean something at the start of a line
+    if escaped.starts_with(['#', '>', '-', '+']) {
+        escaped.insert(0, '\\');
+    }
+    escaped
+}
+
+impl ArticleTranscript {
+    /// Returns the paragraphs of the text, and whether each is a heading. The title, which the
+    /// text starts with, is left out if it's the given one.
+    pub fn paragraphs(&self, title: &str) -> Vec<(&str, bool)> {
+        let mut paragraphs = Vec::new();
+        let mut start = 0;
+        for line in self.text.split('\n') {
+            let end = start + line.chars().count();
+            let heading = self
+                .timepoints
+                .iter()
+                .any(|tp| tp.heading && (start..end).contains(&tp.offset));
+            if !line.trim().is_empty() {
+                paragraphs.push((line.trim(), heading));
+            }
+            start = end + 1;
+        }
+        if paragraphs.first().is_some_and(|(p, _)| *p == title.trim()) {
+            paragraphs.remove(0);
+        }
+        paragraphs
+    }
+
+    /// Returns the text of the given article, with its title, byline, and source link, in the
+    /// given format
+    pub fn export(&self, meta: &ArticleMetadata, format: TextExportFormat) -> String {
+        let byline = [
+            meta.author.as_ref().map(|a| format!("By {a}")),
+            meta.publication.as_ref().map(|p| format!("From {p}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" · ");
+        let paragraphs = self.paragraphs(&meta.title);
+
+        let mut out = String::new();
+        match format {
+            TextExportFormat::Markdown => {
+                out.push_str(&format!("# {}\n\n", escape_markdown(&meta.title)));
+                if !byline.is_empty() {
+                    out.push_str(&format!("*{}*\n\n", escape_markdown(&byline)));
+                }
+                if let Some(url) = &meta.source_url {
+                    out.push_str(&format!("Source: <{url}>\n\n"));
+                }
+                for (paragraph, heading) in paragraphs {
+                    let prefix = if heading { "## " } else { "" };
+                    out.push_str(&format!("{prefix}{}\n\n", escape_markdown(paragraph)));
+                }
+                out.truncate(out.trim_end().len());
+                out.push('\n');
+            }
+            TextExportFormat::Html => {
+                let title = escape_html(&meta.title);
+                out.push_str(&format!(
+                    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                     <title>{title}</title>\n</head>\n<body>\n<article>\n<h1>{title}</h1>\n"
+                ));
+                if !byline.is_empty() {
+                    out.push_str(&format!("<p><em>{}</em></p>\n", escape_html(&byline)));
+                }
+                if let Some(url) = &meta.source_url {
+                    let url = escape_html(url);
+                    out.push_str(&format!("<p>Source: <a href=\"{url}\">{url}</a></p>\n"));
+                }
+                for (paragraph, heading) in paragraphs {
+                    let tag = if heading { "h2" } else { "p" };
+                    out.push_str(&format!("<{tag}>{}</{tag}>\n", escape_html(paragraph)));
+                }
+                out.push_str("</article>\n</body>\n</html>\n");
+            }
+        }
+        out
+    }
+}
+
+/// The style an article is read in. Each style is mapped to engine-specific SSML on the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeakingStyle {
+    /// The voice's natural delivery
+    #[default]
+    Neutral,
+    /// Slower and lower. Suited to long-form reading.
+    Narration,
+    /// Brisk and even, like a news anchor
+    Newscast,
+    /// Brighter and livelier
+    Cheerful,
+}
+
+impl SpeakingStyle {
+    /// All the available styles, in the order they should be displayed
+    pub const ALL: [SpeakingStyle; 4] = [
+        SpeakingStyle::Neutral,
+        SpeakingStyle::Narration,
+        SpeakingStyle::Newscast,
+        SpeakingStyle::Cheerful,
+    ];
+
+    /// The identifier of this style. This is also its serialized form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpeakingStyle::Neutral => "neutral",
+            SpeakingStyle::Narration => "narration",
+            SpeakingStyle::Newscast => "newscast",
+            SpeakingStyle::Cheerful => "cheerful",
+        }
+    }
+
+    /// A human-readable description of this style
+    pub fn description(&self) -> &'static str {
+        match self {
+            SpeakingStyle::Neutral => "Neutral",
+            SpeakingStyle::Narration => "Narration (long-form reading)",
+            SpeakingStyle::Newscast => "Newscast",
+            SpeakingStyle::Cheerful => "Cheerful",
+        }
+    }
+}
+
+impl FromStr for SpeakingStyle {
+    type Err = String;
+
+    /// Parses a style from its identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SpeakingStyle::ALL
+            .into_iter()
+            .find(|style| style.as_str() == s)
+            .ok_or_else(|| format!("unknown speaking style '{s}'"))
+    }
+}
+
+/// The default silence between paragraphs, in milliseconds
+pub const DEFAULT_PARAGRAPH_GAP_MS: u32 = 750;
+
+/// The default silence after a heading, in milliseconds
+pub const DEFAULT_HEADING_PAUSE_MS: u32 = 1000;
+
+/// The shortest allowed pause between paragraphs or after a heading, in milliseconds
+pub const MIN_PAUSE_MS: u32 = 300;
+
+/// The longest allowed pause between paragraphs or after a heading, in milliseconds
+pub const MAX_PAUSE_MS: u32 = 1500;
+
+/// What's read before an article's text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frontmatter {
+    /// Nothing. The article starts with its first paragraph.
+    None,
+    /// The title, read as a heading
+    #[default]
+    Title,
+    /// The title, author, publication date, publication, and length, e.g., "Title, by Author,
+    /// published March 2024, from The Atlantic, 28 minutes"
+    Full,
+}
+
+impl Frontmatter {
+    /// All the options, in the order they should be displayed
+    pub const ALL: [Frontmatter; 3] = [Frontmatter::Title, Frontmatter::Full, Frontmatter::None];
+
+    /// The identifier of this option. This is also its serialized form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frontmatter::None => "none",
+            Frontmatter::Title => "title",
+            Frontmatter::Full => "full",
+        }
+    }
+
+    /// A human-readable description of this option
+    pub fn description(&self) -> &'static str {
+        match self {
+            Frontmatter::None => "Nothing",
+            Frontmatter::Title => "The title",
+            Frontmatter::Full => "The title, author, date, publication, and length",
+        }
+    }
+}
+
+impl FromStr for Frontmatter {
+    type Err = String;
+
+    /// Parses an option from its identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Frontmatter::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| format!("unknown frontmatter option '{s}'"))
+    }
+}
+
+/// The sample rates an article's MP3 can have, in Hz
+const MP3_SAMPLE_RATES: [u32; 9] = [8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000];
+
+/// The bitrates an MP3 can have at sample rates of 32 kHz and up, in kbit/s
+const MP3_HIGH_RATE_BITRATES: [u32; 14] = [
+    32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+
+/// The bitrates an MP3 can have at sample rates below 32 kHz, in kbit/s
+const MP3_LOW_RATE_BITRATES: [u32; 14] =
+    [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// Returns the value in the given list that's closest to the given one
+fn closest(values: &[u32], value: u32) -> u32 {
+    values
+        .iter()
+        .copied()
+        .min_by_key(|v| v.abs_diff(value))
+        .unwrap_or(value)
+}
+
+/// How an article's audio is encoded. The audio is synthesized at 24 kHz, and speech is mono, so
+/// going higher mostly takes up more space. Going lower makes smaller files, at some cost to
+/// clarity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncodingOptions {
+    /// The MP3's sample rate, in Hz
+    pub sample_rate: u32,
+    /// The MP3's bitrate, in kbit/s
+    pub bitrate_kbps: u32,
+    /// Whether the MP3 is stereo rather than mono. Both channels are the same.
+    pub stereo: bool,
+}
+
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        EncodingOptions {
+            sample_rate: 24000,
+            bitrate_kbps: 64,
+            stereo: false,
+        }
+    }
+}
+
+impl EncodingOptions {
+    /// Returns these options with the sample rate and bitrate moved to the closest ones an MP3 can
+    /// have. Which bitrates are allowed depends on the sample rate.
+    pub fn clamped(&self) -> EncodingOptions {
+        let sample_rate = closest(&MP3_SAMPLE_RATES, self.sample_rate);
+        let bitrates = if sample_rate >= 32000 {
+            &MP3_HIGH_RATE_BITRATES
+        } else {
+            &MP3_LOW_RATE_BITRATES
+        };
+        EncodingOptions {
+            sample_rate,
+            bitrate_kbps: closest(bitrates, self.bitrate_kbps),
+            stereo: self.stereo,
+        }
+    }
+}
+
+/// Options that control how the audio of an article is produced
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SynthesisOptions {
+    /// The silence between paragraphs, in milliseconds
+    pub paragraph_gap_ms: u32,
+    /// The silence after a heading, in milliseconds
+    pub heading_pause_ms: u32,
+    /// The name of the ambient bed to mix under the speech, if any
+    pub ambient_bed: Option<String>,
+    /// Experimental. Whether to read parenthetical asides and bracketed footnotes more quietly
+    pub soften_asides: bool,
+    /// What's read before the article's text
+    pub frontmatter: Frontmatter,
+    /// Whether to say that the article has ended after its text
+    pub outro: bool,
+    /// Whether to follow the article with a short spoken summary of its key points, as a chapter
+    /// of its own. This only happens if the server has a language model to pick them.
+    pub key_points: bool,
+    /// How to encode the audio. If this isn't set, the server's default is used.
+    pub encoding: Option<EncodingOptions>,
+}
+
+impl Default for SynthesisOptions {
+    fn default() -> Self {
+        SynthesisOptions {
+            paragraph_gap_ms: DEFAULT_PARAGRAPH_GAP_MS,
+            heading_pause_ms: DEFAULT_HEADING_PAUSE_MS,
+            ambient_bed: None,
+            soften_asides: false,
+            frontmatter: Frontmatter::default(),
+            outro: false,
+            key_points: false,
+            encoding: None,
+        }
+    }
+}
+
+impl SynthesisOptions {
+    /// Returns these options with every value clamped to its allowed range
+    pub fn clamped(&self) -> SynthesisOptions {
+        SynthesisOptions {
+            paragraph_gap_ms: self.paragraph_gap_ms.clamp(MIN_PAUSE_MS, MAX_PAUSE_MS),
+            heading_pause_ms: self.heading_pause_ms.clamp(MIN_PAUSE_MS, MAX_PAUSE_MS),
+            ambient_bed: self.ambient_bed.clone(),
+            soften_asides: self.soften_asides,
+            frontmatter: self.frontmatter,
+            outro: self.outro,
+            key_points: self.key_points,
+            encoding: self.encoding.map(|e| e.clamped()),
+        }
+    }
+}
+
+/// Describes a background audio track that can be mixed under an article
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AmbientBedInfo {
+    /// The bed's name. This is how the bed is referred to in `SynthesisOptions`.
+    pub name: String,
+    /// A human-readable description of the bed
+    pub description: String,
+}
+
+/// The request type for when the client sends the raw text of the article they want converted
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ArticleTextSubmission {
     pub title: String,
     pub body: String,
+    /// The style to read the article in
+    #[serde(default)]
+    pub style: SpeakingStyle,
+    /// How to produce the article's audio
+    #[serde(default)]
+    pub options: SynthesisOptions,
+    /// Whether to split the article into a group of parts if it's longer than `MAX_ARTICLE_LEN`.
+    /// If this isn't set, overly long articles are rejected with a `SplitOffer`.
+    #[serde(default)]
+    pub split: bool,
+    /// The name of the voice to read the article in. If this isn't set, the voice is picked to
+    /// match the article's language.
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Where the text came from. If this isn't set, it was pasted in.
+    #[serde(default)]
+    pub source_type: Option<SourceType>,
+    /// Whether to respond as soon as synthesis begins, with a `JobStarted`, rather than waiting for
+    /// the conversion to finish. The job's progress is at `/api/jobs/{id}`.
+    #[serde(default)]
+    pub background: bool,
+    /// Whether to convert the article even if it's already in the library. If this isn't set, such
+    /// articles are rejected with the article that's already there.
+    #[serde(default)]
+    pub force: bool,
 }
 
 impl ArticleTextSubmission {
     /// Converts this submission into its serialized string form
     pub fn serialize(&self) -> String {
-        // TODO: Internationalize this to use the correct stop character for the given language
-        // Include the title at the top of the article.
-        format!("{}. {}", self.title, self.body)
+        // Include the title at the top of the article, on its own line so that it's read as a
+        // heading
+        format!("{}\n{}", self.title, self.body)
     }
 }
 
 /// The request type for when the client sends just the article's URL
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ArticleUrlSubmission {
     pub url: String,
+    /// The style to read the article in
+    #[serde(default)]
+    pub style: SpeakingStyle,
+    /// How to produce the article's audio
+    #[serde(default)]
+    pub options: SynthesisOptions,
+    /// Whether to split the article into a group of parts if it's longer than `MAX_ARTICLE_LEN`.
+    /// If this isn't set, overly long articles are rejected with a `SplitOffer`.
+    #[serde(default)]
+    pub split: bool,
+    /// A CSS selector for the element that contains the article's body. This overrides both
+    /// automatic extraction and any rule saved for the article's site.
+    #[serde(default)]
+    pub selector: Option<String>,
+    /// Whether to save `selector` as the rule for the article's site, so that it's used for every
+    /// article from the site from now on
+    #[serde(default)]
+    pub save_selector: bool,
+    /// Whether to convert the automatically extracted text even if the extractor is unsure of it.
+    /// If this isn't set, such articles are rejected with an `ExtractionChoice`.
+    #[serde(default)]
+    pub trust_extraction: bool,
+    /// The extraction strategy to use, e.g., the one that was previewed. If this isn't set, the
+    /// strategies are tried in turn until one finds something that looks like an article.
+    #[serde(default)]
+    pub strategy: Option<ExtractionStrategy>,
+    /// The name of the voice to read the article in. If this isn't set, the voice is picked to
+    /// match the article's language.
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Whether to respond as soon as synthesis begins, with a `JobStarted`, rather than waiting for
+    /// the conversion to finish. The job's progress is at `/api/jobs/{id}`.
+    #[serde(default)]
+    pub background: bool,
+    /// Whether to convert the article even if it's already in the library. If this isn't set, such
+    /// articles are rejected with the article that's already there.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// A way of finding the article's text on a fetched page
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractionStrategy {
+    /// The part of the page with the most prose, the way Firefox's Reader View finds it
+    Readability,
+    /// Trafilatura's heuristics
+    Trafilatura,
+    /// The AMP version of the page, which is a stripped-down copy of the article
+    Amp,
+    /// The part of the page matching a CSS selector, given by the user or saved for the site
+    Selector,
+    /// The site's extractor plugin
+    Plugin,
+}
+
+impl ExtractionStrategy {
+    /// The automatic strategies, in the order they're tried
+    pub const AUTOMATIC: [ExtractionStrategy; 3] = [
+        ExtractionStrategy::Readability,
+        ExtractionStrategy::Trafilatura,
+        ExtractionStrategy::Amp,
+    ];
+
+    /// The identifier of this strategy. This is also its serialized form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExtractionStrategy::Readability => "readability",
+            ExtractionStrategy::Trafilatura => "trafilatura",
+            ExtractionStrategy::Amp => "amp",
+            ExtractionStrategy::Selector => "selector",
+            ExtractionStrategy::Plugin => "plugin",
+        }
+    }
+
+    /// A human-readable description of this strategy
+    pub fn description(&self) -> &'static str {
+        match self {
+            ExtractionStrategy::Readability => "Reader view",
+            ExtractionStrategy::Trafilatura => "Text heuristics",
+            ExtractionStrategy::Amp => "The page's AMP version",
+            ExtractionStrategy::Selector => "A CSS selector",
+            ExtractionStrategy::Plugin => "The site's extractor plugin",
+        }
+    }
+}
+
+impl FromStr for ExtractionStrategy {
+    type Err = String;
+
+    /// Parses a strategy from its identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [ExtractionStrategy::Selector, ExtractionStrategy::Plugin]
+            .into_iter()
+            .chain(ExtractionStrategy::AUTOMATIC)
+            .find(|strategy| strategy.as_str() == s)
+            .ok_or_else(|| format!("unknown extraction strategy '{s}'"))
+    }
+}
+
+/// What would be read aloud if a URL submission were converted. The client shows it so the user can
+/// check it before spending any TTS on it, and resubmits with `strategy` and `trust_extraction`
+/// set to convert exactly this.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionPreview {
+    pub title: String,
+    /// The article's text, one paragraph per line
+    pub text: String,
+    /// The number of words in the text
+    pub words: usize,
+    /// The strategy that found the text
+    pub strategy: ExtractionStrategy,
+    /// Whether the text looks like an article. If no strategy found one, this is the best of what
+    /// they found.
+    pub looks_like_article: bool,
+    /// Other parts of the page that might be the article, to try instead
+    pub alternatives: Vec<ExtractionCandidate>,
+}
+
+/// A part of a fetched page that might be the body of the article
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionCandidate {
+    /// The CSS selector of the element containing the text. This is `None` for the text that the
+    /// extractor picked on its own.
+    pub selector: Option<String>,
+    /// The beginning of the text
+    pub preview: String,
+    /// The number of words in the text
+    pub words: usize,
+}
+
+/// The response to a URL submission whose body the extractor is unsure of. The client picks one of
+/// the candidates and resubmits with `selector` or `trust_extraction` set.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionChoice {
+    /// How sure the extractor is of its pick, from 0 to 1
+    pub confidence: f64,
+    /// The extractor's pick, followed by the alternatives
+    pub candidates: Vec<ExtractionCandidate>,
+}
+
+/// The response to a submission that's too long to convert as a single article. It says how the
+/// article would be split up, so the client can resubmit with `split` set.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SplitOffer {
+    /// The number of words in the article
+    pub words: usize,
+    /// The number of parts the article would be split into
+    pub parts: usize,
+}
+
+/// The request type for when the client wants to synthesize the rest of an incomplete article
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FinishArticleSubmission {
+    /// The ID of the incomplete article
+    pub id: String,
+}
+
+/// The request type for when the client wants to re-run text extraction on the archived HTML of an
+/// article
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReExtractSubmission {
+    /// The ID of the article to re-extract
+    pub id: String,
+    /// Whether to replace the article's audio with a synthesis of the newly extracted text
+    pub resynthesize: bool,
+}
+
+/// The request type for deleting articles from the library
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArticleDeletion {
+    /// The IDs of the articles to delete
+    pub ids: Vec<String>,
+}
+
+/// The request type for rating an article
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArticleRating {
+    /// The ID of the article
+    pub id: String,
+    /// The rating to give it, or `None` to take its rating away
+    pub rating: Option<Rating>,
+}
+
+/// An article's audio as it's stored on the server, for admins managing the server's disk
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StoredAudio {
+    /// The account whose library the article is in, or `None` for the shared library
+    pub library: Option<String>,
+    /// The ID of the article
+    pub id: String,
+    /// The title of the article
+    pub title: String,
+    /// The size of the article's MP3, in bytes
+    pub bytes: u64,
+    /// When the audio was last fetched, in seconds since the Unix epoch. If it hasn't been fetched
+    /// since the server started keeping track, this is when it was made.
+    pub last_fetched: u64,
+}
+
+/// How much space the audio on the server takes up. This is what admins get when they ask.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioUsage {
+    /// The most bytes the audio can take up before the least recently fetched is deleted, if the
+    /// server has a limit
+    pub max_bytes: Option<u64>,
+    /// How many bytes the transcoded copies of the audio take up
+    pub transcoded_bytes: u64,
+    /// Every article's audio, in every library
+    pub articles: Vec<StoredAudio>,
+}
+
+/// The request type for an admin deleting an article from any library
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminDeletion {
+    /// The account whose library the article is in, or `None` for the shared library
+    pub library: Option<String>,
+    /// The ID of the article
+    pub id: String,
+}
+
+/// A page in the server's cache of fetched pages
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CachedPage {
+    /// The URL the page was fetched from
+    pub url: String,
+    /// The size of the page's HTML, in bytes
+    pub bytes: u64,
+    /// When the page was fetched, in seconds since the Unix epoch
+    pub fetched: u64,
+}
+
+/// The response type for an admin listing the server's cache of fetched pages
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageCacheUsage {
+    /// How long a page is used for before it's fetched again, in seconds. If this is 0, pages
+    /// aren't cached.
+    pub ttl_secs: u64,
+    /// Every cached page, including the ones that have expired but haven't been deleted yet
+    pub pages: Vec<CachedPage>,
+}
+
+/// The request type for an admin clearing the server's cache of fetched pages
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageCacheClearing {
+    /// The URL of the page to delete from the cache. If this is `None`, every page is deleted.
+    pub url: Option<String>,
+}
+
+/// An article in the encrypted library. It was encrypted on the device that added it, with a key
+/// that never leaves that device, so the server only has ciphertext.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedArticleInfo {
+    /// The ID the server gave the article
+    pub id: String,
+    /// The article's encrypted metadata, e.g., its title
+    pub meta: Vec<u8>,
+    /// When the article was uploaded, in seconds since the Unix epoch
+    pub uploaded: u64,
+    /// The size of the encrypted article, in bytes
+    pub size: u64,
+}
+
+/// The response to a re-extraction request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReExtractResponse {
+    /// The ID of the article. This changes if the article was resynthesized with different text
+    pub id: String,
+    /// The newly extracted title
+    pub title: String,
+    /// The newly extracted body
+    pub body: String,
+}
+
+/// The maximum length of a voice preview, in characters
+pub const MAX_PREVIEW_CHARS: usize = 500;
+
+/// Describes a TTS voice offered by the server
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VoiceInfo {
+    /// The voice's name, e.g., `en-US-Wavenet-C`. This is how the voice is referred to in requests.
+    pub name: String,
+    /// A human-readable description of the voice
+    pub description: String,
+    /// The language the voice speaks, e.g., `en-US`, if it's known
+    #[serde(default)]
+    pub language_code: Option<String>,
+    /// Whether this is a custom voice, i.e., one that was unlocked with a voice key
+    #[serde(default)]
+    pub custom: bool,
+}
+
+/// The request type for when the client wants to hear a short sample of text in a given voice
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VoicePreviewSubmission {
+    /// The text to speak. At most `MAX_PREVIEW_CHARS` characters.
+    pub text: String,
+    /// The name of the voice to speak it in
+    pub voice: String,
+}
+
+/// The request type for when the client asks a question about an article. The client sends the
+/// article's text, so articles the server no longer has can be asked about too.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArticleQuestion {
+    /// The title of the article
+    pub title: String,
+    /// The text of the article, as in its transcript
+    pub text: String,
+    /// The question
+    pub question: String,
+}
+
+/// The answer to an `ArticleQuestion`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArticleAnswer {
+    pub answer: String,
+}
+
+/// The state of a conversion job
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// The job is synthesizing
+    Running,
+    /// The job was paused. No new TTS requests are made until it's resumed.
+    Paused,
+    /// The job was cancelled. Nothing it synthesized is kept.
+    Cancelled,
+    /// The job finished and its article is in the library
+    Done,
+    /// The job stopped because of an error
+    Failed,
+}
+
+impl JobStatus {
+    /// Returns whether the job is over, i.e., can no longer be paused, resumed, or cancelled
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Running | JobStatus::Paused)
+    }
+}
+
+/// How urgently a job should be done. When there's more work than the server can do at once, the
+/// TTS requests of higher-priority jobs go first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPriority {
+    /// For work nobody is waiting on
+    Low,
+    /// The default
+    #[default]
+    Normal,
+    /// For the article the user wants to listen to right now
+    High,
+}
+
+/// Describes a conversion job the server is working on, or recently worked on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobInfo {
+    /// The job's ID. This is only unique for the lifetime of the server process.
+    pub id: u64,
+    /// The title of the article being converted
+    pub title: String,
+    /// The state of the job
+    pub status: JobStatus,
+    /// How urgently the job should be done
+    #[serde(default)]
+    pub priority: JobPriority,
+    /// The number of TTS requests that have completed
+    pub chunks_done: usize,
+    /// The total number of TTS requests the job makes. This is 0 until the text has been broken up.
+    pub chunks_total: usize,
+    /// The number of characters synthesized so far
+    #[serde(default)]
+    pub chars_done: usize,
+    /// The total number of characters to synthesize. This is 0 until the text has been broken up.
+    #[serde(default)]
+    pub chars_total: usize,
+    /// The estimated number of seconds until the job is done, if there's enough to go on
+    #[serde(default)]
+    pub eta_secs: Option<u64>,
+    /// The number of chunks of audio that can be previewed. Chunks are numbered from 0.
+    #[serde(default)]
+    pub audio_chunks: usize,
+    /// If the job failed, this is why
+    pub error: Option<String>,
+    /// The ID of the job this one is part of, if any. A submission that's added in the background
+    /// gets a job of its own, and every part it's converted in is a job under that.
+    #[serde(default)]
+    pub parent: Option<u64>,
+    /// The IDs of the articles the job added, once it's done
+    #[serde(default)]
+    pub articles: Vec<String>,
+    /// Whether synthesis of any of the job's articles stopped partway through, once it's done
+    #[serde(default)]
+    pub incomplete: bool,
+}
+
+/// The response type for when a submission is being added in the background
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobStarted {
+    /// The ID of the job adding the submission
+    pub id: u64,
+}
+
+/// The request type for pausing, resuming, or cancelling a job
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobControlSubmission {
+    /// The ID of the job
+    pub id: u64,
+}
+
+/// The request type for changing the priority of a job
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobPrioritySubmission {
+    /// The ID of the job
+    pub id: u64,
+    /// The job's new priority
+    pub priority: JobPriority,
+}
+
+/// A feed the server polls for new articles
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeedSubscription {
+    /// The URL of the RSS or Atom feed
+    pub url: String,
+    /// The feed's title, if it has one
+    pub title: Option<String>,
+    /// When the feed was last checked for new articles, in seconds since the Unix epoch
+    pub last_polled: Option<u64>,
+    /// When the feed was last fetched without an error, in seconds since the Unix epoch
+    pub last_success: Option<u64>,
+    /// How many times in a row fetching the feed has failed. Failing feeds are checked less often.
+    pub error_streak: u32,
+    /// The error fetching the feed failed with last, if it's failing
+    pub last_error: Option<String>,
+}
+
+/// The request type for subscribing to or unsubscribing from a feed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeedSubmission {
+    /// The URL of the RSS or Atom feed
+    pub url: String,
+}
+
+/// An article found in an export from another reader, e.g., Wallabag, Shiori, or Linkding
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImportedArticle {
+    /// The URL of the article
+    pub url: String,
+    /// The title of the article, or its URL if the export didn't have one
+    pub title: String,
+    /// When the article was saved in the other reader, in seconds since the Unix epoch
+    pub datetime_added: Option<u64>,
+    /// The article's text, if the export has it. Articles without text are fetched from their URL.
+    pub text: Option<String>,
+}
+
+/// The request type for converting some of the articles found in an export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportSubmission {
+    /// The articles to convert, in order
+    pub articles: Vec<ImportedArticle>,
+}
+
+/// An article from an import that couldn't be converted
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImportFailure {
+    /// The title of the article
+    pub title: String,
+    /// What went wrong
+    pub error: String,
+}
+
+/// What happened when a library archive was imported
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LibraryImportSummary {
+    /// The number of articles that were added to the library
+    pub imported: usize,
+    /// The number of articles that were skipped because they're already in the library
+    pub skipped: usize,
+}
+
+/// How far along the current (or last) import is
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportProgress {
+    /// Whether the import is still converting articles
+    pub running: bool,
+    /// The number of articles being imported
+    pub total: usize,
+    /// The number of articles that have been dealt with, whether they were converted, skipped, or
+    /// failed
+    pub done: usize,
+    /// The number of articles that were skipped because they're already in the library
+    pub skipped: usize,
+    /// The articles that couldn't be converted
+    pub failed: Vec<ImportFailure>,
+}
+
+/// The speaking rate used to estimate how long an article will take to listen to, in words per
+/// minute
+pub const ESTIMATED_WORDS_PER_MIN: usize = 160;
+
+/// Where an inbox item is on its way to the library
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InboxState {
+    /// Saved for later. Nothing has been spent on it.
+    Saved,
+    /// Waiting its turn to be converted
+    Queued,
+    /// Being converted right now
+    Converting,
+    /// Conversion failed, for the given reason. It can be tried again.
+    Failed(String),
+}
+
+/// An article saved to the inbox. It isn't converted until the listener says so. Once it's
+/// converted, it leaves the inbox and is in the library.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InboxItem {
+    /// The URL of the article
+    pub url: String,
+    /// The title of the article, if it could be fetched when it was saved
+    pub title: Option<String>,
+    /// The number of words in the article, if it could be fetched when it was saved
+    pub words: Option<usize>,
+    /// When the article was saved, in seconds since the Unix epoch
+    pub datetime_saved: u64,
+    /// The tags the article was saved with
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub state: InboxState,
+}
+
+impl InboxItem {
+    /// Returns roughly how long the article will be once it's converted, in minutes
+    pub fn estimated_minutes(&self) -> Option<usize> {
+        self.words
+            .map(|words| words.div_ceil(ESTIMATED_WORDS_PER_MIN))
+    }
+}
+
+/// The request type for saving an article to the inbox
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InboxSubmission {
+    /// The URL of the article
+    pub url: String,
+    /// Tags to save the article with, e.g., to match an auto-convert rule
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// The request type for converting or removing some of the inbox's items
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InboxSelection {
+    /// The URLs of the items
+    pub urls: Vec<String>,
+}
+
+/// A reminder to listen to an article. Once it's due, it's shown at the top of the home view until
+/// it's dismissed or it expires.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Reminder {
+    /// The ID of the article
+    pub article_id: String,
+    /// The title of the article
+    pub title: String,
+    /// When the reminder is due, in seconds since the Unix epoch
+    pub remind_at: u64,
+}
+
+/// The request type for setting a reminder. An article has at most one reminder, so this replaces
+/// any reminder the article already has.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReminderSubmission {
+    /// The ID of the article
+    pub article_id: String,
+    /// The title of the article
+    pub title: String,
+    /// When the reminder is due, in seconds since the Unix epoch
+    pub remind_at: u64,
+}
+
+/// The request type for dismissing the reminder of an article
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReminderDismissal {
+    /// The ID of the article
+    pub article_id: String,
+}
+
+/// Quiet mode, which holds back push notifications for a while. Reminders that come due while it's
+/// on are still shown in the app, but no device is notified of them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuietMode {
+    /// When quiet mode ends, in seconds since the Unix epoch, or `None` if it's off
+    pub until: Option<u64>,
+    /// The quiet hours, if they're set. Setting quiet mode doesn't change them.
+    #[serde(default)]
+    pub hours: Option<QuietHours>,
+}
+
+/// Quiet hours every day, e.g., 22:00 to 07:00. Push notifications of reminders that come due in
+/// them wait until they're over, and so do feed conversions on the server and downloads that
+/// devices resume on their own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// When they start, in minutes after midnight
+    pub start: u16,
+    /// When they end, in minutes after midnight. If it's before the start, they go past midnight.
+    pub end: u16,
+    /// The listener's time zone, as minutes ahead of UTC, e.g., -300 for New York in winter
+    pub utc_offset: i32,
+}
+
+impl QuietHours {
+    /// The number of minutes in a day
+    pub const DAY_MINUTES: u16 = 24 * 60;
+
+    /// Returns whether these are valid quiet hours, i.e., they start and end within a day, in a
+    /// time zone that exists
+    pub fn is_valid(&self) -> bool {
+        self.start < Self::DAY_MINUTES
+            && self.end < Self::DAY_MINUTES
+            && self.utc_offset.abs() <= 14 * 60
+    }
+
+    /// Returns whether the given time, in seconds since the Unix epoch, is in quiet hours. Hours
+    /// that start and end at the same time are never on.
+    pub fn contains(&self, t: u64) -> bool {
+        let minute =
+            (t as i64 / 60 + self.utc_offset as i64).rem_euclid(Self::DAY_MINUTES as i64) as u16;
+        if self.start <= self.end {
+            self.start <= minute && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// The keys of a push subscription
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PushSubscriptionKeys {
+    /// The device's public key, as unpadded URL-safe base64
+    pub p256dh: String,
+    /// The device's auth secret, as unpadded URL-safe base64
+    pub auth: String,
+}
+
+/// A device's subscription to push notifications. This is the JSON form of the browser's
+/// `PushSubscription`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PushSubscription {
+    /// The URL that messages for the device are POSTed to
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+/// A command for the player from another device, e.g., the minimal page on a watch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemoteCommand {
+    /// Plays if the player is paused, and pauses if it's playing
+    TogglePlay,
+    Play,
+    Pause,
+    /// Plays the next article in the queue
+    Next,
+    /// Plays the previous article in the queue
+    Previous,
+    JumpForward,
+    JumpBackward,
+}
+
+/// The response to a request for the remote commands sent to the player
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RemoteCommands {
+    /// The number of the latest command sent. Asking for the commands after it next time gets only
+    /// the new ones.
+    pub latest: u64,
+    /// The commands sent after the number that was asked about, oldest first
+    pub commands: Vec<RemoteCommand>,
+}
+
+/// What the player is doing. The player reports this while remote control is on, so remote
+/// controls can show it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    /// The title of the article that's loaded, if any
+    pub title: Option<String>,
+    /// Whether the audio is playing
+    pub playing: bool,
+    /// How far into the article the audio is, in seconds
+    pub elapsed_secs: f64,
+    /// The length of the article's audio, in seconds. This is 0 if nothing is loaded.
+    pub duration_secs: f64,
+}
+
+/// Who's signed in. This is what the server says when asked about the current session.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountStatus {
+    /// Whether the server has accounts. If it doesn't, everyone shares one library and nobody signs
+    /// in.
+    pub accounts_enabled: bool,
+    /// The name of the account that's signed in, if any
+    pub name: Option<String>,
+    /// Whether the account that's signed in needs a two-factor code to sign in
+    #[serde(default)]
+    pub totp_enabled: bool,
+    /// Whether whoever's asking can manage the server, i.e., see and delete the audio of every
+    /// library. Without accounts, everyone can, unless this is a demo.
+    #[serde(default)]
+    pub is_admin: bool,
+    /// Whether this is a public demo, whose library can't be changed
+    #[serde(default)]
+    pub demo: bool,
+}
+
+/// The request type for signing up or signing in
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Credentials {
+    /// The account name
+    pub name: String,
+    pub password: String,
+    /// The code the server's config requires to sign up, if it requires one. It's ignored when
+    /// signing in.
+    #[serde(default)]
+    pub signup_code: Option<String>,
+    /// The code from the account's authenticator app, if it has two-factor authentication on. It's
+    /// ignored when signing up.
+    #[serde(default)]
+    pub totp_code: Option<String>,
+    /// Whether to return the session token when signing in, for clients on other sites. They can't
+    /// use the session cookie, so they send the token in an `Authorization: Bearer` header instead.
+    #[serde(default)]
+    pub want_token: bool,
+}
+
+/// The response type for signing in with `Credentials::want_token` set
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionToken {
+    pub token: String,
+}
+
+/// The request type for pairing a new device. The code it gets is a QR code of a link to the app
+/// that signs the device in.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PairingRequest {
+    /// The URL the new device can reach the app at, e.g., `http://192.168.1.5:9382` or
+    /// `http://[2001:db8::5]:9382`. This might not be the URL the pairing device uses, e.g., if
+    /// that's `localhost`.
+    pub app_url: String,
+}
+
+/// The response type for pairing a new device
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PairingCode {
+    /// The link the new device opens. With accounts on, it has a one-time code that signs it in.
+    pub url: String,
+    /// The QR code of the link, as an SVG
+    pub qr_svg: String,
+    /// How many seconds the link works for. It's 0 if there's nothing to expire, i.e., accounts
+    /// are off.
+    pub expires_in_secs: u64,
+}
+
+/// The response type for starting to set up two-factor authentication. The secret goes in an
+/// authenticator app, either typed in or as the `otpauth://` URI.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpSetup {
+    /// The secret, in base32
+    pub secret: String,
+    pub uri: String,
+}
+
+/// The request type for turning two-factor authentication on or off, which needs a code from the
+/// authenticator app
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+/// The request type for deleting the account that's signed in, along with its library. It needs
+/// the password, and the two-factor code if that's on, so a device left signed in can't do it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountDeletion {
+    pub password: String,
+    #[serde(default)]
+    pub totp_code: Option<String>,
+}
+
+/// The request type for making a link that shares an article's audio
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareSubmission {
+    /// The ID of the article
+    pub article_id: String,
+    /// The title of the article
+    pub title: String,
+    /// How many days the link works for
+    pub days: u32,
+}
+
+/// A link that shares an article's audio with people who can't sign in
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedLink {
+    /// The ID of the link, which is how it's revoked
+    pub id: String,
+    /// The ID of the article
+    pub article_id: String,
+    /// The title of the article
+    pub title: String,
+    /// The link, relative to the server
+    pub url: String,
+    /// When the link stops working, in seconds since the Unix epoch
+    pub expires: u64,
+    /// Whether this is a link to the library's shared collection, rather than to one article. Then
+    /// `title` is the collection's name, and `article_id` is empty.
+    #[serde(default)]
+    pub collection: bool,
+}
+
+/// The request type for revoking a shared link
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareRevocation {
+    /// The ID of the link
+    pub id: String,
+}
+
+/// The request type for making a link to the library's shared collection, i.e., every article it
+/// has a shared link to. Another server can subscribe to the collection with the link.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionShareSubmission {
+    /// What the collection is called on the servers that subscribe to it
+    pub name: String,
+    /// How many days the link works for
+    pub days: u32,
+}
+
+/// What a link to a shared collection returns: the collection's articles, as shared links
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedCollection {
+    pub name: String,
+    /// The links to the articles, relative to the sharing server
+    pub articles: Vec<SharedLink>,
+    /// When the link to the collection stops working, in seconds since the Unix epoch
+    pub expires: u64,
+}
+
+/// The request type for subscribing to another server's shared collection, or unsubscribing
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionSubscription {
+    /// The link to the collection the other server gave out
+    pub url: String,
+}
+
+/// An article in a shared collection this server is subscribed to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteArticle {
+    /// The article's ID, which it keeps once it's copied
+    pub id: String,
+    pub title: String,
+    /// When the sharing server stops sharing it, in seconds since the Unix epoch
+    pub expires: u64,
+    /// Whether it's been copied to the library already
+    pub in_library: bool,
+}
+
+/// A shared collection this server is subscribed to, as it was just fetched
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteCollection {
+    /// The link to the collection
+    pub url: String,
+    /// The collection's name, as of the last time it could be fetched
+    pub name: String,
+    pub articles: Vec<RemoteArticle>,
+    /// Why the collection couldn't be fetched, e.g., because the link expired or was revoked
+    pub error: Option<String>,
+}
+
+/// The request type for copying an article from a subscribed collection to the library
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RemoteCopyRequest {
+    /// The link to the collection the article is in
+    pub collection_url: String,
+    /// The ID of the article
+    pub article_id: String,
+}
+
+/// A message between two of a listener's devices, relayed by the server so they can connect to each
+/// other directly and pass an article's audio between them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PeerSignal {
+    /// The device that sent the message. Devices make up their IDs, which last as long as the page.
+    pub from: String,
+    /// The device the message is for, or `None` if it's for every other device of the library
+    #[serde(default)]
+    pub to: Option<String>,
+    /// The ID of the article whose audio the connection is for
+    pub article_id: String,
+    pub kind: PeerSignalKind,
+}
+
+/// What a `PeerSignal` says
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "sdp", rename_all = "lowercase")]
+pub enum PeerSignalKind {
+    /// Asks the other devices whether they have the article's audio
+    Want,
+    /// Offers to send the audio, with the sending device's WebRTC session description
+    Offer(String),
+    /// Takes up an offer, with the receiving device's WebRTC session description
+    Answer(String),
+}
+
+/// What's kept in step between a listener's devices: the queue, how far each queued article has
+/// been listened to, and the playback speed. Devices send theirs, and get back what the server has
+/// after merging it in.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncState {
+    /// The queue, in order
+    pub queue: Vec<SyncedQueueEntry>,
+    /// When the queue was last changed, in milliseconds since the Unix epoch. The latest change
+    /// wins. 0 if it's never been changed since syncing began.
+    pub queue_updated: f64,
+    /// How far each queued article has been listened to. The furthest position wins.
+    pub positions: Vec<SyncedPosition>,
+    /// The playback speed, as a multiple of normal speed. 0 if it's unknown.
+    pub playback_speed: f64,
+    /// When the playback speed was last changed, in milliseconds since the Unix epoch. The latest
+    /// change wins.
+    pub speed_updated: f64,
+    /// The playlists, including the deleted ones, so the deletion reaches every device. The latest
+    /// change to each playlist wins.
+    #[serde(default)]
+    pub playlists: Vec<SyncedPlaylist>,
+    /// The device that sent this, if it's a device's state. The server's merge has none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceCheckIn>,
+}
+
+/// Who a device is, sent along with its sync state so it's listed among the library's devices
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceCheckIn {
+    /// The ID of the device. It's made by the device the first time it syncs, and never changes.
+    pub id: String,
+    /// What the device calls itself. This is only taken the first time it syncs, so renaming it
+    /// from another device sticks.
+    pub nickname: String,
+    /// How much audio the device has saved for listening offline, in bytes
+    pub cached_bytes: u64,
+}
+
+/// A device that syncs with the library
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// The ID of the device
+    pub id: String,
+    /// The device's nickname
+    pub nickname: String,
+    /// When the device last synced, in seconds since the Unix epoch
+    pub last_seen: u64,
+    /// How much audio the device had saved for listening offline when it last synced, in bytes
+    pub cached_bytes: u64,
+}
+
+/// The request type for renaming a device
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceRename {
+    /// The ID of the device
+    pub id: String,
+    /// The device's new nickname
+    pub nickname: String,
+}
+
+/// The request type for revoking a device, so it can't sync anymore
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceRevocation {
+    /// The ID of the device
+    pub id: String,
+}
+
+/// A playlist, i.e., a named list of articles kept apart from the queue
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncedPlaylist {
+    /// The ID of the playlist. It's made by the device that made the playlist, and never changes.
+    pub id: String,
+    /// The name of the playlist
+    pub name: String,
+    /// The articles in the playlist, in order
+    pub entries: Vec<SyncedQueueEntry>,
+    /// When the playlist was last changed, in milliseconds since the Unix epoch
+    pub updated: f64,
+    /// Whether the playlist was deleted
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+/// An article in the synced queue
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncedQueueEntry {
+    /// The ID of the article
+    pub id: String,
+    /// The title of the article
+    pub title: String,
+    /// The group the article is a part of, if any
+    #[serde(default)]
+    pub group: Option<ArticleGroup>,
+    /// The publication the article is from, if known
+    #[serde(default)]
+    pub publication: Option<String>,
+    /// The article's author, if known
+    #[serde(default)]
+    pub author: Option<String>,
+    /// The path on the server of the article's lead image, if it has one
+    #[serde(default)]
+    pub image: Option<String>,
+    /// What the article is like to listen to, if known
+    #[serde(default)]
+    pub reading_profile: Option<ReadingProfile>,
+}
+
+/// How far an article has been listened to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncedPosition {
+    /// The ID of the article
+    pub id: String,
+    /// The elapsed time, in seconds
+    pub elapsed: f64,
+    /// Whether the article was listened to the end
+    pub finished: bool,
+    /// When the article was last played, in milliseconds since the Unix epoch
+    pub last_played: f64,
+    /// The ID of the device that got to this position, if known
+    #[serde(default)]
+    pub device: Option<String>,
+    /// When the listener last picked a position in the article over another device's, in
+    /// milliseconds since the Unix epoch. 0 if they never did.
+    #[serde(default)]
+    pub chosen: f64,
+}
+
+impl SyncedPosition {
+    /// Returns whether this position is further into the article than the given one. Finishing is
+    /// as far as it goes.
+    pub fn is_further_than(&self, other: &SyncedPosition) -> bool {
+        (self.finished, self.elapsed) > (other.finished, other.elapsed)
+    }
+
+    /// Returns whether this position wins over the given one when they're merged. A position the
+    /// listener picked wins over any that was last played before they picked it, even one further
+    /// along. Otherwise, the furthest wins.
+    pub fn wins_over(&self, other: &SyncedPosition) -> bool {
+        let picked_over_other = self.chosen > other.last_played;
+        let other_picked_over = other.chosen > self.last_played;
+        match (picked_over_other, other_picked_over) {
+            (true, true) => self.chosen > other.chosen,
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => self.is_further_than(other),
+        }
+    }
 }
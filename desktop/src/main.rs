@@ -0,0 +1,164 @@
+//! The desktop app. It starts the server it's bundled with, on this machine only, and opens a
+//! window on it. The server serves the frontend, built with its `desktop` feature, and reads
+//! articles with Piper, if that's bundled too, so nothing needs a cloud service or a browser. The
+//! library, the audio, and the server's config are all kept in the app's data directory.
+
+// Don't open a console window alongside the app on Windows
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+
+use std::{
+    env, fs,
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use tauri::{AppHandle, Manager, RunEvent, Url, WebviewUrl, WebviewWindowBuilder};
+
+/// How long the server gets to start listening
+const SERVER_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to check whether the server's listening yet
+const SERVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The server config the app starts with. It reads articles with the bundled Piper. It's written
+/// to the data directory the first time the app runs, and can be changed there, e.g., to use a
+/// cloud engine instead. See `server/src/config.rs`.
+const DEFAULT_CONFIG: &str = r#"[engine]
+kind = "local"
+command = "{piper}"
+args = ["--model", "{model}", "--output-raw"]
+sample_rate = 22050
+"#;
+
+/// The bundled server, which is stopped when the app exits
+struct Server(Mutex<Child>);
+
+/// Returns the port the server listens on, which is saved in the given data directory. The
+/// frontend's IndexedDB and localStorage belong to the server's origin, port and all, so the queue
+/// and the cached articles only carry over if it's the same every time. It's picked the first
+/// time, and only picked again if something else has taken it since.
+fn server_port(data: &Path) -> Result<u16, AnyError> {
+    let path = data.join("port");
+    let saved = fs::read_to_string(&path)
+        .ok()
+        .and_then(|p| p.trim().parse().ok());
+    if let Some(port) = saved {
+        if TcpListener::bind((Ipv4Addr::LOCALHOST, port)).is_ok() {
+            return Ok(port);
+        }
+        eprintln!("Port {port} is taken, so the queue and cached articles start over");
+    }
+
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?
+        .local_addr()?
+        .port();
+    fs::create_dir_all(data).map_err(|e| anyhow!("Could not create {data:?}: {e}"))?;
+    fs::write(&path, port.to_string()).map_err(|e| anyhow!("Could not write {path:?}: {e}"))?;
+    Ok(port)
+}
+
+/// Writes the default config to the given path, if there's no config there yet, using the Piper
+/// bundled in the given resource directory
+fn write_default_config(path: &Path, resources: &Path) -> Result<(), AnyError> {
+    if path.exists() {
+        return Ok(());
+    }
+    let piper_dir = resources.join("piper");
+    let piper = piper_dir.join(format!("piper{}", env::consts::EXE_SUFFIX));
+    let model = piper_dir.join("voice.onnx");
+    // TOML strings use backslashes as escapes, so Windows paths need theirs doubled
+    let escape = |p: PathBuf| p.to_string_lossy().replace('\\', "\\\\");
+    let config = DEFAULT_CONFIG
+        .replace("{piper}", &escape(piper))
+        .replace("{model}", &escape(model));
+    fs::write(path, config).map_err(|e| anyhow!("Could not write {path:?}: {e}"))
+}
+
+/// Starts the bundled server on the given port. Its files go in the given data directory, and it
+/// serves the frontend from the given resource directory.
+fn start_server(port: u16, resources: &Path, data: &Path) -> Result<Child, AnyError> {
+    // The server keeps everything it doesn't get a path for in its working directory
+    let server_dir = data.join("server");
+    fs::create_dir_all(&server_dir).map_err(|e| anyhow!("Could not create {server_dir:?}: {e}"))?;
+    let config = data.join("config.toml");
+    write_default_config(&config, resources)?;
+
+    // Tauri puts sidecar programs next to the app's own
+    let exe = env::current_exe()?
+        .parent()
+        .ok_or_else(|| anyhow!("The app isn't in a directory"))?
+        .join(format!("readtomyshoe-server{}", env::consts::EXE_SUFFIX));
+    Command::new(&exe)
+        .current_dir(&server_dir)
+        .args(["--addr", "127.0.0.1", "--log", "info"])
+        .arg("--port")
+        .arg(port.to_string())
+        .arg("--static-dir")
+        .arg(resources.join("dist"))
+        .arg("--config")
+        .arg(&config)
+        .spawn()
+        .map_err(|e| anyhow!("Could not start {exe:?}: {e}"))
+}
+
+/// Waits until the given server is listening on the given port. Fails if it exits first or takes
+/// too long.
+fn wait_for_server(server: &Server, port: u16) -> Result<(), AnyError> {
+    let start = Instant::now();
+    while TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_err() {
+        if let Some(status) = server.0.lock().unwrap().try_wait()? {
+            bail!("The server exited on startup: {status}");
+        }
+        if start.elapsed() > SERVER_START_TIMEOUT {
+            bail!("The server didn't start within {SERVER_START_TIMEOUT:?}");
+        }
+        thread::sleep(SERVER_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Stops the bundled server, if it's running
+fn stop_server(app: &AppHandle) {
+    let Some(server) = app.try_state::<Server>() else {
+        return;
+    };
+    let mut child = server.0.lock().unwrap();
+    if let Err(e) = child.kill().and_then(|_| child.wait()) {
+        eprintln!("Could not stop the server: {e}");
+    }
+}
+
+fn main() {
+    let app = tauri::Builder::default()
+        .setup(|app| {
+            let resources = app.path().resource_dir()?;
+            let data = app.path().app_data_dir()?;
+            let port = server_port(&data)?;
+            let child = start_server(port, &resources, &data)?;
+            app.manage(Server(Mutex::new(child)));
+            if let Err(e) = wait_for_server(&app.state::<Server>(), port) {
+                stop_server(app.handle());
+                return Err(e.into());
+            }
+
+            let url: Url = format!("http://127.0.0.1:{port}/").parse()?;
+            WebviewWindowBuilder::new(app, "main", WebviewUrl::External(url))
+                .title("ReadToMyShoe")
+                .inner_size(1000.0, 800.0)
+                .build()?;
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("Could not build the app");
+
+    app.run(|app, event| {
+        if let RunEvent::Exit = event {
+            stop_server(app);
+        }
+    });
+}
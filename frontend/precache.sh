@@ -0,0 +1,24 @@
+#!/usr/bin/env bash
+# Run by Trunk after every build. Fills in the service worker's list of app shell files with
+# everything the build made, and names its cache after a checksum of them. That way, a new build
+# means a new service worker, which precaches the new files and clears out the old ones.
+set -euo pipefail
+IFS=$'\n\t'
+
+cd "$TRUNK_STAGING_DIR"
+
+# The service worker lives next to the files, so their names are relative to it. The app itself is
+# one directory up, which is the root unless the app is served under a subpath.
+files=$(find . -type f ! -name '*.gz' ! -name 'service-worker.js' ! -name 'index.html' \
+    | sed 's|^\./||' | sort)
+build_id=$(cat index.html $files | cksum | cut -d ' ' -f 1)
+list='"../"'
+for f in $files; do
+    list="$list, \"$f\""
+done
+
+sed -e "s|^const buildId = .*|const buildId = \"$build_id\";|" \
+    -e "s|^const appShellFiles = .*|const appShellFiles = [$list];|" \
+    service-worker.js > service-worker.js.tmp
+mv service-worker.js.tmp service-worker.js
+grep -q "^const buildId = \"$build_id\";" service-worker.js
@@ -1,27 +1,38 @@
 // Code mostly taken from MDN:
 // https://developer.mozilla.org/en-US/docs/Web/Progressive_web_apps/Offline_Service_workers
 
-const cacheName = "readtomyshow-v1";
+// The files that make up the app, so it opens offline. The build (see precache.sh) fills in every
+// file it made, and sets the build ID to a checksum of them, so each build gets its own cache.
+// Names are relative to this file, which lives with the assets.
+const buildId = "dev";
+const appShellFiles = ["../", "manifest.json", "rtms-color-32x32.png", "rtms-color-180x180.png", "rtms-color-512x512.png", "readtomyshoe-frontend.js", "readtomyshoe-frontend_bg.wasm"];
 
-const appShellFiles = [
-    "/",
-    "/index.html",
-    "/assets/rtms-color-32x32.png",
-    "/assets/rtms-color-180x180.png",
-    "/assets/rtms-color-512x512.png",
-    "/assets/readtomyshoe-frontend.js",
-    "/assets/readtomyshoe-frontend_bg.wasm"
-];
+const cacheName = `readtomyshoe-${buildId}`;
 
-// Add to caches on installation
+// The path of the app, e.g., "/" or "/rtms/" when it's served under a subpath. This file lives in
+// its assets/ directory.
+const appRoot = new URL("../", self.location).pathname;
+
+// Pages the server makes itself. Every other page is the app, which picks what to show from the URL.
+const serverPages = ["/mini", "/pair", "/shared/"];
+
+// Returns the path of the given URL within the app, e.g., "/api/list-articles"
+function appPath(url) {
+    return url.pathname.startsWith(appRoot) ? url.pathname.slice(appRoot.length - 1) : url.pathname;
+}
+
+// Add to caches on installation. The files are fetched past the HTTP cache, so an old build's files
+// don't end up in a new build's cache. The new service worker takes over right away, since the app
+// is already running the files it just cached.
 self.addEventListener('install', (e) => {
     e.waitUntil((async () => {
         const cache = await caches.open(cacheName);
-        await cache.addAll(appShellFiles);
+        await cache.addAll(appShellFiles.map((file) => new Request(file, { cache: "reload" })));
+        await self.skipWaiting();
     })());
 });
 
-// Clear old caches
+// Clear old caches, and start handling the pages that are already open
 self.addEventListener('activate', (e) => {
     e.waitUntil(caches.keys().then((keyList) => {
         // Delete all the keys that don't belong to the current `cacheName`
@@ -29,14 +40,36 @@ self.addEventListener('activate', (e) => {
             if (key === cacheName) { return; }
             return caches.delete(key);
         }))
-    }));
+    }).then(() => self.clients.claim()));
 });
 
 // Try to fetch content from the network. On failure, serve from the cache.
 self.addEventListener('fetch', (e) => {
-    // We don't cache API calls or internal pages
     const reqUrl = new URL(e.request.url);
-    if (reqUrl.pathname.startsWith("/api") || reqUrl.pathname.startsWith("/add")) {
+    const path = appPath(reqUrl);
+    const isServerPage = serverPages.some((prefix) => path.startsWith(prefix));
+
+    // Every page of the app is the same page, so opening any of them offline, e.g., /playlists or a
+    // link to an author, gets the cached app
+    if (e.request.mode === "navigate" && !isServerPage) {
+        e.respondWith((async () => {
+            try {
+                const response = await fetch(e.request);
+                if (response.ok) {
+                    const cache = await caches.open(cacheName);
+                    cache.put(appRoot, response.clone());
+                }
+                return response;
+            } catch {
+                return (await caches.match(appRoot)) || Response.error();
+            }
+        })());
+        return;
+    }
+
+    // We don't cache API calls, audio, or internal pages. Audio is kept in IndexedDB instead.
+    const uncached = ["/api", "/add", "/audio/", ...serverPages];
+    if (uncached.some((prefix) => path.startsWith(prefix))) {
         return;
     }
 
@@ -59,7 +92,28 @@ self.addEventListener('fetch', (e) => {
         } catch {
             // If fetching fails, try to hit the cache
             const c = await caches.match(e.request);
-            return c;
+            return c || Response.error();
+        }
+    })());
+});
+
+// Show the reminders the server pushes. The message is JSON with a title and a body.
+self.addEventListener('push', (e) => {
+    const message = e.data ? e.data.json() : { title: "ReadToMyShoe", body: "" };
+    e.waitUntil(self.registration.showNotification(message.title, {
+        body: message.body,
+        icon: "rtms-color-180x180.png",
+    }));
+});
+
+// Open the app when a notification is clicked. Due reminders are at the top of the home view.
+self.addEventListener('notificationclick', (e) => {
+    e.notification.close();
+    e.waitUntil((async () => {
+        const windows = await clients.matchAll({ type: "window" });
+        if (windows.length > 0) {
+            return windows[0].focus();
         }
+        return clients.openWindow(appRoot);
     })());
 });
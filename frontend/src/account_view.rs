@@ -0,0 +1,417 @@
+//! Signing in and out, when the server has accounts. Each account has its own library. When the
+//! account changes, the page reloads so everything shows the new account's library.
+//!
+//! Other servers, i.e., ones the app wasn't loaded from, are signed in to with a session token rather
+//! than cookies. The token is saved with the server. See `servers.rs`.
+//!
+//! Signed in, accounts can turn on two-factor authentication, after which signing in needs a code
+//! from an authenticator app as well as the password.
+//!
+//! Accounts can be deleted too, with the password, which deletes their library on the server and
+//! everything this device has cached of it. Everything in the library can be downloaded first, from
+//! the library's backup section.
+
+use crate::{caching, servers, utils};
+use common::{AccountDeletion, AccountStatus, Credentials, SessionToken, TotpCode, TotpSetup};
+
+use anyhow::{bail, Error as AnyError};
+use gloo_net::http::Response;
+use serde::Serialize;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+const NAME_FORM_ID: &str = "account-name-input";
+const PASSWORD_FORM_ID: &str = "account-password-input";
+const SIGNUP_CODE_FORM_ID: &str = "account-signup-code-input";
+const TOTP_CODE_FORM_ID: &str = "account-totp-code-input";
+const TOTP_SETTINGS_CODE_FORM_ID: &str = "account-totp-settings-code-input";
+const DELETION_PASSWORD_FORM_ID: &str = "account-deletion-password-input";
+const DELETION_TOTP_CODE_FORM_ID: &str = "account-deletion-totp-code-input";
+
+/// Asks the server who's signed in. This fails when offline, in which case the app carries on with
+/// whatever is cached.
+pub(crate) async fn fetch_status() -> Result<AccountStatus, AnyError> {
+    let resp = utils::get("/api/account")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching account"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing account JSON"))
+}
+
+/// POSTs the given body, if any, to the given account endpoint
+async fn post_account(endpoint: &str, body: Option<&impl Serialize>) -> Result<Response, AnyError> {
+    let req = utils::post(endpoint);
+    let req = match body {
+        Some(body) => req.json(body)?,
+        None => req,
+    };
+    let resp = req
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(resp)
+}
+
+/// Asks the server for a new two-factor secret, to put in an authenticator app
+async fn start_totp_setup() -> Result<TotpSetup, AnyError> {
+    post_account("/api/totp-setup", None::<&()>)
+        .await?
+        .json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing two-factor setup JSON"))
+}
+
+/// Deletes the account that's signed in, then everything cached of its library on this device
+async fn delete_account(deletion: AccountDeletion) -> Result<(), AnyError> {
+    post_account("/api/delete-account", Some(&deletion)).await?;
+    servers::set_token(None);
+    for &table in caching::TABLES {
+        caching::table_clear(table).await?;
+    }
+    Ok(())
+}
+
+/// Returns the value of the input with the given ID
+fn input_value(id: &str) -> String {
+    gloo_utils::document()
+        .get_element_by_id(id)
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .map(|input| input.value())
+        .unwrap_or_default()
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// Who's signed in
+    pub status: AccountStatus,
+}
+
+pub(crate) enum AccountMsg {
+    SignIn,
+    SignUp,
+    SignOut,
+    /// Gets a new two-factor secret to set up
+    StartTotpSetup,
+    /// Shows the given two-factor secret, for putting in an authenticator app
+    SetTotpSetup(TotpSetup),
+    /// Turns two-factor authentication on with the code that's been entered
+    EnableTotp,
+    /// Turns two-factor authentication off with the code that's been entered
+    DisableTotp,
+    /// Deletes the account with the password that's been entered, once the listener confirms
+    DeleteAccount,
+    /// The account changed, so the page has to reload
+    Changed,
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+/// The sign in form, or, once signed in, who's signed in, a button to sign out, and the two-factor
+/// authentication settings
+#[derive(Default)]
+pub(crate) struct Account {
+    err: Option<AnyError>,
+    /// The two-factor secret that's being set up, if any
+    totp_setup: Option<TotpSetup>,
+}
+
+impl Component for Account {
+    type Message = AccountMsg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Account::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        let (endpoint, creds) = match msg {
+            AccountMsg::SignIn | AccountMsg::SignUp => {
+                let signup_code = Some(input_value(SIGNUP_CODE_FORM_ID))
+                    .filter(|code| matches!(msg, AccountMsg::SignUp) && !code.is_empty());
+                let totp_code = Some(input_value(TOTP_CODE_FORM_ID))
+                    .filter(|code| matches!(msg, AccountMsg::SignIn) && !code.trim().is_empty());
+                let creds = Credentials {
+                    name: input_value(NAME_FORM_ID).trim().to_lowercase(),
+                    password: input_value(PASSWORD_FORM_ID),
+                    signup_code,
+                    totp_code,
+                    want_token: servers::active().is_some(),
+                };
+                let endpoint = match msg {
+                    AccountMsg::SignUp => "/api/signup",
+                    _ => "/api/login",
+                };
+                (endpoint, Some(creds))
+            }
+            AccountMsg::SignOut => ("/api/logout", None),
+            AccountMsg::StartTotpSetup => {
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match start_totp_setup().await {
+                        Ok(setup) => AccountMsg::SetTotpSetup(setup),
+                        Err(e) => AccountMsg::SetError(e),
+                    }
+                });
+                return true;
+            }
+            AccountMsg::SetTotpSetup(setup) => {
+                self.totp_setup = Some(setup);
+                return true;
+            }
+            AccountMsg::EnableTotp | AccountMsg::DisableTotp => {
+                let endpoint = match msg {
+                    AccountMsg::EnableTotp => "/api/totp-enable",
+                    _ => "/api/totp-disable",
+                };
+                let code = TotpCode {
+                    code: input_value(TOTP_SETTINGS_CODE_FORM_ID).trim().to_string(),
+                };
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match post_account(endpoint, Some(&code)).await {
+                        Ok(_) => AccountMsg::Changed,
+                        Err(e) => AccountMsg::SetError(e),
+                    }
+                });
+                return true;
+            }
+            AccountMsg::DeleteAccount => {
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message(
+                        "Delete this account, and every article, bit of progress, and setting in \
+                        it? This can't be undone.",
+                    )
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+                let deletion = AccountDeletion {
+                    password: input_value(DELETION_PASSWORD_FORM_ID),
+                    totp_code: Some(input_value(DELETION_TOTP_CODE_FORM_ID))
+                        .filter(|code| !code.trim().is_empty()),
+                };
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match delete_account(deletion).await {
+                        Ok(()) => AccountMsg::Changed,
+                        Err(e) => AccountMsg::SetError(e),
+                    }
+                });
+                return true;
+            }
+            AccountMsg::Changed => {
+                let _ = gloo_utils::window().location().reload();
+                return false;
+            }
+            AccountMsg::SetError(e) => {
+                self.err = Some(e);
+                return true;
+            }
+        };
+
+        self.err = None;
+        let want_token = creds.as_ref().is_some_and(|c| c.want_token);
+        ctx.link().send_future(async move {
+            let resp = match post_account(endpoint, creds.as_ref()).await {
+                Ok(resp) => resp,
+                Err(e) => return AccountMsg::SetError(e),
+            };
+            if want_token {
+                match resp.json::<SessionToken>().await {
+                    Ok(SessionToken { token }) => servers::set_token(Some(token)),
+                    Err(e) => {
+                        return AccountMsg::SetError(
+                            AnyError::from(e).context("Error parsing session token JSON"),
+                        )
+                    }
+                }
+            } else if creds.is_none() {
+                // Signed out
+                servers::set_token(None);
+            }
+            AccountMsg::Changed
+        });
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let status = &ctx.props().status;
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+
+        if !status.accounts_enabled {
+            return html! {};
+        }
+        if let Some(name) = &status.name {
+            let sign_out = link.callback(|_| AccountMsg::SignOut);
+            return html! {
+                <div class="account">
+                    <p>
+                        { format!("Signed in as {name} ") }
+                        <button onclick={sign_out}>{ "Sign out" }</button>
+                        <span role="alert" style={ "color: red;" }>{ err_str }</span>
+                    </p>
+                    { self.view_totp_settings(ctx) }
+                    { self.view_deletion(ctx) }
+                </div>
+            };
+        }
+
+        let sign_in = link.callback(|e: FocusEvent| {
+            e.prevent_default();
+            AccountMsg::SignIn
+        });
+        let sign_up = link.callback(|_| AccountMsg::SignUp);
+        html! {
+            <form class="account" onsubmit={sign_in}>
+                <fieldset>
+                    <legend><h2>{ "Sign in" }</h2></legend>
+                    <div class="field">
+                        <label for={NAME_FORM_ID}>{ "Name:" }</label>
+                        <input type="text" id={NAME_FORM_ID} autocomplete="username" />
+                    </div>
+                    <div class="field">
+                        <label for={PASSWORD_FORM_ID}>{ "Password:" }</label>
+                        <input
+                            type="password"
+                            id={PASSWORD_FORM_ID}
+                            autocomplete="current-password"
+                        />
+                    </div>
+                    <div class="field">
+                        <label for={TOTP_CODE_FORM_ID}>
+                            { "Two-factor code, if you've turned it on:" }
+                        </label>
+                        <input
+                            type="text"
+                            id={TOTP_CODE_FORM_ID}
+                            inputmode="numeric"
+                            autocomplete="one-time-code"
+                        />
+                    </div>
+                    <button type="submit">{ "Sign in" }</button>
+                    <details>
+                        <summary>{ "New here? Sign up" }</summary>
+                        <div class="field">
+                            <label for={SIGNUP_CODE_FORM_ID}>{ "Signup code, if the server needs one:" }</label>
+                            <input type="text" id={SIGNUP_CODE_FORM_ID} />
+                        </div>
+                        <button type="button" onclick={sign_up}>{ "Sign up" }</button>
+                    </details>
+                    <p role="alert" style={ "color: red;" }>{ err_str }</p>
+                </fieldset>
+            </form>
+        }
+    }
+}
+
+impl Account {
+    /// The two-factor authentication settings: turning it off if it's on, or setting it up if not
+    fn view_totp_settings(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let code_input = html! {
+            <div class="field">
+                <label for={TOTP_SETTINGS_CODE_FORM_ID}>{ "Code from the app:" }</label>
+                <input
+                    type="text"
+                    id={TOTP_SETTINGS_CODE_FORM_ID}
+                    inputmode="numeric"
+                    autocomplete="one-time-code"
+                />
+            </div>
+        };
+
+        let body = match (&self.totp_setup, ctx.props().status.totp_enabled) {
+            (_, true) => {
+                let disable = link.callback(|_| AccountMsg::DisableTotp);
+                html! {
+                    <>
+                        <p>{ "Two-factor authentication is on." }</p>
+                        { code_input }
+                        <button onclick={disable}>{ "Turn off" }</button>
+                    </>
+                }
+            }
+            (Some(setup), false) => {
+                let enable = link.callback(|_| AccountMsg::EnableTotp);
+                html! {
+                    <>
+                        <p>
+                            { "Add this secret to an authenticator app, then enter the code it \
+                               shows: " }
+                            <code>{ &setup.secret }</code>
+                        </p>
+                        <p><a href={setup.uri.clone()}>{ "Open in an authenticator app" }</a></p>
+                        { code_input }
+                        <button onclick={enable}>{ "Turn on" }</button>
+                    </>
+                }
+            }
+            (None, false) => {
+                let start = link.callback(|_| AccountMsg::StartTotpSetup);
+                html! {
+                    <>
+                        <p>{ "Signing in can need a code from an authenticator app as well as \
+                              the password." }</p>
+                        <button onclick={start}>{ "Set up" }</button>
+                    </>
+                }
+            }
+        };
+
+        html! {
+            <details class="two-factor">
+                <summary>{ "Two-factor authentication" }</summary>
+                { body }
+            </details>
+        }
+    }
+
+    /// The form for deleting the account, which needs the password, and the two-factor code if
+    /// that's on
+    fn view_deletion(&self, ctx: &Context<Self>) -> Html {
+        let delete = ctx.link().callback(|_| AccountMsg::DeleteAccount);
+        html! {
+            <details class="account-deletion">
+                <summary>{ "Delete account" }</summary>
+                <p>{ "This deletes the account's whole library from the server, including its \
+                      progress, ratings, and playlists, and everything this device has saved of \
+                      it. To keep a copy, download everything from \"Back up or move the \
+                      library\" first." }</p>
+                <div class="field">
+                    <label for={DELETION_PASSWORD_FORM_ID}>{ "Password:" }</label>
+                    <input
+                        type="password"
+                        id={DELETION_PASSWORD_FORM_ID}
+                        autocomplete="current-password"
+                    />
+                </div>
+                if ctx.props().status.totp_enabled {
+                    <div class="field">
+                        <label for={DELETION_TOTP_CODE_FORM_ID}>{ "Two-factor code:" }</label>
+                        <input
+                            type="text"
+                            id={DELETION_TOTP_CODE_FORM_ID}
+                            inputmode="numeric"
+                            autocomplete="one-time-code"
+                        />
+                    </div>
+                }
+                <button onclick={delete}>{ "Delete account" }</button>
+            </details>
+        }
+    }
+}
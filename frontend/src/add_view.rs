@@ -1,25 +1,155 @@
-use common::{ArticleTextSubmission, ArticleUrlSubmission, MAX_TITLE_UTF16_CODEUNITS};
+use crate::{
+    app_view::Route,
+    encryption,
+    feeds_view::Feeds,
+    import_view::Import,
+    inbox_view::Inbox,
+    job_view::{control_job, fetch_job, status_str, JobAction, Jobs},
+    servers, utils,
+    voice_compare_view::{fetch_voices, with_voice_key, VoiceComparison},
+};
+use common::{
+    AmbientBedInfo, ArticleMetadata, ArticleTextSubmission, ArticleUrlSubmission, EncodingOptions,
+    ExtractionChoice, ExtractionPreview, ExtractionStrategy, Frontmatter, JobInfo, JobStarted,
+    JobStatus, ParsedDocument, SourceType, SpeakingStyle, SplitOffer, SynthesisOptions, VoiceInfo,
+    DEFAULT_HEADING_PAUSE_MS, DEFAULT_PARAGRAPH_GAP_MS, MAX_PAUSE_MS, MAX_TITLE_UTF16_CODEUNITS,
+    MIN_PAUSE_MS,
+};
 
 use anyhow::{anyhow, bail, Error as AnyError};
-use gloo_net::http::Request;
+use gloo_timers::future::TimeoutFuture;
+use url::Url;
 use wasm_bindgen::JsValue;
 use yew::{html::Scope, prelude::*};
+use yew_router::prelude::*;
 
 const URL_FORM_ID: &str = "article-url-input";
 const TITLE_FORM_ID: &str = "article-title-input";
 const BODY_FORM_ID: &str = "article-body-input";
+const DOCUMENT_FORM_ID: &str = "article-document-input";
+const SECTION_FORM_ID: &str = "article-section-input";
+const STYLE_FORM_ID: &str = "article-style-input";
+const PARAGRAPH_GAP_FORM_ID: &str = "article-paragraph-gap-input";
+const HEADING_PAUSE_FORM_ID: &str = "article-heading-pause-input";
+const AMBIENT_BED_FORM_ID: &str = "article-ambient-bed-input";
+const VOICE_FORM_ID: &str = "article-voice-input";
+const SOFTEN_ASIDES_FORM_ID: &str = "article-soften-asides-input";
+const FRONTMATTER_FORM_ID: &str = "article-frontmatter-input";
+const OUTRO_FORM_ID: &str = "article-outro-input";
+const KEY_POINTS_FORM_ID: &str = "article-key-points-input";
+const ENCODING_FORM_ID: &str = "article-encoding-input";
+const ENCRYPT_FORM_ID: &str = "article-encrypt-input";
+const CANDIDATE_FORM_ID_PREFIX: &str = "extraction-candidate-input";
+const SAVE_SELECTOR_FORM_ID: &str = "extraction-save-selector-input";
+const PREVIEW_STRATEGY_FORM_ID: &str = "preview-strategy-input";
+const PREVIEW_SELECTOR_FORM_ID: &str = "preview-selector-input";
+const PREVIEW_SELECTORS_LIST_ID: &str = "preview-selectors";
+const PREVIEW_SAVE_SELECTOR_FORM_ID: &str = "preview-save-selector-input";
+const JOB_PROGRESS_ID: &str = "add-job-progress";
 
-/// POSTs the given ArticleTextSubmission to the server for conversion
-async fn submit_article_text(submission: &ArticleTextSubmission) -> Result<(), AnyError> {
+/// The granularity of the pause length inputs, in milliseconds
+const PAUSE_STEP_MS: u32 = 50;
+
+/// The encodings that can be picked instead of the server's default, with their descriptions
+const ENCODING_PRESETS: [(&str, EncodingOptions); 3] = [
+    (
+        "Smallest (16 kHz, 24 kbit/s)",
+        EncodingOptions {
+            sample_rate: 16000,
+            bitrate_kbps: 24,
+            stereo: false,
+        },
+    ),
+    (
+        "Standard (24 kHz, 64 kbit/s)",
+        EncodingOptions {
+            sample_rate: 24000,
+            bitrate_kbps: 64,
+            stereo: false,
+        },
+    ),
+    (
+        "Stereo, for players that need it (44.1 kHz, 128 kbit/s)",
+        EncodingOptions {
+            sample_rate: 44100,
+            bitrate_kbps: 128,
+            stereo: true,
+        },
+    ),
+];
+
+/// How often the progress of a submission that's converting is checked, in milliseconds
+const JOB_POLL_INTERVAL_MS: u32 = 1000;
+
+/// The HTTP status the server responds with when a submission is being added in the background
+const ACCEPTED: u16 = 202;
+
+/// The HTTP status the server responds with when an article is too long to convert as one
+const PAYLOAD_TOO_LARGE: u16 = 413;
+
+/// The HTTP status the server responds with when it's unsure which part of a page is the article
+const MULTIPLE_CHOICES: u16 = 300;
+
+/// The HTTP status the server responds with when the article at a URL is already in the library
+const CONFLICT: u16 = 409;
+
+/// What the server did with a submission
+enum Submitted {
+    /// The article was added. There's more than one article if it was split into parts.
+    Added(Vec<ArticleMetadata>),
+    /// The article is too long to convert as one, and the server offered to split it up
+    SplitOffered(SplitOffer),
+    /// The server is unsure which part of the page is the article, and offered the candidates
+    ExtractionUncertain(ExtractionChoice),
+    /// The article is already in the library, as the given article
+    AlreadyAdded(Box<ArticleMetadata>),
+    /// The article is being converted in the background, by the given job
+    Started(JobStarted),
+}
+
+/// Parses the server's response to a submission
+async fn parse_submission_resp(resp: gloo_net::http::Response) -> Result<Submitted, AnyError> {
+    if resp.status() == ACCEPTED {
+        resp.json()
+            .await
+            .map(Submitted::Started)
+            .map_err(|e| AnyError::from(e).context("Error parsing job"))
+    } else if resp.status() == PAYLOAD_TOO_LARGE {
+        resp.json()
+            .await
+            .map(Submitted::SplitOffered)
+            .map_err(|e| AnyError::from(e).context("Error parsing split offer"))
+    } else if resp.status() == MULTIPLE_CHOICES {
+        resp.json()
+            .await
+            .map(Submitted::ExtractionUncertain)
+            .map_err(|e| AnyError::from(e).context("Error parsing extraction candidates"))
+    } else if resp.status() == CONFLICT {
+        resp.json()
+            .await
+            .map(|meta| Submitted::AlreadyAdded(Box::new(meta)))
+            .map_err(|e| AnyError::from(e).context("Error parsing article metadata"))
+    } else {
+        resp.json()
+            .await
+            .map(Submitted::Added)
+            .map_err(|e| AnyError::from(e).context("Error parsing article metadata"))
+    }
+}
+
+/// POSTs the given ArticleTextSubmission to the server for conversion. Returns the new articles'
+/// metadata, the job converting them, the server's offer to split the article up, or the article
+/// with the same text that's already in the library.
+async fn submit_article_text(submission: &ArticleTextSubmission) -> Result<Submitted, AnyError> {
     tracing::debug!("Adding article {:?}", submission);
     let endpoint = "/api/add-article-by-text";
-    let resp = Request::post(endpoint)
+    let resp = with_voice_key(utils::post(endpoint))
         .json(&submission)?
         .send()
         .await
         .map_err(|e| anyhow!("Error POSTing to {endpoint}: {}", e))?;
 
-    if !resp.ok() {
+    if !resp.ok() && ![PAYLOAD_TOO_LARGE, CONFLICT].contains(&resp.status()) {
         bail!(
             "Error adding article \"{}\" ({}; {:?})",
             submission.title,
@@ -28,20 +158,23 @@ async fn submit_article_text(submission: &ArticleTextSubmission) -> Result<(), A
         );
     }
 
-    Ok(())
+    parse_submission_resp(resp).await
 }
 
-/// POSTs the given ArticleTextSubmission to the server for fetching and conversion
-async fn submit_article_url(submission: &ArticleUrlSubmission) -> Result<(), AnyError> {
+/// POSTs the given ArticleUrlSubmission to the server for fetching and conversion. Returns the new
+/// articles' metadata, the job converting them, the server's offer to split the article up, the parts of the page the
+/// server thinks might be the article, or the article that's already in the library.
+async fn submit_article_url(submission: &ArticleUrlSubmission) -> Result<Submitted, AnyError> {
     tracing::debug!("Adding article {:?}", submission);
     let endpoint = "/api/add-article-by-url";
-    let resp = Request::post(endpoint)
+    let resp = with_voice_key(utils::post(endpoint))
         .json(&submission)?
         .send()
         .await
         .map_err(|e| anyhow!("Error POSTing to {endpoint}: {}", e))?;
 
-    if !resp.ok() {
+    let expected_statuses = [PAYLOAD_TOO_LARGE, MULTIPLE_CHOICES, CONFLICT];
+    if !resp.ok() && !expected_statuses.contains(&resp.status()) {
         bail!(
             "Error adding article \"{}\". {}. {}",
             submission.url,
@@ -50,7 +183,215 @@ async fn submit_article_url(submission: &ArticleUrlSubmission) -> Result<(), Any
         );
     }
 
-    Ok(())
+    parse_submission_resp(resp).await
+}
+
+/// POSTs the given ArticleUrlSubmission to the server's preview endpoint
+async fn post_preview(
+    submission: &ArticleUrlSubmission,
+) -> Result<gloo_net::http::Response, AnyError> {
+    let endpoint = "/api/preview-article";
+    utils::post(endpoint)
+        .json(&submission)?
+        .send()
+        .await
+        .map_err(|e| anyhow!("Error POSTing to {endpoint}: {}", e))
+}
+
+/// POSTs the given ArticleUrlSubmission to the server to see what would be read aloud, without
+/// converting anything. If the article is already in the library, asks the user whether to go on
+/// anyway, and if so, sets the submission to convert it again.
+async fn preview_article_url(
+    submission: &mut ArticleUrlSubmission,
+) -> Result<ExtractionPreview, AnyError> {
+    let mut resp = post_preview(submission).await?;
+    if resp.status() == CONFLICT {
+        let meta: ArticleMetadata = resp
+            .json()
+            .await
+            .map_err(|e| AnyError::from(e).context("Error parsing article metadata"))?;
+        if !convert_again(&meta) {
+            bail!(
+                "This article is already in the library, as \"{}\".",
+                meta.title
+            );
+        }
+        submission.force = true;
+        resp = post_preview(submission).await?;
+    }
+    if !resp.ok() {
+        bail!(
+            "Error previewing \"{}\": {}",
+            submission.url,
+            utils::resp_error(resp).await
+        );
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing preview"))
+}
+
+/// Uploads the given document to the server, and returns its text
+async fn parse_document(file: web_sys::File) -> Result<ParsedDocument, AnyError> {
+    let url = format!(
+        "/api/parse-document?filename={}",
+        urlencoding::encode(&file.name())
+    );
+    let resp = utils::post(&url)
+        .body(file)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error uploading document"))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing document JSON"))
+}
+
+/// Asks the user whether to take the server up on its offer to split their article into parts
+fn accept_split(offer: &SplitOffer) -> bool {
+    gloo_utils::window()
+        .confirm_with_message(&format!(
+            "This article is {} words long. Convert it as {} parts?",
+            offer.words, offer.parts
+        ))
+        .unwrap_or(false)
+}
+
+/// Asks the user whether to convert an article again, even though it's already in the library as
+/// the given article
+fn convert_again(meta: &ArticleMetadata) -> bool {
+    gloo_utils::window()
+        .confirm_with_message(&format!(
+            "This article is already in the library, as \"{}\". Convert it again anyway?",
+            meta.title
+        ))
+        .unwrap_or(false)
+}
+
+/// Describes the outcome of a submission
+fn outcome_msg(res: Result<Submitted, AnyError>) -> AddMsg {
+    let metas = match res {
+        Ok(Submitted::Added(metas)) => metas,
+        Ok(Submitted::SplitOffered(_) | Submitted::ExtractionUncertain(_)) => {
+            return AddMsg::AddProgress("The article was not converted.".to_string())
+        }
+        Ok(Submitted::AlreadyAdded(meta)) => {
+            return AddMsg::AddProgress(format!(
+                "This article is already in the library, as \"{}\".",
+                meta.title
+            ))
+        }
+        Ok(Submitted::Started(_)) => {
+            return AddMsg::AddProgress("The article is converting.".to_string())
+        }
+        Err(e) => return AddMsg::SetError(e),
+    };
+
+    added_msg(metas.len(), metas.iter().any(|meta| meta.incomplete))
+}
+
+/// Describes the outcome of adding the given number of articles. If `incomplete` is set, the
+/// conversion of some of them stopped partway through.
+fn added_msg(count: usize, incomplete: bool) -> AddMsg {
+    if incomplete {
+        AddMsg::AddProgress(
+            "Conversion stopped partway through. The beginning of the article is in the library, \
+            where you can finish the conversion."
+                .to_string(),
+        )
+    } else if count > 1 {
+        AddMsg::AddProgress(format!("Success! Added as {count} parts."))
+    } else {
+        AddMsg::AddProgress("Success!".to_string())
+    }
+}
+
+/// Describes the outcome of a submission. If it's being added in the background, this follows its
+/// job until the job finishes, showing the job's progress along the way.
+async fn follow_submission(link: Scope<Add>, res: Result<Submitted, AnyError>) -> AddMsg {
+    let id = match res {
+        Ok(Submitted::Started(JobStarted { id })) => id,
+        res => return outcome_msg(res),
+    };
+
+    loop {
+        let job = match fetch_job(id).await {
+            Ok(job) => job,
+            Err(e) => {
+                link.send_message(AddMsg::SetJob(None));
+                return AddMsg::SetError(e);
+            }
+        };
+        if !job.status.is_finished() {
+            link.send_message(AddMsg::SetJob(Some(job)));
+            TimeoutFuture::new(JOB_POLL_INTERVAL_MS).await;
+            continue;
+        }
+
+        link.send_message(AddMsg::SetJob(None));
+        return match job.status {
+            JobStatus::Done => added_msg(job.articles.len(), job.incomplete),
+            JobStatus::Cancelled => {
+                AddMsg::AddProgress("The conversion was cancelled.".to_string())
+            }
+            _ => AddMsg::SetError(anyhow!(
+                "Error converting \"{}\": {}",
+                job.title,
+                job.error.as_deref().unwrap_or("unknown error")
+            )),
+        };
+    }
+}
+
+/// Fetches the list of ambient beds the server offers
+async fn fetch_ambient_beds() -> Result<Vec<AmbientBedInfo>, AnyError> {
+    let resp = utils::get("/api/list-ambient-beds")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching ambient bed list"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching ambient bed list {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing ambient bed list JSON"))
+}
+
+/// Returns the first web link in the given text
+fn find_link(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(str::to_string)
+}
+
+/// Returns the URL that was shared to the app, if the Add page was opened by the browser's share
+/// menu or by the bookmarklet. Some browsers share the URL as the text, along with the page's
+/// title, so the text is searched too.
+fn shared_url() -> Option<String> {
+    let href = gloo_utils::window().location().href().ok()?;
+    let page = Url::parse(&href).ok()?;
+    let param = |name: &str| {
+        page.query_pairs()
+            .find(|(k, _)| k == name)
+            .and_then(|(_, v)| find_link(&v))
+    };
+    param("url").or_else(|| param("text"))
+}
+
+/// Returns a bookmarklet that opens the Add page with the current page's URL
+fn bookmarklet() -> String {
+    let add_url = servers::local_url("/add");
+    format!("javascript:location.href='{add_url}?url='+encodeURIComponent(location.href)")
 }
 
 /// Retrives the value of the element with the given ID
@@ -63,8 +404,77 @@ fn get_elem_value(id: &str) -> String {
         .unwrap()
 }
 
-/// POSTs the article title and body to the server for conversion
-fn add_by_text_cb(link: Scope<Add>) {
+/// Sets the value of the element with the given ID
+fn set_elem_value(id: &str, value: &str) {
+    let doc = gloo_utils::document();
+    let elem = doc.get_element_by_id(id).unwrap();
+    js_sys::Reflect::set(
+        &elem,
+        &JsValue::from_str("value"),
+        &JsValue::from_str(value),
+    )
+    .unwrap();
+}
+
+/// Retrieves whether the checkbox with the given ID is checked
+fn get_elem_checked(id: &str) -> bool {
+    let doc = gloo_utils::document();
+    let elem = doc.get_element_by_id(id).unwrap();
+    js_sys::Reflect::get(&elem, &JsValue::from_str("checked"))
+        .unwrap()
+        .as_bool()
+        .unwrap()
+}
+
+/// Retrieves the value of the element with the given ID, if the element exists
+fn get_optional_elem_value(id: &str) -> Option<String> {
+    gloo_utils::document().get_element_by_id(id)?;
+    Some(get_elem_value(id))
+}
+
+/// Retrieves the speaking style the user selected
+fn get_selected_style() -> SpeakingStyle {
+    get_elem_value(STYLE_FORM_ID).parse().unwrap_or_default()
+}
+
+/// Retrieves the voice the user selected. `None` means the server picks one that speaks the
+/// article's language.
+fn get_selected_voice() -> Option<String> {
+    // The selector is missing until the voice list arrives
+    get_optional_elem_value(VOICE_FORM_ID).filter(|v| !v.is_empty())
+}
+
+/// Retrieves the synthesis options the user selected. Unparseable values are replaced with their
+/// defaults.
+fn get_selected_options() -> SynthesisOptions {
+    let defaults = SynthesisOptions::default();
+    SynthesisOptions {
+        paragraph_gap_ms: get_elem_value(PARAGRAPH_GAP_FORM_ID)
+            .parse()
+            .unwrap_or(defaults.paragraph_gap_ms),
+        heading_pause_ms: get_elem_value(HEADING_PAUSE_FORM_ID)
+            .parse()
+            .unwrap_or(defaults.heading_pause_ms),
+        // The selector is missing if the server has no beds, and the empty value means no bed
+        ambient_bed: get_optional_elem_value(AMBIENT_BED_FORM_ID).filter(|b| !b.is_empty()),
+        soften_asides: get_elem_checked(SOFTEN_ASIDES_FORM_ID),
+        frontmatter: get_elem_value(FRONTMATTER_FORM_ID)
+            .parse()
+            .unwrap_or_default(),
+        outro: get_elem_checked(OUTRO_FORM_ID),
+        key_points: get_elem_checked(KEY_POINTS_FORM_ID),
+        // The empty value means the server's default
+        encoding: get_elem_value(ENCODING_FORM_ID)
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| ENCODING_PRESETS.get(i))
+            .map(|(_, encoding)| *encoding),
+    }
+}
+
+/// POSTs the article title and body to the server for conversion. The text came from a document of
+/// the given type, if any, and was pasted in otherwise.
+fn add_by_text_cb(link: Scope<Add>, source_type: Option<SourceType>) {
     // Collect the title and body
     let title = get_elem_value(TITLE_FORM_ID);
     let body = get_elem_value(BODY_FORM_ID);
@@ -77,71 +487,475 @@ fn add_by_text_cb(link: Scope<Add>) {
     }
 
     // Construct the submission and update the progress
-    let submission = ArticleTextSubmission { title, body };
+    let style = get_selected_style();
+    let mut submission = ArticleTextSubmission {
+        title,
+        body,
+        style,
+        options: get_selected_options(),
+        split: false,
+        voice: get_selected_voice(),
+        source_type,
+        background: true,
+        force: false,
+    };
     link.send_message(AddMsg::AddProgress("Converting to speech...".to_string()));
 
+    // Encrypted articles don't go in the library, so they aren't split up or converted in the
+    // background. What's submitted isn't logged either.
+    if get_elem_checked(ENCRYPT_FORM_ID) {
+        link.send_future(async move {
+            match encryption::add_private_article(&submission).await {
+                Ok(title) => AddMsg::AddProgress(format!(
+                    "Success! {title} is in the encrypted library on the main page."
+                )),
+                Err(e) => AddMsg::SetError(e),
+            }
+        });
+        return;
+    }
+
     tracing::debug!("Submitting {:?}", submission);
 
-    // Make the submission
+    // Make the submission. If the article is already in the library, offer to convert it again.
+    // If it's too long, offer to split it up. Either way, resubmit.
+    let follow_link = link.clone();
     link.send_future(async move {
-        if let Err(e) = submit_article_text(&submission).await {
-            // On error, send the error
-            AddMsg::SetError(e)
-        } else {
-            // On success, say so
-            AddMsg::AddProgress("Success!".to_string())
-        }
+        let res = match submit_article_text(&submission).await {
+            Ok(Submitted::AlreadyAdded(meta)) if convert_again(&meta) => {
+                submission.force = true;
+                submit_article_text(&submission).await
+            }
+            res => res,
+        };
+        let res = match res {
+            Ok(Submitted::SplitOffered(offer)) if accept_split(&offer) => {
+                submission.split = true;
+                submit_article_text(&submission).await
+            }
+            res => res,
+        };
+        follow_submission(follow_link, res).await
     });
 }
 
-/// POSTs the article url to the server for fetching and conversion
-fn add_by_url_cb(link: Scope<Add>) {
-    // Collect the article URL
+/// POSTs the URL that was shared to the app to the server for fetching and conversion. The form
+/// hasn't been rendered yet, so the default style and options are used.
+fn add_shared_url(link: Scope<Add>, url: String) {
+    let submission = ArticleUrlSubmission {
+        url,
+        style: SpeakingStyle::default(),
+        options: SynthesisOptions::default(),
+        split: false,
+        selector: None,
+        save_selector: false,
+        trust_extraction: false,
+        strategy: None,
+        voice: None,
+        background: true,
+        force: false,
+    };
+    link.send_message(AddMsg::AddProgress(
+        "Fetching and converting the shared article...".to_string(),
+    ));
+
+    submit_url(link, submission);
+}
+
+/// Makes a submission of the URL in the form, with the options picked in the form. Returns `None`
+/// if there's no URL.
+fn url_submission() -> Option<ArticleUrlSubmission> {
     let url = get_elem_value(URL_FORM_ID);
 
     if url.is_empty() {
         gloo_utils::window()
             .alert_with_message("Must fill out the URL")
             .unwrap();
-        return;
+        return None;
     }
 
-    // Construct the submission and update the progress
-    let submission = ArticleUrlSubmission { url };
+    Some(ArticleUrlSubmission {
+        url,
+        style: get_selected_style(),
+        options: get_selected_options(),
+        split: false,
+        selector: None,
+        save_selector: false,
+        trust_extraction: false,
+        strategy: None,
+        voice: get_selected_voice(),
+        background: true,
+        force: false,
+    })
+}
+
+/// POSTs the article url to the server for fetching and conversion
+fn add_by_url_cb(link: Scope<Add>) {
+    let Some(submission) = url_submission() else {
+        return;
+    };
     link.send_message(AddMsg::AddProgress(
         "Fetching and converting article...".to_string(),
     ));
 
+    submit_url(link, submission);
+}
+
+/// Fetches the article at the given URL submission to show what would be read aloud
+fn preview_url(link: Scope<Add>, mut submission: ArticleUrlSubmission) {
+    link.send_message(AddMsg::AddProgress("Fetching article...".to_string()));
+    link.send_future(async move {
+        match preview_article_url(&mut submission).await {
+            Ok(preview) => AddMsg::ShowPreview(submission, preview),
+            Err(e) => AddMsg::SetError(e),
+        }
+    });
+}
+
+/// Previews the article at the URL in the form
+fn preview_by_url_cb(link: Scope<Add>) {
+    if let Some(submission) = url_submission() {
+        preview_url(link, submission);
+    }
+}
+
+/// Previews the submission again, with the strategy or selector the user picked instead
+fn repreview_cb(link: Scope<Add>, mut submission: ArticleUrlSubmission) {
+    let selector = get_elem_value(PREVIEW_SELECTOR_FORM_ID).trim().to_string();
+    if selector.is_empty() {
+        submission.selector = None;
+        submission.strategy = get_elem_value(PREVIEW_STRATEGY_FORM_ID).parse().ok();
+    } else {
+        submission.selector = Some(selector);
+        submission.strategy = None;
+    }
+    preview_url(link, submission);
+}
+
+/// Converts exactly the text that was previewed
+fn convert_preview_cb(
+    link: Scope<Add>,
+    mut submission: ArticleUrlSubmission,
+    preview: &ExtractionPreview,
+) {
+    submission.strategy = Some(preview.strategy);
+    submission.trust_extraction = true;
+    submission.save_selector =
+        submission.selector.is_some() && get_elem_checked(PREVIEW_SAVE_SELECTOR_FORM_ID);
+    link.send_message(AddMsg::ClearPreview);
+    link.send_message(AddMsg::AddProgress(
+        "Converting the previewed text...".to_string(),
+    ));
+
+    submit_url(link, submission);
+}
+
+/// Makes the given URL submission. If the article is already in the library, offers to convert it
+/// again, and if it's too long, offers to split it up, and resubmits. If the server is unsure which
+/// part of the page is the article, has the user pick.
+fn submit_url(link: Scope<Add>, mut submission: ArticleUrlSubmission) {
     tracing::debug!("Submitting {:?}", submission);
 
-    // Make the submission
+    let follow_link = link.clone();
     link.send_future(async move {
-        if let Err(e) = submit_article_url(&submission).await {
-            // On error, send the error
-            AddMsg::SetError(e)
-        } else {
-            // On success, say so
-            AddMsg::AddProgress("Success!".to_string())
+        let res = match submit_article_url(&submission).await {
+            Ok(Submitted::AlreadyAdded(meta)) if convert_again(&meta) => {
+                submission.force = true;
+                submit_article_url(&submission).await
+            }
+            res => res,
+        };
+        let res = match res {
+            Ok(Submitted::SplitOffered(offer)) if accept_split(&offer) => {
+                submission.split = true;
+                submit_article_url(&submission).await
+            }
+            res => res,
+        };
+        match res {
+            Ok(Submitted::ExtractionUncertain(choice)) => {
+                AddMsg::OfferExtractionChoice(submission, choice)
+            }
+            res => follow_submission(follow_link, res).await,
         }
     });
 }
 
+/// Resubmits the URL submission with the extraction candidate the user picked
+fn choose_extraction_cb(
+    link: Scope<Add>,
+    mut submission: ArticleUrlSubmission,
+    choice: &ExtractionChoice,
+) {
+    let picked = (0..choice.candidates.len())
+        .find(|i| get_elem_checked(&format!("{CANDIDATE_FORM_ID_PREFIX}-{i}")))
+        .and_then(|i| choice.candidates.get(i));
+    let picked = match picked {
+        Some(c) => c,
+        None => {
+            gloo_utils::window()
+                .alert_with_message("Must pick a part of the page")
+                .unwrap();
+            return;
+        }
+    };
+
+    // The extractor's own pick has no selector. Picking it means trusting the extractor.
+    match &picked.selector {
+        Some(selector) => {
+            submission.selector = Some(selector.clone());
+            submission.save_selector = get_elem_checked(SAVE_SELECTOR_FORM_ID);
+        }
+        None => submission.trust_extraction = true,
+    }
+    link.send_message(AddMsg::ClearExtractionChoice);
+    link.send_message(AddMsg::AddProgress(
+        "Converting the chosen text...".to_string(),
+    ));
+
+    submit_url(link, submission);
+}
+
 #[derive(Default)]
 pub(crate) struct Add {
     err: Option<AnyError>,
     progress: Vec<String>,
+    /// The ambient beds offered by the server
+    ambient_beds: Vec<AmbientBedInfo>,
+    /// The voices offered by the server
+    voices: Vec<VoiceInfo>,
+    /// The URL that was shared to the app, if any
+    shared_url: Option<String>,
+    /// A URL submission the server was unsure how to extract, and the candidates it offered
+    extraction_choice: Option<(ArticleUrlSubmission, ExtractionChoice)>,
+    /// A URL submission that's being previewed, and what would be read aloud
+    preview: Option<(ArticleUrlSubmission, ExtractionPreview)>,
+    /// The document whose text is in the title and body fields, if any
+    document: Option<ParsedDocument>,
+    /// The job converting the last submission, while it's converting
+    job: Option<JobInfo>,
 }
 
 pub enum AddMsg {
     SetError(AnyError),
     AddProgress(String),
+    SetAmbientBeds(Vec<AmbientBedInfo>),
+    SetVoices(Vec<VoiceInfo>),
+    OfferExtractionChoice(ArticleUrlSubmission, ExtractionChoice),
+    ClearExtractionChoice,
+    ShowPreview(ArticleUrlSubmission, ExtractionPreview),
+    ClearPreview,
+    /// Uploads the picked document for its text
+    ReadDocument,
+    SetDocument(ParsedDocument),
+    /// Fills in the title and body with the picked section of the document
+    PickSection,
+    SetJob(Option<JobInfo>),
+    /// Cancels the job converting the last submission
+    CancelJob,
+}
+
+impl Add {
+    /// Renders the progress of the job converting the last submission, if it's converting
+    fn render_job(&self, ctx: &Context<Self>) -> Html {
+        let job = match &self.job {
+            Some(job) => job,
+            None => return Html::default(),
+        };
+        // The progress bar is indeterminate until the article's text has been broken up
+        let progress = if job.chars_total > 0 {
+            html! {
+                <progress
+                    id={JOB_PROGRESS_ID}
+                    max={job.chars_total.to_string()}
+                    value={job.chars_done.to_string()}
+                />
+            }
+        } else {
+            html! { <progress id={JOB_PROGRESS_ID} /> }
+        };
+        let cancel = ctx.link().callback(|_| AddMsg::CancelJob);
+
+        html! {
+            <div class="field">
+                <label for={JOB_PROGRESS_ID}>{ status_str(job) }</label>
+                { progress }
+                <button onclick={cancel}>{ "Cancel" }</button>
+            </div>
+        }
+    }
+
+    /// Fills in the title and body fields with the given section of the document
+    fn fill_in_section(&self, index: usize) {
+        if let Some(section) = self.document.as_ref().and_then(|d| d.sections.get(index)) {
+            set_elem_value(TITLE_FORM_ID, &section.title);
+            set_elem_value(BODY_FORM_ID, &section.body);
+        }
+    }
+
+    /// Renders the picker for the section of the document to add, if it has more than one
+    fn render_section_picker(&self, ctx: &Context<Self>) -> Html {
+        let sections = match &self.document {
+            Some(doc) if doc.sections.len() > 1 => &doc.sections,
+            _ => return Html::default(),
+        };
+        let options = sections
+            .iter()
+            .enumerate()
+            .map(|(i, section)| {
+                html! { <option value={ i.to_string() }>{ section.title.clone() }</option> }
+            })
+            .collect::<Html>();
+        let pick = ctx.link().callback(|_| AddMsg::PickSection);
+
+        html! {
+            <div class="field">
+                <label for={SECTION_FORM_ID}>{ "Chapter:" }</label>
+                <select id={SECTION_FORM_ID} onchange={pick}>{ options }</select>
+            </div>
+        }
+    }
+
+    /// Renders the parts of the page the server offered as the article, for the user to pick from
+    fn render_extraction_choice(&self, ctx: &Context<Self>) -> Html {
+        let (submission, choice) = match &self.extraction_choice {
+            Some(c) => c,
+            None => return Html::default(),
+        };
+
+        let candidates = choice
+            .candidates
+            .iter()
+            .enumerate()
+            .map(|(i, candidate)| {
+                let id = format!("{CANDIDATE_FORM_ID_PREFIX}-{i}");
+                let name = match &candidate.selector {
+                    Some(selector) => html! { <code>{ selector.clone() }</code> },
+                    None => html! { <strong>{ "The automatic pick" }</strong> },
+                };
+                html! {
+                    <div class="field">
+                        <input type="radio" name="extraction-candidate" id={id.clone()} checked={i == 0} />
+                        <label for={id}>
+                            { name }
+                            { format!(" ({} words): ", candidate.words) }
+                            <q>{ format!("{}…", candidate.preview) }</q>
+                        </label>
+                    </div>
+                }
+            })
+            .collect::<Html>();
+
+        let link = ctx.link().clone();
+        let (submission, choice) = (submission.clone(), choice.clone());
+        let choose = Callback::from(move |_| {
+            choose_extraction_cb(link.clone(), submission.clone(), &choice)
+        });
+        let cancel = ctx.link().callback(|_| AddMsg::ClearExtractionChoice);
+
+        html! {
+            <fieldset>
+                <legend><h2>{ "Which part of the page is the article?" }</h2></legend>
+                { candidates }
+                <div class="field">
+                    <input type="checkbox" id={SAVE_SELECTOR_FORM_ID} />
+                    <label for={SAVE_SELECTOR_FORM_ID}>
+                        { "Use the same part for every article from this site" }
+                    </label>
+                </div>
+                <button onclick={choose}>{ "Convert" }</button>
+                <button onclick={cancel}>{ "Cancel" }</button>
+            </fieldset>
+        }
+    }
+
+    /// Renders the preview of what would be read aloud, with ways to find the article differently
+    fn render_preview(&self, ctx: &Context<Self>) -> Html {
+        let (submission, preview) = match &self.preview {
+            Some(p) => p,
+            None => return Html::default(),
+        };
+
+        let strategy_options = ExtractionStrategy::AUTOMATIC
+            .iter()
+            .map(|strategy| {
+                html! {
+                    <option value={ strategy.as_str() } selected={ *strategy == preview.strategy }>
+                        { strategy.description() }
+                    </option>
+                }
+            })
+            .collect::<Html>();
+        let selector_options = preview
+            .alternatives
+            .iter()
+            .filter_map(|c| c.selector.clone())
+            .map(|selector| html! { <option value={selector} /> })
+            .collect::<Html>();
+        let paragraphs = preview
+            .text
+            .lines()
+            .map(|line| html! { <p>{ line.to_string() }</p> })
+            .collect::<Html>();
+
+        let (link, link2) = (ctx.link().clone(), ctx.link().clone());
+        let (submission1, submission2) = (submission.clone(), submission.clone());
+        let preview2 = preview.clone();
+        let repreview = Callback::from(move |_| repreview_cb(link.clone(), submission1.clone()));
+        let convert = Callback::from(move |_| {
+            convert_preview_cb(link2.clone(), submission2.clone(), &preview2)
+        });
+        let cancel = ctx.link().callback(|_| AddMsg::ClearPreview);
+
+        html! {
+            <fieldset>
+                <legend><h2>{ format!("Preview: {}", preview.title) }</h2></legend>
+                <p>{ format!(
+                    "{} words, found with: {}",
+                    preview.words,
+                    preview.strategy.description()
+                ) }</p>
+                if !preview.looks_like_article {
+                    <p>{ "This doesn't look like an article. Try finding it another way below." }</p>
+                }
+                <blockquote class="extractionPreview">{ paragraphs }</blockquote>
+                <div class="field">
+                    <label for={PREVIEW_STRATEGY_FORM_ID}>{ "Find the article with:" }</label>
+                    <select id={PREVIEW_STRATEGY_FORM_ID}>{ strategy_options }</select>
+                </div>
+                <div class="field">
+                    <label for={PREVIEW_SELECTOR_FORM_ID}>
+                        { "Or the part of the page matching this CSS selector:" }
+                    </label>
+                    <input
+                        type="text"
+                        id={PREVIEW_SELECTOR_FORM_ID}
+                        list={PREVIEW_SELECTORS_LIST_ID}
+                        value={ submission.selector.clone().unwrap_or_default() }
+                    />
+                    <datalist id={PREVIEW_SELECTORS_LIST_ID}>{ selector_options }</datalist>
+                </div>
+                if submission.selector.is_some() {
+                    <div class="field">
+                        <input type="checkbox" id={PREVIEW_SAVE_SELECTOR_FORM_ID} />
+                        <label for={PREVIEW_SAVE_SELECTOR_FORM_ID}>
+                            { "Use the same selector for every article from this site" }
+                        </label>
+                    </div>
+                }
+                <button onclick={repreview}>{ "Preview again" }</button>
+                <button onclick={convert}>{ "Convert" }</button>
+                <button onclick={cancel}>{ "Cancel" }</button>
+            </fieldset>
+        }
+    }
 }
 
 impl Component for Add {
     type Message = AddMsg;
     type Properties = ();
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             AddMsg::SetError(e) => {
                 self.err = Some(e);
@@ -149,19 +963,119 @@ impl Component for Add {
             AddMsg::AddProgress(p) => {
                 self.progress.push(p);
             }
+            AddMsg::SetAmbientBeds(beds) => {
+                self.ambient_beds = beds;
+            }
+            AddMsg::SetVoices(voices) => {
+                self.voices = voices;
+            }
+            AddMsg::OfferExtractionChoice(submission, choice) => {
+                self.progress.push(
+                    "The article couldn't be found on the page with certainty. Pick it below."
+                        .to_string(),
+                );
+                self.extraction_choice = Some((submission, choice));
+            }
+            AddMsg::ClearExtractionChoice => {
+                self.extraction_choice = None;
+            }
+            AddMsg::ShowPreview(submission, preview) => {
+                self.err = None;
+                self.preview = Some((submission, preview));
+            }
+            AddMsg::ClearPreview => {
+                self.preview = None;
+            }
+            AddMsg::ReadDocument => {
+                let file = match utils::picked_file(DOCUMENT_FORM_ID) {
+                    Some(file) => file,
+                    None => {
+                        self.err = Some(anyhow!("Pick a document first"));
+                        return true;
+                    }
+                };
+                self.err = None;
+                self.progress.push(format!("Reading {}...", file.name()));
+                ctx.link().send_future(async move {
+                    match parse_document(file).await {
+                        Ok(doc) => AddMsg::SetDocument(doc),
+                        Err(e) => AddMsg::SetError(e),
+                    }
+                });
+            }
+            AddMsg::SetDocument(doc) => {
+                self.progress.push(format!(
+                    "Read the {}. Check the title and body below, then submit.",
+                    doc.source_type.description()
+                ));
+                self.document = Some(doc);
+                self.fill_in_section(0);
+            }
+            AddMsg::PickSection => {
+                let index = get_elem_value(SECTION_FORM_ID).parse().unwrap_or_default();
+                self.fill_in_section(index);
+                return false;
+            }
+            AddMsg::SetJob(job) => {
+                self.job = job;
+            }
+            AddMsg::CancelJob => {
+                if let Some(job) = &self.job {
+                    let id = job.id;
+                    ctx.link().send_future(async move {
+                        match control_job(id, JobAction::Cancel).await {
+                            Ok(()) => AddMsg::AddProgress("Cancelling...".to_string()),
+                            Err(e) => AddMsg::SetError(e),
+                        }
+                    });
+                }
+                return false;
+            }
         }
         true
     }
 
-    fn create(_ctx: &Context<Self>) -> Self {
-        Add::default()
+    fn create(ctx: &Context<Self>) -> Self {
+        // Kick off a future to get the ambient bed list
+        ctx.link().send_future(async move {
+            match fetch_ambient_beds().await {
+                Ok(beds) => AddMsg::SetAmbientBeds(beds),
+                Err(e) => AddMsg::SetError(e),
+            }
+        });
+        // And the voice list
+        ctx.link().send_future(async move {
+            match fetch_voices().await {
+                Ok(voices) => AddMsg::SetVoices(voices),
+                Err(e) => AddMsg::SetError(e),
+            }
+        });
+
+        // If a URL was shared to the app, convert it right away. Drop it from the address bar so
+        // that reloading the page doesn't submit it again.
+        let shared_url = shared_url();
+        if let Some(url) = &shared_url {
+            add_shared_url(ctx.link().clone(), url.clone());
+            if let Some(history) = ctx.link().history() {
+                history.replace(Route::Add);
+            }
+        }
+
+        Add {
+            shared_url,
+            ..Add::default()
+        }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let link = ctx.link().clone();
         let link2 = ctx.link().clone();
-        let add_text_callback = Callback::from(move |_| add_by_text_cb(link.clone()));
+        let source_type = self.document.as_ref().map(|doc| doc.source_type);
+        let add_text_callback = Callback::from(move |_| add_by_text_cb(link.clone(), source_type));
+        let read_document = ctx.link().callback(|_| AddMsg::ReadDocument);
         let add_url_callback = Callback::from(move |_| add_by_url_cb(link2.clone()));
+        let link3 = ctx.link().clone();
+        let preview_url_callback = Callback::from(move |_| preview_by_url_cb(link3.clone()));
 
         let err_str = self
             .err
@@ -169,23 +1083,185 @@ impl Component for Add {
             .map(|e| format!("{}", e))
             .unwrap_or("".to_string());
 
+        let style_options = SpeakingStyle::ALL
+            .iter()
+            .map(|style| {
+                html! {
+                    <option value={ style.as_str() } selected={ *style == SpeakingStyle::default() }>
+                        { style.description() }
+                    </option>
+                }
+            })
+            .collect::<Html>();
+        let frontmatter_options = Frontmatter::ALL
+            .iter()
+            .map(|f| {
+                html! {
+                    <option value={ f.as_str() } selected={ *f == Frontmatter::default() }>
+                        { f.description() }
+                    </option>
+                }
+            })
+            .collect::<Html>();
+
+        // Only show the ambient bed selector if the server offers any
+        let ambient_bed_field = if self.ambient_beds.is_empty() {
+            Html::default()
+        } else {
+            let bed_options = self
+                .ambient_beds
+                .iter()
+                .map(|bed| {
+                    html! {
+                        <option value={ bed.name.clone() }>{ bed.description.clone() }</option>
+                    }
+                })
+                .collect::<Html>();
+
+            html! {
+                <div class="field">
+                    <label for={AMBIENT_BED_FORM_ID}>{ "Background ambience:" }</label>
+                    <select id={AMBIENT_BED_FORM_ID}>
+                        <option value="" selected=true>{ "None" }</option>
+                        { bed_options }
+                    </select>
+                </div>
+            }
+        };
+
+        let encoding_options = ENCODING_PRESETS
+            .iter()
+            .enumerate()
+            .map(|(i, (description, _))| {
+                html! { <option value={ i.to_string() }>{ *description }</option> }
+            })
+            .collect::<Html>();
+
+        // The voice selector appears once the voice list arrives
+        let voice_field = if self.voices.is_empty() {
+            Html::default()
+        } else {
+            let voice_options = self
+                .voices
+                .iter()
+                .map(|voice| {
+                    let label = match &voice.language_code {
+                        Some(lang) => format!("{} ({lang})", voice.description),
+                        None => voice.description.clone(),
+                    };
+                    html! {
+                        <option value={ voice.name.clone() }>{ label }</option>
+                    }
+                })
+                .collect::<Html>();
+
+            html! {
+                <div class="field">
+                    <label for={VOICE_FORM_ID}>{ "Voice:" }</label>
+                    <select id={VOICE_FORM_ID}>
+                        <option value="" selected=true>
+                            { "Automatic (match the article's language)" }
+                        </option>
+                        { voice_options }
+                    </select>
+                </div>
+            }
+        };
+
         html! {
             <main>
                 <h1>{ "Add article" }</h1>
                 <p>{
                     "You may add an article either by providing a URL, or by pasting the title
-                    and body text"
+                    and body text, or by reading them from a document"
                 }</p>
+                { voice_field }
+                <div class="field">
+                    <label for={STYLE_FORM_ID}>{ "Speaking style:" }</label>
+                    <select id={STYLE_FORM_ID}>{ style_options }</select>
+                </div>
+                <div class="field">
+                    <label for={FRONTMATTER_FORM_ID}>{ "Read before the article:" }</label>
+                    <select id={FRONTMATTER_FORM_ID}>{ frontmatter_options }</select>
+                </div>
+                <div class="field">
+                    <input type="checkbox" id={OUTRO_FORM_ID} />
+                    <label for={OUTRO_FORM_ID}>{ "Say \"End of article\" at the end" }</label>
+                </div>
+                <div class="field">
+                    <input type="checkbox" id={KEY_POINTS_FORM_ID} />
+                    <label for={KEY_POINTS_FORM_ID}>
+                        { "Add a minute of key points at the end, if the server can pick them" }
+                    </label>
+                </div>
+                <div class="field">
+                    <label for={ENCODING_FORM_ID}>{ "Audio quality:" }</label>
+                    <select id={ENCODING_FORM_ID}>
+                        <option value="" selected=true>{ "Server default" }</option>
+                        { encoding_options }
+                    </select>
+                </div>
+                <div class="field">
+                    <label for={PARAGRAPH_GAP_FORM_ID}>{ "Pause between paragraphs (ms):" }</label>
+                    <input
+                        type="number"
+                        id={PARAGRAPH_GAP_FORM_ID}
+                        min={MIN_PAUSE_MS.to_string()}
+                        max={MAX_PAUSE_MS.to_string()}
+                        step={PAUSE_STEP_MS.to_string()}
+                        value={DEFAULT_PARAGRAPH_GAP_MS.to_string()}
+                    />
+                </div>
+                <div class="field">
+                    <label for={HEADING_PAUSE_FORM_ID}>{ "Pause after headings (ms):" }</label>
+                    <input
+                        type="number"
+                        id={HEADING_PAUSE_FORM_ID}
+                        min={MIN_PAUSE_MS.to_string()}
+                        max={MAX_PAUSE_MS.to_string()}
+                        step={PAUSE_STEP_MS.to_string()}
+                        value={DEFAULT_HEADING_PAUSE_MS.to_string()}
+                    />
+                </div>
+                { ambient_bed_field }
+                <div class="field">
+                    <input type="checkbox" id={SOFTEN_ASIDES_FORM_ID} />
+                    <label for={SOFTEN_ASIDES_FORM_ID}>
+                        { "Read (asides) and [footnotes] more quietly (experimental)" }
+                    </label>
+                </div>
                 <fieldset>
                     <legend><h2>{ "Add article by URL" }</h2></legend>
                     <div class="field">
                         <label for={URL_FORM_ID}>{ "Article URL:" }</label>
-                        <input type="text" id={URL_FORM_ID} required=true />
+                        <input
+                            type="text"
+                            id={URL_FORM_ID}
+                            value={self.shared_url.clone().unwrap_or_default()}
+                            required=true
+                        />
                     </div>
                     <button type="submit" onclick={add_url_callback}>{ "Submit" }</button>
+                    <button type="button" onclick={preview_url_callback}>
+                        { "Preview" }
+                    </button>
                 </fieldset>
+                { self.render_extraction_choice(ctx) }
+                { self.render_preview(ctx) }
                 <fieldset>
                     <legend><h2>{ "Add article by text" }</h2></legend>
+                    <div class="field">
+                        <label for={DOCUMENT_FORM_ID}>
+                            { "Read from a document (.txt, .epub, .pdf, or .docx):" }
+                        </label>
+                        <input
+                            type="file"
+                            id={DOCUMENT_FORM_ID}
+                            accept=".txt,.epub,.pdf,.docx"
+                        />
+                        <button type="button" onclick={read_document}>{ "Read" }</button>
+                    </div>
+                    { self.render_section_picker(ctx) }
                     <div class="field">
                         <label for={TITLE_FORM_ID}>{ "Article title:" }</label>
                         <input
@@ -199,6 +1275,13 @@ impl Component for Add {
                         <label for={BODY_FORM_ID}>{ "Article body:" }</label>
                         <textarea id={BODY_FORM_ID} rows="10" cols="33" required=true></textarea>
                     </div>
+                    <div class="field">
+                        <input type="checkbox" id={ENCRYPT_FORM_ID} />
+                        <label for={ENCRYPT_FORM_ID}>
+                            { "Encrypt on this device. The server won't keep the text or audio, \
+                            but only this device can play it, and it can't be searched." }
+                        </label>
+                    </div>
                     <div>
                         <button type="submit" onclick={add_text_callback}>{ "Submit" }</button>
                     </div>
@@ -207,12 +1290,26 @@ impl Component for Add {
                     <p>
                         { self.progress.join(" ") }
                     </p>
+                    { self.render_job(ctx) }
                 </section>
                 <section role="alert" id="errors" title="errors">
                     <p style={ "color: red;" }>
                         { err_str }
                     </p>
                 </section>
+                <fieldset>
+                    <legend><h2>{ "Add from anywhere" }</h2></legend>
+                    <p>{
+                        "On a phone, install this app to the home screen, then share pages to it
+                        from the browser. On a computer, drag this link to the bookmarks bar, and
+                        click it on any article: "
+                    }<a href={ bookmarklet() }>{ "Read to my shoe" }</a></p>
+                </fieldset>
+                <Inbox />
+                <Feeds />
+                <Import />
+                <Jobs />
+                <VoiceComparison />
             </main>
         }
     }
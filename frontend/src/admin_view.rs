@@ -0,0 +1,289 @@
+//! The server storage panel, for admins. It lists the audio of every library on the server, with its
+//! size and when it was last fetched, and deletes articles from any library to free up space. It also
+//! lists the pages in the server's cache of fetched pages, and clears them.
+
+use crate::utils;
+use common::{AdminDeletion, AudioUsage, PageCacheClearing, PageCacheUsage, StoredAudio};
+
+use anyhow::{bail, Error as AnyError};
+use yew::prelude::*;
+
+/// Fetches the audio of every library
+async fn fetch_usage() -> Result<AudioUsage, AnyError> {
+    let resp = utils::get("/api/admin/audio")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching stored audio"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing stored audio JSON"))
+}
+
+/// Deletes the given article from the given library on the server
+async fn delete_article(library: Option<String>, id: String) -> Result<(), AnyError> {
+    let resp = utils::post("/api/admin/delete-article")
+        .json(&AdminDeletion { library, id })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error deleting article"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+/// Fetches the list of cached pages
+async fn fetch_page_cache() -> Result<PageCacheUsage, AnyError> {
+    let resp = utils::get("/api/admin/page-cache")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching cached pages"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing cached pages JSON"))
+}
+
+/// Deletes the page at the given URL from the server's page cache, or every page if no URL is given
+async fn clear_page_cache(url: Option<String>) -> Result<(), AnyError> {
+    let resp = utils::post("/api/admin/clear-page-cache")
+        .json(&PageCacheClearing { url })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error clearing the page cache"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+/// Formats the given number of bytes in megabytes
+fn format_megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1e6)
+}
+
+pub(crate) enum AdminMsg {
+    /// Fetches the stored audio
+    Refresh,
+    /// Sets the stored audio
+    SetUsage(AudioUsage),
+    /// Deletes the given article
+    Delete(StoredAudio),
+    /// Sets the cached pages
+    SetPageCache(PageCacheUsage),
+    /// Deletes the page at the given URL from the page cache, or every page if there's no URL
+    ClearPageCache(Option<String>),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+/// The server storage panel on the main page. It's only shown to admins.
+#[derive(Default)]
+pub(crate) struct ServerStorage {
+    usage: AudioUsage,
+    page_cache: PageCacheUsage,
+    err: Option<AnyError>,
+}
+
+impl Component for ServerStorage {
+    type Message = AdminMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        ServerStorage::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            AdminMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    match fetch_usage().await {
+                        Ok(usage) => AdminMsg::SetUsage(usage),
+                        Err(e) => AdminMsg::SetError(e),
+                    }
+                });
+                ctx.link().send_future(async move {
+                    match fetch_page_cache().await {
+                        Ok(page_cache) => AdminMsg::SetPageCache(page_cache),
+                        Err(e) => AdminMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            AdminMsg::SetUsage(mut usage) => {
+                // Least recently fetched first, since that's what's deleted first
+                usage.articles.sort_by_key(|a| a.last_fetched);
+                self.usage = usage;
+                self.err = None;
+            }
+
+            AdminMsg::Delete(article) => {
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message(&format!("Delete \"{}\" from the server?", article.title))
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+                ctx.link().send_future(async move {
+                    match delete_article(article.library, article.id).await {
+                        Ok(()) => AdminMsg::Refresh,
+                        Err(e) => AdminMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            AdminMsg::SetPageCache(mut page_cache) => {
+                // Most recently fetched first
+                page_cache
+                    .pages
+                    .sort_by_key(|p| std::cmp::Reverse(p.fetched));
+                self.page_cache = page_cache;
+            }
+
+            AdminMsg::ClearPageCache(url) => {
+                ctx.link().send_future(async move {
+                    match clear_page_cache(url).await {
+                        Ok(()) => AdminMsg::Refresh,
+                        Err(e) => AdminMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            AdminMsg::SetError(e) => {
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let total_bytes =
+            self.usage.transcoded_bytes + self.usage.articles.iter().map(|a| a.bytes).sum::<u64>();
+        let usage = match self.usage.max_bytes {
+            Some(max_bytes) if max_bytes > 0 => html! {
+                <p>
+                    <meter value={ total_bytes.to_string() } max={ max_bytes.to_string() } />
+                    { format!(
+                        " Using {} of {}. The least recently fetched audio is deleted to stay under.",
+                        format_megabytes(total_bytes),
+                        format_megabytes(max_bytes),
+                    ) }
+                </p>
+            },
+            _ => html! {
+                <p>{ format!(
+                    "Using {}, with no limit",
+                    format_megabytes(total_bytes),
+                ) }</p>
+            },
+        };
+
+        let rows = self
+            .usage
+            .articles
+            .iter()
+            .map(|article| {
+                let library = article.library.as_deref().unwrap_or("shared");
+                let delete = {
+                    let article = article.clone();
+                    link.callback(move |_| AdminMsg::Delete(article.clone()))
+                };
+                html! {
+                    <tr>
+                        <td dir="auto">{ &article.title }</td>
+                        <td>{ library }</td>
+                        <td>{ format_megabytes(article.bytes) }</td>
+                        <td>{ format!("Fetched {}", utils::format_date(article.last_fetched)) }</td>
+                        <td>
+                            <button
+                                onclick={delete}
+                                aria-label={ format!("Delete {} from the server", article.title) }
+                            >
+                                { "🗑" }
+                            </button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+        let cached_rows = self
+            .page_cache
+            .pages
+            .iter()
+            .map(|page| {
+                let remove = {
+                    let url = page.url.clone();
+                    link.callback(move |_| AdminMsg::ClearPageCache(Some(url.clone())))
+                };
+                html! {
+                    <tr>
+                        <td><a href={ page.url.clone() }>{ &page.url }</a></td>
+                        <td>{ format_megabytes(page.bytes) }</td>
+                        <td>{ format!("Fetched {}", utils::format_date(page.fetched)) }</td>
+                        <td>
+                            <button
+                                onclick={remove}
+                                aria-label={ format!("Remove {} from the page cache", page.url) }
+                            >
+                                { "🗑" }
+                            </button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+        let page_cache = if self.page_cache.ttl_secs == 0 {
+            html! { <p>{ "Fetched pages aren't cached" }</p> }
+        } else {
+            let cached_bytes = self.page_cache.pages.iter().map(|p| p.bytes).sum();
+            let clear_all = link.callback(|_| AdminMsg::ClearPageCache(None));
+            html! {
+                <details>
+                    <summary>{ format!(
+                        "Page cache: {} pages, {}, kept for {} minutes",
+                        self.page_cache.pages.len(),
+                        format_megabytes(cached_bytes),
+                        self.page_cache.ttl_secs / 60,
+                    ) }</summary>
+                    <button onclick={clear_all}>{ "Clear the page cache" }</button>
+                    <table aria-label="Cached pages">
+                        { cached_rows }
+                    </table>
+                </details>
+            }
+        };
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+        let ontoggle = link.callback(|_| AdminMsg::Refresh);
+
+        html! {
+            <details class="serverStorage" {ontoggle}>
+                <summary>{ "Server storage" }</summary>
+                { usage }
+                <p>{ format!(
+                    "{} articles, plus {} of transcoded copies",
+                    self.usage.articles.len(),
+                    format_megabytes(self.usage.transcoded_bytes),
+                ) }</p>
+                <table aria-label="Audio on the server">
+                    { rows }
+                </table>
+                { page_cache }
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </details>
+        }
+    }
+}
@@ -1,5 +1,12 @@
 use crate::{
-    add_view::Add, library_view::Library, main_view::Main, player_view::Player, queue_view::Queue,
+    add_view::Add,
+    dev_view::DevView,
+    library_view::{Browse, Library, ViewStates},
+    main_view::Main,
+    player_view::Player,
+    playlists_view::Playlists,
+    queue_view::Queue,
+    reminders_view::Reminders,
     WeakComponentLink,
 };
 
@@ -12,16 +19,46 @@ pub enum Route {
     Home,
     #[at("/add")]
     Add,
+    /// The developer view. Nothing links to it.
+    #[at("/dev")]
+    Dev,
+    #[at("/authors")]
+    Authors,
+    /// The articles by an author. The name is URL-encoded.
+    #[at("/authors/:name")]
+    Author { name: String },
+    #[at("/publications")]
+    Publications,
+    /// The articles from a publication. The name is URL-encoded.
+    #[at("/publications/:name")]
+    Publication { name: String },
+    #[at("/playlists")]
+    Playlists,
+    /// The articles matching a smart playlist. The name is URL-encoded.
+    #[at("/playlists/:name")]
+    Playlist { name: String },
     #[not_found]
     #[at("/404")]
     NotFound,
 }
 
+/// Decodes a URL-encoded route parameter. If it isn't valid, it's used as is.
+fn decode(param: &str) -> String {
+    urlencoding::decode(param)
+        .map(|p| p.into_owned())
+        .unwrap_or_else(|_| param.to_string())
+}
+
 #[derive(Default)]
 pub struct App {
     player_link: WeakComponentLink<Player>,
     queue_link: WeakComponentLink<Queue>,
     library_link: WeakComponentLink<Library>,
+    reminders_link: WeakComponentLink<Reminders>,
+    playlists_link: WeakComponentLink<Playlists>,
+    /// How the listener left each part of the library. It's kept here so it's still around after
+    /// visiting the Add page.
+    view_states: ViewStates,
 }
 
 impl Component for App {
@@ -40,20 +77,46 @@ impl Component for App {
         let player_link_copy = self.player_link.clone();
         let queue_link_copy = self.queue_link.clone();
         let library_link_copy = self.library_link.clone();
+        let reminders_link_copy = self.reminders_link.clone();
+        let playlists_link_copy = self.playlists_link.clone();
+        let view_states_copy = self.view_states.clone();
 
         let switch = move |routes: &Route| {
             let player_link = player_link_copy.clone();
             let queue_link = queue_link_copy.clone();
             let library_link = library_link_copy.clone();
+            let reminders_link = reminders_link_copy.clone();
+            let playlists_link = playlists_link_copy.clone();
+            let view_states = view_states_copy.clone();
+
+            // Browsing the library happens on the main page, so the player and queue stay around
+            let browse = match routes {
+                Route::Home => Browse::All,
+                Route::Authors => Browse::Authors,
+                Route::Author { name } => Browse::Author(decode(name)),
+                Route::Publications => Browse::Publications,
+                Route::Publication { name } => Browse::Publication(decode(name)),
+                Route::Playlists => Browse::Playlists,
+                Route::Playlist { name } => Browse::Playlist(decode(name)),
+                Route::Add => {
+                    return html! {
+                        <Add />
+                    }
+                }
+                Route::Dev => return html! { <DevView /> },
+                Route::NotFound => return html! { <h1>{ "404" }</h1> },
+            };
 
-            match routes {
-                Route::Home => html! {
-                    <Main {player_link} {queue_link} {library_link} />
-                },
-                Route::Add => html! {
-                    <Add />
-                },
-                Route::NotFound => html! { <h1>{ "404" }</h1> },
+            html! {
+                <Main
+                    {player_link}
+                    {queue_link}
+                    {library_link}
+                    {reminders_link}
+                    {playlists_link}
+                    {browse}
+                    {view_states}
+                />
             }
         };
 
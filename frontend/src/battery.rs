@@ -0,0 +1,99 @@
+//! Saving battery when it's low. Where the browser has the Battery Status API, the app does less in
+//! the background while the battery is low and not charging: unfinished downloads aren't resumed on
+//! their own, and the player saves its state less often. The listener can turn this off.
+
+use std::rc::Rc;
+
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::BatteryManager;
+
+/// The battery level, as a fraction of full, at or below which the battery counts as low
+const LOW_BATTERY_LEVEL: f64 = 0.2;
+
+/// The localStorage key that records the battery saver being turned off
+const SAVER_OFF_KEY: &str = "battery-saver-off";
+
+/// Returns the battery, or `None` if the browser doesn't say
+async fn get_battery() -> Option<BatteryManager> {
+    // navigator.getBattery() only exists in some browsers, so look it up rather than binding it
+    let navigator = gloo_utils::window().navigator();
+    let get_battery: Function = Reflect::get(&navigator, &JsValue::from_str("getBattery"))
+        .ok()?
+        .dyn_into()
+        .ok()?;
+    let promise: Promise = get_battery.call0(&navigator).ok()?.dyn_into().ok()?;
+    JsFuture::from(promise).await.ok()?.dyn_into().ok()
+}
+
+/// Returns whether the given battery is low and not charging
+fn battery_is_low(battery: &BatteryManager) -> bool {
+    !battery.charging() && battery.level() <= LOW_BATTERY_LEVEL
+}
+
+/// Returns whether the battery saver is on, i.e., the listener hasn't turned it off
+pub(crate) fn saver_enabled() -> bool {
+    let storage = gloo_utils::window().local_storage().ok().flatten();
+    let off = storage.and_then(|s| s.get_item(SAVER_OFF_KEY).ok().flatten());
+    off.is_none()
+}
+
+/// Turns the battery saver on or off
+pub(crate) fn set_saver_enabled(enabled: bool) {
+    let storage = match gloo_utils::window().local_storage().ok().flatten() {
+        Some(storage) => storage,
+        None => return,
+    };
+    let res = if enabled {
+        storage.remove_item(SAVER_OFF_KEY)
+    } else {
+        storage.set_item(SAVER_OFF_KEY, "true")
+    };
+    if let Err(e) = res {
+        tracing::error!("Couldn't save the battery saver setting: {:?}", e);
+    }
+}
+
+/// Returns whether the app should save battery, i.e., the battery saver is on, and the battery is
+/// low and not charging
+pub(crate) async fn should_save() -> bool {
+    if !saver_enabled() {
+        return false;
+    }
+    match get_battery().await {
+        Some(battery) => battery_is_low(&battery),
+        None => false,
+    }
+}
+
+/// Watches the battery, so the app can do less while it's low
+pub(crate) struct BatteryMonitor {
+    _cb: Rc<Closure<dyn Fn()>>,
+}
+
+impl BatteryMonitor {
+    /// Starts watching the battery. `on_change` is called with whether the app should save battery
+    /// once it's known, and every time the battery's level or charging changes.
+    pub(crate) fn new(on_change: impl Fn(bool) + 'static) -> BatteryMonitor {
+        let on_change = Rc::new(on_change);
+        let cb: Rc<Closure<dyn Fn()>> = Rc::new(Closure::new(move || {
+            let on_change = on_change.clone();
+            spawn_local(async move { on_change(should_save().await) });
+        }));
+
+        let listener = cb.clone();
+        spawn_local(async move {
+            let battery = match get_battery().await {
+                Some(battery) => battery,
+                None => return,
+            };
+            let func: &Function = listener.as_ref().as_ref().unchecked_ref();
+            battery.set_onlevelchange(Some(func));
+            battery.set_onchargingchange(Some(func));
+            func.call0(&JsValue::NULL).ok();
+        });
+
+        BatteryMonitor { _cb: cb }
+    }
+}
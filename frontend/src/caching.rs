@@ -1,25 +1,40 @@
 use crate::{
-    player_view::{ArticleState, PlayerState},
+    downloads::PartialDownload,
+    history_view::ListeningSession,
+    moments_view::Moment,
+    platform,
+    player_view::{ArticleState, PlayCounts, PlayerState, SpeedSample},
+    playlists_view::Playlist,
     queue_view::{ArticleId, CachedArticle, Queue, QueueEntry},
+    servers,
+    smart_playlist::SmartPlaylist,
+    utils,
 };
 
-use std::{cell::RefCell, sync::Arc};
+use common::{ArticleMetadata, ArticleTranscript, LibraryCatalog};
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 
 use anyhow::{anyhow, bail, Error as AnyError};
 use gloo_utils::window;
 use ringbuffer::AllocRingBuffer;
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::{closure::Closure, JsCast, JsValue};
 use wasm_bindgen_futures::{spawn_local, JsFuture};
 use web_sys::{
-    Blob, BlobPropertyBag, Event, IdbDatabase, IdbObjectStore, IdbObjectStoreParameters,
-    IdbRequest, IdbTransactionMode, RegistrationOptions,
+    Blob, CryptoKey, Event, IdbDatabase, IdbObjectStore, IdbObjectStoreParameters, IdbRequest,
+    IdbTransactionMode, RegistrationOptions,
 };
 
-// TODO Fixme: This path is only valid in production mode
-const SERVICE_WORKER_PATH: &str = "/assets/service-worker.js";
+// TODO Fixme: This path is only valid in production mode. It's relative to the app's base path.
+const SERVICE_WORKER_PATH: &str = "assets/service-worker.js";
 
 const DB_NAME: &str = "readtomyshoe";
-const DB_VERSION: u32 = 1;
+const DB_VERSION: u32 = 10;
 
 /// Name for the table that holds article information
 const ARTICLES_TABLE: &str = "articles";
@@ -35,12 +50,92 @@ const QUEUE_TABLE: &str = "queue";
 /// playing article, and playback speed
 const PLAYER_STATE_TABLE: &str = "player-state";
 
+/// Name for the table that holds the IDs of the articles that have ever been played. Added in v2.
+const LISTENED_TABLE: &str = "listened";
+
+/// Name for the table that holds the user's smart playlists, keyed by name. Added in v2.
+const SMART_PLAYLISTS_TABLE: &str = "smart-playlists";
+
+/// Name for the table that holds the speed every article was listened at, keyed by ID. Added in v3.
+const LISTENING_SPEEDS_TABLE: &str = "listening-speeds";
+
+/// Name for the table that holds the downloads that haven't finished, keyed by article ID. Added in
+/// v4.
+const DOWNLOADS_TABLE: &str = "downloads";
+
+/// Name for the table that holds the chunks of audio the unfinished downloads have received so far.
+/// Added in v4.
+const DOWNLOAD_CHUNKS_TABLE: &str = "download-chunks";
+
+/// Name for the table that holds this device's encryption keys, keyed by name. Added in v5.
+const KEYS_TABLE: &str = "keys";
+
+/// Name for the table that holds every listening session, keyed by article ID and start time. Added
+/// in v6.
+const LISTENING_SESSIONS_TABLE: &str = "listening-sessions";
+
+/// Name for the table that holds the user's playlists, keyed by ID. Added in v7.
+const PLAYLISTS_TABLE: &str = "playlists";
+
+/// Name for the table that holds the metadata of every article in the library, keyed by ID, so the
+/// library can be browsed offline. Added in v8.
+const ARTICLE_METADATA_TABLE: &str = "article-metadata";
+
+/// Name for the table that holds article transcripts, keyed by ID. These are kept apart from the
+/// audio, so an article's text is there whether or not its audio is. Added in v8.
+const TRANSCRIPTS_TABLE: &str = "transcripts";
+
+/// Name for the table that holds how many times each part of every article has been played, keyed
+/// by ID. Added in v9.
+const PLAY_COUNTS_TABLE: &str = "play-counts";
+
+/// Name for the table that holds the moments the listener marked, keyed by article ID and when they
+/// were marked. Added in v10.
+const MOMENTS_TABLE: &str = "moments";
+
+/// Every table, in the order they were added, for the developer view
+pub(crate) const TABLES: &[&str] = &[
+    ARTICLES_TABLE,
+    ARTICLE_STATE_TABLE,
+    QUEUE_TABLE,
+    PLAYER_STATE_TABLE,
+    LISTENED_TABLE,
+    SMART_PLAYLISTS_TABLE,
+    LISTENING_SPEEDS_TABLE,
+    DOWNLOADS_TABLE,
+    DOWNLOAD_CHUNKS_TABLE,
+    KEYS_TABLE,
+    LISTENING_SESSIONS_TABLE,
+    PLAYLISTS_TABLE,
+    ARTICLE_METADATA_TABLE,
+    TRANSCRIPTS_TABLE,
+    PLAY_COUNTS_TABLE,
+    MOMENTS_TABLE,
+];
+
+/// Returns whether the developer view may clear the given table. The keys table holds the key
+/// that encrypts the encrypted library. It can't be exported, so clearing it would leave every
+/// encrypted article on the server unreadable for good.
+pub(crate) fn is_clearable(table: &str) -> bool {
+    table != KEYS_TABLE
+}
+
+/// The key, in the keys table, of the key that encrypts the articles in the encrypted library
+const DEVICE_KEY_NAME: &str = "device";
+
+/// The localStorage key of when the saved catalog was last brought up to date, in seconds since the
+/// Unix epoch by the server's clock
+const CATALOG_SYNCED_AT_KEY: &str = "catalog-synced-at";
+
 /// The queue table only holds one value, and that's the current queue
 const QUEUE_GLOBAL_KEY: f64 = 0.0;
 
 /// The player state table only holds one value, and that's the current player's state
 const PLAYER_STATE_GLOBAL_KEY: f64 = 0.0;
 
+/// The localStorage key the eviction policy is kept under
+const EVICTION_POLICY_KEY: &str = "eviction-policy";
+
 /// Registers service_worker.js to do all the caching for this site. See service_worker.js for more
 /// details.
 pub fn register_service_worker() {
@@ -81,11 +176,6 @@ fn jsvalue_to_str(v: JsValue) -> String {
         .unwrap_or("[JS error not displayable]".to_string())
 }
 
-/// A helper function for methods that return JsValue as an error type
-fn wrap_jserror(context_str: &'static str, v: JsValue) -> AnyError {
-    anyhow!("{context_str}: {:?}", v)
-}
-
 // A thread-local place to save the callbacks associated with each get_db() call. We support 8
 // concurrent calls to the current DB before the callbacks (4 per call) start getting overwritten.
 thread_local!(
@@ -97,11 +187,11 @@ thread_local!(
 pub(crate) async fn get_db() -> Result<IdbDatabase, AnyError> {
     let factory = window()
         .indexed_db()
-        .map_err(|e| wrap_jserror("couldn't get db factory", e))?
+        .map_err(|e| utils::wrap_jserror("couldn't get db factory", e))?
         .unwrap();
     let db_req = factory
-        .open_with_u32(DB_NAME, DB_VERSION)
-        .map_err(|e| wrap_jserror("couldn't open db", e))?;
+        .open_with_u32(&servers::scoped_name(DB_NAME), DB_VERSION)
+        .map_err(|e| utils::wrap_jserror("couldn't open db", e))?;
 
     // Make conditional vars to notify of the four possible events for an IdbOpenDbRequest:
     // success, error, upgradeneeded, and blocked
@@ -145,7 +235,7 @@ pub(crate) async fn get_db() -> Result<IdbDatabase, AnyError> {
         _ = success_var.notified() => {
             let db = db_req
                 .result()
-                .map_err(|e| wrap_jserror("couldn't get db from IdbOpenDbRequest", e))?
+                .map_err(|e| utils::wrap_jserror("couldn't get db from IdbOpenDbRequest", e))?
                 .into();
             Ok(db)
         },
@@ -156,12 +246,12 @@ pub(crate) async fn get_db() -> Result<IdbDatabase, AnyError> {
             Err(AnyError::msg(err_str))
         }
 
-        // Upgradeneeded happens the first time the DB is created. In this case, we need to
-        // initialize the object stores
+        // Upgradeneeded happens the first time the DB is created, and when DB_VERSION goes up. In
+        // this case, we need to create the object stores that don't exist yet
         _ = upgradeneeded_var.notified() => {
             let db = db_req
                 .result()
-                .map_err(|e| wrap_jserror("couldn't get db from IdbOpenDbRequest", e))?
+                .map_err(|e| utils::wrap_jserror("couldn't get db from IdbOpenDbRequest", e))?
                 .into();
             initialize_db(&db).await?;
             Ok(db)
@@ -172,11 +262,24 @@ pub(crate) async fn get_db() -> Result<IdbDatabase, AnyError> {
     }
 }
 
-/// Initializes the database with two object stores:
-///     articles - Stores CachedArticle objects
-///     pos - Stores a single CachedArticlePosition object. This contains global state about
-///           the order of the articles in the queue, the current article being played, and the
-///           current timestamp
+/// Initializes the database with the object stores that don't already exist:
+///     articles - Stores the audio of CachedArticle objects
+///     article-states - Stores the ArticleState of every cached article
+///     queue - Stores the single Queue object
+///     player-state - Stores the single PlayerState object. This contains the current article
+///                    being played, and the playback speed
+///     listened - Stores the IDs of every article that's been played (v2)
+///     smart-playlists - Stores SmartPlaylist objects (v2)
+///     listening-speeds - Stores the SpeedSample of every article that's been listened to (v3)
+///     downloads - Stores the PartialDownload of every unfinished download (v4)
+///     download-chunks - Stores the audio chunks received by unfinished downloads (v4)
+///     keys - Stores this device's CryptoKeys, which can't be exported (v5)
+///     listening-sessions - Stores the ListeningSession of every time an article was played (v6)
+///     playlists - Stores Playlist objects, including the deleted ones (v7)
+///     article-metadata - Stores the ArticleMetadata of every article in the library (v8)
+///     transcripts - Stores the ArticleTranscript of articles downloaded or read (v8)
+///     play-counts - Stores the PlayCounts of every article that's been played (v9)
+///     moments - Stores the Moment objects the listener marked (v10)
 async fn initialize_db(db: &IdbDatabase) -> Result<(), AnyError> {
     tracing::trace!("Initializing DB");
 
@@ -200,16 +303,51 @@ async fn initialize_db(db: &IdbDatabase) -> Result<(), AnyError> {
     let mut pos_params = IdbObjectStoreParameters::new();
     pos_params.auto_increment(false).key_path(None);
 
-    // This will error if "queue" already exists! TODO: Handle this before trying to do a database
-    // schema update.
-    db.create_object_store_with_optional_parameters(ARTICLES_TABLE, &articles_params)
-        .map_err(|e| wrap_jserror("couldn't make articles table", e))?;
-    db.create_object_store_with_optional_parameters(ARTICLE_STATE_TABLE, &articles_params)
-        .map_err(|e| wrap_jserror("couldn't make article states table", e))?;
-    db.create_object_store_with_optional_parameters(QUEUE_TABLE, &queue_params)
-        .map_err(|e| wrap_jserror("couldn't make queue table", e))?;
-    db.create_object_store_with_optional_parameters(PLAYER_STATE_TABLE, &pos_params)
-        .map_err(|e| wrap_jserror("couldn't make pos table", e))?;
+    // The smart playlists table is keyed by name
+    let mut smart_playlists_params = IdbObjectStoreParameters::new();
+    smart_playlists_params
+        .auto_increment(false)
+        .key_path(Some(&JsValue::from_str("name")));
+
+    // The listening sessions table is keyed by article ID and start time
+    let mut sessions_params = IdbObjectStoreParameters::new();
+    sessions_params
+        .auto_increment(false)
+        .key_path(Some(&JsValue::from_str("key")));
+
+    // Download chunks are keyed by their article ID and their index
+    let mut download_chunks_params = IdbObjectStoreParameters::new();
+    download_chunks_params.auto_increment(false).key_path(None);
+
+    // Creating a table that already exists is an error, so only make the ones that are missing.
+    // This is what lets older databases be upgraded.
+    let existing_tables = db.object_store_names();
+    let tables = [
+        (ARTICLES_TABLE, &articles_params),
+        (ARTICLE_STATE_TABLE, &article_states_params),
+        (QUEUE_TABLE, &queue_params),
+        (PLAYER_STATE_TABLE, &pos_params),
+        (LISTENED_TABLE, &article_states_params),
+        (SMART_PLAYLISTS_TABLE, &smart_playlists_params),
+        (LISTENING_SPEEDS_TABLE, &article_states_params),
+        (DOWNLOADS_TABLE, &article_states_params),
+        (DOWNLOAD_CHUNKS_TABLE, &download_chunks_params),
+        (KEYS_TABLE, &queue_params),
+        (LISTENING_SESSIONS_TABLE, &sessions_params),
+        (PLAYLISTS_TABLE, &article_states_params),
+        (ARTICLE_METADATA_TABLE, &article_states_params),
+        (TRANSCRIPTS_TABLE, &queue_params),
+        (PLAY_COUNTS_TABLE, &article_states_params),
+        (MOMENTS_TABLE, &sessions_params),
+    ];
+    for (table_name, params) in tables {
+        if existing_tables.contains(table_name) {
+            continue;
+        }
+        db.create_object_store_with_optional_parameters(table_name, params)
+            .map_err(|e| utils::wrap_jserror("couldn't make table", e))
+            .map_err(|e| e.context(format!("couldn't make {table_name} table")))?;
+    }
 
     Ok(())
 }
@@ -242,9 +380,9 @@ where
     let table = get_db()
         .await?
         .transaction_with_str_and_mode(table_name, transaction_mode)
-        .map_err(|e| wrap_jserror("couldn't start transaction", e))?
+        .map_err(|e| utils::wrap_jserror("couldn't start transaction", e))?
         .object_store(table_name)
-        .map_err(|e| wrap_jserror("couldn't get object store from transaction", e))?;
+        .map_err(|e| utils::wrap_jserror("couldn't get object store from transaction", e))?;
 
     tracing::trace!("Got DB handle");
 
@@ -279,7 +417,7 @@ where
     // Wait for an event to trigger, and cancel the remaining branches
     tokio::select! {
         _ = success_var.notified() => {
-            req.result().map_err(|e| wrap_jserror("DB succeeded but returned error", e))
+            req.result().map_err(|e| utils::wrap_jserror("DB succeeded but returned error", e))
         },
         e = error_rx.recv() => {
             bail!("{:?}", e)
@@ -293,7 +431,7 @@ pub(crate) async fn table_put(table_name: &str, val: &JsValue) -> Result<String,
     let table_op = |table: &IdbObjectStore| {
         table
             .put(val)
-            .map_err(|e| wrap_jserror("couldn't insert value to table", e))
+            .map_err(|e| utils::wrap_jserror("couldn't insert value to table", e))
     };
 
     // Run the operation
@@ -316,7 +454,7 @@ pub(crate) async fn table_put_with_key(
     let table_op = |table: &IdbObjectStore| {
         table
             .put_with_key(val, key)
-            .map_err(|e| wrap_jserror("couldn't insert value to table", e))
+            .map_err(|e| utils::wrap_jserror("couldn't insert value to table", e))
     };
 
     // Run the operation
@@ -332,7 +470,7 @@ pub(crate) async fn table_delete(table_name: &str, key: &str) -> Result<(), AnyE
     let table_op = |table: &IdbObjectStore| {
         table
             .delete(&JsValue::from_str(key))
-            .map_err(|e| wrap_jserror("couldn't save value to table", e))
+            .map_err(|e| utils::wrap_jserror("couldn't save value to table", e))
     };
 
     // Run the operation
@@ -351,7 +489,7 @@ pub(crate) async fn table_get(table_name: &str, key: &JsValue) -> Result<JsValue
     let table_op = |table: &IdbObjectStore| {
         table
             .get(key)
-            .map_err(|e| wrap_jserror("couldn't get from table", e))
+            .map_err(|e| utils::wrap_jserror("couldn't get from table", e))
     };
 
     // Run the operation
@@ -375,7 +513,7 @@ pub(crate) async fn table_get_keys(table_name: &str) -> Result<Vec<JsValue>, Any
     let table_op = |table: &IdbObjectStore| {
         table
             .get_all_keys()
-            .map_err(|e| wrap_jserror("couldn't get all keys from table", e))
+            .map_err(|e| utils::wrap_jserror("couldn't get all keys from table", e))
     };
 
     // Run the operation
@@ -390,7 +528,86 @@ pub(crate) async fn table_get_keys(table_name: &str) -> Result<Vec<JsValue>, Any
     }
 }
 
-/// Saves the given article to IndexedDB, and returns its title and ID
+/// Counts the values in the given table
+pub(crate) async fn table_count(table_name: &str) -> Result<u32, AnyError> {
+    // Request a count() operation on the table
+    let table_op = |table: &IdbObjectStore| {
+        table
+            .count()
+            .map_err(|e| utils::wrap_jserror("couldn't count table", e))
+    };
+
+    // Run the operation
+    match access_db(table_name, false, table_op).await {
+        Ok(val) => Ok(val.as_f64().unwrap_or_default() as u32),
+        Err(e) => Err(anyhow!("Error counting {}: {}", table_name, e)),
+    }
+}
+
+/// Deletes every value in the given table
+pub(crate) async fn table_clear(table_name: &str) -> Result<(), AnyError> {
+    // Request a clear() operation on the table
+    let table_op = |table: &IdbObjectStore| {
+        table
+            .clear()
+            .map_err(|e| utils::wrap_jserror("couldn't clear table", e))
+    };
+
+    // Run the operation
+    match access_db(table_name, true, table_op).await {
+        Ok(_) => {
+            tracing::debug!("Cleared {}", table_name);
+            Ok(())
+        }
+        Err(e) => Err(anyhow!("Error clearing {}: {}", table_name, e)),
+    }
+}
+
+/// Gets all the values from the given table
+pub(crate) async fn table_get_all(table_name: &str) -> Result<Vec<JsValue>, AnyError> {
+    // Request a get_all() operation on the table
+    let table_op = |table: &IdbObjectStore| {
+        table
+            .get_all()
+            .map_err(|e| utils::wrap_jserror("couldn't get all values from table", e))
+    };
+
+    // Run the operation
+    match access_db(table_name, false, table_op).await {
+        Ok(val) => {
+            tracing::trace!("Successfully got all values");
+
+            // Cast the result into a Vec of values
+            Ok(val.dyn_into::<js_sys::Array>().unwrap().to_vec())
+        }
+        Err(e) => Err(anyhow!(
+            "Error getting all values from {}: {}",
+            table_name,
+            e
+        )),
+    }
+}
+
+/// Loads the key that encrypts the articles in the encrypted library, if this device has made one
+pub(crate) async fn load_device_key() -> Result<Option<CryptoKey>, AnyError> {
+    let key = table_get(KEYS_TABLE, &JsValue::from_str(DEVICE_KEY_NAME)).await?;
+    if key.is_undefined() {
+        Ok(None)
+    } else {
+        key.dyn_into()
+            .map(Some)
+            .map_err(|e| utils::wrap_jserror("saved device key isn't a CryptoKey", e))
+    }
+}
+
+/// Saves the key that encrypts the articles in the encrypted library. The key itself is saved,
+/// rather than its bytes, so it stays unexportable.
+pub(crate) async fn save_device_key(key: &CryptoKey) -> Result<(), AnyError> {
+    table_put_with_key(KEYS_TABLE, &JsValue::from_str(DEVICE_KEY_NAME), key).await
+}
+
+/// Saves the given article's audio to IndexedDB, and its transcript apart from it, and returns its
+/// title and ID
 pub(crate) async fn save_article(article: &CachedArticle) -> Result<QueueEntry, AnyError> {
     // Serialize the article manually. We do this instead of using serde because storing blobs is
     // way faster than storing arrays of integers, which is what serde does.
@@ -411,19 +628,35 @@ pub(crate) async fn save_article(article: &CachedArticle) -> Result<QueueEntry,
     )
     .unwrap();
 
-    // Set the blob
-    let blob = {
-        let bytes = js_sys::Uint8Array::from(article.audio_blob.as_slice());
-        // A blob is made from an array of arrays. So construct [bytes] and use that.
-        let parts = js_sys::Array::new();
-        parts.set(0, JsValue::from(bytes));
-        Blob::new_with_u8_array_sequence_and_options(
-            &parts,
-            BlobPropertyBag::new().type_("audio/mp3"),
-        )
-        .unwrap()
-    };
-    js_sys::Reflect::set(&serialized_article, &JsValue::from_str("audio_blob"), &blob).unwrap();
+    // Set the audio chunks and their type. The chunks are kept apart, so loading the article never
+    // needs one buffer as big as the whole audio.
+    let chunks: js_sys::Array = article.audio_chunks.iter().collect();
+    js_sys::Reflect::set(
+        &serialized_article,
+        &JsValue::from_str("audio_chunks"),
+        &chunks,
+    )
+    .unwrap();
+    js_sys::Reflect::set(
+        &serialized_article,
+        &JsValue::from_str("mime_type"),
+        &JsValue::from_str(&article.mime_type),
+    )
+    .unwrap();
+
+    // Save the transcript, unless there isn't one. The encrypted library's articles don't have
+    // theirs, and a saved one shouldn't be overwritten with nothing.
+    if !article.transcript.text.is_empty() || !article.transcript.timepoints.is_empty() {
+        save_transcript(&article.id, &article.transcript).await?;
+    }
+
+    // Set when the article was saved, so it counts as recently used even if it hasn't been played
+    js_sys::Reflect::set(
+        &serialized_article,
+        &JsValue::from_str("saved_at"),
+        &JsValue::from_f64(js_sys::Date::now()),
+    )
+    .unwrap();
 
     // Insert the article
     table_put(ARTICLES_TABLE, &serialized_article).await?;
@@ -437,34 +670,177 @@ pub(crate) async fn load_article(id: &ArticleId) -> Result<CachedArticle, AnyErr
     let serialized_article = table_get(ARTICLES_TABLE, &JsValue::from_str(&id.0)).await?;
     // Get the article's unique ID
     let id = js_sys::Reflect::get(&serialized_article, &JsValue::from_str("id"))
-        .map_err(|e| wrap_jserror("couldn't get article id field", e))?
+        .map_err(|e| utils::wrap_jserror("couldn't get article id field", e))?
         .as_string()
         .unwrap();
     // Get the title, falling back on the ID if it's not set.
     // TODO: Eventually, remove the fallback option. This is just temporary so it doesn't break the
     // sessions from before `title` was saved
     let title = js_sys::Reflect::get(&serialized_article, &JsValue::from_str("title"))
-        .map_err(|e| wrap_jserror("couldn't get article title field", e))
+        .map_err(|e| utils::wrap_jserror("couldn't get article title field", e))
         .map(|t| t.as_string().unwrap_or(id.clone()))?;
-    // Get the MP3 bytes
-    let js_blob: Blob = js_sys::Reflect::get(&serialized_article, &JsValue::from_str("audio_blob"))
-        .unwrap()
-        .dyn_into()
-        .unwrap();
-    let array_buf = JsFuture::from(js_blob.array_buffer()).await.unwrap();
-    let audio_blob = js_sys::Uint8Array::new(&array_buf).to_vec();
+    let get_field =
+        |name: &str| js_sys::Reflect::get(&serialized_article, &JsValue::from_str(name));
+    // Get the audio chunks and their type. The audio isn't read, just referred to. Articles saved
+    // before the audio was chunked have it as one blob of the audio's type, and articles saved
+    // before they could be transcoded are MP3s of type "audio/mp3".
+    let (audio_chunks, mime_type) = match audio_chunks(&serialized_article) {
+        Some(chunks) => (
+            chunks,
+            get_field("mime_type").ok().and_then(|t| t.as_string()),
+        ),
+        None => {
+            let js_blob: Blob = get_field("audio_blob")
+                .ok()
+                .and_then(|b| b.dyn_into().ok())
+                .ok_or_else(|| anyhow!("article {} has no audio", id))?;
+            let mime_type = js_blob.type_();
+            (vec![js_blob], Some(mime_type))
+        }
+    };
+    let mime_type = mime_type
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| crate::utils::MP3_MIME_TYPE.to_string());
+    // Get the transcript. Articles saved before it was kept apart have it alongside the audio, and
+    // ones saved before it was kept at all might only have the section markers, or nothing.
+    let transcript = match load_transcript(&ArticleId(id.clone())).await {
+        Ok(Some(transcript)) => transcript,
+        _ => get_field("transcript")
+            .ok()
+            .and_then(|t| t.into_serde().ok())
+            .unwrap_or_else(|| ArticleTranscript {
+                timepoints: get_field("markers")
+                    .ok()
+                    .and_then(|m| m.into_serde().ok())
+                    .unwrap_or_default(),
+                ..Default::default()
+            }),
+    };
 
     Ok(CachedArticle {
         id: ArticleId(id.clone()),
         title,
-        audio_blob,
+        audio_chunks,
+        mime_type,
+        transcript,
     })
 }
 
+/// Returns the audio chunks of the given serialized article, unless it was saved before the audio
+/// was chunked
+fn audio_chunks(serialized_article: &JsValue) -> Option<Vec<Blob>> {
+    let chunks =
+        js_sys::Reflect::get(serialized_article, &JsValue::from_str("audio_chunks")).ok()?;
+    let chunks: js_sys::Array = chunks.dyn_into().ok()?;
+    chunks.iter().map(|chunk| chunk.dyn_into().ok()).collect()
+}
+
+/// Returns whether the given article's audio is saved
+pub(crate) async fn has_article(id: &ArticleId) -> bool {
+    table_get(ARTICLES_TABLE, &JsValue::from_str(&id.0))
+        .await
+        .is_ok_and(|article| !article.is_undefined())
+}
+
+/// Deletes the given article's audio. Its metadata and transcript are kept, so it can still be
+/// browsed and read offline.
 pub(crate) async fn delete_article(id: &ArticleId) -> Result<(), AnyError> {
     table_delete(ARTICLES_TABLE, &id.0).await
 }
 
+/// Saves the metadata of every article in the given catalog, and forgets the metadata and
+/// transcripts of the articles that aren't in it anymore
+pub(crate) async fn save_catalog(catalog: &LibraryCatalog) -> Result<(), AnyError> {
+    let in_catalog: BTreeSet<&str> = catalog.0.iter().map(|meta| meta.id.as_str()).collect();
+    save_catalog_changes(&catalog.0, &in_catalog).await
+}
+
+/// Saves the metadata of the given changed articles, and forgets the metadata and transcripts of
+/// the articles whose IDs aren't in `in_catalog`
+pub(crate) async fn save_catalog_changes(
+    changed: &[ArticleMetadata],
+    in_catalog: &BTreeSet<&str>,
+) -> Result<(), AnyError> {
+    for meta in changed {
+        table_put(ARTICLE_METADATA_TABLE, &JsValue::from_serde(meta)?).await?;
+    }
+
+    for table_name in [ARTICLE_METADATA_TABLE, TRANSCRIPTS_TABLE] {
+        for key in table_get_keys(table_name).await? {
+            let Some(id) = key.as_string() else {
+                continue;
+            };
+            if !in_catalog.contains(id.as_str()) {
+                table_delete(table_name, &id).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads the catalog as it was last saved, newest article first, like the server lists it
+pub(crate) async fn load_catalog() -> Result<LibraryCatalog, AnyError> {
+    let mut metas: Vec<ArticleMetadata> = table_get_all(ARTICLE_METADATA_TABLE)
+        .await?
+        .into_iter()
+        .filter_map(|v| v.into_serde().ok())
+        .collect();
+    metas.sort_by(|a, b| b.datetime_added.cmp(&a.datetime_added));
+    Ok(LibraryCatalog(metas))
+}
+
+/// Loads when the saved catalog was last brought up to date, if it ever was
+pub(crate) fn load_catalog_synced_at() -> Option<u64> {
+    let storage = window().local_storage().ok().flatten()?;
+    let synced_at = storage
+        .get_item(&servers::scoped_name(CATALOG_SYNCED_AT_KEY))
+        .ok()
+        .flatten()?;
+    synced_at.parse().ok()
+}
+
+/// Saves when the saved catalog was last brought up to date
+pub(crate) fn save_catalog_synced_at(synced_at: u64) {
+    let storage = window().local_storage().ok().flatten();
+    if let Some(storage) = storage {
+        if let Err(e) = storage.set_item(
+            &servers::scoped_name(CATALOG_SYNCED_AT_KEY),
+            &synced_at.to_string(),
+        ) {
+            tracing::error!("Couldn't save when the catalog was synced: {:?}", e);
+        }
+    }
+}
+
+/// Saves the transcript of the given article
+pub(crate) async fn save_transcript(
+    id: &ArticleId,
+    transcript: &ArticleTranscript,
+) -> Result<(), AnyError> {
+    let serialized_transcript = JsValue::from_serde(transcript)?;
+    table_put_with_key(
+        TRANSCRIPTS_TABLE,
+        &JsValue::from_str(&id.0),
+        &serialized_transcript,
+    )
+    .await
+}
+
+/// Deletes the saved transcript of the given article, e.g., because its text changed
+pub(crate) async fn delete_transcript(id: &ArticleId) -> Result<(), AnyError> {
+    table_delete(TRANSCRIPTS_TABLE, &id.0).await
+}
+
+/// Loads the transcript of the given article, if it's been saved
+pub(crate) async fn load_transcript(id: &ArticleId) -> Result<Option<ArticleTranscript>, AnyError> {
+    let transcript = table_get(TRANSCRIPTS_TABLE, &JsValue::from_str(&id.0)).await?;
+    if transcript.is_undefined() {
+        return Ok(None);
+    }
+    Ok(Some(transcript.into_serde()?))
+}
+
 /// Saves the article state to IndexedDB
 pub(crate) async fn save_article_state(state: &ArticleState) -> Result<(), AnyError> {
     let serialized_state = JsValue::from_serde(&state)?;
@@ -515,3 +891,417 @@ pub(crate) async fn load_player_state() -> Result<PlayerState, AnyError> {
         .await
         .and_then(|v| JsValue::into_serde(&v).map_err(Into::into))
 }
+
+/// Records that the given article has been played
+pub(crate) async fn mark_listened(id: &ArticleId) -> Result<(), AnyError> {
+    /// The listened table is keyed by ID, and there's nothing else to store
+    #[derive(Serialize)]
+    struct Listened<'a> {
+        id: &'a ArticleId,
+    }
+
+    let serialized = JsValue::from_serde(&Listened { id })?;
+    table_put(LISTENED_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets the IDs of every article that's been played
+pub(crate) async fn load_listened() -> Result<BTreeSet<ArticleId>, AnyError> {
+    let keys = table_get_keys(LISTENED_TABLE).await?;
+    Ok(keys
+        .into_iter()
+        .filter_map(|key| key.as_string())
+        .map(ArticleId)
+        .collect())
+}
+
+/// Saves the given smart playlist to IndexedDB, replacing any playlist with the same name
+pub(crate) async fn save_smart_playlist(playlist: &SmartPlaylist) -> Result<(), AnyError> {
+    let serialized = JsValue::from_serde(&playlist)?;
+    table_put(SMART_PLAYLISTS_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets all the smart playlists from IndexedDB
+pub(crate) async fn load_smart_playlists() -> Result<Vec<SmartPlaylist>, AnyError> {
+    table_get_all(SMART_PLAYLISTS_TABLE)
+        .await?
+        .into_iter()
+        .map(|v| JsValue::into_serde(&v).map_err(Into::into))
+        .collect()
+}
+
+/// Deletes the smart playlist with the given name
+pub(crate) async fn delete_smart_playlist(name: &str) -> Result<(), AnyError> {
+    table_delete(SMART_PLAYLISTS_TABLE, name).await
+}
+
+/// Records the speed an article was listened at, replacing any earlier record for the article
+pub(crate) async fn save_speed_sample(sample: &SpeedSample) -> Result<(), AnyError> {
+    let serialized = JsValue::from_serde(&sample)?;
+    table_put(LISTENING_SPEEDS_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets the speeds every article has been listened at
+pub(crate) async fn load_speed_samples() -> Result<Vec<SpeedSample>, AnyError> {
+    table_get_all(LISTENING_SPEEDS_TABLE)
+        .await?
+        .into_iter()
+        .map(|v| JsValue::into_serde(&v).map_err(Into::into))
+        .collect()
+}
+
+/// Saves how many times each part of an article has been played, replacing any earlier counts
+pub(crate) async fn save_play_counts(plays: &PlayCounts) -> Result<(), AnyError> {
+    let serialized = JsValue::from_serde(&plays)?;
+    table_put(PLAY_COUNTS_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets how many times each part of the given article has been played, if it has been
+pub(crate) async fn load_play_counts(id: &ArticleId) -> Result<Option<PlayCounts>, AnyError> {
+    let plays = table_get(PLAY_COUNTS_TABLE, &JsValue::from_str(&id.0)).await?;
+    if plays.is_undefined() {
+        return Ok(None);
+    }
+    Ok(Some(plays.into_serde()?))
+}
+
+/// Saves the given moment
+pub(crate) async fn save_moment(moment: &Moment) -> Result<(), AnyError> {
+    let serialized = JsValue::from_serde(&moment)?;
+    table_put(MOMENTS_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets every moment the listener marked
+pub(crate) async fn load_moments() -> Result<Vec<Moment>, AnyError> {
+    table_get_all(MOMENTS_TABLE)
+        .await?
+        .into_iter()
+        .map(|v| JsValue::into_serde(&v).map_err(Into::into))
+        .collect()
+}
+
+/// Deletes the moment with the given key
+pub(crate) async fn delete_moment(key: &str) -> Result<(), AnyError> {
+    table_delete(MOMENTS_TABLE, key).await
+}
+
+/// Saves the given listening session to IndexedDB, replacing any earlier save of it
+pub(crate) async fn save_listening_session(session: &ListeningSession) -> Result<(), AnyError> {
+    let serialized = JsValue::from_serde(&session)?;
+    table_put(LISTENING_SESSIONS_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets every listening session
+pub(crate) async fn load_listening_sessions() -> Result<Vec<ListeningSession>, AnyError> {
+    table_get_all(LISTENING_SESSIONS_TABLE)
+        .await?
+        .into_iter()
+        .map(|v| JsValue::into_serde(&v).map_err(Into::into))
+        .collect()
+}
+
+/// Saves the given playlist to IndexedDB, replacing any playlist with the same ID
+pub(crate) async fn save_playlist(playlist: &Playlist) -> Result<(), AnyError> {
+    let serialized = JsValue::from_serde(&playlist)?;
+    table_put(PLAYLISTS_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets every playlist, including the deleted ones
+pub(crate) async fn load_playlists() -> Result<Vec<Playlist>, AnyError> {
+    table_get_all(PLAYLISTS_TABLE)
+        .await?
+        .into_iter()
+        .map(|v| JsValue::into_serde(&v).map_err(Into::into))
+        .collect()
+}
+
+/// Saves the given unfinished download to IndexedDB, replacing any earlier save of it
+pub(crate) async fn save_download(download: &PartialDownload) -> Result<(), AnyError> {
+    let serialized = JsValue::from_serde(&download)?;
+    table_put(DOWNLOADS_TABLE, &serialized).await?;
+    Ok(())
+}
+
+/// Gets the unfinished download of the given article
+pub(crate) async fn load_download(id: &ArticleId) -> Result<PartialDownload, AnyError> {
+    let key = JsValue::from_str(&id.0);
+    table_get(DOWNLOADS_TABLE, &key)
+        .await
+        .and_then(|v| JsValue::into_serde(&v).map_err(Into::into))
+}
+
+/// Gets all the unfinished downloads
+pub(crate) async fn load_downloads() -> Result<Vec<PartialDownload>, AnyError> {
+    table_get_all(DOWNLOADS_TABLE)
+        .await?
+        .into_iter()
+        .map(|v| JsValue::into_serde(&v).map_err(Into::into))
+        .collect()
+}
+
+/// Deletes the given unfinished download, along with every chunk it received
+pub(crate) async fn delete_download(download: &PartialDownload) -> Result<(), AnyError> {
+    for i in 0..download.num_chunks {
+        table_delete(
+            DOWNLOAD_CHUNKS_TABLE,
+            &download_chunk_key(&download.entry.id, i),
+        )
+        .await?;
+    }
+    table_delete(DOWNLOADS_TABLE, &download.entry.id.0).await
+}
+
+/// Returns the key of the given chunk of the given article's download
+fn download_chunk_key(id: &ArticleId, index: usize) -> String {
+    format!("{}/{}", id.0, index)
+}
+
+/// Saves the given chunk of the given article's download. Like articles, chunks are stored as blobs.
+pub(crate) async fn save_download_chunk(
+    id: &ArticleId,
+    index: usize,
+    chunk: &Blob,
+) -> Result<(), AnyError> {
+    let key = JsValue::from_str(&download_chunk_key(id, index));
+    table_put_with_key(DOWNLOAD_CHUNKS_TABLE, &key, chunk).await?;
+    Ok(())
+}
+
+/// Gets the given chunk of the given article's download
+pub(crate) async fn load_download_chunk(id: &ArticleId, index: usize) -> Result<Blob, AnyError> {
+    let key = JsValue::from_str(&download_chunk_key(id, index));
+    table_get(DOWNLOAD_CHUNKS_TABLE, &key)
+        .await?
+        .dyn_into()
+        .map_err(|e| utils::wrap_jserror("download chunk isn't a blob", e))
+}
+
+/// How much space a cached article takes up
+pub(crate) struct CachedSize {
+    pub(crate) id: ArticleId,
+    pub(crate) title: String,
+    /// The size of the article's audio, in bytes
+    pub(crate) bytes: f64,
+    /// When the article was saved, in milliseconds since the Unix epoch. This is 0 for articles
+    /// saved before this was recorded.
+    pub(crate) saved_at: f64,
+}
+
+/// Gets the size of every cached article. This doesn't read the audio itself, just its length.
+pub(crate) async fn load_cached_sizes() -> Result<Vec<CachedSize>, AnyError> {
+    let articles = table_get_all(ARTICLES_TABLE).await?;
+    Ok(articles
+        .into_iter()
+        .filter_map(|article| {
+            let get_field = |name: &str| js_sys::Reflect::get(&article, &JsValue::from_str(name));
+            let id = get_field("id").ok()?.as_string()?;
+            let bytes = match audio_chunks(&article) {
+                Some(chunks) => chunks.iter().map(Blob::size).sum(),
+                None => get_field("audio_blob")
+                    .ok()
+                    .and_then(|b| b.dyn_into::<Blob>().ok())
+                    .map(|b| b.size())
+                    .unwrap_or(0.0),
+            };
+            Some(CachedSize {
+                title: get_field("title")
+                    .ok()
+                    .and_then(|t| t.as_string())
+                    .unwrap_or_else(|| id.clone()),
+                id: ArticleId(id),
+                bytes,
+                saved_at: get_field("saved_at")
+                    .ok()
+                    .and_then(|t| t.as_f64())
+                    .unwrap_or(0.0),
+            })
+        })
+        .collect())
+}
+
+/// Returns how many bytes the app is using, and how many it's allowed to use, if the browser says
+pub(crate) async fn storage_estimate() -> Option<(f64, f64)> {
+    let promise = window().navigator().storage().estimate().ok()?;
+    let estimate = JsFuture::from(promise).await.ok()?;
+    let get_field = |name: &str| js_sys::Reflect::get(&estimate, &JsValue::from_str(name));
+    Some((
+        get_field("usage").ok()?.as_f64()?,
+        get_field("quota").ok()?.as_f64()?,
+    ))
+}
+
+/// Asks the browser to keep the app's storage even when space runs low. Browsers decide for
+/// themselves, and some ask the listener.
+fn request_persistence() {
+    if !platform::needs_persistent_storage() {
+        return;
+    }
+    let Ok(promise) = window().navigator().storage().persist() else {
+        return;
+    };
+    spawn_local(async move {
+        let persisted = JsFuture::from(promise).await.ok().and_then(|v| v.as_bool());
+        tracing::debug!("Asked for persistent storage. Granted: {:?}", persisted);
+    });
+}
+
+/// When cached articles are deleted to free up space. Pinned articles are never deleted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct EvictionPolicy {
+    /// If this is set, the least recently used articles are deleted while the cached audio takes up
+    /// more than this many megabytes
+    #[serde(default)]
+    pub(crate) max_megabytes: Option<u32>,
+    /// Whether articles that were listened to the end are deleted
+    #[serde(default)]
+    pub(crate) evict_finished: bool,
+    /// The articles that are never deleted
+    #[serde(default)]
+    pub(crate) pinned: BTreeSet<ArticleId>,
+    /// The titles of the pinned articles, so they can be downloaded again if the cached audio is
+    /// wiped. Articles pinned before the titles were kept aren't in here.
+    #[serde(default)]
+    pinned_titles: BTreeMap<ArticleId, String>,
+}
+
+impl EvictionPolicy {
+    /// Loads the policy from localStorage. If there isn't one, nothing is ever deleted.
+    pub(crate) fn load() -> EvictionPolicy {
+        let storage = window().local_storage().ok().flatten();
+        storage
+            .and_then(|s| {
+                s.get_item(&servers::scoped_name(EVICTION_POLICY_KEY))
+                    .ok()
+                    .flatten()
+            })
+            .and_then(|json| js_sys::JSON::parse(&json).ok())
+            .and_then(|v| v.into_serde().ok())
+            .unwrap_or_default()
+    }
+
+    /// Pins the given article, which has the given title, or unpins it if it's pinned. Returns
+    /// whether it's pinned now. Pinning also asks the browser to keep the app's storage when space
+    /// runs low, since the policy can't stop the browser itself from wiping it.
+    pub(crate) fn toggle_pinned(&mut self, id: &ArticleId, title: &str) -> bool {
+        if self.pinned.remove(id) {
+            self.pinned_titles.remove(id);
+            false
+        } else {
+            self.pinned.insert(id.clone());
+            self.pinned_titles.insert(id.clone(), title.to_string());
+            request_persistence();
+            true
+        }
+    }
+
+    /// Saves the policy to localStorage
+    pub(crate) fn save(&self) {
+        let json = JsValue::from_serde(self)
+            .ok()
+            .and_then(|v| js_sys::JSON::stringify(&v).ok())
+            .and_then(|s| s.as_string());
+        let storage = window().local_storage().ok().flatten();
+        if let (Some(storage), Some(json)) = (storage, json) {
+            if let Err(e) = storage.set_item(&servers::scoped_name(EVICTION_POLICY_KEY), &json) {
+                tracing::error!("Couldn't save the eviction policy: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Returns the pinned articles whose audio isn't cached, e.g., because it was wiped, so they can be
+/// downloaded again
+pub(crate) async fn missing_pinned() -> Vec<QueueEntry> {
+    let policy = EvictionPolicy::load();
+    let cached = match load_cached_sizes().await {
+        Ok(sizes) => sizes
+            .into_iter()
+            .map(|size| size.id)
+            .collect::<BTreeSet<_>>(),
+        Err(e) => {
+            tracing::error!("Couldn't check which pinned articles are cached: {}", e);
+            return Vec::new();
+        }
+    };
+    policy
+        .pinned_titles
+        .into_iter()
+        .filter(|(id, _)| policy.pinned.contains(id) && !cached.contains(id))
+        .map(|(id, title)| QueueEntry {
+            id,
+            title,
+            group: None,
+            publication: None,
+            author: None,
+            image: None,
+            reading_profile: None,
+        })
+        .collect()
+}
+
+/// A cached article that the eviction policy might delete
+pub(crate) struct EvictionCandidate {
+    pub(crate) id: ArticleId,
+    /// The size of the article's audio, in bytes
+    pub(crate) bytes: f64,
+    /// When the article was last played or saved, in milliseconds since the Unix epoch
+    pub(crate) last_used: f64,
+    /// Whether the article was listened to the end
+    pub(crate) finished: bool,
+}
+
+/// Gets every cached article, along with when it was last used and whether it was finished
+pub(crate) async fn load_eviction_candidates() -> Result<Vec<EvictionCandidate>, AnyError> {
+    let states: Vec<ArticleState> = table_get_all(ARTICLE_STATE_TABLE)
+        .await?
+        .into_iter()
+        .filter_map(|v| v.into_serde().ok())
+        .collect();
+
+    let sizes = load_cached_sizes().await?;
+    Ok(sizes
+        .into_iter()
+        .map(|size| {
+            let state = states.iter().find(|s| s.id == size.id);
+            EvictionCandidate {
+                bytes: size.bytes,
+                last_used: state.map_or(0.0, |s| s.last_played).max(size.saved_at),
+                finished: state.is_some_and(|s| s.finished),
+                id: size.id,
+            }
+        })
+        .collect())
+}
+
+/// Returns the articles the given policy deletes. The most recently used article is always kept,
+/// since it's likely the one playing or the one just added.
+pub(crate) fn pick_evictions(
+    policy: &EvictionPolicy,
+    mut candidates: Vec<EvictionCandidate>,
+) -> Vec<ArticleId> {
+    // Go from least to most recently used
+    candidates.sort_by(|a, b| a.last_used.total_cmp(&b.last_used));
+    let mut total_bytes: f64 = candidates.iter().map(|c| c.bytes).sum();
+    candidates.pop();
+
+    let mut evicted = Vec::new();
+    let max_bytes = policy.max_megabytes.map(|mb| f64::from(mb) * 1e6);
+    for candidate in candidates {
+        if policy.pinned.contains(&candidate.id) {
+            continue;
+        }
+        let over_limit = max_bytes.is_some_and(|max| total_bytes > max);
+        if over_limit || (policy.evict_finished && candidate.finished) {
+            total_bytes -= candidate.bytes;
+            evicted.push(candidate.id);
+        }
+    }
+
+    evicted
+}
@@ -0,0 +1,250 @@
+//! The time the Player goes by, and its timers, behind the [`Clock`] trait. In the browser, these
+//! are `Date.now()` and `setTimeout`/`setInterval`. Tests swap in a virtual clock, which only moves
+//! when it's advanced, and runs the timers that come due as it does. That way the save loop, the
+//! sleep timer, and the other timed parts of the Player can be tested without waiting on real time.
+
+use std::{any::Any, cell::RefCell, rc::Rc};
+
+use gloo_timers::callback::{Interval, Timeout};
+
+/// A source of the current time, and of timers that go off later
+pub(crate) trait Clock {
+    /// Returns the current time, in milliseconds since the Unix epoch
+    fn now(&self) -> f64;
+
+    /// Calls `callback` once, after `millis` milliseconds
+    fn timeout(&self, millis: u32, callback: Box<dyn FnOnce()>) -> Timer;
+
+    /// Calls `callback` every `millis` milliseconds
+    fn interval(&self, millis: u32, callback: Box<dyn FnMut()>) -> Timer;
+}
+
+/// A timer that hasn't gone off yet, or an interval. Dropping it cancels it, like gloo's timers.
+#[must_use = "a timer is cancelled as soon as it's dropped"]
+pub(crate) struct Timer {
+    /// Whatever cancels the timer when it's dropped
+    _handle: Box<dyn Any>,
+}
+
+impl Timer {
+    fn new(handle: impl Any) -> Timer {
+        Timer {
+            _handle: Box::new(handle),
+        }
+    }
+}
+
+/// The browser's clock
+struct BrowserClock;
+
+impl Clock for BrowserClock {
+    fn now(&self) -> f64 {
+        js_sys::Date::now()
+    }
+
+    fn timeout(&self, millis: u32, callback: Box<dyn FnOnce()>) -> Timer {
+        Timer::new(Timeout::new(millis, callback))
+    }
+
+    fn interval(&self, millis: u32, callback: Box<dyn FnMut()>) -> Timer {
+        Timer::new(Interval::new(millis, callback))
+    }
+}
+
+thread_local!(
+    /// The clock everything goes by. It's the browser's, except in tests.
+    static CLOCK: RefCell<Rc<dyn Clock>> = RefCell::new(Rc::new(BrowserClock))
+);
+
+/// Returns the current time, in milliseconds since the Unix epoch
+pub(crate) fn now() -> f64 {
+    CLOCK.with(|clock| clock.borrow().now())
+}
+
+/// Calls `callback` once, after `millis` milliseconds, unless the returned timer is dropped first
+pub(crate) fn timeout(millis: u32, callback: impl FnOnce() + 'static) -> Timer {
+    let clock = CLOCK.with(|clock| clock.borrow().clone());
+    clock.timeout(millis, Box::new(callback))
+}
+
+/// Calls `callback` every `millis` milliseconds, until the returned timer is dropped
+pub(crate) fn interval(millis: u32, callback: impl FnMut() + 'static) -> Timer {
+    let clock = CLOCK.with(|clock| clock.borrow().clone());
+    clock.interval(millis, Box::new(callback))
+}
+
+/// Makes everything go by the given clock from now on
+#[cfg(test)]
+pub(crate) fn set_clock(clock: Rc<dyn Clock>) {
+    CLOCK.with(|c| *c.borrow_mut() = clock);
+}
+
+#[cfg(test)]
+pub(crate) use virtual_clock::VirtualClock;
+
+#[cfg(test)]
+mod virtual_clock {
+    use super::{Clock, Timer};
+
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    /// Cancels a virtual timer when it's dropped
+    struct Cancel(Rc<Cell<bool>>);
+
+    impl Drop for Cancel {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    enum Callback {
+        Once(Box<dyn FnOnce()>),
+        Repeating(Box<dyn FnMut()>),
+    }
+
+    struct VirtualTimer {
+        /// When the timer goes off next, in milliseconds since the Unix epoch
+        due: f64,
+        /// For intervals, the number of milliseconds between calls
+        period: f64,
+        callback: Callback,
+        cancelled: Rc<Cell<bool>>,
+    }
+
+    /// A clock whose time only moves when it's advanced
+    #[derive(Default)]
+    pub(crate) struct VirtualClock {
+        now: Cell<f64>,
+        timers: RefCell<Vec<VirtualTimer>>,
+    }
+
+    impl VirtualClock {
+        /// Returns a clock that starts at the given time, in milliseconds since the Unix epoch
+        pub(crate) fn new(now: f64) -> VirtualClock {
+            VirtualClock {
+                now: Cell::new(now),
+                ..Default::default()
+            }
+        }
+
+        /// Moves time forward by the given number of milliseconds, running every timer that comes
+        /// due along the way, in the order they come due. Timers set by the ones that run are run
+        /// too, if they come due in time.
+        pub(crate) fn advance(&self, millis: f64) {
+            let end = self.now.get() + millis;
+            loop {
+                // Take the next timer out before running it, since it might set or cancel timers
+                let next = {
+                    let mut timers = self.timers.borrow_mut();
+                    timers.retain(|t| !t.cancelled.get());
+                    timers
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, t)| t.due <= end)
+                        .min_by(|(_, a), (_, b)| a.due.total_cmp(&b.due))
+                        .map(|(i, _)| i)
+                        .map(|i| timers.remove(i))
+                };
+                let Some(timer) = next else {
+                    break;
+                };
+
+                self.now.set(timer.due);
+                match timer.callback {
+                    Callback::Once(callback) => callback(),
+                    Callback::Repeating(mut callback) => {
+                        callback();
+                        self.timers.borrow_mut().push(VirtualTimer {
+                            due: timer.due + timer.period,
+                            callback: Callback::Repeating(callback),
+                            ..timer
+                        });
+                    }
+                }
+            }
+            self.now.set(end);
+        }
+
+        fn add(&self, millis: u32, callback: Callback) -> Timer {
+            // An interval of 0 would never let time move on, so like browsers, it's made longer
+            let period = f64::from(millis.max(1));
+            let cancelled = Rc::new(Cell::new(false));
+            self.timers.borrow_mut().push(VirtualTimer {
+                due: self.now.get() + f64::from(millis),
+                period,
+                callback,
+                cancelled: cancelled.clone(),
+            });
+            Timer::new(Cancel(cancelled))
+        }
+    }
+
+    impl Clock for VirtualClock {
+        fn now(&self) -> f64 {
+            self.now.get()
+        }
+
+        fn timeout(&self, millis: u32, callback: Box<dyn FnOnce()>) -> Timer {
+            self.add(millis, Callback::Once(callback))
+        }
+
+        fn interval(&self, millis: u32, callback: Box<dyn FnMut()>) -> Timer {
+            self.add(millis, Callback::Repeating(callback))
+        }
+    }
+}
+
+#[test]
+fn advancing_virtual_time() {
+    use std::cell::RefCell;
+
+    let clock = Rc::new(VirtualClock::new(1000.0));
+    set_clock(clock.clone());
+    let calls = Rc::new(RefCell::new(Vec::new()));
+
+    // Timers go off in the order they're due, and intervals keep going
+    let log = calls.clone();
+    let _later = timeout(300, move || log.borrow_mut().push(("timeout", now())));
+    let log = calls.clone();
+    let ticks = interval(100, move || log.borrow_mut().push(("tick", now())));
+    clock.advance(250.0);
+    assert_eq!(*calls.borrow(), [("tick", 1100.0), ("tick", 1200.0)]);
+    assert_eq!(now(), 1250.0);
+
+    // Dropping a timer cancels it
+    drop(ticks);
+    clock.advance(1000.0);
+    assert_eq!(calls.borrow().last(), Some(&("timeout", 1300.0)));
+    assert_eq!(calls.borrow().len(), 3);
+}
+
+#[test]
+fn running_after_delay() {
+    use std::cell::Cell;
+
+    let clock = Rc::new(VirtualClock::new(0.0));
+    set_clock(clock.clone());
+
+    // A delayed closure that sets up the next one, like the Player's save loop does
+    fn repeat(count: Rc<Cell<u32>>) -> Timer {
+        crate::utils::run_after_delay(
+            move || {
+                count.set(count.get() + 1);
+                std::mem::forget(repeat(count));
+            },
+            30_000,
+        )
+    }
+    let count = Rc::new(Cell::new(0));
+    std::mem::forget(repeat(count.clone()));
+
+    clock.advance(29_999.0);
+    assert_eq!(count.get(), 0);
+    clock.advance(1.0);
+    assert_eq!(count.get(), 1);
+    clock.advance(90_000.0);
+    assert_eq!(count.get(), 4);
+}
@@ -0,0 +1,255 @@
+//! The collections other ReadToMyShoe servers share with this library. The listener subscribes with
+//! the link they were given, and copies the articles they want into the library.
+
+use crate::{
+    library_view::{Library, LibraryMsg},
+    utils, WeakComponentLink,
+};
+use common::{CollectionSubscription, RemoteCollection, RemoteCopyRequest};
+
+use anyhow::{bail, Error as AnyError};
+use serde::Serialize;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+const COLLECTION_URL_FORM_ID: &str = "collection-url-input";
+
+/// Fetches the collections the library is subscribed to
+async fn fetch_collections() -> Result<Vec<RemoteCollection>, AnyError> {
+    let resp = utils::get("/api/remote-collections")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching collections"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing collections JSON"))
+}
+
+/// POSTs the given JSON to the given endpoint
+async fn post_json(endpoint: &str, body: &impl Serialize) -> Result<(), AnyError> {
+    let resp = utils::post(endpoint)
+        .json(body)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    pub library_link: WeakComponentLink<Library>,
+}
+
+pub(crate) enum CollectionsMsg {
+    /// Fetches the collections
+    Refresh,
+    /// Sets the collections
+    SetCollections(Vec<RemoteCollection>),
+    /// Subscribes to the collection whose link is in the form
+    Subscribe,
+    /// Unsubscribes from the collection with the given link
+    Unsubscribe(String),
+    /// Copies the given article to the library
+    Copy(RemoteCopyRequest),
+    /// Sets the status display to the given message, and refreshes the collections
+    SetStatus(String),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+/// The list of subscribed collections on the main page
+#[derive(Default)]
+pub(crate) struct Collections {
+    collections: Vec<RemoteCollection>,
+    status: Option<String>,
+    err: Option<AnyError>,
+}
+
+impl Component for Collections {
+    type Message = CollectionsMsg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Collections::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            CollectionsMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    match fetch_collections().await {
+                        Ok(collections) => CollectionsMsg::SetCollections(collections),
+                        Err(e) => CollectionsMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            CollectionsMsg::SetCollections(collections) => {
+                self.collections = collections;
+            }
+
+            CollectionsMsg::Subscribe => {
+                let input: HtmlInputElement = match gloo_utils::document()
+                    .get_element_by_id(COLLECTION_URL_FORM_ID)
+                    .and_then(|e| e.dyn_into().ok())
+                {
+                    Some(input) => input,
+                    None => return false,
+                };
+                let url = input.value().trim().to_string();
+                if url.is_empty() {
+                    return false;
+                }
+                input.set_value("");
+
+                self.err = None;
+                self.status = Some("Subscribing...".to_string());
+                ctx.link().send_future(async move {
+                    let sub = CollectionSubscription { url };
+                    match post_json("/api/subscribe-collection", &sub).await {
+                        Ok(()) => CollectionsMsg::SetStatus("Subscribed!".to_string()),
+                        Err(e) => CollectionsMsg::SetError(e),
+                    }
+                });
+            }
+
+            CollectionsMsg::Unsubscribe(url) => {
+                self.err = None;
+                ctx.link().send_future(async move {
+                    let sub = CollectionSubscription { url };
+                    match post_json("/api/unsubscribe-collection", &sub).await {
+                        Ok(()) => CollectionsMsg::SetStatus("Unsubscribed.".to_string()),
+                        Err(e) => CollectionsMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            CollectionsMsg::Copy(req) => {
+                self.err = None;
+                self.status = Some("Copying...".to_string());
+                let library_link = ctx.props().library_link.clone();
+                ctx.link().send_future(async move {
+                    match post_json("/api/copy-remote-article", &req).await {
+                        Ok(()) => {
+                            // Show the new article in the library
+                            if let Some(library) = library_link.borrow().as_ref() {
+                                library.send_message(LibraryMsg::FetchCatalog);
+                            }
+                            CollectionsMsg::SetStatus("Copied to the library.".to_string())
+                        }
+                        Err(e) => CollectionsMsg::SetError(e),
+                    }
+                });
+            }
+
+            CollectionsMsg::SetStatus(status) => {
+                self.status = Some(status);
+                ctx.link().send_message(CollectionsMsg::Refresh);
+            }
+
+            CollectionsMsg::SetError(e) => {
+                self.status = None;
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let collections = self
+            .collections
+            .iter()
+            .map(|collection| {
+                let url = collection.url.clone();
+                let unsubscribe = link.callback(move |_| CollectionsMsg::Unsubscribe(url.clone()));
+                let articles = collection
+                    .articles
+                    .iter()
+                    .map(|article| {
+                        let req = RemoteCopyRequest {
+                            collection_url: collection.url.clone(),
+                            article_id: article.id.clone(),
+                        };
+                        let copy = link.callback(move |_| CollectionsMsg::Copy(req.clone()));
+                        let action = if article.in_library {
+                            html! { <span class="articleMetadata">{ "In the library" }</span> }
+                        } else {
+                            html! {
+                                <button
+                                    onclick={copy}
+                                    aria-label={ format!("Copy {} to the library", article.title) }
+                                >
+                                    { "Copy" }
+                                </button>
+                            }
+                        };
+                        html! {
+                            <li><bdi>{ &article.title }</bdi>{ " " }{ action }</li>
+                        }
+                    })
+                    .collect::<Html>();
+                let contents = match &collection.error {
+                    Some(e) => html! { <p class="articleMetadata">{ e }</p> },
+                    None if collection.articles.is_empty() => {
+                        html! { <p class="articleMetadata">{ "Nothing's shared right now." }</p> }
+                    }
+                    None => html! { <ul>{ articles }</ul> },
+                };
+                html! {
+                    <li>
+                        <bdi>{ &collection.name }</bdi>
+                        <button
+                            onclick={unsubscribe}
+                            aria-label={ format!("Unsubscribe from {}", collection.name) }
+                        >
+                            { "Unsubscribe" }
+                        </button>
+                        { contents }
+                    </li>
+                }
+            })
+            .collect::<Html>();
+        let list = if self.collections.is_empty() {
+            html! { <p>{ "You aren't subscribed to any collections." }</p> }
+        } else {
+            html! { <ul>{ collections }</ul> }
+        };
+
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+        let ontoggle = link.callback(|_| CollectionsMsg::Refresh);
+        let subscribe = link.callback(|_| CollectionsMsg::Subscribe);
+
+        html! {
+            <details class="shares" {ontoggle}>
+                <summary>{ "Other libraries" }</summary>
+                <p class="articleMetadata">{
+                    "Collections that people on other ReadToMyShoe servers shared with you. Copy
+                    the articles you want into your library."
+                }</p>
+                { list }
+                <div class="field">
+                    <label for={COLLECTION_URL_FORM_ID}>{ "Link to a collection:" }</label>
+                    <input type="text" id={COLLECTION_URL_FORM_ID} />
+                </div>
+                <button onclick={subscribe}>{ "Subscribe" }</button>
+                <p aria-live="polite">{ self.status.clone().unwrap_or_default() }</p>
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </details>
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Sorts the library's articles into sections by the day they were added, e.g., "Today", so a
+//! library that feeds add to every day isn't one long list. Days start at midnight in the browser's
+//! time zone, not the server's.
+
+use wasm_bindgen::JsValue;
+
+/// The day an article was added, as the library groups them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum DaySection {
+    Today,
+    Yesterday,
+    /// The five days before yesterday
+    ThisWeek,
+    /// Before that, or never recorded
+    Earlier,
+}
+
+impl DaySection {
+    /// The section's heading
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            DaySection::Today => "Today",
+            DaySection::Yesterday => "Yesterday",
+            DaySection::ThisWeek => "This week",
+            DaySection::Earlier => "Earlier",
+        }
+    }
+}
+
+/// When today, yesterday, and this week started, in milliseconds since the Unix epoch
+pub(crate) struct DayStarts([f64; 3]);
+
+impl DayStarts {
+    /// Returns when the sections start, as of the given time in milliseconds since the Unix epoch.
+    /// Midnights are found with the browser's calendar, so days that are 23 or 25 hours long
+    /// because of daylight saving time start when they should.
+    pub(crate) fn at(now: f64) -> DayStarts {
+        let now = js_sys::Date::new(&JsValue::from_f64(now));
+        let midnight = |days_ago: i32| {
+            js_sys::Date::new_with_year_month_day(
+                now.get_full_year(),
+                now.get_month() as i32,
+                now.get_date() as i32 - days_ago,
+            )
+            .get_time()
+        };
+        DayStarts([midnight(0), midnight(1), midnight(6)])
+    }
+
+    /// Returns the section of an article added at the given time, in seconds since the Unix epoch
+    pub(crate) fn section(&self, added: Option<u64>) -> DaySection {
+        let Some(added) = added.map(|t| t as f64 * 1000.0) else {
+            return DaySection::Earlier;
+        };
+        let [today, yesterday, this_week] = self.0;
+        if added >= today {
+            DaySection::Today
+        } else if added >= yesterday {
+            DaySection::Yesterday
+        } else if added >= this_week {
+            DaySection::ThisWeek
+        } else {
+            DaySection::Earlier
+        }
+    }
+}
+
+#[test]
+fn sectioning() {
+    const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+    // Today started at 1 663 000 000 s, and yesterday was 25 hours long
+    let today = 1_663_000_000_000.0;
+    let starts = DayStarts([today, today - DAY_MS - 3_600_000.0, today - 6.0 * DAY_MS]);
+    let secs_before = |ms: f64| Some(((today - ms) / 1000.0) as u64);
+
+    assert_eq!(starts.section(secs_before(-1000.0)), DaySection::Today);
+    assert_eq!(starts.section(secs_before(0.0)), DaySection::Today);
+    assert_eq!(starts.section(secs_before(1000.0)), DaySection::Yesterday);
+    assert_eq!(
+        starts.section(secs_before(DAY_MS + 1000.0)),
+        DaySection::Yesterday
+    );
+    assert_eq!(
+        starts.section(secs_before(2.0 * DAY_MS)),
+        DaySection::ThisWeek
+    );
+    assert_eq!(
+        starts.section(secs_before(7.0 * DAY_MS)),
+        DaySection::Earlier
+    );
+    assert_eq!(starts.section(None), DaySection::Earlier);
+}
@@ -0,0 +1,419 @@
+//! The developer view, at /dev. It isn't linked from anywhere. It shows the saved player state,
+//! how much is in each IndexedDB table, what's waiting to be synced, and the latest log entries, so
+//! listeners can paste them into a bug report. Tables can be cleared one at a time, for getting out
+//! of a bad state without losing everything else. The network can be made to seem offline or slow,
+//! for trying out how the app copes.
+
+use crate::{
+    caching, downloads,
+    log_buffer::{self, LogEntry},
+    network_sim::{self, NetworkCondition},
+    player_view,
+    position_conflicts::{self, PositionConflict},
+    queue_view::QueueEntry,
+    sync,
+};
+
+use anyhow::Error as AnyError;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+use yew::prelude::*;
+
+/// What the developer view shows, other than the log
+pub(crate) struct Snapshot {
+    /// The saved player state, as JSON
+    player_state: String,
+    /// The number of values in each table, or why they couldn't be counted
+    tables: Vec<(&'static str, Result<u32, String>)>,
+    /// What this device has changed since it last synced
+    pending_sync: Vec<String>,
+    /// The positions from other devices that were held back
+    conflicts: Vec<PositionConflict>,
+    /// The downloads that were interrupted
+    unfinished: Vec<QueueEntry>,
+    /// When this device last synced, in milliseconds since the Unix epoch, or 0 if it never has
+    last_synced: f64,
+    device_id: String,
+}
+
+/// Returns the given value as indented JSON
+fn to_pretty_json<T: Serialize>(value: &T) -> String {
+    JsValue::from_serde(value)
+        .ok()
+        .and_then(|v| {
+            js_sys::JSON::stringify_with_replacer_and_space(
+                &v,
+                &JsValue::NULL,
+                &JsValue::from_f64(2.0),
+            )
+            .ok()
+        })
+        .and_then(|s| s.as_string())
+        .unwrap_or_else(|| "[not displayable]".to_string())
+}
+
+/// Formats the given time in milliseconds since the Unix epoch as a date and time
+fn format_ms(t: f64) -> String {
+    js_sys::Date::new(&JsValue::from_f64(t))
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+/// Formats the given log entry as a line, e.g., "12:01:02 WARN readtomyshoe_frontend::sync: ..."
+fn format_entry(entry: &LogEntry) -> String {
+    let time: String = js_sys::Date::new(&JsValue::from_f64(entry.time))
+        .to_locale_time_string("default")
+        .into();
+    format!("{time} {} {}: {}", entry.level, entry.target, entry.message)
+}
+
+/// Describes what this device has changed since it last synced, at the given time
+async fn pending_sync(last_synced: f64) -> Result<Vec<String>, AnyError> {
+    let local = sync::local_state().await?;
+    let mut pending = Vec::new();
+    if local.queue_updated > last_synced {
+        pending.push(format!(
+            "The queue, changed {}",
+            format_ms(local.queue_updated)
+        ));
+    }
+    if local.speed_updated > last_synced {
+        pending.push(format!("The playback speed, {}x", local.playback_speed));
+    }
+    for pos in local
+        .positions
+        .iter()
+        .filter(|p| p.last_played > last_synced)
+    {
+        let title = local
+            .queue
+            .iter()
+            .find(|entry| entry.id == pos.id)
+            .map_or(pos.id.as_str(), |entry| entry.title.as_str());
+        pending.push(format!(
+            "The position in \"{title}\": {}",
+            player_view::format_time(pos.elapsed)
+        ));
+    }
+    for playlist in local.playlists.iter().filter(|p| p.updated > last_synced) {
+        let change = if playlist.deleted {
+            "deleted"
+        } else {
+            "changed"
+        };
+        pending.push(format!("The playlist \"{}\", {change}", playlist.name));
+    }
+    Ok(pending)
+}
+
+/// Gathers what the developer view shows
+async fn load_snapshot() -> Snapshot {
+    let player_state = match caching::load_player_state().await {
+        Ok(state) => to_pretty_json(&state),
+        Err(e) => format!("None saved: {e}"),
+    };
+
+    let mut tables = Vec::new();
+    for &table in caching::TABLES {
+        let count = caching::table_count(table).await.map_err(|e| e.to_string());
+        tables.push((table, count));
+    }
+
+    let last_synced = position_conflicts::last_synced();
+    let pending_sync = pending_sync(last_synced)
+        .await
+        .unwrap_or_else(|e| vec![format!("Couldn't tell: {e}")]);
+
+    Snapshot {
+        player_state,
+        tables,
+        pending_sync,
+        conflicts: position_conflicts::load_conflicts(),
+        unfinished: downloads::unfinished().await,
+        last_synced,
+        device_id: sync::device_id(),
+    }
+}
+
+pub(crate) enum DevMsg {
+    /// Gathers everything again
+    Refresh,
+    /// Sets what's shown
+    SetSnapshot(Snapshot),
+    /// Deletes everything in the given table, once the developer confirms
+    ClearTable(&'static str),
+    /// Forgets the positions that were held back
+    ClearConflicts,
+    /// Forgets the kept log entries
+    ClearLog,
+    /// Simulates the given network condition
+    SetNetwork(NetworkCondition),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+/// The developer view
+#[derive(Default)]
+pub(crate) struct DevView {
+    snapshot: Option<Snapshot>,
+    log: Vec<LogEntry>,
+    err: Option<AnyError>,
+}
+
+impl DevView {
+    /// Renders the number of values in each table, with a button to clear it
+    fn render_tables(&self, ctx: &Context<Self>, snapshot: &Snapshot) -> Html {
+        let rows: Html = snapshot
+            .tables
+            .iter()
+            .map(|&(table, ref count)| {
+                let count = match count {
+                    Ok(n) => n.to_string(),
+                    Err(e) => e.clone(),
+                };
+                let clear = ctx.link().callback(move |_| DevMsg::ClearTable(table));
+                html! {
+                    <tr>
+                        <td><code>{ table }</code></td>
+                        <td>{ count }</td>
+                        <td>
+                            if caching::is_clearable(table) {
+                                <button onclick={clear} aria-label={ format!("Clear {table}") }>
+                                    { "Clear" }
+                                </button>
+                            } else {
+                                { "Holds the encryption key, so it's kept" }
+                            }
+                        </td>
+                    </tr>
+                }
+            })
+            .collect();
+        html! {
+            <section>
+                <h2>{ "IndexedDB" }</h2>
+                <p class="articleMetadata">{
+                    "Reload the page after clearing a table, so nothing that's open puts its old \
+                    values back."
+                }</p>
+                <table aria-label="Tables">{ rows }</table>
+            </section>
+        }
+    }
+
+    /// Renders what's waiting to be synced, and what came from syncing that's waiting on the
+    /// listener
+    fn render_sync(&self, ctx: &Context<Self>, snapshot: &Snapshot) -> Html {
+        let last_synced = if snapshot.last_synced > 0.0 {
+            format_ms(snapshot.last_synced)
+        } else {
+            "never".to_string()
+        };
+        let list = |items: Vec<String>| -> Html {
+            if items.is_empty() {
+                html! { <p class="articleMetadata">{ "None" }</p> }
+            } else {
+                let items: Html = items
+                    .into_iter()
+                    .map(|i| html! { <li>{ i }</li> })
+                    .collect();
+                html! { <ul>{ items }</ul> }
+            }
+        };
+        let conflicts = snapshot
+            .conflicts
+            .iter()
+            .map(|c| {
+                format!(
+                    "{}: {} here, {} on {}",
+                    c.id.0,
+                    player_view::format_time(c.local.elapsed),
+                    player_view::format_time(c.remote.elapsed),
+                    c.remote_name
+                )
+            })
+            .collect();
+        let unfinished = snapshot
+            .unfinished
+            .iter()
+            .map(|entry| entry.title.clone())
+            .collect();
+        let clear_conflicts = ctx.link().callback(|_| DevMsg::ClearConflicts);
+
+        html! {
+            <section>
+                <h2>{ "Sync" }</h2>
+                <p>{ format!("Device ID: {}", snapshot.device_id) }</p>
+                <p>{ format!("Last synced: {last_synced}") }</p>
+                <h3>{ "Changed since" }</h3>
+                { list(snapshot.pending_sync.clone()) }
+                <h3>{ "Held back positions" }</h3>
+                { list(conflicts) }
+                if !snapshot.conflicts.is_empty() {
+                    <button onclick={clear_conflicts}>{ "Forget held back positions" }</button>
+                }
+                <h3>{ "Unfinished downloads" }</h3>
+                { list(unfinished) }
+            </section>
+        }
+    }
+
+    /// Renders the picker for the simulated network condition
+    fn render_network(&self, ctx: &Context<Self>) -> Html {
+        let current = network_sim::condition();
+        let options: Html = NetworkCondition::ALL
+            .into_iter()
+            .map(|condition| {
+                let onchange = ctx.link().callback(move |_| DevMsg::SetNetwork(condition));
+                html! {
+                    <label class="field">
+                        <input
+                            type="radio"
+                            name="network-condition"
+                            checked={condition == current}
+                            {onchange}
+                        />
+                        { format!(" {}", condition.label()) }
+                    </label>
+                }
+            })
+            .collect();
+        html! {
+            <fieldset>
+                <legend><h2>{ "Network" }</h2></legend>
+                <p class="articleMetadata">{
+                    "Requests to the server behave as picked here, on this device, until it's set \
+                    back to normal. Nothing else is affected."
+                }</p>
+                { options }
+            </fieldset>
+        }
+    }
+
+    /// Renders the kept log entries
+    fn render_log(&self, ctx: &Context<Self>) -> Html {
+        let lines = self
+            .log
+            .iter()
+            .map(format_entry)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let clear_log = ctx.link().callback(|_| DevMsg::ClearLog);
+        html! {
+            <section>
+                <h2>{ "Log" }</h2>
+                <p class="articleMetadata">{
+                    format!("The latest {} entries, oldest first, without trace entries", self.log.len())
+                }</p>
+                <pre class="devLog">{ lines }</pre>
+                <button onclick={clear_log}>{ "Clear log" }</button>
+            </section>
+        }
+    }
+}
+
+impl Component for DevView {
+    type Message = DevMsg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(DevMsg::Refresh);
+        DevView::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            DevMsg::Refresh => {
+                ctx.link()
+                    .send_future(async move { DevMsg::SetSnapshot(load_snapshot().await) });
+                return false;
+            }
+
+            DevMsg::SetSnapshot(snapshot) => {
+                self.snapshot = Some(snapshot);
+                self.log = log_buffer::entries();
+            }
+
+            DevMsg::ClearTable(table) => {
+                if !caching::is_clearable(table) {
+                    return false;
+                }
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message(&format!(
+                        "Delete everything in {table}? This can't be undone."
+                    ))
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+                ctx.link().send_future(async move {
+                    match caching::table_clear(table).await {
+                        Ok(()) => DevMsg::Refresh,
+                        Err(e) => DevMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            DevMsg::ClearConflicts => {
+                for conflict in position_conflicts::load_conflicts() {
+                    position_conflicts::resolve(&conflict.id);
+                }
+                ctx.link().send_message(DevMsg::Refresh);
+                return false;
+            }
+
+            DevMsg::ClearLog => {
+                log_buffer::clear();
+                self.log.clear();
+            }
+
+            DevMsg::SetNetwork(condition) => {
+                network_sim::set_condition(condition);
+            }
+
+            DevMsg::SetError(e) => {
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let refresh = ctx.link().callback(|_| DevMsg::Refresh);
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+        let body = match &self.snapshot {
+            None => html! { <p>{ "Loading…" }</p> },
+            Some(snapshot) => html! {
+                <>
+                    <section>
+                        <h2>{ "Player state" }</h2>
+                        <p class="articleMetadata">{ "As last saved" }</p>
+                        <pre class="devLog">{ &snapshot.player_state }</pre>
+                    </section>
+                    { self.render_tables(ctx, snapshot) }
+                    { self.render_sync(ctx, snapshot) }
+                    { self.render_log(ctx) }
+                </>
+            },
+        };
+
+        html! {
+            <main>
+                <h1>{ "Developer" }</h1>
+                <p>
+                    { "What this device has saved, for bug reports. " }
+                    <button onclick={refresh}>{ "Refresh" }</button>
+                </p>
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+                { self.render_network(ctx) }
+                { body }
+            </main>
+        }
+    }
+}
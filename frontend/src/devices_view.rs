@@ -0,0 +1,322 @@
+//! The devices that sync with the library, with when each last synced and how much audio it has
+//! saved, so they can be renamed and revoked. The queued articles are listed with the device that
+//! got to each one's position, which explains a position that jumped after syncing.
+
+use crate::{caching, encryption, player_view, position_conflicts, storage_view, sync, utils};
+use common::{DeviceInfo, DeviceRename, DeviceRevocation};
+
+use anyhow::{bail, Error as AnyError};
+use wasm_bindgen::JsValue;
+use yew::prelude::*;
+
+/// Fetches the devices that sync with the library
+pub(crate) async fn fetch_devices() -> Result<Vec<DeviceInfo>, AnyError> {
+    let resp = utils::get("/api/list-devices")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching devices"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing devices JSON"))
+}
+
+/// Gives the device with the given ID the given nickname
+async fn rename_device(id: String, nickname: String) -> Result<(), AnyError> {
+    let resp = utils::post("/api/rename-device")
+        .json(&DeviceRename { id, nickname })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error renaming device"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+/// Revokes the device with the given ID
+async fn revoke_device(id: String) -> Result<(), AnyError> {
+    let resp = utils::post("/api/revoke-device")
+        .json(&DeviceRevocation { id })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error revoking device"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+/// Where a queued article's position came from
+pub(crate) struct PositionSource {
+    title: String,
+    /// The elapsed time, in seconds
+    elapsed: f64,
+    finished: bool,
+    /// The ID of the device that got to it, if it wasn't this one
+    device: Option<String>,
+}
+
+/// Loads where the positions in the queued articles came from. Articles from the encrypted library
+/// aren't synced, so they're left out.
+async fn load_position_sources() -> Vec<PositionSource> {
+    let queue = caching::load_queue().await.unwrap_or_default();
+    let mut sources = Vec::new();
+    for entry in queue.entries() {
+        if encryption::is_private(&entry.id) {
+            continue;
+        }
+        if let Ok(state) = caching::load_article_state(&entry.id).await {
+            sources.push(PositionSource {
+                title: entry.title.clone(),
+                elapsed: state.elapsed,
+                finished: state.finished,
+                device: state.device,
+            });
+        }
+    }
+    sources
+}
+
+/// Formats the given time in seconds since the Unix epoch as a date and time
+fn format_time(t: u64) -> String {
+    let js_date = js_sys::Date::new(&JsValue::from_f64(t as f64 * 1000.0));
+    js_date
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+pub(crate) enum DevicesMsg {
+    /// Fetches the devices and the positions
+    Refresh,
+    /// Sets the devices and the positions
+    SetDevices(Vec<DeviceInfo>, Vec<PositionSource>),
+    /// Asks for a new nickname for the device with the given ID, and gives it that
+    Rename(String),
+    /// Revokes the device with the given ID
+    Revoke(String),
+    /// Turns letting the furthest position win without asking on or off
+    ToggleFurthestWins,
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+/// The list of devices on the main page
+#[derive(Default)]
+pub(crate) struct Devices {
+    devices: Vec<DeviceInfo>,
+    positions: Vec<PositionSource>,
+    err: Option<AnyError>,
+}
+
+impl Devices {
+    /// Returns the nickname of the device with the given ID, as far as this device knows, which has
+    /// the given ID
+    fn nickname(&self, device: Option<&str>, this_device: &str) -> String {
+        match device {
+            None => "this device".to_string(),
+            Some(id) if id == this_device => "this device".to_string(),
+            Some(id) => self
+                .devices
+                .iter()
+                .find(|d| d.id == id)
+                .map_or_else(|| "a revoked device".to_string(), |d| d.nickname.clone()),
+        }
+    }
+
+    /// Renders the queued articles with the device each one's position came from
+    fn render_positions(&self, this_device: &str) -> Html {
+        if self.positions.is_empty() {
+            return Html::default();
+        }
+        let rows: Html = self
+            .positions
+            .iter()
+            .map(|pos| {
+                let position = if pos.finished {
+                    "Finished".to_string()
+                } else {
+                    player_view::format_time(pos.elapsed)
+                };
+                html! {
+                    <tr>
+                        <td><bdi>{ &pos.title }</bdi></td>
+                        <td>{ position }</td>
+                        <td>{ format!("from {}", self.nickname(pos.device.as_deref(), this_device)) }</td>
+                    </tr>
+                }
+            })
+            .collect();
+        html! {
+            <>
+                <p class="articleMetadata">{ "Where each queued article's position came from:" }</p>
+                <table aria-label="Positions by device">{ rows }</table>
+            </>
+        }
+    }
+}
+
+impl Component for Devices {
+    type Message = DevicesMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Devices::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            DevicesMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    match fetch_devices().await {
+                        Ok(devices) => {
+                            DevicesMsg::SetDevices(devices, load_position_sources().await)
+                        }
+                        Err(e) => DevicesMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            DevicesMsg::SetDevices(devices, positions) => {
+                self.devices = devices;
+                self.positions = positions;
+                self.err = None;
+            }
+
+            DevicesMsg::Rename(id) => {
+                let current = self
+                    .devices
+                    .iter()
+                    .find(|d| d.id == id)
+                    .map(|d| d.nickname.clone())
+                    .unwrap_or_default();
+                let nickname = gloo_utils::window()
+                    .prompt_with_message_and_default("Nickname:", &current)
+                    .ok()
+                    .flatten()
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty() && *name != current);
+                let Some(nickname) = nickname else {
+                    return false;
+                };
+                ctx.link().send_future(async move {
+                    match rename_device(id, nickname).await {
+                        Ok(()) => DevicesMsg::Refresh,
+                        Err(e) => DevicesMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            DevicesMsg::Revoke(id) => {
+                ctx.link().send_future(async move {
+                    match revoke_device(id).await {
+                        Ok(()) => DevicesMsg::Refresh,
+                        Err(e) => DevicesMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            DevicesMsg::ToggleFurthestWins => {
+                position_conflicts::set_furthest_wins(!position_conflicts::furthest_wins());
+            }
+
+            DevicesMsg::SetError(e) => {
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let this_device = sync::device_id();
+        let rows: Html = self
+            .devices
+            .iter()
+            .map(|device| {
+                let is_this_device = device.id == this_device;
+                let id = device.id.clone();
+                let rename = link.callback(move |_| DevicesMsg::Rename(id.clone()));
+                let id = device.id.clone();
+                let revoke = link.callback(move |_| DevicesMsg::Revoke(id.clone()));
+                html! {
+                    <tr>
+                        <td>
+                            <bdi>{ &device.nickname }</bdi>
+                            if is_this_device {
+                                { " (this device)" }
+                            }
+                        </td>
+                        <td>{ format!("Last synced {}", format_time(device.last_seen)) }</td>
+                        <td>{ format!(
+                            "{} saved",
+                            storage_view::format_megabytes(device.cached_bytes as f64)
+                        ) }</td>
+                        <td>
+                            <button
+                                onclick={rename}
+                                aria-label={ format!("Rename {}", device.nickname) }
+                            >
+                                { "Rename" }
+                            </button>
+                            // Revoking this device would only sign it out from under the listener
+                            if !is_this_device {
+                                <button
+                                    onclick={revoke}
+                                    aria-label={ format!("Revoke {}", device.nickname) }
+                                >
+                                    { "Revoke" }
+                                </button>
+                            }
+                        </td>
+                    </tr>
+                }
+            })
+            .collect();
+        let list = if self.devices.is_empty() {
+            html! {
+                <p class="articleMetadata">{
+                    "No devices have synced yet. They're listed once they do."
+                }</p>
+            }
+        } else {
+            html! { <table aria-label="Devices">{ rows }</table> }
+        };
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+        let ontoggle = link.callback(|_| DevicesMsg::Refresh);
+        let toggle_furthest_wins = link.callback(|_| DevicesMsg::ToggleFurthestWins);
+
+        html! {
+            <details class="devices" {ontoggle}>
+                <summary>{ "Devices" }</summary>
+                { list }
+                <p class="articleMetadata">{
+                    "Revoking a device stops it syncing, and signs it out if accounts are on."
+                }</p>
+                <p>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={position_conflicts::furthest_wins()}
+                            onchange={toggle_furthest_wins}
+                        />
+                        { " Resolve conflicting positions automatically: the furthest one wins, \
+                        instead of asking which to continue from" }
+                    </label>
+                </p>
+                { self.render_positions(&this_device) }
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </details>
+        }
+    }
+}
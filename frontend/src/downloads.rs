@@ -0,0 +1,412 @@
+//! Downloads the audio of articles in the background. The audio is fetched in chunks with HTTP range
+//! requests, and every chunk is saved to IndexedDB as soon as it arrives, so a download that's
+//! interrupted, whether by a dropped connection or by the page closing, picks up where it left off.
+//! Chunks that fail for reasons that might go away on their own are retried with exponential
+//! backoff.
+//!
+//! The server can transcode audio to formats that are smaller than MP3. Downloads ask for the ones
+//! the browser says it can play, those it decodes efficiently first, and the server sends the first
+//! of them it has, or MP3 otherwise. The format that was sent is kept with the article.
+//!
+//! The size of audio that isn't downloaded can be probed with a HEAD request, so the library can
+//! show it without downloading anything.
+//!
+//! Before downloading from the server, another of the listener's devices on the same network is
+//! asked for the audio (see `peers`).
+
+use crate::{
+    caching, peers, player_view,
+    queue_view::{ArticleId, CachedArticle, QueueEntry},
+    servers, utils,
+};
+
+use std::cell::RefCell;
+
+use anyhow::{anyhow, Error as AnyError};
+use gloo_net::http::{Method, Request};
+use gloo_timers::future::TimeoutFuture;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AudioConfiguration, Blob, HtmlAudioElement, MediaCapabilitiesInfo, MediaDecodingConfiguration,
+    MediaDecodingType,
+};
+
+/// The number of bytes fetched per request
+const CHUNK_SIZE: usize = 512 * 1024;
+
+/// The MIME type chunks are saved with. The type of the whole audio is kept with the article.
+pub(crate) const CHUNK_MIME_TYPE: &str = "application/octet-stream";
+
+/// The number of times a chunk is tried before the download is given up on
+const MAX_ATTEMPTS: u32 = 5;
+
+/// The number of milliseconds to wait before the first retry of a chunk. Every retry after that
+/// waits twice as long as the one before.
+const FIRST_RETRY_DELAY: u32 = 1000;
+
+/// The formats the server might transcode audio to, in order of preference, with the MIME types
+/// the browser is asked about. Opus is the smallest for speech.
+const TRANSCODE_FORMATS: &[(&str, &str)] =
+    &[("opus", "audio/ogg; codecs=opus"), ("aac", "audio/aac")];
+
+thread_local!(
+    /// The `formats` parameter, once it's been worked out. The browser's answers don't change while
+    /// the page is open, and asking takes a round trip through the Media Capabilities API.
+    static PLAYABLE_FORMATS: RefCell<Option<String>> = RefCell::default()
+);
+
+/// A download that hasn't finished yet. This is what's kept in IndexedDB, alongside the chunks
+/// received so far.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct PartialDownload {
+    /// The article being downloaded. This is flattened so the download is keyed by article ID.
+    #[serde(flatten)]
+    pub(crate) entry: QueueEntry,
+    /// The size of the audio in bytes, if the server has said yet
+    pub(crate) total_size: Option<usize>,
+    /// The number of chunks received so far
+    pub(crate) num_chunks: usize,
+    /// The MIME type of the audio, if the server has said yet
+    #[serde(default)]
+    pub(crate) mime_type: Option<String>,
+}
+
+/// A chunk of audio from the server
+struct Chunk {
+    bytes: Vec<u8>,
+    /// The size of the whole audio
+    total_size: usize,
+    /// Whether the server ignored the range and sent the whole audio
+    whole: bool,
+    /// The MIME type of the audio
+    mime_type: String,
+}
+
+/// The ways fetching a chunk can fail
+enum ChunkError {
+    /// The failure might go away if the chunk is tried again, e.g., the connection dropped
+    Transient(AnyError),
+    /// Trying again won't help, e.g., the article doesn't exist
+    Fatal(AnyError),
+}
+
+/// Returns whether an HTTP error status might go away on its own
+fn is_transient_status(status: u16) -> bool {
+    status >= 500 || status == 408 || status == 429
+}
+
+/// Parses the total size out of a Content-Range header, e.g., `bytes 0-524287/1234567`
+fn parse_total_size(content_range: &str) -> Option<usize> {
+    content_range.rsplit('/').next()?.trim().parse().ok()
+}
+
+/// Fetches the chunk of the audio at the given URL that starts at the given byte
+async fn fetch_chunk(url: &str, start: usize) -> Result<Chunk, ChunkError> {
+    let range = format!("bytes={}-{}", start, start + CHUNK_SIZE - 1);
+    let resp = utils::get(url)
+        .header("Range", &range)
+        .send()
+        .await
+        .map_err(|e| ChunkError::Transient(e.into()))?;
+
+    let status = resp.status();
+    if !resp.ok() {
+        let err = anyhow!("{} ({})", status, resp.status_text());
+        return Err(if is_transient_status(status) {
+            ChunkError::Transient(err)
+        } else {
+            ChunkError::Fatal(err)
+        });
+    }
+
+    let content_range = resp.headers().get("content-range");
+    let mime_type = resp
+        .headers()
+        .get("content-type")
+        .unwrap_or_else(|| utils::MP3_MIME_TYPE.to_string());
+    let bytes = resp
+        .binary()
+        .await
+        .map_err(|e| ChunkError::Transient(e.into()))?;
+
+    // A 206 is the requested range. Anything else is the whole audio.
+    if status == 206 {
+        let total_size = content_range
+            .as_deref()
+            .and_then(parse_total_size)
+            .ok_or_else(|| ChunkError::Fatal(anyhow!("malformed Content-Range")))?;
+        Ok(Chunk {
+            bytes,
+            total_size,
+            whole: false,
+            mime_type,
+        })
+    } else {
+        Ok(Chunk {
+            total_size: bytes.len(),
+            bytes,
+            whole: true,
+            mime_type,
+        })
+    }
+}
+
+/// Fetches the chunk of the audio at the given URL that starts at the given byte, retrying
+/// transient failures with exponential backoff
+async fn fetch_chunk_with_retries(url: &str, start: usize) -> Result<Chunk, AnyError> {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match fetch_chunk(url, start).await {
+            Ok(chunk) => return Ok(chunk),
+            Err(ChunkError::Transient(e)) if attempt < MAX_ATTEMPTS => {
+                tracing::warn!("Fetching {url} from byte {start} failed, retrying: {e}");
+                TimeoutFuture::new(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(ChunkError::Transient(e)) | Err(ChunkError::Fatal(e)) => return Err(e),
+        }
+    }
+}
+
+/// Asks the browser whether it can decode audio of the given MIME type, and whether it can do so
+/// without draining the battery, e.g., in hardware. Returns `None` if the browser doesn't have the
+/// Media Capabilities API, or won't say.
+async fn decoding_info(mime_type: &str) -> Option<MediaCapabilitiesInfo> {
+    let navigator = gloo_utils::window().navigator();
+    // Older browsers don't have navigator.mediaCapabilities at all
+    if !js_sys::Reflect::has(&navigator, &"mediaCapabilities".into()).unwrap_or(false) {
+        return None;
+    }
+
+    let mut audio = AudioConfiguration::new();
+    audio.content_type(mime_type);
+    let mut config = MediaDecodingConfiguration::new(MediaDecodingType::File);
+    config.audio(&audio);
+    let info = JsFuture::from(navigator.media_capabilities().decoding_info(&config))
+        .await
+        .ok()?;
+    Some(info.unchecked_into())
+}
+
+/// Returns the formats the server could transcode to that this browser can play, best first,
+/// comma-separated for the `formats` parameter. Formats the browser says it decodes efficiently
+/// come before the ones it doesn't, and otherwise they're in the order of `TRANSCODE_FORMATS`. If
+/// none are playable, the server sends MP3.
+async fn playable_formats() -> String {
+    if let Some(formats) = PLAYABLE_FORMATS.with(|formats| formats.borrow().clone()) {
+        return formats;
+    }
+
+    let audio = match HtmlAudioElement::new() {
+        Ok(audio) => audio,
+        Err(_) => return String::new(),
+    };
+    let mut playable = Vec::new();
+    for (name, mime_type) in TRANSCODE_FORMATS {
+        // canPlayType says "probably", "maybe", or nothing
+        if audio.can_play_type(mime_type).is_empty() {
+            continue;
+        }
+        let efficient = match decoding_info(mime_type).await {
+            Some(info) if !info.supported() => continue,
+            Some(info) => info.power_efficient(),
+            // Without a say either way, go by preference
+            None => true,
+        };
+        playable.push((*name, efficient));
+    }
+    // The sort is stable, so the preference order holds among equals
+    playable.sort_by_key(|&(_, efficient)| !efficient);
+
+    let formats = playable
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>()
+        .join(",");
+    PLAYABLE_FORMATS.with(|cached| *cached.borrow_mut() = Some(formats.clone()));
+    formats
+}
+
+/// Returns the URL of the given article's audio, in the best format for this browser
+async fn audio_url(id: &ArticleId) -> String {
+    let filename = format!("{}.mp3", id.0);
+    format!(
+        "/api/audio-blobs/{}?formats={}",
+        urlencoding::encode(&filename),
+        playable_formats().await,
+    )
+}
+
+/// Asks the server how many bytes downloading the given article's audio would take, without
+/// downloading it. If the server hasn't transcoded the audio yet, this is the size of the MP3,
+/// which is bigger.
+pub(crate) async fn probe_size(id: &ArticleId) -> Result<f64, AnyError> {
+    let resp = utils::authorize(Request::new(&servers::api_url(&audio_url(id).await)))
+        .method(Method::HEAD)
+        .send()
+        .await
+        .map_err(|e| anyhow!("Error probing the audio of {:?}: {e}", id))?;
+    if !resp.ok() {
+        return Err(anyhow!(
+            "Error probing the audio of {:?}: {} ({})",
+            id,
+            resp.status(),
+            resp.status_text()
+        ));
+    }
+
+    resp.headers()
+        .get("content-length")
+        .and_then(|len| len.parse().ok())
+        .ok_or_else(|| anyhow!("The audio of {:?} has no length", id))
+}
+
+/// Loads the chunks of audio that an earlier attempt at the given download received. If any of them
+/// can't be loaded, the download starts over.
+async fn load_received(download: &mut PartialDownload) -> Vec<Blob> {
+    let mut chunks = Vec::new();
+    for i in 0..download.num_chunks {
+        match caching::load_download_chunk(&download.entry.id, i).await {
+            Ok(chunk) => chunks.push(chunk),
+            Err(e) => {
+                tracing::warn!("Restarting download of {}: {}", download.entry.id.0, e);
+                download.total_size = None;
+                download.num_chunks = 0;
+                download.mime_type = None;
+                return Vec::new();
+            }
+        }
+    }
+    chunks
+}
+
+/// Returns the number of bytes in the given chunks
+fn received_bytes(chunks: &[Blob]) -> usize {
+    chunks.iter().map(|chunk| chunk.size() as usize).sum()
+}
+
+/// Downloads the audio of the given article, picking up from wherever an earlier attempt left off,
+/// and saves the article. `on_progress` is called with the fraction downloaded after every chunk.
+/// Returns the entry to put in the queue.
+pub(crate) async fn download(
+    entry: QueueEntry,
+    on_progress: impl Fn(f64),
+) -> Result<QueueEntry, AnyError> {
+    let id = entry.id.clone();
+    // An article that was taken out of the queue, but kept, is already here
+    if caching::has_article(&id).await {
+        return Ok(entry);
+    }
+    let url = audio_url(&id).await;
+
+    let mut download = match caching::load_download(&id).await {
+        Ok(download) => PartialDownload { entry, ..download },
+        Err(_) => PartialDownload {
+            entry,
+            total_size: None,
+            num_chunks: 0,
+            mime_type: None,
+        },
+    };
+    // The audio is kept as the blobs the chunks were saved as, so the whole of it is never in the
+    // app's memory at once
+    let mut chunks = load_received(&mut download).await;
+
+    // Another device nearby might have it already. Downloads that are partway through carry on
+    // from the server, so what they've received isn't thrown away.
+    if chunks.is_empty() {
+        if let Some(article) = peers::fetch(&download.entry, &on_progress).await {
+            caching::save_article(&article).await?;
+            if let Err(e) = caching::delete_download(&download).await {
+                tracing::error!("Couldn't clean up the download of {}: {}", id.0, e);
+            }
+            return Ok(download.entry);
+        }
+    }
+
+    let mut received = received_bytes(&chunks);
+
+    loop {
+        if let Some(total_size) = download.total_size {
+            if received >= total_size {
+                break;
+            }
+            on_progress(received as f64 / total_size as f64);
+        }
+
+        let chunk = fetch_chunk_with_retries(&url, received)
+            .await
+            .map_err(|e| e.context(format!("Error fetching article {:?}", id)))?;
+        if chunk.whole {
+            chunks = vec![utils::bytes_to_blob(&chunk.bytes, CHUNK_MIME_TYPE)];
+            download.mime_type = Some(chunk.mime_type);
+            break;
+        }
+        // If the format changed, e.g., because the server's transcode formats did, the chunks from
+        // before are of no use. Downloads started before the format was kept are MP3s.
+        let received_type = download.mime_type.as_deref().unwrap_or("audio/mpeg");
+        if download.num_chunks > 0 && received_type != chunk.mime_type {
+            tracing::warn!("Restarting download of {}: its format changed", id.0);
+            chunks.clear();
+            received = 0;
+            download.num_chunks = 0;
+            download.total_size = None;
+            download.mime_type = None;
+            continue;
+        }
+        if chunk.bytes.is_empty() {
+            return Err(anyhow!("Article {:?} ended early", id));
+        }
+
+        // Save the chunk before counting it, so a saved download never counts a missing chunk
+        let blob = utils::bytes_to_blob(&chunk.bytes, CHUNK_MIME_TYPE);
+        caching::save_download_chunk(&id, download.num_chunks, &blob).await?;
+        download.num_chunks += 1;
+        download.total_size = Some(chunk.total_size);
+        download.mime_type = Some(chunk.mime_type);
+        caching::save_download(&download).await?;
+        received += chunk.bytes.len();
+        chunks.push(blob);
+    }
+    on_progress(1.0);
+
+    // Fetch the transcript too, so sections can be skipped between and read along with offline
+    let transcript = player_view::fetch_transcript(&id)
+        .await
+        .map_err(|e| tracing::debug!("No transcript for {}: {:#}", id.0, e))
+        .unwrap_or_default();
+
+    let article = CachedArticle {
+        title: download.entry.title.clone(),
+        id: id.clone(),
+        audio_chunks: chunks,
+        mime_type: download
+            .mime_type
+            .clone()
+            .unwrap_or_else(|| utils::MP3_MIME_TYPE.to_string()),
+        transcript,
+    };
+    caching::save_article(&article).await?;
+
+    // The article's saved, so the chunks aren't needed anymore
+    if let Err(e) = caching::delete_download(&download).await {
+        tracing::error!("Couldn't clean up the download of {}: {}", id.0, e);
+    }
+
+    Ok(download.entry)
+}
+
+/// Returns the downloads that were interrupted before they finished, e.g., by the page closing
+pub(crate) async fn unfinished() -> Vec<QueueEntry> {
+    match caching::load_downloads().await {
+        Ok(downloads) => downloads.into_iter().map(|d| d.entry).collect(),
+        Err(e) => {
+            tracing::error!("Couldn't load unfinished downloads: {}", e);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! The encrypted library. Articles added to it are encrypted on this device, with an AES-GCM key
+//! that's made here, kept in IndexedDB, and can't be exported, so the server only ever has
+//! ciphertext. They're decrypted when they're put in the queue.
+//!
+//! That privacy costs a few things. The server and its TTS engine still see the text while it's
+//! being synthesized, since that's how it gets read aloud; they just don't keep it. Articles can
+//! only be added as text, since the server would have to keep a URL to fetch it. Nothing in the
+//! encrypted library can be searched, and only the device that added an article can play it. The
+//! queue entries of encrypted articles aren't synced, so their titles stay on this device, which
+//! means a queue synced from another device can drop them. They're still in the encrypted library.
+//!
+//! An uploaded article is the length of its encrypted metadata as a big-endian `u32`, then the
+//! encrypted metadata, then the encrypted audio. See `encrypted.rs` in the server.
+
+use crate::{
+    caching,
+    queue_view::{ArticleId, CachedArticle, QueueEntry},
+    utils,
+};
+use common::{ArticleDeletion, ArticleTextSubmission, EncryptedArticleInfo};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AesGcmParams, AesKeyGenParams, CryptoKey, SubtleCrypto};
+
+/// The prefix of the IDs of encrypted articles in the queue, which keeps them from being synced
+pub(crate) const PRIVATE_ID_PREFIX: &str = "private-";
+
+/// The length of the random IV at the start of every ciphertext, in bytes
+const IV_LEN: usize = 12;
+
+/// What the encrypted library knows about an article, besides its audio. It's encrypted along
+/// with the audio, so the server never sees it.
+#[derive(Serialize, Deserialize)]
+struct PrivateArticleMeta {
+    title: String,
+    /// When the article was added, in milliseconds since the Unix epoch
+    added: f64,
+}
+
+/// An article in the encrypted library
+#[derive(Clone, PartialEq)]
+pub(crate) struct PrivateArticle {
+    /// The ID the server gave the article
+    pub(crate) id: String,
+    /// The article's title, or `None` if this device can't decrypt it, e.g., it was added on
+    /// another device
+    pub(crate) title: Option<String>,
+    /// When the article was uploaded, in seconds since the Unix epoch
+    pub(crate) uploaded: u64,
+    /// The size of the encrypted article, in bytes
+    pub(crate) size: u64,
+}
+
+/// Returns whether the given queue entry is an article from the encrypted library
+pub(crate) fn is_private(id: &ArticleId) -> bool {
+    id.0.starts_with(PRIVATE_ID_PREFIX)
+}
+
+/// Returns the browser's WebCrypto interface
+fn subtle() -> Result<SubtleCrypto, AnyError> {
+    let crypto = gloo_utils::window()
+        .crypto()
+        .map_err(|e| utils::wrap_jserror("couldn't get WebCrypto", e))?;
+    Ok(crypto.subtle())
+}
+
+/// Loads the key that encrypts the articles this device adds, or makes one if it has none
+async fn device_key() -> Result<CryptoKey, AnyError> {
+    if let Some(key) = caching::load_device_key().await? {
+        return Ok(key);
+    }
+
+    tracing::info!("Making a new device key for the encrypted library");
+    let params = AesKeyGenParams::new("AES-GCM", 256);
+    let usages = js_sys::Array::of2(&"encrypt".into(), &"decrypt".into());
+    let promise = subtle()?
+        .generate_key_with_object(&params, false, &usages)
+        .map_err(|e| utils::wrap_jserror("couldn't make key", e))?;
+    let key: CryptoKey = JsFuture::from(promise)
+        .await
+        .map_err(|e| utils::wrap_jserror("couldn't make key", e))?
+        .dyn_into()
+        .map_err(|e| utils::wrap_jserror("made key isn't a CryptoKey", e))?;
+
+    caching::save_device_key(&key).await?;
+    Ok(key)
+}
+
+/// Encrypts the given bytes. The ciphertext starts with the random IV it was encrypted with.
+async fn encrypt(key: &CryptoKey, plaintext: &[u8]) -> Result<Vec<u8>, AnyError> {
+    let mut iv = [0u8; IV_LEN];
+    gloo_utils::window()
+        .crypto()
+        .map_err(|e| utils::wrap_jserror("couldn't get WebCrypto", e))?
+        .get_random_values_with_u8_array(&mut iv)
+        .map_err(|e| utils::wrap_jserror("couldn't make IV", e))?;
+
+    let params = AesGcmParams::new("AES-GCM", &js_sys::Uint8Array::from(&iv[..]));
+    let promise = subtle()?
+        .encrypt_with_object_and_u8_array(&params, key, &mut plaintext.to_vec())
+        .map_err(|e| utils::wrap_jserror("couldn't encrypt", e))?;
+    let ciphertext = JsFuture::from(promise)
+        .await
+        .map_err(|e| utils::wrap_jserror("couldn't encrypt", e))?;
+
+    let mut out = iv.to_vec();
+    out.extend(js_sys::Uint8Array::new(&ciphertext).to_vec());
+    Ok(out)
+}
+
+/// Decrypts the given bytes, which start with the IV they were encrypted with. This fails if they
+/// were encrypted with a different key, or changed since.
+async fn decrypt(key: &CryptoKey, ciphertext: &[u8]) -> Result<Vec<u8>, AnyError> {
+    if ciphertext.len() < IV_LEN {
+        bail!("The ciphertext is truncated");
+    }
+    let (iv, data) = ciphertext.split_at(IV_LEN);
+
+    let params = AesGcmParams::new("AES-GCM", &js_sys::Uint8Array::from(iv));
+    let promise = subtle()?
+        .decrypt_with_object_and_u8_array(&params, key, &mut data.to_vec())
+        .map_err(|e| utils::wrap_jserror("couldn't decrypt", e))?;
+    let plaintext = JsFuture::from(promise)
+        .await
+        .map_err(|e| utils::wrap_jserror("couldn't decrypt", e))?;
+    Ok(js_sys::Uint8Array::new(&plaintext).to_vec())
+}
+
+/// Decrypts the given metadata
+async fn decrypt_meta(key: &CryptoKey, ciphertext: &[u8]) -> Result<PrivateArticleMeta, AnyError> {
+    let json = String::from_utf8(decrypt(key, ciphertext).await?)?;
+    js_sys::JSON::parse(&json)
+        .map_err(|e| utils::wrap_jserror("couldn't parse metadata", e))?
+        .into_serde()
+        .map_err(|e| AnyError::from(e).context("couldn't parse metadata"))
+}
+
+/// Has the server read the given article aloud without keeping it, then encrypts the audio and
+/// puts it in the encrypted library. Returns the article's title.
+pub(crate) async fn add_private_article(
+    submission: &ArticleTextSubmission,
+) -> Result<String, AnyError> {
+    let resp = utils::post("/api/synthesize-private")
+        .json(submission)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error converting article"))?;
+    if !resp.ok() {
+        bail!(
+            "Error converting article: {}",
+            utils::resp_error(resp).await
+        );
+    }
+    let audio = resp
+        .binary()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error reading article audio"))?;
+
+    let key = device_key().await?;
+    let meta = PrivateArticleMeta {
+        title: submission.title.clone(),
+        added: js_sys::Date::now(),
+    };
+    let meta_json: String = js_sys::JSON::stringify(&JsValue::from_serde(&meta)?)
+        .map_err(|e| utils::wrap_jserror("couldn't serialize metadata", e))?
+        .into();
+    let meta = encrypt(&key, meta_json.as_bytes()).await?;
+    let audio = encrypt(&key, &audio).await?;
+
+    let mut upload = (meta.len() as u32).to_be_bytes().to_vec();
+    upload.extend(meta);
+    upload.extend(audio);
+    let resp = utils::post("/api/upload-encrypted-article")
+        .header("Content-Type", "application/octet-stream")
+        .body(js_sys::Uint8Array::from(&upload[..]))
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error uploading encrypted article"))?;
+    if !resp.ok() {
+        bail!(
+            "Error uploading encrypted article: {}",
+            utils::resp_error(resp).await
+        );
+    }
+
+    Ok(submission.title.clone())
+}
+
+/// Fetches the articles in the encrypted library, and decrypts the titles of the ones this device
+/// added
+pub(crate) async fn fetch_private_articles() -> Result<Vec<PrivateArticle>, AnyError> {
+    let resp = utils::get("/api/list-encrypted-articles")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching encrypted library"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    let infos: Vec<EncryptedArticleInfo> = resp
+        .json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing encrypted library JSON"))?;
+
+    // A device that's never added anything can't decrypt anything either
+    let key = caching::load_device_key().await?;
+    let mut articles = Vec::new();
+    for info in infos {
+        let title = match &key {
+            Some(key) => decrypt_meta(key, &info.meta).await.ok().map(|m| m.title),
+            None => None,
+        };
+        articles.push(PrivateArticle {
+            id: info.id,
+            title,
+            uploaded: info.uploaded,
+            size: info.size,
+        });
+    }
+    Ok(articles)
+}
+
+/// Downloads and decrypts the given article from the encrypted library, and caches it so it can be
+/// queued
+pub(crate) async fn download_private_article(id: &str) -> Result<QueueEntry, AnyError> {
+    let resp = utils::get(&format!("/api/encrypted-article/{id}"))
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching encrypted article"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    let upload = resp
+        .binary()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error reading encrypted article"))?;
+
+    let Some(len_bytes) = upload.get(..4) else {
+        bail!("The encrypted article is truncated");
+    };
+    let meta_len = u32::from_be_bytes(len_bytes.try_into()?) as usize;
+    if upload.len() < 4 + meta_len {
+        bail!("The encrypted article is truncated");
+    }
+    let (meta, audio) = upload[4..].split_at(meta_len);
+
+    let key = caching::load_device_key()
+        .await?
+        .ok_or_else(|| anyhow!("This article was encrypted on another device"))?;
+    let meta = decrypt_meta(&key, meta)
+        .await
+        .map_err(|_| anyhow!("This article was encrypted on another device"))?;
+    let audio = decrypt(&key, audio).await?;
+
+    let article = CachedArticle {
+        title: meta.title,
+        id: ArticleId(format!("{PRIVATE_ID_PREFIX}{id}")),
+        audio_chunks: vec![utils::bytes_to_blob(&audio, utils::MP3_MIME_TYPE)],
+        mime_type: utils::MP3_MIME_TYPE.to_string(),
+        transcript: Default::default(),
+    };
+    caching::save_article(&article).await
+}
+
+/// Deletes the given article from the encrypted library
+pub(crate) async fn delete_private_article(id: String) -> Result<(), AnyError> {
+    let resp = utils::post("/api/delete-encrypted-articles")
+        .json(&ArticleDeletion { ids: vec![id] })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error deleting encrypted article"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
@@ -0,0 +1,277 @@
+use crate::utils;
+use common::{FeedSubmission, FeedSubscription};
+
+use anyhow::{bail, Error as AnyError};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+const FEED_URL_FORM_ID: &str = "feed-url-input";
+const OPML_FORM_ID: &str = "feed-opml-input";
+
+/// Fetches the list of feeds the server is subscribed to
+async fn fetch_feeds() -> Result<Vec<FeedSubscription>, AnyError> {
+    let resp = utils::get("/api/list-feeds")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching feed list"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching feed list {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing feed list JSON"))
+}
+
+/// POSTs the given feed to the given endpoint
+async fn submit_feed(endpoint: &str, url: String) -> Result<(), AnyError> {
+    let resp = utils::post(endpoint)
+        .json(&FeedSubmission { url })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+/// Uploads the given OPML file to the server. Returns the number of feeds subscribed to.
+async fn import_opml(file: web_sys::File) -> Result<usize, AnyError> {
+    let opml = utils::file_text(&file).await?;
+
+    let resp = utils::post("/api/import-opml")
+        .body(opml)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error uploading OPML"))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    let added: Vec<FeedSubscription> = resp
+        .json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing new feeds"))?;
+    Ok(added.len())
+}
+
+/// Lists the feeds the server adds articles from, and lets the user subscribe to more
+#[derive(Default)]
+pub(crate) struct Feeds {
+    feeds: Vec<FeedSubscription>,
+    /// What the last subscription did, if anything
+    status: Option<String>,
+    err: Option<AnyError>,
+}
+
+/// Formats the given number of seconds since the Unix epoch as a date and time
+fn format_time(t: u64) -> String {
+    let js_date = js_sys::Date::new(&JsValue::from_f64(t as f64 * 1000.0));
+    js_date
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+/// Describes how a failing feed is failing
+fn feed_health(feed: &FeedSubscription) -> String {
+    let since = feed
+        .last_success
+        .map(|t| format!(" It last worked {}.", format_time(t)))
+        .unwrap_or_default();
+    let streak = match feed.error_streak {
+        1 => "The last check failed".to_string(),
+        n => format!("The last {n} checks failed, so it's being checked less often"),
+    };
+    format!(
+        "{streak}.{since} {}",
+        feed.last_error.clone().unwrap_or_default()
+    )
+}
+
+pub(crate) enum FeedsMsg {
+    /// Fetches the feed list
+    FetchFeeds,
+    /// Sets the feed list
+    SetFeeds(Vec<FeedSubscription>),
+    /// Subscribes to the feed whose URL is in the form
+    Subscribe,
+    /// Unsubscribes from the feed with the given URL
+    Unsubscribe(String),
+    /// Subscribes to every feed in the OPML file picked in the form
+    ImportOpml,
+    /// Sets the status display to the given message, and refreshes the feed list
+    SetStatus(String),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+impl Component for Feeds {
+    type Message = FeedsMsg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(FeedsMsg::FetchFeeds);
+        Feeds::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            FeedsMsg::FetchFeeds => {
+                ctx.link().send_future(async move {
+                    match fetch_feeds().await {
+                        Ok(feeds) => FeedsMsg::SetFeeds(feeds),
+                        Err(e) => FeedsMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            FeedsMsg::SetFeeds(feeds) => {
+                self.feeds = feeds;
+            }
+
+            FeedsMsg::Subscribe => {
+                let input: HtmlInputElement = match gloo_utils::document()
+                    .get_element_by_id(FEED_URL_FORM_ID)
+                    .and_then(|e| e.dyn_into().ok())
+                {
+                    Some(input) => input,
+                    None => return false,
+                };
+                let url = input.value().trim().to_string();
+                if url.is_empty() {
+                    return false;
+                }
+                input.set_value("");
+
+                self.err = None;
+                self.status = Some("Subscribing...".to_string());
+                ctx.link().send_future(async move {
+                    match submit_feed("/api/subscribe-feed", url).await {
+                        Ok(()) => FeedsMsg::SetStatus(
+                            "Subscribed! The newest article is being converted.".to_string(),
+                        ),
+                        Err(e) => FeedsMsg::SetError(e),
+                    }
+                });
+            }
+
+            FeedsMsg::Unsubscribe(url) => {
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match submit_feed("/api/unsubscribe-feed", url).await {
+                        Ok(()) => FeedsMsg::SetStatus("Unsubscribed.".to_string()),
+                        Err(e) => FeedsMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            FeedsMsg::ImportOpml => {
+                let file = match utils::picked_file(OPML_FORM_ID) {
+                    Some(file) => file,
+                    None => return false,
+                };
+
+                self.err = None;
+                self.status = Some("Importing...".to_string());
+                ctx.link().send_future(async move {
+                    match import_opml(file).await {
+                        Ok(n) => FeedsMsg::SetStatus(format!("Subscribed to {n} new feeds.")),
+                        Err(e) => FeedsMsg::SetError(e),
+                    }
+                });
+            }
+
+            FeedsMsg::SetStatus(status) => {
+                self.status = Some(status);
+                ctx.link().send_message(FeedsMsg::FetchFeeds);
+            }
+
+            FeedsMsg::SetError(e) => {
+                self.status = None;
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let feed_items = self
+            .feeds
+            .iter()
+            .map(|feed| {
+                let name = feed.title.clone().unwrap_or_else(|| feed.url.clone());
+                let url = feed.url.clone();
+                let unsubscribe = ctx
+                    .link()
+                    .callback(move |_| FeedsMsg::Unsubscribe(url.clone()));
+                let checked = feed
+                    .last_polled
+                    .map(|t| format!(" Last checked {}.", format_time(t)))
+                    .unwrap_or_default();
+                html! {
+                    <li>
+                        <a href={ feed.url.clone() }>{ name.clone() }</a>
+                        <button onclick={unsubscribe} aria-label={ format!("Unsubscribe from {name}") }>
+                            { "Unsubscribe" }
+                        </button>
+                        <small>{ checked }</small>
+                        if feed.error_streak > 0 {
+                            <p style="color: red;">
+                                { feed_health(feed) }
+                            </p>
+                        }
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        let feed_list = if self.feeds.is_empty() {
+            html! { <p>{ "You aren't subscribed to any feeds." }</p> }
+        } else {
+            html! { <ul>{ feed_items }</ul> }
+        };
+
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or("".to_string());
+
+        let subscribe = ctx.link().callback(|_| FeedsMsg::Subscribe);
+        let import = ctx.link().callback(|_| FeedsMsg::ImportOpml);
+
+        html! {
+            <fieldset>
+                <legend><h2>{ "Feeds" }</h2></legend>
+                <p>{
+                    "New articles from these RSS and Atom feeds are added to the library
+                    automatically"
+                }</p>
+                { feed_list }
+                <div class="field">
+                    <label for={FEED_URL_FORM_ID}>{ "Feed URL:" }</label>
+                    <input type="text" id={FEED_URL_FORM_ID} />
+                </div>
+                <button onclick={subscribe}>{ "Subscribe" }</button>
+                <div class="field">
+                    <label for={OPML_FORM_ID}>{ "Import an OPML file:" }</label>
+                    <input type="file" id={OPML_FORM_ID} accept=".opml,.xml" />
+                </div>
+                <button onclick={import}>{ "Import" }</button>
+                <p aria-live="polite">{ self.status.clone().unwrap_or_default() }</p>
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </fieldset>
+        }
+    }
+}
@@ -0,0 +1,765 @@
+//! The listening history. Every time an article is played, the player records a listening session:
+//! when it started, how much of the article was listened to, and how far through it got. The
+//! history panel lists the recent sessions and the articles that were finished, and the library and
+//! queue show how far each article has been listened to.
+//!
+//! Each session also keeps the stretches of the article that were played without a break, and when.
+//! The panel lays those out as a timeline of each day, so something heard at a certain time can be
+//! found and played again from there.
+//!
+//! The panel also sums up each source (see [`smart_playlist::source_of`]): how many of its articles
+//! were finished once started, how they were rated, and how fast they were listened to. This helps
+//! pick which feeds and newsletters to drop.
+
+use crate::{
+    caching,
+    library_view::{self, Library, LibraryMsg},
+    player_view::{format_time, Player, PlayerMsg, SpeedSample},
+    queue_view::{ArticleId, QueueEntry},
+    smart_playlist, WeakComponentLink,
+};
+use common::{ArticleMetadata, Rating};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// Sessions shorter than this, in seconds of audio, aren't kept. They're usually the listener
+/// skipping past an article, or checking what it is.
+const MIN_SESSION_SECS: f64 = 15.0;
+
+/// How many sessions, and finished articles, the history panel lists
+const MAX_LISTED: usize = 20;
+
+/// How far back "this week" goes, in milliseconds
+const WEEK_MS: f64 = 7.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Playing that picks up within this many milliseconds of where it stopped, at the same place in
+/// the audio, continues the same stretch
+const SPAN_GAP_MS: f64 = 10_000.0;
+
+/// How far apart, in seconds of audio, the end of one stretch and the start of the next can be and
+/// still be the same stretch
+const SPAN_GAP_SECS: f64 = 1.0;
+
+/// The radius of the listening progress ring, which is drawn in a 16×16 box
+const RING_RADIUS: f64 = 6.0;
+
+/// The length of the listening progress ring
+const RING_CIRCUMFERENCE: f64 = 2.0 * std::f64::consts::PI * RING_RADIUS;
+
+/// A stretch of an article that was played without a break
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct ListeningSpan {
+    /// When the stretch started and ended, in milliseconds since the Unix epoch
+    pub(crate) started: f64,
+    pub(crate) ended: f64,
+    /// Where in the audio the stretch started and ended, in seconds
+    pub(crate) from: f64,
+    pub(crate) to: f64,
+}
+
+impl ListeningSpan {
+    /// Returns where in the audio the stretch was at the given time, if it was playing then. The
+    /// position is worked out as if it played at an even speed.
+    fn position_at(&self, time: f64) -> Option<f64> {
+        if time < self.started || time > self.ended {
+            return None;
+        }
+        let fraction = if self.ended > self.started {
+            (time - self.started) / (self.ended - self.started)
+        } else {
+            0.0
+        };
+        Some(self.from + fraction * (self.to - self.from))
+    }
+}
+
+/// One stretch of listening to an article, from when it started playing until something else did
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct ListeningSession {
+    /// The key the session is kept under: the article's ID and when the session started
+    key: String,
+    /// The ID of the article
+    pub(crate) id: ArticleId,
+    /// The title of the article
+    pub(crate) title: String,
+    /// When the session started, in milliseconds since the Unix epoch
+    pub(crate) started: f64,
+    /// How many seconds of the article's audio were listened to
+    pub(crate) secs_listened: f64,
+    /// How far through the article the session got, from 0 to 1
+    pub(crate) progress: f64,
+    /// Whether the session got to the end of the article
+    pub(crate) finished: bool,
+    /// The stretches of the article that were played without a break, in order. Sessions from
+    /// before these were kept don't have any.
+    #[serde(default)]
+    pub(crate) spans: Vec<ListeningSpan>,
+}
+
+impl ListeningSession {
+    /// Starts a session of the given article
+    pub(crate) fn new(entry: &QueueEntry) -> ListeningSession {
+        let started = js_sys::Date::now();
+        ListeningSession {
+            key: format!("{}@{started}", entry.id.0),
+            id: entry.id.clone(),
+            title: entry.title.clone(),
+            started,
+            secs_listened: 0.0,
+            progress: 0.0,
+            finished: false,
+            spans: Vec::new(),
+        }
+    }
+
+    /// Records that the given number of seconds of audio were listened to, and that the session is
+    /// now the given fraction of the way through the article
+    pub(crate) fn listened(&mut self, secs: f64, progress: f64, finished: bool) {
+        self.secs_listened += secs;
+        if progress.is_finite() {
+            self.progress = progress.clamp(0.0, 1.0);
+        }
+        self.finished |= finished;
+    }
+
+    /// Records that the audio from `from` to `to`, in seconds, was just played. This continues the
+    /// last stretch if it picks up where that left off, and starts a new one otherwise.
+    pub(crate) fn played(&mut self, from: f64, to: f64) {
+        let now = js_sys::Date::now();
+        match self.spans.last_mut() {
+            Some(span)
+                if now - span.ended <= SPAN_GAP_MS && (from - span.to).abs() <= SPAN_GAP_SECS =>
+            {
+                span.ended = now;
+                span.to = to;
+            }
+            _ => self.spans.push(ListeningSpan {
+                started: now,
+                ended: now,
+                from,
+                to,
+            }),
+        }
+    }
+
+    /// Returns how far through the article the session got, as a percentage
+    fn pct(&self) -> u32 {
+        (100.0 * self.progress).round() as u32
+    }
+}
+
+/// Saves the given session, if it's long enough to be worth keeping
+pub(crate) async fn save_session(session: &ListeningSession) {
+    if session.secs_listened < MIN_SESSION_SECS {
+        return;
+    }
+    let _ = caching::save_listening_session(session)
+        .await
+        .map_err(|e| tracing::error!("Couldn't save listening session: {}", e));
+}
+
+/// Like `save_session`, but in the background
+pub(crate) fn spawn_save_session(session: &ListeningSession) {
+    let session = session.clone();
+    spawn_local(async move { save_session(&session).await });
+}
+
+/// Plays the given article from the given number of seconds in. If it's already playing, it seeks
+/// there. Otherwise the library plays it, downloading it first if need be, and the player starts it
+/// there.
+pub(crate) fn play_from(
+    player_link: &WeakComponentLink<Player>,
+    library_link: &WeakComponentLink<Library>,
+    id: ArticleId,
+    title: String,
+    secs: f64,
+) {
+    if let Some(player) = player_link.borrow().as_ref() {
+        player.send_message(PlayerMsg::PlayFrom {
+            id: id.clone(),
+            secs,
+        });
+    }
+    let entry = QueueEntry {
+        id,
+        title,
+        group: None,
+        publication: None,
+        author: None,
+        image: None,
+        reading_profile: None,
+    };
+    if let Some(library) = library_link.borrow().as_ref() {
+        library.send_message(LibraryMsg::PlayArticle(entry));
+    }
+}
+
+/// Loads every listening session, oldest first. If this fails, there's no history.
+async fn load_sessions() -> Vec<ListeningSession> {
+    let mut sessions = caching::load_listening_sessions()
+        .await
+        .map_err(|e| tracing::error!("Couldn't load listening history: {}", e))
+        .unwrap_or_default();
+    sessions.sort_by(|a, b| a.started.total_cmp(&b.started));
+    sessions
+}
+
+/// Loads how far each article has been listened to, as a percentage. This is the furthest any
+/// session got, or 100 if one finished it.
+pub(crate) async fn load_listened_pcts() -> BTreeMap<ArticleId, u32> {
+    let mut pcts = BTreeMap::new();
+    for session in load_sessions().await {
+        let pct = if session.finished { 100 } else { session.pct() };
+        let furthest = pcts.entry(session.id).or_insert(0);
+        *furthest = pct.max(*furthest);
+    }
+    pcts
+}
+
+/// Returns how far a group has been listened to, as the average of how far its given parts have,
+/// if any of them have been started
+pub(crate) fn group_listened_pct(part_pcts: &[Option<u32>]) -> Option<u32> {
+    if part_pcts.iter().all(Option::is_none) {
+        return None;
+    }
+    let total: u32 = part_pcts.iter().map(|pct| pct.unwrap_or(0)).sum();
+    Some(total / part_pcts.len() as u32)
+}
+
+/// Renders a small ring that fills up as an article is listened to, next to a badge saying whether
+/// the article's audio is saved on this device, so a list shows both at a glance
+pub(crate) fn render_progress_ring(pct: Option<u32>, saved: bool) -> Html {
+    let pct = pct.unwrap_or(0).min(100);
+    let label = match pct {
+        0 => "Not started".to_string(),
+        100 => "Listened".to_string(),
+        pct => format!("{pct}% listened"),
+    };
+    let filled = RING_CIRCUMFERENCE * f64::from(pct) / 100.0;
+    let (saved_icon, saved_label) = if saved {
+        ("📥", "Saved on this device")
+    } else {
+        ("☁️", "Not saved on this device")
+    };
+
+    html! {
+        <span class="listenedBadge">
+            <svg
+                class="progressRing"
+                viewBox="0 0 16 16"
+                width="16"
+                height="16"
+                role="img"
+                aria-label={ label.clone() }
+            >
+                <title>{ label }</title>
+                <circle class="ringTrack" cx="8" cy="8" r={ RING_RADIUS.to_string() } />
+                <circle
+                    class="ringFill"
+                    cx="8"
+                    cy="8"
+                    r={ RING_RADIUS.to_string() }
+                    transform="rotate(-90 8 8)"
+                    stroke-dasharray={ format!("{filled:.2} {RING_CIRCUMFERENCE:.2}") }
+                />
+            </svg>
+            <span class="savedBadge" role="img" aria-label={saved_label} title={saved_label}>
+                { saved_icon }
+            </span>
+        </span>
+    }
+}
+
+/// How the listener has taken to the articles from one source
+#[derive(Debug, Default)]
+struct SourceStats {
+    /// The source, as [`smart_playlist::source_of`] names it
+    source: String,
+    /// How many of its articles are in the library
+    articles: usize,
+    /// How many of its articles have been started
+    started: usize,
+    /// How many of the started ones have been finished
+    finished: usize,
+    /// How many of its articles were rated up, and how many down
+    ups: usize,
+    downs: usize,
+    /// The speeds its articles were listened at
+    speeds: Vec<f64>,
+}
+
+impl SourceStats {
+    /// Returns the percentage of started articles that were finished, if any were started
+    fn completion_pct(&self) -> Option<u32> {
+        (self.started > 0)
+            .then(|| (100.0 * self.finished as f64 / self.started as f64).round() as u32)
+    }
+
+    /// Returns the average rating, from -1 for all down to 1 for all up, if any were rated
+    fn avg_rating(&self) -> Option<f64> {
+        let rated = self.ups + self.downs;
+        (rated > 0).then(|| (self.ups as f64 - self.downs as f64) / rated as f64)
+    }
+
+    /// Returns the average listening speed, if any were listened at a recorded speed
+    fn avg_speed(&self) -> Option<f64> {
+        (!self.speeds.is_empty())
+            .then(|| self.speeds.iter().sum::<f64>() / self.speeds.len() as f64)
+    }
+}
+
+/// Sums up each source in the given catalog from the given sessions and speeds. Sources with the
+/// most started articles come first. Articles no longer in the library aren't counted.
+fn source_stats(
+    catalog: &[ArticleMetadata],
+    sessions: &[ListeningSession],
+    speeds: &[SpeedSample],
+) -> Vec<SourceStats> {
+    let mut stats: BTreeMap<String, SourceStats> = BTreeMap::new();
+    let mut sources: BTreeMap<ArticleId, String> = BTreeMap::new();
+    for meta in catalog {
+        let Some(source) = smart_playlist::source_of(meta) else {
+            continue;
+        };
+        let entry = stats.entry(source.clone()).or_insert_with(|| SourceStats {
+            source: source.clone(),
+            ..Default::default()
+        });
+        entry.articles += 1;
+        match meta.rating {
+            Some(Rating::Up) => entry.ups += 1,
+            Some(Rating::Down) => entry.downs += 1,
+            None => (),
+        }
+        sources.insert(ArticleId(meta.id.clone()), source);
+    }
+
+    // An article counts as finished if any of its sessions finished it
+    let mut started: BTreeMap<&ArticleId, bool> = BTreeMap::new();
+    for session in sessions {
+        *started.entry(&session.id).or_default() |= session.finished;
+    }
+    for (id, finished) in started {
+        if let Some(entry) = sources.get(id).and_then(|s| stats.get_mut(s)) {
+            entry.started += 1;
+            entry.finished += finished as usize;
+        }
+    }
+    for sample in speeds {
+        if let Some(entry) = sources.get(&sample.id).and_then(|s| stats.get_mut(s)) {
+            entry.speeds.push(sample.speed);
+        }
+    }
+
+    let mut stats: Vec<SourceStats> = stats.into_values().collect();
+    stats.sort_by(|a, b| b.started.cmp(&a.started).then(a.source.cmp(&b.source)));
+    stats
+}
+
+/// Formats the given time in milliseconds since the Unix epoch as a local date
+fn format_date(ms: f64) -> String {
+    js_sys::Date::new(&JsValue::from_f64(ms))
+        .to_locale_date_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+/// Formats the given time in milliseconds since the Unix epoch as a local time of day, e.g., "8:40"
+fn format_clock(ms: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(ms));
+    format!("{}:{:02}", date.get_hours(), date.get_minutes())
+}
+
+/// A local day, as its year, month from 0 to 11, and day of the month
+type Day = (u32, i32, i32);
+
+/// Returns the local day it is now
+fn today() -> Day {
+    let now = js_sys::Date::new_0();
+    (
+        now.get_full_year(),
+        now.get_month() as i32,
+        now.get_date() as i32,
+    )
+}
+
+/// Parses the value of a date input, YYYY-MM-DD
+fn parse_day(value: &str) -> Option<Day> {
+    let mut parts = value.split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month: i32 = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Some((year, month - 1, day))
+}
+
+/// Returns the time the given local day starts at, or the given time of day on it, in
+/// milliseconds since the Unix epoch. Days past the end of the month roll over into the next.
+fn time_on((year, month, day): Day, hours: i32, minutes: i32) -> f64 {
+    js_sys::Date::new_with_year_month_day_hr_min(year, month, day, hours, minutes).get_time()
+}
+
+/// Parses the value of a time input, HH:MM, as the hours and minutes
+fn parse_time_of_day(value: &str) -> Option<(i32, i32)> {
+    let (hours, minutes) = value.split_once(':')?;
+    let minutes = minutes.get(..2).unwrap_or(minutes);
+    Some((hours.parse().ok()?, minutes.parse().ok()?))
+}
+
+/// Formats the given number of seconds in whole minutes, e.g., "12 min"
+fn format_minutes(secs: f64) -> String {
+    format!("{} min", (secs / 60.0).round())
+}
+
+pub(crate) enum HistoryMsg {
+    /// Loads the sessions again
+    Refresh,
+    /// Shows the timeline of the given day
+    SetDay(Day),
+    /// Looks up what was playing at the given time of day, as hours and minutes, or stops looking
+    SetTimeOfDay(Option<(i32, i32)>),
+    /// Plays the given article, by ID and title, from the given number of seconds in
+    PlayFrom {
+        id: ArticleId,
+        title: String,
+        secs: f64,
+    },
+    /// Sets the sessions, oldest first
+    SetSessions(Vec<ListeningSession>),
+    /// Sets the library catalog, if it could be fetched, and the listening speeds, for summing up
+    /// the sources
+    SetSourceData {
+        catalog: Option<Vec<ArticleMetadata>>,
+        speeds: Vec<SpeedSample>,
+    },
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// Links to the player and the library, for playing something from the timeline again
+    pub player_link: WeakComponentLink<Player>,
+    pub library_link: WeakComponentLink<Library>,
+}
+
+/// The listening history panel on the main page
+pub(crate) struct History {
+    /// Every listening session, oldest first
+    sessions: Vec<ListeningSession>,
+    /// The day the timeline shows
+    day: Day,
+    /// The time of day the timeline looks up what was playing at, as hours and minutes, if any
+    time_of_day: Option<(i32, i32)>,
+    /// The stats of each source. This is `None` if the library couldn't be fetched.
+    source_stats: Option<Vec<SourceStats>>,
+    /// The library catalog and listening speeds the source stats come from
+    catalog: Option<Vec<ArticleMetadata>>,
+    speeds: Vec<SpeedSample>,
+}
+
+impl History {
+    /// Sums up the sources again, from the current sessions, catalog, and speeds
+    fn update_source_stats(&mut self) {
+        self.source_stats = self
+            .catalog
+            .as_ref()
+            .map(|catalog| source_stats(catalog, &self.sessions, &self.speeds));
+    }
+
+    /// Renders the timeline of the chosen day: every stretch of listening, when it was, and where
+    /// in its article, and what was playing at the chosen time of day
+    fn render_timeline(&self, ctx: &Context<Self>) -> Html {
+        let (year, month, day) = self.day;
+        let day_start = time_on(self.day, 0, 0);
+        let day_end = time_on((year, month, day + 1), 0, 0);
+        let mut spans: Vec<(&ListeningSession, &ListeningSpan)> = self
+            .sessions
+            .iter()
+            .flat_map(|session| session.spans.iter().map(move |span| (session, span)))
+            .filter(|(_, span)| span.ended >= day_start && span.started < day_end)
+            .collect();
+        spans.sort_by(|(_, a), (_, b)| a.started.total_cmp(&b.started));
+
+        let play_button = |session: &ListeningSession, secs: f64| {
+            let (id, title) = (session.id.clone(), session.title.clone());
+            let onclick = ctx.link().callback(move |_| HistoryMsg::PlayFrom {
+                id: id.clone(),
+                title: title.clone(),
+                secs,
+            });
+            html! {
+                <button {onclick}>{ format!("▶ Play from {}", format_time(secs)) }</button>
+            }
+        };
+
+        // What was playing at the chosen time
+        let lookup = self.time_of_day.map(|(hours, minutes)| {
+            let time = time_on(self.day, hours, minutes);
+            let found = spans
+                .iter()
+                .find_map(|(session, span)| Some((*session, span.position_at(time)?)));
+            let clock = format!("{hours}:{minutes:02}");
+            match found {
+                Some((session, secs)) => html! {
+                    <p role="status">
+                        { format!("At {clock}: ") }
+                        <bdi>{ &session.title }</bdi>
+                        { format!(", {} in. ", format_time(secs)) }
+                        { play_button(session, secs) }
+                    </p>
+                },
+                None => html! {
+                    <p role="status">{ format!("Nothing was playing at {clock}") }</p>
+                },
+            }
+        });
+
+        let rows = spans
+            .iter()
+            .map(|(session, span)| {
+                html! {
+                    <tr>
+                        <td>{ format!(
+                            "{}–{}",
+                            format_clock(span.started),
+                            format_clock(span.ended),
+                        ) }</td>
+                        <td dir="auto">{ &session.title }</td>
+                        <td>{ format!("{}–{}", format_time(span.from), format_time(span.to)) }</td>
+                        <td>{ play_button(session, span.from) }</td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+
+        let onchange_day = ctx.link().batch_callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            parse_day(&input.value()).map(HistoryMsg::SetDay)
+        });
+        let onchange_time = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            HistoryMsg::SetTimeOfDay(parse_time_of_day(&input.value()))
+        });
+        html! {
+            <>
+                <h3>{ "Timeline" }</h3>
+                <p>
+                    <label>
+                        { "Day: " }
+                        <input
+                            type="date"
+                            value={ format!("{year:04}-{:02}-{day:02}", month + 1) }
+                            onchange={onchange_day}
+                        />
+                    </label>
+                    { " " }
+                    <label>
+                        { "What was playing at " }
+                        <input type="time" onchange={onchange_time} />
+                    </label>
+                </p>
+                { lookup.unwrap_or_default() }
+                if spans.is_empty() {
+                    <p class="articleMetadata">{ "Nothing was listened to that day" }</p>
+                } else {
+                    <table aria-label="Timeline">
+                        { rows }
+                    </table>
+                }
+            </>
+        }
+    }
+}
+
+impl Component for History {
+    type Message = HistoryMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(HistoryMsg::Refresh);
+        History {
+            sessions: Vec::new(),
+            day: today(),
+            time_of_day: None,
+            source_stats: None,
+            catalog: None,
+            speeds: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            HistoryMsg::Refresh => {
+                ctx.link()
+                    .send_future(async move { HistoryMsg::SetSessions(load_sessions().await) });
+                ctx.link().send_future(async move {
+                    let catalog = library_view::fetch_catalog()
+                        .await
+                        .map_err(|e| tracing::warn!("Couldn't fetch library for source stats: {e}"))
+                        .ok()
+                        .map(|catalog| catalog.0);
+                    let speeds = caching::load_speed_samples()
+                        .await
+                        .map_err(|e| tracing::error!("Couldn't load listening speeds: {e}"))
+                        .unwrap_or_default();
+                    HistoryMsg::SetSourceData { catalog, speeds }
+                });
+                false
+            }
+            HistoryMsg::SetDay(day) => {
+                self.day = day;
+                true
+            }
+            HistoryMsg::SetTimeOfDay(time_of_day) => {
+                self.time_of_day = time_of_day;
+                true
+            }
+            HistoryMsg::PlayFrom { id, title, secs } => {
+                let props = ctx.props();
+                play_from(&props.player_link, &props.library_link, id, title, secs);
+                false
+            }
+            HistoryMsg::SetSessions(sessions) => {
+                self.sessions = sessions;
+                self.update_source_stats();
+                true
+            }
+            HistoryMsg::SetSourceData { catalog, speeds } => {
+                self.catalog = catalog;
+                self.speeds = speeds;
+                self.update_source_stats();
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let week_ago = js_sys::Date::now() - WEEK_MS;
+        let total_secs: f64 = self.sessions.iter().map(|s| s.secs_listened).sum();
+        let week_secs: f64 = self
+            .sessions
+            .iter()
+            .filter(|s| s.started >= week_ago)
+            .map(|s| s.secs_listened)
+            .sum();
+
+        let recent_rows = self
+            .sessions
+            .iter()
+            .rev()
+            .take(MAX_LISTED)
+            .map(|session| {
+                html! {
+                    <tr>
+                        <td dir="auto">{ &session.title }</td>
+                        <td>{ format_date(session.started) }</td>
+                        <td>{ format_minutes(session.secs_listened) }</td>
+                        <td>{ format!("{}%", session.pct()) }</td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+
+        // Each finished article is listed once, as of the last time it was finished
+        let mut seen = BTreeSet::new();
+        let finished_items = self
+            .sessions
+            .iter()
+            .rev()
+            .filter(|s| s.finished && seen.insert(s.id.clone()))
+            .take(MAX_LISTED)
+            .map(|session| {
+                html! {
+                    <li>
+                        <bdi>{ &session.title }</bdi>
+                        <span class="articleMetadata">
+                            { format!(" {}", format_date(session.started)) }
+                        </span>
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        let source_stats = match &self.source_stats {
+            None => html! {
+                <p class="articleMetadata">{
+                    "Source stats need the library, which couldn't be fetched"
+                }</p>
+            },
+            Some(stats) if stats.is_empty() => html! {
+                <p class="articleMetadata">{ "No sources yet" }</p>
+            },
+            Some(stats) => {
+                let rows = stats
+                    .iter()
+                    .map(|source| {
+                        let dash = || "–".to_string();
+                        let completion = source
+                            .completion_pct()
+                            .map(|p| format!("{p}%"))
+                            .unwrap_or_else(dash);
+                        let rating = source
+                            .avg_rating()
+                            .map(|r| format!("{r:+.1} ({}👍 {}👎)", source.ups, source.downs))
+                            .unwrap_or_else(dash);
+                        let speed = source
+                            .avg_speed()
+                            .map(|s| format!("{s:.2}×"))
+                            .unwrap_or_else(dash);
+                        html! {
+                            <tr>
+                                <td dir="auto">{ &source.source }</td>
+                                <td>{ format!("{} of {}", source.started, source.articles) }</td>
+                                <td>{ completion }</td>
+                                <td>{ rating }</td>
+                                <td>{ speed }</td>
+                            </tr>
+                        }
+                    })
+                    .collect::<Html>();
+                html! {
+                    <table aria-label="Sources">
+                        <tr>
+                            <th>{ "Source" }</th>
+                            <th>{ "Started" }</th>
+                            <th>{ "Finished" }</th>
+                            <th>{ "Rating" }</th>
+                            <th>{ "Speed" }</th>
+                        </tr>
+                        { rows }
+                    </table>
+                }
+            }
+        };
+
+        let ontoggle = ctx.link().callback(|_| HistoryMsg::Refresh);
+        html! {
+            <details class="history" {ontoggle}>
+                <summary>{ "Listening history" }</summary>
+                <p>{ format!(
+                    "Listened for {} this week, and {} in all",
+                    format_minutes(week_secs),
+                    format_minutes(total_secs),
+                ) }</p>
+                if self.sessions.is_empty() {
+                    <p class="articleMetadata">{ "Nothing's been listened to yet" }</p>
+                } else {
+                    <h3>{ "Recently played" }</h3>
+                    <table aria-label="Recently played">
+                        { recent_rows }
+                    </table>
+                    <h3>{ "Finished" }</h3>
+                    <ul aria-label="Finished articles">
+                        { finished_items }
+                    </ul>
+                    { self.render_timeline(ctx) }
+                }
+                <h3>{ "Sources" }</h3>
+                { source_stats }
+            </details>
+        }
+    }
+}
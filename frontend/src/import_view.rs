@@ -0,0 +1,322 @@
+use crate::utils;
+use common::{ImportProgress, ImportSubmission, ImportedArticle};
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Error as AnyError};
+use gloo_timers::callback::Interval;
+use yew::prelude::*;
+
+const EXPORT_FORM_ID: &str = "import-export-input";
+
+/// How often the import's progress is refreshed while it's running, in milliseconds
+const POLL_INTERVAL_MS: u32 = 2000;
+
+/// Sends the given export to the server, and returns the articles it found in it
+async fn parse_export(file: web_sys::File) -> Result<Vec<ImportedArticle>, AnyError> {
+    let export = utils::file_text(&file).await?;
+    let resp = utils::post("/api/parse-import")
+        .body(export)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error uploading export"))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing imported articles"))
+}
+
+/// Asks the server to convert the given articles
+async fn start_import(articles: Vec<ImportedArticle>) -> Result<(), AnyError> {
+    let resp = utils::post("/api/start-import")
+        .json(&ImportSubmission { articles })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error starting import"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error starting import. Another import may be running. {}",
+            resp.status_text()
+        );
+    }
+    Ok(())
+}
+
+/// Fetches the progress of the current (or last) import
+async fn fetch_progress() -> Result<ImportProgress, AnyError> {
+    let resp = utils::get("/api/import-progress")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching import progress"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching import progress {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing import progress JSON"))
+}
+
+/// A wizard for importing articles from another reader. The listener picks an export, then picks
+/// which of the articles in it to convert, then watches the conversion's progress.
+#[derive(Default)]
+pub(crate) struct Import {
+    /// The articles found in the export
+    articles: Vec<ImportedArticle>,
+    /// The indices of the articles that are picked for conversion
+    selected: BTreeSet<usize>,
+    /// The progress of the current (or last) import, if there's been one
+    progress: Option<ImportProgress>,
+    err: Option<AnyError>,
+    /// Refreshes the progress while an import is running. The polling stops when this is dropped.
+    poller: Option<Interval>,
+}
+
+pub(crate) enum ImportMsg {
+    /// Sends the export picked in the form to the server
+    ReadExport,
+    /// Sets the articles found in the export. They all start out picked.
+    SetArticles(Vec<ImportedArticle>),
+    /// Picks or unpicks the article with the given index
+    Toggle(usize),
+    /// Picks every article, or none of them
+    SelectAll(bool),
+    /// Starts converting the picked articles
+    Start,
+    /// Fetches the import's progress
+    FetchProgress,
+    /// Sets the import's progress
+    SetProgress(ImportProgress),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+impl Component for Import {
+    type Message = ImportMsg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        // Pick up an import that's already running
+        ctx.link().send_message(ImportMsg::FetchProgress);
+        Import::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ImportMsg::ReadExport => {
+                let file = match utils::picked_file(EXPORT_FORM_ID) {
+                    Some(file) => file,
+                    None => return false,
+                };
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match parse_export(file).await {
+                        Ok(articles) => ImportMsg::SetArticles(articles),
+                        Err(e) => ImportMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            ImportMsg::SetArticles(articles) => {
+                self.selected = (0..articles.len()).collect();
+                self.articles = articles;
+            }
+
+            ImportMsg::Toggle(i) => {
+                if !self.selected.remove(&i) {
+                    self.selected.insert(i);
+                }
+            }
+
+            ImportMsg::SelectAll(all) => {
+                self.selected = if all {
+                    (0..self.articles.len()).collect()
+                } else {
+                    BTreeSet::new()
+                };
+            }
+
+            ImportMsg::Start => {
+                let articles: Vec<ImportedArticle> = self
+                    .selected
+                    .iter()
+                    .map(|&i| self.articles[i].clone())
+                    .collect();
+                if articles.is_empty() {
+                    return false;
+                }
+
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match start_import(articles).await {
+                        Ok(()) => ImportMsg::FetchProgress,
+                        Err(e) => ImportMsg::SetError(e),
+                    }
+                });
+                // The articles are on their way. Clear the list so they aren't imported twice.
+                self.articles.clear();
+                self.selected.clear();
+            }
+
+            ImportMsg::FetchProgress => {
+                ctx.link().send_future(async move {
+                    match fetch_progress().await {
+                        Ok(progress) => ImportMsg::SetProgress(progress),
+                        Err(e) => ImportMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            ImportMsg::SetProgress(progress) => {
+                // Poll while the import is running, and stop once it's done
+                if progress.running && self.poller.is_none() {
+                    let link = ctx.link().clone();
+                    self.poller = Some(Interval::new(POLL_INTERVAL_MS, move || {
+                        link.send_message(ImportMsg::FetchProgress)
+                    }));
+                } else if !progress.running {
+                    self.poller = None;
+                }
+
+                // There's nothing to show if there's never been an import
+                self.progress = Some(progress).filter(|p| p.total > 0);
+            }
+
+            ImportMsg::SetError(e) => {
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or("".to_string());
+        let read_export = ctx.link().callback(|_| ImportMsg::ReadExport);
+
+        html! {
+            <fieldset>
+                <legend><h2>{ "Import from another reader" }</h2></legend>
+                <p>{
+                    "Import articles from a Wallabag, Shiori, or Linkding export, either JSON or
+                    HTML bookmarks, from a CSV export, e.g., Pocket's, or from a list of articles
+                    exported from a library"
+                }</p>
+                <div class="field">
+                    <label for={EXPORT_FORM_ID}>{ "Export file:" }</label>
+                    <input type="file" id={EXPORT_FORM_ID} accept=".json,.html,.htm,.csv" />
+                </div>
+                <button onclick={read_export}>{ "Read file" }</button>
+                { self.render_articles(ctx) }
+                { self.render_progress() }
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </fieldset>
+        }
+    }
+}
+
+impl Import {
+    /// Renders the articles found in the export, for the listener to pick from
+    fn render_articles(&self, ctx: &Context<Self>) -> Html {
+        if self.articles.is_empty() {
+            return Html::default();
+        }
+
+        let items = self
+            .articles
+            .iter()
+            .enumerate()
+            .map(|(i, article)| {
+                let id = format!("{EXPORT_FORM_ID}-{i}");
+                let onchange = ctx.link().callback(move |_| ImportMsg::Toggle(i));
+                let date = article
+                    .datetime_added
+                    .map(|t| format!(" (saved {})", utils::format_date(t)))
+                    .unwrap_or_default();
+                html! {
+                    <li>
+                        <input
+                            type="checkbox"
+                            id={id.clone()}
+                            checked={self.selected.contains(&i)}
+                            {onchange}
+                        />
+                        <label for={id}>{ article.title.clone() }{ date }</label>
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        let select_all = ctx.link().callback(|_| ImportMsg::SelectAll(true));
+        let select_none = ctx.link().callback(|_| ImportMsg::SelectAll(false));
+        let start = ctx.link().callback(|_| ImportMsg::Start);
+        let running = self.progress.as_ref().is_some_and(|p| p.running);
+
+        html! {
+            <>
+                <p>{ format!("Found {} articles. Pick the ones to convert.", self.articles.len()) }</p>
+                <button onclick={select_all}>{ "Select all" }</button>
+                <button onclick={select_none}>{ "Select none" }</button>
+                <ul class="importList">{ items }</ul>
+                <button onclick={start} disabled={running || self.selected.is_empty()}>
+                    { format!("Convert {} articles", self.selected.len()) }
+                </button>
+            </>
+        }
+    }
+
+    /// Renders how far along the current (or last) import is
+    fn render_progress(&self) -> Html {
+        let progress = match &self.progress {
+            Some(progress) => progress,
+            None => return Html::default(),
+        };
+
+        let summary = if progress.running {
+            format!("Importing: {} of {} done", progress.done, progress.total)
+        } else {
+            format!(
+                "Import finished: {} of {} done",
+                progress.done, progress.total
+            )
+        };
+        let skipped = if progress.skipped > 0 {
+            format!(", {} already in the library", progress.skipped)
+        } else {
+            String::new()
+        };
+        let failed = if progress.failed.is_empty() {
+            String::new()
+        } else {
+            format!(", {} failed", progress.failed.len())
+        };
+
+        let failures = progress
+            .failed
+            .iter()
+            .map(|f| html! { <li>{ format!("{}: {}", f.title, f.error) }</li> })
+            .collect::<Html>();
+
+        html! {
+            <section aria-live="polite" title="import progress">
+                <progress value={progress.done.to_string()} max={progress.total.to_string()} />
+                <p>{ summary }{ skipped }{ failed }</p>
+                <ul>{ failures }</ul>
+            </section>
+        }
+    }
+}
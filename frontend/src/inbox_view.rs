@@ -0,0 +1,351 @@
+use crate::utils;
+use common::{InboxItem, InboxSelection, InboxState, InboxSubmission};
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Error as AnyError};
+use gloo_timers::callback::Interval;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+const INBOX_URL_FORM_ID: &str = "inbox-url-input";
+const INBOX_TAGS_FORM_ID: &str = "inbox-tags-input";
+const INBOX_ITEM_FORM_ID_PREFIX: &str = "inbox-item-input";
+
+/// How often the inbox is refreshed while items are being converted, in milliseconds
+const POLL_INTERVAL_MS: u32 = 3000;
+
+/// Fetches the inbox's items
+async fn fetch_inbox() -> Result<Vec<InboxItem>, AnyError> {
+    let resp = utils::get("/api/list-inbox")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching inbox"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching inbox {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing inbox JSON"))
+}
+
+/// Returns the input element with the given ID
+fn get_input(id: &str) -> Option<HtmlInputElement> {
+    gloo_utils::document()
+        .get_element_by_id(id)
+        .and_then(|e| e.dyn_into().ok())
+}
+
+/// Saves the article at the given URL to the inbox with the given tags. Returns whether an
+/// auto-convert rule queued it.
+async fn save_to_inbox(url: String, tags: Vec<String>) -> Result<bool, AnyError> {
+    let resp = utils::post("/api/save-to-inbox")
+        .json(&InboxSubmission { url, tags })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error saving to inbox"))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    let item: InboxItem = resp
+        .json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing inbox item"))?;
+    Ok(item.state == InboxState::Queued)
+}
+
+/// POSTs the given items to the given endpoint
+async fn submit_selection(endpoint: &str, urls: Vec<String>) -> Result<(), AnyError> {
+    let resp = utils::post(endpoint)
+        .json(&InboxSelection { urls })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+/// Returns whether the given item is on its way to the library
+fn is_busy(item: &InboxItem) -> bool {
+    matches!(item.state, InboxState::Queued | InboxState::Converting)
+}
+
+/// Articles saved for later. Nothing is converted until it's picked here, so the TTS quota is only
+/// spent on articles that'll be listened to.
+#[derive(Default)]
+pub(crate) struct Inbox {
+    items: Vec<InboxItem>,
+    /// The URLs of the items that are picked
+    selected: BTreeSet<String>,
+    /// What the last action did, if anything
+    status: Option<String>,
+    err: Option<AnyError>,
+    /// Refreshes the inbox while items are being converted. The polling stops when this is dropped.
+    poller: Option<Interval>,
+}
+
+pub(crate) enum InboxMsg {
+    /// Fetches the inbox's items
+    FetchItems,
+    /// Sets the inbox's items
+    SetItems(Vec<InboxItem>),
+    /// Saves the article whose URL and tags are in the form
+    Save,
+    /// Picks or unpicks the item with the given URL
+    Toggle(String),
+    /// Queues the picked items for conversion
+    Convert,
+    /// Removes the picked items from the inbox
+    Remove,
+    /// Sets the status display to the given message, and refreshes the inbox
+    SetStatus(String),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+impl Component for Inbox {
+    type Message = InboxMsg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(InboxMsg::FetchItems);
+        Inbox::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            InboxMsg::FetchItems => {
+                ctx.link().send_future(async move {
+                    match fetch_inbox().await {
+                        Ok(items) => InboxMsg::SetItems(items),
+                        Err(e) => InboxMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            InboxMsg::SetItems(items) => {
+                // Poll while anything is being converted, and stop once nothing is
+                let busy = items.iter().any(is_busy);
+                if busy && self.poller.is_none() {
+                    let link = ctx.link().clone();
+                    self.poller = Some(Interval::new(POLL_INTERVAL_MS, move || {
+                        link.send_message(InboxMsg::FetchItems)
+                    }));
+                } else if !busy {
+                    self.poller = None;
+                }
+
+                // Forget the picks of items that have left the inbox or are on their way
+                self.selected
+                    .retain(|url| items.iter().any(|i| &i.url == url && !is_busy(i)));
+                self.items = items;
+            }
+
+            InboxMsg::Save => {
+                let (url_input, tags_input) =
+                    match (get_input(INBOX_URL_FORM_ID), get_input(INBOX_TAGS_FORM_ID)) {
+                        (Some(url_input), Some(tags_input)) => (url_input, tags_input),
+                        _ => return false,
+                    };
+                let url = url_input.value().trim().to_string();
+                if url.is_empty() {
+                    return false;
+                }
+                let tags = tags_input
+                    .value()
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+                url_input.set_value("");
+                tags_input.set_value("");
+
+                self.err = None;
+                self.status = Some("Saving...".to_string());
+                ctx.link().send_future(async move {
+                    match save_to_inbox(url, tags).await {
+                        Ok(true) => InboxMsg::SetStatus(
+                            "Saved. It matched an auto-convert rule, so it's being converted."
+                                .to_string(),
+                        ),
+                        Ok(false) => InboxMsg::SetStatus("Saved to the inbox.".to_string()),
+                        Err(e) => InboxMsg::SetError(e),
+                    }
+                });
+            }
+
+            InboxMsg::Toggle(url) => {
+                if !self.selected.remove(&url) {
+                    self.selected.insert(url);
+                }
+            }
+
+            InboxMsg::Convert => {
+                let urls: Vec<String> = self.selected.iter().cloned().collect();
+                if urls.is_empty() {
+                    return false;
+                }
+                self.selected.clear();
+
+                self.err = None;
+                ctx.link().send_future(async move {
+                    let n = urls.len();
+                    match submit_selection("/api/convert-inbox", urls).await {
+                        Ok(()) => InboxMsg::SetStatus(format!("Converting {n} articles.")),
+                        Err(e) => InboxMsg::SetError(e),
+                    }
+                });
+            }
+
+            InboxMsg::Remove => {
+                let urls: Vec<String> = self.selected.iter().cloned().collect();
+                if urls.is_empty() {
+                    return false;
+                }
+                self.selected.clear();
+
+                self.err = None;
+                ctx.link().send_future(async move {
+                    let n = urls.len();
+                    match submit_selection("/api/remove-from-inbox", urls).await {
+                        Ok(()) => InboxMsg::SetStatus(format!("Removed {n} articles.")),
+                        Err(e) => InboxMsg::SetError(e),
+                    }
+                });
+            }
+
+            InboxMsg::SetStatus(status) => {
+                self.status = Some(status);
+                ctx.link().send_message(InboxMsg::FetchItems);
+            }
+
+            InboxMsg::SetError(e) => {
+                self.status = None;
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or("".to_string());
+
+        let save = ctx.link().callback(|_| InboxMsg::Save);
+
+        html! {
+            <fieldset>
+                <legend><h2>{ "Inbox" }</h2></legend>
+                <p>{
+                    "Save articles here to decide on later. They aren't converted until you pick
+                    them."
+                }</p>
+                <div class="field">
+                    <label for={INBOX_URL_FORM_ID}>{ "Article URL:" }</label>
+                    <input type="text" id={INBOX_URL_FORM_ID} />
+                </div>
+                <div class="field">
+                    <label for={INBOX_TAGS_FORM_ID}>{ "Tags (comma-separated):" }</label>
+                    <input type="text" id={INBOX_TAGS_FORM_ID} />
+                </div>
+                <button onclick={save}>{ "Save for later" }</button>
+                { self.render_items(ctx) }
+                <p aria-live="polite">{ self.status.clone().unwrap_or_default() }</p>
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </fieldset>
+        }
+    }
+}
+
+impl Inbox {
+    /// Renders the inbox's items, for the listener to pick from
+    fn render_items(&self, ctx: &Context<Self>) -> Html {
+        if self.items.is_empty() {
+            return html! { <p>{ "The inbox is empty." }</p> };
+        }
+
+        let items = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let id = format!("{INBOX_ITEM_FORM_ID_PREFIX}-{i}");
+                let url = item.url.clone();
+                let onchange = ctx.link().callback(move |_| InboxMsg::Toggle(url.clone()));
+                let title = item.title.clone().unwrap_or_else(|| item.url.clone());
+                let tags = if item.tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", item.tags.join(", "))
+                };
+                let length = item
+                    .estimated_minutes()
+                    .map(|mins| format!(" (about {mins} min)"))
+                    .unwrap_or_default();
+                let state = match &item.state {
+                    InboxState::Saved => String::new(),
+                    InboxState::Queued => " Queued".to_string(),
+                    InboxState::Converting => " Converting...".to_string(),
+                    InboxState::Failed(e) => format!(" Failed: {e}"),
+                };
+                html! {
+                    <li>
+                        <input
+                            type="checkbox"
+                            id={id.clone()}
+                            checked={self.selected.contains(&item.url)}
+                            disabled={is_busy(item)}
+                            {onchange}
+                        />
+                        <label for={id}>{ title }{ length }{ tags }</label>
+                        { " " }<a href={ item.url.clone() }>{ "(link)" }</a>
+                        <strong>{ state }</strong>
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        // Say how much listening the conversion will make, so the quota isn't spent blindly
+        let picked_mins: usize = self
+            .items
+            .iter()
+            .filter(|item| self.selected.contains(&item.url))
+            .filter_map(InboxItem::estimated_minutes)
+            .sum();
+        let convert_label = if picked_mins > 0 {
+            format!(
+                "Convert {} articles (about {picked_mins} min)",
+                self.selected.len()
+            )
+        } else {
+            format!("Convert {} articles", self.selected.len())
+        };
+
+        let convert = ctx.link().callback(|_| InboxMsg::Convert);
+        let remove = ctx.link().callback(|_| InboxMsg::Remove);
+        let nothing_picked = self.selected.is_empty();
+
+        html! {
+            <>
+                <ul class="importList">{ items }</ul>
+                <button onclick={convert} disabled={nothing_picked}>{ convert_label }</button>
+                <button onclick={remove} disabled={nothing_picked}>{ "Remove" }</button>
+            </>
+        }
+    }
+}
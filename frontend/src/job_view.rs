@@ -0,0 +1,445 @@
+use crate::{
+    player_view::{Player, PlayerMsg},
+    servers, utils, WeakComponentLink,
+};
+use common::{JobControlSubmission, JobInfo, JobPriority, JobPrioritySubmission, JobStatus};
+
+use anyhow::{bail, Error as AnyError};
+use gloo_timers::callback::Interval;
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+/// How often the job list is refreshed, in milliseconds
+const POLL_INTERVAL_MS: u32 = 2000;
+
+/// The things a user can do to a running job
+#[derive(Clone, Copy)]
+pub(crate) enum JobAction {
+    Pause,
+    Resume,
+    Cancel,
+    /// Moves the job to the front of the queue
+    ConvertNow,
+}
+
+impl JobAction {
+    fn endpoint(&self) -> &'static str {
+        match self {
+            JobAction::Pause => "/api/pause-job",
+            JobAction::Resume => "/api/resume-job",
+            JobAction::Cancel => "/api/cancel-job",
+            JobAction::ConvertNow => "/api/prioritize-job",
+        }
+    }
+}
+
+/// Fetches the list of running and recently finished jobs
+async fn fetch_jobs() -> Result<Vec<JobInfo>, AnyError> {
+    let resp = utils::get("/api/list-jobs")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching job list"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching job list {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing job list JSON"))
+}
+
+/// Fetches the progress of the given job
+pub(crate) async fn fetch_job(id: u64) -> Result<JobInfo, AnyError> {
+    let resp = utils::get(&format!("/api/jobs/{id}"))
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching job"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching job {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing job JSON"))
+}
+
+/// Asks the server to pause, resume, cancel, or prioritize the given job
+pub(crate) async fn control_job(id: u64, action: JobAction) -> Result<(), AnyError> {
+    let endpoint = action.endpoint();
+    let req = match action {
+        JobAction::ConvertNow => utils::post(endpoint).json(&JobPrioritySubmission {
+            id,
+            priority: JobPriority::High,
+        })?,
+        _ => utils::post(endpoint).json(&JobControlSubmission { id })?,
+    };
+    let resp = req
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error controlling job. The job may have already finished. {}",
+            resp.status_text(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Describes roughly how long is left, given a number of seconds
+fn eta_str(secs: u64) -> String {
+    let mins = secs.div_ceil(60);
+    if mins <= 1 {
+        "less than a minute left".to_string()
+    } else if mins < 60 {
+        format!("about {mins} min left")
+    } else {
+        format!("about {}h {}min left", mins / 60, mins % 60)
+    }
+}
+
+/// Describes the state of the given job
+pub(crate) fn status_str(job: &JobInfo) -> String {
+    let progress = if job.chunks_total > 0 {
+        format!(" ({}/{} parts)", job.chunks_done, job.chunks_total)
+    } else {
+        String::new()
+    };
+
+    // Only running jobs are getting closer to done
+    let progress = match job.eta_secs {
+        Some(secs) if job.status == JobStatus::Running => format!("{progress}, {}", eta_str(secs)),
+        _ => progress,
+    };
+
+    match job.status {
+        JobStatus::Running if job.priority == JobPriority::High => {
+            format!("Converting first{progress}")
+        }
+        JobStatus::Running => format!("Converting{progress}"),
+        JobStatus::Paused => format!("Paused{progress}"),
+        JobStatus::Cancelled => "Cancelled".to_string(),
+        JobStatus::Done => "Done".to_string(),
+        JobStatus::Failed => match &job.error {
+            Some(e) => format!("Failed: {e}"),
+            None => "Failed".to_string(),
+        },
+    }
+}
+
+/// Returns the URL the given job's audio is streamed from
+fn job_audio_url(id: u64) -> String {
+    servers::api_url(&format!("/api/job-audio/{id}"))
+}
+
+/// Returns the URL of a single chunk of the given job's audio
+fn job_audio_chunk_url(id: u64, chunk: usize) -> String {
+    servers::api_url(&format!("/api/job-audio/{id}/{chunk}"))
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// A link to the Player component. If this is set, the panel is in compact mode: it only shows
+    /// unfinished jobs, and they can be listened to in the Player while they're converting.
+    #[prop_or_default]
+    pub player_link: Option<WeakComponentLink<Player>>,
+}
+
+/// A panel that shows the server's conversion jobs, and lets them be paused, resumed, cancelled,
+/// and moved to the front of the queue
+pub(crate) struct Jobs {
+    jobs: Vec<JobInfo>,
+    /// The job and chunk whose audio is being previewed, if any
+    preview: Option<(u64, usize)>,
+    err: Option<AnyError>,
+    /// Refreshes the job list periodically. The polling stops when this is dropped.
+    _poller: Interval,
+}
+
+pub(crate) enum JobsMsg {
+    /// Fetches the job list
+    FetchJobs,
+    /// Sets the job list
+    SetJobs(Vec<JobInfo>),
+    /// Pauses, resumes, cancels, or prioritizes the job with the given ID
+    Control(u64, JobAction),
+    /// Plays the given job's audio in the Player, while it's being converted
+    Listen(JobInfo),
+    /// Previews the given chunk of the given job's audio
+    Preview(u64, usize),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+impl Component for Jobs {
+    type Message = JobsMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(JobsMsg::FetchJobs);
+
+        let link = ctx.link().clone();
+        let poller = Interval::new(POLL_INTERVAL_MS, move || {
+            link.send_message(JobsMsg::FetchJobs)
+        });
+
+        Jobs {
+            jobs: Vec::new(),
+            preview: None,
+            err: None,
+            _poller: poller,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            JobsMsg::FetchJobs => {
+                ctx.link().send_future(async move {
+                    match fetch_jobs().await {
+                        Ok(jobs) => JobsMsg::SetJobs(jobs),
+                        Err(e) => JobsMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            JobsMsg::SetJobs(jobs) => {
+                if self.jobs == jobs {
+                    return false;
+                }
+                self.jobs = jobs;
+            }
+
+            JobsMsg::Control(id, action) => {
+                // Cancelling throws away everything the job has done, so make sure the user meant
+                // to click this
+                if let JobAction::Cancel = action {
+                    let confirmed = gloo_utils::window()
+                        .confirm_with_message(
+                            "Cancel this conversion? Everything converted so far will be discarded.",
+                        )
+                        .unwrap_or(false);
+                    if !confirmed {
+                        return false;
+                    }
+                }
+
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match control_job(id, action).await {
+                        Ok(()) => JobsMsg::FetchJobs,
+                        Err(e) => JobsMsg::SetError(e),
+                    }
+                });
+            }
+
+            JobsMsg::Listen(job) => {
+                if let Some(player_link) = &ctx.props().player_link {
+                    let player_link = player_link.borrow().clone().unwrap();
+                    player_link.send_message(PlayerMsg::PlayStream {
+                        url: job_audio_url(job.id),
+                        title: job.title,
+                    });
+                }
+                return false;
+            }
+
+            JobsMsg::Preview(id, chunk) => {
+                self.preview = Some((id, chunk));
+            }
+
+            JobsMsg::SetError(e) => {
+                // The compact panel sits on the main page, which should work offline. Don't
+                // complain about not being able to reach the server there.
+                if ctx.props().player_link.is_some() {
+                    tracing::debug!("Couldn't update jobs: {e}");
+                    return false;
+                }
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let compact = ctx.props().player_link.is_some();
+        let jobs: Vec<&JobInfo> = self
+            .jobs
+            .iter()
+            .filter(|job| !compact || !job.status.is_finished())
+            .collect();
+
+        // The compact panel only shows up when something's converting
+        if compact && jobs.is_empty() {
+            return Html::default();
+        }
+        let no_jobs = jobs.is_empty();
+
+        let job_items = jobs
+            .into_iter()
+            .map(|job| {
+                let id = job.id;
+                let button = |action: JobAction, label: &'static str, class: &'static str| {
+                    let cb = ctx.link().callback(move |_| JobsMsg::Control(id, action));
+                    html! {
+                        <button {class} onclick={cb} aria-label={ format!("{label} {}", job.title) }>
+                            { label }
+                        </button>
+                    }
+                };
+
+                // Unfinished jobs can be bumped to the front of the queue, unless they're there
+                // already
+                let convert_now = if job.status.is_finished() || job.priority == JobPriority::High {
+                    Html::default()
+                } else {
+                    button(JobAction::ConvertNow, "Convert now", "convertJobNow")
+                };
+                // In compact mode, the audio converted so far can be listened to
+                let listen = if compact && job.chunks_done > 0 && !job.status.is_finished() {
+                    let job_copy = job.clone();
+                    let cb = ctx.link().callback(move |_| JobsMsg::Listen(job_copy.clone()));
+                    html! {
+                        <button
+                            class="listenToJob"
+                            onclick={cb}
+                            aria-label={ format!("Listen to {} now", job.title) }
+                        >
+                            { "Listen now" }
+                        </button>
+                    }
+                } else {
+                    Html::default()
+                };
+                // In full mode, every chunk converted so far can be previewed on its own
+                let preview = if !compact && job.audio_chunks > 0 && !job.status.is_finished() {
+                    self.render_preview(ctx, job)
+                } else {
+                    Html::default()
+                };
+                let controls = match job.status {
+                    JobStatus::Running => html! {
+                        <>
+                            { button(JobAction::Pause, "Pause", "pauseJob") }
+                            { button(JobAction::Cancel, "Cancel", "cancelJob") }
+                            { convert_now }
+                        </>
+                    },
+                    JobStatus::Paused => html! {
+                        <>
+                            { button(JobAction::Resume, "Resume", "resumeJob") }
+                            { button(JobAction::Cancel, "Cancel", "cancelJob") }
+                            { convert_now }
+                        </>
+                    },
+                    _ => Html::default(),
+                };
+
+                html! {
+                    <li>
+                        <span class="jobTitle">{ job.title.clone() }</span>
+                        <span class="jobStatus">{ status_str(job) }</span>
+                        { listen }
+                        { controls }
+                        { preview }
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or("".to_string());
+
+        let job_list = if no_jobs {
+            html! { <p>{ "Nothing is being converted right now." }</p> }
+        } else {
+            html! { <ul aria-live="polite">{ job_items }</ul> }
+        };
+
+        if compact {
+            return html! {
+                <section title="Converting">
+                    <h2>{ "Converting" }</h2>
+                    { job_list }
+                </section>
+            };
+        }
+
+        html! {
+            <fieldset>
+                <legend><h2>{ "Conversions" }</h2></legend>
+                { job_list }
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </fieldset>
+        }
+    }
+}
+
+impl Jobs {
+    /// Renders a selector for the chunks of the given job, and a player for the selected one
+    fn render_preview(&self, ctx: &Context<Self>, job: &JobInfo) -> Html {
+        let id = job.id;
+        let selector_id = format!("job-preview-{id}");
+        let selected = match self.preview {
+            Some((preview_id, chunk)) if preview_id == id => Some(chunk),
+            _ => None,
+        };
+
+        let onchange = ctx.link().batch_callback(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            select
+                .value()
+                .parse()
+                .ok()
+                .map(|chunk| JobsMsg::Preview(id, chunk))
+        });
+        let options = (0..job.audio_chunks)
+            .map(|chunk| {
+                html! {
+                    <option value={ chunk.to_string() } selected={ selected == Some(chunk) }>
+                        { format!("Part {}", chunk + 1) }
+                    </option>
+                }
+            })
+            .collect::<Html>();
+
+        let audio = match selected {
+            Some(chunk) => html! {
+                <audio
+                    controls=true
+                    autoplay=true
+                    src={ job_audio_chunk_url(id, chunk) }
+                    aria-label={ format!("Part {} of {}", chunk + 1, job.title) }
+                />
+            },
+            None => Html::default(),
+        };
+
+        html! {
+            <div class="field">
+                <label for={ selector_id.clone() }>{ "Preview:" }</label>
+                <select id={ selector_id } {onchange}>
+                    <option value="" selected={ selected.is_none() }>{ "Choose a part" }</option>
+                    { options }
+                </select>
+                { audio }
+            </div>
+        }
+    }
+}
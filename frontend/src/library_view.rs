@@ -1,28 +1,62 @@
 use crate::{
     app_view::Route,
-    caching,
-    queue_view::{ArticleId, CachedArticle, Queue, QueueEntry, QueueMsg},
-    WeakComponentLink,
+    battery, caching,
+    day_sections::{DaySection, DayStarts},
+    downloads, history_view,
+    playlists_view::{Playlists, PlaylistsMsg},
+    queue_view::{self, ArticleId, Placement, Queue, QueueEntry, QueueMsg},
+    reminders_view::{self, Reminders, RemindersMsg},
+    servers,
+    shares_view::{self, DEFAULT_SHARE_DAYS},
+    smart_playlist::{self, MatchState, Query, SmartPlaylist},
+    storage_view, utils, WeakComponentLink,
+};
+use common::{
+    ArticleDeletion, ArticleGroup, ArticleMetadata, ArticleRating, CatalogFormat,
+    FinishArticleSubmission, LibraryCatalog, LibraryDelta, LibraryImportSummary, Rating,
+    ReExtractResponse, ReExtractSubmission, SharedLink, SourceType,
 };
-use common::{ArticleMetadata, LibraryCatalog};
 
-use std::collections::BTreeMap;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 
 use anyhow::{bail, Error as AnyError};
-use gloo_net::http::Request;
 use url::Url;
 use wasm_bindgen::{closure::Closure, JsCast, JsValue};
-use web_sys::PageTransitionEvent;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlSelectElement, PageTransitionEvent};
 use yew::{html::Scope, prelude::*};
 use yew_router::prelude::*;
 
-/// Fetches the list of articles
-async fn fetch_catalog() -> Result<LibraryCatalog, AnyError> {
+/// The number of rows shown per page of articles. A group takes up one row.
+const PAGE_SIZE: usize = 50;
+
+/// The ID of the input the library archive to import is picked in
+const ARCHIVE_INPUT_ID: &str = "library-archive-input";
+
+/// The ID of the input the article bundle to import is picked in
+const BUNDLE_INPUT_ID: &str = "article-bundle-input";
+
+/// Fetches the list of articles, and saves it so the library can be browsed offline. If the server
+/// can't be reached, the list as it was last saved is returned instead.
+pub(crate) async fn fetch_catalog() -> Result<LibraryCatalog, AnyError> {
     tracing::debug!("Fetching article list");
-    let resp = Request::get("/api/list-articles")
-        .send()
-        .await
-        .map_err(|e| AnyError::from(e).context("Error fetching article list"))?;
+    let resp = match utils::get("/api/list-articles").send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            let err = AnyError::from(e).context("Error fetching article list");
+            return match caching::load_catalog().await {
+                Ok(saved) if !saved.0.is_empty() => {
+                    tracing::info!("Offline, so showing the saved article list: {:#}", err);
+                    Ok(saved)
+                }
+                _ => Err(err),
+            };
+        }
+    };
     tracing::debug!("Done fetching. Response is {:?}", resp);
 
     if !resp.ok() {
@@ -34,132 +68,374 @@ async fn fetch_catalog() -> Result<LibraryCatalog, AnyError> {
         );
     }
 
-    resp.json()
+    let catalog: LibraryCatalog = resp
+        .json()
         .await
-        .map_err(|e| AnyError::from(e).context("Error parsing article list JSON"))
+        .map_err(|e| AnyError::from(e).context("Error parsing article list JSON"))?;
+
+    // Saving every article's metadata takes a while, and the list doesn't need to wait for it
+    let to_save = catalog.clone();
+    spawn_local(async move {
+        if let Err(e) = caching::save_catalog(&to_save).await {
+            tracing::error!("Couldn't save the article list: {:#}", e);
+        }
+    });
+
+    Ok(catalog)
 }
 
-/// Fetches a specific article. The `lib_link` parameter is so it can report fetch progress.
-async fn fetch_article(
-    id: &ArticleId,
-    title: &str,
-    lib_link: Scope<Library>,
-) -> Result<CachedArticle, AnyError> {
-    // Fetch the audio blobs
-    let filename = format!("{}.mp3", id.0);
-    let encoded_title = urlencoding::encode(&filename);
-    let resp = Request::get(&format!("/api/audio-blobs/{encoded_title}"))
+/// Deletes the saved transcript of the given article, so the new one is fetched when it's needed
+async fn forget_transcript(id: &ArticleId) {
+    if let Err(e) = caching::delete_transcript(id).await {
+        tracing::error!("Couldn't delete the transcript of {}: {:#}", id.0, e);
+    }
+}
+
+/// Fetches what changed in the library since the given time, in seconds since the Unix epoch.
+/// Everything changed since 0.
+async fn fetch_changes(since: u64) -> Result<LibraryDelta, AnyError> {
+    let resp = utils::get(&format!("/api/library-changes?since={since}"))
         .send()
         .await
-        .map_err(|e| {
-            let ctx = format!("Error fetching article {:?}", id);
-            AnyError::from(e).context(ctx)
-        })?;
+        .map_err(|e| AnyError::from(e).context("Error fetching library changes"))?;
     if !resp.ok() {
         bail!(
-            "Error fetching article {} ({})",
+            "Error fetching library changes {} ({})",
             resp.status(),
             resp.status_text()
         );
     }
 
-    // Get the size of the audio blob. This is the denominator when we compute percentage
-    // downloaded.
-    let blob_size = resp
-        .headers()
-        .get("content-length")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
-
-    // Convert the response into one that will fire a given callback every time it gets a chunk. We
-    // use this callback to inform the library of the download status.
-    let mut num_bytes_read = 0;
-    let id_copy = id.clone();
-    let resp = crate::utils::response_with_read_callback(resp, move |chunk| {
-        // Update the number of bytes read
-        num_bytes_read += chunk.byte_length() as usize;
-
-        // Tell the library how much progress we've made
-        lib_link.send_message(LibraryMsg::SetDownloadProgress {
-            id: id_copy.clone(),
-            progress: num_bytes_read as f64 / blob_size as f64,
-        });
-    })?;
-
-    // Download the whole response
-    let audio_blob = resp
-        .binary()
+    resp.json()
         .await
-        .map_err(|e| AnyError::msg(format!("Error parsing audio binary: {e}")))?;
+        .map_err(|e| AnyError::from(e).context("Error parsing library changes JSON"))
+}
 
-    // Return the article
-    Ok(CachedArticle {
-        title: title.to_string(),
-        id: id.clone(),
-        audio_blob,
-    })
+/// How an article changed when the library was last refreshed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Update {
+    New,
+    Changed,
 }
 
-// Defines a stable ID that refers to whatever is to the left of an article's name. That's either
-// an Add to Queue button, a download progress indicator, or a "Queued" indicator.
-fn libitem_status_elem_id(id: &ArticleId) -> String {
-    format!("status-lib-{}", urlencoding::encode(&id.0))
+/// The sizes of the audio of articles that aren't downloaded, probed from the server as they're
+/// shown
+#[derive(Default)]
+struct AudioSizes {
+    /// The size of each probed article's audio, in bytes. It's `None` while the probe is going, or
+    /// if it failed.
+    probed: BTreeMap<ArticleId, Option<f64>>,
+    /// The articles that were shown before they were probed. They're probed after the view renders.
+    wanted: RefCell<BTreeSet<ArticleId>>,
 }
 
-/// Renders an item in the library
-fn render_lib_item(
-    metadata: ArticleMetadata,
-    library_link: Scope<Library>,
-    download_progress: Option<DownloadProgress>,
-) -> Html {
-    let title = metadata.title.clone();
-    let id = ArticleId(metadata.id.clone());
-    let title_copy = title.clone();
-
-    // Generate the ID for the button/progress indicator
-    let status_elem_id = libitem_status_elem_id(&id);
-
-    // Define the Add to Queue callback
-    let add_to_queue = library_link.callback_once(move |_| {
-        // Tell the library to fetch the article. This will change the button to a progress
-        // indicator
-        LibraryMsg::FetchArticle {
-            id: id.clone(),
-            title: title_copy.clone(),
+impl AudioSizes {
+    /// Returns the size of the given article's audio, if it's been probed. If not, it will be.
+    fn get(&self, id: &ArticleId) -> Option<f64> {
+        match self.probed.get(id) {
+            Some(size) => *size,
+            None => {
+                self.wanted.borrow_mut().insert(id.clone());
+                None
+            }
+        }
+    }
+
+    /// Takes the articles that need probing, and notes that they're being probed
+    fn take_wanted(&mut self) -> Vec<ArticleId> {
+        let wanted = std::mem::take(self.wanted.get_mut());
+        for id in &wanted {
+            self.probed.insert(id.clone(), None);
         }
+        wanted.into_iter().collect()
+    }
+}
+
+/// The saved catalog, brought up to date with the server
+pub(crate) struct CatalogRefresh {
+    catalog: LibraryCatalog,
+    /// How each article that was added or changed since the last refresh changed
+    updates: BTreeMap<ArticleId, Update>,
+    /// How many articles were deleted since the last refresh
+    removed: usize,
+}
+
+/// Brings the saved catalog up to date with the server, fetching only what changed since it was
+/// last brought up to date. If nothing was saved, the whole catalog is fetched, and none of it
+/// counts as changed.
+async fn refresh_catalog() -> Result<CatalogRefresh, AnyError> {
+    let saved = caching::load_catalog().await.unwrap_or_else(|e| {
+        tracing::error!("Couldn't load the saved catalog: {:#}", e);
+        LibraryCatalog(Vec::new())
     });
+    let first_sync = saved.0.is_empty();
+    let since = match first_sync {
+        true => 0,
+        false => caching::load_catalog_synced_at().unwrap_or(0),
+    };
+    let delta = fetch_changes(since).await?;
 
-    // Make a source URL link if it exists and is valid. Otherwise make this part empty.
-    let url = metadata
-        .source_url
-        .as_ref()
-        .and_then(|u| Url::parse(u).ok())
-        .map(|u| html! { <a href={ String::from(u) } title="Article source">{ "[source]" }</a> })
-        .unwrap_or(Html::default());
-
-    // Format the date the article was added
-    let lang = gloo_utils::window()
-        .navigator()
-        .language()
-        .unwrap_or("en-US".to_string());
-    let date_added: Option<String> = metadata.datetime_added.map(|t| {
-        // Convert to a local date string  by making a Date object and giving it the unix time
-        let js_date = js_sys::Date::new_0();
-        // set_time takes number of milliseconds since epoch, so multiply by 1000
-        js_date.set_time((t as f64) * 1000.0);
-        js_date.to_locale_string(&lang, &JsValue::TRUE).into()
+    // Drop what was deleted, and put in what was added or changed. The server might send back
+    // some articles that didn't change after all, so only count the ones that did.
+    let mut by_id: BTreeMap<String, ArticleMetadata> = saved
+        .0
+        .into_iter()
+        .map(|meta| (meta.id.clone(), meta))
+        .collect();
+    let num_saved = by_id.len();
+    let in_catalog: BTreeSet<String> = delta.ids.into_iter().collect();
+    by_id.retain(|id, _| in_catalog.contains(id));
+    let removed = num_saved - by_id.len();
+
+    let mut updates = BTreeMap::new();
+    for meta in &delta.changed {
+        let update = match by_id.get(&meta.id) {
+            None => Some(Update::New),
+            Some(old) if old != meta => Some(Update::Changed),
+            Some(_) => None,
+        };
+        if let (Some(update), false) = (update, first_sync) {
+            updates.insert(ArticleId(meta.id.clone()), update);
+        }
+        by_id.insert(meta.id.clone(), meta.clone());
+    }
+    let mut metas: Vec<ArticleMetadata> = by_id.into_values().collect();
+    metas.sort_by(|a, b| b.datetime_added.cmp(&a.datetime_added));
+
+    // Save the changes in the background. The time is only saved once they are, so a refresh
+    // that's cut short is picked up again next time.
+    spawn_local(async move {
+        let in_catalog = in_catalog.iter().map(String::as_str).collect();
+        match caching::save_catalog_changes(&delta.changed, &in_catalog).await {
+            Ok(()) => caching::save_catalog_synced_at(delta.as_of),
+            Err(e) => tracing::error!("Couldn't save the library changes: {:#}", e),
+        }
     });
-    let date_added_str = match date_added {
-        Some(d) => format!("Added {d}"),
-        None => format!("Date added unknown"),
+
+    Ok(CatalogRefresh {
+        catalog: LibraryCatalog(metas),
+        updates,
+        removed,
+    })
+}
+
+/// Deletes the saved transcript on the archived page of the given article, and to
+/// resynthesize the audio from the new text
+async fn re_extract_article(id: &ArticleId) -> Result<ReExtractResponse, AnyError> {
+    let submission = ReExtractSubmission {
+        id: id.0.clone(),
+        resynthesize: true,
+    };
+    let endpoint = "/api/re-extract-article";
+    let resp = utils::post(endpoint)
+        .json(&submission)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error re-extracting article. {}. {}",
+            resp.status_text(),
+            resp.text().await.unwrap_or("".to_string())
+        );
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing re-extraction response"))
+}
+
+/// Asks the server to synthesize the rest of an article whose conversion failed partway through
+async fn finish_article(id: &ArticleId) -> Result<ArticleMetadata, AnyError> {
+    let submission = FinishArticleSubmission { id: id.0.clone() };
+    let endpoint = "/api/finish-article";
+    let resp = utils::post(endpoint)
+        .json(&submission)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error finishing conversion. {}. {}",
+            resp.status_text(),
+            resp.text().await.unwrap_or("".to_string())
+        );
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing finish response"))
+}
+
+/// Uploads the given library archive, i.e., one made by exporting a library, and returns how many
+/// articles were imported from it
+async fn import_archive(file: web_sys::File) -> Result<LibraryImportSummary, AnyError> {
+    let resp = utils::post("/api/import-library")
+        .header("Content-Type", "application/x-tar")
+        .body(file)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error uploading archive"))?;
+
+    if !resp.ok() {
+        bail!("Error importing archive: {}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing import summary"))
+}
+
+/// Uploads the given article bundle, i.e., one made by exporting an article, and returns the
+/// article that was imported from it
+async fn import_bundle(file: web_sys::File) -> Result<ArticleMetadata, AnyError> {
+    let resp = utils::post("/api/import-bundle")
+        .header("Content-Type", "application/zip")
+        .body(file)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error uploading bundle"))?;
+
+    if !resp.ok() {
+        bail!("Error importing bundle: {}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing imported article"))
+}
+
+/// Asks the server to delete the given articles from the library
+async fn delete_articles(ids: &[ArticleId]) -> Result<(), AnyError> {
+    let deletion = ArticleDeletion {
+        ids: ids.iter().map(|id| id.0.clone()).collect(),
+    };
+    let endpoint = "/api/delete-articles";
+    let resp = utils::post(endpoint)
+        .json(&deletion)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error deleting articles. {}. {}",
+            resp.status_text(),
+            resp.text().await.unwrap_or("".to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// Asks the server to give the given article the given rating, or take its rating away
+async fn rate_article(id: &ArticleId, rating: Option<Rating>) -> Result<(), AnyError> {
+    let submission = ArticleRating {
+        id: id.0.clone(),
+        rating,
     };
+    let endpoint = "/api/rate-article";
+    let resp = utils::post(endpoint)
+        .json(&submission)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error rating article. {}. {}",
+            resp.status_text(),
+            resp.text().await.unwrap_or("".to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// Renders the thumbs up and thumbs down buttons for the article with the given title, which has
+/// the given rating. Pressing the button of the rating it has already takes the rating away.
+pub(crate) fn render_rating_buttons(
+    title: &str,
+    current: Option<Rating>,
+    rate: Callback<Option<Rating>>,
+) -> Html {
+    [Rating::Up, Rating::Down]
+        .into_iter()
+        .map(|rating| {
+            let pressed = current == Some(rating);
+            let onclick = rate.reform(move |_| (!pressed).then_some(rating));
+            let label = match rating {
+                Rating::Up => format!("Worth it: {title}"),
+                Rating::Down => format!("Not worth it: {title}"),
+            };
+            html! {
+                <button
+                    class="rateArticle"
+                    aria-label={ label.clone() }
+                    aria-pressed={ pressed.to_string() }
+                    title={ label }
+                    {onclick}
+                >
+                    { rating.emoji() }
+                </button>
+            }
+        })
+        .collect()
+}
+
+/// Loads the saved smart playlists, sorted by name
+async fn load_smart_playlists() -> LibraryMsg {
+    match caching::load_smart_playlists().await {
+        Ok(mut playlists) => {
+            playlists.sort_by(|a, b| a.name.cmp(&b.name));
+            LibraryMsg::SetSmartPlaylists(playlists)
+        }
+        Err(e) => LibraryMsg::SetError(e.context("Couldn't load smart playlists")),
+    }
+}
+
+/// Loads the set of articles that have been listened to. If this fails, nothing counts as
+/// listened.
+async fn load_listened() -> BTreeSet<ArticleId> {
+    caching::load_listened()
+        .await
+        .map_err(|e| tracing::error!("Couldn't load listening history: {}", e))
+        .unwrap_or_default()
+}
+
+// Defines a stable ID that refers to whatever is to the left of an article's name. That's either
+// an Add to Queue button, a download progress indicator, a Retry button, or a "Queued" indicator.
+/// Describes how long an article is, e.g., "12 min" or "1 h 5 min"
+fn format_duration(secs: u64) -> String {
+    let mins = (secs + 30) / 60;
+    match mins {
+        0 => "Under a minute".to_string(),
+        1..=59 => format!("{mins} min"),
+        _ => format!("{} h {} min", mins / 60, mins % 60),
+    }
+}
+
+fn libitem_status_elem_id(id: &ArticleId) -> String {
+    format!("status-lib-{}", urlencoding::encode(&id.0))
+}
+
+/// Like `libitem_status_elem_id`, but for the status of a whole group
+fn libgroup_status_elem_id(group_id: &str) -> String {
+    format!("status-libgroup-{}", urlencoding::encode(group_id))
+}
 
-    // If the article is downloading, display download progress instead of the "Add to Queue"
-    // button
-    let add_to_queue_button = if let Some(progress) = download_progress {
+/// Renders what goes to the left of a library item's name: an Add to Queue button if it isn't
+/// downloaded, a progress bar if it's downloading, a Retry button if its download failed, and
+/// "Queued" if it's done
+fn render_status(
+    status_elem_id: String,
+    title: &str,
+    download_progress: Option<DownloadProgress>,
+    add_to_queue: Callback<MouseEvent>,
+) -> Html {
+    if let Some(progress) = download_progress {
         match progress {
-            // If it's in progress, show the percentage in smallish text
+            // If it's in progress, show a progress bar
             DownloadProgress::InProgress(fraction) => {
                 let pct_val = format!("{}", (100.0 * fraction).floor() as usize);
                 let pct_str = format!("{}%", pct_val);
@@ -170,14 +446,30 @@ fn render_lib_item(
                         tabindex="-1"
                         id={ status_elem_id }
                         role="progressbar"
-                        aria-valuenow={ pct_val }
+                        aria-valuenow={ pct_val.clone() }
                         aria-label={ title_text.clone() }
                         title={ title_text }
                     >
-                        <span aria-hidden="true">{ pct_str }</span>
+                        <progress aria-hidden="true" max="100" value={ pct_val }>
+                            { pct_str }
+                        </progress>
                     </div>
                 }
             }
+            // If the download failed, offer to try again. It resumes where it left off.
+            DownloadProgress::Failed => {
+                let title_text = format!("Download failed, retry: {title}");
+                html! {
+                    <button
+                        id={ status_elem_id }
+                        onclick={ add_to_queue }
+                        aria-label={ title_text.clone() }
+                        title={ title_text }
+                    >
+                        { "Retry" }
+                    </button>
+                }
+            }
             // If it's done downloading display "Queued" in smallish text
             DownloadProgress::Done => {
                 let title_text = format!("Queued: {title}");
@@ -196,7 +488,7 @@ fn render_lib_item(
             }
         }
     } else {
-        // If the article isn't being downloaded, display an Add to Queue button
+        // If it isn't being downloaded, display an Add to Queue button
         let add_title_text = format!("Add to queue: {}", title);
         html! {
             <button
@@ -208,25 +500,158 @@ fn render_lib_item(
                 { "+" }
             </button>
         }
-    };
+    }
+}
+
+/// Renders the checkbox that selects the given articles for a bulk action. It's checked if all of
+/// them are selected.
+fn render_select(
+    ids: Vec<ArticleId>,
+    title: &str,
+    selected: &BTreeSet<ArticleId>,
+    library_link: &Scope<Library>,
+) -> Html {
+    let checked = ids.iter().all(|id| selected.contains(id));
+    let onchange = library_link.callback(move |_| LibraryMsg::ToggleSelected(ids.clone()));
+    html! {
+        <input
+            type="checkbox"
+            aria-label={ format!("Select: {title}") }
+            {checked}
+            {onchange}
+        />
+    }
+}
+
+/// Renders the links to the previous and next pages of articles, if there's more than one page
+fn render_page_nav(library_link: &Scope<Library>, page: usize, num_pages: usize) -> Html {
+    if num_pages <= 1 {
+        return Html::default();
+    }
+
+    let prev = library_link.callback(move |_| LibraryMsg::SetPage(page - 1));
+    let next = library_link.callback(move |_| LibraryMsg::SetPage(page + 1));
+    html! {
+        <nav class="libraryPages" aria-label="Library pages">
+            <button onclick={prev} disabled={ page == 0 }>{ "Previous" }</button>
+            <span>{ format!(" Page {} of {num_pages} ", page + 1) }</span>
+            <button onclick={next} disabled={ page + 1 >= num_pages }>{ "Next" }</button>
+        </nav>
+    }
+}
 
+/// Renders the heading of the articles added on the given day, with how many there are. Clicking it
+/// collapses or expands them.
+fn render_day_heading(
+    library_link: &Scope<Library>,
+    day: DaySection,
+    count: usize,
+    collapsed: bool,
+) -> Html {
+    let onclick = library_link.callback(move |_| LibraryMsg::SetDayCollapsed {
+        day,
+        collapsed: !collapsed,
+    });
+    let arrow = if collapsed { "▸" } else { "▾" };
     html! {
-        <tr role="listitem" aria-label={ title.clone() }>
-            <td class="addToQueue">{add_to_queue_button}</td>
-            <td class = "articleDetails">
-                <p class="libArticleTitle">{ title }</p>
-                <span class="articleMetadata">{ date_added_str }</span>
-                <span class="articleMetadata">{ url }</span>
-            </td>
+        <tr class="daySection">
+            <th colspan="3" scope="colgroup">
+                <button {onclick} aria-expanded={ (!collapsed).to_string() }>
+                    { format!("{arrow} {}", day.label()) }
+                </button>
+                <span class="articleMetadata">{ format!(" ({count})") }</span>
+            </th>
         </tr>
     }
 }
 
-/// Describes whether an article is downloading (and if so, how much of it has downloaded), or if
-/// it's done downloading
+/// Renders a list of the given names, e.g., authors, with how many articles each has. Every name
+/// links to the route that `to_route` makes from its URL-encoded form.
+fn render_index<'a>(
+    names: impl Iterator<Item = &'a str>,
+    label: &str,
+    to_route: impl Fn(String) -> Route,
+) -> Html {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for name in names {
+        *counts.entry(name).or_default() += 1;
+    }
+    if counts.is_empty() {
+        return html! { <p style="font-style: italic">{ "Nothing to show" }</p> };
+    }
+
+    let items = counts
+        .into_iter()
+        .map(|(name, count)| {
+            let to = to_route(urlencoding::encode(name).into_owned());
+            html! {
+                <li>
+                    <Link<Route> {to}>{ name }</Link<Route>>
+                    <span class="articleMetadata">{ format!(" ({count})") }</span>
+                </li>
+            }
+        })
+        .collect::<Html>();
+
+    html! { <ul aria-label={ label.to_string() }>{ items }</ul> }
+}
+
+/// Which part of the library is shown
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Browse {
+    /// Every article
+    #[default]
+    All,
+    /// The list of authors
+    Authors,
+    /// The articles by the given author
+    Author(String),
+    /// The list of publications
+    Publications,
+    /// The articles from the given publication
+    Publication(String),
+    /// The list of smart playlists
+    Playlists,
+    /// The articles matching the smart playlist with the given name
+    Playlist(String),
+}
+
+/// How the listener left one part of the library: what they searched for, which page, groups, and
+/// days they had open, and how far down they'd scrolled
+#[derive(Clone, Debug, Default)]
+struct ViewState {
+    search: String,
+    page: usize,
+    expanded_groups: BTreeSet<String>,
+    collapsed_days: BTreeSet<DaySection>,
+    scroll_y: f64,
+}
+
+/// A row of the library's list of articles
+enum LibRow<'a> {
+    /// The heading of the articles added on a day, and how many there are
+    Day(DaySection, usize),
+    /// An article, or the first part of a group
+    Article(&'a ArticleMetadata),
+}
+
+/// How the listener left each part of the library they've browsed. This is held by the App, so it
+/// outlives the Library, which goes away when the Add page is opened. Clones share the same state.
+#[derive(Clone, Default)]
+pub(crate) struct ViewStates(Rc<RefCell<BTreeMap<Browse, ViewState>>>);
+
+impl PartialEq for ViewStates {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Describes whether an article is downloading (and if so, how much of it has downloaded), if its
+/// download failed, or if it's done downloading
 #[derive(Copy, Clone, Debug)]
 enum DownloadProgress {
     InProgress(f64),
+    Failed,
     Done,
 }
 
@@ -236,6 +661,46 @@ pub(crate) struct Library {
     err: Option<AnyError>,
     catalog: Option<LibraryCatalog>,
     download_progresses: BTreeMap<ArticleId, DownloadProgress>,
+    /// The user's saved searches
+    smart_playlists: Vec<SmartPlaylist>,
+    /// The articles that have been listened to, as of the last time it was loaded
+    listened: BTreeSet<ArticleId>,
+    /// How far each article has been listened to, as a percentage
+    listened_pcts: BTreeMap<ArticleId, u32>,
+    /// The contents of the search box
+    search: String,
+    /// The page of articles being shown, starting at 0
+    page: usize,
+    /// The IDs of the groups whose parts are listed
+    expanded_groups: BTreeSet<String>,
+    /// The days whose articles aren't listed
+    collapsed_days: BTreeSet<DaySection>,
+    /// The part of the library being shown. Its view state is saved under this when it's left.
+    browse: Browse,
+    /// How far down to scroll once the catalog is showing, when coming back to a part of the
+    /// library
+    restore_scroll: Option<f64>,
+    /// How the articles that changed since the library was last opened changed
+    updates: BTreeMap<ArticleId, Update>,
+    /// Whether the catalog is being brought up to date with the server
+    refreshing: bool,
+    /// What the last refresh of the catalog did, if it's worth saying
+    refresh_note: Option<String>,
+    /// The sizes of the audio of the articles shown that aren't downloaded
+    audio_sizes: AudioSizes,
+    /// An article that's being fetched by auto-advance, and should play once it's queued
+    play_when_queued: Option<ArticleId>,
+    /// Where the articles that are downloading go in the queue once they're done, if they were
+    /// given a place. The rest go at the end.
+    placements: BTreeMap<ArticleId, Placement>,
+    /// The ID of the article or group whose actions menu is open, if any
+    menu_open: Option<String>,
+    /// The articles selected for a bulk action
+    selected: BTreeSet<ArticleId>,
+    /// The IDs and names of the playlists articles can be added to
+    playlists: Vec<(String, String)>,
+    /// How the import of a library archive went, if one was started
+    archive_status: Option<String>,
     _pageshow_action: Option<Closure<dyn 'static + Fn(PageTransitionEvent)>>,
 }
 
@@ -245,11 +710,28 @@ pub(crate) enum LibraryMsg {
     /// Sets the Library's error display to the given error
     SetError(AnyError),
     /// Tells the library to do a fetch() for the specific article
-    FetchArticle { id: ArticleId, title: String },
-    /// Tells the library to fetch() the catalog
+    FetchArticle {
+        id: ArticleId,
+        title: String,
+        group: Option<ArticleGroup>,
+    },
+    /// Tells the library to fetch() every part of the group with the given ID that isn't already
+    /// downloaded
+    FetchGroup(String),
+    /// Tells the library to bring the catalog up to date with the server
     FetchCatalog,
+    /// Shows the catalog as it was brought up to date
+    CatalogRefreshed(CatalogRefresh),
+    /// Bringing the catalog up to date failed. If the saved catalog is showing, it stays up.
+    RefreshFailed(AnyError),
+    /// Sets the size of the given article's audio, as probed from the server
+    SetAudioSize { id: ArticleId, bytes: f64 },
     /// Updates the download progress of the given article
     SetDownloadProgress { id: ArticleId, progress: f64 },
+    /// Marks the download of the given article as failed, so it can be retried
+    DownloadFailed { id: ArticleId, err: AnyError },
+    /// Resumes the given downloads, which were interrupted before they finished
+    ResumeDownloads(Vec<QueueEntry>),
     /// Sets the browser focus on the HTML element with the given DOM ID
     SetFocus(String),
     /// Tells the Library to send the given queue entry to the Queue
@@ -259,12 +741,102 @@ pub(crate) enum LibraryMsg {
     MarkAsQueued(Vec<ArticleId>),
     /// Sets the given article as Not Downloaded in the library view
     MarkAsUnqueued(ArticleId),
+    /// Tells the server to re-extract and resynthesize the given article from its archived page
+    ReExtract(ArticleId),
+    /// Tells the server to synthesize the rest of the given incomplete article
+    FinishConversion(ArticleId),
+    /// Has the reminders ask when to be reminded of the given article
+    RemindLater { id: ArticleId, title: String },
+    /// Gives the given article the given rating, or takes its rating away
+    Rate {
+        id: ArticleId,
+        rating: Option<Rating>,
+    },
+    /// Asks how long a link to the given article should work for, and makes one
+    Share { id: ArticleId, title: String },
+    /// Shows the given shared link, so it can be copied
+    ShowSharedLink(SharedLink),
+    /// Selects the given articles, or deselects them if they're all selected already
+    ToggleSelected(Vec<ArticleId>),
+    /// Deselects every article
+    ClearSelection,
+    /// Adds every selected article that isn't queued to the queue
+    QueueSelected,
+    /// Asks to confirm, then deletes the selected articles from the library
+    DeleteSelected,
+    /// Adds the selected articles to the playlist with the given ID
+    AddSelectedToPlaylist(String),
+    /// Sets the IDs and names of the playlists. This is called by the Playlists component.
+    SetPlaylists(Vec<(String, String)>),
+    /// Plays the given article, downloading it into the queue first if it isn't there. This is
+    /// called by the Queue when it's playing a playlist, and by the listening history's timeline.
+    PlayArticle(QueueEntry),
+    /// Puts the given articles at the given place in the queue, in order, downloading the ones that
+    /// aren't downloaded first. Playing a group now plays its first part, and the rest follow it.
+    QueueAt {
+        ids: Vec<ArticleId>,
+        placement: Placement,
+    },
+    /// Takes the given articles out of the queue, but keeps them downloaded
+    Unqueue(Vec<ArticleId>),
+    /// Opens the actions menu of the article or group with the given ID, or closes it if it's open
+    ToggleMenu(String),
+    /// Uploads the library archive picked in the form, and imports it
+    ImportArchive,
+    /// The library archive was imported. Fetches the catalog again, so the imported articles show.
+    ArchiveImported(LibraryImportSummary),
+    /// Sets how the import of a library archive went
+    SetArchiveStatus(String),
+    /// Uploads the article bundle picked in the form, and imports it
+    ImportBundle,
+    /// The article bundle was imported. Fetches the catalog again, so the article shows.
+    BundleImported(ArticleMetadata),
+    /// The given articles were deleted from the library. They're taken out of the queue too.
+    ArticlesDeleted(Vec<ArticleId>),
+    /// Sets the list of smart playlists
+    SetSmartPlaylists(Vec<SmartPlaylist>),
+    /// Sets the set of articles that have been listened to
+    SetListened(BTreeSet<ArticleId>),
+    /// Sets how far each article has been listened to, as a percentage. This is called by the
+    /// Queue.
+    SetListenedPcts(BTreeMap<ArticleId, u32>),
+    /// Sets the search box contents
+    SetSearch(String),
+    /// Shows the given page of articles
+    SetPage(usize),
+    /// Lists the parts of the group with the given ID, or stops listing them
+    SetGroupExpanded { id: String, expanded: bool },
+    /// Hides or shows the articles added on the given day
+    SetDayCollapsed { day: DaySection, collapsed: bool },
+    /// Asks for a name, and saves the current search as a smart playlist under it
+    SaveSearch,
+    /// Deletes the smart playlist with the given name
+    DeleteSmartPlaylist(String),
+    /// Makes the smart playlist with the given name the auto-advance source, or stops it being one
+    ToggleAutoAdvance(String),
+    /// A message from the queue saying the given article finished and nothing in the queue follows
+    /// it. Plays the next article from the auto-advance playlist, if there is one.
+    AutoAdvance(ArticleId),
+    /// Plays an article that comes after the given one in the auto-advance playlist, from the
+    /// sources the listener rates highest, given the freshly loaded set of listened articles
+    PlayNextMatch {
+        after: ArticleId,
+        listened: BTreeSet<ArticleId>,
+    },
 }
 
 #[derive(PartialEq, Properties)]
 pub(crate) struct Props {
     pub queue_link: WeakComponentLink<Queue>,
     pub library_link: WeakComponentLink<Library>,
+    pub reminders_link: WeakComponentLink<Reminders>,
+    pub playlists_link: WeakComponentLink<Playlists>,
+    /// Which part of the library to show
+    #[prop_or_default]
+    pub browse: Browse,
+    /// How the listener left each part of the library, so it's restored when they come back
+    #[prop_or_default]
+    pub view_states: ViewStates,
 }
 
 impl Component for Library {
@@ -272,8 +844,6 @@ impl Component for Library {
     type Properties = Props;
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
-        let lib_link = ctx.link().clone();
-
         match msg {
             LibraryMsg::SetCatalog(catalog) => {
                 self.err = None;
@@ -286,35 +856,55 @@ impl Component for Library {
             }
 
             LibraryMsg::FetchCatalog => {
+                self.refreshing = true;
                 ctx.link().send_future(async move {
-                    match fetch_catalog().await {
-                        Ok(list) => LibraryMsg::SetCatalog(list),
-                        Err(e) => LibraryMsg::SetError(e.into()),
+                    match refresh_catalog().await {
+                        Ok(refresh) => LibraryMsg::CatalogRefreshed(refresh),
+                        Err(e) => LibraryMsg::RefreshFailed(e),
                     }
                 });
             }
 
-            LibraryMsg::FetchArticle { id, title } => {
-                // We've been asked to fetch an article. Immediately set its progress to 0%
-                self.download_progresses
-                    .insert(id.clone(), DownloadProgress::InProgress(0.0));
+            LibraryMsg::CatalogRefreshed(refresh) => {
+                self.refreshing = false;
+                self.err = None;
+                self.catalog = Some(refresh.catalog);
 
-                // Fetch an article, save it, and relay the article handle. If there's an error,
-                // post it
-                let id_copy = id.clone();
-                ctx.link().send_future(async move {
-                    let article = match fetch_article(&id_copy, &title, lib_link).await {
-                        Ok(a) => a,
-                        Err(e) => return LibraryMsg::SetError(e),
-                    };
+                let new = refresh
+                    .updates
+                    .values()
+                    .filter(|u| **u == Update::New)
+                    .count();
+                let changed = refresh.updates.len() - new;
+                let counts = [
+                    (new, "new"),
+                    (changed, "changed"),
+                    (refresh.removed, "removed"),
+                ];
+                let counts: Vec<String> = counts
+                    .iter()
+                    .filter(|(n, _)| *n > 0)
+                    .map(|(n, what)| format!("{n} {what}"))
+                    .collect();
+                if !counts.is_empty() {
+                    self.refresh_note = Some(format!("Updated: {}", counts.join(", ")));
+                }
+                self.updates.extend(refresh.updates);
+            }
 
-                    let queue_entry = match caching::save_article(&article).await {
-                        Ok(h) => h,
-                        Err(e) => return LibraryMsg::SetError(e),
-                    };
+            LibraryMsg::RefreshFailed(err) => {
+                self.refreshing = false;
+                if self.catalog.is_some() {
+                    tracing::warn!("Couldn't refresh the library: {:#}", err);
+                    self.refresh_note =
+                        Some("Offline. This is the library as it was last loaded.".to_string());
+                } else {
+                    self.err = Some(err);
+                }
+            }
 
-                    LibraryMsg::PassArticleToQueue(queue_entry)
-                });
+            LibraryMsg::FetchArticle { id, title, group } => {
+                self.fetch_article(ctx, id.clone(), title, group);
 
                 // When the Add to Queue button is pressed, the button turns into a progress
                 // indicator. By default, this indicator does not receive cursor focus. For
@@ -325,6 +915,31 @@ impl Component for Library {
                     .send_future(async move { LibraryMsg::SetFocus(status_elem_id) });
             }
 
+            LibraryMsg::FetchGroup(group_id) => {
+                // Fetch every part that isn't downloading or downloaded already
+                let parts: Vec<ArticleMetadata> = self
+                    .catalog
+                    .iter()
+                    .flat_map(|catalog| catalog.0.iter())
+                    .filter(|meta| matches!(&meta.group, Some(g) if g.id == group_id))
+                    .filter(|meta| {
+                        matches!(
+                            self.download_progresses.get(&ArticleId(meta.id.clone())),
+                            Some(DownloadProgress::Failed) | None
+                        )
+                    })
+                    .cloned()
+                    .collect();
+                for meta in parts {
+                    self.fetch_article(ctx, ArticleId(meta.id), meta.title, meta.group);
+                }
+
+                // As with a single article, move the focus to the progress indicator
+                let status_elem_id = libgroup_status_elem_id(&group_id);
+                ctx.link()
+                    .send_future(async move { LibraryMsg::SetFocus(status_elem_id) });
+            }
+
             LibraryMsg::SetFocus(elem_id) => {
                 // Focus the HTML element with the given ID
                 gloo_utils::document()
@@ -336,26 +951,65 @@ impl Component for Library {
                     .unwrap();
             }
 
+            LibraryMsg::SetAudioSize { id, bytes } => {
+                self.audio_sizes.probed.insert(id, Some(bytes));
+            }
+
             LibraryMsg::SetDownloadProgress { id, progress } => {
                 // Update the article's download progress
                 self.download_progresses
                     .insert(id.clone(), DownloadProgress::InProgress(progress));
             }
 
+            LibraryMsg::DownloadFailed { id, err } => {
+                tracing::error!("Download of {} failed: {:?}", id.0, err);
+                self.download_progresses
+                    .insert(id.clone(), DownloadProgress::Failed);
+
+                // Auto-advance can't wait on an article that isn't coming
+                if self.play_when_queued.as_ref() == Some(&id) {
+                    self.play_when_queued = None;
+                }
+                self.placements.remove(&id);
+            }
+
+            LibraryMsg::ResumeDownloads(entries) => {
+                for entry in entries {
+                    if !self.download_progresses.contains_key(&entry.id) {
+                        self.download(ctx, entry);
+                    }
+                }
+            }
+
             LibraryMsg::PassArticleToQueue(queue_entry) => {
-                // Tell the queue about the article
+                // Tell the queue about the article, and where it goes if it was given a place
+                let msg = match self.placements.remove(&queue_entry.id) {
+                    Some(placement) => QueueMsg::AddAt(queue_entry.clone(), placement),
+                    None => QueueMsg::Add(queue_entry.clone()),
+                };
                 ctx.props()
                     .queue_link
                     .borrow()
                     .clone()
                     .unwrap()
-                    .send_message(QueueMsg::Add(queue_entry.clone()));
+                    .send_message(msg);
 
                 // Being entered into the queue means it's done downloading. Mark it done. This
                 // will turn the progress indicator into the text "Queued".
                 self.download_progresses
                     .insert(queue_entry.id.clone(), DownloadProgress::Done);
 
+                // If auto-advance was waiting on this article, play it
+                if self.play_when_queued.as_ref() == Some(&queue_entry.id) {
+                    self.play_when_queued = None;
+                    ctx.props()
+                        .queue_link
+                        .borrow()
+                        .clone()
+                        .unwrap()
+                        .send_message(QueueMsg::PlayEntry(queue_entry.id.clone()));
+                }
+
                 // If the download progress indicator was focused, the browser will not necessarily
                 // focus on the new "Queued" text. For accessibility, we manually shift the focus.
                 // The IDs of both are the same.
@@ -384,45 +1038,526 @@ impl Component for Library {
                 // Mark the article as not downloaded
                 self.download_progresses.remove(&id);
             }
-        }
 
-        // Every one of the above messages causes a visible change in the library
-        true
-    }
+            LibraryMsg::ReExtract(id) => {
+                // Resynthesis costs TTS quota, so make sure the user meant to click this
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message(
+                        "Re-extract this article from its saved page and regenerate its audio?",
+                    )
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
 
-    fn create(ctx: &Context<Self>) -> Self {
-        // Set the queue link to this Library
-        ctx.props()
-            .library_link
-            .borrow_mut()
-            .replace(ctx.link().clone());
+                // Do the re-extraction. The article might have a new ID afterwards, so refetch
+                // the catalog. Its text changed, so the saved transcript is out of date.
+                ctx.link().send_future(async move {
+                    match re_extract_article(&id).await {
+                        Ok(_) => {
+                            forget_transcript(&id).await;
+                            LibraryMsg::FetchCatalog
+                        }
+                        Err(e) => LibraryMsg::SetError(e),
+                    }
+                });
+            }
 
-        // Kick of a future that will fetch the article list
-        ctx.link().send_future(async move {
-            match fetch_catalog().await {
-                Ok(list) => LibraryMsg::SetCatalog(list),
-                Err(e) => LibraryMsg::SetError(e.into()),
+            LibraryMsg::FinishConversion(id) => {
+                // The article is still incomplete if this fails partway again, so either way,
+                // refetch the catalog to get its current state. The saved transcript only covers
+                // what was synthesized before.
+                ctx.link().send_future(async move {
+                    match finish_article(&id).await {
+                        Ok(_) => {
+                            forget_transcript(&id).await;
+                            LibraryMsg::FetchCatalog
+                        }
+                        Err(e) => LibraryMsg::SetError(e),
+                    }
+                });
             }
-        });
 
-        // Save the pageshow callback and set it on document.window. This is so that when you hit
-        // the back button from adding an article, it will try to reload the catalog.
-        let lib_link = ctx.link().clone();
-        let pageshow_cb = Closure::new(move |evt: PageTransitionEvent| {
-            // If the page is loading from a cache, force it to reload the library
-            if evt.persisted() {
-                lib_link.send_message(LibraryMsg::FetchCatalog)
+            LibraryMsg::Rate { id, rating } => {
+                // Show the rating right away. If it can't be saved, fetch the catalog again to
+                // show what is.
+                for meta in self.catalog.iter_mut().flat_map(|c| c.0.iter_mut()) {
+                    if meta.id == id.0 {
+                        meta.rating = rating;
+                    }
+                }
+                ctx.link().send_future_batch(async move {
+                    match rate_article(&id, rating).await {
+                        Ok(()) => Vec::new(),
+                        Err(e) => vec![LibraryMsg::SetError(e), LibraryMsg::FetchCatalog],
+                    }
+                });
             }
-        });
-        gloo_utils::window()
-            .add_event_listener_with_callback("pageshow", pageshow_cb.as_ref().unchecked_ref())
-            .expect("couldn't register pageshow callback");
 
-        Library {
-            _pageshow_action: Some(pageshow_cb),
-            ..Default::default()
-        }
-    }
+            LibraryMsg::RemindLater { id, title } => {
+                ctx.props()
+                    .reminders_link
+                    .borrow()
+                    .clone()
+                    .unwrap()
+                    .send_message(RemindersMsg::RemindLater { id, title });
+                return false;
+            }
+
+            LibraryMsg::Share { id, title } => {
+                let days = gloo_utils::window()
+                    .prompt_with_message_and_default(
+                        &format!("How many days should the link to \"{title}\" work for?"),
+                        &DEFAULT_SHARE_DAYS.to_string(),
+                    )
+                    .ok()
+                    .flatten()
+                    .and_then(|days| days.trim().parse().ok());
+                let days = match days {
+                    Some(d) => d,
+                    None => return false,
+                };
+
+                ctx.link().send_future(async move {
+                    match shares_view::share_article(id.0, title, days).await {
+                        Ok(link) => LibraryMsg::ShowSharedLink(link),
+                        Err(e) => LibraryMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            LibraryMsg::ShowSharedLink(link) => {
+                let _ = gloo_utils::window().prompt_with_message_and_default(
+                    "Copy this link to share the article. It can be revoked under Shared links.",
+                    &shares_view::absolute_url(&link),
+                );
+                return false;
+            }
+
+            LibraryMsg::ToggleSelected(ids) => {
+                if ids.iter().all(|id| self.selected.contains(id)) {
+                    for id in &ids {
+                        self.selected.remove(id);
+                    }
+                } else {
+                    self.selected.extend(ids);
+                }
+            }
+
+            LibraryMsg::ClearSelection => {
+                self.selected.clear();
+            }
+
+            LibraryMsg::QueueSelected => {
+                // Fetch every selected article that isn't downloading or downloaded already
+                let metas: Vec<ArticleMetadata> = self
+                    .catalog
+                    .iter()
+                    .flat_map(|catalog| catalog.0.iter())
+                    .filter(|meta| self.selected.contains(&ArticleId(meta.id.clone())))
+                    .filter(|meta| {
+                        matches!(
+                            self.download_progresses.get(&ArticleId(meta.id.clone())),
+                            Some(DownloadProgress::Failed) | None
+                        )
+                    })
+                    .cloned()
+                    .collect();
+                for meta in metas {
+                    self.fetch_article(ctx, ArticleId(meta.id), meta.title, meta.group);
+                }
+                self.selected.clear();
+            }
+
+            LibraryMsg::DeleteSelected => {
+                let ids: Vec<ArticleId> = self.selected.iter().cloned().collect();
+                let plural = if ids.len() == 1 { "" } else { "s" };
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message(&format!(
+                        "Delete {} article{plural} from the library? This can't be undone.",
+                        ids.len()
+                    ))
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+
+                ctx.link().send_future(async move {
+                    match delete_articles(&ids).await {
+                        Ok(()) => LibraryMsg::ArticlesDeleted(ids),
+                        Err(e) => LibraryMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            LibraryMsg::AddSelectedToPlaylist(playlist) => {
+                // Add them in the order the library lists them
+                let entries: Vec<QueueEntry> = self
+                    .catalog
+                    .iter()
+                    .flat_map(|catalog| catalog.0.iter())
+                    .filter(|meta| self.selected.contains(&ArticleId(meta.id.clone())))
+                    .map(|meta| {
+                        self.queue_entry(
+                            ArticleId(meta.id.clone()),
+                            meta.title.clone(),
+                            meta.group.clone(),
+                        )
+                    })
+                    .collect();
+                if let Some(playlists_link) = ctx.props().playlists_link.borrow().as_ref() {
+                    playlists_link.send_message(PlaylistsMsg::AddEntries { playlist, entries });
+                }
+                self.selected.clear();
+            }
+
+            LibraryMsg::SetPlaylists(playlists) => {
+                self.playlists = playlists;
+            }
+
+            LibraryMsg::PlayArticle(entry) => {
+                self.play_article(ctx, entry);
+            }
+
+            LibraryMsg::QueueAt { ids, placement } => {
+                self.menu_open = None;
+                for (i, id) in ids.into_iter().enumerate() {
+                    let placement = match placement {
+                        Placement::Now if i > 0 => Placement::Next,
+                        placement => placement,
+                    };
+                    self.queue_at(ctx, id, placement);
+                }
+            }
+
+            LibraryMsg::Unqueue(ids) => {
+                self.menu_open = None;
+                ctx.props()
+                    .queue_link
+                    .borrow()
+                    .clone()
+                    .unwrap()
+                    .send_message(QueueMsg::RemoveArticles(ids));
+            }
+
+            LibraryMsg::ToggleMenu(id) => {
+                self.menu_open = match self.menu_open.take() {
+                    Some(open) if open == id => None,
+                    _ => Some(id),
+                };
+            }
+
+            LibraryMsg::ImportArchive => {
+                let Some(file) = utils::picked_file(ARCHIVE_INPUT_ID) else {
+                    return false;
+                };
+                self.archive_status = Some("Importing…".to_string());
+                ctx.link().send_future(async move {
+                    match import_archive(file).await {
+                        Ok(summary) => LibraryMsg::ArchiveImported(summary),
+                        Err(e) => LibraryMsg::SetArchiveStatus(format!("{e}")),
+                    }
+                });
+            }
+
+            LibraryMsg::ArchiveImported(LibraryImportSummary { imported, skipped }) => {
+                let status = format!(
+                    "Imported {imported} articles. Skipped {skipped} that were already here."
+                );
+                self.archive_status = Some(status);
+                ctx.link().send_message(LibraryMsg::FetchCatalog);
+            }
+
+            LibraryMsg::SetArchiveStatus(status) => {
+                self.archive_status = Some(status);
+            }
+
+            LibraryMsg::ImportBundle => {
+                let Some(file) = utils::picked_file(BUNDLE_INPUT_ID) else {
+                    return false;
+                };
+                self.archive_status = Some("Importing…".to_string());
+                ctx.link().send_future(async move {
+                    match import_bundle(file).await {
+                        Ok(meta) => LibraryMsg::BundleImported(meta),
+                        Err(e) => LibraryMsg::SetArchiveStatus(format!("{e}")),
+                    }
+                });
+            }
+
+            LibraryMsg::BundleImported(meta) => {
+                self.archive_status = Some(format!("Imported {}.", meta.title));
+                ctx.link().send_message(LibraryMsg::FetchCatalog);
+            }
+
+            LibraryMsg::ArticlesDeleted(ids) => {
+                for id in &ids {
+                    self.selected.remove(id);
+                }
+                ctx.props()
+                    .queue_link
+                    .borrow()
+                    .clone()
+                    .unwrap()
+                    .send_message(QueueMsg::DeleteArticles(ids));
+                ctx.link().send_message(LibraryMsg::FetchCatalog);
+            }
+
+            LibraryMsg::SetSmartPlaylists(playlists) => {
+                self.smart_playlists = playlists;
+            }
+
+            LibraryMsg::SetListened(listened) => {
+                self.listened = listened;
+            }
+
+            LibraryMsg::SetListenedPcts(pcts) => {
+                self.listened_pcts = pcts;
+            }
+
+            LibraryMsg::SetSearch(search) => {
+                self.search = search;
+                self.page = 0;
+            }
+
+            LibraryMsg::SetPage(page) => {
+                self.page = page;
+            }
+
+            LibraryMsg::SetGroupExpanded { id, expanded } => {
+                if expanded {
+                    self.expanded_groups.insert(id);
+                } else {
+                    self.expanded_groups.remove(&id);
+                }
+            }
+
+            LibraryMsg::SetDayCollapsed { day, collapsed } => {
+                if collapsed {
+                    self.collapsed_days.insert(day);
+                } else {
+                    self.collapsed_days.remove(&day);
+                }
+            }
+
+            LibraryMsg::SaveSearch => {
+                let name = gloo_utils::window()
+                    .prompt_with_message("Name this smart playlist")
+                    .ok()
+                    .flatten()
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty());
+                let name = match name {
+                    Some(n) => n,
+                    None => return false,
+                };
+
+                // Saving under an existing name replaces that playlist
+                if self.smart_playlists.iter().any(|p| p.name == name) {
+                    let confirmed = gloo_utils::window()
+                        .confirm_with_message(&format!("Replace the smart playlist \"{name}\"?"))
+                        .unwrap_or(false);
+                    if !confirmed {
+                        return false;
+                    }
+                }
+
+                let playlist = SmartPlaylist {
+                    name,
+                    query: self.search.clone(),
+                    auto_advance: false,
+                };
+                ctx.link().send_future(async move {
+                    if let Err(e) = caching::save_smart_playlist(&playlist).await {
+                        return LibraryMsg::SetError(e.context("Couldn't save smart playlist"));
+                    }
+                    load_smart_playlists().await
+                });
+
+                return false;
+            }
+
+            LibraryMsg::DeleteSmartPlaylist(name) => {
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message(&format!("Delete the smart playlist \"{name}\"?"))
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+
+                // The playlist's page won't exist anymore, so go back to the list of playlists
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Playlists);
+                }
+                ctx.link().send_future(async move {
+                    if let Err(e) = caching::delete_smart_playlist(&name).await {
+                        return LibraryMsg::SetError(e.context("Couldn't delete smart playlist"));
+                    }
+                    load_smart_playlists().await
+                });
+
+                return false;
+            }
+
+            LibraryMsg::ToggleAutoAdvance(name) => {
+                // Only one playlist can be the auto-advance source, so turning one on turns the
+                // rest off. Save every playlist whose setting changed.
+                let changed: Vec<SmartPlaylist> = self
+                    .smart_playlists
+                    .iter()
+                    .filter_map(|p| {
+                        let auto_advance = p.name == name && !p.auto_advance;
+                        (auto_advance != p.auto_advance).then(|| SmartPlaylist {
+                            auto_advance,
+                            ..p.clone()
+                        })
+                    })
+                    .collect();
+
+                ctx.link().send_future(async move {
+                    for playlist in &changed {
+                        if let Err(e) = caching::save_smart_playlist(playlist).await {
+                            return LibraryMsg::SetError(e.context("Couldn't save smart playlist"));
+                        }
+                    }
+                    load_smart_playlists().await
+                });
+
+                return false;
+            }
+
+            LibraryMsg::AutoAdvance(after) => {
+                if self.smart_playlists.iter().any(|p| p.auto_advance) {
+                    // The article that just finished was marked listened when it started, so
+                    // reload the history before picking what's next
+                    ctx.link().send_future(async move {
+                        let listened = load_listened().await;
+                        LibraryMsg::PlayNextMatch { after, listened }
+                    });
+                }
+
+                return false;
+            }
+
+            LibraryMsg::PlayNextMatch { after, listened } => {
+                self.listened = listened;
+
+                let playlist = self.smart_playlists.iter().find(|p| p.auto_advance);
+                let query = match playlist.map(|p| p.query.parse::<Query>()) {
+                    Some(Ok(q)) => q,
+                    Some(Err(e)) => {
+                        tracing::error!("Auto-advance playlist has an invalid search: {}", e);
+                        return true;
+                    }
+                    None => return true,
+                };
+
+                // Go on to the matches after the one that finished. If that one isn't a match
+                // (anymore), start from the top. Of those, play the first one from the sources
+                // the listener rates highest.
+                let matches = self.matching(&query);
+                let pos = matches.iter().position(|meta| meta.id == after.0);
+                let candidates = match pos {
+                    Some(i) => &matches[i + 1..],
+                    None => &matches[..],
+                };
+                let scores =
+                    smart_playlist::source_scores(self.catalog.iter().flat_map(|c| c.0.iter()));
+                let next = match smart_playlist::pick_next(candidates, &scores) {
+                    Some(meta) => meta.clone(),
+                    None => return true,
+                };
+
+                let entry = self.queue_entry(ArticleId(next.id), next.title, next.group);
+                self.play_article(ctx, entry);
+            }
+        }
+
+        // Every one of the above messages causes a visible change in the library
+        true
+    }
+
+    fn create(ctx: &Context<Self>) -> Self {
+        // Set the queue link to this Library
+        ctx.props()
+            .library_link
+            .borrow_mut()
+            .replace(ctx.link().clone());
+
+        // Show the library as it was last saved right away, and bring it up to date in the
+        // background
+        ctx.link().send_future_batch(async move {
+            let mut msgs = Vec::new();
+            match caching::load_catalog().await {
+                Ok(saved) if !saved.0.is_empty() => msgs.push(LibraryMsg::SetCatalog(saved)),
+                Ok(_) => {}
+                Err(e) => tracing::error!("Couldn't load the saved catalog: {:#}", e),
+            }
+            msgs.push(LibraryMsg::FetchCatalog);
+            msgs
+        });
+
+        // Save the pageshow callback and set it on document.window. This is so that when you hit
+        // the back button from adding an article, it will try to reload the catalog.
+        let lib_link = ctx.link().clone();
+        let pageshow_cb = Closure::new(move |evt: PageTransitionEvent| {
+            // If the page is loading from a cache, force it to reload the library
+            if evt.persisted() {
+                lib_link.send_message(LibraryMsg::FetchCatalog)
+            }
+        });
+        gloo_utils::window()
+            .add_event_listener_with_callback("pageshow", pageshow_cb.as_ref().unchecked_ref())
+            .expect("couldn't register pageshow callback");
+
+        // Load the smart playlists and the listening history they're evaluated against
+        ctx.link().send_future(load_smart_playlists());
+        ctx.link()
+            .send_future(async move { LibraryMsg::SetListened(load_listened().await) });
+
+        // Pick back up the downloads that were going when the page was last closed, and download
+        // the pinned articles again if their audio was wiped, unless the battery is low, or it's
+        // quiet hours. They can still be downloaded by hand.
+        ctx.link().send_future(async move {
+            let unfinished =
+                if battery::should_save().await || reminders_view::in_quiet_hours().await {
+                    Vec::new()
+                } else {
+                    let mut entries = downloads::unfinished().await;
+                    for entry in caching::missing_pinned().await {
+                        if !entries.iter().any(|e| e.id == entry.id) {
+                            entries.push(entry);
+                        }
+                    }
+                    entries
+                };
+            LibraryMsg::ResumeDownloads(unfinished)
+        });
+
+        let mut library = Library {
+            _pageshow_action: Some(pageshow_cb),
+            ..Default::default()
+        };
+        library.load_view_state(ctx);
+        library
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        // Browsing somewhere else shows a different list. Remember where this one was left, and
+        // pick the other back up where it was left, or from the top if it's new.
+        if self.browse != ctx.props().browse {
+            self.save_view_state(ctx);
+            self.load_view_state(ctx);
+        }
+
+        // Browsing somewhere else might show smart playlists, so refresh the listening history
+        ctx.link()
+            .send_future(async move { LibraryMsg::SetListened(load_listened().await) });
+
+        true
+    }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         // If there's an error, render it
@@ -433,34 +1568,92 @@ impl Component for Library {
                 </p>
             }
         } else if let Some(catalog) = &self.catalog {
-            // If there's a list, render all the items
-            let rendered_list = catalog
-                .0
-                .iter()
-                .map(|metadata| {
-                    let meta = metadata.clone();
-                    let link = ctx.link().clone();
-                    let download_progress = self
-                        .download_progresses
-                        .get(&ArticleId(meta.id.clone()))
-                        .cloned();
-
-                    render_lib_item(meta, link, download_progress)
-                })
-                .collect::<Html>();
+            let articles = catalog.0.iter();
+            let (heading, contents) = match &ctx.props().browse {
+                Browse::All => ("Library".to_string(), self.render_search(ctx)),
+                Browse::Author(name) => {
+                    let articles = articles.filter(|meta| meta.author.as_ref() == Some(name));
+                    (
+                        format!("Articles by {name}"),
+                        self.render_articles(ctx, articles),
+                    )
+                }
+                Browse::Publication(name) => {
+                    let articles: Vec<&ArticleMetadata> = articles
+                        .filter(|meta| meta.publication.as_ref() == Some(name))
+                        .collect();
+
+                    // Point to the publication's site, so more articles can be found there
+                    let site = articles
+                        .iter()
+                        .filter_map(|meta| meta.source_url.as_ref())
+                        .find_map(|u| Url::parse(u).ok())
+                        .map(|u| u.origin().ascii_serialization());
+                    let find_more = match site {
+                        Some(site) => html! {
+                            <p>
+                                <a href={ site.clone() }>{ format!("Find more at {site}") }</a>
+                                { " and add them by URL" }
+                            </p>
+                        },
+                        None => Html::default(),
+                    };
+
+                    let contents = html! {
+                        <>
+                            { find_more }
+                            { self.render_articles(ctx, articles.into_iter()) }
+                        </>
+                    };
+                    (format!("Articles from {name}"), contents)
+                }
+                Browse::Authors => {
+                    let names = articles.filter_map(|meta| meta.author.as_deref());
+                    let index = render_index(names, "Authors", |name| Route::Author { name });
+                    ("Authors".to_string(), index)
+                }
+                Browse::Publications => {
+                    let names = articles.filter_map(|meta| meta.publication.as_deref());
+                    let index =
+                        render_index(names, "Publications", |name| Route::Publication { name });
+                    ("Publications".to_string(), index)
+                }
+                Browse::Playlists => ("Smart playlists".to_string(), self.render_smart_playlists()),
+                Browse::Playlist(name) => (
+                    format!("Smart playlist: {name}"),
+                    self.render_smart_playlist(ctx, name),
+                ),
+            };
+
+            // Say quietly whether the library is being brought up to date, and what that did
+            let refresh_status = match (self.refreshing, &self.refresh_note) {
+                (true, _) => html! { <p class="refreshStatus">{ "Checking for changes…" }</p> },
+                (false, Some(note)) => html! { <p class="refreshStatus">{ note }</p> },
+                (false, None) => Html::default(),
+            };
+
             html! {
                 <section title="Library">
                     <div id="libraryHeader">
-                        <h2>{ "Library" }</h2>
+                        <h2>{ heading }</h2>
                         <span id="addArticle">
                             <Link<Route> to={Route::Add}>
                                 { "Add Article" }
                             </Link<Route>>
                         </span>
                     </div>
-                    <table role="list" aria-label="Library catalog">
-                        { rendered_list }
-                    </table>
+                    <nav class="browseLibrary" aria-label="Browse the library">
+                        <Link<Route> to={Route::Home}>{ "All articles" }</Link<Route>>
+                        { " · " }
+                        <Link<Route> to={Route::Authors}>{ "Authors" }</Link<Route>>
+                        { " · " }
+                        <Link<Route> to={Route::Publications}>{ "Publications" }</Link<Route>>
+                        { " · " }
+                        <Link<Route> to={Route::Playlists}>{ "Smart playlists" }</Link<Route>>
+                    </nav>
+                    <div role="status">{ refresh_status }</div>
+                    { contents }
+                    { self.render_backup(ctx) }
                     <p
                         id="libErrors"
                         role="alert"
@@ -473,4 +1666,954 @@ impl Component for Library {
             Default::default()
         }
     }
+
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        // The list has to be there to scroll down it, so wait for the catalog
+        if self.catalog.is_some() {
+            if let Some(y) = self.restore_scroll.take() {
+                gloo_utils::window().scroll_to_with_x_and_y(0.0, y);
+            }
+        }
+
+        // Probe the sizes of the articles that were just shown. This goes one at a time, so a page
+        // of probes doesn't hold up anything else.
+        let wanted = self.audio_sizes.take_wanted();
+        if !wanted.is_empty() {
+            let link = ctx.link().clone();
+            spawn_local(async move {
+                for id in wanted {
+                    match downloads::probe_size(&id).await {
+                        Ok(bytes) => link.send_message(LibraryMsg::SetAudioSize { id, bytes }),
+                        Err(e) => tracing::debug!("Couldn't probe the size: {:#}", e),
+                    }
+                }
+            });
+        }
+    }
+
+    fn destroy(&mut self, ctx: &Context<Self>) {
+        self.save_view_state(ctx);
+    }
+}
+
+impl Library {
+    /// Saves how the part of the library being shown was left, so it's restored when it's next
+    /// shown
+    fn save_view_state(&self, ctx: &Context<Self>) {
+        // If the scroll is still to be restored, the page hasn't moved from it yet
+        let scroll_y = self
+            .restore_scroll
+            .unwrap_or_else(|| gloo_utils::window().scroll_y().unwrap_or(0.0));
+        let state = ViewState {
+            search: self.search.clone(),
+            page: self.page,
+            expanded_groups: self.expanded_groups.clone(),
+            collapsed_days: self.collapsed_days.clone(),
+            scroll_y,
+        };
+        ctx.props()
+            .view_states
+            .0
+            .borrow_mut()
+            .insert(self.browse.clone(), state);
+    }
+
+    /// Shows the part of the library in the props the way it was left, or from the top if it
+    /// hasn't been shown before
+    fn load_view_state(&mut self, ctx: &Context<Self>) {
+        let browse = ctx.props().browse.clone();
+        let state = ctx
+            .props()
+            .view_states
+            .0
+            .borrow()
+            .get(&browse)
+            .cloned()
+            .unwrap_or_default();
+
+        self.search = state.search;
+        self.page = state.page;
+        self.expanded_groups = state.expanded_groups;
+        self.collapsed_days = state.collapsed_days;
+        self.restore_scroll = Some(state.scroll_y);
+        self.browse = browse;
+    }
+
+    /// Returns the articles in the catalog that match the given query, in catalog order
+    fn matching(&self, query: &Query) -> Vec<&ArticleMetadata> {
+        let queued: BTreeSet<ArticleId> = self
+            .download_progresses
+            .iter()
+            .filter(|(_, progress)| matches!(progress, DownloadProgress::Done))
+            .map(|(id, _)| id.clone())
+            .collect();
+        let state = MatchState {
+            listened: &self.listened,
+            queued: &queued,
+            now: (js_sys::Date::now() / 1000.0) as u64,
+        };
+
+        self.catalog
+            .iter()
+            .flat_map(|catalog| catalog.0.iter())
+            .filter(|meta| query.matches(meta, &state))
+            .collect()
+    }
+
+    /// Renders the search box, and the articles matching the search. If the search is empty, every
+    /// article is shown.
+    fn render_search(&self, ctx: &Context<Self>) -> Html {
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            LibraryMsg::SetSearch(input.value())
+        });
+        let save = ctx.link().callback(|_| LibraryMsg::SaveSearch);
+
+        let search = self.search.trim();
+        let (results, can_save) = if search.is_empty() {
+            let catalog = self.catalog.iter().flat_map(|catalog| catalog.0.iter());
+            (self.render_articles(ctx, catalog), false)
+        } else {
+            match search.parse::<Query>() {
+                Ok(query) => {
+                    let matches = self.matching(&query).into_iter();
+                    (self.render_articles(ctx, matches), true)
+                }
+                Err(e) => (
+                    html! {
+                        <p style="color: red;" role="alert">{ format!("Invalid search: {e}") }</p>
+                    },
+                    false,
+                ),
+            }
+        };
+
+        html! {
+            <>
+                <div class="librarySearch">
+                    <input
+                        type="search"
+                        aria-label="Search the library"
+                        placeholder="e.g., author:doe unlistened duration<20min"
+                        value={ self.search.clone() }
+                        {oninput}
+                    />
+                    <button onclick={save} disabled={ !can_save }>
+                        { "Save as smart playlist" }
+                    </button>
+                </div>
+                { results }
+            </>
+        }
+    }
+
+    /// Renders the links to export the library as an archive or a catalog, and the forms to import
+    /// an archive, or a single article's bundle. Catalogs are imported in the import view.
+    fn render_backup(&self, ctx: &Context<Self>) -> Html {
+        let catalog_links = CatalogFormat::ALL
+            .into_iter()
+            .map(|format| {
+                let url = format!("/api/export-catalog?format={}", format.as_str());
+                html! {
+                    <>
+                        { " " }
+                        <a href={ servers::api_url(&url) } download="">{ format.description() }</a>
+                    </>
+                }
+            })
+            .collect::<Html>();
+        let import = ctx.link().callback(|_| LibraryMsg::ImportArchive);
+        let import_bundle = ctx.link().callback(|_| LibraryMsg::ImportBundle);
+        let status = self.archive_status.clone().unwrap_or_default();
+
+        html! {
+            <details class="libraryBackup">
+                <summary>{ "Back up or move the library" }</summary>
+                <p>
+                    <a href={ servers::api_url("/api/export-library") } download="">{ "Export library" }</a>
+                    { " as an archive of every article's audio, transcript, and details" }
+                </p>
+                <p>
+                    <a href={ servers::api_url("/api/takeout") } download="">{ "Download all my data" }</a>
+                    { " as an archive of everything the server keeps, with progress, ratings, and \
+                      playlists too. It's for keeping, not importing." }
+                </p>
+                <p>
+                    { "Or just export the list of articles, with their playlists and progress, as" }
+                    { catalog_links }
+                    { ". Importing the list like another reader's export converts the missing articles again." }
+                </p>
+                <div class="field">
+                    <label for={ARCHIVE_INPUT_ID}>{ "Library archive:" }</label>
+                    <input type="file" id={ARCHIVE_INPUT_ID} accept=".tar,application/x-tar" />
+                </div>
+                <button onclick={import}>{ "Import archive" }</button>
+                <p>{ "Single articles can be downloaded as bundles with 📦, and imported here." }</p>
+                <div class="field">
+                    <label for={BUNDLE_INPUT_ID}>{ "Article bundle:" }</label>
+                    <input type="file" id={BUNDLE_INPUT_ID} accept=".rtms,application/zip" />
+                </div>
+                <button onclick={import_bundle}>{ "Import bundle" }</button>
+                <p role="status">{ status }</p>
+            </details>
+        }
+    }
+
+    /// Renders the list of smart playlists
+    fn render_smart_playlists(&self) -> Html {
+        if self.smart_playlists.is_empty() {
+            return html! {
+                <p style="font-style: italic">{"
+                    No smart playlists. Search the library from the \"All articles\" page, then
+                    save the search to make one.
+                "}</p>
+            };
+        }
+
+        let items = self
+            .smart_playlists
+            .iter()
+            .map(|playlist| {
+                let name = urlencoding::encode(&playlist.name).into_owned();
+                let auto_advance = if playlist.auto_advance {
+                    " (auto-advance)"
+                } else {
+                    ""
+                };
+                html! {
+                    <li>
+                        <Link<Route> to={Route::Playlist { name }}>
+                            { playlist.name.clone() }
+                        </Link<Route>>
+                        <span class="articleMetadata">
+                            { format!(" {}{auto_advance}", playlist.query) }
+                        </span>
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        html! { <ul aria-label="Smart playlists">{ items }</ul> }
+    }
+
+    /// Renders the smart playlist with the given name, its settings, and the articles matching it
+    fn render_smart_playlist(&self, ctx: &Context<Self>, name: &str) -> Html {
+        let playlist = match self.smart_playlists.iter().find(|p| p.name == name) {
+            Some(p) => p,
+            None => {
+                return html! {
+                    <p style="font-style: italic">{ format!("No smart playlist named {name}") }</p>
+                }
+            }
+        };
+
+        let toggle = {
+            let name = name.to_string();
+            ctx.link()
+                .callback(move |_| LibraryMsg::ToggleAutoAdvance(name.clone()))
+        };
+        let delete = {
+            let name = name.to_string();
+            ctx.link()
+                .callback(move |_| LibraryMsg::DeleteSmartPlaylist(name.clone()))
+        };
+
+        let results = match playlist.query.parse::<Query>() {
+            Ok(query) => self.render_articles(ctx, self.matching(&query).into_iter()),
+            Err(e) => html! {
+                <p style="color: red;" role="alert">{ format!("Invalid search: {e}") }</p>
+            },
+        };
+
+        html! {
+            <>
+                <p class="articleMetadata">{ format!("Search: {}", playlist.query) }</p>
+                <p>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={ playlist.auto_advance }
+                            onchange={toggle}
+                        />
+                        { " Play these when the queue runs out" }
+                    </label>
+                    { " " }
+                    <button onclick={delete}>{ "Delete playlist" }</button>
+                </p>
+                { results }
+            </>
+        }
+    }
+
+    /// Renders an item in the library
+    fn render_lib_item(&self, library_link: &Scope<Library>, metadata: ArticleMetadata) -> Html {
+        let id = ArticleId(metadata.id.clone());
+        let download_progress = self.download_progresses.get(&id).cloned();
+        let listened = self.listened.contains(&id);
+        let listened_pct = self.listened_pcts.get(&id).copied();
+        let update = self.updates.get(&id).copied();
+        let title = metadata.title.clone();
+        let id = ArticleId(metadata.id.clone());
+        let title_copy = title.clone();
+        let group = metadata.group.clone();
+
+        // Generate the ID for the button/progress indicator
+        let status_elem_id = libitem_status_elem_id(&id);
+
+        // Define the Add to Queue callback
+        let add_to_queue = library_link.callback_once(move |_| {
+            // Tell the library to fetch the article. This will change the button to a progress
+            // indicator
+            LibraryMsg::FetchArticle {
+                id: id.clone(),
+                title: title_copy.clone(),
+                group: group.clone(),
+            }
+        });
+
+        // Make a source URL link if it exists and is valid. Otherwise make this part empty.
+        let url = metadata
+            .source_url
+            .as_ref()
+            .and_then(|u| Url::parse(u).ok())
+            .map(
+                |u| html! { <a href={ String::from(u) } title="Article source">{ "[source]" }</a> },
+            )
+            .unwrap_or(Html::default());
+
+        // Link the author and publication to the pages that list everything else from them
+        let author = metadata
+            .author
+            .clone()
+            .map(|name| {
+                let to = Route::Author {
+                    name: urlencoding::encode(&name).into_owned(),
+                };
+                html! { <>{ "By " }<Link<Route> {to}>{ name }</Link<Route>></> }
+            })
+            .unwrap_or_default();
+        let publication = metadata
+            .publication
+            .clone()
+            .map(|name| {
+                let to = Route::Publication {
+                    name: urlencoding::encode(&name).into_owned(),
+                };
+                html! { <>{ "From " }<Link<Route> {to}>{ name }</Link<Route>></> }
+            })
+            .unwrap_or_default();
+
+        // Articles that came from a URL can be re-extracted from their archived page
+        let re_extract_button = if metadata.source_url.is_some() {
+            let id = ArticleId(metadata.id.clone());
+            let re_extract = library_link.callback(move |_| LibraryMsg::ReExtract(id.clone()));
+            let re_extract_title_text = format!("Re-extract: {}", title);
+            html! {
+                <button
+                    class="reExtract"
+                    onclick={ re_extract }
+                    aria-label={ re_extract_title_text.clone() }
+                    title={ re_extract_title_text }
+                >
+                    { "↻" }
+                </button>
+            }
+        } else {
+            Html::default()
+        };
+
+        // Any article can be set to come back later
+        let remind_later = {
+            let id = ArticleId(metadata.id.clone());
+            let title = title.clone();
+            library_link.callback(move |_| LibraryMsg::RemindLater {
+                id: id.clone(),
+                title: title.clone(),
+            })
+        };
+        let remind_title_text = format!("Remind me later: {}", title);
+        let share = {
+            let id = ArticleId(metadata.id.clone());
+            let title = title.clone();
+            library_link.callback(move |_| LibraryMsg::Share {
+                id: id.clone(),
+                title: title.clone(),
+            })
+        };
+        let share_title_text = format!("Share a link: {}", title);
+
+        // Finished articles can be downloaded as MP3s, tagged for music players, as bundles for another
+        // server, or as their text
+        let download_button = if metadata.incomplete {
+            Html::default()
+        } else {
+            let id = urlencoding::encode(&metadata.id);
+            let href = servers::api_url(&format!("/api/export-article/{id}"));
+            let download_title_text = format!("Download MP3: {}", title);
+            let bundle_href = servers::api_url(&format!("/api/export-bundle/{id}"));
+            let bundle_title_text = format!("Download bundle: {}", title);
+            let text_href = servers::api_url(&format!("/api/export-text/{id}?format=markdown"));
+            let text_title_text = format!("Download text: {}", title);
+            html! {
+                <>
+                    <a
+                        class="downloadMp3"
+                        {href}
+                        download=""
+                        aria-label={ download_title_text.clone() }
+                        title={ download_title_text }
+                    >
+                        { "⬇" }
+                    </a>
+                    <a
+                        class="downloadBundle"
+                        href={bundle_href}
+                        download=""
+                        aria-label={ bundle_title_text.clone() }
+                        title={ bundle_title_text }
+                    >
+                        { "📦" }
+                    </a>
+                    <a
+                        class="downloadText"
+                        href={text_href}
+                        download=""
+                        aria-label={ text_title_text.clone() }
+                        title={ text_title_text }
+                    >
+                        { "📝" }
+                    </a>
+                </>
+            }
+        };
+        let rating_buttons = {
+            let id = ArticleId(metadata.id.clone());
+            let rate = library_link.callback(move |rating| LibraryMsg::Rate {
+                id: id.clone(),
+                rating,
+            });
+            render_rating_buttons(&title, metadata.rating, rate)
+        };
+        let remind_later_button = html! {
+            <button
+                class="remindLater"
+                onclick={ remind_later }
+                aria-label={ remind_title_text.clone() }
+                title={ remind_title_text }
+            >
+                { "⏰" }
+            </button>
+        };
+        let share_button = html! {
+            <button
+                class="share"
+                onclick={ share }
+                aria-label={ share_title_text.clone() }
+                title={ share_title_text }
+            >
+                { "🔗" }
+            </button>
+        };
+
+        // Articles whose conversion failed partway through can be finished
+        let incomplete_notice = if metadata.incomplete {
+            let id = ArticleId(metadata.id.clone());
+            let finish = library_link.callback(move |_| LibraryMsg::FinishConversion(id.clone()));
+            let finish_title_text = format!("Finish conversion: {}", title);
+            html! {
+                <>
+                    { "Incomplete " }
+                    <button
+                        class="finishConversion"
+                        onclick={ finish }
+                        aria-label={ finish_title_text.clone() }
+                        title={ finish_title_text }
+                    >
+                        { "Finish conversion" }
+                    </button>
+                </>
+            }
+        } else {
+            Html::default()
+        };
+
+        // Articles that a feed subscription added are marked new until they're listened to
+        let feed_notice = match &metadata.feed {
+            Some(feed) if !listened => html! { <strong>{ format!("New from {feed}") }</strong> },
+            Some(feed) => html! { <>{ format!("From feed {feed}") }</> },
+            None => Html::default(),
+        };
+
+        // Say where the text came from, unless it's a web page, which the source link already says
+        let source_notice = match metadata.source_type {
+            Some(SourceType::Web) | None => Html::default(),
+            Some(source_type) => html! { <>{ source_type.description() }</> },
+        };
+
+        // Format the date the article was added
+        let lang = gloo_utils::window()
+            .navigator()
+            .language()
+            .unwrap_or("en-US".to_string());
+        let date_added: Option<String> = metadata.datetime_added.map(|t| {
+            // Convert to a local date string  by making a Date object and giving it the unix time
+            let js_date = js_sys::Date::new_0();
+            // set_time takes number of milliseconds since epoch, so multiply by 1000
+            js_date.set_time((t as f64) * 1000.0);
+            js_date.to_locale_string(&lang, &JsValue::TRUE).into()
+        });
+        let date_added_str = match date_added {
+            Some(d) => format!("Added {d}"),
+            None => format!("Date added unknown"),
+        };
+        let duration_str = metadata
+            .duration_secs
+            .map(format_duration)
+            .unwrap_or_default();
+        // Say how much downloading the article would take. Downloaded ones are already here.
+        let size_str = if download_progress.is_none() {
+            self.audio_sizes
+                .get(&ArticleId(metadata.id.clone()))
+                .map(storage_view::format_megabytes)
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        // Point out what the last refresh brought in
+        let update_badge = match update {
+            Some(Update::New) => html! { <span class="updateBadge">{ "New" }</span> },
+            Some(Update::Changed) => html! { <span class="updateBadge">{ "Updated" }</span> },
+            None => Html::default(),
+        };
+
+        // If the article is downloading, display download progress instead of the "Add to Queue"
+        // button
+        let saved = matches!(download_progress, Some(DownloadProgress::Done));
+        let add_to_queue_button =
+            render_status(status_elem_id, &title, download_progress, add_to_queue);
+        let queue_menu = self.render_queue_menu(
+            library_link,
+            &metadata.id,
+            &title,
+            vec![ArticleId(metadata.id.clone())],
+        );
+        let select = render_select(
+            vec![ArticleId(metadata.id.clone())],
+            &title,
+            &self.selected,
+            library_link,
+        );
+
+        html! {
+            <tr role="listitem" aria-label={ title.clone() }>
+                <td class="selectArticle">{ select }</td>
+                <td class="addToQueue">{ add_to_queue_button }{ queue_menu }</td>
+                <td class = "articleDetails">
+                    <p class="libArticleTitle" dir="auto">{ title }{ update_badge }</p>
+                    <span class="articleMetadata">{ date_added_str }</span>
+                    <span class="articleMetadata">{ duration_str }</span>
+                    <span class="articleMetadata">{ size_str }</span>
+                    <span class="articleMetadata">{ author }</span>
+                    <span class="articleMetadata">{ publication }</span>
+                    <span class="articleMetadata">{ url }{ source_notice }</span>
+                    <span class="articleMetadata">{ re_extract_button }</span>
+                    <span class="articleMetadata">{ remind_later_button }{ share_button }{ download_button }</span>
+                    <span class="articleMetadata">{ rating_buttons }</span>
+                    <span class="articleMetadata">{ incomplete_notice }</span>
+                    <span class="articleMetadata">{ feed_notice }</span>
+                    <span class="articleMetadata">
+                        { history_view::render_progress_ring(listened_pct, saved) }
+                    </span>
+                </td>
+            </tr>
+        }
+    }
+
+    /// Renders the menu of where to put the given articles in the queue, for the row with the given
+    /// ID and title. If any of them are queued, the menu can take them out of the queue too.
+    fn render_queue_menu(
+        &self,
+        library_link: &Scope<Library>,
+        row_id: &str,
+        title: &str,
+        ids: Vec<ArticleId>,
+    ) -> Html {
+        let menu_open = self.menu_open.as_deref() == Some(row_id);
+        let row_id = row_id.to_string();
+        let toggle_callback =
+            library_link.callback(move |_| LibraryMsg::ToggleMenu(row_id.clone()));
+        let place_callback = |placement| {
+            let ids = ids.clone();
+            library_link.callback(move |_| LibraryMsg::QueueAt {
+                ids: ids.clone(),
+                placement,
+            })
+        };
+        let queued = ids.iter().any(|id| {
+            matches!(
+                self.download_progresses.get(id),
+                Some(DownloadProgress::Done)
+            )
+        });
+        let remove_callback = queued.then(|| {
+            let ids = ids.clone();
+            library_link.callback(move |_| LibraryMsg::Unqueue(ids.clone()))
+        });
+        queue_view::render_placement_menu(
+            title,
+            menu_open,
+            toggle_callback,
+            place_callback,
+            remove_callback,
+        )
+    }
+
+    /// Renders a group of articles as a single library item. The parts are listed when the item is
+    /// expanded, and the whole group can be added to the queue at once.
+    fn render_lib_group(
+        &self,
+        library_link: &Scope<Library>,
+        group: &ArticleGroup,
+        parts: Vec<ArticleMetadata>,
+    ) -> Html {
+        let expanded = self.expanded_groups.contains(&group.id);
+        let title = group.title.clone();
+
+        // The group is downloaded when all its parts are, and failed if any part failed and none are
+        // still going. Otherwise, it's as far along as its parts are on average.
+        let part_progresses: Vec<Option<DownloadProgress>> = parts
+            .iter()
+            .map(|meta| {
+                self.download_progresses
+                    .get(&ArticleId(meta.id.clone()))
+                    .cloned()
+            })
+            .collect();
+        let download_progress = if part_progresses
+            .iter()
+            .all(|p| matches!(p, Some(DownloadProgress::Done)))
+        {
+            Some(DownloadProgress::Done)
+        } else if part_progresses
+            .iter()
+            .any(|p| matches!(p, Some(DownloadProgress::Failed)))
+            && !part_progresses
+                .iter()
+                .any(|p| matches!(p, Some(DownloadProgress::InProgress(_))))
+        {
+            Some(DownloadProgress::Failed)
+        } else if part_progresses.iter().any(Option::is_some) {
+            let total: f64 = part_progresses
+                .iter()
+                .map(|p| match p {
+                    Some(DownloadProgress::InProgress(fraction)) => *fraction,
+                    Some(DownloadProgress::Done) => 1.0,
+                    Some(DownloadProgress::Failed) | None => 0.0,
+                })
+                .sum();
+            Some(DownloadProgress::InProgress(total / parts.len() as f64))
+        } else {
+            None
+        };
+
+        let group_id = group.id.clone();
+        let add_to_queue = library_link.callback(move |_| LibraryMsg::FetchGroup(group_id.clone()));
+
+        // Remember whether the parts are listed, so they still are after navigating away and back
+        let group_id = group.id.clone();
+        let ontoggle = library_link.callback(move |e: Event| {
+            let details: web_sys::HtmlDetailsElement = e.target_unchecked_into();
+            LibraryMsg::SetGroupExpanded {
+                id: group_id.clone(),
+                expanded: details.open(),
+            }
+        });
+        let add_to_queue_button = render_status(
+            libgroup_status_elem_id(&group.id),
+            &title,
+            download_progress,
+            add_to_queue,
+        );
+
+        // Selecting the group selects all its parts, and the group is queued in part order
+        let part_ids: Vec<ArticleId> = parts
+            .iter()
+            .map(|meta| ArticleId(meta.id.clone()))
+            .collect();
+        let queue_menu = self.render_queue_menu(library_link, &group.id, &title, part_ids.clone());
+        let select = render_select(part_ids, &title, &self.selected, library_link);
+
+        // Missing parts are still missing if the rest of the group is expanded, so say so
+        let parts_str = if parts.len() == group.parts as usize {
+            format!("{} parts", group.parts)
+        } else {
+            format!("{} of {} parts", parts.len(), group.parts)
+        };
+
+        let part_pcts: Vec<Option<u32>> = parts
+            .iter()
+            .map(|meta| self.listened_pcts.get(&ArticleId(meta.id.clone())).copied())
+            .collect();
+        let listened_pct = history_view::group_listened_pct(&part_pcts);
+        let saved = matches!(download_progress, Some(DownloadProgress::Done));
+
+        let rendered_parts = parts
+            .into_iter()
+            .map(|meta| self.render_lib_item(library_link, meta))
+            .collect::<Html>();
+
+        html! {
+            <tr role="listitem" aria-label={ title.clone() }>
+                <td class="selectArticle">{ select }</td>
+                <td class="addToQueue">{ add_to_queue_button }{ queue_menu }</td>
+                <td class = "articleDetails">
+                    <p class="libArticleTitle" dir="auto">{ title.clone() }</p>
+                    <span class="articleMetadata">
+                        { history_view::render_progress_ring(listened_pct, saved) }
+                    </span>
+                    <details open={expanded} {ontoggle}>
+                        <summary class="articleMetadata">{ parts_str }</summary>
+                        <table role="list" aria-label={ format!("Parts of {title}") }>
+                            { rendered_parts }
+                        </table>
+                    </details>
+                </td>
+            </tr>
+        }
+    }
+
+    /// Renders the current page of the given articles. The parts of a group are rendered together,
+    /// where the group's first part appears in the list.
+    fn render_articles<'a>(
+        &self,
+        ctx: &Context<Self>,
+        articles: impl Iterator<Item = &'a ArticleMetadata>,
+    ) -> Html {
+        let articles: Vec<&ArticleMetadata> = articles.collect();
+        if articles.is_empty() {
+            return html! { <p style="font-style: italic">{ "No articles" }</p> };
+        }
+
+        // Every row is an article, or the first part of a group. Only the rows on this page are
+        // rendered.
+        let mut seen_groups = BTreeSet::new();
+        let rows: Vec<&ArticleMetadata> = articles
+            .iter()
+            .filter(|meta| match &meta.group {
+                Some(group) => seen_groups.insert(group.id.clone()),
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        // The articles are newest first, so each day's are together under its heading. The
+        // articles of collapsed days aren't listed.
+        let day_starts = DayStarts::at(js_sys::Date::now());
+        let mut lib_rows: Vec<LibRow> = Vec::new();
+        let mut day_start = 0;
+        for meta in rows {
+            let day = day_starts.section(meta.datetime_added);
+            if !matches!(lib_rows.get(day_start), Some(LibRow::Day(d, _)) if *d == day) {
+                day_start = lib_rows.len();
+                lib_rows.push(LibRow::Day(day, 0));
+            }
+            if let Some(LibRow::Day(_, count)) = lib_rows.get_mut(day_start) {
+                *count += 1;
+            }
+            if !self.collapsed_days.contains(&day) {
+                lib_rows.push(LibRow::Article(meta));
+            }
+        }
+        let num_pages = lib_rows.len().div_ceil(PAGE_SIZE);
+        let page = self.page.min(num_pages - 1);
+
+        let rendered_list = lib_rows
+            .iter()
+            .skip(page * PAGE_SIZE)
+            .take(PAGE_SIZE)
+            .map(|row| {
+                let metadata = match row {
+                    LibRow::Day(day, count) => {
+                        let collapsed = self.collapsed_days.contains(day);
+                        return render_day_heading(ctx.link(), *day, *count, collapsed);
+                    }
+                    LibRow::Article(metadata) => metadata,
+                };
+                let meta = (*metadata).clone();
+
+                if let Some(group) = &meta.group {
+                    let mut parts: Vec<ArticleMetadata> = articles
+                        .iter()
+                        .filter(|m| matches!(&m.group, Some(g) if g.id == group.id))
+                        .map(|m| (*m).clone())
+                        .collect();
+                    parts.sort_by_key(|m| m.group.as_ref().map(|g| g.part));
+                    return self.render_lib_group(ctx.link(), group, parts);
+                }
+
+                self.render_lib_item(ctx.link(), meta)
+            })
+            .collect::<Html>();
+
+        html! {
+            <>
+                { self.render_bulk_actions(ctx) }
+                <table role="list" aria-label="Library catalog">
+                    { rendered_list }
+                </table>
+                { render_page_nav(ctx.link(), page, num_pages) }
+            </>
+        }
+    }
+
+    /// Renders the actions that apply to all the selected articles, if any are selected
+    fn render_bulk_actions(&self, ctx: &Context<Self>) -> Html {
+        if self.selected.is_empty() {
+            return Html::default();
+        }
+
+        let queue = ctx.link().callback(|_| LibraryMsg::QueueSelected);
+        let delete = ctx.link().callback(|_| LibraryMsg::DeleteSelected);
+        let clear = ctx.link().callback(|_| LibraryMsg::ClearSelection);
+
+        // Picking a playlist adds the selection to it
+        let add_to_playlist = if self.playlists.is_empty() {
+            Html::default()
+        } else {
+            let onchange = ctx.link().batch_callback(|e: Event| {
+                let select: HtmlSelectElement = e.target_unchecked_into();
+                let id = select.value();
+                select.set_value("");
+                (!id.is_empty()).then(|| LibraryMsg::AddSelectedToPlaylist(id))
+            });
+            let options = self
+                .playlists
+                .iter()
+                .map(|(id, name)| html! { <option value={ id.clone() }>{ name }</option> })
+                .collect::<Html>();
+            html! {
+                <select {onchange} aria-label="Add to playlist">
+                    <option value="" selected=true>{ "Add to playlist…" }</option>
+                    { options }
+                </select>
+            }
+        };
+
+        html! {
+            <div class="bulkActions" role="toolbar" aria-label="Selected articles">
+                { format!("{} selected: ", self.selected.len()) }
+                <button onclick={queue}>{ "Add to queue" }</button>
+                { add_to_playlist }
+                <button onclick={delete}>{ "Delete" }</button>
+                <button onclick={clear}>{ "Clear selection" }</button>
+            </div>
+        }
+    }
+
+    /// Returns the queue entry for the given article. The group, byline, lead image, and reading
+    /// profile aren't cached with the article, so the queue has to be told about them.
+    fn queue_entry(&self, id: ArticleId, title: String, group: Option<ArticleGroup>) -> QueueEntry {
+        let meta = self
+            .catalog
+            .as_ref()
+            .and_then(|catalog| catalog.0.iter().find(|meta| meta.id == id.0));
+        QueueEntry {
+            id,
+            title,
+            group,
+            publication: meta.and_then(|meta| meta.publication.clone()),
+            author: meta.and_then(|meta| meta.author.clone()),
+            image: meta.and_then(|meta| meta.image.clone()),
+            reading_profile: meta.and_then(|meta| meta.reading_profile.clone()),
+        }
+    }
+
+    /// Fetches an article, saves it, and relays it to the queue. If there's an error, the article is
+    /// marked as failed.
+    fn fetch_article(
+        &mut self,
+        ctx: &Context<Self>,
+        id: ArticleId,
+        title: String,
+        group: Option<ArticleGroup>,
+    ) {
+        let entry = self.queue_entry(id, title, group);
+        self.download(ctx, entry);
+    }
+
+    /// Plays the article in the given entry if it's queued. Otherwise, it's played once it's
+    /// downloaded, downloading it if it isn't already.
+    fn play_article(&mut self, ctx: &Context<Self>, entry: QueueEntry) {
+        match self.download_progresses.get(&entry.id) {
+            // It's already queued. Play it.
+            Some(DownloadProgress::Done) => ctx
+                .props()
+                .queue_link
+                .borrow()
+                .clone()
+                .unwrap()
+                .send_message(QueueMsg::PlayEntry(entry.id)),
+            // It's downloading. Play it when it's done.
+            Some(DownloadProgress::InProgress(_)) => self.play_when_queued = Some(entry.id),
+            // Download it, then play it
+            Some(DownloadProgress::Failed) | None => {
+                self.play_when_queued = Some(entry.id.clone());
+                self.download(ctx, entry);
+            }
+        }
+    }
+
+    /// Puts the given article at the given place in the queue. If it isn't downloaded, it's
+    /// downloaded first, and put there once it's done.
+    fn queue_at(&mut self, ctx: &Context<Self>, id: ArticleId, placement: Placement) {
+        let progress = self.download_progresses.get(&id).cloned();
+        if matches!(progress, Some(DownloadProgress::Done)) {
+            ctx.props()
+                .queue_link
+                .borrow()
+                .clone()
+                .unwrap()
+                .send_message(QueueMsg::PlaceArticle(id, placement));
+            return;
+        }
+
+        self.placements.insert(id.clone(), placement);
+        if matches!(progress, Some(DownloadProgress::InProgress(_))) {
+            return;
+        }
+        let meta = self
+            .catalog
+            .as_ref()
+            .and_then(|catalog| catalog.0.iter().find(|meta| meta.id == id.0));
+        if let Some(meta) = meta {
+            let entry = self.queue_entry(id, meta.title.clone(), meta.group.clone());
+            self.download(ctx, entry);
+        }
+    }
+
+    /// Downloads the article in the given entry in the background, reporting its progress, and
+    /// relays the entry to the queue once it's saved
+    fn download(&mut self, ctx: &Context<Self>, entry: QueueEntry) {
+        // Immediately set the article's progress to 0%
+        self.download_progresses
+            .insert(entry.id.clone(), DownloadProgress::InProgress(0.0));
+
+        let lib_link = ctx.link().clone();
+        ctx.link().send_future(async move {
+            let id = entry.id.clone();
+            let progress_id = id.clone();
+            let on_progress = move |progress| {
+                lib_link.send_message(LibraryMsg::SetDownloadProgress {
+                    id: progress_id.clone(),
+                    progress,
+                })
+            };
+
+            match downloads::download(entry, on_progress).await {
+                Ok(queue_entry) => LibraryMsg::PassArticleToQueue(queue_entry),
+                Err(err) => LibraryMsg::DownloadFailed { id, err },
+            }
+        });
+    }
 }
@@ -0,0 +1,80 @@
+//! Keeps the latest log entries in memory, so the developer view can show them. That's handy for
+//! listeners reporting bugs on phones, where the browser console is out of reach. Trace entries are
+//! left out, since there are so many of them that they'd push everything else out.
+
+use std::{cell::RefCell, fmt::Debug};
+
+use ringbuffer::{AllocRingBuffer, RingBufferExt, RingBufferWrite};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// How many entries are kept. It has to be a power of 2.
+const CAPACITY: usize = 256;
+
+/// A logged event
+#[derive(Clone)]
+pub(crate) struct LogEntry {
+    /// When it was logged, in milliseconds since the Unix epoch
+    pub(crate) time: f64,
+    pub(crate) level: Level,
+    /// The module it was logged from
+    pub(crate) target: String,
+    pub(crate) message: String,
+}
+
+thread_local!(
+    static ENTRIES: RefCell<AllocRingBuffer<LogEntry>> =
+        RefCell::new(AllocRingBuffer::with_capacity(CAPACITY))
+);
+
+/// Returns the kept entries, oldest first
+pub(crate) fn entries() -> Vec<LogEntry> {
+    ENTRIES.with(|entries| entries.borrow().to_vec())
+}
+
+/// Forgets the kept entries
+pub(crate) fn clear() {
+    ENTRIES.with(|entries| entries.borrow_mut().clear());
+}
+
+/// Writes an event's message, followed by its other fields as "name=value"
+#[derive(Default)]
+struct MessageWriter(String);
+
+impl Visit for MessageWriter {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            self.0.push_str(&format!("{:?}", value));
+        } else {
+            self.0.push_str(&format!("{}={:?}", field.name(), value));
+        }
+    }
+}
+
+/// The layer that keeps the entries. It's added alongside the one that logs to the console.
+pub(crate) struct LogBuffer;
+
+impl<S: Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() == Level::TRACE {
+            return;
+        }
+
+        let mut writer = MessageWriter::default();
+        event.record(&mut writer);
+        let entry = LogEntry {
+            time: js_sys::Date::now(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: writer.0,
+        };
+        ENTRIES.with(|entries| entries.borrow_mut().push(entry));
+    }
+}
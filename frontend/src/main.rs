@@ -1,17 +1,51 @@
 use core::{cell::RefCell, ops::Deref};
 use std::rc::Rc;
 
+mod account_view;
 mod add_view;
+mod admin_view;
 mod app_view;
+mod battery;
 mod caching;
+mod clock;
+mod collections_view;
+mod day_sections;
+mod dev_view;
+mod devices_view;
+mod downloads;
+mod encryption;
+mod feeds_view;
+mod history_view;
+mod import_view;
+mod inbox_view;
+mod job_view;
 mod library_view;
+mod log_buffer;
 mod main_view;
+mod moments_view;
+mod network_sim;
+mod pairing_view;
+mod peers;
+mod platform;
 mod player_view;
+mod playlists_view;
+mod position_conflicts;
+mod private_view;
 mod queue_view;
+mod reminders_view;
+mod servers;
+mod servers_view;
+mod shares_view;
+mod smart_playlist;
+mod storage_view;
+mod sync;
 mod utils;
+mod voice_compare_view;
 
 use app_view::App;
 
+use tracing_subscriber::{layer::SubscriberExt, Registry};
+use tracing_wasm::{WASMLayer, WASMLayerConfig};
 use yew::html::{Component, ImplicitClone, Scope};
 
 pub struct WeakComponentLink<C: Component>(Rc<RefCell<Option<Scope<C>>>>);
@@ -45,9 +79,17 @@ impl<C: Component> PartialEq for WeakComponentLink<C> {
 
 fn main() {
     console_error_panic_hook::set_once();
-    tracing_wasm::set_as_global_default();
+    // Log to the console, and keep the latest entries for the developer view
+    tracing::subscriber::set_global_default(
+        Registry::default()
+            .with(WASMLayer::new(WASMLayerConfig::default()))
+            .with(log_buffer::LogBuffer),
+    )
+    .expect("default global");
 
-    caching::register_service_worker();
+    if platform::uses_service_worker() {
+        caching::register_service_worker();
+    }
 
     yew::start_app::<App>();
 }
@@ -1,19 +1,53 @@
-use crate::{library_view::Library, player_view::Player, queue_view::Queue, WeakComponentLink};
+use crate::{
+    account_view::{self, Account},
+    admin_view::ServerStorage,
+    app_view::Route,
+    collections_view::Collections,
+    devices_view::Devices,
+    history_view::History,
+    job_view::Jobs,
+    library_view::{Browse, Library, ViewStates},
+    moments_view::Moments,
+    network_sim::{self, NetworkCondition},
+    pairing_view::PairDevice,
+    peers,
+    player_view::{self, Player, PlayerMsg},
+    playlists_view::{Playlists, PlaylistsMsg},
+    private_view::PrivateLibrary,
+    queue_view::{Queue, QueueMsg},
+    reminders_view::Reminders,
+    servers_view::ServerSwitcher,
+    shares_view::Shares,
+    storage_view::Storage,
+    sync::{self, SyncChanges, SYNC_FREQ},
+    WeakComponentLink,
+};
 
+use common::AccountStatus;
+use gloo_timers::callback::Interval;
 use yew::prelude::*;
+use yew_router::prelude::*;
 
-// TODO Fixme: This path is only valid in production mode
-const LOGO_PATH: &str = "/assets/rtms-color-180x180.png";
+// TODO Fixme: This path is only valid in production mode. It's relative to the app's base path.
+const LOGO_PATH: &str = "assets/rtms-color-180x180.png";
 
 pub(crate) struct Main {
     /// Indicates whether the app has access to an IndexedDb. If this is false, it's a fatal error
     has_db_access: bool,
+    /// Who's signed in, if the server has said. It doesn't say when it can't be reached, e.g.,
+    /// offline, and then the app carries on with whatever is cached.
+    account: Option<AccountStatus>,
+    /// The timer that syncs with the other devices every SYNC_FREQ milliseconds, while the audio is
+    /// playing
+    _sync_interval: Option<Interval>,
 }
 
 impl Default for Main {
     fn default() -> Main {
         Main {
             has_db_access: true,
+            account: None,
+            _sync_interval: None,
         }
     }
 }
@@ -23,11 +57,26 @@ pub(crate) struct Props {
     pub player_link: WeakComponentLink<Player>,
     pub queue_link: WeakComponentLink<Queue>,
     pub library_link: WeakComponentLink<Library>,
+    pub reminders_link: WeakComponentLink<Reminders>,
+    pub playlists_link: WeakComponentLink<Playlists>,
+    /// Which part of the library to show
+    #[prop_or_default]
+    pub browse: Browse,
+    /// How the listener left each part of the library
+    #[prop_or_default]
+    pub view_states: ViewStates,
 }
 
 pub enum Message {
     /// Message indicates that we don't have access to an IndexedDb. This is a fatal error
     DbFailed,
+    /// Sets who's signed in
+    SetAccount(AccountStatus),
+    /// Syncs the queue, positions, and speed with the other devices. Periodic syncs are skipped
+    /// unless the audio is playing.
+    Sync { periodic: bool },
+    /// Passes what changed in a sync on to the queue, player, and playlists
+    Synced(SyncChanges),
 }
 
 impl Component for Main {
@@ -44,14 +93,86 @@ impl Component for Main {
             }
         });
 
-        Main::default()
+        // Find out whether someone has to sign in
+        ctx.link().send_future_batch(async move {
+            match account_view::fetch_status().await {
+                Ok(status) => vec![Message::SetAccount(status)],
+                Err(e) => {
+                    tracing::debug!("Couldn't fetch account: {:#}", e);
+                    Vec::new()
+                }
+            }
+        });
+
+        // Sync now, and every so often while playing
+        ctx.link().send_message(Message::Sync { periodic: false });
+        let link = ctx.link().clone();
+        let sync_interval = Interval::new(SYNC_FREQ, move || {
+            link.send_message(Message::Sync { periodic: true })
+        });
+
+        Main {
+            _sync_interval: Some(sync_interval),
+            ..Main::default()
+        }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Message::DbFailed => {
                 self.has_db_access = false;
             }
+            Message::SetAccount(status) => {
+                // Pass cached audio between this device and the listener's others. A demo server
+                // can't relay the messages that takes.
+                if !status.demo {
+                    peers::serve();
+                }
+                self.account = Some(status);
+            }
+            Message::Sync { periodic } => {
+                if periodic && !player_view::is_playing() {
+                    return false;
+                }
+                // A demo server can't store anything, so there's nothing to sync with
+                if self.account.as_ref().is_some_and(|status| status.demo) {
+                    return false;
+                }
+                ctx.link().send_future_batch(async move {
+                    match sync::sync().await {
+                        Ok(changes) => vec![Message::Synced(changes)],
+                        Err(e) => {
+                            // This fails when offline, or signed out, and then there's nothing to
+                            // sync with
+                            tracing::debug!("Couldn't sync: {:#}", e);
+                            Vec::new()
+                        }
+                    }
+                });
+                return false;
+            }
+            Message::Synced(changes) => {
+                let props = ctx.props();
+                if let Some((entries, updated)) = changes.queue {
+                    if let Some(queue_link) = props.queue_link.borrow().as_ref() {
+                        queue_link.send_message(QueueMsg::SetSynced { entries, updated });
+                    }
+                }
+                if let Some(player_link) = props.player_link.borrow().as_ref() {
+                    if let Some((speed, updated)) = changes.speed {
+                        player_link.send_message(PlayerMsg::SetSyncedSpeed { speed, updated });
+                    }
+                    if !changes.positions.is_empty() {
+                        player_link.send_message(PlayerMsg::SetSyncedPositions(changes.positions));
+                    }
+                }
+                if changes.playlists {
+                    if let Some(playlists_link) = props.playlists_link.borrow().as_ref() {
+                        playlists_link.send_message(PlaylistsMsg::Refresh);
+                    }
+                }
+                return false;
+            }
         }
 
         true
@@ -61,6 +182,10 @@ impl Component for Main {
         let player_link = &ctx.props().player_link;
         let queue_link = &ctx.props().queue_link;
         let library_link = &ctx.props().library_link;
+        let reminders_link = &ctx.props().reminders_link;
+        let playlists_link = &ctx.props().playlists_link;
+        let browse = ctx.props().browse.clone();
+        let view_states = ctx.props().view_states.clone();
 
         // If we don't have IndexedDB access, don't show anything
         if !self.has_db_access {
@@ -75,13 +200,80 @@ impl Component for Main {
             };
         }
 
+        // If the server has accounts and nobody's signed in, there's nothing else to show
+        let account = match &self.account {
+            Some(status) if status.accounts_enabled && status.name.is_none() => {
+                return html! {
+                    <>
+                        { header() }
+                        <Account status={status.clone()} />
+                    </>
+                };
+            }
+            Some(status) => html! { <Account status={status.clone()} /> },
+            None => html! {},
+        };
+        let is_admin = self.account.as_ref().is_some_and(|status| status.is_admin);
+        let demo = self.account.as_ref().is_some_and(|status| status.demo);
+        let demo_banner = if demo {
+            html! {
+                <p class="demoBanner" role="note">{
+                    "This is a demo. Try the player and listen offline, but the library can't be
+                    changed. Self-host ReadToMyShoe to add your own articles."
+                }</p>
+            }
+        } else {
+            html! {}
+        };
+
+        // Say when requests are failing or slow on purpose, so it isn't mistaken for a real outage
+        let network_banner = match network_sim::condition() {
+            NetworkCondition::Normal => html! {},
+            condition => html! {
+                <p class="demoBanner" role="note">
+                    { format!("Simulating a network condition: {}. ", condition.label()) }
+                    <Link<Route> to={Route::Dev}>{ "Change it" }</Link<Route>>
+                </p>
+            },
+        };
+
         // Show the main view
         html! {
             <>
                 { header() }
+                { demo_banner }
+                { network_banner }
+                { account }
+                // A demo has nothing to sign in to, and its address is public anyway
+                if !demo {
+                    <PairDevice />
+                    <Devices />
+                }
+                <Reminders {library_link} {reminders_link} />
                 <Player {player_link} {queue_link}  />
+                <Jobs player_link={ Some(player_link.clone()) } />
                 <Queue {player_link} {queue_link} {library_link} />
-                <Library {queue_link} {library_link} />
+                <Storage {queue_link} />
+                if is_admin {
+                    <ServerStorage />
+                }
+                <History {player_link} {library_link} />
+                <Moments {player_link} {library_link} />
+                // Sharing and subscribing change the library, which a demo can't
+                if !demo {
+                    <Shares />
+                    <Collections {library_link} />
+                }
+                <PrivateLibrary {queue_link} />
+                <Playlists {queue_link} {library_link} {playlists_link} />
+                <Library
+                    {queue_link}
+                    {library_link}
+                    {reminders_link}
+                    {playlists_link}
+                    {browse}
+                    {view_states}
+                />
             </>
         }
     }
@@ -159,6 +351,7 @@ fn header() -> Html {
                     <summary><span id="helpLink">{ "Help" }</span></summary>
                     <div aria-live="polite">{ help_text }</div>
                 </details>
+                <ServerSwitcher />
             </nav>
             <hr />
         </header>
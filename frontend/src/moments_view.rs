@@ -0,0 +1,361 @@
+//! Moments: the points in articles the listener marked as interesting with one tap of the player's
+//! moment button, e.g., while walking. Unlike taking notes, there's nothing to fill in. Each moment
+//! keeps when in the article it was, and the sentence being spoken then and the ones on either
+//! side, so it can be recognized later without playing it. The moments panel lists them, and plays
+//! from any of them. An article's moments can be saved as a Podcasting 2.0 chapters file, so other
+//! players show them as chapters.
+
+use crate::{
+    caching, history_view,
+    library_view::Library,
+    player_view::{format_time, Player},
+    queue_view::{ArticleId, QueueEntry},
+    utils, WeakComponentLink,
+};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+
+/// A point in an article the listener marked
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Moment {
+    /// The key the moment is kept under: the article's ID and when it was marked
+    key: String,
+    /// The ID of the article
+    pub(crate) id: ArticleId,
+    /// The title of the article
+    pub(crate) title: String,
+    /// Where in the article's audio the moment is, in seconds
+    pub(crate) secs: f64,
+    /// When the moment was marked, in milliseconds since the Unix epoch
+    pub(crate) marked: f64,
+    /// The sentence before the one being spoken, the one being spoken, and the one after. These
+    /// are empty if the article has no transcript.
+    pub(crate) before: String,
+    pub(crate) sentence: String,
+    pub(crate) after: String,
+}
+
+impl Moment {
+    /// Marks the given time in the given article, with the sentences around it
+    pub(crate) fn new(entry: &QueueEntry, secs: f64, context: [String; 3]) -> Moment {
+        let marked = js_sys::Date::now();
+        let [before, sentence, after] = context;
+        Moment {
+            key: format!("{}@{marked}", entry.id.0),
+            id: entry.id.clone(),
+            title: entry.title.clone(),
+            secs,
+            marked,
+            before,
+            sentence,
+            after,
+        }
+    }
+}
+
+/// The version of the Podcasting 2.0 chapters format that's written
+const CHAPTERS_VERSION: &str = "1.2.0";
+
+/// The MIME type of a chapters file
+const CHAPTERS_MIME_TYPE: &str = "application/json+chapters";
+
+/// How many characters of a moment's sentence make its chapter's title
+const CHAPTER_TITLE_CHARS: usize = 60;
+
+/// A Podcasting 2.0 chapters file. See
+/// https://github.com/Podcastindex-org/podcast-namespace/blob/main/chapters/jsonChapters.md
+#[derive(Debug, PartialEq, Serialize)]
+struct ChaptersFile {
+    version: &'static str,
+    title: String,
+    chapters: Vec<Chapter>,
+}
+
+/// A chapter, which lasts until the next one starts
+#[derive(Debug, PartialEq, Serialize)]
+struct Chapter {
+    /// When the chapter starts, in seconds
+    #[serde(rename = "startTime")]
+    start_time: f64,
+    title: String,
+}
+
+/// Returns the chapters file for the given article's moments. Each moment starts a chapter named
+/// after the sentence that was being spoken. The article's title covers what comes before the
+/// first one, so players don't put the start of the article in the first moment's chapter.
+fn chapters_file(title: &str, moments: &[&Moment]) -> ChaptersFile {
+    let mut moments = moments.to_vec();
+    moments.sort_by(|a, b| a.secs.total_cmp(&b.secs));
+    moments.dedup_by(|a, b| a.secs == b.secs);
+
+    let mut chapters = Vec::new();
+    if moments.first().is_none_or(|m| m.secs > 0.0) {
+        chapters.push(Chapter {
+            start_time: 0.0,
+            title: title.to_string(),
+        });
+    }
+    for moment in moments {
+        let sentence = moment.sentence.trim();
+        let title = if sentence.is_empty() {
+            format!("Moment at {}", format_time(moment.secs))
+        } else if sentence.chars().count() > CHAPTER_TITLE_CHARS {
+            let start: String = sentence.chars().take(CHAPTER_TITLE_CHARS).collect();
+            format!("{}…", start.trim_end())
+        } else {
+            sentence.to_string()
+        };
+        chapters.push(Chapter {
+            start_time: moment.secs,
+            title,
+        });
+    }
+
+    ChaptersFile {
+        version: CHAPTERS_VERSION,
+        title: title.to_string(),
+        chapters,
+    }
+}
+
+/// Has the browser save the given article's moments as a chapters file
+fn save_chapters(title: &str, moments: &[&Moment]) {
+    let file = chapters_file(title, moments);
+    let json = JsValue::from_serde(&file)
+        .ok()
+        .and_then(|v| js_sys::JSON::stringify(&v).ok())
+        .and_then(|s| s.as_string());
+    let Some(json) = json else {
+        tracing::error!("Couldn't write the chapters of {title}");
+        return;
+    };
+    let filename = utils::file_name(title, "chapters.json");
+    if let Err(e) = utils::save_file(&filename, &json, CHAPTERS_MIME_TYPE) {
+        tracing::error!("Couldn't save the chapters of {title}: {e}");
+    }
+}
+
+/// Formats the given time in milliseconds since the Unix epoch as a local date
+fn format_date(ms: f64) -> String {
+    js_sys::Date::new(&JsValue::from_f64(ms))
+        .to_locale_date_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// Links to the player and the library, for playing from a moment
+    pub player_link: WeakComponentLink<Player>,
+    pub library_link: WeakComponentLink<Library>,
+}
+
+pub(crate) enum MomentsMsg {
+    /// Loads the moments again
+    Refresh,
+    /// Sets the moments, newest first
+    SetMoments(Vec<Moment>),
+    /// Plays the article of the moment with the given key from the moment
+    Play(String),
+    /// Deletes the moment with the given key
+    Delete(String),
+    /// Saves the moments in the article with the given ID as a chapters file
+    ExportChapters(ArticleId),
+}
+
+/// The moments panel on the main page
+#[derive(Default)]
+pub(crate) struct Moments {
+    /// Every moment, newest first
+    moments: Vec<Moment>,
+}
+
+impl Component for Moments {
+    type Message = MomentsMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(MomentsMsg::Refresh);
+        Moments::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            MomentsMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    let mut moments = caching::load_moments()
+                        .await
+                        .map_err(|e| tracing::error!("Couldn't load moments: {}", e))
+                        .unwrap_or_default();
+                    moments.sort_by(|a, b| b.marked.total_cmp(&a.marked));
+                    MomentsMsg::SetMoments(moments)
+                });
+                false
+            }
+            MomentsMsg::SetMoments(moments) => {
+                self.moments = moments;
+                true
+            }
+            MomentsMsg::Play(key) => {
+                if let Some(moment) = self.moments.iter().find(|m| m.key == key) {
+                    let props = ctx.props();
+                    history_view::play_from(
+                        &props.player_link,
+                        &props.library_link,
+                        moment.id.clone(),
+                        moment.title.clone(),
+                        moment.secs,
+                    );
+                }
+                false
+            }
+            MomentsMsg::Delete(key) => {
+                self.moments.retain(|m| m.key != key);
+                spawn_local(async move {
+                    if let Err(e) = caching::delete_moment(&key).await {
+                        tracing::error!("Couldn't delete moment: {}", e);
+                    }
+                });
+                true
+            }
+            MomentsMsg::ExportChapters(id) => {
+                let moments: Vec<&Moment> = self.moments.iter().filter(|m| m.id == id).collect();
+                if let Some(first) = moments.first() {
+                    save_chapters(&first.title, &moments);
+                }
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let items = self
+            .moments
+            .iter()
+            .map(|moment| {
+                let key = moment.key.clone();
+                let play_cb = ctx.link().callback(move |_| MomentsMsg::Play(key.clone()));
+                let key = moment.key.clone();
+                let delete_cb = ctx
+                    .link()
+                    .callback(move |_| MomentsMsg::Delete(key.clone()));
+                let snippet = if moment.sentence.is_empty() {
+                    Html::default()
+                } else {
+                    html! {
+                        <blockquote dir="auto">
+                            { format!("{} ", moment.before) }
+                            <mark>{ &moment.sentence }</mark>
+                            { format!(" {}", moment.after) }
+                        </blockquote>
+                    }
+                };
+                html! {
+                    <li>
+                        <bdi>{ &moment.title }</bdi>
+                        <span class="articleMetadata">
+                            { format!(
+                                " {} in, marked {}",
+                                format_time(moment.secs),
+                                format_date(moment.marked),
+                            ) }
+                        </span>
+                        { " " }
+                        <button onclick={play_cb}>
+                            { format!("▶ Play from {}", format_time(moment.secs)) }
+                        </button>
+                        <button
+                            aria-label="Delete moment"
+                            title="Delete moment"
+                            onclick={delete_cb}
+                        >
+                            { "✕" }
+                        </button>
+                        { snippet }
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        // One button per article with moments, in the order they're listed
+        let mut articles: Vec<&Moment> = Vec::new();
+        for moment in &self.moments {
+            if !articles.iter().any(|m| m.id == moment.id) {
+                articles.push(moment);
+            }
+        }
+        let export_buttons = articles
+            .into_iter()
+            .map(|moment| {
+                let id = moment.id.clone();
+                let onclick = ctx
+                    .link()
+                    .callback(move |_| MomentsMsg::ExportChapters(id.clone()));
+                html! {
+                    <button {onclick}><bdi>{ &moment.title }</bdi></button>
+                }
+            })
+            .collect::<Html>();
+
+        let ontoggle = ctx.link().callback(|_| MomentsMsg::Refresh);
+        html! {
+            <details class="moments" {ontoggle}>
+                <summary>{ "Moments" }</summary>
+                if self.moments.is_empty() {
+                    <p class="articleMetadata">{
+                        "No moments yet. Tap ⭐ in the player to mark one."
+                    }</p>
+                } else {
+                    <ul aria-label="Moments">
+                        { items }
+                    </ul>
+                    <p class="chaptersExport">
+                        { "Save an article's moments as podcast chapters: " }
+                        { export_buttons }
+                    </p>
+                }
+            </details>
+        }
+    }
+}
+
+#[test]
+fn making_chapters() {
+    let moment = |secs: f64, sentence: &str| Moment {
+        key: format!("a@{secs}"),
+        id: ArticleId("a".to_string()),
+        title: "Boots".to_string(),
+        secs,
+        marked: 0.0,
+        before: String::new(),
+        sentence: sentence.to_string(),
+        after: String::new(),
+    };
+    let long = "Laces ".repeat(20);
+    let moments = [
+        moment(95.0, ""),
+        moment(12.5, "Boots are made for walking."),
+        moment(95.0, "Marked twice."),
+        moment(200.0, &long),
+    ];
+    let file = chapters_file("Boots", &moments.iter().collect::<Vec<_>>());
+
+    assert_eq!(file.version, CHAPTERS_VERSION);
+    let chapters: Vec<(f64, &str)> = file
+        .chapters
+        .iter()
+        .map(|c| (c.start_time, c.title.as_str()))
+        .collect();
+    let long_title = format!("{}…", long[..CHAPTER_TITLE_CHARS].trim_end());
+    assert_eq!(
+        chapters,
+        [
+            (0.0, "Boots"),
+            (12.5, "Boots are made for walking."),
+            (95.0, "Moment at 1:35"),
+            (200.0, long_title.as_str()),
+        ]
+    );
+}
@@ -0,0 +1,97 @@
+//! Simulated network conditions, for trying out how the app behaves offline or on a slow connection
+//! without the browser's developer tools, which phones don't have. The condition's applied to every
+//! request to a server, just before it's sent. It's set in the developer view, and kept in
+//! localStorage so it lasts through reloads.
+
+use gloo_timers::future::TimeoutFuture;
+use gloo_utils::window;
+
+/// The localStorage key of the simulated condition. It's for the whole device, not one server.
+const NETWORK_SIM_KEY: &str = "simulated-network";
+
+/// How long every request waits on a slow connection, in milliseconds
+const SLOW_DELAY_MS: u32 = 3000;
+
+/// The network conditions that can be simulated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NetworkCondition {
+    /// Requests go through as usual
+    Normal,
+    /// Requests fail as if there were no connection
+    Offline,
+    /// Requests wait before going through
+    Slow,
+}
+
+impl NetworkCondition {
+    /// All the conditions, in the order they're offered
+    pub(crate) const ALL: [NetworkCondition; 3] = [
+        NetworkCondition::Normal,
+        NetworkCondition::Offline,
+        NetworkCondition::Slow,
+    ];
+
+    /// The name the condition is saved under
+    fn name(&self) -> &'static str {
+        match self {
+            NetworkCondition::Normal => "normal",
+            NetworkCondition::Offline => "offline",
+            NetworkCondition::Slow => "slow",
+        }
+    }
+
+    /// Describes the condition, for picking it
+    pub(crate) fn label(&self) -> String {
+        match self {
+            NetworkCondition::Normal => "Normal".to_string(),
+            NetworkCondition::Offline => "Offline: every request fails".to_string(),
+            NetworkCondition::Slow => {
+                format!("Slow: every request waits {} s", SLOW_DELAY_MS / 1000)
+            }
+        }
+    }
+}
+
+/// Returns the simulated condition
+pub(crate) fn condition() -> NetworkCondition {
+    let name = window()
+        .local_storage()
+        .ok()
+        .flatten()
+        .and_then(|storage| storage.get_item(NETWORK_SIM_KEY).ok().flatten());
+    NetworkCondition::ALL
+        .into_iter()
+        .find(|c| Some(c.name()) == name.as_deref())
+        .unwrap_or(NetworkCondition::Normal)
+}
+
+/// Sets the simulated condition
+pub(crate) fn set_condition(condition: NetworkCondition) {
+    let Some(storage) = window().local_storage().ok().flatten() else {
+        return;
+    };
+    let res = match condition {
+        NetworkCondition::Normal => storage.remove_item(NETWORK_SIM_KEY),
+        _ => storage.set_item(NETWORK_SIM_KEY, condition.name()),
+    };
+    if let Err(e) = res {
+        tracing::error!("Couldn't save the simulated network condition: {:?}", e);
+    }
+}
+
+/// Holds a request back, or fails it, as the simulated condition says. The error is the one a
+/// failed fetch gives, so it's handled like the real thing.
+pub(crate) async fn simulate() -> Result<(), gloo_net::Error> {
+    match condition() {
+        NetworkCondition::Normal => Ok(()),
+        NetworkCondition::Offline => {
+            let err = js_sys::Error::new("Failed to fetch (simulated offline)");
+            err.set_name("TypeError");
+            Err(gloo_net::Error::JsError(err.into()))
+        }
+        NetworkCondition::Slow => {
+            TimeoutFuture::new(SLOW_DELAY_MS).await;
+            Ok(())
+        }
+    }
+}
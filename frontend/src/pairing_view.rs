@@ -0,0 +1,136 @@
+//! Pairing a new device, e.g., a phone, by scanning a QR code instead of typing in the server's
+//! address and a password. The code is of a link to the app that, when the server has accounts,
+//! signs the new device in to this account. The link is only good once, for a few minutes.
+//!
+//! The address the new device needs isn't always the one this device uses, e.g., if this device is
+//! the server itself, at `localhost`, or the server is behind NAT. So it can be changed, to, e.g., a
+//! LAN address or a public IPv6 address.
+
+use crate::{servers, utils};
+use common::{PairingCode, PairingRequest};
+
+use anyhow::{bail, Error as AnyError};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+const APP_URL_FORM_ID: &str = "pairing-app-url-input";
+
+/// Asks the server for a pairing link to the app at the given URL
+async fn fetch_pairing_code(app_url: String) -> Result<PairingCode, AnyError> {
+    let endpoint = "/api/pair-device";
+    let resp = utils::post(endpoint)
+        .json(&PairingRequest { app_url })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing pairing JSON"))
+}
+
+/// Returns the URL of the app on the active server, as this device reaches it
+fn default_app_url() -> String {
+    match servers::active() {
+        Some(server) => server.base_url,
+        None => servers::local_url(""),
+    }
+}
+
+pub(crate) enum PairingMsg {
+    /// Gets a pairing link for the URL in the form
+    Start,
+    /// Shows the given pairing link, or the error getting it
+    SetCode(Result<PairingCode, String>),
+}
+
+/// The "Pair a device" box
+#[derive(Default)]
+pub(crate) struct PairDevice {
+    code: Option<Result<PairingCode, String>>,
+}
+
+impl Component for PairDevice {
+    type Message = PairingMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        PairDevice::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            PairingMsg::Start => {
+                let app_url = gloo_utils::document()
+                    .get_element_by_id(APP_URL_FORM_ID)
+                    .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+                    .map(|input| input.value())
+                    .unwrap_or_else(default_app_url);
+                ctx.link().send_future(async move {
+                    let code = fetch_pairing_code(app_url).await;
+                    PairingMsg::SetCode(code.map_err(|e| format!("{e:#}")))
+                });
+                false
+            }
+            PairingMsg::SetCode(code) => {
+                self.code = Some(code);
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let code = match &self.code {
+            Some(Ok(code)) => {
+                let qr_src = format!("data:image/svg+xml,{}", urlencoding::encode(&code.qr_svg));
+                let expiry = match code.expires_in_secs {
+                    0 => "Scan it with the new device's camera.".to_string(),
+                    secs => format!(
+                        "Scan it with the new device's camera. It signs in once, within {} \
+                         minutes.",
+                        secs / 60
+                    ),
+                };
+                html! {
+                    <>
+                        <img
+                            class="pairingCode"
+                            src={qr_src}
+                            width="240"
+                            height="240"
+                            alt="QR code of the pairing link"
+                        />
+                        <p>{ expiry }</p>
+                        <p class="articleMetadata"><code>{ &code.url }</code></p>
+                    </>
+                }
+            }
+            Some(Err(e)) => html! { <p style="color: red;" role="alert">{ e }</p> },
+            None => html! {},
+        };
+
+        html! {
+            <details class="pairDevice">
+                <summary>{ "Pair a device" }</summary>
+                <p>{
+                    "Show a QR code that opens the app on another device, e.g., a phone. If the
+                    server has accounts, the device is signed in to this one."
+                }</p>
+                <label for={APP_URL_FORM_ID}>{ "Address the device can reach: " }</label>
+                <input
+                    type="url"
+                    id={APP_URL_FORM_ID}
+                    value={ default_app_url() }
+                    placeholder="http://192.168.1.5:9382"
+                />
+                <button onclick={ ctx.link().callback(|_| PairingMsg::Start) }>
+                    { "Show QR code" }
+                </button>
+                { code }
+            </details>
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Passes cached audio directly between a listener's devices, e.g., from a laptop that has an
+//! article to a phone that's about to download it, when they're on the same network. That's faster
+//! than downloading it again from a server that's far away or slow.
+//!
+//! The devices connect with WebRTC, and the server relays the messages that set the connection up
+//! (see `PeerSignal`). Every open page polls for those messages. A device that's about to download
+//! an article first asks the others for it. One that has the article cached offers it, and the
+//! first offer is taken up. No ICE servers are used, so only addresses on the local network are
+//! tried, and the audio never leaves it. If no device offers the article in time, or the transfer
+//! fails, the article is downloaded from the server as usual.
+
+use crate::{
+    caching, downloads,
+    queue_view::{ArticleId, CachedArticle, QueueEntry},
+    utils,
+};
+use common::{ArticleTranscript, PeerSignal, PeerSignalKind};
+
+use std::{cell::RefCell, collections::HashMap};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use gloo_timers::future::TimeoutFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    MessageEvent, RtcConfiguration, RtcDataChannel, RtcDataChannelInit, RtcDataChannelState,
+    RtcDataChannelType, RtcIceGatheringState, RtcPeerConnection, RtcSdpType,
+    RtcSessionDescriptionInit,
+};
+
+/// How often a page checks for messages from the other devices, in milliseconds
+const IDLE_POLL_FREQ: u32 = 3_000;
+
+/// How often a page checks for messages while it's setting up a connection, in milliseconds
+const BUSY_POLL_FREQ: u32 = 300;
+
+/// How long a device waits for another to offer an article, in milliseconds. This has to cover the
+/// other device noticing the request, so it's longer than `IDLE_POLL_FREQ`.
+const OFFER_TIMEOUT: u32 = 5_000;
+
+/// How long setting up a connection can take once it's been offered, in milliseconds
+const CONNECT_TIMEOUT: u32 = 10_000;
+
+/// How long a transfer can go without any data arriving before it's given up on, in milliseconds
+const RECEIVE_TIMEOUT: u32 = 10_000;
+
+/// How often the state of a connection is checked while waiting on it, in milliseconds
+const CHECK_FREQ: u32 = 50;
+
+/// The size of the messages the audio is sent in. Browsers all handle messages this small.
+const SEND_CHUNK_LEN: usize = 16 * 1024;
+
+/// The most data that can be waiting to be sent before the sender waits for it to drain
+const MAX_BUFFERED: u32 = 1024 * 1024;
+
+/// The size of the blobs received audio is kept in, like downloaded audio is
+const SAVE_CHUNK_LEN: usize = 512 * 1024;
+
+/// The first message of a transfer, which says what the rest of it is
+#[derive(Serialize, Deserialize)]
+struct TransferHeader {
+    /// The MIME type of the audio
+    mime_type: String,
+    /// The size of the audio in bytes
+    total_size: usize,
+    transcript: ArticleTranscript,
+}
+
+/// The connections this page is setting up
+#[derive(Default)]
+struct PeerState {
+    /// Whether this page is polling for messages
+    serving: bool,
+    /// The articles this device asked for, with where to send the first offer of each
+    wanted: HashMap<String, oneshot::Sender<(String, String)>>,
+    /// The offers this device made, by the device and article they're for, with where to send the
+    /// answer
+    offered: HashMap<(String, String), oneshot::Sender<String>>,
+}
+
+impl PeerState {
+    /// Returns whether a connection is being set up
+    fn is_busy(&self) -> bool {
+        !self.wanted.is_empty() || !self.offered.is_empty()
+    }
+}
+
+thread_local!(
+    /// The ID of this device, for as long as the page is open
+    static DEVICE_ID: String = format!(
+        "{:012x}-{:012x}",
+        (js_sys::Math::random() * 1e14) as u64,
+        (js_sys::Math::random() * 1e14) as u64
+    );
+
+    static STATE: RefCell<PeerState> = RefCell::default();
+);
+
+/// Returns the ID of this device
+fn device_id() -> String {
+    DEVICE_ID.with(Clone::clone)
+}
+
+/// Sends the given message to the other devices through the server, and returns how many it went
+/// to
+async fn send_signal(signal: &PeerSignal) -> Result<usize, AnyError> {
+    let resp = utils::post("/api/peer-signal")
+        .json(signal)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error sending peer signal"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json().await.map_err(Into::into)
+}
+
+/// Fetches the messages the other devices sent this one
+async fn receive_signals() -> Result<Vec<PeerSignal>, AnyError> {
+    let url = format!("/api/peer-signals?device={}", device_id());
+    let resp = utils::get(&url)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching peer signals"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json().await.map_err(Into::into)
+}
+
+/// Starts polling for messages from the other devices, so this one can offer them the articles it
+/// has cached, and take up their offers. This only starts once per page.
+pub(crate) fn serve() {
+    let started = STATE.with(|state| std::mem::replace(&mut state.borrow_mut().serving, true));
+    if started {
+        return;
+    }
+
+    spawn_local(async {
+        loop {
+            match receive_signals().await {
+                Ok(signals) => signals.into_iter().for_each(handle_signal),
+                // This fails when offline, and then there's nobody to talk to anyway
+                Err(e) => tracing::debug!("Couldn't fetch peer signals: {:#}", e),
+            }
+            let busy = STATE.with(|state| state.borrow().is_busy());
+            TimeoutFuture::new(if busy { BUSY_POLL_FREQ } else { IDLE_POLL_FREQ }).await;
+        }
+    });
+}
+
+/// Acts on a message from another device
+fn handle_signal(signal: PeerSignal) {
+    let PeerSignal {
+        from,
+        article_id,
+        kind,
+        ..
+    } = signal;
+    match kind {
+        // Offer the article if it's cached here
+        PeerSignalKind::Want => spawn_local(async move {
+            let id = ArticleId(article_id);
+            let Ok(article) = caching::load_article(&id).await else {
+                return;
+            };
+            if let Err(e) = send_article(&from, article).await {
+                tracing::debug!("Couldn't send {} to device {from}: {:#}", id.0, e);
+            }
+        }),
+        // Take up the first offer, and let the rest time out
+        PeerSignalKind::Offer(sdp) => {
+            let waiting = STATE.with(|state| state.borrow_mut().wanted.remove(&article_id));
+            if let Some(waiting) = waiting {
+                let _ = waiting.send((from, sdp));
+            }
+        }
+        PeerSignalKind::Answer(sdp) => {
+            let key = (from, article_id);
+            let waiting = STATE.with(|state| state.borrow_mut().offered.remove(&key));
+            if let Some(waiting) = waiting {
+                let _ = waiting.send(sdp);
+            }
+        }
+    }
+}
+
+/// Makes a connection, and the channel the audio goes through. The channel is set up the same way
+/// on both ends, rather than announced by one of them, so neither has to wait to hear about it.
+fn new_connection() -> Result<(RtcPeerConnection, RtcDataChannel), AnyError> {
+    // No ICE servers, so only addresses on the local network are tried
+    let mut config = RtcConfiguration::new();
+    config.ice_servers(&js_sys::Array::new());
+    let pc = RtcPeerConnection::new_with_configuration(&config)
+        .map_err(|e| utils::wrap_jserror("couldn't make peer connection", e))?;
+
+    let mut init = RtcDataChannelInit::new();
+    init.negotiated(true).id(0);
+    let channel = pc.create_data_channel_with_data_channel_dict("audio", &init);
+    channel.set_binary_type(RtcDataChannelType::Arraybuffer);
+    Ok((pc, channel))
+}
+
+/// Waits until the given condition holds, checking it every so often, or fails with the given
+/// error once `millis` milliseconds have gone by
+async fn wait_until(
+    millis: u32,
+    err: &'static str,
+    mut condition: impl FnMut() -> bool,
+) -> Result<(), AnyError> {
+    let deadline = js_sys::Date::now() + millis as f64;
+    while !condition() {
+        if js_sys::Date::now() > deadline {
+            bail!(err);
+        }
+        TimeoutFuture::new(CHECK_FREQ).await;
+    }
+    Ok(())
+}
+
+/// Waits for the given message, or returns `None` once `millis` milliseconds have gone by
+async fn wait_for<T>(millis: u32, rx: oneshot::Receiver<T>) -> Option<T> {
+    tokio::select! {
+        value = rx => value.ok(),
+        _ = TimeoutFuture::new(millis) => None,
+    }
+}
+
+/// Sets the local description of the given connection to the given offer or answer, and returns it
+/// once the connection knows every address it can be reached at. Sending the addresses along with
+/// the description saves relaying each of them as it's found.
+async fn set_local_description(
+    pc: &RtcPeerConnection,
+    sdp_type: RtcSdpType,
+    description: JsValue,
+) -> Result<String, AnyError> {
+    let sdp = js_sys::Reflect::get(&description, &JsValue::from_str("sdp"))
+        .ok()
+        .and_then(|sdp| sdp.as_string())
+        .ok_or_else(|| anyhow!("the session description has no SDP"))?;
+    JsFuture::from(pc.set_local_description(RtcSessionDescriptionInit::new(sdp_type).sdp(&sdp)))
+        .await
+        .map_err(|e| utils::wrap_jserror("couldn't set local description", e))?;
+
+    wait_until(CONNECT_TIMEOUT, "gathering addresses timed out", || {
+        pc.ice_gathering_state() == RtcIceGatheringState::Complete
+    })
+    .await?;
+    pc.local_description()
+        .map(|description| description.sdp())
+        .ok_or_else(|| anyhow!("the connection lost its local description"))
+}
+
+/// Sets the remote description of the given connection
+async fn set_remote_description(
+    pc: &RtcPeerConnection,
+    sdp_type: RtcSdpType,
+    sdp: &str,
+) -> Result<(), AnyError> {
+    JsFuture::from(pc.set_remote_description(RtcSessionDescriptionInit::new(sdp_type).sdp(sdp)))
+        .await
+        .map(|_| ())
+        .map_err(|e| utils::wrap_jserror("couldn't set remote description", e))
+}
+
+/// Offers the given article to the given device, and sends it if the device takes up the offer
+async fn send_article(to: &str, article: CachedArticle) -> Result<(), AnyError> {
+    let (pc, channel) = new_connection()?;
+    let key = (to.to_string(), article.id.0.clone());
+    let result = async {
+        let offer = JsFuture::from(pc.create_offer())
+            .await
+            .map_err(|e| utils::wrap_jserror("couldn't make offer", e))?;
+        let sdp = set_local_description(&pc, RtcSdpType::Offer, offer).await?;
+
+        let (tx, rx) = oneshot::channel();
+        STATE.with(|state| state.borrow_mut().offered.insert(key.clone(), tx));
+        send_signal(&PeerSignal {
+            from: device_id(),
+            to: Some(to.to_string()),
+            article_id: article.id.0.clone(),
+            kind: PeerSignalKind::Offer(sdp),
+        })
+        .await?;
+        let answer = wait_for(CONNECT_TIMEOUT, rx)
+            .await
+            .ok_or_else(|| anyhow!("the offer wasn't taken up"))?;
+        set_remote_description(&pc, RtcSdpType::Answer, &answer).await?;
+        wait_until(CONNECT_TIMEOUT, "connecting timed out", || {
+            channel.ready_state() == RtcDataChannelState::Open
+        })
+        .await?;
+
+        // Say what's coming, then send the audio a piece at a time, letting the channel drain
+        // whenever it's backed up
+        let header = TransferHeader {
+            mime_type: article.mime_type.clone(),
+            total_size: article
+                .audio_chunks
+                .iter()
+                .map(|chunk| chunk.size() as usize)
+                .sum(),
+            transcript: article.transcript.clone(),
+        };
+        let header: String = js_sys::JSON::stringify(&JsValue::from_serde(&header)?)
+            .map_err(|e| utils::wrap_jserror("couldn't serialize transfer header", e))?
+            .into();
+        channel
+            .send_with_str(&header)
+            .map_err(|e| utils::wrap_jserror("couldn't send transfer header", e))?;
+        for chunk in &article.audio_chunks {
+            let buf = JsFuture::from(chunk.array_buffer())
+                .await
+                .map_err(|e| utils::wrap_jserror("couldn't read cached audio", e))?;
+            let bytes = js_sys::Uint8Array::new(&buf).to_vec();
+            for piece in bytes.chunks(SEND_CHUNK_LEN) {
+                wait_until(RECEIVE_TIMEOUT, "the transfer stalled", || {
+                    channel.buffered_amount() <= MAX_BUFFERED
+                })
+                .await?;
+                channel
+                    .send_with_u8_array(piece)
+                    .map_err(|e| utils::wrap_jserror("couldn't send audio", e))?;
+            }
+        }
+
+        // Let the other device take everything before hanging up
+        wait_until(RECEIVE_TIMEOUT, "the transfer stalled", || {
+            channel.buffered_amount() == 0
+        })
+        .await
+    }
+    .await;
+
+    STATE.with(|state| state.borrow_mut().offered.remove(&key));
+    channel.close();
+    pc.close();
+    result
+}
+
+/// Returns the next message that arrives on a connection, unless it's closed or nothing arrives in
+/// time
+async fn next_message(rx: &mut mpsc::UnboundedReceiver<JsValue>) -> Result<JsValue, AnyError> {
+    tokio::select! {
+        msg = rx.recv() => msg.ok_or_else(|| anyhow!("the connection closed")),
+        _ = TimeoutFuture::new(RECEIVE_TIMEOUT) => Err(anyhow!("the transfer stalled")),
+    }
+}
+
+/// Asks the other devices for the given article, and receives it from the first that offers it.
+/// `on_progress` is called with the fraction received as the audio arrives.
+async fn receive_article(
+    entry: &QueueEntry,
+    on_progress: &impl Fn(f64),
+) -> Result<CachedArticle, AnyError> {
+    let article_id = entry.id.0.clone();
+    let (offer_tx, offer_rx) = oneshot::channel();
+    STATE.with(|state| {
+        state
+            .borrow_mut()
+            .wanted
+            .insert(article_id.clone(), offer_tx)
+    });
+
+    let want = PeerSignal {
+        from: device_id(),
+        to: None,
+        article_id: article_id.clone(),
+        kind: PeerSignalKind::Want,
+    };
+    let offer = match send_signal(&want).await {
+        Ok(0) => Err(anyhow!("no other devices are around")),
+        Ok(_) => wait_for(OFFER_TIMEOUT, offer_rx)
+            .await
+            .ok_or_else(|| anyhow!("no other device offered it")),
+        Err(e) => Err(e),
+    };
+    STATE.with(|state| state.borrow_mut().wanted.remove(&article_id));
+    let (from, offer) = offer?;
+
+    let (pc, channel) = new_connection()?;
+    // Collect the messages as they arrive
+    let (msg_tx, mut msg_rx) = mpsc::unbounded_channel();
+    let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |e: MessageEvent| {
+        let _ = msg_tx.send(e.data());
+    });
+    channel.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+
+    let result = async {
+        set_remote_description(&pc, RtcSdpType::Offer, &offer).await?;
+        let answer = JsFuture::from(pc.create_answer())
+            .await
+            .map_err(|e| utils::wrap_jserror("couldn't make answer", e))?;
+        let sdp = set_local_description(&pc, RtcSdpType::Answer, answer).await?;
+        send_signal(&PeerSignal {
+            from: device_id(),
+            to: Some(from.clone()),
+            article_id: article_id.clone(),
+            kind: PeerSignalKind::Answer(sdp),
+        })
+        .await?;
+
+        // The header comes first, and then the audio
+        let header: TransferHeader = next_message(&mut msg_rx)
+            .await?
+            .as_string()
+            .and_then(|json| js_sys::JSON::parse(&json).ok())
+            .ok_or_else(|| anyhow!("the transfer didn't start with a header"))?
+            .into_serde()?;
+        let mut chunks = Vec::new();
+        let mut buf = Vec::with_capacity(SAVE_CHUNK_LEN);
+        let mut received = 0;
+        while received < header.total_size {
+            let piece = js_sys::Uint8Array::new(&next_message(&mut msg_rx).await?).to_vec();
+            received += piece.len();
+            buf.extend(piece);
+            if buf.len() >= SAVE_CHUNK_LEN {
+                chunks.push(utils::bytes_to_blob(&buf, downloads::CHUNK_MIME_TYPE));
+                buf.clear();
+            }
+            on_progress(received as f64 / header.total_size.max(1) as f64);
+        }
+        if received != header.total_size {
+            bail!("got more audio than was said");
+        }
+        if !buf.is_empty() {
+            chunks.push(utils::bytes_to_blob(&buf, downloads::CHUNK_MIME_TYPE));
+        }
+
+        Ok(CachedArticle {
+            id: entry.id.clone(),
+            title: entry.title.clone(),
+            audio_chunks: chunks,
+            mime_type: header.mime_type,
+            transcript: header.transcript,
+        })
+    }
+    .await;
+
+    channel.set_onmessage(None);
+    channel.close();
+    pc.close();
+    result
+}
+
+/// Gets the given article from another of the listener's devices, if one nearby has it cached. This
+/// gives up quickly if none do. `on_progress` is called with the fraction received as the audio
+/// arrives.
+pub(crate) async fn fetch(entry: &QueueEntry, on_progress: &impl Fn(f64)) -> Option<CachedArticle> {
+    // Offers can't be heard unless this page is polling for messages
+    if !STATE.with(|state| state.borrow().serving) {
+        return None;
+    }
+
+    match receive_article(entry, on_progress).await {
+        Ok(article) => {
+            tracing::info!("Got {} from another device", entry.id.0);
+            Some(article)
+        }
+        Err(e) => {
+            tracing::debug!("Couldn't get {} from another device: {:#}", entry.id.0, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! What differs between the web app and the desktop app. The desktop app, built with the `desktop`
+//! feature, runs in the OS's webview rather than a browser, loaded from a server bundled with it on
+//! the same machine. Webviews don't all have the browser APIs the web app leans on, and some of
+//! those APIs are pointless there, so the rest of the app asks here instead of using them directly.
+
+use gloo_utils::window;
+use web_sys::MediaSession;
+
+/// Returns the MediaSession API, for the OS media controls. Browsers have it, but some webviews,
+/// e.g., WebKitGTK's, don't, in which case there are no media controls.
+pub(crate) fn media_session() -> Option<MediaSession> {
+    let navigator = window().navigator();
+    let has_it = !cfg!(feature = "desktop")
+        || js_sys::Reflect::has(&navigator, &"mediaSession".into()).unwrap_or(false);
+    has_it.then(|| navigator.media_session())
+}
+
+/// Returns whether a service worker caches the app for offline use. The desktop app's server is on
+/// the same machine, so it's never offline, and webviews' support for service workers is patchy.
+pub(crate) fn uses_service_worker() -> bool {
+    !cfg!(feature = "desktop")
+}
+
+/// Returns whether the browser has to be asked to keep IndexedDB through storage pressure. The
+/// desktop app's IndexedDB is in its own data directory, which nothing else clears.
+pub(crate) fn needs_persistent_storage() -> bool {
+    !cfg!(feature = "desktop")
+}
@@ -0,0 +1,200 @@
+//! Asks the server's language model questions about the article that's playing, e.g., to go over
+//! something right after listening. The article's text comes from its transcript, so it's sent
+//! along with the question. Answers can be spoken by the browser's own speech synthesis. Servers
+//! without a language model say so, and nothing is answered.
+
+use crate::utils;
+use common::{ArticleAnswer, ArticleQuestion, ArticleTranscript};
+
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use web_sys::{HtmlInputElement, SpeechSynthesisUtterance};
+use yew::prelude::*;
+
+/// Sends the given question to the server, and returns the answer
+pub(super) async fn ask(question: &ArticleQuestion) -> Result<String, AnyError> {
+    let endpoint = "/api/ask-about-article";
+    let resp = utils::post(endpoint)
+        .json(question)?
+        .send()
+        .await
+        .map_err(|e| anyhow!("Error POSTing to {endpoint}: {}", e))?;
+
+    if resp.status() == 404 {
+        bail!("This server has no language model to answer questions.");
+    }
+    if !resp.ok() {
+        bail!(
+            "Couldn't get an answer {}. {}",
+            resp.status_text(),
+            resp.text().await.unwrap_or_default()
+        );
+    }
+
+    let answer: ArticleAnswer = resp
+        .json()
+        .await
+        .map_err(|e| anyhow!("Error parsing the answer: {e}"))?;
+    Ok(answer.answer)
+}
+
+/// Speaks the given text with the browser's speech synthesis, cutting off anything it's saying
+fn speak(text: &str) {
+    let synth = gloo_utils::window().speech_synthesis();
+    match (synth, SpeechSynthesisUtterance::new_with_text(text)) {
+        (Ok(synth), Ok(utterance)) => {
+            synth.cancel();
+            synth.speak(&utterance);
+        }
+        _ => tracing::warn!("This browser can't speak"),
+    }
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// The title of the article that's playing
+    pub title: String,
+    /// The transcript of the article that's playing. Nothing is shown if it has no text.
+    pub transcript: Rc<ArticleTranscript>,
+}
+
+pub(crate) enum AskMsg {
+    /// The question field changed
+    SetQuestion(String),
+    /// Sets whether answers are spoken
+    SetSpeak(bool),
+    /// Asks the question
+    Ask,
+    /// Sets the answer, or the error getting it
+    SetAnswer(Result<String, String>),
+    /// Speaks the answer again
+    Speak,
+}
+
+/// The "Ask about this article" box of the player
+#[derive(Default)]
+pub(crate) struct AskAboutArticle {
+    question: String,
+    /// Whether answers are spoken as soon as they arrive
+    speak: bool,
+    /// Whether a question is waiting for its answer
+    asking: bool,
+    answer: Option<Result<String, String>>,
+}
+
+impl Component for AskAboutArticle {
+    type Message = AskMsg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        AskAboutArticle::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            AskMsg::SetQuestion(question) => {
+                self.question = question;
+                false
+            }
+            AskMsg::SetSpeak(speak) => {
+                self.speak = speak;
+                false
+            }
+            AskMsg::Ask => {
+                if self.asking || self.question.trim().is_empty() {
+                    return false;
+                }
+                self.asking = true;
+                let props = ctx.props();
+                let question = ArticleQuestion {
+                    title: props.title.clone(),
+                    text: props.transcript.text.clone(),
+                    question: self.question.clone(),
+                };
+                ctx.link().send_future(async move {
+                    AskMsg::SetAnswer(ask(&question).await.map_err(|e| format!("{e:#}")))
+                });
+                true
+            }
+            AskMsg::SetAnswer(answer) => {
+                self.asking = false;
+                if let (true, Ok(answer)) = (self.speak, &answer) {
+                    speak(answer);
+                }
+                self.answer = Some(answer);
+                true
+            }
+            AskMsg::Speak => {
+                if let Some(Ok(answer)) = &self.answer {
+                    speak(answer);
+                }
+                false
+            }
+        }
+    }
+
+    fn changed(&mut self, _ctx: &Context<Self>) -> bool {
+        // A different article might be playing now, and the answer was about the last one
+        self.answer = None;
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if ctx.props().transcript.text.is_empty() {
+            return Html::default();
+        }
+
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            AskMsg::SetQuestion(input.value())
+        });
+        let onkeydown = ctx
+            .link()
+            .batch_callback(|e: KeyboardEvent| (e.key() == "Enter").then_some(AskMsg::Ask));
+        let speak_cb = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            AskMsg::SetSpeak(input.checked())
+        });
+
+        let answer = match &self.answer {
+            _ if self.asking => html! { <p style="font-style: italic">{ "Thinking…" }</p> },
+            Some(Ok(answer)) => html! {
+                <p dir="auto" role="status">
+                    { answer }
+                    { " " }
+                    <button onclick={ ctx.link().callback(|_| AskMsg::Speak) }>
+                        { "🔊 Speak" }
+                    </button>
+                </p>
+            },
+            Some(Err(e)) => html! { <p style="color: red;" role="alert">{ e }</p> },
+            None => Html::default(),
+        };
+
+        html! {
+            <details class="askAboutArticle">
+                <summary>{ "Ask about this article" }</summary>
+                <input
+                    type="text"
+                    aria-label="Question about the article"
+                    placeholder="What did they say about…?"
+                    value={ self.question.clone() }
+                    {oninput}
+                    {onkeydown}
+                />
+                <button
+                    disabled={ self.asking }
+                    onclick={ ctx.link().callback(|_| AskMsg::Ask) }
+                >
+                    { "Ask" }
+                </button>
+                <label>
+                    <input type="checkbox" checked={self.speak} onchange={speak_cb} />
+                    { " Speak answers" }
+                </label>
+                { answer }
+            </details>
+        }
+    }
+}
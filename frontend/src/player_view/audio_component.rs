@@ -1,7 +1,13 @@
-use super::media_session::MediaSessionState;
+use super::{
+    audio_processing,
+    media_session::{MediaSessionState, TrackInfo},
+    stream_source,
+};
 use crate::WeakComponentLink;
 
-use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use std::cell::Cell;
+
+use wasm_bindgen::{closure::Closure, JsCast};
 use wasm_bindgen_futures::{spawn_local, JsFuture};
 use web_sys::{Blob, HtmlAudioElement, MediaSessionActionDetails, Url};
 use yew::prelude::*;
@@ -9,8 +15,24 @@ use yew::prelude::*;
 /// The ID of the unique audio element the page
 pub const AUDIO_ELEM_ID: &str = "mainAudio";
 
-// If an audio jump offset isn't set, jump by 10 seconds
-const DEFAULT_JUMP_SIZE: f64 = 10.0;
+/// The number of seconds the jump buttons jump, unless the listener picks something else
+pub(crate) const DEFAULT_JUMP_SIZE: f64 = 10.0;
+
+// Articles that were left this many seconds or less from the end start over from the beginning
+const RESTART_MARGIN: f64 = 5.0;
+
+// A seek lands on the time it was expected to if it's within this many seconds of it
+const EXPECTED_SEEK_TOLERANCE: f64 = 1.0;
+
+thread_local! {
+    /// The time the last seek made by the app itself went to, until the audio reports seeking to it.
+    /// This tells those seeks apart from the listener's seeks with the scrubber.
+    static EXPECTED_SEEK: Cell<Option<f64>> = Cell::new(None);
+
+    /// The number of seconds to jump backward and forward by. This is kept here rather than passed
+    /// around, since the MediaSession handlers are made before the player state is loaded.
+    static JUMP_SIZES: Cell<(f64, f64)> = Cell::new((DEFAULT_JUMP_SIZE, DEFAULT_JUMP_SIZE));
+}
 
 /// Holds operations we can do on the unique <audio> element on this page
 pub struct GlobalAudio;
@@ -25,22 +47,46 @@ impl GlobalAudio {
             .unwrap()
     }
 
-    /// Seeks to the specified time
+    /// Seeks to the specified time. This is for seeks the app makes on purpose, e.g., from a
+    /// button, so the seek is expected.
     pub fn seek(time: f64) {
+        EXPECTED_SEEK.with(|expected| expected.set(Some(time)));
+        GlobalAudio::scrub(time);
+    }
+
+    /// Seeks to the specified time the way the scrubber does, i.e., without marking the seek as
+    /// expected
+    pub fn scrub(time: f64) {
         let audio_elem = GlobalAudio::get_elem();
         audio_elem.set_current_time(time);
     }
 
+    /// Returns whether the seek that's in progress was made with `seek()`. Call this when the audio
+    /// starts seeking.
+    pub fn take_expected_seek() -> bool {
+        let elapsed = GlobalAudio::get_elapsed();
+        EXPECTED_SEEK
+            .with(Cell::take)
+            .is_some_and(|time| (time - elapsed).abs() <= EXPECTED_SEEK_TOLERANCE)
+    }
+
     /// Gets the current elapsed time, in seconds
     pub fn get_elapsed() -> f64 {
         let audio_elem = GlobalAudio::get_elem();
         audio_elem.current_time()
     }
 
-    /// Gets the current playback speed
+    /// Gets the duration of the loaded audio, in seconds. This is NaN if nothing is loaded.
+    pub fn get_duration() -> f64 {
+        let audio_elem = GlobalAudio::get_elem();
+        audio_elem.duration()
+    }
+
+    /// Gets the listener's playback speed. This is the default rate rather than the current one,
+    /// since the audio is sped up while it skips a silence.
     pub fn get_playback_speed() -> f64 {
         let audio_elem = GlobalAudio::get_elem();
-        audio_elem.playback_rate()
+        audio_elem.default_playback_rate()
     }
 
     /// Fast-seeks to the specified time
@@ -57,37 +103,37 @@ impl GlobalAudio {
         let new_time = f64::min(audio_elem.duration(), audio_elem.current_time() + offset);
         let new_time = f64::max(0.0, new_time);
 
-        audio_elem.set_current_time(new_time);
+        GlobalAudio::seek(new_time);
     }
 
-    /// Jumps forward by JUMP_SIZE seconds
-    pub fn jump_forward(details: MediaSessionActionDetails) {
-        let seek_offset =
-            js_sys::Reflect::get(&details, &JsValue::from_str("seekOffset")).map(|t| t.as_f64());
-
-        // If the offset isn't given, use the default jump size
-        let seek_offset = match seek_offset {
-            Ok(Some(off)) => off,
-            _ => DEFAULT_JUMP_SIZE,
-        };
+    /// Sets the number of seconds to jump backward and forward by
+    pub fn set_jump_sizes(backward: f64, forward: f64) {
+        JUMP_SIZES.with(|sizes| sizes.set((backward, forward)));
+    }
 
-        tracing::trace!("Jumping forward {} seconds", seek_offset);
-        GlobalAudio::jump_offset(seek_offset);
+    /// Jumps forward by the listener's jump size
+    pub fn jump_forward() {
+        let (_, forward) = JUMP_SIZES.with(Cell::get);
+        tracing::trace!("Jumping forward {} seconds", forward);
+        GlobalAudio::jump_offset(forward);
     }
 
-    /// Jumps backward by JUMP_SIZE seconds
-    pub fn jump_backward(details: MediaSessionActionDetails) {
-        let seek_offset =
-            js_sys::Reflect::get(&details, &JsValue::from_str("seekOffset")).map(|t| t.as_f64());
+    /// Jumps backward by the listener's jump size
+    pub fn jump_backward() {
+        let (backward, _) = JUMP_SIZES.with(Cell::get);
+        tracing::trace!("Jumping backward {} seconds", backward);
+        GlobalAudio::jump_offset(-backward);
+    }
 
-        // If the offset isn't given, use the default jump size
-        let seek_offset = match seek_offset {
-            Ok(Some(off)) => off,
-            _ => DEFAULT_JUMP_SIZE,
-        };
+    /// The MediaSession "seekforward" handler. The offset the OS suggests, if any, is ignored in
+    /// favor of the one the listener picked.
+    pub fn media_session_jump_forward(_details: MediaSessionActionDetails) {
+        GlobalAudio::jump_forward();
+    }
 
-        tracing::trace!("Jumping backward {} seconds", seek_offset);
-        GlobalAudio::jump_offset(-seek_offset);
+    /// The MediaSession "seekbackward" handler. See `media_session_jump_forward`.
+    pub fn media_session_jump_backward(_details: MediaSessionActionDetails) {
+        GlobalAudio::jump_backward();
     }
 
     // A helper function that plays empty audio. This is necessary because of a quirk in Safari that
@@ -107,6 +153,7 @@ impl GlobalAudio {
     pub async fn play() {
         tracing::trace!("Playing audio");
         let audio_elem = GlobalAudio::get_elem();
+        audio_processing::start();
 
         let promise = audio_elem.play().unwrap();
         let res = JsFuture::from(promise).await;
@@ -129,6 +176,18 @@ impl GlobalAudio {
         audio_elem.pause().unwrap();
     }
 
+    /// Gets the volume of the <audio> tag, from 0 (muted) to 1 (full volume)
+    pub fn get_volume() -> f64 {
+        let audio_elem = GlobalAudio::get_elem();
+        audio_elem.volume()
+    }
+
+    /// Sets the volume of the <audio> tag, from 0 (muted) to 1 (full volume)
+    pub fn set_volume(volume: f64) {
+        let audio_elem = GlobalAudio::get_elem();
+        audio_elem.set_volume(volume.clamp(0.0, 1.0));
+    }
+
     /// Stops playback. This pauses the audio and unloads the source
     pub fn stop() {
         GlobalAudio::pause();
@@ -138,16 +197,26 @@ impl GlobalAudio {
 
     /// Sets the <audio>'s src to the given article's MP3 blob
     pub fn set_source(blob: &Blob) {
+        // Construct a URL that refers to the blob. This will be the audio player's src attribute
+        let blob_url = Url::create_object_url_with_blob(&blob).unwrap();
+        GlobalAudio::set_source_url(&blob_url);
+    }
+
+    /// Sets the <audio>'s src to the given URL
+    pub fn set_source_url(url: &str) {
         // Pause the current
         let audio_elem = GlobalAudio::get_elem();
         audio_elem.pause().unwrap();
 
-        // Initialize the MediaSession with metadata and callbacks
+        // Free the blob or media source the old src referred to, if there was one. Otherwise it's
+        // kept in memory until the page closes.
+        let old_src = audio_elem.src();
+        if old_src.starts_with("blob:") && old_src != url {
+            let _ = Url::revoke_object_url(&old_src);
+        }
 
-        // Construct a URL that refers to the blob. This will be the audio player's src attribute
-        let blob_url = Url::create_object_url_with_blob(&blob).unwrap();
         // Set the src
-        audio_elem.set_src(&blob_url);
+        audio_elem.set_src(url);
     }
 
     /// Sets the playback speed of the <audio> tag and updates the speed selection combobox
@@ -209,23 +278,42 @@ async fn sleep(millis: i32) {
 pub struct Props {
     /// A link to myself. We have to set this on creation
     pub audio_link: WeakComponentLink<Audio>,
+    /// Called when the audio plays to the end
+    #[prop_or_default]
+    pub on_ended: Callback<Event>,
+    /// Called whenever the elapsed time changes
+    #[prop_or_default]
+    pub on_timeupdate: Callback<Event>,
+    /// Called when the audio starts seeking to a new position
+    #[prop_or_default]
+    pub on_seeking: Callback<Event>,
+    /// Called when the audio starts playing
+    #[prop_or_default]
+    pub on_play: Callback<Event>,
+    /// Called when the audio is paused
+    #[prop_or_default]
+    pub on_pause: Callback<Event>,
 }
 
 pub enum AudioMsg {
     /// Load the given blob and set start time to `elapsed` seconds
     Load {
         src: Blob,
-        title: String,
+        track: TrackInfo,
         elapsed: f64,
     },
 
+    /// Load the audio at the given URL. This is for audio that's streamed from the server rather
+    /// than stored locally.
+    LoadUrl { src: String, title: String },
+
     /// Load the given blob and play from `elapsed` seconds
     Play,
 
-    /// Jump forward `JUMP_SIZE` seconds
+    /// Jump forward by the listener's jump size
     JumpForward,
 
-    /// Jump backward `JUMP_SIZE` seconds
+    /// Jump backward by the listener's jump size
     JumpBackward,
 
     /// Sets the audio playback speed to the given percentage
@@ -271,12 +359,12 @@ impl Component for Audio {
         match msg {
             AudioMsg::Load {
                 src,
-                title,
+                track,
                 elapsed,
             } => {
-                // Set the audio source and title
+                // Set the audio source and what the OS media controls show
                 GlobalAudio::set_source(&src);
-                MediaSessionState::set_title(&title);
+                MediaSessionState::set_track(&track);
 
                 // Register the closure that runs whenever the audio's source is loaded
                 let link = ctx.link().clone();
@@ -289,6 +377,29 @@ impl Component for Audio {
                 self.audio_elem_cbs._canplay_cb = Some(cb);
             }
 
+            AudioMsg::LoadUrl { src, title } => {
+                // Streamed audio always starts from the beginning, so there's no elapsed time to
+                // set. Make sure a pending seek from a previous load doesn't fire.
+                if let Some(cb) = self.audio_elem_cbs._canplay_cb.take() {
+                    GlobalAudio::remove_canplay_cb(&cb);
+                }
+
+                // Stream through MSE where possible, so playback starts with the first chunk
+                let src = if stream_source::is_supported() {
+                    stream_source::open(src.clone()).unwrap_or_else(|e| {
+                        tracing::warn!("Couldn't stream through MSE, using the URL instead: {e}");
+                        src
+                    })
+                } else {
+                    src
+                };
+                GlobalAudio::set_source_url(&src);
+                MediaSessionState::set_track(&TrackInfo {
+                    title,
+                    ..Default::default()
+                });
+            }
+
             AudioMsg::Play => {
                 spawn_local(async move {
                     GlobalAudio::play().await;
@@ -296,11 +407,11 @@ impl Component for Audio {
             }
 
             AudioMsg::JumpForward => {
-                GlobalAudio::jump_offset(DEFAULT_JUMP_SIZE);
+                GlobalAudio::jump_forward();
             }
 
             AudioMsg::JumpBackward => {
-                GlobalAudio::jump_offset(-DEFAULT_JUMP_SIZE);
+                GlobalAudio::jump_backward();
             }
 
             AudioMsg::_SetElapsed(elapsed) => {
@@ -313,6 +424,14 @@ impl Component for Audio {
                         .expect("got _SetElapsed from outside a canplay callback"),
                 );
 
+                // An article that was listened to the end starts over
+                let duration = GlobalAudio::get_duration();
+                let elapsed = if duration.is_finite() && elapsed >= duration - RESTART_MARGIN {
+                    0.0
+                } else {
+                    elapsed
+                };
+
                 // Seek to the desired position and update the MediaSession scrubber
                 GlobalAudio::seek(elapsed);
             }
@@ -331,9 +450,18 @@ impl Component for Audio {
         false
     }
 
-    fn view(&self, _ctx: &Context<Self>) -> Html {
+    fn view(&self, ctx: &Context<Self>) -> Html {
         html! {
-            <audio controls=true style={ "display: block;" } id={AUDIO_ELEM_ID}>
+            <audio
+                controls=true
+                style={ "display: block;" }
+                id={AUDIO_ELEM_ID}
+                onended={ ctx.props().on_ended.clone() }
+                ontimeupdate={ ctx.props().on_timeupdate.clone() }
+                onseeking={ ctx.props().on_seeking.clone() }
+                onplay={ ctx.props().on_play.clone() }
+                onpause={ ctx.props().on_pause.clone() }
+            >
                 { "Your browser does not support the <code>audio</code> element" }
             </audio>
         }
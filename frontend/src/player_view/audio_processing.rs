@@ -0,0 +1,306 @@
+//! Optional processing of the audio as it plays: skipping through silences, and evening out the
+//! volume. Both work by routing the <audio> element through a Web Audio graph. The graph is made the
+//! first time the audio plays with either option on, and since an element can only be routed once,
+//! it stays for the life of the page. With both options off it passes the audio straight through.
+//!
+//! Silences are skipped by speeding through them rather than seeking past them, since there's no
+//! telling how long a silence is until it's over. The audio's level is checked on a timer, and a
+//! silence is only sped through once it's gone on long enough to be more than a natural pause.
+//! Timers are slowed down in hidden tabs, which would mean speeding through the start of the next
+//! sentence, so silences aren't skipped when the checks come too far apart.
+
+use super::{Player, PlayerMsg};
+use crate::clock::{self, Timer};
+
+use std::cell::{Cell, RefCell};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    AnalyserNode, AudioContext, AudioContextState, AudioNode, DynamicsCompressorNode, GainNode,
+    HtmlMediaElement, MediaElementAudioSourceNode,
+};
+use yew::{html::Scope, prelude::*};
+
+/// How often the audio's level is checked for silence, in milliseconds
+const SILENCE_CHECK_MS: u32 = 50;
+
+/// If the checks come further apart than this, in milliseconds, they're too slow to catch the end
+/// of a silence in time, so silences aren't skipped
+const MAX_CHECK_GAP_MS: f64 = 200.0;
+
+/// Audio quieter than this, in RMS amplitude, counts as silence. This is about -46 dBFS.
+const SILENCE_LEVEL: f32 = 0.005;
+
+/// Silences shorter than this, in seconds of audio, are left alone, so sentences still have a
+/// pause between them
+const MIN_SILENCE_SECS: f64 = 0.4;
+
+/// How many times faster than the listener's speed silences are played
+const SILENCE_SPEEDUP: f64 = 4.0;
+
+/// Browsers don't play faster than this
+const MAX_PLAYBACK_RATE: f64 = 16.0;
+
+/// How much the volume is boosted after compression, which makes quiet voices about as loud as
+/// the loud ones
+const MAKEUP_GAIN: f32 = 1.8;
+
+/// How the audio is processed as it plays
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ProcessingOptions {
+    /// Whether to speed through long silences
+    #[serde(default)]
+    pub(crate) skip_silence: bool,
+    /// Whether to even out the volume with a compressor
+    #[serde(default)]
+    pub(crate) normalize_volume: bool,
+}
+
+impl ProcessingOptions {
+    /// Returns whether anything is processed
+    fn any(&self) -> bool {
+        self.skip_silence || self.normalize_volume
+    }
+}
+
+/// The Web Audio graph the <audio> element is routed through
+struct Graph {
+    ctx: AudioContext,
+    source: MediaElementAudioSourceNode,
+    /// Measures the level of the audio for skipping silence. It isn't connected to the output.
+    analyser: AnalyserNode,
+    compressor: DynamicsCompressorNode,
+    makeup: GainNode,
+}
+
+/// Where the silence check is at
+#[derive(Default)]
+struct SilenceState {
+    /// The audio's time when the current silence started, if it's silent
+    silent_since: Option<f64>,
+    /// When the last check was, in milliseconds since the Unix epoch
+    last_check: f64,
+    /// Whether the audio is being sped through a silence
+    speeding: bool,
+}
+
+thread_local! {
+    static OPTIONS: Cell<ProcessingOptions> = Cell::new(ProcessingOptions::default());
+    static GRAPH: RefCell<Option<Graph>> = RefCell::new(None);
+    static SILENCE_CHECK: RefCell<Option<Timer>> = RefCell::new(None);
+    static SILENCE_STATE: RefCell<SilenceState> = RefCell::new(SilenceState::default());
+}
+
+/// Returns the <audio> element the player plays through
+fn audio_elem() -> Option<HtmlMediaElement> {
+    gloo_utils::document()
+        .get_element_by_id(super::audio_component::AUDIO_ELEM_ID)?
+        .dyn_into()
+        .ok()
+}
+
+/// Makes the graph and routes the <audio> element through it
+fn make_graph() -> Result<Graph, JsValue> {
+    let elem = audio_elem().ok_or_else(|| JsValue::from_str("there's no <audio> element"))?;
+    let ctx = AudioContext::new()?;
+    let source = ctx.create_media_element_source(&elem)?;
+
+    let analyser = ctx.create_analyser()?;
+    analyser.set_fft_size(2048);
+
+    // A gentle compressor, like the ones podcast apps use to even out voices
+    let compressor = ctx.create_dynamics_compressor()?;
+    compressor.threshold().set_value(-30.0);
+    compressor.knee().set_value(20.0);
+    compressor.ratio().set_value(6.0);
+    compressor.attack().set_value(0.005);
+    compressor.release().set_value(0.25);
+    let makeup = ctx.create_gain()?;
+    makeup.gain().set_value(MAKEUP_GAIN);
+
+    Ok(Graph {
+        ctx,
+        source,
+        analyser,
+        compressor,
+        makeup,
+    })
+}
+
+/// Connects the graph up for the given options
+fn connect(graph: &Graph, options: ProcessingOptions) -> Result<(), JsValue> {
+    for node in [
+        graph.source.unchecked_ref::<AudioNode>(),
+        &graph.compressor,
+        &graph.makeup,
+    ] {
+        node.disconnect()?;
+    }
+
+    let destination = graph.ctx.destination();
+    graph.source.connect_with_audio_node(&graph.analyser)?;
+    if options.normalize_volume {
+        graph
+            .source
+            .connect_with_audio_node(&graph.compressor)?
+            .connect_with_audio_node(&graph.makeup)?
+            .connect_with_audio_node(&destination)?;
+    } else {
+        graph.source.connect_with_audio_node(&destination)?;
+    }
+    Ok(())
+}
+
+/// Sets how the audio is processed. The graph isn't made until the audio next plays, since it can
+/// only start running when the listener does something.
+pub(crate) fn set_options(options: ProcessingOptions) {
+    OPTIONS.with(|o| o.set(options));
+    GRAPH.with(|graph| {
+        if let Some(graph) = graph.borrow().as_ref() {
+            if let Err(e) = connect(graph, options) {
+                tracing::error!("Couldn't connect the audio processing: {:?}", e);
+            }
+        }
+    });
+
+    // Stop speeding through a silence if skipping is off now
+    if !options.skip_silence {
+        stop_speeding();
+    }
+    SILENCE_CHECK.with(|check| {
+        let mut check = check.borrow_mut();
+        match (options.skip_silence, check.is_some()) {
+            (true, false) => *check = Some(clock::interval(SILENCE_CHECK_MS, check_silence)),
+            (false, true) => *check = None,
+            _ => (),
+        }
+    });
+}
+
+/// Starts the processing, making the graph if it hasn't been made yet. This has to be called when
+/// the audio plays, since browsers only let audio processing start when the listener does
+/// something.
+pub(crate) fn start() {
+    let options = OPTIONS.with(Cell::get);
+    GRAPH.with(|graph| {
+        let mut graph = graph.borrow_mut();
+        if graph.is_none() && options.any() {
+            match make_graph().and_then(|g| connect(&g, options).map(|()| g)) {
+                Ok(g) => *graph = Some(g),
+                Err(e) => tracing::error!("Couldn't start the audio processing: {:?}", e),
+            }
+        }
+
+        // The audio is silent while the graph is suspended, so always resume it
+        if let Some(graph) = graph.as_ref() {
+            if graph.ctx.state() != AudioContextState::Running {
+                let _ = graph.ctx.resume();
+            }
+        }
+    });
+}
+
+/// Returns the RMS level of the audio the graph is playing, if it's playing any
+fn current_level() -> Option<f32> {
+    GRAPH.with(|graph| {
+        let graph = graph.borrow();
+        let analyser = &graph.as_ref()?.analyser;
+        let mut samples = vec![0.0f32; analyser.fft_size() as usize];
+        analyser.get_float_time_domain_data(&mut samples);
+        let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        Some(mean_square.sqrt())
+    })
+}
+
+/// Puts the audio back to the listener's speed, if it's speeding through a silence
+fn stop_speeding() {
+    SILENCE_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.silent_since = None;
+        if std::mem::take(&mut state.speeding) {
+            if let Some(elem) = audio_elem() {
+                elem.set_playback_rate(elem.default_playback_rate());
+            }
+        }
+    });
+}
+
+/// Checks the audio's level, and speeds through it if it's been silent long enough, or slows back
+/// down if it's not silent anymore
+fn check_silence() {
+    let Some(elem) = audio_elem() else {
+        return;
+    };
+    let now = clock::now();
+    let last_check = SILENCE_STATE.with(|s| std::mem::replace(&mut s.borrow_mut().last_check, now));
+    let level = current_level();
+
+    let checks_too_slow = now - last_check > MAX_CHECK_GAP_MS;
+    let is_silent = level.is_some_and(|l| l < SILENCE_LEVEL);
+    if elem.paused() || checks_too_slow || !is_silent {
+        stop_speeding();
+        return;
+    }
+
+    SILENCE_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let elapsed = elem.current_time();
+        let silent_since = *state.silent_since.get_or_insert(elapsed);
+        if !state.speeding && elapsed - silent_since >= MIN_SILENCE_SECS {
+            state.speeding = true;
+            let rate = (elem.default_playback_rate() * SILENCE_SPEEDUP).min(MAX_PLAYBACK_RATE);
+            elem.set_playback_rate(rate);
+        }
+    });
+}
+
+/// Renders the toggles for the audio processing
+pub(crate) fn render_processing_settings(
+    player_link: &Scope<Player>,
+    options: ProcessingOptions,
+) -> Html {
+    let toggle_skip = player_link.callback(move |_| {
+        PlayerMsg::SetAudioProcessing(ProcessingOptions {
+            skip_silence: !options.skip_silence,
+            ..options
+        })
+    });
+    let toggle_normalize = player_link.callback(move |_| {
+        PlayerMsg::SetAudioProcessing(ProcessingOptions {
+            normalize_volume: !options.normalize_volume,
+            ..options
+        })
+    });
+
+    html! {
+        <>
+            <p>
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={ options.skip_silence }
+                        onchange={toggle_skip}
+                    />
+                    { " Skip silences: speed through long pauses between paragraphs" }
+                </label>
+            </p>
+            <p>
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={ options.normalize_volume }
+                        onchange={toggle_normalize}
+                    />
+                    { " Even out volume: make quiet and loud voices about as loud" }
+                </label>
+            </p>
+            if options.any() {
+                <p>
+                    { "On iPhones and iPads, audio that's processed can stop when the screen is \
+                       locked. Silences aren't skipped while this page is in the background." }
+                </p>
+            }
+        </>
+    }
+}
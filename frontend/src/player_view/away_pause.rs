@@ -0,0 +1,98 @@
+//! Pausing playback when the listener has gone away, i.e., the page has been hidden and nobody has
+//! touched it for a while. This keeps a forgotten tab from playing through articles to an empty
+//! room, and marking them as listened to.
+
+use super::{Player, PlayerMsg};
+use crate::clock;
+
+use std::cell::Cell;
+
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::HtmlSelectElement;
+use yew::{html::Scope, prelude::*};
+
+/// The number of milliseconds between checks of whether the listener has gone away
+pub(crate) const AWAY_CHECK_FREQ: u32 = 30_000;
+
+/// The lengths of time without interaction after which playback can be paused, in minutes
+const AWAY_PAUSE_MINUTES: &[u32] = &[15, 30, 60, 120];
+
+/// The page events that count as the listener interacting with it
+const ACTIVITY_EVENTS: &[&str] = &["pointerdown", "keydown", "wheel", "visibilitychange"];
+
+thread_local! {
+    /// When the listener last interacted with the page, in milliseconds since the Unix epoch
+    static LAST_ACTIVITY: Cell<f64> = Cell::new(clock::now());
+}
+
+/// Records that the listener just interacted with the page
+pub(crate) fn record_activity() {
+    LAST_ACTIVITY.with(|last| last.set(clock::now()));
+}
+
+/// Returns whether the page is hidden and has gone the given number of minutes without any
+/// interaction
+pub(crate) fn is_away(minutes: u32) -> bool {
+    let idle_millis = clock::now() - LAST_ACTIVITY.with(Cell::get);
+    gloo_utils::document().hidden() && idle_millis >= f64::from(minutes) * 60_000.0
+}
+
+/// The listener that records every interaction with the page
+pub(crate) struct ActivityListener {
+    _cb: Closure<dyn Fn()>,
+}
+
+impl Default for ActivityListener {
+    /// Starts listening for interactions with the page
+    fn default() -> Self {
+        let cb = Closure::new(record_activity);
+        let document = gloo_utils::document();
+        for event in ACTIVITY_EVENTS {
+            if let Err(e) =
+                document.add_event_listener_with_callback(event, cb.as_ref().unchecked_ref())
+            {
+                tracing::error!("Could not listen for {event} events: {:?}", e);
+            }
+        }
+
+        ActivityListener { _cb: cb }
+    }
+}
+
+/// Renders the selector for how long the listener has to be away before playback is paused
+pub(crate) fn render_away_pause_selector(
+    player_link: &Scope<Player>,
+    minutes: Option<u32>,
+) -> Html {
+    let onchange = player_link.callback(|e: Event| {
+        let select: HtmlSelectElement = e.target_unchecked_into();
+        PlayerMsg::SetAwayPause(select.value().parse().ok())
+    });
+
+    let options: Html = AWAY_PAUSE_MINUTES
+        .iter()
+        .map(|&m| {
+            html! {
+                <option value={ m.to_string() } selected={minutes == Some(m)}>
+                    { format!("after {m} minutes") }
+                </option>
+            }
+        })
+        .collect();
+
+    html! {
+        <p>
+            <label for="away-pause-selector">
+                { "Pause when the tab is hidden and untouched: " }
+            </label>
+            <select
+                title="Pause when away"
+                id="away-pause-selector"
+                {onchange}
+            >
+                <option value="" selected={minutes.is_none()}>{ "never" }</option>
+                { options }
+            </select>
+        </p>
+    }
+}
@@ -0,0 +1,57 @@
+//! What the listener is offered when an article plays to the end and nothing else starts playing:
+//! say whether it was worth it, go on to the next article, take the finished one out of the queue,
+//! or hear it again.
+
+use super::{Player, PlayerMsg};
+use crate::{library_view, queue_view::QueueEntry};
+use common::Rating;
+
+use yew::{html::Scope, prelude::*};
+
+/// What the listener can do with the article that just ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EndAction {
+    /// Plays the article after it in the queue
+    PlayNext,
+    /// Takes it out of the queue. It's still in the library.
+    Remove,
+    /// Plays it again from the start
+    Replay,
+    /// Gives it the given rating, or takes its rating away. The actions stay up.
+    Rate(Option<Rating>),
+    /// Does nothing
+    Dismiss,
+}
+
+/// Renders the actions for the given article, which just ended and was given the given rating
+pub(crate) fn render_end_sheet(
+    player_link: &Scope<Player>,
+    entry: &QueueEntry,
+    rating: Option<Rating>,
+) -> Html {
+    let action = |action: EndAction| player_link.callback(move |_| PlayerMsg::EndOfArticle(action));
+    let rate = player_link.callback(|rating| PlayerMsg::EndOfArticle(EndAction::Rate(rating)));
+
+    html! {
+        <div class="endSheet" role="dialog" aria-label="Finished article">
+            <p>
+                <strong>{ "Finished: " }</strong>
+                <bdi>{ entry.title.clone() }</bdi>
+            </p>
+            <p>
+                { "Was it worth it? " }
+                { library_view::render_rating_buttons(&entry.title, rating, rate) }
+            </p>
+            <button onclick={action(EndAction::PlayNext)}>{ "Next article" }</button>
+            <button onclick={action(EndAction::Remove)}>{ "Remove from queue" }</button>
+            <button onclick={action(EndAction::Replay)}>{ "Play again" }</button>
+            <button
+                aria-label="Dismiss"
+                title="Dismiss"
+                onclick={action(EndAction::Dismiss)}
+            >
+                { "✕" }
+            </button>
+        </div>
+    }
+}
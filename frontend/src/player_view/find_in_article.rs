@@ -0,0 +1,277 @@
+//! Searches the text of the article that's playing, and seeks the audio to the matches. Where a
+//! passage is in the audio is worked out from the article's transcript, which says when each of its
+//! paragraphs starts.
+
+use super::audio_component::GlobalAudio;
+use crate::{caching, queue_view::ArticleId, utils};
+use common::ArticleTranscript;
+
+use anyhow::{bail, Error as AnyError};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// The most matches that are listed
+const MAX_MATCHES: usize = 20;
+
+/// The number of characters of context shown on either side of a match
+const MATCH_CONTEXT: usize = 40;
+
+/// Fetches the transcript of the given article, unless it's been saved already. Fetched transcripts
+/// are saved, so the article can be read and searched offline.
+pub(crate) async fn fetch_transcript(id: &ArticleId) -> Result<ArticleTranscript, AnyError> {
+    if let Ok(Some(transcript)) = caching::load_transcript(id).await {
+        return Ok(transcript);
+    }
+
+    let endpoint = format!("/api/article-transcript/{}", urlencoding::encode(&id.0));
+    let resp = utils::get(&endpoint)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Couldn't load the article's text"))?;
+
+    if resp.status() == 404 {
+        bail!(
+            "This article's text isn't available. Only articles added since texts started being \
+             kept can be searched."
+        );
+    }
+    if !resp.ok() {
+        bail!(
+            "Couldn't load the article's text {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+
+    let transcript = resp
+        .json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing the article's text"))?;
+    if let Err(e) = caching::save_transcript(id, &transcript).await {
+        tracing::error!("Couldn't save the transcript of {}: {:#}", id.0, e);
+    }
+
+    Ok(transcript)
+}
+
+/// Returns the character offsets of the case-insensitive matches of `query` in `text`
+fn find_matches(text: &[char], query: &str) -> Vec<usize> {
+    // Lowercase character by character, so offsets stay the same
+    let lower = |c: &char| c.to_lowercase().next().unwrap_or(*c);
+    let needle: Vec<char> = query.chars().map(|c| lower(&c)).collect();
+    let haystack: Vec<char> = text.iter().map(lower).collect();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| *window == needle.as_slice())
+        .map(|(i, _)| i)
+        .take(MAX_MATCHES)
+        .collect()
+}
+
+/// Returns the time in the audio at which the given character offset is spoken. Between
+/// timepoints, speech is assumed to go at a constant rate.
+pub(super) fn time_at(transcript: &ArticleTranscript, text_len: usize, offset: usize) -> f64 {
+    let tps = &transcript.timepoints;
+    let idx = tps.partition_point(|tp| tp.offset <= offset);
+
+    // The timepoints on either side of the offset. The start and end of the text bound the rest.
+    let (start_offset, start_secs) = match idx.checked_sub(1).map(|i| tps[i]) {
+        Some(tp) => (tp.offset, tp.secs),
+        None => (0, 0.0),
+    };
+    let (end_offset, end_secs) = match tps.get(idx) {
+        Some(tp) => (tp.offset, tp.secs),
+        None => (text_len, transcript.duration_secs),
+    };
+
+    if end_offset <= start_offset {
+        return start_secs;
+    }
+    let fraction = (offset - start_offset) as f64 / (end_offset - start_offset) as f64;
+    start_secs + fraction * (end_secs - start_secs)
+}
+
+/// Formats the given number of seconds as M:SS, or H:MM:SS if it's long enough
+pub(crate) fn format_time(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    let (h, m, s) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// The article that's playing, if any
+    pub article: Option<ArticleId>,
+}
+
+pub(crate) enum FindMsg {
+    /// Sets the text to find
+    SetQuery(String),
+    /// Sets the transcript of the given article, or the error from fetching it
+    SetTranscript(ArticleId, Result<ArticleTranscript, String>),
+    /// Seeks the audio to the given time
+    Seek(f64),
+}
+
+/// The search box for the playing article
+#[derive(Default)]
+pub(crate) struct FindInArticle {
+    /// The text to find
+    query: String,
+    /// The transcript of the article with the given ID, or the error from fetching it
+    transcript: Option<(ArticleId, Result<ArticleTranscript, String>)>,
+    /// The article whose transcript is being fetched, if any
+    fetching: Option<ArticleId>,
+}
+
+impl FindInArticle {
+    /// Fetches the transcript of the playing article, if there's something to search for and it
+    /// isn't already fetched or being fetched
+    fn fetch_if_needed(&mut self, ctx: &Context<Self>) {
+        let id = match &ctx.props().article {
+            Some(id) => id.clone(),
+            None => return,
+        };
+        let fetched = matches!(&self.transcript, Some((t_id, _)) if *t_id == id);
+        if self.query.is_empty() || fetched || self.fetching.as_ref() == Some(&id) {
+            return;
+        }
+
+        self.fetching = Some(id.clone());
+        ctx.link().send_future(async move {
+            let res = fetch_transcript(&id).await.map_err(|e| format!("{e:#}"));
+            FindMsg::SetTranscript(id, res)
+        });
+    }
+
+    /// Renders the matches of the query in the given transcript
+    fn render_matches(&self, ctx: &Context<Self>, transcript: &ArticleTranscript) -> Html {
+        let text: Vec<char> = transcript.text.chars().collect();
+        let query_len = self.query.chars().count();
+        let matches = find_matches(&text, &self.query);
+        if matches.is_empty() {
+            return html! { <p style="font-style: italic">{ "No matches" }</p> };
+        }
+
+        let items = matches
+            .into_iter()
+            .map(|offset| {
+                let secs = time_at(transcript, text.len(), offset);
+                let seek = ctx.link().callback(move |_| FindMsg::Seek(secs));
+
+                // Show the match with a little context, on one line
+                let before_start = offset.saturating_sub(MATCH_CONTEXT);
+                let after_end = (offset + query_len + MATCH_CONTEXT).min(text.len());
+                let snippet = |range: std::ops::Range<usize>| -> String {
+                    text[range]
+                        .iter()
+                        .map(|&c| if c == '\n' { ' ' } else { c })
+                        .collect()
+                };
+                let ellipsis = |show: bool| if show { "…" } else { "" };
+
+                html! {
+                    <li>
+                        <button
+                            onclick={seek}
+                            aria-label={ format!("Play from {}", format_time(secs)) }
+                        >
+                            { format_time(secs) }
+                        </button>
+                        { " " }
+                        // The snippet might be right-to-left, so keep it from reordering the time
+                        <bdi>
+                            { ellipsis(before_start > 0) }
+                            { snippet(before_start..offset) }
+                            <mark>{ snippet(offset..offset + query_len) }</mark>
+                            { snippet(offset + query_len..after_end) }
+                            { ellipsis(after_end < text.len()) }
+                        </bdi>
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        html! { <ul aria-label="Matches in the article">{ items }</ul> }
+    }
+}
+
+impl Component for FindInArticle {
+    type Message = FindMsg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            FindMsg::SetQuery(query) => {
+                self.query = query;
+                self.fetch_if_needed(ctx);
+            }
+            FindMsg::SetTranscript(id, res) => {
+                if self.fetching.as_ref() == Some(&id) {
+                    self.fetching = None;
+                }
+                self.transcript = Some((id, res));
+            }
+            FindMsg::Seek(secs) => {
+                GlobalAudio::seek(secs);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        // A different article might be playing now
+        self.fetch_if_needed(ctx);
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let article = match &ctx.props().article {
+            Some(id) => id,
+            None => return Html::default(),
+        };
+
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            FindMsg::SetQuery(input.value())
+        });
+
+        let results = match &self.transcript {
+            _ if self.query.is_empty() => Html::default(),
+            Some((id, Ok(transcript))) if id == article => self.render_matches(ctx, transcript),
+            Some((id, Err(e))) if id == article => html! {
+                <p style="color: red;" role="alert">{ e.clone() }</p>
+            },
+            _ => html! { <p style="font-style: italic">{ "Loading the article's text…" }</p> },
+        };
+
+        html! {
+            <details class="findInArticle">
+                <summary>{ "Find in article" }</summary>
+                <input
+                    type="search"
+                    aria-label="Text to find in the article"
+                    placeholder="Find where they said…"
+                    value={ self.query.clone() }
+                    {oninput}
+                />
+                { results }
+            </details>
+        }
+    }
+}
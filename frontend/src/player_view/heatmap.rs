@@ -0,0 +1,99 @@
+//! How many times each part of an article has been played, repeats included. The waveform strip
+//! shades the parts that were played more darker, and an article only counts as finished once most
+//! of it has actually been heard, rather than as soon as the position reaches the end.
+
+use crate::queue_view::ArticleId;
+use common::LOUDNESS_STEP_SECS;
+
+use serde::{Deserialize, Serialize};
+
+/// How much of an article has to have been heard for it to count as finished
+const FINISHED_COVERAGE: f64 = 0.9;
+
+/// Without play counts, an article counts as finished once its position is this many seconds from
+/// the end
+const FINISHED_MARGIN_SECS: f64 = 1.0;
+
+/// The number of times each part of an article has been played
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayCounts {
+    /// The ID of the article
+    pub(crate) id: ArticleId,
+    /// How many times every `LOUDNESS_STEP_SECS` of the article has been played, up to 255. These
+    /// line up with the levels of the article's loudness.
+    pub(crate) counts: Vec<u8>,
+}
+
+impl PlayCounts {
+    /// Makes the play counts of an article that hasn't been played
+    pub(crate) fn new(id: ArticleId) -> PlayCounts {
+        PlayCounts {
+            id,
+            counts: Vec::new(),
+        }
+    }
+
+    /// Counts the audio from `from` to `to`, in seconds, as played. A part is counted when the
+    /// position passes its start. Returns whether any count changed.
+    pub(crate) fn record(&mut self, from: f64, to: f64) -> bool {
+        if from.is_nan() || to.is_nan() || to <= from || from < 0.0 {
+            return false;
+        }
+        let first = (from / LOUDNESS_STEP_SECS).ceil() as usize;
+        let end = (to / LOUDNESS_STEP_SECS).ceil() as usize;
+        if first >= end {
+            return false;
+        }
+
+        if self.counts.len() < end {
+            self.counts.resize(end, 0);
+        }
+        for count in &mut self.counts[first..end] {
+            *count = count.saturating_add(1);
+        }
+        true
+    }
+
+    /// Returns how much of audio of the given length has been played at least once, from 0 to 1
+    pub(crate) fn coverage(&self, duration: f64) -> f64 {
+        let parts = (duration / LOUDNESS_STEP_SECS).ceil();
+        if parts.is_nan() || parts <= 0.0 {
+            return 0.0;
+        }
+        let played = self.counts.iter().filter(|&&count| count > 0).count();
+        (played as f64 / parts).min(1.0)
+    }
+}
+
+/// Returns whether an article of the given length, at the given elapsed time, has been finished.
+/// With play counts, that's whether most of it has been heard. Without, it's whether the position
+/// reached the end. The duration is NaN if nothing is loaded, which doesn't count as finished.
+pub(crate) fn is_finished(plays: Option<&PlayCounts>, elapsed: f64, duration: f64) -> bool {
+    match plays {
+        Some(plays) => plays.coverage(duration) >= FINISHED_COVERAGE,
+        None => duration - elapsed <= FINISHED_MARGIN_SECS,
+    }
+}
+
+#[test]
+fn play_counting() {
+    let mut plays = PlayCounts::new(ArticleId("fish".to_string()));
+
+    // Listening from the start counts every part passed
+    assert!(plays.record(0.0, 0.3));
+    assert!(!plays.record(0.3, 0.45));
+    assert!(plays.record(0.45, 2.2));
+    assert_eq!(plays.counts, [1, 1, 1, 1, 1]);
+
+    // Going back and listening again counts again, and nothing is counted going backwards
+    assert!(plays.record(1.0, 1.6));
+    assert!(!plays.record(1.6, 0.2));
+    assert_eq!(plays.counts, [1, 1, 2, 2, 1]);
+
+    // Jumping to the end doesn't finish an article, but hearing most of it does
+    assert!(!is_finished(Some(&plays), 9.9, 10.0));
+    assert!(is_finished(None, 9.9, 10.0));
+    plays.record(2.2, 9.0);
+    assert!(is_finished(Some(&plays), 9.0, 10.0));
+    assert!(!is_finished(Some(&plays), 9.0, f64::NAN));
+}
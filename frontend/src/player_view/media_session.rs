@@ -1,4 +1,5 @@
-use super::audio_component::GlobalAudio;
+use super::{audio_component::GlobalAudio, away_pause::record_activity};
+use crate::{platform, queue_view::QueueEntry, servers};
 
 use wasm_bindgen::{
     closure::{Closure, IntoWasmClosure},
@@ -9,12 +10,34 @@ use web_sys::{
     MediaImage, MediaMetadata, MediaSession, MediaSessionAction, MediaSessionActionDetails,
 };
 
-/// Use the RTMS logo as the album image
-const ALBUM_IMAGE_URL: &str = "/assets/rtms-color-512x512.png";
+/// Use the RTMS logo as the album image of articles that don't have a lead image. It's relative to
+/// the app's base path.
+const ALBUM_IMAGE_URL: &str = "assets/rtms-color-512x512.png";
+
+/// What the OS media controls show about the playing article
+#[derive(Clone, Debug, Default)]
+pub struct TrackInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub publication: Option<String>,
+    /// The URL of the article's lead image
+    pub image: Option<String>,
+}
+
+impl From<&QueueEntry> for TrackInfo {
+    fn from(entry: &QueueEntry) -> TrackInfo {
+        TrackInfo {
+            title: entry.title.clone(),
+            author: entry.author.clone(),
+            publication: entry.publication.clone(),
+            image: entry.image.clone(),
+        }
+    }
+}
 
-/// Helper function to retrieve the MediaSession API
-fn get_media_session() -> MediaSession {
-    gloo_utils::window().navigator().media_session()
+/// Helper function to retrieve the MediaSession API, if there is one
+fn get_media_session() -> Option<MediaSession> {
+    platform::media_session()
 }
 
 /// These are the callbacks the browser calls when the user performs a MediaSession operation like
@@ -34,52 +57,57 @@ impl Default for MediaSessionCallbacks {
     /// cause the following controls to be displayed on the lockscreen: play/pause, jump back, jump
     /// forward, scrobble.
     fn default() -> Self {
+        // Playing and pausing from the lockscreen or headphones count as interacting with the page
         let _play_action = Closure::new(|| {
+            record_activity();
             spawn_local(async move {
                 GlobalAudio::play().await;
             })
         });
-        let _pause_action = Closure::new(|| GlobalAudio::pause());
+        let _pause_action = Closure::new(|| {
+            record_activity();
+            GlobalAudio::pause()
+        });
         let _seek_to_action = Closure::new(MediaSessionState::seek_to);
-        let _jump_forward_action = Closure::new(GlobalAudio::jump_forward);
-        let _jump_backward_action = Closure::new(GlobalAudio::jump_backward);
+        let _jump_forward_action = Closure::new(GlobalAudio::media_session_jump_forward);
+        let _jump_backward_action = Closure::new(GlobalAudio::media_session_jump_backward);
 
         // The next track button currently does nothing
         let _next_track_action = Closure::new(|| ());
         // The prev track button just resets the playback to the beginning
         let _prev_track_action = Closure::new(|| GlobalAudio::seek(0.0));
 
-        let media_session = get_media_session();
-
         // Set all the callbacks
-        media_session.set_action_handler(
-            MediaSessionAction::Play,
-            Some(_play_action.as_ref().unchecked_ref()),
-        );
-        media_session.set_action_handler(
-            MediaSessionAction::Pause,
-            Some(_pause_action.as_ref().unchecked_ref()),
-        );
-        media_session.set_action_handler(
-            MediaSessionAction::Seekto,
-            Some(_seek_to_action.as_ref().unchecked_ref()),
-        );
-        media_session.set_action_handler(
-            MediaSessionAction::Seekforward,
-            Some(_jump_forward_action.as_ref().unchecked_ref()),
-        );
-        media_session.set_action_handler(
-            MediaSessionAction::Seekbackward,
-            Some(_jump_backward_action.as_ref().unchecked_ref()),
-        );
-        media_session.set_action_handler(
-            MediaSessionAction::Nexttrack,
-            Some(_next_track_action.as_ref().unchecked_ref()),
-        );
-        media_session.set_action_handler(
-            MediaSessionAction::Previoustrack,
-            Some(_prev_track_action.as_ref().unchecked_ref()),
-        );
+        if let Some(media_session) = get_media_session() {
+            media_session.set_action_handler(
+                MediaSessionAction::Play,
+                Some(_play_action.as_ref().unchecked_ref()),
+            );
+            media_session.set_action_handler(
+                MediaSessionAction::Pause,
+                Some(_pause_action.as_ref().unchecked_ref()),
+            );
+            media_session.set_action_handler(
+                MediaSessionAction::Seekto,
+                Some(_seek_to_action.as_ref().unchecked_ref()),
+            );
+            media_session.set_action_handler(
+                MediaSessionAction::Seekforward,
+                Some(_jump_forward_action.as_ref().unchecked_ref()),
+            );
+            media_session.set_action_handler(
+                MediaSessionAction::Seekbackward,
+                Some(_jump_backward_action.as_ref().unchecked_ref()),
+            );
+            media_session.set_action_handler(
+                MediaSessionAction::Nexttrack,
+                Some(_next_track_action.as_ref().unchecked_ref()),
+            );
+            media_session.set_action_handler(
+                MediaSessionAction::Previoustrack,
+                Some(_prev_track_action.as_ref().unchecked_ref()),
+            );
+        }
 
         MediaSessionCallbacks {
             _play_action,
@@ -102,10 +130,12 @@ impl MediaSessionCallbacks {
         self._prev_track_action = Closure::new(action);
 
         // Update the handler
-        get_media_session().set_action_handler(
-            MediaSessionAction::Previoustrack,
-            Some(self._prev_track_action.as_ref().unchecked_ref()),
-        );
+        if let Some(media_session) = get_media_session() {
+            media_session.set_action_handler(
+                MediaSessionAction::Previoustrack,
+                Some(self._prev_track_action.as_ref().unchecked_ref()),
+            );
+        }
     }
 
     /// Sets the action performed when the user clicks the "next track" button
@@ -116,10 +146,12 @@ impl MediaSessionCallbacks {
         self._next_track_action = Closure::new(action);
 
         // Update the handler
-        get_media_session().set_action_handler(
-            MediaSessionAction::Nexttrack,
-            Some(self._next_track_action.as_ref().unchecked_ref()),
-        );
+        if let Some(media_session) = get_media_session() {
+            media_session.set_action_handler(
+                MediaSessionAction::Nexttrack,
+                Some(self._next_track_action.as_ref().unchecked_ref()),
+            );
+        }
     }
 }
 
@@ -128,23 +160,41 @@ pub struct MediaSessionState;
 impl MediaSessionState {
     /// Clears the metadata of the session. This means nothing is playing
     pub fn clear() {
-        let media_session = get_media_session();
-        media_session.set_metadata(None);
+        if let Some(media_session) = get_media_session() {
+            media_session.set_metadata(None);
+        }
     }
 
-    /// Sets the MediaSession title of the currently playing track
-    pub fn set_title(title: &str) {
-        let media_session = get_media_session();
+    /// Sets the MediaSession metadata of the currently playing track. Like a podcast player, the
+    /// artist is the author, or the publication if the author isn't known, and the album is the
+    /// publication.
+    pub fn set_track(track: &TrackInfo) {
+        let Some(media_session) = get_media_session() else {
+            return;
+        };
 
-        // Set the title
         let metadata = MediaMetadata::new().unwrap();
-        metadata.set_title(&title);
+        metadata.set_title(&track.title);
+        if let Some(artist) = track.author.as_ref().or(track.publication.as_ref()) {
+            metadata.set_artist(artist);
+        }
+        if let Some(publication) = &track.publication {
+            metadata.set_album(publication);
+        }
 
-        // Set the artwork. It's an array consisting of just 1 image
+        // Set the artwork. It's an array consisting of just 1 image, the article's if it has one
         let artwork = js_sys::Array::new_with_length(1);
-        let mut image = MediaImage::new(ALBUM_IMAGE_URL);
+        let mut image = match &track.image {
+            // Images the server saved are at its own paths, e.g., /api/images/...
+            Some(url) if url.starts_with('/') => MediaImage::new(&servers::api_url(url)),
+            Some(url) => MediaImage::new(url),
+            None => {
+                let mut image = MediaImage::new(ALBUM_IMAGE_URL);
+                image.type_("image/png");
+                image
+            }
+        };
         image.sizes("any");
-        image.type_("image/png");
         artwork.set(0, image.into());
         metadata.set_artwork(&artwork);
 
@@ -173,7 +223,7 @@ impl MediaSessionState {
             // If "fast seek" is set, us that method
             match fast_seek {
                 Ok(Some(true)) => GlobalAudio::fast_seek(time),
-                _ => GlobalAudio::seek(time),
+                _ => GlobalAudio::scrub(time),
             }
         } else if let Ok(Some(off)) = seek_offset {
             GlobalAudio::jump_offset(off);
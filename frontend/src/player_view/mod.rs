@@ -1,13 +1,64 @@
+mod ask;
 mod audio_component;
+mod audio_processing;
+mod away_pause;
+mod end_of_article;
+mod find_in_article;
+mod heatmap;
 mod media_session;
+mod position_pick;
+mod read_along;
+mod recap;
+mod reload_resume;
+mod remote_control;
+mod save_schedule;
+mod sections;
+mod shortcuts;
+mod sleep_timer;
+mod speed_advice;
+mod speed_training;
+mod stream_source;
+mod tab_sync;
+mod text_export;
+mod waveform;
+
+pub(crate) use find_in_article::{fetch_transcript, format_time};
+pub(crate) use heatmap::PlayCounts;
+pub(crate) use speed_advice::SpeedSample;
 
 use crate::{
+    battery::{self, BatteryMonitor},
     caching,
+    clock::{self, Timer},
+    history_view::{self, ListeningSession},
+    moments_view::Moment,
+    position_conflicts::{self, PositionConflict},
     queue_view::{ArticleId, Queue, QueueEntry, QueueMsg},
-    utils, WeakComponentLink,
+    WeakComponentLink,
 };
-use audio_component::{Audio, AudioMsg, GlobalAudio};
-use media_session::MediaSessionCallbacks;
+use ask::AskAboutArticle;
+use audio_component::{Audio, AudioMsg, GlobalAudio, DEFAULT_JUMP_SIZE};
+use audio_processing::ProcessingOptions;
+use away_pause::{ActivityListener, AWAY_CHECK_FREQ};
+use common::{
+    ArticleGroup, ArticleTranscript, PlayerStatus, Rating, RemoteCommand, RemoteCommands,
+};
+use end_of_article::EndAction;
+use find_in_article::FindInArticle;
+use media_session::{MediaSessionCallbacks, TrackInfo};
+use read_along::{ReadAlong, ReadAlongMsg};
+use recap::RecapMode;
+use reload_resume::{GestureListener, RELOAD_WINDOW_MS};
+use remote_control::REMOTE_POLL_FREQ;
+use save_schedule::{schedule_periodic_save, schedule_seek_save};
+use shortcuts::{Shortcut, ShortcutListener};
+use sleep_timer::{SleepTimer, SLEEP_TIMER_TICK_FREQ};
+use speed_training::{Comprehension, SpeedTraining};
+use tab_sync::{TabChannel, TabMessage};
+use text_export::TextExport;
+use waveform::{Waveform, WaveformMsg};
+
+use std::{rc::Rc, time::Duration};
 
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::{closure::Closure, JsCast};
@@ -17,40 +68,87 @@ use yew::{html::Scope, prelude::*};
 
 const SPEED_SELECTOR_ID: &str = "speed-selector";
 
-// The number of milliseconds between times saving Player state
-const PLAYER_STATE_SAVE_FREQ: i32 = 10000;
+/// The number of milliseconds to wait for the tab that's playing to hand over to this one
+const TAKE_OVER_TIMEOUT: u32 = 2000;
+
+/// Time updates further apart than this, in seconds of audio, came from a jump rather than from
+/// listening, so they don't count toward the listening session
+const MAX_LISTENED_STEP_SECS: f64 = 5.0;
+
+/// How long the player says a moment was marked, in milliseconds
+const MOMENT_MARKED_DELAY: u32 = 3000;
+
+/// Seeks with the scrubber that jump at least this many seconds can be undone
+const UNDOABLE_JUMP_SECS: f64 = 60.0;
 
 /// All the playback speeds we support
 const PLAYBACK_SPEEDS: &[f64] = &[0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0, 2.5, 3.0, 4.0];
 
-/// Loads the given article and its playback state, and sets the <audio>'s src to the MP3 blob.
-/// Returns the desired elapsed time for the article.
-async fn prepare_for_play(id: &ArticleId, audio_link: &Scope<Audio>) -> f64 {
+/// Loads the article in the given entry and its playback state, and sets the <audio>'s src to the
+/// MP3 blob. It starts at `start` if that's given, or else where it was left. Returns the desired
+/// elapsed time for the article, and the article's transcript.
+async fn prepare_for_play(
+    entry: &QueueEntry,
+    audio_link: &Scope<Audio>,
+    start: Option<f64>,
+) -> (f64, Rc<ArticleTranscript>) {
+    let id = &entry.id;
     // Load the article state and set the elapsed time.
-    let elapsed = match caching::load_article_state(&id).await {
-        Ok(state) => state.elapsed,
-        Err(e) => {
-            tracing::debug!("Article state did not load {}: {}", id.0, e);
-            0.0
-        }
+    let elapsed = match start {
+        Some(start) => start,
+        None => match caching::load_article_state(&id).await {
+            Ok(state) => state.elapsed,
+            Err(e) => {
+                tracing::debug!("Article state did not load {}: {}", id.0, e);
+                0.0
+            }
+        },
     };
 
     // Load the article and set the <audio> src to it
-    match caching::load_article(&id).await {
+    let transcript = match caching::load_article(&id).await {
         Ok(article) => {
-            let mp3_blob = utils::bytes_to_mp3_blob(&article.audio_blob);
             audio_link.send_message(AudioMsg::Load {
-                src: mp3_blob,
-                title: article.title,
+                src: article.audio(),
+                track: TrackInfo {
+                    title: article.title,
+                    ..TrackInfo::from(entry)
+                },
                 elapsed,
             });
+            article.transcript
         }
         Err(e) => {
             tracing::error!("Couldn't load article {}: {}", id.0, e);
+            ArticleTranscript::default()
         }
-    }
+    };
+
+    // Articles saved before transcripts were kept don't have their text. Try getting it now.
+    let transcript = if transcript.text.is_empty() {
+        fetch_transcript(id).await.unwrap_or_else(|e| {
+            tracing::debug!("No transcript for {}: {:#}", id.0, e);
+            transcript
+        })
+    } else {
+        transcript
+    };
+
+    (elapsed, Rc::new(transcript))
+}
+
+/// Computes how far the listener is through the whole of the given group, as a percentage. The
+/// parts of a group are about equally long, so each counts for the same share.
+fn group_progress_pct(group: &ArticleGroup) -> u32 {
+    let duration = GlobalAudio::get_duration();
+    let part_fraction = if duration.is_finite() && duration > 0.0 {
+        GlobalAudio::get_elapsed() / duration
+    } else {
+        0.0
+    };
 
-    elapsed
+    let parts_done = group.part.saturating_sub(1) as f64;
+    (100.0 * (parts_done + part_fraction) / group.parts as f64).floor() as u32
 }
 
 /// Returns the combobox used to select playback speed
@@ -68,6 +166,19 @@ fn get_selected_playback_speed() -> f64 {
     speed_selector.value().parse().unwrap_or(1.0)
 }
 
+/// Loads how many times each part of the given article has been played. Articles that haven't
+/// been played start from nothing.
+async fn load_play_counts(id: &ArticleId) -> PlayCounts {
+    match caching::load_play_counts(id).await {
+        Ok(Some(plays)) => plays,
+        Ok(None) => PlayCounts::new(id.clone()),
+        Err(e) => {
+            tracing::error!("Couldn't load play counts of {}: {}", id.0, e);
+            PlayCounts::new(id.clone())
+        }
+    }
+}
+
 /// Sets the playback speed of the <audio> tag and updates the speed selection combobox
 fn set_playback_speed(speed: f64, audio_link: &Scope<Audio>) {
     // Set the audio's playback speed
@@ -89,6 +200,73 @@ fn trigger_save(periodic: bool, player: &Scope<Player>) {
     player.send_message(PlayerMsg::SaveState { elapsed, periodic });
 }
 
+/// Sets off the next periodic save. Each periodic save schedules the next.
+fn schedule_save(player: &Scope<Player>, battery_low: bool) -> Timer {
+    let player = player.clone();
+    schedule_periodic_save(battery_low, move || trigger_save(true, &player))
+}
+
+/// Listens for the page being hidden or closed, and tells the player to save its state when it is.
+/// Otherwise, closing the tab would lose everything since the last periodic save.
+struct HideListener {
+    _cb: Closure<dyn Fn(Event)>,
+}
+
+impl HideListener {
+    fn new(player: Scope<Player>) -> HideListener {
+        let cb = Closure::new(move |e: Event| {
+            // The page becoming visible again isn't a reason to save
+            if e.type_() == "visibilitychange" && !gloo_utils::document().hidden() {
+                return;
+            }
+            player.send_message(PlayerMsg::PageHidden);
+        });
+
+        let func = cb.as_ref().unchecked_ref();
+        let window_res = gloo_utils::window().add_event_listener_with_callback("pagehide", func);
+        let document_res =
+            gloo_utils::document().add_event_listener_with_callback("visibilitychange", func);
+        if let Err(e) = window_res.and(document_res) {
+            tracing::error!("Could not listen for the page being hidden: {:?}", e);
+        }
+
+        HideListener { _cb: cb }
+    }
+}
+
+/// Saves how far the listener got in the given article, so that it resumes from there the next
+/// time it's played. This is for when the player moves away from the article, and the periodic
+/// save might be up to PLAYER_STATE_SAVE_FREQ milliseconds stale.
+fn save_position(entry: &QueueEntry, plays: Option<&PlayCounts>) {
+    let elapsed = GlobalAudio::get_elapsed();
+    // Nothing's been loaded yet if the elapsed time is 0. Don't overwrite the saved position.
+    if elapsed == 0.0 {
+        return;
+    }
+
+    let state = ArticleState::new(entry.id.clone(), elapsed, plays);
+    spawn_local(async move {
+        let _ = caching::save_article_state(&state)
+            .await
+            .map_err(|e| tracing::error!("Couldn't save position in {}: {}", state.id.0, e));
+    });
+}
+
+/// Returns whether the audio is playing
+pub(crate) fn is_playing() -> bool {
+    GlobalAudio::is_playing()
+}
+
+/// Records the speed the given article is being listened at, for suggesting speeds later
+fn record_speed(entry: &QueueEntry, speed: f64) {
+    let sample = SpeedSample::new(entry, speed);
+    spawn_local(async move {
+        let _ = caching::save_speed_sample(&sample)
+            .await
+            .map_err(|e| tracing::error!("Couldn't save listening speed: {}", e));
+    });
+}
+
 #[derive(PartialEq, Properties)]
 pub(crate) struct Props {
     /// A link to myself. We have to set this on creation
@@ -101,12 +279,133 @@ pub(crate) enum PlayerMsg {
     /// Play the given article
     Play(QueueEntry),
 
+    /// Marks the current point of the article that's playing as a moment
+    MarkMoment,
+
+    /// Stops saying that a moment was marked
+    DismissMomentMarked,
+
+    /// Seek to the given time in the given article if it's playing, or else start there the next
+    /// time it's played. The listening history uses this to go back to something heard earlier.
+    PlayFrom { id: ArticleId, secs: f64 },
+
+    /// Play the audio of an article that's still being converted, streamed from the given URL. The
+    /// article isn't in the queue, so its playback state isn't saved.
+    PlayStream { url: String, title: String },
+
     /// Ask the queue for the previous track
     AskForPrevTrack,
 
     /// Ask the queue for the next track
     AskForNextTrack,
 
+    /// Skip to the start of the current section, or the previous one if the current one just
+    /// started. At the start of the article, this asks the queue for the previous track.
+    PrevSection,
+
+    /// Skip to the next section. In the last section, this asks the queue for the next track.
+    NextSection,
+
+    /// The transcript of the given article was loaded
+    SetTranscript {
+        id: ArticleId,
+        transcript: Rc<ArticleTranscript>,
+    },
+
+    /// How many times each part of an article has been played was loaded
+    SetPlayCounts(PlayCounts),
+
+    /// The current track played to the end. If it's part of a group, the next part is played.
+    /// Otherwise, if continuous playback is on, the next article in the queue is, or else the next
+    /// article from the auto-advance playlist, if there is one.
+    TrackEnded,
+
+    /// The listener picked what to do with the article that just ended
+    EndOfArticle(EndAction),
+
+    /// Turns continuous playback on or off
+    ToggleContinuousPlayback,
+
+    /// Offers the given speed for the article with the given ID, if it's still playing
+    SuggestSpeed { id: ArticleId, speed: f64 },
+
+    /// Sets the playback speed to the suggested one
+    ApplySuggestedSpeed,
+
+    /// Hides the speed suggestion
+    DismissSpeedSuggestion,
+
+    /// Starts speed training up to the given speed, or stops it if `None`
+    SetSpeedTraining(Option<f64>),
+
+    /// The listener said how much of the article that just ended they followed
+    CheckComprehension(Comprehension),
+
+    /// Hides the comprehension prompt without answering it
+    DismissComprehensionCheck,
+
+    /// Pauses playback after the given duration, fading the volume out just before
+    SetSleepTimer(Duration),
+
+    /// Pauses playback when the current article ends, instead of going on to the next one
+    SleepAtEndOfArticle,
+
+    /// Turns off the sleep timer
+    CancelSleepTimer,
+
+    /// Sets how many seconds the jump buttons jump backward and forward by
+    SetJumpSizes { backward: f64, forward: f64 },
+
+    /// Sets whether silences are skipped and the volume is evened out
+    SetAudioProcessing(ProcessingOptions),
+
+    /// The listener pressed the given keyboard shortcut
+    Shortcut(Shortcut),
+
+    /// Checks how long the sleep timer has left, fading out the volume or pausing playback if it's
+    /// time
+    SleepTimerTick,
+
+    /// Sets how many minutes the listener can be away before playback is paused, or turns pausing
+    /// off if `None`
+    SetAwayPause(Option<u32>),
+
+    /// Pauses playback if the listener has been away too long
+    CheckAway,
+
+    /// The elapsed time changed. Records the position, and updates the progress through the
+    /// current group, if any.
+    TimeUpdate,
+
+    /// The audio started seeking. If the seek wasn't made by the app, i.e., it's from the
+    /// scrubber, it's undone if the position is locked, or else offered to be undone if it's big.
+    Seeking,
+
+    /// Goes back to where the audio was before the last big seek
+    UndoSeek,
+
+    /// Hides the offer to undo the last big seek
+    DismissSeekUndo,
+
+    /// Locks or unlocks the position, i.e., whether seeking with the scrubber is allowed
+    ToggleLockPosition,
+
+    /// The audio started playing. Tells the other tabs, so they pause.
+    AudioPlayed,
+
+    /// The audio was paused. Saves the position and tells the other tabs, so one of them can take
+    /// over.
+    AudioPaused,
+
+    /// Another tab sent the given message
+    FromOtherTab(TabMessage),
+
+    /// Picks up from where another tab left off. Loads the saved state and plays it.
+    TakeOver,
+
+    /// The saved state was loaded for taking over from another tab
+    TookOver(PlayerState),
+
     /// Stops playback if a particular ID is playing. This is so that removing a playing item from
     /// the queue stops the current playback
     StopIfPlaying(ArticleId),
@@ -128,27 +427,194 @@ pub(crate) enum PlayerMsg {
         /// or not we reset the timer
         periodic: bool,
     },
+
+    /// The page was hidden or is being closed. Saves the state right away.
+    PageHidden,
+
+    /// Sets whether the battery is low enough that the player should do less in the background
+    SetBatteryLow(bool),
+
+    /// Turns the battery saver on or off
+    ToggleBatterySaver,
+
+    /// Turns remote control on or off
+    ToggleRemoteControl,
+
+    /// Reports the player's status to the server, and fetches the commands remotes sent
+    PollRemote,
+
+    /// The server sent the given remote commands
+    RemoteCommands(RemoteCommands),
+
+    /// Another device changed the playback speed to the given one at the given time
+    SetSyncedSpeed { speed: f64, updated: f64 },
+
+    /// Another device got further in the given articles. If the current one is among them and
+    /// it's paused, it skips ahead.
+    SetSyncedPositions(Vec<ArticleState>),
+
+    /// The given article was opened, and has a position from another device that conflicts with
+    /// this device's. Asks the listener which to continue from.
+    ShowPositionConflict(PositionConflict),
+
+    /// The listener picked the other device's position in the current article, or this device's
+    PickPosition { remote: bool },
+
+    /// The article that was playing before the page reloaded is loaded again, at the given elapsed
+    /// time. Offers to resume it.
+    OfferResume { id: ArticleId, elapsed: f64 },
+
+    /// Resumes the article that was playing before the page reloaded
+    ResumeAfterReload,
+
+    /// Hides the offer to resume after the reload
+    DismissResume,
+
+    /// The listener tapped the page. If they asked for it, this resumes after a reload.
+    PageTapped,
+
+    /// Turns resuming on the first tap after a reload on or off
+    ToggleResumeOnTap,
+
+    /// Sets what's recapped when an article's resumed after a break
+    SetRecapMode(RecapMode),
+
+    /// Speaks the given recap of the current article, then plays it
+    PlayRecap(String),
+
+    /// The recap finished or was skipped, so the article plays
+    RecapDone,
 }
 
 /// Holds the elapsed time in a given article
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ArticleState {
     /// The ID of the referenced article
-    id: ArticleId,
+    pub(crate) id: ArticleId,
     /// The elapsed time of the article, in seconds
-    elapsed: f64,
+    pub(crate) elapsed: f64,
+    /// Whether the elapsed time reached the end of the article
+    #[serde(default)]
+    pub(crate) finished: bool,
+    /// When the article was last played, in milliseconds since the Unix epoch
+    #[serde(default)]
+    pub(crate) last_played: f64,
+    /// The ID of the device that got to this position, if it came from another device by syncing.
+    /// It's `None` if this device got to it, or it was saved before devices were told apart.
+    #[serde(default)]
+    pub(crate) device: Option<String>,
+}
+
+impl ArticleState {
+    /// Makes the state of the given article, which is loaded in the <audio>, at the given elapsed
+    /// time. Whether it's finished goes by how much of it was played, if that's known.
+    fn new(id: ArticleId, elapsed: f64, plays: Option<&PlayCounts>) -> ArticleState {
+        let finished = heatmap::is_finished(plays, elapsed, GlobalAudio::get_duration());
+        ArticleState {
+            id,
+            elapsed,
+            finished,
+            last_played: clock::now(),
+            device: None,
+        }
+    }
 }
 
 /// The Player component of our app. This handles all the player logic.
 pub(crate) struct Player {
     /// A link to the player's <audio> component
     audio_link: WeakComponentLink<Audio>,
-    /// The closure that runs every PLAYER_STATE_SAVE_FREQ seconds saving the player state
-    _trigger_save_cb: Closure<dyn 'static + Fn()>,
+    /// The timer for the next periodic save of the player state
+    save_timer: Timer,
     /// Callbacks for the media session API
     _media_session_cbs: MediaSessionCallbacks,
     /// Holds all the serializable state of this player. This will be loaded from the IndexedDB
     state: PlayerState,
+    /// The title of the article being streamed, if any
+    streaming_title: Option<String>,
+    /// If the current article is part of a group, how far through the group the listener is, as a
+    /// percentage
+    group_progress: Option<u32>,
+    /// The playback speed suggested for the current article, if any
+    speed_suggestion: Option<f64>,
+    /// The article that was finished during speed training, and the speed it was played at, while
+    /// the listener hasn't said how much of it they followed
+    comprehension_check: Option<(QueueEntry, f64)>,
+    /// The article that just played to the end, while nothing else has started
+    ended: Option<QueueEntry>,
+    /// The rating the listener gave the article that just ended, if they gave one
+    ended_rating: Option<Rating>,
+    /// The listening session of the current article, once it's started playing
+    session: Option<ListeningSession>,
+    /// The timer that checks on the sleep timer every SLEEP_TIMER_TICK_FREQ milliseconds, while
+    /// it's set
+    sleep_timer_tick: Option<Timer>,
+    /// The number of minutes left on the sleep timer, if it's counting down
+    sleep_minutes_left: Option<u64>,
+    /// The transcript of the current article, which has its section markers. Empty if it doesn't
+    /// have one.
+    transcript: Rc<ArticleTranscript>,
+    /// A link to the pane that reads along with the audio
+    read_along_link: WeakComponentLink<ReadAlong>,
+    /// A link to the waveform strip, for telling it when the elapsed time and play counts change
+    waveform_link: WeakComponentLink<Waveform>,
+    /// How many times each part of the current article has been played. `None` until they're
+    /// loaded.
+    plays: Option<PlayCounts>,
+    /// The article that should start at the given time, rather than where it was left, the next
+    /// time it's played
+    play_from: Option<(ArticleId, f64)>,
+    /// Where in the article the last moment was marked, while the player says so, and the timer
+    /// that stops it saying so
+    moment_marked: Option<(f64, Timer)>,
+    /// The elapsed time when the audio last reported it, i.e., the position before any seek that's
+    /// in progress
+    last_position: f64,
+    /// The last big seek with the scrubber, as the positions it went from and to, if it can be
+    /// undone
+    seek_undo: Option<(f64, f64)>,
+    /// The timeout after which the position is saved following a seek, if one is pending
+    seek_save: Option<Timer>,
+    /// The channel to the Players in other tabs
+    tabs: Rc<TabChannel>,
+    /// Whether another tab is playing
+    playing_elsewhere: bool,
+    /// Whether another tab has played since this one loaded its state. A stale tab doesn't save its
+    /// state, so it doesn't overwrite the other tab's.
+    stale: bool,
+    /// If this tab is waiting for the playing tab to save its position, to take over from it, the
+    /// timeout after which it stops waiting
+    taking_over: Option<Timer>,
+    /// The volume before the sleep timer started fading it out, if it has
+    volume_before_fade: Option<f64>,
+    /// Records when the listener last interacted with the page
+    _activity_listener: ActivityListener,
+    /// Saves the state when the page is hidden or closed
+    _hide_listener: HideListener,
+    /// Turns key presses into keyboard shortcuts
+    _shortcut_listener: ShortcutListener,
+    /// Whether the battery is low, and the battery saver is on
+    battery_low: bool,
+    /// Keeps `battery_low` up to date
+    _battery_monitor: BatteryMonitor,
+    /// The timer that checks whether the listener has gone away every AWAY_CHECK_FREQ
+    /// milliseconds, while pausing when away is on
+    away_check: Option<Timer>,
+    /// The timer that polls for remote commands every REMOTE_POLL_FREQ milliseconds, while remote
+    /// control is on
+    remote_poll: Option<Timer>,
+    /// The number of the latest remote command, once the server has said what it is
+    remote_seq: Option<u64>,
+    /// The elapsed time of the article that was playing before the page reloaded, while resuming
+    /// it is on offer
+    resume_offer: Option<f64>,
+    /// A position from another device that conflicts with this device's in the current article,
+    /// while the listener hasn't picked between them
+    position_conflict: Option<PositionConflict>,
+    /// The recap of the current article that's being spoken before it plays, if any
+    recap: Option<String>,
+    /// Tells the player about taps, for resuming on the first one
+    _gesture_listener: GestureListener,
 }
 
 /// Holds what's playing, how long it's been playing, and how fast
@@ -157,7 +623,59 @@ pub struct PlayerState {
     /// Handle and title of the currently playing article
     now_playing: Option<QueueEntry>,
     /// The audio playback speed, as a percentage
-    playback_speed: f64,
+    pub(crate) playback_speed: f64,
+    /// When the listener last changed the playback speed, in milliseconds since the Unix epoch, or
+    /// when the device it was synced from did. 0 if it hasn't changed since syncing began.
+    #[serde(default)]
+    pub(crate) speed_updated: f64,
+    /// Whether to go on to the next article when the current one ends
+    #[serde(default = "default_continuous_playback")]
+    continuous_playback: bool,
+    /// Whether seeking with the scrubber is undone, and the Player's seek buttons are disabled
+    #[serde(default)]
+    lock_position: bool,
+    /// When to pause playback, if a sleep timer is set
+    #[serde(default)]
+    sleep_timer: Option<SleepTimer>,
+    /// The number of minutes the page can be hidden and untouched before playback is paused, if
+    /// pausing when away is on
+    #[serde(default)]
+    away_pause_minutes: Option<u32>,
+    /// Whether the player takes commands from other devices
+    #[serde(default)]
+    remote_control: bool,
+    /// The number of seconds the jump buttons jump backward by
+    #[serde(default = "default_jump_size")]
+    jump_backward_secs: f64,
+    /// The number of seconds the jump buttons jump forward by
+    #[serde(default = "default_jump_size")]
+    jump_forward_secs: f64,
+    /// Whether silences are skipped and the volume is evened out
+    #[serde(default)]
+    audio_processing: ProcessingOptions,
+    /// When the audio was last playing as the state was saved, in milliseconds since the Unix
+    /// epoch. `None` if it was paused.
+    #[serde(default)]
+    playing_at: Option<f64>,
+    /// Whether the first tap on the page resumes the article that was playing before a reload
+    #[serde(default)]
+    resume_on_tap: bool,
+    /// The listener's speed training, if it's on
+    #[serde(default)]
+    speed_training: Option<SpeedTraining>,
+    /// What's recapped when an article's resumed after a break
+    #[serde(default)]
+    recap_mode: RecapMode,
+}
+
+/// Continuous playback is on unless the listener turns it off
+fn default_continuous_playback() -> bool {
+    true
+}
+
+/// The jump buttons jump by the default size unless the listener picks another
+fn default_jump_size() -> f64 {
+    DEFAULT_JUMP_SIZE
 }
 
 impl Default for PlayerState {
@@ -165,6 +683,19 @@ impl Default for PlayerState {
         PlayerState {
             now_playing: None,
             playback_speed: 1.0,
+            speed_updated: 0.0,
+            continuous_playback: default_continuous_playback(),
+            lock_position: false,
+            sleep_timer: None,
+            away_pause_minutes: None,
+            remote_control: false,
+            jump_backward_secs: default_jump_size(),
+            jump_forward_secs: default_jump_size(),
+            audio_processing: ProcessingOptions::default(),
+            playing_at: None,
+            resume_on_tap: false,
+            speed_training: None,
+            recap_mode: RecapMode::Off,
         }
     }
 }
@@ -180,20 +711,19 @@ impl Component for Player {
             .borrow_mut()
             .replace(ctx.link().clone());
 
-        // Set up the closure that gets called every 10sec and triggers a save event
-        let link = ctx.link().clone();
-        let periodic = true;
-        let trigger_save_cb = Closure::new(move || trigger_save(periodic, &link));
-
         // Set up the MediaSession API
         let mut _media_session_cbs = MediaSessionCallbacks::default();
-        // Hook up the prev and next track buttons
+        // Hook up the prev and next track buttons. They skip between sections, and go to the
+        // previous or next track at the ends of the article.
+        let link = ctx.link().clone();
+        _media_session_cbs.set_prevtrack_action(move || link.send_message(PlayerMsg::PrevSection));
         let link = ctx.link().clone();
-        _media_session_cbs
-            .set_prevtrack_action(move || link.send_message(PlayerMsg::AskForPrevTrack));
+        _media_session_cbs.set_nexttrack_action(move || link.send_message(PlayerMsg::NextSection));
+
+        // Listen to the Players in other tabs
         let link = ctx.link().clone();
-        _media_session_cbs
-            .set_nexttrack_action(move || link.send_message(PlayerMsg::AskForNextTrack));
+        let tabs = TabChannel::new(move |msg| link.send_message(PlayerMsg::FromOtherTab(msg)));
+        let tabs = Rc::new(tabs);
 
         // Kick off a future to get the last known player state
         let link = ctx.link().clone();
@@ -207,15 +737,55 @@ impl Component for Player {
             }
         });
 
-        // Kick off the state saving loop in PLAYER_STATE_SAVE_FREQ seconds
-        utils::run_after_delay(&trigger_save_cb, PLAYER_STATE_SAVE_FREQ);
+        // Save less often while the battery is low
+        let battery_link = ctx.link().clone();
+
+        // Kick off the state saving loop
+        let save_timer = schedule_save(ctx.link(), false);
 
         // Return the default values for now. Hopefully they get overwritten by the
         // load_player_state callback
         Self {
-            _trigger_save_cb: trigger_save_cb,
+            save_timer,
             _media_session_cbs,
             state: PlayerState::default(),
+            streaming_title: None,
+            group_progress: None,
+            speed_suggestion: None,
+            comprehension_check: None,
+            ended: None,
+            ended_rating: None,
+            session: None,
+            sleep_timer_tick: None,
+            sleep_minutes_left: None,
+            volume_before_fade: None,
+            transcript: Rc::default(),
+            read_along_link: WeakComponentLink::default(),
+            waveform_link: WeakComponentLink::default(),
+            plays: None,
+            play_from: None,
+            moment_marked: None,
+            last_position: 0.0,
+            seek_undo: None,
+            seek_save: None,
+            tabs,
+            playing_elsewhere: false,
+            stale: false,
+            taking_over: None,
+            _activity_listener: ActivityListener::default(),
+            _hide_listener: HideListener::new(ctx.link().clone()),
+            _shortcut_listener: ShortcutListener::new(ctx.link().clone()),
+            battery_low: false,
+            _battery_monitor: BatteryMonitor::new(move |low| {
+                battery_link.send_message(PlayerMsg::SetBatteryLow(low))
+            }),
+            away_check: None,
+            remote_poll: None,
+            remote_seq: None,
+            resume_offer: None,
+            position_conflict: None,
+            recap: None,
+            _gesture_listener: GestureListener::new(ctx.link().clone()),
             audio_link: WeakComponentLink::default(),
         }
     }
@@ -228,11 +798,69 @@ impl Component for Player {
             PlayerMsg::Play(queue_entry) => {
                 let player_link = ctx.link().clone();
 
+                // Remember where the listener was in the article that was playing, unless
+                // another tab has played since. The new article's position is loaded from disk, so
+                // this tab isn't stale anymore.
+                if let Some(entry) = self.state.now_playing.as_ref().filter(|_| !self.stale) {
+                    save_position(entry, self.current_plays());
+                }
+                self.stale = false;
+                self.end_session(&queue_link);
+
                 // Change now-playing to the new article
                 self.state.now_playing = Some(queue_entry.clone());
+                queue_link.send_message(QueueMsg::NowPlaying(Some(queue_entry.id.clone())));
+                let start = self
+                    .play_from
+                    .take()
+                    .filter(|(id, _)| id == &queue_entry.id)
+                    .map(|(_, secs)| secs);
+                self.streaming_title = None;
+                self.transcript = Rc::default();
+                self.set_plays(None);
+                self.seek_undo = None;
+                self.ended = None;
+                if self.recap.take().is_some() {
+                    recap::stop_recap();
+                }
+
+                // Remember that this article has been listened to, for smart playlists
+                let id = queue_entry.id.clone();
+                spawn_local(async move {
+                    let _ = caching::mark_listened(&id)
+                        .await
+                        .map_err(|e| tracing::error!("Couldn't mark {} as listened: {}", id.0, e));
+                });
+
+                // Suggest a speed to start at, from the speeds similar articles were listened at.
+                // During speed training, the article starts at the day's training speed instead.
+                self.speed_suggestion = None;
+                if let Some(training) = &self.state.speed_training {
+                    let speed = training.speed();
+                    if speed != self.state.playback_speed {
+                        set_playback_speed(speed, &audio_link);
+                        self.state.playback_speed = speed;
+                        self.state.speed_updated = clock::now();
+                    }
+                } else {
+                    let link = ctx.link().clone();
+                    let entry = queue_entry.clone();
+                    spawn_local(async move {
+                        match caching::load_speed_samples().await {
+                            Ok(samples) => {
+                                if let Some(speed) = speed_advice::suggest(&entry, &samples) {
+                                    let id = entry.id;
+                                    link.send_message(PlayerMsg::SuggestSpeed { id, speed });
+                                }
+                            }
+                            Err(e) => tracing::error!("Couldn't load listening speeds: {}", e),
+                        }
+                    });
+                }
 
                 // Load the track, play it, and save the player state to disk
                 tracing::debug!("Playing track {}", queue_entry.id.0);
+                let recap_mode = self.state.recap_mode;
                 spawn_local(async move {
                     // Do a useless play() action. This necessary because Safari is buggy and
                     // doesn't allow the first media action (like play or pause) to come from
@@ -240,9 +868,36 @@ impl Component for Player {
                     GlobalAudio::fake_play().await;
                     tracing::trace!("Did a fake play");
 
+                    // An article left a while ago gets a recap first, unless it's starting
+                    // somewhere else
+                    let recap_due = recap_mode != RecapMode::Off
+                        && start.is_none()
+                        && caching::load_article_state(&queue_entry.id)
+                            .await
+                            .is_ok_and(|state| recap::is_due(&state, clock::now()));
+
                     // Load the article and play it
-                    let new_elapsed = prepare_for_play(&queue_entry.id, &audio_link).await;
-                    audio_link.send_message(AudioMsg::Play);
+                    let (new_elapsed, transcript) =
+                        prepare_for_play(&queue_entry, &audio_link, start).await;
+                    let recap = if recap_due {
+                        let title = &queue_entry.title;
+                        recap::make_recap(recap_mode, title, &transcript, new_elapsed).await
+                    } else {
+                        None
+                    };
+                    match recap {
+                        Some(recap) => player_link.send_message(PlayerMsg::PlayRecap(recap)),
+                        None => audio_link.send_message(AudioMsg::Play),
+                    }
+                    if let Some(conflict) = position_conflicts::find(&queue_entry.id) {
+                        player_link.send_message(PlayerMsg::ShowPositionConflict(conflict));
+                    }
+                    player_link.send_message(PlayerMsg::SetTranscript {
+                        id: queue_entry.id.clone(),
+                        transcript,
+                    });
+                    let plays = load_play_counts(&queue_entry.id).await;
+                    player_link.send_message(PlayerMsg::SetPlayCounts(plays));
 
                     // Save the new article with the new elapsed time to disk. This isn't done
                     // through trigger_save because it might be the case at this point that the
@@ -259,6 +914,73 @@ impl Component for Player {
                 true
             }
 
+            PlayerMsg::MarkMoment => {
+                let Some(entry) = self.state.now_playing.as_ref() else {
+                    return false;
+                };
+                let secs = GlobalAudio::get_elapsed();
+                let context = read_along::context_at(&self.transcript, secs);
+                let moment = Moment::new(entry, secs, context);
+                spawn_local(async move {
+                    if let Err(e) = caching::save_moment(&moment).await {
+                        tracing::error!("Couldn't save moment: {}", e);
+                    }
+                });
+
+                let link = ctx.link().clone();
+                let timer = clock::timeout(MOMENT_MARKED_DELAY, move || {
+                    link.send_message(PlayerMsg::DismissMomentMarked)
+                });
+                self.moment_marked = Some((secs, timer));
+                true
+            }
+
+            PlayerMsg::DismissMomentMarked => {
+                self.moment_marked = None;
+                true
+            }
+
+            PlayerMsg::PlayFrom { id, secs } => {
+                if self.state.now_playing.as_ref().map(|entry| &entry.id) == Some(&id) {
+                    // A seek the listener asked for, so it can be undone like one with the scrubber
+                    GlobalAudio::scrub(secs);
+                    self.play_from = None;
+                } else {
+                    self.play_from = Some((id, secs));
+                }
+                false
+            }
+
+            PlayerMsg::PlayStream { url, title } => {
+                if let Some(entry) = self.state.now_playing.as_ref().filter(|_| !self.stale) {
+                    save_position(entry, self.current_plays());
+                }
+                self.end_session(&queue_link);
+
+                // Nothing from the queue is playing anymore. The stream can't be resumed after a
+                // reload, so save the player as having nothing loaded
+                self.state.now_playing = None;
+                queue_link.send_message(QueueMsg::NowPlaying(None));
+                self.streaming_title = Some(title.clone());
+                self.transcript = Rc::default();
+                self.set_plays(None);
+                self.ended = None;
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                tracing::debug!("Streaming {url}");
+                spawn_local(async move {
+                    // See PlayerMsg::Play for why this is necessary
+                    GlobalAudio::fake_play().await;
+
+                    audio_link.send_message(AudioMsg::LoadUrl { src: url, title });
+                    audio_link.send_message(AudioMsg::Play);
+                });
+
+                // The state was updated. Refresh the player view
+                true
+            }
+
             PlayerMsg::AskForPrevTrack => {
                 // Ask the queue to start playing the track that comes before
                 // self.state.now_playing
@@ -281,178 +1003,1182 @@ impl Component for Player {
                 false
             }
 
-            PlayerMsg::UpdatePlaybackSpeed => {
-                // Check the playback speed selector and update the playback speed accordingly.
-                // Also save the speed in the state.
-                let speed = get_selected_playback_speed();
-                set_playback_speed(speed, &audio_link);
-                self.state.playback_speed = speed;
-
-                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
-                let periodic = false;
-                trigger_save(periodic, &ctx.link());
+            PlayerMsg::PrevSection => {
+                match sections::prev_section(
+                    &self.transcript.timepoints,
+                    GlobalAudio::get_elapsed(),
+                ) {
+                    Some(start) => GlobalAudio::seek(start),
+                    None => ctx.link().send_message(PlayerMsg::AskForPrevTrack),
+                }
 
                 false
             }
 
-            PlayerMsg::StopIfPlaying(id) => {
-                // Check if the given ID matches the currently playing article
-                if self.state.now_playing.as_ref().map(|entry| &entry.id) == Some(&id) {
-                    // On match, stop playing and clear the <audio> element of all information
-                    audio_link.send_message(AudioMsg::Stop);
+            PlayerMsg::NextSection => {
+                match sections::next_section(
+                    &self.transcript.timepoints,
+                    GlobalAudio::get_elapsed(),
+                ) {
+                    Some(start) => GlobalAudio::seek(start),
+                    None => ctx.link().send_message(PlayerMsg::AskForNextTrack),
+                }
 
-                    // Now clear the current track, and save the state
-                    self.state.now_playing = None;
-                    // This is an ad-hoc (ie non-periodic) save
-                    let periodic = false;
-                    trigger_save(periodic, &ctx.link());
+                false
+            }
 
-                    // The state was updated. Refresh the player view
-                    true
-                } else {
-                    false
+            PlayerMsg::SetTranscript { id, transcript } => {
+                // Only take the transcript of the article that's still playing
+                if self.state.now_playing.as_ref().map(|entry| &entry.id) != Some(&id) {
+                    return false;
                 }
+
+                self.transcript = transcript;
+                true
             }
 
-            PlayerMsg::SetState(state) => {
-                // Set the state and make it reflected in the player. That is, set the playback
-                // speed and the currently playing article
-                self.state = state;
-                set_playback_speed(self.state.playback_speed, &audio_link);
+            PlayerMsg::SetPlayCounts(plays) => {
+                // Only take the counts of the article that's still playing
+                if self.state.now_playing.as_ref().map(|entry| &entry.id) == Some(&plays.id) {
+                    self.set_plays(Some(plays));
+                }
+                false
+            }
 
-                // Load up the article specified by now_playing
+            PlayerMsg::TrackEnded => {
                 if let Some(entry) = self.state.now_playing.clone() {
-                    spawn_local(async move {
-                        prepare_for_play(&entry.id, &audio_link).await;
+                    record_speed(&entry, self.state.playback_speed);
+                    // Ask how much of it was followed, if it was played for speed training
+                    if self.state.speed_training.is_some() {
+                        self.comprehension_check = Some((entry.clone(), self.state.playback_speed));
+                    }
+                    // Reaching the end only finishes the article if most of it was heard
+                    let duration = GlobalAudio::get_duration();
+                    let finished = heatmap::is_finished(self.current_plays(), duration, duration);
+                    if let Some(session) = self.session.as_mut() {
+                        session.listened(0.0, 1.0, finished);
+                    }
+                    self.end_session(&queue_link);
+
+                    // Offer what to do next. If the queue plays something, this goes away.
+                    self.ended = Some(entry.clone());
+                    self.ended_rating = None;
+
+                    // If the sleep timer was waiting for this, stay paused
+                    if self.state.sleep_timer == Some(SleepTimer::EndOfArticle) {
+                        self.set_sleep_timer(None, ctx);
+                        let periodic = false;
+                        trigger_save(periodic, &ctx.link());
+                        return true;
+                    }
+
+                    // Otherwise, the queue decides what plays next
+                    queue_link.send_message(QueueMsg::TrackEnded {
+                        id: entry.id,
+                        continuous: self.state.continuous_playback,
                     });
                 }
 
-                // The state was updated. Refresh the player view
                 true
             }
 
-            PlayerMsg::SaveState { elapsed, periodic } => {
-                // If this was a periodic save, set up the next trigger
-                if periodic {
-                    utils::run_after_delay(&self._trigger_save_cb, PLAYER_STATE_SAVE_FREQ);
+            PlayerMsg::EndOfArticle(action) => {
+                // Rating the article leaves the other actions up
+                if let (EndAction::Rate(rating), Some(entry)) = (action, &self.ended) {
+                    self.ended_rating = rating;
+                    queue_link.send_message(QueueMsg::Rate {
+                        id: entry.id.clone(),
+                        rating,
+                    });
+                    return true;
                 }
 
-                // Sometimes the browser will unload our tab if the audio is paused. When the user
-                // comes back to the tab, the page is refreshed and the audio playback is set to
-                // 0sec. This is fine, as the user can just hit the Play/Pause button or the queue
-                // play button (not the <audio> play button, since that'd play from the beginning).
-                // However, if the user does not hit the button within 10sec, the elapsed time of
-                // 0sec will be saved, and the user will lose their place. To prevent this, do not
-                // do a periodic save if the current elapsed time is 0sec.
-                if periodic && elapsed == 0.0 {
+                let Some(entry) = self.ended.take() else {
                     return false;
+                };
+                match action {
+                    EndAction::PlayNext => {
+                        queue_link.send_message(QueueMsg::PlayTrackAfter(entry.id));
+                    }
+                    EndAction::Remove => {
+                        queue_link.send_message(QueueMsg::DeleteArticles(vec![entry.id]));
+                    }
+                    EndAction::Replay => {
+                        GlobalAudio::seek(0.0);
+                        audio_link.send_message(AudioMsg::Play);
+                    }
+                    EndAction::Rate(_) | EndAction::Dismiss => (),
                 }
 
-                // Collect the states to save. Player state holds now-playing and playback speed.
-                // Article state holds elapsed time
-                let player_state = self.state.clone();
-                let article_state = player_state.now_playing.clone().map(|entry| ArticleState {
-                    id: entry.id,
-                    elapsed,
-                });
+                true
+            }
 
-                // Save the states
-                spawn_local(async move {
-                    // Save the player state first
-                    match caching::save_player_state(&player_state).await {
-                        Ok(_) => tracing::trace!("Successfully saved player state"),
-                        Err(e) => tracing::error!("Could not save player state: {}", e),
-                    }
+            PlayerMsg::ToggleContinuousPlayback => {
+                self.state.continuous_playback = !self.state.continuous_playback;
 
-                    // Try to save the article state. There may well be nothing playing. In which
-                    // case, do nothing.
-                    if let Some(s) = article_state {
-                        match caching::save_article_state(&s).await {
-                            Ok(_) => tracing::trace!("Successfully saved article state"),
-                            Err(e) => tracing::error!("Could not save article state: {}", e),
-                        }
-                    } else {
-                        tracing::trace!("No article to save");
-                    }
-                });
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
 
-                false
+                true
             }
-        }
-    }
 
-    fn view(&self, ctx: &Context<Self>) -> Html {
-        let player_link = ctx.props().player_link.borrow().clone().unwrap();
+            PlayerMsg::SetSleepTimer(duration) => {
+                self.set_sleep_timer(Some(SleepTimer::after(duration)), ctx);
 
-        //
-        // Callbacks for the play, jump backward and forward buttons
-        //
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
 
-        let audio_link = self.audio_link.clone();
-        let jump_forward_cb = Callback::from(move |_: MouseEvent| {
-            audio_link
-                .borrow()
-                .as_ref()
-                .unwrap()
-                .send_message(AudioMsg::JumpForward)
-        });
+                true
+            }
 
-        let audio_link = self.audio_link.clone();
-        let jump_backward_cb = Callback::from(move |_: MouseEvent| {
-            audio_link
-                .borrow()
-                .as_ref()
-                .unwrap()
-                .send_message(AudioMsg::JumpBackward)
-        });
+            PlayerMsg::SleepAtEndOfArticle => {
+                self.set_sleep_timer(Some(SleepTimer::EndOfArticle), ctx);
 
-        // Callback for the "go to beginning". When prevtrack is clicked, go to the beginning of
-        // the audio. If it's clicked twice within one second, i.e., double-clicked, then move to
-        // the previous track.
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::CancelSleepTimer => {
+                self.set_sleep_timer(None, ctx);
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::SleepTimerTick => {
+                let remaining = match self.state.sleep_timer.and_then(|t| t.remaining_secs()) {
+                    Some(remaining) => remaining,
+                    None => return false,
+                };
+
+                // A countdown that's run out pauses playback. An end-of-article timer waits for
+                // the track to end instead.
+                let is_countdown = matches!(self.state.sleep_timer, Some(SleepTimer::After { .. }));
+                if is_countdown && remaining <= 0.0 {
+                    tracing::debug!("Sleep timer went off");
+                    GlobalAudio::pause();
+                    self.set_sleep_timer(None, ctx);
+                    let periodic = false;
+                    trigger_save(periodic, &ctx.link());
+                    return true;
+                }
+
+                // Fade out over the last stretch, and restore the volume if the listener seeks
+                // back out of it
+                match sleep_timer::fade_out_level(remaining) {
+                    Some(level) => {
+                        let full = *self
+                            .volume_before_fade
+                            .get_or_insert_with(GlobalAudio::get_volume);
+                        GlobalAudio::set_volume(full * level);
+                    }
+                    None => {
+                        if let Some(volume) = self.volume_before_fade.take() {
+                            GlobalAudio::set_volume(volume);
+                        }
+                    }
+                }
+
+                // Only refresh the player view if the displayed number of minutes changed
+                let minutes_left = is_countdown.then(|| (remaining / 60.0).ceil() as u64);
+                if minutes_left == self.sleep_minutes_left {
+                    return false;
+                }
+                self.sleep_minutes_left = minutes_left;
+                true
+            }
+
+            PlayerMsg::SetAwayPause(minutes) => {
+                self.state.away_pause_minutes = minutes;
+                self.start_away_check(ctx);
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::CheckAway => {
+                let away = self
+                    .state
+                    .away_pause_minutes
+                    .is_some_and(away_pause::is_away);
+                if away && GlobalAudio::is_playing() {
+                    // Pause and save the position, so the listener can pick up where they left
+                    tracing::debug!("Pausing, since the listener is away");
+                    GlobalAudio::pause();
+                    let periodic = false;
+                    trigger_save(periodic, &ctx.link());
+                }
+
+                false
+            }
+
+            PlayerMsg::TimeUpdate => {
+                let elapsed = GlobalAudio::get_elapsed();
+                let from = self.last_position;
+                let step = elapsed - from;
+                self.last_position = elapsed;
+                if GlobalAudio::is_playing() && step > 0.0 && step <= MAX_LISTENED_STEP_SECS {
+                    self.record_listening(from, elapsed);
+                    self.record_plays(from, elapsed);
+                }
+
+                // Move the read-along highlight
+                if let Some(read_along) = self.read_along_link.borrow().as_ref() {
+                    read_along.send_message(ReadAlongMsg::TimeUpdate(self.last_position));
+                }
+                // Shade the waveform up to here
+                if let Some(waveform) = self.waveform_link.borrow().as_ref() {
+                    waveform.send_message(WaveformMsg::TimeUpdate(self.last_position));
+                }
+
+                let progress = self
+                    .state
+                    .now_playing
+                    .as_ref()
+                    .and_then(|entry| entry.group.as_ref())
+                    .map(group_progress_pct);
+
+                // Only refresh the player view if the displayed percentage changed
+                if progress == self.group_progress {
+                    return false;
+                }
+                self.group_progress = progress;
+                true
+            }
+
+            PlayerMsg::Seeking => {
+                let from = self.last_position;
+                let to = GlobalAudio::get_elapsed();
+                self.last_position = to;
+
+                // Save the new position once the seeking settles. Replacing the timeout cancels
+                // the one from the last seek.
+                let player_link = ctx.link().clone();
+                self.seek_save = Some(schedule_seek_save(move || {
+                    // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic)
+                    // save
+                    let periodic = false;
+                    trigger_save(periodic, &player_link);
+                }));
+
+                // Only the listener's seeks in queued articles are checked
+                if GlobalAudio::take_expected_seek() || self.state.now_playing.is_none() {
+                    return false;
+                }
+
+                let jump = to - from;
+                if self.state.lock_position && jump.abs() >= 1.0 {
+                    tracing::debug!("Position is locked. Undoing seek from {from} to {to}");
+                    GlobalAudio::seek(from);
+                    self.last_position = from;
+                    false
+                } else if jump.abs() >= UNDOABLE_JUMP_SECS {
+                    // Keep the position from before the first of several seeks in a row, so
+                    // dragging the scrubber around can be undone all at once
+                    let from = match self.seek_undo {
+                        Some((first, last)) if (last - from).abs() < 1.0 => first,
+                        _ => from,
+                    };
+                    self.seek_undo = Some((from, to));
+                    true
+                } else {
+                    false
+                }
+            }
+
+            PlayerMsg::UndoSeek => {
+                if let Some((from, _)) = self.seek_undo.take() {
+                    GlobalAudio::seek(from);
+                }
+                true
+            }
+
+            PlayerMsg::DismissSeekUndo => {
+                self.seek_undo = None;
+                true
+            }
+
+            PlayerMsg::SetJumpSizes { backward, forward } => {
+                self.state.jump_backward_secs = backward;
+                self.state.jump_forward_secs = forward;
+                GlobalAudio::set_jump_sizes(backward, forward);
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::SetAudioProcessing(options) => {
+                self.state.audio_processing = options;
+                audio_processing::set_options(options);
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::Shortcut(shortcut) => {
+                tracing::trace!("Keyboard shortcut {:?}", shortcut);
+                let jump_allowed = !self.state.lock_position;
+                match shortcut {
+                    Shortcut::TogglePlay if GlobalAudio::is_playing() => GlobalAudio::pause(),
+                    Shortcut::TogglePlay => audio_link.send_message(AudioMsg::Play),
+                    Shortcut::JumpBackward if jump_allowed => {
+                        audio_link.send_message(AudioMsg::JumpBackward)
+                    }
+                    Shortcut::JumpForward if jump_allowed => {
+                        audio_link.send_message(AudioMsg::JumpForward)
+                    }
+                    Shortcut::JumpBackward | Shortcut::JumpForward => (),
+                    Shortcut::MarkMoment => ctx.link().send_message(PlayerMsg::MarkMoment),
+                    Shortcut::Faster | Shortcut::Slower => {
+                        let faster = matches!(shortcut, Shortcut::Faster);
+                        let current = self.state.playback_speed;
+                        // Picking the speed in the selector is what changes it
+                        if let Some(speed) = shortcuts::step_speed(PLAYBACK_SPEEDS, current, faster)
+                        {
+                            get_speed_selector().set_value(&format!("{}", speed));
+                            ctx.link().send_message(PlayerMsg::UpdatePlaybackSpeed);
+                        }
+                    }
+                }
+
+                false
+            }
+
+            PlayerMsg::ToggleLockPosition => {
+                self.state.lock_position = !self.state.lock_position;
+                self.seek_undo = None;
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::AudioPlayed => {
+                // The <audio> controls can play a stale article. Pick up from the other tab instead.
+                if self.stale {
+                    GlobalAudio::pause();
+                    ctx.link().send_message(PlayerMsg::TakeOver);
+                    return false;
+                }
+
+                // Playing over the recap cuts it short
+                let cut_recap = self.recap.take().is_some();
+                if cut_recap {
+                    recap::stop_recap();
+                }
+
+                // However playback started, there's nothing left to resume
+                let had_resume_offer = self.resume_offer.take().is_some();
+
+                // Playing from the <audio> controls doesn't go through GlobalAudio::play()
+                audio_processing::start();
+                self.tabs.send(TabMessage::Playing);
+                let was_playing_elsewhere = self.playing_elsewhere;
+                self.playing_elsewhere = false;
+                was_playing_elsewhere || had_resume_offer || cut_recap
+            }
+
+            PlayerMsg::AudioPaused => {
+                // Save the position before telling the other tabs, so one of them can take over
+                if !self.stale {
+                    self.save(GlobalAudio::get_elapsed(), Some(TabMessage::Paused));
+                }
+
+                false
+            }
+
+            PlayerMsg::FromOtherTab(msg) => {
+                match msg {
+                    TabMessage::Hello => {
+                        if GlobalAudio::is_playing() {
+                            self.tabs.send(TabMessage::Playing);
+                        }
+                        false
+                    }
+                    TabMessage::Playing => {
+                        // Only one tab plays at a time. Save the position before going stale.
+                        if GlobalAudio::is_playing() {
+                            GlobalAudio::pause();
+                            self.save(GlobalAudio::get_elapsed(), None);
+                        }
+                        self.playing_elsewhere = true;
+                        self.stale = true;
+                        true
+                    }
+                    TabMessage::Release => {
+                        // Another tab is taking over. Hand over the position once it's saved.
+                        if GlobalAudio::is_playing() && !self.stale {
+                            GlobalAudio::pause();
+                            self.save(GlobalAudio::get_elapsed(), Some(TabMessage::Paused));
+                            self.stale = true;
+                            return true;
+                        }
+                        false
+                    }
+                    TabMessage::Paused => {
+                        self.playing_elsewhere = false;
+                        if self.taking_over.take().is_some() {
+                            ctx.link().send_message(PlayerMsg::TakeOver);
+                        }
+                        true
+                    }
+                }
+            }
+
+            PlayerMsg::TakeOver => {
+                // If another tab is playing, have it save its position first. If it doesn't answer,
+                // e.g., because it was closed, go ahead as if it did.
+                if self.playing_elsewhere {
+                    self.tabs.send(TabMessage::Release);
+                    let link = ctx.link().clone();
+                    self.taking_over = Some(clock::timeout(TAKE_OVER_TIMEOUT, move || {
+                        link.send_message(PlayerMsg::FromOtherTab(TabMessage::Paused))
+                    }));
+                    return false;
+                }
+
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    match caching::load_player_state().await {
+                        Ok(state) => link.send_message(PlayerMsg::TookOver(state)),
+                        Err(e) => tracing::error!("Could not load player state: {}", e),
+                    }
+                });
+
+                false
+            }
+
+            PlayerMsg::TookOver(mut state) => {
+                // Play what the other tab was playing. Its position is loaded from disk by Play.
+                let now_playing = state.now_playing.take();
+                self.set_state(state, ctx);
+                self.stale = false;
+                self.playing_elsewhere = false;
+                if let Some(entry) = now_playing {
+                    ctx.link().send_message(PlayerMsg::Play(entry));
+                }
+
+                true
+            }
+
+            PlayerMsg::UpdatePlaybackSpeed => {
+                // Check the playback speed selector and update the playback speed accordingly.
+                // Also save the speed in the state.
+                let speed = get_selected_playback_speed();
+                set_playback_speed(speed, &audio_link);
+                self.state.playback_speed = speed;
+                self.state.speed_updated = clock::now();
+                if let Some(entry) = &self.state.now_playing {
+                    record_speed(entry, speed);
+                }
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                // The listener picked a speed, so there's no need to suggest one
+                self.speed_suggestion.take().is_some()
+            }
+
+            PlayerMsg::SuggestSpeed { id, speed } => {
+                // Only suggest a change, and only for the article it was worked out for
+                let is_playing =
+                    self.state.now_playing.as_ref().map(|entry| &entry.id) == Some(&id);
+                if !is_playing || speed == self.state.playback_speed {
+                    return false;
+                }
+
+                self.speed_suggestion = Some(speed);
+                true
+            }
+
+            PlayerMsg::ApplySuggestedSpeed => {
+                if let Some(speed) = self.speed_suggestion.take() {
+                    set_playback_speed(speed, &audio_link);
+                    self.state.playback_speed = speed;
+                    self.state.speed_updated = clock::now();
+                    if let Some(entry) = &self.state.now_playing {
+                        record_speed(entry, speed);
+                    }
+
+                    // This is an ad-hoc (ie non-periodic) save
+                    let periodic = false;
+                    trigger_save(periodic, &ctx.link());
+                }
+
+                true
+            }
+
+            PlayerMsg::DismissSpeedSuggestion => {
+                self.speed_suggestion = None;
+                true
+            }
+
+            PlayerMsg::SetSpeedTraining(target) => {
+                // Training starts from the speed the listener's at now
+                let speed = self.state.playback_speed;
+                self.state.speed_training = target.map(|target| SpeedTraining::new(speed, target));
+                self.comprehension_check = None;
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::CheckComprehension(comprehension) => {
+                self.comprehension_check = None;
+                if let Some(training) = self.state.speed_training.as_mut() {
+                    training.record(comprehension);
+
+                    // This is an ad-hoc (ie non-periodic) save
+                    let periodic = false;
+                    trigger_save(periodic, &ctx.link());
+                }
+
+                true
+            }
+
+            PlayerMsg::DismissComprehensionCheck => {
+                self.comprehension_check = None;
+                true
+            }
+
+            PlayerMsg::StopIfPlaying(id) => {
+                // Check if the given ID matches the currently playing article
+                if self.state.now_playing.as_ref().map(|entry| &entry.id) == Some(&id) {
+                    // On match, stop playing and clear the <audio> element of all information
+                    audio_link.send_message(AudioMsg::Stop);
+
+                    // Now clear the current track, and save the state
+                    self.state.now_playing = None;
+                    queue_link.send_message(QueueMsg::NowPlaying(None));
+                    self.transcript = Rc::default();
+                    self.set_plays(None);
+                    self.ended = None;
+                    self.end_session(&queue_link);
+                    // This is an ad-hoc (ie non-periodic) save
+                    let periodic = false;
+                    trigger_save(periodic, &ctx.link());
+
+                    // The state was updated. Refresh the player view
+                    true
+                } else {
+                    false
+                }
+            }
+
+            PlayerMsg::SetState(state) => {
+                self.set_state(state, ctx);
+
+                // If the audio was playing just before the page loaded, the page was reloaded out
+                // from under the listener. Offer to pick up where they were.
+                let reloaded_mid_play = self
+                    .state
+                    .playing_at
+                    .is_some_and(|t| clock::now() - t < RELOAD_WINDOW_MS);
+
+                // Load up the article specified by now_playing
+                let now_playing = self
+                    .state
+                    .now_playing
+                    .as_ref()
+                    .map(|entry| entry.id.clone());
+                queue_link.send_message(QueueMsg::NowPlaying(now_playing));
+                if let Some(entry) = self.state.now_playing.clone() {
+                    let player_link = ctx.link().clone();
+                    spawn_local(async move {
+                        let (elapsed, transcript) =
+                            prepare_for_play(&entry, &audio_link, None).await;
+                        if let Some(conflict) = position_conflicts::find(&entry.id) {
+                            player_link.send_message(PlayerMsg::ShowPositionConflict(conflict));
+                        }
+                        let id = entry.id;
+                        if reloaded_mid_play {
+                            let id = id.clone();
+                            player_link.send_message(PlayerMsg::OfferResume { id, elapsed });
+                        }
+                        let plays = load_play_counts(&id).await;
+                        player_link.send_message(PlayerMsg::SetTranscript { id, transcript });
+                        player_link.send_message(PlayerMsg::SetPlayCounts(plays));
+                    });
+                }
+
+                // The state was updated. Refresh the player view
+                true
+            }
+
+            PlayerMsg::SaveState { elapsed, periodic } => {
+                // If this was a periodic save, set up the next trigger
+                if periodic {
+                    self.save_timer = schedule_save(ctx.link(), self.battery_low);
+                }
+
+                // Periodic saves only keep the elapsed time fresh while the audio is playing.
+                // Nothing changes while it's paused without an ad-hoc save, so skip them then.
+                //
+                // This also matters because sometimes the browser will unload our tab if the audio
+                // is paused. When the user comes back to the tab, the page is refreshed and the
+                // audio playback is set to 0sec. A periodic save then would save the elapsed time
+                // of 0sec, and the user would lose their place.
+                if periodic && (!GlobalAudio::is_playing() || elapsed == 0.0) {
+                    return false;
+                }
+
+                // Another tab has played since this one loaded its state, so this one's is out of
+                // date. Don't overwrite the other tab's.
+                if self.stale {
+                    tracing::trace!("Not saving, since another tab has played since");
+                    return false;
+                }
+
+                self.save(elapsed, None);
+                false
+            }
+
+            PlayerMsg::PageHidden => {
+                // As with periodic saves, an elapsed time of 0 means the audio hasn't been loaded
+                // since the page was, so saving it would lose the listener's place. And a stale tab
+                // doesn't save at all.
+                let elapsed = GlobalAudio::get_elapsed();
+                if elapsed == 0.0 || self.stale {
+                    return false;
+                }
+
+                self.save(elapsed, None);
+                false
+            }
+
+            PlayerMsg::SetBatteryLow(low) => {
+                self.battery_low = low;
+                true
+            }
+
+            PlayerMsg::ToggleBatterySaver => {
+                battery::set_saver_enabled(!battery::saver_enabled());
+                ctx.link().send_future(async move {
+                    PlayerMsg::SetBatteryLow(battery::should_save().await)
+                });
+                true
+            }
+
+            PlayerMsg::ToggleRemoteControl => {
+                self.state.remote_control = !self.state.remote_control;
+                self.start_remote_poll(ctx);
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::PollRemote => {
+                // Only the tab that's playing takes commands
+                if self.stale {
+                    return false;
+                }
+
+                let title = match (&self.state.now_playing, &self.streaming_title) {
+                    (Some(entry), _) => Some(entry.title.clone()),
+                    (None, title) => title.clone(),
+                };
+                let duration = GlobalAudio::get_duration();
+                let status = PlayerStatus {
+                    title,
+                    playing: GlobalAudio::is_playing(),
+                    elapsed_secs: GlobalAudio::get_elapsed(),
+                    // The duration is NaN if nothing is loaded
+                    duration_secs: if duration.is_finite() { duration } else { 0.0 },
+                };
+                let after = self.remote_seq;
+                let player_link = ctx.link().clone();
+                spawn_local(async move {
+                    match remote_control::poll(&status, after).await {
+                        Ok(commands) => {
+                            player_link.send_message(PlayerMsg::RemoteCommands(commands))
+                        }
+                        Err(e) => tracing::debug!("Couldn't poll for remote commands: {:#}", e),
+                    }
+                });
+
+                false
+            }
+
+            PlayerMsg::RemoteCommands(RemoteCommands { latest, commands }) => {
+                // Remote control might have been turned off, or another tab taken over, while the
+                // commands were on their way
+                if !self.state.remote_control || self.stale {
+                    return false;
+                }
+                self.remote_seq = Some(latest);
+
+                for command in commands {
+                    tracing::debug!("Got remote command {:?}", command);
+                    let jump_allowed = !self.state.lock_position;
+                    match command {
+                        RemoteCommand::TogglePlay if GlobalAudio::is_playing() => {
+                            GlobalAudio::pause()
+                        }
+                        RemoteCommand::TogglePlay | RemoteCommand::Play => {
+                            audio_link.send_message(AudioMsg::Play)
+                        }
+                        RemoteCommand::Pause => GlobalAudio::pause(),
+                        RemoteCommand::Next => ctx.link().send_message(PlayerMsg::AskForNextTrack),
+                        RemoteCommand::Previous => {
+                            ctx.link().send_message(PlayerMsg::AskForPrevTrack)
+                        }
+                        RemoteCommand::JumpForward if jump_allowed => {
+                            audio_link.send_message(AudioMsg::JumpForward)
+                        }
+                        RemoteCommand::JumpBackward if jump_allowed => {
+                            audio_link.send_message(AudioMsg::JumpBackward)
+                        }
+                        RemoteCommand::JumpForward | RemoteCommand::JumpBackward => (),
+                    }
+                }
+
+                false
+            }
+
+            PlayerMsg::SetSyncedSpeed { speed, updated } => {
+                if updated <= self.state.speed_updated {
+                    return false;
+                }
+                set_playback_speed(speed, &audio_link);
+                self.state.playback_speed = speed;
+                self.state.speed_updated = updated;
+
+                // Only the player state is saved. The current article might not be loaded yet, and
+                // saving its position now would lose the listener's place.
+                let player_state = self.state.clone();
+                spawn_local(async move {
+                    let _ = caching::save_player_state(&player_state)
+                        .await
+                        .map_err(|e| tracing::error!("Could not save player state: {}", e));
+                });
+                true
+            }
+
+            PlayerMsg::SetSyncedPositions(states) => {
+                // Skip ahead in the current article if another device got further in it. If it's
+                // playing, this device is the one being listened on, so it's left alone.
+                let now_playing = self.state.now_playing.as_ref().map(|entry| &entry.id);
+                let synced = states.into_iter().find(|s| Some(&s.id) == now_playing);
+                if let Some(state) = synced {
+                    if !GlobalAudio::is_playing() && state.elapsed > GlobalAudio::get_elapsed() {
+                        GlobalAudio::seek(state.elapsed);
+                    }
+                }
+                false
+            }
+
+            PlayerMsg::ShowPositionConflict(conflict) => {
+                // The listener may have moved on while the article was loading
+                let now_playing = self.state.now_playing.as_ref().map(|entry| &entry.id);
+                if now_playing != Some(&conflict.id) {
+                    return false;
+                }
+                self.position_conflict = Some(conflict);
+                true
+            }
+
+            PlayerMsg::PickPosition { remote } => {
+                let Some(conflict) = self.position_conflict.take() else {
+                    return false;
+                };
+                position_conflicts::resolve(&conflict.id);
+                if remote {
+                    let state = ArticleState::from(conflict.remote);
+                    let now_playing = self.state.now_playing.as_ref().map(|entry| &entry.id);
+                    if now_playing == Some(&state.id) {
+                        GlobalAudio::seek(state.elapsed);
+                    }
+                    spawn_local(async move {
+                        if let Err(e) = caching::save_article_state(&state).await {
+                            tracing::error!("Couldn't save position in {}: {}", state.id.0, e);
+                        }
+                    });
+                } else {
+                    // The pick goes out with the next sync, so the other devices take this one
+                    position_conflicts::pick_local(&conflict.id, clock::now());
+                }
+                true
+            }
+
+            PlayerMsg::OfferResume { id, elapsed } => {
+                // Nothing to offer if the listener has moved on, or already pressed play
+                let still_loaded = self.state.now_playing.as_ref().map(|e| &e.id) == Some(&id);
+                if !still_loaded || self.stale || GlobalAudio::is_playing() {
+                    return false;
+                }
+                self.resume_offer = Some(elapsed);
+                true
+            }
+
+            PlayerMsg::ResumeAfterReload => {
+                if self.resume_offer.take().is_some() {
+                    audio_link.send_message(AudioMsg::Play);
+                }
+                true
+            }
+
+            PlayerMsg::DismissResume => {
+                self.resume_offer = None;
+                true
+            }
+
+            PlayerMsg::PageTapped => {
+                if self.state.resume_on_tap && self.resume_offer.is_some() {
+                    ctx.link().send_message(PlayerMsg::ResumeAfterReload);
+                }
+                false
+            }
+
+            PlayerMsg::ToggleResumeOnTap => {
+                self.state.resume_on_tap = !self.state.resume_on_tap;
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::SetRecapMode(mode) => {
+                self.state.recap_mode = mode;
+
+                // Save state to disk, since it changed. This is an ad-hoc (ie non-periodic) save
+                let periodic = false;
+                trigger_save(periodic, &ctx.link());
+
+                true
+            }
+
+            PlayerMsg::PlayRecap(recap) => {
+                if recap::speak_recap(&ctx.link(), &recap) {
+                    self.recap = Some(recap);
+                    true
+                } else {
+                    audio_link.send_message(AudioMsg::Play);
+                    false
+                }
+            }
+
+            PlayerMsg::RecapDone => {
+                // The recap's speech ends after it's skipped, or the audio's played over it
+                if self.recap.take().is_none() {
+                    return false;
+                }
+                recap::stop_recap();
+                audio_link.send_message(AudioMsg::Play);
+                true
+            }
+        }
+    }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        // An unusual speed only gets its option in the speed selector once it's rendered, so it
+        // can only be selected now
+        if !PLAYBACK_SPEEDS.contains(&self.state.playback_speed) {
+            get_speed_selector().set_value(&format!("{}", self.state.playback_speed));
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let player_link = ctx.props().player_link.borrow().clone().unwrap();
+
+        //
+        // Callbacks for the play, jump backward and forward buttons
+        //
+
+        let audio_link = self.audio_link.clone();
+        let jump_forward_cb = Callback::from(move |_: MouseEvent| {
+            audio_link
+                .borrow()
+                .as_ref()
+                .unwrap()
+                .send_message(AudioMsg::JumpForward)
+        });
+
+        let audio_link = self.audio_link.clone();
+        let jump_backward_cb = Callback::from(move |_: MouseEvent| {
+            audio_link
+                .borrow()
+                .as_ref()
+                .unwrap()
+                .send_message(AudioMsg::JumpBackward)
+        });
+
+        // Callback for the "go to beginning". When prevtrack is clicked, go to the beginning of
+        // the audio. If it's clicked twice within one second, i.e., double-clicked, then move to
+        // the previous track.
         let gotobeginning_cb = Callback::from(move |_: MouseEvent| {
             GlobalAudio::seek(0.0);
         });
 
+        // The seek buttons are disabled while the position is locked
+        let locked = self.state.lock_position;
+        let jump_backward_label =
+            format!("Jump backwards {} seconds", self.state.jump_backward_secs);
+        let jump_forward_label = format!("Jump forwards {} seconds", self.state.jump_forward_secs);
+
+        // If the article has sections, show buttons to skip between them
+        let section_buttons = if self.transcript.timepoints.is_empty() {
+            Html::default()
+        } else {
+            let prev_section_cb = player_link.callback(|_| PlayerMsg::PrevSection);
+            let next_section_cb = player_link.callback(|_| PlayerMsg::NextSection);
+            html! {
+                <>
+                    <button
+                        aria-label="Previous section"
+                        title="Previous section"
+                        disabled={locked}
+                        onclick={prev_section_cb}
+                    >
+                        { "⏪" }
+                    </button>
+                    <button
+                        aria-label="Next section"
+                        title="Next section"
+                        disabled={locked}
+                        onclick={next_section_cb}
+                    >
+                        { "⏩" }
+                    </button>
+                </>
+            }
+        };
+
         // Callback for the playback speed
         let playback_speed_cb = player_link.callback(|_| PlayerMsg::UpdatePlaybackSpeed);
 
         // Set nowplaying
         let now_playing = self.state.now_playing.clone();
-        let playback_speed_selector = render_playback_speed_selector(playback_speed_cb);
-        let now_playing_html = now_playing
-            .as_ref()
-            .map(|entry| html! {<span> {entry.title.clone()} </span>})
-            .unwrap_or(html! {<span style="font-style: italic">{"[no article loaded]"}</span>});
+        let playback_speed_selector =
+            render_playback_speed_selector(playback_speed_cb, self.state.playback_speed);
+        let now_playing_html = match (&now_playing, &self.streaming_title) {
+            (Some(entry), _) => html! {<bdi> {entry.title.clone()} </bdi>},
+            (None, Some(title)) => html! {
+                <span> <bdi>{title.clone()}</bdi> <em>{" (still converting)"}</em> </span>
+            },
+            (None, None) => html! {<span style="font-style: italic">{"[no article loaded]"}</span>},
+        };
+
+        // If the current article is part of a group, show the progress through the whole group
+        let now_playing_group = now_playing.as_ref().and_then(|entry| entry.group.as_ref());
+        let group_progress_html = match (now_playing_group, self.group_progress) {
+            (Some(group), Some(pct)) => html! {
+                <p>
+                    <label for="group-progress">
+                        { format!("{} (part {} of {}): ", group.title, group.part, group.parts) }
+                    </label>
+                    <progress id="group-progress" max="100" value={ pct.to_string() } />
+                    { format!(" {pct}%") }
+                </p>
+            },
+            _ => Html::default(),
+        };
+        let playing_id = now_playing.as_ref().map(|entry| entry.id.clone());
+        let on_ended = player_link.callback(|_| PlayerMsg::TrackEnded);
+        let on_timeupdate = player_link.callback(|_| PlayerMsg::TimeUpdate);
+        let on_seeking = player_link.callback(|_| PlayerMsg::Seeking);
+        let on_play = player_link.callback(|_| PlayerMsg::AudioPlayed);
+        let on_pause = player_link.callback(|_| PlayerMsg::AudioPaused);
+
+        // If another tab has played, offer to pick up from it
+        let take_over_html = if self.stale {
+            let take_over_cb = player_link.callback(|_| PlayerMsg::TakeOver);
+            let status = if self.playing_elsewhere {
+                "Playing in another tab."
+            } else {
+                "Another tab was playing."
+            };
+            html! {
+                <p role="status">
+                    <strong>{ status }</strong>
+                    { " " }
+                    <button onclick={take_over_cb}>{ "Take over" }</button>
+                </p>
+            }
+        } else {
+            Html::default()
+        };
+        let continuous_playback_cb = player_link.callback(|_| PlayerMsg::ToggleContinuousPlayback);
+        let lock_position_cb = player_link.callback(|_| PlayerMsg::ToggleLockPosition);
+        let battery_saver_cb = player_link.callback(|_| PlayerMsg::ToggleBatterySaver);
+        let battery_low_html = if self.battery_low {
+            html! {
+                <span role="status">
+                    { " (the battery is low, so the position is saved less often)" }
+                </span>
+            }
+        } else {
+            Html::default()
+        };
+
+        // If the listener just jumped far with the scrubber, offer to go back
+        let seek_undo_html = match self.seek_undo {
+            Some((from, to)) => {
+                let undo_cb = player_link.callback(|_| PlayerMsg::UndoSeek);
+                let dismiss_cb = player_link.callback(|_| PlayerMsg::DismissSeekUndo);
+                let direction = if to > from { "forward" } else { "back" };
+                let minutes = ((to - from).abs() / 60.0).round();
+                let plural = if minutes == 1.0 { "" } else { "s" };
+                html! {
+                    <p role="status">
+                        { format!("Jumped {minutes} minute{plural} {direction}. Undo? ") }
+                        <button onclick={undo_cb}>{ "Undo" }</button>
+                        <button
+                            aria-label="Dismiss undo"
+                            title="Dismiss undo"
+                            onclick={dismiss_cb}
+                        >
+                            { "✕" }
+                        </button>
+                    </p>
+                }
+            }
+            None => Html::default(),
+        };
+
+        // If there's a suggested speed, offer it
+        let speed_suggestion_html = match self.speed_suggestion {
+            Some(speed) => {
+                let apply_cb = player_link.callback(|_| PlayerMsg::ApplySuggestedSpeed);
+                let dismiss_cb = player_link.callback(|_| PlayerMsg::DismissSpeedSuggestion);
+                html! {
+                    <p role="status">
+                        { format!("Similar articles you listen to at {speed}x. Apply? ") }
+                        <button onclick={apply_cb}>{ "Apply" }</button>
+                        <button
+                            aria-label="Dismiss speed suggestion"
+                            title="Dismiss speed suggestion"
+                            onclick={dismiss_cb}
+                        >
+                            { "✕" }
+                        </button>
+                    </p>
+                }
+            }
+            None => Html::default(),
+        };
+
+        // If an article was just finished during speed training, ask how much of it was followed
+        let comprehension_html = match &self.comprehension_check {
+            Some((entry, speed)) => {
+                speed_training::render_comprehension_prompt(&player_link, &entry.title, *speed)
+            }
+            None => Html::default(),
+        };
+
+        // If another device has a conflicting position in this article, ask which to continue from
+        let current_id = self.state.now_playing.as_ref().map(|entry| &entry.id);
+        let position_pick_html = match &self.position_conflict {
+            Some(conflict) if current_id == Some(&conflict.id) => {
+                position_pick::render_position_pick(&player_link, conflict)
+            }
+            _ => Html::default(),
+        };
+
+        let recap_html = match &self.recap {
+            Some(recap) => recap::render_recap(&player_link, recap),
+            None => Html::default(),
+        };
+
+        // If the page reloaded in the middle of an article, offer to resume it
+        let resume_html = match (self.resume_offer, &self.state.now_playing) {
+            (Some(elapsed), Some(entry)) => {
+                reload_resume::render_resume_overlay(&player_link, &entry.title, elapsed)
+            }
+            _ => Html::default(),
+        };
+
+        let end_sheet_html = match &self.ended {
+            Some(entry) => end_of_article::render_end_sheet(&player_link, entry, self.ended_rating),
+            None => Html::default(),
+        };
+
+        let moment_marked_html = match &self.moment_marked {
+            Some((secs, _)) => html! {
+                <p role="status">{ format!("Marked a moment at {}", format_time(*secs)) }</p>
+            },
+            None => Html::default(),
+        };
 
         let audio_link = self.audio_link.clone();
         html! {
             <section title="Player">
                 <h2>{ "Player" }</h2>
                 <p><strong>{ "Now Playing: " }</strong> { now_playing_html }</p>
-                <Audio {audio_link} />
+                { group_progress_html }
+                { take_over_html }
+                { resume_html }
+                { position_pick_html }
+                { recap_html }
+                <Audio {audio_link} {on_ended} {on_timeupdate} {on_seeking} {on_play} {on_pause} />
+                <Waveform
+                    transcript={self.transcript.clone()}
+                    waveform_link={self.waveform_link.clone()}
+                />
+                <button
+                    class="momentButton"
+                    title="Mark this moment (m)"
+                    disabled={ self.state.now_playing.is_none() }
+                    onclick={ player_link.callback(|_| PlayerMsg::MarkMoment) }
+                >
+                    { "⭐ Mark moment" }
+                </button>
+                { moment_marked_html }
+                { seek_undo_html }
                 <div class="audiocontrol" title="More playback controls">
                     <button
                         aria-label="Go to beginning"
                         title="Go to beginning"
+                        disabled={locked}
                         onclick={gotobeginning_cb}
                     >
                         { "⏮️" }
                     </button>
 
                     <button
-                        aria-label="Jump backwards 10 seconds"
-                        title="Jump backwards 10 seconds"
+                        aria-label={ jump_backward_label.clone() }
+                        title={ jump_backward_label }
+                        disabled={locked}
                         onclick={jump_backward_cb}
                     >
                         { "↩️" }
                     </button>
                     <button
-                        aria-label="Jump forwards 10 seconds"
-                        title="Jump forwards 10 seconds"
+                        aria-label={ jump_forward_label.clone() }
+                        title={ jump_forward_label }
+                        disabled={locked}
                         onclick={jump_forward_cb}
                     >
                     { "↪️" }
                     </button>
+                    { section_buttons }
 
                     <div class="playbackSpeedSection">
                         <label id="speedSelectorLabel" for={SPEED_SELECTOR_ID}>
@@ -461,15 +2187,264 @@ impl Component for Player {
                         { playback_speed_selector }
                     </div>
                 </div>
+                { end_sheet_html }
+                { speed_suggestion_html }
+                { comprehension_html }
+                { sleep_timer::render_sleep_timer(&player_link, self.sleep_minutes_left) }
+                { away_pause::render_away_pause_selector(&player_link, self.state.away_pause_minutes) }
+                { remote_control::render_remote_control_toggle(&player_link, self.state.remote_control) }
+                { reload_resume::render_resume_toggle(&player_link, self.state.resume_on_tap) }
+                { recap::render_recap_selector(&player_link, self.state.recap_mode) }
+                { shortcuts::render_jump_settings(
+                    &player_link,
+                    self.state.jump_backward_secs,
+                    self.state.jump_forward_secs,
+                ) }
+                { audio_processing::render_processing_settings(&player_link, self.state.audio_processing) }
+                { speed_training::render_training_settings(
+                    &player_link,
+                    self.state.speed_training.as_ref(),
+                ) }
+                <p>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={ self.state.continuous_playback }
+                            onchange={continuous_playback_cb}
+                        />
+                        { " Continuous playback: play the next article when this one ends" }
+                    </label>
+                </p>
+                <p>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={ self.state.lock_position }
+                            onchange={lock_position_cb}
+                        />
+                        { " Lock position: undo seeks with the scrubber" }
+                    </label>
+                </p>
+                <p>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={ battery::saver_enabled() }
+                            onchange={battery_saver_cb}
+                        />
+                        { " Battery saver: do less in the background when the battery is low" }
+                    </label>
+                    { battery_low_html }
+                </p>
+                <FindInArticle article={playing_id.clone()} />
+                <AskAboutArticle
+                    title={ now_playing.as_ref().map(|e| e.title.clone()).unwrap_or_default() }
+                    transcript={self.transcript.clone()}
+                />
+                <ReadAlong
+                    transcript={self.transcript.clone()}
+                    read_along_link={self.read_along_link.clone()}
+                />
+                <TextExport
+                    article={playing_id}
+                    title={ now_playing.as_ref().map(|e| e.title.clone()).unwrap_or_default() }
+                    transcript={self.transcript.clone()}
+                />
             </section>
         }
     }
 }
 
-/// Renders the playback speed selector, using the given callback for onchange events
-fn render_playback_speed_selector(onchange: Callback<Event>) -> Html {
+impl Player {
+    /// Saves the player state, and the given elapsed time as the current article's. Once it's
+    /// saved, the other tabs are told `then_tell`, if given.
+    fn save(&self, elapsed: f64, then_tell: Option<TabMessage>) {
+        // Collect the states to save. Player state holds now-playing and playback speed.
+        // Article state holds elapsed time
+        // Note whether the audio is playing, so a reload can pick it back up
+        let mut player_state = self.state.clone();
+        player_state.playing_at = GlobalAudio::is_playing().then(clock::now);
+        let plays = self.current_plays().cloned();
+        let article_state = player_state
+            .now_playing
+            .clone()
+            .map(|entry| ArticleState::new(entry.id, elapsed, plays.as_ref()));
+        let session = self.session.clone();
+
+        // Save the states
+        let tabs = self.tabs.clone();
+        spawn_local(async move {
+            // Save the player state first
+            match caching::save_player_state(&player_state).await {
+                Ok(_) => tracing::trace!("Successfully saved player state"),
+                Err(e) => tracing::error!("Could not save player state: {}", e),
+            }
+
+            // Try to save the article state. There may well be nothing playing. In which
+            // case, do nothing.
+            if let Some(s) = article_state {
+                match caching::save_article_state(&s).await {
+                    Ok(_) => tracing::trace!("Successfully saved article state"),
+                    Err(e) => tracing::error!("Could not save article state: {}", e),
+                }
+            } else {
+                tracing::trace!("No article to save");
+            }
+            if let Some(plays) = plays {
+                if let Err(e) = caching::save_play_counts(&plays).await {
+                    tracing::error!("Could not save play counts: {}", e);
+                }
+            }
+            if let Some(s) = session {
+                history_view::save_session(&s).await;
+            }
+
+            if let Some(msg) = then_tell {
+                tabs.send(msg);
+            }
+        });
+    }
+
+    /// Adds the audio from `from` to `to`, in seconds, to the listening session of the current
+    /// article, starting one if it hasn't been
+    fn record_listening(&mut self, from: f64, to: f64) {
+        let Some(entry) = self.state.now_playing.as_ref() else {
+            return;
+        };
+        // Another tab or device may have changed the article without this one playing it
+        if self.session.as_ref().is_some_and(|s| s.id != entry.id) {
+            if let Some(old) = self.session.take() {
+                history_view::spawn_save_session(&old);
+            }
+        }
+        let session = self
+            .session
+            .get_or_insert_with(|| ListeningSession::new(entry));
+
+        let elapsed = GlobalAudio::get_elapsed();
+        let duration = GlobalAudio::get_duration();
+        let plays = self.plays.as_ref().filter(|plays| plays.id == entry.id);
+        let finished = heatmap::is_finished(plays, elapsed, duration);
+        session.listened(to - from, elapsed / duration, finished);
+        session.played(from, to);
+    }
+
+    /// Returns how many times each part of the current article has been played, if they're loaded
+    fn current_plays(&self) -> Option<&PlayCounts> {
+        let id = &self.state.now_playing.as_ref()?.id;
+        self.plays.as_ref().filter(|plays| &plays.id == id)
+    }
+
+    /// Sets the play counts of the current article, and shows them on the waveform
+    fn set_plays(&mut self, plays: Option<PlayCounts>) {
+        let counts = plays.as_ref().map(|p| p.counts.clone()).unwrap_or_default();
+        if let Some(waveform) = self.waveform_link.borrow().as_ref() {
+            waveform.send_message(WaveformMsg::Plays(counts));
+        }
+        self.plays = plays;
+    }
+
+    /// Counts the audio from `from` to `to`, in seconds, as played in the current article
+    fn record_plays(&mut self, from: f64, to: f64) {
+        let Some(id) = self.state.now_playing.as_ref().map(|entry| &entry.id) else {
+            return;
+        };
+        let Some(plays) = self.plays.as_mut().filter(|plays| &plays.id == id) else {
+            return;
+        };
+        if plays.record(from, to) {
+            if let Some(waveform) = self.waveform_link.borrow().as_ref() {
+                waveform.send_message(WaveformMsg::Plays(plays.counts.clone()));
+            }
+        }
+    }
+
+    /// Saves the current listening session, if any, and has the queue and library show how far its
+    /// article has been listened to now
+    fn end_session(&mut self, queue_link: &Scope<Queue>) {
+        let Some(session) = self.session.take() else {
+            return;
+        };
+        let queue_link = queue_link.clone();
+        spawn_local(async move {
+            history_view::save_session(&session).await;
+            queue_link.send_message(QueueMsg::RefreshListened);
+        });
+    }
+
+    /// Sets the state and makes it reflected in the player. That is, sets the playback speed and
+    /// the timers. The current article isn't loaded.
+    fn set_state(&mut self, state: PlayerState, ctx: &Context<Self>) {
+        self.state = state;
+        let audio_link = self.audio_link.borrow().clone().unwrap();
+        set_playback_speed(self.state.playback_speed, &audio_link);
+        GlobalAudio::set_jump_sizes(self.state.jump_backward_secs, self.state.jump_forward_secs);
+        audio_processing::set_options(self.state.audio_processing);
+
+        // Pick the sleep timer back up, unless it went off while the page was closed
+        let sleep_timer = self.state.sleep_timer.filter(|t| !t.is_expired());
+        self.set_sleep_timer(sleep_timer, ctx);
+        self.start_away_check(ctx);
+        self.start_remote_poll(ctx);
+    }
+
+    /// Starts polling for remote commands, if remote control is on, or stops polling if it's off
+    fn start_remote_poll(&mut self, ctx: &Context<Self>) {
+        // A new poll starts over with finding out the latest command, so it doesn't act on old ones
+        self.remote_seq = None;
+        self.remote_poll = self.state.remote_control.then(|| {
+            let link = ctx.link().clone();
+            clock::interval(REMOTE_POLL_FREQ, move || {
+                link.send_message(PlayerMsg::PollRemote)
+            })
+        });
+    }
+
+    /// Starts checking whether the listener has gone away, if pausing when away is on, or stops
+    /// checking if it's off
+    fn start_away_check(&mut self, ctx: &Context<Self>) {
+        self.away_check = self.state.away_pause_minutes.map(|_| {
+            let link = ctx.link().clone();
+            clock::interval(AWAY_CHECK_FREQ, move || {
+                link.send_message(PlayerMsg::CheckAway)
+            })
+        });
+    }
+
+    /// Sets the sleep timer, or turns it off if `None`. Any fade-out in progress is undone. The
+    /// player state isn't saved, that's up to the caller.
+    fn set_sleep_timer(&mut self, timer: Option<SleepTimer>, ctx: &Context<Self>) {
+        self.state.sleep_timer = timer;
+        sleep_timer::show_selection(timer);
+        if let Some(volume) = self.volume_before_fade.take() {
+            GlobalAudio::set_volume(volume);
+        }
+
+        // Check on the timer regularly while it's set
+        self.sleep_minutes_left = None;
+        self.sleep_timer_tick = timer.map(|_| {
+            let link = ctx.link().clone();
+            clock::interval(SLEEP_TIMER_TICK_FREQ, move || {
+                link.send_message(PlayerMsg::SleepTimerTick)
+            })
+        });
+        if timer.is_some() {
+            ctx.link().send_message(PlayerMsg::SleepTimerTick);
+        }
+    }
+}
+
+/// Renders the playback speed selector, using the given callback for onchange events. The current
+/// speed is offered too, if it's not one of the usual ones, e.g., a speed training speed.
+fn render_playback_speed_selector(onchange: Callback<Event>, current: f64) -> Html {
+    let mut speeds = PLAYBACK_SPEEDS.to_vec();
+    if !speeds.contains(&current) {
+        speeds.push(current);
+        speeds.sort_by(f64::total_cmp);
+    }
+
     // Construct all the <option> values
-    let options: Html = PLAYBACK_SPEEDS
+    let options: Html = speeds
         .iter()
         .map(|speed| {
             let speed_str = format!("{}", speed);
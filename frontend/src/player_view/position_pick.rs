@@ -0,0 +1,40 @@
+//! Picking between this device's position in an article and a conflicting one from another device,
+//! when the article's opened. See `position_conflicts.rs` for when positions conflict.
+
+use super::{find_in_article::format_time, Player, PlayerMsg};
+use crate::position_conflicts::PositionConflict;
+use common::SyncedPosition;
+
+use yew::{html::Scope, prelude::*};
+
+/// Describes where the given position is, e.g., "42:10"
+fn describe(pos: &SyncedPosition) -> String {
+    if pos.finished {
+        "the end".to_string()
+    } else {
+        format_time(pos.elapsed)
+    }
+}
+
+/// Renders the chooser between the positions in the given conflict, e.g., "Phone: 42:10 • This
+/// device: 17:55"
+pub(crate) fn render_position_pick(
+    player_link: &Scope<Player>,
+    conflict: &PositionConflict,
+) -> Html {
+    let pick_remote = player_link.callback(|_| PlayerMsg::PickPosition { remote: true });
+    let pick_local = player_link.callback(|_| PlayerMsg::PickPosition { remote: false });
+    html! {
+        <p class="positionPick" role="group" aria-label="Pick a position">
+            { "Your devices disagree on where you are. Continue from " }
+            <button onclick={pick_remote}>
+                <bdi>{ &conflict.remote_name }</bdi>
+                { format!(": {}", describe(&conflict.remote)) }
+            </button>
+            { " • " }
+            <button onclick={pick_local}>
+                { format!("This device: {}", describe(&conflict.local)) }
+            </button>
+        </p>
+    }
+}
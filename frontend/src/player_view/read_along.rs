@@ -0,0 +1,332 @@
+//! The text of the article that's playing, with the sentence being spoken highlighted. The pane
+//! scrolls along with the audio, and clicking a sentence plays from it. When each sentence is
+//! spoken is worked out from the article's transcript, the same way as for finding in the article.
+//!
+//! Scrolling the pane by hand, e.g., to peek ahead, stops it following the audio, so it doesn't
+//! scroll back out from under the reader. It follows again when "Resume following" is tapped, a
+//! sentence is clicked, or the pane is reopened.
+
+use super::{
+    audio_component::GlobalAudio,
+    find_in_article::{format_time, time_at},
+};
+use crate::WeakComponentLink;
+use common::ArticleTranscript;
+
+use std::rc::Rc;
+
+use web_sys::HtmlElement;
+use yew::prelude::*;
+
+/// A sentence of the article
+struct Sentence {
+    text: String,
+    /// The paragraph the sentence is in. Consecutive sentences with the same number are rendered
+    /// together.
+    paragraph: usize,
+    /// When the sentence starts in the audio, in seconds
+    secs: f64,
+}
+
+/// How far, in pixels, the pane can be from where it was last scrolled to before it counts as
+/// scrolled by hand. Browsers round scroll positions, so it's not always exactly there.
+const MANUAL_SCROLL_TOLERANCE_PX: i32 = 2;
+
+/// Punctuation that ends a sentence when it's followed by whitespace, including the Arabic question
+/// mark and the Urdu full stop
+const SENTENCE_ENDS: &[char] = &['.', '!', '?', '…', '؟', '۔'];
+
+/// Punctuation that ends a sentence wherever it is, since CJK text doesn't put spaces between
+/// sentences
+const UNSPACED_SENTENCE_ENDS: &[char] = &['。', '！', '？'];
+
+/// Splits the given text into sentences, and returns the character range and paragraph number of
+/// each. A sentence ends at a sentence-ending mark that's followed by whitespace, at a CJK
+/// full stop or question or exclamation mark, or at a line break.
+fn split_sentences(text: &[char]) -> Vec<(std::ops::Range<usize>, usize)> {
+    let mut sentences = Vec::new();
+    let mut paragraph = 0;
+    let mut start = 0;
+
+    let mut push = |range: std::ops::Range<usize>, paragraph| {
+        // Leave out the whitespace between sentences, and the blank ones
+        let skip = text[range.clone()]
+            .iter()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        if range.start + skip < range.end {
+            sentences.push((range.start + skip..range.end, paragraph));
+        }
+    };
+
+    for (i, &c) in text.iter().enumerate() {
+        if c == '\n' {
+            push(start..i, paragraph);
+            paragraph += 1;
+            start = i + 1;
+        } else if UNSPACED_SENTENCE_ENDS.contains(&c)
+            || (SENTENCE_ENDS.contains(&c) && text.get(i + 1).is_none_or(|n| n.is_whitespace()))
+        {
+            push(start..i + 1, paragraph);
+            start = i + 1;
+        }
+    }
+    push(start..text.len(), paragraph);
+
+    sentences
+}
+
+/// Splits the given transcript into sentences, and works out when each is spoken
+fn sentences_of(transcript: &ArticleTranscript) -> Vec<Sentence> {
+    let text: Vec<char> = transcript.text.chars().collect();
+    split_sentences(&text)
+        .into_iter()
+        .map(|(range, paragraph)| Sentence {
+            secs: time_at(transcript, text.len(), range.start),
+            text: text[range].iter().collect(),
+            paragraph,
+        })
+        .collect()
+}
+
+/// Returns the sentence being spoken at the given elapsed time, and the ones before and after it.
+/// Any that aren't there, e.g., before the first sentence, are empty.
+pub(super) fn context_at(transcript: &ArticleTranscript, elapsed: f64) -> [String; 3] {
+    let sentences = sentences_of(transcript);
+    let Some(current) = sentences
+        .partition_point(|s| s.secs <= elapsed)
+        .checked_sub(1)
+    else {
+        return Default::default();
+    };
+    let text = |i: Option<usize>| {
+        i.and_then(|i| sentences.get(i))
+            .map(|s| s.text.clone())
+            .unwrap_or_default()
+    };
+    [
+        text(current.checked_sub(1)),
+        text(Some(current)),
+        text(Some(current + 1)),
+    ]
+}
+
+/// Returns the sentences that have been started by the given elapsed time, in order
+pub(super) fn sentences_heard(transcript: &ArticleTranscript, elapsed: f64) -> Vec<String> {
+    let sentences = sentences_of(transcript);
+    let heard = sentences.partition_point(|s| s.secs <= elapsed);
+    sentences.into_iter().take(heard).map(|s| s.text).collect()
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// The transcript of the article that's playing. Nothing is shown if it's empty.
+    pub transcript: Rc<ArticleTranscript>,
+    /// A link to myself. The player uses it to say when the elapsed time changes.
+    pub read_along_link: WeakComponentLink<ReadAlong>,
+}
+
+pub(crate) enum ReadAlongMsg {
+    /// The audio is at the given elapsed time
+    TimeUpdate(f64),
+    /// Seeks the audio to the given time
+    Seek(f64),
+    /// The pane was opened or closed
+    Toggled,
+    /// The pane was scrolled, by following the audio or by hand
+    Scrolled,
+    /// Starts following the audio again after the pane was scrolled by hand
+    ResumeFollowing,
+}
+
+/// The read-along pane of the player
+pub(crate) struct ReadAlong {
+    /// The sentences of the transcript
+    sentences: Vec<Sentence>,
+    /// The index of the sentence being spoken, if any
+    current: Option<usize>,
+    /// The scrolling box the sentences are in
+    pane_ref: NodeRef,
+    /// The sentence being spoken
+    current_ref: NodeRef,
+    /// Whether the pane should scroll to the sentence being spoken once it's rendered
+    scroll_pending: bool,
+    /// Whether the pane scrolls along with the audio. It stops when it's scrolled by hand.
+    following: bool,
+    /// Where the pane was last scrolled to by following the audio
+    followed_scroll_top: i32,
+}
+
+impl ReadAlong {
+    /// Returns the index of the sentence being spoken at the given elapsed time
+    fn sentence_at(&self, elapsed: f64) -> Option<usize> {
+        self.sentences
+            .partition_point(|s| s.secs <= elapsed)
+            .checked_sub(1)
+    }
+
+    /// Moves the highlight to the sentence at the given elapsed time. Returns whether it moved.
+    fn set_elapsed(&mut self, elapsed: f64) -> bool {
+        let current = self.sentence_at(elapsed);
+        if current == self.current {
+            return false;
+        }
+        self.current = current;
+        self.scroll_pending = true;
+        true
+    }
+
+    /// Follows the audio again, scrolling to the sentence being spoken
+    fn resume_following(&mut self) {
+        self.following = true;
+        self.scroll_pending = true;
+    }
+}
+
+impl Component for ReadAlong {
+    type Message = ReadAlongMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.props()
+            .read_along_link
+            .borrow_mut()
+            .replace(ctx.link().clone());
+
+        let mut read_along = Self {
+            sentences: sentences_of(&ctx.props().transcript),
+            current: None,
+            pane_ref: NodeRef::default(),
+            current_ref: NodeRef::default(),
+            scroll_pending: false,
+            following: true,
+            followed_scroll_top: 0,
+        };
+        read_along.set_elapsed(GlobalAudio::get_elapsed());
+        read_along
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ReadAlongMsg::TimeUpdate(elapsed) => self.set_elapsed(elapsed),
+            ReadAlongMsg::Seek(secs) => {
+                GlobalAudio::seek(secs);
+                // Clicking a sentence is done peeking
+                self.resume_following();
+                true
+            }
+            ReadAlongMsg::Toggled => {
+                // The sentence can only be scrolled to while the pane is open
+                self.resume_following();
+                true
+            }
+            ReadAlongMsg::Scrolled => {
+                let Some(pane) = self.pane_ref.cast::<HtmlElement>() else {
+                    return false;
+                };
+                let moved = (pane.scroll_top() - self.followed_scroll_top).abs();
+                if self.following && moved > MANUAL_SCROLL_TOLERANCE_PX {
+                    self.following = false;
+                    return true;
+                }
+                false
+            }
+            ReadAlongMsg::ResumeFollowing => {
+                self.resume_following();
+                true
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        // A different article might be playing now
+        self.sentences = sentences_of(&ctx.props().transcript);
+        self.current = None;
+        self.following = true;
+        self.set_elapsed(GlobalAudio::get_elapsed());
+        true
+    }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        if !std::mem::take(&mut self.scroll_pending) || !self.following {
+            return;
+        }
+
+        // Scroll the pane so the sentence being spoken is a third of the way down it. Where it
+        // ends up is read back, since it's kept within the pane's bounds.
+        let pane = self.pane_ref.cast::<HtmlElement>();
+        let sentence = self.current_ref.cast::<HtmlElement>();
+        if let (Some(pane), Some(sentence)) = (pane, sentence) {
+            pane.set_scroll_top(sentence.offset_top() - pane.client_height() / 3);
+            self.followed_scroll_top = pane.scroll_top();
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if self.sentences.is_empty() {
+            return Html::default();
+        }
+
+        // Render the sentences, grouped into their paragraphs
+        let mut paragraphs: Vec<Vec<Html>> = Vec::new();
+        let mut last_paragraph = None;
+        for (i, sentence) in self.sentences.iter().enumerate() {
+            if last_paragraph != Some(sentence.paragraph) {
+                paragraphs.push(Vec::new());
+                last_paragraph = Some(sentence.paragraph);
+            }
+
+            let secs = sentence.secs;
+            let onclick = ctx.link().callback(move |_| ReadAlongMsg::Seek(secs));
+            let is_current = self.current == Some(i);
+            // CJK sentences aren't spaced apart
+            let spacer = match sentence.text.chars().last() {
+                Some(c) if UNSPACED_SENTENCE_ENDS.contains(&c) => "",
+                _ => " ",
+            };
+            let node_ref = if is_current {
+                self.current_ref.clone()
+            } else {
+                NodeRef::default()
+            };
+            paragraphs.last_mut().unwrap().push(html! {
+                <>
+                    <span
+                        class={ classes!("sentence", is_current.then(|| "current")) }
+                        ref={node_ref}
+                        title={ format!("Play from {}", format_time(secs)) }
+                        {onclick}
+                    >
+                        { sentence.text.clone() }
+                    </span>
+                    { spacer }
+                </>
+            });
+        }
+        let paragraphs = paragraphs
+            .into_iter()
+            // Each paragraph takes its direction from its text, so Arabic and Hebrew read right to
+            // left even in an article that mixes them with English
+            .map(|sentences| html! { <p dir="auto">{ for sentences }</p> })
+            .collect::<Html>();
+
+        let ontoggle = ctx.link().callback(|_| ReadAlongMsg::Toggled);
+        let onscroll = ctx.link().callback(|_| ReadAlongMsg::Scrolled);
+        html! {
+            <details class="readAlong" {ontoggle}>
+                <summary>{ "Article text" }</summary>
+                <div class="readAlongText" ref={self.pane_ref.clone()} {onscroll}>
+                    { paragraphs }
+                </div>
+                if !self.following {
+                    <button
+                        class="resumeFollowing"
+                        onclick={ ctx.link().callback(|_| ReadAlongMsg::ResumeFollowing) }
+                    >
+                        { "↓ Resume following" }
+                    </button>
+                }
+            </details>
+        }
+    }
+}
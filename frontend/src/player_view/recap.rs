@@ -0,0 +1,201 @@
+//! "Previously on…" recaps. Coming back to a long article after a day or more, it's easy to have
+//! lost the thread, so with the setting on, the player first speaks a short recap, then carries on
+//! from where the listener left off. The recap is either the last few sentences they heard, from
+//! the transcript, or a summary of everything they've heard so far from the server's language
+//! model. Servers without one get the last sentences instead. The recap's spoken by the browser's
+//! own speech synthesis, like answers to questions about the article.
+
+use super::{ask, read_along, ArticleState, Player, PlayerMsg};
+use common::{ArticleQuestion, ArticleTranscript};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::SpeechSynthesisUtterance;
+use yew::{html::Scope, prelude::*};
+
+/// How long an article has to have been left, in milliseconds, to get a recap
+const RECAP_AFTER_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// How far into an article the listener has to be, in seconds, for there to be anything to recap
+const MIN_RECAP_ELAPSED_SECS: f64 = 60.0;
+
+/// How many of the last sentences heard make up a recap
+const RECAP_SENTENCES: usize = 3;
+
+/// What's recapped when an article's resumed after a break
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RecapMode {
+    /// Nothing. Articles carry on where they were left.
+    #[default]
+    Off,
+    /// The last few sentences heard
+    LastSentences,
+    /// A summary of everything heard so far
+    Summary,
+}
+
+impl RecapMode {
+    /// All the modes, in the order they're offered
+    const ALL: [RecapMode; 3] = [RecapMode::Off, RecapMode::LastSentences, RecapMode::Summary];
+
+    /// The value of the mode's option in the selector
+    fn value(&self) -> &'static str {
+        match self {
+            RecapMode::Off => "off",
+            RecapMode::LastSentences => "last-sentences",
+            RecapMode::Summary => "summary",
+        }
+    }
+
+    /// Describes the mode, for picking it
+    fn label(&self) -> &'static str {
+        match self {
+            RecapMode::Off => "never",
+            RecapMode::LastSentences => "the last few sentences heard",
+            RecapMode::Summary => "a summary of what was heard, if the server can make one",
+        }
+    }
+}
+
+/// Returns whether an article left in the given state gets a recap, at the given time in
+/// milliseconds since the Unix epoch
+pub(crate) fn is_due(state: &ArticleState, now: f64) -> bool {
+    !state.finished
+        && state.elapsed >= MIN_RECAP_ELAPSED_SECS
+        && state.last_played > 0.0
+        && now - state.last_played >= RECAP_AFTER_MS
+}
+
+/// Returns the last few of the given sentences, as one passage
+fn last_sentences(heard: &[String]) -> String {
+    heard[heard.len().saturating_sub(RECAP_SENTENCES)..].join(" ")
+}
+
+/// Makes the recap of the given article up to the given elapsed time. Returns `None` if there's
+/// nothing to recap, e.g., because the article has no transcript.
+pub(crate) async fn make_recap(
+    mode: RecapMode,
+    title: &str,
+    transcript: &ArticleTranscript,
+    elapsed: f64,
+) -> Option<String> {
+    let heard = read_along::sentences_heard(transcript, elapsed);
+    if mode == RecapMode::Off || heard.is_empty() {
+        return None;
+    }
+
+    if mode == RecapMode::Summary {
+        let question = ArticleQuestion {
+            title: title.to_string(),
+            text: heard.join(" "),
+            question: "In two or three sentences, recap what this part of the article covers, to \
+                remind a listener who's coming back to it."
+                .to_string(),
+        };
+        match ask::ask(&question).await {
+            Ok(summary) => return Some(summary),
+            Err(e) => tracing::debug!("No summary for the recap, so using the last sentences: {e}"),
+        }
+    }
+    Some(last_sentences(&heard))
+}
+
+/// Speaks the given recap, and tells the player once it's done. Returns false if the browser can't
+/// speak, so the player should carry on right away.
+pub(crate) fn speak_recap(player_link: &Scope<Player>, recap: &str) -> bool {
+    let synth = gloo_utils::window().speech_synthesis();
+    let utterance = SpeechSynthesisUtterance::new_with_text(&format!("Previously: {recap}"));
+    let (Ok(synth), Ok(utterance)) = (synth, utterance) else {
+        tracing::warn!("This browser can't speak, so there's no recap");
+        return false;
+    };
+
+    // Skipping the recap cancels the speech, which ends it with an error instead
+    for set_handler in [
+        SpeechSynthesisUtterance::set_onend,
+        SpeechSynthesisUtterance::set_onerror,
+    ] {
+        let link = player_link.clone();
+        let done = Closure::once_into_js(move || link.send_message(PlayerMsg::RecapDone));
+        set_handler(&utterance, Some(done.unchecked_ref()));
+    }
+    synth.cancel();
+    synth.speak(&utterance);
+    true
+}
+
+/// Stops speaking the recap
+pub(crate) fn stop_recap() {
+    if let Ok(synth) = gloo_utils::window().speech_synthesis() {
+        synth.cancel();
+    }
+}
+
+/// Renders the recap being spoken, with a button to skip it
+pub(crate) fn render_recap(player_link: &Scope<Player>, recap: &str) -> Html {
+    let skip = player_link.callback(|_| PlayerMsg::RecapDone);
+    html! {
+        <p class="recap" role="status">
+            <strong>{ "Previously: " }</strong>
+            <span dir="auto">{ recap }</span>
+            { " " }
+            <button onclick={skip}>{ "Skip recap" }</button>
+        </p>
+    }
+}
+
+/// Renders the selector for what's recapped after a break
+pub(crate) fn render_recap_selector(player_link: &Scope<Player>, mode: RecapMode) -> Html {
+    let onchange = player_link.callback(|e: Event| {
+        let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+        let mode = RecapMode::ALL
+            .into_iter()
+            .find(|m| m.value() == select.value())
+            .unwrap_or_default();
+        PlayerMsg::SetRecapMode(mode)
+    });
+
+    let options: Html = RecapMode::ALL
+        .iter()
+        .map(|m| {
+            html! {
+                <option value={ m.value() } selected={ *m == mode }>{ m.label() }</option>
+            }
+        })
+        .collect();
+
+    html! {
+        <p>
+            <label for="recap-selector">
+                { "Recap articles resumed after a day or more: " }
+            </label>
+            <select id="recap-selector" {onchange}>{ options }</select>
+        </p>
+    }
+}
+
+#[test]
+fn recapping() {
+    let day_ms = RECAP_AFTER_MS;
+    let state = |elapsed: f64, finished: bool, last_played: f64| ArticleState {
+        id: crate::queue_view::ArticleId("a".to_string()),
+        elapsed,
+        finished,
+        last_played,
+        device: None,
+    };
+
+    // Only unfinished articles that are well underway and were left a day ago get recaps
+    let now = 10.0 * day_ms;
+    assert!(is_due(&state(600.0, false, now - day_ms), now));
+    assert!(!is_due(&state(600.0, false, now - day_ms + 1.0), now));
+    assert!(!is_due(&state(600.0, true, now - day_ms), now));
+    assert!(!is_due(&state(30.0, false, now - day_ms), now));
+    assert!(!is_due(&state(600.0, false, 0.0), now));
+
+    let heard: Vec<String> = ["One.", "Two.", "Three.", "Four."]
+        .map(str::to_string)
+        .to_vec();
+    assert_eq!(last_sentences(&heard), "Two. Three. Four.");
+    assert_eq!(last_sentences(&heard[..1]), "One.");
+}
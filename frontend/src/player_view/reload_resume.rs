@@ -0,0 +1,83 @@
+//! Picking playback back up after the page reloads. Mobile browsers reload tabs that have been in
+//! the background a while, which stops the audio. If an article was playing shortly before the page
+//! loaded, the player restores it at its position and offers to resume it with one tap. With the
+//! setting on, the first tap anywhere on the page resumes it. Browsers don't let a page play audio
+//! before the listener has touched it, so it can't resume by itself.
+
+use super::{find_in_article::format_time, Player, PlayerMsg};
+
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::Element;
+use yew::{html::Scope, prelude::*};
+
+/// How recently the audio had to be playing, in milliseconds, for the page loading to count as a
+/// reload in the middle of playback. Coming back to the app later is just opening it.
+pub(crate) const RELOAD_WINDOW_MS: f64 = 30.0 * 60.0 * 1000.0;
+
+/// The class of the resume overlay. Taps inside it are handled by its own buttons.
+const OVERLAY_CLASS: &str = "resumeOverlay";
+
+/// Listens for taps on the page, and tells the player about each, so it can resume on the first
+/// one
+pub(crate) struct GestureListener {
+    _cb: Closure<dyn Fn(Event)>,
+}
+
+impl GestureListener {
+    pub(crate) fn new(player: Scope<Player>) -> GestureListener {
+        let cb = Closure::new(move |e: Event| {
+            // The overlay's buttons and the <audio> controls do their own thing with the tap
+            let target = e.target().and_then(|t| t.dyn_into::<Element>().ok());
+            let handled_elsewhere = target.is_some_and(|t| {
+                t.closest(&format!(".{OVERLAY_CLASS}, audio"))
+                    .ok()
+                    .flatten()
+                    .is_some()
+            });
+            if !handled_elsewhere {
+                player.send_message(PlayerMsg::PageTapped);
+            }
+        });
+
+        let func = cb.as_ref().unchecked_ref();
+        if let Err(e) = gloo_utils::document().add_event_listener_with_callback("pointerdown", func)
+        {
+            tracing::error!("Could not listen for taps: {:?}", e);
+        }
+
+        GestureListener { _cb: cb }
+    }
+}
+
+/// Renders the offer to resume the given article at the given elapsed time
+pub(crate) fn render_resume_overlay(
+    player_link: &Scope<Player>,
+    title: &str,
+    elapsed: f64,
+) -> Html {
+    let resume_cb = player_link.callback(|_| PlayerMsg::ResumeAfterReload);
+    let dismiss_cb = player_link.callback(|_| PlayerMsg::DismissResume);
+    html! {
+        <div class={OVERLAY_CLASS} role="dialog" aria-label="Resume playback">
+            <button class="resumeButton" onclick={resume_cb}>
+                { "▶️ Resume " }
+                <bdi>{ title }</bdi>
+                { format!(" from {}", format_time(elapsed)) }
+            </button>
+            <button onclick={dismiss_cb}>{ "Not now" }</button>
+        </div>
+    }
+}
+
+/// Renders the checkbox for resuming on the first tap after a reload
+pub(crate) fn render_resume_toggle(player_link: &Scope<Player>, enabled: bool) -> Html {
+    let onchange = player_link.callback(|_| PlayerMsg::ToggleResumeOnTap);
+    html! {
+        <p>
+            <label>
+                <input type="checkbox" checked={enabled} {onchange} />
+                { " Resume after reloads: if the page reloads mid-article, the first tap resumes it" }
+            </label>
+        </p>
+    }
+}
@@ -0,0 +1,60 @@
+//! Controlling the player from other devices, e.g., the `/mini` page on a watch. While remote
+//! control is on, the player polls the server for the commands remotes sent, and reports what it's
+//! doing so they can show it.
+
+use super::{Player, PlayerMsg};
+use crate::utils;
+use common::{PlayerStatus, RemoteCommands};
+
+use anyhow::{bail, Error as AnyError};
+use yew::{html::Scope, prelude::*};
+
+/// The number of milliseconds between polls for remote commands, while remote control is on
+pub(crate) const REMOTE_POLL_FREQ: u32 = 2000;
+
+/// Tells the server what the player is doing, then fetches the commands sent after the one with the
+/// given number. If no number is given, this only finds out the number of the latest command.
+pub(crate) async fn poll(
+    status: &PlayerStatus,
+    after: Option<u64>,
+) -> Result<RemoteCommands, AnyError> {
+    let resp = utils::post("/api/player-status")
+        .json(status)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Couldn't report the player's status"))?;
+    if !resp.ok() {
+        bail!("Couldn't report the player's status ({})", resp.status());
+    }
+
+    let endpoint = match after {
+        Some(after) => format!("/api/remote-commands?after={after}"),
+        None => "/api/remote-commands".to_string(),
+    };
+    let resp = utils::get(&endpoint)
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Couldn't fetch remote commands"))?;
+    if !resp.ok() {
+        bail!("Couldn't fetch remote commands ({})", resp.status());
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing remote commands"))
+}
+
+/// Renders the checkbox that turns remote control on and off
+pub(crate) fn render_remote_control_toggle(player_link: &Scope<Player>, enabled: bool) -> Html {
+    let onchange = player_link.callback(|_| PlayerMsg::ToggleRemoteControl);
+    html! {
+        <p>
+            <label>
+                <input type="checkbox" checked={enabled} {onchange} />
+                { " Remote control: take commands from other devices, e.g., " }
+                <a href="mini">{ "/mini" }</a>
+                { " on a watch" }
+            </label>
+        </p>
+    }
+}
@@ -0,0 +1,97 @@
+//! When the Player saves its state. While the audio plays, a periodic save keeps the elapsed time
+//! fresh, and each one sets off the next. Seeks save too, once the seeking settles. Pausing and
+//! switching articles save right away, so they aren't scheduled here.
+
+use crate::{
+    clock::{self, Timer},
+    utils,
+};
+
+// The number of milliseconds between times saving Player state while the audio is playing. This is
+// just a heartbeat. Pausing, seeking, and switching articles all save right away.
+const PLAYER_STATE_SAVE_FREQ: u32 = 30000;
+
+/// Like PLAYER_STATE_SAVE_FREQ, but for while the battery is low
+const LOW_BATTERY_SAVE_FREQ: u32 = 120000;
+
+/// The number of milliseconds to wait after a seek before saving, so that dragging the scrubber
+/// around only saves once
+const SEEK_SAVE_DELAY: u32 = 1000;
+
+/// Calls `save` when the next periodic save is due, which is later while the battery is low.
+/// Dropping the returned timer cancels it.
+pub(super) fn schedule_periodic_save(battery_low: bool, save: impl FnOnce() + 'static) -> Timer {
+    let freq = if battery_low {
+        LOW_BATTERY_SAVE_FREQ
+    } else {
+        PLAYER_STATE_SAVE_FREQ
+    };
+    utils::run_after_delay(save, freq)
+}
+
+/// Calls `save` once seeking has settled. Replacing the returned timer with the one from the next
+/// seek cancels it, so a run of seeks saves once.
+pub(super) fn schedule_seek_save(save: impl FnOnce() + 'static) -> Timer {
+    clock::timeout(SEEK_SAVE_DELAY, save)
+}
+
+#[test]
+fn scheduling_saves() {
+    use crate::clock::{set_clock, VirtualClock};
+    use std::{cell::RefCell, rc::Rc};
+
+    let clock = Rc::new(VirtualClock::new(0.0));
+    set_clock(clock.clone());
+    let saves: Rc<RefCell<Vec<(&str, f64)>>> = Rc::default();
+
+    // Each periodic save schedules the next, like the Player's SaveState does, going by whether
+    // the battery is low at the time
+    let battery_low = Rc::new(RefCell::new(false));
+    let periodic_timer: Rc<RefCell<Option<Timer>>> = Rc::default();
+    fn periodic(
+        saves: Rc<RefCell<Vec<(&'static str, f64)>>>,
+        battery_low: Rc<RefCell<bool>>,
+        timer: Rc<RefCell<Option<Timer>>>,
+    ) {
+        let next = {
+            let (saves, battery_low, timer) = (saves.clone(), battery_low.clone(), timer.clone());
+            move || {
+                saves.borrow_mut().push(("periodic", clock::now()));
+                periodic(saves, battery_low, timer);
+            }
+        };
+        let low = *battery_low.borrow();
+        *timer.borrow_mut() = Some(schedule_periodic_save(low, next));
+    }
+    periodic(saves.clone(), battery_low.clone(), periodic_timer.clone());
+
+    clock.advance(60_000.0);
+    assert_eq!(
+        *saves.borrow(),
+        [("periodic", 30_000.0), ("periodic", 60_000.0)]
+    );
+
+    // On low battery, the save after the next one comes later
+    *battery_low.borrow_mut() = true;
+    clock.advance(30_000.0);
+    assert_eq!(saves.borrow().last(), Some(&("periodic", 90_000.0)));
+    clock.advance(119_999.0);
+    assert_eq!(saves.borrow().len(), 3);
+    clock.advance(1.0);
+    assert_eq!(saves.borrow().last(), Some(&("periodic", 210_000.0)));
+
+    // Seeks in quick succession only save once, a second after the last of them
+    drop(periodic_timer.borrow_mut().take());
+    saves.borrow_mut().clear();
+    let mut seek_timer = None;
+    for _ in 0..3 {
+        let saves = saves.clone();
+        seek_timer = Some(schedule_seek_save(move || {
+            saves.borrow_mut().push(("seek", clock::now()))
+        }));
+        clock.advance(500.0);
+    }
+    clock.advance(10_000.0);
+    assert_eq!(*saves.borrow(), [("seek", 212_000.0)]);
+    drop(seek_timer);
+}
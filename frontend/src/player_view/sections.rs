@@ -0,0 +1,43 @@
+//! Skipping between the sections of the article that's playing. Sections start at the article's
+//! headings, or at its paragraphs if it has no headings. When each one starts is taken from the
+//! article's transcript, and kept with the article in IndexedDB so it works offline.
+
+use common::Timepoint;
+
+/// If the audio is more than this many seconds into a section, skipping back goes to the start of
+/// the section rather than to the previous one
+const RESTART_SECTION_SECS: f64 = 3.0;
+
+/// Skipping forward goes to the first section that starts at least this many seconds from now, so
+/// it doesn't get stuck on the section that was just skipped to
+const MIN_SKIP_SECS: f64 = 0.5;
+
+/// Returns the times the sections start at. An article's title counts as a heading, so the headings
+/// only make sections if there's more than one.
+fn section_starts(markers: &[Timepoint]) -> Vec<f64> {
+    let headings: Vec<f64> = markers
+        .iter()
+        .filter(|tp| tp.heading)
+        .map(|tp| tp.secs)
+        .collect();
+    if headings.len() > 1 {
+        headings
+    } else {
+        markers.iter().map(|tp| tp.secs).collect()
+    }
+}
+
+/// Returns when the section after the one at `elapsed` starts, if there is one
+pub(crate) fn next_section(markers: &[Timepoint], elapsed: f64) -> Option<f64> {
+    section_starts(markers)
+        .into_iter()
+        .find(|&start| start >= elapsed + MIN_SKIP_SECS)
+}
+
+/// Returns when the section at `elapsed` starts, or when the one before it starts if `elapsed` is
+/// close to the start of its section. Returns `None` at the start of the first section.
+pub(crate) fn prev_section(markers: &[Timepoint], elapsed: f64) -> Option<f64> {
+    section_starts(markers)
+        .into_iter()
+        .rfind(|&start| start < elapsed - RESTART_SECTION_SECS)
+}
@@ -0,0 +1,160 @@
+//! How far the jump buttons jump, and the keyboard shortcuts for the player. The jump sizes are
+//! used by the on-screen buttons, the lockscreen and headphone controls, the shortcuts, and remote
+//! control alike.
+
+use super::{Player, PlayerMsg};
+
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{HtmlElement, HtmlSelectElement, KeyboardEvent};
+use yew::{html::Scope, prelude::*};
+
+/// The jump sizes the listener can pick from, in seconds
+const JUMP_SIZES: &[u32] = &[5, 10, 15, 30, 45, 60, 90];
+
+/// What a keyboard shortcut does
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Shortcut {
+    /// Space plays or pauses
+    TogglePlay,
+    /// The left arrow jumps backward
+    JumpBackward,
+    /// The right arrow jumps forward
+    JumpForward,
+    /// `+` speeds up to the next playback speed
+    Faster,
+    /// `-` slows down to the previous playback speed
+    Slower,
+    /// `m` marks the moment
+    MarkMoment,
+}
+
+impl Shortcut {
+    /// Returns the shortcut the given key is for, if any
+    fn from_key(key: &str) -> Option<Shortcut> {
+        match key {
+            " " => Some(Shortcut::TogglePlay),
+            "ArrowLeft" => Some(Shortcut::JumpBackward),
+            "ArrowRight" => Some(Shortcut::JumpForward),
+            // `=` is `+` without shift on most keyboards
+            "+" | "=" => Some(Shortcut::Faster),
+            "-" => Some(Shortcut::Slower),
+            "m" => Some(Shortcut::MarkMoment),
+            _ => None,
+        }
+    }
+}
+
+/// Returns whether the given key press is meant for something else on the page, e.g., typing in a
+/// search box or moving through a select
+fn is_for_something_else(e: &KeyboardEvent) -> bool {
+    if e.ctrl_key() || e.meta_key() || e.alt_key() || e.default_prevented() {
+        return true;
+    }
+
+    let Some(target) = e.target().and_then(|t| t.dyn_into::<HtmlElement>().ok()) else {
+        return false;
+    };
+    let tag = target.tag_name();
+    matches!(
+        tag.as_str(),
+        "INPUT" | "TEXTAREA" | "SELECT" | "BUTTON" | "AUDIO"
+    ) || target.is_content_editable()
+}
+
+/// Returns the next speed up or down from the given one, out of the given speeds, which are
+/// ascending. Speeds that aren't among them go to the nearest one in that direction.
+pub(crate) fn step_speed(speeds: &[f64], current: f64, faster: bool) -> Option<f64> {
+    if faster {
+        speeds.iter().copied().find(|&s| s > current)
+    } else {
+        speeds.iter().rev().copied().find(|&s| s < current)
+    }
+}
+
+/// The listener that turns key presses on the page into shortcuts
+pub(crate) struct ShortcutListener {
+    _cb: Closure<dyn Fn(KeyboardEvent)>,
+}
+
+impl ShortcutListener {
+    /// Starts listening for shortcuts, and sends them to the given player
+    pub(crate) fn new(player: Scope<Player>) -> ShortcutListener {
+        let cb = Closure::new(move |e: KeyboardEvent| {
+            if is_for_something_else(&e) {
+                return;
+            }
+            if let Some(shortcut) = Shortcut::from_key(&e.key()) {
+                // Otherwise space scrolls the page, and the arrows might too
+                e.prevent_default();
+                player.send_message(PlayerMsg::Shortcut(shortcut));
+            }
+        });
+
+        let func = cb.as_ref().unchecked_ref();
+        if let Err(e) = gloo_utils::document().add_event_listener_with_callback("keydown", func) {
+            tracing::error!("Could not listen for keyboard shortcuts: {:?}", e);
+        }
+
+        ShortcutListener { _cb: cb }
+    }
+}
+
+/// Renders a selector for one of the jump sizes
+fn render_jump_selector(
+    id: &'static str,
+    label: &'static str,
+    secs: f64,
+    onchange: Callback<Event>,
+) -> Html {
+    let options: Html = JUMP_SIZES
+        .iter()
+        .map(|&s| {
+            html! {
+                <option value={ s.to_string() } selected={ f64::from(s) == secs }>
+                    { format!("{s} seconds") }
+                </option>
+            }
+        })
+        .collect();
+
+    html! {
+        <p>
+            <label for={id}>{ label }</label>
+            <select {id} {onchange}>{ options }</select>
+        </p>
+    }
+}
+
+/// Renders the settings for the jump sizes, and the list of keyboard shortcuts
+pub(crate) fn render_jump_settings(
+    player_link: &Scope<Player>,
+    backward: f64,
+    forward: f64,
+) -> Html {
+    let parse = |e: Event| {
+        let select: HtmlSelectElement = e.target_unchecked_into();
+        select.value().parse::<f64>().ok()
+    };
+    let on_backward = player_link.batch_callback(move |e| {
+        parse(e).map(|backward| PlayerMsg::SetJumpSizes { backward, forward })
+    });
+    let on_forward = player_link.batch_callback(move |e| {
+        parse(e).map(|forward| PlayerMsg::SetJumpSizes { backward, forward })
+    });
+
+    html! {
+        <details class="jumpSettings">
+            <summary>{ "Jumps and keyboard shortcuts" }</summary>
+            { render_jump_selector("jump-backward-selector", "Jump back by: ", backward, on_backward) }
+            { render_jump_selector("jump-forward-selector", "Jump forward by: ", forward, on_forward) }
+            <dl>
+                <dt><kbd>{ "Space" }</kbd></dt>
+                <dd>{ "Play or pause" }</dd>
+                <dt><kbd>{ "←" }</kbd>{ " / " }<kbd>{ "→" }</kbd></dt>
+                <dd>{ "Jump back or forward" }</dd>
+                <dt><kbd>{ "+" }</kbd>{ " / " }<kbd>{ "-" }</kbd></dt>
+                <dd>{ "Speed up or slow down" }</dd>
+            </dl>
+        </details>
+    }
+}
@@ -0,0 +1,168 @@
+//! The sleep timer, which pauses playback after a while. The volume is faded out over the last
+//! stretch before pausing, so the listener isn't woken up by the audio cutting off.
+
+use super::{audio_component::GlobalAudio, Player, PlayerMsg};
+use crate::clock;
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlSelectElement;
+use yew::{html::Scope, prelude::*};
+
+const SLEEP_TIMER_SELECTOR_ID: &str = "sleep-timer-selector";
+
+/// The number of milliseconds between checks of how long the sleep timer has left
+pub(crate) const SLEEP_TIMER_TICK_FREQ: u32 = 1000;
+
+/// The number of seconds over which the volume fades out before the sleep timer pauses playback
+const FADE_OUT_SECS: f64 = 30.0;
+
+/// The sleep timer lengths offered, in minutes
+const SLEEP_TIMER_MINUTES: &[u64] = &[15, 30, 45, 60];
+
+/// The selector value for pausing at the end of the article
+const END_OF_ARTICLE_VALUE: &str = "end";
+
+/// When the sleep timer pauses playback
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SleepTimer {
+    /// After the given number of minutes, which end at the given time, in milliseconds since the
+    /// Unix epoch
+    After { minutes: u64, deadline: f64 },
+    /// When the current article ends
+    EndOfArticle,
+}
+
+impl SleepTimer {
+    /// Returns a timer that goes off after the given duration from now
+    pub(crate) fn after(duration: Duration) -> SleepTimer {
+        SleepTimer::After {
+            minutes: duration.as_secs() / 60,
+            deadline: clock::now() + duration.as_millis() as f64,
+        }
+    }
+
+    /// Returns the number of seconds until playback is paused. For the end of the article, this is
+    /// the time left in the audio at the current playback speed. Returns `None` if nothing is
+    /// loaded.
+    pub(crate) fn remaining_secs(&self) -> Option<f64> {
+        match self {
+            SleepTimer::After { deadline, .. } => Some((deadline - clock::now()) / 1000.0),
+            SleepTimer::EndOfArticle => {
+                let left = GlobalAudio::get_duration() - GlobalAudio::get_elapsed();
+                let speed = GlobalAudio::get_playback_speed();
+                (left.is_finite() && speed > 0.0).then(|| left / speed)
+            }
+        }
+    }
+
+    /// Returns whether a timer restored from a save has already gone off
+    pub(crate) fn is_expired(&self) -> bool {
+        matches!(self, SleepTimer::After { deadline, .. } if *deadline <= clock::now())
+    }
+
+    /// Returns the value of the sleep timer selector option for this timer
+    fn selector_value(&self) -> String {
+        match self {
+            SleepTimer::After { minutes, .. } => minutes.to_string(),
+            SleepTimer::EndOfArticle => END_OF_ARTICLE_VALUE.to_string(),
+        }
+    }
+}
+
+/// Returns how much of its full volume the audio should play at with the given number of seconds
+/// left on the sleep timer, or `None` if it isn't fading out yet. The volume ramps down linearly
+/// over the last FADE_OUT_SECS seconds.
+pub(crate) fn fade_out_level(remaining_secs: f64) -> Option<f64> {
+    (remaining_secs < FADE_OUT_SECS).then(|| (remaining_secs / FADE_OUT_SECS).max(0.0))
+}
+
+/// Returns the message for the given value of the sleep timer selector
+fn selection_msg(value: &str) -> PlayerMsg {
+    if value == END_OF_ARTICLE_VALUE {
+        return PlayerMsg::SleepAtEndOfArticle;
+    }
+    match value.parse::<u64>() {
+        Ok(minutes) => PlayerMsg::SetSleepTimer(Duration::from_secs(60 * minutes)),
+        Err(_) => PlayerMsg::CancelSleepTimer,
+    }
+}
+
+/// Makes the sleep timer selector show the given timer. This is needed when the timer changes
+/// without the listener picking it, i.e., when it's restored or goes off.
+pub(crate) fn show_selection(timer: Option<SleepTimer>) {
+    let selector: Option<HtmlSelectElement> = gloo_utils::document()
+        .get_element_by_id(SLEEP_TIMER_SELECTOR_ID)
+        .and_then(|elem| elem.dyn_into().ok());
+    if let Some(selector) = selector {
+        selector.set_value(&timer.map(|t| t.selector_value()).unwrap_or_default());
+    }
+}
+
+/// Renders the sleep timer selector, along with the number of minutes left on the timer, if it's
+/// counting down
+pub(crate) fn render_sleep_timer(player_link: &Scope<Player>, minutes_left: Option<u64>) -> Html {
+    let onchange = player_link.callback(|e: Event| {
+        let select: HtmlSelectElement = e.target_unchecked_into();
+        selection_msg(&select.value())
+    });
+
+    let off = html! { <option value="" selected=true>{ "Off" }</option> };
+    let lengths: Html = SLEEP_TIMER_MINUTES
+        .iter()
+        .map(|minutes| {
+            html! {
+                <option value={ minutes.to_string() }>{ format!("{minutes} minutes") }</option>
+            }
+        })
+        .collect();
+    let end_of_article = html! {
+        <option value={END_OF_ARTICLE_VALUE}>{ "End of article" }</option>
+    };
+
+    let status = match minutes_left {
+        Some(minutes) => html! { <span>{ format!(" Pausing in {minutes} min") }</span> },
+        None => Html::default(),
+    };
+
+    html! {
+        <div class="sleepTimerSection">
+            <label for={SLEEP_TIMER_SELECTOR_ID}>{ "Sleep timer:" }</label>
+            <select
+                title="Sleep timer"
+                name={SLEEP_TIMER_SELECTOR_ID}
+                id={SLEEP_TIMER_SELECTOR_ID}
+                {onchange}
+            >
+                { off }
+                { lengths }
+                { end_of_article }
+            </select>
+            { status }
+        </div>
+    }
+}
+
+#[test]
+fn counting_down() {
+    use crate::clock::{set_clock, VirtualClock};
+    use std::rc::Rc;
+
+    let clock = Rc::new(VirtualClock::new(0.0));
+    set_clock(clock.clone());
+
+    let timer = SleepTimer::after(Duration::from_secs(15 * 60));
+    assert_eq!(timer.selector_value(), "15");
+    assert_eq!(timer.remaining_secs(), Some(900.0));
+    assert_eq!(fade_out_level(timer.remaining_secs().unwrap()), None);
+
+    // The volume fades out over the last stretch, and the timer goes off at the deadline
+    clock.advance(885_000.0);
+    assert_eq!(timer.remaining_secs(), Some(15.0));
+    assert_eq!(fade_out_level(15.0), Some(0.5));
+    assert!(!timer.is_expired());
+    clock.advance(15_000.0);
+    assert!(timer.is_expired());
+}
@@ -0,0 +1,99 @@
+//! Suggests a playback speed for an article, based on the speeds that similar articles were
+//! listened at. Articles are similar if they're from the same publication, or in the same language
+//! and about as dense.
+
+use super::PLAYBACK_SPEEDS;
+use crate::queue_view::{ArticleId, QueueEntry};
+use common::ReadingProfile;
+
+use serde::{Deserialize, Serialize};
+
+/// The fewest similar articles a suggestion is made from
+const MIN_SIMILAR: usize = 3;
+
+/// The most similar articles a suggestion is made from. The most similar ones are used.
+const MAX_SIMILAR: usize = 10;
+
+/// Articles less similar than this aren't counted. Being from the same publication is enough, as is
+/// being in the same language and having complexities within an eighth of each other.
+const MIN_SIMILARITY: f64 = 1.0;
+
+/// The speed an article was listened at, along with what's needed to tell which articles are
+/// similar to it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpeedSample {
+    /// The ID of the article
+    pub(crate) id: ArticleId,
+    /// The publication the article is from, if known
+    publication: Option<String>,
+    /// What the article's text is like, if known
+    reading_profile: Option<ReadingProfile>,
+    /// The playback speed
+    pub(crate) speed: f64,
+}
+
+impl SpeedSample {
+    /// Records that the given article was listened at the given speed
+    pub(crate) fn new(entry: &QueueEntry, speed: f64) -> SpeedSample {
+        SpeedSample {
+            id: entry.id.clone(),
+            publication: entry.publication.clone(),
+            reading_profile: entry.reading_profile.clone(),
+            speed,
+        }
+    }
+}
+
+/// Returns how similar the given article is to the sampled one. Articles in different languages
+/// aren't similar at all.
+fn similarity(entry: &QueueEntry, sample: &SpeedSample) -> f64 {
+    let mut score = 0.0;
+
+    if entry.publication.is_some() && entry.publication == sample.publication {
+        score += 1.0;
+    }
+
+    if let (Some(a), Some(b)) = (&entry.reading_profile, &sample.reading_profile) {
+        match (&a.language, &b.language) {
+            (Some(x), Some(y)) if x != y => return 0.0,
+            (Some(_), Some(_)) => score += 0.5,
+            _ => (),
+        }
+
+        // Complexities that are a quarter apart or more don't count
+        let closeness = 1.0 - 4.0 * (a.complexity - b.complexity).abs() as f64;
+        score += closeness.max(0.0);
+    }
+
+    score
+}
+
+/// Suggests a speed for the given article out of the speeds other articles were listened at. The
+/// suggestion is the average speed of the most similar articles, weighted by how similar they are,
+/// and rounded to the nearest speed the player offers. Returns `None` if too few articles are
+/// similar.
+pub(crate) fn suggest(entry: &QueueEntry, samples: &[SpeedSample]) -> Option<f64> {
+    let mut similar: Vec<(f64, f64)> = samples
+        .iter()
+        .filter(|sample| sample.id != entry.id)
+        .map(|sample| (similarity(entry, sample), sample.speed))
+        .filter(|&(score, _)| score >= MIN_SIMILARITY)
+        .collect();
+    if similar.len() < MIN_SIMILAR {
+        return None;
+    }
+    similar.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    similar.truncate(MAX_SIMILAR);
+
+    let total_score: f64 = similar.iter().map(|(score, _)| score).sum();
+    let mean = similar
+        .iter()
+        .map(|(score, speed)| score * speed)
+        .sum::<f64>()
+        / total_score;
+
+    PLAYBACK_SPEEDS
+        .iter()
+        .copied()
+        .min_by(|a, b| (a - mean).abs().total_cmp(&(b - mean).abs()))
+}
@@ -0,0 +1,227 @@
+//! Speed training, for listeners who want to get used to listening faster. Every article starts at
+//! the day's training speed, which creeps up from where training started toward a target, by a
+//! little every day. After each article, a quick prompt asks how much of it was followed. Losing
+//! the thread sets the ramp back a week, so it doesn't outrun the listener.
+
+use super::{Player, PlayerMsg};
+use crate::clock;
+
+use serde::{Deserialize, Serialize};
+use web_sys::HtmlSelectElement;
+use yew::{html::Scope, prelude::*};
+
+/// The number of milliseconds in a day
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// How much faster the training speed gets every week
+const WEEKLY_STEP: f64 = 0.05;
+
+/// The speeds training can work up to
+const TRAINING_TARGETS: &[f64] = &[1.5, 1.75, 2.0, 2.5, 3.0];
+
+/// The number of recent answers to the comprehension prompt that are kept
+const MAX_CHECKS: usize = 10;
+
+/// How much of an article the listener says they followed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comprehension {
+    /// Followed all of it
+    All,
+    /// Followed most of it
+    Most,
+    /// Lost the thread. This sets training back.
+    Little,
+}
+
+impl Comprehension {
+    /// The answers, in the order they're offered
+    const ALL: [Comprehension; 3] = [
+        Comprehension::All,
+        Comprehension::Most,
+        Comprehension::Little,
+    ];
+
+    /// The answer's button text
+    fn label(&self) -> &'static str {
+        match self {
+            Comprehension::All => "All of it",
+            Comprehension::Most => "Most of it",
+            Comprehension::Little => "I lost the thread",
+        }
+    }
+
+    /// The answer in the list of recent answers
+    fn emoji(&self) -> &'static str {
+        match self {
+            Comprehension::All => "🟢",
+            Comprehension::Most => "🟡",
+            Comprehension::Little => "🔴",
+        }
+    }
+}
+
+/// A listener's speed training
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpeedTraining {
+    /// The speed training started at
+    start_speed: f64,
+    /// The speed training works up to
+    target_speed: f64,
+    /// When training started, in milliseconds since the Unix epoch
+    started: f64,
+    /// How much the training speed was set back by losing the thread
+    setback: f64,
+    /// The most recent answers to the comprehension prompt, oldest first
+    checks: Vec<Comprehension>,
+}
+
+impl SpeedTraining {
+    /// Starts training from the given speed up to the given one
+    pub(crate) fn new(start_speed: f64, target_speed: f64) -> SpeedTraining {
+        SpeedTraining {
+            start_speed: start_speed.min(target_speed),
+            target_speed,
+            started: clock::now(),
+            setback: 0.0,
+            checks: Vec::new(),
+        }
+    }
+
+    /// Returns today's training speed, to the hundredth. It goes up by a seventh of a week's step
+    /// every whole day.
+    pub(crate) fn speed(&self) -> f64 {
+        let days = ((clock::now() - self.started) / DAY_MS).floor().max(0.0);
+        let speed = self.start_speed + WEEKLY_STEP * days / 7.0 - self.setback;
+        let speed = speed.clamp(self.start_speed, self.target_speed);
+        (speed * 100.0 + 1e-9).floor() / 100.0
+    }
+
+    /// Records how much of an article was followed at the training speed
+    pub(crate) fn record(&mut self, comprehension: Comprehension) {
+        if comprehension == Comprehension::Little {
+            // Don't let setbacks pile up below the starting speed, or the ramp would stall
+            let progress = self.speed() - self.start_speed;
+            self.setback += WEEKLY_STEP.min(progress.max(0.0));
+        }
+        self.checks.push(comprehension);
+        if self.checks.len() > MAX_CHECKS {
+            self.checks.remove(0);
+        }
+    }
+}
+
+/// Renders the speed training selector, along with today's speed if training is on
+pub(crate) fn render_training_settings(
+    player_link: &Scope<Player>,
+    training: Option<&SpeedTraining>,
+) -> Html {
+    let onchange = player_link.callback(|e: Event| {
+        let select: HtmlSelectElement = e.target_unchecked_into();
+        PlayerMsg::SetSpeedTraining(select.value().parse().ok())
+    });
+
+    let target = training.map(|t| t.target_speed);
+    let options: Html = TRAINING_TARGETS
+        .iter()
+        .map(|&speed| {
+            html! {
+                <option value={ speed.to_string() } selected={target == Some(speed)}>
+                    { format!("up to {speed}x") }
+                </option>
+            }
+        })
+        .collect();
+
+    let status = match training {
+        Some(training) => {
+            let checks: String = training.checks.iter().map(|c| c.emoji()).collect();
+            html! {
+                <span>
+                    { format!(" Today: {}x, going up {WEEKLY_STEP}x a week. ", training.speed()) }
+                    if !checks.is_empty() {
+                        <span title="How much of recent articles you followed">{ checks }</span>
+                    }
+                </span>
+            }
+        }
+        None => Html::default(),
+    };
+
+    html! {
+        <p>
+            <label for="speed-training-selector">{ "Speed training: " }</label>
+            <select title="Speed training" id="speed-training-selector" {onchange}>
+                <option value="" selected={training.is_none()}>{ "off" }</option>
+                { options }
+            </select>
+            { status }
+        </p>
+    }
+}
+
+/// Renders the prompt asking how much of the given article was followed at the given speed
+pub(crate) fn render_comprehension_prompt(
+    player_link: &Scope<Player>,
+    title: &str,
+    speed: f64,
+) -> Html {
+    let answers: Html = Comprehension::ALL
+        .into_iter()
+        .map(|answer| {
+            let onclick = player_link.callback(move |_| PlayerMsg::CheckComprehension(answer));
+            html! { <button {onclick}>{ answer.label() }</button> }
+        })
+        .collect();
+    let dismiss_cb = player_link.callback(|_| PlayerMsg::DismissComprehensionCheck);
+
+    html! {
+        <p class="comprehensionCheck" role="status">
+            { "How much of " }
+            <bdi>{ title.to_string() }</bdi>
+            { format!(" did you follow at {speed}x? ") }
+            { answers }
+            <button
+                aria-label="Skip comprehension check"
+                title="Skip comprehension check"
+                onclick={dismiss_cb}
+            >
+                { "✕" }
+            </button>
+        </p>
+    }
+}
+
+#[test]
+fn ramping_up() {
+    use crate::clock::{set_clock, VirtualClock};
+    use std::rc::Rc;
+
+    let clock = Rc::new(VirtualClock::new(0.0));
+    set_clock(clock.clone());
+
+    let mut training = SpeedTraining::new(1.0, 1.25);
+    assert_eq!(training.speed(), 1.0);
+
+    // A week's step is spread over the days of the week
+    clock.advance(2.0 * DAY_MS);
+    assert_eq!(training.speed(), 1.01);
+    clock.advance(5.0 * DAY_MS);
+    assert_eq!(training.speed(), 1.05);
+
+    // Losing the thread sets it back a week, but not below where it started
+    training.record(Comprehension::Little);
+    assert_eq!(training.speed(), 1.0);
+    training.record(Comprehension::Little);
+    clock.advance(7.0 * DAY_MS);
+    assert_eq!(training.speed(), 1.05);
+
+    // It stops at the target
+    clock.advance(100.0 * DAY_MS);
+    assert_eq!(training.speed(), 1.25);
+
+    for _ in 0..MAX_CHECKS {
+        training.record(Comprehension::All);
+    }
+    assert_eq!(training.checks.len(), MAX_CHECKS);
+    assert!(!training.checks.contains(&Comprehension::Little));
+}
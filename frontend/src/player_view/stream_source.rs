@@ -0,0 +1,111 @@
+//! Plays audio that's still being synthesized through Media Source Extensions. The audio is
+//! fetched as the server streams it, and every chunk is appended to the <audio>'s buffer as it
+//! arrives, so playback starts as soon as the first chunk is in. Browsers without MSE support for
+//! MP3 (e.g., Safari on iPhones) get the stream's URL as the <audio>'s src instead.
+
+use crate::utils;
+
+use anyhow::{anyhow, Error as AnyError};
+use gloo_net::http::Request;
+use js_sys::{Promise, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    MediaSource, MediaSourceReadyState, ReadableStreamDefaultReader, SourceBuffer,
+    SourceBufferAppendMode, Url,
+};
+
+/// The MIME type of the streamed audio
+const MP3_MIME_TYPE: &str = "audio/mpeg";
+
+/// Returns whether the browser can play streamed MP3s through MSE
+pub(crate) fn is_supported() -> bool {
+    // MediaSource doesn't exist at all on some browsers, so check before calling anything on it
+    let has_media_source =
+        js_sys::Reflect::has(&gloo_utils::window(), &"MediaSource".into()).unwrap_or(false);
+    has_media_source && MediaSource::is_type_supported(MP3_MIME_TYPE)
+}
+
+/// Starts streaming the audio at the given URL. Returns the URL to set as the <audio>'s src. The
+/// audio is fetched once the <audio> opens the source, and fetching stops if it moves on to
+/// another source.
+pub(crate) fn open(url: String) -> Result<String, AnyError> {
+    let media_source =
+        MediaSource::new().map_err(|e| utils::wrap_jserror("couldn't make MediaSource", e))?;
+    let src = Url::create_object_url_with_source(&media_source)
+        .map_err(|e| utils::wrap_jserror("couldn't make MediaSource URL", e))?;
+
+    spawn_local(async move {
+        if let Err(e) = feed(&media_source, &url).await {
+            tracing::error!("Error streaming {url}: {e}");
+        }
+    });
+
+    Ok(src)
+}
+
+/// Returns a future that resolves the next time `set_handler` calls its given handler
+async fn next_event(set_handler: impl Fn(Option<&js_sys::Function>)) -> Result<(), AnyError> {
+    let event = Promise::new(&mut |resolve, _| set_handler(Some(&resolve)));
+    JsFuture::from(event)
+        .await
+        .map_err(|e| utils::wrap_jserror("error waiting for event", e))?;
+    set_handler(None);
+    Ok(())
+}
+
+/// Fetches the audio at the given URL into the given MediaSource, chunk by chunk
+async fn feed(media_source: &MediaSource, url: &str) -> Result<(), AnyError> {
+    // The source buffer can only be added once the <audio> has opened the source
+    next_event(|handler| media_source.set_onsourceopen(handler)).await?;
+    let buffer: SourceBuffer = media_source
+        .add_source_buffer(MP3_MIME_TYPE)
+        .map_err(|e| utils::wrap_jserror("couldn't add source buffer", e))?;
+    // MP3 frames have no timestamps of their own. Play the chunks one after the other.
+    buffer.set_mode(SourceBufferAppendMode::Sequence);
+
+    let resp = utils::authorize(Request::get(url)).send().await?;
+    if !resp.ok() {
+        return Err(anyhow!("{} ({})", resp.status(), resp.status_text()));
+    }
+    let reader: ReadableStreamDefaultReader = resp
+        .body()
+        .ok_or_else(|| anyhow!("response has no body"))?
+        .get_reader()
+        .unchecked_into();
+
+    loop {
+        let chunk = JsFuture::from(reader.read())
+            .await
+            .map_err(|e| utils::wrap_jserror("couldn't read stream", e))?;
+        let done = js_sys::Reflect::get(&chunk, &JsValue::from_str("done"))
+            .map(|d| d.is_truthy())
+            .unwrap_or(true);
+        if done {
+            break;
+        }
+
+        // If the <audio> has moved on to another source, stop fetching
+        if media_source.ready_state() != MediaSourceReadyState::Open {
+            let _ = reader.cancel();
+            return Ok(());
+        }
+
+        // Append the chunk, and wait for the buffer to take it before appending the next one
+        let bytes: Uint8Array = js_sys::Reflect::get(&chunk, &JsValue::from_str("value"))
+            .map_err(|e| utils::wrap_jserror("couldn't get chunk", e))?
+            .unchecked_into();
+        buffer
+            .append_buffer_with_array_buffer_view(&bytes)
+            .map_err(|e| utils::wrap_jserror("couldn't append chunk", e))?;
+        next_event(|handler| buffer.set_onupdateend(handler)).await?;
+    }
+
+    // The whole article is in. This lets the <audio> know its duration, and that it can end.
+    if media_source.ready_state() == MediaSourceReadyState::Open {
+        media_source
+            .end_of_stream()
+            .map_err(|e| utils::wrap_jserror("couldn't end stream", e))?;
+    }
+    Ok(())
+}
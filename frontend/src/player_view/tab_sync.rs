@@ -0,0 +1,86 @@
+//! Coordinates the Players of every tab the app is open in, so that only one of them plays at a
+//! time. Tabs tell each other when they start and stop playing over a BroadcastChannel.
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{BroadcastChannel, MessageEvent};
+
+/// The name of the channel the tabs talk over
+const CHANNEL_NAME: &str = "readtomyshoe-player";
+
+/// What a tab tells the others
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) enum TabMessage {
+    /// The tab just opened. Tabs that are playing answer with `Playing`.
+    Hello,
+    /// The tab started playing
+    Playing,
+    /// The tab wants to take over playing. The tab that's playing pauses, and answers with
+    /// `Paused` once its position is saved.
+    Release,
+    /// The tab stopped playing, and saved its position
+    Paused,
+}
+
+/// This tab's end of the channel
+pub(crate) struct TabChannel {
+    /// The channel, or `None` if the browser doesn't support BroadcastChannel
+    channel: Option<BroadcastChannel>,
+    _onmessage: Closure<dyn Fn(MessageEvent)>,
+}
+
+impl TabChannel {
+    /// Joins the channel, calling `on_message` with every message from the other tabs, and says
+    /// hello
+    pub(crate) fn new(on_message: impl Fn(TabMessage) + 'static) -> TabChannel {
+        let onmessage = Closure::new(move |e: MessageEvent| match e.data().into_serde() {
+            Ok(msg) => on_message(msg),
+            Err(e) => tracing::warn!("Got a malformed message from another tab: {}", e),
+        });
+
+        let channel = match BroadcastChannel::new(CHANNEL_NAME) {
+            Ok(channel) => {
+                channel.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+                Some(channel)
+            }
+            Err(e) => {
+                tracing::warn!("Couldn't coordinate with other tabs: {:?}", e);
+                None
+            }
+        };
+
+        let tab_channel = TabChannel {
+            channel,
+            _onmessage: onmessage,
+        };
+        tab_channel.send(TabMessage::Hello);
+        tab_channel
+    }
+
+    /// Tells the other tabs the given message
+    pub(crate) fn send(&self, msg: TabMessage) {
+        let channel = match &self.channel {
+            Some(channel) => channel,
+            None => return,
+        };
+
+        let msg = match JsValue::from_serde(&msg) {
+            Ok(msg) => msg,
+            Err(e) => {
+                tracing::error!("Couldn't serialize tab message: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = channel.post_message(&msg) {
+            tracing::error!("Couldn't message other tabs: {:?}", e);
+        }
+    }
+}
+
+impl Drop for TabChannel {
+    fn drop(&mut self) {
+        if let Some(channel) = &self.channel {
+            channel.close();
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Saves the text of the article that's playing as Markdown or HTML, with its title, byline, and
+//! source link, e.g., for quoting something that was heard. It's made from the saved transcript
+//! and catalog, so it works offline. The server has the same export at `/api/export-text`.
+
+use crate::{caching, queue_view::ArticleId, utils};
+use common::{ArticleMetadata, ArticleTranscript, TextExportFormat};
+
+use std::rc::Rc;
+
+use yew::prelude::*;
+
+/// Returns the given article's metadata from the saved catalog. Articles that aren't in it, e.g.,
+/// because the catalog hasn't been loaded on this device, just get their title.
+async fn load_metadata(id: &ArticleId, title: &str) -> ArticleMetadata {
+    let catalog = caching::load_catalog()
+        .await
+        .map(|c| c.0)
+        .unwrap_or_default();
+    catalog
+        .into_iter()
+        .find(|meta| meta.id == id.0)
+        .unwrap_or_else(|| ArticleMetadata {
+            id: id.0.clone(),
+            title: title.to_string(),
+            ..Default::default()
+        })
+}
+
+/// Returns the name of the file the given article's text is saved as, e.g., `Some Title.md`
+fn export_filename(title: &str, format: TextExportFormat) -> String {
+    utils::file_name(title, format.extension())
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// The article that's playing, if any
+    pub article: Option<ArticleId>,
+    /// The title of the article that's playing
+    pub title: String,
+    /// The transcript of the article that's playing. Nothing is shown if it has no text.
+    pub transcript: Rc<ArticleTranscript>,
+}
+
+pub(crate) enum TextExportMsg {
+    /// Saves the article's text in the given format
+    Export(TextExportFormat),
+    /// The text was saved, or couldn't be
+    Done(Result<(), String>),
+}
+
+/// The buttons that save the text of the article that's playing
+#[derive(Default)]
+pub(crate) struct TextExport {
+    err: Option<String>,
+}
+
+impl Component for TextExport {
+    type Message = TextExportMsg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        TextExport::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            TextExportMsg::Export(format) => {
+                let Some(id) = ctx.props().article.clone() else {
+                    return false;
+                };
+                let title = ctx.props().title.clone();
+                let transcript = ctx.props().transcript.clone();
+                ctx.link().send_future(async move {
+                    let meta = load_metadata(&id, &title).await;
+                    let text = transcript.export(&meta, format);
+                    let filename = export_filename(&meta.title, format);
+                    let res = utils::save_file(&filename, &text, format.mime_type());
+                    TextExportMsg::Done(res.map_err(|e| format!("{e}")))
+                });
+                false
+            }
+            TextExportMsg::Done(res) => {
+                self.err = res.err();
+                true
+            }
+        }
+    }
+
+    fn changed(&mut self, _ctx: &Context<Self>) -> bool {
+        self.err = None;
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let props = ctx.props();
+        if props.article.is_none() || props.transcript.text.is_empty() {
+            return Html::default();
+        }
+
+        let buttons = TextExportFormat::ALL
+            .into_iter()
+            .map(|format| {
+                let onclick = ctx.link().callback(move |_| TextExportMsg::Export(format));
+                html! {
+                    <button {onclick}>{ format.description() }</button>
+                }
+            })
+            .collect::<Html>();
+        html! {
+            <p class="textExport">
+                { "Save the article's text as " }
+                { buttons }
+                if let Some(err) = &self.err {
+                    <span style="color: red;" role="alert">{ format!(" {err}") }</span>
+                }
+            </p>
+        }
+    }
+}
+
+#[test]
+fn export_filenames() {
+    assert_eq!(
+        export_filename("Fish: Chips?", TextExportFormat::Markdown),
+        "Fish_ Chips_.md"
+    );
+    assert_eq!(export_filename("Etc. ", TextExportFormat::Html), "Etc.html");
+}
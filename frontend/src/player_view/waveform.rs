@@ -0,0 +1,223 @@
+//! A strip under the player showing how loud the article that's playing is over time, so its
+//! silences and sections can be seen at a glance. The part that's been played is shaded, behind it
+//! the parts that were played more than once are darker the more they were, sections are marked
+//! where their headings start, and clicking the strip seeks there like the scrubber does. The
+//! loudness is worked out by the server when it encodes the audio, and comes with the transcript.
+
+use super::audio_component::GlobalAudio;
+use crate::WeakComponentLink;
+use common::{ArticleTranscript, LOUDNESS_STEP_SECS};
+
+use std::{fmt::Write, rc::Rc};
+
+use wasm_bindgen::JsCast;
+use web_sys::Element;
+use yew::prelude::*;
+
+/// The height of the strip, in the units of its drawing
+const HEIGHT: f64 = 100.0;
+
+/// Parts played this many times or more are shaded the darkest
+const MAX_SHADED_PLAYS: u8 = 4;
+
+/// Returns the runs of parts that were played the same number of times, as where each run starts,
+/// how long it is, and how many times it was played. Parts that weren't played are left out.
+fn play_runs(counts: &[u8]) -> Vec<(usize, usize, u8)> {
+    let mut runs: Vec<(usize, usize, u8)> = Vec::new();
+    for (i, &count) in counts.iter().enumerate() {
+        let count = count.min(MAX_SHADED_PLAYS);
+        match runs.last_mut() {
+            Some((start, len, run_count)) if *run_count == count && *start + *len == i => *len += 1,
+            _ if count > 0 => runs.push((i, 1, count)),
+            _ => (),
+        }
+    }
+    runs
+}
+
+/// Returns the SVG path of the waveform, an outline that's as tall as the audio is loud and
+/// mirrored about the middle. Each level is one unit wide.
+fn waveform_path(loudness: &[u8]) -> String {
+    let mid = HEIGHT / 2.0;
+    // Quiet parts are still drawn a little, so the line doesn't vanish in the gaps
+    let half_height = |level: u8| (level as f64 / 255.0 * mid).max(0.5);
+
+    let mut path = format!("M0,{mid}");
+    for (i, &level) in loudness.iter().enumerate() {
+        let y = mid - half_height(level);
+        let _ = write!(path, " L{i},{y:.1} L{},{y:.1}", i + 1);
+    }
+    let _ = write!(path, " L{},{mid}", loudness.len());
+    for (i, &level) in loudness.iter().enumerate().rev() {
+        let y = mid + half_height(level);
+        let _ = write!(path, " L{},{y:.1} L{i},{y:.1}", i + 1);
+    }
+    path.push('Z');
+    path
+}
+
+/// Returns where the sections after the first start, in seconds. Those are the headings other
+/// than the title.
+fn section_breaks(transcript: &ArticleTranscript) -> Vec<f64> {
+    transcript
+        .timepoints
+        .iter()
+        .filter(|tp| tp.heading && tp.secs > 0.0)
+        .map(|tp| tp.secs)
+        .collect()
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// The transcript of the article that's playing. Nothing is shown if it has no loudness. The
+    /// play counts are sent separately, since they change as the article plays.
+    pub transcript: Rc<ArticleTranscript>,
+    /// A link to myself. The player uses it to say when the elapsed time changes.
+    pub waveform_link: WeakComponentLink<Waveform>,
+}
+
+pub(crate) enum WaveformMsg {
+    /// The audio is at the given elapsed time
+    TimeUpdate(f64),
+    /// The strip was clicked
+    Seek(MouseEvent),
+    /// How many times each part of the article has been played changed
+    Plays(Vec<u8>),
+}
+
+/// The waveform strip of the player
+pub(crate) struct Waveform {
+    /// The SVG path of the waveform
+    path: String,
+    /// Where the sections start, in seconds
+    breaks: Vec<f64>,
+    /// How many levels have been played
+    played: usize,
+    /// The runs of parts that were played the same number of times, as in `play_runs`
+    plays: Vec<(usize, usize, u8)>,
+}
+
+impl Waveform {
+    /// Returns the length of the audio the waveform covers, in seconds
+    fn duration(ctx: &Context<Self>) -> f64 {
+        ctx.props().transcript.loudness.len() as f64 * LOUDNESS_STEP_SECS
+    }
+
+    /// Shades the levels up to the given elapsed time. Returns whether that changed anything.
+    fn set_elapsed(&mut self, elapsed: f64) -> bool {
+        let played = (elapsed / LOUDNESS_STEP_SECS).max(0.0) as usize;
+        if played == self.played {
+            return false;
+        }
+        self.played = played;
+        true
+    }
+}
+
+impl Component for Waveform {
+    type Message = WaveformMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.props()
+            .waveform_link
+            .borrow_mut()
+            .replace(ctx.link().clone());
+
+        let transcript = &ctx.props().transcript;
+        let mut waveform = Self {
+            path: waveform_path(&transcript.loudness),
+            breaks: section_breaks(transcript),
+            played: 0,
+            plays: Vec::new(),
+        };
+        waveform.set_elapsed(GlobalAudio::get_elapsed());
+        waveform
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            WaveformMsg::TimeUpdate(elapsed) => self.set_elapsed(elapsed),
+            WaveformMsg::Seek(e) => {
+                let Some(strip) = e
+                    .current_target()
+                    .and_then(|t| t.dyn_into::<Element>().ok())
+                else {
+                    return false;
+                };
+                let width = strip.client_width();
+                if width > 0 {
+                    let fraction = (e.offset_x() as f64 / width as f64).clamp(0.0, 1.0);
+                    // The listener's own seek, so it's locked and undone like the scrubber's
+                    GlobalAudio::scrub(fraction * Self::duration(ctx));
+                }
+                false
+            }
+            WaveformMsg::Plays(counts) => {
+                let plays = play_runs(&counts);
+                let changed = plays != self.plays;
+                self.plays = plays;
+                changed
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        // A different article might be playing now
+        let transcript = &ctx.props().transcript;
+        self.path = waveform_path(&transcript.loudness);
+        self.breaks = section_breaks(transcript);
+        self.played = 0;
+        self.set_elapsed(GlobalAudio::get_elapsed());
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let len = ctx.props().transcript.loudness.len();
+        if len == 0 {
+            return Html::default();
+        }
+
+        let breaks = self
+            .breaks
+            .iter()
+            .map(|secs| {
+                let x = secs / LOUDNESS_STEP_SECS;
+                html! {
+                    <line class="sectionBreak" x1={x.to_string()} x2={x.to_string()}
+                        y1="0" y2={HEIGHT.to_string()} />
+                }
+            })
+            .collect::<Html>();
+        let heat = self
+            .plays
+            .iter()
+            .map(|&(start, len, count)| {
+                let opacity = count as f64 / MAX_SHADED_PLAYS as f64 * 0.6;
+                html! {
+                    <rect class="plays" x={start.to_string()} width={len.to_string()}
+                        height={HEIGHT.to_string()} fill-opacity={format!("{opacity:.2}")} />
+                }
+            })
+            .collect::<Html>();
+        let onclick = ctx.link().callback(WaveformMsg::Seek);
+        html! {
+            <svg
+                class="waveform"
+                role="img"
+                aria-label="The article's waveform. Click to seek."
+                viewBox={ format!("0 0 {len} {HEIGHT}") }
+                preserveAspectRatio="none"
+                {onclick}
+            >
+                <clipPath id="waveformPlayed">
+                    <rect width={ self.played.min(len).to_string() } height={HEIGHT.to_string()} />
+                </clipPath>
+                { heat }
+                <path class="unplayed" d={self.path.clone()} />
+                <path class="played" d={self.path.clone()} clip-path="url(#waveformPlayed)" />
+                { breaks }
+            </svg>
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Playlists are named lists of articles, e.g., "commute" or "long reads", kept apart from the
+//! queue. Articles are added to them from the library. Playing a playlist plays its articles in
+//! order, downloading each into the queue as it comes up. Playlists sync between devices along with
+//! the queue. Deleted ones are kept, marked deleted, so the deletion syncs too.
+
+use crate::{
+    caching,
+    library_view::{Library, LibraryMsg},
+    queue_view::{Queue, QueueEntry, QueueMsg},
+    WeakComponentLink,
+};
+use common::SyncedPlaylist;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+
+/// A named list of articles
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Playlist {
+    /// The ID of the playlist. It never changes, so the playlist can be renamed.
+    pub(crate) id: String,
+    pub(crate) name: String,
+    /// The articles in the playlist, in order
+    pub(crate) entries: Vec<QueueEntry>,
+    /// When the playlist was last changed, in milliseconds since the Unix epoch
+    pub(crate) updated: f64,
+    /// Whether the playlist was deleted
+    #[serde(default)]
+    pub(crate) deleted: bool,
+}
+
+impl From<SyncedPlaylist> for Playlist {
+    fn from(playlist: SyncedPlaylist) -> Playlist {
+        Playlist {
+            id: playlist.id,
+            name: playlist.name,
+            entries: playlist.entries.into_iter().map(Into::into).collect(),
+            updated: playlist.updated,
+            deleted: playlist.deleted,
+        }
+    }
+}
+
+impl Playlist {
+    /// Makes an empty playlist with the given name
+    fn new(name: String) -> Playlist {
+        let now = js_sys::Date::now();
+        Playlist {
+            id: format!(
+                "{:x}-{:x}",
+                now as u64,
+                (js_sys::Math::random() * 1e12) as u64
+            ),
+            name,
+            entries: Vec::new(),
+            updated: now,
+            deleted: false,
+        }
+    }
+
+    /// Notes that the listener changed the playlist, and saves it to the IndexedDB
+    fn save(&mut self) {
+        self.updated = js_sys::Date::now();
+        let playlist = self.clone();
+        spawn_local(async move {
+            let _ = caching::save_playlist(&playlist)
+                .await
+                .map_err(|e| tracing::error!("Couldn't save playlist: {}", e));
+        });
+    }
+}
+
+/// Asks for a playlist name, with the given prompt and current name. Blank names are no name.
+fn prompt_for_name(prompt: &str, current: &str) -> Option<String> {
+    gloo_utils::window()
+        .prompt_with_message_and_default(prompt, current)
+        .ok()
+        .flatten()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// A link to the Queue component, which plays the playlists
+    pub queue_link: WeakComponentLink<Queue>,
+    /// A link to the Library component, which adds articles to the playlists
+    pub library_link: WeakComponentLink<Library>,
+    /// A link to myself. We have to set this on creation
+    pub playlists_link: WeakComponentLink<Playlists>,
+}
+
+pub(crate) enum PlaylistsMsg {
+    /// Loads the playlists again, e.g., after a sync changed them
+    Refresh,
+    /// Sets the playlists, including the deleted ones
+    SetPlaylists(Vec<Playlist>),
+    /// Asks for a name, and makes an empty playlist with it
+    Create,
+    /// Asks for a new name for the playlist with the given ID
+    Rename(String),
+    /// Asks to confirm, then deletes the playlist with the given ID
+    Delete(String),
+    /// Adds the given articles to the end of the playlist with the given ID. Articles that are in
+    /// it already are skipped.
+    AddEntries {
+        playlist: String,
+        entries: Vec<QueueEntry>,
+    },
+    /// Takes the entry at the given index out of the playlist with the given ID
+    RemoveEntry { playlist: String, idx: usize },
+    /// Plays the playlist with the given ID from the start
+    Play(String),
+}
+
+/// The playlists panel on the main page
+#[derive(Default)]
+pub(crate) struct Playlists {
+    /// The playlists that haven't been deleted, by name
+    playlists: Vec<Playlist>,
+}
+
+impl Playlists {
+    /// Returns the playlist with the given ID, if it hasn't been deleted
+    fn get_mut(&mut self, id: &str) -> Option<&mut Playlist> {
+        self.playlists.iter_mut().find(|p| p.id == id)
+    }
+
+    /// Tells the library the names of the playlists, so it can offer to add articles to them
+    fn tell_library(&self, ctx: &Context<Self>) {
+        if let Some(library_link) = ctx.props().library_link.borrow().as_ref() {
+            let names = self
+                .playlists
+                .iter()
+                .map(|p| (p.id.clone(), p.name.clone()))
+                .collect();
+            library_link.send_message(LibraryMsg::SetPlaylists(names));
+        }
+    }
+}
+
+impl Component for Playlists {
+    type Message = PlaylistsMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        // Set the playlists link to this Playlists
+        ctx.props()
+            .playlists_link
+            .borrow_mut()
+            .replace(ctx.link().clone());
+        ctx.link().send_message(PlaylistsMsg::Refresh);
+        Playlists::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            PlaylistsMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    let playlists = caching::load_playlists().await.unwrap_or_else(|e| {
+                        tracing::error!("Couldn't load playlists: {}", e);
+                        Vec::new()
+                    });
+                    PlaylistsMsg::SetPlaylists(playlists)
+                });
+                return false;
+            }
+
+            PlaylistsMsg::SetPlaylists(playlists) => {
+                self.playlists = playlists.into_iter().filter(|p| !p.deleted).collect();
+                self.playlists.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+
+            PlaylistsMsg::Create => {
+                let Some(name) = prompt_for_name("Name the new playlist", "") else {
+                    return false;
+                };
+                let mut playlist = Playlist::new(name);
+                playlist.save();
+                self.playlists.push(playlist);
+                self.playlists.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+
+            PlaylistsMsg::Rename(id) => {
+                let Some(playlist) = self.get_mut(&id) else {
+                    return false;
+                };
+                let Some(name) = prompt_for_name("Rename the playlist", &playlist.name) else {
+                    return false;
+                };
+                playlist.name = name;
+                playlist.save();
+                self.playlists.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+
+            PlaylistsMsg::Delete(id) => {
+                let Some(playlist) = self.get_mut(&id) else {
+                    return false;
+                };
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message(&format!("Delete the playlist \"{}\"?", playlist.name))
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+                playlist.deleted = true;
+                playlist.entries.clear();
+                playlist.save();
+                self.playlists.retain(|p| p.id != id);
+            }
+
+            PlaylistsMsg::AddEntries { playlist, entries } => {
+                let Some(playlist) = self.get_mut(&playlist) else {
+                    return false;
+                };
+                for entry in entries {
+                    if !playlist.entries.iter().any(|e| e.id == entry.id) {
+                        playlist.entries.push(entry);
+                    }
+                }
+                playlist.save();
+                return true;
+            }
+
+            PlaylistsMsg::RemoveEntry { playlist, idx } => {
+                let Some(playlist) = self.get_mut(&playlist) else {
+                    return false;
+                };
+                if idx < playlist.entries.len() {
+                    playlist.entries.remove(idx);
+                    playlist.save();
+                }
+                return true;
+            }
+
+            PlaylistsMsg::Play(id) => {
+                let Some(playlist) = self.get_mut(&id) else {
+                    return false;
+                };
+                let queue_link = ctx.props().queue_link.borrow().clone().unwrap();
+                queue_link.send_message(QueueMsg::PlayPlaylist(playlist.entries.clone()));
+                return false;
+            }
+        }
+
+        // Every change to the list of playlists changes what the library offers
+        self.tell_library(ctx);
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let playlists = self
+            .playlists
+            .iter()
+            .map(|playlist| {
+                let id = playlist.id.clone();
+                let play = link.callback(move |_| PlaylistsMsg::Play(id.clone()));
+                let id = playlist.id.clone();
+                let rename = link.callback(move |_| PlaylistsMsg::Rename(id.clone()));
+                let id = playlist.id.clone();
+                let delete = link.callback(move |_| PlaylistsMsg::Delete(id.clone()));
+                let entries = playlist
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, entry)| {
+                        let playlist = playlist.id.clone();
+                        let remove = link
+                            .callback(move |_| PlaylistsMsg::RemoveEntry {
+                                playlist: playlist.clone(),
+                                idx,
+                            });
+                        html! {
+                            <li>
+                                <bdi>{ &entry.title }</bdi>
+                                <button
+                                    class="articleMetadata"
+                                    onclick={remove}
+                                    aria-label={ format!("Take {} out of the playlist", entry.title) }
+                                >
+                                    { "✕" }
+                                </button>
+                            </li>
+                        }
+                    })
+                    .collect::<Html>();
+
+                html! {
+                    <li>
+                        <strong>{ &playlist.name }</strong>
+                        <span class="articleMetadata">
+                            { format!("{} articles ", playlist.entries.len()) }
+                            <button
+                                onclick={play}
+                                disabled={ playlist.entries.is_empty() }
+                                aria-label={ format!("Play {}", playlist.name) }
+                            >
+                                { "▶️" }
+                            </button>
+                            <button onclick={rename}>{ "Rename" }</button>
+                            <button onclick={delete}>{ "Delete" }</button>
+                        </span>
+                        <ol aria-label={ playlist.name.clone() }>{ entries }</ol>
+                    </li>
+                }
+            })
+            .collect::<Html>();
+        let create = link.callback(|_| PlaylistsMsg::Create);
+
+        html! {
+            <details class="playlists">
+                <summary>{ "Playlists" }</summary>
+                if self.playlists.is_empty() {
+                    <p class="articleMetadata">{
+                        "No playlists yet. Add articles to one by selecting them in the library."
+                    }</p>
+                }
+                <ul class="playlistList">{ playlists }</ul>
+                <button onclick={create}>{ "New playlist" }</button>
+            </details>
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Positions that conflict between devices, e.g., after listening to the same article offline on a
+//! phone and a laptop. Syncing would take whichever position is furthest along, and lose the other.
+//! Instead, a position from another device that would replace one this device played since it last
+//! synced is held back, and the listener picks between them the next time the article's opened.
+//! Picking this device's position sends it with the time of the pick, so it wins over the other
+//! one everywhere. With the setting on, the furthest wins without asking, like before.
+//!
+//! The conflicts, picks, and setting are kept in localStorage, like the eviction policy.
+
+use crate::{queue_view::ArticleId, servers};
+use common::SyncedPosition;
+
+use gloo_utils::window;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+/// How far apart two positions have to be, in seconds, to conflict. Closer than this, the
+/// furthest wins without asking.
+const CONFLICT_SECS: f64 = 30.0;
+
+/// How many picks are remembered. Picks only matter while an article's in the queue.
+const MAX_PICKS: usize = 50;
+
+/// The localStorage key of the held back positions
+const CONFLICTS_KEY: &str = "position-conflicts";
+
+/// The localStorage key of the listener's picks
+const PICKS_KEY: &str = "position-picks";
+
+/// The localStorage key of when this device last synced
+const LAST_SYNCED_KEY: &str = "last-synced";
+
+/// The localStorage key of the setting to let the furthest position win without asking
+const FURTHEST_WINS_KEY: &str = "furthest-position-wins";
+
+/// A position from another device that was held back, since it conflicts with this device's
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PositionConflict {
+    pub(crate) id: ArticleId,
+    /// This device's position
+    pub(crate) local: SyncedPosition,
+    /// The other device's position
+    pub(crate) remote: SyncedPosition,
+    /// The other device's nickname
+    pub(crate) remote_name: String,
+}
+
+/// When the listener picked this device's position in an article over another device's
+#[derive(Serialize, Deserialize)]
+struct Pick {
+    id: ArticleId,
+    /// When, in milliseconds since the Unix epoch
+    at: f64,
+}
+
+/// Loads the JSON under the given key from localStorage, if it's there and parses
+fn load_json<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let storage = window().local_storage().ok().flatten()?;
+    let json = storage
+        .get_item(&servers::scoped_name(key))
+        .ok()
+        .flatten()?;
+    js_sys::JSON::parse(&json).ok()?.into_serde().ok()
+}
+
+/// Saves the given value as JSON under the given key in localStorage
+fn save_json<T: Serialize>(key: &str, value: &T) {
+    let json = JsValue::from_serde(value)
+        .ok()
+        .and_then(|v| js_sys::JSON::stringify(&v).ok())
+        .and_then(|s| s.as_string());
+    let storage = window().local_storage().ok().flatten();
+    if let (Some(storage), Some(json)) = (storage, json) {
+        if let Err(e) = storage.set_item(&servers::scoped_name(key), &json) {
+            tracing::error!("Couldn't save {key}: {:?}", e);
+        }
+    }
+}
+
+/// Returns whether the furthest position wins without asking
+pub(crate) fn furthest_wins() -> bool {
+    load_json(FURTHEST_WINS_KEY).unwrap_or(false)
+}
+
+/// Sets whether the furthest position wins without asking
+pub(crate) fn set_furthest_wins(on: bool) {
+    save_json(FURTHEST_WINS_KEY, &on);
+}
+
+/// Returns when this device last synced, in milliseconds since the Unix epoch, or 0 if it never
+/// has
+pub(crate) fn last_synced() -> f64 {
+    load_json(LAST_SYNCED_KEY).unwrap_or(0.0)
+}
+
+/// Notes when this device last synced
+pub(crate) fn set_last_synced(t: f64) {
+    save_json(LAST_SYNCED_KEY, &t);
+}
+
+/// Returns whether the given position from another device conflicts with the given one from this
+/// device, which has the given ID. They conflict if this device played its position since it last
+/// synced, at the given time, and they're far apart. A position the listener picked on another
+/// device doesn't conflict, since they already chose.
+pub(crate) fn is_conflict(
+    local: &SyncedPosition,
+    remote: &SyncedPosition,
+    last_synced: f64,
+    this_device: &str,
+) -> bool {
+    let local_is_ours = local.device.as_deref().is_none_or(|d| d == this_device);
+    let remote_is_theirs = remote.device.as_deref().is_some_and(|d| d != this_device);
+    let played_since_sync = local.last_played > last_synced;
+    let picked = remote.chosen > local.last_played;
+    let far_apart =
+        local.finished != remote.finished || (local.elapsed - remote.elapsed).abs() > CONFLICT_SECS;
+    local_is_ours && remote_is_theirs && played_since_sync && !picked && far_apart
+}
+
+/// Loads the held back positions
+pub(crate) fn load_conflicts() -> Vec<PositionConflict> {
+    load_json(CONFLICTS_KEY).unwrap_or_default()
+}
+
+/// Holds back the given position, in place of any other held back in the same article
+pub(crate) fn hold_back(conflict: PositionConflict) {
+    let mut conflicts = load_conflicts();
+    conflicts.retain(|c| c.id != conflict.id);
+    conflicts.push(conflict);
+    save_json(CONFLICTS_KEY, &conflicts);
+}
+
+/// Returns the held back position in the given article, if there is one
+pub(crate) fn find(id: &ArticleId) -> Option<PositionConflict> {
+    load_conflicts().into_iter().find(|c| &c.id == id)
+}
+
+/// Forgets the held back position in the given article, once the listener's picked
+pub(crate) fn resolve(id: &ArticleId) {
+    let mut conflicts = load_conflicts();
+    conflicts.retain(|c| &c.id != id);
+    save_json(CONFLICTS_KEY, &conflicts);
+}
+
+/// Notes that the listener picked this device's position in the given article at the given time
+pub(crate) fn pick_local(id: &ArticleId, at: f64) {
+    let mut picks: Vec<Pick> = load_json(PICKS_KEY).unwrap_or_default();
+    picks.retain(|p| &p.id != id);
+    picks.push(Pick { id: id.clone(), at });
+    if picks.len() > MAX_PICKS {
+        picks.remove(0);
+    }
+    save_json(PICKS_KEY, &picks);
+}
+
+/// Returns when the listener last picked this device's position in the given article, or 0 if
+/// they never did
+pub(crate) fn picked_at(id: &ArticleId) -> f64 {
+    let picks: Vec<Pick> = load_json(PICKS_KEY).unwrap_or_default();
+    picks
+        .into_iter()
+        .find(|p| &p.id == id)
+        .map_or(0.0, |p| p.at)
+}
+
+#[test]
+fn finding_conflicts() {
+    let pos = |elapsed: f64, last_played: f64, device: Option<&str>| SyncedPosition {
+        id: "a".to_string(),
+        elapsed,
+        finished: false,
+        last_played,
+        device: device.map(str::to_string),
+        chosen: 0.0,
+    };
+
+    // Both devices listened since this one last synced, at 100
+    let local = pos(60.0, 150.0, None);
+    assert!(is_conflict(
+        &local,
+        &pos(600.0, 120.0, Some("phone")),
+        100.0,
+        "laptop"
+    ));
+
+    // Nothing was played here since the last sync, so the phone just picked up where it left off
+    assert!(!is_conflict(
+        &local,
+        &pos(600.0, 120.0, Some("phone")),
+        200.0,
+        "laptop"
+    ));
+
+    // Close positions, this device's own, and ones picked on the other device don't conflict
+    assert!(!is_conflict(
+        &local,
+        &pos(80.0, 120.0, Some("phone")),
+        100.0,
+        "laptop"
+    ));
+    assert!(!is_conflict(
+        &local,
+        &pos(600.0, 120.0, Some("laptop")),
+        100.0,
+        "laptop"
+    ));
+    let picked = SyncedPosition {
+        chosen: 160.0,
+        ..pos(600.0, 120.0, Some("phone"))
+    };
+    assert!(!is_conflict(&local, &picked, 100.0, "laptop"));
+
+    // A position this device got from the phone before isn't this device's to defend
+    let synced_before = pos(60.0, 150.0, Some("phone"));
+    assert!(!is_conflict(
+        &synced_before,
+        &pos(600.0, 120.0, Some("tablet")),
+        100.0,
+        "laptop"
+    ));
+}
@@ -0,0 +1,183 @@
+//! The encrypted library, which is listed apart from the library since the server can't read it.
+//! Articles are added to it from the Add page, and decrypted when they're put in the queue. See
+//! `encryption.rs`.
+
+use crate::{
+    encryption::{self, PrivateArticle},
+    queue_view::{Queue, QueueEntry, QueueMsg},
+    utils, WeakComponentLink,
+};
+
+use anyhow::Error as AnyError;
+use yew::prelude::*;
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// A link to the Queue component, which decrypted articles are added to
+    pub queue_link: WeakComponentLink<Queue>,
+}
+
+pub(crate) enum PrivateMsg {
+    /// Fetches the articles
+    Refresh,
+    /// Sets the articles
+    SetArticles(Vec<PrivateArticle>),
+    /// Downloads and decrypts the article with the given ID, and queues it
+    Queue(String),
+    /// Tells the queue about the decrypted article
+    Queued(QueueEntry),
+    /// Deletes the article with the given ID
+    Delete(String),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+/// The encrypted library on the main page
+#[derive(Default)]
+pub(crate) struct PrivateLibrary {
+    articles: Vec<PrivateArticle>,
+    /// The ID of the article being downloaded and decrypted, if any
+    decrypting: Option<String>,
+    err: Option<AnyError>,
+}
+
+impl Component for PrivateLibrary {
+    type Message = PrivateMsg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        PrivateLibrary::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            PrivateMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    match encryption::fetch_private_articles().await {
+                        Ok(articles) => PrivateMsg::SetArticles(articles),
+                        Err(e) => PrivateMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            PrivateMsg::SetArticles(articles) => {
+                self.articles = articles;
+                self.err = None;
+            }
+
+            PrivateMsg::Queue(id) => {
+                self.decrypting = Some(id.clone());
+                ctx.link().send_future(async move {
+                    match encryption::download_private_article(&id).await {
+                        Ok(entry) => PrivateMsg::Queued(entry),
+                        Err(e) => PrivateMsg::SetError(e),
+                    }
+                });
+            }
+
+            PrivateMsg::Queued(entry) => {
+                self.decrypting = None;
+                ctx.props()
+                    .queue_link
+                    .borrow()
+                    .clone()
+                    .unwrap()
+                    .send_message(QueueMsg::Add(entry));
+            }
+
+            PrivateMsg::Delete(id) => {
+                ctx.link().send_future(async move {
+                    match encryption::delete_private_article(id).await {
+                        Ok(()) => PrivateMsg::Refresh,
+                        Err(e) => PrivateMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            PrivateMsg::SetError(e) => {
+                self.decrypting = None;
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let rows = self
+            .articles
+            .iter()
+            .map(|article| {
+                let title = article
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| "(Encrypted on another device)".to_string());
+                let delete_id = article.id.clone();
+                let delete = link.callback(move |_| PrivateMsg::Delete(delete_id.clone()));
+
+                // Only the device that encrypted an article can play it
+                let queue_button = if article.title.is_none() {
+                    html! {}
+                } else if self.decrypting.as_ref() == Some(&article.id) {
+                    html! { <span>{ "Decrypting..." }</span> }
+                } else {
+                    let queue_id = article.id.clone();
+                    let queue = link.callback(move |_| PrivateMsg::Queue(queue_id.clone()));
+                    html! {
+                        <button onclick={queue} aria-label={ format!("Add {title} to queue") }>
+                            { "Add to queue" }
+                        </button>
+                    }
+                };
+
+                html! {
+                    <tr>
+                        <td>{ &title }</td>
+                        <td class="articleMetadata">{ format!(
+                            "Added {}, {:.1} MB",
+                            utils::format_date(article.uploaded),
+                            article.size as f64 / 1e6,
+                        ) }</td>
+                        <td>{ queue_button }</td>
+                        <td>
+                            <button onclick={delete} aria-label={ format!("Delete {title}") }>
+                                { "Delete" }
+                            </button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+        let ontoggle = link.callback(|_| PrivateMsg::Refresh);
+        let list = if self.articles.is_empty() {
+            html! {
+                <p class="articleMetadata">{
+                    "Nothing's encrypted. Add an article by text with \"Encrypt on this device\"
+                    checked."
+                }</p>
+            }
+        } else {
+            html! { <table aria-label="Encrypted library">{ rows }</table> }
+        };
+
+        html! {
+            <details class="privateLibrary" {ontoggle}>
+                <summary>{ "Encrypted library" }</summary>
+                <p class="articleMetadata">{
+                    "These articles were encrypted on the device that added them, and only it can
+                    play them. They can't be searched."
+                }</p>
+                { list }
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </details>
+        }
+    }
+}
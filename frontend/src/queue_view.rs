@@ -1,13 +1,18 @@
 use crate::{
-    caching,
+    caching::{self, EvictionPolicy},
+    history_view,
     library_view::{Library, LibraryMsg},
     player_view::{Player, PlayerMsg},
-    WeakComponentLink,
+    utils, WeakComponentLink,
 };
+use common::{ArticleGroup, ArticleTranscript, Rating, ReadingProfile, SyncedQueueEntry};
+
+use std::collections::{BTreeMap, BTreeSet};
 
 use serde::{Deserialize, Serialize};
 use wasm_bindgen_futures::spawn_local;
-use yew::prelude::*;
+use web_sys::Blob;
+use yew::{html::Scope, prelude::*};
 
 #[derive(PartialEq, Properties)]
 pub(crate) struct Props {
@@ -19,11 +24,61 @@ pub(crate) struct Props {
     pub library_link: WeakComponentLink<Library>,
 }
 
-/// An entry in the queue has the title and ID of the article
+/// An entry in the queue has the title and ID of the article, and the group it's a part of, if any.
+/// Its publication and reading profile are kept for suggesting a playback speed, and its author,
+/// publication, and lead image for the OS media controls.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct QueueEntry {
     pub(crate) id: ArticleId,
     pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) group: Option<ArticleGroup>,
+    #[serde(default)]
+    pub(crate) publication: Option<String>,
+    #[serde(default)]
+    pub(crate) author: Option<String>,
+    #[serde(default)]
+    pub(crate) image: Option<String>,
+    #[serde(default)]
+    pub(crate) reading_profile: Option<ReadingProfile>,
+}
+
+impl From<SyncedQueueEntry> for QueueEntry {
+    fn from(entry: SyncedQueueEntry) -> QueueEntry {
+        QueueEntry {
+            id: ArticleId(entry.id),
+            title: entry.title,
+            group: entry.group,
+            publication: entry.publication,
+            author: entry.author,
+            image: entry.image,
+            reading_profile: entry.reading_profile,
+        }
+    }
+}
+
+impl From<&QueueEntry> for SyncedQueueEntry {
+    fn from(entry: &QueueEntry) -> SyncedQueueEntry {
+        SyncedQueueEntry {
+            id: entry.id.0.clone(),
+            title: entry.title.clone(),
+            group: entry.group.clone(),
+            publication: entry.publication.clone(),
+            author: entry.author.clone(),
+            image: entry.image.clone(),
+            reading_profile: entry.reading_profile.clone(),
+        }
+    }
+}
+
+impl QueueEntry {
+    /// Returns whether this entry and the given one are parts of the same group
+    pub(crate) fn same_group(&self, other: &QueueEntry) -> bool {
+        match (&self.group, &other.group) {
+            (Some(a), Some(b)) => a.id == b.id,
+            _ => false,
+        }
+    }
 }
 
 pub(crate) enum QueueMsg {
@@ -31,20 +86,109 @@ pub(crate) enum QueueMsg {
     Add(QueueEntry),
     /// Deletes the entry at the given index
     Delete(usize),
+    /// Deletes every part of the group with the given ID
+    DeleteGroup(String),
+    /// Deletes the given articles from storage, and takes them out of the queue if they're in it
+    DeleteArticles(Vec<ArticleId>),
+    /// Takes the item starting at the given index out of the queue, but keeps its articles
+    /// downloaded, so queueing them again doesn't download them again
+    Remove(usize),
+    /// Like `Remove`, for the entries of the given articles. Articles that aren't queued are
+    /// skipped.
+    RemoveArticles(Vec<ArticleId>),
+    /// Deletes the entries whose articles have been listened to the end
+    RemoveFinished,
+    /// Deletes the entries whose articles the eviction policy says to delete
+    Evict,
+    /// The item starting at the given index started being dragged
+    DragStart(usize),
+    /// The item being dragged was dropped on the item starting at the given index. It's moved
+    /// there.
+    Drop(usize),
     /// Sets the queue contents. Used in loading from previous state
     SetQueue(Queue),
+    /// Sets the queue to the given entries, which another device changed it to at the given time.
+    /// The articles that aren't downloaded yet are downloaded, and put in their place once they
+    /// are.
+    SetSynced {
+        entries: Vec<QueueEntry>,
+        updated: f64,
+    },
     /// A message from the player asking to get the article that comes after the given one
     PlayTrackAfter(ArticleId),
     /// A message from the player asking to get the article that comes before the given one
     PlayTrackBefore(ArticleId),
+    /// A message from the player, when the given article has finished. The next part of its group
+    /// is played, if there is one. Otherwise, if `continuous` is set, the next entry in the queue is
+    /// played, or the library is asked to auto-advance if there's nothing after it.
+    TrackEnded { id: ArticleId, continuous: bool },
+    /// Plays the queued article with the given ID
+    PlayEntry(ArticleId),
+    /// A message from the player rating the given article. The library saves the rating.
+    Rate {
+        id: ArticleId,
+        rating: Option<Rating>,
+    },
+    /// Loads how far each article has been listened to again, for the queue and the library
+    RefreshListened,
+    /// Sets how far each article has been listened to, as a percentage
+    SetListenedPcts(BTreeMap<ArticleId, u32>),
+    /// Plays the given playlist from the start. When each of its articles finishes, the next one
+    /// plays, whatever's in the queue.
+    PlayPlaylist(Vec<QueueEntry>),
+    /// Pins the given entries' articles, or unpins them if they're all pinned
+    TogglePinned(Vec<QueueEntry>),
+    /// Loads which articles are pinned again, once they've been changed in the storage panel
+    RefreshPinned,
+    /// A message from the player, when it starts playing the given article, or stops playing
+    /// anything from the queue
+    NowPlaying(Option<ArticleId>),
+    /// Opens the actions menu of the item that starts with the given article, or closes it if it's
+    /// open
+    ToggleMenu(ArticleId),
+    /// Moves the item starting at the given index to the given place in the queue
+    Place(usize, Placement),
+    /// Moves the item that has the given article in it to the given place in the queue. Articles
+    /// that aren't queued are skipped.
+    PlaceArticle(ArticleId, Placement),
+    /// Adds the given entry to the queue, and moves its item to the given place
+    AddAt(QueueEntry, Placement),
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Where an item is moved to in the queue. An item is a single article, or consecutive parts of a
+/// group, which always move together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Placement {
+    /// Right after the item that's playing, and it starts playing. If nothing in the queue is
+    /// playing, it goes first.
+    Now,
+    /// Right after the item that's playing, so it plays when that's done. If nothing in the queue
+    /// is playing, it goes first.
+    Next,
+    /// After every other item
+    End,
+}
+
+#[derive(Clone)]
 pub struct CachedArticle {
     pub title: String,
     // TODO: Make id unique. Currently it's just a copy of the title
     pub id: ArticleId,
-    pub audio_blob: Vec<u8>,
+    /// The article's audio, in the chunks it was downloaded in. Keeping it as blobs, rather than
+    /// bytes, keeps it out of the app's memory until the browser plays it.
+    pub audio_chunks: Vec<Blob>,
+    /// The MIME type of the audio. It's MP3 unless the server transcoded it to something smaller.
+    pub mime_type: String,
+    /// The article's text, and when each paragraph of it starts in the audio. This is for skipping
+    /// between sections and reading along, offline too.
+    pub transcript: ArticleTranscript,
+}
+
+impl CachedArticle {
+    /// Returns the article's audio as one blob, for playing
+    pub fn audio(&self) -> Blob {
+        utils::join_blobs(&self.audio_chunks, &self.mime_type)
+    }
 }
 
 impl From<&CachedArticle> for QueueEntry {
@@ -52,6 +196,11 @@ impl From<&CachedArticle> for QueueEntry {
         QueueEntry {
             title: article.title.clone(),
             id: article.id.clone(),
+            group: None,
+            publication: None,
+            author: None,
+            image: None,
+            reading_profile: None,
         }
     }
 }
@@ -71,11 +220,53 @@ pub(crate) struct QueuePosition {
 #[derive(Clone, Default, Serialize, Deserialize)]
 pub(crate) struct Queue {
     entries: Vec<QueueEntry>,
+    /// When the listener last changed the queue, in milliseconds since the Unix epoch, or when the
+    /// device it was synced from did. 0 if it hasn't changed since syncing began.
+    #[serde(default)]
+    updated: f64,
+    /// The index of the item being dragged, if any
+    #[serde(skip)]
+    dragging: Option<usize>,
+    /// The order of the queue last synced from another device. Articles from it that are still
+    /// downloading go in their place once they're done.
+    #[serde(skip)]
+    synced_order: Vec<ArticleId>,
+    /// How far each article has been listened to, as a percentage
+    #[serde(skip)]
+    listened_pcts: BTreeMap<ArticleId, u32>,
+    /// The playlist being played, if any
+    #[serde(skip)]
+    playing_playlist: Vec<QueueEntry>,
+    /// The articles the eviction policy never deletes
+    #[serde(skip)]
+    pinned: BTreeSet<ArticleId>,
+    /// The article the player is playing, if it's been told
+    #[serde(skip)]
+    playing: Option<ArticleId>,
+    /// The first article of the item whose actions menu is open, if any
+    #[serde(skip)]
+    menu_open: Option<ArticleId>,
 }
 
 impl Queue {
+    /// The entries, in order
+    pub(crate) fn entries(&self) -> &[QueueEntry] {
+        &self.entries
+    }
+
+    /// When the queue last changed, in milliseconds since the Unix epoch
+    pub(crate) fn updated(&self) -> f64 {
+        self.updated
+    }
+
+    /// Notes that the listener changed the queue, and saves it to the IndexedDB
+    fn save(&mut self) {
+        self.updated = js_sys::Date::now();
+        self.persist();
+    }
+
     /// Saves the queue to the IndexedDB
-    fn save(&self) {
+    fn persist(&self) {
         let self_copy = self.clone();
         spawn_local(async move {
             let _ = caching::save_queue(&self_copy)
@@ -84,6 +275,172 @@ impl Queue {
         });
     }
 
+    /// Adds the given entry to the queue. Parts of a group are kept together and in order, however
+    /// they arrive.
+    fn insert(&mut self, entry: QueueEntry) {
+        // An article from a synced queue goes before the first entry that follows it there
+        if let Some(synced_pos) = self.synced_order.iter().position(|id| id == &entry.id) {
+            let follows = &self.synced_order[synced_pos + 1..];
+            let pos = self.entries.iter().position(|e| follows.contains(&e.id));
+            match pos {
+                Some(i) => self.entries.insert(i, entry),
+                None => self.entries.push(entry),
+            }
+            return;
+        }
+
+        let part = entry.group.as_ref().map(|g| g.part).unwrap_or(0);
+        let group_positions = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.same_group(&entry))
+            .map(|(i, e)| (i, e.group.as_ref().map(|g| g.part).unwrap_or(0)));
+
+        // Go before the first later part of the group. If there isn't one, go after the last
+        // earlier part. If there isn't one of those either, go at the end.
+        let mut pos = None;
+        for (i, other_part) in group_positions {
+            if other_part > part {
+                pos = Some(i);
+                break;
+            }
+            pos = Some(i + 1);
+        }
+
+        match pos {
+            Some(i) => self.entries.insert(i, entry),
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Returns the number of entries in the item starting at the given index. Consecutive parts of
+    /// the same group are a single item.
+    fn item_len(&self, start: usize) -> usize {
+        let first = &self.entries[start];
+        self.entries[start..]
+            .iter()
+            .take_while(|e| e.same_group(first))
+            .count()
+            .max(1)
+    }
+
+    /// Moves the item starting at index `from` to where the item starting at index `to` is. The
+    /// items in between shift over by one.
+    fn move_item(&mut self, from: usize, to: usize) {
+        let (from_len, to_len) = (self.item_len(from), self.item_len(to));
+        if (from..from + from_len).contains(&to) {
+            return;
+        }
+
+        let item: Vec<QueueEntry> = self.entries.drain(from..from + from_len).collect();
+        // Moving down puts the item after the one it was dropped on. Moving up puts it before.
+        let insert_at = if to > from {
+            to + to_len - from_len
+        } else {
+            to
+        };
+        self.entries.splice(insert_at..insert_at, item);
+    }
+
+    /// Returns the index of the first entry of the item that has the given entry in it
+    fn item_start(&self, idx: usize) -> usize {
+        let entry = &self.entries[idx];
+        self.entries[..idx]
+            .iter()
+            .rev()
+            .take_while(|e| e.same_group(entry))
+            .fold(idx, |start, _| start - 1)
+    }
+
+    /// Returns the index of the entry that's playing, if it's in the queue
+    fn playing_idx(&self) -> Option<usize> {
+        let playing = self.playing.as_ref()?;
+        self.entries.iter().position(|e| &e.id == playing)
+    }
+
+    /// Returns whether the item starting at the given index has the entry that's playing in it
+    fn is_playing(&self, start: usize) -> bool {
+        let len = self.item_len(start);
+        self.playing_idx()
+            .is_some_and(|p| (start..start + len).contains(&p))
+    }
+
+    /// Moves the item starting at the given index to the given place, and returns its new index.
+    /// Moving the item that's playing to play now or next leaves it where it is.
+    fn place_item(&mut self, from: usize, placement: Placement) -> usize {
+        if placement != Placement::End && self.is_playing(from) {
+            return from;
+        }
+
+        let len = self.item_len(from);
+        let playing = self.playing_idx();
+
+        let item: Vec<QueueEntry> = self.entries.drain(from..from + len).collect();
+        let to = match placement {
+            Placement::End => self.entries.len(),
+            Placement::Now | Placement::Next => match playing {
+                // The playing item's index is from before the item was taken out
+                Some(p) => {
+                    let p = if p > from { p - len } else { p };
+                    let start = self.item_start(p);
+                    start + self.item_len(start)
+                }
+                None => 0,
+            },
+        };
+        self.entries.splice(to..to, item);
+        to
+    }
+
+    /// Moves the item starting at the given index to the given place, and saves the queue if that
+    /// moved it. Playing it now plays it, unless it's already playing.
+    fn place(&mut self, ctx: &Context<Self>, from: usize, placement: Placement) {
+        let was_playing = self.is_playing(from);
+        let to = self.place_item(from, placement);
+        if to != from {
+            self.save();
+        }
+        if placement == Placement::Now && !was_playing {
+            let player_link = ctx.props().player_link.borrow().clone().unwrap();
+            player_link.send_message(PlayerMsg::Play(self.entries[to].clone()));
+        }
+    }
+
+    /// Deletes the entries that match the given predicate, and their articles
+    fn delete_where(&mut self, ctx: &Context<Self>, pred: impl Fn(&QueueEntry) -> bool) {
+        self.remove_where(ctx, pred);
+        self.save();
+    }
+
+    /// Like `delete_where`, but doesn't save the queue
+    fn remove_where(&mut self, ctx: &Context<Self>, pred: impl Fn(&QueueEntry) -> bool) {
+        for entry in self.take_out_where(ctx, pred) {
+            spawn_local(delete_cached(entry.id));
+        }
+    }
+
+    /// Takes the entries that match the given predicate out of the queue, and returns them. Their
+    /// articles stay in storage.
+    fn take_out_where(
+        &mut self,
+        ctx: &Context<Self>,
+        pred: impl Fn(&QueueEntry) -> bool,
+    ) -> Vec<QueueEntry> {
+        let player_link = ctx.props().player_link.borrow().clone().unwrap();
+        let library_link = ctx.props().library_link.borrow().clone().unwrap();
+
+        let (taken, rest): (Vec<QueueEntry>, Vec<QueueEntry>) =
+            self.entries.drain(..).partition(pred);
+        self.entries = rest;
+
+        for entry in &taken {
+            player_link.send_message(PlayerMsg::StopIfPlaying(entry.id.clone()));
+            library_link.send_message(LibraryMsg::MarkAsUnqueued(entry.id.clone()));
+        }
+        taken
+    }
+
     /// Attempts to load the queue from IndexedDB
     async fn load() -> Option<Queue> {
         caching::load_queue()
@@ -93,6 +450,19 @@ impl Queue {
     }
 }
 
+/// Deletes the given article and the reader's position in it from storage
+async fn delete_cached(id: ArticleId) {
+    // Delete the article itself
+    let _ = caching::delete_article(&id)
+        .await
+        .map_err(|e| tracing::error!("Couldn't delete article {}: {}", &id.0, e));
+
+    // Delete the reader's position in the article
+    let _ = caching::delete_article_state(&id)
+        .await
+        .map_err(|e| tracing::error!("Couldn't delete state of article {}: {}", &id.0, e));
+}
+
 impl Component for Queue {
     type Message = QueueMsg;
     type Properties = Props;
@@ -116,31 +486,135 @@ impl Component for Queue {
                 self.save();
 
                 // Delete the article from storage
-                spawn_local(async move {
-                    // Delete the article itself
-                    let _ = caching::delete_article(&entry.id).await.map_err(|e| {
-                        tracing::error!("Couldn't delete article {}: {}", &entry.id.0, e)
-                    });
-
-                    // Delete the reader's position in the article
-                    let _ = caching::delete_article_state(&entry.id).await.map_err(|e| {
-                        tracing::error!("Couldn't delete state of article {}: {}", &entry.id.0, e)
-                    });
+                spawn_local(delete_cached(entry.id));
+            }
+            QueueMsg::DeleteGroup(group_id) => {
+                // Remove every part of the group, and delete them all from the cache
+                self.delete_where(
+                    ctx,
+                    |entry| matches!(&entry.group, Some(group) if group.id == group_id),
+                );
+            }
+            QueueMsg::DeleteArticles(ids) => {
+                // Articles that were taken out of the queue, but kept, are only in storage
+                let (queued, unqueued): (Vec<ArticleId>, Vec<ArticleId>) = ids
+                    .into_iter()
+                    .partition(|id| self.entries.iter().any(|e| &e.id == id));
+                self.delete_where(ctx, |entry| queued.contains(&entry.id));
+                for id in unqueued {
+                    spawn_local(delete_cached(id));
+                }
+            }
+            QueueMsg::Remove(idx) => {
+                let item = self.entries[idx..idx + self.item_len(idx)]
+                    .iter()
+                    .map(|e| e.id.clone())
+                    .collect();
+                ctx.link().send_message(QueueMsg::RemoveArticles(item));
+                return false;
+            }
+            QueueMsg::RemoveArticles(ids) => {
+                self.menu_open = None;
+                self.take_out_where(ctx, |entry| ids.contains(&entry.id));
+                self.save();
+            }
+            QueueMsg::RemoveFinished => {
+                // Look up how far each article was listened to, then delete the finished ones
+                let entries = self.entries.clone();
+                ctx.link().send_future(async move {
+                    let mut finished = Vec::new();
+                    for entry in entries {
+                        let state = caching::load_article_state(&entry.id).await;
+                        if state.is_ok_and(|s| s.finished) {
+                            finished.push(entry.id);
+                        }
+                    }
+                    QueueMsg::DeleteArticles(finished)
+                });
+
+                return false;
+            }
+            QueueMsg::Evict => {
+                // Work out what the policy deletes, then delete it
+                ctx.link().send_future_batch(async move {
+                    let policy = caching::EvictionPolicy::load();
+                    let evicted = match caching::load_eviction_candidates().await {
+                        Ok(candidates) => caching::pick_evictions(&policy, candidates),
+                        Err(e) => {
+                            tracing::error!("Couldn't check what to evict: {}", e);
+                            Vec::new()
+                        }
+                    };
+                    if evicted.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![QueueMsg::DeleteArticles(evicted)]
+                    }
                 });
+
+                return false;
+            }
+            QueueMsg::DragStart(idx) => {
+                self.dragging = Some(idx);
+                return false;
+            }
+            QueueMsg::Drop(to) => {
+                let from = match self.dragging.take() {
+                    Some(from) if from != to => from,
+                    _ => return false,
+                };
+                self.move_item(from, to);
+                self.save();
             }
             QueueMsg::Add(entry) => {
-                // Add the entry to the queue
-                self.entries.push(entry);
+                // Add the entry to the queue. An article that was downloaded because another
+                // device queued it doesn't count as a change here.
+                let synced = self.synced_order.contains(&entry.id);
+                self.insert(entry);
                 // Save it to IndexedDB
-                self.save()
+                if synced {
+                    self.persist();
+                } else {
+                    self.save();
+                }
+                // Make room for it, if the eviction policy says to
+                ctx.link().send_message(QueueMsg::Evict);
             }
             QueueMsg::SetQueue(queue) => {
+                // A sync may have finished first, in which case it's already got the newer queue
+                if queue.updated < self.updated {
+                    return false;
+                }
                 // Copy the IDs down
                 let queue_ids = queue.entries.iter().map(|entry| entry.id.clone()).collect();
                 // Set the queue
-                *self = queue;
+                *self = Queue {
+                    pinned: EvictionPolicy::load().pinned,
+                    ..queue
+                };
                 // Tell the library what to mark as queued
                 library_link.send_message(LibraryMsg::MarkAsQueued(queue_ids));
+                ctx.link().send_message(QueueMsg::RefreshListened);
+                // Apply the eviction policy, since it might have changed, or articles might have
+                // been finished, since the last visit
+                ctx.link().send_message(QueueMsg::Evict);
+            }
+            QueueMsg::SetSynced { entries, updated } => {
+                // Take out the articles the other device took out
+                let synced_ids: Vec<ArticleId> = entries.iter().map(|e| e.id.clone()).collect();
+                self.remove_where(ctx, |entry| !synced_ids.contains(&entry.id));
+
+                // Put the rest in the other device's order, and download the ones that are missing
+                let (present, missing): (Vec<QueueEntry>, Vec<QueueEntry>) = entries
+                    .into_iter()
+                    .partition(|e| self.entries.iter().any(|local| local.id == e.id));
+                self.entries = present;
+                self.synced_order = synced_ids;
+                self.updated = updated;
+                self.persist();
+                if !missing.is_empty() {
+                    library_link.send_message(LibraryMsg::ResumeDownloads(missing));
+                }
             }
             QueueMsg::PlayTrackBefore(article_id) => {
                 // Find the article ID in the queue
@@ -166,6 +640,110 @@ impl Component for Queue {
                     player_link.send_message(PlayerMsg::Play(p.clone()));
                 }
             }
+            QueueMsg::TrackEnded { id, continuous } => {
+                // Find the article ID in the queue, and the entry after it
+                let now_playing_idx = self.entries.iter().position(|x| x.id == id);
+                let next = now_playing_idx
+                    .and_then(|i| Some((&self.entries[i], self.entries.get(i + 1)?)));
+                let playlist_next = self
+                    .playing_playlist
+                    .iter()
+                    .position(|x| x.id == id)
+                    .and_then(|i| self.playing_playlist.get(i + 1));
+
+                // Keep playing if the next entry continues the same group. Otherwise, if playback
+                // is continuous, play the next article in the playlist being played, or the next
+                // entry, or let the auto-advance playlist, if any, pick what's next.
+                match (next, playlist_next) {
+                    (Some((cur, next)), _) if cur.same_group(next) => {
+                        player_link.send_message(PlayerMsg::Play(next.clone()));
+                    }
+                    (_, Some(playlist_next)) if continuous => {
+                        library_link.send_message(LibraryMsg::PlayArticle(playlist_next.clone()));
+                    }
+                    (Some((_, next)), None) if continuous => {
+                        player_link.send_message(PlayerMsg::Play(next.clone()));
+                    }
+                    (None, None) if continuous => {
+                        library_link.send_message(LibraryMsg::AutoAdvance(id))
+                    }
+                    _ => (),
+                }
+
+                return false;
+            }
+            QueueMsg::PlayEntry(article_id) => {
+                if let Some(entry) = self.entries.iter().find(|x| x.id == article_id) {
+                    player_link.send_message(PlayerMsg::Play(entry.clone()));
+                }
+
+                return false;
+            }
+            QueueMsg::Rate { id, rating } => {
+                library_link.send_message(LibraryMsg::Rate { id, rating });
+                return false;
+            }
+            QueueMsg::RefreshListened => {
+                ctx.link().send_future(async move {
+                    QueueMsg::SetListenedPcts(history_view::load_listened_pcts().await)
+                });
+                return false;
+            }
+            QueueMsg::SetListenedPcts(pcts) => {
+                library_link.send_message(LibraryMsg::SetListenedPcts(pcts.clone()));
+                self.listened_pcts = pcts;
+            }
+            QueueMsg::PlayPlaylist(entries) => {
+                if let Some(first) = entries.first() {
+                    library_link.send_message(LibraryMsg::PlayArticle(first.clone()));
+                }
+                self.playing_playlist = entries;
+                return false;
+            }
+            QueueMsg::TogglePinned(entries) => {
+                let mut policy = EvictionPolicy::load();
+                let all_pinned = entries.iter().all(|e| policy.pinned.contains(&e.id));
+                for entry in entries {
+                    if all_pinned || !policy.pinned.contains(&entry.id) {
+                        policy.toggle_pinned(&entry.id, &entry.title);
+                    }
+                }
+                policy.save();
+                self.pinned = policy.pinned;
+            }
+            QueueMsg::RefreshPinned => {
+                self.pinned = EvictionPolicy::load().pinned;
+            }
+            QueueMsg::NowPlaying(id) => {
+                self.playing = id;
+                return false;
+            }
+            QueueMsg::ToggleMenu(id) => {
+                self.menu_open = match self.menu_open.take() {
+                    Some(open) if open == id => None,
+                    _ => Some(id),
+                };
+            }
+            QueueMsg::Place(from, placement) => {
+                self.menu_open = None;
+                self.place(ctx, from, placement);
+            }
+            QueueMsg::PlaceArticle(id, placement) => {
+                let Some(idx) = self.entries.iter().position(|e| e.id == id) else {
+                    return false;
+                };
+                let from = self.item_start(idx);
+                self.place(ctx, from, placement);
+            }
+            QueueMsg::AddAt(entry, placement) => {
+                let id = entry.id.clone();
+                self.insert(entry);
+                let idx = self.entries.iter().position(|e| e.id == id).unwrap();
+                let from = self.item_start(idx);
+                self.save();
+                self.place(ctx, from, placement);
+                ctx.link().send_message(QueueMsg::Evict);
+            }
         }
 
         true
@@ -189,7 +767,10 @@ impl Component for Queue {
                 .collect()
         });
 
-        Self::default()
+        Queue {
+            pinned: EvictionPolicy::load().pinned,
+            ..Default::default()
+        }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
@@ -205,16 +786,58 @@ impl Component for Queue {
                 "}</p>
             }
         } else {
-            self.entries
-                .iter()
-                .enumerate()
-                .map(|(i, entry)| render_queue_item(entry, i, player_link, queue_link))
-                .collect::<Html>()
+            // Consecutive parts of the same group are shown as a single item
+            let mut items = Vec::new();
+            let mut i = 0;
+            while i < self.entries.len() {
+                let group_len = self.item_len(i);
+                if group_len > 1 {
+                    let parts = &self.entries[i..i + group_len];
+                    let part_pcts: Vec<Option<u32>> = parts
+                        .iter()
+                        .map(|part| self.listened_pcts.get(&part.id).copied())
+                        .collect();
+                    let listened_pct = history_view::group_listened_pct(&part_pcts);
+                    let pinned = parts.iter().all(|part| self.pinned.contains(&part.id));
+                    items.push(render_queue_group(
+                        parts,
+                        i,
+                        listened_pct,
+                        pinned,
+                        self.menu_open.as_ref() == Some(&parts[0].id),
+                        player_link,
+                        queue_link,
+                    ));
+                } else {
+                    let entry = &self.entries[i];
+                    let listened_pct = self.listened_pcts.get(&entry.id).copied();
+                    items.push(render_queue_item(
+                        entry,
+                        i,
+                        listened_pct,
+                        self.pinned.contains(&entry.id),
+                        self.menu_open.as_ref() == Some(&entry.id),
+                        player_link,
+                        queue_link,
+                    ));
+                }
+                i += group_len;
+            }
+            items.into_iter().collect::<Html>()
         };
 
+        let remove_finished = ctx.link().callback(|_| QueueMsg::RemoveFinished);
         html! {
             <section title="Queue">
                 <h2>{ "Queue" }</h2>
+                <p>
+                    <button onclick={remove_finished} disabled={ self.entries.is_empty() }>
+                        { "Remove finished" }
+                    </button>
+                    <span class="articleMetadata">
+                        { " Drag articles to reorder them, or use their ⋯ menu" }
+                    </span>
+                </p>
                 <table role="list" aria-label="Queue entries">
                     { rendered_list }
                 </table>
@@ -223,9 +846,154 @@ impl Component for Queue {
     }
 }
 
+/// Returns the callbacks that let the item starting at the given index be dragged, and other items
+/// be dropped on it: `ondragstart`, `ondragover`, and `ondrop`
+fn drag_callbacks(
+    pos: usize,
+    queue_scope: &Scope<Queue>,
+) -> (
+    Callback<DragEvent>,
+    Callback<DragEvent>,
+    Callback<DragEvent>,
+) {
+    let ondragstart = queue_scope.callback(move |e: DragEvent| {
+        // Firefox only drags things that carry some data
+        if let Some(data) = e.data_transfer() {
+            data.set_effect_allowed("move");
+            let _ = data.set_data("text/plain", &pos.to_string());
+        }
+        QueueMsg::DragStart(pos)
+    });
+    // Items are only drop targets if dragging over them is cancelled
+    let ondragover = Callback::from(|e: DragEvent| e.prevent_default());
+    let ondrop = queue_scope.callback(move |e: DragEvent| {
+        e.prevent_default();
+        QueueMsg::Drop(pos)
+    });
+
+    (ondragstart, ondragover, ondrop)
+}
+
+/// Renders the button that pins or unpins the given entries' articles, which go by the given title
+fn render_pin_button(
+    entries: &[QueueEntry],
+    title: &str,
+    pinned: bool,
+    queue_scope: &Scope<Queue>,
+) -> Html {
+    let entries = entries.to_vec();
+    let onclick = queue_scope.callback(move |_| QueueMsg::TogglePinned(entries.clone()));
+    let pin_title_text = if pinned {
+        format!("Unpin: {title}")
+    } else {
+        format!("Pin: {title}")
+    };
+    html! {
+        <button
+            class="pinToggle"
+            aria-pressed={ pinned.to_string() }
+            aria-label={ pin_title_text.clone() }
+            title={ pin_title_text }
+            {onclick}
+        >
+            { "📌" }
+        </button>
+    }
+}
+
+/// Renders the button that opens the actions menu of the item starting at the given index, and the
+/// menu itself if it's open. The item goes by the given title, and starts with the given article.
+fn render_actions_menu(
+    pos: usize,
+    first_id: &ArticleId,
+    title: &str,
+    menu_open: bool,
+    queue_scope: &Scope<Queue>,
+) -> Html {
+    let first_id = first_id.clone();
+    let toggle_callback = queue_scope.callback(move |_| QueueMsg::ToggleMenu(first_id.clone()));
+    let place_callback = |placement| queue_scope.callback(move |_| QueueMsg::Place(pos, placement));
+    let remove_callback = queue_scope.callback(move |_| QueueMsg::Remove(pos));
+    render_placement_menu(
+        title,
+        menu_open,
+        toggle_callback,
+        place_callback,
+        Some(remove_callback),
+    )
+}
+
+/// Renders the button that opens a menu of where to put the item with the given title in the
+/// queue, and the menu itself if it's open. If the item is queued, the menu can also take it out of
+/// the queue. The library's rows have this menu too.
+pub(crate) fn render_placement_menu(
+    title: &str,
+    menu_open: bool,
+    toggle_callback: Callback<MouseEvent>,
+    place_callback: impl Fn(Placement) -> Callback<MouseEvent>,
+    remove_callback: Option<Callback<MouseEvent>>,
+) -> Html {
+    let menu_title_text = format!("Actions: {title}");
+
+    let menu = if menu_open {
+        let remove_button = match remove_callback {
+            Some(onclick) => html! {
+                <button role="menuitem" {onclick}>{ "Remove from queue" }</button>
+            },
+            None => Html::default(),
+        };
+        html! {
+            <span class="queueMenu" role="menu" aria-label={ menu_title_text.clone() }>
+                <button role="menuitem" onclick={place_callback(Placement::Now)}>
+                    { "Play now" }
+                </button>
+                <button role="menuitem" onclick={place_callback(Placement::Next)}>
+                    { "Play next" }
+                </button>
+                <button role="menuitem" onclick={place_callback(Placement::End)}>
+                    { "Add to end" }
+                </button>
+                { remove_button }
+            </span>
+        }
+    } else {
+        html! {}
+    };
+
+    html! {
+        <>
+            <button
+                aria-haspopup="menu"
+                aria-expanded={ menu_open.to_string() }
+                aria-label={ menu_title_text.clone() }
+                title={ menu_title_text }
+                onclick={toggle_callback}
+            >
+                { "⋯" }
+            </button>
+            { menu }
+        </>
+    }
+}
+
+/// Returns the callback that opens the actions menu of the item that starts with the given
+/// article when it's right-clicked, instead of the browser's menu
+fn context_menu_callback(first_id: &ArticleId, queue_scope: &Scope<Queue>) -> Callback<MouseEvent> {
+    let first_id = first_id.clone();
+    queue_scope.callback(move |e: MouseEvent| {
+        e.prevent_default();
+        QueueMsg::ToggleMenu(first_id.clone())
+    })
+}
+
+/// Renders a single article in the queue, with how much of it has been listened to, and whether
+/// it's pinned
 fn render_queue_item(
     entry: &QueueEntry,
     pos: usize,
+    listened_pct: Option<u32>,
+    pinned: bool,
+    menu_open: bool,
     player_link: &WeakComponentLink<Player>,
     queue_link: &WeakComponentLink<Queue>,
 ) -> Html {
@@ -238,13 +1006,31 @@ fn render_queue_item(
         player_scope.send_message(PlayerMsg::Play(entry_copy.clone()));
     });
     let remove_callback = queue_scope.callback(move |_| QueueMsg::Delete(pos));
+    let (ondragstart, ondragover, ondrop) = drag_callbacks(pos, &queue_scope);
+    let pin_button = render_pin_button(
+        std::slice::from_ref(entry),
+        &entry.title,
+        pinned,
+        &queue_scope,
+    );
+    let oncontextmenu = context_menu_callback(&entry.id, &queue_scope);
+    let actions_menu = render_actions_menu(pos, &entry.id, &entry.title, menu_open, &queue_scope);
 
     // The ARIA text for the buttons
     let play_title_text = format!("Play: {}", entry.title);
     let delete_title_text = format!("Delete from queue: {}", entry.title);
 
     html! {
-        <tr role="listitem" aria-label={ entry.title.clone() } class="queueControl">
+        <tr
+            role="listitem"
+            aria-label={ entry.title.clone() }
+            class="queueControl"
+            draggable="true"
+            {ondragstart}
+            {ondragover}
+            {ondrop}
+            {oncontextmenu}
+        >
             <td>
                 <button
                     class="queuePlay"
@@ -256,9 +1042,12 @@ fn render_queue_item(
                 </button>
             </td>
             <td class="queueArticleTitle">
-                {&entry.title}
+                <bdi>{ &entry.title }</bdi>
+                // Articles are only queued once they're saved on this device
+                { history_view::render_progress_ring(listened_pct, true) }
             </td>
             <td>
+                { pin_button }
                 <button
                     aria-label={ delete_title_text.clone() }
                     title={ delete_title_text }
@@ -266,7 +1055,145 @@ fn render_queue_item(
                 >
                     { "🗑" }
                 </button>
+                { actions_menu }
             </td>
         </tr>
     }
 }
+
+/// Renders consecutive parts of a group as a single item. Playing the group starts at its first
+/// queued part, and the parts are deleted together. How far the group has been listened to is the
+/// average of its queued parts. It's pinned if all its queued parts are.
+fn render_queue_group(
+    parts: &[QueueEntry],
+    pos: usize,
+    listened_pct: Option<u32>,
+    pinned: bool,
+    menu_open: bool,
+    player_link: &WeakComponentLink<Player>,
+    queue_link: &WeakComponentLink<Queue>,
+) -> Html {
+    let player_scope = player_link.borrow().clone().unwrap();
+    let queue_scope = queue_link.borrow().clone().unwrap();
+    let group = parts[0].group.clone().unwrap();
+    let first_part = parts[0].clone();
+
+    // Define the callbacks for clicking the play button and delete button
+    let play_callback = Callback::from(move |_| {
+        player_scope.send_message(PlayerMsg::Play(first_part.clone()));
+    });
+    let group_id = group.id.clone();
+    let remove_callback = queue_scope.callback(move |_| QueueMsg::DeleteGroup(group_id.clone()));
+    let (ondragstart, ondragover, ondrop) = drag_callbacks(pos, &queue_scope);
+    let pin_button = render_pin_button(parts, &group.title, pinned, &queue_scope);
+    let oncontextmenu = context_menu_callback(&parts[0].id, &queue_scope);
+    let actions_menu =
+        render_actions_menu(pos, &parts[0].id, &group.title, menu_open, &queue_scope);
+
+    // The ARIA text for the buttons
+    let play_title_text = format!("Play: {}", group.title);
+    let delete_title_text = format!("Delete from queue: {}", group.title);
+
+    html! {
+        <tr
+            role="listitem"
+            aria-label={ group.title.clone() }
+            class="queueControl"
+            draggable="true"
+            {ondragstart}
+            {ondragover}
+            {ondrop}
+            {oncontextmenu}
+        >
+            <td>
+                <button
+                    class="queuePlay"
+                    aria-label={ play_title_text.clone() }
+                    title={ play_title_text }
+                    onclick={play_callback}
+                >
+                    { "▶️" }
+                </button>
+            </td>
+            <td class="queueArticleTitle">
+                <bdi>{ &group.title }</bdi>
+                <span class="articleMetadata">
+                    { format!(" ({} of {} parts)", parts.len(), group.parts) }
+                </span>
+                { history_view::render_progress_ring(listened_pct, true) }
+            </td>
+            <td>
+                { pin_button }
+                <button
+                    aria-label={ delete_title_text.clone() }
+                    title={ delete_title_text }
+                    onclick={remove_callback}
+                >
+                    { "🗑" }
+                </button>
+                { actions_menu }
+            </td>
+        </tr>
+    }
+}
+
+#[test]
+fn placing_items() {
+    let article = |id: &str| QueueEntry {
+        id: ArticleId(id.to_string()),
+        title: id.to_string(),
+        group: None,
+        publication: None,
+        author: None,
+        image: None,
+        reading_profile: None,
+    };
+    let part = |id: &str, part| QueueEntry {
+        group: Some(ArticleGroup {
+            id: "g".to_string(),
+            title: "g".to_string(),
+            part,
+            parts: 2,
+        }),
+        ..article(id)
+    };
+    let ids = |queue: &Queue| {
+        queue
+            .entries
+            .iter()
+            .map(|e| e.id.0.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let mut queue = Queue {
+        entries: vec![
+            article("a"),
+            part("g1", 1),
+            part("g2", 2),
+            article("b"),
+            article("c"),
+        ],
+        ..Default::default()
+    };
+
+    // With nothing playing, playing now or next puts the item first. Groups move together.
+    assert_eq!(queue.place_item(1, Placement::Next), 0);
+    assert_eq!(ids(&queue), "g1 g2 a b c");
+    assert_eq!(queue.place_item(0, Placement::End), 3);
+    assert_eq!(ids(&queue), "a b c g1 g2");
+
+    // Otherwise they go right after the item that's playing, even when it's a group being played
+    // from its second part
+    queue.playing = Some(ArticleId("g2".to_string()));
+    assert_eq!(queue.place_item(0, Placement::Now), 4);
+    assert_eq!(ids(&queue), "b c g1 g2 a");
+    assert_eq!(queue.place_item(4, Placement::Next), 4);
+    assert_eq!(queue.place_item(1, Placement::Next), 3);
+    assert_eq!(ids(&queue), "b g1 g2 c a");
+
+    // The item that's playing stays where it is, unless it's sent to the end
+    assert_eq!(queue.place_item(1, Placement::Now), 1);
+    assert_eq!(ids(&queue), "b g1 g2 c a");
+    assert_eq!(queue.place_item(1, Placement::End), 3);
+    assert_eq!(ids(&queue), "b c a g1 g2");
+}
@@ -0,0 +1,701 @@
+use crate::{
+    clock,
+    library_view::{Library, LibraryMsg},
+    queue_view::ArticleId,
+    utils, WeakComponentLink,
+};
+use common::{
+    PushSubscription, QuietHours, QuietMode, Reminder, ReminderDismissal, ReminderSubmission,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use gloo_timers::callback::Interval;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    HtmlInputElement, Notification, NotificationPermission, PushSubscriptionOptionsInit,
+};
+use yew::prelude::*;
+
+/// How often the reminders are refreshed, in milliseconds. This is how late a reminder can show up
+/// while the app is open.
+const POLL_INTERVAL_MS: u32 = 60_000;
+
+/// The hour a reminder for a day goes off at if no part of the day is given
+const DEFAULT_HOUR: u32 = 9;
+
+/// How long quiet mode can be turned on for, in seconds, and how that's described
+const QUIET_PERIODS: [(u64, &str); 4] = [
+    (60 * 60, "for an hour"),
+    (4 * 60 * 60, "for 4 hours"),
+    (8 * 60 * 60, "for 8 hours"),
+    (24 * 60 * 60, "for a day"),
+];
+
+/// The quiet hours offered until the listener sets their own: 22:00 to 07:00
+const DEFAULT_QUIET_HOURS: (u16, u16) = (22 * 60, 7 * 60);
+
+const QUIET_START_INPUT_ID: &str = "quiet-hours-start";
+const QUIET_END_INPUT_ID: &str = "quiet-hours-end";
+
+/// The days of the week, in the order `Date.getDay()` counts them
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// Returns the hour the given part of the day starts at
+fn part_of_day_hour(word: &str) -> Option<u32> {
+    match word {
+        "morning" => Some(9),
+        "afternoon" => Some(14),
+        "evening" => Some(18),
+        "night" | "tonight" => Some(20),
+        _ => None,
+    }
+}
+
+/// Parses when the listener wants to be reminded, in local time. This takes things like "in 2
+/// hours", "tonight", "tomorrow morning", "saturday", or "saturday evening", and falls back to
+/// whatever `Date.parse()` makes of it. Returns the time in seconds since the Unix epoch.
+fn parse_when(input: &str) -> Option<u64> {
+    let input = input.trim().to_lowercase();
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let now = js_sys::Date::new_0();
+
+    // Relative times, e.g., "in 20 minutes"
+    if let ["in", n, unit] = words.as_slice() {
+        let n: f64 = n.parse().ok()?;
+        let unit_secs = match unit.trim_end_matches('s') {
+            "minute" | "min" => 60.0,
+            "hour" => 60.0 * 60.0,
+            "day" => 24.0 * 60.0 * 60.0,
+            "week" => 7.0 * 24.0 * 60.0 * 60.0,
+            _ => return None,
+        };
+        return Some((now.get_time() / 1000.0 + n * unit_secs) as u64);
+    }
+
+    // A day, a part of the day, or both, e.g., "saturday morning". A time that's already passed
+    // means the next time it comes around.
+    let (day, part) = match words.as_slice() {
+        [day, part] => (Some(*day), part_of_day_hour(part)),
+        [word] => match part_of_day_hour(word) {
+            Some(hour) => (None, Some(hour)),
+            None => (Some(*word), None),
+        },
+        _ => (None, None),
+    };
+    let today = now.get_day() as usize;
+    let (days_ahead, days_to_retry) = match day {
+        Some("today") => (0, 1),
+        Some("tomorrow") => (1, 1),
+        Some(day) => match WEEKDAYS.iter().position(|d| *d == day) {
+            Some(weekday) => ((weekday + 7 - today) % 7, 7),
+            None => return parse_date(&input),
+        },
+        None if part.is_some() => (0, 1),
+        None => return parse_date(&input),
+    };
+
+    let date = js_sys::Date::new(&JsValue::from_f64(now.get_time()));
+    date.set_date(date.get_date() + days_ahead as u32);
+    date.set_hours(part.unwrap_or(DEFAULT_HOUR));
+    date.set_minutes(0);
+    date.set_seconds(0);
+    date.set_milliseconds(0);
+    if date.get_time() <= now.get_time() {
+        date.set_date(date.get_date() + days_to_retry);
+    }
+    Some((date.get_time() / 1000.0) as u64)
+}
+
+/// Parses the given date with `Date.parse()`, and returns it in seconds since the Unix epoch
+fn parse_date(input: &str) -> Option<u64> {
+    let millis = js_sys::Date::parse(input);
+    millis.is_finite().then(|| (millis / 1000.0) as u64)
+}
+
+/// Formats the given time in seconds since the Unix epoch as a local date and time
+fn format_time(t: u64) -> String {
+    let lang = gloo_utils::window()
+        .navigator()
+        .language()
+        .unwrap_or("en-US".to_string());
+    let js_date = js_sys::Date::new(&JsValue::from_f64(t as f64 * 1000.0));
+    js_date.to_locale_string(&lang, &JsValue::UNDEFINED).into()
+}
+
+/// Returns the current time in seconds since the Unix epoch, by the app's clock
+fn now_secs() -> u64 {
+    (clock::now() / 1000.0) as u64
+}
+
+/// Fetches the reminders that haven't expired
+async fn fetch_reminders() -> Result<Vec<Reminder>, AnyError> {
+    let resp = utils::get("/api/list-reminders")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching reminders"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching reminders {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing reminders JSON"))
+}
+
+/// Fetches whether quiet mode is on, and until when
+async fn fetch_quiet_mode() -> Result<QuietMode, AnyError> {
+    let resp = utils::get("/api/quiet-mode")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching quiet mode"))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing quiet mode JSON"))
+}
+
+/// Returns this device's time zone, as minutes ahead of UTC
+fn utc_offset() -> i32 {
+    -(js_sys::Date::new_0().get_timezone_offset() as i32)
+}
+
+/// Returns whether it's the listener's quiet hours now, in this device's time zone. Downloads that
+/// would otherwise start on their own wait until they're over. If the server can't be reached, it
+/// isn't.
+pub(crate) async fn in_quiet_hours() -> bool {
+    match fetch_quiet_mode().await {
+        Ok(QuietMode {
+            hours: Some(hours), ..
+        }) => QuietHours {
+            utc_offset: utc_offset(),
+            ..hours
+        }
+        .contains(now_secs()),
+        _ => false,
+    }
+}
+
+/// Formats the given number of minutes after midnight as HH:MM, like an `<input type="time">`
+fn format_minutes(minutes: u16) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Parses the HH:MM of an `<input type="time">` as a number of minutes after midnight
+fn parse_minutes(value: &str) -> Option<u16> {
+    let (hours, minutes) = value.split_once(':')?;
+    let (hours, minutes): (u16, u16) = (hours.parse().ok()?, minutes.get(..2)?.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Returns the number of minutes after midnight in the time input with the given ID
+fn input_minutes(id: &str) -> Option<u16> {
+    gloo_utils::document()
+        .get_element_by_id(id)
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .and_then(|input| parse_minutes(&input.value()))
+}
+
+/// POSTs the given JSON to the given endpoint
+async fn post_json(endpoint: &str, body: &impl serde::Serialize) -> Result<(), AnyError> {
+    let resp = utils::post(endpoint)
+        .json(body)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context(format!("Error POSTing to {endpoint}")))?;
+
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+/// Returns whether this browser can receive push notifications
+fn push_supported() -> bool {
+    let window = gloo_utils::window();
+    js_sys::Reflect::has(&window, &JsValue::from_str("PushManager")).unwrap_or(false)
+        && !window.navigator().service_worker().is_undefined()
+}
+
+/// Subscribes this device to push notifications of reminders
+async fn enable_push() -> Result<(), AnyError> {
+    let permission = Notification::request_permission()
+        .map_err(|e| utils::wrap_jserror("couldn't ask for notification permission", e))?;
+    JsFuture::from(permission)
+        .await
+        .map_err(|e| utils::wrap_jserror("couldn't ask for notification permission", e))?;
+    if Notification::permission() != NotificationPermission::Granted {
+        bail!("Notifications aren't allowed for this site");
+    }
+
+    // Subscribe with the server's key, and tell the server about the subscription
+    let resp = utils::get("/api/push-key")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching push key"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    let key = resp.text().await?;
+
+    let sw_container = gloo_utils::window().navigator().service_worker();
+    let ready = sw_container
+        .ready()
+        .map_err(|e| utils::wrap_jserror("couldn't get service worker", e))?;
+    let registration: web_sys::ServiceWorkerRegistration = JsFuture::from(ready)
+        .await
+        .map_err(|e| utils::wrap_jserror("couldn't get service worker", e))?
+        .unchecked_into();
+    let mut options = PushSubscriptionOptionsInit::new();
+    options
+        .user_visible_only(true)
+        .application_server_key(Some(&JsValue::from_str(&key)));
+    let subscribing = registration
+        .push_manager()
+        .and_then(|manager| manager.subscribe_with_options(&options))
+        .map_err(|e| utils::wrap_jserror("couldn't subscribe to push notifications", e))?;
+    let sub: web_sys::PushSubscription = JsFuture::from(subscribing)
+        .await
+        .map_err(|e| utils::wrap_jserror("couldn't subscribe to push notifications", e))?
+        .unchecked_into();
+    let sub_json = sub
+        .to_json()
+        .map_err(|e| utils::wrap_jserror("couldn't read push subscription", e))?;
+    let sub: PushSubscription = serde_wasm_bindgen::from_value(sub_json.into())
+        .map_err(|e| anyhow!("couldn't read push subscription: {e}"))?;
+
+    post_json("/api/subscribe-push", &sub).await
+}
+
+/// "Listen later" reminders. Due ones are shown at the top of the home view, and the rest are
+/// listed underneath.
+#[derive(Default)]
+pub(crate) struct Reminders {
+    reminders: Vec<Reminder>,
+    /// Whether push notifications are held back, and until when
+    quiet_mode: QuietMode,
+    /// What the last action did, if anything
+    status: Option<String>,
+    err: Option<AnyError>,
+    /// Refreshes the reminders, so that they show up once they're due
+    _poller: Option<Interval>,
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    pub library_link: WeakComponentLink<Library>,
+    pub reminders_link: WeakComponentLink<Reminders>,
+}
+
+pub(crate) enum RemindersMsg {
+    /// Fetches the reminders
+    FetchReminders,
+    /// Sets the reminders
+    SetReminders(Vec<Reminder>),
+    /// Asks the listener when to be reminded of the given article, and sets the reminder
+    RemindLater { id: ArticleId, title: String },
+    /// Adds the article of the given reminder to the queue, and dismisses the reminder
+    Listen(Reminder),
+    /// Dismisses the reminder of the given article
+    Dismiss(String),
+    /// Subscribes this device to push notifications of reminders
+    EnablePush,
+    /// Turns quiet mode on for the given number of seconds, or off
+    SetQuietMode(Option<u64>),
+    /// Shows whether quiet mode is on, and the quiet hours
+    ShowQuietMode(QuietMode),
+    /// Sets the quiet hours to the ones in the form, in this device's time zone
+    SaveQuietHours,
+    /// Turns the quiet hours off
+    ClearQuietHours,
+    /// Sets the status display to the given message, and refreshes the reminders
+    SetStatus(String),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+impl Component for Reminders {
+    type Message = RemindersMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        // Let the library ask for reminders
+        ctx.props()
+            .reminders_link
+            .borrow_mut()
+            .replace(ctx.link().clone());
+
+        ctx.link().send_message(RemindersMsg::FetchReminders);
+        let link = ctx.link().clone();
+        let poller = Interval::new(POLL_INTERVAL_MS, move || {
+            link.send_message(RemindersMsg::FetchReminders)
+        });
+
+        Reminders {
+            _poller: Some(poller),
+            ..Reminders::default()
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            RemindersMsg::FetchReminders => {
+                ctx.link().send_future(async move {
+                    match fetch_reminders().await {
+                        Ok(reminders) => RemindersMsg::SetReminders(reminders),
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+                ctx.link().send_future(async move {
+                    match fetch_quiet_mode().await {
+                        Ok(quiet_mode) => RemindersMsg::ShowQuietMode(quiet_mode),
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            RemindersMsg::SetReminders(reminders) => {
+                self.reminders = reminders;
+            }
+
+            RemindersMsg::RemindLater { id, title } => {
+                let input = gloo_utils::window()
+                    .prompt_with_message(
+                        "When should you be reminded? E.g., \"tonight\", \"saturday morning\", or \
+                        \"in 2 hours\"",
+                    )
+                    .ok()
+                    .flatten()
+                    .filter(|input| !input.trim().is_empty());
+                let input = match input {
+                    Some(i) => i,
+                    None => return false,
+                };
+                let remind_at = match parse_when(&input) {
+                    Some(t) => t,
+                    None => {
+                        self.err = Some(anyhow!("Couldn't tell when \"{input}\" is"));
+                        return true;
+                    }
+                };
+
+                self.err = None;
+                let submission = ReminderSubmission {
+                    article_id: id.0,
+                    title: title.clone(),
+                    remind_at,
+                };
+                ctx.link().send_future(async move {
+                    match post_json("/api/set-reminder", &submission).await {
+                        Ok(()) => RemindersMsg::SetStatus(format!(
+                            "You'll be reminded of \"{title}\" on {}.",
+                            format_time(remind_at)
+                        )),
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+            }
+
+            RemindersMsg::Listen(reminder) => {
+                ctx.props()
+                    .library_link
+                    .borrow()
+                    .clone()
+                    .unwrap()
+                    .send_message(LibraryMsg::FetchArticle {
+                        id: ArticleId(reminder.article_id.clone()),
+                        title: reminder.title,
+                        group: None,
+                    });
+                ctx.link()
+                    .send_message(RemindersMsg::Dismiss(reminder.article_id));
+            }
+
+            RemindersMsg::Dismiss(article_id) => {
+                // Hide it right away, rather than wait on the server
+                self.reminders.retain(|r| r.article_id != article_id);
+                let dismissal = ReminderDismissal { article_id };
+                ctx.link().send_future(async move {
+                    match post_json("/api/dismiss-reminder", &dismissal).await {
+                        Ok(()) => RemindersMsg::FetchReminders,
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+            }
+
+            RemindersMsg::EnablePush => {
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match enable_push().await {
+                        Ok(()) => RemindersMsg::SetStatus(
+                            "This device will be notified when reminders are due.".to_string(),
+                        ),
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+            }
+
+            RemindersMsg::SetQuietMode(secs) => {
+                self.err = None;
+                let quiet_mode = QuietMode {
+                    until: secs.map(|secs| now_secs() + secs),
+                    hours: self.quiet_mode.hours,
+                };
+                ctx.link().send_future(async move {
+                    match post_json("/api/set-quiet-mode", &quiet_mode).await {
+                        Ok(()) => RemindersMsg::ShowQuietMode(quiet_mode),
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            RemindersMsg::ShowQuietMode(quiet_mode) => {
+                // The server goes by the time zone the hours were set in. Keep it up to date as
+                // this device moves through time zones and daylight saving time.
+                if let Some(hours) = quiet_mode.hours.filter(|h| h.utc_offset != utc_offset()) {
+                    let hours = QuietHours {
+                        utc_offset: utc_offset(),
+                        ..hours
+                    };
+                    ctx.link().send_future(async move {
+                        match post_json("/api/set-quiet-hours", &Some(hours)).await {
+                            Ok(()) => RemindersMsg::FetchReminders,
+                            Err(e) => RemindersMsg::SetError(e),
+                        }
+                    });
+                }
+                self.quiet_mode = quiet_mode;
+            }
+
+            RemindersMsg::SaveQuietHours => {
+                self.err = None;
+                let (Some(start), Some(end)) = (
+                    input_minutes(QUIET_START_INPUT_ID),
+                    input_minutes(QUIET_END_INPUT_ID),
+                ) else {
+                    self.err = Some(anyhow!("Pick when quiet hours start and end"));
+                    return true;
+                };
+                let hours = QuietHours {
+                    start,
+                    end,
+                    utc_offset: utc_offset(),
+                };
+                ctx.link().send_future(async move {
+                    match post_json("/api/set-quiet-hours", &Some(hours)).await {
+                        Ok(()) => RemindersMsg::SetStatus(format!(
+                            "Quiet hours are {} to {}.",
+                            format_minutes(start),
+                            format_minutes(end)
+                        )),
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            RemindersMsg::ClearQuietHours => {
+                self.err = None;
+                ctx.link().send_future(async move {
+                    match post_json("/api/set-quiet-hours", &None::<QuietHours>).await {
+                        Ok(()) => RemindersMsg::SetStatus("Quiet hours are off.".to_string()),
+                        Err(e) => RemindersMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            RemindersMsg::SetStatus(status) => {
+                self.status = Some(status);
+                ctx.link().send_message(RemindersMsg::FetchReminders);
+            }
+
+            RemindersMsg::SetError(e) => {
+                self.status = None;
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let now = now_secs();
+        let (due, upcoming): (Vec<&Reminder>, Vec<&Reminder>) =
+            self.reminders.iter().partition(|r| r.remind_at <= now);
+
+        let due_section = if due.is_empty() {
+            Html::default()
+        } else {
+            let due_list = due
+                .iter()
+                .map(|reminder| {
+                    let listen_reminder = (*reminder).clone();
+                    let listen = ctx
+                        .link()
+                        .callback(move |_| RemindersMsg::Listen(listen_reminder.clone()));
+                    let article_id = reminder.article_id.clone();
+                    let dismiss = ctx
+                        .link()
+                        .callback(move |_| RemindersMsg::Dismiss(article_id.clone()));
+                    html! {
+                        <li>
+                            <strong>{ reminder.title.clone() }</strong>
+                            { " " }<button onclick={listen}>{ "Add to queue" }</button>
+                            <button onclick={dismiss}>{ "Dismiss" }</button>
+                        </li>
+                    }
+                })
+                .collect::<Html>();
+            html! {
+                <section aria-label="Due reminders">
+                    <h2>{ "Time to listen" }</h2>
+                    <ul>{ due_list }</ul>
+                </section>
+            }
+        };
+
+        let upcoming_section = if upcoming.is_empty() {
+            Html::default()
+        } else {
+            let upcoming_list = upcoming
+                .iter()
+                .map(|reminder| {
+                    let article_id = reminder.article_id.clone();
+                    let cancel = ctx
+                        .link()
+                        .callback(move |_| RemindersMsg::Dismiss(article_id.clone()));
+                    html! {
+                        <li>
+                            { format!("{} ({})", reminder.title, format_time(reminder.remind_at)) }
+                            { " " }<button onclick={cancel}>{ "Cancel" }</button>
+                        </li>
+                    }
+                })
+                .collect::<Html>();
+            html! {
+                <details>
+                    <summary>{ format!("Upcoming reminders ({})", upcoming.len()) }</summary>
+                    <ul>{ upcoming_list }</ul>
+                </details>
+            }
+        };
+
+        // Push notifications are only offered once there's something to be notified of
+        let push_button = if push_supported() && !self.reminders.is_empty() {
+            let enable_push = ctx.link().callback(|_| RemindersMsg::EnablePush);
+            html! {
+                <button onclick={enable_push}>{ "Notify this device when reminders are due" }</button>
+            }
+        } else {
+            Html::default()
+        };
+
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or("".to_string());
+
+        html! {
+            <>
+                { due_section }
+                { upcoming_section }
+                { push_button }
+                { self.render_quiet_mode(ctx) }
+                { self.render_quiet_hours(ctx) }
+                <p aria-live="polite">{ self.status.clone().unwrap_or_default() }</p>
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </>
+        }
+    }
+}
+
+impl Reminders {
+    /// Renders the form for the quiet hours
+    fn render_quiet_hours(&self, ctx: &Context<Self>) -> Html {
+        let hours = self.quiet_mode.hours;
+        let (start, end) = hours.map_or(DEFAULT_QUIET_HOURS, |h| (h.start, h.end));
+        let save = ctx.link().callback(|_| RemindersMsg::SaveQuietHours);
+        let clear = ctx.link().callback(|_| RemindersMsg::ClearQuietHours);
+        let summary = match hours {
+            Some(_) => format!(
+                "Quiet hours: {} to {}",
+                format_minutes(start),
+                format_minutes(end)
+            ),
+            None => "Quiet hours: off".to_string(),
+        };
+        html! {
+            <details class="quietHours">
+                <summary>{ summary }</summary>
+                <p class="articleMetadata">{
+                    "Every day in quiet hours, notifications of reminders wait until they're \
+                    over, and so do new articles from feeds and downloads that pick back up on \
+                    their own."
+                }</p>
+                <div class="field">
+                    <label for={QUIET_START_INPUT_ID}>{ "From:" }</label>
+                    <input type="time" id={QUIET_START_INPUT_ID} value={format_minutes(start)} />
+                    <label for={QUIET_END_INPUT_ID}>{ " to:" }</label>
+                    <input type="time" id={QUIET_END_INPUT_ID} value={format_minutes(end)} />
+                </div>
+                <button onclick={save}>{ "Save quiet hours" }</button>
+                if hours.is_some() {
+                    <button onclick={clear}>{ "Turn off quiet hours" }</button>
+                }
+            </details>
+        }
+    }
+
+    /// Renders the buttons that turn quiet mode on for a while, or, if it's on, until when it is
+    /// and the button that turns it off. This is only offered once there are reminders to be
+    /// notified of.
+    fn render_quiet_mode(&self, ctx: &Context<Self>) -> Html {
+        if let Some(until) = self.quiet_mode.until.filter(|&until| until > now_secs()) {
+            let turn_off = ctx.link().callback(|_| RemindersMsg::SetQuietMode(None));
+            return html! {
+                <p>
+                    { format!("Quiet mode: notifications are held back until {}. ", format_time(until)) }
+                    <button onclick={turn_off}>{ "Turn off quiet mode" }</button>
+                </p>
+            };
+        }
+        if !push_supported() || self.reminders.is_empty() {
+            return Html::default();
+        }
+
+        let buttons = QUIET_PERIODS
+            .iter()
+            .map(|&(secs, description)| {
+                let onclick = ctx
+                    .link()
+                    .callback(move |_| RemindersMsg::SetQuietMode(Some(secs)));
+                html! { <button {onclick}>{ description }</button> }
+            })
+            .collect::<Html>();
+        html! {
+            <p>
+                { "Hold back notifications " }
+                { buttons }
+            </p>
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! The servers the app talks to. By default, that's the server it was loaded from. The installed
+//! app can be pointed at others too, e.g., a home server and a work server. Every API request goes
+//! to the active server, and each server gets its own local caches, so switching doesn't mix up
+//! their libraries.
+//!
+//! The server the app was loaded from might serve it under a subpath, e.g., `https://host/rtms/`.
+//! It says so in the `<base>` tag of the page, so its paths are put under that.
+//!
+//! Other servers can't be signed in to with cookies, so signing in to one saves its session token,
+//! and requests to it carry the token instead. Those servers have to let this app's origin use
+//! their API, in their `[[cors]]` config, and the server the app was loaded from has to list them
+//! in its `app_servers`, or the app isn't allowed to reach them.
+
+use anyhow::{bail, Error as AnyError};
+use gloo_utils::window;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+/// The localStorage key of the list of other servers
+const SERVERS_KEY: &str = "servers";
+
+/// The localStorage key of the base URL of the active server. If it's missing, the active server
+/// is the one the app was loaded from.
+const ACTIVE_SERVER_KEY: &str = "active-server";
+
+/// A server other than the one the app was loaded from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Server {
+    /// What the server is called in the switcher, e.g., "Home"
+    pub(crate) name: String,
+    /// The server's URL, e.g., `https://shoe.example.com`, without a trailing slash
+    pub(crate) base_url: String,
+    /// The session token of the account signed in to on the server, if any
+    #[serde(default)]
+    pub(crate) token: Option<String>,
+}
+
+/// Returns the given server URL as a base URL, i.e., the origin and path without a trailing slash.
+/// Fails if it's not an HTTP(S) URL.
+pub(crate) fn normalize_base_url(url: &str) -> Result<String, AnyError> {
+    let url = url.trim().trim_end_matches('/');
+    let Some((scheme, rest)) = url.split_once("://") else {
+        bail!("{url:?} isn't a URL. It should look like https://shoe.example.com");
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    if !matches!(scheme.as_str(), "http" | "https") || rest.is_empty() {
+        bail!("{url:?} isn't an http:// or https:// URL");
+    }
+    if rest.contains(['?', '#']) {
+        bail!("{url:?} has a query or fragment, which a server URL can't have");
+    }
+    Ok(format!("{scheme}://{rest}"))
+}
+
+/// Reads the given key from localStorage
+fn get_item(key: &str) -> Option<String> {
+    let storage = window().local_storage().ok().flatten()?;
+    storage.get_item(key).ok().flatten()
+}
+
+/// Writes the given key to localStorage, or removes it if there's no value
+fn set_item(key: &str, value: Option<&str>) {
+    let Some(storage) = window().local_storage().ok().flatten() else {
+        return;
+    };
+    let res = match value {
+        Some(value) => storage.set_item(key, value),
+        None => storage.remove_item(key),
+    };
+    if let Err(e) = res {
+        tracing::error!("Couldn't save {key}: {:?}", e);
+    }
+}
+
+/// Loads the list of other servers
+pub(crate) fn list() -> Vec<Server> {
+    get_item(SERVERS_KEY)
+        .and_then(|json| js_sys::JSON::parse(&json).ok())
+        .and_then(|v| v.into_serde().ok())
+        .unwrap_or_default()
+}
+
+/// Saves the list of other servers
+pub(crate) fn save_list(servers: &[Server]) {
+    let json = JsValue::from_serde(servers)
+        .ok()
+        .and_then(|v| js_sys::JSON::stringify(&v).ok())
+        .and_then(|s| s.as_string());
+    if let Some(json) = json {
+        set_item(SERVERS_KEY, Some(&json));
+    }
+}
+
+/// Returns the active server, or `None` if it's the one the app was loaded from
+pub(crate) fn active() -> Option<Server> {
+    let base_url = get_item(ACTIVE_SERVER_KEY)?;
+    list().into_iter().find(|s| s.base_url == base_url)
+}
+
+/// Makes the server with the given base URL the active one. `None` means the server the app was
+/// loaded from. The page has to be reloaded for this to take effect, since everything loaded so
+/// far came from the old server.
+pub(crate) fn set_active(base_url: Option<&str>) {
+    set_item(ACTIVE_SERVER_KEY, base_url);
+}
+
+/// Saves the given session token for the active server. `None` means it was signed out of.
+pub(crate) fn set_token(token: Option<String>) {
+    let Some(active) = active() else {
+        return;
+    };
+    let mut servers = list();
+    if let Some(server) = servers.iter_mut().find(|s| s.base_url == active.base_url) {
+        server.token = token;
+    }
+    save_list(&servers);
+}
+
+/// Returns the path the server the app was loaded from serves it under, e.g., `/rtms`, without a
+/// trailing slash. It's empty if the app is at the root.
+fn local_base_path() -> String {
+    yew_router::utils::base_url().unwrap_or_default()
+}
+
+/// Returns the URL of the given path, e.g., `/api/list-articles`, on the active server
+pub(crate) fn api_url(path: &str) -> String {
+    api_url_for(active().as_ref(), &local_base_path(), path)
+}
+
+/// Returns the URL of the given path on the given server, where `None` means the one the app was
+/// loaded from, under the given base path
+fn api_url_for(server: Option<&Server>, local_base_path: &str, path: &str) -> String {
+    match server {
+        Some(server) => format!("{}{path}", server.base_url),
+        None => format!("{local_base_path}{path}"),
+    }
+}
+
+/// Returns the full URL of the given path, e.g., `/add`, on the server the app was loaded from, for
+/// giving to someone or to another app
+pub(crate) fn local_url(path: &str) -> String {
+    let origin = window().location().origin().unwrap_or_default();
+    format!("{origin}{}{path}", local_base_path())
+}
+
+/// Returns the given name of something kept locally, e.g., a database or a localStorage key, made
+/// specific to the active server. The server the app was loaded from keeps the plain names, so
+/// nothing saved before there were other servers is lost.
+pub(crate) fn scoped_name(name: &str) -> String {
+    scoped_name_for(active().as_ref(), name)
+}
+
+/// Returns the given name made specific to the given server, as in `scoped_name`
+fn scoped_name_for(server: Option<&Server>, name: &str) -> String {
+    match server {
+        Some(server) => format!("{name}@{}", server.base_url),
+        None => name.to_string(),
+    }
+}
+
+#[test]
+fn server_urls() {
+    assert_eq!(
+        normalize_base_url(" HTTPS://shoe.example.com/ ").unwrap(),
+        "https://shoe.example.com"
+    );
+    assert_eq!(
+        normalize_base_url("http://192.168.1.5:8080/rtms").unwrap(),
+        "http://192.168.1.5:8080/rtms"
+    );
+    assert!(normalize_base_url("shoe.example.com").is_err());
+    assert!(normalize_base_url("ftp://shoe.example.com").is_err());
+    assert!(normalize_base_url("https://shoe.example.com/?x=1").is_err());
+
+    let work = Server {
+        name: "Work".to_string(),
+        base_url: "https://work.example".to_string(),
+        token: None,
+    };
+    // Switching to another server sends requests to it, and keeps its caches apart
+    assert_eq!(
+        api_url_for(None, "/rtms", "/api/list-articles"),
+        "/rtms/api/list-articles"
+    );
+    assert_eq!(
+        api_url_for(Some(&work), "/rtms", "/api/list-articles"),
+        "https://work.example/api/list-articles"
+    );
+    assert_eq!(scoped_name_for(None, "readtomyshoe"), "readtomyshoe");
+    assert_eq!(
+        scoped_name_for(Some(&work), "readtomyshoe"),
+        "readtomyshoe@https://work.example"
+    );
+}
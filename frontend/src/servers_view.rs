@@ -0,0 +1,184 @@
+//! The server switcher in the header. It lists the server the app was loaded from and the other
+//! servers that were added, and switches between them. Switching reloads the page, so everything
+//! comes from the new server and its own local caches.
+
+use crate::servers::{self, Server};
+
+use anyhow::Error as AnyError;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+const NAME_FORM_ID: &str = "server-name-input";
+const URL_FORM_ID: &str = "server-url-input";
+
+/// Returns the value of the input with the given ID
+fn input_value(id: &str) -> String {
+    gloo_utils::document()
+        .get_element_by_id(id)
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .map(|input| input.value())
+        .unwrap_or_default()
+}
+
+pub(crate) enum ServersMsg {
+    /// Adds the server in the form
+    Add,
+    /// Removes the server with the given base URL
+    Remove(String),
+    /// Switches to the server with the given base URL. `None` is the server the app was loaded
+    /// from.
+    Switch(Option<String>),
+}
+
+/// The server switcher
+pub(crate) struct ServerSwitcher {
+    /// The servers other than the one the app was loaded from
+    servers: Vec<Server>,
+    /// The base URL of the active server, if it's not the one the app was loaded from
+    active: Option<String>,
+    err: Option<AnyError>,
+}
+
+impl Component for ServerSwitcher {
+    type Message = ServersMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        ServerSwitcher {
+            servers: servers::list(),
+            active: servers::active().map(|s| s.base_url),
+            err: None,
+        }
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        self.err = None;
+        match msg {
+            ServersMsg::Add => {
+                let base_url = match servers::normalize_base_url(&input_value(URL_FORM_ID)) {
+                    Ok(url) => url,
+                    Err(e) => {
+                        self.err = Some(e);
+                        return true;
+                    }
+                };
+                let name = match input_value(NAME_FORM_ID).trim() {
+                    "" => base_url.clone(),
+                    name => name.to_string(),
+                };
+                // Adding a server that's there already renames it
+                match self.servers.iter_mut().find(|s| s.base_url == base_url) {
+                    Some(server) => server.name = name,
+                    None => self.servers.push(Server {
+                        name,
+                        base_url,
+                        token: None,
+                    }),
+                }
+                servers::save_list(&self.servers);
+                true
+            }
+            ServersMsg::Remove(base_url) => {
+                if self.active.as_ref() == Some(&base_url) {
+                    return false;
+                }
+                self.servers.retain(|s| s.base_url != base_url);
+                servers::save_list(&self.servers);
+                true
+            }
+            ServersMsg::Switch(base_url) => {
+                if base_url != self.active {
+                    servers::set_active(base_url.as_deref());
+                    let _ = gloo_utils::window().location().reload();
+                }
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let active_name = match &self.active {
+            Some(url) => self
+                .servers
+                .iter()
+                .find(|s| &s.base_url == url)
+                .map_or(url.clone(), |s| s.name.clone()),
+            None => "This server".to_string(),
+        };
+
+        let own = {
+            let is_active = self.active.is_none();
+            html! {
+                <li>
+                    { "This server" }
+                    if is_active {
+                        <em>{ " (active)" }</em>
+                    } else {
+                        { " " }
+                        <button onclick={ link.callback(|_| ServersMsg::Switch(None)) }>
+                            { "Switch" }
+                        </button>
+                    }
+                </li>
+            }
+        };
+        let others = self
+            .servers
+            .iter()
+            .map(|server| {
+                let is_active = self.active.as_ref() == Some(&server.base_url);
+                let url = server.base_url.clone();
+                let switch_cb = link.callback(move |_| ServersMsg::Switch(Some(url.clone())));
+                let url = server.base_url.clone();
+                let remove_cb = link.callback(move |_| ServersMsg::Remove(url.clone()));
+                html! {
+                    <li>
+                        <bdi>{ &server.name }</bdi>
+                        <span class="articleMetadata">{ format!(" {}", server.base_url) }</span>
+                        if is_active {
+                            <em>{ " (active)" }</em>
+                        } else {
+                            { " " }
+                            <button onclick={switch_cb}>{ "Switch" }</button>
+                            <button
+                                aria-label={ format!("Remove {}", server.name) }
+                                title="Remove server"
+                                onclick={remove_cb}
+                            >
+                                { "✕" }
+                            </button>
+                        }
+                    </li>
+                }
+            })
+            .collect::<Html>();
+
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+        html! {
+            <details class="servers">
+                <summary>{ format!("Server: {active_name}") }</summary>
+                <ul aria-label="Servers">
+                    { own }
+                    { others }
+                </ul>
+                <p class="articleMetadata">{
+                    "Other servers have to let this app use them, by listing this site in the
+                    [[cors]] section of their config, and this site has to list them in its
+                    app_servers. Each server keeps its own saved articles here."
+                }</p>
+                <label for={NAME_FORM_ID}>{ "Name: " }</label>
+                <input type="text" id={NAME_FORM_ID} placeholder="Home" />
+                <label for={URL_FORM_ID}>{ " URL: " }</label>
+                <input type="url" id={URL_FORM_ID} placeholder="https://shoe.example.com" />
+                <button onclick={ link.callback(|_| ServersMsg::Add) }>{ "Add server" }</button>
+                <p style="color: red;" role="alert">{ err_str }</p>
+            </details>
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Links that share an article's audio with people who can't sign in. They're made from the
+//! library, and listed here so they can be revoked before they expire. The library's collection,
+//! i.e., every article it has a link to, can be shared too, so another server can subscribe to it.
+
+use crate::{servers, utils};
+use common::{CollectionShareSubmission, ShareRevocation, ShareSubmission, SharedLink};
+
+use anyhow::{bail, Error as AnyError};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// How many days a link works for, unless the listener says otherwise
+pub(crate) const DEFAULT_SHARE_DAYS: u32 = 7;
+
+/// How many days a link to the collection works for. Other servers keep using it, so it lasts
+/// longer than a link to an article.
+const COLLECTION_SHARE_DAYS: u32 = 90;
+
+const COLLECTION_NAME_FORM_ID: &str = "collection-name-input";
+
+/// Returns the full URL of the given link, for giving to someone
+pub(crate) fn absolute_url(link: &SharedLink) -> String {
+    servers::local_url(&link.url)
+}
+
+/// Asks the server for a link to the given article that works for the given number of days
+pub(crate) async fn share_article(
+    article_id: String,
+    title: String,
+    days: u32,
+) -> Result<SharedLink, AnyError> {
+    let submission = ShareSubmission {
+        article_id,
+        title,
+        days,
+    };
+    let resp = utils::post("/api/share-article")
+        .json(&submission)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error sharing article"))?;
+    if !resp.ok() {
+        bail!("Error sharing article: {}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing shared link"))
+}
+
+/// Asks the server for a link to the library's collection, under the given name
+async fn share_collection(name: String) -> Result<SharedLink, AnyError> {
+    let submission = CollectionShareSubmission {
+        name,
+        days: COLLECTION_SHARE_DAYS,
+    };
+    let resp = utils::post("/api/share-collection")
+        .json(&submission)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error sharing collection"))?;
+    if !resp.ok() {
+        bail!(
+            "Error sharing collection: {}",
+            utils::resp_error(resp).await
+        );
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing shared link"))
+}
+
+/// Fetches the links to articles in the library
+async fn fetch_shares() -> Result<Vec<SharedLink>, AnyError> {
+    let resp = utils::get("/api/list-shares")
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching shared links"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing shared links JSON"))
+}
+
+/// Revokes the link with the given ID
+async fn revoke_share(id: String) -> Result<(), AnyError> {
+    let resp = utils::post("/api/revoke-share")
+        .json(&ShareRevocation { id })?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error revoking shared link"))?;
+    if !resp.ok() {
+        bail!("{}", utils::resp_error(resp).await);
+    }
+    Ok(())
+}
+
+pub(crate) enum SharesMsg {
+    /// Fetches the links
+    Refresh,
+    /// Sets the links
+    SetShares(Vec<SharedLink>),
+    /// Revokes the link with the given ID
+    Revoke(String),
+    /// Makes a link to the collection, under the name in the form
+    ShareCollection,
+    /// Shows the given new link to the collection, and refreshes the links
+    SharedCollection(SharedLink),
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+/// The list of shared links on the main page
+#[derive(Default)]
+pub(crate) struct Shares {
+    shares: Vec<SharedLink>,
+    /// The link to the collection that was just made, to be given to someone
+    new_collection_link: Option<SharedLink>,
+    err: Option<AnyError>,
+}
+
+impl Component for Shares {
+    type Message = SharesMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Shares::default()
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            SharesMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    match fetch_shares().await {
+                        Ok(shares) => SharesMsg::SetShares(shares),
+                        Err(e) => SharesMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            SharesMsg::SetShares(mut shares) => {
+                shares.sort_by_key(|s| s.expires);
+                self.shares = shares;
+                self.err = None;
+            }
+
+            SharesMsg::Revoke(id) => {
+                ctx.link().send_future(async move {
+                    match revoke_share(id).await {
+                        Ok(()) => SharesMsg::Refresh,
+                        Err(e) => SharesMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            SharesMsg::ShareCollection => {
+                let name = gloo_utils::document()
+                    .get_element_by_id(COLLECTION_NAME_FORM_ID)
+                    .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+                    .map(|input| input.value().trim().to_string())
+                    .unwrap_or_default();
+                if name.is_empty() {
+                    return false;
+                }
+
+                ctx.link().send_future(async move {
+                    match share_collection(name).await {
+                        Ok(link) => SharesMsg::SharedCollection(link),
+                        Err(e) => SharesMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            SharesMsg::SharedCollection(link) => {
+                self.new_collection_link = Some(link);
+                ctx.link().send_message(SharesMsg::Refresh);
+            }
+
+            SharesMsg::SetError(e) => {
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let rows = self
+            .shares
+            .iter()
+            .map(|share| {
+                let id = share.id.clone();
+                let revoke = link.callback(move |_| SharesMsg::Revoke(id.clone()));
+                let kind = if share.collection {
+                    " (collection)"
+                } else {
+                    ""
+                };
+                html! {
+                    <tr>
+                        <td><a href={ absolute_url(share) }>{ &share.title }</a>{ kind }</td>
+                        <td>{ format!("Expires {}", utils::format_date(share.expires)) }</td>
+                        <td>
+                            <button
+                                onclick={revoke}
+                                aria-label={ format!("Revoke link to {}", share.title) }
+                            >
+                                { "Revoke" }
+                            </button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or_default();
+        let ontoggle = link.callback(|_| SharesMsg::Refresh);
+        let list = if self.shares.is_empty() {
+            html! {
+                <p class="articleMetadata">{
+                    "Nothing's shared. Share an article from the library with its 🔗 button."
+                }</p>
+            }
+        } else {
+            html! { <table aria-label="Shared links">{ rows }</table> }
+        };
+
+        let share_collection = link.callback(|_| SharesMsg::ShareCollection);
+        let new_collection_link = self.new_collection_link.as_ref().map(|link| {
+            let url = absolute_url(link);
+            html! {
+                <p aria-live="polite">
+                    { "Give this link to whoever should get your collection. Their server can \
+                    subscribe to it: " }
+                    <input type="text" readonly=true value={url} aria-label="Link to collection" />
+                </p>
+            }
+        });
+
+        html! {
+            <details class="shares" {ontoggle}>
+                <summary>{ "Shared links" }</summary>
+                { list }
+                <p class="articleMetadata">{
+                    "Share your collection, i.e., every article with a link, with another \
+                    ReadToMyShoe server. It lists the articles for them to copy."
+                }</p>
+                <div class="field">
+                    <label for={COLLECTION_NAME_FORM_ID}>{ "Collection name:" }</label>
+                    <input type="text" id={COLLECTION_NAME_FORM_ID} />
+                </div>
+                <button onclick={share_collection}>{ "Share collection" }</button>
+                { for new_collection_link }
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </details>
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Smart playlists are saved searches over the library catalog. They're evaluated locally, so
+//! they update whenever the catalog or the listening history does. When a playlist auto-advances,
+//! articles from the sources the listener rates highly come up first.
+
+use crate::queue_view::ArticleId;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use common::{ArticleMetadata, Rating};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A saved search
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SmartPlaylist {
+    /// The name of the playlist. This is unique.
+    pub(crate) name: String,
+    /// The search, in the syntax that [`Query`] parses
+    pub(crate) query: String,
+    /// Whether the player goes on to the next matching article when the queue runs out
+    #[serde(default)]
+    pub(crate) auto_advance: bool,
+}
+
+/// The local state a query is evaluated against, alongside an article's metadata
+pub(crate) struct MatchState<'a> {
+    /// The articles that have ever been played
+    pub(crate) listened: &'a BTreeSet<ArticleId>,
+    /// The articles that are currently in the queue
+    pub(crate) queued: &'a BTreeSet<ArticleId>,
+    /// The current time, in seconds since the Unix epoch
+    pub(crate) now: u64,
+}
+
+/// A parsed search. Every term has to match for an article to match.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Query(Vec<(bool, Term)>);
+
+/// A single condition on an article
+#[derive(Debug, PartialEq)]
+enum Term {
+    /// The title contains the given lowercase text
+    Title(String),
+    /// The author contains the given lowercase text
+    Author(String),
+    /// The publication contains the given lowercase text
+    Publication(String),
+    /// The audio is shorter than the given number of seconds
+    ShorterThan(u64),
+    /// The audio is longer than the given number of seconds
+    LongerThan(u64),
+    /// The article was added less than the given number of seconds ago
+    AddedWithin(u64),
+    /// The article was added more than the given number of seconds ago
+    AddedBefore(u64),
+    /// The article has been played
+    Listened,
+    /// The article is in the queue
+    Queued,
+    /// The article's audio stops partway through
+    Incomplete,
+    /// The listener gave the article the given rating
+    Rated(Rating),
+}
+
+/// Splits a search into words, keeping quoted phrases together
+fn tokenize(s: &str) -> Result<Vec<String>, AnyError> {
+    let mut tokens = Vec::new();
+    let mut cur = String::new();
+    let mut in_quotes = false;
+
+    for c in s.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !cur.is_empty() {
+                    tokens.push(std::mem::take(&mut cur));
+                }
+            }
+            c => cur.push(c),
+        }
+    }
+
+    if in_quotes {
+        bail!("unclosed quote");
+    }
+    if !cur.is_empty() {
+        tokens.push(cur);
+    }
+
+    Ok(tokens)
+}
+
+/// Parses a number followed by a unit, like "20min", into seconds. `units` maps suffixes to their
+/// length in seconds.
+fn parse_amount(s: &str, units: &[(&str, u64)]) -> Result<u64, AnyError> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: u64 = num
+        .parse()
+        .map_err(|_| anyhow!("expected a number in \"{s}\""))?;
+
+    units
+        .iter()
+        .find(|(suffix, _)| *suffix == unit)
+        .map(|(_, secs)| num * secs)
+        .ok_or_else(|| {
+            let suffixes: Vec<&str> = units.iter().map(|(suffix, _)| *suffix).collect();
+            anyhow!(
+                "unknown unit in \"{s}\", expected one of {}",
+                suffixes.join(", ")
+            )
+        })
+}
+
+/// The units a duration can be given in
+const DURATION_UNITS: &[(&str, u64)] = &[("s", 1), ("m", 60), ("min", 60), ("h", 3600)];
+
+/// The units an age can be given in
+const AGE_UNITS: &[(&str, u64)] = &[("h", 3600), ("d", 86400), ("w", 7 * 86400)];
+
+impl FromStr for Term {
+    type Err = AnyError;
+
+    fn from_str(token: &str) -> Result<Term, AnyError> {
+        let lower = token.to_lowercase();
+
+        // Comparisons, like duration<20min
+        if let Some(idx) = lower.find(['<', '>']) {
+            let (field, rest) = lower.split_at(idx);
+            let less = rest.starts_with('<');
+            let amount = &rest[1..];
+            return match field {
+                "duration" => {
+                    let secs = parse_amount(amount, DURATION_UNITS)?;
+                    Ok(if less {
+                        Term::ShorterThan(secs)
+                    } else {
+                        Term::LongerThan(secs)
+                    })
+                }
+                "added" => {
+                    let secs = parse_amount(amount, AGE_UNITS)?;
+                    Ok(if less {
+                        Term::AddedWithin(secs)
+                    } else {
+                        Term::AddedBefore(secs)
+                    })
+                }
+                _ => Err(anyhow!("can't compare \"{field}\"")),
+            };
+        }
+
+        // Field searches, like author:"Jane Doe"
+        if let Some((field, val)) = lower.split_once(':') {
+            let val = val.to_string();
+            return match field {
+                "title" => Ok(Term::Title(val)),
+                "author" => Ok(Term::Author(val)),
+                "publication" => Ok(Term::Publication(val)),
+                "rating" => val
+                    .parse()
+                    .map(Term::Rated)
+                    .map_err(|_| anyhow!("rating must be \"up\" or \"down\"")),
+                _ => Err(anyhow!("unknown field \"{field}\"")),
+            };
+        }
+
+        // Flags, and otherwise plain words in the title
+        Ok(match lower.as_str() {
+            "listened" => Term::Listened,
+            "queued" => Term::Queued,
+            "incomplete" => Term::Incomplete,
+            _ => Term::Title(lower),
+        })
+    }
+}
+
+impl FromStr for Query {
+    type Err = AnyError;
+
+    /// Parses a search like `author:"jane doe" AND unlistened AND duration<20min`. Terms are
+    /// separated by spaces, and all of them must match. A term can be negated with a leading `-`
+    /// or a preceding `NOT`. `unlistened` is shorthand for `-listened`.
+    fn from_str(s: &str) -> Result<Query, AnyError> {
+        let mut terms = Vec::new();
+        let mut negate_next = false;
+
+        for token in tokenize(s)? {
+            match token.as_str() {
+                "AND" => continue,
+                "NOT" => {
+                    negate_next = !negate_next;
+                    continue;
+                }
+                "OR" => bail!("OR isn't supported. Save each alternative as its own playlist."),
+                _ => (),
+            }
+
+            let (negated, token) = match token.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, token.as_str()),
+            };
+            let (negated, term) = if token.eq_ignore_ascii_case("unlistened") {
+                (!negated, Term::Listened)
+            } else {
+                (negated, token.parse()?)
+            };
+
+            terms.push((negated != negate_next, term));
+            negate_next = false;
+        }
+
+        if negate_next {
+            bail!("NOT must be followed by a term");
+        }
+
+        Ok(Query(terms))
+    }
+}
+
+/// Returns whether `haystack` contains the lowercase `needle`, ignoring case
+fn contains_lower(haystack: Option<&str>, needle: &str) -> bool {
+    haystack
+        .map(|h| h.to_lowercase().contains(needle))
+        .unwrap_or(false)
+}
+
+impl Term {
+    fn matches(&self, meta: &ArticleMetadata, state: &MatchState) -> bool {
+        let id = ArticleId(meta.id.clone());
+        let age = meta
+            .datetime_added
+            .map(|added| state.now.saturating_sub(added));
+
+        match self {
+            Term::Title(s) => contains_lower(Some(&meta.title), s),
+            Term::Author(s) => contains_lower(meta.author.as_deref(), s),
+            Term::Publication(s) => contains_lower(meta.publication.as_deref(), s),
+            Term::ShorterThan(secs) => meta.duration_secs.map(|d| d < *secs).unwrap_or(false),
+            Term::LongerThan(secs) => meta.duration_secs.map(|d| d > *secs).unwrap_or(false),
+            Term::AddedWithin(secs) => age.map(|a| a < *secs).unwrap_or(false),
+            Term::AddedBefore(secs) => age.map(|a| a > *secs).unwrap_or(false),
+            Term::Listened => state.listened.contains(&id),
+            Term::Queued => state.queued.contains(&id),
+            Term::Incomplete => meta.incomplete,
+            Term::Rated(rating) => meta.rating == Some(*rating),
+        }
+    }
+}
+
+impl Query {
+    /// Returns whether the given article satisfies every term of this query
+    pub(crate) fn matches(&self, meta: &ArticleMetadata, state: &MatchState) -> bool {
+        self.0
+            .iter()
+            .all(|(negated, term)| term.matches(meta, state) != *negated)
+    }
+}
+
+/// Returns where the given article is from, for telling which sources the listener likes: its
+/// publication, or else the site it was fetched from
+pub(crate) fn source_of(meta: &ArticleMetadata) -> Option<String> {
+    let publication = meta
+        .publication
+        .as_deref()
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| !p.is_empty());
+    publication.or_else(|| {
+        let url = Url::parse(meta.source_url.as_deref()?).ok()?;
+        let host = url.host_str()?;
+        Some(host.trim_start_matches("www.").to_string())
+    })
+}
+
+/// Returns how much the listener likes each source in the given catalog: the number of its
+/// articles they rated up, minus the number they rated down
+pub(crate) fn source_scores<'a>(
+    catalog: impl IntoIterator<Item = &'a ArticleMetadata>,
+) -> BTreeMap<String, i32> {
+    let mut scores = BTreeMap::new();
+    for meta in catalog {
+        let (Some(rating), Some(source)) = (meta.rating, source_of(meta)) else {
+            continue;
+        };
+        *scores.entry(source).or_insert(0) += match rating {
+            Rating::Up => 1,
+            Rating::Down => -1,
+        };
+    }
+    scores
+}
+
+/// Picks what to play next out of the given articles, which are in playlist order. It's the first
+/// article from the best-liked of their sources, given the sources' scores. Unrated sources score
+/// 0, so they come after the liked ones and before the disliked ones.
+pub(crate) fn pick_next<'a>(
+    candidates: &[&'a ArticleMetadata],
+    scores: &BTreeMap<String, i32>,
+) -> Option<&'a ArticleMetadata> {
+    let score = |meta: &ArticleMetadata| {
+        source_of(meta)
+            .and_then(|source| scores.get(&source).copied())
+            .unwrap_or(0)
+    };
+    candidates
+        .iter()
+        .copied()
+        .fold(None, |best: Option<(&ArticleMetadata, i32)>, meta| {
+            let meta_score = score(meta);
+            match best {
+                Some((_, best_score)) if best_score >= meta_score => best,
+                _ => Some((meta, meta_score)),
+            }
+        })
+        .map(|(meta, _)| meta)
+}
@@ -0,0 +1,252 @@
+//! The storage panel. It shows how much of the browser's storage the app is using, how much each
+//! cached article takes up, and the eviction policy that deletes cached articles to make room.
+//! Pinned articles are never deleted by the policy, and are downloaded again if their audio is wiped.
+//! Articles can be pinned here or in the queue.
+
+use crate::{
+    caching::{self, CachedSize, EvictionPolicy},
+    queue_view::{ArticleId, Queue, QueueMsg},
+    WeakComponentLink,
+};
+
+use gloo_timers::future::TimeoutFuture;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// How long to wait after asking the queue to evict articles before showing the new sizes, in
+/// milliseconds
+const EVICT_SETTLE_MS: u32 = 1000;
+
+/// Formats the given number of bytes in megabytes
+pub(crate) fn format_megabytes(bytes: f64) -> String {
+    format!("{:.1} MB", bytes / 1e6)
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    /// A link to the Queue component, which deletes evicted articles
+    pub queue_link: WeakComponentLink<Queue>,
+}
+
+pub(crate) enum StorageMsg {
+    /// Measures the storage use again
+    Refresh,
+    /// Sets the measurements: the browser's estimate of the usage and quota, if any, and the size
+    /// of every cached article
+    Measured {
+        estimate: Option<(f64, f64)>,
+        articles: Vec<CachedSize>,
+    },
+    /// Pins or unpins the given article, which has the given title
+    TogglePinned(ArticleId, String),
+    /// Turns deleting finished articles on or off
+    ToggleEvictFinished,
+    /// Sets the most megabytes the cached audio can take up, if any
+    SetMaxMegabytes(Option<u32>),
+    /// Applies the eviction policy now
+    FreeUpSpace,
+}
+
+/// The storage panel on the main page
+pub(crate) struct Storage {
+    policy: EvictionPolicy,
+    /// The browser's estimate of how many bytes the app is using, and can use, if it gives one
+    estimate: Option<(f64, f64)>,
+    /// The cached articles, biggest first
+    articles: Vec<CachedSize>,
+}
+
+impl Storage {
+    /// Changes the eviction policy and saves it. It's loaded again first, since articles can be
+    /// pinned in the queue too.
+    fn change_policy(&mut self, change: impl FnOnce(&mut EvictionPolicy)) {
+        self.policy = EvictionPolicy::load();
+        change(&mut self.policy);
+        self.policy.save();
+    }
+}
+
+impl Component for Storage {
+    type Message = StorageMsg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(StorageMsg::Refresh);
+
+        Storage {
+            policy: EvictionPolicy::load(),
+            estimate: None,
+            articles: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            StorageMsg::Refresh => {
+                ctx.link().send_future(async move {
+                    let articles = caching::load_cached_sizes().await.unwrap_or_else(|e| {
+                        tracing::error!("Couldn't measure cached articles: {}", e);
+                        Vec::new()
+                    });
+                    StorageMsg::Measured {
+                        estimate: caching::storage_estimate().await,
+                        articles,
+                    }
+                });
+                return false;
+            }
+
+            StorageMsg::Measured {
+                estimate,
+                mut articles,
+            } => {
+                articles.sort_by(|a, b| b.bytes.total_cmp(&a.bytes));
+                self.estimate = estimate;
+                self.articles = articles;
+                self.policy = EvictionPolicy::load();
+            }
+
+            StorageMsg::TogglePinned(id, title) => {
+                self.change_policy(|policy| {
+                    policy.toggle_pinned(&id, &title);
+                });
+                let queue_link = ctx.props().queue_link.borrow().clone().unwrap();
+                queue_link.send_message(QueueMsg::RefreshPinned);
+            }
+
+            StorageMsg::ToggleEvictFinished => {
+                self.change_policy(|policy| policy.evict_finished = !policy.evict_finished);
+            }
+
+            StorageMsg::SetMaxMegabytes(max_megabytes) => {
+                self.change_policy(|policy| policy.max_megabytes = max_megabytes);
+            }
+
+            StorageMsg::FreeUpSpace => {
+                let queue_link = ctx.props().queue_link.borrow().clone().unwrap();
+                queue_link.send_message(QueueMsg::Evict);
+                ctx.link().send_future(async move {
+                    TimeoutFuture::new(EVICT_SETTLE_MS).await;
+                    StorageMsg::Refresh
+                });
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+
+        let usage = match self.estimate {
+            Some((usage, quota)) if quota > 0.0 => html! {
+                <p>
+                    <meter value={ usage.to_string() } max={ quota.to_string() } />
+                    { format!(
+                        " Using {} of {} ({:.0}%)",
+                        format_megabytes(usage),
+                        format_megabytes(quota),
+                        100.0 * usage / quota,
+                    ) }
+                </p>
+            },
+            _ => html! {
+                <p class="articleMetadata">{ "This browser doesn't say how much space is left" }</p>
+            },
+        };
+        let total_bytes: f64 = self.articles.iter().map(|a| a.bytes).sum();
+        let (pinned_count, pinned_bytes) = self
+            .articles
+            .iter()
+            .filter(|a| self.policy.pinned.contains(&a.id))
+            .fold((0, 0.0), |(count, bytes), a| (count + 1, bytes + a.bytes));
+
+        let article_rows = self
+            .articles
+            .iter()
+            .map(|article| {
+                let (id, title) = (article.id.clone(), article.title.clone());
+                let onchange =
+                    link.callback(move |_| StorageMsg::TogglePinned(id.clone(), title.clone()));
+                let pinned = self.policy.pinned.contains(&article.id);
+                html! {
+                    <tr>
+                        <td>{ &article.title }</td>
+                        <td>{ format_megabytes(article.bytes) }</td>
+                        <td>
+                            <label>
+                                <input type="checkbox" checked={pinned} {onchange} />
+                                { " 📌 Pin" }
+                            </label>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+
+        let toggle_evict_finished = link.callback(|_| StorageMsg::ToggleEvictFinished);
+        let max_megabytes = self
+            .policy
+            .max_megabytes
+            .map(|mb| mb.to_string())
+            .unwrap_or_default();
+        // A blank or invalid limit means there's no limit
+        let set_max_megabytes = link.callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            StorageMsg::SetMaxMegabytes(input.value().trim().parse().ok().filter(|&mb| mb > 0))
+        });
+        let free_up_space = link.callback(|_| StorageMsg::FreeUpSpace);
+        let ontoggle = link.callback(|_| StorageMsg::Refresh);
+
+        html! {
+            <details class="storage" {ontoggle}>
+                <summary>{ "Storage" }</summary>
+                { usage }
+                <p>{ format!(
+                    "{} cached articles, taking up {}",
+                    self.articles.len(),
+                    format_megabytes(total_bytes),
+                ) }</p>
+                <p>{ format!(
+                    "{} pinned, taking up {}",
+                    pinned_count,
+                    format_megabytes(pinned_bytes),
+                ) }</p>
+                <p>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={ self.policy.evict_finished }
+                            onchange={toggle_evict_finished}
+                        />
+                        { " Delete articles once they've been listened to the end" }
+                    </label>
+                </p>
+                <p>
+                    <label>
+                        { "Keep cached articles under " }
+                        <input
+                            type="number"
+                            min="1"
+                            size="5"
+                            placeholder="no limit"
+                            value={max_megabytes}
+                            onchange={set_max_megabytes}
+                        />
+                        { " MB, deleting the least recently played first" }
+                    </label>
+                </p>
+                <p>
+                    <button onclick={free_up_space}>{ "Free up space now" }</button>
+                    <span class="articleMetadata">
+                        { " This also runs whenever an article is added to the queue" }
+                    </span>
+                </p>
+                <table aria-label="Cached articles">
+                    { article_rows }
+                </table>
+            </details>
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Keeps the queue, playback positions, playback speed, and playlists in step with the listener's
+//! other devices, through the server. This device sends what it has, and the server sends back the
+//! merge of it with what the other devices sent. The queue, speed, and each playlist go to whichever
+//! device changed them last, and each article's position to whichever got furthest. See `sync.rs`
+//! in the server.
+//!
+//! The server lists this device under an ID it keeps in localStorage, with a nickname guessed from
+//! the browser, so it can be told apart from the others in the list of devices. A position from
+//! another device that conflicts with one played here is held back for the listener to pick
+//! between. See `position_conflicts.rs`.
+
+use crate::{
+    caching, clock, devices_view, encryption,
+    player_view::ArticleState,
+    playlists_view::Playlist,
+    position_conflicts::{self, PositionConflict},
+    queue_view::{ArticleId, QueueEntry},
+    servers, utils,
+};
+use common::{DeviceCheckIn, SyncState, SyncedPlaylist, SyncedPosition};
+
+use anyhow::{bail, Error as AnyError};
+use gloo_utils::window;
+
+/// How often to sync while the audio is playing, in milliseconds
+pub(crate) const SYNC_FREQ: u32 = 2 * 60 * 1000;
+
+/// The localStorage key of the ID the server lists this device under
+const DEVICE_ID_KEY: &str = "sync-device-id";
+
+/// Returns the ID the server lists this device under, making one if it has none yet
+pub(crate) fn device_id() -> String {
+    let key = servers::scoped_name(DEVICE_ID_KEY);
+    let storage = window().local_storage().ok().flatten();
+    if let Some(id) = storage
+        .as_ref()
+        .and_then(|s| s.get_item(&key).ok().flatten())
+    {
+        return id;
+    }
+    let id = format!(
+        "{:x}-{:x}",
+        js_sys::Date::now() as u64,
+        (js_sys::Math::random() * 1e12) as u64
+    );
+    if let Some(storage) = storage {
+        if let Err(e) = storage.set_item(&key, &id) {
+            tracing::error!("Couldn't save the device ID: {:?}", e);
+        }
+    }
+    id
+}
+
+/// Forgets this device's ID, after it was revoked, so it's listed as a new device once it's signed
+/// in again
+fn forget_device_id() {
+    if let Some(storage) = window().local_storage().ok().flatten() {
+        let _ = storage.remove_item(&servers::scoped_name(DEVICE_ID_KEY));
+    }
+}
+
+/// Guesses a nickname for a device from its browser's user agent string, e.g., "Firefox on
+/// Android"
+fn nickname_from_user_agent(user_agent: &str) -> String {
+    let has = |s: &str| user_agent.contains(s);
+    let browser = if has("Edg/") {
+        "Edge"
+    } else if has("OPR/") {
+        "Opera"
+    } else if has("Firefox/") || has("FxiOS/") {
+        "Firefox"
+    } else if has("Chrome/") || has("CriOS/") {
+        "Chrome"
+    } else if has("Safari/") {
+        "Safari"
+    } else {
+        "Browser"
+    };
+    // Android says it's Linux, and iOS says it's like macOS, so they go first
+    let os = if has("Android") {
+        "Android"
+    } else if has("iPhone") {
+        "iPhone"
+    } else if has("iPad") {
+        "iPad"
+    } else if has("Windows") {
+        "Windows"
+    } else if has("CrOS") {
+        "ChromeOS"
+    } else if has("Macintosh") {
+        "Mac"
+    } else if has("Linux") {
+        "Linux"
+    } else {
+        return browser.to_string();
+    };
+    format!("{browser} on {os}")
+}
+
+/// Says who this device is, for the server's list of devices
+async fn check_in() -> DeviceCheckIn {
+    let user_agent = window().navigator().user_agent().unwrap_or_default();
+    let cached_bytes = caching::load_cached_sizes()
+        .await
+        .map(|sizes| sizes.iter().map(|size| size.bytes).sum::<f64>())
+        .unwrap_or_default();
+    DeviceCheckIn {
+        id: device_id(),
+        nickname: nickname_from_user_agent(&user_agent),
+        cached_bytes: cached_bytes as u64,
+    }
+}
+
+/// What changed on this device because of a sync
+#[derive(Default)]
+pub(crate) struct SyncChanges {
+    /// The queue another device changed it to, and when, if it's newer than this device's
+    pub(crate) queue: Option<(Vec<QueueEntry>, f64)>,
+    /// The positions another device got further in than this one. They're already saved.
+    pub(crate) positions: Vec<ArticleState>,
+    /// The playback speed another device changed it to, and when, if it's newer than this
+    /// device's
+    pub(crate) speed: Option<(f64, f64)>,
+    /// Whether another device changed any playlists. They're already saved.
+    pub(crate) playlists: bool,
+}
+
+impl From<&ArticleState> for SyncedPosition {
+    fn from(state: &ArticleState) -> SyncedPosition {
+        SyncedPosition {
+            id: state.id.0.clone(),
+            elapsed: state.elapsed,
+            finished: state.finished,
+            last_played: state.last_played,
+            device: state.device.clone(),
+            chosen: 0.0,
+        }
+    }
+}
+
+impl From<&Playlist> for SyncedPlaylist {
+    fn from(playlist: &Playlist) -> SyncedPlaylist {
+        SyncedPlaylist {
+            id: playlist.id.clone(),
+            name: playlist.name.clone(),
+            entries: playlist
+                .entries
+                .iter()
+                .filter(|entry| !encryption::is_private(&entry.id))
+                .map(Into::into)
+                .collect(),
+            updated: playlist.updated,
+            deleted: playlist.deleted,
+        }
+    }
+}
+
+impl From<SyncedPosition> for ArticleState {
+    fn from(pos: SyncedPosition) -> ArticleState {
+        ArticleState {
+            id: ArticleId(pos.id),
+            elapsed: pos.elapsed,
+            finished: pos.finished,
+            last_played: pos.last_played,
+            device: pos.device,
+        }
+    }
+}
+
+/// Gathers what this device has to sync. Articles from the encrypted library are left out, so
+/// their titles never reach the server.
+pub(crate) async fn local_state() -> Result<SyncState, AnyError> {
+    // There's no saved queue or player state until something's been queued or played
+    let queue = caching::load_queue().await.unwrap_or_default();
+    let player_state = caching::load_player_state().await.ok();
+    let entries: Vec<_> = queue
+        .entries()
+        .iter()
+        .filter(|entry| !encryption::is_private(&entry.id))
+        .collect();
+
+    let mut positions = Vec::new();
+    for entry in &entries {
+        if let Ok(state) = caching::load_article_state(&entry.id).await {
+            positions.push(SyncedPosition {
+                chosen: position_conflicts::picked_at(&state.id),
+                ..SyncedPosition::from(&state)
+            });
+        }
+    }
+
+    let playlists = caching::load_playlists().await.unwrap_or_default();
+
+    Ok(SyncState {
+        queue: entries.into_iter().map(Into::into).collect(),
+        queue_updated: queue.updated(),
+        positions,
+        playback_speed: player_state.as_ref().map_or(0.0, |s| s.playback_speed),
+        speed_updated: player_state.as_ref().map_or(0.0, |s| s.speed_updated),
+        playlists: playlists.iter().map(Into::into).collect(),
+        device: Some(check_in().await),
+    })
+}
+
+/// Sends this device's state to the server, and saves the positions that the other devices got
+/// further in, and the playlists they changed. The queue and speed are returned rather than saved,
+/// since the queue and player save them themselves.
+pub(crate) async fn sync() -> Result<SyncChanges, AnyError> {
+    let started = clock::now();
+    let local = local_state().await?;
+    let resp = utils::post("/api/sync")
+        .json(&local)?
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error syncing"))?;
+    // 410 Gone means this device was revoked
+    if resp.status() == 410 {
+        forget_device_id();
+    }
+    if !resp.ok() {
+        bail!("Error syncing: {}", utils::resp_error(resp).await);
+    }
+    let merged: SyncState = resp
+        .json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing sync JSON"))?;
+
+    let mut changes = SyncChanges::default();
+    if merged.queue_updated > local.queue_updated {
+        let entries = merged.queue.into_iter().map(Into::into).collect();
+        changes.queue = Some((entries, merged.queue_updated));
+    }
+    if merged.speed_updated > local.speed_updated && merged.playback_speed > 0.0 {
+        changes.speed = Some((merged.playback_speed, merged.speed_updated));
+    }
+
+    // Take the positions that win over this device's, unless they conflict with it
+    let last_synced = position_conflicts::last_synced();
+    let ask = !position_conflicts::furthest_wins();
+    let this_device = device_id();
+    let mut held_back = Vec::new();
+    for pos in merged.positions {
+        let local_pos = local.positions.iter().find(|p| p.id == pos.id);
+        if !local_pos.is_none_or(|local_pos| pos.wins_over(local_pos)) {
+            continue;
+        }
+        if ask {
+            let id = ArticleId(pos.id.clone());
+            // Until the listener picks, keep holding back the latest from the same device
+            if let Some(conflict) = position_conflicts::find(&id) {
+                if conflict.remote.device == pos.device {
+                    position_conflicts::hold_back(PositionConflict {
+                        remote: pos,
+                        ..conflict
+                    });
+                }
+                continue;
+            }
+            if let Some(local_pos) = local_pos.filter(|local_pos| {
+                position_conflicts::is_conflict(local_pos, &pos, last_synced, &this_device)
+            }) {
+                held_back.push((id, local_pos.clone(), pos));
+                continue;
+            }
+        }
+        let state = ArticleState::from(pos);
+        caching::save_article_state(&state).await?;
+        changes.positions.push(state);
+    }
+    if !held_back.is_empty() {
+        // The listener picks between devices by name
+        let devices = devices_view::fetch_devices().await.unwrap_or_default();
+        for (id, local, remote) in held_back {
+            let remote_name = devices
+                .iter()
+                .find(|d| Some(&d.id) == remote.device.as_ref())
+                .map_or_else(|| "Another device".to_string(), |d| d.nickname.clone());
+            position_conflicts::hold_back(PositionConflict {
+                id,
+                local,
+                remote,
+                remote_name,
+            });
+        }
+    }
+    position_conflicts::set_last_synced(started);
+
+    for playlist in merged.playlists {
+        let newer = match local.playlists.iter().find(|p| p.id == playlist.id) {
+            Some(local_playlist) => playlist.updated > local_playlist.updated,
+            None => true,
+        };
+        if newer {
+            caching::save_playlist(&Playlist::from(playlist)).await?;
+            changes.playlists = true;
+        }
+    }
+
+    Ok(changes)
+}
+
+#[test]
+fn guessing_nicknames() {
+    let android =
+        "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) \
+                   Chrome/126.0.0.0 Mobile Safari/537.36";
+    assert_eq!(nickname_from_user_agent(android), "Chrome on Android");
+    let iphone = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 \
+                  (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1";
+    assert_eq!(nickname_from_user_agent(iphone), "Safari on iPhone");
+    let windows = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like \
+                   Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0";
+    assert_eq!(nickname_from_user_agent(windows), "Edge on Windows");
+    let linux = "Mozilla/5.0 (X11; Linux x86_64; rv:127.0) Gecko/20100101 Firefox/127.0";
+    assert_eq!(nickname_from_user_agent(linux), "Firefox on Linux");
+    assert_eq!(nickname_from_user_agent(""), "Browser");
+}
@@ -1,114 +1,177 @@
-use anyhow::Error as AnyError;
-use js_sys::Uint8Array;
-use wasm_bindgen::{closure::Closure, JsCast, JsValue};
-use wasm_bindgen_futures::{spawn_local, JsFuture};
-use web_sys::{
-    Blob, BlobPropertyBag, ReadableStream, ReadableStreamDefaultController,
-    ReadableStreamDefaultReader,
+use crate::{
+    clock::{self, Timer},
+    network_sim, servers,
 };
 
-const MP3_MIME_TYPE: &str = "audio/mp3";
+use anyhow::{anyhow, Error as AnyError};
+use gloo_net::http::{Method, Request, Response};
+use serde::Serialize;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobPropertyBag, File, HtmlDocument, HtmlElement, HtmlInputElement, Url};
+
+/// The MIME type of article audio, unless the server transcoded it to something else
+pub const MP3_MIME_TYPE: &str = "audio/mp3";
 
 /// Converts bytes to a blob with the given MIME type
-pub fn bytes_to_mp3_blob(bytes: &[u8]) -> Blob {
+pub fn bytes_to_blob(bytes: &[u8], mime_type: &str) -> Blob {
     let arr = js_sys::Uint8Array::from(bytes);
 
     // A blob is made from an array of arrays. So construct [bytes] and use that.
     let parts = js_sys::Array::new();
     parts.set(0, JsValue::from(arr));
-    Blob::new_with_u8_array_sequence_and_options(
-        &parts,
-        BlobPropertyBag::new().type_(MP3_MIME_TYPE),
-    )
-    .unwrap()
+    Blob::new_with_u8_array_sequence_and_options(&parts, BlobPropertyBag::new().type_(mime_type))
+        .unwrap()
+}
+
+/// Joins the given blobs into one blob with the given MIME type. The browser refers to the parts
+/// rather than copying them, so this is cheap even for long audio.
+pub fn join_blobs(parts: &[Blob], mime_type: &str) -> Blob {
+    let seq: js_sys::Array = parts.iter().collect();
+    Blob::new_with_blob_sequence_and_options(&seq, BlobPropertyBag::new().type_(mime_type)).unwrap()
 }
 
-/// Runs the given closure after `millis` milliseconds
-pub fn run_after_delay(closure: &Closure<dyn Fn()>, millis: i32) {
-    let win = gloo_utils::window();
-    let func = closure.as_ref().unchecked_ref();
-    if let Err(e) = win.set_timeout_with_callback_and_timeout_and_arguments_0(func, millis) {
-        tracing::error!("Could not set timeout with callback: {:?}", e);
+/// The cookie the server keeps the CSRF token in, when accounts are on
+const CSRF_COOKIE: &str = "rtms_csrf";
+
+/// A request to a server. It's sent under the simulated network condition, if one's set, so every
+/// request to a server can be tried offline. See `network_sim.rs`.
+pub struct ApiRequest(Request);
+
+impl ApiRequest {
+    /// Sets the given header
+    pub fn header(self, key: &str, value: &str) -> Self {
+        ApiRequest(self.0.header(key, value))
+    }
+
+    /// Sets the body to the given value, as JSON
+    pub fn json<T: Serialize + ?Sized>(self, value: &T) -> Result<Self, gloo_net::Error> {
+        self.0.json(value).map(ApiRequest)
+    }
+
+    /// Sets the body
+    pub fn body(self, body: impl Into<JsValue>) -> Self {
+        ApiRequest(self.0.body(body))
+    }
+
+    /// Sets the method
+    pub fn method(self, method: Method) -> Self {
+        ApiRequest(self.0.method(method))
+    }
+
+    /// Sends the request
+    pub async fn send(self) -> Result<Response, gloo_net::Error> {
+        network_sim::simulate().await?;
+        self.0.send().await
     }
 }
 
-// Wraps an HTTP response so that the given function runs every time a chunk is read. This pattern
-// was taken from
-// https://github.com/AnthumChris/fetch-progress-indicators/blob/efaaaf073bc6927a803e5963a92ba9b11a585cc0/fetch-basic/supported-browser.js
-pub(crate) fn response_with_read_callback<F: 'static + FnMut(Uint8Array)>(
-    resp: gloo_net::http::Response,
-    mut callback: F,
-) -> Result<gloo_net::http::Response, AnyError> {
-    // Extract the reader from the given response
-    let reader: ReadableStreamDefaultReader = resp
-        .body()
-        .ok_or(AnyError::msg("could not get repsonse body"))?
-        .get_reader()
-        .unchecked_into();
-
-    // Define the start() function for our custom ReadableStream. This is all that's necessary to
-    // define.
-    let start_cb = Closure::once(move |controller: ReadableStreamDefaultController| {
-        spawn_local(async move {
-            loop {
-                // Every read() operation returns a Promise to (done, chunk), where `done` marks
-                // that the stream has terminated and `chunk` is the incoming bytes, if `done` is
-                // not set.
-                let chunk_promise = reader.read();
-                let chunk = match JsFuture::from(chunk_promise).await {
-                    Ok(c) => c,
-                    Err(e) => {
-                        let _ = ReadableStreamDefaultController::error_with_e(&controller, &e);
-                        break;
-                    }
-                };
-
-                // True iff this stream is finished
-                let done = js_sys::Reflect::get(&chunk, &JsValue::from_str("done")).unwrap()
-                    == JsValue::TRUE;
-
-                // If the stream is done, close it out and return
-                if done {
-                    let _ = ReadableStreamDefaultController::close(&controller);
-                    break;
-                } else {
-                    // If there's another chunk to process get it and append it to the queue
-                    let chunk = js_sys::Reflect::get(&chunk, &JsValue::from_str("value")).unwrap();
-                    match controller.enqueue_with_chunk(&chunk) {
-                        // If appending the chunk fails, abort
-                        Err(e) => {
-                            tracing::error!("could not enqueue chunk: {:?}", e);
-                            break;
-                        }
-                        _ => (),
-                    }
-
-                    // Treat the chunk as an array and call the callback
-                    let chunk = chunk.unchecked_into::<Uint8Array>();
-                    callback(chunk);
-                }
+/// Returns the given request with the active server's credentials. The server the app was loaded
+/// from knows who's signed in from its cookies, so only other servers get their session token.
+pub fn authorize(req: Request) -> ApiRequest {
+    match servers::active().and_then(|server| server.token) {
+        Some(token) => ApiRequest(req.header("Authorization", &format!("Bearer {token}"))),
+        None => ApiRequest(req),
+    }
+}
+
+/// Returns a GET request to the given API path on the active server
+pub fn get(path: &str) -> ApiRequest {
+    authorize(Request::get(&servers::api_url(path)))
+}
+
+/// Returns a POST request to the given API path on the active server. When accounts are on, it
+/// carries the CSRF token the server wants with requests that change things.
+pub fn post(path: &str) -> ApiRequest {
+    let req = authorize(Request::post(&servers::api_url(path)));
+    let cookies = gloo_utils::document()
+        .dyn_into::<HtmlDocument>()
+        .ok()
+        .and_then(|doc| doc.cookie().ok())
+        .unwrap_or_default();
+    let token = cookies.split(';').find_map(|cookie| {
+        let (name, value) = cookie.trim().split_once('=')?;
+        (name == CSRF_COOKIE).then(|| value.to_string())
+    });
+    match token {
+        Some(token) => req.header("X-CSRF-Token", &token),
+        None => req,
+    }
+}
+
+/// Runs the given closure after `millis` milliseconds, by the app's clock. Dropping the returned
+/// timer cancels it.
+pub(crate) fn run_after_delay(closure: impl FnOnce() + 'static, millis: u32) -> Timer {
+    clock::timeout(millis, closure)
+}
+
+/// Wraps the JS error from a method that returns one in an `AnyError`, with the given context
+pub(crate) fn wrap_jserror(context: &str, e: JsValue) -> AnyError {
+    anyhow!("{context}: {:?}", e)
+}
+
+/// Formats the given time in seconds since the Unix epoch as a local date
+pub(crate) fn format_date(t: u64) -> String {
+    js_sys::Date::new(&JsValue::from_f64(t as f64 * 1000.0))
+        .to_locale_date_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+/// Returns the error message the server responded with, or the status if it didn't give one
+pub async fn resp_error(resp: Response) -> String {
+    match resp.text().await {
+        Ok(text) if !text.is_empty() => text,
+        _ => format!("{} ({})", resp.status(), resp.status_text()),
+    }
+}
+
+/// Has the browser save the given text as a file with the given name and MIME type
+pub fn save_file(filename: &str, text: &str, mime_type: &str) -> Result<(), AnyError> {
+    let blob = bytes_to_blob(text.as_bytes(), mime_type);
+    let url = Url::create_object_url_with_blob(&blob)
+        .map_err(|e| anyhow!("Couldn't make blob URL: {:?}", e))?;
+    let link = gloo_utils::document()
+        .create_element("a")
+        .map_err(|e| anyhow!("Couldn't make link: {:?}", e))?
+        .unchecked_into::<HtmlElement>();
+    let _ = link.set_attribute("href", &url);
+    let _ = link.set_attribute("download", filename);
+    link.click();
+    let _ = Url::revoke_object_url(&url);
+    Ok(())
+}
+
+/// Returns a file name made from the given title and extension, e.g., `Some Title.md`. Characters
+/// that file systems don't allow are replaced.
+pub fn file_name(title: &str, extension: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| {
+            if c.is_control() || "<>:\"/\\|?*".contains(c) {
+                '_'
+            } else {
+                c
             }
         })
-    });
+        .collect();
+    format!("{}.{extension}", name.trim_end_matches(['.', ' ']))
+}
+
+/// Returns the file picked in the file input with the given ID, if any
+pub fn picked_file(input_id: &str) -> Option<File> {
+    gloo_utils::document()
+        .get_element_by_id(input_id)?
+        .dyn_into::<HtmlInputElement>()
+        .ok()?
+        .files()?
+        .get(0)
+}
 
-    // Make an object { "start": start_cb }
-    let readable_stream_callbacks = js_sys::Object::default();
-    js_sys::Reflect::set(
-        readable_stream_callbacks.as_ref(),
-        &JsValue::from_str("start"),
-        start_cb.as_ref(),
-    )
-    .unwrap();
-
-    // Make a new ReadableStream with the above callback object
-    let readable_stream =
-        ReadableStream::new_with_underlying_source(&readable_stream_callbacks).unwrap();
-
-    // Finally make a Response out of the above ReadableStream
-    let resp = {
-        let raw = web_sys::Response::new_with_opt_readable_stream(Some(&readable_stream)).unwrap();
-        gloo_net::http::Response::from_raw(raw)
-    };
-
-    Ok(resp)
+/// Reads the given file as text
+pub async fn file_text(file: &File) -> Result<String, AnyError> {
+    JsFuture::from(file.text())
+        .await
+        .ok()
+        .and_then(|text| text.as_string())
+        .ok_or_else(|| anyhow!("Couldn't read {}", file.name()))
 }
@@ -0,0 +1,300 @@
+use crate::utils::{self, ApiRequest};
+use common::{VoiceInfo, VoicePreviewSubmission, MAX_PREVIEW_CHARS};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use wasm_bindgen::JsValue;
+use web_sys::Url;
+use yew::prelude::*;
+
+const SAMPLE_TEXT_ID: &str = "voice-compare-text";
+const VOICE_KEY_ID: &str = "voice-compare-key";
+
+/// The localStorage key under which the user's custom voice key is saved
+const VOICE_KEY_STORAGE_KEY: &str = "readtomyshoe-voice-key";
+
+/// The header the server looks for custom voice keys in
+const VOICE_KEY_HEADER: &str = "X-Voice-Key";
+
+/// The number of voices that can be compared side by side
+const NUM_COMPARED_VOICES: usize = 3;
+
+/// The default text to synthesize
+const DEFAULT_SAMPLE_TEXT: &str =
+    "Mr James Duffy lived in Chapelizod because he wished to live as \
+    far as possible from the city of which he was a citizen and because he found all the other \
+    suburbs of Dublin mean, modern and pretentious.";
+
+/// Returns the DOM ID of the voice selector in the given column
+fn voice_selector_id(column: usize) -> String {
+    format!("voice-compare-select-{column}")
+}
+
+/// Returns the custom voice key the user saved, if any
+fn saved_voice_key() -> Option<String> {
+    let storage = gloo_utils::window().local_storage().ok().flatten()?;
+    storage
+        .get_item(VOICE_KEY_STORAGE_KEY)
+        .ok()
+        .flatten()
+        .filter(|k| !k.is_empty())
+}
+
+/// Saves the user's custom voice key. An empty key clears it.
+fn save_voice_key(key: &str) {
+    if let Some(storage) = gloo_utils::window().local_storage().ok().flatten() {
+        let res = if key.is_empty() {
+            storage.remove_item(VOICE_KEY_STORAGE_KEY)
+        } else {
+            storage.set_item(VOICE_KEY_STORAGE_KEY, key)
+        };
+        if let Err(e) = res {
+            tracing::error!("Couldn't save voice key: {:?}", e);
+        }
+    }
+}
+
+/// Attaches the user's custom voice key to the request, if they have one
+pub(crate) fn with_voice_key(req: ApiRequest) -> ApiRequest {
+    match saved_voice_key() {
+        Some(key) => req.header(VOICE_KEY_HEADER, &key),
+        None => req,
+    }
+}
+
+/// Fetches the list of voices the server offers
+pub(crate) async fn fetch_voices() -> Result<Vec<VoiceInfo>, AnyError> {
+    let resp = with_voice_key(utils::get("/api/list-voices"))
+        .send()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error fetching voice list"))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error fetching voice list {} ({})",
+            resp.status(),
+            resp.status_text()
+        );
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| AnyError::from(e).context("Error parsing voice list JSON"))
+}
+
+/// Asks the server to speak the given text in the given voice, and returns a URL to the resulting
+/// audio
+async fn fetch_preview(submission: &VoicePreviewSubmission) -> Result<String, AnyError> {
+    let endpoint = "/api/preview-voice";
+    let resp = with_voice_key(utils::post(endpoint))
+        .json(&submission)?
+        .send()
+        .await
+        .map_err(|e| anyhow!("Error POSTing to {endpoint}: {}", e))?;
+
+    if !resp.ok() {
+        bail!(
+            "Error previewing voice {}. {}. {}",
+            submission.voice,
+            resp.status_text(),
+            resp.text().await.unwrap_or("".to_string())
+        );
+    }
+
+    // Make a URL for the audio so it can be played in an <audio> element
+    let audio = resp
+        .binary()
+        .await
+        .map_err(|e| anyhow!("Error parsing audio binary: {e}"))?;
+    let blob = utils::bytes_to_blob(&audio, utils::MP3_MIME_TYPE);
+    Url::create_object_url_with_blob(&blob).map_err(|e| anyhow!("Couldn't make blob URL: {:?}", e))
+}
+
+/// Retrives the value of the element with the given ID
+fn get_elem_value(id: &str) -> String {
+    let doc = gloo_utils::document();
+    let elem = doc.get_element_by_id(id).unwrap();
+    js_sys::Reflect::get(&elem, &JsValue::from_str("value"))
+        .unwrap()
+        .as_string()
+        .unwrap()
+}
+
+/// A panel for synthesizing the same text in a few voices and listening to them side by side
+#[derive(Default)]
+pub(crate) struct VoiceComparison {
+    /// The voices offered by the server
+    voices: Vec<VoiceInfo>,
+    /// For each column, the voice that was synthesized and the blob URL of its audio
+    previews: Vec<Option<(String, String)>>,
+    err: Option<AnyError>,
+}
+
+pub(crate) enum VoiceCompareMsg {
+    /// Fetches the list of voices the server offers
+    FetchVoices,
+    /// Sets the list of voices the server offers
+    SetVoices(Vec<VoiceInfo>),
+    /// Saves the custom voice key in the key field and refetches the voice list
+    SaveVoiceKey,
+    /// Synthesizes the sample text with every selected voice
+    Synthesize,
+    /// Sets the audio of the given column to the given blob URL
+    SetPreview {
+        column: usize,
+        voice: String,
+        url: String,
+    },
+    /// Sets the error display to the given error
+    SetError(AnyError),
+}
+
+impl Component for VoiceComparison {
+    type Message = VoiceCompareMsg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        // Get the voice list
+        ctx.link().send_message(VoiceCompareMsg::FetchVoices);
+
+        VoiceComparison {
+            previews: vec![None; NUM_COMPARED_VOICES],
+            ..Default::default()
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            VoiceCompareMsg::FetchVoices => {
+                // Kick off a future to get the voice list
+                ctx.link().send_future(async move {
+                    match fetch_voices().await {
+                        Ok(voices) => VoiceCompareMsg::SetVoices(voices),
+                        Err(e) => VoiceCompareMsg::SetError(e),
+                    }
+                });
+                return false;
+            }
+
+            VoiceCompareMsg::SetVoices(voices) => {
+                self.voices = voices;
+            }
+
+            VoiceCompareMsg::SaveVoiceKey => {
+                // The key may unlock (or lock) custom voices, so refetch the list
+                save_voice_key(get_elem_value(VOICE_KEY_ID).trim());
+                ctx.link().send_message(VoiceCompareMsg::FetchVoices);
+                return false;
+            }
+
+            VoiceCompareMsg::Synthesize => {
+                self.err = None;
+                let text = get_elem_value(SAMPLE_TEXT_ID);
+
+                // Fire off all the previews at once. Each column updates as soon as its audio
+                // arrives
+                for column in 0..NUM_COMPARED_VOICES {
+                    let voice = get_elem_value(&voice_selector_id(column));
+                    let submission = VoicePreviewSubmission {
+                        text: text.clone(),
+                        voice: voice.clone(),
+                    };
+                    ctx.link().send_future(async move {
+                        match fetch_preview(&submission).await {
+                            Ok(url) => VoiceCompareMsg::SetPreview { column, voice, url },
+                            Err(e) => VoiceCompareMsg::SetError(e),
+                        }
+                    });
+                }
+            }
+
+            VoiceCompareMsg::SetPreview { column, voice, url } => {
+                // Free the old audio, if any
+                if let Some((_, old_url)) = self.previews[column].replace((voice, url)) {
+                    let _ = Url::revoke_object_url(&old_url);
+                }
+            }
+
+            VoiceCompareMsg::SetError(e) => {
+                self.err = Some(e);
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let synthesize_cb = ctx.link().callback(|_| VoiceCompareMsg::Synthesize);
+        let save_key_cb = ctx.link().callback(|_| VoiceCompareMsg::SaveVoiceKey);
+
+        // Render a voice selector and audio player for every column. The ith column defaults to
+        // the ith voice.
+        let columns = (0..NUM_COMPARED_VOICES)
+            .map(|column| {
+                let selector_id = voice_selector_id(column);
+                let options = self
+                    .voices
+                    .iter()
+                    .enumerate()
+                    .map(|(i, voice)| {
+                        html! {
+                            <option value={ voice.name.clone() } selected={ i == column }>
+                                { format!("{} ({})", voice.description, voice.name) }
+                                { if voice.custom { " ★" } else { "" } }
+                            </option>
+                        }
+                    })
+                    .collect::<Html>();
+
+                let audio = match &self.previews[column] {
+                    Some((voice, url)) => html! {
+                        <audio controls=true src={ url.clone() } aria-label={ voice.clone() } />
+                    },
+                    None => Html::default(),
+                };
+
+                html! {
+                    <div class="field">
+                        <label for={ selector_id.clone() }>{ format!("Voice {}:", column + 1) }</label>
+                        <select id={ selector_id }>{ options }</select>
+                        { audio }
+                    </div>
+                }
+            })
+            .collect::<Html>();
+
+        let err_str = self
+            .err
+            .as_ref()
+            .map(|e| format!("{}", e))
+            .unwrap_or("".to_string());
+
+        html! {
+            <fieldset>
+                <legend><h2>{ "Compare voices" }</h2></legend>
+                <p>{ "Hear the same text in different voices to help pick the one you like." }</p>
+                <div class="field">
+                    <label for={SAMPLE_TEXT_ID}>{ "Sample text:" }</label>
+                    <textarea
+                        id={SAMPLE_TEXT_ID}
+                        rows="4"
+                        cols="33"
+                        maxlength={MAX_PREVIEW_CHARS.to_string()}
+                        value={DEFAULT_SAMPLE_TEXT}
+                    />
+                </div>
+                <div class="field">
+                    <label for={VOICE_KEY_ID}>{ "Custom voice key:" }</label>
+                    <input
+                        type="password"
+                        id={VOICE_KEY_ID}
+                        value={ saved_voice_key().unwrap_or_default() }
+                        onchange={save_key_cb}
+                    />
+                </div>
+                { columns }
+                <button type="submit" onclick={synthesize_cb}>{ "Synthesize" }</button>
+                <p role="alert" style={ "color: red;" }>{ err_str }</p>
+            </fieldset>
+        }
+    }
+}
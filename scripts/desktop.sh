@@ -0,0 +1,33 @@
+#!/usr/bin/env bash
+# Builds the desktop app: the frontend with its `desktop` feature, the server, and then the Tauri
+# bundle that carries both, and Piper, if it's in desktop/piper/. Needs trunk and the Tauri CLI,
+# i.e., `cargo install tauri-cli --version "^2"`.
+set -euo pipefail
+IFS=$'\n\t'
+
+# Trunk only takes cargo features from index.html, so the desktop feature is put there for the
+# build, then taken out again
+index="$PWD/frontend/index.html"
+cp "$index" "$index.orig"
+trap 'mv "$index.orig" "$index"' EXIT
+pushd frontend
+perl -pi -e 's|</head>|  <link data-trunk rel="rust" data-cargo-features="desktop" />\n  </head>|' \
+    index.html
+RUSTFLAGS=--cfg=web_sys_unstable_apis trunk build --release --public-url /assets/ \
+    --dist ../desktop/dist
+popd
+
+# Tauri wants sidecar programs named for the platform they run on
+cargo build --release -p readtomyshoe-server
+target_triple=$(rustc -vV | sed -n 's/^host: //p')
+exe_suffix=""
+if [[ "$target_triple" == *windows* ]]; then
+    exe_suffix=".exe"
+fi
+mkdir -p desktop/binaries
+cp "target/release/readtomyshoe-server$exe_suffix" \
+    "desktop/binaries/readtomyshoe-server-$target_triple$exe_suffix"
+
+pushd desktop
+cargo tauri build
+popd
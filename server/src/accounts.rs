@@ -0,0 +1,1493 @@
+//! Accounts, so one server can host a library for each member of, e.g., a family. Without
+//! `--accounts`, everyone shares one library and nobody signs in.
+//!
+//! With `--accounts`, people sign up and sign in with a name and password, and get a session cookie.
+//! Everything under `/api/`, `/audio/`, and `/mini` needs a session, apart from signing in and the
+//! token-protected feeds. Each account's articles and audio are kept in their own directory under
+//! `users/` in the audio blob directory. Handlers get that directory with the `LibraryDir`
+//! extractor.
+//!
+//! Since the session is a cookie, other sites could get a browser to make requests with it. To stop
+//! them, requests that change things also need the `X-CSRF-Token` header, set to the value of the
+//! `rtms_csrf` cookie. Other sites can't read that cookie, so they can't set the header. The
+//! token is derived from the session token, so it's only good for its session. Form posts from
+//! `/mini`, which has no JavaScript to set the header, are let through instead if their `Origin` is
+//! this server.
+//!
+//! Clients on other sites, e.g., the app installed from one server and pointed at another, can't
+//! use the cookies. They ask for the session token when signing in, and send it in an
+//! `Authorization: Bearer` header instead. Browsers never add that header on their own, so those
+//! requests don't need the CSRF token.
+//!
+//! A device that's signed in can pair another one, e.g., a phone, by showing a QR code of a link to
+//! the app with a one-time pairing code. Opening it, and tapping the button there, signs the new
+//! device in to the same account, without typing anything. Codes only work for a few minutes, and
+//! are only kept in memory.
+//!
+//! To slow down password guessing, an account is locked for a while after a few failed sign-ins in
+//! a row, and so is an address that's failed a lot, whichever accounts it tried. These are only
+//! kept in memory, so a restart lets everyone off. Accounts can also turn on two-factor
+//! authentication, in which case signing in needs a code from an authenticator app too. See
+//! `totp.rs`.
+//!
+//! An account can be deleted, which deletes its library and ends its sessions too. That needs the
+//! password, and the two-factor code if it's on. Everything in the library can be downloaded first,
+//! with `/api/takeout`. See `export.rs`.
+
+use crate::{
+    admin::DiskManager,
+    config::{CookieConfig, SameSite},
+    json_store::JsonStore,
+    qr, totp,
+    util::{escape_xml, now_secs},
+};
+use common::{
+    AccountDeletion, AccountStatus, Credentials, PairingCode, PairingRequest, SessionToken,
+    TotpCode, TotpSetup,
+};
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, Extension, Form, FromRequest, Query, RequestParts},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{AppendHeaders, Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use openssl::{hash::MessageDigest, memcmp, pkcs5::pbkdf2_hmac, rand::rand_bytes};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The name of the session cookie
+const SESSION_COOKIE: &str = "rtms_session";
+
+/// The name of the cookie that holds the CSRF token. Unlike the session cookie, scripts can read
+/// it.
+const CSRF_COOKIE: &str = "rtms_csrf";
+
+/// The header requests that change things put the CSRF token in
+const CSRF_HEADER: &str = "x-csrf-token";
+
+/// How long a session lasts, in seconds
+const SESSION_LIFETIME_SECS: u64 = 90 * 24 * 60 * 60;
+
+/// How long a pairing code works for, in seconds
+const PAIRING_LIFETIME_SECS: u64 = 10 * 60;
+
+/// The number of PBKDF2 rounds passwords are hashed with
+const PBKDF2_ITERATIONS: usize = 200_000;
+
+/// The shortest password that's accepted
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// How many failed sign-ins in a row an account can have before it's locked
+const MAX_ACCOUNT_FAILURES: u32 = 5;
+
+/// How many failed sign-ins in a row an address can make before it's locked out. This is higher
+/// than the limit for an account, since a household's devices may all have the same address.
+const MAX_ADDR_FAILURES: u32 = 20;
+
+/// How long an account or address is locked for, in seconds
+const LOCKOUT_SECS: u64 = 15 * 60;
+
+/// The longest account name that's accepted. Names are directory names, so they're kept short.
+const MAX_NAME_LEN: usize = 32;
+
+/// The directory, under the audio blob directory, that each account's library is kept in
+const USERS_DIR: &str = "users";
+
+/// The path prefixes that need a session when accounts are on
+const PROTECTED_PREFIXES: &[&str] = &["/api/", "/audio/", "/mini"];
+
+/// The routes under the protected prefixes that don't need a session. The feeds have their own
+/// tokens, since podcast and calendar apps can't sign in.
+const PUBLIC_ROUTES: &[&str] = &[
+    "/api/account",
+    "/api/signup",
+    "/api/login",
+    "/api/logout",
+    "/api/podcast.xml",
+    "/api/podcast-audio.mp3",
+    "/api/calendar.ics",
+];
+
+/// An account, as it's saved
+#[derive(Clone, Serialize, Deserialize)]
+struct Account {
+    name: String,
+    /// The PBKDF2-SHA256 hash of the password, as `iterations$salt$hash`, in base64
+    password_hash: String,
+    /// The two-factor authentication secret, in base32, if it's on
+    #[serde(default)]
+    totp_secret: Option<String>,
+    /// The secret that's being set up, until a code from it turns two-factor authentication on
+    #[serde(default)]
+    pending_totp_secret: Option<String>,
+    /// The time step of the last two-factor code used, so a code can't be used twice
+    #[serde(default)]
+    totp_last_step: u64,
+}
+
+/// A session, as it's saved. Only a hash of the token is kept, so the file doesn't give anyone a
+/// way in.
+#[derive(Clone, Serialize, Deserialize)]
+struct Session {
+    name: String,
+    /// When the session ends, in seconds since the Unix epoch
+    expires: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct AccountsFile {
+    accounts: Vec<Account>,
+    /// The sessions, keyed by the SHA-256 of their token, in base64
+    sessions: HashMap<String, Session>,
+}
+
+/// The accounts and their sessions. These are saved as JSON.
+#[derive(Clone)]
+pub(crate) struct AccountStore {
+    /// The code people have to give to sign up, if any
+    signup_code: Option<String>,
+    /// The attributes of the cookies that are set
+    cookies: CookieConfig,
+    /// The accounts that can manage the server
+    admins: Arc<Vec<String>>,
+    file: JsonStore<AccountsFile>,
+    /// The recent failed sign-ins, keyed by `name:` and the account name, or `addr:` and the address
+    failures: Arc<Mutex<HashMap<String, Failures>>>,
+    /// The unused pairing codes, keyed by their SHA-256 like sessions
+    pairings: Arc<Mutex<HashMap<String, Session>>>,
+}
+
+/// The failed sign-ins in a row for an account or an address
+#[derive(Clone, Copy, Default)]
+struct Failures {
+    count: u32,
+    /// When the last one was, in seconds since the Unix epoch
+    last: u64,
+}
+
+/// The error for sign-ins that are turned away because of too many failed ones
+#[derive(Debug)]
+struct LockedOut;
+
+impl fmt::Display for LockedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many failed sign-ins. Try again in {} minutes.",
+            LOCKOUT_SECS / 60
+        )
+    }
+}
+
+impl std::error::Error for LockedOut {}
+
+/// The account a request was made by. The session middleware puts this in the request's extensions.
+#[derive(Clone)]
+struct User(String);
+
+/// The session a request was made in, as the hash of its token that it's saved under. The session
+/// middleware puts this in the request's extensions along with `User`.
+#[derive(Clone)]
+pub(crate) struct SessionHash(pub(crate) String);
+
+/// Hashes the given session token for saving
+fn hash_token(token: &str) -> String {
+    base64::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Returns the CSRF token that goes with the given session token
+fn csrf_token(session_token: &str) -> String {
+    base64::encode_config(
+        Sha256::digest(format!("csrf:{session_token}").as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// Hashes the given password with a new random salt
+fn hash_password(password: &str) -> Result<String, AnyError> {
+    let mut salt = [0u8; 16];
+    rand_bytes(&mut salt)?;
+    let mut hash = [0u8; 32];
+    pbkdf2_hmac(
+        password.as_bytes(),
+        &salt,
+        PBKDF2_ITERATIONS,
+        MessageDigest::sha256(),
+        &mut hash,
+    )?;
+    Ok(format!(
+        "{PBKDF2_ITERATIONS}${}${}",
+        base64::encode(salt),
+        base64::encode(hash)
+    ))
+}
+
+/// Returns a hash that no password has, with as many rounds as a real one. It's checked against
+/// when there's no such account, so a missing account takes as long to turn away as a wrong
+/// password does, and doesn't give away which names have accounts.
+fn dummy_password_hash() -> String {
+    format!(
+        "{PBKDF2_ITERATIONS}${}${}",
+        base64::encode([0u8; 16]),
+        base64::encode([0u8; 32])
+    )
+}
+
+/// Returns whether the given password has the given hash
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    let mut parts = password_hash.split('$');
+    let (Some(iterations), Some(salt), Some(expected)) = (
+        parts.next().and_then(|i| i.parse().ok()),
+        parts.next().and_then(|s| base64::decode(s).ok()),
+        parts.next().and_then(|h| base64::decode(h).ok()),
+    ) else {
+        return false;
+    };
+
+    let mut hash = vec![0u8; expected.len()];
+    pbkdf2_hmac(
+        password.as_bytes(),
+        &salt,
+        iterations,
+        MessageDigest::sha256(),
+        &mut hash,
+    )
+    .is_ok()
+        && !expected.is_empty()
+        && memcmp::eq(&hash, &expected)
+}
+
+/// Checks that the given account name can be used as a directory name
+fn check_name(name: &str) -> Result<(), AnyError> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        bail!("Names have to be 1 to {MAX_NAME_LEN} characters long");
+    }
+    if !name
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+    {
+        bail!("Names can only have lowercase letters, digits, '-', and '_'");
+    }
+    Ok(())
+}
+
+impl AccountStore {
+    /// Loads the accounts from the given file. If the file doesn't exist, there are no accounts yet.
+    pub(crate) fn load(
+        path: &str,
+        signup_code: Option<String>,
+        cookies: CookieConfig,
+        admins: Vec<String>,
+    ) -> Result<AccountStore, AnyError> {
+        Ok(AccountStore {
+            signup_code,
+            cookies,
+            admins: Arc::new(admins),
+            file: JsonStore::load(path, "accounts")?,
+            failures: Arc::default(),
+            pairings: Arc::default(),
+        })
+    }
+
+    /// Runs the given function on the accounts, and saves them afterwards
+    async fn update<T>(&self, f: impl FnOnce(&mut AccountsFile) -> T) -> Result<T, AnyError> {
+        self.file.update(f).await
+    }
+
+    /// Starts a session for the given account, and returns its token
+    async fn start_session(&self, name: &str) -> Result<String, AnyError> {
+        let mut token = [0u8; 32];
+        rand_bytes(&mut token)?;
+        let token = base64::encode_config(token, base64::URL_SAFE_NO_PAD);
+
+        let session = Session {
+            name: name.to_string(),
+            expires: now_secs() + SESSION_LIFETIME_SECS,
+        };
+        self.update(|file| {
+            // Clear out the expired sessions while we're here
+            let now = now_secs();
+            file.sessions.retain(|_, s| s.expires > now);
+            file.sessions.insert(hash_token(&token), session);
+        })
+        .await?;
+        Ok(token)
+    }
+
+    /// Makes a one-time code that signs a new device in to the given account, and returns it
+    fn start_pairing(&self, name: &str) -> Result<String, AnyError> {
+        let mut code = [0u8; 32];
+        rand_bytes(&mut code)?;
+        let code = base64::encode_config(code, base64::URL_SAFE_NO_PAD);
+
+        let mut pairings = self.pairings.lock().unwrap();
+        let now = now_secs();
+        pairings.retain(|_, p| p.expires > now);
+        pairings.insert(
+            hash_token(&code),
+            Session {
+                name: name.to_string(),
+                expires: now + PAIRING_LIFETIME_SECS,
+            },
+        );
+        Ok(code)
+    }
+
+    /// Uses up the given pairing code, and returns a session token for its account
+    async fn redeem_pairing(&self, code: &str) -> Result<String, AnyError> {
+        let pairing = self.pairings.lock().unwrap().remove(&hash_token(code));
+        match pairing {
+            Some(p) if p.expires > now_secs() => self.start_session(&p.name).await,
+            _ => bail!("This pairing link has expired or been used. Make a new one."),
+        }
+    }
+
+    /// Makes a new account, and returns a session token for it
+    async fn sign_up(&self, creds: &Credentials) -> Result<String, AnyError> {
+        if let Some(code) = &self.signup_code {
+            let given = creds.signup_code.as_deref().unwrap_or_default();
+            if given.len() != code.len() || !memcmp::eq(given.as_bytes(), code.as_bytes()) {
+                bail!("The signup code is wrong");
+            }
+        }
+        check_name(&creds.name)?;
+        if creds.password.chars().count() < MIN_PASSWORD_LEN {
+            bail!("Passwords have to be at least {MIN_PASSWORD_LEN} characters long");
+        }
+
+        let account = Account {
+            name: creds.name.clone(),
+            password_hash: hash_password(&creds.password)?,
+            totp_secret: None,
+            pending_totp_secret: None,
+            totp_last_step: 0,
+        };
+        self.update(|file| {
+            if file.accounts.iter().any(|a| a.name == account.name) {
+                bail!("That name is taken");
+            }
+            file.accounts.push(account);
+            Ok(())
+        })
+        .await??;
+        self.start_session(&creds.name).await
+    }
+
+    /// Returns a `LockedOut` error if the given account or address has failed too often lately
+    fn check_lockout(&self, key: &str, max_failures: u32) -> Result<(), AnyError> {
+        match self.failures.lock().unwrap().get(key) {
+            Some(f) if f.count >= max_failures && f.last + LOCKOUT_SECS > now_secs() => {
+                Err(LockedOut.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Notes a failed sign-in for each of the given accounts and addresses
+    fn record_failure(&self, keys: &[&str]) {
+        let now = now_secs();
+        let mut failures = self.failures.lock().unwrap();
+        // Forget the ones that have been quiet long enough to be let off
+        failures.retain(|_, f| f.last + LOCKOUT_SECS > now);
+        for key in keys {
+            let f = failures.entry(key.to_string()).or_default();
+            f.count += 1;
+            f.last = now;
+        }
+    }
+
+    /// Checks the given two-factor code against the given account's secret. A code that's good is
+    /// used up.
+    async fn use_totp_code(&self, name: &str, code: &str) -> Result<bool, AnyError> {
+        self.update(|file| {
+            let account = file.accounts.iter_mut().find(|a| a.name == name);
+            let Some(account) = account.filter(|a| a.totp_secret.is_some()) else {
+                return Ok(false);
+            };
+            let secret = account.totp_secret.as_deref().unwrap_or_default();
+            match totp::verify(secret, code, now_secs())? {
+                Some(step) if step > account.totp_last_step => {
+                    account.totp_last_step = step;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        })
+        .await?
+    }
+
+    /// Checks the given name, password, and two-factor code, if the account needs one, and returns
+    /// a session token for the account. Sign-ins from the given address are counted towards its
+    /// lockout.
+    async fn sign_in(&self, creds: &Credentials, addr: IpAddr) -> Result<String, AnyError> {
+        let account_key = format!("name:{}", creds.name);
+        let addr_key = format!("addr:{addr}");
+        self.check_lockout(&account_key, MAX_ACCOUNT_FAILURES)?;
+        self.check_lockout(&addr_key, MAX_ADDR_FAILURES)?;
+
+        let account = self
+            .file
+            .read(|file| file.accounts.iter().find(|a| a.name == creds.name).cloned());
+        let password_hash = account
+            .as_ref()
+            .map_or_else(dummy_password_hash, |a| a.password_hash.clone());
+        let verified = verify_password(&creds.password, &password_hash);
+        let account = match account {
+            Some(a) if verified => a,
+            _ => {
+                self.record_failure(&[&account_key, &addr_key]);
+                bail!("The name or password is wrong");
+            }
+        };
+
+        if account.totp_secret.is_some() {
+            let code = creds.totp_code.as_deref().unwrap_or_default();
+            if code.trim().is_empty() {
+                bail!("Enter the code from your authenticator app");
+            }
+            if !self.use_totp_code(&creds.name, code).await? {
+                self.record_failure(&[&account_key, &addr_key]);
+                bail!("The two-factor code is wrong");
+            }
+        }
+
+        self.failures.lock().unwrap().remove(&account_key);
+        self.start_session(&creds.name).await
+    }
+
+    /// Deletes the given account and ends all its sessions, if the given password, and two-factor
+    /// code if it's on, are right. Wrong ones count towards the account's lockout, like sign-ins.
+    async fn delete_account(&self, name: &str, deletion: &AccountDeletion) -> Result<(), AnyError> {
+        let account_key = format!("name:{name}");
+        self.check_lockout(&account_key, MAX_ACCOUNT_FAILURES)?;
+
+        let account = self
+            .file
+            .read(|file| file.accounts.iter().find(|a| a.name == name).cloned());
+        let account = match account {
+            Some(a) if verify_password(&deletion.password, &a.password_hash) => a,
+            _ => {
+                self.record_failure(&[&account_key]);
+                bail!("The password is wrong");
+            }
+        };
+        if account.totp_secret.is_some() {
+            let code = deletion.totp_code.as_deref().unwrap_or_default();
+            if code.trim().is_empty() {
+                bail!("Enter the code from your authenticator app");
+            }
+            if !self.use_totp_code(name, code).await? {
+                self.record_failure(&[&account_key]);
+                bail!("The two-factor code is wrong");
+            }
+        }
+
+        self.update(|file| {
+            file.accounts.retain(|a| a.name != name);
+            file.sessions.retain(|_, session| session.name != name);
+        })
+        .await?;
+        self.pairings
+            .lock()
+            .unwrap()
+            .retain(|_, pairing| pairing.name != name);
+        self.failures.lock().unwrap().remove(&account_key);
+        Ok(())
+    }
+
+    /// Returns what's said about the given account, or about nobody being signed in
+    fn status(&self, name: Option<String>) -> AccountStatus {
+        AccountStatus {
+            accounts_enabled: true,
+            totp_enabled: name.as_deref().is_some_and(|n| self.totp_enabled(n)),
+            is_admin: name.as_deref().is_some_and(|n| self.is_admin(n)),
+            name,
+            demo: false,
+        }
+    }
+
+    /// Returns whether the given account can manage the server
+    fn is_admin(&self, name: &str) -> bool {
+        self.admins.iter().any(|admin| admin == name)
+    }
+
+    /// Returns whether the given account needs a two-factor code to sign in
+    fn totp_enabled(&self, name: &str) -> bool {
+        self.file.read(|file| {
+            file.accounts
+                .iter()
+                .any(|a| a.name == name && a.totp_secret.is_some())
+        })
+    }
+
+    /// Makes a new two-factor secret for the given account. It isn't used until it's confirmed
+    /// with `enable_totp`.
+    async fn start_totp_setup(&self, name: &str) -> Result<TotpSetup, AnyError> {
+        let secret = totp::new_secret()?;
+        self.update(|file| {
+            let account = file
+                .accounts
+                .iter_mut()
+                .find(|a| a.name == name)
+                .ok_or_else(|| anyhow!("There's no account named {name:?}"))?;
+            if account.totp_secret.is_some() {
+                bail!("Two-factor authentication is already on");
+            }
+            account.pending_totp_secret = Some(secret.clone());
+            Ok(())
+        })
+        .await??;
+        Ok(TotpSetup {
+            uri: totp::provisioning_uri(&secret, name),
+            secret,
+        })
+    }
+
+    /// Turns on two-factor authentication for the given account, if the code is good for the
+    /// secret that's being set up
+    async fn enable_totp(&self, name: &str, code: &str) -> Result<(), AnyError> {
+        self.update(|file| {
+            let account = file
+                .accounts
+                .iter_mut()
+                .find(|a| a.name == name)
+                .ok_or_else(|| anyhow!("There's no account named {name:?}"))?;
+            let Some(secret) = account.pending_totp_secret.clone() else {
+                bail!("Two-factor authentication isn't being set up");
+            };
+            let Some(step) = totp::verify(&secret, code, now_secs())? else {
+                bail!(
+                    "The code is wrong. Check the time on the device with the authenticator app."
+                );
+            };
+            account.totp_secret = Some(secret);
+            account.pending_totp_secret = None;
+            account.totp_last_step = step;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Turns off two-factor authentication for the given account, if the code is good
+    async fn disable_totp(&self, name: &str, code: &str) -> Result<(), AnyError> {
+        if !self.use_totp_code(name, code).await? {
+            bail!("The code is wrong");
+        }
+        self.update(|file| {
+            if let Some(account) = file.accounts.iter_mut().find(|a| a.name == name) {
+                account.totp_secret = None;
+                account.totp_last_step = 0;
+            }
+        })
+        .await
+    }
+
+    /// Ends the session with the given token
+    async fn sign_out(&self, token: &str) -> Result<(), AnyError> {
+        self.update(|file| {
+            file.sessions.remove(&hash_token(token));
+        })
+        .await
+    }
+
+    /// Ends the session saved under the given hash, e.g., that of a device that was revoked
+    pub(crate) async fn end_session(&self, hash: &str) -> Result<(), AnyError> {
+        self.update(|file| {
+            file.sessions.remove(hash);
+        })
+        .await
+    }
+
+    /// Returns the name of the account the given session token is for, if it's good
+    fn session_user(&self, token: &str) -> Option<String> {
+        self.file.read(|file| {
+            file.sessions
+                .get(&hash_token(token))
+                .filter(|s| s.expires > now_secs())
+                .map(|s| s.name.clone())
+        })
+    }
+}
+
+/// Returns the value of the cookie with the given name in the given request headers, if any
+fn cookie_value(headers: &HeaderMap, cookie_name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == cookie_name).then(|| value.to_string())
+        })
+}
+
+/// Returns the session token in the `Authorization` header of the given request headers, if any
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Returns the session token in the given request headers, if any. A token in the `Authorization`
+/// header goes before the cookie.
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    bearer_token(headers)
+        .map(str::to_string)
+        .or_else(|| cookie_value(headers, SESSION_COOKIE))
+}
+
+/// Returns a `Set-Cookie` header that sets the given cookie to the given value, for as long as a
+/// session lasts. With no value, it clears the cookie.
+fn set_cookie(
+    config: &CookieConfig,
+    name: &str,
+    value: Option<&str>,
+    http_only: bool,
+) -> (header::HeaderName, HeaderValue) {
+    let same_site = match config.same_site {
+        SameSite::Lax => "Lax",
+        SameSite::Strict => "Strict",
+    };
+    let max_age = if value.is_some() {
+        SESSION_LIFETIME_SECS
+    } else {
+        0
+    };
+    let mut cookie = format!(
+        "{name}={}; Path=/; SameSite={same_site}; Max-Age={max_age}",
+        value.unwrap_or_default()
+    );
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if config.secure {
+        cookie.push_str("; Secure");
+    }
+    (header::SET_COOKIE, HeaderValue::from_str(&cookie).unwrap())
+}
+
+/// Returns the address the given request came from, given the address of the peer that sent it.
+/// If that's this machine, it's taken to be a reverse proxy, and the address is the last one in
+/// `X-Forwarded-For`, which the proxy added. Otherwise the header is ignored, since anyone can set
+/// it.
+fn client_addr(headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+    if !peer.is_loopback() {
+        return peer;
+    }
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .last()
+        .and_then(|addr| addr.trim().parse().ok())
+        .unwrap_or(peer)
+}
+
+/// Returns the `Set-Cookie` headers that set the session and CSRF cookies for the given session
+/// token. With no token, they clear the cookies.
+fn session_cookies(
+    config: &CookieConfig,
+    token: Option<&str>,
+) -> AppendHeaders<header::HeaderName, HeaderValue, 2> {
+    let csrf = token.map(csrf_token);
+    AppendHeaders([
+        set_cookie(config, SESSION_COOKIE, token, true),
+        set_cookie(config, CSRF_COOKIE, csrf.as_deref(), false),
+    ])
+}
+
+/// Returns the response to signing in with the given session token: the cookies, and the token
+/// itself if the client wants it
+fn signed_in(config: &CookieConfig, token: String, want_token: bool) -> Response {
+    let cookies = session_cookies(config, Some(&token));
+    if want_token {
+        (cookies, Json(SessionToken { token })).into_response()
+    } else {
+        cookies.into_response()
+    }
+}
+
+/// Returns the link a new device opens to pair with the app at the given URL. With a pairing code,
+/// it's the page that redeems it, and otherwise it's just the app.
+fn pairing_url(app_url: &str, code: Option<&str>) -> Result<String, AnyError> {
+    let app_url = app_url.trim().trim_end_matches('/');
+    let Some((scheme, rest)) = app_url.split_once("://") else {
+        bail!("{app_url:?} isn't a URL. It should look like http://192.168.1.5:9382");
+    };
+    if !matches!(scheme, "http" | "https") || rest.is_empty() || rest.contains(['?', '#']) {
+        bail!("{app_url:?} isn't an http:// or https:// URL without a query");
+    }
+    Ok(match code {
+        Some(code) => format!("{app_url}/pair?code={code}"),
+        None => format!("{app_url}/"),
+    })
+}
+
+/// Returns whether the given request, which changes things, came from this server's own pages. It
+/// has to have the CSRF token of the given session, or, failing that, an `Origin` that's this
+/// server. Requests with the session token in the `Authorization` header are let through, since
+/// other sites can't get browsers to send it.
+fn is_same_site(headers: &HeaderMap, session_token: &str) -> bool {
+    if bearer_token(headers) == Some(session_token) {
+        return true;
+    }
+    let expected = csrf_token(session_token);
+    if let Some(given) = headers.get(CSRF_HEADER).and_then(|v| v.to_str().ok()) {
+        return given.len() == expected.len() && memcmp::eq(given.as_bytes(), expected.as_bytes());
+    }
+
+    // Behind a reverse proxy, the host the browser used may only be in X-Forwarded-Host
+    let origin_host = headers
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, host)| host);
+    let Some(origin_host) = origin_host else {
+        return false;
+    };
+    [header::HOST.as_str(), "x-forwarded-host"]
+        .into_iter()
+        .filter_map(|name| headers.get(name)?.to_str().ok())
+        .any(|host| host.eq_ignore_ascii_case(origin_host))
+}
+
+/// The directory of the library the request is about: the account's own directory if someone's
+/// signed in, and the audio blob directory otherwise. This needs the audio blob directory as an
+/// `Extension<String>`, like the handlers that use it have always had.
+pub(crate) struct LibraryDir(pub(crate) String);
+
+/// Returns the library directory of the given account
+fn user_dir(audio_blob_dir: &str, name: &str) -> PathBuf {
+    Path::new(audio_blob_dir).join(USERS_DIR).join(name)
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for LibraryDir {
+    type Rejection = StatusCode;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let audio_blob_dir = req
+            .extensions()
+            .get::<String>()
+            .cloned()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let Some(User(name)) = req.extensions().get::<User>() else {
+            return Ok(LibraryDir(audio_blob_dir));
+        };
+
+        let dir = user_dir(&audio_blob_dir, name);
+        fs::create_dir_all(&dir).map_err(|e| {
+            tracing::error!("Couldn't make the library directory of {name}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        dir.into_os_string()
+            .into_string()
+            .map(LibraryDir)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// Who made the request, for handlers that say so, e.g., in an export. Without accounts, nobody's
+/// signed in.
+pub(crate) struct CurrentAccount(pub(crate) AccountStatus);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for CurrentAccount {
+    type Rejection = StatusCode;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Some(accounts) = req.extensions().get::<AccountStore>() else {
+            return Ok(CurrentAccount(AccountStatus::default()));
+        };
+        let name = req
+            .extensions()
+            .get::<User>()
+            .map(|User(name)| name.clone());
+        Ok(CurrentAccount(accounts.status(name)))
+    }
+}
+
+/// Proof that the request was made by someone who can manage the server. Extracting this turns away
+/// everyone else. Without accounts, everyone can, since they can already delete anything.
+pub(crate) struct Admin;
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Admin {
+    type Rejection = StatusCode;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Some(accounts) = req.extensions().get::<AccountStore>() else {
+            return Ok(Admin);
+        };
+        match req.extensions().get::<User>() {
+            Some(User(name)) if accounts.is_admin(name) => Ok(Admin),
+            _ => Err(StatusCode::FORBIDDEN),
+        }
+    }
+}
+
+/// Returns the directory of every library: the shared one, under `None`, and each account's, under
+/// its name
+pub(crate) fn library_dirs(
+    audio_blob_dir: &str,
+) -> Result<Vec<(Option<String>, PathBuf)>, AnyError> {
+    let mut dirs = vec![(None, PathBuf::from(audio_blob_dir))];
+    let users_dir = Path::new(audio_blob_dir).join(USERS_DIR);
+    if !users_dir.exists() {
+        return Ok(dirs);
+    }
+    for entry in fs::read_dir(users_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_dir() && check_name(&name).is_ok() {
+            dirs.push((Some(name.clone()), entry.path()));
+        }
+    }
+    Ok(dirs)
+}
+
+/// Returns the directory of the given account's library, or of the shared library if there's no
+/// account
+pub(crate) fn library_dir(audio_blob_dir: &str, name: Option<&str>) -> Result<PathBuf, AnyError> {
+    match name {
+        None => Ok(PathBuf::from(audio_blob_dir)),
+        Some(name) => {
+            check_name(name)?;
+            Ok(user_dir(audio_blob_dir, name))
+        }
+    }
+}
+
+/// Returns whether the given path needs a session when accounts are on
+fn needs_session(path: &str) -> bool {
+    PROTECTED_PREFIXES.iter().any(|p| path.starts_with(p)) && !PUBLIC_ROUTES.contains(&path)
+}
+
+/// Returns whether requests with the given method can change things
+fn is_unsafe_method(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Turns away requests that need a session and don't have one, and notes who made the ones that do.
+/// Requests that change things are turned away too if they came from another site.
+async fn require_session<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let token = session_token(req.headers());
+    let user = req
+        .extensions()
+        .get::<AccountStore>()
+        .zip(token.as_deref())
+        .and_then(|(accounts, token)| accounts.session_user(token));
+    match (user, token) {
+        (Some(name), Some(token)) => {
+            if is_unsafe_method(req.method())
+                && needs_session(req.uri().path())
+                && !is_same_site(req.headers(), &token)
+            {
+                tracing::info!("Refused a cross-site {} {}", req.method(), req.uri().path());
+                return (StatusCode::FORBIDDEN, "Missing or wrong CSRF token").into_response();
+            }
+            req.extensions_mut().insert(User(name));
+            req.extensions_mut().insert(SessionHash(hash_token(&token)));
+        }
+        _ if needs_session(req.uri().path()) => return StatusCode::UNAUTHORIZED.into_response(),
+        _ => (),
+    }
+    next.run(req).await
+}
+
+// Sets the /api/account, /api/signup, /api/login, /api/logout, /api/delete-account, two-factor
+// authentication, and pairing routes, and the /pair page.
+// If accounts are on, every route set up so far needs a session, so this has to be set up after
+// them. In a demo, there are no accounts, and nobody can manage the server. Deleting an account
+// deletes its library from the given disk.
+pub(crate) fn setup(
+    router: Router,
+    accounts: Option<AccountStore>,
+    demo: bool,
+    disk: DiskManager,
+) -> Router {
+    let Some(accounts) = accounts else {
+        return router.nest(
+            "/api",
+            Router::new()
+                .route(
+                    "/account",
+                    get(move || async move {
+                        Json(AccountStatus {
+                            is_admin: !demo,
+                            demo,
+                            ..Default::default()
+                        })
+                    }),
+                )
+                .route("/pair-device", post(pair_device)),
+        );
+    };
+
+    router
+        .nest(
+            "/api",
+            Router::new()
+                .route("/account", get(account_status))
+                .route("/signup", post(sign_up))
+                .route("/login", post(sign_in))
+                .route("/logout", post(sign_out))
+                .route("/totp-setup", post(start_totp_setup))
+                .route("/totp-enable", post(enable_totp))
+                .route("/totp-disable", post(disable_totp))
+                .route("/pair-device", post(pair_device))
+                .route("/delete-account", post(delete_account))
+                .layer(Extension(disk)),
+        )
+        .route("/pair", get(pairing_page).post(redeem_pairing))
+        .layer(middleware::from_fn(require_session))
+        .layer(Extension(accounts))
+}
+
+/// Returns who's signed in. This also sets the CSRF cookie of sessions that started before there
+/// was one.
+async fn account_status(
+    headers: HeaderMap,
+    user: Option<Extension<User>>,
+    Extension(accounts): Extension<AccountStore>,
+) -> Response {
+    let status = Json(accounts.status(user.map(|Extension(User(name))| name)));
+    match session_token(&headers).filter(|_| status.name.is_some()) {
+        Some(token) if cookie_value(&headers, CSRF_COOKIE).is_none() => (
+            AppendHeaders([set_cookie(
+                &accounts.cookies,
+                CSRF_COOKIE,
+                Some(&csrf_token(&token)),
+                false,
+            )]),
+            status,
+        )
+            .into_response(),
+        _ => status.into_response(),
+    }
+}
+
+/// Makes an account and signs in to it
+async fn sign_up(
+    Json(creds): Json<Credentials>,
+    Extension(accounts): Extension<AccountStore>,
+) -> Result<Response, (StatusCode, String)> {
+    let token = accounts.sign_up(&creds).await.map_err(|e| {
+        tracing::info!("Failed signup for {:?}: {e}", creds.name);
+        (StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+    tracing::info!("Signed up {:?}", creds.name);
+    Ok(signed_in(&accounts.cookies, token, creds.want_token))
+}
+
+/// Signs in to an account
+async fn sign_in(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(creds): Json<Credentials>,
+    Extension(accounts): Extension<AccountStore>,
+) -> Result<Response, (StatusCode, String)> {
+    let addr = client_addr(&headers, peer.ip());
+    let token = accounts.sign_in(&creds, addr).await.map_err(|e| {
+        tracing::info!("Failed login for {:?} from {addr}: {e}", creds.name);
+        let status = if e.is::<LockedOut>() {
+            StatusCode::TOO_MANY_REQUESTS
+        } else {
+            StatusCode::UNAUTHORIZED
+        };
+        (status, e.to_string())
+    })?;
+    Ok(signed_in(&accounts.cookies, token, creds.want_token))
+}
+
+/// Makes a new two-factor secret for the account, for the authenticator app
+async fn start_totp_setup(
+    Extension(User(name)): Extension<User>,
+    Extension(accounts): Extension<AccountStore>,
+) -> Result<Json<TotpSetup>, (StatusCode, String)> {
+    accounts
+        .start_totp_setup(&name)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Error setting up two-factor authentication for {name:?}: {e}");
+            (StatusCode::BAD_REQUEST, e.to_string())
+        })
+}
+
+/// Turns on two-factor authentication for the account, with a code from the new secret
+async fn enable_totp(
+    Json(TotpCode { code }): Json<TotpCode>,
+    Extension(User(name)): Extension<User>,
+    Extension(accounts): Extension<AccountStore>,
+) -> Result<(), (StatusCode, String)> {
+    accounts
+        .enable_totp(&name, &code)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    tracing::info!("Turned on two-factor authentication for {name:?}");
+    Ok(())
+}
+
+/// Turns off two-factor authentication for the account, with a code from the authenticator app
+async fn disable_totp(
+    Json(TotpCode { code }): Json<TotpCode>,
+    Extension(User(name)): Extension<User>,
+    Extension(accounts): Extension<AccountStore>,
+) -> Result<(), (StatusCode, String)> {
+    accounts
+        .disable_totp(&name, &code)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    tracing::info!("Turned off two-factor authentication for {name:?}");
+    Ok(())
+}
+
+/// Deletes the account that's signed in, then everything in its library, and signs out. The account
+/// goes first, so nothing can be added to the library while it's being deleted.
+async fn delete_account(
+    Extension(User(name)): Extension<User>,
+    Extension(accounts): Extension<AccountStore>,
+    Extension(disk): Extension<DiskManager>,
+    Json(deletion): Json<AccountDeletion>,
+) -> Result<Response, (StatusCode, String)> {
+    accounts
+        .delete_account(&name, &deletion)
+        .await
+        .map_err(|e| {
+            tracing::info!("Failed deletion of {name:?}: {e}");
+            let status = if e.is::<LockedOut>() {
+                StatusCode::TOO_MANY_REQUESTS
+            } else {
+                StatusCode::UNAUTHORIZED
+            };
+            (status, e.to_string())
+        })?;
+    tracing::info!("Deleted the account {name:?}");
+
+    let cookies = session_cookies(&accounts.cookies, None);
+    if let Err(e) = disk.delete_library(&name).await {
+        tracing::error!("Error deleting the library of {name:?}: {e}");
+        let msg = format!("The account was deleted, but not all of its library was: {e}");
+        return Ok((cookies, (StatusCode::INTERNAL_SERVER_ERROR, msg)).into_response());
+    }
+    Ok(cookies.into_response())
+}
+
+/// Makes a link, and its QR code, that a new device can open to get the app. With accounts on, the
+/// link signs it in to the account that made it.
+async fn pair_device(
+    user: Option<Extension<User>>,
+    accounts: Option<Extension<AccountStore>>,
+    Json(req): Json<PairingRequest>,
+) -> Result<Json<PairingCode>, (StatusCode, String)> {
+    let code = match (accounts, user) {
+        (Some(Extension(accounts)), Some(Extension(User(name)))) => {
+            let code = accounts.start_pairing(&name).map_err(|e| {
+                tracing::error!("Error making a pairing code for {name:?}: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            })?;
+            tracing::info!("Made a pairing code for {name:?}");
+            Some(code)
+        }
+        _ => None,
+    };
+    let expires_in_secs = if code.is_some() {
+        PAIRING_LIFETIME_SECS
+    } else {
+        0
+    };
+
+    let bad_request = |e: AnyError| (StatusCode::BAD_REQUEST, e.to_string());
+    let url = pairing_url(&req.app_url, code.as_deref()).map_err(bad_request)?;
+    let qr_svg = qr::to_svg(&url).map_err(bad_request)?;
+    Ok(Json(PairingCode {
+        url,
+        qr_svg,
+        expires_in_secs,
+    }))
+}
+
+/// The query string of the /pair page, and the form it posts
+#[derive(Deserialize)]
+struct PairingParams {
+    code: String,
+}
+
+/// Returns a page around the given body
+fn pairing_html(body: &str) -> Html<String> {
+    Html(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+        <title>ReadToMyShoe</title></head><body>{body}</body></html>"
+    ))
+}
+
+/// Shows the page a pairing link opens, with a button that signs the device in. Opening the link
+/// doesn't use up the code itself, since chat apps fetch links to preview them, and would.
+async fn pairing_page(Query(PairingParams { code }): Query<PairingParams>) -> Html<String> {
+    // Relative, so it works under a subpath too
+    pairing_html(&format!(
+        "<form method=\"post\" action=\"pair\">\
+        <input type=\"hidden\" name=\"code\" value=\"{}\">\
+        <button type=\"submit\">Sign this device in to ReadToMyShoe</button></form>",
+        escape_xml(&code)
+    ))
+}
+
+/// Signs a new device in with the pairing code from the /pair page, and sends it to the app
+async fn redeem_pairing(
+    Form(PairingParams { code }): Form<PairingParams>,
+    Extension(accounts): Extension<AccountStore>,
+) -> Response {
+    match accounts.redeem_pairing(&code).await {
+        Ok(token) => {
+            tracing::info!("Paired a device");
+            let cookies = session_cookies(&accounts.cookies, Some(&token));
+            (cookies, Redirect::to("./")).into_response()
+        }
+        Err(e) => {
+            let body = format!("<p>{e}</p><p><a href=\"./\">Open the app</a></p>");
+            (StatusCode::BAD_REQUEST, pairing_html(&body)).into_response()
+        }
+    }
+}
+
+/// Signs out of the current session
+async fn sign_out(
+    headers: HeaderMap,
+    Extension(accounts): Extension<AccountStore>,
+) -> impl IntoResponse {
+    if let Some(token) = session_token(&headers) {
+        if let Err(e) = accounts.sign_out(&token).await {
+            tracing::error!("Error ending session: {e}");
+        }
+    }
+    session_cookies(&accounts.cookies, None)
+}
+
+#[tokio::test]
+async fn accounts_and_sessions() {
+    let path = std::env::temp_dir().join(format!("rtms-accounts-test-{}.json", std::process::id()));
+    let accounts = AccountStore::load(
+        path.to_str().unwrap(),
+        Some("family".to_string()),
+        CookieConfig::default(),
+        Vec::new(),
+    )
+    .unwrap();
+    let creds = |name: &str, password: &str, code: Option<&str>| Credentials {
+        name: name.to_string(),
+        password: password.to_string(),
+        signup_code: code.map(str::to_string),
+        totp_code: None,
+        want_token: false,
+    };
+    let home: IpAddr = "192.0.2.1".parse().unwrap();
+
+    // Signing up needs the code, a usable name, and a long enough password
+    assert!(accounts
+        .sign_up(&creds("alex", "correct horse", None))
+        .await
+        .is_err());
+    assert!(accounts
+        .sign_up(&creds("../alex", "correct horse", Some("family")))
+        .await
+        .is_err());
+    assert!(accounts
+        .sign_up(&creds("alex", "short", Some("family")))
+        .await
+        .is_err());
+    let token = accounts
+        .sign_up(&creds("alex", "correct horse", Some("family")))
+        .await
+        .unwrap();
+    assert_eq!(accounts.session_user(&token).as_deref(), Some("alex"));
+    assert!(accounts
+        .sign_up(&creds("alex", "another horse", Some("family")))
+        .await
+        .is_err());
+
+    // The accounts and sessions survive a restart
+    let accounts = AccountStore::load(
+        path.to_str().unwrap(),
+        None,
+        CookieConfig::default(),
+        Vec::new(),
+    )
+    .unwrap();
+    assert_eq!(accounts.session_user(&token).as_deref(), Some("alex"));
+    assert!(accounts
+        .sign_in(&creds("alex", "wrong horse", None), home)
+        .await
+        .is_err());
+    assert!(accounts
+        .sign_in(&creds("sam", "correct horse", None), home)
+        .await
+        .is_err());
+    let token2 = accounts
+        .sign_in(&creds("alex", "correct horse", None), home)
+        .await
+        .unwrap();
+
+    accounts.sign_out(&token).await.unwrap();
+    assert_eq!(accounts.session_user(&token), None);
+    assert_eq!(accounts.session_user(&token2).as_deref(), Some("alex"));
+
+    // Only the routes that can't sign in are public
+    assert!(needs_session("/api/list-articles"));
+    assert!(needs_session("/audio/f00d.mp3"));
+    assert!(needs_session("/mini"));
+    assert!(!needs_session("/api/login"));
+    assert!(!needs_session("/api/podcast.xml"));
+    assert!(needs_session("/api/podcast-link"));
+    assert!(!needs_session("/add"));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::COOKIE,
+        HeaderValue::from_str(&format!("theme=dark; {SESSION_COOKIE}={token2}")).unwrap(),
+    );
+    assert_eq!(session_token(&headers), Some(token2));
+
+    // With two-factor authentication on, signing in needs a code, and each code only works once
+    let setup = accounts.start_totp_setup("alex").await.unwrap();
+    assert!(setup.uri.contains(&setup.secret));
+    assert!(accounts.enable_totp("alex", "000000x").await.is_err());
+    let code = totp::code(&setup.secret, now_secs());
+    accounts.enable_totp("alex", &code).await.unwrap();
+    assert!(accounts.totp_enabled("alex"));
+    let mut with_code = creds("alex", "correct horse", None);
+    assert!(accounts.sign_in(&with_code, home).await.is_err());
+    with_code.totp_code = Some(code);
+    assert!(accounts.sign_in(&with_code, home).await.is_err());
+    let forget_last_code = || async {
+        accounts
+            .update(|file| file.accounts[0].totp_last_step = 0)
+            .await
+            .unwrap()
+    };
+    forget_last_code().await;
+    with_code.totp_code = Some(totp::code(&setup.secret, now_secs()));
+    accounts.sign_in(&with_code, home).await.unwrap();
+    forget_last_code().await;
+    accounts
+        .disable_totp("alex", &totp::code(&setup.secret, now_secs()))
+        .await
+        .unwrap();
+    assert!(!accounts.totp_enabled("alex"));
+
+    // Too many failures lock the account, even from elsewhere and with the right password
+    let work: IpAddr = "198.51.100.1".parse().unwrap();
+    for _ in 0..MAX_ACCOUNT_FAILURES {
+        let e = accounts
+            .sign_in(&creds("alex", "wrong horse", None), home)
+            .await
+            .unwrap_err();
+        assert!(!e.is::<LockedOut>());
+    }
+    let e = accounts
+        .sign_in(&creds("alex", "correct horse", None), work)
+        .await
+        .unwrap_err();
+    assert!(e.is::<LockedOut>());
+
+    // Too many failures from one address lock it out, whichever accounts it tries
+    let cafe: IpAddr = "203.0.113.1".parse().unwrap();
+    for i in 0..MAX_ADDR_FAILURES {
+        let name = format!("guess{i}");
+        assert!(accounts
+            .sign_in(&creds(&name, "x", None), cafe)
+            .await
+            .is_err());
+    }
+    let e = accounts
+        .sign_in(&creds("sam", "x", None), cafe)
+        .await
+        .unwrap_err();
+    assert!(e.is::<LockedOut>());
+    let e = accounts
+        .sign_in(&creds("sam", "x", None), work)
+        .await
+        .unwrap_err();
+    assert!(!e.is::<LockedOut>());
+
+    // A pairing code signs a new device in to its account, once
+    let code = accounts.start_pairing("alex").unwrap();
+    let paired = accounts.redeem_pairing(&code).await.unwrap();
+    assert_eq!(accounts.session_user(&paired).as_deref(), Some("alex"));
+    assert!(accounts.redeem_pairing(&code).await.is_err());
+    let code = accounts.start_pairing("alex").unwrap();
+    accounts
+        .pairings
+        .lock()
+        .unwrap()
+        .values_mut()
+        .for_each(|p| p.expires = now_secs() - 1);
+    assert!(accounts.redeem_pairing(&code).await.is_err());
+    assert_eq!(
+        pairing_url("http://[2001:db8::5]:9382/", Some("c0de")).unwrap(),
+        "http://[2001:db8::5]:9382/pair?code=c0de"
+    );
+    assert_eq!(
+        pairing_url(" https://example.com/rtms", None).unwrap(),
+        "https://example.com/rtms/"
+    );
+    assert!(pairing_url("192.168.1.5:9382", None).is_err());
+    assert!(pairing_url("javascript://x", None).is_err());
+
+    // The address is only trusted from X-Forwarded-For if a local proxy sent it
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-forwarded-for",
+        HeaderValue::from_static("10.0.0.9, 203.0.113.1"),
+    );
+    let proxy = IpAddr::from([127, 0, 0, 1]);
+    assert_eq!(client_addr(&headers, proxy), cafe);
+    assert_eq!(client_addr(&headers, work), work);
+    assert_eq!(client_addr(&HeaderMap::new(), proxy), proxy);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn deleting_accounts() {
+    let path = std::env::temp_dir().join(format!("rtms-deletion-test-{}.json", std::process::id()));
+    let accounts = AccountStore::load(
+        path.to_str().unwrap(),
+        None,
+        CookieConfig::default(),
+        Vec::new(),
+    )
+    .unwrap();
+    let creds = |name: &str| Credentials {
+        name: name.to_string(),
+        password: "correct horse".to_string(),
+        signup_code: None,
+        totp_code: None,
+        want_token: false,
+    };
+    let deletion = |password: &str| AccountDeletion {
+        password: password.to_string(),
+        totp_code: None,
+    };
+    let token = accounts.sign_up(&creds("alex")).await.unwrap();
+    let other_token = accounts.sign_up(&creds("sam")).await.unwrap();
+    let home: IpAddr = "192.0.2.1".parse().unwrap();
+
+    // Deleting needs the password
+    assert!(accounts
+        .delete_account("alex", &deletion("wrong horse"))
+        .await
+        .is_err());
+    assert_eq!(accounts.session_user(&token).as_deref(), Some("alex"));
+
+    // Then every session ends, and the account can't be signed in to, but others carry on
+    let paired = accounts
+        .redeem_pairing(&accounts.start_pairing("alex").unwrap())
+        .await
+        .unwrap();
+    let code = accounts.start_pairing("alex").unwrap();
+    accounts
+        .delete_account("alex", &deletion("correct horse"))
+        .await
+        .unwrap();
+    assert_eq!(accounts.session_user(&token), None);
+    assert_eq!(accounts.session_user(&paired), None);
+    assert!(accounts.redeem_pairing(&code).await.is_err());
+    assert!(accounts.sign_in(&creds("alex"), home).await.is_err());
+    assert_eq!(accounts.session_user(&other_token).as_deref(), Some("sam"));
+
+    // The name's free again
+    assert!(accounts.sign_up(&creds("alex")).await.is_ok());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn csrf_checks() {
+    let token = "session-token";
+    let csrf = csrf_token(token);
+    assert_ne!(csrf, csrf_token("another-session-token"));
+
+    // The header has to have the session's token
+    let mut headers = HeaderMap::new();
+    headers.insert(header::HOST, HeaderValue::from_static("shoe.example"));
+    assert!(!is_same_site(&headers, token));
+    headers.insert(CSRF_HEADER, HeaderValue::from_str(&csrf).unwrap());
+    assert!(is_same_site(&headers, token));
+    assert!(!is_same_site(&headers, "another-session-token"));
+
+    // Without the header, the origin has to be this server
+    let mut headers = HeaderMap::new();
+    headers.insert(header::HOST, HeaderValue::from_static("shoe.example"));
+    headers.insert(
+        header::ORIGIN,
+        HeaderValue::from_static("https://evil.example"),
+    );
+    assert!(!is_same_site(&headers, token));
+    headers.insert(
+        header::ORIGIN,
+        HeaderValue::from_static("https://shoe.example"),
+    );
+    assert!(is_same_site(&headers, token));
+
+    // Clients on other sites send the session token itself
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ORIGIN,
+        HeaderValue::from_static("https://app.example"),
+    );
+    headers.insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_static("Bearer session-token"),
+    );
+    assert_eq!(session_token(&headers).as_deref(), Some(token));
+    assert!(is_same_site(&headers, token));
+    assert!(!is_same_site(&headers, "another-session-token"));
+    assert!(is_unsafe_method(&Method::POST));
+    assert!(!is_unsafe_method(&Method::GET));
+
+    // Scripts can read the CSRF cookie, but not the session cookie
+    let config = CookieConfig {
+        secure: true,
+        same_site: SameSite::Strict,
+    };
+    let AppendHeaders([(_, session), (_, csrf_cookie)]) = session_cookies(&config, Some(token));
+    let session = session.to_str().unwrap();
+    assert!(session.contains("HttpOnly") && session.contains("Secure"));
+    assert!(session.contains("SameSite=Strict"));
+    assert!(!csrf_cookie.to_str().unwrap().contains("HttpOnly"));
+    assert!(csrf_cookie
+        .to_str()
+        .unwrap()
+        .starts_with(&format!("{CSRF_COOKIE}={csrf};")));
+}
+
+#[tokio::test]
+async fn pairing_links() {
+    // Opening a pairing link only shows a form that posts the code, escaped, back to /pair
+    let params = PairingParams {
+        code: "c0de\"><script>".to_string(),
+    };
+    let Html(page) = pairing_page(Query(params)).await;
+    assert!(page.contains("<form method=\"post\" action=\"pair\">"));
+    assert!(page.contains("value=\"c0de&quot;&gt;&lt;script&gt;\""));
+}
@@ -1,159 +1,644 @@
 use crate::{
-    tts::{get_api_key, tts, TtsRequest},
-    util::{derive_article_id, save_metadata, truncate_to_bytes, StrEncoding},
+    accounts::LibraryDir,
+    ambient::AmbientBeds,
+    canonical,
+    config::AmbientBed,
+    declutter::ClutterRules,
+    extract::{
+        self, check_extraction, extract, looks_like_article, readability_text, select_text,
+        word_count, ExtractedArticle, ExtractionRules, HtmlArchive,
+    },
+    frontmatter::{self, Byline},
+    images,
+    jobs::{JobHandle, JobStore},
+    key_points,
+    llm::LanguageModel,
+    normalize::normalize,
+    page_cache::PageCache,
+    pending::{self, ChunkCache, PendingSynthesis},
+    rate_limit::RateLimiter,
+    reading_profile,
+    search::SearchIndex,
+    transcript,
+    util::{
+        derive_article_id, get_metadata, hash_text, save_metadata, truncate_to_bytes, StrEncoding,
+    },
+    voices::{voice_key, VoiceRegistry},
 };
 use common::{
-    ArticleMetadata, ArticleTextSubmission, ArticleUrlSubmission, MAX_TITLE_UTF16_CODEUNITS,
+    ArticleDeletion, ArticleGroup, ArticleMetadata, ArticleTextSubmission, ArticleTranscript,
+    ArticleUrlSubmission, ExtractionChoice, ExtractionPreview, ExtractionStrategy,
+    FinishArticleSubmission, ImportedArticle, JobStarted, ReExtractResponse, ReExtractSubmission,
+    SourceType, SpeakingStyle, SplitOffer, SynthesisOptions, MAX_ARTICLE_LEN,
+    MAX_TITLE_UTF16_CODEUNITS,
+};
+use tts_pipeline::{
+    audio,
+    tts::{break_greedily_at_delim, tts},
+    Fetch, Page, TtsRequest, Voice,
 };
 
 use std::{
     fs::{self, File, OpenOptions},
+    future::Future,
     io::Write,
-    num::{NonZeroU32, NonZeroUsize},
     path::Path,
-    sync::Arc,
     time::SystemTime,
 };
 
-use anyhow::{anyhow, Context};
-use async_process::Command;
-use axum::{extract::Extension, routing::post, Json, Router};
-use governor::{
-    clock::DefaultClock, middleware::NoOpMiddleware, state::direct::NotKeyed, state::InMemoryState,
-    Quota, RateLimiter as BaseRateLimiter,
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
 };
-use serde::Deserialize;
-
-type DefaultRateLimiter = BaseRateLimiter<NotKeyed, InMemoryState, DefaultClock, NoOpMiddleware>;
-
-/// The rate limiter for TTS calls. The quota contains the quota for characters per minute.
-#[derive(Clone)]
-struct RateLimiter {
-    base_rl: Arc<DefaultRateLimiter>,
-    quota: Quota,
-}
+use futures::future::join_all;
 
-/// A portion of trafilatura's extracted text. The rest of the fields are: title, author, hostname,
-/// date, categories, tags, fingerprint, id, license, comments, raw_text, source, source_hostname,
-/// excerpt, text
-#[derive(Deserialize)]
-struct ExtractedArticle {
-    title: String,
-    text: String,
+/// Where an article came from. This is recorded in the metadata of every article made from it.
+#[derive(Default)]
+struct ArticleSource {
+    url: Option<String>,
+    author: Option<String>,
+    publication: Option<String>,
+    /// When the article was published, as `YYYY-MM-DD`
+    published: Option<String>,
+    /// The title of the feed the article was added from, if any
+    feed: Option<String>,
+    source_type: Option<SourceType>,
+    /// The path on this server of the article's lead image, if it has one
+    image: Option<String>,
 }
 
 #[derive(Debug)]
-struct AddArticleError(anyhow::Error);
+pub(crate) enum AddArticleError {
+    /// The article is too long to convert as a single article. The client is offered to split it.
+    TooLong(SplitOffer),
+    /// The extractor is unsure which part of the page is the article. The client is offered the
+    /// candidates to pick from.
+    Uncertain(ExtractionChoice),
+    /// The article at the submitted URL is already in the library, as the given article
+    AlreadyAdded(Box<ArticleMetadata>),
+    /// Anything else
+    Other(anyhow::Error),
+}
 
 impl From<anyhow::Error> for AddArticleError {
     fn from(error: anyhow::Error) -> Self {
-        Self(error)
+        Self::Other(error)
     }
 }
 
 impl axum::response::IntoResponse for AddArticleError {
     fn into_response(self) -> axum::response::Response {
-        // Log the error and return it
-        let err_str = self.0.to_string();
-        tracing::error!("{}", err_str);
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, err_str).into_response()
+        match self {
+            AddArticleError::TooLong(offer) => {
+                (axum::http::StatusCode::PAYLOAD_TOO_LARGE, Json(offer)).into_response()
+            }
+            AddArticleError::Uncertain(choice) => {
+                (axum::http::StatusCode::MULTIPLE_CHOICES, Json(choice)).into_response()
+            }
+            AddArticleError::AlreadyAdded(meta) => {
+                (axum::http::StatusCode::CONFLICT, Json(meta)).into_response()
+            }
+            AddArticleError::Other(e) => {
+                // Log the error and return it
+                let err_str = e.to_string();
+                tracing::error!("{}", err_str);
+                (axum::http::StatusCode::INTERNAL_SERVER_ERROR, err_str).into_response()
+            }
+        }
     }
 }
 
-// Sets the /api/add-article route
-pub(crate) fn setup(router: Router, max_chars_per_min: NonZeroU32, audio_blob_dir: &str) -> Router {
-    // Set up the rate limiter for our TTS queries
-    let quota = Quota::per_minute(max_chars_per_min);
-    let tts_rate_limiter = RateLimiter {
-        base_rl: Arc::new(DefaultRateLimiter::direct(quota.clone())),
-        quota,
-    };
+/// What the add endpoints respond with when a submission is accepted
+enum Added {
+    /// The new articles' metadata. There's more than one article if it was split into parts.
+    Articles(Vec<ArticleMetadata>),
+    /// The job that's adding the submission in the background
+    Started(JobStarted),
+}
+
+impl IntoResponse for Added {
+    fn into_response(self) -> Response {
+        match self {
+            Added::Articles(metas) => Json(metas).into_response(),
+            Added::Started(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        }
+    }
+}
 
+// Sets the /api/add-article, /api/preview-article, /api/finish-article, /api/re-extract-article,
+// and /api/delete-articles routes
+pub(crate) fn setup(router: Router, converter: Converter) -> Router {
     // Set up the routes
     router.nest(
         "/api",
         Router::new()
             .route("/add-article-by-text", post(add_article_by_text_endpoint))
             .route("/add-article-by-url", post(add_article_by_url_endpoint))
-            .layer(Extension(tts_rate_limiter))
-            .layer(Extension(audio_blob_dir.to_string())),
+            .route("/preview-article", post(preview_article_endpoint))
+            .route("/finish-article", post(finish_article_endpoint))
+            .route("/re-extract-article", post(re_extract_article_endpoint))
+            .route("/delete-articles", post(delete_articles_endpoint))
+            .layer(Extension(converter.audio_blob_dir.clone()))
+            .layer(Extension(converter)),
     )
 }
 
-/// Converts the given article contents to speech, and returns the new filename
+/// Converts the given article contents to speech, and returns the new articles' metadata. There's
+/// more than one new article if the article was split into parts. If the submission asks for it,
+/// this returns as soon as synthesis begins instead, with the job that's converting it.
 async fn add_article_by_text_endpoint(
+    headers: HeaderMap,
     Json(article): Json<ArticleTextSubmission>,
-    Extension(tts_rate_limiter): Extension<RateLimiter>,
-    Extension(audio_blob_dir): Extension<String>,
-) -> Result<String, AddArticleError> {
-    // Just call down to add_article_by_text
+    Extension(converter): Extension<Converter>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Added, AddArticleError> {
+    // Just call down to add_article_in_parts
     tracing::debug!("Adding article by text: '{}'", article.title);
-    let meta = match add_article_by_text(&article, tts_rate_limiter, &audio_blob_dir).await {
-        Ok(m) => m,
+    let converter = converter.in_library(audio_blob_dir);
+    let voice = requested_voice(
+        &converter.voice_registry,
+        article.voice.as_deref(),
+        &headers,
+    )?;
+    let source = ArticleSource {
+        source_type: Some(article.source_type.unwrap_or(SourceType::Pasted)),
+        ..Default::default()
+    };
+    let (background, title) = (article.background, article.title.clone());
+    let job_store = converter.job_store.clone();
+    let conversion = move |job_store: JobStore| async move {
+        let converter = Converter {
+            job_store,
+            ..converter
+        };
+        let metas = add_article_in_parts(&converter, &article, &source, voice.as_ref()).await?;
+        add_key_points(&converter, &metas, article.style, &article.options).await;
+        Ok(metas)
+    };
+    let res = if background {
+        add_in_background(&job_store, &title, conversion).await
+    } else {
+        detach(conversion(job_store)).await.map(Added::Articles)
+    };
+
+    match res {
+        Ok(added) => Ok(added),
         Err(e) => {
             tracing::error!("Error adding by text: {:?}", e);
-            return Err(e);
+            Err(e)
         }
+    }
+}
+
+/// Fetches the article at the given URL, converts it to speech, and returns the new articles'
+/// metadata. There's more than one new article if the article was split into parts. If the
+/// submission asks for it, this returns as soon as synthesis begins instead, with the job that's
+/// converting it.
+async fn add_article_by_url_endpoint(
+    headers: HeaderMap,
+    Json(submission): Json<ArticleUrlSubmission>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Added, AddArticleError> {
+    tracing::debug!("Adding article by URL: {}", submission.url);
+    let converter = converter.in_library(audio_blob_dir);
+    let voice = requested_voice(
+        &converter.voice_registry,
+        submission.voice.as_deref(),
+        &headers,
+    )?;
+    // The article's title isn't known until it's fetched
+    let (background, title) = (submission.background, submission.url.clone());
+    let job_store = converter.job_store.clone();
+    let conversion = move |job_store: JobStore| async move {
+        let converter = Converter {
+            job_store,
+            ..converter
+        };
+        let metas = add_article_by_url(&converter, &submission, None, voice.as_ref()).await?;
+        add_key_points(&converter, &metas, submission.style, &submission.options).await;
+        Ok(metas)
+    };
+    let res = if background {
+        add_in_background(&job_store, &title, conversion).await
+    } else {
+        detach(conversion(job_store)).await.map(Added::Articles)
     };
 
-    // Save the metadata in the ID3 tags
-    let _ = save_metadata(&meta, &audio_blob_dir)
-        .map_err(|e| tracing::error!("Error saving metadata: {e}"));
+    match res {
+        Ok(added) => Ok(added),
+        Err(e) => {
+            tracing::error!("Error adding by url: {:?}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Synthesizes the rest of an article whose synthesis failed partway through, and returns the
+/// article's updated metadata
+async fn finish_article_endpoint(
+    Json(FinishArticleSubmission { id }): Json<FinishArticleSubmission>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Json<ArticleMetadata>, AddArticleError> {
+    tracing::debug!("Finishing article {id}");
+    let converter = converter.in_library(audio_blob_dir);
+    let res = detach(async move { finish_article(&converter, &id).await }).await;
 
-    Ok(meta.id)
+    match res {
+        Ok(m) => Ok(Json(m)),
+        Err(e) => {
+            tracing::error!("Error finishing article: {:?}", e);
+            Err(e)
+        }
+    }
 }
 
-/// Fetches the article at the given URL, converts it to speech, and returns the new filename
-async fn add_article_by_url_endpoint(
-    Json(ArticleUrlSubmission { url }): Json<ArticleUrlSubmission>,
-    Extension(tts_rate_limiter): Extension<RateLimiter>,
-    Extension(audio_blob_dir): Extension<String>,
-) -> Result<String, AddArticleError> {
-    tracing::debug!("Adding article by URL: {url}");
-    let meta = match add_article_by_url(&url, tts_rate_limiter, &audio_blob_dir).await {
-        Ok(m) => m,
+/// Re-runs text extraction on the archived HTML of an article. If `resynthesize` is set, the
+/// article's audio is regenerated from the new text. Returns the newly extracted text and the
+/// article's (possibly new) ID.
+async fn re_extract_article_endpoint(
+    Json(ReExtractSubmission { id, resynthesize }): Json<ReExtractSubmission>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Json<ReExtractResponse>, AddArticleError> {
+    tracing::debug!("Re-extracting article {id}");
+    let converter = converter.in_library(audio_blob_dir);
+    let res = detach(async move { re_extract_article(&converter, &id, resynthesize).await }).await;
+
+    match res {
+        Ok(r) => Ok(Json(r)),
         Err(e) => {
-            tracing::error!("Error adding by url: {:?}", e);
-            return Err(e);
+            tracing::error!("Error re-extracting: {:?}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Deletes the given articles from the library, along with everything kept about them
+async fn delete_articles_endpoint(
+    Json(ArticleDeletion { ids }): Json<ArticleDeletion>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<StatusCode, AddArticleError> {
+    tracing::debug!("Deleting articles {ids:?}");
+    for id in &ids {
+        let (html_archive, search_index) = (&converter.html_archive, &converter.search_index);
+        delete_article(id, &audio_blob_dir, html_archive, search_index).await?;
+    }
+    Ok(StatusCode::OK)
+}
+
+/// Deletes the audio of the given article, and its pending synthesis, transcript, archived HTML, and
+/// search index entry. Only a failure to delete the audio is an error, since the article is gone
+/// from the library once that's deleted.
+pub(crate) async fn delete_article(
+    id: &str,
+    audio_blob_dir: &str,
+    html_archive: &HtmlArchive,
+    search_index: &SearchIndex,
+) -> Result<(), AnyError> {
+    // IDs become file names, so don't let one point outside the audio blob dir
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        bail!("invalid article ID {id:?}");
+    }
+
+    let path = Path::new(audio_blob_dir).join(id).with_extension("mp3");
+    fs::remove_file(&path).map_err(|e| anyhow!("could not delete article {:?}: {e}", path))?;
+
+    let _ = pending::remove(audio_blob_dir, id)
+        .map_err(|e| tracing::error!("Error removing pending synthesis of {id}: {e}"));
+    let _ = transcript::remove(audio_blob_dir, id)
+        .map_err(|e| tracing::error!("Error removing transcript of {id}: {e}"));
+    let _ = html_archive
+        .remove(id)
+        .map_err(|e| tracing::error!("Error removing archived HTML of {id}: {e}"));
+    let _ = search_index
+        .remove(audio_blob_dir, id)
+        .await
+        .map_err(|e| tracing::error!("Error removing {id} from search index: {e}"));
+
+    Ok(())
+}
+
+/// Looks up the voice the client asked for, if it asked for one. Custom voices need the client's
+/// voice key.
+fn requested_voice(
+    voice_registry: &VoiceRegistry,
+    name: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<Option<Voice>, AddArticleError> {
+    let voice = name
+        .map(|name| voice_registry.resolve(name, voice_key(headers)))
+        .transpose()?;
+    Ok(voice)
+}
+
+/// Everything needed to convert articles. The endpoints above convert into the library of whoever
+/// asked, and articles are converted outside of a request too, i.e., the ones from feed
+/// subscriptions, imports, and the inbox. Those go to the library of whoever subscribed, imported,
+/// or saved them. See `in_library`.
+#[derive(Clone)]
+pub(crate) struct Converter {
+    pub(crate) tts_rate_limiter: RateLimiter,
+    pub(crate) voice_registry: VoiceRegistry,
+    pub(crate) audio_blob_dir: String,
+    pub(crate) html_archive: HtmlArchive,
+    pub(crate) ambient_beds: AmbientBeds,
+    pub(crate) job_store: JobStore,
+    pub(crate) search_index: SearchIndex,
+    pub(crate) extraction_rules: ExtractionRules,
+    pub(crate) clutter_rules: ClutterRules,
+    pub(crate) page_cache: PageCache,
+    pub(crate) language_model: LanguageModel,
+}
+
+impl Converter {
+    /// Returns a converter that converts into the library in the given directory, and whose jobs
+    /// belong to it
+    pub(crate) fn in_library(self, audio_blob_dir: String) -> Converter {
+        Converter {
+            job_store: self.job_store.in_library(&audio_blob_dir),
+            audio_blob_dir,
+            ..self
+        }
+    }
+
+    /// Returns a submission of the given URL. Nobody's around to answer questions, so long articles
+    /// are split, and the extractor is trusted.
+    fn unattended_submission(url: &str) -> ArticleUrlSubmission {
+        ArticleUrlSubmission {
+            url: url.to_string(),
+            style: SpeakingStyle::default(),
+            options: SynthesisOptions::default(),
+            split: true,
+            selector: None,
+            save_selector: false,
+            trust_extraction: true,
+            strategy: None,
+            voice: None,
+            background: false,
+            force: false,
+        }
+    }
+
+    /// Fetches the article at the given URL, converts it to speech, and records that it came from
+    /// the feed with the given title
+    pub(crate) async fn add_from_feed(
+        &self,
+        url: &str,
+        feed: &str,
+    ) -> Result<Vec<ArticleMetadata>, AddArticleError> {
+        add_article_by_url(self, &Self::unattended_submission(url), Some(feed), None).await
+    }
+
+    /// Fetches the article at the given URL and converts it to speech
+    pub(crate) async fn add_url(&self, url: &str) -> Result<Vec<ArticleMetadata>, AddArticleError> {
+        add_article_by_url(self, &Self::unattended_submission(url), None, None).await
+    }
+
+    /// Converts an article from another reader's export to speech. If the export had the article's
+    /// text, that's what's read. Otherwise, the article is fetched from its URL.
+    pub(crate) async fn add_imported(
+        &self,
+        article: &ImportedArticle,
+    ) -> Result<Vec<ArticleMetadata>, AddArticleError> {
+        let text = match &article.text {
+            Some(text) => text,
+            None => return self.add_url(&article.url).await,
+        };
+
+        let url = canonical::unwrap_url(&article.url);
+        if let Some(meta) = canonical::find_in_library(&self.audio_blob_dir, &url) {
+            return Err(AddArticleError::AlreadyAdded(Box::new(meta)));
+        }
+
+        let submission = ArticleTextSubmission {
+            title: article.title.clone(),
+            body: text.clone(),
+            style: SpeakingStyle::default(),
+            options: SynthesisOptions::default(),
+            split: true,
+            voice: None,
+            source_type: None,
+            background: false,
+            force: false,
+        };
+        let source = ArticleSource {
+            url: Some(url),
+            source_type: Some(SourceType::Web),
+            ..Default::default()
+        };
+        add_article_in_parts(self, &submission, &source, None).await
+    }
+}
+
+/// Runs the given conversion in its own task, so that it keeps going even if the client goes away.
+/// This way, a client can leave the page and listen to the conversion's job while it's running.
+async fn detach<T: Send + 'static>(
+    conversion: impl Future<Output = Result<T, AddArticleError>> + Send + 'static,
+) -> Result<T, AddArticleError> {
+    tokio::spawn(conversion)
+        .await
+        .map_err(|e| anyhow!("Conversion task failed: {e}"))?
+}
+
+/// Runs the given conversion in its own task, under a job of its own that the conversion's jobs are
+/// parts of. If the conversion ends before synthesis begins, e.g., because the article is too long
+/// or already in the library, this returns what it returned, like `detach`. Otherwise, this returns
+/// the job as soon as synthesis begins, and the conversion carries on in the background.
+async fn add_in_background<F>(
+    job_store: &JobStore,
+    title: &str,
+    conversion: impl FnOnce(JobStore) -> F,
+) -> Result<Added, AddArticleError>
+where
+    F: Future<Output = Result<Vec<ArticleMetadata>, AddArticleError>> + Send + 'static,
+{
+    let job = job_store.start(title);
+    let id = job.id();
+    let conversion = conversion(job_store.under(&job));
+    let mut task = tokio::spawn(async move {
+        let res = conversion.await;
+        match &res {
+            Ok(metas) => job.finish_with_articles(metas),
+            Err(AddArticleError::Other(e)) => job.fail(&e.to_string()),
+            Err(_) => job.fail("The article was not converted"),
+        }
+        res
+    });
+
+    tokio::select! {
+        res = &mut task => {
+            // The client gets the outcome directly, so the job has nothing more to tell
+            job_store.forget(id);
+            res.map_err(|e| anyhow!("Conversion task failed: {e}"))?
+                .map(Added::Articles)
         }
+        _ = job_store.parts_started(id) => Ok(Added::Started(JobStarted { id })),
+    }
+}
+
+/// Splits an article body into parts of at most `MAX_ARTICLE_LEN` bytes, breaking between
+/// paragraphs. A single paragraph that's longer than that becomes a part of its own.
+fn split_into_parts(body: &str) -> Vec<&str> {
+    break_greedily_at_delim(body, '\n', MAX_ARTICLE_LEN)
+        .into_iter()
+        .filter(|part| !part.trim().is_empty())
+        .collect()
+}
+
+/// Returns the voice to read the given article body in. That's the given voice if there is one, or
+/// else a voice that speaks the body's language.
+fn pick_voice(voice_registry: &VoiceRegistry, voice: Option<&Voice>, body: &str) -> Voice {
+    match voice {
+        Some(voice) => voice.clone(),
+        None => {
+            let language = reading_profile::analyze(body).language;
+            voice_registry.voice_for_language(language.as_deref())
+        }
+    }
+}
+
+/// The real logic. Converts the given article contents to speech, splitting them into a group of
+/// parts if they're too long to convert as one article. Saves and returns the metadata of every new
+/// article. If no voice is given, one is picked to match the article's language.
+async fn add_article_in_parts(
+    converter: &Converter,
+    article: &ArticleTextSubmission,
+    source: &ArticleSource,
+    voice: Option<&Voice>,
+) -> Result<Vec<ArticleMetadata>, AddArticleError> {
+    let audio_blob_dir = &converter.audio_blob_dir;
+    // Clean up the typesetting that TTS reads as stutters before anything else looks at the text,
+    // so the transcript and search index match what's read
+    let article = &ArticleTextSubmission {
+        body: normalize(&article.body),
+        ..article.clone()
     };
 
-    // Save the metadata in the ID3 tags
-    let _ = save_metadata(&meta, &audio_blob_dir)
-        .map_err(|e| tracing::error!("Error saving metadata: {e}"));
+    // Don't convert the same words twice, unless asked to
+    let text_hash = hash_text(&article.body);
+    if !article.force {
+        if let Some(meta) = canonical::find_text_in_library(audio_blob_dir, &text_hash) {
+            return Err(AddArticleError::AlreadyAdded(Box::new(meta)));
+        }
+    }
+    let parts = split_into_parts(&article.body);
+    // Every part is read in the same voice, even if the parts on their own look like different
+    // languages
+    let voice = pick_voice(&converter.voice_registry, voice, &article.body);
+    let byline = Byline {
+        author: source.author.as_deref(),
+        publication: source.publication.as_deref(),
+        published: source.published.as_deref(),
+    };
+
+    let results = if parts.len() <= 1 {
+        // Short articles are converted as they are
+        vec![add_article_by_text(converter, article, &byline, &voice).await]
+    } else if !article.split {
+        // Don't split the article up without asking first
+        return Err(AddArticleError::TooLong(SplitOffer {
+            words: article.body.split_whitespace().count(),
+            parts: parts.len(),
+        }));
+    } else {
+        let group = ArticleGroup {
+            id: derive_article_id(article),
+            title: article.title.clone(),
+            part: 0,
+            parts: parts.len() as u32,
+        };
+
+        // Every part is its own job, so the parts can be paused and prioritized separately. The
+        // earlier parts are started first, so they're done first.
+        let conversions = parts.iter().enumerate().map(|(i, body)| {
+            let part = ArticleTextSubmission {
+                title: format!("{} (Part {} of {})", article.title, i + 1, parts.len()),
+                body: body.to_string(),
+                style: article.style,
+                options: article.options.clone(),
+                split: false,
+                voice: Some(voice.name.clone()),
+                source_type: article.source_type,
+                background: false,
+                force: article.force,
+            };
+            let group = ArticleGroup {
+                part: i as u32 + 1,
+                ..group.clone()
+            };
+            let (voice, byline) = (&voice, &byline);
 
-    Ok(meta.id)
+            async move {
+                let mut meta = add_article_by_text(converter, &part, byline, voice).await?;
+                meta.group = Some(group);
+                Ok(meta)
+            }
+        });
+        join_all(conversions).await
+    };
+
+    // Save the metadata of every part that made it, even if some other part didn't
+    let mut metas = Vec::new();
+    let mut first_err = None;
+    for res in results {
+        match res {
+            Ok(mut meta) => {
+                meta.source_url = source.url.clone();
+                meta.author = source.author.clone();
+                meta.publication = source.publication.clone();
+                meta.feed = source.feed.clone();
+                meta.source_type = source.source_type;
+                meta.image = source.image.clone();
+                meta.text_hash = Some(text_hash.clone());
+                let _ = save_metadata(&meta, audio_blob_dir)
+                    .map_err(|e| tracing::error!("Error saving metadata: {e}"));
+                metas.push(meta);
+            }
+            Err(e) => {
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_err {
+        Some(e) => Err(e),
+        None => Ok(metas),
+    }
 }
 
-/// The real logic. Converts the given article contents to speech, and returns the new filename
+/// The real logic. Converts the given article contents to speech in the given voice, and returns the
+/// new filename. The byline is read before the article if its options ask for it.
 async fn add_article_by_text(
+    converter: &Converter,
     article: &ArticleTextSubmission,
-    tts_rate_limiter: RateLimiter,
-    audio_blob_dir: &str,
+    byline: &Byline<'_>,
+    voice: &Voice,
 ) -> Result<ArticleMetadata, AddArticleError> {
     tracing::debug!("Processing article with title '{}'", article.title);
+    let audio_blob_dir = &converter.audio_blob_dir;
 
-    // Serialize the article and get its bytelen
-    let text = article.serialize();
-    let text_len: NonZeroU32 = {
-        let n = text.len();
-        let nzn = NonZeroUsize::new(n).or(NonZeroUsize::new(1)).unwrap();
-        NonZeroU32::try_from(nzn).with_context(|| "Article is is far too large")?
-    };
+    // Look up the ambient bed first, so a bad request doesn't count against the rate limit
+    let ambient_bed = converter
+        .ambient_beds
+        .resolve(article.options.ambient_bed.as_deref())?;
 
-    // If the article bytelen exceeds the limit, error out
-    if tts_rate_limiter.base_rl.check_n(text_len).is_err() {
-        Err(anyhow!(
-            "Usage limit exceeded. This server processes at most {} letters per minute.",
-            tts_rate_limiter.quota.burst_size().get(),
-        ))?;
-    }
+    // Serialize the article and check it against the rate limit
+    let text = frontmatter::narrated_text(article, byline);
+    converter.tts_rate_limiter.check(&text)?;
 
-    let id = derive_article_id(&article);
+    let id = derive_article_id(article);
 
-    // Fail if the article already exists
+    // Fail if the article already exists, unless it's being converted again on purpose. Then the
+    // new audio replaces the old once it's done.
     let savepath = Path::new(&audio_blob_dir).join(&id).with_extension("mp3");
-    if savepath.exists() {
+    if savepath.exists() && !article.force {
         Err(anyhow!("File '{:?}' already exists", savepath))?;
     }
 
@@ -169,17 +654,71 @@ async fn add_article_by_text(
         .open(&tmp_savepath)
         .map_err(|e| anyhow!("Couldn't open tmp savefile '{:?}': {:?}", tmp_savepath, e))?;
 
-    // Try to do a TTS and save to the savefile. On error, make sure to clean up the empty file
-    tts_to_file(&mut tmp_savefile, text).await.map_err(|e| {
+    // Try to do a TTS and save to the savefile. On error, make sure to clean up the empty file, and
+    // the chunks saved along the way, since there's no article to finish
+    let job = converter.job_store.start(&article.title);
+    let cache = ChunkCache::new(audio_blob_dir, &id);
+    let req = TtsRequest {
+        text,
+        voice: voice.clone(),
+        style: article.style,
+        options: article.options.clone(),
+    };
+    let (remaining_text, article_transcript) = tts_to_file(
+        &converter.voice_registry,
+        &mut tmp_savefile,
+        req,
+        ambient_bed.as_ref(),
+        false,
+        &job,
+        &cache,
+    )
+    .await
+    .map_err(|e| {
+        let _ = cache
+            .clear()
+            .map_err(|e| tracing::error!("Error removing saved chunks of {id}: {e}"));
         // Remove the file
-        if let Err(f) = fs::remove_file(&tmp_savepath) {
-            let context = format!("could not delete {id}: {f}");
-            e.0.context(context).into()
-        } else {
-            e
+        match (fs::remove_file(&tmp_savepath), e) {
+            (Err(f), AddArticleError::Other(e)) => {
+                let context = format!("could not delete {id}: {f}");
+                e.context(context).into()
+            }
+            (_, e) => e,
         }
     })?;
 
+    // If synthesis failed partway, keep what we have and remember what's left, so the conversion
+    // can be finished later. This has to happen before the audio is visible to list-articles. The
+    // saved chunks are kept too, so the conversion only makes the requests that are missing.
+    let incomplete = remaining_text.is_some();
+    match remaining_text {
+        Some(remaining_text) => {
+            let pending = PendingSynthesis {
+                remaining_text,
+                style: article.style,
+                options: article.options.clone(),
+                voice: Some(voice.name.clone()),
+            };
+            pending::save(audio_blob_dir, &id, &pending)?;
+        }
+        None => {
+            let _ = cache
+                .clear()
+                .map_err(|e| tracing::error!("Error removing saved chunks of {id}: {e}"));
+            // An article converted again may have been left incomplete the first time
+            if pending::exists(audio_blob_dir, &id) {
+                pending::remove(audio_blob_dir, &id)?;
+            }
+        }
+    }
+    let _ = transcript::save(audio_blob_dir, &id, &article_transcript)
+        .map_err(|e| tracing::error!("Error saving transcript of {id}: {e}"));
+
+    // Say how long the audio is up front, so players can seek before they've downloaded it all
+    let _ = audio::write_info_frame(&tmp_savepath)
+        .map_err(|e| tracing::error!("Error writing Info frame of {id}: {e}"));
+
     // TTS was successful, change the filename
     std::fs::rename(&tmp_savepath, &savepath)
         .map_err(|e| anyhow!("could not rename {:?} to {:?}: {e}", tmp_savepath, savepath))?;
@@ -200,70 +739,755 @@ async fn add_article_by_text(
     )
     .to_string();
 
+    // Make the article's text searchable. The article is usable without this, so don't fail over it
+    let _ = converter
+        .search_index
+        .add(audio_blob_dir, &id, &truncated_title, &article.body)
+        .await
+        .map_err(|e| tracing::error!("Error indexing article {id}: {e}"));
+
     // Return the metadata
+    job.finish();
     Ok(ArticleMetadata {
         id,
         title: truncated_title,
         datetime_added: Some(unix_epoch_now),
         source_url: None,
+        author: None,
+        publication: None,
+        incomplete,
+        duration_secs: None,
+        group: None,
+        reading_profile: Some(reading_profile::analyze(&article.body)),
+        feed: None,
+        voice: Some(voice.name.clone()),
+        source_type: None,
+        image: None,
+        rating: None,
+        text_hash: Some(hash_text(&article.body)),
     })
 }
 
-/// The real logic. Fetches the article at the given URL, converts it to speech, and returns the
-/// new filename
-async fn add_article_by_url(
+/// A page fetched for the article on it
+struct FetchedPage {
+    /// The article's own URL
+    url: String,
+    /// The page as it was fetched
+    html: Vec<u8>,
+    /// The page minus its cookie banners and the like
+    decluttered: Vec<u8>,
+    /// What trafilatura found on the page
+    extracted: ExtractedArticle,
+    /// The URL of the page's AMP version, if it has one
+    amp: Option<String>,
+}
+
+/// Fetches the page of the article at the given URL, unless it's in the page cache, and runs
+/// trafilatura on it. Fails if the article is already in the library, unless `force` is set.
+async fn fetch_page(
+    converter: &Converter,
     url: &str,
-    tts_rate_limiter: RateLimiter,
-    audio_blob_dir: &str,
-) -> Result<ArticleMetadata, AddArticleError> {
-    // TODO: Check earlier that trafilatura is present
-
-    // Run trafilatura on the given URL
-    let output = Command::new("../python_deps/bin/trafilatura")
-        .env("PYTHONPATH", "../python_deps")
-        .arg("--json")
-        .arg("--URL")
-        .arg(&url)
-        .output()
+    force: bool,
+) -> Result<FetchedPage, AddArticleError> {
+    let (audio_blob_dir, page_cache) = (&converter.audio_blob_dir, &converter.page_cache);
+    // Resolve the URL the article was shared under to the article's own URL, and don't convert
+    // articles that are already in the library
+    let already_added = |url: &str| match canonical::find_in_library(audio_blob_dir, url) {
+        Some(meta) if !force => Err(AddArticleError::AlreadyAdded(Box::new(meta))),
+        _ => Ok(()),
+    };
+    let url = canonical::unwrap_url(url);
+    already_added(&url)?;
+
+    // Fetch the page. Once we have it, it might say its article is somewhere else.
+    let Page {
+        url: fetched_url,
+        mut html,
+    } = page_cache.fetch(&url).await?;
+    let page = canonical::inspect_page(&html, &fetched_url);
+    let mut amp = page.amp;
+    let url = if page.canonical != url {
+        already_added(&page.canonical)?;
+        // AMP pages are stripped-down copies of the article, so get the full one if we can
+        if page.is_amp {
+            match page_cache.fetch(&page.canonical).await {
+                Ok(full_page) => {
+                    html = full_page.html;
+                    amp = Some(fetched_url);
+                }
+                Err(e) => tracing::warn!("Using the AMP page, since the full page failed: {e}"),
+            }
+        }
+        page.canonical
+    } else {
+        url
+    };
+
+    // Run extraction on the page, minus its cookie banners and the like. The page is archived as
+    // fetched, so that re-extraction uses whatever the clutter rules are by then.
+    let decluttered = converter.clutter_rules.strip(&url, &html);
+    let extracted = extract(&decluttered).await?;
+    Ok(FetchedPage {
+        url,
+        html,
+        decluttered,
+        extracted,
+        amp,
+    })
+}
+
+/// Finds the article's text on the given page with the given automatic strategy. Returns `None` if
+/// the strategy finds nothing, e.g., because the page has no AMP version.
+async fn run_strategy(
+    converter: &Converter,
+    strategy: ExtractionStrategy,
+    page: &FetchedPage,
+) -> Option<String> {
+    let text = match strategy {
+        ExtractionStrategy::Readability => readability_text(&page.decluttered),
+        ExtractionStrategy::Trafilatura => Some(page.extracted.text.clone()),
+        ExtractionStrategy::Amp => {
+            let amp_url = page.amp.as_deref()?;
+            let amp_page = converter
+                .page_cache
+                .fetch(amp_url)
+                .await
+                .map_err(|e| tracing::warn!("Couldn't fetch the AMP version of {}: {e}", page.url))
+                .ok()?;
+            let html = converter.clutter_rules.strip(amp_url, &amp_page.html);
+            // AMP pages are plain enough that Readability usually gets them right
+            match readability_text(&html).filter(|text| looks_like_article(text)) {
+                Some(text) => Some(text),
+                None => extract(&html).await.ok().map(|extracted| extracted.text),
+            }
+        }
+        ExtractionStrategy::Selector | ExtractionStrategy::Plugin => None,
+    };
+    text.filter(|text| !text.trim().is_empty())
+}
+
+/// Finds the article's text on the given page by trying the automatic strategies in turn. Returns
+/// the first text that looks like an article, or if none does, the longest text any of them found.
+async fn find_body(
+    converter: &Converter,
+    page: &FetchedPage,
+) -> Option<(String, ExtractionStrategy)> {
+    let mut longest: Option<(String, ExtractionStrategy)> = None;
+    for strategy in ExtractionStrategy::AUTOMATIC {
+        let Some(text) = run_strategy(converter, strategy, page).await else {
+            continue;
+        };
+        if looks_like_article(&text) {
+            return Some((text, strategy));
+        }
+        tracing::debug!("{strategy:?} found no article on {}", page.url);
+        if longest.as_ref().is_none_or(|(l, _)| text.len() > l.len()) {
+            longest = Some((text, strategy));
+        }
+    }
+    longest
+}
+
+/// Picks the body of the article on the given page, and the strategy that found it. A selector
+/// given by the client wins, then a strategy given by the client, then the rule saved for the
+/// article's site, then the site's extractor plugin, then the first automatic strategy that finds
+/// an article. If that isn't sure of its text, the client is asked to pick, unless it said to trust
+/// the extraction.
+async fn choose_body(
+    converter: &Converter,
+    submission: &ArticleUrlSubmission,
+    page: &FetchedPage,
+) -> Result<(String, ExtractionStrategy), AddArticleError> {
+    let url = &page.url;
+    let extraction_rules = &converter.extraction_rules;
+    if let Some(selector) = &submission.selector {
+        let body = select_text(&page.decluttered, selector)?;
+        if submission.save_selector {
+            extraction_rules.set(url, selector).await?;
+        }
+        return Ok((body, ExtractionStrategy::Selector));
+    }
+
+    // Selectors and plugins aren't strategies the client can pick. A previewed plugin's text is
+    // found again below.
+    if let Some(strategy) = submission
+        .strategy
+        .filter(|s| !matches!(s, ExtractionStrategy::Selector | ExtractionStrategy::Plugin))
+    {
+        let body = run_strategy(converter, strategy, page)
+            .await
+            .ok_or_else(|| anyhow!("{} found no text on {url}", strategy.description()))?;
+        return Ok((body, strategy));
+    }
+
+    // A saved rule can stop working if the site changes its layout. Fall back to the extractor then.
+    if let Some(selector) = extraction_rules.get(url) {
+        match select_text(&page.decluttered, &selector) {
+            Ok(body) if !body.is_empty() => return Ok((body, ExtractionStrategy::Selector)),
+            Ok(_) => tracing::warn!("Extraction rule '{selector}' for {url} matched no text"),
+            Err(e) => tracing::warn!("Extraction rule for {url} failed: {e}"),
+        }
+    }
+
+    // Like a saved rule, a plugin falls back to the extractor if it stops finding the article
+    if let Some(body) = extraction_rules.plugins().extract(url, &page.html).await {
+        if looks_like_article(&body) {
+            return Ok((body, ExtractionStrategy::Plugin));
+        }
+        tracing::warn!("The extractor plugin for {url} found no article");
+    }
+
+    let (body, strategy) = find_body(converter, page)
         .await
-        .map_err(|e| anyhow!("IO error running trafulatura: {:?}", e))?;
-    // See if the command failed
-    if !output.status.success() {
-        Err(anyhow!("Text extraction failed"))?;
+        .ok_or_else(|| anyhow!("Couldn't find any text on {url}"))?;
+    if !submission.trust_extraction {
+        if let Some(choice) = check_extraction(&page.decluttered, &body) {
+            return Err(AddArticleError::Uncertain(choice));
+        }
     }
 
-    // Convert the CLI output from JSON and turn it into a `ArticleTextSubmission`
-    let parsed_res: ExtractedArticle =
-        serde_json::from_slice(&output.stdout).map_err(|_| anyhow!("Text extraction failed"))?;
+    Ok((body, strategy))
+}
+
+/// Fetches the article at the given URL and returns what would be read aloud if it were converted,
+/// without converting it
+async fn preview_article_endpoint(
+    Json(submission): Json<ArticleUrlSubmission>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Json<ExtractionPreview>, AddArticleError> {
+    tracing::debug!("Previewing article at URL: {}", submission.url);
+    let converter = converter.in_library(audio_blob_dir);
+    let page = fetch_page(&converter, &submission.url, submission.force).await?;
+
+    // The preview is how the user checks the text, so it's never turned down as uncertain. Rules
+    // are only saved once the user converts with them.
+    let submission = ArticleUrlSubmission {
+        trust_extraction: true,
+        save_selector: false,
+        ..submission
+    };
+    let (body, strategy) = choose_body(&converter, &submission, &page).await?;
+
+    let alternatives = extract::alternatives(&page.decluttered, &body);
+    let (mut title, mut body) = (page.extracted.title, body);
+    converter
+        .extraction_rules
+        .transform(&page.url, &mut title, &mut body);
+    let text = normalize(&body);
+    Ok(Json(ExtractionPreview {
+        title,
+        words: word_count(&text),
+        looks_like_article: looks_like_article(&text),
+        text,
+        strategy,
+        alternatives,
+    }))
+}
+
+/// The real logic. Fetches the article at the submitted URL, converts it to speech, and returns the
+/// new articles' metadata
+async fn add_article_by_url(
+    converter: &Converter,
+    submission: &ArticleUrlSubmission,
+    feed: Option<&str>,
+    voice: Option<&Voice>,
+) -> Result<Vec<ArticleMetadata>, AddArticleError> {
+    let page = fetch_page(converter, &submission.url, submission.force).await?;
+    let (mut body, strategy) = choose_body(converter, submission, &page).await?;
+    tracing::debug!("Extracted {} with {strategy:?}", page.url);
+
+    // Let the site's transform scripts clean up what was extracted
+    let FetchedPage {
+        url,
+        html,
+        mut extracted,
+        ..
+    } = page;
+    converter
+        .extraction_rules
+        .transform(&url, &mut extracted.title, &mut body);
+
+    // Keep the lead image for the lockscreen. The article is fine without it, so don't fail over it.
+    let fetcher = converter.page_cache.fetcher();
+    let image = match images::lead_image_url(&html, &url, extracted.image.as_deref()) {
+        Some(image_url) => images::fetch(&converter.audio_blob_dir, fetcher, &image_url)
+            .await
+            .map_err(|e| tracing::warn!("Not keeping the lead image of {url}: {e}"))
+            .ok(),
+        None => None,
+    };
+    let source = ArticleSource {
+        url: Some(url.clone()),
+        publication: extracted.publication(&url),
+        author: extracted.author,
+        published: extracted.date,
+        feed: feed.map(str::to_string),
+        source_type: Some(SourceType::Web),
+        image,
+    };
+    let text_submission = ArticleTextSubmission {
+        title: extracted.title,
+        body,
+        style: submission.style,
+        options: submission.options.clone(),
+        split: submission.split,
+        voice: submission.voice.clone(),
+        source_type: None,
+        background: false,
+        force: submission.force,
+    };
+
+    // Now that we have the article body, call down to add_article_in_parts
+    let metas = add_article_in_parts(converter, &text_submission, &source, voice).await?;
+
+    // Keep the fetched page so we can re-extract later without fetching again. Re-extraction
+    // replaces a single article, so there's no point keeping the page of one that was split up.
+    if let [meta] = metas.as_slice() {
+        let _ = converter
+            .html_archive
+            .save(&meta.id, &html)
+            .map_err(|e| tracing::error!("Error archiving HTML: {e}"));
+    }
+
+    Ok(metas)
+}
+
+/// The real logic. Re-extracts the article with the given ID from its archived HTML, and
+/// optionally replaces its audio with a synthesis of the new text
+async fn re_extract_article(
+    converter: &Converter,
+    id: &str,
+    resynthesize: bool,
+) -> Result<ReExtractResponse, AddArticleError> {
+    let Converter {
+        voice_registry,
+        audio_blob_dir,
+        html_archive,
+        search_index,
+        extraction_rules,
+        clutter_rules,
+        ..
+    } = converter;
+    // Run extraction on the archived page. If the article's site has rules, they're used like they
+    // would be for a new article.
+    let old_path = Path::new(audio_blob_dir).join(id).with_extension("mp3");
+    let old_meta = get_metadata(&old_path)?;
+    let source_url = old_meta.source_url.as_deref().unwrap_or_default();
+    let html = clutter_rules.strip(source_url, &html_archive.load(id)?);
+    let mut extracted = extract(&html).await?;
+    let ruled_body = extraction_rules
+        .get(source_url)
+        .and_then(|selector| select_text(&html, &selector).ok())
+        .filter(|body| !body.is_empty());
+    let mut body = ruled_body.unwrap_or(extracted.text);
+    extraction_rules.transform(source_url, &mut extracted.title, &mut body);
+    // The style and options an article was made with aren't recorded, so resynthesis uses the
+    // defaults
     let text_submission = ArticleTextSubmission {
-        title: parsed_res.title,
-        body: parsed_res.text,
+        title: extracted.title,
+        body,
+        style: SpeakingStyle::default(),
+        options: SynthesisOptions::default(),
+        split: false,
+        voice: None,
+        source_type: None,
+        background: false,
+        force: false,
+    };
+
+    // If we're not resynthesizing, or if the extracted text didn't change, we're done
+    let new_id = derive_article_id(&text_submission);
+    if !resynthesize || new_id == id {
+        return Ok(ReExtractResponse {
+            id: id.to_string(),
+            title: text_submission.title,
+            body: text_submission.body,
+        });
+    }
+
+    // Synthesize the new text in the voice the article was read in, and carry the old metadata
+    // over to the new ID
+    let recorded_voice = old_meta
+        .voice
+        .as_deref()
+        .and_then(|v| voice_registry.resolve_recorded(v));
+    let voice = pick_voice(
+        voice_registry,
+        recorded_voice.as_ref(),
+        &text_submission.body,
+    );
+    let byline = Byline {
+        author: old_meta.author.as_deref(),
+        publication: old_meta.publication.as_deref(),
+        published: None,
+    };
+    let new_meta = add_article_by_text(converter, &text_submission, &byline, &voice).await?;
+    let meta = ArticleMetadata {
+        id: new_meta.id,
+        title: new_meta.title,
+        incomplete: new_meta.incomplete,
+        reading_profile: new_meta.reading_profile,
+        voice: new_meta.voice,
+        text_hash: new_meta.text_hash,
+        ..old_meta
+    };
+    let _ = save_metadata(&meta, audio_blob_dir)
+        .map_err(|e| tracing::error!("Error saving metadata: {e}"));
+
+    // The new article replaces the old one. Delete the old audio and move the archived HTML over
+    fs::remove_file(&old_path)
+        .map_err(|e| anyhow!("could not delete old article {:?}: {e}", old_path))?;
+    let _ = pending::remove(audio_blob_dir, id)
+        .map_err(|e| tracing::error!("Error removing old pending synthesis: {e}"));
+    let _ = transcript::remove(audio_blob_dir, id)
+        .map_err(|e| tracing::error!("Error removing old transcript: {e}"));
+    let _ = search_index
+        .remove(audio_blob_dir, id)
+        .await
+        .map_err(|e| tracing::error!("Error removing old article from search index: {e}"));
+    let _ = html_archive
+        .rename(id, &meta.id)
+        .map_err(|e| tracing::error!("Error moving archived HTML: {e}"));
+
+    Ok(ReExtractResponse {
+        id: meta.id,
+        title: text_submission.title,
+        body: text_submission.body,
+    })
+}
+
+/// The real logic. Synthesizes the rest of an incomplete article and appends it to the article's
+/// audio
+async fn finish_article(
+    converter: &Converter,
+    id: &str,
+) -> Result<ArticleMetadata, AddArticleError> {
+    let Converter {
+        tts_rate_limiter,
+        voice_registry,
+        audio_blob_dir,
+        ambient_beds,
+        job_store,
+        ..
+    } = converter;
+    let PendingSynthesis {
+        remaining_text,
+        style,
+        options,
+        voice,
+    } = pending::load(audio_blob_dir, id)?;
+    // Carry on in the voice the article was started in. Articles from before voices were recorded
+    // were read in the default voice.
+    let voice = voice
+        .as_deref()
+        .and_then(|v| voice_registry.resolve_recorded(v))
+        .unwrap_or_else(|| voice_registry.voice_for_language(None));
+    let ambient_bed = ambient_beds.resolve(options.ambient_bed.as_deref())?;
+    tts_rate_limiter.check(&remaining_text)?;
+
+    let savepath = Path::new(audio_blob_dir).join(id).with_extension("mp3");
+    let job = job_store.start(&get_metadata(&savepath)?.title);
+
+    // Start with a copy of the existing audio. MP3s can be concatenated, so the rest of the
+    // article just gets appended. The existing ID3 tags stay at the front.
+    let tmp_savepath = Path::new(audio_blob_dir).join(id).with_extension("mp3.tmp");
+    fs::copy(&savepath, &tmp_savepath)
+        .map_err(|e| anyhow!("Couldn't copy {:?} to {:?}: {e}", savepath, tmp_savepath))?;
+    let mut tmp_savefile = OpenOptions::new()
+        .append(true)
+        .open(&tmp_savepath)
+        .map_err(|e| anyhow!("Couldn't open tmp savefile '{:?}': {:?}", tmp_savepath, e))?;
+
+    let req = TtsRequest {
+        text: remaining_text,
+        voice: voice.clone(),
+        style,
+        options: with_encoding_of(&options, &savepath),
+    };
+    let res = tts_to_file(
+        voice_registry,
+        &mut tmp_savefile,
+        req,
+        ambient_bed.as_ref(),
+        true,
+        &job,
+        &ChunkCache::new(audio_blob_dir, id),
+    )
+    .await;
+    let (remaining_text, rest_transcript) = match res {
+        Ok(r) => r,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_savepath);
+            return Err(e);
+        }
     };
 
-    // Now that we have the article body, call down to add_article_by_text
-    let mut meta = add_article_by_text(&text_submission, tts_rate_limiter, audio_blob_dir).await?;
-    // Add the URL to the metadata
-    meta.source_url = Some(url.to_string());
+    // Extend the transcript with the newly synthesized text. Articles from before transcripts were
+    // kept don't have one, and a partial one would be misleading, so they stay without.
+    if let Ok(mut article_transcript) = transcript::load(audio_blob_dir, id) {
+        transcript::append(&mut article_transcript, rest_transcript);
+        let _ = transcript::save(audio_blob_dir, id, &article_transcript)
+            .map_err(|e| tracing::error!("Error saving transcript of {id}: {e}"));
+    }
+
+    // Record whatever is still left over. If nothing is, the article is complete
+    let incomplete = remaining_text.is_some();
+    match remaining_text {
+        Some(remaining_text) => {
+            let pending = PendingSynthesis {
+                remaining_text,
+                style,
+                options,
+                voice: Some(voice.name),
+            };
+            pending::save(audio_blob_dir, id, &pending)?;
+        }
+        None => pending::remove(audio_blob_dir, id)?,
+    }
+
+    // The audio is longer now, so the Info frame has to say so
+    let _ = audio::write_info_frame(&tmp_savepath)
+        .map_err(|e| tracing::error!("Error writing Info frame of {id}: {e}"));
+
+    // Replace the old audio
+    std::fs::rename(&tmp_savepath, &savepath)
+        .map_err(|e| anyhow!("could not rename {:?} to {:?}: {e}", tmp_savepath, savepath))?;
 
+    let mut meta = get_metadata(&savepath)?;
+    meta.incomplete = incomplete;
+    job.finish();
     Ok(meta)
 }
 
-/// Converts an article to speech and saves to the given file
-async fn tts_to_file(file: &mut File, text: String) -> Result<(), AddArticleError> {
-    let api_key = get_api_key().map_err(|e| anyhow!("Failed to get Google API key: {:?}", e))?;
+/// Follows each of the given new articles with a chapter of its key points, if the options ask for
+/// it and there's a language model to pick them. The articles are fine without key points, so
+/// failures are only logged.
+async fn add_key_points(
+    converter: &Converter,
+    metas: &[ArticleMetadata],
+    style: SpeakingStyle,
+    options: &SynthesisOptions,
+) {
+    if !options.key_points {
+        return;
+    }
+    if !converter.language_model.is_enabled() {
+        tracing::warn!("Key points were asked for, but no language model is configured");
+        return;
+    }
 
-    // Make the TTS request
+    // Key points of the beginning of an article would be misleading, so incomplete articles get
+    // none
+    for meta in metas.iter().filter(|m| !m.incomplete) {
+        let res = append_key_points(converter, meta, style, options).await;
+        if let Err(e) = res {
+            tracing::error!("Error adding key points to {}: {:?}", meta.id, e);
+        }
+    }
+}
+
+/// Asks the language model for the key points of the given article, and appends them to its audio
+/// and transcript, read in the article's voice
+async fn append_key_points(
+    converter: &Converter,
+    meta: &ArticleMetadata,
+    style: SpeakingStyle,
+    options: &SynthesisOptions,
+) -> Result<(), AddArticleError> {
+    let Converter {
+        tts_rate_limiter,
+        voice_registry,
+        audio_blob_dir,
+        job_store,
+        language_model,
+        ..
+    } = converter;
+    let id = &meta.id;
+    let mut article_transcript = transcript::load(audio_blob_dir, id)?;
+    let text = key_points::summarize(language_model, &article_transcript.text).await?;
+    tts_rate_limiter.check(&text)?;
+    let voice = meta
+        .voice
+        .as_deref()
+        .and_then(|v| voice_registry.resolve_recorded(v))
+        .unwrap_or_else(|| voice_registry.voice_for_language(None));
+    let job = job_store.start(&format!("Key points of {}", meta.title));
+
+    // Append to a copy of the audio, like finishing an article does. The key points are read
+    // without the article's ambient bed, so they're easy to follow.
+    let savepath = Path::new(audio_blob_dir).join(id).with_extension("mp3");
+    let tmp_savepath = Path::new(audio_blob_dir).join(id).with_extension("mp3.tmp");
+    fs::copy(&savepath, &tmp_savepath)
+        .map_err(|e| anyhow!("Couldn't copy {:?} to {:?}: {e}", savepath, tmp_savepath))?;
+    let mut tmp_savefile = OpenOptions::new()
+        .append(true)
+        .open(&tmp_savepath)
+        .map_err(|e| anyhow!("Couldn't open tmp savefile '{:?}': {:?}", tmp_savepath, e))?;
+    let cache = ChunkCache::new(audio_blob_dir, id);
     let req = TtsRequest {
         text,
-        use_wavenet: true,
+        voice,
+        style,
+        options: with_encoding_of(options, &savepath),
     };
-    let bytes = tts(&api_key, req)
-        .await
-        .map_err(|e| anyhow!("TTS failed: {:?}", e))?;
+    let res = tts_to_file(
+        voice_registry,
+        &mut tmp_savefile,
+        req,
+        None,
+        true,
+        &job,
+        &cache,
+    )
+    .await;
+    let _ = cache
+        .clear()
+        .map_err(|e| tracing::error!("Error removing saved chunks of {id}: {e}"));
+    // Half the key points are worse than none
+    let points_transcript = match res {
+        Ok((None, t)) => t,
+        Ok((Some(_), _)) => {
+            let _ = fs::remove_file(&tmp_savepath);
+            Err(anyhow!("Synthesis of the key points stopped partway"))?
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_savepath);
+            return Err(e);
+        }
+    };
+
+    let _ = audio::write_info_frame(&tmp_savepath)
+        .map_err(|e| tracing::error!("Error writing Info frame of {id}: {e}"));
+    std::fs::rename(&tmp_savepath, &savepath)
+        .map_err(|e| anyhow!("could not rename {:?} to {:?}: {e}", tmp_savepath, savepath))?;
+    transcript::append(&mut article_transcript, points_transcript);
+    transcript::save(audio_blob_dir, id, &article_transcript)?;
+
+    job.finish();
+    Ok(())
+}
+
+/// Returns the given options with the encoding of the MP3 at the given path, so that audio appended
+/// to it is encoded the same way, whatever the default is now
+fn with_encoding_of(options: &SynthesisOptions, path: &Path) -> SynthesisOptions {
+    SynthesisOptions {
+        encoding: audio::mp3_encoding(path).or(options.encoding),
+        ..options.clone()
+    }
+}
+
+/// Converts the text of the given request to speech and saves it to the given file. If
+/// `continues_audio` is set, the file already contains the beginning of the article. If synthesis fails partway through, what was
+/// synthesized is saved, and the text that's left is returned. The transcript of what was
+/// synthesized is returned either way. Progress is reported to the given job, and the audio of
+/// every TTS request is saved to the given chunk cache.
+async fn tts_to_file(
+    voice_registry: &VoiceRegistry,
+    file: &mut File,
+    req: TtsRequest,
+    ambient_bed: Option<&AmbientBed>,
+    continues_audio: bool,
+    job: &JobHandle,
+    cache: &ChunkCache,
+) -> Result<(Option<String>, ArticleTranscript), AddArticleError> {
+    // Make the TTS request
+    let text = req.text.clone();
+    let req = TtsRequest {
+        options: voice_registry.with_default_encoding(req.options),
+        ..req
+    };
+    let output = tts(
+        voice_registry.engine(),
+        req,
+        ambient_bed.map(AmbientBed::mix),
+        continues_audio,
+        Some(job),
+        Some(cache),
+    )
+    .await
+    .map_err(|e| {
+        job.fail(&e.to_string());
+        anyhow!("TTS failed: {:?}", e)
+    })?;
 
     // Save the file
-    file.write_all(&bytes)
+    file.write_all(&output.mp3)
         .map_err(|e| anyhow!("Save failed: {:?}", e))?;
 
-    Ok(())
+    // The remaining text is the end of the text, so everything before it was spoken
+    let remaining_len = output
+        .unfinished
+        .as_ref()
+        .map(|(r, _)| r.len())
+        .unwrap_or(0);
+    let spoken = &text[..text.len() - remaining_len];
+    let mut transcript = transcript::new(spoken, &output.timepoints, output.duration_secs);
+    transcript.loudness = output.loudness;
+
+    let remaining_text = output.unfinished.map(|(remaining_text, e)| {
+        tracing::error!(
+            "TTS failed partway through. Keeping what was synthesized: {:?}",
+            e
+        );
+        remaining_text
+    });
+    Ok((remaining_text, transcript))
+}
+
+#[test]
+fn test_split_into_parts() {
+    // A short article is a single part
+    assert_eq!(split_into_parts("Title\nBody"), vec!["Title\nBody"]);
+
+    // A long article is split between paragraphs, and no part is too long
+    let paragraph = "word ".repeat(1000);
+    let body = vec![paragraph.as_str(); 50].join("\n\n");
+    let parts = split_into_parts(&body);
+    assert_eq!(parts.len(), 3);
+    assert!(parts.iter().all(|part| part.len() <= MAX_ARTICLE_LEN));
+    assert!(parts.iter().all(|part| part.starts_with("word")));
+}
+
+#[tokio::test]
+async fn deleting_articles() {
+    let dir = std::env::temp_dir().join(format!("rtms-delete-test-{}", std::process::id()));
+    let audio_blob_dir = dir.join("audio_blobs");
+    fs::create_dir_all(&audio_blob_dir).unwrap();
+    let audio_blob_dir = audio_blob_dir.to_str().unwrap();
+    let html_archive = HtmlArchive::new(
+        dir.join("html").to_str().unwrap(),
+        crate::extract::HtmlRetention::Forever,
+    );
+    let search_index = SearchIndex::open(dir.join("index"), audio_blob_dir).unwrap();
+
+    // Everything kept about the article goes with it
+    let files = ["a.mp3", "a.pending.json", "a.transcript.json"];
+    for file in files {
+        fs::write(Path::new(audio_blob_dir).join(file), b"").unwrap();
+    }
+    html_archive.save("a", b"<p>Hi</p>").unwrap();
+    delete_article("a", audio_blob_dir, &html_archive, &search_index)
+        .await
+        .unwrap();
+    for file in files {
+        assert!(!Path::new(audio_blob_dir).join(file).exists());
+    }
+    assert!(html_archive.load("a").is_err());
+
+    // Articles that don't exist can't be deleted, and IDs can't reach outside the directory
+    assert!(
+        delete_article("a", audio_blob_dir, &html_archive, &search_index)
+            .await
+            .is_err()
+    );
+    fs::write(dir.join("outside.mp3"), b"").unwrap();
+    assert!(
+        delete_article("../outside", audio_blob_dir, &html_archive, &search_index)
+            .await
+            .is_err()
+    );
+    assert!(dir.join("outside.mp3").exists());
+
+    let _ = fs::remove_dir_all(&dir);
 }
@@ -0,0 +1,443 @@
+//! Managing the server's disk. Admins can list the audio of every library, with its size and when
+//! it was last fetched, and delete articles from any library. If the config sets
+//! `max_audio_megabytes`, the articles whose audio was fetched least recently are deleted whenever
+//! the audio takes up more than that.
+//!
+//! Admins can also list and clear the cache of fetched pages, whose expired pages are deleted along
+//! the way.
+//!
+//! When audio was last fetched is recorded as `/audio/` serves it, and saved every few minutes, so
+//! a restart only loses the last few minutes of it. Audio that hasn't been fetched since the server
+//! started keeping track counts as fetched when it was made.
+//!
+//! Deleting an account's library also forgets what the other stores keep about it outside its
+//! directory: its shared links, collection subscriptions, reminders and push subscriptions, inbox,
+//! and feed subscriptions. The takeout has them too.
+
+use crate::{
+    accounts::{self, Admin},
+    add_article,
+    audio_blobs::{self, AudioHashes},
+    extract::HtmlArchive,
+    federation::SubscriptionStore,
+    feeds::FeedStore,
+    inbox::InboxStore,
+    list_articles::{self, LibraryCache},
+    page_cache::PageCache,
+    pending,
+    reminders::ReminderStore,
+    remote::RemoteControls,
+    search::SearchIndex,
+    shares::ShareStore,
+};
+use common::{AdminDeletion, AudioUsage, PageCacheClearing, PageCacheUsage, StoredAudio};
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+
+/// How often the fetch times are saved and the audio is checked against the limit
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Returns the given time in seconds since the Unix epoch
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// When the audio of each article was last fetched, in seconds since the Unix epoch, keyed by the
+/// path of its MP3. This is saved as JSON.
+#[derive(Clone)]
+pub(crate) struct FetchLog {
+    path: PathBuf,
+    fetches: Arc<Mutex<HashMap<PathBuf, u64>>>,
+}
+
+impl FetchLog {
+    /// Loads the fetch times from the given file. If the file doesn't exist, nothing's been fetched
+    /// yet.
+    pub(crate) fn load(path: &str) -> Result<FetchLog, AnyError> {
+        let path = PathBuf::from(path);
+        let fetches = if path.exists() {
+            let json = fs::read(&path)
+                .map_err(|e| anyhow!("Could not read fetch times {:?}: {e}", path))?;
+            serde_json::from_slice(&json)
+                .map_err(|e| anyhow!("Could not parse fetch times {:?}: {e}", path))?
+        } else {
+            HashMap::new()
+        };
+        Ok(FetchLog {
+            path,
+            fetches: Arc::new(Mutex::new(fetches)),
+        })
+    }
+
+    /// Records that the MP3 at the given path was just fetched
+    pub(crate) fn record(&self, mp3_path: &Path) {
+        self.fetches
+            .lock()
+            .unwrap()
+            .insert(mp3_path.to_path_buf(), unix_secs(SystemTime::now()));
+    }
+
+    /// Returns when the MP3 at the given path was last fetched, or the given time it was made if it
+    /// hasn't been
+    fn last_fetched(&self, mp3_path: &Path, made: u64) -> u64 {
+        self.fetches
+            .lock()
+            .unwrap()
+            .get(mp3_path)
+            .copied()
+            .unwrap_or(made)
+    }
+
+    /// Forgets the MP3 at the given path, since it's been deleted
+    fn forget(&self, mp3_path: &Path) {
+        self.fetches.lock().unwrap().remove(mp3_path);
+    }
+
+    /// Saves the fetch times
+    fn save(&self) -> Result<(), AnyError> {
+        let json = serde_json::to_vec(&*self.fetches.lock().unwrap())?;
+        fs::write(&self.path, json)
+            .map_err(|e| anyhow!("Could not save fetch times {:?}: {e}", self.path))
+    }
+}
+
+/// Everything needed to list and delete the audio of every library
+#[derive(Clone)]
+pub(crate) struct DiskManager {
+    pub(crate) audio_blob_dir: String,
+    /// The most bytes the audio can take up, if there's a limit
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) fetch_log: FetchLog,
+    pub(crate) library_cache: LibraryCache,
+    pub(crate) audio_hashes: AudioHashes,
+    pub(crate) html_archive: HtmlArchive,
+    pub(crate) search_index: SearchIndex,
+    pub(crate) page_cache: PageCache,
+    pub(crate) library_stores: LibraryStores,
+}
+
+/// The stores that keep things about each library outside its directory
+#[derive(Clone)]
+pub(crate) struct LibraryStores {
+    pub(crate) shares: ShareStore,
+    pub(crate) collection_subscriptions: SubscriptionStore,
+    pub(crate) reminders: ReminderStore,
+    pub(crate) inbox: InboxStore,
+    pub(crate) feeds: FeedStore,
+    pub(crate) remote_controls: RemoteControls,
+}
+
+impl LibraryStores {
+    /// Returns what each store keeps about the library in the given directory, as JSON files named
+    /// for the stores
+    pub(crate) fn take_out(
+        &self,
+        library_dir: &str,
+    ) -> Result<Vec<(&'static str, Vec<u8>)>, AnyError> {
+        Ok(vec![
+            ("shares.json", self.shares.take_out(library_dir)?),
+            (
+                "collection_subscriptions.json",
+                self.collection_subscriptions.take_out(library_dir)?,
+            ),
+            ("reminders.json", self.reminders.take_out(library_dir)?),
+            ("inbox.json", self.inbox.take_out(library_dir)?),
+            ("feeds.json", self.feeds.take_out(library_dir)?),
+        ])
+    }
+
+    /// Forgets everything the stores keep about the library in the given directory. Every store is
+    /// tried, even if one fails.
+    pub(crate) async fn forget(&self, library_dir: &str) -> Result<(), AnyError> {
+        self.remote_controls.forget_library(library_dir);
+        let results = [
+            self.shares.forget_library(library_dir).await,
+            self.collection_subscriptions
+                .forget_library(library_dir)
+                .await,
+            self.reminders.forget_library(library_dir).await,
+            self.inbox.forget_library(library_dir).await,
+            self.feeds.forget_library(library_dir).await,
+        ];
+        results.into_iter().collect()
+    }
+}
+
+impl DiskManager {
+    /// Lists the audio of every library
+    fn usage(&self) -> Result<AudioUsage, AnyError> {
+        let mut usage = AudioUsage {
+            max_bytes: self.max_bytes,
+            ..Default::default()
+        };
+        for (library, dir) in accounts::library_dirs(&self.audio_blob_dir)? {
+            usage.transcoded_bytes += audio_blobs::transcoded_bytes(&dir)?;
+            let dir_str = dir.to_str().ok_or_else(|| anyhow!("bad path {:?}", dir))?;
+            for meta in list_articles::load_catalog(dir_str, &self.library_cache)? {
+                let path = dir.join(&meta.id).with_extension("mp3");
+                let Ok(file_meta) = fs::metadata(&path) else {
+                    continue;
+                };
+                let made = file_meta.modified().map(unix_secs).unwrap_or_default();
+                usage.articles.push(StoredAudio {
+                    library: library.clone(),
+                    last_fetched: self.fetch_log.last_fetched(&path, made),
+                    bytes: file_meta.len(),
+                    id: meta.id,
+                    title: meta.title,
+                });
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Deletes the given article from the given library, with its transcoded copies and everything
+    /// else kept about it
+    async fn delete(&self, library: Option<&str>, id: &str) -> Result<(), AnyError> {
+        // IDs become file names, so don't let one point outside the library
+        if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+            bail!("invalid article ID {id:?}");
+        }
+        let dir = accounts::library_dir(&self.audio_blob_dir, library)?;
+        let dir_str = dir.to_str().ok_or_else(|| anyhow!("bad path {:?}", dir))?;
+        let path = dir.join(id).with_extension("mp3");
+
+        // The transcoded copies are named for the MP3's hash, so find them before it's gone
+        if let Ok(hash) = self.audio_hashes.hash_of(&path) {
+            let _ = audio_blobs::remove_transcoded(&dir, &hash)
+                .map_err(|e| tracing::error!("Error removing transcoded copies of {id}: {e}"));
+        }
+        add_article::delete_article(id, dir_str, &self.html_archive, &self.search_index).await?;
+        self.fetch_log.forget(&path);
+        Ok(())
+    }
+
+    /// Deletes the library of the given account, with everything kept about each of its articles
+    pub(crate) async fn delete_library(&self, name: &str) -> Result<(), AnyError> {
+        let dir = accounts::library_dir(&self.audio_blob_dir, Some(name))?;
+        if !dir.exists() {
+            return Ok(());
+        }
+        let dir_str = dir.to_str().ok_or_else(|| anyhow!("bad path {:?}", dir))?;
+        for meta in list_articles::load_catalog(dir_str, &self.library_cache)? {
+            if let Err(e) = self.delete(Some(name), &meta.id).await {
+                tracing::error!("Could not delete {} from {name:?}: {e}", meta.id);
+            }
+        }
+        let forgotten = self.library_stores.forget(dir_str).await;
+
+        // Whatever's left, e.g., progress, ratings, and the search index, goes with the directory
+        self.search_index.close(dir_str);
+        fs::remove_dir_all(&dir).map_err(|e| anyhow!("could not delete {:?}: {e}", dir))?;
+        self.library_cache
+            .lock()
+            .unwrap()
+            .retain(|path, _| !path.starts_with(&dir));
+        forgotten
+    }
+
+    /// Deletes the least recently fetched articles until the audio is under the limit, if there is
+    /// one
+    async fn evict(&self) -> Result<(), AnyError> {
+        let Some(max_bytes) = self.max_bytes else {
+            return Ok(());
+        };
+        let usage = self.usage()?;
+        for article in pick_evictions(&usage, max_bytes) {
+            let library = article.library.as_deref();
+            let dir = accounts::library_dir(&self.audio_blob_dir, library)?;
+            // Articles that are still being converted are still being written
+            if dir
+                .to_str()
+                .is_some_and(|d| pending::exists(d, &article.id))
+            {
+                continue;
+            }
+            tracing::info!(
+                "Deleting {} from {library:?} to stay under {max_bytes} bytes",
+                article.id
+            );
+            if let Err(e) = self.delete(library, &article.id).await {
+                tracing::error!("Could not delete {}: {e}", article.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Spawns a background task that periodically saves the fetch times, deletes audio to stay
+    /// under the limit, and deletes expired pages from the page cache
+    pub(crate) fn spawn_sweeper(&self) {
+        let disk = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = disk.fetch_log.save() {
+                    tracing::error!("{e}");
+                }
+                if let Err(e) = disk.evict().await {
+                    tracing::error!("Could not free up disk space: {e}");
+                }
+                if let Err(e) = disk.page_cache.prune() {
+                    tracing::error!("Could not prune the page cache: {e}");
+                }
+            }
+        });
+    }
+}
+
+/// Returns the articles to delete to get the given usage under the given number of bytes: the
+/// least recently fetched ones, oldest first
+fn pick_evictions(usage: &AudioUsage, max_bytes: u64) -> Vec<&StoredAudio> {
+    let total: u64 = usage.transcoded_bytes + usage.articles.iter().map(|a| a.bytes).sum::<u64>();
+    let mut oldest_first: Vec<&StoredAudio> = usage.articles.iter().collect();
+    oldest_first.sort_by_key(|a| a.last_fetched);
+
+    let mut freed = 0;
+    oldest_first
+        .into_iter()
+        .take_while(|article| {
+            let needed = total.saturating_sub(freed) > max_bytes;
+            freed += article.bytes;
+            needed
+        })
+        .collect()
+}
+
+// Sets the /api/admin/audio, /api/admin/delete-article, /api/admin/page-cache, and
+// /api/admin/clear-page-cache routes
+pub(crate) fn setup(router: Router, disk: DiskManager) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/admin/audio", get(list_audio))
+            .route("/admin/delete-article", post(delete_article))
+            .route("/admin/page-cache", get(list_page_cache))
+            .route("/admin/clear-page-cache", post(clear_page_cache))
+            .layer(Extension(disk)),
+    )
+}
+
+/// Lists the audio of every library
+async fn list_audio(
+    _: Admin,
+    Extension(disk): Extension<DiskManager>,
+) -> Result<Json<AudioUsage>, StatusCode> {
+    disk.usage().map(Json).map_err(|e| {
+        tracing::error!("Couldn't list stored audio: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Deletes an article from any library
+async fn delete_article(
+    _: Admin,
+    Json(AdminDeletion { library, id }): Json<AdminDeletion>,
+    Extension(disk): Extension<DiskManager>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    disk.delete(library.as_deref(), &id).await.map_err(|e| {
+        tracing::error!("Couldn't delete {id} from {library:?}: {e}");
+        (StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+    tracing::info!("An admin deleted {id} from {library:?}");
+    Ok(StatusCode::OK)
+}
+
+/// Lists the cached pages
+async fn list_page_cache(
+    _: Admin,
+    Extension(disk): Extension<DiskManager>,
+) -> Result<Json<PageCacheUsage>, StatusCode> {
+    disk.page_cache.usage().map(Json).map_err(|e| {
+        tracing::error!("Couldn't list cached pages: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Deletes the given page from the page cache, or every page if none is given
+async fn clear_page_cache(
+    _: Admin,
+    Json(PageCacheClearing { url }): Json<PageCacheClearing>,
+    Extension(disk): Extension<DiskManager>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let res = match &url {
+        Some(url) => disk.page_cache.remove(url),
+        None => disk.page_cache.clear(),
+    };
+    res.map_err(|e| {
+        tracing::error!("Couldn't clear the page cache: {e}");
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?;
+    tracing::info!(
+        "An admin cleared {} from the page cache",
+        url.as_deref().unwrap_or("everything")
+    );
+    Ok(StatusCode::OK)
+}
+
+#[test]
+fn evicting_least_recently_fetched() {
+    let article = |id: &str, bytes, last_fetched| StoredAudio {
+        library: None,
+        id: id.to_string(),
+        title: id.to_string(),
+        bytes,
+        last_fetched,
+    };
+    let usage = AudioUsage {
+        max_bytes: Some(100),
+        transcoded_bytes: 20,
+        articles: vec![
+            article("new", 40, 300),
+            article("old", 30, 100),
+            article("middle", 30, 200),
+        ],
+    };
+
+    // Nothing goes if it all fits, transcoded copies included
+    assert!(pick_evictions(&usage, 120).is_empty());
+
+    // Otherwise the oldest go first, until it fits
+    let ids = |picked: Vec<&StoredAudio>| picked.iter().map(|a| a.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(pick_evictions(&usage, 100)), ["old"]);
+    assert_eq!(ids(pick_evictions(&usage, 80)), ["old", "middle"]);
+    assert_eq!(ids(pick_evictions(&usage, 0)), ["old", "middle", "new"]);
+}
+
+#[test]
+fn fetch_log_round_trip() {
+    let dir = std::env::temp_dir().join(format!("rtms-fetch-log-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let log_path = dir.join("audio-fetches.json");
+    let mp3 = dir.join("Fish-abc.mp3");
+
+    // Audio that hasn't been fetched counts as fetched when it was made
+    let log = FetchLog::load(log_path.to_str().unwrap()).unwrap();
+    assert_eq!(log.last_fetched(&mp3, 5), 5);
+
+    // Fetches are kept across restarts, and forgotten once the audio's deleted
+    log.record(&mp3);
+    log.save().unwrap();
+    let log = FetchLog::load(log_path.to_str().unwrap()).unwrap();
+    assert!(log.last_fetched(&mp3, 5) > 5);
+    log.forget(&mp3);
+    assert_eq!(log.last_fetched(&mp3, 5), 5);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
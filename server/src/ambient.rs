@@ -0,0 +1,60 @@
+//! Keeps track of the ambient beds this server offers, and provides an endpoint for listing them
+
+use crate::config::AmbientBed;
+use common::AmbientBedInfo;
+
+use std::sync::Arc;
+
+use anyhow::{bail, Error as AnyError};
+use axum::{extract::Extension, routing::get, Json, Router};
+
+/// All the ambient beds this server offers
+#[derive(Clone, Default)]
+pub(crate) struct AmbientBeds(Arc<Vec<AmbientBed>>);
+
+impl AmbientBeds {
+    pub(crate) fn new(beds: Vec<AmbientBed>) -> AmbientBeds {
+        AmbientBeds(Arc::new(beds))
+    }
+
+    /// Lists the available beds
+    pub(crate) fn list(&self) -> Vec<AmbientBedInfo> {
+        self.0
+            .iter()
+            .map(|b| AmbientBedInfo {
+                name: b.name.clone(),
+                description: b.description.clone(),
+            })
+            .collect()
+    }
+
+    /// Looks up the bed with the given name. `None` means no bed.
+    pub(crate) fn resolve(&self, name: Option<&str>) -> Result<Option<AmbientBed>, AnyError> {
+        let name = match name {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+
+        match self.0.iter().find(|b| b.name == name) {
+            Some(b) => Ok(Some(b.clone())),
+            None => bail!("Unknown ambient bed {name}"),
+        }
+    }
+}
+
+// Sets the /api/list-ambient-beds route
+pub(crate) fn setup(router: Router, ambient_beds: AmbientBeds) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/list-ambient-beds", get(list_ambient_beds))
+            .layer(Extension(ambient_beds)),
+    )
+}
+
+/// Lists the ambient beds available to the client
+async fn list_ambient_beds(
+    Extension(ambient_beds): Extension<AmbientBeds>,
+) -> Json<Vec<AmbientBedInfo>> {
+    Json(ambient_beds.list())
+}
@@ -0,0 +1,110 @@
+//! Reading and writing ZIP archives in memory, with the `zip` crate. Uploaded EPUB and .docx files
+//! are ZIP archives, and so are article bundles. Files are written stored, since audio doesn't
+//! compress.
+
+use std::io::{Cursor, Read, Write};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipArchive};
+
+/// A ZIP archive being read
+pub(crate) struct Zip<'a> {
+    archive: ZipArchive<Cursor<&'a [u8]>>,
+    /// The most a file can decompress to, in bytes
+    max_len: usize,
+}
+
+impl<'a> Zip<'a> {
+    /// Reads the archive's central directory. Compressed files in it can decompress to at most
+    /// `max_len` bytes.
+    pub(crate) fn parse(bytes: &'a [u8], max_len: usize) -> Result<Zip<'a>, AnyError> {
+        let archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| match e {
+            ZipError::InvalidArchive(_) => anyhow!("It isn't a ZIP archive, or it's damaged"),
+            e => anyhow!("Couldn't read the ZIP archive: {e}"),
+        })?;
+        Ok(Zip { archive, max_len })
+    }
+
+    /// Returns whether there's a file with the given path in the archive
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.archive.file_names().any(|n| n == name)
+    }
+
+    /// Returns the contents of the file with the given path in the archive. The CRC is checked
+    /// once the whole file has been read.
+    pub(crate) fn read(&mut self, name: &str) -> Result<Vec<u8>, AnyError> {
+        let file = match self.archive.by_name(name) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => bail!("There's no {name} in the archive"),
+            Err(e) => bail!("Couldn't read {name}: {e}"),
+        };
+        let mut contents = Vec::new();
+        file.take(self.max_len as u64 + 1)
+            .read_to_end(&mut contents)
+            .map_err(|e| anyhow!("{name} is damaged: {e}"))?;
+        if contents.len() > self.max_len {
+            bail!("{name} is too big");
+        }
+        Ok(contents)
+    }
+
+    /// Returns the contents of the file with the given path in the archive, as text
+    pub(crate) fn read_string(&mut self, name: &str) -> Result<String, AnyError> {
+        Ok(String::from_utf8_lossy(&self.read(name)?).into_owned())
+    }
+}
+
+/// A ZIP archive being written, in memory. Files are stored without compression.
+pub(crate) struct ZipWriter {
+    writer: zip::ZipWriter<Cursor<Vec<u8>>>,
+}
+
+impl Default for ZipWriter {
+    fn default() -> ZipWriter {
+        ZipWriter {
+            writer: zip::ZipWriter::new(Cursor::new(Vec::new())),
+        }
+    }
+}
+
+impl ZipWriter {
+    /// Adds a file with the given path and contents to the archive
+    pub(crate) fn add(&mut self, name: &str, contents: &[u8]) -> Result<(), AnyError> {
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(contents.len() as u64 >= u32::MAX as u64);
+        self.writer.start_file(name, options)?;
+        self.writer.write_all(contents)?;
+        Ok(())
+    }
+
+    /// Returns the finished archive
+    pub(crate) fn finish(mut self) -> Result<Vec<u8>, AnyError> {
+        Ok(self.writer.finish()?.into_inner())
+    }
+}
+
+#[test]
+fn zip_round_trip() {
+    let mut writer = ZipWriter::default();
+    writer.add("manifest.json", b"{}").unwrap();
+    writer.add("images/café.jpg", &[0xFF; 300]).unwrap();
+    let mut archive = writer.finish().unwrap();
+
+    let mut zip = Zip::parse(&archive, 1024).unwrap();
+    assert!(zip.contains("images/café.jpg"));
+    assert_eq!(zip.read_string("manifest.json").unwrap(), "{}");
+    assert_eq!(zip.read("images/café.jpg").unwrap(), [0xFF; 300]);
+    assert!(zip.read("audio.mp3").is_err());
+
+    // Files bigger than the limit aren't read
+    let mut small = Zip::parse(&archive, 100).unwrap();
+    assert!(small.read("images/café.jpg").is_err());
+
+    // Damage is caught
+    let at = archive.windows(3).position(|w| w == [0xFF; 3]).unwrap();
+    archive[at] = 0;
+    let mut zip = Zip::parse(&archive, 1024).unwrap();
+    assert!(zip.read("images/café.jpg").is_err());
+    assert!(Zip::parse(b"not a zip", 1024).is_err());
+}
@@ -0,0 +1,90 @@
+//! Answers questions about articles with the server's language model, e.g., to go over something
+//! right after listening. This is only offered if a model is configured.
+
+use crate::llm::{self, LanguageModel};
+use common::{ArticleAnswer, ArticleQuestion};
+
+use anyhow::{bail, Error as AnyError};
+use axum::{extract::Extension, http::StatusCode, routing::post, Json, Router};
+
+/// The longest question that can be asked, in characters
+const MAX_QUESTION_CHARS: usize = 1000;
+
+/// What the model is told to do. Answers are kept short enough to be spoken as a voice preview.
+const INSTRUCTIONS: &str = "Answer the user's question about the article they send, using only \
+    what the article says. If the article doesn't say, answer that it doesn't. Answer in at most \
+    three plain sentences and 400 characters, in the language of the question, without markdown. \
+    The answer may be read aloud.";
+
+// Sets the /api/ask-about-article route
+pub(crate) fn setup(router: Router, language_model: LanguageModel) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/ask-about-article", post(ask_about_article))
+            .layer(Extension(language_model)),
+    )
+}
+
+/// Returns the messages that ask the given question about the given article
+fn question_messages(question: &ArticleQuestion) -> Result<[String; 2], AnyError> {
+    let q = question.question.trim();
+    if q.is_empty() {
+        bail!("The question is empty");
+    }
+    if q.chars().count() > MAX_QUESTION_CHARS {
+        bail!("The question is too long. The limit is {MAX_QUESTION_CHARS} characters.");
+    }
+    let article = format!(
+        "{}\n\n{}",
+        question.title,
+        llm::article_excerpt(&question.text)
+    );
+    Ok([article, q.to_string()])
+}
+
+/// Asks the model the given question about the given article, and returns its answer
+async fn ask_about_article(
+    Json(question): Json<ArticleQuestion>,
+    Extension(language_model): Extension<LanguageModel>,
+) -> Result<Json<ArticleAnswer>, (StatusCode, String)> {
+    if !language_model.is_enabled() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "This server has no language model to answer questions".to_string(),
+        ));
+    }
+    let [article, q] = question_messages(&question).map_err(|e| {
+        tracing::error!("Bad question about {:?}: {e}", question.title);
+        (StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+
+    language_model
+        .chat(INSTRUCTIONS, &[&article, &q])
+        .await
+        .map(|answer| {
+            Json(ArticleAnswer {
+                answer: answer.trim().to_string(),
+            })
+        })
+        .map_err(|e| {
+            tracing::error!("Error answering question about {:?}: {e:#}", question.title);
+            (StatusCode::BAD_GATEWAY, format!("{e:#}"))
+        })
+}
+
+#[test]
+fn question_checks() {
+    let question = |q: &str| ArticleQuestion {
+        title: "Fish".to_string(),
+        text: "Fish swim.".to_string(),
+        question: q.to_string(),
+    };
+
+    let [article, q] = question_messages(&question(" Do fish swim? ")).unwrap();
+    assert_eq!(article, "Fish\n\nFish swim.");
+    assert_eq!(q, "Do fish swim?");
+
+    assert!(question_messages(&question("  ")).is_err());
+    assert!(question_messages(&question(&"?".repeat(MAX_QUESTION_CHARS + 1))).is_err());
+}
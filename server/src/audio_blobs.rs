@@ -0,0 +1,434 @@
+//! Serves article audio at URLs named for its content, i.e., `/audio/<sha256>.mp3`. Since the
+//! content at such a URL never changes, it can be cached forever, e.g., by a CDN or a caching
+//! reverse proxy. The old URLs, `/api/audio-blobs/<id>.mp3`, redirect to the current content of the
+//! article, which changes if its conversion is finished.
+//!
+//! If the config lists transcode formats, clients can ask for the audio in one of them, e.g.,
+//! `/api/audio-blobs/<id>.mp3?formats=opus,aac`. They're redirected to `/audio/<sha256>.opus`, where
+//! the hash is still that of the MP3. The transcoded audio is made on the first request and kept in
+//! the `transcoded` directory under the audio blob directory.
+//!
+//! The library probes the size of audio that isn't downloaded with HEAD requests. These aren't
+//! counted as fetches, and don't make a transcoded copy. Until one is made, a probe gets the
+//! headers of the MP3, which is bigger than any transcoded copy.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::{accounts::LibraryDir, admin::FetchLog};
+use tts_pipeline::audio::{self, AudioFormat};
+
+use anyhow::Error as AnyError;
+use axum::{
+    body::Body,
+    extract::{Extension, Path as UrlPath, Query},
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+/// How long content-addressed audio can be cached: a year, which is as long as caches keep anything
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// The directory, under the audio blob directory, that transcoded audio is kept in
+const TRANSCODED_DIR: &str = "transcoded";
+
+/// What's known about an MP3: its content hash, and the modification time and size it had when it
+/// was hashed. If either of those changes, it has to be hashed again.
+#[derive(Clone)]
+struct HashedFile {
+    hash: String,
+    modified: SystemTime,
+    len: u64,
+}
+
+/// The content hashes of the articles' MP3s. Hashing is slow, so every hash is kept until the file
+/// changes.
+#[derive(Clone, Default)]
+pub(crate) struct AudioHashes(Arc<Mutex<HashMap<PathBuf, HashedFile>>>);
+
+/// Returns the SHA-256 of the given file, in hex
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+impl AudioHashes {
+    /// Returns the content hash of the MP3 at the given path
+    pub(crate) fn hash_of(&self, path: &Path) -> io::Result<String> {
+        let meta = fs::metadata(path)?;
+        let (modified, len) = (meta.modified()?, meta.len());
+
+        let cached = self.0.lock().unwrap().get(path).cloned();
+        if let Some(file) = cached.filter(|f| f.modified == modified && f.len == len) {
+            return Ok(file.hash);
+        }
+
+        let hash = hash_file(path)?;
+        self.0.lock().unwrap().insert(
+            path.to_path_buf(),
+            HashedFile {
+                hash: hash.clone(),
+                modified,
+                len,
+            },
+        );
+        Ok(hash)
+    }
+
+    /// Returns the path of the MP3 in the given directory whose content has the given hash, if any
+    pub(crate) fn find(
+        &self,
+        audio_blob_dir: &str,
+        hash: &str,
+    ) -> Result<Option<PathBuf>, AnyError> {
+        // Check the files that were hashed before first, since that's almost always where it is.
+        // They're checked again in case they've changed since. Files in other libraries don't
+        // count, even if they're the same audio.
+        let known: Vec<PathBuf> = self
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(path, file)| {
+                file.hash == hash && path.parent() == Some(Path::new(audio_blob_dir))
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in known {
+            if self.hash_of(&path).is_ok_and(|h| h == hash) {
+                return Ok(Some(path));
+            }
+        }
+
+        // Otherwise, hash everything that hasn't been hashed
+        for entry in fs::read_dir(audio_blob_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "mp3") && self.hash_of(&path)? == hash {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The formats, besides MP3, that audio can be transcoded to, from the config
+#[derive(Clone, Default)]
+pub(crate) struct TranscodeFormats(Arc<Vec<AudioFormat>>);
+
+impl TranscodeFormats {
+    pub(crate) fn new(formats: Vec<AudioFormat>) -> Self {
+        TranscodeFormats(Arc::new(formats))
+    }
+
+    /// Returns the first of the given comma-separated formats that audio can be served in. If none
+    /// of them can, it's MP3.
+    fn pick(&self, requested: Option<&str>) -> AudioFormat {
+        requested
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|name| AudioFormat::from_extension(name.trim()))
+            .find(|format| self.is_enabled(*format))
+            .unwrap_or(AudioFormat::Mp3)
+    }
+
+    /// Returns whether audio can be served in the given format
+    fn is_enabled(&self, format: AudioFormat) -> bool {
+        format == AudioFormat::Mp3 || self.0.contains(&format)
+    }
+}
+
+/// Held while transcoding, so that only one file is transcoded at a time. This keeps a burst of
+/// requests from starting many ffmpegs, or two of them writing the same file.
+#[derive(Clone, Default)]
+struct TranscodeLock(Arc<tokio::sync::Mutex<()>>);
+
+/// Returns the content-addressed URL of the given article's audio in the given format
+pub(crate) fn audio_url(
+    audio_hashes: &AudioHashes,
+    audio_blob_dir: &str,
+    id: &str,
+    format: AudioFormat,
+) -> io::Result<String> {
+    let path = Path::new(audio_blob_dir).join(id).with_extension("mp3");
+    Ok(format!(
+        "/audio/{}.{}",
+        audio_hashes.hash_of(&path)?,
+        format.extension()
+    ))
+}
+
+/// Returns how many bytes the transcoded audio in the given library directory takes up
+pub(crate) fn transcoded_bytes(library_dir: &Path) -> io::Result<u64> {
+    let dir = library_dir.join(TRANSCODED_DIR);
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        total += entry?.metadata()?.len();
+    }
+    Ok(total)
+}
+
+/// Deletes every transcoded copy of the MP3 with the given hash in the given library directory
+pub(crate) fn remove_transcoded(library_dir: &Path, hash: &str) -> io::Result<()> {
+    for format in AudioFormat::ALL {
+        let path = library_dir
+            .join(TRANSCODED_DIR)
+            .join(hash)
+            .with_extension(format.extension());
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+// Sets the /audio/<hash>.<ext> and /api/audio-blobs/<id>.mp3 routes
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    audio_hashes: AudioHashes,
+    transcode_formats: TranscodeFormats,
+    fetch_log: FetchLog,
+) -> Router {
+    router
+        .merge(
+            Router::new()
+                .route("/audio/:filename", get(serve_audio))
+                .layer(Extension(audio_blob_dir.to_string()))
+                .layer(Extension(audio_hashes.clone()))
+                .layer(Extension(fetch_log))
+                .layer(Extension(transcode_formats.clone()))
+                .layer(Extension(TranscodeLock::default())),
+        )
+        .nest(
+            "/api",
+            Router::new()
+                .route("/audio-blobs/:filename", get(redirect_to_audio))
+                .layer(Extension(audio_blob_dir.to_string()))
+                .layer(Extension(audio_hashes))
+                .layer(Extension(transcode_formats)),
+        )
+}
+
+/// Returns whether the MP3 with the given hash is available in the given format without
+/// transcoding it
+fn is_transcoded(audio_blob_dir: &str, hash: &str, format: AudioFormat) -> bool {
+    format == AudioFormat::Mp3
+        || Path::new(audio_blob_dir)
+            .join(TRANSCODED_DIR)
+            .join(hash)
+            .with_extension(format.extension())
+            .exists()
+}
+
+/// Returns the path of the given MP3 transcoded to the given format, transcoding it if that hasn't
+/// been done yet
+async fn transcoded_path(
+    audio_blob_dir: &str,
+    mp3_path: &Path,
+    hash: &str,
+    format: AudioFormat,
+    lock: &TranscodeLock,
+) -> Result<PathBuf, AnyError> {
+    let dir = Path::new(audio_blob_dir).join(TRANSCODED_DIR);
+    let path = dir.join(hash).with_extension(format.extension());
+
+    let _guard = lock.0.lock().await;
+    // It might've been transcoded while this was waiting for the lock
+    if !path.exists() {
+        fs::create_dir_all(&dir)?;
+        audio::transcode(mp3_path, &path, format).await?;
+    }
+    Ok(path)
+}
+
+/// Serves the audio with the given hash, in the format of the given extension. Ranges are
+/// supported, so players can seek. HEAD requests are probes, and get the MP3's headers if it hasn't
+/// been transcoded yet.
+async fn serve_audio(
+    UrlPath(filename): UrlPath<String>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+    Extension(audio_hashes): Extension<AudioHashes>,
+    Extension(transcode_formats): Extension<TranscodeFormats>,
+    Extension(transcode_lock): Extension<TranscodeLock>,
+    Extension(fetch_log): Extension<FetchLog>,
+    req: Request<Body>,
+) -> Result<Response, StatusCode> {
+    let (hash, format) = filename
+        .split_once('.')
+        .filter(|(h, _)| h.len() == 64 && h.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|(h, ext)| Some((h, AudioFormat::from_extension(ext)?)))
+        .filter(|(_, format)| transcode_formats.is_enabled(*format))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let mut path = audio_hashes
+        .find(&audio_blob_dir, hash)
+        .map_err(|e| {
+            tracing::error!("Error looking for audio {hash}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let probing = req.method() == Method::HEAD;
+    if !probing {
+        fetch_log.record(&path);
+    }
+    let served_format = if probing && !is_transcoded(&audio_blob_dir, hash, format) {
+        AudioFormat::Mp3
+    } else {
+        format
+    };
+    if served_format != AudioFormat::Mp3 {
+        path = transcoded_path(&audio_blob_dir, &path, hash, format, &transcode_lock)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error transcoding audio {hash}: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    let mut resp = ServeFile::new(path)
+        .oneshot(req)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_response();
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(served_format.mime_type()),
+    );
+    // Stand-in headers change once the audio is transcoded, so they're not to be kept
+    if served_format != format {
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        return Ok(resp);
+    }
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+    );
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{filename}\"")) {
+        headers.insert(header::ETAG, etag);
+    }
+    Ok(resp)
+}
+
+/// The query string of an audio blob request
+#[derive(Deserialize)]
+struct AudioBlobQuery {
+    /// The formats the client can play, as a comma-separated list in order of preference, e.g.,
+    /// `opus,aac`. If none of them is available, it gets MP3.
+    formats: Option<String>,
+}
+
+/// Redirects from an article's audio to the content-addressed URL of what's in it now, in the
+/// client's preferred format
+async fn redirect_to_audio(
+    UrlPath(filename): UrlPath<String>,
+    Query(query): Query<AudioBlobQuery>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+    Extension(audio_hashes): Extension<AudioHashes>,
+    Extension(transcode_formats): Extension<TranscodeFormats>,
+) -> Result<Redirect, StatusCode> {
+    let id = filename
+        .strip_suffix(".mp3")
+        .filter(|id| !id.is_empty() && !id.starts_with('.') && !id.contains(['/', '\\']))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let format = transcode_formats.pick(query.formats.as_deref());
+    let url =
+        audio_url(&audio_hashes, &audio_blob_dir, id, format).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            _ => {
+                tracing::error!("Error hashing audio of {id}: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    // This isn't permanent, since the article's audio changes if its conversion is finished. It's
+    // relative to /api/audio-blobs/, so it works under a subpath too.
+    Ok(Redirect::temporary(&format!("../..{url}")))
+}
+
+#[test]
+fn finding_audio_by_hash() {
+    let dir = std::env::temp_dir().join(format!("rtms-audio-blobs-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let audio_blob_dir = dir.to_str().unwrap();
+    fs::write(dir.join("fish-abc.mp3"), b"fish audio").unwrap();
+    fs::write(dir.join("chips-def.mp3"), b"chips audio").unwrap();
+
+    let hashes = AudioHashes::default();
+    let url = audio_url(&hashes, audio_blob_dir, "fish-abc", AudioFormat::Mp3).unwrap();
+    let hash = url
+        .strip_prefix("/audio/")
+        .and_then(|f| f.strip_suffix(".mp3"))
+        .unwrap();
+    assert_eq!(hash, hash_file(&dir.join("fish-abc.mp3")).unwrap());
+
+    // Both the files that were hashed already and the ones that weren't are found
+    assert_eq!(
+        hashes.find(audio_blob_dir, hash).unwrap(),
+        Some(dir.join("fish-abc.mp3"))
+    );
+    let chips_hash = hash_file(&dir.join("chips-def.mp3")).unwrap();
+    assert_eq!(
+        hashes.find(audio_blob_dir, &chips_hash).unwrap(),
+        Some(dir.join("chips-def.mp3"))
+    );
+
+    // Once the content changes, the old hash doesn't find it
+    fs::write(dir.join("fish-abc.mp3"), b"more fish audio").unwrap();
+    assert_eq!(hashes.find(audio_blob_dir, hash).unwrap(), None);
+    assert!(audio_url(&hashes, audio_blob_dir, "missing", AudioFormat::Mp3).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn checking_transcoded_copies() {
+    let dir = std::env::temp_dir().join(format!("rtms-transcoded-test-{}", std::process::id()));
+    fs::create_dir_all(dir.join(TRANSCODED_DIR)).unwrap();
+    let audio_blob_dir = dir.to_str().unwrap();
+
+    // MP3s never need transcoding, and other formats only count once their copy is made
+    assert!(is_transcoded(audio_blob_dir, "abc", AudioFormat::Mp3));
+    assert!(!is_transcoded(audio_blob_dir, "abc", AudioFormat::Opus));
+    let copy = dir
+        .join(TRANSCODED_DIR)
+        .join("abc")
+        .with_extension(AudioFormat::Opus.extension());
+    fs::write(copy, b"opus audio").unwrap();
+    assert!(is_transcoded(audio_blob_dir, "abc", AudioFormat::Opus));
+    assert!(!is_transcoded(audio_blob_dir, "abc", AudioFormat::Aac));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn picking_formats() {
+    let formats = TranscodeFormats::new(vec![AudioFormat::Aac]);
+    assert_eq!(formats.pick(Some("opus,aac")), AudioFormat::Aac);
+    assert_eq!(formats.pick(Some("opus, mp3,aac")), AudioFormat::Mp3);
+    assert_eq!(formats.pick(Some("opus,flac")), AudioFormat::Mp3);
+    assert_eq!(formats.pick(None), AudioFormat::Mp3);
+
+    // Nothing is transcoded unless the config says so
+    let formats = TranscodeFormats::default();
+    assert_eq!(formats.pick(Some("opus,aac")), AudioFormat::Mp3);
+    assert!(formats.is_enabled(AudioFormat::Mp3));
+    assert!(!formats.is_enabled(AudioFormat::Opus));
+}
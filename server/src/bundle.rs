@@ -0,0 +1,431 @@
+//! Article bundles, for moving a single article to another server or archiving it. A bundle is a
+//! ZIP archive with the `.rtms` extension that holds everything the library has of the article:
+//!
+//! * `manifest.json`, the bundle's version and the article's details
+//! * `audio.mp3`, the article's audio, with its library tags
+//! * `text.txt`, the text the audio covers, if the article has a transcript
+//! * `timepoints.json`, when each paragraph of the text is spoken
+//! * `loudness.json`, how loud the audio is over time, if that was kept
+//! * `chapters.json`, the article's headings and when they're spoken, for reading without the
+//!   server. They're the timepoints marked as headings, so importing doesn't need them.
+//! * `images/<name>`, the article's lead image, if it has one
+//!
+//! Importing a bundle puts the article back in the library as it was, unless the library already
+//! has it.
+
+use crate::{
+    accounts::LibraryDir,
+    archive::{Zip, ZipWriter},
+    export::{attachment, error_response, export_filename, is_valid_id, mp3_path},
+    images, pending,
+    search::SearchIndex,
+    transcript,
+    util::{get_metadata, now_secs, save_metadata},
+};
+use common::{ArticleMetadata, ArticleTranscript, Timepoint};
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Error as AnyError};
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path as UrlPath},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+/// The version of the bundle format. Bundles of a later version than this can't be imported.
+const BUNDLE_VERSION: u32 = 1;
+
+/// The largest bundle that can be imported, in bytes
+const MAX_BUNDLE_LEN: usize = 512 * 1024 * 1024;
+
+const MANIFEST_NAME: &str = "manifest.json";
+const AUDIO_NAME: &str = "audio.mp3";
+const TEXT_NAME: &str = "text.txt";
+const TIMEPOINTS_NAME: &str = "timepoints.json";
+const CHAPTERS_NAME: &str = "chapters.json";
+const LOUDNESS_NAME: &str = "loudness.json";
+
+/// What a bundle holds
+#[derive(Serialize, Deserialize)]
+struct BundleManifest {
+    /// The version of the bundle format
+    version: u32,
+    /// When the bundle was made, in seconds since the Unix epoch
+    exported: u64,
+    /// The article's details
+    article: ArticleMetadata,
+    /// The length of the audio according to the transcript, in seconds, if there's a transcript
+    #[serde(default)]
+    transcript_secs: Option<f64>,
+    /// The name of the lead image in the bundle, if it has one
+    #[serde(default)]
+    image: Option<String>,
+}
+
+/// A heading of an article, in `chapters.json`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Chapter {
+    title: String,
+    /// When the heading is spoken, in seconds
+    secs: f64,
+}
+
+/// Returns the chapters of the given transcript, i.e., its headings
+fn chapters(transcript: &ArticleTranscript) -> Vec<Chapter> {
+    let chars: Vec<char> = transcript.text.chars().collect();
+    transcript
+        .timepoints
+        .iter()
+        .filter(|tp| tp.heading)
+        .map(|tp| Chapter {
+            title: chars
+                .get(tp.offset..)
+                .unwrap_or_default()
+                .iter()
+                .take_while(|&&c| c != '\n')
+                .collect::<String>()
+                .trim()
+                .to_string(),
+            secs: tp.secs,
+        })
+        .collect()
+}
+
+/// Makes a bundle of the given article, and returns it with the article's details
+fn write_bundle(audio_blob_dir: &str, id: &str) -> Result<(ArticleMetadata, Vec<u8>), AnyError> {
+    let path = mp3_path(audio_blob_dir, id);
+    let meta = get_metadata(&path)?;
+    let transcript = transcript::load(audio_blob_dir, id).ok();
+    let image = meta.image.as_deref().and_then(|image| {
+        let path = images::saved_path(audio_blob_dir, image)?;
+        let name = format!("images/{}", path.file_name()?.to_str()?);
+        path.exists().then_some((path, name))
+    });
+
+    let mut zip = ZipWriter::default();
+    let manifest = BundleManifest {
+        version: BUNDLE_VERSION,
+        exported: now_secs(),
+        article: meta.clone(),
+        transcript_secs: transcript.as_ref().map(|t| t.duration_secs),
+        image: image.as_ref().map(|(_, name)| name.clone()),
+    };
+    zip.add(MANIFEST_NAME, &serde_json::to_vec_pretty(&manifest)?)?;
+    zip.add(AUDIO_NAME, &fs::read(&path)?)?;
+    if let Some(transcript) = &transcript {
+        zip.add(TEXT_NAME, transcript.text.as_bytes())?;
+        zip.add(
+            TIMEPOINTS_NAME,
+            &serde_json::to_vec_pretty(&transcript.timepoints)?,
+        )?;
+        zip.add(
+            CHAPTERS_NAME,
+            &serde_json::to_vec_pretty(&chapters(transcript))?,
+        )?;
+        if !transcript.loudness.is_empty() {
+            zip.add(LOUDNESS_NAME, &serde_json::to_vec(&transcript.loudness)?)?;
+        }
+    }
+    if let Some((path, name)) = &image {
+        zip.add(name, &fs::read(path)?)?;
+    }
+
+    Ok((meta, zip.finish()?))
+}
+
+/// Why a bundle couldn't be imported
+#[derive(Debug)]
+enum ImportError {
+    /// The library already has the article
+    AlreadyThere(String),
+    /// The bundle is broken, or couldn't be put in the library
+    Other(AnyError),
+}
+
+impl From<AnyError> for ImportError {
+    fn from(e: AnyError) -> ImportError {
+        ImportError::Other(e)
+    }
+}
+
+/// Imports the given bundle into the library, and returns the article's details. Its files are
+/// written to a hidden directory in the library first, so nothing half-written ever shows up in
+/// the library.
+fn import_bundle(audio_blob_dir: &str, bundle: &[u8]) -> Result<ArticleMetadata, ImportError> {
+    let mut zip = Zip::parse(bundle, MAX_BUNDLE_LEN).context("The bundle isn't a ZIP archive")?;
+    let manifest: BundleManifest = serde_json::from_slice(&zip.read(MANIFEST_NAME)?)
+        .context("couldn't parse the bundle's manifest")?;
+    if manifest.version > BUNDLE_VERSION {
+        return Err(anyhow!("The bundle was made by a newer version of ReadToMyShoe").into());
+    }
+    let meta = manifest.article;
+    if !is_valid_id(&meta.id) {
+        return Err(anyhow!("The bundle's article has an invalid ID {:?}", meta.id).into());
+    }
+    if mp3_path(audio_blob_dir, &meta.id).exists() {
+        return Err(ImportError::AlreadyThere(meta.title));
+    }
+
+    let audio = zip.read(AUDIO_NAME)?;
+    let transcript = match (zip.contains(TEXT_NAME), manifest.transcript_secs) {
+        (true, Some(duration_secs)) => {
+            let timepoints: Vec<Timepoint> = serde_json::from_slice(&zip.read(TIMEPOINTS_NAME)?)
+                .context("couldn't parse the bundle's timepoints")?;
+            let loudness = if zip.contains(LOUDNESS_NAME) {
+                serde_json::from_slice(&zip.read(LOUDNESS_NAME)?)
+                    .context("couldn't parse the bundle's loudness")?
+            } else {
+                Vec::new()
+            };
+            Some(ArticleTranscript {
+                text: String::from_utf8(zip.read(TEXT_NAME)?)
+                    .context("the bundle's text isn't UTF-8")?,
+                timepoints,
+                duration_secs,
+                loudness,
+            })
+        }
+        _ => None,
+    };
+    // The image has to be where the article's details say it's served from
+    let image = match (&meta.image, &manifest.image) {
+        (Some(served_at), Some(name)) if zip.contains(name) => {
+            let dest = images::saved_path(audio_blob_dir, served_at)
+                .ok_or_else(|| anyhow!("The bundle's image has an invalid path {served_at:?}"))?;
+            Some((dest, zip.read(name)?))
+        }
+        _ => None,
+    };
+
+    let nonce = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(AnyError::from)?
+        .as_nanos();
+    let staging_dir = Path::new(audio_blob_dir).join(format!(".bundle-{nonce}"));
+    let result = (|| {
+        fs::create_dir_all(&staging_dir)?;
+        let staging_dir = staging_dir
+            .to_str()
+            .ok_or_else(|| anyhow!("the library's path isn't UTF-8"))?;
+
+        // The tags are written again, in case the audio was retagged after it was bundled
+        let staged_mp3 = mp3_path(staging_dir, &meta.id);
+        fs::write(&staged_mp3, audio)?;
+        save_metadata(&meta, staging_dir)?;
+        if let Some(transcript) = &transcript {
+            transcript::save(staging_dir, &meta.id, transcript)?;
+            fs::rename(
+                transcript::path(staging_dir, &meta.id),
+                transcript::path(audio_blob_dir, &meta.id),
+            )?;
+        }
+        if let Some((dest, image)) = &image {
+            if !dest.exists() {
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(dest, image)?;
+            }
+        }
+
+        // The MP3 goes in last, since the article is in the library as soon as it's there
+        fs::rename(&staged_mp3, mp3_path(audio_blob_dir, &meta.id))?;
+        Ok::<_, AnyError>(())
+    })();
+    if let Err(e) = fs::remove_dir_all(&staging_dir) {
+        tracing::error!("Could not remove import directory {:?}: {e}", staging_dir);
+    }
+    result?;
+
+    Ok(meta)
+}
+
+// Sets the /api/export-bundle and /api/import-bundle routes
+pub(crate) fn setup(router: Router, audio_blob_dir: &str, search_index: SearchIndex) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/export-bundle/:id", get(export_bundle_endpoint))
+            .route("/import-bundle", post(import_bundle_endpoint))
+            .layer(Extension(audio_blob_dir.to_string()))
+            .layer(Extension(search_index)),
+    )
+}
+
+/// Returns a bundle of the given article
+async fn export_bundle_endpoint(
+    UrlPath(id): UrlPath<String>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Response, (StatusCode, String)> {
+    if !is_valid_id(&id) {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            anyhow!("invalid article ID {id:?}"),
+        ));
+    }
+    if !mp3_path(&audio_blob_dir, &id).exists() {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            anyhow!("No article {id}"),
+        ));
+    }
+    if pending::exists(&audio_blob_dir, &id) {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            anyhow!("{id} is still being converted"),
+        ));
+    }
+
+    let bundle = tokio::task::spawn_blocking(move || write_bundle(&audio_blob_dir, &id))
+        .await
+        .map_err(AnyError::from)
+        .and_then(|r| r);
+    let (meta, bundle) =
+        bundle.map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let filename = export_filename(&meta).replace(".mp3", ".rtms");
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/zip"),
+        ),
+        (header::CONTENT_DISPOSITION, attachment(&filename)),
+    ];
+    Ok((headers, bundle).into_response())
+}
+
+/// Imports a bundle made by `/api/export-bundle` into the library, and returns the article's
+/// details
+async fn import_bundle_endpoint(
+    LibraryDir(audio_blob_dir): LibraryDir,
+    Extension(search_index): Extension<SearchIndex>,
+    bundle: Bytes,
+) -> Result<Json<ArticleMetadata>, (StatusCode, String)> {
+    if bundle.len() > MAX_BUNDLE_LEN {
+        return Err(error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            anyhow!("Bundles can be at most {} MiB", MAX_BUNDLE_LEN >> 20),
+        ));
+    }
+
+    let dir = audio_blob_dir.clone();
+    let meta = tokio::task::spawn_blocking(move || import_bundle(&dir, &bundle))
+        .await
+        .map_err(|e| ImportError::Other(e.into()))
+        .and_then(|r| r)
+        .map_err(|e| match e {
+            ImportError::AlreadyThere(title) => error_response(
+                StatusCode::CONFLICT,
+                anyhow!("{title} is already in the library"),
+            ),
+            ImportError::Other(e) => error_response(StatusCode::BAD_REQUEST, e),
+        })?;
+
+    // Make the article searchable
+    if let Ok(transcript) = transcript::load(&audio_blob_dir, &meta.id) {
+        let _ = search_index
+            .add(&audio_blob_dir, &meta.id, &meta.title, &transcript.text)
+            .await
+            .map_err(|e| tracing::error!("Error indexing imported article {}: {e}", meta.id));
+    }
+
+    tracing::info!("Imported bundle of {}", meta.id);
+    Ok(Json(meta))
+}
+
+#[test]
+fn article_bundles() {
+    let dir = std::env::temp_dir().join(format!("rtms-bundle-test-{}", std::process::id()));
+    let old_library = dir.join("old");
+    let new_library = dir.join("new");
+    fs::create_dir_all(&old_library).unwrap();
+    fs::create_dir_all(&new_library).unwrap();
+    let (old_library, new_library) = (old_library.to_str().unwrap(), new_library.to_str().unwrap());
+
+    let image_url = format!("/api/images/{}.jpg", "cd".repeat(32));
+    let image = images::saved_path(old_library, &image_url).unwrap();
+    fs::create_dir_all(image.parent().unwrap()).unwrap();
+    fs::write(&image, b"a photo").unwrap();
+    let meta = ArticleMetadata {
+        id: "Fish-abc".to_string(),
+        title: "Fish".to_string(),
+        datetime_added: Some(1_663_000_000),
+        source_url: Some("https://example.com/fish".to_string()),
+        image: Some(image_url),
+        ..Default::default()
+    };
+    fs::write(mp3_path(old_library, &meta.id), [0xFFu8; 64]).unwrap();
+    save_metadata(&meta, old_library).unwrap();
+    let text = "Fish\nThey swim.\nChips\nThey don't.";
+    let mut transcript = transcript::new(text, &[(0, 0.0), (5, 1.0), (16, 2.5), (22, 3.0)], 4.0);
+    transcript.loudness = vec![0, 120, 130, 0, 0, 125, 110, 0];
+    transcript::save(old_library, &meta.id, &transcript).unwrap();
+
+    let (_, bundle) = write_bundle(old_library, &meta.id).unwrap();
+    let mut zip = Zip::parse(&bundle, MAX_BUNDLE_LEN).unwrap();
+    let chapters: Vec<Chapter> = serde_json::from_slice(&zip.read(CHAPTERS_NAME).unwrap()).unwrap();
+    assert_eq!(
+        chapters,
+        [
+            Chapter {
+                title: "Fish".to_string(),
+                secs: 0.0
+            },
+            Chapter {
+                title: "Chips".to_string(),
+                secs: 2.5
+            },
+        ]
+    );
+
+    // The article comes back with its tags, transcript, and image
+    let restored = import_bundle(new_library, &bundle).unwrap();
+    assert_eq!(restored.title, "Fish");
+    let restored = get_metadata(&mp3_path(new_library, &meta.id)).unwrap();
+    assert_eq!(restored.source_url, meta.source_url);
+    assert_eq!(transcript::load(new_library, &meta.id).unwrap(), transcript);
+    let restored_image = images::saved_path(new_library, meta.image.as_deref().unwrap());
+    assert_eq!(fs::read(restored_image.unwrap()).unwrap(), b"a photo");
+
+    // Importing again doesn't touch it, and nothing's left behind
+    assert!(matches!(
+        import_bundle(new_library, &bundle),
+        Err(ImportError::AlreadyThere(_))
+    ));
+    let hidden = fs::read_dir(new_library)
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with('.')
+        })
+        .count();
+    assert_eq!(hidden, 0);
+
+    // Bundles that point outside the library are turned away
+    let mut evil = ZipWriter::default();
+    let manifest = BundleManifest {
+        version: BUNDLE_VERSION,
+        exported: 0,
+        article: ArticleMetadata {
+            id: "../evil".to_string(),
+            ..Default::default()
+        },
+        transcript_secs: None,
+        image: None,
+    };
+    evil.add(MANIFEST_NAME, &serde_json::to_vec(&manifest).unwrap())
+        .unwrap();
+    evil.add(AUDIO_NAME, b"audio").unwrap();
+    assert!(import_bundle(new_library, &evil.finish().unwrap()).is_err());
+    assert!(import_bundle(new_library, b"not a bundle").is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
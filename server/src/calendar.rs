@@ -0,0 +1,292 @@
+//! Serves the library and reminders as an iCalendar feed, so listening time can be blocked out in a
+//! calendar app. Every article is an event at the time it was converted, lasting as long as its
+//! audio, and every reminder is an event at the time it's due. Like the podcast feed, the calendar
+//! is only served to requests that have the token from the config file in their query string.
+//!
+//! With accounts, each account's library and reminders have a calendar of their own, at
+//! `?account=NAME` with a token derived from the one in the config file. A signed-in account gets
+//! its calendar's URL from `/api/calendar-link`. Like the podcast feed's, the URLs in the calendar
+//! are made from the `base_url` in the config file.
+
+use crate::{
+    accounts::CurrentAccount,
+    list_articles::{self, LibraryCache},
+    podcast::{feed_library, feed_link, FeedParams},
+    reminders::ReminderStore,
+    subpath::BaseUrl,
+    util::{escape_ical, now_secs},
+};
+use common::{ArticleMetadata, Reminder};
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Extension, Query},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    routing::get,
+    Router,
+};
+use chrono::{TimeZone, Utc};
+
+/// The MIME type of the feed
+const ICAL_MIME_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// How long a reminder's event lasts if its article's length isn't known, in seconds
+const DEFAULT_EVENT_SECS: u64 = 15 * 60;
+
+/// Lines of an iCalendar file can be at most this many bytes long, not counting the line break
+const MAX_LINE_BYTES: usize = 75;
+
+/// The secret that has to be given to get the calendar
+#[derive(Clone)]
+struct CalendarToken(String);
+
+// Sets the /api/calendar.ics and /api/calendar-link routes. If no token or no base URL is given,
+// the calendar isn't served at all.
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    token: Option<String>,
+    base_url: Option<BaseUrl>,
+    reminder_store: ReminderStore,
+) -> Router {
+    let token = match token.filter(|t| !t.is_empty()) {
+        Some(token) => token,
+        None => return router,
+    };
+    let Some(base_url) = base_url else {
+        tracing::warn!(
+            "There's a calendar_token but no base_url to link to, so there's no calendar"
+        );
+        return router;
+    };
+
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/calendar.ics", get(calendar_feed))
+            .route("/calendar-link", get(calendar_link))
+            .layer(Extension(audio_blob_dir.to_string()))
+            .layer(Extension(LibraryCache::default()))
+            .layer(Extension(reminder_store))
+            .layer(Extension(base_url))
+            .layer(Extension(CalendarToken(token))),
+    )
+}
+
+/// Formats the given time, in seconds since the Unix epoch, as a UTC date-time
+fn format_time(secs: u64) -> String {
+    Utc.timestamp_opt(secs as i64, 0)
+        .single()
+        .map(|t| t.format("%Y%m%dT%H%M%SZ").to_string())
+        .unwrap_or_default()
+}
+
+/// Appends the given property to the calendar, folding it over several lines if it's long. Lines
+/// are only broken between characters, so multibyte characters stay whole.
+fn push_line(ical: &mut String, line: &str) {
+    let mut line_len = 0;
+    for c in line.chars() {
+        if line_len + c.len_utf8() > MAX_LINE_BYTES {
+            // A continuation line starts with a space, which counts toward its length
+            ical.push_str("\r\n ");
+            line_len = 1;
+        }
+        ical.push(c);
+        line_len += c.len_utf8();
+    }
+    ical.push_str("\r\n");
+}
+
+/// An event on the calendar
+struct Event<'a> {
+    uid: String,
+    start: u64,
+    duration_secs: u64,
+    summary: String,
+    description: String,
+    url: Option<&'a str>,
+}
+
+/// Returns the event of the conversion of the given article, if it's known when that was
+fn conversion_event(meta: &ArticleMetadata) -> Option<Event<'_>> {
+    let byline = [meta.author.as_deref(), meta.publication.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Some(Event {
+        uid: format!("article-{}", meta.id),
+        start: meta.datetime_added?,
+        duration_secs: meta.duration_secs.unwrap_or(0),
+        summary: format!("Listen: {}", meta.title),
+        description: byline,
+        url: meta.source_url.as_deref(),
+    })
+}
+
+/// Returns the event of the given reminder. It lasts as long as its article does, if that's known.
+fn reminder_event<'a>(reminder: &Reminder, meta: Option<&'a ArticleMetadata>) -> Event<'a> {
+    Event {
+        uid: format!("reminder-{}", reminder.article_id),
+        start: reminder.remind_at,
+        duration_secs: meta
+            .and_then(|m| m.duration_secs)
+            .unwrap_or(DEFAULT_EVENT_SECS),
+        summary: format!("Reminder to listen: {}", reminder.title),
+        description: String::new(),
+        url: meta.and_then(|m| m.source_url.as_deref()),
+    }
+}
+
+/// Renders the given events as a calendar. `now` is when the calendar was made, in seconds since
+/// the Unix epoch.
+fn render_calendar(base_url: &str, events: &[Event], now: u64) -> String {
+    let host = base_url.split("://").last().unwrap_or(base_url);
+    // Under a subpath, the URL has a path too
+    let host = host.split('/').next().unwrap_or(host);
+
+    let mut ical = String::new();
+    push_line(&mut ical, "BEGIN:VCALENDAR");
+    push_line(&mut ical, "VERSION:2.0");
+    push_line(&mut ical, "PRODID:-//ReadToMyShoe//Library//EN");
+    push_line(&mut ical, "X-WR-CALNAME:ReadToMyShoe");
+    for event in events {
+        push_line(&mut ical, "BEGIN:VEVENT");
+        push_line(
+            &mut ical,
+            &format!("UID:{}@{}", escape_ical(&event.uid), host),
+        );
+        push_line(&mut ical, &format!("DTSTAMP:{}", format_time(now)));
+        push_line(&mut ical, &format!("DTSTART:{}", format_time(event.start)));
+        push_line(&mut ical, &format!("DURATION:PT{}S", event.duration_secs));
+        push_line(
+            &mut ical,
+            &format!("SUMMARY:{}", escape_ical(&event.summary)),
+        );
+        if !event.description.is_empty() {
+            push_line(
+                &mut ical,
+                &format!("DESCRIPTION:{}", escape_ical(&event.description)),
+            );
+        }
+        if let Some(url) = event.url {
+            push_line(&mut ical, &format!("URL:{url}"));
+        }
+        push_line(&mut ical, "END:VEVENT");
+    }
+    push_line(&mut ical, "END:VCALENDAR");
+    ical
+}
+
+/// Returns a library's conversions and reminders as a calendar
+async fn calendar_feed(
+    Query(params): Query<FeedParams>,
+    Extension(CalendarToken(secret)): Extension<CalendarToken>,
+    Extension(audio_blob_dir): Extension<String>,
+    Extension(metadata_cache): Extension<LibraryCache>,
+    Extension(reminder_store): Extension<ReminderStore>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+) -> Result<(HeaderMap, String), StatusCode> {
+    let library_dir = feed_library(&audio_blob_dir, &secret, "calendar", &params)?;
+    let now = now_secs();
+
+    let catalog = list_articles::load_catalog(&library_dir, &metadata_cache).map_err(|e| {
+        tracing::error!("error reading dir {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let by_id: HashMap<&str, &ArticleMetadata> = catalog
+        .iter()
+        .map(|meta| (meta.id.as_str(), meta))
+        .collect();
+
+    let reminders = reminder_store.list(&library_dir, now);
+    let events: Vec<Event> = catalog
+        .iter()
+        .filter_map(conversion_event)
+        .chain(reminders.iter().map(|reminder| {
+            let meta = by_id.get(reminder.article_id.as_str()).copied();
+            reminder_event(reminder, meta)
+        }))
+        .collect();
+
+    let mut resp_headers = HeaderMap::new();
+    resp_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(ICAL_MIME_TYPE),
+    );
+    Ok((resp_headers, render_calendar(&base_url, &events, now)))
+}
+
+/// Returns the URL of the calendar of the signed-in account's library
+async fn calendar_link(
+    CurrentAccount(account): CurrentAccount,
+    Extension(CalendarToken(secret)): Extension<CalendarToken>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+) -> String {
+    feed_link(
+        &base_url,
+        "/api/calendar.ics",
+        &secret,
+        "calendar",
+        account.name.as_deref(),
+    )
+}
+
+#[test]
+fn rendering_calendar() {
+    let meta = ArticleMetadata {
+        id: "fish-abc".to_string(),
+        title: "Fish, Chips; and \\ a very long title that goes on and on past the line limit"
+            .to_string(),
+        datetime_added: Some(1_663_000_000),
+        source_url: Some("https://example.com/fish".to_string()),
+        author: Some("A. Writer".to_string()),
+        duration_secs: Some(3723),
+        ..Default::default()
+    };
+    let reminder = Reminder {
+        article_id: "fish-abc".to_string(),
+        title: "Fish".to_string(),
+        remind_at: 1_663_100_000,
+    };
+    let events = [
+        conversion_event(&meta).unwrap(),
+        reminder_event(&reminder, Some(&meta)),
+        reminder_event(&reminder, None),
+    ];
+    let ical = render_calendar("https://shoe.example", &events, 1_663_200_000);
+
+    // Every line is short enough, and ends in CRLF
+    assert!(ical.ends_with("END:VCALENDAR\r\n"));
+    assert!(ical.split("\r\n").all(|line| line.len() <= MAX_LINE_BYTES));
+    assert!(!ical.replace("\r\n", "").contains('\n'));
+
+    // Unfolding the lines gets the properties back
+    let unfolded = ical.replace("\r\n ", "");
+    let lines: Vec<&str> = unfolded.split("\r\n").collect();
+    assert!(lines.contains(
+        &"SUMMARY:Listen: Fish\\, Chips\\; and \\\\ a very long title that goes on and on past \
+          the line limit"
+    ));
+    assert!(lines.contains(&"UID:article-fish-abc@shoe.example"));
+    assert!(lines.contains(&"DTSTART:20220912T162640Z"));
+    assert!(lines.contains(&"DURATION:PT3723S"));
+    assert!(lines.contains(&"DESCRIPTION:A. Writer"));
+    assert!(lines.contains(&"DTSTART:20220913T201320Z"));
+    assert!(lines.contains(&"SUMMARY:Reminder to listen: Fish"));
+    assert_eq!(
+        lines.iter().filter(|l| **l == "DURATION:PT3723S").count(),
+        2
+    );
+    assert!(lines.contains(&"DURATION:PT900S"));
+    assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VEVENT").count(), 3);
+
+    // Articles without a conversion time aren't on the calendar
+    let undated = ArticleMetadata {
+        datetime_added: None,
+        ..meta
+    };
+    assert!(conversion_event(&undated).is_none());
+}
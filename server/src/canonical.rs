@@ -0,0 +1,271 @@
+//! Resolves the many URLs an article gets shared under to the article's own URL. Links to AMP
+//! pages, Google's AMP viewer and cache, and the AMP caches are unwrapped before fetching, and
+//! after fetching, the page's declared canonical URL wins. This way an article that's shared
+//! several ways is only converted once, and its metadata points at the real page. Articles whose
+//! words match an article in the library are caught too, wherever they came from.
+
+use crate::util::get_metadata;
+use common::ArticleMetadata;
+
+use std::{ffi::OsStr, fs};
+
+use reqwest::Url;
+use scraper::{Html, Selector};
+
+/// Query parameters that only say where a link was shared. Parameters starting with `utm_` are
+/// dropped too.
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "igshid", "mc_cid", "mc_eid", "ref_src"];
+
+/// Query parameters that ask for the AMP version of a page
+const AMP_PARAMS: &[&str] = &["amp", "amp_js_v", "usqp"];
+
+/// Proxies can be nested, e.g., Google's AMP viewer showing a page from an AMP cache. This is how
+/// many layers are unwrapped at most.
+const MAX_PROXY_DEPTH: usize = 3;
+
+/// What a fetched page says about itself
+pub(crate) struct PageInfo {
+    /// The canonical URL of the article on the page
+    pub(crate) canonical: String,
+    /// Whether the page is an AMP page, i.e., a stripped-down copy of the article
+    pub(crate) is_amp: bool,
+    /// The URL of the AMP version of the page, if it declares one
+    pub(crate) amp: Option<String>,
+}
+
+/// Returns the URL of the original page, if the given URL is of a page served through Google's
+/// cache, Google's AMP viewer, or an AMP cache
+fn unwrap_proxy(url: &Url) -> Option<Url> {
+    let host = url.host_str()?;
+
+    // Google's cache looks like webcache.googleusercontent.com/search?q=cache:ID:URL+search+terms
+    if host == "webcache.googleusercontent.com" {
+        let (_, q) = url.query_pairs().find(|(k, _)| k == "q")?;
+        let target = q.strip_prefix("cache:")?.split_whitespace().next()?;
+        // The ID is optional
+        let target = match target.split_once(':') {
+            Some((id, rest)) if !id.starts_with("http") && !id.contains('.') => rest,
+            _ => target,
+        };
+        return if target.contains("://") {
+            Url::parse(target).ok()
+        } else {
+            Url::parse(&format!("https://{target}")).ok()
+        };
+    }
+
+    // Google's AMP viewer looks like www.google.com/amp/s/HOST/PATH, and the AMP caches look like
+    // HOST-WITH-DASHES.cdn.ampproject.org/c/s/HOST/PATH. The "s/" means the page is on HTTPS.
+    let rest = if host == "google.com" || host == "www.google.com" {
+        url.path().strip_prefix("/amp/")?
+    } else if host.ends_with(".cdn.ampproject.org") {
+        let path = url.path();
+        ["/c/", "/v/", "/i/"]
+            .iter()
+            .find_map(|prefix| path.strip_prefix(prefix))?
+    } else {
+        return None;
+    };
+    let (scheme, rest) = match rest.strip_prefix("s/") {
+        Some(rest) => ("https", rest),
+        None => ("http", rest),
+    };
+
+    let mut target = Url::parse(&format!("{scheme}://{rest}")).ok()?;
+    target.set_query(url.query());
+    Some(target)
+}
+
+/// Keeps only the query parameters that pass the given filter
+fn retain_params(url: &mut Url, keep: impl Fn(&str, &str) -> bool) {
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, v)| keep(k, v))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    if params.is_empty() {
+        url.set_query(None);
+    } else if params.len() != url.query_pairs().count() {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+}
+
+/// Turns the URL of the AMP version of a page into the URL of the page itself, going by the usual
+/// conventions: an `amp.` subdomain, an `/amp` path segment, an `.amp` extension, or an `amp`
+/// query parameter
+fn strip_amp(url: &mut Url) {
+    if let Some(host) = url.host_str().and_then(|h| h.strip_prefix("amp.")) {
+        let host = host.to_string();
+        let _ = url.set_host(Some(&host));
+    }
+
+    // Drop a trailing /amp or /amp/, and the .amp in names like story.amp.html
+    let mut segments: Vec<String> = url.path().split('/').map(str::to_string).collect();
+    if let Some(last) = segments.iter().rposition(|s| !s.is_empty()) {
+        if segments[last] == "amp" {
+            segments.truncate(last);
+        }
+    }
+    for segment in &mut segments {
+        *segment = segment.replace(".amp.html", ".html");
+        if let Some(name) = segment.strip_suffix(".amp") {
+            *segment = name.to_string();
+        }
+    }
+    url.set_path(&segments.join("/"));
+
+    retain_params(url, |k, v| {
+        let asks_for_amp = AMP_PARAMS.contains(&k) || (k == "outputType" && v == "amp");
+        !asks_for_amp
+    });
+}
+
+/// Returns the URL the given link points to, without any proxy, AMP, or tracking wrapping. Strings
+/// that aren't URLs are returned as they are.
+pub(crate) fn unwrap_url(url: &str) -> String {
+    let mut url = match Url::parse(url.trim()) {
+        Ok(url) => url,
+        Err(_) => return url.trim().to_string(),
+    };
+
+    for _ in 0..MAX_PROXY_DEPTH {
+        match unwrap_proxy(&url) {
+            Some(inner) => url = inner,
+            None => break,
+        }
+    }
+    strip_amp(&mut url);
+    retain_params(&mut url, |k, _| {
+        !k.starts_with("utm_") && !TRACKING_PARAMS.contains(&k)
+    });
+    url.set_fragment(None);
+
+    url.to_string()
+}
+
+/// Reads the canonical URL and AMP-ness of the page that was fetched from the given URL. Pages
+/// that don't declare a canonical URL are taken to be at the URL they were fetched from.
+pub(crate) fn inspect_page(html: &[u8], fetched_url: &str) -> PageInfo {
+    let doc = Html::parse_document(&String::from_utf8_lossy(html));
+
+    // AMP pages are marked with <html amp> or <html ⚡>
+    let root = doc.root_element().value();
+    let is_amp = root.attr("amp").is_some() || root.attr("⚡").is_some();
+
+    let linked = |rel: &str| {
+        let link = Selector::parse(&format!(r#"link[rel~="{rel}"][href]"#)).unwrap();
+        doc.select(&link)
+            .next()
+            .and_then(|elem| elem.value().attr("href"))
+            .and_then(|href| Url::parse(fetched_url).ok()?.join(href.trim()).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+    };
+
+    let canonical = match linked("canonical") {
+        Some(url) => unwrap_url(url.as_str()),
+        None => unwrap_url(fetched_url),
+    };
+    // The AMP version is only worth having if it's another page
+    let amp = linked("amphtml")
+        .map(|url| url.to_string())
+        .filter(|_| !is_amp);
+    PageInfo {
+        canonical,
+        is_amp,
+        amp,
+    }
+}
+
+/// Returns the metadata of every article in the library
+fn library_metadata(audio_blob_dir: &str) -> impl Iterator<Item = ArticleMetadata> {
+    fs::read_dir(audio_blob_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some(OsStr::new("mp3")))
+        .filter_map(|path| get_metadata(&path).ok())
+}
+
+/// Returns the metadata of an article in the library that was fetched from the given canonical URL,
+/// if there is one. The library's URLs are unwrapped too, so articles that were added under an AMP
+/// or cache URL still count.
+pub(crate) fn find_in_library(audio_blob_dir: &str, url: &str) -> Option<ArticleMetadata> {
+    library_metadata(audio_blob_dir)
+        .find(|meta| meta.source_url.as_deref().map(unwrap_url).as_deref() == Some(url))
+}
+
+/// Returns the metadata of an article in the library with the given text hash, if there is one.
+/// Articles from before text hashes were recorded never match.
+pub(crate) fn find_text_in_library(
+    audio_blob_dir: &str,
+    text_hash: &str,
+) -> Option<ArticleMetadata> {
+    library_metadata(audio_blob_dir).find(|meta| meta.text_hash.as_deref() == Some(text_hash))
+}
+
+#[test]
+fn unwrapping() {
+    let article = "https://www.example.com/2022/10/story.html";
+    let cases = [
+        // Already canonical
+        article,
+        // Tracking parameters and fragments
+        "https://www.example.com/2022/10/story.html?utm_source=twitter&fbclid=abc#comments",
+        // AMP conventions
+        "https://www.example.com/2022/10/story.html/amp",
+        "https://www.example.com/2022/10/story.amp.html",
+        "https://www.example.com/2022/10/story.html?amp=1",
+        "https://www.example.com/2022/10/story.html?outputType=amp",
+        // Google's AMP viewer and the AMP cache, also nested
+        "https://www.google.com/amp/s/www.example.com/2022/10/story.html/amp/",
+        "https://www-example-com.cdn.ampproject.org/c/s/www.example.com/2022/10/story.amp.html",
+        "https://www.google.com/amp/s/www-example-com.cdn.ampproject.org/c/s/www.example.com/2022/10/story.html",
+        // Google's cache, with and without an ID and search terms
+        "https://webcache.googleusercontent.com/search?q=cache:https://www.example.com/2022/10/story.html",
+        "https://webcache.googleusercontent.com/search?q=cache:Ab12_x:www.example.com/2022/10/story.html+ship+sailed",
+    ];
+    for case in cases {
+        assert_eq!(unwrap_url(case), article, "unwrapping {case}");
+    }
+
+    // Other query parameters are kept, and AMP-looking words in the middle of a path aren't touched
+    assert_eq!(
+        unwrap_url("https://example.com/story?id=5&utm_medium=email"),
+        "https://example.com/story?id=5"
+    );
+    assert_eq!(
+        unwrap_url("https://amp.example.com/story.amp"),
+        "https://example.com/story"
+    );
+    assert_eq!(
+        unwrap_url("https://example.com/amp/story"),
+        "https://example.com/amp/story"
+    );
+    assert_eq!(unwrap_url(" not a url "), "not a url");
+}
+
+#[test]
+fn page_inspection() {
+    let amp_page = br#"<html amp><head>
+        <link rel="canonical" href="/2022/10/story.html?utm_campaign=x">
+        </head><body></body></html>"#;
+    let info = inspect_page(amp_page, "https://m.example.com/2022/10/story.amp.html");
+    assert!(info.is_amp);
+    assert_eq!(info.canonical, "https://m.example.com/2022/10/story.html");
+    assert_eq!(info.amp, None);
+
+    let page = br#"<html><head>
+        <link rel="canonical" href="https://www.example.com/story">
+        <link rel="amphtml" href="/story/amp">
+        </head></html>"#;
+    let info = inspect_page(page, "https://m.example.com/story");
+    assert!(!info.is_amp);
+    assert_eq!(info.canonical, "https://www.example.com/story");
+    assert_eq!(info.amp.as_deref(), Some("https://m.example.com/story/amp"));
+
+    // Without a declared canonical URL, the page is where it was fetched from
+    let info = inspect_page(b"<html><body>Hi</body></html>", "https://example.com/a#top");
+    assert_eq!(info.canonical, "https://example.com/a");
+}
@@ -0,0 +1,466 @@
+//! The server's configuration file. Everything that doesn't fit in a command line flag goes here.
+
+use common::EncodingOptions;
+use tts_pipeline::audio::{AudioFormat, BedMix};
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error as AnyError};
+use serde::Deserialize;
+
+/// The contents of the config file passed via `--config`. Every section is optional.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ServerConfig {
+    /// The TTS engine articles are converted with
+    pub(crate) engine: EngineConfig,
+    /// Voices beyond the builtin ones, e.g., custom/cloned voices
+    pub(crate) custom_voices: Vec<CustomVoice>,
+    /// Background audio that can be mixed under articles
+    pub(crate) ambient_beds: Vec<AmbientBed>,
+    /// Per-site changes to how cookie banners and interstitials are stripped from fetched pages
+    pub(crate) clutter_overrides: Vec<ClutterOverride>,
+    /// The token podcast apps put in the query string to get the library as a podcast, i.e.,
+    /// `/api/podcast.xml?token=TOKEN`. If this or `base_url` isn't set, there's no podcast feed.
+    pub(crate) podcast_token: Option<String>,
+    /// The token calendar apps put in the query string to get the conversions and reminders as a
+    /// calendar, i.e., `/api/calendar.ics?token=TOKEN`. If this or `base_url` isn't set, there's no
+    /// calendar.
+    pub(crate) calendar_token: Option<String>,
+    /// Rules for converting articles as soon as they're saved to the inbox. Articles that match no
+    /// rule wait there to be picked.
+    pub(crate) inbox_rules: Vec<InboxRule>,
+    /// How push services can reach whoever runs this server, as a `mailto:` or `https:` URL. Push
+    /// services use it if this server's notifications cause trouble.
+    pub(crate) push_contact: Option<String>,
+    /// The formats, besides MP3, that article audio can be transcoded to for clients that ask for
+    /// them, e.g., `["opus", "aac"]`. They take up much less space on phones. If this is empty,
+    /// articles are only served as MP3.
+    pub(crate) transcode_formats: Vec<AudioFormat>,
+    /// The code people have to give to sign up for an account, when accounts are on. If this isn't
+    /// set, anyone who can reach the server can sign up.
+    pub(crate) signup_code: Option<String>,
+    /// The accounts that can manage the server, i.e., see and delete the audio of every library,
+    /// when accounts are on. Without accounts, everyone can.
+    pub(crate) admins: Vec<String>,
+    /// The most megabytes the audio of every library can take up, transcoded copies included. When
+    /// it's more, the articles whose audio was fetched least recently are deleted until it isn't.
+    /// If this isn't set, nothing is deleted.
+    pub(crate) max_audio_megabytes: Option<u64>,
+    /// The attributes of the cookies set when accounts are on
+    pub(crate) cookies: CookieConfig,
+    /// The sites that can embed the app in a frame, e.g., `["https://blog.example.com"]`, for using
+    /// the player as a widget. If this is empty, no site can.
+    pub(crate) embed_origins: Vec<String>,
+    /// The other ReadToMyShoe servers the app can be switched to, e.g.,
+    /// `["https://work.example.com"]`. The app's Content Security Policy only lets it reach this
+    /// server and these. Each of them has to let this app's origin in with its `[[cors]]` config.
+    pub(crate) app_servers: Vec<String>,
+    /// The other sites, e.g., browser extensions and web-based clients, that can use the API from a
+    /// browser, and what they can use. If this is empty, no other site can.
+    pub(crate) cors: Vec<CorsRule>,
+    /// The language model that picks the key points of articles that ask for them, and answers
+    /// questions about articles. If this isn't set, neither is offered.
+    pub(crate) language_model: Option<LanguageModelConfig>,
+    /// Where the app is reached, e.g., `https://example.com/rtms/`. The app is served under its
+    /// path, and the proxy has to pass it through rather than strip it. If this isn't set, the app
+    /// is served at the root. See `subpath.rs`. The podcast feed and the calendar link back to the
+    /// whole URL, so they're only served if it's a whole URL rather than just a path.
+    pub(crate) base_url: Option<String>,
+    /// How article audio is encoded when the article doesn't ask for something else, as the
+    /// `[encoding]` table, e.g., `sample_rate = 16000` and `bitrate_kbps = 32` for smaller files.
+    /// By default, it's 64 kbit/s mono at 24 kHz.
+    pub(crate) encoding: EncodingOptions,
+    /// How the server fetches articles and their images from other sites
+    pub(crate) fetch: FetchConfig,
+}
+
+/// The policy for requests to other sites, i.e., fetching articles and their images. In the config
+/// file, this is the `[fetch]` table, e.g.,
+///
+/// ```toml
+/// [fetch]
+/// user_agent = "readtomyshoe (+https://example.com/about)"
+/// timeout_secs = 10
+/// deny_sites = ["internal.example.com"]
+/// proxy = "socks5://127.0.0.1:1080"
+///
+/// [[fetch.site_proxies]]
+/// site = "intranet.example.com"
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct FetchConfig {
+    /// The User-Agent header sent with every request. If this isn't set, none is sent.
+    pub(crate) user_agent: Option<String>,
+    /// How long a request can take before it's given up on, in seconds
+    pub(crate) timeout_secs: u64,
+    /// The most megabytes a page can be
+    pub(crate) max_megabytes: u64,
+    /// The sites, e.g., `example.com`, that the server fetches from. Their subdomains are included.
+    /// If this is empty, every site is.
+    pub(crate) allow_sites: Vec<String>,
+    /// The sites the server never fetches from. Their subdomains are included.
+    pub(crate) deny_sites: Vec<String>,
+    /// Whether addresses that aren't on the public internet, e.g., `localhost` and `10.0.0.1`, can
+    /// be fetched from. Only turn this on if everyone who can reach the server is trusted.
+    pub(crate) allow_private_addresses: bool,
+    /// The proxy requests go through, e.g., `http://proxy.example.com:3128` or
+    /// `socks5://127.0.0.1:1080`. If this isn't set, the `HTTPS_PROXY` and `HTTP_PROXY` environment
+    /// variables are used, if they're set.
+    pub(crate) proxy: Option<String>,
+    /// Proxies for particular sites, which override `proxy`. The first that matches is used.
+    pub(crate) site_proxies: Vec<SiteProxy>,
+}
+
+/// The proxy requests to a site go through
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct SiteProxy {
+    /// The site this applies to, e.g., `example.com`. Its subdomains are included.
+    pub(crate) site: String,
+    /// The proxy, like `FetchConfig::proxy`. If this isn't set, the site is reached directly.
+    pub(crate) proxy: Option<String>,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            user_agent: None,
+            timeout_secs: 30,
+            max_megabytes: 20,
+            allow_sites: Vec::new(),
+            deny_sites: Vec::new(),
+            allow_private_addresses: false,
+            proxy: None,
+            site_proxies: Vec::new(),
+        }
+    }
+}
+
+/// A language model behind an OpenAI-compatible chat completions API. In the config file, this is
+/// the `[language_model]` table, e.g.,
+///
+/// ```toml
+/// [language_model]
+/// endpoint = "https://api.openai.com/v1/chat/completions"
+/// model = "gpt-4o-mini"
+/// api_key_file = "openai_api.key"
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct LanguageModelConfig {
+    /// The URL of the chat completions endpoint
+    pub(crate) endpoint: String,
+    /// The name of the model to ask
+    pub(crate) model: String,
+    /// The file that holds the API key. Models running locally might not need one.
+    pub(crate) api_key_file: Option<PathBuf>,
+}
+
+/// An origin that can make cross-origin requests to the API, and the parts of the API it can use.
+/// In the config file, these are `[[cors]]` tables, e.g.,
+///
+/// ```toml
+/// [[cors]]
+/// origin = "https://reader.example.com"
+/// paths = ["/api/add-article-by-url", "/api/list-articles"]
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct CorsRule {
+    /// The origin, e.g., `https://reader.example.com` or `chrome-extension://EXTENSION_ID`
+    pub(crate) origin: String,
+    /// The API paths the origin can use. A path ending in `/` covers everything under it. If this
+    /// is empty, the origin can use the whole API.
+    #[serde(default)]
+    pub(crate) paths: Vec<String>,
+}
+
+/// The attributes of the session and CSRF cookies. In the config file, this is the `[cookies]`
+/// table, e.g.,
+///
+/// ```toml
+/// [cookies]
+/// secure = true
+/// same_site = "strict"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct CookieConfig {
+    /// Whether cookies are only sent over HTTPS. Turn this on if the server is only reached over
+    /// HTTPS, e.g., behind a reverse proxy that terminates TLS.
+    pub(crate) secure: bool,
+    /// The `SameSite` attribute of the cookies
+    pub(crate) same_site: SameSite,
+}
+
+/// When browsers send cookies with requests that come from other sites
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SameSite {
+    /// With top-level navigations from other sites, e.g., following a link to the server. This is
+    /// the default.
+    #[default]
+    Lax,
+    /// Never. Following a link to the server from elsewhere shows the sign in form.
+    Strict,
+}
+
+/// The TTS engine to use, and how to reach it. In the config file, this is the `[engine]` table,
+/// e.g.,
+///
+/// ```toml
+/// [engine]
+/// kind = "local"
+/// command = "piper"
+/// args = ["--model", "en_US-lessac-medium.onnx", "--output-raw"]
+/// sample_rate = 22050
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum EngineConfig {
+    /// Google Cloud TTS. This is the default.
+    Google {
+        /// The file that holds the API key
+        #[serde(default = "default_google_key_file")]
+        api_key_file: PathBuf,
+    },
+    /// Azure Speech
+    Azure {
+        /// The region of the Speech resource, e.g., `eastus`
+        region: String,
+        /// The file that holds the Speech resource's key
+        key_file: PathBuf,
+    },
+    /// Amazon Polly
+    Polly {
+        /// The AWS region, e.g., `us-east-1`
+        region: String,
+        /// The ID of the access key to sign requests with
+        access_key_id: String,
+        /// The file that holds the secret access key
+        secret_key_file: PathBuf,
+        /// Whether to use Polly's neural voices rather than its standard ones
+        #[serde(default)]
+        neural: bool,
+    },
+    /// A program on this machine, e.g., Piper. The article text is written to its stdin, one
+    /// paragraph per line, and it writes the audio to its stdout.
+    Local {
+        /// The program to run
+        command: PathBuf,
+        /// The arguments to run it with
+        #[serde(default)]
+        args: Vec<String>,
+        /// If the program writes raw 16-bit mono samples, this is their sample rate. Otherwise,
+        /// it's expected to write a WAV file.
+        sample_rate: Option<u32>,
+        /// The name the program's voice is listed under
+        #[serde(default = "default_local_voice")]
+        voice: String,
+    },
+}
+
+impl Default for EngineConfig {
+    fn default() -> EngineConfig {
+        EngineConfig::Google {
+            api_key_file: default_google_key_file(),
+        }
+    }
+}
+
+/// Google Cloud's API key is kept in the working directory by default
+fn default_google_key_file() -> PathBuf {
+    "gcp_api.key".into()
+}
+
+/// The voice of a local engine is listed as "local" by default
+fn default_local_voice() -> String {
+    "local".to_string()
+}
+
+/// The default volume of an ambient bed, relative to its original volume
+fn default_bed_volume() -> f32 {
+    0.15
+}
+
+/// A background audio track, e.g., rain or cafe noise, that's looped under the speech of an article
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct AmbientBed {
+    /// The name clients use to refer to this bed
+    pub(crate) name: String,
+    /// A human-readable description of the bed
+    #[serde(default)]
+    pub(crate) description: String,
+    /// The path to the audio file. Any format ffmpeg can read is fine.
+    pub(crate) path: PathBuf,
+    /// The volume of the bed relative to its original volume, before ducking
+    #[serde(default = "default_bed_volume")]
+    pub(crate) volume: f32,
+}
+
+impl AmbientBed {
+    /// Returns how the bed is mixed under speech
+    pub(crate) fn mix(&self) -> BedMix<'_> {
+        BedMix {
+            path: &self.path,
+            volume: self.volume,
+        }
+    }
+}
+
+/// By default, the clutter heuristics run on every site
+fn default_clutter_heuristics() -> bool {
+    true
+}
+
+/// Changes to how clutter, i.e., cookie banners and interstitials, is stripped from the pages of a
+/// site before extraction
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct ClutterOverride {
+    /// The site this applies to, e.g., `example.com`. Its subdomains are included.
+    pub(crate) site: String,
+    /// Whether the heuristics look for clutter on this site's pages at all
+    #[serde(default = "default_clutter_heuristics")]
+    pub(crate) heuristics: bool,
+    /// CSS selectors of more elements to strip from this site's pages
+    #[serde(default)]
+    pub(crate) strip: Vec<String>,
+    /// CSS selectors of elements that are never stripped, e.g., because the heuristics mistake them
+    /// for clutter. Elements containing them aren't stripped either.
+    #[serde(default)]
+    pub(crate) keep: Vec<String>,
+}
+
+/// A rule for converting inbox items as soon as they're saved. An item matches if it meets every
+/// criterion the rule has, so a rule without criteria matches everything.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct InboxRule {
+    /// The site the article is on, e.g., `example.com`. Its subdomains are included.
+    pub(crate) site: Option<String>,
+    /// A tag the article was saved with
+    pub(crate) tag: Option<String>,
+    /// The most minutes the article is estimated to take to listen to. Articles whose length is
+    /// unknown don't match.
+    pub(crate) max_minutes: Option<usize>,
+}
+
+/// A custom voice, e.g., a Google Cloud custom voice model trained on someone's own voice. Only the
+/// Google engine uses the model. Other engines use the voice's name as their own voice name.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct CustomVoice {
+    /// The name clients use to refer to this voice
+    pub(crate) name: String,
+    /// A human-readable description of the voice
+    #[serde(default)]
+    pub(crate) description: String,
+    /// The resource name of the voice model, e.g.,
+    /// `projects/PROJECT/locations/LOCATION/models/MODEL`
+    pub(crate) model: String,
+    /// The language the voice speaks, e.g., `en-US`
+    pub(crate) language_code: String,
+    /// The keys that grant access to this voice. Clients present a key in the `X-Voice-Key` header.
+    /// If this is empty, the voice is available to everyone.
+    #[serde(default)]
+    pub(crate) access_keys: Vec<String>,
+}
+
+impl CustomVoice {
+    /// Returns whether the holder of the given key may use this voice
+    pub(crate) fn is_permitted(&self, key: Option<&str>) -> bool {
+        self.access_keys.is_empty()
+            || key
+                .map(|k| self.access_keys.iter().any(|a| a == k))
+                .unwrap_or(false)
+    }
+}
+
+impl ServerConfig {
+    /// Loads the config from the given TOML file
+    pub(crate) fn load(path: &Path) -> Result<ServerConfig, AnyError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read config file {:?}: {e}", path))?;
+        toml::from_str(&contents)
+            .map_err(|e| anyhow!("Could not parse config file {:?}: {e}", path))
+    }
+}
+
+#[test]
+fn custom_voice_permissions() {
+    let config: ServerConfig = toml::from_str(
+        r#"
+        [[custom_voices]]
+        name = "narrator"
+        model = "projects/p/locations/global/models/m"
+        language_code = "en-US"
+        access_keys = ["hunter2"]
+
+        [[custom_voices]]
+        name = "shared"
+        model = "projects/p/locations/global/models/n"
+        language_code = "en-GB"
+        "#,
+    )
+    .unwrap();
+
+    let narrator = &config.custom_voices[0];
+    assert!(narrator.is_permitted(Some("hunter2")));
+    assert!(!narrator.is_permitted(Some("hunter3")));
+    assert!(!narrator.is_permitted(None));
+
+    let shared = &config.custom_voices[1];
+    assert!(shared.is_permitted(None));
+}
+
+#[test]
+fn ambient_bed_defaults() {
+    let config: ServerConfig = toml::from_str(
+        r#"
+        [[ambient_beds]]
+        name = "rain"
+        path = "beds/rain.ogg"
+        "#,
+    )
+    .unwrap();
+
+    let bed = &config.ambient_beds[0];
+    assert_eq!(bed.path, PathBuf::from("beds/rain.ogg"));
+    assert_eq!(bed.volume, default_bed_volume());
+    assert!(config.custom_voices.is_empty());
+}
+
+#[test]
+fn engine_config() {
+    // Google is used when no engine is given
+    let config: ServerConfig = toml::from_str("").unwrap();
+    assert!(matches!(config.engine, EngineConfig::Google { .. }));
+
+    let config: ServerConfig = toml::from_str(
+        r#"
+        [engine]
+        kind = "local"
+        command = "piper"
+        args = ["--output-raw"]
+        sample_rate = 22050
+        "#,
+    )
+    .unwrap();
+    match config.engine {
+        EngineConfig::Local {
+            command,
+            args,
+            sample_rate,
+            voice,
+        } => {
+            assert_eq!(command, PathBuf::from("piper"));
+            assert_eq!(args, vec!["--output-raw"]);
+            assert_eq!(sample_rate, Some(22050));
+            assert_eq!(voice, default_local_voice());
+        }
+        other => panic!("Expected a local engine, got {:?}", other),
+    }
+
+    // Polly needs its credentials
+    assert!(
+        toml::from_str::<ServerConfig>("[engine]\nkind = \"polly\"\nregion = \"us-east-1\"")
+            .is_err()
+    );
+}
@@ -0,0 +1,145 @@
+//! Cross-origin resource sharing (CORS), so browser extensions and web-based clients on other sites
+//! can use the API. Browsers only let another site read the API's responses if the server says it
+//! can, so nothing is allowed unless the `[[cors]]` tables in the config list the site. Each one can
+//! be limited to some of the API.
+//!
+//! With accounts on, browsers don't send the session cookie with requests from other sites, so
+//! those requests are turned away like any other without a session, unless they have the session
+//! token in their `Authorization` header.
+
+use crate::config::CorsRule;
+
+use std::sync::Arc;
+
+use axum::{
+    extract::Extension,
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+/// The methods the API is used with
+const ALLOWED_METHODS: &str = "GET, POST";
+
+/// The request headers clients can send
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type, X-Voice-Key";
+
+/// How long browsers can remember a preflight response, in seconds
+const PREFLIGHT_MAX_AGE_SECS: u64 = 10 * 60;
+
+/// The origins that can use the API, and what they can use
+#[derive(Clone)]
+struct CorsRules(Arc<Vec<CorsRule>>);
+
+impl CorsRules {
+    /// Returns whether the given origin can use the given path
+    fn allows(&self, origin: &str, path: &str) -> bool {
+        let path_allowed = |rule: &CorsRule| {
+            rule.paths.is_empty()
+                || rule.paths.iter().any(|p| match p.strip_suffix('/') {
+                    Some(_) => path.starts_with(p.as_str()),
+                    None => path == p,
+                })
+        };
+        path.starts_with("/api/")
+            && self
+                .0
+                .iter()
+                .any(|rule| rule.origin.eq_ignore_ascii_case(origin) && path_allowed(rule))
+    }
+}
+
+// Lets the origins in the given rules use the API. This has to be set up after accounts, so
+// preflight requests, which never have cookies, are answered before they're turned away for having
+// no session.
+pub(crate) fn setup(router: Router, rules: Vec<CorsRule>) -> Router {
+    if rules.is_empty() {
+        return router;
+    }
+    router
+        .layer(middleware::from_fn(cors))
+        .layer(Extension(CorsRules(Arc::new(rules))))
+}
+
+/// Answers preflight requests from the allowed origins, and tells browsers the allowed origins can
+/// read the responses to their requests
+async fn cors<B>(req: Request<B>, next: Next<B>) -> Response {
+    let path = req.uri().path().to_string();
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let allowed_origin = req
+        .extensions()
+        .get::<CorsRules>()
+        .zip(origin)
+        .filter(|(rules, origin)| rules.allows(origin, &path))
+        .map(|(_, origin)| origin);
+    let is_preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+
+    let mut resp = match &allowed_origin {
+        Some(_) if is_preflight => {
+            let mut resp = StatusCode::NO_CONTENT.into_response();
+            let headers = resp.headers_mut();
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static(ALLOWED_METHODS),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(ALLOWED_HEADERS),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from(PREFLIGHT_MAX_AGE_SECS),
+            );
+            resp
+        }
+        _ => next.run(req).await,
+    };
+
+    // Responses differ by origin, so caches have to keep them apart
+    if path.starts_with("/api/") {
+        resp.headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Origin"));
+    }
+    if let Some(origin) = allowed_origin.and_then(|o| HeaderValue::from_str(&o).ok()) {
+        resp.headers_mut()
+            .insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    resp
+}
+
+#[test]
+fn cors_rules() {
+    let rules = CorsRules(Arc::new(vec![
+        CorsRule {
+            origin: "https://reader.example".to_string(),
+            paths: vec![],
+        },
+        CorsRule {
+            origin: "chrome-extension://abcdef".to_string(),
+            paths: vec![
+                "/api/add-article-by-url".to_string(),
+                "/api/jobs/".to_string(),
+            ],
+        },
+    ]));
+
+    // An origin without paths can use the whole API, and nothing else
+    assert!(rules.allows("https://reader.example", "/api/list-articles"));
+    assert!(rules.allows("HTTPS://Reader.Example", "/api/sync"));
+    assert!(!rules.allows("https://reader.example", "/audio/f00d.mp3"));
+    assert!(!rules.allows("https://evil.example", "/api/list-articles"));
+
+    // An origin with paths can only use those
+    assert!(rules.allows("chrome-extension://abcdef", "/api/add-article-by-url"));
+    assert!(rules.allows("chrome-extension://abcdef", "/api/jobs/123"));
+    assert!(!rules.allows("chrome-extension://abcdef", "/api/add-article-by-url-2"));
+    assert!(!rules.allows("chrome-extension://abcdef", "/api/delete-articles"));
+}
@@ -0,0 +1,164 @@
+//! The Content Security Policy every response is sent with. It only lets the app load scripts,
+//! styles, and media from this server, so an article title or feed entry that smuggles in markup
+//! can't run anything.
+//!
+//! The app needs a few allowances: WebAssembly compilation (`'wasm-unsafe-eval'`), the inline
+//! script Trunk writes into `index.html` to start the WASM (allowed by its hash, which is worked out
+//! when the server starts), inline styles, and `blob:` URLs for the audio the player loads from
+//! IndexedDB and streams through Media Source Extensions.
+//!
+//! By default, no other site can put the app in a frame. `embed_origins` in the config lists the
+//! sites that can, for embedding the player as a widget.
+//!
+//! The app can be switched to other servers, whose API it calls and whose audio it plays.
+//! `app_servers` in the config lists them, and nothing else can be reached.
+
+use anyhow::{anyhow, Error as AnyError};
+use axum::{
+    http::{header, HeaderValue},
+    Router,
+};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+/// Returns the CSP hash sources, e.g., `'sha256-...'`, of the inline scripts in the given HTML
+fn inline_script_hashes(html: &str) -> Vec<String> {
+    let mut hashes = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("<script") {
+        rest = &rest[start..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let Some(script_end) = rest.find("</script>") else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        if !tag.contains("src=") && tag_end < script_end {
+            let script = &rest[tag_end + 1..script_end];
+            hashes.push(format!(
+                "'sha256-{}'",
+                base64::encode(Sha256::digest(script.as_bytes()))
+            ));
+        }
+        rest = &rest[script_end..];
+    }
+    hashes
+}
+
+/// Returns the origins of the given server URLs, e.g., `https://example.com` for
+/// `https://example.com/rtms/`, since the app calls paths all over them
+fn server_origins(app_servers: &[String]) -> Result<Vec<String>, AnyError> {
+    app_servers
+        .iter()
+        .map(|server| {
+            let url = Url::parse(server.trim())
+                .map_err(|e| anyhow!("app_servers has {server:?}, which isn't a URL: {e}"))?;
+            match url.origin() {
+                origin if origin.is_tuple() => Ok(origin.ascii_serialization()),
+                _ => Err(anyhow!(
+                    "app_servers has {server:?}, which isn't a server URL"
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Returns the policy for the app whose `index.html` is given, which the given origins can embed,
+/// and which can reach the servers at the given origins
+fn policy(index_html: &str, embed_origins: &[String], server_origins: &[String]) -> String {
+    let script_src = ["'self'", "'wasm-unsafe-eval'"]
+        .into_iter()
+        .map(str::to_string)
+        .chain(inline_script_hashes(index_html))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let frame_ancestors = if embed_origins.is_empty() {
+        "'none'".to_string()
+    } else {
+        format!("'self' {}", embed_origins.join(" "))
+    };
+    let servers: String = server_origins.iter().map(|o| format!(" {o}")).collect();
+
+    [
+        "default-src 'self'".to_string(),
+        format!("script-src {script_src}"),
+        "style-src 'self' 'unsafe-inline'".to_string(),
+        "img-src 'self' data: blob:".to_string(),
+        format!("media-src 'self' blob:{servers}"),
+        format!("connect-src 'self'{servers}"),
+        "worker-src 'self'".to_string(),
+        "manifest-src 'self'".to_string(),
+        "object-src 'none'".to_string(),
+        "base-uri 'self'".to_string(),
+        "form-action 'self'".to_string(),
+        format!("frame-ancestors {frame_ancestors}"),
+    ]
+    .join("; ")
+}
+
+/// Sends every response with the Content Security Policy for the app whose `index.html`, as it's
+/// served, is given, and which can be switched to the given servers. Unless some origins can embed
+/// the app, this also tells older browsers not to frame it.
+pub(crate) fn setup(
+    router: Router,
+    index_html: &str,
+    embed_origins: &[String],
+    app_servers: &[String],
+) -> Router {
+    let server_origins = server_origins(app_servers).unwrap();
+    let csp = HeaderValue::from_str(&policy(index_html, embed_origins, &server_origins))
+        .expect("embed_origins should be origins, e.g., https://example.com");
+
+    let router = router.layer(SetResponseHeaderLayer::if_not_present(
+        header::CONTENT_SECURITY_POLICY,
+        csp,
+    ));
+    if embed_origins.is_empty() {
+        router.layer(SetResponseHeaderLayer::if_not_present(
+            header::X_FRAME_OPTIONS,
+            HeaderValue::from_static("DENY"),
+        ))
+    } else {
+        router
+    }
+}
+
+#[test]
+fn content_security_policy() {
+    let index_html = r#"<html><head>
+        <script type="module">import init from '/assets/rtms.js';init('/assets/rtms_bg.wasm');</script>
+        <script src="/assets/other.js"></script>
+        </head></html>"#;
+    let hashes = inline_script_hashes(index_html);
+    let expected = base64::encode(Sha256::digest(
+        b"import init from '/assets/rtms.js';init('/assets/rtms_bg.wasm');",
+    ));
+    assert_eq!(hashes, vec![format!("'sha256-{expected}'")]);
+
+    let csp = policy(index_html, &[], &[]);
+    assert!(csp.contains(&format!(
+        "script-src 'self' 'wasm-unsafe-eval' 'sha256-{expected}';"
+    )));
+    assert!(csp.contains("media-src 'self' blob:;"));
+    assert!(csp.contains("connect-src 'self';"));
+    assert!(csp.ends_with("frame-ancestors 'none'"));
+
+    let csp = policy(index_html, &["https://blog.example".to_string()], &[]);
+    assert!(csp.ends_with("frame-ancestors 'self' https://blog.example"));
+
+    // Switching to another server means calling its API and playing its audio, wherever on it the
+    // app is served
+    let app_servers = [
+        "https://work.example/rtms/".to_string(),
+        "http://192.168.1.5:8080".to_string(),
+    ];
+    let origins = server_origins(&app_servers).unwrap();
+    assert_eq!(origins, ["https://work.example", "http://192.168.1.5:8080"]);
+    let csp = policy(index_html, &[], &origins);
+    assert!(csp.contains("connect-src 'self' https://work.example http://192.168.1.5:8080;"));
+    assert!(csp.contains("media-src 'self' blob: https://work.example http://192.168.1.5:8080;"));
+    assert!(server_origins(&["work.example".to_string()]).is_err());
+    assert!(server_origins(&["data:text/plain,hi".to_string()]).is_err());
+}
@@ -0,0 +1,79 @@
+//! A small CSV reader and writer, for library catalogs and the CSV exports of other readers, e.g.,
+//! Pocket. Fields are separated by commas and quoted with double quotes, and a quote in a quoted
+//! field is written twice, as in RFC 4180.
+
+/// Returns the given fields as a line of CSV, ending in CRLF. Fields are only quoted if they need
+/// to be.
+pub(crate) fn write_row<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut row = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// Parses the given CSV into rows of fields. Quoted fields can have line breaks in them. Blank
+/// lines are skipped.
+pub(crate) fn parse(csv: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = csv.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => (),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|f| !f.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    row.push(field);
+    if row.iter().any(|f| !f.is_empty()) {
+        rows.push(row);
+    }
+    rows
+}
+
+#[test]
+fn reading_and_writing() {
+    let header = write_row(&["url", "title"]);
+    assert_eq!(header, "url,title\r\n");
+    let row = write_row(&["https://example.com/", "Boots, \"laced\"\nand not"]);
+    assert_eq!(
+        row,
+        "https://example.com/,\"Boots, \"\"laced\"\"\nand not\"\r\n"
+    );
+
+    let csv = format!("{header}\n{row}https://example.com/2,");
+    assert_eq!(
+        parse(&csv),
+        [
+            vec!["url", "title"],
+            vec!["https://example.com/", "Boots, \"laced\"\nand not"],
+            vec!["https://example.com/2", ""],
+        ]
+    );
+}
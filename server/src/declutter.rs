@@ -0,0 +1,261 @@
+//! Strips cookie-consent banners, "continue reading" interstitials, and the like from fetched pages
+//! before text extraction, so the audio doesn't start with a cookie policy. Clutter is found
+//! heuristically, and sites where the heuristics get it wrong can be adjusted in the config file.
+
+use crate::{
+    config::ClutterOverride,
+    extract::{element_text, is_on_site},
+};
+
+use std::{collections::HashSet, sync::Arc};
+
+use scraper::{ElementRef, Html, Selector};
+
+/// Elements with more text than this aren't stripped by the heuristics. Banners and interstitials
+/// are short, and a long element is more likely a wrapper around the article itself.
+const MAX_CLUTTER_LEN: usize = 1500;
+
+/// Parts of IDs and classes that mark consent banners, paywall prompts, and their backdrops. Most
+/// are the names of the common consent management platforms.
+const CLUTTER_MARKERS: &[&str] = &[
+    "cookie",
+    "consent",
+    "gdpr",
+    "ccpa",
+    "onetrust",
+    "didomi",
+    "qc-cmp",
+    "truste",
+    "usercentrics",
+    "sp_message",
+    "interstitial",
+    "paywall",
+    "regwall",
+    "tp-modal",
+    "tp-backdrop",
+    "continue-reading",
+];
+
+/// Button and link text that asks the reader to accept something
+const ACCEPT_PHRASES: &[&str] = &[
+    "accept",
+    "accept all",
+    "accept cookies",
+    "agree",
+    "i agree",
+    "allow all",
+    "got it",
+    "ok",
+    "reject all",
+    "manage preferences",
+];
+
+/// The entire text of elements that interrupt an article
+const INTERSTITIAL_PHRASES: &[&str] = &[
+    "advertisement",
+    "article continues below",
+    "continue reading",
+    "continue reading below",
+    "keep reading",
+    "read more",
+    "read the full story",
+    "scroll to continue with content",
+    "story continues below advertisement",
+];
+
+/// Elements that are never stripped by the heuristics, since they hold the whole page or article
+const PROTECTED_TAGS: &[&str] = &["html", "head", "body", "main", "article"];
+
+/// The clutter stripping rules, i.e., the per-site overrides from the config file
+#[derive(Clone, Default)]
+pub(crate) struct ClutterRules(Arc<Vec<ClutterOverride>>);
+
+impl ClutterRules {
+    pub(crate) fn new(overrides: Vec<ClutterOverride>) -> ClutterRules {
+        ClutterRules(Arc::new(overrides))
+    }
+
+    /// Returns the override for the site of the given URL, if any
+    fn get(&self, url: &str) -> Option<&ClutterOverride> {
+        self.0.iter().find(|o| is_on_site(url, &o.site))
+    }
+
+    /// Returns the given page, fetched from the given URL, with its clutter removed. If there's no
+    /// clutter, the page is returned as is.
+    pub(crate) fn strip(&self, url: &str, html: &[u8]) -> Vec<u8> {
+        let mut doc = Html::parse_document(&String::from_utf8_lossy(html));
+        let over = self.get(url);
+        let heuristics = over.map(|o| o.heuristics).unwrap_or(true);
+
+        // Find everything the override protects. Unparseable selectors are logged and skipped.
+        let parse = |selector: &String| match Selector::parse(selector) {
+            Ok(s) => Some(s),
+            Err(_) => {
+                tracing::warn!("Invalid clutter selector '{selector}'");
+                None
+            }
+        };
+        let kept: HashSet<_> = over
+            .iter()
+            .flat_map(|o| o.keep.iter().filter_map(parse))
+            .flat_map(|s| doc.select(&s).map(|e| e.id()).collect::<Vec<_>>())
+            .collect();
+        let is_kept = |elem: ElementRef| {
+            kept.contains(&elem.id())
+                || elem
+                    .descendants()
+                    .any(|d| ElementRef::wrap(d).is_some_and(|e| kept.contains(&e.id())))
+        };
+
+        // Collect the explicitly stripped elements, then the ones that look like clutter
+        let mut clutter: Vec<_> = over
+            .iter()
+            .flat_map(|o| o.strip.iter().filter_map(parse))
+            .flat_map(|s| doc.select(&s).collect::<Vec<_>>())
+            .filter(|&e| !is_kept(e))
+            .map(|e| e.id())
+            .collect();
+        if heuristics {
+            let all = Selector::parse("*").unwrap();
+            clutter.extend(
+                doc.select(&all)
+                    .filter(|&e| is_clutter(e) && !is_kept(e))
+                    .map(|e| e.id()),
+            );
+        }
+
+        if clutter.is_empty() {
+            return html.to_vec();
+        }
+
+        tracing::debug!("Stripping {} cluttering elements from {url}", clutter.len());
+        for id in clutter {
+            if let Some(mut node) = doc.tree.get_mut(id) {
+                node.detach();
+            }
+        }
+        doc.root_element().html().into_bytes()
+    }
+}
+
+/// Returns the given text in lowercase, with its whitespace collapsed and any punctuation, arrows,
+/// and the like trimmed off the ends
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_string()
+}
+
+/// Returns whether the given element looks like a consent banner or an interstitial
+fn is_clutter(elem: ElementRef) -> bool {
+    let value = elem.value();
+    if PROTECTED_TAGS.contains(&value.name()) {
+        return false;
+    }
+
+    let text = element_text(elem);
+    if text.chars().count() > MAX_CLUTTER_LEN {
+        return false;
+    }
+
+    // Consent platforms and paywalls mostly name their elements after what they are
+    let marked = value
+        .id()
+        .into_iter()
+        .chain(value.classes())
+        .map(str::to_lowercase)
+        .any(|name| CLUTTER_MARKERS.iter().any(|m| name.contains(m)));
+    if marked {
+        return true;
+    }
+
+    // Interstitials are nothing but a phrase like "Continue reading"
+    let text = normalize(&text);
+    if INTERSTITIAL_PHRASES.contains(&text.as_str()) {
+        return true;
+    }
+
+    // A short bit of text about cookies with a button to accept them is a consent banner. Requiring
+    // the button keeps articles that merely talk about cookies intact.
+    let controls = Selector::parse("button, a, input[type=button], input[type=submit]").unwrap();
+    text.contains("cookie")
+        && elem.select(&controls).any(|c| {
+            let label = c.value().attr("value").map(normalize);
+            let label = label.unwrap_or_else(|| normalize(&c.text().collect::<String>()));
+            ACCEPT_PHRASES.contains(&label.as_str())
+        })
+}
+
+#[test]
+fn clutter_stripping() {
+    let story = "<p>The ship sailed on, and nobody aboard knew where it was going.</p>".repeat(3);
+    let html = format!(
+        r#"<html><body>
+        <div id="onetrust-banner-sdk">We use cookies. <button>Accept all</button></div>
+        <div class="notice"><p>This site uses cookies to improve your experience.</p>
+            <a href="/settings">OK</a></div>
+        <article>{story}
+            <div class="promo"><a href="/more">Continue reading →</a></div>
+            <p>Baking cookies takes patience, and the recipe is best made ahead.</p>
+        </article>
+        </body></html>"#
+    );
+
+    let rules = ClutterRules::default();
+    let stripped = rules.strip("https://example.com/story", html.as_bytes());
+    let stripped = String::from_utf8(stripped).unwrap();
+    assert!(!stripped.contains("onetrust"));
+    assert!(!stripped.contains("This site uses cookies"));
+    assert!(!stripped.contains("Continue reading"));
+    assert!(stripped.contains("The ship sailed on"));
+    assert!(stripped.contains("Baking cookies"));
+
+    // A page without clutter is left as is
+    let clean = format!("<html><body><article>{story}</article></body></html>");
+    assert_eq!(
+        rules.strip("https://example.com/story", clean.as_bytes()),
+        clean.as_bytes()
+    );
+}
+
+#[test]
+fn clutter_overrides() {
+    let html = r#"<html><body>
+        <div class="cookie-jar">A story about a cookie jar. <button>OK</button></div>
+        <div class="subscribe">Subscribe for more</div>
+        <p>The rest of the story.</p>
+        </body></html>"#;
+    let rules = ClutterRules::new(vec![
+        ClutterOverride {
+            site: "example.com".to_string(),
+            heuristics: true,
+            strip: vec!["div.subscribe".to_string()],
+            keep: vec!["div.cookie-jar".to_string()],
+        },
+        ClutterOverride {
+            site: "other.org".to_string(),
+            heuristics: false,
+            strip: Vec::new(),
+            keep: Vec::new(),
+        },
+    ]);
+
+    // Overrides apply to subdomains too
+    let stripped = rules.strip("https://news.example.com/story", html.as_bytes());
+    let stripped = String::from_utf8(stripped).unwrap();
+    assert!(stripped.contains("cookie jar"));
+    assert!(!stripped.contains("Subscribe"));
+
+    // Sites without heuristics are left alone
+    let stripped = rules.strip("https://other.org/story", html.as_bytes());
+    assert_eq!(stripped, html.as_bytes());
+
+    // Only the site itself and its subdomains match
+    let stripped = rules.strip("https://notexample.com/story", html.as_bytes());
+    let stripped = String::from_utf8(stripped).unwrap();
+    assert!(!stripped.contains("cookie jar"));
+    assert!(stripped.contains("Subscribe"));
+}
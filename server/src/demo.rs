@@ -0,0 +1,64 @@
+//! Demo mode, for hosting a public instance that people can try the player and offline caching on
+//! before they self-host. Whatever is in the audio blob directory is the demo library, and it's
+//! read-only: nothing can add, change, or delete articles, and nothing in the background converts
+//! or sweeps them. The only thing that reaches the TTS engine is voice previews, which are shorter,
+//! and more tightly rate limited, than usual. There are no accounts, and nobody can manage the
+//! server.
+
+use axum::{
+    http::{Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+/// The longest voice preview, in characters
+pub(crate) const MAX_PREVIEW_CHARS: usize = 200;
+
+/// The most characters that can be synthesized per minute, across everyone trying the demo
+pub(crate) const MAX_CHARS_PER_MIN: u32 = 2_000;
+
+/// The routes that can be used to change things in demo mode. Previewing a voice doesn't change
+/// anything, but it has to be POSTed.
+const ALLOWED_WRITES: &[&str] = &["/api/preview-voice"];
+
+/// What's said to requests that would change something
+const READ_ONLY_MESSAGE: &str = "This is a demo, so the library can't be changed. Self-host \
+    ReadToMyShoe to add your own articles.";
+
+/// Returns whether the given request is allowed in demo mode, i.e., whether it only reads
+fn is_allowed(method: &Method, path: &str) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        || ALLOWED_WRITES.contains(&path)
+}
+
+// Turns away every request that would change something. This has to be set up after everything it
+// guards.
+pub(crate) fn setup(router: Router) -> Router {
+    router.layer(middleware::from_fn(read_only))
+}
+
+/// Turns away the request if it would change something
+async fn read_only<B>(req: Request<B>, next: Next<B>) -> Response {
+    if is_allowed(req.method(), req.uri().path()) {
+        next.run(req).await
+    } else {
+        (StatusCode::FORBIDDEN, READ_ONLY_MESSAGE).into_response()
+    }
+}
+
+#[test]
+fn demo_requests() {
+    assert!(is_allowed(&Method::GET, "/api/list-articles"));
+    assert!(is_allowed(&Method::HEAD, "/audio/fish-abc.mp3"));
+    assert!(is_allowed(&Method::POST, "/api/preview-voice"));
+    for path in [
+        "/api/add-article-by-url",
+        "/api/delete-articles",
+        "/api/sync",
+        "/api/signup",
+        "/mini",
+    ] {
+        assert!(!is_allowed(&Method::POST, path), "{path}");
+    }
+}
@@ -0,0 +1,883 @@
+//! Reads the text out of uploaded documents, for articles that aren't on the web, e.g., paywalled
+//! articles saved from the browser, or documents sent by email. The client uploads the file, gets
+//! back its text, and adds it by text like anything pasted in.
+//!
+//! These kinds of documents are read:
+//!
+//! * Plain text. Hard-wrapped lines are joined into paragraphs.
+//! * EPUB. Each chapter is its own section, so a chapter can be added on its own.
+//! * PDF, if it has a text layer in one of the standard Latin encodings. Scanned PDFs have no text
+//!   layer, and PDFs whose fonts need a `ToUnicode` table to be read aren't supported. Pages set in
+//!   columns are read a column at a time.
+//! * Word documents (.docx)
+//!
+//! EPUB and .docx files are ZIP archives, which are read with the reader in `archive`.
+
+use crate::{archive::Zip, extract::select_text};
+use common::{DocumentSection, ParsedDocument, SourceType};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{body::Bytes, extract::Query, http::StatusCode, routing::post, Json, Router};
+use flate2::read::ZlibDecoder;
+use serde::Deserialize;
+
+/// The largest document that's read, in bytes. This also limits how much a file in an archive can
+/// decompress to.
+const MAX_DOCUMENT_LEN: usize = 50 * 1024 * 1024;
+
+/// The PDF content stream operators that start a new line, a line's leading below the last
+const PDF_NEWLINE_OPERATORS: &[&str] = &["T*", "'", "\""];
+
+/// How far left a `TJ` adjustment has to move the next glyph, in thousandths of the font size, to
+/// count as a space between words
+const PDF_SPACE_ADJUSTMENT: f64 = -200.0;
+
+/// How far apart the left edges of columns are at least, in points. Moving further than this along
+/// a line is taken to be a jump to the next column, rather than a gap between words.
+const PDF_MIN_COLUMN_SPACING: f64 = 100.0;
+
+/// The fewest lines that have to start at the same place across the page for it to be the left
+/// edge of a column
+const PDF_MIN_COLUMN_LINES: usize = 4;
+
+/// How far right of its column's left edge a line can start and still be aligned with it, in
+/// points, e.g., when its paragraph is indented
+const PDF_INDENT_TOLERANCE: f64 = 24.0;
+
+/// Joins hard-wrapped lines into paragraphs, one per line. Paragraphs are separated by blank lines.
+/// If there are none, every line is taken to be a paragraph.
+fn join_wrapped_lines(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    if !lines.iter().any(|line| line.is_empty()) {
+        return lines.join("\n");
+    }
+    lines
+        .split(|line| line.is_empty())
+        .filter(|para| !para.is_empty())
+        .map(|para| para.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reads a plain text file
+fn parse_txt(bytes: &[u8], title: &str) -> Vec<DocumentSection> {
+    let text = String::from_utf8_lossy(bytes);
+    vec![DocumentSection {
+        title: title.to_string(),
+        body: join_wrapped_lines(text.trim_start_matches('\u{feff}')),
+    }]
+}
+
+/// Returns the text of the first element with the given local name in the given XML, if any
+fn xml_text(xml: &str, name: &str) -> Option<String> {
+    let doc = roxmltree::Document::parse(xml).ok()?;
+    let text = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == name)?
+        .text()?
+        .trim()
+        .to_string();
+    Some(text).filter(|t| !t.is_empty())
+}
+
+/// Reads a Word document. Every paragraph in the main document is kept, headings included.
+fn parse_docx(bytes: &[u8], title: &str) -> Result<Vec<DocumentSection>, AnyError> {
+    let mut zip = Zip::parse(bytes, MAX_DOCUMENT_LEN)?;
+    let xml = zip.read_string("word/document.xml")?;
+    let doc = roxmltree::Document::parse(&xml)
+        .map_err(|e| anyhow!("Couldn't parse the document's XML: {e}"))?;
+
+    let paragraphs = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "p")
+        .map(|p| {
+            p.descendants()
+                .filter_map(|n| match n.tag_name().name() {
+                    "t" => n.text(),
+                    "tab" | "br" => Some(" "),
+                    _ => None,
+                })
+                .collect::<String>()
+        })
+        .map(|para| para.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|para| !para.is_empty())
+        .collect::<Vec<_>>();
+
+    // The title in the document's properties is often left as whatever the template had, so it's
+    // only used if there is one
+    let title = zip
+        .read_string("docProps/core.xml")
+        .ok()
+        .and_then(|core| xml_text(&core, "title"))
+        .unwrap_or_else(|| title.to_string());
+    Ok(vec![DocumentSection {
+        title,
+        body: paragraphs.join("\n"),
+    }])
+}
+
+/// Resolves the given path, relative to the directory of the given file in an archive, into a
+/// path from the top of the archive
+fn archive_path(base_file: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = urlencoding::decode(href).map_or_else(|_| href.to_string(), |h| h.into_owned());
+    let mut path = Path::new(base_file)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    for part in href.split('/') {
+        match part {
+            ".." => {
+                path.pop();
+            }
+            "." | "" => (),
+            part => path.push(part),
+        }
+    }
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Reads an EPUB. Every document in the reading order with any text is a section, titled by its
+/// first heading.
+fn parse_epub(bytes: &[u8], title: &str) -> Result<Vec<DocumentSection>, AnyError> {
+    let mut zip = Zip::parse(bytes, MAX_DOCUMENT_LEN)?;
+
+    // The container says where the package document is, which lists the book's files
+    let container = zip.read_string("META-INF/container.xml")?;
+    let container = roxmltree::Document::parse(&container)
+        .map_err(|e| anyhow!("Couldn't parse the EPUB's container: {e}"))?;
+    let opf_path = container
+        .descendants()
+        .find(|n| n.tag_name().name() == "rootfile")
+        .and_then(|n| n.attribute("full-path"))
+        .ok_or_else(|| anyhow!("The EPUB doesn't say where its package document is"))?;
+    let opf = zip.read_string(opf_path)?;
+    let package = roxmltree::Document::parse(&opf)
+        .map_err(|e| anyhow!("Couldn't parse the EPUB's package document: {e}"))?;
+
+    let book_title = xml_text(&opf, "title").unwrap_or_else(|| title.to_string());
+    let manifest: HashMap<&str, &str> = package
+        .descendants()
+        .filter(|n| n.tag_name().name() == "item")
+        .filter_map(|n| Some((n.attribute("id")?, n.attribute("href")?)))
+        .collect();
+    let spine = package
+        .descendants()
+        .filter(|n| n.tag_name().name() == "itemref")
+        .filter_map(|n| manifest.get(n.attribute("idref")?));
+
+    let mut sections = Vec::new();
+    for href in spine {
+        let content = zip.read(&archive_path(opf_path, href))?;
+        let body = select_text(&content, "body").unwrap_or_default();
+        if body.trim().is_empty() {
+            continue;
+        }
+        let heading = select_text(&content, "h1, h2, h3")
+            .ok()
+            .and_then(|h| h.lines().next().map(str::to_string))
+            .filter(|h| !h.is_empty());
+        let title = match heading {
+            Some(heading) => format!("{book_title}: {heading}"),
+            None => format!("{book_title} ({})", sections.len() + 1),
+        };
+        sections.push(DocumentSection { title, body });
+    }
+    Ok(sections)
+}
+
+/// Returns the contents of every stream in the given PDF that looks like page content, in the
+/// order they're in the file. Streams that are fonts, images, or other objects are skipped.
+fn pdf_content_streams(pdf: &[u8]) -> Vec<Vec<u8>> {
+    let find = |haystack: &[u8], needle: &[u8], from: usize| {
+        haystack
+            .get(from..)?
+            .windows(needle.len())
+            .position(|w| w == needle)
+            .map(|i| i + from)
+    };
+
+    let mut streams = Vec::new();
+    let mut pos = 0;
+    while let Some(keyword) = find(pdf, b"stream", pos) {
+        pos = keyword + b"stream".len();
+        // Skip "endstream", and anything that isn't the start of a stream
+        if pdf[..keyword].ends_with(b"end") {
+            continue;
+        }
+        let Some(end) = find(pdf, b"endstream", pos) else {
+            break;
+        };
+        let dict_start = pdf[..keyword]
+            .windows(2)
+            .rposition(|w| w == b"<<")
+            .unwrap_or_default();
+        let dict = String::from_utf8_lossy(&pdf[dict_start..keyword]);
+
+        let mut data = &pdf[pos..end];
+        if data.starts_with(b"\r\n") {
+            data = &data[2..];
+        } else if data.starts_with(b"\n") {
+            data = &data[1..];
+        }
+        pos = end + b"endstream".len();
+
+        let is_content = ["/Type", "/Subtype", "/Length1", "/Length2"]
+            .iter()
+            .all(|key| !dict.contains(key));
+        if !is_content {
+            continue;
+        }
+        if dict.contains("/FlateDecode") {
+            let mut contents = Vec::new();
+            // Streams are often followed by a stray end-of-line, which the decoder can ignore
+            let _ = ZlibDecoder::new(data)
+                .take(MAX_DOCUMENT_LEN as u64)
+                .read_to_end(&mut contents);
+            streams.push(contents);
+        } else if !dict.contains("/Filter") {
+            streams.push(data.to_vec());
+        }
+    }
+    streams
+}
+
+/// A token in a PDF content stream
+#[derive(Debug, PartialEq)]
+enum PdfToken {
+    /// A string, literal or hex
+    Str(Vec<u8>),
+    Number(f64),
+    /// An operator, or anything else that isn't needed, like a name
+    Other(String),
+    ArrayStart,
+    ArrayEnd,
+}
+
+/// Splits the given PDF content stream into tokens
+fn pdf_tokens(content: &[u8]) -> Vec<PdfToken> {
+    let is_delim = |b: u8| b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < content.len() {
+        match content[i] {
+            b if b.is_ascii_whitespace() => i += 1,
+            b'%' => {
+                while i < content.len() && content[i] != b'\n' && content[i] != b'\r' {
+                    i += 1;
+                }
+            }
+            b'(' => {
+                let (s, end) = pdf_literal_string(content, i + 1);
+                tokens.push(PdfToken::Str(s));
+                i = end;
+            }
+            b'<' if content.get(i + 1) == Some(&b'<') => i += 2,
+            b'>' if content.get(i + 1) == Some(&b'>') => i += 2,
+            b'<' => {
+                let end = content[i..]
+                    .iter()
+                    .position(|&b| b == b'>')
+                    .map_or(content.len(), |p| i + p);
+                let mut hex: Vec<u8> = content[i + 1..end]
+                    .iter()
+                    .copied()
+                    .filter(u8::is_ascii_hexdigit)
+                    .collect();
+                if hex.len() % 2 == 1 {
+                    hex.push(b'0');
+                }
+                let s = hex
+                    .chunks(2)
+                    .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                    .collect();
+                tokens.push(PdfToken::Str(s));
+                i = end + 1;
+            }
+            b'[' => {
+                tokens.push(PdfToken::ArrayStart);
+                i += 1;
+            }
+            b']' => {
+                tokens.push(PdfToken::ArrayEnd);
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                i += 1;
+                while i < content.len() && !is_delim(content[i]) {
+                    i += 1;
+                }
+                let word = String::from_utf8_lossy(&content[start..i]).into_owned();
+                match word.parse() {
+                    Ok(n) => tokens.push(PdfToken::Number(n)),
+                    Err(_) => tokens.push(PdfToken::Other(word)),
+                }
+            }
+        }
+    }
+    tokens
+}
+
+/// Reads the PDF literal string that starts at the given offset, just after its opening
+/// parenthesis. Returns the string and the offset just after its closing parenthesis.
+fn pdf_literal_string(content: &[u8], start: usize) -> (Vec<u8>, usize) {
+    let mut s = Vec::new();
+    let mut depth = 0;
+    let mut i = start;
+    while i < content.len() {
+        let b = content[i];
+        i += 1;
+        match b {
+            b'\\' => {
+                let Some(&escaped) = content.get(i) else {
+                    break;
+                };
+                i += 1;
+                match escaped {
+                    b'n' => s.push(b'\n'),
+                    b'r' => s.push(b'\r'),
+                    b't' => s.push(b'\t'),
+                    b'b' => s.push(8),
+                    b'f' => s.push(12),
+                    b'0'..=b'7' => {
+                        let mut value = (escaped - b'0') as u32;
+                        for _ in 0..2 {
+                            match content.get(i) {
+                                Some(&d @ b'0'..=b'7') => {
+                                    value = value * 8 + (d - b'0') as u32;
+                                    i += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        s.push(value as u8);
+                    }
+                    // A backslash at the end of a line continues the string on the next
+                    b'\r' => {
+                        if content.get(i) == Some(&b'\n') {
+                            i += 1;
+                        }
+                    }
+                    b'\n' => (),
+                    other => s.push(other),
+                }
+            }
+            b'(' => {
+                depth += 1;
+                s.push(b);
+            }
+            b')' if depth == 0 => break,
+            b')' => {
+                depth -= 1;
+                s.push(b);
+            }
+            _ => s.push(b),
+        }
+    }
+    (s, i)
+}
+
+/// A line of text in a PDF, and where on the page it starts, in points from the bottom left
+#[derive(Debug, Default)]
+struct PdfLine {
+    x: f64,
+    y: f64,
+    text: String,
+}
+
+/// Ends the current line, keeping it if it has any text, and starts a new one at the given position
+fn start_pdf_line(lines: &mut Vec<PdfLine>, line: &mut PdfLine, x: f64, y: f64) {
+    let done = std::mem::replace(
+        line,
+        PdfLine {
+            x,
+            y,
+            text: String::new(),
+        },
+    );
+    if !done.text.trim().is_empty() {
+        lines.push(done);
+    }
+}
+
+/// Returns the lines of text shown by the given PDF content stream, in the order they're drawn.
+/// Strings are taken to be in the standard Latin encodings, which is close enough for most text.
+/// Positions only follow the text matrix, so pages whose coordinates are rotated or flipped, e.g.,
+/// by `cm`, come out in the order they're drawn.
+fn pdf_lines(content: &[u8]) -> Vec<PdfLine> {
+    let decode = |s: &[u8]| s.iter().map(|&b| b as char).collect::<String>();
+    let mut lines = Vec::new();
+    let mut line = PdfLine::default();
+    // Where the current line starts, and how the text matrix scales moves
+    let (mut line_x, mut line_y) = (0.0, 0.0);
+    let (mut scale_x, mut scale_y) = (1.0, 1.0);
+    let mut leading = 0.0;
+    let mut operands: Vec<PdfToken> = Vec::new();
+    let mut in_array = false;
+
+    for token in pdf_tokens(content) {
+        match token {
+            PdfToken::ArrayStart => in_array = true,
+            PdfToken::ArrayEnd => in_array = false,
+            // Names, like the font in `/F1 12 Tf`, are operands too
+            PdfToken::Other(op) if !in_array && !op.starts_with('/') => {
+                let numbers: Vec<f64> = operands
+                    .iter()
+                    .filter_map(|t| match t {
+                        PdfToken::Number(n) => Some(*n),
+                        _ => None,
+                    })
+                    .collect();
+
+                // Work out where the text moves to, if it moves
+                let moved_to = match (op.as_str(), numbers.as_slice()) {
+                    ("BT", _) => {
+                        (scale_x, scale_y) = (1.0, 1.0);
+                        Some((0.0, 0.0))
+                    }
+                    ("Tm", [.., a, _, _, d, e, f]) => {
+                        (scale_x, scale_y) = (*a, *d);
+                        Some((*e, *f))
+                    }
+                    ("Td" | "TD", [.., tx, ty]) => {
+                        if op == "TD" {
+                            leading = -ty;
+                        }
+                        Some((line_x + tx * scale_x, line_y + ty * scale_y))
+                    }
+                    ("TL", [.., tl]) => {
+                        leading = *tl;
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some((x, y)) = moved_to {
+                    // A move along the line is a gap between words, unless it's far enough to be
+                    // the next column
+                    let along_line = y == line_y && (x - line_x).abs() < PDF_MIN_COLUMN_SPACING;
+                    if along_line && !line.text.is_empty() {
+                        line.text.push(' ');
+                    } else {
+                        start_pdf_line(&mut lines, &mut line, x, y);
+                    }
+                    (line_x, line_y) = (x, y);
+                }
+                if PDF_NEWLINE_OPERATORS.contains(&op.as_str()) {
+                    line_y -= leading * scale_y;
+                    start_pdf_line(&mut lines, &mut line, line_x, line_y);
+                }
+                if op == "ET" {
+                    start_pdf_line(&mut lines, &mut line, line_x, line_y);
+                }
+
+                match op.as_str() {
+                    "Tj" | "'" | "\"" => {
+                        if let Some(PdfToken::Str(s)) = operands.last() {
+                            line.text.push_str(&decode(s));
+                        }
+                    }
+                    "TJ" => {
+                        for operand in &operands {
+                            match operand {
+                                PdfToken::Str(s) => line.text.push_str(&decode(s)),
+                                PdfToken::Number(n) if *n < PDF_SPACE_ADJUSTMENT => {
+                                    line.text.push(' ')
+                                }
+                                _ => (),
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+                operands.clear();
+            }
+            token => operands.push(token),
+        }
+    }
+    start_pdf_line(&mut lines, &mut line, 0.0, 0.0);
+    lines
+}
+
+/// Returns the left edges of the columns the given lines are set in, from left to right. These are
+/// the places where enough lines start, far enough apart.
+fn pdf_column_edges(lines: &[PdfLine]) -> Vec<f64> {
+    let mut starts: BTreeMap<i64, usize> = BTreeMap::new();
+    for line in lines {
+        *starts.entry(line.x.round() as i64).or_default() += 1;
+    }
+
+    let mut edges: Vec<f64> = Vec::new();
+    for (x, count) in starts {
+        let x = x as f64;
+        let far_enough = edges
+            .last()
+            .is_none_or(|&edge| x - edge >= PDF_MIN_COLUMN_SPACING);
+        if count >= PDF_MIN_COLUMN_LINES && far_enough {
+            edges.push(x);
+        }
+    }
+    edges
+}
+
+/// Puts the given lines of a PDF page in reading order. Pages set in columns often draw their lines
+/// across the page, so the columns come out interleaved. If the lines are aligned along more than
+/// one left edge, and the columns they make are side by side, they're read a column at a time, top
+/// to bottom. Anything else is left in the order it's drawn in, since that's usually right.
+fn pdf_reading_order(lines: Vec<PdfLine>) -> Vec<PdfLine> {
+    let edges = pdf_column_edges(&lines);
+    if edges.len() < 2 {
+        return lines;
+    }
+
+    // Every line goes in the rightmost column that starts left of it. Rounding can put a line just
+    // left of its column's edge.
+    let column_of = |line: &PdfLine| {
+        edges
+            .iter()
+            .rposition(|&edge| line.x.round() >= edge)
+            .unwrap_or_default()
+    };
+    let columns: Vec<usize> = lines.iter().map(column_of).collect();
+
+    // Make sure these are columns, and not, e.g., words placed one at a time: most lines in a
+    // column are aligned with its edge, and each column is beside the one before it
+    let mut ranges = Vec::new();
+    for (i, &edge) in edges.iter().enumerate() {
+        let in_column: Vec<&PdfLine> = lines
+            .iter()
+            .zip(&columns)
+            .filter(|(_, &c)| c == i)
+            .map(|(line, _)| line)
+            .collect();
+        let aligned = in_column
+            .iter()
+            .filter(|line| line.x - edge <= PDF_INDENT_TOLERANCE)
+            .count();
+        if aligned * 2 < in_column.len() {
+            return lines;
+        }
+        let top = in_column.iter().map(|l| l.y).fold(f64::MIN, f64::max);
+        let bottom = in_column.iter().map(|l| l.y).fold(f64::MAX, f64::min);
+        ranges.push((bottom, top));
+    }
+    let side_by_side = ranges
+        .windows(2)
+        .all(|pair| pair[0].0 <= pair[1].1 && pair[1].0 <= pair[0].1);
+    if !side_by_side {
+        return lines;
+    }
+
+    let mut ordered: Vec<(usize, PdfLine)> = columns.into_iter().zip(lines).collect();
+    ordered.sort_by(|(a_col, a), (b_col, b)| a_col.cmp(b_col).then(b.y.total_cmp(&a.y)));
+    ordered.into_iter().map(|(_, line)| line).collect()
+}
+
+/// Returns the text shown by the given PDF content stream, one line per line of text, in reading
+/// order
+fn pdf_text(content: &[u8]) -> String {
+    pdf_reading_order(pdf_lines(content))
+        .into_iter()
+        .map(|line| line.text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Joins the lines of text from a PDF into paragraphs, one per line. A line is taken to end a
+/// paragraph if it ends a sentence. Words broken across lines with a hyphen are put back together.
+fn join_pdf_lines(text: &str) -> String {
+    let mut paragraphs = Vec::new();
+    let mut para = String::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            continue;
+        }
+        if para.ends_with('-') {
+            para.pop();
+        } else if !para.is_empty() {
+            para.push(' ');
+        }
+        para.push_str(&line);
+        if line.ends_with(['.', '!', '?', ':', '"', '”']) {
+            paragraphs.push(std::mem::take(&mut para));
+        }
+    }
+    if !para.is_empty() {
+        paragraphs.push(para);
+    }
+    paragraphs.join("\n")
+}
+
+/// Reads the text layer of a PDF
+fn parse_pdf(bytes: &[u8], title: &str) -> Result<Vec<DocumentSection>, AnyError> {
+    if !bytes.starts_with(b"%PDF") {
+        bail!("It isn't a PDF");
+    }
+    let text: String = pdf_content_streams(bytes)
+        .iter()
+        .map(|content| pdf_text(content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let body = join_pdf_lines(&text);
+
+    // Text in fonts this can't decode comes out as gibberish, mostly not letters
+    let chars = body.chars().filter(|c| !c.is_whitespace());
+    let (letters, total) = chars.fold((0, 0), |(letters, total), c| {
+        (letters + c.is_alphanumeric() as usize, total + 1)
+    });
+    if total == 0 || letters * 2 < total {
+        bail!(
+            "The PDF has no text that can be read. It may be scanned, or its fonts may not be \
+             supported."
+        );
+    }
+
+    Ok(vec![DocumentSection {
+        title: title.to_string(),
+        body,
+    }])
+}
+
+/// Reads the given document, which has the given filename. The type of document is told by the
+/// filename's extension.
+fn parse_document(filename: &str, bytes: &[u8]) -> Result<ParsedDocument, AnyError> {
+    let path = PathBuf::from(filename);
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let (source_type, sections) = match extension.as_str() {
+        "txt" | "text" | "md" => (SourceType::Txt, parse_txt(bytes, &title)),
+        "epub" => (SourceType::Epub, parse_epub(bytes, &title)?),
+        "pdf" => (SourceType::Pdf, parse_pdf(bytes, &title)?),
+        "docx" => (SourceType::Docx, parse_docx(bytes, &title)?),
+        _ => bail!("Only .txt, .epub, .pdf, and .docx files can be read"),
+    };
+
+    let sections: Vec<_> = sections
+        .into_iter()
+        .filter(|s| !s.body.trim().is_empty())
+        .collect();
+    if sections.is_empty() {
+        bail!("There's no text in {filename}");
+    }
+    Ok(ParsedDocument {
+        source_type,
+        sections,
+    })
+}
+
+#[derive(Deserialize)]
+struct DocumentQuery {
+    filename: String,
+}
+
+// Sets the /api/parse-document route
+pub(crate) fn setup(router: Router) -> Router {
+    router.nest(
+        "/api",
+        Router::new().route("/parse-document", post(parse_document_endpoint)),
+    )
+}
+
+/// Returns the text of the uploaded document
+async fn parse_document_endpoint(
+    Query(DocumentQuery { filename }): Query<DocumentQuery>,
+    bytes: Bytes,
+) -> Result<Json<ParsedDocument>, (StatusCode, String)> {
+    if bytes.len() > MAX_DOCUMENT_LEN {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Documents can be at most {} MiB", MAX_DOCUMENT_LEN >> 20),
+        ));
+    }
+    tracing::debug!("Reading uploaded document {filename:?}");
+    parse_document(&filename, &bytes).map(Json).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Couldn't read {filename}: {e:#}"),
+        )
+    })
+}
+
+#[test]
+fn reading_documents() {
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    // Makes a ZIP archive of the given files
+    let zip = |files: &[(&str, &str)]| {
+        let mut writer = crate::archive::ZipWriter::default();
+        for (name, contents) in files {
+            writer.add(name, contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap()
+    };
+
+    // Plain text is unwrapped
+    let doc = parse_document(
+        "notes.txt",
+        b"A line that's\nwrapped.\n\nAnother paragraph.\n",
+    )
+    .unwrap();
+    assert_eq!(doc.source_type, SourceType::Txt);
+    assert_eq!(doc.sections[0].title, "notes");
+    assert_eq!(
+        doc.sections[0].body,
+        "A line that's wrapped.\nAnother paragraph."
+    );
+
+    // Word documents keep their paragraphs, and their title if they have one
+    let docx = zip(&[
+        (
+            "word/document.xml",
+            r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+                <w:body>
+                    <w:p><w:r><w:t>Fish</w:t></w:r><w:r><w:t xml:space="preserve"> and chips</w:t></w:r></w:p>
+                    <w:p><w:r><w:t>Second</w:t><w:tab/><w:t>paragraph</w:t></w:r></w:p>
+                </w:body>
+            </w:document>"#,
+        ),
+        (
+            "docProps/core.xml",
+            r#"<cp:coreProperties xmlns:cp="cp" xmlns:dc="http://purl.org/dc/elements/1.1/">
+                <dc:title>Fish Report</dc:title>
+            </cp:coreProperties>"#,
+        ),
+    ]);
+    let doc = parse_document("report.docx", &docx).unwrap();
+    assert_eq!(doc.source_type, SourceType::Docx);
+    assert_eq!(doc.sections[0].title, "Fish Report");
+    assert_eq!(doc.sections[0].body, "Fish and chips\nSecond paragraph");
+
+    // Each EPUB chapter with text is a section, in the reading order
+    let epub = zip(&[
+        (
+            "META-INF/container.xml",
+            r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#,
+        ),
+        (
+            "OEBPS/content.opf",
+            r#"<package xmlns:dc="http://purl.org/dc/elements/1.1/">
+                <metadata><dc:title>Sea Tales</dc:title></metadata>
+                <manifest>
+                    <item id="c2" href="text/chapter%202.xhtml"/>
+                    <item id="c1" href="text/one.xhtml"/>
+                    <item id="cover" href="cover.xhtml"/>
+                </manifest>
+                <spine><itemref idref="cover"/><itemref idref="c1"/><itemref idref="c2"/></spine>
+            </package>"#,
+        ),
+        (
+            "OEBPS/cover.xhtml",
+            "<html><body><img src='c.jpg'/></body></html>",
+        ),
+        (
+            "OEBPS/text/one.xhtml",
+            "<html><body><h1>The Cod</h1><p>It swam.</p></body></html>",
+        ),
+        (
+            "OEBPS/text/chapter 2.xhtml",
+            "<html><body><p>It swam on.</p></body></html>",
+        ),
+    ]);
+    let doc = parse_document("tales.epub", &epub).unwrap();
+    assert_eq!(doc.source_type, SourceType::Epub);
+    let titles: Vec<_> = doc.sections.iter().map(|s| s.title.as_str()).collect();
+    assert_eq!(titles, ["Sea Tales: The Cod", "Sea Tales (2)"]);
+    assert_eq!(doc.sections[0].body, "The Cod\nIt swam.");
+
+    // PDF text comes out of compressed content streams, and its lines are joined into paragraphs
+    let content = b"BT /F1 12 Tf 72 700 Td (The fish were fresh and the chips) Tj \
+        0 -14 Td [(were hot, so every) -300 (body ate well.)] TJ \
+        0 -14 Td (Then they went home.) Tj ET";
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let mut pdf = b"%PDF-1.4\n1 0 obj\n<< /Type /Font /Subtype /Type1 >>\nendobj\n".to_vec();
+    pdf.extend(
+        format!(
+            "4 0 obj\n<< /Length {} /Filter /FlateDecode >>\nstream\n",
+            compressed.len()
+        )
+        .as_bytes(),
+    );
+    pdf.extend(compressed);
+    pdf.extend(b"\nendstream\nendobj\n%%EOF\n");
+    let doc = parse_document("Fish.PDF", &pdf).unwrap();
+    assert_eq!(doc.source_type, SourceType::Pdf);
+    assert_eq!(
+        doc.sections[0].body,
+        "The fish were fresh and the chips were hot, so every body ate well.\nThen they went home."
+    );
+    assert_eq!(
+        pdf_tokens(br"(a\(b\) \101\nc)"),
+        vec![PdfToken::Str(b"a(b) A\nc".to_vec())]
+    );
+
+    // Anything else is turned away
+    assert!(parse_document("song.mp3", b"ID3").is_err());
+    assert!(parse_document("empty.txt", b"\n\n").is_err());
+    assert!(parse_document("fake.pdf", b"%PDF-1.4\n%%EOF").is_err());
+}
+
+#[test]
+fn reading_pdf_columns() {
+    // Two columns drawn across the page, a line of each at a time, under a heading
+    let mut content = b"BT /F1 14 Tf 1 0 0 1 72 740 Tm (Fish News) Tj /F1 10 Tf".to_vec();
+    let left = ["Cod were", "seen off", "the coast", "in May."];
+    let right = ["Haddock", "were not", "seen at", "all."];
+    for (i, (l, r)) in left.iter().zip(right).enumerate() {
+        let y = 700 - 12 * i;
+        content.extend(format!(" 1 0 0 1 72 {y} Tm ({l}) Tj 1 0 0 1 320 {y} Tm ({r}) Tj").bytes());
+    }
+    content.extend(b" ET");
+    assert_eq!(
+        join_pdf_lines(&pdf_text(&content)),
+        "Fish News Cod were seen off the coast in May.\nHaddock were not seen at all."
+    );
+
+    // The same with relative moves, jumping across to the right column and back
+    let mut content = b"BT 72 700 Td".to_vec();
+    for (l, r) in left.iter().zip(right) {
+        content.extend(format!(" ({l}) Tj 248 0 Td ({r}) Tj -248 -12 Td").bytes());
+    }
+    content.extend(b" ET");
+    assert_eq!(
+        join_pdf_lines(&pdf_text(&content)),
+        "Cod were seen off the coast in May.\nHaddock were not seen at all."
+    );
+
+    // One column with indented paragraphs, and words moved along the line, is left as it is
+    let content = b"BT 90 700 Td (One) Tj 30 0 Td (two.) Tj -18 -12 Td (Three) Tj \
+        0 -12 Td (four.) Tj 18 -12 Td (Five) Tj -18 -12 Td (six.) Tj 0 -12 Td (Seven.) Tj ET";
+    assert_eq!(
+        pdf_text(content),
+        "One two.\nThree\nfour.\nFive\nsix.\nSeven."
+    );
+
+    // So are lines that start in two places but aren't side by side, e.g., a pull quote below
+    let mut content = b"BT".to_vec();
+    for i in 0..4 {
+        content.extend(format!(" 1 0 0 1 72 {} Tm (Top {i}) Tj", 700 - 12 * i).bytes());
+    }
+    for i in 0..4 {
+        content.extend(format!(" 1 0 0 1 200 {} Tm (Bottom {i}) Tj", 600 - 12 * i).bytes());
+    }
+    content.extend(b" ET");
+    let text = pdf_text(&content);
+    assert!(text.starts_with("Top 0\nTop 1"));
+    assert!(text.ends_with("Bottom 2\nBottom 3"));
+}
@@ -0,0 +1,331 @@
+//! The encrypted library, for listeners who'd rather the server not keep what they listen to. The
+//! server synthesizes such an article's text like any other, but doesn't keep the text, the audio,
+//! or a job for it. The device that added it encrypts the audio and the article's metadata with a
+//! key that never leaves the device, and uploads the result, which is all the server keeps. Nothing
+//! in it can be searched, synced, or played on another device.
+//!
+//! The server sees the text while it's synthesizing it, and so does the TTS engine. It's only
+//! what's kept afterwards that's encrypted.
+//!
+//! An uploaded article is the length of its encrypted metadata as a big-endian `u32`, then the
+//! encrypted metadata, then the encrypted audio. The server only reads the lengths, so it can list
+//! the articles' metadata without sending their audio. The articles are kept in the `encrypted`
+//! directory of their library.
+
+use crate::{
+    accounts::LibraryDir,
+    ambient::AmbientBeds,
+    config::AmbientBed,
+    frontmatter,
+    normalize::normalize,
+    rate_limit::RateLimiter,
+    reading_profile,
+    voices::{voice_key, VoiceRegistry},
+};
+use common::{ArticleDeletion, ArticleTextSubmission, EncryptedArticleInfo, MAX_ARTICLE_LEN};
+use tts_pipeline::{tts, TtsRequest};
+
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path as UrlPath},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use openssl::rand::rand_bytes;
+
+/// The largest encrypted article that can be uploaded, in bytes
+const MAX_ENCRYPTED_ARTICLE_LEN: usize = 200 * 1024 * 1024;
+
+/// The directory, in a library's directory, that the encrypted articles are kept in
+const ENCRYPTED_DIRNAME: &str = "encrypted";
+
+/// Returns the directory the given library's encrypted articles are kept in
+fn encrypted_dir(library_dir: &str) -> PathBuf {
+    Path::new(library_dir).join(ENCRYPTED_DIRNAME)
+}
+
+/// Returns the path of the given encrypted article, or an error if the ID isn't one the server
+/// would have given out
+fn article_path(library_dir: &str, id: &str) -> Result<PathBuf, AnyError> {
+    if id.len() != 32 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("invalid encrypted article ID {id:?}");
+    }
+    Ok(encrypted_dir(library_dir).join(format!("{id}.bin")))
+}
+
+/// Returns the length of the encrypted metadata at the start of the given upload, or an error if
+/// the upload is too short to have it
+fn meta_len(upload: &[u8]) -> Result<usize, AnyError> {
+    let len = match upload.get(..4) {
+        Some(b) => u32::from_be_bytes(b.try_into()?) as usize,
+        None => bail!("encrypted article is truncated"),
+    };
+    if upload.len() < 4 + len {
+        bail!("encrypted article is truncated");
+    }
+    Ok(len)
+}
+
+/// Returns the info of the encrypted article at the given path
+fn read_info(path: &Path) -> Result<EncryptedArticleInfo, AnyError> {
+    let id = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow!("bad encrypted article filename {:?}", path))?
+        .to_string();
+    let mut file = File::open(path).map_err(|e| anyhow!("Could not open {:?}: {e}", path))?;
+    let file_meta = file.metadata()?;
+    let uploaded = file_meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    // Only read as far as the end of the metadata
+    let mut len_bytes = [0u8; 4];
+    file.read_exact(&mut len_bytes)?;
+    let mut meta = vec![0u8; u32::from_be_bytes(len_bytes) as usize];
+    file.read_exact(&mut meta)
+        .map_err(|e| anyhow!("Could not read metadata of {:?}: {e}", path))?;
+
+    Ok(EncryptedArticleInfo {
+        id,
+        meta,
+        uploaded,
+        size: file_meta.len(),
+    })
+}
+
+/// Saves the given upload to the given library, and returns its info
+fn save_article(library_dir: &str, upload: &[u8]) -> Result<EncryptedArticleInfo, AnyError> {
+    meta_len(upload)?;
+
+    let mut id_bytes = [0u8; 16];
+    rand_bytes(&mut id_bytes)?;
+    let id: String = id_bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+    let dir = encrypted_dir(library_dir);
+    fs::create_dir_all(&dir).map_err(|e| anyhow!("Could not create {:?}: {e}", dir))?;
+    let path = article_path(library_dir, &id)?;
+    fs::write(&path, upload).map_err(|e| anyhow!("Could not save {:?}: {e}", path))?;
+    read_info(&path)
+}
+
+/// Lists the encrypted articles in the given library, newest first
+fn list_articles(library_dir: &str) -> Result<Vec<EncryptedArticleInfo>, AnyError> {
+    let dir = encrypted_dir(library_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut infos = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("bin") {
+            continue;
+        }
+        match read_info(&path) {
+            Ok(info) => infos.push(info),
+            Err(e) => tracing::error!("Skipping encrypted article: {e}"),
+        }
+    }
+    infos.sort_by(|a, b| b.uploaded.cmp(&a.uploaded).then(a.id.cmp(&b.id)));
+    Ok(infos)
+}
+
+// Sets the /api/synthesize-private, /api/list-encrypted-articles, /api/upload-encrypted-article,
+// /api/encrypted-article, and /api/delete-encrypted-articles routes
+pub(crate) fn setup(
+    router: Router,
+    tts_rate_limiter: RateLimiter,
+    voice_registry: VoiceRegistry,
+    ambient_beds: AmbientBeds,
+    audio_blob_dir: &str,
+) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/synthesize-private", post(synthesize_private))
+            .route("/list-encrypted-articles", get(list_encrypted_articles))
+            .route("/upload-encrypted-article", post(upload_encrypted_article))
+            .route("/encrypted-article/:id", get(get_encrypted_article))
+            .route(
+                "/delete-encrypted-articles",
+                post(delete_encrypted_articles),
+            )
+            .layer(Extension(tts_rate_limiter))
+            .layer(Extension(voice_registry))
+            .layer(Extension(ambient_beds))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Logs the given error and turns it into a response with the given status
+fn error_response(status: StatusCode, e: AnyError) -> (StatusCode, String) {
+    tracing::error!("{e}");
+    (status, e.to_string())
+}
+
+/// Synthesizes the given article and returns the MP3, without keeping anything. Articles that are
+/// too long to synthesize as one aren't split up, since the parts couldn't be finished later.
+async fn synthesize_private(
+    headers: HeaderMap,
+    Json(mut article): Json<ArticleTextSubmission>,
+    Extension(tts_rate_limiter): Extension<RateLimiter>,
+    Extension(voice_registry): Extension<VoiceRegistry>,
+    Extension(ambient_beds): Extension<AmbientBeds>,
+) -> Result<Response, (StatusCode, String)> {
+    // The title isn't logged, since that's the kind of thing this is for not keeping
+    tracing::debug!("Synthesizing a private article");
+    article.body = normalize(&article.body);
+    let bad_request = |e| error_response(StatusCode::BAD_REQUEST, e);
+
+    if article.body.len() > MAX_ARTICLE_LEN {
+        return Err(error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            anyhow!("The article is too long to encrypt. Add it in parts."),
+        ));
+    }
+    let voice = match &article.voice {
+        Some(name) => voice_registry
+            .resolve(name, voice_key(&headers))
+            .map_err(bad_request)?,
+        None => {
+            let language = reading_profile::analyze(&article.body).language;
+            voice_registry.voice_for_language(language.as_deref())
+        }
+    };
+    let ambient_bed = ambient_beds
+        .resolve(article.options.ambient_bed.as_deref())
+        .map_err(bad_request)?;
+    // Nothing's known about where the article came from besides what was submitted
+    let text = frontmatter::narrated_text(&article, &Default::default());
+    tts_rate_limiter
+        .check(&text)
+        .map_err(|e| error_response(StatusCode::TOO_MANY_REQUESTS, e))?;
+
+    let req = TtsRequest {
+        text,
+        voice,
+        style: article.style,
+        options: voice_registry.with_default_encoding(article.options),
+    };
+    let output = tts(
+        voice_registry.engine(),
+        req,
+        ambient_bed.as_ref().map(AmbientBed::mix),
+        false,
+        None,
+        None,
+    )
+    .await
+    .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e.context("TTS failed")))?;
+
+    // What's left of a partial synthesis couldn't be finished later
+    if let Some((_, e)) = output.unfinished {
+        return Err(error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.context("TTS failed partway through"),
+        ));
+    }
+
+    Ok(([(header::CONTENT_TYPE, "audio/mpeg")], output.mp3).into_response())
+}
+
+/// Lists the encrypted articles in the library
+async fn list_encrypted_articles(
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<Vec<EncryptedArticleInfo>>, (StatusCode, String)> {
+    list_articles(&library_dir)
+        .map(Json)
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Saves an encrypted article to the library, and returns its info
+async fn upload_encrypted_article(
+    LibraryDir(library_dir): LibraryDir,
+    body: Bytes,
+) -> Result<Json<EncryptedArticleInfo>, (StatusCode, String)> {
+    if body.len() > MAX_ENCRYPTED_ARTICLE_LEN {
+        return Err(error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            anyhow!("Encrypted articles can be at most {MAX_ENCRYPTED_ARTICLE_LEN} bytes"),
+        ));
+    }
+    meta_len(&body).map_err(|e| error_response(StatusCode::BAD_REQUEST, e))?;
+
+    save_article(&library_dir, &body)
+        .map(Json)
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Returns the given encrypted article, as it was uploaded
+async fn get_encrypted_article(
+    UrlPath(id): UrlPath<String>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Response, (StatusCode, String)> {
+    let path =
+        article_path(&library_dir, &id).map_err(|e| error_response(StatusCode::BAD_REQUEST, e))?;
+    let bytes = fs::read(&path)
+        .map_err(|_| error_response(StatusCode::NOT_FOUND, anyhow!("No encrypted article {id}")))?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response())
+}
+
+/// Deletes the given encrypted articles from the library
+async fn delete_encrypted_articles(
+    LibraryDir(library_dir): LibraryDir,
+    Json(ArticleDeletion { ids }): Json<ArticleDeletion>,
+) -> Result<(), (StatusCode, String)> {
+    for id in ids {
+        let path = article_path(&library_dir, &id)
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, e))?;
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| {
+                error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Could not delete {:?}: {e}", path),
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn encrypted_articles() {
+    let dir = std::env::temp_dir().join(format!("rtms-encrypted-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let dir = dir.to_str().unwrap();
+
+    // A library without encrypted articles has nothing to list
+    assert!(list_articles(dir).unwrap().is_empty());
+
+    // Uploads have to have their metadata
+    assert!(save_article(dir, &[0, 0]).is_err());
+    assert!(save_article(dir, &[0, 0, 0, 5, 1, 2]).is_err());
+
+    // The metadata is listed on its own, and the whole upload comes back as it was
+    let upload = [&[0, 0, 0, 3][..], b"abc", b"audio"].concat();
+    let info = save_article(dir, &upload).unwrap();
+    assert_eq!(info.meta, b"abc");
+    assert_eq!(info.size, upload.len() as u64);
+    assert_eq!(list_articles(dir).unwrap(), vec![info.clone()]);
+    let path = article_path(dir, &info.id).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), upload);
+
+    // IDs can't point anywhere else
+    assert!(article_path(dir, "../../etc/passwd").is_err());
+    assert!(article_path(dir, &"a".repeat(31)).is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,57 @@
+//! Sets up the TTS engine the config file asks for. The engines themselves are in the
+//! `tts-pipeline` crate.
+
+use crate::config::EngineConfig;
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Error as AnyError};
+use tts_pipeline::engines::{AzureEngine, GoogleEngine, LocalEngine, PollyEngine, TtsEngine};
+
+/// The engine the server was configured with
+pub(crate) type SharedEngine = Arc<dyn TtsEngine>;
+
+/// Reads the secret in the given file, e.g., an API key
+fn read_secret(path: &Path) -> Result<String, AnyError> {
+    std::fs::read_to_string(path)
+        .map(|s| s.trim().to_string())
+        .map_err(|e| {
+            anyhow!(
+                "Could not open key file {:?}. \
+                Read the README for info about how to get a key. {:?}",
+                path,
+                e,
+            )
+        })
+}
+
+/// Sets up the engine the config file asks for. Any keys the engine needs are read now, so a
+/// missing key is caught at startup.
+pub(crate) fn from_config(config: &EngineConfig) -> Result<SharedEngine, AnyError> {
+    let engine: SharedEngine = match config {
+        EngineConfig::Google { api_key_file } => {
+            Arc::new(GoogleEngine::new(read_secret(api_key_file)?))
+        }
+        EngineConfig::Azure { region, key_file } => {
+            Arc::new(AzureEngine::new(region, read_secret(key_file)?))
+        }
+        EngineConfig::Polly {
+            region,
+            access_key_id,
+            secret_key_file,
+            neural,
+        } => Arc::new(PollyEngine::new(
+            region,
+            access_key_id,
+            read_secret(secret_key_file)?,
+            *neural,
+        )),
+        EngineConfig::Local {
+            command,
+            args,
+            sample_rate,
+            voice,
+        } => Arc::new(LocalEngine::new(command, args, *sample_rate, voice)),
+    };
+    Ok(engine)
+}
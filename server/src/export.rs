@@ -0,0 +1,1236 @@
+//! Exports the library to a directory, for listening on plain MP3 players or syncing with, e.g.,
+//! Syncthing. Every article's MP3 is copied with tags that make sense to a music player, and the
+//! directory gets an `index.json` of the articles and a `feed.xml` podcast feed that points at the
+//! copies. Running the export again only copies the articles that are new.
+//!
+//! The library can also be downloaded as a tar archive, for backups and moving to another server.
+//! The archive holds the library's own files, i.e., every finished article's MP3 with its library
+//! tags, its transcript, and its lead image, plus a `manifest.json` of the articles in it.
+//! Importing the archive puts the articles the library doesn't already have back in it. Single
+//! articles can be downloaded too, as MP3s with the same filenames and tags as the directory export,
+//! or as their cleaned-up text in Markdown or HTML, for quoting.
+//!
+//! When the archive is too big to keep around, the library's catalog can be exported instead, as
+//! JSON or CSV. It lists every article's URL, title, playlists, and how far it's been listened to,
+//! and importing it with the other readers' exports in `import.rs` converts the articles again.
+//!
+//! For taking everything elsewhere, or just seeing what's kept, there's a takeout too. It's a tar
+//! archive of every file in the library's directory, i.e., the audio, transcripts, and images, but
+//! also the listening progress, ratings, playlists, and the rest of what's synced, plus an
+//! `account.json` of the account it's from. What the server keeps about the library elsewhere, e.g.,
+//! its shared links, reminders, inbox, and feeds, is in `stores/`. It's for keeping, not importing.
+//! Transcoded copies of the audio are left out, since they're made again when they're needed.
+
+use crate::{
+    accounts::{CurrentAccount, LibraryDir},
+    admin::LibraryStores,
+    csv, images,
+    list_articles::{self, LibraryCache},
+    pending,
+    podcast::{self, Episode},
+    search::SearchIndex,
+    sync, transcript,
+    util::{get_metadata, now_secs},
+};
+use common::{
+    AccountStatus, ArticleMetadata, CatalogFormat, LibraryImportSummary, Rating, SyncState,
+    TextExportFormat,
+};
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Error as AnyError};
+use axum::{
+    body::StreamBody,
+    extract::{BodyStream, Extension, Path as UrlPath, Query},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use futures::StreamExt;
+use id3::{Tag, TagLike, Version};
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// The characters that can't be in a filename on FAT filesystems, which most MP3 players use
+const FORBIDDEN_FILENAME_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// The version of the archive format. Archives of a later version than this can't be imported.
+const ARCHIVE_VERSION: u32 = 1;
+
+/// The name of the manifest in an archive
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// The size of the chunks an archive is streamed in, in bytes
+const ARCHIVE_CHUNK_LEN: usize = 64 * 1024;
+
+/// The number of chunks of an archive that can be waiting to be sent
+const ARCHIVE_CHANNEL_LEN: usize = 16;
+
+/// The name of the account's details in a takeout
+const TAKEOUT_ACCOUNT_NAME: &str = "account.json";
+
+/// The directory of a takeout that the library's files are put in
+const TAKEOUT_LIBRARY_DIR: &str = "library";
+
+/// The directory of a takeout that what the other stores keep about the library is put in
+const TAKEOUT_STORES_DIR: &str = "stores";
+
+/// The directories of a library that are left out of a takeout: the other accounts' libraries,
+/// which are in the shared one's, and the transcoded copies of the audio
+const TAKEOUT_SKIPPED_DIRS: &[&str] = &["users", "transcoded"];
+
+/// The version of the catalog format
+const CATALOG_VERSION: u32 = 1;
+
+/// The columns of a CSV catalog, in order
+const CATALOG_COLUMNS: [&str; 9] = [
+    "url",
+    "title",
+    "datetime_added",
+    "author",
+    "publication",
+    "rating",
+    "tags",
+    "elapsed_secs",
+    "finished",
+];
+
+/// The list of the articles in an archive
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    /// The version of the archive format
+    version: u32,
+    /// When the archive was made, in seconds since the Unix epoch
+    exported: u64,
+    /// The articles in the archive. The files of each are named for its ID.
+    articles: Vec<ArticleMetadata>,
+}
+
+/// The account a takeout is from
+#[derive(Serialize)]
+struct TakeoutAccount {
+    /// When the takeout was made, in seconds since the Unix epoch
+    exported: u64,
+    #[serde(flatten)]
+    account: AccountStatus,
+}
+
+/// An article in `index.json`
+#[derive(Serialize)]
+struct ExportedArticle<'a> {
+    /// The name of the article's MP3 in the export
+    file: &'a str,
+    #[serde(flatten)]
+    meta: &'a ArticleMetadata,
+}
+
+/// The catalog of the library, i.e., what's needed to rebuild it without the audio
+#[derive(Serialize)]
+struct Catalog {
+    /// The version of the catalog format
+    version: u32,
+    /// When the catalog was made, in seconds since the Unix epoch
+    exported: u64,
+    articles: Vec<CatalogEntry>,
+}
+
+/// An article in the catalog
+#[derive(Debug, PartialEq, Serialize)]
+struct CatalogEntry {
+    /// The URL the article was added from. Articles added as text don't have one, and can't be
+    /// imported again.
+    url: Option<String>,
+    title: String,
+    /// When the article was added, in seconds since the Unix epoch
+    datetime_added: Option<u64>,
+    author: Option<String>,
+    publication: Option<String>,
+    rating: Option<Rating>,
+    /// The names of the playlists the article is in
+    tags: Vec<String>,
+    /// How far into the article the listener got, in seconds, if they started it
+    elapsed_secs: Option<f64>,
+    /// Whether the article was listened to the end
+    finished: bool,
+}
+
+/// Returns the catalog entries of the given articles, with their playlists and listening progress
+/// from the given sync state. The parts of a split-up article are listed once, since importing the
+/// article splits it up again.
+fn catalog_entries(catalog: &[ArticleMetadata], sync_state: &SyncState) -> Vec<CatalogEntry> {
+    let mut seen_urls = HashSet::new();
+    catalog
+        .iter()
+        .filter(|meta| {
+            meta.source_url
+                .as_ref()
+                .is_none_or(|url| seen_urls.insert(url.clone()))
+        })
+        .map(|meta| {
+            let position = sync_state.positions.iter().find(|pos| pos.id == meta.id);
+            let tags = sync_state
+                .playlists
+                .iter()
+                .filter(|p| !p.deleted && p.entries.iter().any(|entry| entry.id == meta.id))
+                .map(|p| p.name.clone())
+                .collect();
+            CatalogEntry {
+                url: meta.source_url.clone(),
+                title: meta.title.clone(),
+                datetime_added: meta.datetime_added,
+                author: meta.author.clone(),
+                publication: meta.publication.clone(),
+                rating: meta.rating,
+                tags,
+                elapsed_secs: position.map(|pos| pos.elapsed),
+                finished: position.is_some_and(|pos| pos.finished),
+            }
+        })
+        .collect()
+}
+
+/// Returns the given catalog entries in the given format. In CSV, tags are separated by `|`, like
+/// Pocket does.
+fn render_catalog(articles: Vec<CatalogEntry>, format: CatalogFormat) -> Result<String, AnyError> {
+    match format {
+        CatalogFormat::Json => {
+            let catalog = Catalog {
+                version: CATALOG_VERSION,
+                exported: now_secs(),
+                articles,
+            };
+            Ok(serde_json::to_string_pretty(&catalog)?)
+        }
+        CatalogFormat::Csv => {
+            let mut csv = csv::write_row(&CATALOG_COLUMNS);
+            for article in articles {
+                csv.push_str(&csv::write_row(&[
+                    article.url.unwrap_or_default(),
+                    article.title,
+                    article
+                        .datetime_added
+                        .map(|t| t.to_string())
+                        .unwrap_or_default(),
+                    article.author.unwrap_or_default(),
+                    article.publication.unwrap_or_default(),
+                    article
+                        .rating
+                        .map(|r| r.as_str().to_string())
+                        .unwrap_or_default(),
+                    article.tags.join("|"),
+                    article
+                        .elapsed_secs
+                        .map(|t| t.to_string())
+                        .unwrap_or_default(),
+                    article.finished.to_string(),
+                ]));
+            }
+            Ok(csv)
+        }
+    }
+}
+
+/// Returns the name the given article's MP3 gets in the export. The date it was added comes first,
+/// so players that sort by name play the oldest first.
+pub(crate) fn export_filename(meta: &ArticleMetadata) -> String {
+    let date = meta
+        .datetime_added
+        .and_then(|t| Utc.timestamp_opt(t as i64, 0).single())
+        .map(|t| format!("{} ", t.format("%Y-%m-%d")))
+        .unwrap_or_default();
+    let name: String = format!("{date}{}", meta.id)
+        .chars()
+        .map(|c| {
+            if c.is_control() || FORBIDDEN_FILENAME_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("{}.mp3", name.trim_end_matches(['.', ' ']))
+}
+
+/// Retags the given copy of an article for music players. See `set_player_tags`.
+fn retag(meta: &ArticleMetadata, path: &Path) -> Result<(), AnyError> {
+    let mut tag = Tag::read_from_path(path).unwrap_or_default();
+    set_player_tags(meta, &mut tag);
+    tag.write_to_path(path, Version::Id3v24).map_err(Into::into)
+}
+
+/// Returns a copy of the given article's MP3, retagged for music players. See `set_player_tags`.
+fn retagged_copy(meta: &ArticleMetadata, mp3: &[u8]) -> Result<Vec<u8>, AnyError> {
+    let mut tag = Tag::read_from(mp3).unwrap_or_default();
+    set_player_tags(meta, &mut tag);
+
+    // Swap the old tag for the new one
+    let mut reader = io::Cursor::new(mp3);
+    Tag::skip(&mut reader)?;
+    let audio = &mp3[reader.position() as usize..];
+    let mut copy = Vec::with_capacity(mp3.len());
+    tag.write_to(&mut copy, Version::Id3v24)?;
+    copy.extend_from_slice(audio);
+    Ok(copy)
+}
+
+/// Sets the tags that make sense to a music player. The library keeps the source URL as the
+/// artist, which means nothing on a player, so the byline is used instead, and the group or
+/// publication becomes the album.
+fn set_player_tags(meta: &ArticleMetadata, tag: &mut Tag) {
+    tag.set_title(&meta.title);
+
+    let byline = [meta.author.as_deref(), meta.publication.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if byline.is_empty() {
+        tag.set_artist("ReadToMyShoe");
+    } else {
+        tag.set_artist(byline);
+    }
+
+    match (&meta.group, &meta.publication) {
+        (Some(group), _) => tag.set_album(&group.title),
+        (None, Some(publication)) => tag.set_album(publication),
+        (None, None) => tag.set_album("ReadToMyShoe"),
+    }
+    tag.set_genre("Podcast");
+    if let Some(url) = &meta.source_url {
+        tag.add_frame(id3::frame::Comment {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: url.clone(),
+        });
+    }
+}
+
+/// Exports every finished article in the audio blob directory to the given directory, and returns
+/// how many were copied. Articles that are still being converted are left out.
+pub(crate) fn export_library(audio_blob_dir: &str, out_dir: &Path) -> Result<usize, AnyError> {
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("couldn't make export directory {}", out_dir.display()))?;
+
+    let mut catalog = list_articles::load_catalog(audio_blob_dir, &LibraryCache::default())?;
+    catalog.retain(|meta| !meta.incomplete);
+    catalog.sort_by_key(|meta| std::cmp::Reverse(meta.datetime_added));
+
+    let mut copied = 0;
+    let mut filenames = Vec::new();
+    let mut episodes = Vec::new();
+    for meta in catalog {
+        let filename = export_filename(&meta);
+        let dest = out_dir.join(&filename);
+
+        if !dest.exists() {
+            let src = Path::new(audio_blob_dir)
+                .join(&meta.id)
+                .with_extension("mp3");
+            // Copy to a temporary name first, so a sync never picks up a half-written file
+            let tmp = dest.with_extension("mp3.tmp");
+            fs::copy(&src, &tmp).with_context(|| format!("couldn't copy {}", src.display()))?;
+            retag(&meta, &tmp)?;
+            fs::rename(&tmp, &dest)?;
+            copied += 1;
+        }
+
+        episodes.push(Episode {
+            size: fs::metadata(&dest)?.len(),
+            audio_url: urlencoding::encode(&filename).into_owned(),
+            meta,
+        });
+        filenames.push(filename);
+    }
+
+    // Write the index and the feed. Their URLs are relative, so the directory can be put anywhere.
+    let index: Vec<ExportedArticle> = filenames
+        .iter()
+        .zip(&episodes)
+        .map(|(file, episode)| ExportedArticle {
+            file,
+            meta: &episode.meta,
+        })
+        .collect();
+    fs::write(
+        out_dir.join("index.json"),
+        serde_json::to_vec_pretty(&index)?,
+    )?;
+    fs::write(
+        out_dir.join("feed.xml"),
+        podcast::render_feed(".", &episodes),
+    )?;
+
+    Ok(copied)
+}
+
+/// Returns whether the given article ID can name a file in the library, i.e., it can't point
+/// outside it
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && !id.starts_with('.') && !id.contains(['/', '\\'])
+}
+
+/// Returns the path of the given article's MP3
+pub(crate) fn mp3_path(audio_blob_dir: &str, id: &str) -> PathBuf {
+    Path::new(audio_blob_dir).join(format!("{id}.mp3"))
+}
+
+/// Writes an archive of every finished article in the audio blob directory to the given writer,
+/// and returns how many articles are in it
+fn write_archive(audio_blob_dir: &str, writer: impl Write) -> Result<usize, AnyError> {
+    let mut catalog = list_articles::load_catalog(audio_blob_dir, &LibraryCache::default())?;
+    catalog.retain(|meta| !meta.incomplete && is_valid_id(&meta.id));
+    catalog.sort_by_key(|meta| std::cmp::Reverse(meta.datetime_added));
+
+    let mut archive = tar::Builder::new(writer);
+
+    // The manifest goes first, so an import knows what it's getting before the audio arrives
+    let manifest = serde_json::to_vec_pretty(&Manifest {
+        version: ARCHIVE_VERSION,
+        exported: now_secs(),
+        articles: catalog.clone(),
+    })?;
+    append_bytes(&mut archive, MANIFEST_NAME, &manifest)?;
+
+    let mut images_added = HashSet::new();
+    for meta in &catalog {
+        let id = &meta.id;
+        archive.append_path_with_name(mp3_path(audio_blob_dir, id), format!("{id}.mp3"))?;
+
+        let transcript_path = transcript::path(audio_blob_dir, id);
+        if transcript_path.exists() {
+            archive.append_path_with_name(transcript_path, format!("{id}.transcript.json"))?;
+        }
+
+        // Articles from the same site often share an image, so it's only added once
+        let image = meta.image.as_deref().and_then(|image| {
+            let path = images::saved_path(audio_blob_dir, image)?;
+            let name = format!("images/{}", path.file_name()?.to_str()?);
+            Some((path, name))
+        });
+        if let Some((path, name)) = image {
+            if path.exists() && images_added.insert(name.clone()) {
+                archive.append_path_with_name(path, name)?;
+            }
+        }
+    }
+
+    archive.into_inner()?.flush()?;
+    Ok(catalog.len())
+}
+
+/// Adds a file with the given name and contents to the given archive
+fn append_bytes<W: Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(now_secs());
+    archive.append_data(&mut header, name, data)
+}
+
+/// Returns the files in the given library directory that go in a takeout, relative to it, in order.
+/// Hidden files, e.g., uploads that are still being imported, are left out.
+fn takeout_files(library_dir: &Path) -> Result<Vec<PathBuf>, AnyError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(library_dir.join(&dir))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = dir.join(&*name);
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                let skipped = dir.as_os_str().is_empty() && TAKEOUT_SKIPPED_DIRS.contains(&&*name);
+                if !skipped {
+                    dirs.push(path);
+                }
+            } else if file_type.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Writes a takeout of the given library directory, which belongs to the given account, to the
+/// given writer, and returns how many of the library's files are in it. `stores` are the files of
+/// what the other stores keep about the library.
+fn write_takeout(
+    library_dir: &str,
+    account: AccountStatus,
+    stores: &[(&str, Vec<u8>)],
+    writer: impl Write,
+) -> Result<usize, AnyError> {
+    let mut archive = tar::Builder::new(writer);
+    let account = serde_json::to_vec_pretty(&TakeoutAccount {
+        exported: now_secs(),
+        account,
+    })?;
+    append_bytes(&mut archive, TAKEOUT_ACCOUNT_NAME, &account)?;
+    for (name, json) in stores {
+        append_bytes(&mut archive, &format!("{TAKEOUT_STORES_DIR}/{name}"), json)?;
+    }
+
+    let files = takeout_files(Path::new(library_dir))?;
+    for path in &files {
+        archive.append_path_with_name(
+            Path::new(library_dir).join(path),
+            Path::new(TAKEOUT_LIBRARY_DIR).join(path),
+        )?;
+    }
+
+    archive.into_inner()?.flush()?;
+    Ok(files.len())
+}
+
+/// Returns where the archive file with the given name is unpacked to in the given staging
+/// directory, if it's one an archive should have. Anything else, e.g., a path that points outside
+/// the directory, is left out.
+fn staged_path(staging_dir: &str, name: &str) -> Option<PathBuf> {
+    match name.split('/').collect::<Vec<_>>()[..] {
+        ["images", filename] => images::saved_path(staging_dir, &format!("/api/images/{filename}")),
+        [filename] => {
+            let id = filename
+                .strip_suffix(".transcript.json")
+                .or_else(|| filename.strip_suffix(".mp3"))?;
+            is_valid_id(id).then(|| Path::new(staging_dir).join(filename))
+        }
+        _ => None,
+    }
+}
+
+/// Moves the given file from the staging directory into the library, unless the library already
+/// has it
+fn move_in(staged: &Path, dest: &Path) -> Result<(), AnyError> {
+    if staged.exists() && !dest.exists() {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(staged, dest)?;
+    }
+    Ok(())
+}
+
+/// Unpacks the given archive into the given staging directory, and moves the articles the library
+/// doesn't have yet into it. Returns the articles that were imported, and how many were skipped.
+fn unpack_archive(
+    audio_blob_dir: &str,
+    staging_dir: &str,
+    reader: impl Read,
+) -> Result<(Vec<ArticleMetadata>, usize), AnyError> {
+    let mut manifest: Option<Manifest> = None;
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+
+        if name == MANIFEST_NAME {
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json)?;
+            let parsed: Manifest =
+                serde_json::from_slice(&json).context("couldn't parse the archive's manifest")?;
+            if parsed.version > ARCHIVE_VERSION {
+                bail!("The archive was made by a newer version of ReadToMyShoe");
+            }
+            manifest = Some(parsed);
+            continue;
+        }
+
+        let Some(dest) = staged_path(staging_dir, &name) else {
+            tracing::warn!("Leaving {name:?} out of the import");
+            continue;
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&dest)?;
+    }
+    let manifest = manifest.ok_or_else(|| {
+        anyhow!("The archive has no {MANIFEST_NAME}, so it isn't a library export")
+    })?;
+
+    let mut imported = Vec::new();
+    let mut skipped = 0;
+    for meta in manifest.articles {
+        let id = &meta.id;
+        if !is_valid_id(id) {
+            tracing::warn!("Leaving article with invalid ID {id:?} out of the import");
+            continue;
+        }
+        let staged_mp3 = mp3_path(staging_dir, id);
+        let dest_mp3 = mp3_path(audio_blob_dir, id);
+        if dest_mp3.exists() {
+            skipped += 1;
+            continue;
+        }
+        if !staged_mp3.exists() {
+            tracing::warn!("The archive lists {id} but doesn't have its audio");
+            continue;
+        }
+
+        // The MP3 goes in last, since the article is in the library as soon as it's there
+        move_in(
+            &transcript::path(staging_dir, id),
+            &transcript::path(audio_blob_dir, id),
+        )?;
+        let image = meta.image.as_deref().and_then(|image| {
+            Some((
+                images::saved_path(staging_dir, image)?,
+                images::saved_path(audio_blob_dir, image)?,
+            ))
+        });
+        if let Some((staged, dest)) = image {
+            move_in(&staged, &dest)?;
+        }
+        fs::rename(&staged_mp3, &dest_mp3)?;
+        imported.push(meta);
+    }
+
+    Ok((imported, skipped))
+}
+
+/// Imports the given archive into the library. It's unpacked into a hidden directory in the
+/// library first, so nothing half-unpacked ever shows up in the library.
+fn import_archive(
+    audio_blob_dir: &str,
+    reader: impl Read,
+) -> Result<(Vec<ArticleMetadata>, usize), AnyError> {
+    let nonce = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let staging_dir = Path::new(audio_blob_dir).join(format!(".import-{nonce}"));
+    fs::create_dir_all(&staging_dir)?;
+
+    let staging_dir_str = staging_dir
+        .to_str()
+        .ok_or_else(|| anyhow!("the library's path isn't UTF-8"))?;
+    let result = unpack_archive(audio_blob_dir, staging_dir_str, reader);
+    if let Err(e) = fs::remove_dir_all(&staging_dir) {
+        tracing::error!("Could not remove import directory {:?}: {e}", staging_dir);
+    }
+    result
+}
+
+/// A writer that sends what's written to it down a channel, for streaming an archive as it's
+/// written
+struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the download was cancelled"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the Content-Disposition header that has the client download the response as a file with
+/// the given name. Names that aren't ASCII get a fallback, for clients that don't support
+/// `filename*`.
+pub(crate) fn attachment(filename: &str) -> HeaderValue {
+    let fallback: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let value = format!(
+        "attachment; filename=\"{fallback}\"; filename*=UTF-8''{}",
+        urlencoding::encode(filename)
+    );
+    HeaderValue::from_str(&value).unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+/// Logs the given error and makes it the response
+pub(crate) fn error_response(status: StatusCode, e: AnyError) -> (StatusCode, String) {
+    tracing::error!("{e}");
+    (status, e.to_string())
+}
+
+/// The query of a request for the library's catalog
+#[derive(Deserialize)]
+struct CatalogExportParams {
+    #[serde(default)]
+    format: CatalogFormat,
+}
+
+/// The query of a request for an article's text
+#[derive(Deserialize)]
+struct TextExportParams {
+    #[serde(default)]
+    format: TextExportFormat,
+}
+
+// Sets the /api/export-library, /api/import-library, /api/export-catalog, /api/export-article,
+// /api/export-text, and /api/takeout routes
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    search_index: SearchIndex,
+    library_stores: LibraryStores,
+) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/export-library", get(export_library_endpoint))
+            .route("/import-library", post(import_library_endpoint))
+            .route("/export-catalog", get(export_catalog_endpoint))
+            .route("/export-article/:id", get(export_article_endpoint))
+            .route("/export-text/:id", get(export_text_endpoint))
+            .route("/takeout", get(takeout_endpoint))
+            .layer(Extension(audio_blob_dir.to_string()))
+            .layer(Extension(search_index))
+            .layer(Extension(library_stores)),
+    )
+}
+
+/// Streams the tar archive that the given function writes, as a file with the given name. If
+/// something goes wrong partway through, the response is cut off, so the client doesn't take what it
+/// got for a whole archive.
+fn stream_archive(
+    filename: String,
+    write: impl FnOnce(BufWriter<ChannelWriter>) -> Result<(), AnyError> + Send + 'static,
+) -> Response {
+    let (tx, rx) = mpsc::channel(ARCHIVE_CHANNEL_LEN);
+    let name = filename.clone();
+    tokio::task::spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(ARCHIVE_CHUNK_LEN, ChannelWriter(tx.clone()));
+        if let Err(e) = write(writer) {
+            tracing::error!("Error writing {name}: {e}");
+            let _ = tx.blocking_send(Err(io::Error::other(e.to_string())));
+        }
+    });
+
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/x-tar"),
+        ),
+        (header::CONTENT_DISPOSITION, attachment(&filename)),
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+    ];
+    (headers, StreamBody::new(ReceiverStream::new(rx))).into_response()
+}
+
+/// Streams an archive of the whole library
+async fn export_library_endpoint(LibraryDir(audio_blob_dir): LibraryDir) -> Response {
+    let filename = format!("readtomyshoe-library-{}.tar", Utc::now().format("%Y-%m-%d"));
+    stream_archive(filename, move |writer| {
+        let count = write_archive(&audio_blob_dir, writer)?;
+        tracing::info!("Exported {count} articles");
+        Ok(())
+    })
+}
+
+/// Streams a takeout of everything kept in the library, for whoever's signed in
+async fn takeout_endpoint(
+    LibraryDir(library_dir): LibraryDir,
+    CurrentAccount(account): CurrentAccount,
+    Extension(library_stores): Extension<LibraryStores>,
+) -> Result<Response, (StatusCode, String)> {
+    let stores = library_stores
+        .take_out(&library_dir)
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let filename = format!("readtomyshoe-takeout-{}.tar", Utc::now().format("%Y-%m-%d"));
+    Ok(stream_archive(filename, move |writer| {
+        let count = write_takeout(&library_dir, account, &stores, writer)?;
+        tracing::info!("Took out {count} files");
+        Ok(())
+    }))
+}
+
+/// Imports an archive made by `/api/export-library` into the library. Articles the library already
+/// has are skipped.
+async fn import_library_endpoint(
+    LibraryDir(audio_blob_dir): LibraryDir,
+    Extension(search_index): Extension<SearchIndex>,
+    mut body: BodyStream,
+) -> Result<Json<LibraryImportSummary>, (StatusCode, String)> {
+    // Archives can be bigger than memory, so save the upload first
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let upload_path = Path::new(&audio_blob_dir).join(format!(".import-{nonce}.tar"));
+    let saved: Result<(), AnyError> = async {
+        let mut file = tokio::fs::File::create(&upload_path).await?;
+        while let Some(chunk) = body.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await.map_err(Into::into)
+    }
+    .await;
+
+    let result = match saved {
+        Ok(()) => {
+            let (dir, path) = (audio_blob_dir.clone(), upload_path.clone());
+            tokio::task::spawn_blocking(move || import_archive(&dir, fs::File::open(path)?))
+                .await
+                .map_err(AnyError::from)
+                .and_then(|r| r)
+        }
+        Err(e) => Err(e.context("couldn't receive the archive")),
+    };
+    let _ = tokio::fs::remove_file(&upload_path).await;
+    let (imported, skipped) = result.map_err(|e| error_response(StatusCode::BAD_REQUEST, e))?;
+
+    // Make the imported articles searchable
+    for meta in &imported {
+        let Ok(transcript) = transcript::load(&audio_blob_dir, &meta.id) else {
+            continue;
+        };
+        let _ = search_index
+            .add(&audio_blob_dir, &meta.id, &meta.title, &transcript.text)
+            .await
+            .map_err(|e| tracing::error!("Error indexing imported article {}: {e}", meta.id));
+    }
+
+    tracing::info!("Imported {} articles, skipped {skipped}", imported.len());
+    Ok(Json(LibraryImportSummary {
+        imported: imported.len(),
+        skipped,
+    }))
+}
+
+/// Returns the library's catalog in the given format. Reimport it with `/api/parse-import`.
+async fn export_catalog_endpoint(
+    Query(CatalogExportParams { format }): Query<CatalogExportParams>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Response, (StatusCode, String)> {
+    let catalog = tokio::task::spawn_blocking(move || {
+        let mut catalog = list_articles::load_catalog(&audio_blob_dir, &LibraryCache::default())?;
+        catalog.sort_by_key(|meta| std::cmp::Reverse(meta.datetime_added));
+        let entries = catalog_entries(&catalog, &sync::load(&audio_blob_dir)?);
+        render_catalog(entries, format)
+    })
+    .await
+    .map_err(AnyError::from)
+    .and_then(|r| r)
+    .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let filename = format!(
+        "readtomyshoe-catalog-{}.{}",
+        Utc::now().format("%Y-%m-%d"),
+        format.as_str()
+    );
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.mime_type()),
+        ),
+        (header::CONTENT_DISPOSITION, attachment(&filename)),
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+    ];
+    Ok((headers, catalog).into_response())
+}
+
+/// Returns the given article's MP3, named and tagged for music players the way the directory
+/// export does
+async fn export_article_endpoint(
+    UrlPath(id): UrlPath<String>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Response, (StatusCode, String)> {
+    if !is_valid_id(&id) {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            anyhow!("invalid article ID {id:?}"),
+        ));
+    }
+    let path = mp3_path(&audio_blob_dir, &id);
+    if !path.exists() {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            anyhow!("No article {id}"),
+        ));
+    }
+    if pending::exists(&audio_blob_dir, &id) {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            anyhow!("{id} is still being converted"),
+        ));
+    }
+
+    let mp3 = tokio::task::spawn_blocking(move || {
+        let meta = get_metadata(&path)?;
+        let mp3 = retagged_copy(&meta, &fs::read(&path)?)?;
+        Ok::<_, AnyError>((export_filename(&meta), mp3))
+    })
+    .await
+    .map_err(AnyError::from)
+    .and_then(|r| r);
+    let (filename, mp3) = mp3.map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let headers = [
+        (header::CONTENT_TYPE, HeaderValue::from_static("audio/mpeg")),
+        (header::CONTENT_DISPOSITION, attachment(&filename)),
+    ];
+    Ok((headers, mp3).into_response())
+}
+
+/// Returns the filename and contents of the given article's text in the given format
+fn article_text(
+    audio_blob_dir: &str,
+    id: &str,
+    format: TextExportFormat,
+) -> Result<(String, String), AnyError> {
+    let meta = get_metadata(&mp3_path(audio_blob_dir, id))?;
+    let transcript = transcript::load(audio_blob_dir, id)?;
+    let filename = export_filename(&meta).replace(".mp3", &format!(".{}", format.extension()));
+    Ok((filename, transcript.export(&meta, format)))
+}
+
+/// Returns the given article's text, with its title, byline, and source link, as Markdown or HTML.
+/// Articles converted before transcripts were kept don't have any.
+async fn export_text_endpoint(
+    UrlPath(id): UrlPath<String>,
+    Query(TextExportParams { format }): Query<TextExportParams>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Response, (StatusCode, String)> {
+    if !is_valid_id(&id) {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            anyhow!("invalid article ID {id:?}"),
+        ));
+    }
+    let (filename, text) =
+        tokio::task::spawn_blocking(move || article_text(&audio_blob_dir, &id, format))
+            .await
+            .map_err(AnyError::from)
+            .and_then(|r| r)
+            .map_err(|e| error_response(StatusCode::NOT_FOUND, e))?;
+
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.mime_type()),
+        ),
+        (header::CONTENT_DISPOSITION, attachment(&filename)),
+    ];
+    Ok((headers, text).into_response())
+}
+
+#[test]
+fn exporting_library() {
+    let dir = std::env::temp_dir().join(format!("rtms-export-test-{}", std::process::id()));
+    let audio_blob_dir = dir.join("audio_blobs");
+    let out_dir = dir.join("export");
+    fs::create_dir_all(&audio_blob_dir).unwrap();
+    let audio_blob_dir = audio_blob_dir.to_str().unwrap();
+
+    let meta = ArticleMetadata {
+        id: "Fish: Chips-abc".to_string(),
+        title: "Fish: Chips".to_string(),
+        datetime_added: Some(1_663_000_000),
+        source_url: Some("https://example.com/fish".to_string()),
+        author: Some("A. Writer".to_string()),
+        publication: Some("The Daily Fish".to_string()),
+        ..Default::default()
+    };
+    fs::write(
+        Path::new(audio_blob_dir).join(format!("{}.mp3", meta.id)),
+        [0u8; 64],
+    )
+    .unwrap();
+    crate::util::save_metadata(&meta, audio_blob_dir).unwrap();
+
+    assert_eq!(export_library(audio_blob_dir, &out_dir).unwrap(), 1);
+    // Exporting again doesn't copy anything
+    assert_eq!(export_library(audio_blob_dir, &out_dir).unwrap(), 0);
+
+    // The copy is named for the date and has player-friendly tags
+    let filename = "2022-09-12 Fish_ Chips-abc.mp3";
+    let tag = Tag::read_from_path(out_dir.join(filename)).unwrap();
+    assert_eq!(tag.title(), Some("Fish: Chips"));
+    assert_eq!(tag.artist(), Some("A. Writer, The Daily Fish"));
+    assert_eq!(tag.album(), Some("The Daily Fish"));
+
+    let index: serde_json::Value =
+        serde_json::from_slice(&fs::read(out_dir.join("index.json")).unwrap()).unwrap();
+    assert_eq!(index[0]["file"], filename);
+    assert_eq!(index[0]["title"], "Fish: Chips");
+
+    let feed = fs::read_to_string(out_dir.join("feed.xml")).unwrap();
+    let doc = roxmltree::Document::parse(&feed).unwrap();
+    let enclosure = doc
+        .descendants()
+        .find(|n| n.has_tag_name("enclosure"))
+        .unwrap();
+    assert_eq!(
+        enclosure.attribute("url"),
+        Some("2022-09-12%20Fish_%20Chips-abc.mp3")
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn archiving_library() {
+    let dir = std::env::temp_dir().join(format!("rtms-archive-test-{}", std::process::id()));
+    let old_library = dir.join("old");
+    let new_library = dir.join("new");
+    fs::create_dir_all(&old_library).unwrap();
+    fs::create_dir_all(&new_library).unwrap();
+    let (old_library, new_library) = (old_library.to_str().unwrap(), new_library.to_str().unwrap());
+
+    let image = images::saved_path(old_library, &format!("/api/images/{}.jpg", "ab".repeat(32)));
+    let image = image.unwrap();
+    fs::create_dir_all(image.parent().unwrap()).unwrap();
+    fs::write(&image, b"a photo").unwrap();
+    let meta = ArticleMetadata {
+        id: "Fish: Chips-abc".to_string(),
+        title: "Fish: Chips".to_string(),
+        datetime_added: Some(1_663_000_000),
+        image: Some(format!("/api/images/{}.jpg", "ab".repeat(32))),
+        ..Default::default()
+    };
+    fs::write(mp3_path(old_library, &meta.id), [0xFFu8; 64]).unwrap();
+    crate::util::save_metadata(&meta, old_library).unwrap();
+    let transcript = transcript::new("Fish and chips.", &[(0, 0.0)], 1.0);
+    transcript::save(old_library, &meta.id, &transcript).unwrap();
+
+    let mut archive = Vec::new();
+    assert_eq!(write_archive(old_library, &mut archive).unwrap(), 1);
+
+    // The article comes back with its tags, transcript, and image
+    let (imported, skipped) = import_archive(new_library, &archive[..]).unwrap();
+    assert_eq!((imported.len(), skipped), (1, 0));
+    let restored = get_metadata(&mp3_path(new_library, &meta.id)).unwrap();
+    assert_eq!(restored.title, "Fish: Chips");
+    assert_eq!(restored.image, meta.image);
+    assert_eq!(
+        transcript::load(new_library, &meta.id).unwrap().text,
+        "Fish and chips."
+    );
+    let restored_image = images::saved_path(new_library, meta.image.as_deref().unwrap());
+    assert_eq!(fs::read(restored_image.unwrap()).unwrap(), b"a photo");
+
+    // Importing again skips what's already there, and leaves nothing behind
+    let (imported, skipped) = import_archive(new_library, &archive[..]).unwrap();
+    assert_eq!((imported.len(), skipped), (0, 1));
+    let hidden = fs::read_dir(new_library)
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with('.')
+        })
+        .count();
+    assert_eq!(hidden, 0);
+
+    // Only the files an export makes are unpacked, and archives need a manifest
+    assert_eq!(staged_path("/s", "../evil.mp3"), None);
+    assert_eq!(staged_path("/s", "images/../../evil.jpg"), None);
+    assert_eq!(staged_path("/s", "notes.txt"), None);
+    assert!(import_archive(new_library, &[0u8; 1024][..]).is_err());
+
+    // A single article is downloaded with player tags
+    let mp3 = fs::read(mp3_path(old_library, &meta.id)).unwrap();
+    let copy = retagged_copy(&meta, &mp3).unwrap();
+    assert_eq!(&copy[copy.len() - 64..], &[0xFFu8; 64]);
+    let tag = Tag::read_from(&copy[..]).unwrap();
+    assert_eq!(tag.album(), Some("ReadToMyShoe"));
+    assert_eq!(
+        attachment("Café.mp3"),
+        "attachment; filename=\"Caf_.mp3\"; filename*=UTF-8''Caf%C3%A9.mp3"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn taking_out() {
+    let dir = std::env::temp_dir().join(format!("rtms-takeout-test-{}", std::process::id()));
+    for (path, contents) in [
+        ("Fish-abc.mp3", "audio"),
+        ("sync.json", "progress"),
+        ("images/ab.jpg", "a photo"),
+        ("transcoded/ab.ogg", "a copy"),
+        ("users/sam/Chips-def.mp3", "someone else's"),
+        (".import-1.tar", "half an upload"),
+    ] {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    let account = AccountStatus {
+        accounts_enabled: true,
+        name: Some("alex".to_string()),
+        ..Default::default()
+    };
+
+    let stores = [("inbox.json", b"[]".to_vec())];
+
+    let mut takeout = Vec::new();
+    assert_eq!(
+        write_takeout(dir.to_str().unwrap(), account, &stores, &mut takeout).unwrap(),
+        3
+    );
+
+    // The account comes first, then the other stores, then the library's own files, without
+    // copies or other libraries
+    let mut archive = tar::Archive::new(&takeout[..]);
+    let mut files = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        files.push((entry.path().unwrap().display().to_string(), contents));
+    }
+    let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "account.json",
+            "stores/inbox.json",
+            "library/Fish-abc.mp3",
+            "library/images/ab.jpg",
+            "library/sync.json"
+        ]
+    );
+    assert!(files[0].1.contains("\"name\": \"alex\""));
+    assert_eq!(files[1].1, "[]");
+    assert_eq!(files[4].1, "progress");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn exporting_text() {
+    let dir = std::env::temp_dir().join(format!("rtms-text-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let audio_blob_dir = dir.to_str().unwrap();
+
+    let meta = ArticleMetadata {
+        id: "Fish-abc".to_string(),
+        title: "Fish".to_string(),
+        datetime_added: Some(1_663_000_000),
+        source_url: Some("https://example.com/fish?a=1&b=2".to_string()),
+        author: Some("A. Writer".to_string()),
+        ..Default::default()
+    };
+    fs::write(mp3_path(audio_blob_dir, &meta.id), [0xFFu8; 64]).unwrap();
+    crate::util::save_metadata(&meta, audio_blob_dir).unwrap();
+    let text = "Fish\nChips are *great* <really>.\nBatter\nIt's crisp.";
+    let mut transcript = transcript::new(text, &[(0, 0.0), (5, 1.0), (33, 2.0), (40, 3.0)], 4.0);
+    // The heading detection is the synthesizer's business, so mark it here
+    transcript.timepoints[2].heading = true;
+    transcript::save(audio_blob_dir, &meta.id, &transcript).unwrap();
+
+    let (filename, markdown) =
+        article_text(audio_blob_dir, &meta.id, TextExportFormat::Markdown).unwrap();
+    assert_eq!(filename, "2022-09-12 Fish-abc.md");
+    assert_eq!(
+        markdown,
+        "# Fish\n\n*By A. Writer*\n\nSource: <https://example.com/fish?a=1&b=2>\n\n\
+         Chips are \\*great\\* \\<really>.\n\n## Batter\n\nIt's crisp.\n"
+    );
+
+    let (filename, html) = article_text(audio_blob_dir, &meta.id, TextExportFormat::Html).unwrap();
+    assert_eq!(filename, "2022-09-12 Fish-abc.html");
+    assert!(html.contains("<title>Fish</title>"));
+    assert!(html.contains("<a href=\"https://example.com/fish?a=1&amp;b=2\">"));
+    assert!(html.contains("<p>Chips are *great* &lt;really&gt;.</p>\n<h2>Batter</h2>\n"));
+    // The title isn't repeated as the first paragraph
+    assert_eq!(html.matches("Fish</").count(), 2);
+
+    // Articles without a transcript have no text to export
+    transcript::remove(audio_blob_dir, &meta.id).unwrap();
+    assert!(article_text(audio_blob_dir, &meta.id, TextExportFormat::Html).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn exporting_catalog() {
+    use common::{SyncedPlaylist, SyncedPosition, SyncedQueueEntry};
+
+    let article = |id: &str, url: Option<&str>| ArticleMetadata {
+        id: id.to_string(),
+        title: id.to_string(),
+        datetime_added: Some(1_663_000_000),
+        source_url: url.map(str::to_string),
+        ..Default::default()
+    };
+    let catalog = [
+        article("Boots, part 1", Some("https://example.com/boots")),
+        article("Boots, part 2", Some("https://example.com/boots")),
+        article("Pasted", None),
+    ];
+    let playlist = |name: &str, deleted| SyncedPlaylist {
+        id: name.to_string(),
+        name: name.to_string(),
+        entries: vec![SyncedQueueEntry {
+            id: "Boots, part 1".to_string(),
+            title: "Boots".to_string(),
+            group: None,
+            publication: None,
+            author: None,
+            image: None,
+            reading_profile: None,
+        }],
+        updated: 0.0,
+        deleted,
+    };
+    let sync_state = SyncState {
+        positions: vec![SyncedPosition {
+            id: "Boots, part 1".to_string(),
+            elapsed: 12.5,
+            finished: false,
+            last_played: 0.0,
+            device: None,
+            chosen: 0.0,
+        }],
+        playlists: vec![playlist("Shoes", false), playlist("Old", true)],
+        ..Default::default()
+    };
+
+    // The parts of a split-up article are one entry
+    let entries = catalog_entries(&catalog, &sync_state);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].tags, ["Shoes"]);
+    assert_eq!(entries[0].elapsed_secs, Some(12.5));
+    assert_eq!(entries[1].url, None);
+    assert_eq!(entries[1].elapsed_secs, None);
+
+    let csv = render_catalog(entries, CatalogFormat::Csv).unwrap();
+    assert_eq!(
+        csv,
+        "url,title,datetime_added,author,publication,rating,tags,elapsed_secs,finished\r\n\
+         https://example.com/boots,\"Boots, part 1\",1663000000,,,,Shoes,12.5,false\r\n\
+         ,Pasted,1663000000,,,,,,false\r\n"
+    );
+    let json = render_catalog(catalog_entries(&catalog, &sync_state), CatalogFormat::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["version"], CATALOG_VERSION);
+    assert_eq!(json["articles"][0]["url"], "https://example.com/boots");
+}
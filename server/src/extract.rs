@@ -0,0 +1,753 @@
+//! Fetches article pages, runs text extraction on them, and keeps the fetched HTML around so that
+//! extraction can be re-run later without hitting the source site again. When extraction isn't
+//! sure it found the article, the other likely parts of the page are offered as alternatives, and
+//! the user's pick can be saved as a rule for the site.
+//!
+//! Pages are read with whichever of a few strategies finds something that looks like an article
+//! first: the part of the page with the most prose, as Readability finds it, then trafilatura's
+//! heuristics, then the same two on the page's AMP version. Text that's too short or mostly short
+//! lines, like a menu or a cookie banner, doesn't count.
+
+use crate::{json_store::JsonStore, plugins::ExtractorPlugins, scripts::TransformScripts};
+
+use common::{ExtractionCandidate, ExtractionChoice};
+use tts_pipeline::article::Trafilatura;
+pub(crate) use tts_pipeline::article::{site_of, ExtractedArticle};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Error as AnyError};
+use scraper::{ElementRef, Html, Node, Selector};
+
+/// How often the HTML archive is checked for expired pages
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Paragraphs shorter than this many characters don't count toward the score of the element they're
+/// in. They're usually captions, bylines, and the like.
+const MIN_PARAGRAPH_LEN: usize = 25;
+
+/// The share of the extracted paragraphs that have to be found on the page for their order to be
+/// checked against it. Below this, the extractor changed the text too much to tell.
+const MIN_FOUND_SHARE: f64 = 0.8;
+
+/// Text with fewer words than this isn't taken for an article. Menus and cookie banners usually
+/// have fewer.
+const MIN_ARTICLE_WORDS: usize = 80;
+
+/// The share of an article's words that have to be in sizeable paragraphs. Menus, lists of links,
+/// and banners are mostly short lines.
+const MIN_PROSE_SHARE: f64 = 0.6;
+
+/// Extraction that's less sure of its pick than this is checked with the user
+const MIN_CONFIDENCE: f64 = 0.4;
+
+/// The most alternatives that are offered besides the extractor's pick
+const MAX_ALTERNATIVES: usize = 3;
+
+/// The length of the previews of the candidates, in characters
+const PREVIEW_LEN: usize = 300;
+
+/// Elements whose text is never part of an article
+const SKIPPED_TAGS: &[&str] = &["script", "style", "noscript", "template", "svg", "button"];
+
+/// Elements whose text goes on its own line
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "footer",
+    "blockquote",
+    "pre",
+    "li",
+    "ul",
+    "ol",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "br",
+    "tr",
+    "table",
+    "figure",
+    "figcaption",
+];
+
+/// Returns whether the given URL is on the given site, e.g., `example.com`, or one of its
+/// subdomains
+pub(crate) fn is_on_site(url: &str, site: &str) -> bool {
+    site_of(url)
+        .is_some_and(|s| s == site || s.strip_suffix(site).is_some_and(|sub| sub.ends_with('.')))
+}
+
+/// How long fetched HTML is kept in the archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum HtmlRetention {
+    /// Fetched HTML is deleted as soon as extraction is done
+    Never,
+    /// Fetched HTML is kept for the given number of days
+    Days(u64),
+    /// Fetched HTML is never deleted
+    Forever,
+}
+
+impl FromStr for HtmlRetention {
+    type Err = AnyError;
+
+    /// Parses "never", "forever", or a number of days like "30" or "30d"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(HtmlRetention::Never),
+            "forever" => Ok(HtmlRetention::Forever),
+            days => {
+                let days = days.strip_suffix('d').unwrap_or(days);
+                days.parse()
+                    .map(HtmlRetention::Days)
+                    .map_err(|_| anyhow!("invalid retention policy '{s}'"))
+            }
+        }
+    }
+}
+
+/// The on-disk store of fetched article HTML. Pages are saved as `ARTICLEID.html`.
+#[derive(Clone)]
+pub(crate) struct HtmlArchive {
+    dir: PathBuf,
+    retention: HtmlRetention,
+}
+
+impl HtmlArchive {
+    pub(crate) fn new(dir: &str, retention: HtmlRetention) -> HtmlArchive {
+        HtmlArchive {
+            dir: PathBuf::from(dir),
+            retention,
+        }
+    }
+
+    /// Returns the path of the archived HTML for the given article
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).with_extension("html")
+    }
+
+    /// Saves the HTML that the given article was extracted from. Does nothing if the retention
+    /// policy is to never keep HTML.
+    pub(crate) fn save(&self, id: &str, html: &[u8]) -> Result<(), AnyError> {
+        if self.retention == HtmlRetention::Never {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(id), html).map_err(Into::into)
+    }
+
+    /// Loads the archived HTML of the given article
+    pub(crate) fn load(&self, id: &str) -> Result<Vec<u8>, AnyError> {
+        std::fs::read(self.path(id)).map_err(|e| anyhow!("No archived HTML for article {id}: {e}"))
+    }
+
+    /// Moves the archived HTML of an article to a new article ID. This is used when re-extraction
+    /// changes the text, and thus the ID, of an article.
+    pub(crate) fn rename(&self, old_id: &str, new_id: &str) -> Result<(), AnyError> {
+        std::fs::rename(self.path(old_id), self.path(new_id)).map_err(Into::into)
+    }
+
+    /// Deletes the archived HTML of the given article, if there is any
+    pub(crate) fn remove(&self, id: &str) -> Result<(), AnyError> {
+        let path = self.path(id);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes all the archived pages that are older than the retention policy allows
+    fn prune(&self) -> Result<(), AnyError> {
+        let max_age = match self.retention {
+            HtmlRetention::Forever | HtmlRetention::Never => return Ok(()),
+            HtmlRetention::Days(d) => Duration::from_secs(d * 24 * 60 * 60),
+        };
+
+        // The archive dir might not exist yet if nothing has been fetched
+        if !self.dir.exists() {
+            return Ok(());
+        }
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("html") {
+                continue;
+            }
+
+            let age = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| SystemTime::now().duration_since(t).ok());
+            if age.map(|a| a > max_age).unwrap_or(false) {
+                tracing::debug!("Pruning archived HTML {:?}", path);
+                std::fs::remove_file(&path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Spawns a background task that periodically prunes the archive
+    pub(crate) fn spawn_pruner(&self) {
+        let archive = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = archive.prune() {
+                    tracing::error!("Could not prune HTML archive: {e}");
+                }
+            }
+        });
+    }
+}
+
+/// The per-site rules for where the body of an article is on a page. These are saved as a JSON
+/// object mapping each site's host to a CSS selector for the element containing the body. Sites
+/// that a selector can't handle can have an extractor plugin instead, and sites whose text needs
+/// cleaning up after it's extracted can have a transform script.
+#[derive(Clone)]
+pub(crate) struct ExtractionRules {
+    rules: JsonStore<BTreeMap<String, String>>,
+    plugins: ExtractorPlugins,
+    transforms: TransformScripts,
+}
+
+impl ExtractionRules {
+    /// Loads the rules from the given file. If the file doesn't exist, there are no rules yet.
+    pub(crate) fn load(path: &str) -> Result<ExtractionRules, AnyError> {
+        Ok(ExtractionRules {
+            rules: JsonStore::load(path, "extraction rules")?,
+            plugins: ExtractorPlugins::default(),
+            transforms: TransformScripts::default(),
+        })
+    }
+
+    /// Uses the given extractor plugins for the sites they handle
+    pub(crate) fn with_plugins(self, plugins: ExtractorPlugins) -> ExtractionRules {
+        ExtractionRules { plugins, ..self }
+    }
+
+    /// Returns the extractor plugins
+    pub(crate) fn plugins(&self) -> &ExtractorPlugins {
+        &self.plugins
+    }
+
+    /// Uses the given scripts to transform the text extracted from the sites they're for
+    pub(crate) fn with_transforms(self, transforms: TransformScripts) -> ExtractionRules {
+        ExtractionRules { transforms, ..self }
+    }
+
+    /// Runs the transform scripts for the site of the given URL on the given article
+    pub(crate) fn transform(&self, url: &str, title: &mut String, text: &mut String) {
+        self.transforms.transform(url, title, text);
+    }
+
+    /// Returns the selector saved for the site of the given URL, if any
+    pub(crate) fn get(&self, url: &str) -> Option<String> {
+        let site = site_of(url)?;
+        self.rules.read(|rules| rules.get(&site).cloned())
+    }
+
+    /// Saves the given selector as the rule for the site of the given URL, replacing any existing
+    /// rule for the site
+    pub(crate) async fn set(&self, url: &str, selector: &str) -> Result<(), AnyError> {
+        let site = site_of(url).ok_or_else(|| anyhow!("{url} has no site to save a rule for"))?;
+        self.rules
+            .update(|rules| {
+                rules.insert(site, selector.to_string());
+            })
+            .await
+    }
+}
+
+/// A part of a page that might be the body of the article
+struct Candidate {
+    /// The CSS selector of the element
+    selector: String,
+    /// The element's text
+    text: String,
+    /// How much the element looks like an article body
+    score: f64,
+}
+
+/// Collects the text of the given element into `out`, putting the text of block elements on lines
+/// of their own
+fn push_text(elem: ElementRef, out: &mut String) {
+    let name = elem.value().name();
+    if SKIPPED_TAGS.contains(&name) {
+        return;
+    }
+
+    let block = BLOCK_TAGS.contains(&name);
+    if block {
+        out.push('\n');
+    }
+    for child in elem.children() {
+        match child.value() {
+            Node::Text(text) => out.push_str(text),
+            Node::Element(_) => push_text(ElementRef::wrap(child).unwrap(), out),
+            _ => (),
+        }
+    }
+    if block {
+        out.push('\n');
+    }
+}
+
+/// Returns the text of the given element, one paragraph per line, the way trafilatura returns it
+pub(crate) fn element_text(elem: ElementRef) -> String {
+    let mut text = String::new();
+    push_text(elem, &mut text);
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns whether the given class or ID can go in a selector as it is
+fn is_plain_ident(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Returns a CSS selector for the given element. The selector is made of IDs and classes rather
+/// than positions, so that it has a chance of working on other pages from the same site. It's
+/// made longer until it only matches the element, or until it reaches the top of the page.
+fn selector_for(doc: &Html, elem: ElementRef) -> String {
+    let mut parts = Vec::new();
+    let mut cur = Some(elem);
+
+    while let Some(e) = cur {
+        let value = e.value();
+        match value.id().filter(|id| is_plain_ident(id)) {
+            Some(id) => {
+                // IDs are unique, so there's no need to go further up
+                parts.push(format!("{}#{id}", value.name()));
+                break;
+            }
+            None => {
+                let classes: String = value
+                    .classes()
+                    .filter(|c| is_plain_ident(c))
+                    .map(|c| format!(".{c}"))
+                    .collect();
+                parts.push(format!("{}{classes}", value.name()));
+            }
+        }
+
+        let selector = parts.iter().rev().cloned().collect::<Vec<_>>().join(" > ");
+        let unique = Selector::parse(&selector)
+            .map(|s| doc.select(&s).nth(1).is_none())
+            .unwrap_or(false);
+        if unique {
+            break;
+        }
+        cur = e
+            .parent()
+            .and_then(ElementRef::wrap)
+            .filter(|p| p.value().name() != "html");
+    }
+
+    parts.into_iter().rev().collect::<Vec<_>>().join(" > ")
+}
+
+/// Finds the parts of the page that look most like an article body, best first. Like Readability,
+/// every sizeable paragraph adds to the score of its parent, and half as much to its grandparent.
+/// Elements that are mostly links are marked down. Elements that contain one another aren't both
+/// returned.
+fn find_candidates(doc: &Html, max: usize) -> Vec<Candidate> {
+    let paragraphs = Selector::parse("p, pre, blockquote").unwrap();
+    let links = Selector::parse("a").unwrap();
+
+    let mut scores = HashMap::new();
+    for paragraph in doc.select(&paragraphs) {
+        let text = element_text(paragraph);
+        let len = text.chars().count();
+        if len < MIN_PARAGRAPH_LEN {
+            continue;
+        }
+
+        let score = 1.0 + text.matches(',').count() as f64 + (len / 100).min(3) as f64;
+        let containers = paragraph
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take_while(|e| !["body", "html"].contains(&e.value().name()))
+            .take(2);
+        for (container, share) in containers.zip([1.0, 0.5]) {
+            *scores.entry(container.id()).or_insert(0.0) += score * share;
+        }
+    }
+
+    // Mark down the elements that are mostly links
+    let mut scored: Vec<(ElementRef, f64)> = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let elem = ElementRef::wrap(doc.tree.get(id)?)?;
+            let text_len = element_text(elem).chars().count().max(1);
+            let link_len: usize = elem
+                .select(&links)
+                .map(|a| element_text(a).chars().count())
+                .sum();
+            let link_density = (link_len as f64 / text_len as f64).min(1.0);
+            Some((elem, score * (1.0 - link_density)))
+        })
+        .collect();
+    scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    let mut picked: Vec<ElementRef> = Vec::new();
+    let mut candidates = Vec::new();
+    for (elem, score) in scored {
+        if candidates.len() >= max {
+            break;
+        }
+        let overlaps = picked.iter().any(|p| {
+            p.ancestors().any(|a| a.id() == elem.id()) || elem.ancestors().any(|a| a.id() == p.id())
+        });
+        if score <= 0.0 || overlaps {
+            continue;
+        }
+
+        // The selector might match an earlier element than this one. Use the text the selector
+        // actually gets, since that's what the user would get if they picked it.
+        let selector = selector_for(doc, elem);
+        let text = match select_in(doc, &selector) {
+            Ok(text) if !text.is_empty() => text,
+            _ => continue,
+        };
+        if candidates.iter().any(|c: &Candidate| c.text == text) {
+            continue;
+        }
+
+        picked.push(elem);
+        candidates.push(Candidate {
+            selector,
+            text,
+            score,
+        });
+    }
+
+    candidates
+}
+
+/// Returns how sure extraction is of its pick, from 0 to 1. It's unsure when the best two parts of
+/// the page score about as well as each other, or when the extracted text is much shorter than the
+/// best-scoring part of the page.
+fn confidence(candidates: &[Candidate], extracted_words: usize) -> f64 {
+    let separation = match candidates {
+        [best, second, ..] if best.score > 0.0 => 1.0 - second.score / best.score,
+        _ => 1.0,
+    };
+    let coverage = match candidates.first().map(|c| word_count(&c.text)) {
+        Some(best_words) if best_words > 0 => (extracted_words as f64 / best_words as f64).min(1.0),
+        _ => 1.0,
+    };
+
+    separation.min(coverage)
+}
+
+/// Returns whether the given character is from a script that doesn't put spaces between words,
+/// i.e., Chinese characters and Japanese kana
+fn is_unspaced(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}')
+}
+
+/// Returns the number of words in the given text. Each Chinese character or Japanese kana counts as
+/// a word, since there are no spaces to count by, and a character is about a word's worth of
+/// reading.
+pub(crate) fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .map(|w| w.chars().filter(|&c| is_unspaced(c)).count().max(1))
+        .sum()
+}
+
+/// Returns whether the given extracted text looks like an article, rather than, e.g., a menu or a
+/// cookie banner. It has to be long enough, and mostly sizeable paragraphs.
+pub(crate) fn looks_like_article(text: &str) -> bool {
+    let words = word_count(text);
+    let prose_words: usize = text
+        .lines()
+        .filter(|line| line.chars().count() >= MIN_PARAGRAPH_LEN)
+        .map(word_count)
+        .sum();
+    words >= MIN_ARTICLE_WORDS && prose_words as f64 >= MIN_PROSE_SHARE * words as f64
+}
+
+/// Returns the text of the part of the given page with the most prose, the way Readability finds
+/// it, if the page has any prose
+pub(crate) fn readability_text(html: &[u8]) -> Option<String> {
+    let doc = Html::parse_document(&String::from_utf8_lossy(html));
+    find_candidates(&doc, 1).pop().map(|c| c.text)
+}
+
+/// Makes the client-facing description of a candidate text
+fn to_extraction_candidate(selector: Option<String>, text: &str) -> ExtractionCandidate {
+    ExtractionCandidate {
+        selector,
+        preview: text.chars().take(PREVIEW_LEN).collect(),
+        words: word_count(text),
+    }
+}
+
+/// Returns the parts of the given page, besides `extracted_text`, that might be the body of the
+/// article
+fn alternatives_in(candidates: Vec<Candidate>, extracted_text: &str) -> Vec<ExtractionCandidate> {
+    candidates
+        .into_iter()
+        .filter(|c| c.text != extracted_text)
+        .take(MAX_ALTERNATIVES)
+        .map(|c| to_extraction_candidate(Some(c.selector), &c.text))
+        .collect()
+}
+
+/// Returns the parts of the given page, besides `extracted_text`, that might be the body of the
+/// article
+pub(crate) fn alternatives(html: &[u8], extracted_text: &str) -> Vec<ExtractionCandidate> {
+    let doc = Html::parse_document(&String::from_utf8_lossy(html));
+    alternatives_in(find_candidates(&doc, MAX_ALTERNATIVES + 1), extracted_text)
+}
+
+/// Checks how sure extraction is that `extracted_text` is the body of the article on the given
+/// page. If it's unsure, or the text doesn't look like an article at all, returns the choice to put
+/// to the user: the extracted text, and the other parts of the page that might be the body.
+pub(crate) fn check_extraction(html: &[u8], extracted_text: &str) -> Option<ExtractionChoice> {
+    let doc = Html::parse_document(&String::from_utf8_lossy(html));
+    let candidates = find_candidates(&doc, MAX_ALTERNATIVES + 1);
+
+    let confidence = confidence(&candidates, word_count(extracted_text));
+    if confidence >= MIN_CONFIDENCE && looks_like_article(extracted_text) {
+        return None;
+    }
+
+    let extracted = to_extraction_candidate(None, extracted_text);
+    let alternatives = alternatives_in(candidates, extracted_text);
+    Some(ExtractionChoice {
+        confidence,
+        candidates: std::iter::once(extracted).chain(alternatives).collect(),
+    })
+}
+
+/// Puts the paragraphs of the given extracted text back in the order they're in on the given page.
+/// The page's order is the one it's meant to be read in, even when it's laid out in columns, but
+/// extractors can move paragraphs, e.g., when they pick them out of a page's columns separately.
+/// Paragraphs are found on the page by their text. Short ones, and ones that aren't found, stay
+/// after the paragraph they came after. The text is left as it is if it's in order, or if too
+/// little of it can be found on the page.
+pub(crate) fn restore_page_order(html: &[u8], text: &str) -> String {
+    let doc = Html::parse_document(&String::from_utf8_lossy(html));
+    let page_text = element_text(doc.root_element());
+    let paragraphs: Vec<&str> = text.lines().collect();
+
+    // Find each paragraph on the page, after the last one if it's there
+    let mut positions = Vec::with_capacity(paragraphs.len());
+    let (mut searched, mut found, mut after) = (0, 0, 0);
+    let mut in_order = true;
+    for para in &paragraphs {
+        let para = para.split_whitespace().collect::<Vec<_>>().join(" ");
+        if para.chars().count() < MIN_PARAGRAPH_LEN {
+            positions.push(None);
+            continue;
+        }
+        searched += 1;
+        let pos = match page_text[after..].find(&para) {
+            Some(pos) => Some(after + pos),
+            None => {
+                let pos = page_text.find(&para);
+                in_order &= pos.is_none();
+                pos
+            }
+        };
+        if let Some(pos) = pos {
+            found += 1;
+            after = pos + para.len();
+        }
+        positions.push(pos);
+    }
+    if in_order || (found as f64) < MIN_FOUND_SHARE * searched as f64 {
+        return text.to_string();
+    }
+
+    // Paragraphs that weren't found go where the paragraph before them does
+    let mut last = 0;
+    let keys: Vec<usize> = positions
+        .iter()
+        .map(|pos| {
+            last = pos.unwrap_or(last);
+            last
+        })
+        .collect();
+    let mut ordered: Vec<(usize, &str)> = keys.into_iter().zip(paragraphs).collect();
+    ordered.sort_by_key(|(key, _)| *key);
+    ordered
+        .into_iter()
+        .map(|(_, para)| para)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the text of the first element in the document that matches the given selector
+fn select_in(doc: &Html, selector: &str) -> Result<String, AnyError> {
+    let parsed = Selector::parse(selector).map_err(|_| anyhow!("Invalid selector '{selector}'"))?;
+    doc.select(&parsed)
+        .next()
+        .map(element_text)
+        .ok_or_else(|| anyhow!("Nothing on the page matches '{selector}'"))
+}
+
+/// Returns the text of the first element on the given page that matches the given CSS selector
+pub(crate) fn select_text(html: &[u8], selector: &str) -> Result<String, AnyError> {
+    let doc = Html::parse_document(&String::from_utf8_lossy(html));
+    select_in(&doc, selector)
+}
+
+/// Runs trafilatura on the given HTML and returns the extracted title and body
+pub(crate) async fn extract(html: &[u8]) -> Result<ExtractedArticle, AnyError> {
+    // TODO: Check earlier that trafilatura is present
+    let mut article = Trafilatura::default().run(html).await?;
+    article.text = restore_page_order(html, &article.text);
+    Ok(article)
+}
+
+#[test]
+fn retention_parsing() {
+    assert_eq!(
+        "never".parse::<HtmlRetention>().unwrap(),
+        HtmlRetention::Never
+    );
+    assert_eq!(
+        "forever".parse::<HtmlRetention>().unwrap(),
+        HtmlRetention::Forever
+    );
+    assert_eq!(
+        "30".parse::<HtmlRetention>().unwrap(),
+        HtmlRetention::Days(30)
+    );
+    assert_eq!(
+        "7d".parse::<HtmlRetention>().unwrap(),
+        HtmlRetention::Days(7)
+    );
+    assert!("soon".parse::<HtmlRetention>().is_err());
+}
+
+#[test]
+fn uncertain_extraction() {
+    let paragraph = |words: &str| format!("<p>{}</p>", [words; 12].join(", "));
+    let story = paragraph("the ship sailed on");
+    let comments = paragraph("great article thanks");
+    let html = format!(
+        r#"<html><body>
+        <nav><a href="/">Home</a></nav>
+        <div class="story">{story}{story}{story}</div>
+        <div id="comments" class="thread">{comments}{comments}{comments}</div>
+        </body></html>"#
+    );
+
+    // The story and the comments look alike, so extraction is unsure and offers both
+    let choice = check_extraction(html.as_bytes(), "the ship sailed on").unwrap();
+    assert!(choice.confidence < MIN_CONFIDENCE);
+    assert_eq!(choice.candidates[0].selector, None);
+    let selectors: Vec<_> = choice.candidates[1..]
+        .iter()
+        .map(|c| c.selector.as_deref().unwrap())
+        .collect();
+    assert!(selectors.contains(&"div.story"));
+    assert!(selectors.contains(&"div#comments"));
+
+    // Picking one gets its text
+    let text = select_text(html.as_bytes(), "div#comments").unwrap();
+    assert!(text.starts_with("great article thanks"));
+    assert_eq!(text.lines().count(), 3);
+    assert!(select_text(html.as_bytes(), "div.missing").is_err());
+
+    // With only one likely part, extraction that found all of it is sure
+    let html = format!("<html><body><div class=\"story\">{story}{story}</div></body></html>");
+    let doc = Html::parse_document(&html);
+    let extracted = select_in(&doc, "div.story").unwrap();
+    assert!(check_extraction(html.as_bytes(), &extracted).is_none());
+}
+
+#[test]
+fn recognizing_articles() {
+    let paragraph = "The ship sailed on through the night, and nobody aboard slept a wink.";
+    let article = [paragraph; 8].join("\n");
+    assert!(looks_like_article(&article));
+
+    // Menus are mostly short lines, and banners are short
+    let menu = ["Home", "World", "Politics", "Business", "Sport"]
+        .repeat(20)
+        .join("\n");
+    assert!(!looks_like_article(&menu));
+    assert!(!looks_like_article(
+        "We use cookies to improve your experience. By continuing, you agree to our use of cookies."
+    ));
+
+    // Readability finds the part of the page with the most prose, and not the menu
+    let html = format!(
+        "<html><body><ul><li><a href=\"/\">Home</a></li></ul><div class=\"story\">{}</div></body></html>",
+        [paragraph; 8].map(|p| format!("<p>{p}</p>")).concat()
+    );
+    assert_eq!(readability_text(html.as_bytes()).unwrap(), article);
+    assert_eq!(
+        readability_text(b"<html><body><nav>Home</nav></body></html>"),
+        None
+    );
+}
+
+#[test]
+fn counting_words() {
+    assert_eq!(word_count("The cat sat on the mat."), 6);
+    assert_eq!(word_count("مرحبا بالعالم"), 2);
+    assert_eq!(word_count("今天天气很好。 Hello"), 7);
+}
+
+#[test]
+fn page_order() {
+    let para = |n: &str| format!("This is the {n} paragraph of the article, about fish.");
+    let (first, second, third) = (para("first"), para("second"), para("third"));
+    let html = format!(
+        "<html><body><article><p>{first}</p><p>{second}</p><p>{third}</p></article></body></html>"
+    );
+
+    // Paragraphs that were moved go back where they are on the page, and short paragraphs, and
+    // ones that aren't on the page, stay after the one they came after
+    let text = [third.as_str(), "Caption", first.as_str(), second.as_str()].join("\n");
+    let expected = [first.as_str(), second.as_str(), third.as_str(), "Caption"].join("\n");
+    assert_eq!(restore_page_order(html.as_bytes(), &text), expected);
+
+    // Text that's in order is left alone, even with paragraphs that aren't on the page
+    let text = [
+        first.as_str(),
+        "An added line that isn't on the page",
+        third.as_str(),
+    ]
+    .join("\n");
+    assert_eq!(restore_page_order(html.as_bytes(), &text), text);
+
+    // So is text that's mostly not on the page
+    let other = |n: &str| format!("Something else entirely, the {n} of them.");
+    let text = [
+        other("first"),
+        third.clone(),
+        other("second"),
+        first.clone(),
+    ]
+    .join("\n");
+    assert_eq!(restore_page_order(html.as_bytes(), &text), text);
+}
@@ -0,0 +1,426 @@
+//! Subscriptions to the shared collections of other ReadToMyShoe servers. Someone on another server
+//! shares their collection, and gives the link to the listener, who subscribes to it here. The
+//! articles in it are listed alongside the library, and copied to it when the listener asks, audio,
+//! ID3 tags, and all. Nothing is copied without asking, and a collection whose link expires or is
+//! revoked lists nothing more. See `shares.rs` for the other side.
+//!
+//! Other servers are reached under the same outbound policy as the pages articles come from, and
+//! audio is only copied from the server that shares the collection.
+
+use crate::{
+    accounts::LibraryDir,
+    export::is_valid_id,
+    fetcher::{ErrorStatus, Fetcher},
+    json_store::JsonStore,
+    util::get_metadata,
+};
+use common::{
+    ArticleMetadata, CollectionSubscription, RemoteArticle, RemoteCollection, RemoteCopyRequest,
+    SharedCollection,
+};
+
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+/// The path of a link to a collection, on the server that shares it
+const COLLECTION_PATH: &str = "/shared/collection.json";
+
+/// The most bytes of audio that are copied for one article. Hours of speech are well under this.
+const MAX_AUDIO_BYTES: usize = 500 * 1024 * 1024;
+
+/// A subscription to a collection, as it's saved
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Subscription {
+    /// The library that's subscribed
+    library_dir: String,
+    /// The link to the collection
+    url: String,
+    /// The collection's name, as of the last time it was fetched
+    name: String,
+}
+
+/// The subscriptions of every library. These are saved as a JSON list.
+#[derive(Clone)]
+pub(crate) struct SubscriptionStore {
+    subs: JsonStore<Vec<Subscription>>,
+}
+
+impl SubscriptionStore {
+    /// Loads the subscriptions from the given file. If the file doesn't exist, there are no
+    /// subscriptions yet.
+    pub(crate) fn load(path: &str) -> Result<SubscriptionStore, AnyError> {
+        Ok(SubscriptionStore {
+            subs: JsonStore::load(path, "collection subscriptions")?,
+        })
+    }
+
+    /// Returns the subscriptions of the given library
+    fn list(&self, library_dir: &str) -> Vec<Subscription> {
+        self.subs.read(|subs| {
+            subs.iter()
+                .filter(|sub| sub.library_dir == library_dir)
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Returns whether the given library is subscribed to the collection at the given link
+    fn contains(&self, library_dir: &str, url: &str) -> bool {
+        self.list(library_dir).iter().any(|sub| sub.url == url)
+    }
+
+    /// Subscribes the given library to the given collection, or updates the collection's name if
+    /// it's subscribed already
+    async fn subscribe(&self, library_dir: &str, url: &str, name: &str) -> Result<(), AnyError> {
+        self.subs
+            .update(|subs| {
+                match subs
+                    .iter_mut()
+                    .find(|sub| sub.library_dir == library_dir && sub.url == url)
+                {
+                    Some(sub) => sub.name = name.to_string(),
+                    None => subs.push(Subscription {
+                        library_dir: library_dir.to_string(),
+                        url: url.to_string(),
+                        name: name.to_string(),
+                    }),
+                }
+            })
+            .await
+    }
+
+    /// Unsubscribes the given library from the collection at the given link
+    async fn unsubscribe(&self, library_dir: &str, url: &str) -> Result<(), AnyError> {
+        self.subs
+            .update(|subs| subs.retain(|sub| !(sub.library_dir == library_dir && sub.url == url)))
+            .await
+    }
+
+    /// Returns the subscriptions of the given library as JSON, for a takeout
+    pub(crate) fn take_out(&self, library_dir: &str) -> Result<Vec<u8>, AnyError> {
+        Ok(serde_json::to_vec_pretty(&self.list(library_dir))?)
+    }
+
+    /// Unsubscribes the given library from every collection
+    pub(crate) async fn forget_library(&self, library_dir: &str) -> Result<(), AnyError> {
+        self.subs
+            .update(|subs| subs.retain(|sub| sub.library_dir != library_dir))
+            .await
+    }
+}
+
+// Sets the /api/remote-collections, /api/subscribe-collection, /api/unsubscribe-collection, and
+// /api/copy-remote-article routes
+pub(crate) fn setup(router: Router, sub_store: SubscriptionStore, fetcher: Fetcher) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/remote-collections", get(remote_collections))
+            .route("/subscribe-collection", post(subscribe_collection))
+            .route("/unsubscribe-collection", post(unsubscribe_collection))
+            .route("/copy-remote-article", post(copy_remote_article))
+            .layer(Extension(sub_store))
+            .layer(Extension(fetcher)),
+    )
+}
+
+/// Checks that the given URL is a link to a shared collection, and returns it parsed
+fn parse_collection_url(url: &str) -> Result<Url, AnyError> {
+    let parsed = Url::parse(url.trim()).map_err(|e| anyhow!("Not a URL: {e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.path() != COLLECTION_PATH {
+        bail!("Not a link to a shared collection. Those end in {COLLECTION_PATH}?share=...");
+    }
+    Ok(parsed)
+}
+
+/// Fetches the collection at the given link
+async fn fetch_collection(fetcher: &Fetcher, url: &str) -> Result<SharedCollection, AnyError> {
+    let url = parse_collection_url(url)?;
+    let fetched = fetcher
+        .fetch(url.as_str(), fetcher.max_len())
+        .await
+        .map_err(|e| match e.downcast_ref::<ErrorStatus>() {
+            Some(e) if e.status == reqwest::StatusCode::FORBIDDEN => {
+                anyhow!("The link to the collection expired or was revoked")
+            }
+            _ => anyhow!("Could not fetch the collection: {e}"),
+        })?;
+    serde_json::from_slice(&fetched.body)
+        .map_err(|e| anyhow!("Could not parse the collection: {e}"))
+}
+
+/// Returns the URL of an article's audio, from its link in the collection at the given URL. Links
+/// are relative to the server that shares the collection, and can't point anywhere else.
+fn audio_url(collection_url: &Url, link: &str) -> Result<Url, AnyError> {
+    let url = collection_url
+        .join(link)
+        .map_err(|e| anyhow!("Bad link to the article: {e}"))?;
+    if url.origin() != collection_url.origin() {
+        bail!("The link to the article is to another server");
+    }
+    Ok(url)
+}
+
+/// Returns the path of the given article's audio in the given library
+fn audio_path(library_dir: &str, id: &str) -> PathBuf {
+    Path::new(library_dir).join(id).with_extension("mp3")
+}
+
+/// Returns whether the given bytes start like an MP3, i.e., with ID3 tags or an MPEG frame
+fn looks_like_mp3(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"ID3") || matches!(bytes, [0xFF, b, ..] if b & 0xE0 == 0xE0)
+}
+
+/// Returns whether the file at the given path starts like an MP3
+fn file_looks_like_mp3(path: &Path) -> Result<bool, AnyError> {
+    let mut start = Vec::new();
+    File::open(path)
+        .and_then(|f| f.take(3).read_to_end(&mut start))
+        .map_err(|e| anyhow!("Could not read {:?}: {e}", path))?;
+    Ok(looks_like_mp3(&start))
+}
+
+/// Lists the articles of the given collection, as the given library sees them
+fn list_articles(library_dir: &str, collection: &SharedCollection) -> Vec<RemoteArticle> {
+    collection
+        .articles
+        .iter()
+        .filter(|link| is_valid_id(&link.article_id))
+        .map(|link| RemoteArticle {
+            id: link.article_id.clone(),
+            title: link.title.clone(),
+            expires: link.expires,
+            in_library: audio_path(library_dir, &link.article_id).exists(),
+        })
+        .collect()
+}
+
+/// Fetches every collection the library is subscribed to. The ones that can't be fetched are
+/// listed with the error.
+async fn remote_collections(
+    Extension(sub_store): Extension<SubscriptionStore>,
+    Extension(fetcher): Extension<Fetcher>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<Vec<RemoteCollection>> {
+    let fetches = sub_store.list(&library_dir).into_iter().map(|sub| async {
+        match fetch_collection(&fetcher, &sub.url).await {
+            Ok(collection) => {
+                // Keep the name up to date, so it's right when the collection can't be fetched
+                if collection.name != sub.name {
+                    let _ = sub_store
+                        .subscribe(&library_dir, &sub.url, &collection.name)
+                        .await
+                        .map_err(|e| tracing::error!("{e}"));
+                }
+                RemoteCollection {
+                    articles: list_articles(&library_dir, &collection),
+                    url: sub.url,
+                    name: collection.name,
+                    error: None,
+                }
+            }
+            Err(e) => RemoteCollection {
+                url: sub.url,
+                name: sub.name,
+                articles: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        }
+    });
+    Json(futures::future::join_all(fetches).await)
+}
+
+/// Subscribes the library to the collection at the given link, once it's checked that the link
+/// works
+async fn subscribe_collection(
+    Json(CollectionSubscription { url }): Json<CollectionSubscription>,
+    Extension(sub_store): Extension<SubscriptionStore>,
+    Extension(fetcher): Extension<Fetcher>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<RemoteCollection>, (StatusCode, String)> {
+    let url = url.trim().to_string();
+    let collection = fetch_collection(&fetcher, &url)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    sub_store
+        .subscribe(&library_dir, &url, &collection.name)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    Ok(Json(RemoteCollection {
+        articles: list_articles(&library_dir, &collection),
+        url,
+        name: collection.name,
+        error: None,
+    }))
+}
+
+/// Unsubscribes the library from the collection at the given link. Articles copied from it stay.
+async fn unsubscribe_collection(
+    Json(CollectionSubscription { url }): Json<CollectionSubscription>,
+    Extension(sub_store): Extension<SubscriptionStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match sub_store.unsubscribe(&library_dir, &url).await {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("{e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Copies the given article from a subscribed collection to the library, and returns its metadata
+async fn copy_remote_article(
+    Json(req): Json<RemoteCopyRequest>,
+    Extension(sub_store): Extension<SubscriptionStore>,
+    Extension(fetcher): Extension<Fetcher>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<ArticleMetadata>, (StatusCode, String)> {
+    if !sub_store.contains(&library_dir, &req.collection_url) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Not subscribed to that collection".to_string(),
+        ));
+    }
+    if !is_valid_id(&req.article_id) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid article ID".to_string()));
+    }
+    if audio_path(&library_dir, &req.article_id).exists() {
+        return Err((
+            StatusCode::CONFLICT,
+            "The article is in the library already".to_string(),
+        ));
+    }
+
+    copy_article(&fetcher, &library_dir, &req.collection_url, &req.article_id)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Could not copy {}: {e}", req.article_id);
+            (StatusCode::BAD_GATEWAY, e.to_string())
+        })
+}
+
+/// The real logic. Downloads the given article's audio through its link in the given collection,
+/// and saves it to the given library.
+async fn copy_article(
+    fetcher: &Fetcher,
+    library_dir: &str,
+    collection_url: &str,
+    article_id: &str,
+) -> Result<ArticleMetadata, AnyError> {
+    // Get a fresh link, in case the one the listener saw has expired since
+    let collection = fetch_collection(fetcher, collection_url).await?;
+    let link = collection
+        .articles
+        .iter()
+        .find(|link| link.article_id == article_id)
+        .ok_or_else(|| anyhow!("The article isn't shared anymore"))?;
+    let audio_url = audio_url(&parse_collection_url(collection_url)?, &link.url)?;
+
+    // Write to a temp file first, so list-articles doesn't read it half-written
+    let savepath = audio_path(library_dir, article_id);
+    let tmp_savepath = savepath.with_extension("mp3.tmp");
+    let downloaded = fetcher
+        .download(audio_url.as_str(), MAX_AUDIO_BYTES, &tmp_savepath)
+        .await
+        .map_err(|e| anyhow!("Could not fetch the audio: {e}"))
+        .and_then(|()| match file_looks_like_mp3(&tmp_savepath)? {
+            true => Ok(()),
+            false => Err(anyhow!("The other server didn't send MP3 audio")),
+        });
+    if let Err(e) = downloaded {
+        let _ = std::fs::remove_file(&tmp_savepath);
+        return Err(e);
+    }
+    std::fs::rename(&tmp_savepath, &savepath)
+        .map_err(|e| anyhow!("could not rename {:?} to {:?}: {e}", tmp_savepath, savepath))?;
+    tracing::info!(
+        "Copied {article_id} from the collection {}",
+        collection.name
+    );
+
+    get_metadata(&savepath)
+}
+
+#[test]
+fn collection_links() {
+    let url = "https://other.example.com/shared/collection.json?share=a&expires=1&sig=b";
+    assert!(parse_collection_url(url).is_ok());
+    assert!(parse_collection_url("https://other.example.com/shared/fish.mp3?share=a").is_err());
+    assert!(parse_collection_url("file:///shared/collection.json").is_err());
+
+    // Links to articles are relative to the server that shares them, and can't leave it
+    let collection_url = parse_collection_url(url).unwrap();
+    assert_eq!(
+        audio_url(
+            &collection_url,
+            "/shared/fish-abc.mp3?share=c&expires=2&sig=d"
+        )
+        .unwrap()
+        .as_str(),
+        "https://other.example.com/shared/fish-abc.mp3?share=c&expires=2&sig=d"
+    );
+    for link in [
+        "https://intranet.example.com/secret.mp3",
+        "//169.254.169.254/latest/meta-data/",
+        "http://other.example.com/shared/fish-abc.mp3",
+        "https://other.example.com:8443/shared/fish-abc.mp3",
+    ] {
+        assert!(audio_url(&collection_url, link).is_err(), "{link}");
+    }
+
+    // Articles can only be copied under IDs that stay in the library
+    assert!(is_valid_id("fish-abc"));
+    for id in ["", "../fish", ".hidden", "a/b", "a\\b"] {
+        assert!(!is_valid_id(id), "{id}");
+    }
+
+    assert!(looks_like_mp3(b"ID3\x04\x00"));
+    assert!(looks_like_mp3(&[0xFF, 0xFB, 0x90]));
+    assert!(!looks_like_mp3(b"<!DOCTYPE html>"));
+}
+
+#[tokio::test]
+async fn subscriptions() {
+    let path = std::env::temp_dir().join(format!("rtms-subs-test-{}.json", std::process::id()));
+    let store = SubscriptionStore::load(path.to_str().unwrap()).unwrap();
+    let url = "https://other.example.com/shared/collection.json?share=a&expires=1&sig=b";
+
+    // Subscribing again just renames the collection, and each library has its own subscriptions
+    store.subscribe("library", url, "Picks").await.unwrap();
+    store
+        .subscribe("library", url, "Weekend reads")
+        .await
+        .unwrap();
+    let store = SubscriptionStore::load(path.to_str().unwrap()).unwrap();
+    let subs = store.list("library");
+    assert_eq!(subs.len(), 1);
+    assert_eq!(subs[0].name, "Weekend reads");
+    assert!(store.contains("library", url));
+    assert!(!store.contains("other library", url));
+
+    store.unsubscribe("other library", url).await.unwrap();
+    assert!(store.contains("library", url));
+    store.unsubscribe("library", url).await.unwrap();
+    assert!(store.list("library").is_empty());
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,633 @@
+//! Feed subscriptions, which make the library work like a podcast of the listener's favorite blogs.
+//! The listener subscribes to RSS or Atom feeds, one at a time or by importing an OPML file. A
+//! background task polls the feeds, and converts every entry it hasn't seen before into an article.
+//!
+//! A few feeds are polled at a time. Feeds are fetched conditionally, with the ETag and
+//! Last-Modified they last came with, so a feed that hasn't changed costs next to nothing. A feed
+//! that fails is polled less and less often, up to `MAX_BACKOFF_DOUBLINGS` intervals apart, until it
+//! works again. Nothing's polled in the listener's quiet hours, since converting is heavy work.
+//!
+//! Every library has its own subscriptions, and their entries are converted into it.
+
+use crate::{
+    accounts::LibraryDir,
+    add_article::{AddArticleError, Converter},
+    fetcher::{Fetcher, Validators},
+    json_store::JsonStore,
+    reminders::ReminderStore,
+    util::now_secs,
+};
+use common::{FeedSubmission, FeedSubscription};
+
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use futures::stream::{self, StreamExt};
+use roxmltree::{Document, Node, ParsingOptions};
+use serde::{Deserialize, Serialize};
+
+/// The number of a feed's newest entries that are converted when it's subscribed to. The rest of
+/// the entries it has at that point are skipped.
+const FEED_BACKFILL: usize = 1;
+
+/// The most entries converted from one feed per poll. This keeps a feed that suddenly lists its
+/// whole archive from using up the TTS quota.
+const MAX_NEW_PER_POLL: usize = 5;
+
+/// The most feeds polled at once
+const MAX_CONCURRENT_POLLS: usize = 4;
+
+/// How many times the time between polls of a failing feed can double
+const MAX_BACKOFF_DOUBLINGS: u32 = 6;
+
+/// A feed the server polls, as saved
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Subscription {
+    /// The directory of the library that subscribed to the feed
+    library_dir: String,
+    url: String,
+    title: Option<String>,
+    /// The keys of the entries that have been converted or skipped
+    seen: BTreeSet<String>,
+    last_polled: Option<u64>,
+    /// What the feed was last fetched with, for fetching it conditionally
+    #[serde(default)]
+    validators: Validators,
+    #[serde(default)]
+    last_success: Option<u64>,
+    #[serde(default)]
+    error_streak: u32,
+    #[serde(default)]
+    last_error: Option<String>,
+}
+
+impl Subscription {
+    /// Returns whether this is the given library's subscription to the feed at the given URL
+    fn is(&self, library_dir: &str, url: &str) -> bool {
+        self.library_dir == library_dir && self.url == url
+    }
+
+    /// Returns whether the feed should be polled at the given time, given how often feeds are
+    /// polled. Failing feeds wait twice as long after every failure.
+    fn is_due(&self, now: u64, interval: Duration) -> bool {
+        let backoff = interval.as_secs() << self.error_streak.min(MAX_BACKOFF_DOUBLINGS);
+        // The poller ticks every interval, so a little early is as good as on time
+        let wait = backoff.saturating_sub(interval.as_secs());
+        self.error_streak == 0 || self.last_polled.is_none_or(|t| now >= t + wait)
+    }
+}
+
+impl From<&Subscription> for FeedSubscription {
+    fn from(sub: &Subscription) -> FeedSubscription {
+        FeedSubscription {
+            url: sub.url.clone(),
+            title: sub.title.clone(),
+            last_polled: sub.last_polled,
+            last_success: sub.last_success,
+            error_streak: sub.error_streak,
+            last_error: sub.last_error.clone(),
+        }
+    }
+}
+
+/// An entry in a feed
+#[derive(Debug, PartialEq, Eq)]
+struct FeedEntry {
+    /// What identifies the entry: its GUID or ID if it has one, else its link
+    key: String,
+    /// The URL of the entry's article
+    link: String,
+}
+
+/// The parts of a feed we care about
+#[derive(Debug)]
+struct ParsedFeed {
+    title: Option<String>,
+    /// The feed's entries, in the order the feed lists them, which is usually newest first
+    entries: Vec<FeedEntry>,
+}
+
+/// The feed subscriptions of every library. These are saved as a JSON list.
+#[derive(Clone)]
+pub(crate) struct FeedStore {
+    subs: JsonStore<Vec<Subscription>>,
+}
+
+impl FeedStore {
+    /// Loads the subscriptions from the given file. If the file doesn't exist, there are no
+    /// subscriptions yet.
+    pub(crate) fn load(path: &str) -> Result<FeedStore, AnyError> {
+        Ok(FeedStore {
+            subs: JsonStore::load(path, "feed subscriptions")?,
+        })
+    }
+
+    /// Runs the given function on the subscriptions, and saves them afterwards
+    async fn update<T>(&self, f: impl FnOnce(&mut Vec<Subscription>) -> T) -> Result<T, AnyError> {
+        self.subs.update(f).await
+    }
+
+    /// Returns every subscription of the given library
+    fn list(&self, library_dir: &str) -> Vec<FeedSubscription> {
+        self.subs.read(|subs| {
+            subs.iter()
+                .filter(|sub| sub.library_dir == library_dir)
+                .map(Into::into)
+                .collect()
+        })
+    }
+
+    /// Returns the given library's subscriptions, with the entries seen in each, as JSON for a
+    /// takeout
+    pub(crate) fn take_out(&self, library_dir: &str) -> Result<Vec<u8>, AnyError> {
+        let subs: Vec<Subscription> = self.subs.read(|subs| {
+            subs.iter()
+                .filter(|sub| sub.library_dir == library_dir)
+                .cloned()
+                .collect()
+        });
+        Ok(serde_json::to_vec_pretty(&subs)?)
+    }
+
+    /// Unsubscribes the given library from every feed
+    pub(crate) async fn forget_library(&self, library_dir: &str) -> Result<(), AnyError> {
+        self.update(|subs| subs.retain(|sub| sub.library_dir != library_dir))
+            .await
+    }
+
+    /// Returns whether the given library is subscribed to the feed at the given URL
+    fn contains(&self, library_dir: &str, url: &str) -> bool {
+        self.subs
+            .read(|subs| subs.iter().any(|sub| sub.is(library_dir, url)))
+    }
+
+    /// Returns the library directories and URLs of the subscriptions that should be polled now,
+    /// given how often feeds are polled. Libraries in their quiet hours, according to the given
+    /// function, are left out.
+    fn due(&self, interval: Duration, is_quiet: impl Fn(&str) -> bool) -> Vec<(String, String)> {
+        let now = now_secs();
+        self.subs.read(|subs| {
+            subs.iter()
+                .filter(|sub| sub.is_due(now, interval) && !is_quiet(&sub.library_dir))
+                .map(|sub| (sub.library_dir.clone(), sub.url.clone()))
+                .collect()
+        })
+    }
+
+    /// Records that the given library's subscription to the feed at the given URL was just polled,
+    /// with the given result. A failed poll adds to the feed's error streak, and a successful one
+    /// ends it. Returns the subscription, or `None` if it's been unsubscribed from.
+    async fn record_poll(
+        &self,
+        library_dir: &str,
+        url: &str,
+        res: Result<(), String>,
+    ) -> Result<Option<Subscription>, AnyError> {
+        self.update(|subs| {
+            let sub = subs.iter_mut().find(|sub| sub.is(library_dir, url))?;
+            let now = now_secs();
+            sub.last_polled = Some(now);
+            match res {
+                Ok(()) => {
+                    sub.last_success = Some(now);
+                    sub.error_streak = 0;
+                    sub.last_error = None;
+                }
+                Err(e) => {
+                    sub.error_streak += 1;
+                    sub.last_error = Some(e);
+                }
+            }
+            Some(sub.clone())
+        })
+        .await
+    }
+
+    /// Records that the given entry of the given library's feed has been dealt with
+    async fn mark_seen(&self, library_dir: &str, url: &str, key: &str) -> Result<(), AnyError> {
+        self.update(|subs| {
+            if let Some(sub) = subs.iter_mut().find(|sub| sub.is(library_dir, url)) {
+                sub.seen.insert(key.to_string());
+            }
+        })
+        .await
+    }
+}
+
+// Sets the /api/list-feeds, /api/subscribe-feed, /api/unsubscribe-feed, and /api/import-opml
+// routes
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    feed_store: FeedStore,
+    converter: Converter,
+) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/list-feeds", get(list_feeds))
+            .route("/subscribe-feed", post(subscribe_feed))
+            .route("/unsubscribe-feed", post(unsubscribe_feed))
+            .route("/import-opml", post(import_opml))
+            .layer(Extension(feed_store))
+            .layer(Extension(converter))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Returns the text of the first child of the given node with the given local name, if it isn't
+/// empty
+fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Parses the given XML into a document. Feeds sometimes come with a DTD, so that's allowed.
+fn parse_xml(xml: &str) -> Result<Document<'_>, AnyError> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    Document::parse_with_options(xml, options).map_err(|e| anyhow!("Invalid XML: {e}"))
+}
+
+/// Parses an RSS (1.0 or 2.0) or Atom feed. Elements are matched by their local names alone, since
+/// the versions put them in different namespaces.
+fn parse_feed(xml: &str) -> Result<ParsedFeed, AnyError> {
+    let doc = parse_xml(xml)?;
+    let root = doc.root_element();
+    let is_named = |name: &'static str| move |node: &Node| node.tag_name().name() == name;
+
+    match root.tag_name().name() {
+        "feed" => {
+            // Atom entries link to their article with an alternate link, which is the default
+            let entries = root
+                .children()
+                .filter(is_named("entry"))
+                .filter_map(|entry| {
+                    let link = entry
+                        .children()
+                        .filter(is_named("link"))
+                        .find(|link| matches!(link.attribute("rel"), None | Some("alternate")))
+                        .and_then(|link| link.attribute("href"))?
+                        .to_string();
+                    let key = child_text(entry, "id").unwrap_or_else(|| link.clone());
+                    Some(FeedEntry { key, link })
+                })
+                .collect();
+            Ok(ParsedFeed {
+                title: child_text(root, "title"),
+                entries,
+            })
+        }
+        "rss" | "RDF" => {
+            // In RSS 2.0 the items are in the channel, and in RSS 1.0 they're next to it
+            let title = root
+                .descendants()
+                .find(is_named("channel"))
+                .and_then(|channel| child_text(channel, "title"));
+            let entries = root
+                .descendants()
+                .filter(is_named("item"))
+                .filter_map(|item| {
+                    let link = child_text(item, "link")?;
+                    let key = child_text(item, "guid").unwrap_or_else(|| link.clone());
+                    Some(FeedEntry { key, link })
+                })
+                .collect();
+            Ok(ParsedFeed { title, entries })
+        }
+        other => bail!("Not an RSS or Atom feed: the root element is <{other}>"),
+    }
+}
+
+/// Returns the URLs of the feeds listed in the given OPML file
+fn parse_opml(xml: &str) -> Result<Vec<String>, AnyError> {
+    let doc = parse_xml(xml)?;
+    Ok(doc
+        .descendants()
+        .filter(|node| node.tag_name().name() == "outline")
+        .filter_map(|outline| outline.attribute("xmlUrl"))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Fetches and parses the feed at the given URL, along with what to fetch it with next time.
+/// Returns `None` if it hasn't changed since it was fetched with the given validators.
+async fn fetch_feed(
+    fetcher: &Fetcher,
+    url: &str,
+    validators: &Validators,
+) -> Result<Option<(ParsedFeed, Validators)>, AnyError> {
+    let Some(fetched) = fetcher
+        .fetch_if_changed(url, fetcher.max_len(), validators)
+        .await
+        .map_err(|e| e.context("Could not fetch feed"))?
+    else {
+        return Ok(None);
+    };
+    let xml = String::from_utf8_lossy(&fetched.body);
+    let feed = parse_feed(&xml).map_err(|e| e.context(format!("Could not parse feed {url}")))?;
+    Ok(Some((feed, fetched.validators)))
+}
+
+/// Subscribes the given library to the feed at the given URL. Only the newest FEED_BACKFILL entries
+/// are converted.
+async fn subscribe(
+    library_dir: &str,
+    url: &str,
+    feed_store: &FeedStore,
+    converter: &Converter,
+) -> Result<FeedSubscription, AnyError> {
+    if feed_store.contains(library_dir, url) {
+        bail!("Already subscribed to {url}");
+    }
+
+    let (feed, _) = fetch_feed(converter.page_cache.fetcher(), url, &Validators::default())
+        .await?
+        .ok_or_else(|| anyhow!("Could not fetch feed {url}"))?;
+    // The validators aren't kept, so the backfill poll below gets the whole feed
+    let now = now_secs();
+    let sub = Subscription {
+        library_dir: library_dir.to_string(),
+        url: url.to_string(),
+        title: feed.title.clone(),
+        seen: feed
+            .entries
+            .iter()
+            .skip(FEED_BACKFILL)
+            .map(|entry| entry.key.clone())
+            .collect(),
+        last_polled: Some(now),
+        validators: Validators::default(),
+        last_success: Some(now),
+        error_streak: 0,
+        last_error: None,
+    };
+    let info = FeedSubscription::from(&sub);
+    feed_store.update(|subs| subs.push(sub)).await?;
+
+    // Convert the backfill in the background
+    let feed_store = feed_store.clone();
+    let converter = converter.clone();
+    let (library_dir, url) = (library_dir.to_string(), url.to_string());
+    tokio::spawn(async move { poll_feed(&library_dir, &url, &feed_store, &converter).await });
+
+    Ok(info)
+}
+
+/// Converts the entries of the feed at the given URL that haven't been seen yet into the given
+/// library, oldest first. Entries that fail to convert are skipped from then on, so a broken entry
+/// doesn't use up the TTS quota on every poll.
+async fn poll_feed(library_dir: &str, url: &str, feed_store: &FeedStore, converter: &Converter) {
+    let converter = converter.clone().in_library(library_dir.to_string());
+    let validators = feed_store.subs.read(|subs| {
+        subs.iter()
+            .find(|sub| sub.is(library_dir, url))
+            .map(|sub| sub.validators.clone())
+            .unwrap_or_default()
+    });
+    let res = fetch_feed(converter.page_cache.fetcher(), url, &validators).await;
+    if let Err(e) = &res {
+        tracing::error!("{e:#}");
+    }
+    let recorded = feed_store
+        .record_poll(
+            library_dir,
+            url,
+            res.as_ref().map(|_| ()).map_err(|e| format!("{e:#}")),
+        )
+        .await;
+    let (feed, validators) = match (res, recorded) {
+        (Ok(Some(fetched)), Ok(Some(_))) => fetched,
+        // The feed hasn't changed, or the feed couldn't be fetched
+        (Ok(None), _) | (Err(_), _) => return,
+        // The feed was unsubscribed from while it was being fetched
+        (_, Ok(None)) => return,
+        (_, Err(e)) => {
+            tracing::error!("{e}");
+            return;
+        }
+    };
+
+    // The title to record in the new articles. It's kept up to date with the feed's.
+    let title = feed.title.clone().unwrap_or_else(|| url.to_string());
+    let seen = feed_store
+        .update(|subs| {
+            let sub = subs.iter_mut().find(|sub| sub.is(library_dir, url))?;
+            sub.title = feed.title.clone();
+            sub.validators = validators;
+            Some(sub.seen.clone())
+        })
+        .await;
+    let Ok(Some(seen)) = seen else {
+        return;
+    };
+
+    let new_entries = feed
+        .entries
+        .iter()
+        .rev()
+        .filter(|entry| !seen.contains(&entry.key))
+        .take(MAX_NEW_PER_POLL);
+    for entry in new_entries {
+        tracing::info!("Adding {} from feed {url}", entry.link);
+        match converter.add_from_feed(&entry.link, &title).await {
+            Ok(_) => (),
+            Err(AddArticleError::AlreadyAdded(_)) => {
+                tracing::debug!("{} from feed {url} is already in the library", entry.link)
+            }
+            Err(e) => tracing::error!("Could not add {} from feed {url}: {:?}", entry.link, e),
+        }
+        if let Err(e) = feed_store.mark_seen(library_dir, url, &entry.key).await {
+            tracing::error!("{e}");
+        }
+    }
+}
+
+/// Spawns a background task that polls the feeds at the given interval, a few at a time. Failing
+/// feeds are skipped until they're due, and each library's feeds are skipped altogether in its
+/// quiet hours in the given store.
+pub(crate) fn spawn_poller(
+    feed_store: FeedStore,
+    converter: Converter,
+    interval: Duration,
+    reminder_store: ReminderStore,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let now = now_secs();
+            let due = feed_store.due(interval, |library_dir| {
+                reminder_store.in_quiet_hours(library_dir, now)
+            });
+            stream::iter(due)
+                .for_each_concurrent(MAX_CONCURRENT_POLLS, |(library_dir, url)| {
+                    let (feed_store, converter) = (&feed_store, &converter);
+                    async move { poll_feed(&library_dir, &url, feed_store, converter).await }
+                })
+                .await;
+        }
+    });
+}
+
+/// Lists the library's feed subscriptions
+async fn list_feeds(
+    Extension(feed_store): Extension<FeedStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<Vec<FeedSubscription>> {
+    Json(feed_store.list(&library_dir))
+}
+
+/// Subscribes the library to the given feed, and returns the new subscription
+async fn subscribe_feed(
+    Json(FeedSubmission { url }): Json<FeedSubmission>,
+    Extension(feed_store): Extension<FeedStore>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<FeedSubscription>, (StatusCode, String)> {
+    subscribe(&library_dir, url.trim(), &feed_store, &converter)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Error subscribing to feed: {e:#}");
+            (StatusCode::BAD_REQUEST, format!("{e:#}"))
+        })
+}
+
+/// Unsubscribes the library from the given feed. The articles it added stay in the library.
+async fn unsubscribe_feed(
+    Json(FeedSubmission { url }): Json<FeedSubmission>,
+    Extension(feed_store): Extension<FeedStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match feed_store
+        .update(|subs| subs.retain(|sub| !sub.is(&library_dir, &url)))
+        .await
+    {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error unsubscribing from feed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Subscribes the library to every feed in the given OPML file that it isn't subscribed to
+/// already, and returns the new subscriptions. Feeds that can't be fetched are skipped.
+async fn import_opml(
+    Extension(feed_store): Extension<FeedStore>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(library_dir): LibraryDir,
+    opml: String,
+) -> Result<Json<Vec<FeedSubscription>>, (StatusCode, String)> {
+    let urls = parse_opml(&opml).map_err(|e| (StatusCode::BAD_REQUEST, format!("{e:#}")))?;
+
+    let mut added = Vec::new();
+    for url in urls {
+        if feed_store.contains(&library_dir, &url) {
+            continue;
+        }
+        match subscribe(&library_dir, &url, &feed_store, &converter).await {
+            Ok(sub) => added.push(sub),
+            Err(e) => tracing::error!("Skipping feed from OPML: {e:#}"),
+        }
+    }
+    Ok(Json(added))
+}
+
+#[test]
+fn parsing_feeds() {
+    let rss = r#"<?xml version="1.0"?>
+        <rss version="2.0"><channel>
+            <title>Shoe Blog</title>
+            <item><title>New</title><link>https://example.com/new</link><guid>2</guid></item>
+            <item><title>Old</title><link> https://example.com/old </link></item>
+            <item><title>No link</title></item>
+        </channel></rss>"#;
+    let feed = parse_feed(rss).unwrap();
+    assert_eq!(feed.title.as_deref(), Some("Shoe Blog"));
+    assert_eq!(
+        feed.entries,
+        [
+            FeedEntry {
+                key: "2".to_string(),
+                link: "https://example.com/new".to_string()
+            },
+            FeedEntry {
+                key: "https://example.com/old".to_string(),
+                link: "https://example.com/old".to_string()
+            },
+        ]
+    );
+
+    let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+            <title>Laces</title>
+            <entry>
+                <id>tag:example.com,2022:1</id>
+                <link rel="self" href="https://example.com/feed/1"/>
+                <link href="https://example.com/1"/>
+            </entry>
+        </feed>"#;
+    let feed = parse_feed(atom).unwrap();
+    assert_eq!(feed.title.as_deref(), Some("Laces"));
+    assert_eq!(
+        feed.entries,
+        [FeedEntry {
+            key: "tag:example.com,2022:1".to_string(),
+            link: "https://example.com/1".to_string()
+        }]
+    );
+
+    assert!(parse_feed("<html><body/></html>").is_err());
+}
+
+#[test]
+fn parsing_opml() {
+    let opml = r#"<opml version="2.0"><body>
+            <outline text="Blogs">
+                <outline text="Shoe Blog" type="rss" xmlUrl="https://example.com/rss"/>
+            </outline>
+            <outline text="Laces" type="rss" xmlUrl="https://example.org/atom"/>
+        </body></opml>"#;
+    assert_eq!(
+        parse_opml(opml).unwrap(),
+        ["https://example.com/rss", "https://example.org/atom"]
+    );
+}
+
+#[test]
+fn backing_off() {
+    let hour = Duration::from_secs(60 * 60);
+    let mut sub = Subscription {
+        library_dir: "lib".to_string(),
+        url: "https://example.com/rss".to_string(),
+        title: None,
+        seen: BTreeSet::new(),
+        last_polled: Some(0),
+        validators: Validators::default(),
+        last_success: Some(0),
+        error_streak: 0,
+        last_error: None,
+    };
+    assert!(sub.is_due(0, hour));
+
+    // A feed that's failed twice waits four intervals, counting the one the poller just waited
+    sub.error_streak = 2;
+    assert!(!sub.is_due(2 * 60 * 60, hour));
+    assert!(sub.is_due(3 * 60 * 60, hour));
+
+    // The backoff stops growing
+    sub.error_streak = 100;
+    assert!(sub.is_due(63 * 60 * 60, hour));
+}
@@ -0,0 +1,591 @@
+//! Fetches pages and images from other sites, following the outbound request policy in the config
+//! file's `[fetch]` table. Every request has a timeout and a cap on how much it downloads, and goes
+//! out with the configured user agent. Sites can be denied, or all but a list of them can be.
+//!
+//! So that a shared server can't be used to probe the network it's on, addresses that aren't
+//! public, e.g., `localhost` and `192.168.0.1`, are refused unless the config allows them. IPv6
+//! addresses that stand for IPv4 ones, e.g., NAT64's `64:ff9b::7f00:1`, are checked as the IPv4
+//! address. Every redirect is checked the same way before it's followed. The check is done on the
+//! addresses a host resolves to, so a public name for a private address doesn't get through either,
+//! and it's done again on every lookup the client makes when it connects, so the name can't be made
+//! to resolve somewhere else in between. Other requests the server makes, e.g., push messages, get
+//! the same address check.
+//!
+//! Requests can go through an HTTP or SOCKS proxy, for the whole server or for particular sites.
+
+use crate::{config::FetchConfig, extract::is_on_site};
+
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    header, redirect, Response, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+/// The most redirects followed for a single request
+const MAX_REDIRECTS: usize = 10;
+
+/// What a request got back
+pub(crate) struct Fetched {
+    /// The body of the response
+    pub(crate) body: Vec<u8>,
+    /// The URL the response came from, after redirects
+    pub(crate) url: String,
+    /// The Content-Type of the response, or the empty string if it didn't say
+    pub(crate) content_type: String,
+    /// What the response said to check it against next time
+    pub(crate) validators: Validators,
+}
+
+/// What a response said to check it against when it's fetched again. Fetching it again with these
+/// gets nothing if it hasn't changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Validators {
+    /// The response's ETag header
+    pub(crate) etag: Option<String>,
+    /// The response's Last-Modified header
+    pub(crate) last_modified: Option<String>,
+}
+
+/// The error when the other site responds with an error status, so callers can tell, e.g., a
+/// missing page from a forbidden one
+#[derive(Debug)]
+pub(crate) struct ErrorStatus {
+    pub(crate) url: Url,
+    pub(crate) status: StatusCode,
+}
+
+impl fmt::Display for ErrorStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Could not fetch {}: the server said {}",
+            self.url, self.status
+        )
+    }
+}
+
+impl std::error::Error for ErrorStatus {}
+
+/// Returns whether the given address is on the public internet, as opposed to loopback, a private
+/// network, link-local, and the like
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match embedded_v4(ip) {
+            Some(ip) => is_public_v4(ip),
+            None => !is_private_v6(ip),
+        },
+    }
+}
+
+/// Returns the IPv4 address the given IPv6 address stands for, if it's one that reaches an IPv4
+/// address: IPv4-mapped (`::ffff:0:0/96`), IPv4-compatible (`::/96`), NAT64 (`64:ff9b::/96`), or
+/// 6to4 (`2002::/16`)
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let [.., a, b, c, d] = ip.octets();
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0 | 0xffff, _, _] | [0x64, 0xff9b, 0, 0, 0, 0, _, _] => {
+            Some(Ipv4Addr::new(a, b, c, d))
+        }
+        [0x2002, high, low, ..] => Some(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low))),
+        _ => None,
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    // 100.64.0.0/10 is carrier-grade NAT, and 0.0.0.0/8 is "this network"
+    let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xC0) == 64;
+    let this_network = ip.octets()[0] == 0;
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || shared
+        || this_network)
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    // fec0::/10 is the deprecated site-local range, which some networks still route privately
+    let site_local = (ip.segments()[0] & 0xffc0) == 0xfec0;
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        || site_local
+}
+
+/// Looks up hosts for the fetcher's client, failing if a host has an address that isn't public.
+/// The hosts of configured proxies are looked up without the check, since they're trusted.
+struct PublicResolver {
+    proxy_hosts: Vec<String>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let checked = !self.proxy_hosts.contains(&host);
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if checked && addrs.iter().any(|addr| !is_public(addr.ip())) {
+                return Err(anyhow!("{host} isn't on the public internet").into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The proxies from the config, parsed
+struct Proxies {
+    /// The proxy for sites that don't have their own
+    default: Option<Url>,
+    /// The proxies of particular sites, where `None` means the site is reached directly
+    sites: Vec<(String, Option<Url>)>,
+}
+
+impl Proxies {
+    /// Parses the proxies in the given config
+    fn new(config: &FetchConfig) -> Result<Proxies, AnyError> {
+        let parse = |proxy: &Option<String>| {
+            proxy
+                .as_deref()
+                .map(|p| Url::parse(p).map_err(|e| anyhow!("Invalid proxy {p}: {e}")))
+                .transpose()
+        };
+        let sites = config
+            .site_proxies
+            .iter()
+            .map(|sp| Ok((sp.site.clone(), parse(&sp.proxy)?)))
+            .collect::<Result<_, AnyError>>()?;
+        Ok(Proxies {
+            default: parse(&config.proxy)?,
+            sites,
+        })
+    }
+
+    /// Returns whether any proxy is configured, so the environment's are overridden
+    fn is_configured(&self) -> bool {
+        self.default.is_some() || !self.sites.is_empty()
+    }
+
+    /// Returns the hosts of all the proxies
+    fn hosts(&self) -> Vec<String> {
+        let proxies = self.sites.iter().filter_map(|(_, proxy)| proxy.as_ref());
+        self.default
+            .iter()
+            .chain(proxies)
+            .filter_map(|proxy| proxy.host_str().map(str::to_string))
+            .collect()
+    }
+
+    /// Returns the proxy a request to the given URL goes through, if any
+    fn pick(&self, url: &Url) -> Option<Url> {
+        self.sites
+            .iter()
+            .find(|(site, _)| is_on_site(url.as_str(), site))
+            .map_or(self.default.as_ref(), |(_, proxy)| proxy.as_ref())
+            .cloned()
+    }
+}
+
+/// Makes requests to other sites
+#[derive(Clone)]
+pub(crate) struct Fetcher {
+    client: reqwest::Client,
+    config: Arc<FetchConfig>,
+}
+
+/// Returns a client that makes requests the way the given config says
+fn build_client(config: &FetchConfig) -> Result<reqwest::Client, AnyError> {
+    // Redirects are followed by hand, so every hop can be checked
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .redirect(redirect::Policy::none());
+    if let Some(user_agent) = &config.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    let proxies = Proxies::new(config)?;
+    if !config.allow_private_addresses {
+        let proxy_hosts = proxies.hosts();
+        builder = builder.dns_resolver(Arc::new(PublicResolver { proxy_hosts }));
+    }
+    if proxies.is_configured() {
+        builder = builder.proxy(reqwest::Proxy::custom(move |url| proxies.pick(url)));
+    }
+    Ok(builder.build()?)
+}
+
+impl Fetcher {
+    pub(crate) fn new(config: FetchConfig) -> Result<Fetcher, AnyError> {
+        Ok(Fetcher {
+            client: build_client(&config)?,
+            config: Arc::new(config),
+        })
+    }
+
+    /// The most bytes a response can have
+    pub(crate) fn max_len(&self) -> usize {
+        (self.config.max_megabytes * 1_000_000) as usize
+    }
+
+    /// Checks that the given URL is one that can be fetched, going by its scheme and its site
+    fn check_site(&self, url: &Url) -> Result<(), AnyError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("Can't fetch {url}: only http and https URLs can be fetched");
+        }
+        let on_any = |sites: &[String]| sites.iter().any(|site| is_on_site(url.as_str(), site));
+        if on_any(&self.config.deny_sites) {
+            bail!("Can't fetch {url}: the server doesn't fetch from its site");
+        }
+        if !self.config.allow_sites.is_empty() && !on_any(&self.config.allow_sites) {
+            bail!("Can't fetch {url}: the server only fetches from certain sites");
+        }
+        Ok(())
+    }
+
+    /// Checks that the host of the given URL is on the public internet, unless private addresses
+    /// are allowed. Names are checked again when the client looks them up to connect, but this
+    /// check catches them before any request, even one that would go through a proxy.
+    async fn check_address(&self, url: &Url) -> Result<(), AnyError> {
+        if self.config.allow_private_addresses {
+            return Ok(());
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("Can't fetch {url}: it has no host"))?;
+        // IPv6 addresses are in brackets in URLs
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = literal.parse() {
+            if !is_public(ip) {
+                bail!("Can't fetch {url}: it isn't on the public internet");
+            }
+            return Ok(());
+        }
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| anyhow!("Could not look up {host}: {e}"))?
+            .collect();
+        if addrs.iter().any(|addr| !is_public(addr.ip())) {
+            bail!("Can't fetch {url}: it isn't on the public internet");
+        }
+        if addrs.is_empty() {
+            bail!("Could not look up {host}: it has no addresses");
+        }
+        Ok(())
+    }
+
+    /// Returns the client for a request to the given URL, once its host's address is checked
+    pub(crate) async fn client_for(&self, url: &Url) -> Result<&reqwest::Client, AnyError> {
+        self.check_address(url).await?;
+        Ok(&self.client)
+    }
+
+    /// Fetches the given URL, following redirects. Fails if the policy doesn't allow the URL or
+    /// any it redirects to, if the response is an error, or if the body is longer than `max_len`.
+    pub(crate) async fn fetch(&self, url: &str, max_len: usize) -> Result<Fetched, AnyError> {
+        self.fetch_if_changed(url, max_len, &Validators::default())
+            .await?
+            .ok_or_else(|| anyhow!("Could not fetch {url}: the server said it hadn't changed"))
+    }
+
+    /// Fetches the given URL like `fetch`, unless it hasn't changed since it was fetched with the
+    /// given validators, in which case there's nothing
+    pub(crate) async fn fetch_if_changed(
+        &self,
+        url: &str,
+        max_len: usize,
+        validators: &Validators,
+    ) -> Result<Option<Fetched>, AnyError> {
+        let Some((url, mut resp)) = self.get(url, max_len, validators).await? else {
+            return Ok(None);
+        };
+        let header_value = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let content_type = header_value(header::CONTENT_TYPE).unwrap_or_default();
+        let validators = Validators {
+            etag: header_value(header::ETAG),
+            last_modified: header_value(header::LAST_MODIFIED),
+        };
+        // The length can be missing or wrong, so count as it comes in
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > max_len {
+                bail!("Could not fetch {url}: it's over {max_len} bytes");
+            }
+        }
+        Ok(Some(Fetched {
+            body,
+            url: url.to_string(),
+            content_type,
+            validators,
+        }))
+    }
+
+    /// Fetches the given URL like `fetch`, but writes the body to the given file as it comes in,
+    /// rather than keeping it in memory. If it fails partway, the file is left partly written.
+    pub(crate) async fn download(
+        &self,
+        url: &str,
+        max_len: usize,
+        path: &Path,
+    ) -> Result<(), AnyError> {
+        let (url, mut resp) = self
+            .get(url, max_len, &Validators::default())
+            .await?
+            .ok_or_else(|| anyhow!("Could not fetch {url}: the server said it hadn't changed"))?;
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| anyhow!("Could not create {:?}: {e}", path))?;
+        let mut len = 0;
+        while let Some(chunk) = resp.chunk().await? {
+            len += chunk.len();
+            if len > max_len {
+                bail!("Could not fetch {url}: it's over {max_len} bytes");
+            }
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| anyhow!("Could not write {:?}: {e}", path))?;
+        }
+        file.flush()
+            .await
+            .map_err(|e| anyhow!("Could not write {:?}: {e}", path))
+    }
+
+    /// Requests the given URL, following redirects, and returns the response once it's known to
+    /// be a success and not to say it's over `max_len` bytes, along with the URL it came from.
+    /// There's none if it hasn't changed since it was fetched with the given validators.
+    async fn get(
+        &self,
+        url: &str,
+        max_len: usize,
+        validators: &Validators,
+    ) -> Result<Option<(Url, Response)>, AnyError> {
+        let mut url = Url::parse(url).map_err(|e| anyhow!("Could not fetch {url}: {e}"))?;
+        for _ in 0..=MAX_REDIRECTS {
+            self.check_site(&url)?;
+            let client = self.client_for(&url).await?;
+
+            let mut req = client.get(url.clone());
+            if let Some(etag) = &validators.etag {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+            let resp = req
+                .send()
+                .await
+                .map_err(|e| anyhow!("Could not fetch {url}: {e}"))?;
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+            if resp.status().is_redirection() {
+                let location = resp
+                    .headers()
+                    .get(header::LOCATION)
+                    .ok_or_else(|| anyhow!("Could not fetch {url}: it redirected nowhere"))?;
+                let location = location.to_str().unwrap_or_default();
+                url = url
+                    .join(location)
+                    .map_err(|e| anyhow!("{url} redirected to a bad URL: {e}"))?;
+                continue;
+            }
+            if resp.status().is_client_error() || resp.status().is_server_error() {
+                let status = resp.status();
+                return Err(ErrorStatus { url, status }.into());
+            }
+            if resp
+                .content_length()
+                .is_some_and(|len| len > max_len as u64)
+            {
+                bail!("Could not fetch {url}: it's over {max_len} bytes");
+            }
+            return Ok(Some((url, resp)));
+        }
+        bail!("Could not fetch {url}: too many redirects")
+    }
+
+    /// Fetches the page at the given URL and returns its raw HTML, and the URL the page ended up
+    /// being at after redirects
+    pub(crate) async fn fetch_html(&self, url: &str) -> Result<(Vec<u8>, String), AnyError> {
+        let fetched = self.fetch(url, self.max_len()).await?;
+        Ok((fetched.body, fetched.url))
+    }
+}
+
+#[test]
+fn public_addresses() {
+    for ip in [
+        "93.184.216.34",
+        "2606:2800:220:1:248:1893:25c8:1946",
+        "64:ff9b::5db8:d822",
+        "2002:5db8:d822::1",
+    ] {
+        assert!(is_public(ip.parse().unwrap()), "{ip}");
+    }
+    for ip in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "fd00::1",
+        "fe80::1",
+        "0.1.2.3",
+        "::ffff:127.0.0.1",
+        "::127.0.0.1",
+        "64:ff9b::7f00:1",
+        "64:ff9b::a9fe:a9fe",
+        "2002:c0a8:101::1",
+        "fec0::1",
+    ] {
+        assert!(!is_public(ip.parse().unwrap()), "{ip}");
+    }
+}
+
+#[tokio::test]
+async fn refusing_urls() {
+    let fetcher = |config| Fetcher::new(config).unwrap();
+    let default = fetcher(FetchConfig::default());
+
+    // None of these get as far as a request
+    for url in [
+        "file:///etc/passwd",
+        "http://127.0.0.1:8080/admin",
+        "http://localhost:8080/admin",
+        "http://[::1]/",
+        "http://169.254.169.254/latest/meta-data/",
+    ] {
+        assert!(default.fetch_html(url).await.is_err(), "{url}");
+    }
+
+    let listed = fetcher(FetchConfig {
+        allow_sites: vec!["example.com".to_string()],
+        deny_sites: vec!["private.example.com".to_string()],
+        ..Default::default()
+    });
+    let check = |url: &str| listed.check_site(&Url::parse(url).unwrap());
+    assert!(check("https://example.com/fish").is_ok());
+    assert!(check("https://news.example.com/fish").is_ok());
+    assert!(check("https://private.example.com/fish").is_err());
+    assert!(check("https://example.org/fish").is_err());
+
+    // Private addresses can be allowed, e.g., for a server that only fetches from an intranet
+    let private = fetcher(FetchConfig {
+        allow_private_addresses: true,
+        ..Default::default()
+    });
+    let url = Url::parse("http://10.0.0.1/").unwrap();
+    assert!(private.check_address(&url).await.is_ok());
+    assert!(default.check_address(&url).await.is_err());
+
+    // Names are checked again when the client looks them up to connect, except a proxy's
+    let resolver = PublicResolver {
+        proxy_hosts: vec!["localhost".to_string()],
+    };
+    assert!(resolver.resolve("localhost".parse().unwrap()).await.is_ok());
+    let resolver = PublicResolver {
+        proxy_hosts: Vec::new(),
+    };
+    assert!(resolver
+        .resolve("localhost".parse().unwrap())
+        .await
+        .is_err());
+}
+
+#[test]
+fn picking_proxies() {
+    use crate::config::SiteProxy;
+
+    let proxies = Proxies::new(&FetchConfig {
+        proxy: Some("socks5://127.0.0.1:1080".to_string()),
+        site_proxies: vec![
+            SiteProxy {
+                site: "intranet.example.com".to_string(),
+                proxy: None,
+            },
+            SiteProxy {
+                site: "example.com".to_string(),
+                proxy: Some("http://proxy.example.com:3128".to_string()),
+            },
+        ],
+        ..Default::default()
+    })
+    .unwrap();
+    let pick = |url: &str| proxies.pick(&Url::parse(url).unwrap()).map(String::from);
+
+    // The first site that matches wins, and sites without a proxy are reached directly
+    assert_eq!(pick("https://wiki.intranet.example.com/"), None);
+    assert_eq!(
+        pick("https://news.example.com/fish").as_deref(),
+        Some("http://proxy.example.com:3128/")
+    );
+    // Every other site goes through the default
+    assert_eq!(
+        pick("https://example.org/").as_deref(),
+        Some("socks5://127.0.0.1:1080")
+    );
+
+    assert!(Proxies::new(&FetchConfig {
+        proxy: Some("not a proxy".to_string()),
+        ..Default::default()
+    })
+    .is_err());
+}
+
+#[tokio::test]
+async fn downloading() {
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    // A server that sends 1000 bytes without saying how long they are
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/audio.mp3", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut req = [0; 1024];
+            let _ = conn.read(&mut req).await;
+            let head = "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n";
+            conn.write_all(head.as_bytes()).await.unwrap();
+            conn.write_all(&[b'a'; 1000]).await.unwrap();
+        }
+    });
+
+    let fetcher = Fetcher::new(FetchConfig {
+        allow_private_addresses: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let path = std::env::temp_dir().join(format!("rtms-download-{}", std::process::id()));
+    fetcher.download(&url, 1000, &path).await.unwrap();
+    assert_eq!(std::fs::read(&path).unwrap().len(), 1000);
+    // The body is counted as it comes in, and stops at the cap
+    assert!(fetcher.download(&url, 999, &path).await.is_err());
+    assert!(std::fs::read(&path).unwrap().len() <= 999);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,137 @@
+//! What's read before and after an article's text. By default it's the title, on its own line so
+//! it's read as a heading. Some listeners want the whole byline too, the way an audiobook announces
+//! itself, and some would rather skip straight to the first paragraph. An outro can say where the
+//! article ends, which is otherwise easy to miss with the next one queued up.
+
+use common::{ArticleTextSubmission, Frontmatter, ESTIMATED_WORDS_PER_MIN};
+
+use chrono::NaiveDate;
+
+/// What's known about where an article came from, beyond its title
+#[derive(Default)]
+pub(crate) struct Byline<'a> {
+    pub(crate) author: Option<&'a str>,
+    pub(crate) publication: Option<&'a str>,
+    /// When the article was published, as `YYYY-MM-DD`
+    pub(crate) published: Option<&'a str>,
+}
+
+/// What's read after the article's text, if its options ask for an outro
+const OUTRO: &str = "End of article.";
+
+/// Returns the given publication date, e.g., `2024-03-15`, as it's read, e.g., "March 2024"
+fn spoken_date(date: &str) -> Option<String> {
+    // Extractors sometimes give a time as well
+    let day = date.trim().get(..10)?;
+    let date = NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?;
+    Some(date.format("%B %Y").to_string())
+}
+
+/// Returns the line that announces the given article, e.g., "Title, by Author, published March
+/// 2024, from The Atlantic, 28 minutes". It doesn't end like a sentence, so it's read as a heading.
+fn announcement(title: &str, body: &str, byline: &Byline) -> String {
+    fn nonempty(s: Option<&str>) -> Option<&str> {
+        s.map(str::trim).filter(|s| !s.is_empty())
+    }
+
+    let mut parts = vec![title.trim().trim_end_matches(['.', ',']).to_string()];
+    if let Some(author) = nonempty(byline.author) {
+        parts.push(format!("by {author}"));
+    }
+    if let Some(date) = byline.published.and_then(spoken_date) {
+        parts.push(format!("published {date}"));
+    }
+    if let Some(publication) = nonempty(byline.publication) {
+        parts.push(format!("from {publication}"));
+    }
+    let minutes = body
+        .split_whitespace()
+        .count()
+        .div_ceil(ESTIMATED_WORDS_PER_MIN)
+        .max(1);
+    parts.push(match minutes {
+        1 => "1 minute".to_string(),
+        m => format!("{m} minutes"),
+    });
+
+    parts.join(", ")
+}
+
+/// Returns the text that's read aloud for the given article: its body, after whatever frontmatter
+/// its options ask for, and before the outro if they ask for one
+pub(crate) fn narrated_text(article: &ArticleTextSubmission, byline: &Byline) -> String {
+    let mut text = match article.options.frontmatter {
+        Frontmatter::None => article.body.clone(),
+        Frontmatter::Title => article.serialize(),
+        Frontmatter::Full => {
+            let announcement = announcement(&article.title, &article.body, byline);
+            format!("{announcement}\n{}", article.body)
+        }
+    };
+    // The outro is a paragraph of its own, so it comes after the usual pause
+    if article.options.outro {
+        text.push('\n');
+        text.push_str(OUTRO);
+    }
+    text
+}
+
+#[test]
+fn frontmatter() {
+    let mut article = ArticleTextSubmission {
+        title: "The Long Road.".to_string(),
+        body: "word ".repeat(4000),
+        style: Default::default(),
+        options: Default::default(),
+        split: false,
+        voice: None,
+        source_type: None,
+        background: false,
+        force: false,
+    };
+    let byline = Byline {
+        author: Some("Jane Doe"),
+        publication: Some("The Atlantic"),
+        published: Some("2024-03-15"),
+    };
+
+    // By default, only the title is read
+    assert_eq!(narrated_text(&article, &byline), article.serialize());
+
+    // The full frontmatter is a line of its own, and it's read as a heading
+    article.options.frontmatter = Frontmatter::Full;
+    let text = narrated_text(&article, &byline);
+    let (first_line, rest) = text.split_once('\n').unwrap();
+    assert_eq!(
+        first_line,
+        "The Long Road, by Jane Doe, published March 2024, from The Atlantic, 25 minutes"
+    );
+    assert_eq!(rest, article.body);
+    assert!(tts_pipeline::ssml::is_heading(first_line));
+
+    // Whatever isn't known is left out, and dates that can't be read are too
+    article.body = "A short one.".to_string();
+    let byline = Byline {
+        published: Some("sometime"),
+        ..Default::default()
+    };
+    assert_eq!(
+        narrated_text(&article, &byline),
+        "The Long Road, 1 minute\nA short one."
+    );
+    assert_eq!(
+        spoken_date("2023-11-02T08:00:00Z").unwrap(),
+        "November 2023"
+    );
+
+    // Or nothing's read before the body at all
+    article.options.frontmatter = Frontmatter::None;
+    assert_eq!(narrated_text(&article, &byline), "A short one.");
+
+    // The outro comes after the body, on a line of its own
+    article.options.outro = true;
+    assert_eq!(
+        narrated_text(&article, &byline),
+        "A short one.\nEnd of article."
+    );
+}
@@ -0,0 +1,237 @@
+//! Articles' lead images, e.g., the photo at the top of a news story, which players show as the
+//! artwork of the article on the lockscreen. An image is fetched when its article is added, and kept
+//! in the `images` directory under the audio blob directory, named for its content, i.e.,
+//! `/api/images/<sha256>.jpg`. That keeps it same-origin, so the page's CSP allows it, and it can
+//! be cached forever.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{accounts::LibraryDir, fetcher::Fetcher};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    body::Body,
+    extract::{Extension, Path as UrlPath},
+    http::{header, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use reqwest::Url;
+use scraper::{Html, Selector};
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+/// The directory, under the audio blob directory, that images are kept in
+const IMAGES_DIR: &str = "images";
+
+/// The largest image that's kept. Lead images are usually well under this; anything bigger isn't
+/// worth storing for a lockscreen thumbnail.
+const MAX_IMAGE_LEN: usize = 5_000_000;
+
+/// How long content-addressed images can be cached: a year, which is as long as caches keep
+/// anything
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// The image types that are kept, and the extensions they're kept under
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/webp", "webp"),
+    ("image/gif", "gif"),
+];
+
+/// The tags pages declare their lead images with, in order of preference
+const LEAD_IMAGE_SELECTORS: &[(&str, &str)] = &[
+    (r#"meta[property="og:image"][content]"#, "content"),
+    (r#"meta[property="og:image:url"][content]"#, "content"),
+    (r#"meta[name="twitter:image"][content]"#, "content"),
+    (r#"meta[name="twitter:image:src"][content]"#, "content"),
+    (r#"link[rel~="image_src"][href]"#, "href"),
+];
+
+/// Returns the URL of the lead image of the page at the given URL. This is the image the page
+/// declares for link previews, or else the one the extractor found, if any.
+pub(crate) fn lead_image_url(
+    html: &[u8],
+    page_url: &str,
+    extracted: Option<&str>,
+) -> Option<String> {
+    let doc = Html::parse_document(&String::from_utf8_lossy(html));
+    let declared = LEAD_IMAGE_SELECTORS.iter().find_map(|(selector, attr)| {
+        let selector = Selector::parse(selector).unwrap();
+        doc.select(&selector)
+            .filter_map(|elem| elem.value().attr(attr))
+            .map(str::trim)
+            .find(|src| !src.is_empty())
+    });
+
+    let src = declared.or(extracted.map(str::trim))?;
+    let url = Url::parse(page_url).ok()?.join(src).ok()?;
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
+
+/// Returns the extension images of the given MIME type are kept under, if they're kept at all
+fn extension_of(mime_type: &str) -> Option<&'static str> {
+    let mime_type = mime_type.split(';').next()?.trim();
+    IMAGE_TYPES
+        .iter()
+        .find(|(t, _)| t.eq_ignore_ascii_case(mime_type))
+        .map(|(_, ext)| *ext)
+}
+
+/// Returns the MIME type of images kept under the given extension
+fn mime_type_of(ext: &str) -> Option<&'static str> {
+    IMAGE_TYPES.iter().find(|(_, e)| *e == ext).map(|(t, _)| *t)
+}
+
+/// Saves the given image of the given MIME type in the library, and returns the path it's served
+/// at
+fn save(audio_blob_dir: &str, bytes: &[u8], mime_type: &str) -> Result<String, AnyError> {
+    let ext = extension_of(mime_type).ok_or_else(|| anyhow!("{mime_type} isn't an image type"))?;
+    let hash: String = Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let filename = format!("{hash}.{ext}");
+
+    let dir = Path::new(audio_blob_dir).join(IMAGES_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(&filename);
+    // Articles from the same site often share an image
+    if !path.exists() {
+        fs::write(path, bytes)?;
+    }
+    Ok(format!("/api/images/{filename}"))
+}
+
+/// Fetches the image at the given URL with the given fetcher and saves it in the library. Returns
+/// the path it's served at.
+pub(crate) async fn fetch(
+    audio_blob_dir: &str,
+    fetcher: &Fetcher,
+    url: &str,
+) -> Result<String, AnyError> {
+    let fetched = fetcher
+        .fetch(url, MAX_IMAGE_LEN.min(fetcher.max_len()))
+        .await
+        .map_err(|e| anyhow!("Could not fetch image: {e}"))?;
+    if extension_of(&fetched.content_type).is_none() {
+        bail!(
+            "Image {url} has unsupported type {:?}",
+            fetched.content_type
+        );
+    }
+    save(audio_blob_dir, &fetched.body, &fetched.content_type)
+}
+
+// Sets the /api/images/<hash>.<ext> route
+pub(crate) fn setup(router: Router, audio_blob_dir: &str) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/images/:filename", get(serve_image))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Returns the MIME type of the image with the given filename, if it's the name of a saved image
+fn parse_filename(filename: &str) -> Option<&'static str> {
+    filename
+        .split_once('.')
+        .filter(|(h, _)| h.len() == 64 && h.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|(_, ext)| mime_type_of(ext))
+}
+
+/// Returns where the saved image served at the given path, e.g., `/api/images/<hash>.jpg`, is kept
+/// under the given audio blob directory, if it's the path of a saved image
+pub(crate) fn saved_path(audio_blob_dir: &str, served_at: &str) -> Option<PathBuf> {
+    let filename = served_at.strip_prefix("/api/images/")?;
+    parse_filename(filename)?;
+    Some(Path::new(audio_blob_dir).join(IMAGES_DIR).join(filename))
+}
+
+/// Serves the image with the given filename
+async fn serve_image(
+    UrlPath(filename): UrlPath<String>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+    req: Request<Body>,
+) -> Result<Response, StatusCode> {
+    let mime_type = parse_filename(&filename).ok_or(StatusCode::NOT_FOUND)?;
+    let path = Path::new(&audio_blob_dir).join(IMAGES_DIR).join(&filename);
+    if !path.exists() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut resp = ServeFile::new(path)
+        .oneshot(req)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_response();
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+    );
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(mime_type));
+    Ok(resp)
+}
+
+#[test]
+fn lead_images() {
+    let page = "https://www.example.com/2022/10/story.html";
+
+    // Link preview images win, and relative ones are resolved against the page
+    let html = br#"<html><head>
+        <meta name="twitter:image" content="https://cdn.example.com/twitter.jpg">
+        <meta property="og:image" content="/images/og.jpg">
+        </head><body></body></html>"#;
+    assert_eq!(
+        lead_image_url(html, page, Some("https://cdn.example.com/extracted.jpg")).as_deref(),
+        Some("https://www.example.com/images/og.jpg")
+    );
+
+    // Otherwise it's what the extractor found, if anything
+    let html = b"<html><head></head><body></body></html>";
+    assert_eq!(
+        lead_image_url(html, page, Some("photo.png")).as_deref(),
+        Some("https://www.example.com/2022/10/photo.png")
+    );
+    assert_eq!(lead_image_url(html, page, None), None);
+
+    // Images that can't be fetched over HTTP aren't used
+    let html = br#"<meta property="og:image" content="data:image/png;base64,AAAA">"#;
+    assert_eq!(lead_image_url(html, page, None), None);
+}
+
+#[test]
+fn saving_images() {
+    let dir = std::env::temp_dir().join(format!("rtms-images-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let audio_blob_dir = dir.to_str().unwrap();
+
+    let path = save(audio_blob_dir, b"a photo", "image/JPEG; charset=binary").unwrap();
+    let filename = path.strip_prefix("/api/images/").unwrap();
+    assert_eq!(parse_filename(filename), Some("image/jpeg"));
+    assert_eq!(
+        fs::read(dir.join(IMAGES_DIR).join(filename)).unwrap(),
+        b"a photo"
+    );
+
+    // The same image is saved once, and things that aren't images aren't saved
+    assert_eq!(
+        save(audio_blob_dir, b"a photo", "image/jpeg").unwrap(),
+        path
+    );
+    assert!(save(audio_blob_dir, b"<svg/>", "image/svg+xml").is_err());
+
+    // Only the names of saved images are served
+    assert_eq!(parse_filename("../secrets.jpg"), None);
+    assert_eq!(parse_filename(&filename.replace(".jpg", ".exe")), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,355 @@
+//! Imports articles from other self-hosted readers. The listener uploads an export from Wallabag,
+//! Shiori, or Linkding, either as JSON or as a Netscape bookmark file, or a CSV export, e.g., from
+//! Pocket, and the articles in it are listed for them to pick from. This library's own catalog
+//! exports, from `/api/export-catalog`, are read the same way, so importing one converts the articles
+//! the library is missing again. The picked articles are converted one at a time in the
+//! background, and the client polls for progress.
+
+use crate::{
+    accounts::LibraryDir,
+    add_article::{AddArticleError, Converter},
+    csv,
+    extract::select_text,
+};
+use common::{ImportFailure, ImportProgress, ImportSubmission, ImportedArticle};
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, FixedOffset};
+use scraper::{Html, Selector};
+use serde_json::Value;
+
+/// The progress of the current (or last) import of every library, by library directory. Only one
+/// import runs at a time in each library.
+#[derive(Clone, Default)]
+pub(crate) struct Imports(Arc<Mutex<HashMap<String, ImportProgress>>>);
+
+impl Imports {
+    /// Returns the progress of the library's current import
+    fn progress(&self, library_dir: &str) -> ImportProgress {
+        let imports = self.0.lock().unwrap();
+        imports.get(library_dir).cloned().unwrap_or_default()
+    }
+
+    /// Updates the progress of the library's current import with the given function
+    fn update<T>(&self, library_dir: &str, f: impl FnOnce(&mut ImportProgress) -> T) -> T {
+        f(self
+            .0
+            .lock()
+            .unwrap()
+            .entry(library_dir.to_string())
+            .or_default())
+    }
+}
+
+// Sets the /api/parse-import, /api/start-import, and /api/import-progress routes
+pub(crate) fn setup(router: Router, converter: Converter) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/parse-import", post(parse_import))
+            .route("/start-import", post(start_import))
+            .route("/import-progress", get(import_progress))
+            .layer(Extension(Imports::default()))
+            .layer(Extension(converter.audio_blob_dir.clone()))
+            .layer(Extension(converter)),
+    )
+}
+
+/// Parses a date the way the exports write them, e.g., `2022-09-12T10:00:00+0200`, into seconds
+/// since the Unix epoch
+fn parse_date(date: &str) -> Option<u64> {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::<FixedOffset>::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%z"))
+        .ok()
+        .and_then(|date| date.timestamp().try_into().ok())
+}
+
+/// Returns the first of the given fields of the JSON object that's a non-empty string
+fn first_str<'a>(obj: &'a Value, fields: &[&str]) -> Option<&'a str> {
+    fields
+        .iter()
+        .filter_map(|field| obj.get(field)?.as_str())
+        .map(str::trim)
+        .find(|s| !s.is_empty())
+}
+
+/// Parses a JSON export. Wallabag exports a list of entries, Shiori and Linkding list their
+/// bookmarks under `bookmarks` and `results` respectively, and catalogs list their articles under
+/// `articles`.
+fn parse_json_export(json: &str) -> Result<Vec<ImportedArticle>, AnyError> {
+    let doc: Value = serde_json::from_str(json)?;
+    let entries = match &doc {
+        Value::Array(entries) => entries,
+        Value::Object(obj) => ["bookmarks", "results", "items", "articles"]
+            .iter()
+            .find_map(|key| obj.get(*key)?.as_array())
+            .ok_or_else(|| anyhow!("There's no list of articles in the JSON"))?,
+        _ => bail!("There's no list of articles in the JSON"),
+    };
+
+    let articles = entries
+        .iter()
+        .filter_map(|entry| {
+            let url = first_str(entry, &["url"])?.to_string();
+            let title = first_str(entry, &["title"]).unwrap_or(&url).to_string();
+            let datetime_added = first_str(entry, &["created_at", "date_added", "modified"])
+                .and_then(parse_date)
+                .or_else(|| entry.get("datetime_added")?.as_u64());
+            // Wallabag's content is HTML, and Shiori's is plain text. Parsing plain text as HTML
+            // leaves it as it is.
+            let text = first_str(entry, &["content"])
+                .and_then(|content| select_text(content.as_bytes(), "body").ok())
+                .filter(|text| !text.trim().is_empty());
+            Some(ImportedArticle {
+                url,
+                title,
+                datetime_added,
+                text,
+            })
+        })
+        .collect();
+    Ok(articles)
+}
+
+/// Parses a Netscape bookmark file, the HTML export format every reader supports
+fn parse_bookmark_export(html: &str) -> Vec<ImportedArticle> {
+    let doc = Html::parse_document(html);
+    let selector = Selector::parse("a[href]").unwrap();
+    doc.select(&selector)
+        .filter_map(|link| {
+            let url = link.value().attr("href")?.trim().to_string();
+            // Skip bookmarklets, folders, and the like
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return None;
+            }
+            let title = link.text().collect::<String>().trim().to_string();
+            let title = if title.is_empty() { url.clone() } else { title };
+            let datetime_added = link
+                .value()
+                .attr("add_date")
+                .and_then(|date| date.trim().parse().ok());
+            Some(ImportedArticle {
+                url,
+                title,
+                datetime_added,
+                text: None,
+            })
+        })
+        .collect()
+}
+
+/// Parses a CSV export with a header row. The columns are found by name, so Pocket's export, with
+/// `time_added`, reads as well as a catalog, with `datetime_added`.
+fn parse_csv_export(export: &str) -> Result<Vec<ImportedArticle>, AnyError> {
+    let mut rows = csv::parse(export).into_iter();
+    let header = rows.next().unwrap_or_default();
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|col| names.contains(&col.trim().to_lowercase().as_str()))
+    };
+    let url_col = column(&["url"]).ok_or_else(|| anyhow!("The CSV has no url column"))?;
+    let title_col = column(&["title"]);
+    let date_col = column(&["datetime_added", "time_added"]);
+
+    let articles = rows
+        .filter_map(|row| {
+            let field = |col: Option<usize>| {
+                let field = row.get(col?)?.trim();
+                (!field.is_empty()).then_some(field)
+            };
+            let url = field(Some(url_col))?.to_string();
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return None;
+            }
+            let title = field(title_col).unwrap_or(&url).to_string();
+            let datetime_added = field(date_col).and_then(|date| date.parse().ok());
+            Some(ImportedArticle {
+                url,
+                title,
+                datetime_added,
+                text: None,
+            })
+        })
+        .collect();
+    Ok(articles)
+}
+
+/// Parses an export from another reader. Articles that appear more than once are only listed once.
+fn parse_export(export: &str) -> Result<Vec<ImportedArticle>, AnyError> {
+    let trimmed = export.trim_start();
+    let articles = if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        parse_json_export(trimmed)?
+    } else if trimmed.starts_with('<') {
+        parse_bookmark_export(export)
+    } else {
+        parse_csv_export(export)?
+    };
+
+    let mut seen = BTreeSet::new();
+    let articles: Vec<ImportedArticle> = articles
+        .into_iter()
+        .filter(|article| seen.insert(article.url.clone()))
+        .collect();
+    if articles.is_empty() {
+        bail!("No articles were found in the file");
+    }
+    Ok(articles)
+}
+
+/// Converts the given articles one at a time, keeping track of the progress
+async fn run_import(articles: Vec<ImportedArticle>, imports: Imports, converter: Converter) {
+    for article in articles {
+        tracing::info!("Importing {}", article.url);
+        let res = converter.add_imported(&article).await;
+        imports.update(&converter.audio_blob_dir, |progress| {
+            progress.done += 1;
+            match res {
+                Ok(_) => (),
+                Err(AddArticleError::AlreadyAdded(_)) => progress.skipped += 1,
+                Err(e) => {
+                    tracing::error!("Could not import {}: {:?}", article.url, e);
+                    let error = match e {
+                        AddArticleError::Other(e) => format!("{e:#}"),
+                        e => format!("{e:?}"),
+                    };
+                    progress.failed.push(ImportFailure {
+                        title: article.title,
+                        error,
+                    });
+                }
+            }
+        });
+    }
+    imports.update(&converter.audio_blob_dir, |progress| {
+        progress.running = false
+    });
+}
+
+/// Lists the articles in the given export
+async fn parse_import(export: String) -> Result<Json<Vec<ImportedArticle>>, (StatusCode, String)> {
+    parse_export(&export)
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{e:#}")))
+}
+
+/// Starts converting the given articles in the background. Fails if an import is already running in
+/// the library.
+async fn start_import(
+    Json(ImportSubmission { articles }): Json<ImportSubmission>,
+    Extension(imports): Extension<Imports>,
+    Extension(converter): Extension<Converter>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> StatusCode {
+    let started = imports.update(&audio_blob_dir, |progress| {
+        if progress.running {
+            return false;
+        }
+        *progress = ImportProgress {
+            running: true,
+            total: articles.len(),
+            ..Default::default()
+        };
+        true
+    });
+    if !started {
+        return StatusCode::CONFLICT;
+    }
+
+    // The articles go to the library of whoever started the import
+    let converter = converter.in_library(audio_blob_dir);
+    tokio::spawn(run_import(articles, imports, converter));
+    StatusCode::OK
+}
+
+/// Returns how far along the library's current (or last) import is
+async fn import_progress(
+    Extension(imports): Extension<Imports>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Json<ImportProgress> {
+    Json(imports.progress(&audio_blob_dir))
+}
+
+#[test]
+fn parsing_exports() {
+    // Wallabag
+    let wallabag = r#"[
+        {"title": "Boots", "url": "https://example.com/boots",
+         "content": "<p>Boots are <em>good</em>.</p><p>So are shoes.</p>",
+         "created_at": "2022-09-12T18:26:40+0200"},
+        {"title": "", "url": "https://example.com/untitled", "content": ""},
+        {"title": "Boots again", "url": "https://example.com/boots"}
+    ]"#;
+    let articles = parse_export(wallabag).unwrap();
+    assert_eq!(articles.len(), 2);
+    assert_eq!(articles[0].title, "Boots");
+    assert_eq!(
+        articles[0].text.as_deref(),
+        Some("Boots are good.\nSo are shoes.")
+    );
+    assert_eq!(articles[0].datetime_added, Some(1_663_000_000));
+    assert_eq!(articles[1].title, "https://example.com/untitled");
+    assert_eq!(articles[1].text, None);
+
+    // Linkding's API
+    let linkding = r#"{"count": 1, "results": [
+        {"url": "https://example.com/laces", "title": "Laces",
+         "date_added": "2022-09-12T16:26:40.123Z"}
+    ]}"#;
+    let articles = parse_export(linkding).unwrap();
+    assert_eq!(articles[0].title, "Laces");
+    assert_eq!(articles[0].datetime_added, Some(1_663_000_000));
+
+    // A Netscape bookmark file
+    let bookmarks = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+        <DL><p>
+            <DT><A HREF="https://example.com/soles" ADD_DATE="1663000000">Soles</A>
+            <DT><A HREF="javascript:void(0)">Bookmarklet</A>
+        </DL><p>"#;
+    let articles = parse_export(bookmarks).unwrap();
+    assert_eq!(
+        articles,
+        [ImportedArticle {
+            url: "https://example.com/soles".to_string(),
+            title: "Soles".to_string(),
+            datetime_added: Some(1_663_000_000),
+            text: None,
+        }]
+    );
+
+    // A Pocket CSV export
+    let pocket = "title,url,time_added,tags,status\r\n\
+        \"Heels, high\",https://example.com/heels,1663000000,shoes|style,unread\r\n\
+        ,https://example.com/flats,,,archive\r\n";
+    let articles = parse_export(pocket).unwrap();
+    assert_eq!(articles.len(), 2);
+    assert_eq!(articles[0].title, "Heels, high");
+    assert_eq!(articles[0].datetime_added, Some(1_663_000_000));
+    assert_eq!(articles[1].title, "https://example.com/flats");
+
+    // A catalog of this library. Articles that were added as text have no URL to convert again.
+    let catalog = r#"{"version": 1, "exported": 1663000100, "articles": [
+        {"url": "https://example.com/clogs", "title": "Clogs", "datetime_added": 1663000000,
+         "tags": ["Shoes"], "elapsed_secs": 12.5, "finished": false},
+        {"url": null, "title": "Pasted", "datetime_added": 1663000000, "tags": [],
+         "finished": true}
+    ]}"#;
+    let articles = parse_export(catalog).unwrap();
+    assert_eq!(articles.len(), 1);
+    assert_eq!(articles[0].title, "Clogs");
+    assert_eq!(articles[0].datetime_added, Some(1_663_000_000));
+
+    assert!(parse_export("[]").is_err());
+    assert!(parse_export("title\r\nNo URLs here\r\n").is_err());
+}
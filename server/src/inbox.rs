@@ -0,0 +1,502 @@
+//! The read-it-later inbox. Saving an article to the inbox only fetches its title and length, so
+//! nothing is spent on TTS until the listener picks what to convert. Picked articles are converted
+//! one at a time by a background worker, and leave the inbox once they're in the library. Articles
+//! that match one of the auto-convert rules in the config file are queued as soon as they're saved.
+//!
+//! Every library has an inbox of its own, and the articles picked from it are converted into it.
+
+use crate::{
+    accounts::LibraryDir,
+    add_article::{AddArticleError, Converter},
+    canonical,
+    config::InboxRule,
+    extract::{extract, is_on_site},
+    json_store::JsonStore,
+    page_cache::PageCache,
+    util::now_secs,
+};
+use common::{InboxItem, InboxSelection, InboxState, InboxSubmission};
+use tts_pipeline::Fetch;
+
+use std::sync::Arc;
+
+use anyhow::{bail, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+/// An item, as saved
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedItem {
+    /// The directory of the library whose inbox the item is in
+    library_dir: String,
+    #[serde(flatten)]
+    item: InboxItem,
+}
+
+/// The items of every library's inbox, oldest first. These are saved as a JSON list.
+#[derive(Clone)]
+pub(crate) struct InboxStore {
+    items: JsonStore<Vec<SavedItem>>,
+    /// The rules for which new items are converted right away
+    rules: Arc<Vec<InboxRule>>,
+    /// Wakes the worker when items are queued
+    queued: Arc<Notify>,
+}
+
+impl InboxStore {
+    /// Loads the inbox from the given file. If the file doesn't exist, the inbox is empty. Items
+    /// that were being converted when the server stopped are queued again.
+    pub(crate) fn load(path: &str, rules: Vec<InboxRule>) -> Result<InboxStore, AnyError> {
+        let items = JsonStore::load_with(path, "inbox", |items: &mut Vec<SavedItem>| {
+            for SavedItem { item, .. } in items {
+                if item.state == InboxState::Converting {
+                    item.state = InboxState::Queued;
+                }
+            }
+        })?;
+
+        let store = InboxStore {
+            items,
+            rules: Arc::new(rules),
+            queued: Arc::new(Notify::new()),
+        };
+        // Pick up where the last run left off
+        store.queued.notify_one();
+        Ok(store)
+    }
+
+    /// Runs the given function on the items of every library, and saves them afterwards
+    async fn update_all<T>(&self, f: impl FnOnce(&mut Vec<SavedItem>) -> T) -> Result<T, AnyError> {
+        self.items.update(f).await
+    }
+
+    /// Runs the given function on the items of the given library, and saves them afterwards
+    async fn update<T>(
+        &self,
+        library_dir: &str,
+        f: impl FnOnce(&mut Vec<InboxItem>) -> T,
+    ) -> Result<T, AnyError> {
+        self.update_all(|all| {
+            let (mut items, others): (Vec<SavedItem>, Vec<SavedItem>) = all
+                .drain(..)
+                .partition(|saved| saved.library_dir == library_dir);
+            let mut library_items = items.drain(..).map(|saved| saved.item).collect();
+            let res = f(&mut library_items);
+            *all = others;
+            all.extend(library_items.into_iter().map(|item| SavedItem {
+                library_dir: library_dir.to_string(),
+                item,
+            }));
+            res
+        })
+        .await
+    }
+
+    /// Returns the items of the given library's inbox as JSON, for a takeout
+    pub(crate) fn take_out(&self, library_dir: &str) -> Result<Vec<u8>, AnyError> {
+        Ok(serde_json::to_vec_pretty(&self.list(library_dir))?)
+    }
+
+    /// Empties the given library's inbox. An item that's being converted still finishes, but isn't
+    /// kept.
+    pub(crate) async fn forget_library(&self, library_dir: &str) -> Result<(), AnyError> {
+        self.update_all(|items| items.retain(|saved| saved.library_dir != library_dir))
+            .await
+    }
+
+    /// Returns every item of the given library
+    fn list(&self, library_dir: &str) -> Vec<InboxItem> {
+        self.items.read(|items| {
+            items
+                .iter()
+                .filter(|saved| saved.library_dir == library_dir)
+                .map(|saved| saved.item.clone())
+                .collect()
+        })
+    }
+
+    /// Returns whether the given item matches an auto-convert rule
+    fn auto_converts(&self, item: &InboxItem) -> bool {
+        self.rules.iter().any(|rule| rule_matches(rule, item))
+    }
+
+    /// Queues the items of the given library with the given URLs for conversion. Items that are
+    /// already queued or being converted are left alone.
+    async fn queue(&self, library_dir: &str, urls: &[String]) -> Result<(), AnyError> {
+        self.update(library_dir, |items| {
+            for item in items.iter_mut().filter(|item| urls.contains(&item.url)) {
+                if matches!(item.state, InboxState::Saved | InboxState::Failed(_)) {
+                    item.state = InboxState::Queued;
+                }
+            }
+        })
+        .await?;
+        self.queued.notify_one();
+        Ok(())
+    }
+
+    /// Marks the oldest queued item of any library as being converted, and returns its library's
+    /// directory and its URL
+    async fn start_next(&self) -> Result<Option<(String, String)>, AnyError> {
+        self.update_all(|items| {
+            // Each library's items keep their order, but the libraries' items can be interleaved
+            // in any order
+            let saved = items
+                .iter_mut()
+                .filter(|saved| saved.item.state == InboxState::Queued)
+                .min_by_key(|saved| saved.item.datetime_saved)?;
+            saved.item.state = InboxState::Converting;
+            Some((saved.library_dir.clone(), saved.item.url.clone()))
+        })
+        .await
+    }
+
+    /// Records how the conversion of the item of the given library with the given URL went.
+    /// Converted items leave the inbox, and failed ones stay with the error.
+    async fn finish(
+        &self,
+        library_dir: &str,
+        url: &str,
+        res: Result<(), String>,
+    ) -> Result<(), AnyError> {
+        self.update(library_dir, |items| match res {
+            Ok(()) => items.retain(|item| item.url != url),
+            Err(e) => {
+                if let Some(item) = items.iter_mut().find(|item| item.url == url) {
+                    item.state = InboxState::Failed(e);
+                }
+            }
+        })
+        .await
+    }
+}
+
+/// Returns whether the given item meets every criterion of the given rule
+fn rule_matches(rule: &InboxRule, item: &InboxItem) -> bool {
+    let site_matches = rule
+        .site
+        .as_ref()
+        .is_none_or(|site| is_on_site(&item.url, site));
+    let tag_matches = rule
+        .tag
+        .as_ref()
+        .is_none_or(|tag| item.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+    let length_matches = rule
+        .max_minutes
+        .is_none_or(|max| item.estimated_minutes().is_some_and(|mins| mins <= max));
+    site_matches && tag_matches && length_matches
+}
+
+// Sets the /api/list-inbox, /api/save-to-inbox, /api/remove-from-inbox, and /api/convert-inbox
+// routes
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    inbox_store: InboxStore,
+    page_cache: PageCache,
+) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/list-inbox", get(list_inbox))
+            .route("/save-to-inbox", post(save_to_inbox))
+            .route("/remove-from-inbox", post(remove_from_inbox))
+            .route("/convert-inbox", post(convert_inbox))
+            .layer(Extension(inbox_store))
+            .layer(Extension(page_cache))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Spawns the background task that converts queued items, one at a time, each into the library
+/// whose inbox it's in
+pub(crate) fn spawn_worker(inbox_store: InboxStore, converter: Converter) {
+    tokio::spawn(async move {
+        loop {
+            let (library_dir, url) = match inbox_store.start_next().await {
+                Ok(Some(next)) => next,
+                Ok(None) => {
+                    inbox_store.queued.notified().await;
+                    continue;
+                }
+                Err(e) => {
+                    tracing::error!("{e}");
+                    inbox_store.queued.notified().await;
+                    continue;
+                }
+            };
+
+            tracing::info!("Converting {url} from the inbox");
+            let converter = converter.clone().in_library(library_dir.clone());
+            let res = match converter.add_url(&url).await {
+                Ok(_) => Ok(()),
+                // Someone got to it another way. Either way, it's in the library now.
+                Err(AddArticleError::AlreadyAdded(_)) => Ok(()),
+                Err(AddArticleError::Other(e)) => Err(format!("{e:#}")),
+                Err(e) => Err(format!("{e:?}")),
+            };
+            if let Err(e) = &res {
+                tracing::error!("Could not convert {url} from the inbox: {e}");
+            }
+            if let Err(e) = inbox_store.finish(&library_dir, &url, res).await {
+                tracing::error!("{e}");
+            }
+        }
+    });
+}
+
+/// Saves the article at the given URL to the given library's inbox, and returns the new item. The
+/// article is fetched to find its title and length. If that fails, it's saved without them. If the
+/// article matches an auto-convert rule, it's queued right away.
+async fn save(
+    library_dir: &str,
+    url: &str,
+    tags: Vec<String>,
+    inbox_store: &InboxStore,
+    page_cache: &PageCache,
+) -> Result<InboxItem, AnyError> {
+    let url = canonical::unwrap_url(url);
+    if inbox_store
+        .list(library_dir)
+        .iter()
+        .any(|item| item.url == url)
+    {
+        bail!("{url} is already in the inbox");
+    }
+
+    let extracted = match page_cache.fetch(&url).await {
+        Ok(page) => extract(&page.html).await,
+        Err(e) => Err(e),
+    };
+    let (title, words) = match extracted {
+        Ok(article) => (
+            Some(article.title).filter(|t| !t.is_empty()),
+            Some(article.text.split_whitespace().count()),
+        ),
+        Err(e) => {
+            tracing::warn!("Saving {url} to the inbox without its details: {e:#}");
+            (None, None)
+        }
+    };
+
+    let mut item = InboxItem {
+        url,
+        title,
+        words,
+        datetime_saved: now_secs(),
+        tags,
+        state: InboxState::Saved,
+    };
+    let auto_convert = inbox_store.auto_converts(&item);
+    if auto_convert {
+        tracing::info!("Auto-converting {} from the inbox", item.url);
+        item.state = InboxState::Queued;
+    }
+    // Check again, since the URL could've been saved while the article was being fetched
+    inbox_store
+        .update(library_dir, |items| {
+            if items.iter().any(|i| i.url == item.url) {
+                bail!("{} is already in the inbox", item.url);
+            }
+            items.push(item.clone());
+            Ok(())
+        })
+        .await??;
+    if auto_convert {
+        inbox_store.queued.notify_one();
+    }
+    Ok(item)
+}
+
+/// Lists the items of the library's inbox
+async fn list_inbox(
+    Extension(inbox_store): Extension<InboxStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<Vec<InboxItem>> {
+    Json(inbox_store.list(&library_dir))
+}
+
+/// Saves the given article to the library's inbox, and returns the new item
+async fn save_to_inbox(
+    Json(InboxSubmission { url, tags }): Json<InboxSubmission>,
+    Extension(inbox_store): Extension<InboxStore>,
+    Extension(page_cache): Extension<PageCache>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<InboxItem>, (StatusCode, String)> {
+    let tags = tags
+        .iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    save(&library_dir, url.trim(), tags, &inbox_store, &page_cache)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Error saving to inbox: {e:#}");
+            (StatusCode::BAD_REQUEST, format!("{e:#}"))
+        })
+}
+
+/// Removes the given items from the library's inbox. Items that are being converted stay.
+async fn remove_from_inbox(
+    Json(InboxSelection { urls }): Json<InboxSelection>,
+    Extension(inbox_store): Extension<InboxStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    let res = inbox_store
+        .update(&library_dir, |items| {
+            items.retain(|item| item.state == InboxState::Converting || !urls.contains(&item.url))
+        })
+        .await;
+    match res {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error removing from inbox: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Queues the given items of the library's inbox for conversion
+async fn convert_inbox(
+    Json(InboxSelection { urls }): Json<InboxSelection>,
+    Extension(inbox_store): Extension<InboxStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match inbox_store.queue(&library_dir, &urls).await {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error queueing inbox items: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[tokio::test]
+async fn queueing() {
+    let path = std::env::temp_dir().join(format!("rtms-inbox-test-{}.json", std::process::id()));
+    let item = |library_dir: &str, url: &str, state| SavedItem {
+        library_dir: library_dir.to_string(),
+        item: InboxItem {
+            url: url.to_string(),
+            title: None,
+            words: None,
+            datetime_saved: 0,
+            tags: Vec::new(),
+            state,
+        },
+    };
+    let items = vec![
+        item("lib", "https://example.com/a", InboxState::Saved),
+        item("lib", "https://example.com/b", InboxState::Converting),
+        item(
+            "lib",
+            "https://example.com/c",
+            InboxState::Failed("oops".to_string()),
+        ),
+        item("other", "https://example.com/a", InboxState::Saved),
+    ];
+    std::fs::write(&path, serde_json::to_vec(&items).unwrap()).unwrap();
+    async fn next(store: &InboxStore) -> Option<String> {
+        store
+            .start_next()
+            .await
+            .unwrap()
+            .map(|(library_dir, url)| format!("{library_dir} {url}"))
+    }
+
+    // The conversion that was interrupted is picked up again first
+    let store = InboxStore::load(path.to_str().unwrap(), Vec::new()).unwrap();
+    assert_eq!(store.list("lib")[1].state, InboxState::Queued);
+    assert_eq!(
+        next(&store).await.as_deref(),
+        Some("lib https://example.com/b")
+    );
+    assert_eq!(next(&store).await, None);
+
+    // Saved and failed items can be queued, in inbox order, and only in their own library
+    store
+        .queue(
+            "lib",
+            &[
+                "https://example.com/c".into(),
+                "https://example.com/a".into(),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        next(&store).await.as_deref(),
+        Some("lib https://example.com/a")
+    );
+    assert_eq!(store.list("other")[0].state, InboxState::Saved);
+
+    // Converted items leave the inbox, and failed ones stay
+    store
+        .finish("lib", "https://example.com/a", Ok(()))
+        .await
+        .unwrap();
+    store
+        .finish("lib", "https://example.com/b", Err("nope".to_string()))
+        .await
+        .unwrap();
+    let urls: Vec<String> = store.list("lib").into_iter().map(|i| i.url).collect();
+    assert_eq!(urls, ["https://example.com/b", "https://example.com/c"]);
+    assert_eq!(
+        store.list("lib")[0].state,
+        InboxState::Failed("nope".to_string())
+    );
+    assert_eq!(store.list("other").len(), 1);
+
+    // A deleted library's inbox is forgotten, and the others' stay
+    store.forget_library("other").await.unwrap();
+    assert!(store.list("other").is_empty());
+    assert_eq!(store.list("lib").len(), 2);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn auto_convert_rules() {
+    let item = InboxItem {
+        url: "https://blog.example.com/post".to_string(),
+        title: None,
+        words: Some(1000),
+        datetime_saved: 0,
+        tags: vec!["Short".to_string()],
+        state: InboxState::Saved,
+    };
+    let rule = |site: Option<&str>, tag: Option<&str>, max_minutes| InboxRule {
+        site: site.map(str::to_string),
+        tag: tag.map(str::to_string),
+        max_minutes,
+    };
+
+    assert!(rule_matches(&rule(Some("example.com"), None, None), &item));
+    assert!(!rule_matches(&rule(Some("ample.com"), None, None), &item));
+    assert!(rule_matches(&rule(None, Some("short"), None), &item));
+    assert!(!rule_matches(&rule(None, Some("long"), None), &item));
+
+    // 1000 words is about 7 minutes
+    assert!(rule_matches(&rule(None, None, Some(20)), &item));
+    assert!(!rule_matches(&rule(None, None, Some(5)), &item));
+
+    // Every criterion has to match
+    assert!(!rule_matches(
+        &rule(Some("example.com"), Some("long"), Some(20)),
+        &item
+    ));
+
+    // Articles of unknown length aren't known to be short enough
+    let unknown = InboxItem {
+        words: None,
+        ..item.clone()
+    };
+    assert!(!rule_matches(&rule(None, None, Some(20)), &unknown));
+}
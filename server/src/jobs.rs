@@ -0,0 +1,948 @@
+//! Keeps track of the conversions the server is working on, and lets them be paused, resumed,
+//! cancelled, and prioritized while they run. Every TTS request a job makes has to wait for a slot,
+//! and slots go to the highest-priority jobs first. Jobs only check for pauses and cancellations
+//! between TTS requests, so requests that are already in flight always finish.
+//!
+//! Jobs also keep the audio they've synthesized so far, so an article can be listened to while it's
+//! still being converted. Finished jobs feed a running estimate of how fast each voice synthesizes,
+//! which is used to estimate when unfinished jobs will be done.
+//!
+//! A submission that's added in the background gets a job of its own, which the jobs for the parts
+//! it's converted in are started under. Its status sums up theirs, and pausing, resuming, or
+//! cancelling it does the same to them.
+//!
+//! Every job belongs to the library it converts into. The jobs are all scheduled together, since
+//! they share the TTS request slots, but they can only be seen, controlled, and listened to through
+//! their own library.
+
+use crate::accounts::LibraryDir;
+use common::{
+    ArticleMetadata, JobControlSubmission, JobInfo, JobPriority, JobPrioritySubmission, JobStatus,
+};
+use tts_pipeline::SynthesisJob;
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Error as AnyError};
+use async_trait::async_trait;
+use axum::{
+    body::StreamBody,
+    extract::{Extension, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use bytes::Bytes;
+use futures::stream::{self, Stream};
+use tokio::sync::Notify;
+
+/// How long finished jobs are kept around, so the client can see how they ended
+const FINISHED_JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// How much each finished job counts towards a voice's throughput estimate. The rest comes from the
+/// jobs before it.
+const THROUGHPUT_SMOOTHING: f64 = 0.3;
+
+/// The maximum number of TTS requests that are in flight at once, across all jobs. When there are
+/// more requests than this, the highest-priority jobs go first.
+const MAX_CONCURRENT_TTS_REQUESTS: usize = 8;
+
+struct JobEntry {
+    info: JobInfo,
+    /// The directory of the library the job converts into
+    library: String,
+    /// The number of this job's TTS requests that are waiting for a slot
+    waiting: usize,
+    /// When the job finished, if it has
+    finished_at: Option<Instant>,
+    /// The MP3s of the chunks synthesized so far, in order. Concatenated, they make a valid MP3.
+    /// This is kept until the job is forgotten.
+    audio: Vec<Bytes>,
+    /// The voice the job is synthesized with. Throughput is tracked per voice. This is empty until
+    /// the job's work is planned.
+    engine: String,
+    /// How long the job has spent synthesizing, not counting the current stretch
+    active: Duration,
+    /// When the current stretch of synthesizing began. This is `None` while the job is paused,
+    /// finished, or not yet planned.
+    running_since: Option<Instant>,
+}
+
+impl JobEntry {
+    /// Sets the job's status. Does nothing if the job is already finished.
+    fn set_status(&mut self, status: JobStatus) {
+        if self.info.status.is_finished() {
+            return;
+        }
+
+        self.info.status = status;
+        if status.is_finished() {
+            self.finished_at = Some(Instant::now());
+        }
+
+        // Only count the time spent running towards throughput
+        if status == JobStatus::Running {
+            if !self.engine.is_empty() && self.running_since.is_none() {
+                self.running_since = Some(Instant::now());
+            }
+        } else if let Some(t) = self.running_since.take() {
+            self.active += t.elapsed();
+        }
+    }
+
+    /// Returns how long the job has spent synthesizing
+    fn active_time(&self) -> Duration {
+        self.active + self.running_since.map(|t| t.elapsed()).unwrap_or_default()
+    }
+
+    /// Returns the job's own throughput so far, in characters per second, if it's done enough to
+    /// tell
+    fn throughput(&self) -> Option<f64> {
+        let secs = self.active_time().as_secs_f64();
+        if self.info.chars_done == 0 || secs == 0.0 {
+            return None;
+        }
+        Some(self.info.chars_done as f64 / secs)
+    }
+}
+
+#[derive(Default)]
+struct JobStoreInner {
+    next_id: u64,
+    jobs: BTreeMap<u64, JobEntry>,
+    /// The number of TTS requests in flight, across all jobs
+    in_flight: usize,
+    /// The estimated throughput of every voice that's been used, in characters per second
+    throughput: HashMap<String, f64>,
+}
+
+impl JobStoreInner {
+    /// Forgets the jobs that finished a while ago
+    fn prune(&mut self) {
+        self.jobs.retain(|_, entry| match entry.finished_at {
+            Some(t) => t.elapsed() < FINISHED_JOB_RETENTION,
+            None => true,
+        });
+    }
+
+    /// Folds the throughput of the given finished job into its voice's estimate
+    fn record_throughput(&mut self, entry_id: u64) {
+        let (engine, rate) = match self.jobs.get(&entry_id) {
+            Some(e) => match e.throughput() {
+                Some(rate) => (e.engine.clone(), rate),
+                None => return,
+            },
+            None => return,
+        };
+
+        self.throughput
+            .entry(engine)
+            .and_modify(|r| *r = THROUGHPUT_SMOOTHING * rate + (1.0 - THROUGHPUT_SMOOTHING) * *r)
+            .or_insert(rate);
+    }
+
+    /// Estimates how many seconds are left in the given unfinished job. The job's own throughput is
+    /// used if it's made any progress. Otherwise, its voice's past throughput is used. Returns
+    /// `None` if there's nothing to go on.
+    fn eta_secs(&self, entry: &JobEntry) -> Option<u64> {
+        if entry.info.status.is_finished() || entry.info.chars_total == 0 {
+            return None;
+        }
+
+        let rate = entry
+            .throughput()
+            .or_else(|| self.throughput.get(&entry.engine).copied())?;
+        let chars_left = entry.info.chars_total.saturating_sub(entry.info.chars_done);
+        Some((chars_left as f64 / rate).ceil() as u64)
+    }
+
+    /// Returns the ID of the job whose turn it is to make a TTS request. This is the
+    /// highest-priority running job with waiting requests. Ties go to the oldest job.
+    fn next_in_line(&self) -> Option<u64> {
+        self.jobs
+            .values()
+            .filter(|e| e.info.status == JobStatus::Running && e.waiting > 0)
+            .max_by_key(|e| (e.info.priority, Reverse(e.info.id)))
+            .map(|e| e.info.id)
+    }
+
+    /// Returns the jobs that were started under the given job
+    fn parts_of(&self, id: u64) -> impl Iterator<Item = &JobEntry> {
+        self.jobs
+            .values()
+            .filter(move |e| e.info.parent == Some(id))
+    }
+
+    /// Returns the info of the given job, with its estimated time left. The progress of a job with
+    /// parts is the total of theirs.
+    fn info(&self, entry: &JobEntry) -> JobInfo {
+        let mut info = JobInfo {
+            eta_secs: self.eta_secs(entry),
+            ..entry.info.clone()
+        };
+
+        let parts: Vec<&JobEntry> = self.parts_of(entry.info.id).collect();
+        if !parts.is_empty() {
+            info.chunks_done = parts.iter().map(|p| p.info.chunks_done).sum();
+            info.chunks_total = parts.iter().map(|p| p.info.chunks_total).sum();
+            info.chars_done = parts.iter().map(|p| p.info.chars_done).sum();
+            info.chars_total = parts.iter().map(|p| p.info.chars_total).sum();
+            info.eta_secs = if info.status.is_finished() {
+                None
+            } else {
+                // The parts are synthesized side by side, but they share the TTS request slots,
+                // so they're about as quick as one after another
+                parts
+                    .iter()
+                    .filter(|p| !p.info.status.is_finished())
+                    .map(|p| self.eta_secs(p))
+                    .sum()
+            };
+        }
+
+        info
+    }
+}
+
+#[derive(Default)]
+struct JobStoreShared {
+    inner: Mutex<JobStoreInner>,
+    /// Wakes up waiting TTS requests whenever something that might let them proceed changes
+    changed: Notify,
+}
+
+/// All the jobs this server is running or recently ran, as seen from one library
+#[derive(Clone, Default)]
+pub(crate) struct JobStore {
+    shared: Arc<JobStoreShared>,
+    /// The job that the jobs started through this store are parts of, if any
+    parent: Option<u64>,
+    /// The directory of the library whose jobs this store sees, and which the jobs started
+    /// through it convert into
+    library: String,
+}
+
+impl JobStore {
+    fn lock(&self) -> MutexGuard<'_, JobStoreInner> {
+        self.shared.inner.lock().unwrap()
+    }
+
+    /// Returns a store whose jobs are started as parts of the given job
+    pub(crate) fn under(&self, parent: &JobHandle) -> JobStore {
+        JobStore {
+            shared: self.shared.clone(),
+            parent: Some(parent.id),
+            library: self.library.clone(),
+        }
+    }
+
+    /// Returns a store that sees the jobs of the library in the given directory, and starts jobs
+    /// in it
+    pub(crate) fn in_library(&self, library_dir: &str) -> JobStore {
+        JobStore {
+            shared: self.shared.clone(),
+            parent: self.parent,
+            library: library_dir.to_string(),
+        }
+    }
+
+    /// Returns the given job, if it's in this store's library
+    fn entry<'a>(&self, store: &'a JobStoreInner, id: u64) -> Option<&'a JobEntry> {
+        store.jobs.get(&id).filter(|e| e.library == self.library)
+    }
+
+    /// Registers a new running job for the article with the given title. A part of a paused or
+    /// cancelled job starts out paused or cancelled too, and with the same priority.
+    pub(crate) fn start(&self, title: &str) -> JobHandle {
+        let mut store = self.lock();
+        store.prune();
+
+        let id = store.next_id;
+        store.next_id += 1;
+
+        let (status, priority) = match self.parent.and_then(|p| store.jobs.get(&p)) {
+            Some(p) if !p.info.status.is_finished() || p.info.status == JobStatus::Cancelled => {
+                (p.info.status, p.info.priority)
+            }
+            _ => (JobStatus::Running, JobPriority::default()),
+        };
+
+        let info = JobInfo {
+            id,
+            title: title.to_string(),
+            status: JobStatus::Running,
+            priority,
+            chunks_done: 0,
+            chunks_total: 0,
+            chars_done: 0,
+            chars_total: 0,
+            eta_secs: None,
+            audio_chunks: 0,
+            error: None,
+            parent: self.parent,
+            articles: Vec::new(),
+            incomplete: false,
+        };
+        let mut entry = JobEntry {
+            info,
+            library: self.library.clone(),
+            waiting: 0,
+            finished_at: None,
+            audio: Vec::new(),
+            engine: String::new(),
+            active: Duration::ZERO,
+            running_since: None,
+        };
+        entry.set_status(status);
+        store.jobs.insert(id, entry);
+
+        JobHandle {
+            store: JobStore {
+                shared: self.shared.clone(),
+                parent: None,
+                library: self.library.clone(),
+            },
+            id,
+        }
+    }
+
+    /// Lists the library's jobs, newest first, with their estimated times left. Jobs with parts are
+    /// left out, since their parts are listed.
+    pub(crate) fn list(&self) -> Vec<JobInfo> {
+        let mut store = self.lock();
+        store.prune();
+        store
+            .jobs
+            .values()
+            .rev()
+            .filter(|e| e.library == self.library)
+            .filter(|e| store.parts_of(e.info.id).next().is_none())
+            .map(|e| store.info(e))
+            .collect()
+    }
+
+    /// Returns the info of the given job, with its estimated time left. Returns `None` if there's
+    /// no such job in the library.
+    pub(crate) fn get(&self, id: u64) -> Option<JobInfo> {
+        let mut store = self.lock();
+        store.prune();
+        self.entry(&store, id).map(|e| store.info(e))
+    }
+
+    /// Forgets the given job. Its parts, if it has any, are kept.
+    pub(crate) fn forget(&self, id: u64) {
+        self.lock().jobs.remove(&id);
+    }
+
+    /// Waits until the given job has parts, i.e., until its synthesis has begun
+    pub(crate) async fn parts_started(&self, id: u64) {
+        loop {
+            // Start listening for changes before looking at the state, so none are missed
+            let changed = self.shared.changed.notified();
+            if self.lock().parts_of(id).next().is_some() {
+                return;
+            }
+            changed.await;
+        }
+    }
+
+    /// Applies the given function to the given unfinished job, and to its unfinished parts, and
+    /// returns its updated info. Returns `None` if there's no such job in the library, or if it's
+    /// already finished.
+    fn control(&self, id: u64, f: impl Fn(&mut JobEntry)) -> Option<JobInfo> {
+        let info = {
+            let mut store = self.lock();
+            let entry = store.jobs.get_mut(&id)?;
+            if entry.library != self.library || entry.info.status.is_finished() {
+                return None;
+            }
+
+            f(entry);
+            store
+                .jobs
+                .values_mut()
+                .filter(|e| e.info.parent == Some(id) && !e.info.status.is_finished())
+                .for_each(&f);
+            store.info(&store.jobs[&id])
+        };
+
+        self.shared.changed.notify_waiters();
+        Some(info)
+    }
+
+    /// Sets the status of the given unfinished job. See `control`.
+    fn set_status(&self, id: u64, status: JobStatus) -> Option<JobInfo> {
+        self.control(id, |e| e.set_status(status))
+    }
+
+    /// Sets the priority of the given unfinished job. See `control`.
+    fn set_priority(&self, id: u64, priority: JobPriority) -> Option<JobInfo> {
+        self.control(id, |e| e.info.priority = priority)
+    }
+
+    /// Returns the given job's audio chunks, starting at chunk index `start`, and whether the job is
+    /// finished. Returns `None` if there's no such job in the library.
+    fn audio_from(&self, id: u64, start: usize) -> Option<(Vec<Bytes>, bool)> {
+        let store = self.lock();
+        let entry = self.entry(&store, id)?;
+        let chunks = entry.audio.get(start..).unwrap_or_default().to_vec();
+        Some((chunks, entry.info.status.is_finished()))
+    }
+
+    /// Returns the MP3 of the given chunk of the given job's audio. Returns `None` if there's no
+    /// such job in the library, or it doesn't have that chunk yet.
+    fn audio_chunk(&self, id: u64, chunk: usize) -> Option<Bytes> {
+        self.entry(&self.lock(), id)?.audio.get(chunk).cloned()
+    }
+
+    /// Returns a stream of the given job's audio. The stream starts with everything synthesized so
+    /// far, and then produces new chunks as they're synthesized, until the job finishes. Returns
+    /// `None` if there's no such job in the library.
+    fn audio_stream(&self, id: u64) -> Option<impl Stream<Item = Result<Bytes, Infallible>>> {
+        self.audio_from(id, 0)?;
+
+        let stream = stream::unfold((self.clone(), 0), move |(store, next)| async move {
+            loop {
+                // Start listening for changes before looking at the state, so none are missed
+                let shared = store.shared.clone();
+                let changed = shared.changed.notified();
+
+                let (chunks, finished) = store.audio_from(id, next)?;
+                if !chunks.is_empty() {
+                    let next = next + chunks.len();
+                    let bytes = Bytes::from(chunks.concat());
+                    return Some((Ok(bytes), (store, next)));
+                }
+                if finished {
+                    return None;
+                }
+
+                changed.await;
+            }
+        });
+        Some(stream)
+    }
+
+    /// Applies the given function to the given job's entry, if it exists
+    fn update(&self, id: u64, f: impl FnOnce(&mut JobEntry)) {
+        if let Some(entry) = self.lock().jobs.get_mut(&id) {
+            f(entry);
+        }
+        self.shared.changed.notify_waiters();
+    }
+}
+
+/// The running side of a job. This is how a conversion reports its progress and waits for its turn
+/// to make TTS requests. If the handle is dropped before the job is finished, the job is marked as
+/// failed.
+pub(crate) struct JobHandle {
+    store: JobStore,
+    id: u64,
+}
+
+impl JobHandle {
+    /// Returns the job's ID
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Records the work this job is going to do: the voice it uses, the number of TTS requests it
+    /// makes, and the total number of characters in them. This starts the job's synthesis timer.
+    pub(crate) fn set_plan(&self, engine: &str, chunks_total: usize, chars_total: usize) {
+        self.store.update(self.id, |e| {
+            e.engine = engine.to_string();
+            e.info.chunks_total = chunks_total;
+            e.info.chars_total = chars_total;
+            if e.info.status == JobStatus::Running {
+                e.running_since = Some(Instant::now());
+            }
+        });
+    }
+
+    /// Records that a TTS request of the given number of characters completed
+    pub(crate) fn chunk_done(&self, chars: usize) {
+        self.store.update(self.id, |e| {
+            e.info.chunks_done += 1;
+            e.info.chars_done += chars;
+        });
+    }
+
+    /// Adds the MP3 of the next synthesized chunk to the job's audio
+    pub(crate) fn push_audio(&self, mp3: Bytes) {
+        self.store.update(self.id, |e| {
+            e.audio.push(mp3);
+            e.info.audio_chunks = e.audio.len();
+        });
+    }
+
+    /// Returns whether the job has been cancelled
+    pub(crate) fn is_cancelled(&self) -> bool {
+        let store = self.store.lock();
+        matches!(
+            store.jobs.get(&self.id),
+            Some(e) if e.info.status == JobStatus::Cancelled
+        )
+    }
+
+    /// Waits until the job is running and it's the job's turn to make a TTS request. The returned
+    /// slot must be held for the duration of the request. Returns an error if the job is cancelled
+    /// or otherwise stops running.
+    pub(crate) async fn request_slot(&self) -> Result<RequestSlot, AnyError> {
+        let _waiting = WaitingRequest::new(self);
+
+        loop {
+            // Start listening for changes before looking at the state, so none are missed
+            let changed = self.store.shared.changed.notified();
+
+            {
+                let mut store = self.store.lock();
+                let status = store.jobs.get(&self.id).map(|e| e.info.status);
+                match status {
+                    Some(JobStatus::Running) => {
+                        let has_room = store.in_flight < MAX_CONCURRENT_TTS_REQUESTS;
+                        if has_room && store.next_in_line() == Some(self.id) {
+                            store.in_flight += 1;
+                            return Ok(RequestSlot {
+                                store: self.store.clone(),
+                            });
+                        }
+                    }
+                    Some(JobStatus::Paused) => (),
+                    Some(JobStatus::Cancelled) => bail!("Conversion was cancelled"),
+                    _ => bail!("Conversion is no longer running"),
+                }
+            }
+
+            changed.await;
+        }
+    }
+
+    /// Marks the job as done, and records its throughput
+    pub(crate) fn finish(&self) {
+        let mut store = self.store.lock();
+        let newly_done = match store.jobs.get_mut(&self.id) {
+            Some(e) if !e.info.status.is_finished() => {
+                e.set_status(JobStatus::Done);
+                true
+            }
+            _ => false,
+        };
+        if newly_done {
+            store.record_throughput(self.id);
+        }
+        drop(store);
+
+        self.store.shared.changed.notify_waiters();
+    }
+
+    /// Records the articles the job added, and marks it as done. See `finish`.
+    pub(crate) fn finish_with_articles(&self, metas: &[ArticleMetadata]) {
+        self.store.update(self.id, |e| {
+            e.info.articles = metas.iter().map(|m| m.id.clone()).collect();
+            e.info.incomplete = metas.iter().any(|m| m.incomplete);
+        });
+        self.finish();
+    }
+
+    /// Marks the job as failed with the given error
+    pub(crate) fn fail(&self, err: &str) {
+        self.store.update(self.id, |e| {
+            if !e.info.status.is_finished() {
+                e.info.error = Some(err.to_string());
+                e.set_status(JobStatus::Failed);
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl SynthesisJob for JobHandle {
+    fn set_plan(&self, voice: &str, requests: usize, chars: usize) {
+        JobHandle::set_plan(self, voice, requests, chars)
+    }
+
+    fn chunk_done(&self, chars: usize) {
+        JobHandle::chunk_done(self, chars)
+    }
+
+    fn push_audio(&self, mp3: Bytes) {
+        JobHandle::push_audio(self, mp3)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        JobHandle::is_cancelled(self)
+    }
+
+    async fn request_slot(&self) -> Result<Box<dyn Send>, AnyError> {
+        Ok(Box::new(JobHandle::request_slot(self).await?))
+    }
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        // This does nothing if the job already finished
+        self.fail("Conversion stopped unexpectedly");
+    }
+}
+
+/// Counts a TTS request as waiting for a slot for as long as this exists
+struct WaitingRequest<'a>(&'a JobHandle);
+
+impl<'a> WaitingRequest<'a> {
+    fn new(job: &'a JobHandle) -> Self {
+        job.store.update(job.id, |e| e.waiting += 1);
+        WaitingRequest(job)
+    }
+}
+
+impl Drop for WaitingRequest<'_> {
+    fn drop(&mut self) {
+        self.0.store.update(self.0.id, |e| e.waiting -= 1);
+    }
+}
+
+/// Permission to make one TTS request. The slot is freed when this is dropped.
+pub(crate) struct RequestSlot {
+    store: JobStore,
+}
+
+impl Drop for RequestSlot {
+    fn drop(&mut self) {
+        self.store.lock().in_flight -= 1;
+        self.store.shared.changed.notify_waiters();
+    }
+}
+
+// Sets the /api/list-jobs, /api/jobs, /api/pause-job, /api/resume-job, /api/cancel-job,
+// /api/prioritize-job, and /api/job-audio routes
+pub(crate) fn setup(router: Router, audio_blob_dir: &str, job_store: JobStore) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/list-jobs", get(list_jobs))
+            .route("/jobs/:id", get(job_status))
+            .route("/pause-job", post(pause_job))
+            .route("/resume-job", post(resume_job))
+            .route("/cancel-job", post(cancel_job))
+            .route("/prioritize-job", post(prioritize_job))
+            .route("/job-audio/:id", get(job_audio))
+            .route("/job-audio/:id/:chunk", get(job_audio_chunk))
+            .layer(Extension(job_store))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Lists the library's running and recently finished jobs
+async fn list_jobs(
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<Vec<JobInfo>> {
+    Json(job_store.in_library(&library_dir).list())
+}
+
+/// Reports the progress of the given job
+async fn job_status(
+    Path(id): Path<u64>,
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<JobInfo>, StatusCode> {
+    let job_store = job_store.in_library(&library_dir);
+    job_store.get(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Pauses the given job. Its in-flight TTS requests still finish.
+async fn pause_job(
+    Json(JobControlSubmission { id }): Json<JobControlSubmission>,
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<JobInfo>, StatusCode> {
+    let job_store = job_store.in_library(&library_dir);
+    tracing::debug!("Pausing job {id}");
+    job_store
+        .set_status(id, JobStatus::Paused)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Resumes the given paused job
+async fn resume_job(
+    Json(JobControlSubmission { id }): Json<JobControlSubmission>,
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<JobInfo>, StatusCode> {
+    let job_store = job_store.in_library(&library_dir);
+    tracing::debug!("Resuming job {id}");
+    job_store
+        .set_status(id, JobStatus::Running)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Cancels the given job. Nothing it synthesized is kept.
+async fn cancel_job(
+    Json(JobControlSubmission { id }): Json<JobControlSubmission>,
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<JobInfo>, StatusCode> {
+    let job_store = job_store.in_library(&library_dir);
+    tracing::debug!("Cancelling job {id}");
+    job_store
+        .set_status(id, JobStatus::Cancelled)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Sets the priority of the given job. Its waiting TTS requests go ahead of those of
+/// lower-priority jobs.
+async fn prioritize_job(
+    Json(JobPrioritySubmission { id, priority }): Json<JobPrioritySubmission>,
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<JobInfo>, StatusCode> {
+    let job_store = job_store.in_library(&library_dir);
+    tracing::debug!("Setting priority of job {id} to {:?}", priority);
+    job_store
+        .set_priority(id, priority)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Streams the audio of the given job as an MP3, as it's synthesized. The response ends when the job
+/// does.
+async fn job_audio(
+    Path(id): Path<u64>,
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Response, StatusCode> {
+    let job_store = job_store.in_library(&library_dir);
+    let stream = job_store.audio_stream(id).ok_or(StatusCode::NOT_FOUND)?;
+    let headers = [
+        (header::CONTENT_TYPE, "audio/mpeg"),
+        (header::CACHE_CONTROL, "no-store"),
+    ];
+    Ok((headers, StreamBody::new(stream)).into_response())
+}
+
+/// Returns the MP3 of a single chunk of the given job's audio. This lets the client check the
+/// conversion so far without listening to all of it.
+async fn job_audio_chunk(
+    Path((id, chunk)): Path<(u64, usize)>,
+    Extension(job_store): Extension<JobStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Response, StatusCode> {
+    let job_store = job_store.in_library(&library_dir);
+    let mp3 = job_store
+        .audio_chunk(id, chunk)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(([(header::CONTENT_TYPE, "audio/mpeg")], mp3).into_response())
+}
+
+#[test]
+fn job_lifecycle() {
+    let job_store = JobStore::default();
+    let job = job_store.start("Hello");
+    job.set_plan("voice", 3, 300);
+    job.chunk_done(100);
+
+    // A running job can be paused and resumed
+    let id = job_store.list()[0].id;
+    assert_eq!(
+        job_store.set_status(id, JobStatus::Paused).unwrap().status,
+        JobStatus::Paused
+    );
+    assert_eq!(
+        job_store
+            .set_status(id, JobStatus::Running)
+            .unwrap()
+            .chunks_done,
+        1
+    );
+
+    // Once it's cancelled, it stays cancelled, even if it fails or finishes afterwards
+    job_store.set_status(id, JobStatus::Cancelled).unwrap();
+    assert!(job.is_cancelled());
+    assert!(job_store.set_priority(id, JobPriority::High).is_none());
+    job.finish();
+    drop(job);
+    assert_eq!(job_store.list()[0].status, JobStatus::Cancelled);
+    assert!(job_store.set_status(id, JobStatus::Running).is_none());
+
+    // Dropping an unfinished job marks it as failed
+    drop(job_store.start("World"));
+    let jobs = job_store.list();
+    assert_eq!(jobs[0].title, "World");
+    assert_eq!(jobs[0].status, JobStatus::Failed);
+}
+
+#[test]
+fn job_priorities() {
+    let job_store = JobStore::default();
+    let old = job_store.start("Old");
+    let new = job_store.start("New");
+    let next_in_line = || job_store.lock().next_in_line();
+
+    // Nobody's in line until a job has a waiting request
+    assert_eq!(next_in_line(), None);
+    let _new_waiting = WaitingRequest::new(&new);
+    assert_eq!(next_in_line(), Some(new.id));
+
+    // Among jobs of the same priority, the oldest goes first
+    let old_waiting = WaitingRequest::new(&old);
+    assert_eq!(next_in_line(), Some(old.id));
+
+    // Higher priority jobs go first
+    job_store.set_priority(new.id, JobPriority::High).unwrap();
+    assert_eq!(next_in_line(), Some(new.id));
+
+    // Paused jobs don't get a turn
+    job_store.set_status(new.id, JobStatus::Paused).unwrap();
+    assert_eq!(next_in_line(), Some(old.id));
+    drop(old_waiting);
+    assert_eq!(next_in_line(), None);
+}
+
+#[test]
+fn job_audio_streaming() {
+    use futures::StreamExt;
+
+    let job_store = JobStore::default();
+    let job = job_store.start("Hello");
+    job.push_audio(Bytes::from_static(b"ab"));
+    job.push_audio(Bytes::from_static(b"c"));
+
+    // Everything synthesized so far is available, and the job isn't done
+    let (chunks, finished) = job_store.audio_from(job.id, 0).unwrap();
+    assert_eq!(chunks.concat(), b"abc");
+    assert!(!finished);
+
+    // Asking past the end isn't an error, there's just nothing there yet
+    let (chunks, _) = job_store.audio_from(job.id, 5).unwrap();
+    assert!(chunks.is_empty());
+
+    // Chunks can be fetched individually too
+    assert_eq!(job_store.list()[0].audio_chunks, 2);
+    assert_eq!(job_store.audio_chunk(job.id, 1).unwrap(), &b"c"[..]);
+    assert!(job_store.audio_chunk(job.id, 2).is_none());
+
+    // The stream ends once the job finishes and all its audio has been sent
+    job.finish();
+    let stream = job_store.audio_stream(job.id).unwrap();
+    let sent: Vec<Bytes> = futures::executor::block_on(stream.map(Result::unwrap).collect());
+    assert_eq!(sent.concat(), b"abc");
+}
+
+#[test]
+fn job_eta() {
+    let job_store = JobStore::default();
+
+    // A job that hasn't done anything, with a voice that's never been used, has no ETA
+    let job = job_store.start("Hello");
+    job.set_plan("voice", 2, 1000);
+    assert_eq!(job_store.list()[0].eta_secs, None);
+
+    // Pretend the first half took 10 seconds. Then the rest should take about as long
+    {
+        let mut store = job_store.lock();
+        let entry = store.jobs.get_mut(&job.id).unwrap();
+        entry.active = Duration::from_secs(10);
+        entry.running_since = None;
+    }
+    job.chunk_done(500);
+    assert_eq!(job_store.list()[0].eta_secs, Some(10));
+
+    // Once it's done, its throughput is used for new jobs with the same voice
+    job.finish();
+    assert_eq!(job_store.lock().throughput["voice"], 50.0);
+    let job = job_store.start("World");
+    job.set_plan("voice", 1, 100);
+    assert_eq!(job_store.list()[0].eta_secs, Some(2));
+
+    // But not for other voices
+    let job = job_store.start("Other");
+    job.set_plan("other voice", 1, 100);
+    assert_eq!(job_store.list()[0].eta_secs, None);
+}
+
+#[test]
+fn job_parts() {
+    let job_store = JobStore::default();
+    let job = job_store.start("https://example.com/long");
+    let parts = job_store.under(&job);
+
+    // Until synthesis begins, the job is listed on its own
+    assert_eq!(job_store.list()[0].id, job.id);
+    let part1 = parts.start("Long (Part 1 of 2)");
+    let part2 = parts.start("Long (Part 2 of 2)");
+    futures::executor::block_on(job_store.parts_started(job.id));
+
+    // Once it has parts, they're listed instead, and its progress is the total of theirs
+    let listed: Vec<u64> = job_store.list().iter().map(|j| j.id).collect();
+    assert_eq!(listed, vec![part2.id, part1.id]);
+    part1.set_plan("voice", 2, 200);
+    part2.set_plan("voice", 3, 300);
+    part1.chunk_done(100);
+    part2.chunk_done(100);
+    let info = job_store.get(job.id).unwrap();
+    assert_eq!((info.chunks_done, info.chunks_total), (2, 5));
+    assert_eq!((info.chars_done, info.chars_total), (200, 500));
+
+    // Pausing or cancelling the job does the same to its unfinished parts
+    part1.finish();
+    job_store.set_status(job.id, JobStatus::Paused).unwrap();
+    assert_eq!(job_store.get(part2.id).unwrap().status, JobStatus::Paused);
+    job_store.set_status(job.id, JobStatus::Cancelled).unwrap();
+    assert_eq!(job_store.get(part1.id).unwrap().status, JobStatus::Done);
+    assert!(part2.is_cancelled());
+
+    // Parts started after the job was cancelled start out cancelled
+    assert!(parts.start("Long (Part 3 of 3)").is_cancelled());
+
+    // A job that finishes records the articles it added
+    let job = job_store.start("Short");
+    job.finish_with_articles(&[ArticleMetadata {
+        id: "abc".to_string(),
+        incomplete: true,
+        ..Default::default()
+    }]);
+    let info = job_store.get(job.id).unwrap();
+    assert_eq!(info.status, JobStatus::Done);
+    assert_eq!(info.articles, vec!["abc".to_string()]);
+    assert!(info.incomplete);
+
+    job_store.forget(job.id);
+    assert!(job_store.get(job.id).is_none());
+}
+
+#[test]
+fn jobs_by_library() {
+    let job_store = JobStore::default();
+    let (kim, lee) = (
+        job_store.in_library("users/kim"),
+        job_store.in_library("users/lee"),
+    );
+    let job = kim.start("Kim's article");
+    job.push_audio(Bytes::from_static(b"mp3"));
+    let part = kim.under(&job).start("Kim's article, part 1");
+    let id = job.id();
+
+    // Only the library the job converts into can see it, control it, or listen to it. Its parts
+    // are in the same library.
+    assert_eq!(kim.list().len(), 1);
+    assert_eq!(kim.get(part.id()).unwrap().parent, Some(id));
+    assert_eq!(kim.audio_chunk(id, 0).unwrap(), Bytes::from_static(b"mp3"));
+    assert!(lee.list().is_empty());
+    assert!(lee.get(id).is_none() && lee.get(part.id()).is_none());
+    assert!(lee.set_status(id, JobStatus::Cancelled).is_none());
+    assert!(lee.audio_chunk(id, 0).is_none() && lee.audio_stream(id).is_none());
+    assert_eq!(kim.get(id).unwrap().status, JobStatus::Running);
+
+    part.finish();
+    job.finish();
+}
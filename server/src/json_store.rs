@@ -0,0 +1,130 @@
+//! A value that's kept in memory and saved to a JSON file whenever it changes. This is how the
+//! server keeps its small stores, e.g., accounts, shared links, and feed subscriptions.
+//!
+//! Saves are written to a temporary file next to the real one, which is then renamed over it, so a
+//! crash partway through a save leaves the old file rather than half of the new one. The writing is
+//! done on the blocking thread pool, one save at a time and in the order the changes were made.
+//! Reading the value never waits on the disk.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Error as AnyError};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A value that's saved as JSON whenever it's updated
+pub(crate) struct JsonStore<T> {
+    path: Arc<PathBuf>,
+    /// What's in the store, e.g., "shared links", for errors
+    what: &'static str,
+    value: Arc<Mutex<T>>,
+    /// Held from when a change is made until it's saved, so saves land in order
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+// Derived, this would need `T: Clone`
+impl<T> Clone for JsonStore<T> {
+    fn clone(&self) -> JsonStore<T> {
+        JsonStore {
+            path: self.path.clone(),
+            what: self.what,
+            value: self.value.clone(),
+            saving: self.saving.clone(),
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    /// Loads the value from the given file. If the file doesn't exist, the value is the default.
+    pub(crate) fn load(path: &str, what: &'static str) -> Result<JsonStore<T>, AnyError> {
+        JsonStore::load_with(path, what, |_| ())
+    }
+
+    /// Loads the value like `load`, and then runs the given function on it, e.g., to pick up where
+    /// the last run left off. What the function changes is saved with the next update.
+    pub(crate) fn load_with(
+        path: &str,
+        what: &'static str,
+        f: impl FnOnce(&mut T),
+    ) -> Result<JsonStore<T>, AnyError> {
+        let path = PathBuf::from(path);
+        let mut value = if path.exists() {
+            let json =
+                fs::read(&path).map_err(|e| anyhow!("Could not read {what} {:?}: {e}", path))?;
+            serde_json::from_slice(&json)
+                .map_err(|e| anyhow!("Could not parse {what} {:?}: {e}", path))?
+        } else {
+            T::default()
+        };
+        f(&mut value);
+
+        Ok(JsonStore {
+            path: Arc::new(path),
+            what,
+            value: Arc::new(Mutex::new(value)),
+            saving: Arc::default(),
+        })
+    }
+
+    /// Runs the given function on the value
+    pub(crate) fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.value.lock().unwrap())
+    }
+
+    /// Runs the given function on the value, and saves it afterwards
+    pub(crate) async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, AnyError> {
+        let _saving = self.saving.lock().await;
+        let (res, json) = {
+            let mut value = self.value.lock().unwrap();
+            let res = f(&mut value);
+            (res, serde_json::to_vec_pretty(&*value)?)
+        };
+
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || write_atomically(&path, &json))
+            .await?
+            .map_err(|e| anyhow!("Could not save {} {:?}: {e}", self.what, self.path))?;
+        Ok(res)
+    }
+}
+
+/// Writes the given bytes to a temporary file next to the given path, and then renames it to the
+/// path
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+#[tokio::test]
+async fn saving() {
+    let path = std::env::temp_dir().join(format!("rtms-json-store-{}.json", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    let store: JsonStore<Vec<u32>> = JsonStore::load(path_str, "numbers").unwrap();
+    assert!(store.read(Vec::is_empty));
+
+    // Every update is saved, and nothing is left next to the file
+    let len = store.update(|nums| {
+        nums.extend([1, 2, 3]);
+        nums.len()
+    });
+    assert_eq!(len.await.unwrap(), 3);
+    store.update(|nums| nums.retain(|&n| n != 2)).await.unwrap();
+    let reloaded: JsonStore<Vec<u32>> = JsonStore::load(path_str, "numbers").unwrap();
+    assert_eq!(reloaded.read(Clone::clone), [1, 3]);
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    assert!(!Path::new(&tmp_path).exists());
+
+    // A file that isn't the right JSON isn't loaded over
+    fs::write(&path, "{").unwrap();
+    assert!(JsonStore::<Vec<u32>>::load(path_str, "numbers").is_err());
+    fs::remove_file(&path).unwrap();
+}
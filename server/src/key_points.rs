@@ -0,0 +1,91 @@
+//! Picks the key points of articles with a language model, so they can be read after the article as
+//! a short chapter of their own. That way, an article's takeaways can be replayed without the rest
+//! of it.
+
+use crate::llm::{self, LanguageModel};
+
+use anyhow::{bail, Error as AnyError};
+
+/// How many points are picked
+const NUM_POINTS: usize = 5;
+
+/// The most words in a point. Five points of this length take about a minute to read.
+const MAX_WORDS_PER_POINT: usize = 25;
+
+/// The heading of the key points chapter. It's short and unpunctuated, so it's read as a heading.
+const HEADING: &str = "Key points";
+
+/// Asks the given model for the key points of the given article text, and returns them as the text
+/// of a chapter to append to the article
+pub(crate) async fn summarize(model: &LanguageModel, text: &str) -> Result<String, AnyError> {
+    let instructions = format!(
+        "List the {NUM_POINTS} most important points of the article the user sends. Write each \
+        point as one plain sentence of at most {MAX_WORDS_PER_POINT} words, on its own line, in \
+        the article's language. The points will be read aloud, so don't use markdown, \
+        abbreviations, or anything but the points."
+    );
+    let reply = model
+        .chat(&instructions, &[&llm::article_excerpt(text)])
+        .await?;
+
+    let points = parse_points(&reply);
+    if points.is_empty() {
+        bail!("The model's reply had no points: {reply:?}");
+    }
+    Ok(chapter_text(&points))
+}
+
+/// Returns the points in the given reply of the model. Models number or bullet them and introduce
+/// them no matter what they're told, so that's removed.
+fn parse_points(reply: &str) -> Vec<String> {
+    reply
+        .lines()
+        .map(|line| {
+            line.trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .trim_start_matches(['.', ')', '-', '*', '•'])
+                .trim()
+        })
+        .filter(|point| !point.is_empty() && !point.ends_with(':'))
+        .take(NUM_POINTS)
+        .map(|point| {
+            // Points are read as paragraphs, which mustn't be mistaken for headings
+            let mut point = point.replace("**", "");
+            if !point.ends_with(['.', '!', '?']) {
+                point.push('.');
+            }
+            point
+        })
+        .collect()
+}
+
+/// Returns the text of the key points chapter. It starts on a new paragraph, since it's appended to
+/// the article's text.
+fn chapter_text(points: &[String]) -> String {
+    format!("\n{HEADING}\n{}", points.join("\n"))
+}
+
+#[test]
+fn key_point_parsing() {
+    let reply = "Here are the points:\n\n1. Fish can **swim**.\n2) Chips are fried\n- Both are \
+                 served together!\n* Vinegar is optional.\n• Salt is not.\n6. One too many.";
+    let points = parse_points(reply);
+    assert_eq!(
+        points,
+        [
+            "Fish can swim.",
+            "Chips are fried.",
+            "Both are served together!",
+            "Vinegar is optional.",
+            "Salt is not.",
+        ]
+    );
+
+    // The heading is read as one, and the points aren't
+    let text = chapter_text(&points);
+    let paragraphs: Vec<&str> = text.split('\n').filter(|p| !p.is_empty()).collect();
+    assert!(tts_pipeline::ssml::is_heading(paragraphs[0]));
+    assert!(paragraphs[1..]
+        .iter()
+        .all(|p| !tts_pipeline::ssml::is_heading(p)));
+}
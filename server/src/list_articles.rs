@@ -1,47 +1,135 @@
-use crate::util::get_metadata;
+use crate::{
+    accounts::LibraryDir,
+    pending,
+    util::{get_metadata, now_secs},
+};
 
 use std::{
     collections::BTreeMap,
     ffi::OsStr,
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
 };
 
-use common::{ArticleMetadata, LibraryCatalog};
+use common::{ArticleMetadata, LibraryCatalog, LibraryDelta};
+use tts_pipeline::audio;
 
-use axum::{extract::Extension, http::StatusCode, routing::get, Json, Router};
+use axum::{
+    extract::{Extension, Query},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
 use tower_http::compression::CompressionLayer;
 
-/// The in-memory metadata cache of all the articles in the library. There is currently no way to
-/// invalidate the cache, so if a file changes, the server needs to be restarted.
-type LibraryCache = Arc<Mutex<BTreeMap<PathBuf, ArticleMetadata>>>;
+/// The header that says how many articles matched, before the offset and limit were applied
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
 
-// Sets the /api/list-articles route
-pub(crate) fn setup(router: Router, audio_blob_dir: &str) -> Router {
+/// The in-memory metadata cache of all the articles in the library. Ratings update it when they
+/// change, but there is otherwise no way to invalidate the cache, so if a file changes, the server
+/// needs to be restarted.
+pub(crate) type LibraryCache = Arc<Mutex<BTreeMap<PathBuf, ArticleMetadata>>>;
+
+// Sets the /api/list-articles and /api/library-changes routes
+pub(crate) fn setup(router: Router, audio_blob_dir: &str, library_cache: LibraryCache) -> Router {
     router.nest(
         "/api",
         Router::new()
             .route("/list-articles", get(list_articles))
+            .route("/library-changes", get(library_changes))
             .layer(Extension(audio_blob_dir.to_string()))
-            .layer(Extension(LibraryCache::default()))
+            .layer(Extension(library_cache))
             .layer(CompressionLayer::new()),
     )
 }
 
-/// Lists the articles in the audio blob directory
-async fn list_articles(
-    Extension(audio_blob_dir): Extension<String>,
-    Extension(metadata_cache): Extension<LibraryCache>,
-) -> Result<Json<LibraryCatalog>, StatusCode> {
-    // Try to open the directory
-    let dir: fs::ReadDir = match fs::read_dir(audio_blob_dir) {
-        Ok(d) => d,
-        Err(e) => {
-            tracing::error!("error reading dir {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+/// The orders the catalog can be listed in
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Sort {
+    /// Most recently added first
+    #[default]
+    Date,
+    /// Alphabetically by title
+    Title,
+    /// Shortest first. Articles of unknown length go last.
+    Duration,
+}
+
+/// Narrows down and orders the catalog. With no parameters, every article is listed, most recently
+/// added first.
+#[derive(Debug, Default, Deserialize)]
+struct ListParams {
+    /// Only list articles whose title, author, or publication contains this, ignoring case
+    q: Option<String>,
+    /// The order to list the articles in
+    #[serde(default)]
+    sort: Sort,
+    /// The number of matching articles to skip
+    #[serde(default)]
+    offset: usize,
+    /// The most articles to list
+    limit: Option<usize>,
+}
+
+/// Asks for what changed in the library since the given time
+#[derive(Debug, Deserialize)]
+struct ChangesParams {
+    /// In seconds since the Unix epoch
+    since: u64,
+}
+
+/// Returns whether any of the article's title, author, or publication contains the given lowercase
+/// search term
+fn matches_search(meta: &ArticleMetadata, term: &str) -> bool {
+    [
+        Some(&meta.title),
+        meta.author.as_ref(),
+        meta.publication.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|field| field.to_lowercase().contains(term))
+}
+
+/// Applies the given parameters to the catalog. Returns the page of articles, and the number of
+/// articles that matched in all.
+fn select(
+    mut metadatas: Vec<ArticleMetadata>,
+    params: &ListParams,
+) -> (Vec<ArticleMetadata>, usize) {
+    if let Some(term) = params.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let term = term.to_lowercase();
+        metadatas.retain(|meta| matches_search(meta, &term));
+    }
+
+    match params.sort {
+        Sort::Date => metadatas.sort_by_key(|meta| std::cmp::Reverse(meta.datetime_added)),
+        Sort::Title => metadatas.sort_by_cached_key(|meta| meta.title.to_lowercase()),
+        Sort::Duration => {
+            metadatas.sort_by_key(|meta| (meta.duration_secs.is_none(), meta.duration_secs))
         }
-    };
+    }
+
+    let total = metadatas.len();
+    let page = metadatas
+        .into_iter()
+        .skip(params.offset)
+        .take(params.limit.unwrap_or(usize::MAX))
+        .collect();
+    (page, total)
+}
+
+/// Reads the metadata of every article in the audio blob directory, from the cache where possible
+pub(crate) fn load_catalog(
+    audio_blob_dir: &str,
+    metadata_cache: &LibraryCache,
+) -> Result<Vec<ArticleMetadata>, std::io::Error> {
+    // Try to open the directory
+    let dir: fs::ReadDir = fs::read_dir(audio_blob_dir)?;
 
     // List the directory and collect the metadata
     let metadatas = dir
@@ -67,7 +155,7 @@ async fn list_articles(
                 let meta = cache.get(&path).cloned().or_else(|| {
                     // If this file isn't in the cache, get the metadata
                     already_cached = false;
-                    get_metadata(&entry)
+                    get_metadata(&path)
                         .map_err(|e| tracing::error!("Could not extract metadata: {e}"))
                         .ok()
                 });
@@ -81,19 +169,165 @@ async fn list_articles(
                 meta
             } else {
                 // If the cache lock is poisoned, just get the metadata from the file
-                get_metadata(&entry)
+                get_metadata(&path)
                     .map_err(|e| tracing::error!("Could not extract metadata: {e}"))
                     .ok()
             }
         })
-        .collect::<Vec<ArticleMetadata>>();
+        .map(|mut meta| {
+            // Whether an article is complete, and so how long it is, can change, so these aren't
+            // cached
+            meta.incomplete = pending::exists(audio_blob_dir, &meta.id);
+            let path = Path::new(audio_blob_dir)
+                .join(&meta.id)
+                .with_extension("mp3");
+            meta.duration_secs = audio::mp3_duration_secs(&path);
+            meta
+        })
+        .collect();
+
+    Ok(metadatas)
+}
 
-    // Package the metadata and sort by time modified, most recently modified first
-    let mut library_catalog = LibraryCatalog(metadatas);
-    library_catalog
-        .0
-        .sort_by_key(|meta| std::cmp::Reverse(meta.datetime_added));
+/// Lists the articles in the audio blob directory. The parameters can search, sort, and paginate
+/// the list. The number of articles that matched the search is in the X-Total-Count header.
+async fn list_articles(
+    Query(params): Query<ListParams>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+    Extension(metadata_cache): Extension<LibraryCache>,
+) -> Result<(HeaderMap, Json<LibraryCatalog>), StatusCode> {
+    let metadatas = load_catalog(&audio_blob_dir, &metadata_cache).map_err(|e| {
+        tracing::error!("error reading dir {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Search, sort, and paginate the metadata, and package it
+    let (page, total) = select(metadatas, &params);
+    let mut headers = HeaderMap::new();
+    headers.insert(TOTAL_COUNT_HEADER, HeaderValue::from(total));
 
     // Done
-    Ok(Json(library_catalog))
+    Ok((headers, Json(LibraryCatalog(page))))
+}
+
+/// Returns the changes to the catalog since the given time. `modified` says when an article's file
+/// was last written to. Every change to an article, e.g., rating it, rewrites its file. If it isn't
+/// known when an article was written to, it counts as changed.
+fn changes_since(
+    metadatas: Vec<ArticleMetadata>,
+    since: u64,
+    as_of: u64,
+    modified: impl Fn(&ArticleMetadata) -> Option<u64>,
+) -> LibraryDelta {
+    let ids = metadatas.iter().map(|meta| meta.id.clone()).collect();
+    // A file written to in the same second as the last check might have been missed by it, so that
+    // second counts again
+    let changed = metadatas
+        .into_iter()
+        .filter(|meta| modified(meta).is_none_or(|t| t >= since))
+        .collect();
+
+    LibraryDelta {
+        changed,
+        ids,
+        as_of,
+    }
+}
+
+/// Lists the articles that were added or changed since the given time, and the IDs of every
+/// article, so deletions can be spotted
+async fn library_changes(
+    Query(params): Query<ChangesParams>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+    Extension(metadata_cache): Extension<LibraryCache>,
+) -> Result<Json<LibraryDelta>, StatusCode> {
+    // Note the time first, so nothing written to while the catalog loads is missed next time
+    let as_of = now_secs();
+
+    let metadatas = load_catalog(&audio_blob_dir, &metadata_cache).map_err(|e| {
+        tracing::error!("error reading dir {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let modified = |meta: &ArticleMetadata| {
+        let path = Path::new(&audio_blob_dir)
+            .join(&meta.id)
+            .with_extension("mp3");
+        let t = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+        t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+    };
+    Ok(Json(changes_since(
+        metadatas,
+        params.since,
+        as_of,
+        modified,
+    )))
+}
+
+#[test]
+fn selecting() {
+    let article = |id: &str, title: &str, added: u64, duration: Option<u64>| ArticleMetadata {
+        id: id.to_string(),
+        title: title.to_string(),
+        datetime_added: Some(added),
+        duration_secs: duration,
+        ..Default::default()
+    };
+    let catalog = vec![
+        article("a", "Boats", 1, Some(300)),
+        article("b", "apples", 3, None),
+        article("c", "Cider Apples", 2, Some(100)),
+    ];
+    let ids = |(page, _): &(Vec<ArticleMetadata>, usize)| -> Vec<String> {
+        page.iter().map(|meta| meta.id.clone()).collect()
+    };
+
+    // By default, everything is listed, newest first
+    let all = select(catalog.clone(), &ListParams::default());
+    assert_eq!(ids(&all), ["b", "c", "a"]);
+    assert_eq!(all.1, 3);
+
+    // Searching ignores case, and the total counts every match, not just the page
+    let params = ListParams {
+        q: Some("APPLE".to_string()),
+        sort: Sort::Title,
+        offset: 1,
+        limit: Some(1),
+    };
+    let searched = select(catalog.clone(), &params);
+    assert_eq!(ids(&searched), ["c"]);
+    assert_eq!(searched.1, 2);
+
+    // Articles of unknown length go last
+    let params = ListParams {
+        sort: Sort::Duration,
+        ..Default::default()
+    };
+    assert_eq!(ids(&select(catalog, &params)), ["c", "a", "b"]);
+}
+
+#[test]
+fn changes() {
+    let article = |id: &str| ArticleMetadata {
+        id: id.to_string(),
+        ..Default::default()
+    };
+    let catalog = vec![
+        article("old"),
+        article("edge"),
+        article("new"),
+        article("unknown"),
+    ];
+    let modified = |meta: &ArticleMetadata| match meta.id.as_str() {
+        "old" => Some(90),
+        "edge" => Some(100),
+        "new" => Some(150),
+        _ => None,
+    };
+
+    let delta = changes_since(catalog, 100, 200, modified);
+    let changed: Vec<&str> = delta.changed.iter().map(|meta| meta.id.as_str()).collect();
+    assert_eq!(changed, ["edge", "new", "unknown"]);
+    assert_eq!(delta.ids, ["old", "edge", "new", "unknown"]);
+    assert_eq!(delta.as_of, 200);
 }
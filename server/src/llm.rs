@@ -0,0 +1,118 @@
+//! A barebones client to a language model behind an OpenAI-compatible chat completions API. The
+//! model is optional, and so is everything that uses it: key points and questions about articles.
+
+use crate::config::LanguageModelConfig;
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Error as AnyError};
+use serde::Deserialize;
+
+/// The most characters of an article a model is given. Of longer articles, only the beginning is
+/// sent.
+const MAX_ARTICLE_CHARS: usize = 60_000;
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: String,
+}
+
+/// The model from the config, and its API key
+struct Model {
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+/// The language model this server uses, if it has one
+#[derive(Clone, Default)]
+pub(crate) struct LanguageModel(Option<Arc<Model>>);
+
+impl LanguageModel {
+    /// Sets up the given model. `None` means there's no model.
+    pub(crate) fn new(config: Option<LanguageModelConfig>) -> Result<LanguageModel, AnyError> {
+        let config = match config {
+            Some(c) => c,
+            None => return Ok(LanguageModel::default()),
+        };
+        let api_key = config
+            .api_key_file
+            .map(|path| {
+                std::fs::read_to_string(&path)
+                    .map(|key| key.trim().to_string())
+                    .map_err(|e| anyhow!("Could not open key file {:?}: {e}", path))
+            })
+            .transpose()?;
+
+        Ok(LanguageModel(Some(Arc::new(Model {
+            endpoint: config.endpoint,
+            model: config.model,
+            api_key,
+        }))))
+    }
+
+    /// Returns whether there's a model
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Sends the given messages to the model, and returns its reply. `instructions` is the system
+    /// message, and the others are the user's, in order.
+    pub(crate) async fn chat(
+        &self,
+        instructions: &str,
+        messages: &[&str],
+    ) -> Result<String, AnyError> {
+        let model = self
+            .0
+            .as_ref()
+            .ok_or_else(|| anyhow!("No language model is configured"))?;
+
+        let client = reqwest::Client::new();
+        let body = request_body(&model.model, instructions, messages);
+        let mut req = client.post(&model.endpoint).json(&body);
+        if let Some(key) = &model.api_key {
+            req = req.bearer_auth(key);
+        }
+        let res: ChatResponse = req
+            .send()
+            .await
+            .with_context(|| "Couldn't make language model request")?
+            .error_for_status()
+            .with_context(|| "Language model request failed")?
+            .json()
+            .await?;
+
+        match res.choices.into_iter().next() {
+            Some(choice) => Ok(choice.message.content),
+            None => bail!("The model didn't reply"),
+        }
+    }
+}
+
+/// Returns the JSON body of a chat completions request
+fn request_body(model: &str, instructions: &str, messages: &[&str]) -> serde_json::Value {
+    let messages: Vec<serde_json::Value> = std::iter::once(("system", instructions))
+        .chain(messages.iter().map(|&m| ("user", m)))
+        .map(|(role, content)| serde_json::json!({ "role": role, "content": content }))
+        .collect();
+    serde_json::json!({
+        "model": model,
+        "messages": messages,
+    })
+}
+
+/// Returns the given article text, cut down to what a model is given
+pub(crate) fn article_excerpt(text: &str) -> String {
+    text.chars().take(MAX_ARTICLE_CHARS).collect()
+}
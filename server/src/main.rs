@@ -1,7 +1,59 @@
+mod accounts;
 mod add_article;
+mod admin;
+mod ambient;
+mod archive;
+mod ask;
+mod audio_blobs;
+mod bundle;
+mod calendar;
+mod canonical;
+mod config;
+mod cors;
+mod csp;
+mod csv;
+mod declutter;
+mod demo;
+mod documents;
+mod encrypted;
+mod engines;
+mod export;
+mod extract;
+mod federation;
+mod feeds;
+mod fetcher;
+mod frontmatter;
+mod images;
+mod import;
+mod inbox;
+mod jobs;
+mod json_store;
+mod key_points;
 mod list_articles;
-mod tts;
+mod llm;
+mod mpd;
+mod normalize;
+mod page_cache;
+mod peers;
+mod pending;
+mod plugins;
+mod podcast;
+mod push;
+mod qr;
+mod rate_limit;
+mod ratings;
+mod reading_profile;
+mod reminders;
+mod remote;
+mod scripts;
+mod search;
+mod shares;
+mod subpath;
+mod sync;
+mod totp;
+mod transcript;
 mod util;
+mod voices;
 
 use std::{
     future::ready,
@@ -9,16 +61,19 @@ use std::{
     num::NonZeroU32,
     path::PathBuf,
     str::FromStr,
+    time::Duration,
 };
 
 use axum::{
     body::Body,
     http::{HeaderValue, Request, StatusCode},
-    response::Response,
+    response::{Html, Response},
     routing::{get, get_service},
     Router,
 };
 use clap::Parser;
+use config::ServerConfig;
+use extract::{ExtractionRules, HtmlArchive, HtmlRetention};
 use tower::ServiceBuilder;
 use tower_http::{
     services::{ServeDir, ServeFile},
@@ -31,6 +86,10 @@ use tower_http::{
     about = "The primary backend for Readtomyshoe"
 )]
 struct Opt {
+    /// The path to the config file. See `config.rs` for its contents
+    #[clap(short = 'c', long = "config")]
+    config_file: Option<PathBuf>,
+
     /// The log level
     #[clap(short = 'l', long = "log", default_value = "debug")]
     log_level: String,
@@ -60,14 +119,111 @@ struct Opt {
     /// caution: a malicious user can rack up your Google Cloud costs.
     #[clap(long = "max-chars-per-min", default_value = "5000000")]
     max_chars_per_min: NonZeroU32,
+
+    /// Runs a public demo. The library in the audio blob directory is read-only, accounts are off,
+    /// and nothing but short voice previews is synthesized. See `demo.rs`.
+    #[clap(long = "demo")]
+    demo: bool,
+
+    /// The directory where the HTML of fetched articles is kept, so that articles can be
+    /// re-extracted without fetching them again
+    #[clap(long = "html-archive-dir", default_value = "html_archive")]
+    html_archive_dir: String,
+
+    /// How long to keep fetched HTML. One of "never", "forever", or a number of days, e.g., "30d"
+    #[clap(long = "html-retention", default_value = "forever")]
+    html_retention: HtmlRetention,
+
+    /// The directory where fetched pages are cached, so that previewing an article and then adding
+    /// it doesn't fetch it twice
+    #[clap(long = "page-cache-dir", default_value = "page_cache")]
+    page_cache_dir: String,
+
+    /// How long a fetched page is cached for, in minutes. 0 turns the cache off.
+    #[clap(long = "page-cache-minutes", default_value = "60")]
+    page_cache_minutes: u64,
+
+    /// The directory where the full-text search index of the articles is kept
+    #[clap(long = "search-index-dir", default_value = "search_index")]
+    search_index_dir: String,
+
+    /// The file where the per-site rules for finding the body of an article are kept
+    #[clap(long = "extraction-rules", default_value = "extraction_rules.json")]
+    extraction_rules_path: String,
+
+    /// The directory of site-specific extractor plugins. Each is a WASI module, `<name>.wasm`, with
+    /// a manifest, `<name>.json`, listing the sites it handles. They're run with the wasmtime CLI,
+    /// which has to be on the `PATH`.
+    #[clap(long = "extractor-plugins", default_value = "extractor_plugins")]
+    extractor_plugins_dir: String,
+
+    /// The file where the feed subscriptions are kept
+    #[clap(long = "feeds", default_value = "feeds.json")]
+    feeds_path: String,
+
+    /// How often to check the subscribed feeds for new articles, in minutes
+    #[clap(long = "feed-poll-minutes", default_value = "60")]
+    feed_poll_minutes: u64,
+
+    /// The file where the read-it-later inbox is kept
+    #[clap(long = "inbox", default_value = "inbox.json")]
+    inbox_path: String,
+
+    /// The file where the "listen later" reminders and push subscriptions are kept
+    #[clap(long = "reminders", default_value = "reminders.json")]
+    reminders_path: String,
+
+    /// If this is given, the library is exported to this directory, and the server exits rather than
+    /// starting. See `export.rs`.
+    #[clap(long = "export-library")]
+    export_dir: Option<PathBuf>,
+
+    /// The file where the key for sending push notifications is kept. It's made if it's missing.
+    #[clap(long = "vapid-key", default_value = "vapid_key.pem")]
+    vapid_key_path: String,
+
+    /// The address to serve MPD clients on, e.g., "127.0.0.1:6600", so desktop media controls can
+    /// drive the player. If this isn't given, there's no MPD socket.
+    #[clap(long = "mpd-addr")]
+    mpd_addr: Option<SocketAddr>,
+
+    /// The file where the accounts are kept. If this is given, everyone has their own library and
+    /// has to sign in. Otherwise, there's one library, open to whoever can reach the server. See
+    /// `accounts.rs`.
+    #[clap(long = "accounts")]
+    accounts_path: Option<String>,
+
+    /// The file where the links that share articles are kept
+    #[clap(long = "shares", default_value = "shares.json")]
+    shares_path: String,
+
+    /// The file where the key that signs shared links is kept. It's made if it's missing. Deleting
+    /// it revokes every shared link.
+    #[clap(long = "share-key", default_value = "share_key")]
+    share_key_path: String,
+
+    /// The file where the subscriptions to other servers' shared collections are kept
+    #[clap(
+        long = "collection-subscriptions",
+        default_value = "collection_subscriptions.json"
+    )]
+    collection_subscriptions_path: String,
+
+    /// The file where the times each article's audio was last fetched are kept, for deleting the
+    /// least recently fetched when the audio takes up too much space. See `admin.rs`.
+    #[clap(long = "audio-fetches", default_value = "audio-fetches.json")]
+    audio_fetches_path: String,
 }
 
 #[tokio::main]
 async fn main() {
     let opt = Opt::parse();
 
-    // Check up front that the Google cloud API key was set
-    tts::get_api_key().unwrap();
+    // Load the config file if one was given
+    let config = match opt.config_file {
+        Some(ref path) => ServerConfig::load(path).unwrap(),
+        None => ServerConfig::default(),
+    };
 
     // Setup logging & RUST_LOG from args
     if std::env::var("RUST_LOG").is_err() {
@@ -76,32 +232,242 @@ async fn main() {
 
     tracing_subscriber::fmt::init();
 
+    // If this is just an export, do it and stop
+    if let Some(ref export_dir) = opt.export_dir {
+        let copied = export::export_library(&opt.audio_blob_dir, export_dir).unwrap();
+        tracing::info!(
+            "Exported {} new articles to {}",
+            copied,
+            export_dir.display()
+        );
+        return;
+    }
+
+    // Set up the TTS engine. This checks up front that its keys are there.
+    let engine = engines::from_config(&config.engine).unwrap();
+
     // A generic error handler that just returns 500
     let ret_500 = |_| ready(StatusCode::INTERNAL_SERVER_ERROR);
 
     // Make a service that returns the static assets from /assets, and serves index.html
-    // everywhere else. Also try to serve gzipped assets when the .gz file exists
+    // everywhere else. Also try to serve gzipped assets when the .gz file exists. Under a subpath,
+    // index.html is rewritten to load the assets from there, so it's served from memory.
     let static_dir: PathBuf = opt.static_dir.as_str().into();
+    let index_path = static_dir.join(&opt.index_file);
+    let base_path = subpath::BasePath::new(config.base_url.as_deref()).unwrap();
+    let base_url = subpath::BaseUrl::new(config.base_url.as_deref());
+    let index_html = subpath::load_index(&index_path, &base_path);
     let asset_service = get_service(ServeDir::new(&opt.static_dir).precompressed_gzip());
-    let index_service =
-        get_service(ServeFile::new(static_dir.join(&opt.index_file)).precompressed_gzip());
     let asset_router = Router::new()
-        .nest("/assets", asset_service.handle_error(ret_500.clone()))
-        .layer(ServiceBuilder::new().map_response(add_asset_headers))
-        .fallback(index_service.handle_error(ret_500.clone()));
+        .nest("/assets", asset_service.handle_error(ret_500))
+        .layer(ServiceBuilder::new().map_response(add_asset_headers));
+    let asset_router = if base_path.is_root() {
+        let index_service = get_service(ServeFile::new(&index_path).precompressed_gzip());
+        asset_router.fallback(index_service.handle_error(ret_500))
+    } else {
+        let index_html = index_html.clone();
+        asset_router.fallback(get(move || ready(Html(index_html.clone()))))
+    };
 
-    // Make a service that just returns files from /audio_blobs
-    let audio_blob_service =
-        get_service(ServeDir::new("audio_blobs")).handle_error(ret_500.clone());
-    let app = asset_router.nest("/api/audio-blobs", audio_blob_service);
+    // Serve the audio at /audio/, and redirect to it from /api/audio-blobs/
+    let audio_hashes = audio_blobs::AudioHashes::default();
+    let transcode_formats = audio_blobs::TranscodeFormats::new(config.transcode_formats.clone());
+    let fetch_log = admin::FetchLog::load(&opt.audio_fetches_path).unwrap();
+    let app = audio_blobs::setup(
+        asset_router,
+        &opt.audio_blob_dir,
+        audio_hashes.clone(),
+        transcode_formats,
+        fetch_log.clone(),
+    );
 
     // Set up /api/
-    let app = list_articles::setup(app, &opt.audio_blob_dir);
-    let app = add_article::setup(app, opt.max_chars_per_min, &opt.audio_blob_dir);
+    let library_cache = list_articles::LibraryCache::default();
+    let app = list_articles::setup(app, &opt.audio_blob_dir, library_cache.clone());
+    let app = ratings::setup(app, library_cache.clone());
+    let app = transcript::setup(app, &opt.audio_blob_dir);
+    let app = images::setup(app, &opt.audio_blob_dir);
+    let app = podcast::setup(
+        app,
+        &opt.audio_blob_dir,
+        config.podcast_token,
+        base_url.clone(),
+        audio_hashes.clone(),
+    );
+    let max_chars_per_min = match NonZeroU32::new(demo::MAX_CHARS_PER_MIN) {
+        Some(demo_max) if opt.demo => opt.max_chars_per_min.min(demo_max),
+        _ => opt.max_chars_per_min,
+    };
+    let tts_rate_limiter = rate_limit::RateLimiter::new(max_chars_per_min);
+    let html_archive = HtmlArchive::new(&opt.html_archive_dir, opt.html_retention);
+    html_archive.spawn_pruner();
+    let ambient_beds = ambient::AmbientBeds::new(config.ambient_beds);
+    let job_store = jobs::JobStore::default();
+    let search_index =
+        search::SearchIndex::open(&opt.search_index_dir, &opt.audio_blob_dir).unwrap();
+    let extractor_plugins = plugins::ExtractorPlugins::load(&opt.extractor_plugins_dir).unwrap();
+    // The transform scripts are kept next to the config file
+    let scripts_dir = match opt.config_file {
+        Some(ref path) => path.with_file_name("scripts"),
+        None => PathBuf::from("scripts"),
+    };
+    let transform_scripts = scripts::TransformScripts::load(&scripts_dir).unwrap();
+    let extraction_rules = ExtractionRules::load(&opt.extraction_rules_path)
+        .unwrap()
+        .with_plugins(extractor_plugins)
+        .with_transforms(transform_scripts);
+    let clutter_rules = declutter::ClutterRules::new(config.clutter_overrides);
+    let fetcher = fetcher::Fetcher::new(config.fetch).unwrap();
+    let page_cache = page_cache::PageCache::new(
+        &opt.page_cache_dir,
+        Duration::from_secs(60 * opt.page_cache_minutes),
+        fetcher.clone(),
+    );
+    let voice_registry = voices::VoiceRegistry::new(engine, config.custom_voices, config.encoding);
+    let language_model = llm::LanguageModel::new(config.language_model).unwrap();
+    let converter = add_article::Converter {
+        tts_rate_limiter: tts_rate_limiter.clone(),
+        voice_registry: voice_registry.clone(),
+        audio_blob_dir: opt.audio_blob_dir.clone(),
+        html_archive: html_archive.clone(),
+        ambient_beds: ambient_beds.clone(),
+        job_store: job_store.in_library(&opt.audio_blob_dir),
+        search_index: search_index.clone(),
+        extraction_rules,
+        clutter_rules,
+        page_cache: page_cache.clone(),
+        language_model: language_model.clone(),
+    };
+    let app = add_article::setup(app, converter.clone());
+    let app = encrypted::setup(
+        app,
+        tts_rate_limiter.clone(),
+        voice_registry.clone(),
+        ambient_beds.clone(),
+        &opt.audio_blob_dir,
+    );
+    let max_preview_chars = if opt.demo {
+        demo::MAX_PREVIEW_CHARS
+    } else {
+        common::MAX_PREVIEW_CHARS
+    };
+    let app = voices::setup(app, tts_rate_limiter, voice_registry, max_preview_chars);
+    let app = ambient::setup(app, ambient_beds);
+    let app = ask::setup(app, language_model);
+    let app = jobs::setup(app, &opt.audio_blob_dir, job_store);
+    let library_stores = admin::LibraryStores {
+        shares: shares::ShareStore::load(&opt.shares_path, &opt.share_key_path).unwrap(),
+        collection_subscriptions: federation::SubscriptionStore::load(
+            &opt.collection_subscriptions_path,
+        )
+        .unwrap(),
+        reminders: reminders::ReminderStore::load(&opt.reminders_path).unwrap(),
+        inbox: inbox::InboxStore::load(&opt.inbox_path, config.inbox_rules).unwrap(),
+        feeds: feeds::FeedStore::load(&opt.feeds_path).unwrap(),
+        remote_controls: remote::RemoteControls::default(),
+    };
+    let app = export::setup(
+        app,
+        &opt.audio_blob_dir,
+        search_index.clone(),
+        library_stores.clone(),
+    );
+    let app = bundle::setup(app, &opt.audio_blob_dir, search_index.clone());
+    let disk_manager = admin::DiskManager {
+        audio_blob_dir: opt.audio_blob_dir.clone(),
+        max_bytes: config.max_audio_megabytes.map(|mb| mb * 1_000_000),
+        fetch_log,
+        library_cache,
+        audio_hashes,
+        html_archive,
+        search_index: search_index.clone(),
+        page_cache: page_cache.clone(),
+        library_stores: library_stores.clone(),
+    };
+    // The demo library is curated, so nothing in the background adds to it or deletes from it
+    if !opt.demo {
+        disk_manager.spawn_sweeper();
+    }
+    let app = admin::setup(app, disk_manager.clone());
+    let app = search::setup(app, &opt.audio_blob_dir, search_index);
+    let admin::LibraryStores {
+        shares: share_store,
+        collection_subscriptions: sub_store,
+        reminders: reminder_store,
+        inbox: inbox_store,
+        feeds: feed_store,
+        remote_controls,
+    } = library_stores;
+    let feed_poll_interval = Duration::from_secs(60 * opt.feed_poll_minutes);
+    if !opt.demo {
+        feeds::spawn_poller(
+            feed_store.clone(),
+            converter.clone(),
+            feed_poll_interval,
+            reminder_store.clone(),
+        );
+    }
+    let app = feeds::setup(app, &opt.audio_blob_dir, feed_store, converter.clone());
+    let app = import::setup(app, converter.clone());
+    let app = documents::setup(app);
+    if !opt.demo {
+        inbox::spawn_worker(inbox_store.clone(), converter);
+    }
+    let app = inbox::setup(app, &opt.audio_blob_dir, inbox_store, page_cache);
+    let web_push =
+        push::WebPush::load_or_generate(&opt.vapid_key_path, config.push_contact, fetcher.clone())
+            .unwrap();
+    // Nobody can set reminders in a demo, so there's nothing to send
+    if !opt.demo {
+        reminders::spawn_scheduler(reminder_store.clone(), web_push.clone());
+    }
+    let app = calendar::setup(
+        app,
+        &opt.audio_blob_dir,
+        config.calendar_token,
+        base_url,
+        reminder_store.clone(),
+    );
+    let app = reminders::setup(app, &opt.audio_blob_dir, reminder_store, web_push);
+    if let Some(mpd_addr) = opt.mpd_addr {
+        mpd::spawn_server(mpd_addr, remote_controls.library(&opt.audio_blob_dir))
+            .await
+            .unwrap();
+    }
+    let app = remote::setup(app, &opt.audio_blob_dir, remote_controls);
+    let app = shares::setup(app, &opt.audio_blob_dir, share_store);
+    let app = federation::setup(app, sub_store, fetcher);
+    let app = sync::setup(app, &opt.audio_blob_dir);
+    let app = peers::setup(app, &opt.audio_blob_dir);
 
-    // Make a /healthz endpoint for Docker health checks
+    // Set up accounts last, since they guard everything above. A demo has none.
+    if opt.demo && opt.accounts_path.is_some() {
+        tracing::warn!("Accounts are off in demo mode");
+    }
+    let accounts = opt
+        .accounts_path
+        .as_deref()
+        .filter(|_| !opt.demo)
+        .map(|path| {
+            accounts::AccountStore::load(path, config.signup_code, config.cookies, config.admins)
+                .unwrap()
+        });
+    let app = accounts::setup(app, accounts, opt.demo, disk_manager);
+    let app = if opt.demo { demo::setup(app) } else { app };
+
+    // Let the sites in the config use the API. This wraps accounts, so preflight requests are
+    // answered before they need a session.
+    let app = cors::setup(app, config.cors);
+
+    // Put everything under the base path, if there is one
+    let app = subpath::setup(app, base_path);
+
+    // Make a /healthz endpoint for Docker health checks. It's at the root, whatever the base path.
     let app = app.route("/healthz", get(|| async { "ok" }));
 
+    // Send everything with a Content Security Policy
+    let app = csp::setup(app, &index_html, &config.embed_origins, &config.app_servers);
+
     // Tracing for the entire app
     let app = app.layer(
         TraceLayer::new_for_http().make_span_with(|req: &Request<Body>| {
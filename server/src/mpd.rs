@@ -0,0 +1,350 @@
+//! A socket that speaks enough of the MPD protocol for desktop media controls to drive the player.
+//! MPD clients, and bridges like mpDris2 that expose MPD over MPRIS, see the player as an MPD
+//! server with a one-song playlist. Their commands go to the player through the remote control
+//! channel, so the player has to have remote control turned on. This is off unless `--mpd-addr` is
+//! given, and should only listen on the loopback interface, since MPD has no authentication here.
+//! For the same reason, it only drives the player of the shared library, not an account's.
+
+use crate::remote::RemoteControl;
+use common::{PlayerStatus, RemoteCommand};
+
+use std::{fmt::Write, net::SocketAddr, time::Duration};
+
+use anyhow::Error as AnyError;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// What the server says when a client connects. Clients check the version to see what they can
+/// send.
+const GREETING: &str = "OK MPD 0.21.0\n";
+
+/// How often an idling client's player status is checked for changes
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The error codes MPD sends in an `ACK`
+const ACK_ERROR_ARG: u32 = 2;
+const ACK_ERROR_UNKNOWN: u32 = 5;
+const ACK_ERROR_SYSTEM: u32 = 52;
+
+/// A command that failed
+#[derive(Debug, PartialEq)]
+struct Ack {
+    code: u32,
+    command: String,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: u32, command: &str, message: &str) -> Self {
+        Ack {
+            code,
+            command: command.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Formats the error as MPD does. `index` is the position of the failed command in its
+    /// command list, or 0 if it wasn't in one.
+    fn render(&self, index: usize) -> String {
+        format!(
+            "ACK [{}@{}] {{{}}} {}\n",
+            self.code, index, self.command, self.message
+        )
+    }
+}
+
+/// Binds the given address, and spawns a background task that serves MPD clients on it
+pub(crate) async fn spawn_server(
+    addr: SocketAddr,
+    remote_control: RemoteControl,
+) -> Result<(), AnyError> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Serving MPD clients on {}", addr);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::error!("Error accepting MPD client: {e}");
+                    continue;
+                }
+            };
+            let remote_control = remote_control.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_client(stream, remote_control).await {
+                    tracing::debug!("MPD client {peer} disconnected: {e}");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// What an idling client is told about. It's told once this changes.
+fn idle_snapshot(remote_control: &RemoteControl) -> Option<(Option<String>, bool)> {
+    remote_control
+        .status()
+        .map(|status| (status.title, status.playing))
+}
+
+/// Serves one client until it disconnects
+async fn serve_client(stream: TcpStream, remote_control: RemoteControl) -> Result<(), AnyError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut session = Session::new(remote_control.clone());
+    writer.write_all(GREETING.as_bytes()).await?;
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        match line.split_whitespace().next() {
+            Some("close") => break,
+            Some("idle") => {
+                // Wait for the player to change, or for the client to take back the idle
+                let before = idle_snapshot(&remote_control);
+                let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+                let changed = loop {
+                    tokio::select! {
+                        next = lines.next_line() => match next? {
+                            // Any command ends the idle, but only noidle is allowed
+                            Some(_) => break false,
+                            None => return Ok(()),
+                        },
+                        _ = interval.tick() => {
+                            if idle_snapshot(&remote_control) != before {
+                                break true;
+                            }
+                        }
+                    }
+                };
+                let resp = if changed {
+                    "changed: player\nOK\n"
+                } else {
+                    "OK\n"
+                };
+                writer.write_all(resp.as_bytes()).await?;
+            }
+            _ => {
+                if let Some(resp) = session.handle_line(line) {
+                    writer.write_all(resp.as_bytes()).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The state of a client's connection, apart from idling
+struct Session {
+    remote_control: RemoteControl,
+    /// The commands of the command list being sent, if any, and whether each one's success should
+    /// be acknowledged with `list_OK`
+    command_list: Option<(Vec<String>, bool)>,
+}
+
+impl Session {
+    fn new(remote_control: RemoteControl) -> Self {
+        Session {
+            remote_control,
+            command_list: None,
+        }
+    }
+
+    /// Handles a line from the client, and returns the response. There's no response while a
+    /// command list is being sent.
+    fn handle_line(&mut self, line: &str) -> Option<String> {
+        match (line, self.command_list.as_mut()) {
+            ("command_list_begin", None) => {
+                self.command_list = Some((Vec::new(), false));
+                None
+            }
+            ("command_list_ok_begin", None) => {
+                self.command_list = Some((Vec::new(), true));
+                None
+            }
+            ("command_list_end", Some(_)) => {
+                let (commands, list_ok) = self.command_list.take()?;
+                let mut resp = String::new();
+                for (i, command) in commands.iter().enumerate() {
+                    match respond(command, &self.remote_control) {
+                        Ok(out) => {
+                            resp.push_str(&out);
+                            if list_ok {
+                                resp.push_str("list_OK\n");
+                            }
+                        }
+                        // The rest of the list isn't run
+                        Err(ack) => return Some(resp + ack.render(i).as_str()),
+                    }
+                }
+                Some(resp + "OK\n")
+            }
+            (_, Some((commands, _))) => {
+                commands.push(line.to_string());
+                None
+            }
+            (_, None) => Some(match respond(line, &self.remote_control) {
+                Ok(out) => out + "OK\n",
+                Err(ack) => ack.render(0),
+            }),
+        }
+    }
+}
+
+/// Renders the `status` response for the given player status. Without one, the player is stopped.
+fn render_status(status: Option<&PlayerStatus>) -> String {
+    let mut out = "volume: -1\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\n".to_string();
+    match status {
+        Some(status) if status.title.is_some() => {
+            let state = if status.playing { "play" } else { "pause" };
+            let _ = write!(
+                out,
+                "playlist: 1\nplaylistlength: 1\nstate: {state}\nsong: 0\nsongid: 1\n\
+                 time: {}:{}\nelapsed: {:.3}\nduration: {:.3}\n",
+                status.elapsed_secs as u64,
+                status.duration_secs as u64,
+                status.elapsed_secs,
+                status.duration_secs,
+            );
+        }
+        _ => out.push_str("playlist: 1\nplaylistlength: 0\nstate: stop\n"),
+    }
+    out
+}
+
+/// Renders the `currentsong` response for the given player status
+fn render_current_song(status: Option<&PlayerStatus>) -> String {
+    match status {
+        Some(PlayerStatus {
+            title: Some(title),
+            duration_secs,
+            ..
+        }) => {
+            // Responses are line-based, so the title has to fit on one line
+            let title = title.replace(['\r', '\n'], " ");
+            format!(
+                "file: {title}\nTitle: {title}\nArtist: ReadToMyShoe\nTime: {}\n\
+                 duration: {duration_secs:.3}\nPos: 0\nId: 1\n",
+                *duration_secs as u64,
+            )
+        }
+        _ => String::new(),
+    }
+}
+
+/// Runs a single command, and returns its output
+fn respond(line: &str, remote_control: &RemoteControl) -> Result<String, Ack> {
+    let mut words = line.split_whitespace().map(|word| word.trim_matches('"'));
+    let name = words.next().unwrap_or_default();
+    let arg = words.next();
+
+    let command = match (name, arg) {
+        ("ping", _) => return Ok(String::new()),
+        ("status", _) => return Ok(render_status(remote_control.status().as_ref())),
+        ("currentsong", _) => return Ok(render_current_song(remote_control.status().as_ref())),
+        ("stats", _) => return Ok("songs: 1\nuptime: 0\nplaytime: 0\n".to_string()),
+        ("play" | "playid", _) => RemoteCommand::Play,
+        ("pause", None) => RemoteCommand::TogglePlay,
+        ("pause", Some("1")) | ("stop", _) => RemoteCommand::Pause,
+        ("pause", Some("0")) => RemoteCommand::Play,
+        ("next", _) => RemoteCommand::Next,
+        ("previous", _) => RemoteCommand::Previous,
+        // The player only jumps by its set amounts, so relative seeks just say which way to jump
+        ("seekcur", Some(offset)) if offset.starts_with('+') => RemoteCommand::JumpForward,
+        ("seekcur", Some(offset)) if offset.starts_with('-') => RemoteCommand::JumpBackward,
+        ("seekcur", _) => {
+            return Err(Ack::new(
+                ACK_ERROR_ARG,
+                name,
+                "Only relative seeks are supported",
+            ))
+        }
+        ("pause", Some(_)) => return Err(Ack::new(ACK_ERROR_ARG, name, "Boolean (0/1) expected")),
+        _ => {
+            let message = format!("unknown command \"{name}\"");
+            return Err(Ack::new(ACK_ERROR_UNKNOWN, name, &message));
+        }
+    };
+
+    if remote_control.status().is_none() {
+        return Err(Ack::new(
+            ACK_ERROR_SYSTEM,
+            name,
+            "The player isn't listening. Turn on remote control in the player.",
+        ));
+    }
+    remote_control.send(command);
+    Ok(String::new())
+}
+
+#[test]
+fn mpd_commands() {
+    let remote = RemoteControl::default();
+    let mut session = Session::new(remote.clone());
+    let seq = remote.commands_after(None).latest;
+
+    // Nothing is playing until the player reports
+    assert_eq!(session.handle_line("ping").unwrap(), "OK\n");
+    assert!(session
+        .handle_line("status")
+        .unwrap()
+        .contains("state: stop\n"));
+    assert!(session
+        .handle_line("pause")
+        .unwrap()
+        .starts_with("ACK [52@0] {pause}"));
+
+    remote.report(PlayerStatus {
+        title: Some("Fish\nand Chips".to_string()),
+        playing: true,
+        elapsed_secs: 75.5,
+        duration_secs: 600.0,
+    });
+    let status = session.handle_line("status").unwrap();
+    assert!(status.contains("state: play\n"));
+    assert!(status.contains("elapsed: 75.500\n"));
+    assert!(status.contains("time: 75:600\n"));
+    assert!(session
+        .handle_line("currentsong")
+        .unwrap()
+        .contains("Title: Fish and Chips\n"));
+
+    // Commands go to the player
+    assert_eq!(session.handle_line("pause \"1\"").unwrap(), "OK\n");
+    assert_eq!(session.handle_line("seekcur -10").unwrap(), "OK\n");
+    assert!(session
+        .handle_line("seekcur 10")
+        .unwrap()
+        .starts_with("ACK [2@0] {seekcur}"));
+    assert!(session
+        .handle_line("listall")
+        .unwrap()
+        .starts_with("ACK [5@0] {listall}"));
+
+    // Command lists run in order, and stop at the first error
+    assert_eq!(session.handle_line("command_list_ok_begin"), None);
+    assert_eq!(session.handle_line("next"), None);
+    assert_eq!(session.handle_line("bogus"), None);
+    assert_eq!(session.handle_line("previous"), None);
+    assert_eq!(
+        session.handle_line("command_list_end").unwrap(),
+        "list_OK\nACK [5@1] {bogus} unknown command \"bogus\"\n"
+    );
+    assert_eq!(session.handle_line("command_list_begin"), None);
+    assert_eq!(session.handle_line("pause"), None);
+    assert_eq!(session.handle_line("command_list_end").unwrap(), "OK\n");
+
+    assert_eq!(
+        remote.commands_after(Some(seq)).commands,
+        [
+            RemoteCommand::Pause,
+            RemoteCommand::JumpBackward,
+            RemoteCommand::Next,
+            RemoteCommand::TogglePlay
+        ]
+    );
+}
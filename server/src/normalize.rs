@@ -0,0 +1,137 @@
+//! Cleans up article text before it's synthesized. Text copied out of PDFs, and some sites, has
+//! typesetting left in it that TTS reads as stutters: soft hyphens, words hyphenated across line
+//! breaks, and lines hard-wrapped mid-sentence. Curly quotes and odd spaces are made plain too, since
+//! some engines pause on them or read them out.
+
+use std::collections::HashSet;
+
+/// Punctuation that a line can end with and still be the end of a paragraph. Lines that end with
+/// anything else, and are followed by a line starting in lowercase, were wrapped mid-sentence.
+const PARAGRAPH_ENDS: &[char] = &['.', '!', '?', '…', ':', ';', '"', '\'', ')', ']'];
+
+/// Returns the plain version of the given character. Quotes become straight quotes, Unicode
+/// hyphens become `-`, and the odd spaces become normal ones. Soft hyphens and zero-width spaces
+/// are dropped, so they're `None`.
+fn plain(c: char) -> Option<char> {
+    match c {
+        '\u{00AD}' | '\u{200B}' | '\u{2060}' | '\u{FEFF}' => None,
+        '‘' | '’' | '‚' | '‛' | '′' => Some('\''),
+        '“' | '”' | '„' | '‟' | '″' => Some('"'),
+        '\u{2010}' | '\u{2011}' => Some('-'),
+        '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\t' => Some(' '),
+        c => Some(c),
+    }
+}
+
+/// Makes the typographic characters in the given line plain, and collapses its runs of spaces
+fn clean_line(line: &str) -> String {
+    let plain_line: String = line.chars().filter_map(plain).collect();
+    plain_line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the hyphenated words in the given text, e.g., "well-known", in lowercase. A word that's
+/// broken across lines at one of these hyphens keeps it when it's put back together.
+fn hyphenated_words(text: &str) -> HashSet<String> {
+    text.split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|w| w.contains('-'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Returns whether a line ending with `line` was wrapped before `next`, i.e., the sentence carries
+/// on from one to the other
+fn is_wrapped(line: &str, next: &str) -> bool {
+    let continues_lowercase = next.chars().next().is_some_and(char::is_lowercase);
+    continues_lowercase && !line.ends_with(PARAGRAPH_ENDS)
+}
+
+/// Joins the given line onto the end of the given paragraph. If the paragraph ends with a word
+/// broken by a hyphen, the word is put back together, with the hyphen if it's a hyphenated word
+/// elsewhere in the text.
+fn join_line(para: &mut String, line: &str, hyphenated: &HashSet<String>) {
+    let broken = para
+        .strip_suffix('-')
+        .filter(|rest| rest.ends_with(char::is_alphabetic))
+        .filter(|_| line.starts_with(char::is_lowercase));
+    let Some(rest) = broken else {
+        para.push(' ');
+        para.push_str(line);
+        return;
+    };
+
+    let head = rest.rsplit(char::is_whitespace).next().unwrap_or_default();
+    let tail = line.split(char::is_whitespace).next().unwrap_or_default();
+    let compound = format!("{head}-{tail}")
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    let rest_len = rest.len();
+    para.truncate(rest_len);
+    if hyphenated.contains(&compound) {
+        para.push('-');
+    }
+    para.push_str(line);
+}
+
+/// Normalizes the given article text, whose paragraphs are separated by line breaks. Lines that
+/// were wrapped mid-sentence are joined, words hyphenated across lines are put back together, and
+/// typographic characters are made plain. Blank lines are dropped.
+pub(crate) fn normalize(text: &str) -> String {
+    let hyphenated = hyphenated_words(text);
+    let mut paragraphs: Vec<String> = Vec::new();
+    // Whether the last line can be carried on by the next. A blank line ends the paragraph.
+    let mut open = false;
+
+    for line in text.lines() {
+        let line = clean_line(line);
+        if line.is_empty() {
+            open = false;
+            continue;
+        }
+
+        match paragraphs.last_mut() {
+            Some(para) if open && is_wrapped(para, &line) => join_line(para, &line, &hyphenated),
+            _ => paragraphs.push(line),
+        }
+        open = true;
+    }
+
+    paragraphs.join("\n")
+}
+
+#[test]
+fn normalizing() {
+    // Hard-wrapped lines are joined, and broken words are put back together
+    let text = "The committee reviewed the pro-\nposal in detail and found\nit sound.\nIt passed.";
+    assert_eq!(
+        normalize(text),
+        "The committee reviewed the proposal in detail and found it sound.\nIt passed."
+    );
+
+    // Hyphenated words keep their hyphen, if they're hyphenated elsewhere in the text
+    let text = "A well-known fact.\nThe fact is well-\nknown to all.";
+    assert_eq!(
+        normalize(text),
+        "A well-known fact.\nThe fact is well-known to all."
+    );
+
+    // Soft hyphens and odd spaces go, and curly quotes are made straight
+    let text = "“It’s a hy\u{00AD}phen\u{00AD}ated word,”\u{00A0}she  said.";
+    assert_eq!(normalize(text), "\"It's a hyphenated word,\" she said.");
+
+    // Headings, paragraphs, and sentences that start with a capital aren't joined
+    let text = "Introduction\nThe study began in 2019\nResults were mixed.";
+    assert_eq!(normalize(text), text);
+
+    // Neither are lines on either side of a blank line, and blank lines are dropped
+    let text = "The list goes on and\n\n  \nand on.\n\n\nThe end.";
+    assert_eq!(normalize(text), "The list goes on and\nand on.\nThe end.");
+
+    // Dashes between words aren't taken for hyphenation
+    let text = "It was a long year -\nthe longest yet.";
+    assert_eq!(normalize(text), "It was a long year - the longest yet.");
+
+    // Text without Latin letters is left as it is
+    let text = "这是第一段。\n这是第二段。";
+    assert_eq!(normalize(text), text);
+}
@@ -0,0 +1,244 @@
+//! A disk cache of fetched pages, so that previewing an article, previewing it again with another
+//! strategy, and then adding it only fetch the page from its site once. Pages are keyed by the URL
+//! they were fetched from, and are fetched again once they're older than the cache's TTL. Expired
+//! pages are deleted by the disk sweeper in `admin.rs`, and admins can clear the cache by hand.
+//!
+//! Every page is saved as `HASH.html`, where `HASH` is the SHA-256 of its URL, next to a
+//! `HASH.json` that says which URL it is and where that redirected to. When the page was fetched is
+//! the modification time of the HTML.
+
+use crate::fetcher::Fetcher;
+use common::{CachedPage, PageCacheUsage};
+use tts_pipeline::{Fetch, Page};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What's saved about a cached page besides its HTML
+#[derive(Debug, Serialize, Deserialize)]
+struct PageInfo {
+    /// The URL the page was fetched from
+    url: String,
+    /// The URL the page ended up being at after redirects
+    final_url: String,
+}
+
+/// The on-disk cache of fetched pages
+#[derive(Clone)]
+pub(crate) struct PageCache {
+    dir: PathBuf,
+    /// How long a page is used for before it's fetched again. If this is zero, nothing is cached.
+    ttl: Duration,
+    fetcher: Fetcher,
+}
+
+impl PageCache {
+    pub(crate) fn new(dir: &str, ttl: Duration, fetcher: Fetcher) -> PageCache {
+        PageCache {
+            dir: PathBuf::from(dir),
+            ttl,
+            fetcher,
+        }
+    }
+
+    /// Returns what fetches pages that aren't cached. This is also for fetching things that aren't
+    /// cached at all, e.g., images.
+    pub(crate) fn fetcher(&self) -> &Fetcher {
+        &self.fetcher
+    }
+
+    /// Returns the paths of the HTML and the info of the page at the given URL
+    fn paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let hash: String = Sha256::digest(url.as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let base = self.dir.join(hash);
+        (base.with_extension("html"), base.with_extension("json"))
+    }
+
+    /// Returns how long ago the page with the given HTML file was fetched
+    fn age(html_path: &Path) -> Option<Duration> {
+        let modified = fs::metadata(html_path).and_then(|m| m.modified()).ok()?;
+        Some(
+            SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Returns the cached HTML of the page at the given URL, and the URL it redirected to, if it's
+    /// cached and hasn't expired
+    fn get(&self, url: &str) -> Option<(Vec<u8>, String)> {
+        let (html_path, info_path) = self.paths(url);
+        if Self::age(&html_path)? >= self.ttl {
+            return None;
+        }
+        let info: PageInfo = serde_json::from_slice(&fs::read(info_path).ok()?).ok()?;
+        // A hash collision is as good as impossible, but a wrong page would be very confusing
+        if info.url != url {
+            return None;
+        }
+        Some((fs::read(html_path).ok()?, info.final_url))
+    }
+
+    /// Caches the given HTML of the page at the given URL, which redirected to the given URL
+    fn put(&self, url: &str, html: &[u8], final_url: &str) -> Result<(), AnyError> {
+        fs::create_dir_all(&self.dir)?;
+        let (html_path, info_path) = self.paths(url);
+        let info = PageInfo {
+            url: url.to_string(),
+            final_url: final_url.to_string(),
+        };
+        fs::write(info_path, serde_json::to_vec(&info)?)?;
+        fs::write(html_path, html)?;
+        Ok(())
+    }
+
+    /// Lists the cached pages, expired or not
+    pub(crate) fn usage(&self) -> Result<PageCacheUsage, AnyError> {
+        let mut usage = PageCacheUsage {
+            ttl_secs: self.ttl.as_secs(),
+            pages: Vec::new(),
+        };
+        // The cache dir doesn't exist until something's been cached
+        if !self.dir.exists() {
+            return Ok(usage);
+        }
+
+        for entry in fs::read_dir(&self.dir)? {
+            let info_path = entry?.path();
+            if info_path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(info) = fs::read(&info_path)
+                .ok()
+                .and_then(|json| serde_json::from_slice::<PageInfo>(&json).ok())
+            else {
+                continue;
+            };
+            let Ok(html_meta) = fs::metadata(info_path.with_extension("html")) else {
+                continue;
+            };
+            let fetched = html_meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            usage.pages.push(CachedPage {
+                url: info.url,
+                bytes: html_meta.len(),
+                fetched,
+            });
+        }
+        Ok(usage)
+    }
+
+    /// Deletes the cached copy of the page at the given URL, if there is one
+    pub(crate) fn remove(&self, url: &str) -> Result<(), AnyError> {
+        let (html_path, info_path) = self.paths(url);
+        for path in [html_path, info_path] {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .map_err(|e| anyhow!("Could not delete cached page {:?}: {e}", path))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes every cached page
+    pub(crate) fn clear(&self) -> Result<(), AnyError> {
+        for page in self.usage()?.pages {
+            self.remove(&page.url)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the cached pages that have expired
+    pub(crate) fn prune(&self) -> Result<(), AnyError> {
+        for page in self.usage()?.pages {
+            let expired = Self::age(&self.paths(&page.url).0).is_none_or(|age| age >= self.ttl);
+            if expired {
+                self.remove(&page.url)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Fetch for PageCache {
+    /// Returns the raw HTML of the page at the given URL, and the URL the page ended up being at
+    /// after redirects, like `Fetcher::fetch_html`. The page is only fetched if it isn't cached, or
+    /// has expired.
+    async fn fetch(&self, url: &str) -> Result<Page, AnyError> {
+        let (html, url) = if self.ttl.is_zero() {
+            self.fetcher.fetch_html(url).await?
+        } else if let Some(cached) = self.get(url) {
+            tracing::debug!("Using the cached copy of {url}");
+            cached
+        } else {
+            let (html, final_url) = self.fetcher.fetch_html(url).await?;
+            if let Err(e) = self.put(url, &html, &final_url) {
+                tracing::error!("Couldn't cache {url}: {e}");
+            }
+            (html, final_url)
+        };
+        Ok(Page { url, html })
+    }
+}
+
+#[test]
+fn caching_pages() {
+    let dir = std::env::temp_dir().join(format!("rtms-page-cache-test-{}", std::process::id()));
+    let fetcher = Fetcher::new(Default::default()).unwrap();
+    let cache = PageCache::new(
+        dir.to_str().unwrap(),
+        Duration::from_secs(60),
+        fetcher.clone(),
+    );
+    let url = "https://example.com/fish";
+
+    // A cached page comes back with where it redirected to
+    assert!(cache.get(url).is_none());
+    cache
+        .put(url, b"<p>Fish</p>", "https://example.com/fish/")
+        .unwrap();
+    assert_eq!(
+        cache.get(url),
+        Some((
+            b"<p>Fish</p>".to_vec(),
+            "https://example.com/fish/".to_string()
+        ))
+    );
+    let usage = cache.usage().unwrap();
+    assert_eq!(usage.ttl_secs, 60);
+    assert_eq!(usage.pages.len(), 1);
+    assert_eq!(usage.pages[0].url, url);
+    assert_eq!(usage.pages[0].bytes, 11);
+
+    // Fresh pages survive pruning, and expired ones don't
+    cache.prune().unwrap();
+    assert!(cache.get(url).is_some());
+    let expired = PageCache::new(dir.to_str().unwrap(), Duration::ZERO, fetcher);
+    assert!(expired.get(url).is_none());
+    expired.prune().unwrap();
+    assert!(cache.usage().unwrap().pages.is_empty());
+
+    // Clearing deletes everything
+    cache.put(url, b"<p>Fish</p>", url).unwrap();
+    cache.put("https://example.com/chips", b"", url).unwrap();
+    cache.clear().unwrap();
+    assert!(cache.usage().unwrap().pages.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
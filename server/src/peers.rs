@@ -0,0 +1,229 @@
+//! Relays the messages a listener's devices send each other to set up direct WebRTC connections, so
+//! a device can get an article's audio from another one on the same network rather than
+//! downloading it again. The server only passes the messages along. The audio never goes through
+//! it.
+//!
+//! Devices poll for the messages sent to them, and a message for every device of a library goes to
+//! the ones that have polled lately. Messages are kept in memory, and dropped if they aren't picked
+//! up soon, since a connection that takes that long to set up isn't going to work anyway.
+
+use crate::accounts::LibraryDir;
+use common::{PeerSignal, PeerSignalKind};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Error as AnyError};
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+/// How long a message waits to be picked up before it's dropped
+const SIGNAL_TTL: Duration = Duration::from_secs(60);
+
+/// How long since a device last polled before messages for every device stop going to it
+const DEVICE_TTL: Duration = Duration::from_secs(30);
+
+/// The most messages that can wait for one device. The oldest are dropped to make room.
+const MAX_PENDING: usize = 64;
+
+/// The longest a device ID can be
+const MAX_DEVICE_ID_LEN: usize = 64;
+
+/// The longest session description a message can carry, in bytes
+const MAX_SDP_LEN: usize = 16 * 1024;
+
+/// A device of a library: the library's directory, and the device's ID
+type DeviceKey = (String, String);
+
+/// The devices that have polled lately, and the messages waiting for them
+#[derive(Default)]
+struct Mailboxes {
+    last_polled: HashMap<DeviceKey, Instant>,
+    pending: HashMap<DeviceKey, VecDeque<(Instant, PeerSignal)>>,
+}
+
+impl Mailboxes {
+    /// Drops the messages and devices that have been around too long
+    fn prune(&mut self, now: Instant) {
+        self.last_polled
+            .retain(|_, polled| now.duration_since(*polled) < DEVICE_TTL);
+        for signals in self.pending.values_mut() {
+            signals.retain(|(sent, _)| now.duration_since(*sent) < SIGNAL_TTL);
+        }
+        self.pending.retain(|_, signals| !signals.is_empty());
+    }
+
+    /// Leaves the given message for the device it's for, or for every other device of the library
+    /// that's polled lately. Returns how many devices it was left for.
+    fn send(&mut self, library: &str, signal: PeerSignal, now: Instant) -> usize {
+        self.prune(now);
+        let devices: Vec<String> = match &signal.to {
+            Some(device) => vec![device.clone()],
+            None => self
+                .last_polled
+                .keys()
+                .filter(|(lib, device)| lib == library && *device != signal.from)
+                .map(|(_, device)| device.clone())
+                .collect(),
+        };
+
+        for device in &devices {
+            let signals = self
+                .pending
+                .entry((library.to_string(), device.clone()))
+                .or_default();
+            if signals.len() >= MAX_PENDING {
+                signals.pop_front();
+            }
+            signals.push_back((now, signal.clone()));
+        }
+        devices.len()
+    }
+
+    /// Takes the messages waiting for the given device, and notes that it polled
+    fn receive(&mut self, library: &str, device: &str, now: Instant) -> Vec<PeerSignal> {
+        self.prune(now);
+        let key = (library.to_string(), device.to_string());
+        self.last_polled.insert(key.clone(), now);
+        self.pending
+            .remove(&key)
+            .map(|signals| signals.into_iter().map(|(_, signal)| signal).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The messages waiting to be relayed
+#[derive(Clone, Default)]
+pub(crate) struct PeerSignals(Arc<Mutex<Mailboxes>>);
+
+/// Returns whether the given device ID is one a device could have made, rather than something
+/// that could fill the server's memory
+fn is_valid_device_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_DEVICE_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Checks that the given message is one worth relaying
+fn validate(signal: &PeerSignal) -> Result<(), AnyError> {
+    if !is_valid_device_id(&signal.from) || !signal.to.as_deref().is_none_or(is_valid_device_id) {
+        bail!("Invalid device ID");
+    }
+    if signal.article_id.is_empty() || signal.article_id.len() > 1024 {
+        bail!("Invalid article ID");
+    }
+    match &signal.kind {
+        PeerSignalKind::Want => (),
+        PeerSignalKind::Offer(sdp) | PeerSignalKind::Answer(sdp) => {
+            if sdp.len() > MAX_SDP_LEN {
+                bail!("The session description is too long");
+            }
+            if signal.to.is_none() {
+                bail!("Offers and answers are for one device");
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sets the /api/peer-signal and /api/peer-signals routes
+pub(crate) fn setup(router: Router, audio_blob_dir: &str) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/peer-signal", post(send_signal))
+            .route("/peer-signals", get(receive_signals))
+            .layer(Extension(PeerSignals::default()))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Relays the given message to the library's other devices, and returns how many it went to. A
+/// device that asks for an article and finds no others around doesn't have to wait for an offer.
+async fn send_signal(
+    LibraryDir(library): LibraryDir,
+    Extension(signals): Extension<PeerSignals>,
+    Json(signal): Json<PeerSignal>,
+) -> Result<Json<usize>, (StatusCode, String)> {
+    validate(&signal).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let sent_to = signals
+        .0
+        .lock()
+        .unwrap()
+        .send(&library, signal, Instant::now());
+    Ok(Json(sent_to))
+}
+
+#[derive(Deserialize)]
+struct ReceiveParams {
+    /// The ID of the device that's polling
+    device: String,
+}
+
+/// Returns the messages waiting for the given device
+async fn receive_signals(
+    LibraryDir(library): LibraryDir,
+    Extension(signals): Extension<PeerSignals>,
+    Query(params): Query<ReceiveParams>,
+) -> Result<Json<Vec<PeerSignal>>, (StatusCode, String)> {
+    if !is_valid_device_id(&params.device) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid device ID".to_string()));
+    }
+    let received = signals
+        .0
+        .lock()
+        .unwrap()
+        .receive(&library, &params.device, Instant::now());
+    Ok(Json(received))
+}
+
+#[test]
+fn relaying_signals() {
+    let signal = |from: &str, to: Option<&str>, kind| PeerSignal {
+        from: from.to_string(),
+        to: to.map(str::to_string),
+        article_id: "fish-abc".to_string(),
+        kind,
+    };
+    let mut mailboxes = Mailboxes::default();
+    let start = Instant::now();
+
+    // A message for every device goes to the ones of the same library that have polled, but not
+    // back to the sender
+    for (library, device) in [("a", "phone"), ("a", "laptop"), ("b", "tablet")] {
+        assert!(mailboxes.receive(library, device, start).is_empty());
+    }
+    let want = signal("phone", None, PeerSignalKind::Want);
+    assert_eq!(mailboxes.send("a", want.clone(), start), 1);
+    assert_eq!(mailboxes.receive("a", "laptop", start), vec![want]);
+    assert!(mailboxes.receive("a", "laptop", start).is_empty());
+    assert!(mailboxes.receive("a", "phone", start).is_empty());
+    assert!(mailboxes.receive("b", "tablet", start).is_empty());
+
+    // A message for one device waits for it, but not forever
+    let offer = signal("laptop", Some("phone"), PeerSignalKind::Offer("v=0".into()));
+    assert_eq!(mailboxes.send("a", offer.clone(), start), 1);
+    assert_eq!(mailboxes.receive("a", "phone", start), vec![offer.clone()]);
+    mailboxes.send("a", offer, start);
+    let later = start + SIGNAL_TTL;
+    assert!(mailboxes.receive("a", "phone", later).is_empty());
+
+    // Devices that stop polling stop getting messages for every device
+    let want = signal("phone", None, PeerSignalKind::Want);
+    assert_eq!(mailboxes.send("a", want, later + DEVICE_TTL), 0);
+
+    // Only sensible messages are relayed
+    assert!(validate(&signal("phone", None, PeerSignalKind::Want)).is_ok());
+    assert!(validate(&signal("phone", None, PeerSignalKind::Answer("v=0".into()))).is_err());
+    assert!(validate(&signal("../phone", None, PeerSignalKind::Want)).is_err());
+    let huge = PeerSignalKind::Offer("a".repeat(MAX_SDP_LEN + 1));
+    assert!(validate(&signal("laptop", Some("phone"), huge)).is_err());
+}
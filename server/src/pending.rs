@@ -0,0 +1,230 @@
+//! Keeps track of articles whose synthesis failed partway through. The text that's yet to be
+//! synthesized is saved next to the article's audio as `ARTICLEID.pending.json`, so the conversion
+//! can be finished later.
+//!
+//! The audio of every TTS request is saved as it comes in, in `ARTICLEID.chunks/`, until the
+//! synthesis is complete. Requests that finished after the one that failed don't have to be made
+//! again when the conversion is finished.
+
+use common::{SpeakingStyle, SynthesisOptions};
+use tts_pipeline::{ChunkStore, SynthesizedChunk, TtsRequest};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The unfinished part of an article's synthesis
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PendingSynthesis {
+    /// The text that's yet to be synthesized
+    pub(crate) remaining_text: String,
+    /// The style the article is being read in
+    pub(crate) style: SpeakingStyle,
+    /// The options the article is being synthesized with
+    pub(crate) options: SynthesisOptions,
+    /// The voice the article is being read in. This is `None` for articles from before voices were
+    /// recorded.
+    #[serde(default)]
+    pub(crate) voice: Option<String>,
+}
+
+/// Returns the path of the pending synthesis file of the given article
+fn path(audio_blob_dir: &str, id: &str) -> PathBuf {
+    Path::new(audio_blob_dir).join(format!("{id}.pending.json"))
+}
+
+/// Returns whether the given article has a pending synthesis, i.e., whether it's incomplete
+pub(crate) fn exists(audio_blob_dir: &str, id: &str) -> bool {
+    path(audio_blob_dir, id).exists()
+}
+
+/// Saves the pending synthesis of the given article, replacing any existing one
+pub(crate) fn save(
+    audio_blob_dir: &str,
+    id: &str,
+    pending: &PendingSynthesis,
+) -> Result<(), AnyError> {
+    let json = serde_json::to_vec(pending)?;
+    std::fs::write(path(audio_blob_dir, id), json).map_err(Into::into)
+}
+
+/// Loads the pending synthesis of the given article
+pub(crate) fn load(audio_blob_dir: &str, id: &str) -> Result<PendingSynthesis, AnyError> {
+    let json = std::fs::read(path(audio_blob_dir, id))
+        .map_err(|_| anyhow!("Article {id} has no unfinished conversion"))?;
+    serde_json::from_slice(&json).map_err(Into::into)
+}
+
+/// Deletes the pending synthesis of the given article, and its saved chunks, if there are any
+pub(crate) fn remove(audio_blob_dir: &str, id: &str) -> Result<(), AnyError> {
+    let p = path(audio_blob_dir, id);
+    if p.exists() {
+        std::fs::remove_file(p)?;
+    }
+    ChunkCache::new(audio_blob_dir, id).clear()
+}
+
+/// The audio of the TTS requests made for an article, saved as they finish. A chunk is found by its
+/// request, so it's found again when the text after a failure is broken into the same requests.
+pub(crate) struct ChunkCache {
+    dir: PathBuf,
+}
+
+impl ChunkCache {
+    /// Returns the chunk cache of the given article
+    pub(crate) fn new(audio_blob_dir: &str, id: &str) -> ChunkCache {
+        ChunkCache {
+            dir: Path::new(audio_blob_dir).join(format!("{id}.chunks")),
+        }
+    }
+
+    /// Returns the path the audio of the given request is saved at
+    fn chunk_path(&self, req: &TtsRequest) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(req.voice.name.as_bytes());
+        hasher.update([0]);
+        hasher.update(req.voice.custom_model.as_deref().unwrap_or_default());
+        hasher.update([0]);
+        hasher.update(req.ssml().as_bytes());
+        let hash: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        self.dir.join(hash)
+    }
+
+    /// Returns the saved audio of the given request, if there is any
+    pub(crate) fn get(&self, req: &TtsRequest) -> Option<SynthesizedChunk> {
+        let bytes = fs::read(self.chunk_path(req)).ok()?;
+        decode_chunk(&bytes)
+            .map_err(|e| tracing::error!("Ignoring unreadable saved chunk: {e}"))
+            .ok()
+    }
+
+    /// Saves the audio of the given request
+    pub(crate) fn put(&self, req: &TtsRequest, chunk: &SynthesizedChunk) -> Result<(), AnyError> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow!("Could not create {:?}: {e}", self.dir))?;
+
+        // Write to a temp file first, so a chunk that was cut off is never read
+        let path = self.chunk_path(req);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, encode_chunk(chunk))
+            .map_err(|e| anyhow!("Could not save {:?}: {e}", tmp_path))?;
+        fs::rename(&tmp_path, &path)
+            .map_err(|e| anyhow!("Could not rename {:?} to {:?}: {e}", tmp_path, path))
+    }
+
+    /// Deletes all the saved chunks
+    pub(crate) fn clear(&self) -> Result<(), AnyError> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)
+                .map_err(|e| anyhow!("Could not delete {:?}: {e}", self.dir))?;
+        }
+        Ok(())
+    }
+}
+
+impl ChunkStore for ChunkCache {
+    fn get(&self, req: &TtsRequest) -> Option<SynthesizedChunk> {
+        ChunkCache::get(self, req)
+    }
+
+    fn put(&self, req: &TtsRequest, chunk: &SynthesizedChunk) -> Result<(), AnyError> {
+        ChunkCache::put(self, req, chunk)
+    }
+}
+
+/// Serializes a chunk as the number of marks, the marks, and then the samples, all little-endian
+fn encode_chunk(chunk: &SynthesizedChunk) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(4 + chunk.marks.len() * 16 + chunk.samples.len() * 2);
+    bytes.extend((chunk.marks.len() as u32).to_le_bytes());
+    for &(offset, time) in &chunk.marks {
+        bytes.extend((offset as u64).to_le_bytes());
+        bytes.extend(time.to_le_bytes());
+    }
+    for sample in &chunk.samples {
+        bytes.extend(sample.to_le_bytes());
+    }
+    bytes
+}
+
+/// Deserializes a chunk serialized by `encode_chunk`
+fn decode_chunk(bytes: &[u8]) -> Result<SynthesizedChunk, AnyError> {
+    let num_marks = match bytes.get(..4) {
+        Some(b) => u32::from_le_bytes(b.try_into()?) as usize,
+        None => bail!("chunk is truncated"),
+    };
+    let samples_start = 4 + num_marks * 16;
+    if bytes.len() < samples_start || !(bytes.len() - samples_start).is_multiple_of(2) {
+        bail!("chunk is truncated");
+    }
+
+    let marks = bytes[4..samples_start]
+        .chunks_exact(16)
+        .map(|m| {
+            let offset = u64::from_le_bytes(m[..8].try_into().unwrap()) as usize;
+            let time = f64::from_le_bytes(m[8..].try_into().unwrap());
+            (offset, time)
+        })
+        .collect();
+    let samples = bytes[samples_start..]
+        .chunks_exact(2)
+        .map(|s| i16::from_le_bytes([s[0], s[1]]))
+        .collect();
+    Ok(SynthesizedChunk { samples, marks })
+}
+
+#[test]
+fn saving_chunks() {
+    use tts_pipeline::Voice;
+
+    let dir = std::env::temp_dir().join(format!("rtms-chunks-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let dir = dir.to_str().unwrap();
+
+    let req = TtsRequest {
+        text: "Hello there.\nGeneral Kenobi.".to_string(),
+        voice: Voice::default(),
+        style: SpeakingStyle::default(),
+        options: SynthesisOptions::default(),
+    };
+    let chunk = SynthesizedChunk {
+        samples: vec![0, 1, -1, i16::MAX, i16::MIN],
+        marks: vec![(0, 0.0), (13, 1.25)],
+    };
+
+    // A saved chunk comes back as it was
+    let cache = ChunkCache::new(dir, "abc");
+    assert!(cache.get(&req).is_none());
+    cache.put(&req, &chunk).unwrap();
+    let saved = cache.get(&req).unwrap();
+    assert_eq!(saved.samples, chunk.samples);
+    assert_eq!(saved.marks, chunk.marks);
+
+    // The same text in another voice is a different request
+    let other_voice = TtsRequest {
+        voice: Voice {
+            name: "other".to_string(),
+            ..Voice::default()
+        },
+        ..req.clone()
+    };
+    assert!(cache.get(&other_voice).is_none());
+
+    // A cut-off chunk is never read
+    assert!(decode_chunk(&encode_chunk(&chunk)[..10]).is_err());
+
+    // Once the synthesis is done, the chunks go
+    remove(dir, "abc").unwrap();
+    assert!(cache.get(&req).is_none());
+    assert!(!Path::new(dir).join("abc.chunks").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
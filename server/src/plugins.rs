@@ -0,0 +1,284 @@
+//! Site-specific extractors shipped as WASM plugins, so extraction rules for tricky sites can be
+//! contributed without recompiling the server. Each plugin is a WASI module, `<name>.wasm`, next to
+//! a manifest, `<name>.json`, that lists the sites it handles:
+//!
+//! ```json
+//! { "sites": ["example.com", "news.example.org"], "description": "Example's paywall layout" }
+//! ```
+//!
+//! The module is run with the article's URL as its only argument and the page's HTML on stdin. It
+//! prints `{"text": "..."}` to stdout, with one paragraph per line, and exits successfully. It runs
+//! in wasmtime's sandbox, with no access to the filesystem or the network, and with caps on its
+//! memory, the instructions it runs, and how much it prints. It's killed if it takes too long.
+//! Plugins are loaded when the server starts.
+
+use crate::extract::is_on_site;
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use async_process::Command;
+use futures::{
+    future::join,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use serde::Deserialize;
+
+/// The wasmtime CLI, which runs the plugins
+const WASMTIME: &str = "wasmtime";
+
+/// How long a plugin gets to extract an article before it's killed
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most memory a plugin can grow to, in bytes
+const PLUGIN_MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Roughly the most WASM instructions a plugin can run before it's stopped
+const PLUGIN_FUEL: u64 = 10_000_000_000;
+
+/// The most a plugin can print, in bytes. Anything longer isn't read, and the plugin is killed.
+const PLUGIN_MAX_OUTPUT: usize = 10 * 1024 * 1024;
+
+/// A plugin's manifest
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    /// The sites the plugin extracts articles from, e.g., `example.com`. Subdomains count too.
+    sites: Vec<String>,
+    /// What the plugin is for
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// What a plugin prints once it's done
+#[derive(Deserialize)]
+struct PluginOutput {
+    /// The article's text, one paragraph per line
+    text: String,
+}
+
+/// A site-specific extractor
+#[derive(Debug)]
+struct Plugin {
+    /// The file name of the module, without the extension
+    name: String,
+    /// The path of the module
+    module: PathBuf,
+    manifest: Manifest,
+}
+
+/// The extractor plugins found in the plugin directory
+#[derive(Clone, Default)]
+pub(crate) struct ExtractorPlugins {
+    plugins: Arc<Vec<Plugin>>,
+}
+
+impl ExtractorPlugins {
+    /// Loads the plugins in the given directory. If the directory doesn't exist, there are no
+    /// plugins. Modules without a manifest, or with one that can't be read, are skipped.
+    pub(crate) fn load(dir: &str) -> Result<ExtractorPlugins, AnyError> {
+        let dir = Path::new(dir);
+        if !dir.exists() {
+            return Ok(ExtractorPlugins::default());
+        }
+
+        let mut plugins = Vec::new();
+        let entries =
+            std::fs::read_dir(dir).map_err(|e| anyhow!("Could not read plugins {:?}: {e}", dir))?;
+        for entry in entries {
+            let module = entry?.path();
+            if module.extension().is_none_or(|ext| ext != "wasm") {
+                continue;
+            }
+            match load_plugin(&module) {
+                Ok(plugin) => {
+                    tracing::info!(
+                        "Loaded extractor plugin {} for {}",
+                        plugin.name,
+                        plugin.manifest.sites.join(", ")
+                    );
+                    plugins.push(plugin);
+                }
+                Err(e) => tracing::error!("Skipping extractor plugin {:?}: {e}", module),
+            }
+        }
+        // Go by name, so which plugin handles a site doesn't depend on the order of the directory
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(ExtractorPlugins {
+            plugins: Arc::new(plugins),
+        })
+    }
+
+    /// Returns the plugin for the site of the given URL, if any. If several handle the site, the
+    /// first by name wins.
+    fn for_url(&self, url: &str) -> Option<&Plugin> {
+        self.plugins.iter().find(|plugin| {
+            plugin
+                .manifest
+                .sites
+                .iter()
+                .any(|site| is_on_site(url, site))
+        })
+    }
+
+    /// Runs the plugin for the site of the given URL on the given HTML, and returns the text it
+    /// found. Returns `None` if no plugin handles the site, or if the plugin fails or finds nothing.
+    pub(crate) async fn extract(&self, url: &str, html: &[u8]) -> Option<String> {
+        let plugin = self.for_url(url)?;
+        match run_plugin(plugin, url, html).await {
+            Ok(text) if !text.trim().is_empty() => Some(text),
+            Ok(_) => {
+                tracing::warn!("Extractor plugin {} found no text on {url}", plugin.name);
+                None
+            }
+            Err(e) => {
+                tracing::warn!("Extractor plugin {} failed on {url}: {e}", plugin.name);
+                None
+            }
+        }
+    }
+}
+
+/// Loads the plugin with the given module, from the manifest next to it
+fn load_plugin(module: &Path) -> Result<Plugin, AnyError> {
+    let name = module
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("The module's name isn't valid UTF-8"))?
+        .to_string();
+    let manifest_path = module.with_extension("json");
+    let json = std::fs::read(&manifest_path)
+        .map_err(|e| anyhow!("Could not read manifest {:?}: {e}", manifest_path))?;
+    let manifest: Manifest = serde_json::from_slice(&json)
+        .map_err(|e| anyhow!("Could not parse manifest {:?}: {e}", manifest_path))?;
+    if manifest.sites.is_empty() {
+        bail!("The manifest lists no sites");
+    }
+    if let Some(description) = &manifest.description {
+        tracing::debug!("Extractor plugin {name}: {description}");
+    }
+
+    Ok(Plugin {
+        name,
+        module: module.to_path_buf(),
+        manifest,
+    })
+}
+
+/// Runs the given plugin on the given page and returns the text it prints
+async fn run_plugin(plugin: &Plugin, url: &str, html: &[u8]) -> Result<String, AnyError> {
+    // Run the module, piping the HTML in through stdin. No directories are preopened, so it can't
+    // touch the filesystem. Its stderr is dropped rather than mixed into the server's.
+    let limits = format!(
+        "max-memory-size={PLUGIN_MAX_MEMORY},fuel={PLUGIN_FUEL},timeout={}s",
+        PLUGIN_TIMEOUT.as_secs()
+    );
+    let mut child = Command::new(WASMTIME)
+        .arg("run")
+        .arg("-W")
+        .arg(limits)
+        .arg(&plugin.module)
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("IO error running {WASMTIME}: {:?}", e))?;
+
+    // Write the HTML while the output's read, both within the time limit, so a plugin that doesn't
+    // read all its input can't hold the request up. Closing stdin tells the plugin the input is
+    // done.
+    let mut stdin = child.stdin.take().unwrap();
+    let write = async move {
+        stdin.write_all(html).await?;
+        stdin.close().await
+    };
+
+    // Read one byte past the cap, to tell output that's too long from output that's just long
+    // enough
+    let stdout = child.stdout.take().unwrap();
+    let read = async move {
+        let mut stdout_buf = Vec::new();
+        let limit = PLUGIN_MAX_OUTPUT as u64 + 1;
+        stdout.take(limit).read_to_end(&mut stdout_buf).await?;
+        Ok::<_, std::io::Error>(stdout_buf)
+    };
+
+    // The child is dropped, and so killed, if it runs out of time or prints too much
+    let run = async {
+        let (written, stdout) = join(write, read).await;
+        let stdout = stdout.map_err(|e| anyhow!("IO error running {WASMTIME}: {:?}", e))?;
+        if stdout.len() > PLUGIN_MAX_OUTPUT {
+            bail!("Printed over {PLUGIN_MAX_OUTPUT} bytes");
+        }
+        let status = child
+            .status()
+            .await
+            .map_err(|e| anyhow!("IO error running {WASMTIME}: {:?}", e))?;
+        Ok((written, stdout, status))
+    };
+    let (written, stdout, status) = tokio::time::timeout(PLUGIN_TIMEOUT, run)
+        .await
+        .map_err(|_| anyhow!("Timed out after {PLUGIN_TIMEOUT:?}"))??;
+    if let Err(e) = written {
+        tracing::debug!("Plugin didn't read all of the HTML: {e}");
+    }
+    if !status.success() {
+        bail!("Exited with {status}");
+    }
+
+    let output: PluginOutput =
+        serde_json::from_slice(&stdout).map_err(|e| anyhow!("Couldn't parse the output: {e}"))?;
+    Ok(output.text)
+}
+
+#[test]
+fn loading_plugins() {
+    let dir = std::env::temp_dir().join(format!("rtms-plugins-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, contents: &str| std::fs::write(dir.join(name), contents).unwrap();
+
+    // Plugins are matched by site, subdomains included. Those without a usable manifest are skipped.
+    write("news.wasm", "");
+    write(
+        "news.json",
+        r#"{"sites": ["example.com"], "description": "Example News"}"#,
+    );
+    write("blog.wasm", "");
+    write(
+        "blog.json",
+        r#"{"sites": ["blog.example.org", "example.net"]}"#,
+    );
+    write("broken.wasm", "");
+    write("broken.json", "{");
+    write("nosites.wasm", "");
+    write("nosites.json", r#"{"sites": []}"#);
+    write("orphan.wasm", "");
+    write("notes.json", r#"{"sites": ["example.com"]}"#);
+
+    let plugins = ExtractorPlugins::load(dir.to_str().unwrap()).unwrap();
+    let names = plugins
+        .plugins
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["blog", "news"]);
+
+    let name_for = |url| plugins.for_url(url).map(|p| p.name.as_str());
+    assert_eq!(name_for("https://www.example.com/2024/story"), Some("news"));
+    assert_eq!(name_for("https://live.example.com/story"), Some("news"));
+    assert_eq!(name_for("https://blog.example.org/post"), Some("blog"));
+    assert_eq!(name_for("https://example.org/post"), None);
+    assert_eq!(name_for("https://notexample.com/story"), None);
+
+    // A missing directory just means there are no plugins
+    std::fs::remove_dir_all(&dir).unwrap();
+    let plugins = ExtractorPlugins::load(dir.to_str().unwrap()).unwrap();
+    assert!(plugins.for_url("https://example.com/story").is_none());
+}
@@ -0,0 +1,512 @@
+//! Serves the library as a podcast feed, so it can be subscribed to in a podcast app. Every article
+//! is an episode whose enclosure is its MP3. Since a podcast app can't log in, the feed is only
+//! served to requests that have the token from the config file in their query string.
+//!
+//! With accounts, each account's library has a feed of its own, at `?account=NAME` with a token
+//! derived from the one in the config file. A signed-in account gets its feed's URL from
+//! `/api/podcast-link`. Podcast apps can't get at audio that needs a session, so its episodes are
+//! linked like shared articles, with an expiry and a signature that only open that one episode.
+//! The feed's token isn't in them, since enclosure URLs tend to end up in more places than the
+//! feed's URL.
+//!
+//! The feed links to the server at the `base_url` in the config file, never at the host a request
+//! says it's for, so there's no feed unless that's set too.
+
+use crate::{
+    accounts::{self, CurrentAccount},
+    audio_blobs::{self, AudioHashes},
+    list_articles::{self, LibraryCache},
+    subpath::BaseUrl,
+    util::{escape_xml, now_secs},
+};
+use common::ArticleMetadata;
+use tts_pipeline::audio::AudioFormat;
+
+use std::{fs, path::Path};
+
+use axum::{
+    body::Body,
+    extract::{Extension, Query},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+/// The MIME type of the feed
+const RSS_MIME_TYPE: &str = "application/rss+xml; charset=utf-8";
+
+/// The secret that has to be given to get the feed
+#[derive(Clone)]
+struct PodcastToken(String);
+
+/// The query string of a feed request
+#[derive(Deserialize)]
+pub(crate) struct FeedParams {
+    token: Option<String>,
+    /// The account whose library the feed is of. Without one, it's of the shared library.
+    account: Option<String>,
+}
+
+/// How long the link to an episode in an account's feed is good for. Podcast apps fetch the feed
+/// far more often than this, and get fresh links when they do.
+const EPISODE_LINK_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// The query string of a request for an episode's audio
+#[derive(Deserialize)]
+struct EpisodeParams {
+    /// The account whose library the episode is in
+    account: String,
+    /// The hash of the episode's MP3
+    hash: String,
+    /// When the link stops working, in seconds since the epoch
+    expires: u64,
+    /// The signature from `episode_sig`
+    sig: String,
+}
+
+/// An article, as an episode of the podcast
+pub(crate) struct Episode {
+    pub(crate) meta: ArticleMetadata,
+    /// The size of the article's MP3, in bytes
+    pub(crate) size: u64,
+    /// Where the article's MP3 can be downloaded from
+    pub(crate) audio_url: String,
+}
+
+// Sets the /api/podcast.xml, /api/podcast-audio.mp3, and /api/podcast-link routes. If no token or
+// no base URL is given, the feed isn't served at all.
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    token: Option<String>,
+    base_url: Option<BaseUrl>,
+    audio_hashes: AudioHashes,
+) -> Router {
+    let token = match token.filter(|t| !t.is_empty()) {
+        Some(token) => token,
+        None => return router,
+    };
+    let Some(base_url) = base_url else {
+        tracing::warn!("There's a podcast_token but no base_url to link to, so there's no feed");
+        return router;
+    };
+
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/podcast.xml", get(podcast_feed))
+            .route("/podcast-audio.mp3", get(episode_audio))
+            .route("/podcast-link", get(podcast_link))
+            .layer(Extension(audio_blob_dir.to_string()))
+            .layer(Extension(LibraryCache::default()))
+            .layer(Extension(audio_hashes))
+            .layer(Extension(base_url))
+            .layer(Extension(PodcastToken(token))),
+    )
+}
+
+/// Returns whether the given token is the right one. Every byte is compared, so the time this
+/// takes doesn't say how much of the token was right.
+pub(crate) fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Returns the HMAC of the given fields by the given secret, in hex. The fields are separated by
+/// newlines, which none of them can contain.
+fn hmac_hex(secret: &str, fields: &[&str]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(fields.join("\n").as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Returns the token that gets the given kind of feed of the given account's library, or of the
+/// shared library if there's no account. An account's token is an HMAC of its name by the token in
+/// the config file, so it only gets that account's feed.
+pub(crate) fn feed_token(secret: &str, kind: &str, account: Option<&str>) -> String {
+    match account {
+        Some(name) => hmac_hex(secret, &[kind, name]),
+        None => secret.to_string(),
+    }
+}
+
+/// Returns the signature of the link to the episode with the given MP3 hash in the given account's
+/// feed, which expires at the given time
+fn episode_sig(secret: &str, account: &str, hash: &str, expires: u64) -> String {
+    hmac_hex(
+        secret,
+        &["podcast-audio", account, hash, &expires.to_string()],
+    )
+}
+
+/// Checks the token of a request for the given kind of feed, and returns the directory of the
+/// library the feed is of
+pub(crate) fn feed_library(
+    audio_blob_dir: &str,
+    secret: &str,
+    kind: &str,
+    FeedParams { token, account }: &FeedParams,
+) -> Result<String, StatusCode> {
+    let expected = feed_token(secret, kind, account.as_deref());
+    if !token.as_ref().is_some_and(|t| token_matches(t, &expected)) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    existing_library(audio_blob_dir, account.as_deref())
+}
+
+/// Returns the directory of the given account's library, or of the shared library if there's no
+/// account, if it exists
+fn existing_library(audio_blob_dir: &str, account: Option<&str>) -> Result<String, StatusCode> {
+    let dir =
+        accounts::library_dir(audio_blob_dir, account).map_err(|_| StatusCode::BAD_REQUEST)?;
+    // An account that's never signed in has no library yet
+    if !dir.exists() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    dir.into_os_string()
+        .into_string()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Returns the URL of the given kind of feed of the signed-in account's library, or of the shared
+/// library without accounts. `path` is where the feed is served, e.g., `/api/podcast.xml`.
+pub(crate) fn feed_link(
+    base_url: &str,
+    path: &str,
+    secret: &str,
+    kind: &str,
+    account: Option<&str>,
+) -> String {
+    let token = feed_token(secret, kind, account);
+    match account {
+        // Account names are letters, digits, dashes, and underscores, so they needn't be escaped
+        Some(name) => format!("{base_url}{path}?account={name}&token={token}"),
+        None => format!("{base_url}{path}?token={token}"),
+    }
+}
+
+/// Formats the given duration the way podcast apps expect, e.g., `1:02:03`
+fn format_duration(secs: u64) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+/// Renders the given episode as an RSS item
+fn render_item(episode: &Episode) -> String {
+    let meta = &episode.meta;
+
+    let mut item = String::from("<item>");
+    item.push_str(&format!("<title>{}</title>", escape_xml(&meta.title)));
+    item.push_str(&format!(
+        "<guid isPermaLink=\"false\">{}</guid>",
+        escape_xml(&meta.id)
+    ));
+    item.push_str(&format!(
+        "<enclosure url=\"{}\" length=\"{}\" type=\"audio/mpeg\"/>",
+        escape_xml(&episode.audio_url),
+        episode.size,
+    ));
+    let pub_date = meta
+        .datetime_added
+        .and_then(|t| Utc.timestamp_opt(t as i64, 0).single());
+    if let Some(pub_date) = pub_date {
+        item.push_str(&format!("<pubDate>{}</pubDate>", pub_date.to_rfc2822()));
+    }
+    if let Some(secs) = meta.duration_secs {
+        item.push_str(&format!(
+            "<itunes:duration>{}</itunes:duration>",
+            format_duration(secs)
+        ));
+    }
+    if let Some(author) = &meta.author {
+        item.push_str(&format!(
+            "<itunes:author>{}</itunes:author>",
+            escape_xml(author)
+        ));
+    }
+    if let Some(url) = &meta.source_url {
+        item.push_str(&format!("<link>{}</link>", escape_xml(url)));
+    }
+
+    // Say where the article's from, since that's all there is to say about it
+    let byline = [meta.author.as_deref(), meta.publication.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut description = match meta.source_url.as_deref() {
+        Some(url) if byline.is_empty() => url.to_string(),
+        Some(url) => format!("{byline}. {url}"),
+        None => byline,
+    };
+    if meta.incomplete {
+        description.push_str(" (The audio only covers the beginning of the article.)");
+    }
+    item.push_str(&format!(
+        "<description>{}</description>",
+        escape_xml(description.trim())
+    ));
+
+    item.push_str("</item>");
+    item
+}
+
+/// Renders the given episodes as a podcast feed, in the given order. `link` is where the feed says
+/// the podcast lives.
+pub(crate) fn render_feed(link: &str, episodes: &[Episode]) -> String {
+    let items: String = episodes.iter().map(render_item).collect();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">\
+        <channel>\
+        <title>ReadToMyShoe</title>\
+        <link>{}</link>\
+        <description>The articles in your ReadToMyShoe library, read aloud</description>\
+        <language>en</language>\
+        <itunes:author>ReadToMyShoe</itunes:author>\
+        <itunes:explicit>false</itunes:explicit>\
+        {items}\
+        </channel>\
+        </rss>",
+        escape_xml(link),
+    )
+}
+
+/// Returns a library as a podcast feed, most recently added first
+async fn podcast_feed(
+    Query(params): Query<FeedParams>,
+    Extension(PodcastToken(secret)): Extension<PodcastToken>,
+    Extension(audio_blob_dir): Extension<String>,
+    Extension(metadata_cache): Extension<LibraryCache>,
+    Extension(audio_hashes): Extension<AudioHashes>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+) -> Result<(HeaderMap, String), StatusCode> {
+    let library_dir = feed_library(&audio_blob_dir, &secret, "podcast", &params)?;
+
+    let mut catalog = list_articles::load_catalog(&library_dir, &metadata_cache).map_err(|e| {
+        tracing::error!("error reading dir {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    catalog.sort_by_key(|meta| std::cmp::Reverse(meta.datetime_added));
+
+    // Podcast apps want to know how big each episode is before they download it. The shared
+    // library's audio is linked at its content-addressed URL, so it can be cached. An account's
+    // audio needs a session there, so it's linked with a signature instead. The expiry is rounded
+    // to the day, so the links don't change every time the feed is fetched.
+    let expires = (now_secs() + EPISODE_LINK_SECS) / (24 * 60 * 60) * (24 * 60 * 60);
+    let episodes: Vec<Episode> = catalog
+        .into_iter()
+        .filter_map(|meta| {
+            let path = Path::new(&library_dir).join(&meta.id).with_extension("mp3");
+            let size = fs::metadata(&path).ok()?.len();
+            // Podcast apps all play MP3, but not necessarily anything else
+            let audio_url = match &params.account {
+                Some(name) => {
+                    let hash = audio_hashes.hash_of(&path).ok()?;
+                    let sig = episode_sig(&secret, name, &hash, expires);
+                    format!(
+                        "{base_url}/api/podcast-audio.mp3\
+                         ?account={name}&hash={hash}&expires={expires}&sig={sig}"
+                    )
+                }
+                None => {
+                    audio_blobs::audio_url(&audio_hashes, &library_dir, &meta.id, AudioFormat::Mp3)
+                        .map(|url| format!("{base_url}{url}"))
+                        .ok()?
+                }
+            };
+            Some(Episode {
+                meta,
+                size,
+                audio_url,
+            })
+        })
+        .collect();
+
+    let mut resp_headers = HeaderMap::new();
+    resp_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(RSS_MIME_TYPE),
+    );
+    Ok((resp_headers, render_feed(&base_url, &episodes)))
+}
+
+/// Serves the MP3 of an episode of an account's feed, if the link's signature is good and it
+/// hasn't expired. Ranges are supported, so podcast apps can resume downloads.
+async fn episode_audio(
+    Query(params): Query<EpisodeParams>,
+    Extension(PodcastToken(secret)): Extension<PodcastToken>,
+    Extension(audio_blob_dir): Extension<String>,
+    Extension(audio_hashes): Extension<AudioHashes>,
+    req: Request<Body>,
+) -> Result<Response, StatusCode> {
+    let EpisodeParams {
+        account,
+        hash,
+        expires,
+        sig,
+    } = params;
+    let expected = episode_sig(&secret, &account, &hash, expires);
+    if !token_matches(&sig, &expected) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if expires <= now_secs() {
+        return Err(StatusCode::GONE);
+    }
+    let library_dir = existing_library(&audio_blob_dir, Some(&account))?;
+    let path = audio_hashes
+        .find(&library_dir, &hash)
+        .map_err(|e| {
+            tracing::error!("Error looking for audio {hash}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut resp = ServeFile::new(path)
+        .oneshot(req)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_response();
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(AudioFormat::Mp3.mime_type()),
+    );
+    Ok(resp)
+}
+
+/// Returns the URL of the podcast feed of the signed-in account's library
+async fn podcast_link(
+    CurrentAccount(account): CurrentAccount,
+    Extension(PodcastToken(secret)): Extension<PodcastToken>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+) -> String {
+    feed_link(
+        &base_url,
+        "/api/podcast.xml",
+        &secret,
+        "podcast",
+        account.name.as_deref(),
+    )
+}
+
+#[test]
+fn rendering_feed() {
+    let episode = Episode {
+        meta: ArticleMetadata {
+            id: "Fish & Chips-abc".to_string(),
+            title: "Fish & <Chips>".to_string(),
+            datetime_added: Some(1_663_000_000),
+            source_url: Some("https://example.com/fish?a=1&b=2".to_string()),
+            author: Some("A. Writer".to_string()),
+            duration_secs: Some(3723),
+            ..Default::default()
+        },
+        size: 1234,
+        audio_url: "https://shoe.example/audio/f00d.mp3".to_string(),
+    };
+    let feed = render_feed("https://shoe.example", &[episode]);
+
+    let doc = roxmltree::Document::parse(&feed).unwrap();
+    let item = doc.descendants().find(|n| n.has_tag_name("item")).unwrap();
+    let child = |name: &str| {
+        item.children()
+            .find(|n| n.tag_name().name() == name)
+            .unwrap()
+    };
+
+    assert_eq!(child("title").text(), Some("Fish & <Chips>"));
+    assert_eq!(child("duration").text(), Some("1:02:03"));
+    assert_eq!(
+        child("pubDate").text(),
+        Some("Mon, 12 Sep 2022 16:26:40 +0000")
+    );
+    let enclosure = child("enclosure");
+    assert_eq!(
+        enclosure.attribute("url"),
+        Some("https://shoe.example/audio/f00d.mp3")
+    );
+    assert_eq!(enclosure.attribute("length"), Some("1234"));
+    assert_eq!(
+        child("description").text(),
+        Some("A. Writer. https://example.com/fish?a=1&b=2")
+    );
+
+    assert!(token_matches("secret", "secret"));
+    assert!(!token_matches("secreT", "secret"));
+    assert!(!token_matches("secret!", "secret"));
+}
+
+#[test]
+fn account_feed_tokens() {
+    // The shared library's feed has the token from the config file, and every account's feed has
+    // a token of its own
+    assert_eq!(feed_token("secret", "podcast", None), "secret");
+    let alice = feed_token("secret", "podcast", Some("alice"));
+    assert_eq!(alice.len(), 64);
+    assert_eq!(alice, feed_token("secret", "podcast", Some("alice")));
+    assert_ne!(alice, feed_token("secret", "podcast", Some("bob")));
+    assert_ne!(alice, feed_token("secret", "calendar", Some("alice")));
+    assert_ne!(alice, feed_token("other", "podcast", Some("alice")));
+
+    // A token only opens the feed it's for
+    let params = |account: Option<&str>, token: &str| FeedParams {
+        token: Some(token.to_string()),
+        account: account.map(str::to_string),
+    };
+    let dir = std::env::temp_dir().join(format!("rtms-feed-test-{}", std::process::id()));
+    let audio_blob_dir = dir.to_str().unwrap();
+    fs::create_dir_all(accounts::library_dir(audio_blob_dir, Some("alice")).unwrap()).unwrap();
+    let library = |params| feed_library(audio_blob_dir, "secret", "podcast", &params);
+    assert_eq!(library(params(None, "secret")).unwrap(), audio_blob_dir);
+    assert!(library(params(Some("alice"), &alice))
+        .unwrap()
+        .ends_with("alice"));
+    assert_eq!(
+        library(params(Some("bob"), &alice)),
+        Err(StatusCode::UNAUTHORIZED)
+    );
+    assert_eq!(
+        library(params(Some("alice"), "secret")),
+        Err(StatusCode::UNAUTHORIZED)
+    );
+    assert_eq!(
+        feed_link(
+            "https://shoe.example",
+            "/api/podcast.xml",
+            "secret",
+            "podcast",
+            Some("alice")
+        ),
+        format!("https://shoe.example/api/podcast.xml?account=alice&token={alice}")
+    );
+
+    // An episode's link only opens that episode, until it expires, and says nothing of the token
+    let sig = episode_sig("secret", "alice", "f00d", 100);
+    assert_eq!(sig, episode_sig("secret", "alice", "f00d", 100));
+    assert_ne!(sig, episode_sig("secret", "alice", "beef", 100));
+    assert_ne!(sig, episode_sig("secret", "alice", "f00d", 200));
+    assert_ne!(sig, episode_sig("secret", "bob", "f00d", 100));
+    assert_ne!(sig, alice);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,315 @@
+//! Sends Web Push notifications to the listener's devices. Messages are encrypted for the device as
+//! in RFC 8291, and the server identifies itself to push services with a VAPID key (RFC 8292),
+//! which is generated the first time the server runs. Push services are reached under the same
+//! address check as pages are fetched, so a subscription can't point the server at its own network.
+
+use crate::fetcher::Fetcher;
+use common::PushSubscription;
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use hmac::{Hmac, Mac};
+use openssl::{
+    bn::BigNumContext,
+    derive::Deriver,
+    ec::{EcGroup, EcKey, EcPoint, PointConversionForm},
+    ecdsa::EcdsaSig,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rand::rand_bytes,
+    symm::{encrypt_aead, Cipher},
+};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+
+/// How long push services should hold on to a message for an offline device, in seconds
+const MESSAGE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// How long a VAPID token is good for, in seconds. Push services reject ones good for over a day.
+const TOKEN_LIFETIME_SECS: u64 = 12 * 60 * 60;
+
+/// The record size put in the header of encrypted messages. Messages are small enough to fit in
+/// one record.
+const RECORD_SIZE: u32 = 4096;
+
+/// Who push services should contact about this server's messages, if it has no contact set
+const DEFAULT_CONTACT: &str = "https://github.com/rozbb/readtomyshoe";
+
+/// Encodes the given bytes as unpadded URL-safe base64, the way Web Push wants everything encoded
+fn b64(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Decodes unpadded URL-safe base64. Browsers don't always leave the padding off, so it's allowed.
+fn unb64(s: &str) -> Result<Vec<u8>, AnyError> {
+    base64::decode_config(s.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+        .map_err(|e| anyhow!("Invalid base64: {e}"))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// HKDF with SHA-256, for outputs of at most one hash long
+fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+    let prk = hmac_sha256(salt, ikm);
+    let mut info = info.to_vec();
+    info.push(1);
+    hmac_sha256(&prk, &info)[..len].to_vec()
+}
+
+fn p256() -> EcGroup {
+    EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("P-256 is supported")
+}
+
+/// Returns the given key's public point in uncompressed form
+fn public_bytes<T: openssl::pkey::HasPublic>(key: &EcKey<T>) -> Result<Vec<u8>, AnyError> {
+    let mut ctx = BigNumContext::new()?;
+    Ok(key
+        .public_key()
+        .to_bytes(&p256(), PointConversionForm::UNCOMPRESSED, &mut ctx)?)
+}
+
+/// Encrypts the given message for the device with the given public key and auth secret, using the
+/// given ephemeral key and salt. This is the `aes128gcm` content coding of RFC 8291.
+fn encrypt_with(
+    ephemeral: &EcKey<Private>,
+    salt: &[u8; 16],
+    device_key: &[u8],
+    auth_secret: &[u8],
+    message: &[u8],
+) -> Result<Vec<u8>, AnyError> {
+    let group = p256();
+    let mut ctx = BigNumContext::new()?;
+    let device_point = EcPoint::from_bytes(&group, device_key, &mut ctx)
+        .map_err(|e| anyhow!("Invalid device key: {e}"))?;
+    let device_pkey = PKey::from_ec_key(EcKey::from_public_key(&group, &device_point)?)?;
+    let ephemeral_pkey = PKey::from_ec_key(ephemeral.clone())?;
+    let ephemeral_public = public_bytes(ephemeral)?;
+
+    // Derive the content encryption key and nonce from the shared secret and the auth secret
+    let mut deriver = Deriver::new(&ephemeral_pkey)?;
+    deriver.set_peer(&device_pkey)?;
+    let shared_secret = deriver.derive_to_vec()?;
+    let key_info = [b"WebPush: info\0", device_key, &ephemeral_public].concat();
+    let ikm = hkdf(auth_secret, &shared_secret, &key_info, 32);
+    let cek = hkdf(salt, &ikm, b"Content-Encoding: aes128gcm\0", 16);
+    let nonce = hkdf(salt, &ikm, b"Content-Encoding: nonce\0", 12);
+
+    // The message is one record, so it ends with the last-record delimiter
+    let plaintext = [message, &[2]].concat();
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_128_gcm(),
+        &cek,
+        Some(&nonce),
+        &[],
+        &plaintext,
+        &mut tag,
+    )?;
+
+    // The header says how to decrypt: the salt, the record size, and the ephemeral public key
+    Ok([
+        salt.as_slice(),
+        &RECORD_SIZE.to_be_bytes(),
+        &[ephemeral_public.len() as u8],
+        &ephemeral_public,
+        &ciphertext,
+        &tag,
+    ]
+    .concat())
+}
+
+/// Encrypts the given message for the given subscription
+fn encrypt(sub: &PushSubscription, message: &[u8]) -> Result<Vec<u8>, AnyError> {
+    let ephemeral = EcKey::generate(&p256())?;
+    let mut salt = [0u8; 16];
+    rand_bytes(&mut salt)?;
+    encrypt_with(
+        &ephemeral,
+        &salt,
+        &unb64(&sub.keys.p256dh)?,
+        &unb64(&sub.keys.auth)?,
+        message,
+    )
+}
+
+/// What happened to a push message
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    /// The push service took the message
+    Sent,
+    /// The subscription is gone, e.g., because the listener revoked permission, and should be
+    /// forgotten
+    Expired,
+}
+
+/// The server's VAPID key, and what it needs to send push messages with it
+#[derive(Clone)]
+pub(crate) struct WebPush {
+    key: EcKey<Private>,
+    contact: String,
+    fetcher: Fetcher,
+}
+
+impl WebPush {
+    /// Loads the VAPID key from the given PEM file. If there's no such file, a new key is made and
+    /// saved there. Subscriptions are tied to the key, so it has to stay the same across restarts.
+    /// Messages are sent with the given fetcher.
+    pub(crate) fn load_or_generate(
+        path: &str,
+        contact: Option<String>,
+        fetcher: Fetcher,
+    ) -> Result<WebPush, AnyError> {
+        let path = Path::new(path);
+        let key = if path.exists() {
+            let pem = std::fs::read(path)
+                .map_err(|e| anyhow!("Could not read VAPID key {:?}: {e}", path))?;
+            EcKey::private_key_from_pem(&pem)
+                .map_err(|e| anyhow!("Could not parse VAPID key {:?}: {e}", path))?
+        } else {
+            tracing::info!(
+                "Generating a VAPID key for push notifications at {:?}",
+                path
+            );
+            let key = EcKey::generate(&p256())?;
+            std::fs::write(path, key.private_key_to_pem()?)
+                .map_err(|e| anyhow!("Could not save VAPID key {:?}: {e}", path))?;
+            key
+        };
+
+        Ok(WebPush {
+            key,
+            contact: contact.unwrap_or_else(|| DEFAULT_CONTACT.to_string()),
+            fetcher,
+        })
+    }
+
+    /// Returns the public key that browsers subscribe with, i.e., the `applicationServerKey`
+    pub(crate) fn public_key(&self) -> String {
+        b64(&public_bytes(&self.key).expect("the key is a P-256 key"))
+    }
+
+    /// Makes a VAPID token for sending to the push service at the given origin. This is a JWT
+    /// signed with ES256.
+    fn token(&self, audience: &str) -> Result<String, AnyError> {
+        let exp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + TOKEN_LIFETIME_SECS;
+        let header = b64(br#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = serde_json::json!({ "aud": audience, "exp": exp, "sub": self.contact });
+        let signing_input = format!("{header}.{}", b64(claims.to_string().as_bytes()));
+
+        // JWTs want the signature as the two 32-byte integers, not DER
+        let sig = EcdsaSig::sign(&Sha256::digest(signing_input.as_bytes()), &self.key)?;
+        let signature = [sig.r().to_vec_padded(32)?, sig.s().to_vec_padded(32)?].concat();
+        Ok(format!("{signing_input}.{}", b64(&signature)))
+    }
+
+    /// Sends the given message to the given subscription
+    pub(crate) async fn send(
+        &self,
+        sub: &PushSubscription,
+        message: &[u8],
+    ) -> Result<Delivery, AnyError> {
+        let endpoint =
+            reqwest::Url::parse(&sub.endpoint).map_err(|e| anyhow!("Invalid endpoint: {e}"))?;
+        let audience = endpoint.origin().ascii_serialization();
+        let body = encrypt(sub, message)?;
+
+        let client = self.fetcher.client_for(&endpoint).await?;
+        let resp = client
+            .post(endpoint)
+            .header(
+                "Authorization",
+                format!(
+                    "vapid t={}, k={}",
+                    self.token(&audience)?,
+                    self.public_key()
+                ),
+            )
+            .header("TTL", MESSAGE_TTL_SECS.to_string())
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Push request failed: {e}"))?;
+
+        match resp.status() {
+            s if s.is_success() => Ok(Delivery::Sent),
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(Delivery::Expired),
+            s => bail!(
+                "Push service responded {s}: {}",
+                resp.text().await.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+// The example from RFC 8291, section 5
+#[test]
+fn encryption() {
+    let ephemeral_private = unb64("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw").unwrap();
+    let ephemeral_public = unb64(
+        "BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8",
+    )
+    .unwrap();
+    let device_key = unb64(
+        "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+    )
+    .unwrap();
+    let auth_secret = unb64("BTBZMqHH6r4Tts7J_aSIgg").unwrap();
+    let salt: [u8; 16] = unb64("DGv6ra1nlYgDCS1FRnbzlw").unwrap().try_into().unwrap();
+
+    let group = p256();
+    let mut ctx = BigNumContext::new().unwrap();
+    let ephemeral = EcKey::from_private_components(
+        &group,
+        &openssl::bn::BigNum::from_slice(&ephemeral_private).unwrap(),
+        &EcPoint::from_bytes(&group, &ephemeral_public, &mut ctx).unwrap(),
+    )
+    .unwrap();
+
+    let encrypted = encrypt_with(
+        &ephemeral,
+        &salt,
+        &device_key,
+        &auth_secret,
+        b"When I grow up, I want to be a watermelon",
+    )
+    .unwrap();
+    assert_eq!(
+        b64(&encrypted),
+        "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYW\
+        AmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSg\
+        Sxsj_Qulcy4a-fN"
+    );
+}
+
+#[test]
+fn vapid_tokens() {
+    let push = WebPush {
+        key: EcKey::generate(&p256()).unwrap(),
+        contact: DEFAULT_CONTACT.to_string(),
+        fetcher: Fetcher::new(Default::default()).unwrap(),
+    };
+    let token = push.token("https://push.example.net").unwrap();
+    let parts: Vec<&str> = token.split('.').collect();
+    assert_eq!(parts.len(), 3);
+
+    // The signature checks out against the public key that's handed to browsers
+    let signature = unb64(parts[2]).unwrap();
+    let sig = EcdsaSig::from_private_components(
+        openssl::bn::BigNum::from_slice(&signature[..32]).unwrap(),
+        openssl::bn::BigNum::from_slice(&signature[32..]).unwrap(),
+    )
+    .unwrap();
+    let digest = Sha256::digest(format!("{}.{}", parts[0], parts[1]).as_bytes());
+    assert!(sig.verify(&digest, &push.key).unwrap());
+    assert_eq!(unb64(&push.public_key()).unwrap().len(), 65);
+}
@@ -0,0 +1,38 @@
+//! QR codes, for showing a link as something a phone camera can open. Text is encoded at error
+//! correction level M, which is plenty for a URL. Codes are drawn as SVG.
+
+use anyhow::{anyhow, Error as AnyError};
+use qrcode::{render::svg, EcLevel, QrCode};
+
+/// The size of a module in the SVG. It's scaled to fit wherever it's shown, so this just keeps
+/// the numbers small.
+const MODULE_SIZE: u32 = 1;
+
+/// Draws the given text as a QR code in an SVG, black on white, with a quiet zone around it
+pub(crate) fn to_svg(text: &str) -> Result<String, AnyError> {
+    let code = QrCode::with_error_correction_level(text, EcLevel::M)
+        .map_err(|e| anyhow!("Could not make a QR code of {} characters: {e}", text.len()))?;
+    Ok(code
+        .render::<svg::Color>()
+        .module_dimensions(MODULE_SIZE, MODULE_SIZE)
+        .dark_color(svg::Color("#000"))
+        .light_color(svg::Color("#fff"))
+        .quiet_zone(true)
+        .build())
+}
+
+#[test]
+fn qr_encoding() {
+    // A pairing link fits in a version 3 code, 29 modules and the 4-module quiet zone on each side
+    let url = "https://example.com/pair?code=abc";
+    assert_eq!(
+        QrCode::with_error_correction_level(url, EcLevel::M)
+            .unwrap()
+            .width(),
+        29
+    );
+    let svg = to_svg(url).unwrap();
+    assert!(svg.contains("<svg"));
+    assert!(svg.contains("width=\"37\""));
+    assert!(to_svg(&"a".repeat(5000)).is_err());
+}
@@ -0,0 +1,52 @@
+//! Limits how much text the server synthesizes, so a busy server doesn't run up the TTS bill
+
+use anyhow::{bail, Context, Error as AnyError};
+use governor::{
+    clock::DefaultClock, middleware::NoOpMiddleware, state::direct::NotKeyed, state::InMemoryState,
+    Quota, RateLimiter as BaseRateLimiter,
+};
+
+use std::{
+    num::{NonZeroU32, NonZeroUsize},
+    sync::Arc,
+};
+
+type DefaultRateLimiter = BaseRateLimiter<NotKeyed, InMemoryState, DefaultClock, NoOpMiddleware>;
+
+/// The rate limiter for TTS calls. The quota contains the quota for characters per minute.
+#[derive(Clone)]
+pub(crate) struct RateLimiter {
+    base_rl: Arc<DefaultRateLimiter>,
+    quota: Quota,
+}
+
+impl RateLimiter {
+    /// Makes a rate limiter that allows `max_chars_per_min` characters per minute
+    pub(crate) fn new(max_chars_per_min: NonZeroU32) -> RateLimiter {
+        let quota = Quota::per_minute(max_chars_per_min);
+        RateLimiter {
+            base_rl: Arc::new(DefaultRateLimiter::direct(quota)),
+            quota,
+        }
+    }
+
+    /// Checks whether the given text can be synthesized without exceeding the rate limit. If so,
+    /// the text's length is counted towards the limit. If not, returns an error.
+    pub(crate) fn check(&self, text: &str) -> Result<(), AnyError> {
+        let text_len: NonZeroU32 = {
+            let n = text.len();
+            let nzn = NonZeroUsize::new(n).or(NonZeroUsize::new(1)).unwrap();
+            NonZeroU32::try_from(nzn).with_context(|| "Article is is far too large")?
+        };
+
+        // If the article bytelen exceeds the limit, error out
+        if self.base_rl.check_n(text_len).is_err() {
+            bail!(
+                "Usage limit exceeded. This server processes at most {} letters per minute.",
+                self.quota.burst_size().get(),
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Thumbs-up and thumbs-down ratings of articles. A rating is kept in the article's ID3 tag with the
+//! rest of its metadata, so it goes wherever the article does, e.g., into library archives.
+
+use crate::{
+    accounts::LibraryDir,
+    list_articles::LibraryCache,
+    util::{get_metadata, save_metadata},
+};
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{extract::Extension, http::StatusCode, routing::post, Json, Router};
+use common::{ArticleRating, Rating};
+
+// Sets the /api/rate-article route
+pub(crate) fn setup(router: Router, library_cache: LibraryCache) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/rate-article", post(rate_article))
+            .layer(Extension(library_cache)),
+    )
+}
+
+/// Sets the rating of the article with the given ID, or takes it away. The library's metadata cache
+/// is updated too, so the new rating is listed.
+fn set_rating(
+    audio_blob_dir: &str,
+    library_cache: &LibraryCache,
+    id: &str,
+    rating: Option<Rating>,
+) -> Result<(), AnyError> {
+    // IDs become file names, so don't let one point outside the audio blob dir
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        bail!("invalid article ID {id:?}");
+    }
+    let path = Path::new(audio_blob_dir).join(id).with_extension("mp3");
+    if !path.exists() {
+        bail!("no article {id:?}");
+    }
+
+    let mut meta = get_metadata(&path)?;
+    meta.rating = rating;
+    save_metadata(&meta, audio_blob_dir)?;
+
+    let mut cache = library_cache
+        .lock()
+        .map_err(|_| anyhow!("metadata cache is poisoned"))?;
+    if let Some(cached) = cache.get_mut(&path) {
+        cached.rating = rating;
+    }
+    Ok(())
+}
+
+/// Rates the given article
+async fn rate_article(
+    Json(ArticleRating { id, rating }): Json<ArticleRating>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+    Extension(library_cache): Extension<LibraryCache>,
+) -> StatusCode {
+    match set_rating(&audio_blob_dir, &library_cache, &id, rating) {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Couldn't rate {id}: {e}");
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+#[test]
+fn rating_articles() {
+    use crate::list_articles;
+    use common::ArticleMetadata;
+
+    let dir = std::env::temp_dir().join(format!("rtms-ratings-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let audio_blob_dir = dir.to_str().unwrap();
+    let meta = ArticleMetadata {
+        id: "Fish-abc".to_string(),
+        title: "Fish".to_string(),
+        publication: Some("The Daily Fish".to_string()),
+        ..Default::default()
+    };
+    std::fs::write(dir.join("Fish-abc.mp3"), [0xFFu8; 64]).unwrap();
+    save_metadata(&meta, audio_blob_dir).unwrap();
+
+    // The rating is listed, even once the article's metadata is cached, and the rest is kept
+    let cache = LibraryCache::default();
+    list_articles::load_catalog(audio_blob_dir, &cache).unwrap();
+    set_rating(audio_blob_dir, &cache, "Fish-abc", Some(Rating::Up)).unwrap();
+    let catalog = list_articles::load_catalog(audio_blob_dir, &cache).unwrap();
+    assert_eq!(catalog[0].rating, Some(Rating::Up));
+    let saved = get_metadata(&dir.join("Fish-abc.mp3")).unwrap();
+    assert_eq!(saved.rating, Some(Rating::Up));
+    assert_eq!(saved.publication, meta.publication);
+
+    // Ratings can be taken away, and only articles in the library can be rated
+    set_rating(audio_blob_dir, &cache, "Fish-abc", None).unwrap();
+    assert_eq!(
+        get_metadata(&dir.join("Fish-abc.mp3")).unwrap().rating,
+        None
+    );
+    assert!(set_rating(audio_blob_dir, &cache, "Chips-def", Some(Rating::Down)).is_err());
+    assert!(set_rating(audio_blob_dir, &cache, "../Fish-abc", Some(Rating::Down)).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
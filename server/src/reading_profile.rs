@@ -0,0 +1,212 @@
+//! Works out what an article's text is like to listen to: the language it's in, and how dense it
+//! is. The client compares these across articles to suggest a playback speed.
+
+use common::ReadingProfile;
+
+use std::ops::RangeInclusive;
+
+/// Common short words of the languages that can be detected. Each language is detected by how
+/// many of the text's words are on its list.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "is", "that", "with", "was", "for", "it", "this", "are",
+            "be", "have", "from",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "que", "de", "y", "en", "es", "por", "con", "para", "una",
+            "del", "se",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "des", "est", "que", "une", "dans", "pour", "pas", "qui",
+            "sur", "du", "au",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "den", "ein", "eine", "zu", "von",
+            "auf", "sich", "dem",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "che", "di", "e", "la", "non", "per", "una", "sono", "del", "della", "con",
+            "gli", "anche", "è",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "a", "os", "que", "de", "e", "não", "uma", "para", "com", "do", "da", "em", "se",
+            "é",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "van", "is", "dat", "niet", "op", "te", "met", "voor",
+            "zijn", "ook", "wordt",
+        ],
+    ),
+];
+
+/// The smallest share of a text's words that have to be stopwords of a language for the text to be
+/// detected as that language
+const MIN_STOPWORD_SHARE: f64 = 0.05;
+
+/// Languages with scripts of their own, and the Unicode blocks of those scripts. These are detected
+/// by how many of the text's letters are in the script, since CJK text doesn't put spaces between
+/// its words. Japanese mixes kana with Chinese characters, so its kana are checked before Chinese.
+const SCRIPTS: &[(&str, &[RangeInclusive<char>])] = &[
+    (
+        "ar",
+        &[
+            '\u{0600}'..='\u{06FF}',
+            '\u{0750}'..='\u{077F}',
+            '\u{FB50}'..='\u{FDFF}',
+            '\u{FE70}'..='\u{FEFF}',
+        ],
+    ),
+    ("he", &['\u{0590}'..='\u{05FF}', '\u{FB1D}'..='\u{FB4F}']),
+    ("ja", &['\u{3040}'..='\u{30FF}']),
+    (
+        "ko",
+        &[
+            '\u{1100}'..='\u{11FF}',
+            '\u{3130}'..='\u{318F}',
+            '\u{AC00}'..='\u{D7AF}',
+        ],
+    ),
+    ("zh", &['\u{3400}'..='\u{4DBF}', '\u{4E00}'..='\u{9FFF}']),
+];
+
+/// The smallest share of a text's letters that have to be in a language's script for the text to
+/// be detected as that language
+const MIN_SCRIPT_SHARE: f64 = 0.3;
+
+/// Words at least this many letters long count as long words in the LIX readability score
+const LONG_WORD_LEN: usize = 7;
+
+/// The LIX scores of very plain and very dense text. Complexity goes from 0 to 1 between them.
+const LIX_RANGE: (f64, f64) = (20.0, 60.0);
+
+/// Returns the language of the given text, if enough of its letters are in that language's script
+fn detect_script(text: &str) -> Option<String> {
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    let (lang, _) = SCRIPTS.iter().find(|(_, blocks)| {
+        let in_script = letters
+            .iter()
+            .filter(|c| blocks.iter().any(|block| block.contains(c)))
+            .count();
+        in_script > 0 && in_script as f64 >= MIN_SCRIPT_SHARE * letters.len() as f64
+    })?;
+    Some(lang.to_string())
+}
+
+/// Returns the language of the given words, if they have enough stopwords of one language
+fn detect_language(words: &[String]) -> Option<String> {
+    let (lang, hits) = STOPWORDS
+        .iter()
+        .map(|(lang, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|w| stopwords.contains(&w.as_str()))
+                .count();
+            (lang, hits)
+        })
+        .max_by_key(|&(_, hits)| hits)?;
+
+    (hits as f64 >= MIN_STOPWORD_SHARE * words.len() as f64 && hits > 0).then(|| lang.to_string())
+}
+
+/// Returns the complexity of a text with the given words and number of sentences. This is the LIX
+/// readability score, i.e., the average sentence length plus the percentage of long words, scaled
+/// to between 0 and 1. LIX works about the same across European languages.
+fn complexity(words: &[String], sentences: usize) -> f32 {
+    if words.is_empty() {
+        return 0.0;
+    }
+
+    let long_words = words
+        .iter()
+        .filter(|w| w.chars().count() >= LONG_WORD_LEN)
+        .count();
+    let lix = words.len() as f64 / sentences.max(1) as f64
+        + 100.0 * long_words as f64 / words.len() as f64;
+
+    let (plain, dense) = LIX_RANGE;
+    ((lix - plain) / (dense - plain)).clamp(0.0, 1.0) as f32
+}
+
+/// Analyzes the given article text
+pub(crate) fn analyze(text: &str) -> ReadingProfile {
+    // Words are lowercased and stripped of surrounding punctuation. Sentences end at periods,
+    // question and exclamation marks, and line breaks, so headings count as sentences.
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect();
+    let sentences = text
+        .split(['.', '?', '!', '\n'])
+        .filter(|s| s.chars().any(char::is_alphanumeric))
+        .count();
+
+    ReadingProfile {
+        language: detect_script(text).or_else(|| detect_language(&words)),
+        complexity: complexity(&words, sentences),
+    }
+}
+
+#[test]
+fn profiles() {
+    let plain =
+        analyze("The cat sat on the mat. It was a fine day. The sun was out and it was warm.");
+    assert_eq!(plain.language.as_deref(), Some("en"));
+
+    let dense = analyze(
+        "Notwithstanding considerable institutional heterogeneity, comparative constitutional \
+         scholarship increasingly emphasizes intergovernmental coordination mechanisms, \
+         particularly regarding redistributive macroeconomic stabilization, which \
+         policymakers characterize as fundamentally indispensable.",
+    );
+    assert!(dense.complexity > plain.complexity);
+    assert_eq!(dense.complexity, 1.0);
+
+    let german = analyze("Der Hund ist nicht in dem Haus, und die Katze sitzt auf dem Dach.");
+    assert_eq!(german.language.as_deref(), Some("de"));
+    let spanish = analyze("El perro de los vecinos duerme en la casa con una manta para el frío.");
+    assert_eq!(spanish.language.as_deref(), Some("es"));
+
+    // Languages with their own scripts are detected by script
+    let arabic = analyze("ذهب الولد إلى المدرسة في الصباح، ثم عاد إلى البيت.");
+    assert_eq!(arabic.language.as_deref(), Some("ar"));
+    let hebrew = analyze("הילד הלך לבית הספר בבוקר וחזר הביתה בערב.");
+    assert_eq!(hebrew.language.as_deref(), Some("he"));
+    let japanese = analyze("今日は天気がいいので、公園を散歩しました。");
+    assert_eq!(japanese.language.as_deref(), Some("ja"));
+    let chinese = analyze("今天天气很好，我们去公园散步了。");
+    assert_eq!(chinese.language.as_deref(), Some("zh"));
+    let korean = analyze("오늘은 날씨가 좋아서 공원에서 산책했습니다.");
+    assert_eq!(korean.language.as_deref(), Some("ko"));
+    // A foreign name in an English text doesn't make it foreign
+    let mixed = analyze("The word for peace is שלום, and it was the first word she learned.");
+    assert_eq!(mixed.language.as_deref(), Some("en"));
+
+    // Text with no stopwords has no detectable language
+    let unknown = analyze("Lorem ipsum dolor sit amet, consectetur adipiscing elit.");
+    assert_eq!(unknown.language, None);
+    assert_eq!(analyze("").complexity, 0.0);
+}
@@ -0,0 +1,564 @@
+//! "Listen later" reminders. The listener sets a time to be reminded of an article. Once that time
+//! comes, the article is shown at the top of the home view, and a background task sends a push
+//! notification to every device that asked for them. Reminders expire a while after they're due,
+//! so ones that were never dismissed don't pile up. The listener can turn on quiet mode for a while,
+//! and reminders that come due then aren't pushed at all. They can also set quiet hours every day,
+//! and reminders that come due then are pushed once they're over. Feeds wait for them too.
+//!
+//! Every library has its own reminders, push subscriptions, and quiet times.
+
+use crate::{
+    accounts::LibraryDir,
+    json_store::JsonStore,
+    push::{Delivery, WebPush},
+    util::now_secs,
+};
+use common::{
+    PushSubscription, QuietHours, QuietMode, Reminder, ReminderDismissal, ReminderSubmission,
+};
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Error as AnyError;
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+/// How long a reminder is shown after it's due, in seconds
+const REMINDER_LIFETIME_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// How often the scheduler checks for reminders that have come due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A reminder, as saved
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SavedReminder {
+    article_id: String,
+    title: String,
+    remind_at: u64,
+    /// Whether the push notification for this reminder has gone out
+    pushed: bool,
+}
+
+impl From<&SavedReminder> for Reminder {
+    fn from(r: &SavedReminder) -> Reminder {
+        Reminder {
+            article_id: r.article_id.clone(),
+            title: r.title.clone(),
+            remind_at: r.remind_at,
+        }
+    }
+}
+
+/// Everything the store saves for a library
+#[derive(Default, Serialize, Deserialize)]
+struct Reminders {
+    reminders: Vec<SavedReminder>,
+    /// The devices to notify when a reminder comes due
+    push_subscriptions: Vec<PushSubscription>,
+    /// When quiet mode ends, in seconds since the Unix epoch, if it's been turned on
+    #[serde(default)]
+    quiet_until: Option<u64>,
+    /// The quiet hours, if they've been set
+    #[serde(default)]
+    quiet_hours: Option<QuietHours>,
+}
+
+impl Reminders {
+    /// Drops the reminders that expired before the given time
+    fn prune(&mut self, now: u64) {
+        self.reminders
+            .retain(|r| r.remind_at + REMINDER_LIFETIME_SECS > now);
+    }
+}
+
+/// The reminders and push subscriptions of every library, by the library's directory. These are
+/// saved as JSON.
+#[derive(Clone)]
+pub(crate) struct ReminderStore {
+    reminders: JsonStore<BTreeMap<String, Reminders>>,
+}
+
+impl ReminderStore {
+    /// Loads the reminders from the given file. If the file doesn't exist, there are no reminders
+    /// yet.
+    pub(crate) fn load(path: &str) -> Result<ReminderStore, AnyError> {
+        Ok(ReminderStore {
+            reminders: JsonStore::load(path, "reminders")?,
+        })
+    }
+
+    /// Runs the given function on the reminders of the given library, and saves them afterwards
+    async fn update<T>(
+        &self,
+        library_dir: &str,
+        f: impl FnOnce(&mut Reminders) -> T,
+    ) -> Result<T, AnyError> {
+        self.reminders
+            .update(|reminders| f(reminders.entry(library_dir.to_string()).or_default()))
+            .await
+    }
+
+    /// Runs the given function on the reminders of the given library, if it has any
+    fn read<T: Default>(&self, library_dir: &str, f: impl FnOnce(&Reminders) -> T) -> T {
+        self.reminders
+            .read(|reminders| reminders.get(library_dir).map(f).unwrap_or_default())
+    }
+
+    /// Returns the given library's reminders, push subscriptions, and quiet times as JSON, for a
+    /// takeout
+    pub(crate) fn take_out(&self, library_dir: &str) -> Result<Vec<u8>, AnyError> {
+        self.reminders.read(|reminders| {
+            let empty = Reminders::default();
+            Ok(serde_json::to_vec_pretty(
+                reminders.get(library_dir).unwrap_or(&empty),
+            )?)
+        })
+    }
+
+    /// Forgets the given library's reminders, push subscriptions, and quiet times
+    pub(crate) async fn forget_library(&self, library_dir: &str) -> Result<(), AnyError> {
+        self.reminders
+            .update(|reminders| {
+                reminders.remove(library_dir);
+            })
+            .await
+    }
+
+    /// Returns the reminders of the given library that haven't expired by the given time, soonest
+    /// first
+    pub(crate) fn list(&self, library_dir: &str, now: u64) -> Vec<Reminder> {
+        let mut list: Vec<Reminder> = self.read(library_dir, |reminders| {
+            reminders
+                .reminders
+                .iter()
+                .filter(|r| r.remind_at + REMINDER_LIFETIME_SECS > now)
+                .map(Into::into)
+                .collect()
+        });
+        list.sort_by_key(|r| r.remind_at);
+        list
+    }
+
+    /// Sets the given reminder, replacing the article's old one if it had one
+    async fn set(&self, library_dir: &str, submission: ReminderSubmission) -> Result<(), AnyError> {
+        self.update(library_dir, |reminders| {
+            reminders
+                .reminders
+                .retain(|r| r.article_id != submission.article_id);
+            reminders.reminders.push(SavedReminder {
+                article_id: submission.article_id,
+                title: submission.title,
+                remind_at: submission.remind_at,
+                pushed: false,
+            });
+        })
+        .await
+    }
+
+    /// Removes the reminder of the given article
+    async fn dismiss(&self, library_dir: &str, article_id: &str) -> Result<(), AnyError> {
+        self.update(library_dir, |reminders| {
+            reminders.reminders.retain(|r| r.article_id != article_id)
+        })
+        .await
+    }
+
+    /// Adds the given push subscription, unless it's already there
+    async fn subscribe(&self, library_dir: &str, sub: PushSubscription) -> Result<(), AnyError> {
+        self.update(library_dir, |reminders| {
+            let subs = &mut reminders.push_subscriptions;
+            subs.retain(|s| s.endpoint != sub.endpoint);
+            subs.push(sub);
+        })
+        .await
+    }
+
+    /// Forgets the push subscription with the given endpoint
+    async fn unsubscribe(&self, library_dir: &str, endpoint: &str) -> Result<(), AnyError> {
+        self.update(library_dir, |reminders| {
+            reminders
+                .push_subscriptions
+                .retain(|s| s.endpoint != endpoint)
+        })
+        .await
+    }
+
+    /// Returns whether quiet mode is on at the given time, and until when, along with the quiet
+    /// hours
+    fn quiet_mode(&self, library_dir: &str, now: u64) -> QuietMode {
+        self.read(library_dir, |reminders| QuietMode {
+            until: reminders.quiet_until.filter(|&until| until > now),
+            hours: reminders.quiet_hours,
+        })
+    }
+
+    /// Turns quiet mode on until the given time, or off. This leaves the quiet hours alone.
+    async fn set_quiet_mode(
+        &self,
+        library_dir: &str,
+        QuietMode { until, .. }: QuietMode,
+    ) -> Result<(), AnyError> {
+        self.update(library_dir, |reminders| reminders.quiet_until = until)
+            .await
+    }
+
+    /// Sets the quiet hours, or turns them off
+    async fn set_quiet_hours(
+        &self,
+        library_dir: &str,
+        hours: Option<QuietHours>,
+    ) -> Result<(), AnyError> {
+        self.update(library_dir, |reminders| reminders.quiet_hours = hours)
+            .await
+    }
+
+    /// Returns whether the given time, in seconds since the Unix epoch, is in the given library's
+    /// quiet hours
+    pub(crate) fn in_quiet_hours(&self, library_dir: &str, now: u64) -> bool {
+        let hours = self.read(library_dir, |reminders| reminders.quiet_hours);
+        hours.is_some_and(|hours| hours.contains(now))
+    }
+
+    /// Returns the reminders of every library that are due by the given time and haven't been
+    /// pushed, along with the library's directory and push subscriptions, and marks them as pushed.
+    /// In quiet mode, they're marked without being returned, so they're never pushed. In quiet
+    /// hours, none are returned or marked, so they're pushed once the hours are over. Expired
+    /// reminders are dropped along the way.
+    async fn take_due(&self, now: u64) -> Result<Vec<DueReminders>, AnyError> {
+        self.reminders
+            .update(|all| {
+                all.iter_mut()
+                    .filter_map(|(library_dir, reminders)| {
+                        reminders.prune(now);
+                        if reminders
+                            .quiet_hours
+                            .is_some_and(|hours| hours.contains(now))
+                        {
+                            return None;
+                        }
+                        let quiet = reminders.quiet_until.is_some_and(|until| until > now);
+                        let due: Vec<Reminder> = reminders
+                            .reminders
+                            .iter_mut()
+                            .filter(|r| r.remind_at <= now && !r.pushed)
+                            .map(|r| {
+                                r.pushed = true;
+                                Reminder::from(&*r)
+                            })
+                            .filter(|_| !quiet)
+                            .collect();
+                        (!due.is_empty()).then(|| DueReminders {
+                            library_dir: library_dir.clone(),
+                            reminders: due,
+                            push_subscriptions: reminders.push_subscriptions.clone(),
+                        })
+                    })
+                    .collect()
+            })
+            .await
+    }
+}
+
+/// A library's reminders that have come due, and the devices to notify of them
+struct DueReminders {
+    library_dir: String,
+    reminders: Vec<Reminder>,
+    push_subscriptions: Vec<PushSubscription>,
+}
+
+// Sets the /api/list-reminders, /api/set-reminder, /api/dismiss-reminder, /api/push-key,
+// /api/subscribe-push, /api/quiet-mode, /api/set-quiet-mode, and /api/set-quiet-hours routes
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    reminder_store: ReminderStore,
+    web_push: WebPush,
+) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/list-reminders", get(list_reminders))
+            .route("/set-reminder", post(set_reminder))
+            .route("/dismiss-reminder", post(dismiss_reminder))
+            .route("/push-key", get(push_key))
+            .route("/subscribe-push", post(subscribe_push))
+            .route("/quiet-mode", get(quiet_mode))
+            .route("/set-quiet-mode", post(set_quiet_mode))
+            .route("/set-quiet-hours", post(set_quiet_hours))
+            .layer(Extension(reminder_store))
+            .layer(Extension(web_push))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Pushes a notification of the given reminder to the given devices of the given library. Devices
+/// whose subscriptions are gone are forgotten.
+async fn push_reminder(
+    library_dir: &str,
+    reminder: &Reminder,
+    subs: &[PushSubscription],
+    reminder_store: &ReminderStore,
+    web_push: &WebPush,
+) {
+    let message = serde_json::json!({
+        "title": "Time to listen",
+        "body": reminder.title,
+    })
+    .to_string();
+
+    for sub in subs {
+        match web_push.send(sub, message.as_bytes()).await {
+            Ok(Delivery::Sent) => (),
+            Ok(Delivery::Expired) => {
+                let _ = reminder_store
+                    .unsubscribe(library_dir, &sub.endpoint)
+                    .await
+                    .map_err(|e| tracing::error!("Error removing push subscription: {e}"));
+            }
+            Err(e) => tracing::error!("Error pushing reminder of {}: {e}", reminder.article_id),
+        }
+    }
+}
+
+/// Spawns a background task that sends push notifications for reminders as they come due
+pub(crate) fn spawn_scheduler(reminder_store: ReminderStore, web_push: WebPush) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let due = match reminder_store.take_due(now_secs()).await {
+                Ok(r) => r,
+                Err(e) => {
+                    tracing::error!("Error checking reminders: {e}");
+                    continue;
+                }
+            };
+            for library in due {
+                for reminder in &library.reminders {
+                    push_reminder(
+                        &library.library_dir,
+                        reminder,
+                        &library.push_subscriptions,
+                        &reminder_store,
+                        &web_push,
+                    )
+                    .await;
+                }
+            }
+        }
+    });
+}
+
+/// Lists the library's reminders that haven't expired, soonest first
+async fn list_reminders(
+    Extension(reminder_store): Extension<ReminderStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<Vec<Reminder>> {
+    Json(reminder_store.list(&library_dir, now_secs()))
+}
+
+/// Sets a reminder for an article
+async fn set_reminder(
+    Json(submission): Json<ReminderSubmission>,
+    Extension(reminder_store): Extension<ReminderStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match reminder_store.set(&library_dir, submission).await {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error setting reminder: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Dismisses the reminder of an article
+async fn dismiss_reminder(
+    Json(ReminderDismissal { article_id }): Json<ReminderDismissal>,
+    Extension(reminder_store): Extension<ReminderStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match reminder_store.dismiss(&library_dir, &article_id).await {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error dismissing reminder: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Returns the public key that devices subscribe to push notifications with
+async fn push_key(Extension(web_push): Extension<WebPush>) -> String {
+    web_push.public_key()
+}
+
+/// Subscribes a device to push notifications of reminders
+async fn subscribe_push(
+    Json(sub): Json<PushSubscription>,
+    Extension(reminder_store): Extension<ReminderStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match reminder_store.subscribe(&library_dir, sub).await {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error saving push subscription: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Returns whether quiet mode is on, and until when
+async fn quiet_mode(
+    Extension(reminder_store): Extension<ReminderStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<QuietMode> {
+    Json(reminder_store.quiet_mode(&library_dir, now_secs()))
+}
+
+/// Turns quiet mode on for a while, or off
+async fn set_quiet_mode(
+    Json(quiet_mode): Json<QuietMode>,
+    Extension(reminder_store): Extension<ReminderStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match reminder_store
+        .set_quiet_mode(&library_dir, quiet_mode)
+        .await
+    {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error setting quiet mode: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Sets the quiet hours, or turns them off
+async fn set_quiet_hours(
+    Json(hours): Json<Option<QuietHours>>,
+    Extension(reminder_store): Extension<ReminderStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if hours.is_some_and(|hours| !hours.is_valid()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Quiet hours have to start and end within a day".to_string(),
+        ));
+    }
+    reminder_store
+        .set_quiet_hours(&library_dir, hours)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error setting quiet hours: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+    Ok(StatusCode::OK)
+}
+
+#[tokio::test]
+async fn reminder_lifecycle() {
+    let path = std::env::temp_dir().join("rtms-test-reminders.json");
+    let _ = std::fs::remove_file(&path);
+    let store = ReminderStore::load(path.to_str().unwrap()).unwrap();
+    let remind = |id: &str, remind_at| ReminderSubmission {
+        article_id: id.to_string(),
+        title: format!("Article {id}"),
+        remind_at,
+    };
+
+    store.set("lib", remind("a", 100)).await.unwrap();
+    store.set("lib", remind("b", 200)).await.unwrap();
+    // Setting a reminder again moves it
+    store.set("lib", remind("a", 300)).await.unwrap();
+    let ids = |list: Vec<Reminder>| list.into_iter().map(|r| r.article_id).collect::<Vec<_>>();
+    // The due reminders' IDs, by library
+    let store = &store;
+    let due = |now| async move {
+        store
+            .take_due(now)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|due| format!("{} {}", due.library_dir, ids(due.reminders).join(",")))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(store.list("lib", 0)), ["b", "a"]);
+
+    // Reminders come due once
+    assert_eq!(due(250).await, ["lib b"]);
+    assert_eq!(due(300).await, ["lib a"]);
+    assert!(due(400).await.is_empty());
+
+    // Reminders that come due in quiet mode are never pushed, even once it's over
+    store.set("lib", remind("c", 500)).await.unwrap();
+    store.set("lib", remind("d", 700)).await.unwrap();
+    store
+        .set_quiet_mode(
+            "lib",
+            QuietMode {
+                until: Some(600),
+                hours: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.quiet_mode("lib", 550).until, Some(600));
+    assert!(due(550).await.is_empty());
+    assert_eq!(store.quiet_mode("lib", 600).until, None);
+    assert_eq!(due(700).await, ["lib d"]);
+    store.dismiss("lib", "c").await.unwrap();
+    store.dismiss("lib", "d").await.unwrap();
+
+    // Reminders that come due in quiet hours wait until they're over. These are 22:00 to 07:00
+    // two hours ahead of UTC, i.e., 20:00 to 05:00 UTC.
+    const HOUR: u64 = 60 * 60;
+    let hours = QuietHours {
+        start: 22 * 60,
+        end: 7 * 60,
+        utc_offset: 120,
+    };
+    assert!(hours.is_valid());
+    assert!(hours.contains(20 * HOUR) && hours.contains(24 * HOUR + 4 * HOUR));
+    assert!(!hours.contains(5 * HOUR) && !hours.contains(19 * HOUR));
+    store.set_quiet_hours("lib", Some(hours)).await.unwrap();
+    assert_eq!(store.quiet_mode("lib", 0).hours, Some(hours));
+    store.set("lib", remind("e", 21 * HOUR)).await.unwrap();
+    assert!(store.in_quiet_hours("lib", 21 * HOUR));
+    assert!(due(21 * HOUR).await.is_empty());
+    assert_eq!(due(29 * HOUR).await, ["lib e"]);
+    store.set_quiet_hours("lib", None).await.unwrap();
+    assert!(!store.in_quiet_hours("lib", 21 * HOUR));
+    store.dismiss("lib", "e").await.unwrap();
+
+    // Every library has its own reminders and quiet times
+    store.set("other", remind("f", 21 * HOUR)).await.unwrap();
+    store.set_quiet_hours("lib", Some(hours)).await.unwrap();
+    assert!(store.list("lib", 0).iter().all(|r| r.article_id != "f"));
+    assert!(!store.in_quiet_hours("other", 21 * HOUR));
+    assert_eq!(due(21 * HOUR).await, ["other f"]);
+    store.set_quiet_hours("lib", None).await.unwrap();
+
+    // A deleted library's reminders are forgotten, and the others' stay
+    let took_out = String::from_utf8(store.take_out("other").unwrap()).unwrap();
+    assert!(took_out.contains("Article f"));
+    store.forget_library("other").await.unwrap();
+    assert!(store.list("other", 0).is_empty());
+    assert!(!String::from_utf8(store.take_out("other").unwrap())
+        .unwrap()
+        .contains("Article f"));
+
+    // Due reminders are still listed until they're dismissed or expire
+    store.dismiss("lib", "a").await.unwrap();
+    assert_eq!(ids(store.list("lib", 400)), ["b"]);
+    assert!(store.list("lib", 200 + REMINDER_LIFETIME_SECS).is_empty());
+
+    // Everything survives a restart
+    let reloaded = ReminderStore::load(path.to_str().unwrap()).unwrap();
+    assert_eq!(ids(reloaded.list("lib", 400)), ["b"]);
+    let _ = std::fs::remove_file(&path);
+}
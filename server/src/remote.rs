@@ -0,0 +1,328 @@
+//! Remote control of the player. Other devices send commands to the server, and the player, which
+//! runs in a browser, polls for them while remote control is on. The player also reports what it's
+//! doing, so remotes can show it. The `/mini` page is a remote that needs no WASM or JavaScript, so
+//! it works in watch browsers and on low-power devices.
+//!
+//! Every library has a channel of its own, so remotes only reach the players of the same account.
+
+use crate::{accounts::LibraryDir, util::escape_xml};
+use common::{PlayerStatus, RemoteCommand, RemoteCommands};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Extension, Form, Query},
+    http::StatusCode,
+    response::{Html, Redirect},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+/// Commands the player hasn't picked up within this long are dropped, so a player that turns remote
+/// control on later doesn't act on old commands
+const COMMAND_LIFETIME: Duration = Duration::from_secs(30);
+
+/// If the player hasn't reported in this long, remotes say it might not be listening
+const STATUS_STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// How often the /mini page reloads to show the player's status, in seconds
+const MINI_REFRESH_SECS: u32 = 10;
+
+/// A command, as it waits for the player
+struct SentCommand {
+    seq: u64,
+    command: RemoteCommand,
+    sent_at: Instant,
+}
+
+#[derive(Default)]
+struct RemoteState {
+    /// The number of the latest command sent
+    latest: u64,
+    /// The commands that haven't expired, oldest first
+    commands: VecDeque<SentCommand>,
+    /// What the player last reported, and when
+    status: Option<(PlayerStatus, Instant)>,
+}
+
+/// The channel between remotes and the player. This is kept in memory, since commands are only
+/// good for a few seconds anyway.
+#[derive(Clone, Default)]
+pub(crate) struct RemoteControl(Arc<Mutex<RemoteState>>);
+
+/// The channel of every library, by library directory
+#[derive(Clone, Default)]
+pub(crate) struct RemoteControls(Arc<Mutex<HashMap<String, RemoteControl>>>);
+
+impl RemoteControls {
+    /// Returns the channel of the library in the given directory
+    pub(crate) fn library(&self, library_dir: &str) -> RemoteControl {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(library_dir.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Drops the channel of the library in the given directory, along with any commands waiting in
+    /// it
+    pub(crate) fn forget_library(&self, library_dir: &str) {
+        self.0.lock().unwrap().remove(library_dir);
+    }
+}
+
+impl RemoteControl {
+    /// Sends the given command to the player
+    pub(crate) fn send(&self, command: RemoteCommand) {
+        let mut state = self.0.lock().unwrap();
+        state.latest += 1;
+        let seq = state.latest;
+        state.commands.push_back(SentCommand {
+            seq,
+            command,
+            sent_at: Instant::now(),
+        });
+    }
+
+    /// Returns the commands sent after the one with the given number. If no number is given, the
+    /// player just started listening, and there are no commands for it yet.
+    pub(crate) fn commands_after(&self, after: Option<u64>) -> RemoteCommands {
+        let mut state = self.0.lock().unwrap();
+        while state
+            .commands
+            .front()
+            .is_some_and(|c| c.sent_at.elapsed() > COMMAND_LIFETIME)
+        {
+            state.commands.pop_front();
+        }
+
+        let commands = match after {
+            Some(after) => state
+                .commands
+                .iter()
+                .filter(|c| c.seq > after)
+                .map(|c| c.command)
+                .collect(),
+            None => Vec::new(),
+        };
+        RemoteCommands {
+            latest: state.latest,
+            commands,
+        }
+    }
+
+    /// Records what the player is doing
+    pub(crate) fn report(&self, status: PlayerStatus) {
+        self.0.lock().unwrap().status = Some((status, Instant::now()));
+    }
+
+    /// Returns what the player last reported, if it's reported recently
+    pub(crate) fn status(&self) -> Option<PlayerStatus> {
+        self.0
+            .lock()
+            .unwrap()
+            .status
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() <= STATUS_STALE_AFTER)
+            .map(|(status, _)| status.clone())
+    }
+}
+
+/// The query string of a request for commands
+#[derive(Deserialize)]
+struct CommandParams {
+    after: Option<u64>,
+}
+
+/// The form the /mini page posts
+#[derive(Deserialize)]
+struct MiniForm {
+    command: RemoteCommand,
+}
+
+// Sets the /api/remote-command, /api/remote-commands, and /api/player-status routes, and the /mini
+// page
+pub(crate) fn setup(
+    router: Router,
+    audio_blob_dir: &str,
+    remote_controls: RemoteControls,
+) -> Router {
+    router
+        .nest(
+            "/api",
+            Router::new()
+                .route("/remote-command", post(send_command))
+                .route("/remote-commands", get(list_commands))
+                .route("/player-status", post(report_status))
+                .layer(Extension(remote_controls.clone()))
+                .layer(Extension(audio_blob_dir.to_string())),
+        )
+        .merge(
+            Router::new()
+                .route("/mini", get(mini_page).post(mini_command))
+                .layer(Extension(remote_controls))
+                .layer(Extension(audio_blob_dir.to_string())),
+        )
+}
+
+/// Sends a command to the library's player
+async fn send_command(
+    Json(command): Json<RemoteCommand>,
+    Extension(remote_controls): Extension<RemoteControls>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    remote_controls.library(&library_dir).send(command);
+    StatusCode::OK
+}
+
+/// Returns the commands sent to the library's player after the given one
+async fn list_commands(
+    Query(CommandParams { after }): Query<CommandParams>,
+    Extension(remote_controls): Extension<RemoteControls>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<RemoteCommands> {
+    Json(remote_controls.library(&library_dir).commands_after(after))
+}
+
+/// Records what the library's player is doing
+async fn report_status(
+    Json(status): Json<PlayerStatus>,
+    Extension(remote_controls): Extension<RemoteControls>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    remote_controls.library(&library_dir).report(status);
+    StatusCode::OK
+}
+
+/// Formats the given number of seconds as M:SS
+fn format_time(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Renders the /mini page, given what the player last reported
+fn render_mini(status: Option<&PlayerStatus>) -> String {
+    let now_playing = match status {
+        Some(PlayerStatus {
+            title: Some(title),
+            playing,
+            elapsed_secs,
+            duration_secs,
+        }) => format!(
+            "<p><b>{}</b></p><p>{} {} / {}</p>",
+            escape_xml(title),
+            if *playing { "Playing" } else { "Paused" },
+            format_time(*elapsed_secs),
+            format_time(*duration_secs),
+        ),
+        Some(_) => "<p><i>Nothing loaded</i></p>".to_string(),
+        None => "<p><i>The player isn't listening. Turn on remote control in the player.</i></p>"
+            .to_string(),
+    };
+
+    let buttons: String = [
+        ("previous", "⏮", "Previous article"),
+        ("jump-backward", "↩", "Jump back"),
+        ("toggle-play", "⏯", "Play or pause"),
+        ("jump-forward", "↪", "Jump forward"),
+        ("next", "⏭", "Next article"),
+    ]
+    .iter()
+    .map(|(command, symbol, label)| {
+        format!(
+            "<button name=\"command\" value=\"{command}\" aria-label=\"{label}\" \
+             title=\"{label}\">{symbol}</button>"
+        )
+    })
+    .collect();
+
+    format!(
+        "<!DOCTYPE html>\
+        <html lang=\"en\">\
+        <head>\
+        <meta charset=\"utf-8\">\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+        <meta http-equiv=\"refresh\" content=\"{MINI_REFRESH_SECS}; url=mini\">\
+        <title>ReadToMyShoe</title>\
+        <style>\
+        body {{ font-family: sans-serif; text-align: center; margin: 0.5em; }}\
+        button {{ font-size: 1.5em; min-width: 2em; margin: 0.1em; }}\
+        </style>\
+        </head>\
+        <body>\
+        {now_playing}\
+        <form method=\"post\" action=\"mini\">{buttons}</form>\
+        </body>\
+        </html>"
+    )
+}
+
+/// Shows the minimal remote of the library's player
+async fn mini_page(
+    Extension(remote_controls): Extension<RemoteControls>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Html<String> {
+    let remote_control = remote_controls.library(&library_dir);
+    Html(render_mini(remote_control.status().as_ref()))
+}
+
+/// Sends the command from the minimal remote, and goes back to it
+async fn mini_command(
+    Form(MiniForm { command }): Form<MiniForm>,
+    Extension(remote_controls): Extension<RemoteControls>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Redirect {
+    remote_controls.library(&library_dir).send(command);
+    // Relative, so it works under a subpath too
+    Redirect::to("mini")
+}
+
+#[test]
+fn remote_commands() {
+    let remote = RemoteControl::default();
+
+    // A player that just started listening gets nothing, but learns where to pick up from
+    remote.send(RemoteCommand::Play);
+    let first = remote.commands_after(None);
+    assert_eq!((first.latest, first.commands.len()), (1, 0));
+
+    remote.send(RemoteCommand::Next);
+    remote.send(RemoteCommand::Pause);
+    let next = remote.commands_after(Some(first.latest));
+    assert_eq!(next.latest, 3);
+    assert_eq!(next.commands, [RemoteCommand::Next, RemoteCommand::Pause]);
+    assert!(remote.commands_after(Some(3)).commands.is_empty());
+
+    // The page shows what the player reported
+    assert!(render_mini(remote.status().as_ref()).contains("isn't listening"));
+    remote.report(PlayerStatus {
+        title: Some("Fish & Chips".to_string()),
+        playing: true,
+        elapsed_secs: 75.0,
+        duration_secs: 600.0,
+    });
+    let page = render_mini(remote.status().as_ref());
+    assert!(page.contains("Fish &amp; Chips"));
+    assert!(page.contains("Playing 1:15 / 10:00"));
+    assert!(page.contains("value=\"toggle-play\""));
+
+    // Every library has its own channel
+    let remotes = RemoteControls::default();
+    remotes.library("users/kim").send(RemoteCommand::Play);
+    let kim = remotes.library("users/kim").commands_after(None).latest;
+    assert_eq!(remotes.library("users/lee").commands_after(None).latest, 0);
+    remotes.library("users/kim").send(RemoteCommand::Next);
+    assert_eq!(
+        remotes
+            .library("users/kim")
+            .commands_after(Some(kim))
+            .commands,
+        [RemoteCommand::Next]
+    );
+}
@@ -0,0 +1,191 @@
+//! Per-site scripts that transform an article's text after it's extracted and before it's
+//! synthesized, e.g., to strip a site's boilerplate or expand its abbreviations. They're
+//! [Rhai](https://rhai.rs) scripts in the `scripts` directory next to the config file, named after
+//! the site they're for, e.g., `scripts/example.com.rhai`. A script for a site runs on its
+//! subdomains too.
+//!
+//! A script sees the article's `url`, `title`, and `text`, with one paragraph per line, and changes
+//! the article by assigning to `title` and `text`, e.g.,
+//!
+//! ```rhai
+//! text.replace("Subscribe to our newsletter!", "");
+//! text.replace("approx.", "approximately");
+//! ```
+//!
+//! Scripts can't touch the filesystem or the network, and are stopped if they run too long. If a
+//! script fails, the article is converted as it was extracted.
+
+use crate::extract::is_on_site;
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Error as AnyError};
+use rhai::{Engine, Scope, AST};
+
+/// The most operations a script can run before it's stopped. A call like `text.replace(...)` is one
+/// operation however long the text is, so this is plenty, and stops scripts that loop forever.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A script and the site it's for
+struct Script {
+    site: String,
+    ast: AST,
+}
+
+/// The transform scripts, loaded when the server starts
+#[derive(Clone)]
+pub(crate) struct TransformScripts {
+    engine: Arc<Engine>,
+    scripts: Arc<Vec<Script>>,
+}
+
+impl Default for TransformScripts {
+    fn default() -> TransformScripts {
+        TransformScripts {
+            engine: Arc::new(new_engine()),
+            scripts: Arc::default(),
+        }
+    }
+}
+
+/// Returns an engine that runs scripts with a limit on how long they run
+fn new_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine
+}
+
+impl TransformScripts {
+    /// Loads the scripts in the given directory. If the directory doesn't exist, there are no
+    /// scripts. Scripts that don't compile are skipped.
+    pub(crate) fn load(dir: &Path) -> Result<TransformScripts, AnyError> {
+        if !dir.exists() {
+            return Ok(TransformScripts::default());
+        }
+        let engine = new_engine();
+
+        let mut scripts = Vec::new();
+        let entries =
+            std::fs::read_dir(dir).map_err(|e| anyhow!("Could not read scripts {:?}: {e}", dir))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "rhai") {
+                continue;
+            }
+            let Some(site) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            match engine.compile_file(path.clone()) {
+                Ok(ast) => {
+                    tracing::info!("Loaded transform script for {site}");
+                    scripts.push(Script { site, ast });
+                }
+                Err(e) => tracing::error!("Skipping transform script {:?}: {e}", path),
+            }
+        }
+        // A script for a whole site runs before the ones for its subdomains, so they get the last
+        // word
+        scripts.sort_by(|a, b| (a.site.len(), &a.site).cmp(&(b.site.len(), &b.site)));
+
+        Ok(TransformScripts {
+            engine: Arc::new(engine),
+            scripts: Arc::new(scripts),
+        })
+    }
+
+    /// Runs the scripts for the site of the given URL on the given article, changing its title and
+    /// text in place. A script that fails leaves them as they were.
+    pub(crate) fn transform(&self, url: &str, title: &mut String, text: &mut String) {
+        for script in self.scripts.iter().filter(|s| is_on_site(url, &s.site)) {
+            match self.run(script, url, title, text) {
+                Ok((new_title, new_text)) => {
+                    *title = new_title;
+                    *text = new_text;
+                }
+                Err(e) => {
+                    tracing::warn!("Transform script for {} failed on {url}: {e}", script.site)
+                }
+            }
+        }
+    }
+
+    /// Runs the given script on the given article, and returns its new title and text
+    fn run(
+        &self,
+        script: &Script,
+        url: &str,
+        title: &str,
+        text: &str,
+    ) -> Result<(String, String), AnyError> {
+        let mut scope = Scope::new();
+        scope.push_constant("url", url.to_string());
+        scope.push("title", title.to_string());
+        scope.push("text", text.to_string());
+        self.engine
+            .run_ast_with_scope(&mut scope, &script.ast)
+            .map_err(|e| anyhow!("{e}"))?;
+
+        let get = |name| {
+            scope
+                .get_value::<String>(name)
+                .ok_or_else(|| anyhow!("`{name}` isn't a string anymore"))
+        };
+        Ok((get("title")?, get("text")?))
+    }
+}
+
+#[test]
+fn transforming_articles() {
+    let dir = std::env::temp_dir().join(format!("rtms-scripts-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, contents: &str| std::fs::write(dir.join(name), contents).unwrap();
+
+    write(
+        "example.com.rhai",
+        r#"text.replace("Subscribe now!\n", ""); title = title.sub_string(0, title.index_of(" |"));"#,
+    );
+    write(
+        "news.example.com.rhai",
+        r#"text.replace("approx.", "approximately");"#,
+    );
+    write("loop.example.org.rhai", "loop { text += \".\"; }");
+    write("broken.example.org.rhai", "text = ");
+    write("types.example.net.rhai", "text = 42;");
+    let scripts = TransformScripts::load(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let transform = |url, title: &str, text: &str| {
+        let (mut title, mut text) = (title.to_string(), text.to_string());
+        scripts.transform(url, &mut title, &mut text);
+        (title, text)
+    };
+
+    // Scripts for a site run on its subdomains, the site's first
+    let (title, text) = transform(
+        "https://news.example.com/story",
+        "The Story | Example News",
+        "Subscribe now!\nIt took approx. a year.",
+    );
+    assert_eq!(title, "The Story");
+    assert_eq!(text, "It took approximately a year.");
+
+    // Other sites are left alone
+    let article = ("Title | Site".to_string(), "approx.".to_string());
+    assert_eq!(
+        transform("https://example.org/", "Title | Site", "approx."),
+        article
+    );
+
+    // Scripts that run too long, don't compile, or break the article don't change it
+    for url in [
+        "https://loop.example.org/",
+        "https://broken.example.org/",
+        "https://types.example.net/",
+    ] {
+        assert_eq!(transform(url, "Title | Site", "approx."), article);
+    }
+}
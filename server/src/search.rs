@@ -0,0 +1,411 @@
+//! Full-text search over the text of every article. The title and body of each article are indexed
+//! with tantivy when it's added. Search results come with snippets of the body around the matches,
+//! with character offsets, so the client can find the matched passages in the article.
+//!
+//! Every library has an index of its own, so a search only ever finds articles in the library of
+//! whoever's searching. The shared library's index is in the directory the server is configured
+//! with, and each account's is in a hidden directory in its library, so it goes when the account
+//! does.
+
+use crate::accounts::LibraryDir;
+use common::{SearchHit, SearchResults, SearchSnippet};
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Error as AnyError};
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use tantivy::{
+    collector::TopDocs,
+    directory::MmapDirectory,
+    doc,
+    query::QueryParser,
+    schema::{Field, Schema, Value, STORED, STRING, TEXT},
+    Index, IndexReader, IndexWriter, TantivyDocument, Term,
+};
+
+/// The memory the index writer may use, in bytes
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// The number of results returned when the client doesn't say
+const DEFAULT_LIMIT: usize = 20;
+
+/// The most results that can be asked for at once
+const MAX_LIMIT: usize = 100;
+
+/// The most snippets returned per article
+const MAX_SNIPPETS: usize = 3;
+
+/// The number of characters of context on either side of the matches in a snippet
+const SNIPPET_CONTEXT: usize = 80;
+
+/// The directory in an account's library that its index is in
+const ACCOUNT_INDEX_DIR: &str = ".search_index";
+
+/// The fields of an indexed article
+#[derive(Clone, Copy)]
+struct Fields {
+    id: Field,
+    title: Field,
+    body: Field,
+}
+
+/// The full-text index of one library
+struct LibraryIndex {
+    index: Index,
+    writer: Mutex<IndexWriter>,
+    reader: IndexReader,
+    fields: Fields,
+}
+
+impl LibraryIndex {
+    /// Opens the index in the given directory, creating it if it doesn't exist
+    fn open(dir: impl AsRef<Path>) -> Result<LibraryIndex, AnyError> {
+        let mut schema_builder = Schema::builder();
+        let fields = Fields {
+            id: schema_builder.add_text_field("id", STRING | STORED),
+            title: schema_builder.add_text_field("title", TEXT | STORED),
+            body: schema_builder.add_text_field("body", TEXT | STORED),
+        };
+        let schema = schema_builder.build();
+
+        std::fs::create_dir_all(&dir)?;
+        let index = Index::open_or_create(MmapDirectory::open(&dir)?, schema)?;
+        let writer = index.writer(WRITER_MEMORY)?;
+        let reader = index.reader()?;
+
+        Ok(LibraryIndex {
+            index,
+            writer: Mutex::new(writer),
+            reader,
+            fields,
+        })
+    }
+}
+
+struct SearchIndexInner {
+    /// The directory of the shared library
+    audio_blob_dir: String,
+    /// The shared library's index
+    shared: Arc<LibraryIndex>,
+    /// The indexes of the accounts' libraries that have been opened, by library directory
+    accounts: Mutex<HashMap<String, Arc<LibraryIndex>>>,
+}
+
+/// The full-text indexes of the libraries
+#[derive(Clone)]
+pub(crate) struct SearchIndex(Arc<SearchIndexInner>);
+
+impl SearchIndex {
+    /// Opens the index of the shared library, whose directory is given second, in the given
+    /// directory, creating it if it doesn't exist
+    pub(crate) fn open(
+        dir: impl AsRef<Path>,
+        audio_blob_dir: &str,
+    ) -> Result<SearchIndex, AnyError> {
+        Ok(SearchIndex(Arc::new(SearchIndexInner {
+            audio_blob_dir: audio_blob_dir.to_string(),
+            shared: Arc::new(LibraryIndex::open(dir)?),
+            accounts: Mutex::new(HashMap::new()),
+        })))
+    }
+
+    /// Returns the index of the library in the given directory, opening it if it isn't open yet
+    fn library(&self, library_dir: &str) -> Result<Arc<LibraryIndex>, AnyError> {
+        if library_dir == self.0.audio_blob_dir {
+            return Ok(self.0.shared.clone());
+        }
+
+        let mut accounts = self.0.accounts.lock().unwrap();
+        if let Some(index) = accounts.get(library_dir) {
+            return Ok(index.clone());
+        }
+        let index = Arc::new(LibraryIndex::open(
+            Path::new(library_dir).join(ACCOUNT_INDEX_DIR),
+        )?);
+        accounts.insert(library_dir.to_string(), index.clone());
+        Ok(index)
+    }
+
+    /// Closes the index of the library in the given directory, so the directory can be deleted.
+    /// It's opened again if it's used again.
+    pub(crate) fn close(&self, library_dir: &str) {
+        self.0.accounts.lock().unwrap().remove(library_dir);
+    }
+
+    /// Indexes the given article of the library in the given directory, replacing whatever was
+    /// indexed under its ID before
+    pub(crate) async fn add(
+        &self,
+        library_dir: &str,
+        id: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<(), AnyError> {
+        let inner = self.library(library_dir)?;
+        let fields = inner.fields;
+        let (id, title, body) = (id.to_string(), title.to_string(), body.to_string());
+
+        // Committing writes to disk, so don't do it on the async runtime
+        tokio::task::spawn_blocking(move || {
+            let mut writer = inner.writer.lock().unwrap();
+            writer.delete_term(Term::from_field_text(fields.id, &id));
+            writer.add_document(doc!(
+                fields.id => id,
+                fields.title => title,
+                fields.body => body,
+            ))?;
+            writer.commit()?;
+            inner.reader.reload().map_err(Into::into)
+        })
+        .await?
+    }
+
+    /// Removes the article with the given ID from the index of the library in the given directory
+    pub(crate) async fn remove(&self, library_dir: &str, id: &str) -> Result<(), AnyError> {
+        let inner = self.library(library_dir)?;
+        let id = id.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let mut writer = inner.writer.lock().unwrap();
+            writer.delete_term(Term::from_field_text(inner.fields.id, &id));
+            writer.commit()?;
+            inner.reader.reload().map_err(Into::into)
+        })
+        .await?
+    }
+
+    /// Returns the articles of the library in the given directory matching the given query, best
+    /// match first. The query is in tantivy's query syntax, e.g., `"exact phrase" +required
+    /// -excluded`. Malformed parts are ignored.
+    fn search(
+        &self,
+        library_dir: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, AnyError> {
+        let library = self.library(library_dir)?;
+        let LibraryIndex {
+            index,
+            reader,
+            fields,
+            ..
+        } = &*library;
+
+        let parser = QueryParser::for_index(index, vec![fields.title, fields.body]);
+        let (query, _) = parser.parse_query_lenient(query);
+
+        // Collect the words to highlight in the body. These are already normalized the way the
+        // body's tokens are.
+        let mut words = BTreeSet::new();
+        query.query_terms(&mut |term, _| {
+            if term.field() == fields.body {
+                if let Some(word) = term.value().as_str() {
+                    words.insert(word.to_string());
+                }
+            }
+        });
+
+        let searcher = reader.searcher();
+        let mut tokenizer = index.tokenizer_for_field(fields.body)?;
+        searcher
+            .search(&query, &TopDocs::with_limit(limit))?
+            .into_iter()
+            .map(|(_, addr)| {
+                let doc: TantivyDocument = searcher.doc(addr)?;
+                let get = |field| {
+                    doc.get_first(field)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                let body = get(fields.body);
+
+                // Find where the words occur in the body
+                let mut matches = Vec::new();
+                let mut tokens = tokenizer.token_stream(&body);
+                while tokens.advance() {
+                    let token = tokens.token();
+                    if words.contains(&token.text) {
+                        matches.push(token.offset_from..token.offset_to);
+                    }
+                }
+
+                Ok(SearchHit {
+                    id: get(fields.id),
+                    title: get(fields.title),
+                    snippets: make_snippets(&body, &matches),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Makes snippets of `body` around the given matches, which are byte ranges in ascending order.
+/// Nearby matches share a snippet. Snippets start and end between words where possible.
+fn make_snippets(body: &str, matches: &[Range<usize>]) -> Vec<SearchSnippet> {
+    // Work in characters, since that's what the offsets are in
+    let chars: Vec<char> = body.chars().collect();
+    let char_starts: Vec<usize> = body.char_indices().map(|(i, _)| i).collect();
+    let to_char = |byte: usize| char_starts.partition_point(|&b| b < byte);
+    let matches: Vec<(usize, usize)> = matches
+        .iter()
+        .map(|m| (to_char(m.start), to_char(m.end)))
+        .collect();
+
+    let mut snippets = Vec::new();
+    let mut i = 0;
+    while i < matches.len() && snippets.len() < MAX_SNIPPETS {
+        let (first_start, first_end) = matches[i];
+
+        // Take some context on either side, without cutting words in half
+        let mut start = first_start.saturating_sub(SNIPPET_CONTEXT);
+        while start > 0 && start < first_start && !chars[start - 1].is_whitespace() {
+            start += 1;
+        }
+        let mut end = (first_end + SNIPPET_CONTEXT).min(chars.len());
+        while end < chars.len() && end > first_end && !chars[end].is_whitespace() {
+            end -= 1;
+        }
+
+        // Every match that fits goes in this snippet
+        let in_snippet: Vec<(usize, usize)> = matches[i..]
+            .iter()
+            .take_while(|(_, match_end)| *match_end <= end)
+            .cloned()
+            .collect();
+        i += in_snippet.len();
+
+        snippets.push(SearchSnippet {
+            text: chars[start..end].iter().collect(),
+            start,
+            matches: in_snippet,
+        });
+    }
+
+    snippets
+}
+
+// Sets the /api/search route
+pub(crate) fn setup(router: Router, audio_blob_dir: &str, search_index: SearchIndex) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/search", get(search_endpoint))
+            .layer(Extension(search_index))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    /// The query
+    q: String,
+    /// The maximum number of results
+    limit: Option<usize>,
+}
+
+/// Searches the text of every article in the library
+async fn search_endpoint(
+    Query(SearchParams { q, limit }): Query<SearchParams>,
+    Extension(search_index): Extension<SearchIndex>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<SearchResults>, StatusCode> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    tokio::task::spawn_blocking(move || search_index.search(&library_dir, &q, limit))
+        .await
+        .map_err(|e| anyhow!("Search task failed: {e}"))
+        .and_then(|res| res)
+        .map(|hits| Json(SearchResults(hits)))
+        .map_err(|e| {
+            tracing::error!("Error searching: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[test]
+fn snippets_have_char_offsets() {
+    let filler = "lorem ipsum ".repeat(20);
+    let body = format!("Café crème is good. {filler}Another café.");
+
+    // Find "café" in both places, by byte offset
+    let first = body.find("Café").unwrap();
+    let second = body.rfind("café").unwrap();
+    let matches = [first..first + "Café".len(), second..second + "café".len()];
+    let snippets = make_snippets(&body, &matches);
+
+    // The matches are too far apart to share a snippet
+    assert_eq!(snippets.len(), 2);
+
+    // Offsets are in characters, and the snippet text lines up with them
+    let chars: Vec<char> = body.chars().collect();
+    for snippet in &snippets {
+        for &(start, end) in &snippet.matches {
+            let matched: String = chars[start..end].iter().collect();
+            assert_eq!(matched.to_lowercase(), "café");
+            let in_snippet: String = snippet.text.chars().skip(start - snippet.start).collect();
+            assert!(in_snippet.starts_with(&matched));
+        }
+    }
+    assert_eq!(snippets[0].start, 0);
+    assert!(snippets[1].text.ends_with("Another café."));
+}
+
+#[tokio::test]
+async fn index_and_search() {
+    let dir = std::env::temp_dir().join(format!("rtms-search-test-{}", std::process::id()));
+    let shared = dir.join("library").to_str().unwrap().to_string();
+    let index = SearchIndex::open(dir.join("index"), &shared).unwrap();
+    index
+        .add(
+            &shared,
+            "a",
+            "Shoes",
+            "A story about running shoes and laces.",
+        )
+        .await
+        .unwrap();
+    index
+        .add(&shared, "b", "Boats", "Sailing is mostly waiting for wind.")
+        .await
+        .unwrap();
+
+    let hits = index.search(&shared, "laces", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, "a");
+    assert_eq!(hits[0].snippets[0].matches, vec![(32, 37)]);
+
+    // Re-adding an article replaces it, and removing it takes it out
+    index
+        .add(&shared, "a", "Shoes", "Now it's about sandals.")
+        .await
+        .unwrap();
+    assert!(index.search(&shared, "laces", 10).unwrap().is_empty());
+    index.remove(&shared, "b").await.unwrap();
+    assert!(index.search(&shared, "wind", 10).unwrap().is_empty());
+
+    // An account's library is searched on its own, and an article of the same ID in it is another
+    // article
+    let account = dir.join("library/users/kim").to_str().unwrap().to_string();
+    index
+        .add(&account, "a", "Laces", "All about laces.")
+        .await
+        .unwrap();
+    assert_eq!(index.search(&account, "laces", 10).unwrap().len(), 1);
+    assert!(index.search(&shared, "laces", 10).unwrap().is_empty());
+    index.remove(&account, "a").await.unwrap();
+    assert_eq!(index.search(&shared, "sandals", 10).unwrap().len(), 1);
+    assert!(Path::new(&account).join(ACCOUNT_INDEX_DIR).exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,566 @@
+//! Links that share an article's audio with people who can't sign in, e.g., a friend. A link is
+//! `/shared/<id>.mp3?share=<share>&expires=<time>&sig=<sig>`, where the signature is an HMAC over the
+//! share, the article ID, and the expiry, under a key only the server knows. So a link can't be
+//! changed to point at another article or to last longer. Links stop working once they expire or
+//! are revoked, and the audio itself is never served at a permanent URL.
+//!
+//! A library can also share its collection, i.e., every article it has a link to, at
+//! `/shared/collection.json?share=<share>&expires=<time>&sig=<sig>`. That lists the links to the
+//! articles, so another server can subscribe to the collection and copy the articles it wants. See
+//! `federation.rs`.
+
+use crate::{accounts::LibraryDir, export::is_valid_id, json_store::JsonStore, util::now_secs};
+use common::{
+    CollectionShareSubmission, ShareRevocation, ShareSubmission, SharedCollection, SharedLink,
+};
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use anyhow::{anyhow, Error as AnyError};
+use axum::{
+    body::Body,
+    extract::{Extension, Path as UrlPath, Query},
+    http::{header, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use hmac::{Hmac, Mac};
+use openssl::rand::rand_bytes;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+/// The longest a link can last, in days
+const MAX_SHARE_DAYS: u32 = 30;
+
+/// The longest a link to a collection can last, in days. Subscribing servers keep using the link,
+/// so it's good for longer than a link to one article. The article links in it still expire.
+const MAX_COLLECTION_DAYS: u32 = 365;
+
+/// The file name of a link to a collection, in place of an article's
+const COLLECTION_FILENAME: &str = "collection.json";
+
+/// A link that's been made, as it's saved
+#[derive(Clone, Serialize, Deserialize)]
+struct Share {
+    id: String,
+    article_id: String,
+    title: String,
+    /// The library the article is in
+    library_dir: String,
+    /// When the link stops working, in seconds since the Unix epoch
+    expires: u64,
+    /// Whether this links to the library's collection. Then `title` is the collection's name, and
+    /// `article_id` is empty.
+    #[serde(default)]
+    collection: bool,
+}
+
+impl Share {
+    /// Returns the link, signed with the given key
+    fn to_link(&self, key: &[u8]) -> SharedLink {
+        let sig = sign(key, &self.id, &self.article_id, self.expires);
+        let filename = if self.collection {
+            COLLECTION_FILENAME.to_string()
+        } else {
+            format!("{}.mp3", urlencoding::encode(&self.article_id))
+        };
+        SharedLink {
+            id: self.id.clone(),
+            article_id: self.article_id.clone(),
+            title: self.title.clone(),
+            url: format!(
+                "/shared/{filename}?share={}&expires={}&sig={}",
+                self.id, self.expires, sig
+            ),
+            expires: self.expires,
+            collection: self.collection,
+        }
+    }
+}
+
+/// The links that have been made and not revoked, and the key they're signed with. The links are
+/// saved as a JSON list.
+#[derive(Clone)]
+pub(crate) struct ShareStore {
+    key: Arc<Vec<u8>>,
+    shares: JsonStore<Vec<Share>>,
+}
+
+/// Returns the HMAC that makes the given link valid, in URL-safe base64
+fn sign(key: &[u8], share_id: &str, article_id: &str, expires: u64) -> String {
+    base64::encode_config(
+        mac(key, share_id, article_id, expires)
+            .finalize()
+            .into_bytes(),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// Returns the HMAC of the given link, ready to be finalized or verified
+fn mac(key: &[u8], share_id: &str, article_id: &str, expires: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    // The fields are separated by newlines, which none of them can contain
+    mac.update(format!("{share_id}\n{article_id}\n{expires}").as_bytes());
+    mac
+}
+
+/// Saves the given signing key to the given file, which only this server's user can read, since
+/// anyone with the key can make links
+fn save_key(path: &Path, key: &[u8]) -> Result<(), std::io::Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(path)?
+        .write_all(base64::encode(key).as_bytes())
+}
+
+impl ShareStore {
+    /// Loads the links from the given file, and the signing key from the other given file. If the
+    /// key doesn't exist yet, it's generated. Deleting the key revokes every link.
+    pub(crate) fn load(path: &str, key_path: &str) -> Result<ShareStore, AnyError> {
+        let key_path = Path::new(key_path);
+        let key = if key_path.exists() {
+            let b64 = std::fs::read_to_string(key_path)
+                .map_err(|e| anyhow!("Could not read share key {:?}: {e}", key_path))?;
+            base64::decode(b64.trim())
+                .map_err(|e| anyhow!("Could not parse share key {:?}: {e}", key_path))?
+        } else {
+            tracing::info!("Generating a key for shared links at {:?}", key_path);
+            let mut key = vec![0u8; 32];
+            rand_bytes(&mut key)?;
+            save_key(key_path, &key)
+                .map_err(|e| anyhow!("Could not save share key {:?}: {e}", key_path))?;
+            key
+        };
+
+        Ok(ShareStore {
+            key: Arc::new(key),
+            shares: JsonStore::load(path, "shared links")?,
+        })
+    }
+
+    /// Runs the given function on the links, and saves them afterwards. Expired links are dropped.
+    async fn update<T>(&self, f: impl FnOnce(&mut Vec<Share>) -> T) -> Result<T, AnyError> {
+        self.shares
+            .update(|shares| {
+                let res = f(shares);
+                let now = now_secs();
+                shares.retain(|s| s.expires > now);
+                res
+            })
+            .await
+    }
+
+    /// Makes a link to the given article in the given library
+    async fn share(&self, sub: ShareSubmission, library_dir: &str) -> Result<SharedLink, AnyError> {
+        let days = sub.days.clamp(1, MAX_SHARE_DAYS);
+        self.add(sub.article_id, sub.title, library_dir, days, false)
+            .await
+    }
+
+    /// Makes a link to the collection of the given library
+    async fn share_collection(
+        &self,
+        sub: CollectionShareSubmission,
+        library_dir: &str,
+    ) -> Result<SharedLink, AnyError> {
+        let days = sub.days.clamp(1, MAX_COLLECTION_DAYS);
+        self.add(String::new(), sub.name, library_dir, days, true)
+            .await
+    }
+
+    /// Makes a link with a new ID that lasts the given number of days, and saves it
+    async fn add(
+        &self,
+        article_id: String,
+        title: String,
+        library_dir: &str,
+        days: u32,
+        collection: bool,
+    ) -> Result<SharedLink, AnyError> {
+        let mut id = [0u8; 16];
+        rand_bytes(&mut id)?;
+        let share = Share {
+            id: base64::encode_config(id, base64::URL_SAFE_NO_PAD),
+            article_id,
+            title,
+            library_dir: library_dir.to_string(),
+            expires: now_secs() + days as u64 * 24 * 60 * 60,
+            collection,
+        };
+        let link = share.to_link(&self.key);
+        self.update(|shares| shares.push(share)).await?;
+        Ok(link)
+    }
+
+    /// Returns the unexpired links to articles in the given library
+    fn list(&self, library_dir: &str) -> Vec<SharedLink> {
+        let now = now_secs();
+        self.shares.read(|shares| {
+            shares
+                .iter()
+                .filter(|s| s.library_dir == library_dir && s.expires > now)
+                .map(|s| s.to_link(&self.key))
+                .collect()
+        })
+    }
+
+    /// Revokes the link with the given ID, if it's to an article in the given library
+    async fn revoke(&self, id: &str, library_dir: &str) -> Result<(), AnyError> {
+        self.update(|shares| shares.retain(|s| !(s.id == id && s.library_dir == library_dir)))
+            .await
+    }
+
+    /// Returns every link to the given library, expired or not, as JSON for a takeout
+    pub(crate) fn take_out(&self, library_dir: &str) -> Result<Vec<u8>, AnyError> {
+        let links: Vec<SharedLink> = self.shares.read(|shares| {
+            shares
+                .iter()
+                .filter(|s| s.library_dir == library_dir)
+                .map(|s| s.to_link(&self.key))
+                .collect()
+        });
+        Ok(serde_json::to_vec_pretty(&links)?)
+    }
+
+    /// Revokes every link to the given library
+    pub(crate) async fn forget_library(&self, library_dir: &str) -> Result<(), AnyError> {
+        self.update(|shares| shares.retain(|s| s.library_dir != library_dir))
+            .await
+    }
+
+    /// Checks the given link to the given article, or to a collection if the article ID is empty,
+    /// and returns what it links to if it's good
+    fn verify_share(&self, article_id: &str, query: &ShareQuery) -> Option<Share> {
+        let sig = base64::decode_config(&query.sig, base64::URL_SAFE_NO_PAD).ok()?;
+        mac(&self.key, &query.share, article_id, query.expires)
+            .verify_slice(&sig)
+            .ok()?;
+        if query.expires <= now_secs() {
+            return None;
+        }
+
+        // The signature's good, but the link might've been revoked
+        self.shares.read(|shares| {
+            shares
+                .iter()
+                .find(|s| s.id == query.share && s.article_id == article_id)
+                .filter(|s| s.collection == article_id.is_empty())
+                .cloned()
+        })
+    }
+
+    /// Checks the given link, and returns the path of the audio it's for if it's good
+    fn verify(&self, article_id: &str, query: &ShareQuery) -> Option<PathBuf> {
+        if article_id.is_empty() {
+            return None;
+        }
+        let share = self.verify_share(article_id, query)?;
+        Some(
+            Path::new(&share.library_dir)
+                .join(article_id)
+                .with_extension("mp3"),
+        )
+    }
+
+    /// Checks the given link to a collection, and returns the collection if it's good
+    fn verify_collection(&self, query: &ShareQuery) -> Option<SharedCollection> {
+        let collection = self.verify_share("", query)?;
+        let mut articles: Vec<_> = self
+            .list(&collection.library_dir)
+            .into_iter()
+            .filter(|link| !link.collection)
+            .collect();
+        articles.sort_by_key(|link| std::cmp::Reverse(link.expires));
+        Some(SharedCollection {
+            name: collection.title,
+            articles,
+            expires: collection.expires,
+        })
+    }
+}
+
+// Sets the /shared/<id>.mp3, /shared/collection.json, /api/share-article, /api/share-collection,
+// /api/list-shares, and /api/revoke-share routes
+pub(crate) fn setup(router: Router, audio_blob_dir: &str, share_store: ShareStore) -> Router {
+    router
+        .merge(
+            Router::new()
+                .route("/shared/:filename", get(serve_shared))
+                .layer(Extension(share_store.clone())),
+        )
+        .nest(
+            "/api",
+            Router::new()
+                .route("/share-article", post(share_article))
+                .route("/share-collection", post(share_collection))
+                .route("/list-shares", get(list_shares))
+                .route("/revoke-share", post(revoke_share))
+                .layer(Extension(share_store))
+                .layer(Extension(audio_blob_dir.to_string())),
+        )
+}
+
+/// The query string of a shared link
+#[derive(Deserialize)]
+struct ShareQuery {
+    share: String,
+    expires: u64,
+    sig: String,
+}
+
+/// Serves the audio of a shared link, or the collection of a link to one, if the link is good
+async fn serve_shared(
+    UrlPath(filename): UrlPath<String>,
+    Query(query): Query<ShareQuery>,
+    Extension(share_store): Extension<ShareStore>,
+    req: Request<Body>,
+) -> Result<Response, StatusCode> {
+    if filename == COLLECTION_FILENAME {
+        let collection = share_store
+            .verify_collection(&query)
+            .ok_or(StatusCode::FORBIDDEN)?;
+        return Ok(Json(collection).into_response());
+    }
+
+    let article_id = filename.strip_suffix(".mp3").ok_or(StatusCode::NOT_FOUND)?;
+    let path = share_store
+        .verify(article_id, &query)
+        .ok_or(StatusCode::FORBIDDEN)?;
+
+    let mut resp = ServeFile::new(path)
+        .oneshot(req)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_response();
+    // Don't let anything keep the audio, so revoking the link cuts off access
+    resp.headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Ok(resp)
+}
+
+/// Makes a link to the given article
+async fn share_article(
+    Json(sub): Json<ShareSubmission>,
+    Extension(share_store): Extension<ShareStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<SharedLink>, (StatusCode, String)> {
+    let path = Path::new(&library_dir)
+        .join(&sub.article_id)
+        .with_extension("mp3");
+    if !is_valid_id(&sub.article_id) || !path.exists() {
+        return Err((StatusCode::NOT_FOUND, "No such article".to_string()));
+    }
+
+    share_store
+        .share(sub, &library_dir)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Error sharing article: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/// Makes a link to the library's collection
+async fn share_collection(
+    Json(sub): Json<CollectionShareSubmission>,
+    Extension(share_store): Extension<ShareStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<SharedLink>, (StatusCode, String)> {
+    share_store
+        .share_collection(sub, &library_dir)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Error sharing collection: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/// Lists the links to articles in the library
+async fn list_shares(
+    Extension(share_store): Extension<ShareStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Json<Vec<SharedLink>> {
+    Json(share_store.list(&library_dir))
+}
+
+/// Revokes the given link
+async fn revoke_share(
+    Json(ShareRevocation { id }): Json<ShareRevocation>,
+    Extension(share_store): Extension<ShareStore>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    match share_store.revoke(&id, &library_dir).await {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Error revoking shared link: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[tokio::test]
+async fn shared_links() {
+    let dir = std::env::temp_dir().join(format!("rtms-shares-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let shares_path = dir.join("shares.json");
+    let key_path = dir.join("share_key");
+    let store =
+        ShareStore::load(shares_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let link = store
+        .share(
+            ShareSubmission {
+                article_id: "fish-abc".to_string(),
+                title: "Fish".to_string(),
+                days: 365,
+            },
+            "library",
+        )
+        .await
+        .unwrap();
+    // Links can't last longer than the limit
+    assert!(link.expires <= now_secs() + MAX_SHARE_DAYS as u64 * 24 * 60 * 60);
+
+    let params: std::collections::HashMap<&str, &str> = link
+        .url
+        .split_once('?')
+        .unwrap()
+        .1
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .collect();
+    let query = ShareQuery {
+        share: params["share"].to_string(),
+        expires: params["expires"].parse().unwrap(),
+        sig: params["sig"].to_string(),
+    };
+    assert_eq!(
+        store.verify("fish-abc", &query),
+        Some(Path::new("library").join("fish-abc.mp3"))
+    );
+
+    // The link can't be pointed at another article or made to last longer
+    assert_eq!(store.verify("chips-def", &query), None);
+    let extended = ShareQuery {
+        expires: query.expires + 1,
+        ..query
+    };
+    assert_eq!(store.verify("fish-abc", &extended), None);
+    let query = ShareQuery {
+        expires: query.expires,
+        ..extended
+    };
+
+    // The link survives a restart, but not a new key
+    let store =
+        ShareStore::load(shares_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+    assert!(store.verify("fish-abc", &query).is_some());
+    assert_eq!(store.list("library").len(), 1);
+    assert!(store.list("other library").is_empty());
+    let rekeyed = ShareStore::load(
+        shares_path.to_str().unwrap(),
+        dir.join("other_key").to_str().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(rekeyed.verify("fish-abc", &query), None);
+
+    // Only the library the article's in can revoke the link
+    store.revoke(&link.id, "other library").await.unwrap();
+    assert!(store.verify("fish-abc", &query).is_some());
+    store.revoke(&link.id, "library").await.unwrap();
+    assert_eq!(store.verify("fish-abc", &query), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn shared_collections() {
+    let dir = std::env::temp_dir().join(format!("rtms-collection-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let store = ShareStore::load(
+        dir.join("shares.json").to_str().unwrap(),
+        dir.join("share_key").to_str().unwrap(),
+    )
+    .unwrap();
+    let query_of = |link: &SharedLink| {
+        let params: std::collections::HashMap<&str, &str> = link
+            .url
+            .split_once('?')
+            .unwrap()
+            .1
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .collect();
+        ShareQuery {
+            share: params["share"].to_string(),
+            expires: params["expires"].parse().unwrap(),
+            sig: params["sig"].to_string(),
+        }
+    };
+    let submission = |article_id: &str| ShareSubmission {
+        article_id: article_id.to_string(),
+        title: article_id.to_string(),
+        days: 7,
+    };
+
+    let fish = store
+        .share(submission("fish-abc"), "library")
+        .await
+        .unwrap();
+    store
+        .share(submission("other-xyz"), "other library")
+        .await
+        .unwrap();
+    let link = store
+        .share_collection(
+            CollectionShareSubmission {
+                name: "Weekend reads".to_string(),
+                days: 1000,
+            },
+            "library",
+        )
+        .await
+        .unwrap();
+    assert!(link.collection);
+    assert!(link.url.starts_with("/shared/collection.json?"));
+    assert!(link.expires <= now_secs() + MAX_COLLECTION_DAYS as u64 * 24 * 60 * 60);
+
+    // The collection lists the library's links to articles, and nothing else
+    let query = query_of(&link);
+    let collection = store.verify_collection(&query).unwrap();
+    assert_eq!(collection.name, "Weekend reads");
+    assert_eq!(collection.articles, vec![fish.clone()]);
+
+    // A link to an article isn't a link to a collection, or the other way around
+    assert!(store.verify_collection(&query_of(&fish)).is_none());
+    assert_eq!(store.verify("", &query), None);
+
+    // Revoking the link stops the listing, but not the links in it
+    store.revoke(&link.id, "library").await.unwrap();
+    assert!(store.verify_collection(&query).is_none());
+    assert!(store.verify("fish-abc", &query_of(&fish)).is_some());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
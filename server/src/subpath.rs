@@ -0,0 +1,187 @@
+//! Serving the app under a subpath, e.g., `https://example.com/rtms/`, behind a reverse proxy that
+//! passes the whole path through. `base_url` in the config says where the app is. Every route is
+//! nested under its path, and the `index.html` that's served is rewritten so the app loads its
+//! assets from there. The app reads its base path from `index.html`'s `<base>` tag, and the links
+//! the server makes itself are relative, or start with the base path.
+
+use std::{fs, path::Path};
+
+use anyhow::{bail, Error as AnyError};
+use axum::{extract::Extension, response::Redirect, routing::get, Router};
+use tower_http::add_extension::AddExtension;
+
+/// The path the app is served under, e.g., `/rtms`, without a trailing slash. It's empty if the app
+/// is served at the root.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct BasePath(pub(crate) String);
+
+impl BasePath {
+    /// Returns the base path of the given base URL, e.g., `/rtms` for `https://example.com/rtms/`.
+    /// The URL can also be just the path. `None` means the app is served at the root.
+    pub(crate) fn new(base_url: Option<&str>) -> Result<BasePath, AnyError> {
+        let Some(base_url) = base_url else {
+            return Ok(BasePath::default());
+        };
+        let path = match base_url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("", |i| &rest[i..]),
+            None => base_url,
+        };
+        if !path.is_empty() && !path.starts_with('/') {
+            bail!(
+                "base_url {base_url:?} should be a URL or a path, e.g., https://example.com/rtms/"
+            );
+        }
+        if path.contains(['?', '#', '*', ':', '%']) {
+            bail!("base_url {base_url:?} can't have a query, a fragment, or special characters");
+        }
+        if path
+            .split('/')
+            .any(|segment| segment == "." || segment == "..")
+        {
+            bail!("base_url {base_url:?} can't have . or .. in its path");
+        }
+        Ok(BasePath(path.trim_end_matches('/').to_string()))
+    }
+
+    /// Returns whether the app is served at the root
+    pub(crate) fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The URL the app is reached at, e.g., `https://example.com/rtms`, without a trailing slash. The
+/// feeds that podcast and calendar apps subscribe to link back to the server with it, rather than
+/// with whatever host the request claims to be for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BaseUrl(pub(crate) String);
+
+impl BaseUrl {
+    /// Returns the given base URL, if it's a whole URL rather than just a path. It's assumed to have
+    /// been checked by `BasePath::new`.
+    pub(crate) fn new(base_url: Option<&str>) -> Option<BaseUrl> {
+        let base_url = base_url?.trim_end_matches('/');
+        let (scheme, rest) = base_url.split_once("://")?;
+        let host = rest.split('/').next().unwrap_or_default();
+        if !matches!(scheme, "http" | "https") || host.is_empty() {
+            return None;
+        }
+        Some(BaseUrl(base_url.to_string()))
+    }
+}
+
+/// Returns the given `index.html` with its links to the app's assets and its `<base>` tag moved
+/// under the given base path. Trunk writes them as absolute paths, e.g., `/assets/rtms.js`.
+pub(crate) fn rewrite_index(index_html: &str, base_path: &BasePath) -> String {
+    if base_path.is_root() {
+        return index_html.to_string();
+    }
+    let base = &base_path.0;
+    index_html
+        .replace("<base href=\"/\"", &format!("<base href=\"{base}/\""))
+        .replace("\"/assets/", &format!("\"{base}/assets/"))
+        .replace("'/assets/", &format!("'{base}/assets/"))
+}
+
+/// Reads the `index.html` at the given path, rewritten for the given base path. If it can't be
+/// read, it's empty, and the asset service will say so when it's asked for.
+pub(crate) fn load_index(index_path: &Path, base_path: &BasePath) -> String {
+    let index_html = fs::read_to_string(index_path).unwrap_or_else(|e| {
+        tracing::warn!(
+            "Couldn't read {index_path:?} ({e}). The app's inline scripts will be blocked."
+        );
+        String::new()
+    });
+    rewrite_index(&index_html, base_path)
+}
+
+/// Nests the given app under the given base path. The base path is an extension of every request,
+/// for the handlers that link to the server by its full URL.
+pub(crate) fn setup(router: Router, base_path: BasePath) -> Router {
+    if base_path.is_root() {
+        return router.layer(Extension(base_path));
+    }
+
+    // Going to the base path without its trailing slash works, but the app's relative links don't
+    let slashed = format!("{}/", base_path.0);
+    let redirect = get(move || async move { Redirect::permanent(&slashed) });
+    Router::new()
+        .route(&base_path.0, redirect)
+        .nest(&base_path.0, AddExtension::new(router, base_path.clone()))
+}
+
+#[tokio::test]
+async fn subpath_routing() {
+    use axum::{
+        body::{Body, HttpBody},
+        http::{header, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    assert_eq!(BasePath::new(None).unwrap(), BasePath::default());
+    assert_eq!(
+        BasePath::new(Some("https://example.com/rtms/")).unwrap(),
+        BasePath("/rtms".to_string())
+    );
+    assert_eq!(
+        BasePath::new(Some("/apps/rtms")).unwrap(),
+        BasePath("/apps/rtms".to_string())
+    );
+    assert!(BasePath::new(Some("https://example.com"))
+        .unwrap()
+        .is_root());
+    assert!(BasePath::new(Some("rtms")).is_err());
+    assert!(BasePath::new(Some("/rtms/../admin")).is_err());
+    assert!(BasePath::new(Some("/rtms?x=1")).is_err());
+    assert_eq!(
+        BaseUrl::new(Some("https://example.com/rtms/")),
+        Some(BaseUrl("https://example.com/rtms".to_string()))
+    );
+    assert_eq!(
+        BaseUrl::new(Some("http://example.com")),
+        Some(BaseUrl("http://example.com".to_string()))
+    );
+    assert_eq!(BaseUrl::new(Some("/rtms")), None);
+    assert_eq!(BaseUrl::new(Some("ftp://example.com")), None);
+    assert_eq!(BaseUrl::new(None), None);
+
+    let base_path = BasePath::new(Some("/rtms")).unwrap();
+    let index = "<script type=\"module\">import init from '/assets/rtms.js';</script>\
+                 <link rel=\"manifest\" href=\"/assets/manifest.json\"><base href=\"/\" />";
+    assert_eq!(
+        rewrite_index(index, &base_path),
+        "<script type=\"module\">import init from '/rtms/assets/rtms.js';</script>\
+         <link rel=\"manifest\" href=\"/rtms/assets/manifest.json\"><base href=\"/rtms/\" />"
+    );
+    assert_eq!(rewrite_index(index, &BasePath::default()), index);
+
+    // The app's routes are under the base path, and see their own paths
+    let app = Router::new()
+        .route(
+            "/api/where",
+            get(|Extension(base): Extension<BasePath>| async move { base.0 }),
+        )
+        .fallback(get(|| async { "index" }));
+    let app = setup(app, base_path);
+    let get_path = |path: &str| {
+        let req = Request::get(path).body(Body::empty()).unwrap();
+        app.clone().oneshot(req)
+    };
+
+    let resp = get_path("/rtms/api/where").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().data().await.unwrap().unwrap();
+    assert_eq!(&body[..], b"/rtms");
+
+    for path in ["/rtms/", "/rtms/playlists/fish"] {
+        let resp = get_path(path).await.unwrap();
+        let body = resp.into_body().data().await.unwrap().unwrap();
+        assert_eq!(&body[..], b"index");
+    }
+
+    let resp = get_path("/rtms").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(resp.headers()[header::LOCATION], "/rtms/");
+
+    let resp = get_path("/api/where").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
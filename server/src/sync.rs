@@ -0,0 +1,596 @@
+//! Keeps the queue, playback positions, and playback speed in step between a listener's devices,
+//! e.g., adding articles on a laptop and listening on a phone. Each library has one copy, kept in
+//! `sync.json` in its directory. Devices send theirs, and get back the merge of it with the
+//! server's:
+//!
+//! * The queue is whichever was changed last
+//! * The position in each article is whichever is furthest along, unless the listener picked
+//!   another one over it after it was played
+//! * The playback speed is whichever was changed last
+//! * Each playlist is whichever was changed last. Deleted playlists are kept, marked deleted, so
+//!   the devices that still have them delete them too.
+//!
+//! The devices that sync are listed in `devices.json`, with when each last synced, so they can be
+//! renamed and revoked from any of them. Each position is marked with the device that got to it,
+//! to make sense of a position that jumps. A revoked device is turned away the next time it syncs,
+//! and signed out if accounts are on.
+
+use crate::{
+    accounts::{AccountStore, LibraryDir, SessionHash},
+    util::now_secs,
+};
+use common::{
+    DeviceCheckIn, DeviceInfo, DeviceRename, DeviceRevocation, SyncState, SyncedPlaylist,
+    SyncedPosition,
+};
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+/// The file, in a library's directory, that its sync state is kept in
+const SYNC_FILENAME: &str = "sync.json";
+
+/// The file, in a library's directory, that the devices that sync with it are listed in
+const DEVICES_FILENAME: &str = "devices.json";
+
+/// The longest a device's nickname can be, in characters
+const MAX_NICKNAME_CHARS: usize = 60;
+
+/// How many revoked devices are remembered. Only the first sync after a device is revoked needs
+/// it, so the oldest are forgotten.
+const MAX_REVOKED: usize = 100;
+
+/// Held while a sync state is read, merged, and saved, so two devices syncing at once don't lose
+/// each other's changes
+#[derive(Clone, Default)]
+struct SyncLock(Arc<Mutex<()>>);
+
+/// Returns the current time in milliseconds since the Unix epoch
+fn now_millis() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or_default()
+}
+
+/// Returns whether a device's change, made at `device_updated`, wins over the server's, made at
+/// `server_updated`. If the server has never had a change, any change the device has wins, even
+/// one from before syncing began, which has no time.
+fn device_wins(device_updated: f64, server_updated: f64, device_has_value: bool) -> bool {
+    device_updated > server_updated || (server_updated == 0.0 && device_has_value)
+}
+
+/// Merges the state a device sent into the server's, and returns the result. A change that wins
+/// without a time is given the time it's merged at, so the devices that sync after it take it on.
+fn merge(server: SyncState, device: SyncState) -> SyncState {
+    let (queue, queue_updated) = if device_wins(
+        device.queue_updated,
+        server.queue_updated,
+        !device.queue.is_empty(),
+    ) {
+        let updated = Some(device.queue_updated).filter(|&t| t > 0.0);
+        (device.queue, updated.unwrap_or_else(now_millis))
+    } else {
+        (server.queue, server.queue_updated)
+    };
+
+    let (playback_speed, speed_updated) = if device_wins(
+        device.speed_updated,
+        server.speed_updated,
+        device.playback_speed > 0.0,
+    ) {
+        let updated = Some(device.speed_updated).filter(|&t| t > 0.0);
+        (device.playback_speed, updated.unwrap_or_else(now_millis))
+    } else {
+        (server.playback_speed, server.speed_updated)
+    };
+
+    // Take the winning position in each article. The latest pick between positions is kept with
+    // whichever wins, so positions from before it keep losing. Only the articles in the queue are
+    // kept, so this doesn't grow forever.
+    let mut positions: HashMap<String, SyncedPosition> = HashMap::new();
+    for pos in server.positions.into_iter().chain(device.positions) {
+        match positions.get_mut(&pos.id) {
+            Some(other) if !pos.wins_over(other) => other.chosen = other.chosen.max(pos.chosen),
+            Some(other) => {
+                let chosen = other.chosen.max(pos.chosen);
+                *other = SyncedPosition { chosen, ..pos };
+            }
+            None => {
+                positions.insert(pos.id.clone(), pos);
+            }
+        }
+    }
+    let positions = queue
+        .iter()
+        .filter_map(|entry| positions.remove(&entry.id))
+        .collect();
+
+    // Take the latest change to each playlist, keeping them in the order they were first synced
+    let mut playlists: Vec<SyncedPlaylist> = Vec::new();
+    for playlist in server.playlists.into_iter().chain(device.playlists) {
+        match playlists.iter_mut().find(|p| p.id == playlist.id) {
+            Some(other) if playlist.updated > other.updated => *other = playlist,
+            Some(_) => (),
+            None => playlists.push(playlist),
+        }
+    }
+
+    SyncState {
+        queue,
+        queue_updated,
+        positions,
+        playback_speed,
+        speed_updated,
+        playlists,
+        device: None,
+    }
+}
+
+/// A device that syncs with a library, as it's saved
+#[derive(Serialize, Deserialize)]
+struct SavedDevice {
+    #[serde(flatten)]
+    info: DeviceInfo,
+    /// The hash of the session the device last synced in, if accounts are on, so revoking the
+    /// device signs it out
+    #[serde(default)]
+    session: Option<String>,
+}
+
+/// The devices that sync with a library
+#[derive(Default, Serialize, Deserialize)]
+struct Devices {
+    devices: Vec<SavedDevice>,
+    /// The IDs of the devices that were revoked, oldest first
+    #[serde(default)]
+    revoked: Vec<String>,
+}
+
+/// Returns the given nickname trimmed and cut down to size, or `None` if it's blank
+fn clean_nickname(nickname: &str) -> Option<String> {
+    let nickname: String = nickname.trim().chars().take(MAX_NICKNAME_CHARS).collect();
+    (!nickname.is_empty()).then_some(nickname)
+}
+
+impl Devices {
+    /// Loads the devices listed in the given library directory
+    fn load(library_dir: &str) -> Result<Devices, AnyError> {
+        let path = Path::new(library_dir).join(DEVICES_FILENAME);
+        if !path.exists() {
+            return Ok(Devices::default());
+        }
+        let json = fs::read(&path).map_err(|e| anyhow!("Could not read {:?}: {e}", path))?;
+        serde_json::from_slice(&json).map_err(|e| anyhow!("Could not parse {:?}: {e}", path))
+    }
+
+    /// Saves the devices in the given library directory
+    fn save(&self, library_dir: &str) -> Result<(), AnyError> {
+        let path = Path::new(library_dir).join(DEVICES_FILENAME);
+        fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .map_err(|e| anyhow!("Could not save {:?}: {e}", path))
+    }
+
+    /// Notes that the given device synced at the given time, in seconds since the Unix epoch, in
+    /// the given session. Returns false if the device was revoked.
+    fn check_in(&mut self, device: DeviceCheckIn, session: Option<String>, now: u64) -> bool {
+        if self.revoked.contains(&device.id) {
+            return false;
+        }
+        match self.devices.iter_mut().find(|d| d.info.id == device.id) {
+            Some(saved) => {
+                saved.info.last_seen = now;
+                saved.info.cached_bytes = device.cached_bytes;
+                saved.session = session;
+            }
+            None => self.devices.push(SavedDevice {
+                info: DeviceInfo {
+                    nickname: clean_nickname(&device.nickname).unwrap_or_else(|| "Device".into()),
+                    id: device.id,
+                    last_seen: now,
+                    cached_bytes: device.cached_bytes,
+                },
+                session,
+            }),
+        }
+        true
+    }
+
+    /// Renames the device with the given ID. Returns false if there's no such device.
+    fn rename(&mut self, id: &str, nickname: String) -> bool {
+        match self.devices.iter_mut().find(|d| d.info.id == id) {
+            Some(saved) => {
+                saved.info.nickname = nickname;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes the device with the given ID off the list, and turns it away the next time it syncs.
+    /// Returns the device, if it was listed.
+    fn revoke(&mut self, id: &str) -> Option<SavedDevice> {
+        let index = self.devices.iter().position(|d| d.info.id == id)?;
+        if !self.revoked.iter().any(|r| r == id) {
+            self.revoked.push(id.to_string());
+            if self.revoked.len() > MAX_REVOKED {
+                self.revoked.remove(0);
+            }
+        }
+        Some(self.devices.remove(index))
+    }
+}
+
+/// Returns the sync state saved in the given library directory, or an empty one if no device has
+/// synced yet
+pub(crate) fn load(library_dir: &str) -> Result<SyncState, AnyError> {
+    let path = Path::new(library_dir).join(SYNC_FILENAME);
+    if !path.exists() {
+        return Ok(SyncState::default());
+    }
+    let json = fs::read(&path).map_err(|e| anyhow!("Could not read {:?}: {e}", path))?;
+    serde_json::from_slice(&json).map_err(|e| anyhow!("Could not parse {:?}: {e}", path))
+}
+
+/// Merges the given state into the one saved in the given library directory, saves the result, and
+/// returns it
+fn sync_library(library_dir: &str, device: SyncState) -> Result<SyncState, AnyError> {
+    let path = Path::new(library_dir).join(SYNC_FILENAME);
+    let server = load(library_dir)?;
+
+    let merged = merge(server, device);
+    fs::write(&path, serde_json::to_vec_pretty(&merged)?)
+        .map_err(|e| anyhow!("Could not save {:?}: {e}", path))?;
+    Ok(merged)
+}
+
+/// Lists the given device as synced just now, and marks the positions the device sent that aren't
+/// marked yet as its own. Returns false if the device was revoked.
+fn check_in(
+    library_dir: &str,
+    device: &mut SyncState,
+    session: Option<String>,
+) -> Result<bool, AnyError> {
+    let Some(check_in) = device.device.take() else {
+        return Ok(true);
+    };
+    for pos in &mut device.positions {
+        pos.device.get_or_insert_with(|| check_in.id.clone());
+    }
+    let mut devices = Devices::load(library_dir)?;
+    let allowed = devices.check_in(check_in, session, now_secs());
+    if allowed {
+        devices.save(library_dir)?;
+    }
+    Ok(allowed)
+}
+
+// Sets the /api/sync, /api/list-devices, /api/rename-device, and /api/revoke-device routes
+pub(crate) fn setup(router: Router, audio_blob_dir: &str) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/sync", post(sync))
+            .route("/list-devices", get(list_devices))
+            .route("/rename-device", post(rename_device))
+            .route("/revoke-device", post(revoke_device))
+            .layer(Extension(SyncLock::default()))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Merges the device's state into the library's, and returns the result. A revoked device gets
+/// 410 Gone, so it knows to stop using its ID.
+async fn sync(
+    Json(mut device): Json<SyncState>,
+    Extension(lock): Extension<SyncLock>,
+    session: Option<Extension<SessionHash>>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<SyncState>, (StatusCode, String)> {
+    let _guard = lock.0.lock().unwrap();
+    let session = session.map(|Extension(SessionHash(hash))| hash);
+    let internal_error = |e: AnyError| {
+        tracing::error!("Error syncing: {e}");
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    };
+    if !check_in(&library_dir, &mut device, session).map_err(internal_error)? {
+        return Err((
+            StatusCode::GONE,
+            "This device was revoked. Sign in again to sync.".to_string(),
+        ));
+    }
+    sync_library(&library_dir, device)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// Lists the devices that sync with the library, the most recently seen first
+async fn list_devices(
+    Extension(lock): Extension<SyncLock>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<Json<Vec<DeviceInfo>>, StatusCode> {
+    let _guard = lock.0.lock().unwrap();
+    let devices = Devices::load(&library_dir).map_err(|e| {
+        tracing::error!("Error listing devices: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let mut infos: Vec<_> = devices.devices.into_iter().map(|d| d.info).collect();
+    infos.sort_by_key(|info| std::cmp::Reverse(info.last_seen));
+    Ok(Json(infos))
+}
+
+/// Gives a device a new nickname
+async fn rename_device(
+    Json(DeviceRename { id, nickname }): Json<DeviceRename>,
+    Extension(lock): Extension<SyncLock>,
+    LibraryDir(library_dir): LibraryDir,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let Some(nickname) = clean_nickname(&nickname) else {
+        return Err((StatusCode::BAD_REQUEST, "The nickname is blank".to_string()));
+    };
+    let _guard = lock.0.lock().unwrap();
+    let renamed = Devices::load(&library_dir).and_then(|mut devices| {
+        let found = devices.rename(&id, nickname);
+        devices.save(&library_dir)?;
+        Ok(found)
+    });
+    match renamed {
+        Ok(true) => Ok(StatusCode::OK),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "No such device".to_string())),
+        Err(e) => {
+            tracing::error!("Error renaming device: {e}");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    }
+}
+
+/// Revokes a device, so it can't sync anymore, and ends the session it last synced in
+async fn revoke_device(
+    Json(DeviceRevocation { id }): Json<DeviceRevocation>,
+    Extension(lock): Extension<SyncLock>,
+    accounts: Option<Extension<AccountStore>>,
+    LibraryDir(library_dir): LibraryDir,
+) -> StatusCode {
+    let revoked = {
+        let _guard = lock.0.lock().unwrap();
+        Devices::load(&library_dir).and_then(|mut devices| {
+            let revoked = devices.revoke(&id);
+            devices.save(&library_dir)?;
+            Ok(revoked)
+        })
+    };
+    let session = match revoked {
+        Ok(Some(device)) => device.session,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("Error revoking device: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+    if let (Some(Extension(accounts)), Some(session)) = (accounts, session) {
+        if let Err(e) = accounts.end_session(&session).await {
+            tracing::error!("Error ending the session of a revoked device: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    StatusCode::OK
+}
+
+#[test]
+fn merging_sync_states() {
+    use common::SyncedQueueEntry;
+
+    let entry = |id: &str| SyncedQueueEntry {
+        id: id.to_string(),
+        title: id.to_string(),
+        group: None,
+        publication: None,
+        author: None,
+        image: None,
+        reading_profile: None,
+    };
+    let playlist = |id: &str, ids: &[&str], updated: f64, deleted: bool| SyncedPlaylist {
+        id: id.to_string(),
+        name: id.to_string(),
+        entries: ids.iter().map(|id| entry(id)).collect(),
+        updated,
+        deleted,
+    };
+    let pos = |id: &str, elapsed: f64, finished: bool| SyncedPosition {
+        id: id.to_string(),
+        elapsed,
+        finished,
+        last_played: 0.0,
+        device: None,
+        chosen: 0.0,
+    };
+
+    let dir = std::env::temp_dir().join(format!("rtms-sync-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let dir = dir.to_str().unwrap();
+
+    // The first device to sync has its queue taken, and given a time, even though it has none
+    let laptop = SyncState {
+        queue: vec![entry("a"), entry("b")],
+        queue_updated: 0.0,
+        positions: vec![pos("a", 30.0, false), pos("b", 5.0, false)],
+        playback_speed: 1.5,
+        speed_updated: 100.0,
+        playlists: vec![playlist("commute", &["a"], 10.0, false)],
+        device: None,
+    };
+    let merged = sync_library(dir, laptop).unwrap();
+    assert_eq!(merged.queue, vec![entry("a"), entry("b")]);
+    assert!(merged.queue_updated > 0.0);
+    assert_eq!(merged.playback_speed, 1.5);
+
+    // A phone with an older queue takes the server's. Its positions are merged in, furthest first,
+    // and its newer speed wins.
+    let phone = SyncState {
+        queue: vec![entry("c")],
+        queue_updated: 1.0,
+        positions: vec![
+            pos("a", 10.0, false),
+            pos("b", 2.0, true),
+            pos("c", 50.0, false),
+        ],
+        playback_speed: 2.0,
+        speed_updated: 200.0,
+        playlists: vec![
+            playlist("commute", &["a", "c"], 20.0, false),
+            playlist("long-reads", &["b"], 5.0, false),
+        ],
+        device: None,
+    };
+    let merged = sync_library(dir, phone).unwrap();
+    assert_eq!(merged.queue, vec![entry("a"), entry("b")]);
+    assert_eq!(
+        merged.positions,
+        vec![pos("a", 30.0, false), pos("b", 2.0, true)]
+    );
+    assert_eq!(merged.playback_speed, 2.0);
+
+    // Each playlist is the latest change to it, including the ones only one device has
+    assert_eq!(
+        merged.playlists,
+        vec![
+            playlist("commute", &["a", "c"], 20.0, false),
+            playlist("long-reads", &["b"], 5.0, false),
+        ]
+    );
+
+    // A newer queue replaces the server's, and the positions in the articles taken out are dropped
+    let newer = SyncState {
+        queue: vec![entry("b")],
+        queue_updated: merged.queue_updated + 1.0,
+        ..SyncState::default()
+    };
+    let merged = sync_library(dir, newer.clone()).unwrap();
+    assert_eq!(merged.queue, newer.queue);
+    assert_eq!(merged.queue_updated, newer.queue_updated);
+    assert_eq!(merged.positions, vec![pos("b", 2.0, true)]);
+    assert_eq!(merged.playback_speed, 2.0);
+
+    // A position the listener picked wins over further ones played before they picked it, and the
+    // pick stays with the positions played after it
+    let picked = SyncState {
+        positions: vec![SyncedPosition {
+            last_played: 20.0,
+            chosen: 30.0,
+            ..pos("b", 1.0, false)
+        }],
+        ..SyncState::default()
+    };
+    let merged = sync_library(dir, picked).unwrap();
+    assert_eq!(merged.positions[0].elapsed, 1.0);
+    let later = SyncState {
+        positions: vec![SyncedPosition {
+            last_played: 40.0,
+            ..pos("b", 3.0, false)
+        }],
+        ..SyncState::default()
+    };
+    let merged = sync_library(dir, later).unwrap();
+    assert_eq!(
+        (merged.positions[0].elapsed, merged.positions[0].chosen),
+        (3.0, 30.0)
+    );
+    let stale = SyncState {
+        positions: vec![SyncedPosition {
+            last_played: 25.0,
+            ..pos("b", 50.0, true)
+        }],
+        ..SyncState::default()
+    };
+    let merged = sync_library(dir, stale).unwrap();
+    assert_eq!(merged.positions[0].elapsed, 3.0);
+
+    // Deleting a playlist is a change like any other, and older changes don't bring it back
+    let deletion = SyncState {
+        playlists: vec![playlist("commute", &[], 30.0, true)],
+        ..SyncState::default()
+    };
+    sync_library(dir, deletion).unwrap();
+    let stale = SyncState {
+        playlists: vec![playlist("commute", &["a"], 10.0, false)],
+        ..SyncState::default()
+    };
+    let merged = sync_library(dir, stale).unwrap();
+    assert_eq!(merged.playlists[0], playlist("commute", &[], 30.0, true));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn managing_devices() {
+    let dir = std::env::temp_dir().join(format!("rtms-devices-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let dir = dir.to_str().unwrap();
+
+    let device = |id: &str, nickname: &str, cached_bytes: u64| SyncState {
+        positions: vec![SyncedPosition {
+            id: "a".to_string(),
+            elapsed: 10.0,
+            finished: false,
+            last_played: 0.0,
+            device: None,
+            chosen: 0.0,
+        }],
+        device: Some(DeviceCheckIn {
+            id: id.to_string(),
+            nickname: nickname.to_string(),
+            cached_bytes,
+        }),
+        ..SyncState::default()
+    };
+
+    // Checking in lists the device, and marks its positions as its own
+    let mut phone = device("phone", "  Phone  ", 100);
+    assert!(check_in(dir, &mut phone, Some("hash".to_string())).unwrap());
+    assert!(phone.device.is_none());
+    assert_eq!(phone.positions[0].device.as_deref(), Some("phone"));
+
+    // A device's nickname is only taken the first time, so renaming it sticks
+    let mut devices = Devices::load(dir).unwrap();
+    assert!(devices.rename("phone", "Pocket".to_string()));
+    assert!(!devices.rename("tablet", "Tablet".to_string()));
+    devices.save(dir).unwrap();
+    assert!(check_in(dir, &mut device("phone", "Phone", 200), None).unwrap());
+    let devices = Devices::load(dir).unwrap();
+    assert_eq!(devices.devices.len(), 1);
+    assert_eq!(devices.devices[0].info.nickname, "Pocket");
+    assert_eq!(devices.devices[0].info.cached_bytes, 200);
+    assert!(devices.devices[0].info.last_seen > 0);
+    assert_eq!(clean_nickname(" \t"), None);
+
+    // A revoked device is taken off the list and turned away
+    let mut devices = Devices::load(dir).unwrap();
+    assert_eq!(
+        devices.revoke("phone").map(|d| d.info.id),
+        Some("phone".to_string())
+    );
+    assert!(devices.revoke("phone").is_none());
+    devices.save(dir).unwrap();
+    assert!(!check_in(dir, &mut device("phone", "Phone", 0), None).unwrap());
+    assert!(Devices::load(dir).unwrap().devices.is_empty());
+
+    // State without a device, e.g., from before devices were listed, syncs as before
+    let mut anonymous = SyncState::default();
+    assert!(check_in(dir, &mut anonymous, None).unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,136 @@
+//! Time-based one-time passwords (RFC 6238), for two-factor authentication. These are the 6-digit
+//! codes authenticator apps show, which change every 30 seconds. Secrets are given to the apps in
+//! base32, in an `otpauth://` URI, which most apps can take as a QR code or pasted in.
+
+use anyhow::{anyhow, Error as AnyError};
+use openssl::{hash::MessageDigest, pkey::PKey, rand::rand_bytes, sign::Signer};
+
+/// How long each code lasts, in seconds
+const STEP_SECS: u64 = 30;
+
+/// The number of digits in a code
+const DIGITS: u32 = 6;
+
+/// How many steps either side of the current one a code is accepted from, for clocks that are a
+/// little off
+const SKEW_STEPS: u64 = 1;
+
+/// The length of a secret, in bytes
+const SECRET_LEN: usize = 20;
+
+/// The RFC 4648 base32 alphabet
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Encodes the given bytes as unpadded base32
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let bits = buf.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+        let num_chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..num_chars {
+            let index = (bits >> (35 - i * 5)) & 0x1f;
+            out.push(BASE32_ALPHABET[index as usize] as char);
+        }
+    }
+    out
+}
+
+/// Decodes the given base32, ignoring case, spaces, and padding
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut bits, mut num_bits) = (0u64, 0);
+    for c in s.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())?;
+        bits = bits << 5 | value as u64;
+        num_bits += 5;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            out.push((bits >> num_bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Makes a new random secret, in base32
+pub(crate) fn new_secret() -> Result<String, AnyError> {
+    let mut secret = [0u8; SECRET_LEN];
+    rand_bytes(&mut secret)?;
+    Ok(base32_encode(&secret))
+}
+
+/// Returns the URI that gives the given secret, for the given account, to an authenticator app
+pub(crate) fn provisioning_uri(secret: &str, account: &str) -> String {
+    let label = urlencoding::encode(account);
+    format!("otpauth://totp/ReadToMyShoe:{label}?secret={secret}&issuer=ReadToMyShoe")
+}
+
+/// Returns the code for the given secret at the given step
+fn code_at(secret: &[u8], step: u64) -> Result<u32, AnyError> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(&step.to_be_bytes())?;
+    let mac = signer.sign_to_vec()?;
+
+    // Dynamic truncation, from RFC 4226
+    let offset = (mac[mac.len() - 1] & 0xf) as usize;
+    let truncated = u32::from_be_bytes(mac[offset..offset + 4].try_into()?) & 0x7fff_ffff;
+    Ok(truncated % 10u32.pow(DIGITS))
+}
+
+/// Checks the given code against the given base32 secret at the given time, in seconds since the
+/// Unix epoch. If it's good, returns the step it's from, so the caller can refuse it if it's used
+/// again.
+pub(crate) fn verify(secret: &str, code: &str, now: u64) -> Result<Option<u64>, AnyError> {
+    let secret = base32_decode(secret).ok_or_else(|| anyhow!("The TOTP secret isn't base32"))?;
+    let code = code.trim();
+    let Ok(code) = code.parse::<u32>() else {
+        return Ok(None);
+    };
+
+    let current = now / STEP_SECS;
+    for step in current.saturating_sub(SKEW_STEPS)..=current + SKEW_STEPS {
+        if code_at(&secret, step)? == code {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the code for the given base32 secret at the given time, as an authenticator app would
+#[cfg(test)]
+pub(crate) fn code(secret: &str, now: u64) -> String {
+    let secret = base32_decode(secret).unwrap();
+    format!("{:06}", code_at(&secret, now / STEP_SECS).unwrap())
+}
+
+#[test]
+fn totp_codes() {
+    // The SHA-1 test vectors from RFC 6238, cut down to 6 digits
+    let secret = b"12345678901234567890";
+    assert_eq!(code_at(secret, 59 / STEP_SECS).unwrap(), 287082);
+    assert_eq!(code_at(secret, 1111111109 / STEP_SECS).unwrap(), 81804);
+    assert_eq!(code_at(secret, 2000000000 / STEP_SECS).unwrap(), 279037);
+
+    let encoded = base32_encode(secret);
+    assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    assert_eq!(base32_decode(&encoded.to_lowercase()).unwrap(), secret);
+    assert_eq!(base32_encode(b"f"), "MY");
+    assert_eq!(base32_decode("MY======").unwrap(), b"f");
+
+    // Codes from the step before and after are good too, and nothing further off is
+    assert_eq!(
+        verify(&encoded, "081804", 1111111109).unwrap(),
+        Some(37037036)
+    );
+    assert_eq!(
+        verify(&encoded, "081804", 1111111109 + 30).unwrap(),
+        Some(37037036)
+    );
+    assert_eq!(verify(&encoded, "081804", 1111111109 + 90).unwrap(), None);
+    assert_eq!(verify(&encoded, "not a code", 1111111109).unwrap(), None);
+    assert_eq!(code(&encoded, 1111111109), "081804");
+}
@@ -0,0 +1,172 @@
+//! Keeps the text of every article along with when each of its paragraphs is spoken, so the client
+//! can search an article's text and seek its audio to the matches. The transcript is saved next to
+//! the article's audio as `ARTICLEID.transcript.json`.
+
+use crate::accounts::LibraryDir;
+use common::{ArticleTranscript, Timepoint, LOUDNESS_STEP_SECS};
+use tts_pipeline::ssml::is_heading;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error as AnyError};
+use axum::{
+    extract::{Extension, Path as UrlPath},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+
+/// Returns the path of the transcript file of the given article
+pub(crate) fn path(audio_blob_dir: &str, id: &str) -> PathBuf {
+    Path::new(audio_blob_dir).join(format!("{id}.transcript.json"))
+}
+
+/// Makes the transcript of the given spoken text. The timepoints are byte offsets into `text`, and
+/// the times at which they're spoken. Paragraphs that look like headings are marked as such, so the
+/// client can skip between sections.
+pub(crate) fn new(
+    text: &str,
+    timepoints: &[(usize, f64)],
+    duration_secs: f64,
+) -> ArticleTranscript {
+    // The client works in characters, so convert the offsets
+    let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let timepoints = timepoints
+        .iter()
+        .map(|&(byte_offset, secs)| {
+            let paragraph = text
+                .get(byte_offset..)
+                .and_then(|rest| rest.split('\n').next())
+                .unwrap_or_default();
+            Timepoint {
+                offset: char_starts.partition_point(|&b| b < byte_offset),
+                secs,
+                heading: is_heading(paragraph.trim()),
+            }
+        })
+        .collect();
+
+    ArticleTranscript {
+        text: text.to_string(),
+        timepoints,
+        duration_secs,
+        loudness: Vec::new(),
+    }
+}
+
+/// Appends the transcript of audio that was appended to the article's audio
+pub(crate) fn append(transcript: &mut ArticleTranscript, rest: ArticleTranscript) {
+    let offset = transcript.text.chars().count();
+    let secs = transcript.duration_secs;
+    transcript
+        .timepoints
+        .extend(rest.timepoints.into_iter().map(|tp| Timepoint {
+            offset: offset + tp.offset,
+            secs: secs + tp.secs,
+            ..tp
+        }));
+    transcript.text.push_str(&rest.text);
+    // The loudness levels are at fixed steps, so the existing levels have to cover exactly the
+    // existing audio for the appended ones to line up
+    if !rest.loudness.is_empty() {
+        let steps = (transcript.duration_secs / LOUDNESS_STEP_SECS).round() as usize;
+        transcript.loudness.resize(steps, 0);
+        transcript.loudness.extend(rest.loudness);
+    }
+    transcript.duration_secs += rest.duration_secs;
+}
+
+/// Saves the transcript of the given article, replacing any existing one
+pub(crate) fn save(
+    audio_blob_dir: &str,
+    id: &str,
+    transcript: &ArticleTranscript,
+) -> Result<(), AnyError> {
+    let json = serde_json::to_vec(transcript)?;
+    std::fs::write(path(audio_blob_dir, id), json).map_err(Into::into)
+}
+
+/// Loads the transcript of the given article
+pub(crate) fn load(audio_blob_dir: &str, id: &str) -> Result<ArticleTranscript, AnyError> {
+    let json = std::fs::read(path(audio_blob_dir, id))
+        .map_err(|_| anyhow!("Article {id} has no transcript"))?;
+    serde_json::from_slice(&json).map_err(Into::into)
+}
+
+/// Deletes the transcript of the given article, if there is one
+pub(crate) fn remove(audio_blob_dir: &str, id: &str) -> Result<(), AnyError> {
+    let p = path(audio_blob_dir, id);
+    if p.exists() {
+        std::fs::remove_file(p)?;
+    }
+    Ok(())
+}
+
+// Sets the /api/article-transcript route
+pub(crate) fn setup(router: Router, audio_blob_dir: &str) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/article-transcript/:id", get(transcript_endpoint))
+            .layer(Extension(audio_blob_dir.to_string())),
+    )
+}
+
+/// Returns the transcript of the given article. Articles converted before transcripts were kept
+/// don't have one.
+async fn transcript_endpoint(
+    UrlPath(id): UrlPath<String>,
+    LibraryDir(audio_blob_dir): LibraryDir,
+) -> Result<Json<ArticleTranscript>, StatusCode> {
+    // Don't let the ID point outside the audio blob directory
+    if id.contains(['/', '\\']) || id.starts_with('.') {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    load(&audio_blob_dir, &id).map(Json).map_err(|e| {
+        tracing::debug!("{e}");
+        StatusCode::NOT_FOUND
+    })
+}
+
+#[test]
+fn transcript_offsets() {
+    // Byte offsets become character offsets
+    let text = "Café\nÜber alles.";
+    let mut transcript = new(text, &[(0, 0.0), (6, 1.5)], 3.0);
+    assert_eq!(
+        transcript.timepoints,
+        vec![
+            Timepoint {
+                offset: 0,
+                secs: 0.0,
+                heading: true,
+            },
+            Timepoint {
+                offset: 5,
+                secs: 1.5,
+                heading: false,
+            },
+        ]
+    );
+
+    // Appended audio comes after the existing text and audio, and so does its loudness
+    transcript.loudness = vec![200, 200, 0, 200, 200];
+    let mut rest = new("\nNoch mehr", &[(1, 0.5)], 2.0);
+    rest.loudness = vec![0, 150, 150, 0];
+    append(&mut transcript, rest);
+    assert_eq!(
+        transcript.loudness,
+        [200, 200, 0, 200, 200, 0, 0, 150, 150, 0]
+    );
+    assert_eq!(transcript.text, "Café\nÜber alles.\nNoch mehr");
+    assert_eq!(
+        transcript.timepoints[2],
+        Timepoint {
+            offset: 17,
+            secs: 3.5,
+            heading: true,
+        }
+    );
+    assert_eq!(transcript.duration_secs, 5.0);
+}
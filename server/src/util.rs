@@ -1,7 +1,6 @@
-use common::{ArticleMetadata, ArticleTextSubmission};
+use common::{ArticleGroup, ArticleMetadata, ArticleTextSubmission, ReadingProfile};
 
 use std::{
-    fs::DirEntry,
     path::Path,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -10,7 +9,7 @@ use anyhow::{bail, Error as AnyError};
 use blake2::{Blake2s256, Digest};
 use byteorder::{BigEndian, ByteOrder};
 use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
-use id3::{Tag, TagLike, Version};
+use id3::{frame::ExtendedText, Tag, TagLike, Version};
 
 /// Filenames in `audio_blobs` are of the form `TITLE-HASH.mp3`. This is maximum number of bytes
 /// allowed in `TITLE`. This MUST be less than 256.
@@ -20,6 +19,45 @@ const FILENAME_TITLE_MAXLEN: usize = 20;
 /// BEFORE it is encoded in zbase32.
 const ARTICLE_HASH_BITLEN: u64 = 128;
 
+/// The ID3 frame that holds an article's author. It's the "Lyricist/Text writer" frame.
+const AUTHOR_FRAME_ID: &str = "TEXT";
+
+/// The ID3 frame that holds an article's publication. It's the "Publisher" frame.
+const PUBLICATION_FRAME_ID: &str = "TPUB";
+
+/// The description of the ID3 user-defined text frame that holds an article's group ID
+const GROUP_ID_FRAME_DESC: &str = "ReadToMyShoe Group";
+
+/// The description of the ID3 user-defined text frame that holds an article's detected language
+const LANGUAGE_FRAME_DESC: &str = "ReadToMyShoe Language";
+
+/// The description of the ID3 user-defined text frame that holds an article's complexity
+const COMPLEXITY_FRAME_DESC: &str = "ReadToMyShoe Complexity";
+
+/// The description of the ID3 user-defined text frame that holds the title of the feed an article
+/// was added from
+const FEED_FRAME_DESC: &str = "ReadToMyShoe Feed";
+
+/// The description of the ID3 user-defined text frame that holds the name of the voice an article
+/// was read in
+const VOICE_FRAME_DESC: &str = "ReadToMyShoe Voice";
+
+/// The description of the ID3 user-defined text frame that holds where an article's text came from
+const SOURCE_TYPE_FRAME_DESC: &str = "ReadToMyShoe Source";
+
+/// The description of the ID3 user-defined text frame that holds the path of an article's lead
+/// image
+const IMAGE_FRAME_DESC: &str = "ReadToMyShoe Image";
+
+/// The description of the ID3 user-defined text frame that holds the listener's rating of an article
+const RATING_FRAME_DESC: &str = "ReadToMyShoe Rating";
+
+/// The description of the ID3 user-defined text frame that holds the hash of an article's words
+const TEXT_HASH_FRAME_DESC: &str = "ReadToMyShoe Text Hash";
+
+/// The length of a text hash, in bits
+const TEXT_HASH_BITLEN: u64 = 128;
+
 /// Used in `truncate_to_bytes` to specify the byte encoding of the string to be truncated
 pub(crate) enum StrEncoding {
     Utf8,
@@ -55,7 +93,7 @@ pub(crate) fn truncate_to_bytes(string: &str, max_len: usize, encoding: StrEncod
 }
 
 /// Computes the zbase32 encoded hash of the given article. The output length is ARTICLE_HASH_LEN.
-fn hash_article(ArticleTextSubmission { title, body }: &ArticleTextSubmission) -> String {
+fn hash_article(ArticleTextSubmission { title, body, .. }: &ArticleTextSubmission) -> String {
     // We will compute H(title_len || title || body)
     let mut h = Blake2s256::default();
 
@@ -73,11 +111,25 @@ fn hash_article(ArticleTextSubmission { title, body }: &ArticleTextSubmission) -
     zbase32::encode(&digest, ARTICLE_HASH_BITLEN)
 }
 
+/// Computes the zbase32 encoded hash of the words of the given article text. Case, punctuation, and
+/// spacing are ignored, so the same article extracted from two versions of a page hashes the same.
+pub fn hash_text(body: &str) -> String {
+    let mut h = Blake2s256::default();
+    for word in body
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        h.update(word.to_lowercase());
+        h.update(" ");
+    }
+    zbase32::encode(&h.finalize(), TEXT_HASH_BITLEN)
+}
+
 /// Derives the unique ID of this article. It's of the form SHORTTITLE-HASH.mp3
 pub fn derive_article_id(article: &ArticleTextSubmission) -> String {
     let truncated_title =
         truncate_to_bytes(&article.title, FILENAME_TITLE_MAXLEN, StrEncoding::Utf8);
-    let hash = hash_article(&article);
+    let hash = hash_article(article);
     format!("{truncated_title}-{hash}.mp3")
 }
 
@@ -86,6 +138,19 @@ pub fn derive_article_id(article: &ArticleTextSubmission) -> String {
 ///     url -> Artist
 ///     title -> Title
 ///     date fetched  -> Recording Time
+///     author -> Lyricist/Text writer
+///     publication -> Publisher
+///     group title -> Album
+///     group part / parts -> Track / Total Tracks
+///     group ID -> User-defined text "ReadToMyShoe Group"
+///     language -> User-defined text "ReadToMyShoe Language"
+///     complexity -> User-defined text "ReadToMyShoe Complexity"
+///     feed -> User-defined text "ReadToMyShoe Feed"
+///     voice -> User-defined text "ReadToMyShoe Voice"
+///     source type -> User-defined text "ReadToMyShoe Source"
+///     image -> User-defined text "ReadToMyShoe Image"
+///     rating -> User-defined text "ReadToMyShoe Rating"
+///     text hash -> User-defined text "ReadToMyShoe Text Hash"
 pub fn save_metadata(meta: &ArticleMetadata, audio_blob_dir: &str) -> Result<(), AnyError> {
     let savepath = Path::new(&audio_blob_dir)
         .join(&meta.id)
@@ -119,6 +184,87 @@ pub fn save_metadata(meta: &ArticleMetadata, audio_blob_dir: &str) -> Result<(),
         tag.set_artist(url);
     }
 
+    // Set the byline
+    if let Some(author) = &meta.author {
+        tag.set_text(AUTHOR_FRAME_ID, author);
+    }
+    if let Some(publication) = &meta.publication {
+        tag.set_text(PUBLICATION_FRAME_ID, publication);
+    }
+
+    // If the article is part of a group, record the group as an album, with the part as the track
+    if let Some(group) = &meta.group {
+        tag.set_album(&group.title);
+        tag.set_track(group.part);
+        tag.set_total_tracks(group.parts);
+        tag.add_frame(ExtendedText {
+            description: GROUP_ID_FRAME_DESC.to_string(),
+            value: group.id.clone(),
+        });
+    }
+
+    // Record what the text is like. There's no standard frame for either of these.
+    if let Some(profile) = &meta.reading_profile {
+        if let Some(language) = &profile.language {
+            tag.add_frame(ExtendedText {
+                description: LANGUAGE_FRAME_DESC.to_string(),
+                value: language.clone(),
+            });
+        }
+        tag.add_frame(ExtendedText {
+            description: COMPLEXITY_FRAME_DESC.to_string(),
+            value: profile.complexity.to_string(),
+        });
+    }
+
+    // Record the feed the article came from, if any
+    if let Some(feed) = &meta.feed {
+        tag.add_frame(ExtendedText {
+            description: FEED_FRAME_DESC.to_string(),
+            value: feed.clone(),
+        });
+    }
+
+    // Record the voice the article was read in
+    if let Some(voice) = &meta.voice {
+        tag.add_frame(ExtendedText {
+            description: VOICE_FRAME_DESC.to_string(),
+            value: voice.clone(),
+        });
+    }
+
+    // Record where the text came from
+    if let Some(source_type) = &meta.source_type {
+        tag.add_frame(ExtendedText {
+            description: SOURCE_TYPE_FRAME_DESC.to_string(),
+            value: source_type.as_str().to_string(),
+        });
+    }
+
+    // Record where the lead image is kept
+    if let Some(image) = &meta.image {
+        tag.add_frame(ExtendedText {
+            description: IMAGE_FRAME_DESC.to_string(),
+            value: image.clone(),
+        });
+    }
+
+    // Record the listener's rating
+    if let Some(rating) = &meta.rating {
+        tag.add_frame(ExtendedText {
+            description: RATING_FRAME_DESC.to_string(),
+            value: rating.as_str().to_string(),
+        });
+    }
+
+    // Record the hash of the words, for recognizing the article if it's added again
+    if let Some(text_hash) = &meta.text_hash {
+        tag.add_frame(ExtendedText {
+            description: TEXT_HASH_FRAME_DESC.to_string(),
+            value: text_hash.clone(),
+        });
+    }
+
     // Now write
     tag.write_to_path(savepath, Version::Id3v24)
         .map_err(Into::into)
@@ -129,12 +275,21 @@ pub fn save_metadata(meta: &ArticleMetadata, audio_blob_dir: &str) -> Result<(),
 ///     url <- Artist
 ///     title <- Title
 ///     date fetched  <- Recording Time (or else Unix last modified time)
-pub fn get_metadata(entry: &DirEntry) -> Result<ArticleMetadata, AnyError> {
-    let path = entry.path();
-
+///     author <- Lyricist/Text writer
+///     publication <- Publisher
+///     group <- Album, Track, Total Tracks, and User-defined text "ReadToMyShoe Group"
+///     reading profile <- User-defined texts "ReadToMyShoe Language" and "ReadToMyShoe Complexity"
+///     feed <- User-defined text "ReadToMyShoe Feed"
+///     voice <- User-defined text "ReadToMyShoe Voice"
+///     source type <- User-defined text "ReadToMyShoe Source"
+///     image <- User-defined text "ReadToMyShoe Image"
+///     rating <- User-defined text "ReadToMyShoe Rating"
+///     text hash <- User-defined text "ReadToMyShoe Text Hash"
+pub fn get_metadata(path: &Path) -> Result<ArticleMetadata, AnyError> {
     // The `last_modified_timestamp` is a backup in case the Recording Time isn't set
     let last_modified_timestamp: Option<u64> = {
-        let time_modified: Option<SystemTime> = entry.metadata().and_then(|m| m.modified()).ok();
+        let time_modified: Option<SystemTime> =
+            std::fs::metadata(path).and_then(|m| m.modified()).ok();
         // Convert the time to seconds since epoch
         time_modified
             .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
@@ -152,7 +307,19 @@ pub fn get_metadata(entry: &DirEntry) -> Result<ArticleMetadata, AnyError> {
         title: id.clone(),
         id,
         source_url: None,
+        author: None,
+        publication: None,
         datetime_added: last_modified_timestamp,
+        incomplete: false,
+        duration_secs: None,
+        group: None,
+        reading_profile: None,
+        feed: None,
+        voice: None,
+        source_type: None,
+        image: None,
+        rating: None,
+        text_hash: None,
     };
 
     // Try to get the metadata from the ID3 tags
@@ -160,6 +327,13 @@ pub fn get_metadata(entry: &DirEntry) -> Result<ArticleMetadata, AnyError> {
         // Try to get the ID3 title and source URL (URL is in the Artist field)
         meta.title = tag.title().unwrap_or(&meta.title).to_string();
         meta.source_url = tag.artist().map(str::to_string);
+        let get_text = |id| {
+            tag.get(id)
+                .and_then(|frame| frame.content().text())
+                .map(str::to_string)
+        };
+        meta.author = get_text(AUTHOR_FRAME_ID);
+        meta.publication = get_text(PUBLICATION_FRAME_ID);
 
         // Extract the time recorded and convert it back to a unix timestamp. It's a pain
         let datetime_added = tag.date_recorded().and_then(|recorded| {
@@ -178,11 +352,85 @@ pub fn get_metadata(entry: &DirEntry) -> Result<ArticleMetadata, AnyError> {
             datetime.timestamp().try_into().ok()
         });
         meta.datetime_added = datetime_added.or(meta.datetime_added);
+
+        let get_extended_text = |desc| {
+            tag.extended_texts()
+                .find(|t| t.description == desc)
+                .map(|t| t.value.clone())
+        };
+
+        // The article is only part of a group if every field of the group was recorded
+        let group_id = get_extended_text(GROUP_ID_FRAME_DESC);
+        if let (Some(id), Some(title), Some(part), Some(parts)) =
+            (group_id, tag.album(), tag.track(), tag.total_tracks())
+        {
+            meta.group = Some(ArticleGroup {
+                id,
+                title: title.to_string(),
+                part,
+                parts,
+            });
+        }
+
+        // Articles from before texts were analyzed have no complexity recorded
+        let complexity = get_extended_text(COMPLEXITY_FRAME_DESC).and_then(|c| c.parse().ok());
+        meta.reading_profile = complexity.map(|complexity| ReadingProfile {
+            language: get_extended_text(LANGUAGE_FRAME_DESC),
+            complexity,
+        });
+        meta.feed = get_extended_text(FEED_FRAME_DESC);
+        meta.voice = get_extended_text(VOICE_FRAME_DESC);
+        meta.source_type = get_extended_text(SOURCE_TYPE_FRAME_DESC).and_then(|t| t.parse().ok());
+        meta.image = get_extended_text(IMAGE_FRAME_DESC);
+        meta.rating = get_extended_text(RATING_FRAME_DESC).and_then(|r| r.parse().ok());
+        meta.text_hash = get_extended_text(TEXT_HASH_FRAME_DESC);
     }
 
     Ok(meta)
 }
 
+/// Escapes the characters that are special in XML and HTML, for text or attribute values
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes the given text for use in an iCalendar property value. This isn't XML's escaping:
+/// commas, semicolons, and backslashes are special, and lines are broken with `\n`.
+pub(crate) fn escape_ical(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the seconds since the Unix epoch
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
 #[test]
 fn test_title_truncation() {
     let title = "Money Stuff: AMC’s APEs Might Stick Around";
@@ -191,3 +439,29 @@ fn test_title_truncation() {
         "Money Stuff: AMC’s"
     );
 }
+
+#[test]
+fn test_escaping() {
+    assert_eq!(
+        escape_xml(r#"<a href="x">Tom & Jerry's</a>"#),
+        "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+    );
+    assert_eq!(
+        escape_ical("Lunch; then, a\\b\r\nnap"),
+        "Lunch\\; then\\, a\\\\b\\nnap"
+    );
+}
+
+#[test]
+fn test_text_hash() {
+    // Spacing, case, and punctuation don't matter, but the words do
+    let text = "It was a dark and stormy night.\n\nThe rain fell in torrents.";
+    assert_eq!(
+        hash_text(text),
+        hash_text("it was a dark and stormy night — the rain fell in  torrents")
+    );
+    assert_ne!(
+        hash_text(text),
+        hash_text("It was a dark and stormy night. The snow fell in torrents.")
+    );
+}
@@ -0,0 +1,270 @@
+//! Keeps track of the voices this server offers, and provides endpoints for listing them and for
+//! previewing them on a short sample of text
+
+use crate::{config::CustomVoice, engines::SharedEngine, rate_limit::RateLimiter};
+use common::{EncodingOptions, SpeakingStyle, SynthesisOptions, VoiceInfo, VoicePreviewSubmission};
+use tts_pipeline::{tts, voice::voice_language_code, TtsEngine, TtsRequest, Voice};
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+
+/// The header clients use to present a key for custom voices
+const VOICE_KEY_HEADER: &str = "X-Voice-Key";
+
+/// Returns the language the given language code is for, as it's detected in texts. Engines use a
+/// few codes of their own, e.g., `cmn` for Mandarin, and `iw`, the old code for Hebrew.
+fn base_language(code: &str) -> &str {
+    let lang = code.split('-').next().unwrap_or(code);
+    match lang {
+        "cmn" | "yue" => "zh",
+        "iw" => "he",
+        "arb" => "ar",
+        lang => lang,
+    }
+}
+
+/// Returns the language code of the given builtin voice if its name starts with one. Not every
+/// engine names its voices that way, e.g., Polly's voices are named like people.
+fn known_language_code(voice: &str) -> Option<&str> {
+    let (lang, _) = voice.split_once('-')?;
+    let is_lang = (2..=3).contains(&lang.len()) && lang.chars().all(|c| c.is_ascii_lowercase());
+    is_lang.then(|| voice_language_code(voice))
+}
+
+/// All the voices this server offers, i.e., the builtin voices of its engine and the custom ones,
+/// along with how the audio they make is encoded by default
+#[derive(Clone)]
+pub(crate) struct VoiceRegistry {
+    engine: SharedEngine,
+    custom_voices: Arc<Vec<CustomVoice>>,
+    default_encoding: EncodingOptions,
+}
+
+impl VoiceRegistry {
+    pub(crate) fn new(
+        engine: SharedEngine,
+        custom_voices: Vec<CustomVoice>,
+        default_encoding: EncodingOptions,
+    ) -> VoiceRegistry {
+        VoiceRegistry {
+            engine,
+            custom_voices: Arc::new(custom_voices),
+            default_encoding: default_encoding.clamped(),
+        }
+    }
+
+    /// Returns the given options with this server's default encoding, if they don't ask for one
+    pub(crate) fn with_default_encoding(&self, options: SynthesisOptions) -> SynthesisOptions {
+        SynthesisOptions {
+            encoding: Some(options.encoding.unwrap_or(self.default_encoding)),
+            ..options
+        }
+    }
+
+    /// Lists the voices available to the holder of the given voice key
+    pub(crate) fn list(&self, key: Option<&str>) -> Vec<VoiceInfo> {
+        let builtin = self
+            .engine
+            .voices()
+            .into_iter()
+            .map(|(name, description)| VoiceInfo {
+                name: name.to_string(),
+                description: description.to_string(),
+                language_code: known_language_code(name).map(str::to_string),
+                custom: false,
+            });
+        let custom = self
+            .custom_voices
+            .iter()
+            .filter(|v| v.is_permitted(key))
+            .map(|v| VoiceInfo {
+                name: v.name.clone(),
+                description: v.description.clone(),
+                language_code: Some(v.language_code.clone()),
+                custom: true,
+            });
+
+        builtin.chain(custom).collect()
+    }
+
+    /// Returns the engine the voices are spoken by
+    pub(crate) fn engine(&self) -> &dyn TtsEngine {
+        self.engine.as_ref()
+    }
+
+    /// Looks up the voice with the given name. Errors if no such voice exists, or if it's a custom
+    /// voice that the holder of the given key may not use.
+    pub(crate) fn resolve(&self, name: &str, key: Option<&str>) -> Result<Voice, AnyError> {
+        if self.engine.voices().iter().any(|(n, _)| *n == name) {
+            return Ok(Voice::builtin(name));
+        }
+
+        match self.custom_voices.iter().find(|v| v.name == name) {
+            Some(v) if v.is_permitted(key) => Ok(custom_voice(v)),
+            // Don't reveal the existence of voices the user isn't allowed to see
+            _ => bail!("Unknown voice {name}"),
+        }
+    }
+
+    /// Looks up the voice with the given name that an article was already read in. The article was
+    /// allowed the voice then, so voice keys aren't checked. Returns `None` if the voice is gone,
+    /// e.g., because the server's engine changed.
+    pub(crate) fn resolve_recorded(&self, name: &str) -> Option<Voice> {
+        if self.engine.voices().iter().any(|(n, _)| *n == name) {
+            return Some(Voice::builtin(name));
+        }
+        self.custom_voices
+            .iter()
+            .find(|v| v.name == name)
+            .map(custom_voice)
+    }
+
+    /// Returns the engine's first voice that speaks the given language, e.g., `en`. If there's no
+    /// such voice, or the language isn't known, returns the engine's default voice.
+    pub(crate) fn voice_for_language(&self, language: Option<&str>) -> Voice {
+        let voices = self.engine.voices();
+        let speaks = |name: &str| known_language_code(name).map(base_language) == language;
+        let voice = match language {
+            Some(_) => voices.iter().find(|(name, _)| speaks(name)),
+            None => None,
+        };
+        Voice::builtin(voice.unwrap_or(&voices[0]).0)
+    }
+}
+
+/// Makes the given custom voice ready for synthesis
+fn custom_voice(v: &CustomVoice) -> Voice {
+    Voice {
+        name: v.name.clone(),
+        language_code: v.language_code.clone(),
+        custom_model: Some(v.model.clone()),
+    }
+}
+
+/// Gets the voice key from the request headers, if there is one
+pub(crate) fn voice_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get(VOICE_KEY_HEADER).and_then(|v| v.to_str().ok())
+}
+
+#[derive(Debug)]
+struct PreviewError(anyhow::Error);
+
+impl From<anyhow::Error> for PreviewError {
+    fn from(error: anyhow::Error) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for PreviewError {
+    fn into_response(self) -> Response {
+        // Log the error and return it
+        let err_str = self.0.to_string();
+        tracing::error!("{}", err_str);
+        (StatusCode::INTERNAL_SERVER_ERROR, err_str).into_response()
+    }
+}
+
+/// The longest text that can be previewed, in characters
+#[derive(Clone, Copy)]
+struct MaxPreviewChars(usize);
+
+// Sets the /api/list-voices and /api/preview-voice routes. Previews can be at most the given number
+// of characters long.
+pub(crate) fn setup(
+    router: Router,
+    tts_rate_limiter: RateLimiter,
+    voice_registry: VoiceRegistry,
+    max_preview_chars: usize,
+) -> Router {
+    router.nest(
+        "/api",
+        Router::new()
+            .route("/list-voices", get(list_voices))
+            .route("/preview-voice", post(preview_voice))
+            .layer(Extension(tts_rate_limiter))
+            .layer(Extension(voice_registry))
+            .layer(Extension(MaxPreviewChars(max_preview_chars))),
+    )
+}
+
+/// Lists the voices available to the client
+async fn list_voices(
+    headers: HeaderMap,
+    Extension(voice_registry): Extension<VoiceRegistry>,
+) -> Json<Vec<VoiceInfo>> {
+    Json(voice_registry.list(voice_key(&headers)))
+}
+
+/// Synthesizes a short sample of text with the given voice and returns the MP3
+async fn preview_voice(
+    headers: HeaderMap,
+    Json(VoicePreviewSubmission { text, voice }): Json<VoicePreviewSubmission>,
+    Extension(tts_rate_limiter): Extension<RateLimiter>,
+    Extension(voice_registry): Extension<VoiceRegistry>,
+    Extension(MaxPreviewChars(max_chars)): Extension<MaxPreviewChars>,
+) -> Result<Response, PreviewError> {
+    tracing::debug!("Previewing voice {voice}");
+
+    // Previews are meant to be short. Don't let this be used as a way around adding articles.
+    if text.chars().count() > max_chars {
+        Err(anyhow!(
+            "Preview text is too long. The limit is {max_chars} characters."
+        ))?;
+    }
+    let voice = voice_registry.resolve(&voice, voice_key(&headers))?;
+    tts_rate_limiter.check(&text)?;
+
+    // Do the TTS and return the audio
+    let req = TtsRequest {
+        text,
+        voice,
+        style: SpeakingStyle::default(),
+        options: voice_registry.with_default_encoding(SynthesisOptions::default()),
+    };
+    let output = tts(voice_registry.engine(), req, None, false, None, None)
+        .await
+        .map_err(|e| anyhow!("TTS failed: {:?}", e))?;
+
+    // A partial preview isn't much use
+    if let Some((_, e)) = output.unfinished {
+        Err(anyhow!("TTS failed: {:?}", e))?;
+    }
+
+    Ok(([(header::CONTENT_TYPE, "audio/mpeg")], output.mp3).into_response())
+}
+
+#[test]
+fn voices_for_languages() {
+    use tts_pipeline::voice::DEFAULT_VOICE;
+
+    let registry = VoiceRegistry::new(
+        Arc::new(tts_pipeline::engines::GoogleEngine::new(String::new())),
+        Vec::new(),
+        EncodingOptions::default(),
+    );
+    assert_eq!(registry.voice_for_language(None).name, DEFAULT_VOICE);
+    assert_eq!(registry.voice_for_language(Some("en")).name, DEFAULT_VOICE);
+    // There's no voice for Welsh, so the default is used
+    assert_eq!(registry.voice_for_language(Some("cy")).name, DEFAULT_VOICE);
+    // Google calls Mandarin `cmn`
+    assert_eq!(
+        registry.voice_for_language(Some("zh")).name,
+        "cmn-CN-Wavenet-A"
+    );
+    assert_eq!(
+        registry.voice_for_language(Some("ar")).language_code,
+        "ar-XA"
+    );
+
+    assert_eq!(known_language_code("en-GB-Wavenet-B"), Some("en-GB"));
+    assert_eq!(known_language_code("Joanna"), None);
+    assert_eq!(known_language_code("local"), None);
+}
@@ -0,0 +1,267 @@
+//! Reads articles off the web: their pages are fetched, and their text is extracted from the HTML.
+//! Both are behind traits, so that whatever uses the pipeline can fetch through its own cache and
+//! proxies, and extract with its own rules.
+
+use crate::{
+    engines::TtsEngine,
+    tts::{tts, TtsOutput, TtsRequest},
+    voice::Voice,
+};
+use common::{SpeakingStyle, SynthesisOptions};
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use async_process::Command;
+use async_trait::async_trait;
+use futures::io::AsyncWriteExt;
+use serde::Deserialize;
+
+/// A fetched web page
+#[derive(Clone, Debug)]
+pub struct Page {
+    /// The URL the page ended up at, after redirects
+    pub url: String,
+    /// The page's HTML
+    pub html: Vec<u8>,
+}
+
+/// Fetches web pages, e.g., over HTTP, or from a cache
+#[async_trait]
+pub trait Fetch: Send + Sync {
+    /// Fetches the page at the given URL
+    async fn fetch(&self, url: &str) -> Result<Page, AnyError>;
+}
+
+/// Finds the article on a web page
+#[async_trait]
+pub trait Extract: Send + Sync {
+    /// Extracts the article on the given page. Fails if there's no article to be found.
+    async fn extract(&self, page: &Page) -> Result<ExtractedArticle, AnyError>;
+}
+
+/// A portion of trafilatura's extracted text. The rest of the fields are: hostname,
+/// categories, tags, fingerprint, id, license, comments, raw_text, source, excerpt
+#[derive(Deserialize)]
+pub struct ExtractedArticle {
+    pub title: String,
+    pub text: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// The name of the site the article is from
+    #[serde(default, rename = "source-hostname", alias = "source_hostname")]
+    pub sitename: Option<String>,
+    /// The URL of the article's lead image, if the extractor found one
+    #[serde(default)]
+    pub image: Option<String>,
+    /// When the article was published, as `YYYY-MM-DD`, if the page says
+    #[serde(default)]
+    pub date: Option<String>,
+}
+
+impl ExtractedArticle {
+    /// Returns the name of the publication the article is from. If the page doesn't say, this is
+    /// the host of the given URL, without any leading "www."
+    pub fn publication(&self, url: &str) -> Option<String> {
+        let sitename = self.sitename.as_deref().map(str::trim);
+        match sitename {
+            Some(name) if !name.is_empty() => Some(name.to_string()),
+            _ => site_of(url),
+        }
+    }
+}
+
+/// Returns the host of the given URL, without any leading "www."
+pub fn site_of(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(host.strip_prefix("www.").unwrap_or(host).to_string())
+}
+
+/// Fetches pages over plain HTTP, following redirects
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+impl HttpFetcher {
+    pub fn new() -> Result<HttpFetcher, AnyError> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("readtomyshoe/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(HttpFetcher { client })
+    }
+}
+
+#[async_trait]
+impl Fetch for HttpFetcher {
+    async fn fetch(&self, url: &str) -> Result<Page, AnyError> {
+        let resp = self.client.get(url).send().await?.error_for_status()?;
+        let url = resp.url().to_string();
+        let html = resp.bytes().await?.to_vec();
+        Ok(Page { url, html })
+    }
+}
+
+/// Extracts articles with the trafilatura CLI
+pub struct Trafilatura {
+    program: PathBuf,
+    /// Where trafilatura's Python packages are, if they aren't installed system-wide
+    python_path: Option<PathBuf>,
+}
+
+impl Default for Trafilatura {
+    /// The trafilatura that's installed next to the server, as the README says to
+    fn default() -> Trafilatura {
+        Trafilatura::new(
+            Path::new("../python_deps/bin/trafilatura"),
+            Some(Path::new("../python_deps")),
+        )
+    }
+}
+
+impl Trafilatura {
+    pub fn new(program: &Path, python_path: Option<&Path>) -> Trafilatura {
+        Trafilatura {
+            program: program.to_path_buf(),
+            python_path: python_path.map(Path::to_path_buf),
+        }
+    }
+
+    /// Runs trafilatura on the given HTML
+    pub async fn run(&self, html: &[u8]) -> Result<ExtractedArticle, AnyError> {
+        // Run trafilatura, piping the HTML in through stdin
+        let mut cmd = Command::new(&self.program);
+        if let Some(python_path) = &self.python_path {
+            cmd.env("PYTHONPATH", python_path);
+        }
+        let mut child = cmd
+            .arg("--json")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("IO error running trafilatura: {:?}", e))?;
+
+        // Write the HTML and close stdin so trafilatura knows the input is done. Its stdout is
+        // collected at the same time, so neither pipe can fill up and deadlock.
+        let mut stdin = child.stdin.take().unwrap();
+        let write_input = async move {
+            stdin.write_all(html).await?;
+            stdin.close().await
+        };
+        let (write_res, output) = futures::join!(write_input, child.output());
+
+        let output = output.map_err(|e| anyhow!("IO error running trafilatura: {:?}", e))?;
+        // See if the command failed
+        if !output.status.success() {
+            bail!("Text extraction failed");
+        }
+        write_res.map_err(|e| anyhow!("IO error writing to trafilatura: {:?}", e))?;
+
+        // Convert the CLI output from JSON
+        serde_json::from_slice(&output.stdout).map_err(|_| anyhow!("Text extraction failed"))
+    }
+}
+
+#[async_trait]
+impl Extract for Trafilatura {
+    async fn extract(&self, page: &Page) -> Result<ExtractedArticle, AnyError> {
+        self.run(&page.html).await
+    }
+}
+
+/// Fetches the article at the given URL and extracts it. Returns the page as fetched, along with
+/// the article.
+pub async fn read_article(
+    fetcher: &dyn Fetch,
+    extractor: &dyn Extract,
+    url: &str,
+) -> Result<(Page, ExtractedArticle), AnyError> {
+    let page = fetcher.fetch(url).await?;
+    let article = extractor.extract(&page).await?;
+    Ok((page, article))
+}
+
+/// Reads the article at the given URL and speaks it, title first. Returns the article along with
+/// its audio.
+pub async fn speak_article(
+    fetcher: &dyn Fetch,
+    extractor: &dyn Extract,
+    engine: &dyn TtsEngine,
+    url: &str,
+    voice: Voice,
+    style: SpeakingStyle,
+    options: SynthesisOptions,
+) -> Result<(ExtractedArticle, TtsOutput), AnyError> {
+    let (_, article) = read_article(fetcher, extractor, url).await?;
+    let req = TtsRequest {
+        text: format!("{}\n\n{}", article.title, article.text),
+        voice,
+        style,
+        options,
+    };
+    let output = tts(engine, req, None, false, None, None).await?;
+    Ok((article, output))
+}
+
+#[test]
+fn publication_fallback() {
+    let mut article = ExtractedArticle {
+        title: "Title".to_string(),
+        text: "Body".to_string(),
+        author: None,
+        sitename: Some("The Paper".to_string()),
+        image: None,
+        date: None,
+    };
+    let url = "https://www.example.com/2022/10/article.html";
+    assert_eq!(article.publication(url).as_deref(), Some("The Paper"));
+
+    // Without a site name, the URL's host is used
+    article.sitename = Some(" ".to_string());
+    assert_eq!(article.publication(url).as_deref(), Some("example.com"));
+    article.sitename = None;
+    assert_eq!(article.publication("not a url"), None);
+}
+
+#[tokio::test]
+async fn reading_articles() {
+    /// Serves a page that was redirected to
+    struct Redirected;
+
+    #[async_trait]
+    impl Fetch for Redirected {
+        async fn fetch(&self, url: &str) -> Result<Page, AnyError> {
+            Ok(Page {
+                url: format!("{url}?redirected"),
+                html: b"<h1>Hi</h1>".to_vec(),
+            })
+        }
+    }
+
+    /// Takes the page's HTML for the article's text
+    struct Verbatim;
+
+    #[async_trait]
+    impl Extract for Verbatim {
+        async fn extract(&self, page: &Page) -> Result<ExtractedArticle, AnyError> {
+            Ok(ExtractedArticle {
+                title: page.url.clone(),
+                text: String::from_utf8(page.html.clone())?,
+                author: None,
+                sitename: None,
+                image: None,
+                date: None,
+            })
+        }
+    }
+
+    // The extractor sees the page where it ended up
+    let url = "https://example.com/a";
+    let (page, article) = read_article(&Redirected, &Verbatim, url).await.unwrap();
+    assert_eq!(page.url, "https://example.com/a?redirected");
+    assert_eq!(article.title, page.url);
+    assert_eq!(article.text, "<h1>Hi</h1>");
+}
@@ -0,0 +1,864 @@
+//! Post-processes synthesized speech and encodes it to MP3. Every chunk of an article is trimmed of
+//! leading and trailing silence and normalized to the same loudness, and then the chunks are
+//! stitched together with consistent gaps between them. Optionally, an ambient bed is mixed under
+//! the result.
+//!
+//! Some voices leave clicks where chunks are joined, either from a stray spike at the edge of a
+//! chunk or from the chunk starting or stopping partway through a waveform. So spikes near the
+//! edges are smoothed over, and every chunk is faded in and out over a few milliseconds. Chunks
+//! joined without a gap are crossfaded.
+
+use common::{EncodingOptions, LOUDNESS_STEP_SECS};
+
+use std::{
+    fs,
+    io::{Cursor, Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+    process::Stdio,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use async_process::Command;
+use futures::io::AsyncWriteExt;
+use serde::Deserialize;
+
+/// A background track that's looped under the speech, e.g., rain or cafe noise
+#[derive(Clone, Copy, Debug)]
+pub struct BedMix<'a> {
+    /// The audio file. Any format ffmpeg can read is fine.
+    pub path: &'a Path,
+    /// The volume of the track relative to its original volume, before ducking
+    pub volume: f32,
+}
+
+/// The sample rate we work in, in Hz. Engines that can't produce it are resampled to it.
+pub const SAMPLE_RATE: u32 = 24000;
+
+/// Samples whose magnitude is below this are considered silence. This is about -36dBFS.
+const SILENCE_THRESHOLD: i16 = 512;
+
+/// How much of the silence before and after speech is kept when trimming, in milliseconds. A little
+/// bit of padding keeps the attacks and decays of words from being clipped.
+const TRIM_PADDING_MS: u32 = 30;
+
+/// The loudness every chunk is normalized to, as an RMS amplitude. This is about -20dBFS.
+const TARGET_RMS: f64 = 3277.0;
+
+/// The highest peak amplitude normalization is allowed to produce
+const MAX_PEAK: f64 = 0.98 * i16::MAX as f64;
+
+/// How long the fade in and out at the edges of every chunk is, in milliseconds. This is well within
+/// the trimming padding, so speech isn't faded.
+const FADE_MS: u32 = 5;
+
+/// How far into a chunk from either edge spikes are smoothed over, in milliseconds
+const DECLICK_MS: u32 = 20;
+
+/// A sample this far above or below both of its neighbors is a spike. Speech doesn't move this much
+/// from one sample to the next.
+const CLICK_JUMP: i32 = 8000;
+
+/// Converts a duration in milliseconds to a number of samples
+fn ms_to_samples(ms: u32) -> usize {
+    (SAMPLE_RATE as usize * ms as usize) / 1000
+}
+
+/// Resamples the given samples from the given sample rate to `SAMPLE_RATE`, by linear
+/// interpolation. Speech doesn't have much up high for this to mangle.
+pub fn resample(samples: &[i16], from_rate: u32) -> Vec<i16> {
+    if from_rate == SAMPLE_RATE || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let step = from_rate as f64 / SAMPLE_RATE as f64;
+    let out_len = (samples.len() as f64 / step).floor() as usize;
+    (0..out_len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let j = pos as usize;
+            let frac = pos - j as f64;
+            let a = samples[j] as f64;
+            let b = *samples.get(j + 1).unwrap_or(&samples[j]) as f64;
+            (a + (b - a) * frac).round() as i16
+        })
+        .collect()
+}
+
+/// Decodes raw mono 16-bit little-endian samples at the given sample rate, resampling them to
+/// `SAMPLE_RATE`
+pub fn decode_pcm(pcm: &[u8], sample_rate: u32) -> Vec<i16> {
+    let samples: Vec<i16> = pcm
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    resample(&samples, sample_rate)
+}
+
+/// Decodes the WAV file returned by the TTS engine into its mono 16-bit samples, resampled to
+/// `SAMPLE_RATE`
+pub fn decode_wav(wav: &[u8]) -> Result<Vec<i16>, AnyError> {
+    let reader = hound::WavReader::new(Cursor::new(wav))
+        .map_err(|e| anyhow!("Couldn't parse synthesized audio: {e}"))?;
+
+    let spec = reader.spec();
+    if spec.channels != 1 || spec.bits_per_sample != 16 {
+        bail!("Unexpected synthesized audio format {:?}", spec);
+    }
+
+    let samples: Vec<i16> = reader
+        .into_samples::<i16>()
+        .collect::<Result<_, _>>()
+        .map_err(|e| anyhow!("Couldn't parse synthesized audio: {e}"))?;
+    Ok(resample(&samples, spec.sample_rate))
+}
+
+/// Returns the range of the given samples that's left after removing the silence at the beginning
+/// and end, leaving a little padding. The range is empty if it's all silence.
+fn unsilent_range(samples: &[i16]) -> Range<usize> {
+    let is_loud = |s: &i16| s.unsigned_abs() >= SILENCE_THRESHOLD as u16;
+
+    let (first, last) = match (
+        samples.iter().position(is_loud),
+        samples.iter().rposition(is_loud),
+    ) {
+        (Some(f), Some(l)) => (f, l),
+        // The whole thing is silent
+        _ => return 0..0,
+    };
+
+    let padding = ms_to_samples(TRIM_PADDING_MS);
+    let start = first.saturating_sub(padding);
+    let end = (last + 1 + padding).min(samples.len());
+    start..end
+}
+
+/// Removes the silence at the beginning and end of the given samples, leaving a little padding
+#[cfg(test)]
+fn trim_silence(samples: &[i16]) -> &[i16] {
+    &samples[unsilent_range(samples)]
+}
+
+/// Returns the RMS amplitude of the given samples, which mustn't be empty
+fn rms(samples: &[i16]) -> f64 {
+    let sum_squares: f64 = samples.iter().map(|&s| (s as f64) * (s as f64)).sum();
+    (sum_squares / samples.len() as f64).sqrt()
+}
+
+/// Returns how loud the given samples are over time, as one level for every `LOUDNESS_STEP_SECS`.
+/// Twice `TARGET_RMS` is the loudest level, 255, so normalized speech is around the middle, and the
+/// gaps between paragraphs are near 0.
+pub fn loudness(samples: &[i16]) -> Vec<u8> {
+    let step = (SAMPLE_RATE as f64 * LOUDNESS_STEP_SECS) as usize;
+    samples
+        .chunks(step)
+        .map(|window| {
+            (rms(window) / (2.0 * TARGET_RMS) * 255.0)
+                .min(255.0)
+                .round() as u8
+        })
+        .collect()
+}
+
+/// Scales the given samples so that their RMS amplitude is `TARGET_RMS`. The gain is reduced if
+/// that would clip.
+fn normalize(samples: &mut [i16]) {
+    if samples.is_empty() {
+        return;
+    }
+
+    let rms = rms(samples);
+    let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0) as f64;
+    if rms == 0.0 || peak == 0.0 {
+        return;
+    }
+
+    let gain = (TARGET_RMS / rms).min(MAX_PEAK / peak);
+    for s in samples.iter_mut() {
+        *s = (*s as f64 * gain).round() as i16;
+    }
+}
+
+/// Returns the given duration of silence
+pub fn silence(ms: u32) -> Vec<i16> {
+    vec![0i16; ms_to_samples(ms)]
+}
+
+/// Replaces the single-sample spikes within `DECLICK_MS` of either edge of the given samples with
+/// the average of their neighbors
+fn remove_clicks(samples: &mut [i16]) {
+    let edge = ms_to_samples(DECLICK_MS);
+    let len = samples.len();
+    for i in 1..len.saturating_sub(1) {
+        if i >= edge && i < len - edge {
+            continue;
+        }
+        let (before, s, after) = (
+            samples[i - 1] as i32,
+            samples[i] as i32,
+            samples[i + 1] as i32,
+        );
+        let is_spike = (s - before > CLICK_JUMP && s - after > CLICK_JUMP)
+            || (before - s > CLICK_JUMP && after - s > CLICK_JUMP);
+        if is_spike {
+            samples[i] = ((before + after) / 2) as i16;
+        }
+    }
+}
+
+/// Returns the gain of the `i`th of `n` samples of a fade in. It's a raised cosine, so a fade in
+/// and the reverse of it add up to 1, and crossfade evenly.
+fn fade_in_gain(i: usize, n: usize) -> f64 {
+    let x = std::f64::consts::FRAC_PI_2 * (i as f64 + 0.5) / n as f64;
+    x.sin().powi(2)
+}
+
+/// Fades the given samples in and out over `FADE_MS`, or less if they're too short for that, so
+/// they start and end at silence
+fn fade_edges(samples: &mut [i16]) {
+    let n = ms_to_samples(FADE_MS).min(samples.len() / 2);
+    let len = samples.len();
+    for i in 0..n {
+        let gain = fade_in_gain(i, n);
+        samples[i] = (samples[i] as f64 * gain).round() as i16;
+        samples[len - 1 - i] = (samples[len - 1 - i] as f64 * gain).round() as i16;
+    }
+}
+
+/// Trims the given chunk of silence, smooths over clicks at its edges, normalizes it, and fades it
+/// in and out. Also returns where the trimmed audio starts in the chunk. Returns `None` if the chunk
+/// is all silence.
+fn clean(chunk: &[i16]) -> Option<(usize, Vec<i16>)> {
+    let range = unsilent_range(chunk);
+    if range.is_empty() {
+        return None;
+    }
+
+    let start = range.start;
+    let mut cleaned = chunk[range].to_vec();
+    remove_clicks(&mut cleaned);
+    normalize(&mut cleaned);
+    fade_edges(&mut cleaned);
+    Some((start, cleaned))
+}
+
+/// Trims the given chunk of silence, normalizes it, and smooths its edges
+pub fn clean_chunk(chunk: &[i16]) -> Vec<i16> {
+    clean(chunk).map(|(_, cleaned)| cleaned).unwrap_or_default()
+}
+
+/// Chunks of audio joined into one
+pub struct Stitched {
+    /// The joined audio
+    pub samples: Vec<i16>,
+    /// For every chunk, the sample of the joined audio where the chunk would start if it hadn't
+    /// been trimmed. This can be negative. It's `None` if the chunk was all silence, and dropped.
+    pub chunk_origins: Vec<Option<i64>>,
+}
+
+/// Cleans every chunk, and joins them with `gap_ms` of silence in between. Without a gap, the
+/// chunks' fades overlap, crossfading them.
+pub fn stitch(chunks: Vec<Vec<i16>>, gap_ms: u32) -> Stitched {
+    let gap = silence(gap_ms);
+
+    let mut out: Vec<i16> = Vec::new();
+    let mut chunk_origins = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        let Some((start, cleaned)) = clean(&chunk) else {
+            chunk_origins.push(None);
+            continue;
+        };
+
+        if !out.is_empty() {
+            out.extend_from_slice(&gap);
+        }
+        let overlap = if gap.is_empty() {
+            ms_to_samples(FADE_MS).min(out.len()).min(cleaned.len())
+        } else {
+            0
+        };
+        let chunk_start = out.len() - overlap;
+        chunk_origins.push(Some(chunk_start as i64 - start as i64));
+
+        for (o, &s) in out[chunk_start..].iter_mut().zip(&cleaned) {
+            *o = o.saturating_add(s);
+        }
+        out.extend_from_slice(&cleaned[overlap..]);
+    }
+
+    Stitched {
+        samples: out,
+        chunk_origins,
+    }
+}
+
+/// Builds the ffmpeg filter that loops the ambient bed (input 1) under the speech (input 0). The bed
+/// is ducked whenever there's speech, using the speech as a sidechain.
+fn ambient_filter(bed_volume: f32) -> String {
+    format!(
+        "[0:a]asplit=2[speech][sidechain];\
+         [1:a]aresample={SAMPLE_RATE},aformat=channel_layouts=mono,volume={bed_volume}[bed];\
+         [bed][sidechain]sidechaincompress=threshold=0.02:ratio=8:attack=20:release=400[ducked];\
+         [speech][ducked]amix=inputs=2:duration=first,volume=2[out]"
+    )
+}
+
+/// Encodes the given samples to MP3 using ffmpeg, with the given sample rate, bitrate, and channels.
+/// If an ambient bed is given, it's mixed under the speech.
+pub async fn encode_mp3(
+    samples: &[i16],
+    ambient_bed: Option<BedMix<'_>>,
+    encoding: &EncodingOptions,
+) -> Result<Vec<u8>, AnyError> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
+
+    // The speech comes in raw through stdin
+    let sample_rate = SAMPLE_RATE.to_string();
+    cmd.args(["-f", "s16le", "-ac", "1", "-ar", &sample_rate, "-i", "-"]);
+
+    // The ambient bed is looped forever. amix stops when the speech does.
+    if let Some(bed) = ambient_bed {
+        cmd.args(["-stream_loop", "-1", "-i"])
+            .arg(bed.path)
+            .args(["-filter_complex", &ambient_filter(bed.volume)])
+            .args(["-map", "[out]"]);
+    }
+
+    // Don't write any headers. This way, the output can be appended to an existing MP3, and the
+    // result is still a valid MP3.
+    cmd.args(["-write_xing", "0", "-id3v2_version", "0"]);
+
+    let out_rate = encoding.sample_rate.to_string();
+    let channels = if encoding.stereo { "2" } else { "1" };
+    let bitrate = format!("{}k", encoding.bitrate_kbps);
+    let mut child = cmd
+        .args(["-ar", &out_rate, "-ac", channels])
+        .args(["-codec:a", "libmp3lame", "-b:a", &bitrate, "-f", "mp3", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("IO error running ffmpeg: {:?}", e))?;
+
+    // ffmpeg writes output as it reads input, so we have to feed stdin and collect stdout at the
+    // same time. Otherwise both pipes fill up and we deadlock.
+    let mut stdin = child.stdin.take().unwrap();
+    let pcm: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    let write_input = async move {
+        stdin.write_all(&pcm).await?;
+        stdin.close().await
+    };
+    let (write_res, output) = futures::join!(write_input, child.output());
+
+    let output = output.map_err(|e| anyhow!("IO error running ffmpeg: {:?}", e))?;
+    if !output.status.success() {
+        bail!(
+            "MP3 encoding failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    write_res.map_err(|e| anyhow!("IO error writing to ffmpeg: {:?}", e))?;
+
+    Ok(output.stdout)
+}
+
+/// A format article audio can be served in. Articles are stored as MP3, and transcoded to the
+/// others on request, since they're much smaller at the same quality.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Mp3,
+    /// Opus in an Ogg container
+    Opus,
+    /// AAC in an ADTS stream
+    Aac,
+}
+
+impl AudioFormat {
+    /// Every format
+    pub const ALL: [AudioFormat; 3] = [AudioFormat::Mp3, AudioFormat::Opus, AudioFormat::Aac];
+
+    /// Returns the file extension of this format. It's also the name clients ask for it by.
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Aac => "aac",
+        }
+    }
+
+    /// Returns the format with the given name or file extension, e.g., `opus`
+    pub fn from_extension(ext: &str) -> Option<AudioFormat> {
+        AudioFormat::ALL.into_iter().find(|f| f.extension() == ext)
+    }
+
+    /// Returns the MIME type of this format
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "audio/mpeg",
+            AudioFormat::Opus => "audio/ogg; codecs=opus",
+            AudioFormat::Aac => "audio/aac",
+        }
+    }
+
+    /// Returns the ffmpeg arguments that encode to this format. The bitrates are about half of the
+    /// default MP3 bitrate, which is plenty for speech in these codecs. The sample rate and
+    /// channels are kept from the source.
+    fn ffmpeg_args(self) -> &'static [&'static str] {
+        match self {
+            AudioFormat::Mp3 => &["-codec:a", "libmp3lame", "-b:a", "64k", "-f", "mp3"],
+            AudioFormat::Opus => &[
+                "-codec:a",
+                "libopus",
+                "-b:a",
+                "32k",
+                "-application",
+                "voip",
+                "-f",
+                "ogg",
+            ],
+            AudioFormat::Aac => &["-codec:a", "aac", "-b:a", "48k", "-f", "adts"],
+        }
+    }
+}
+
+/// Transcodes the MP3 at `src` to the given format at `dest`, using ffmpeg. The output is written
+/// to a temporary file first, so a half-transcoded file is never served.
+pub async fn transcode(src: &Path, dest: &Path, format: AudioFormat) -> Result<(), AnyError> {
+    let tmp = dest.with_extension(format!("{}.tmp", format.extension()));
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+        .arg(src)
+        // Drop the cover art and the like. Only the audio is transcoded.
+        .args(["-map", "0:a"])
+        .args(format.ffmpeg_args())
+        .arg(&tmp)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| anyhow!("IO error running ffmpeg: {:?}", e))?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&tmp);
+        bail!(
+            "Transcoding {} failed: {}",
+            src.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    fs::rename(&tmp, dest)?;
+    Ok(())
+}
+
+/// The tag that marks the first frame of a constant-bitrate MP3 as a summary of the file, rather
+/// than audio. The variable-bitrate equivalent is "Xing".
+const INFO_TAG: &[u8] = b"Info";
+
+/// Layer III bitrates in kbps, by bitrate index, for MPEG-1 and for MPEG-2 and 2.5
+const MPEG1_BITRATES: [u32; 15] = [
+    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+const MPEG2_BITRATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// What the header of an MP3 frame says about the frame
+#[derive(Debug, PartialEq)]
+struct FrameHeader {
+    /// The length of the frame, header included, in bytes
+    len: usize,
+    /// The length of the side information that follows the header, in bytes
+    side_info_len: usize,
+    /// The audio's bitrate, in bit/s
+    bitrate: u32,
+    /// The audio's sample rate, in Hz
+    sample_rate: u32,
+    /// Whether the audio is mono
+    mono: bool,
+}
+
+/// Parses the header at the start of the given bytes, if it's the header of a Layer III frame
+fn parse_frame_header(bytes: &[u8]) -> Option<FrameHeader> {
+    let header = bytes.get(..4)?;
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+
+    // 0 is MPEG-2.5, 2 is MPEG-2, and 3 is MPEG-1. Layer 1 is Layer III.
+    let version = (header[1] >> 3) & 0b11;
+    let layer = (header[1] >> 1) & 0b11;
+    if version == 1 || layer != 1 {
+        return None;
+    }
+    let mpeg1 = version == 3;
+
+    let bitrates = if mpeg1 {
+        &MPEG1_BITRATES
+    } else {
+        &MPEG2_BITRATES
+    };
+    let bitrate = 1000
+        * *bitrates
+            .get(usize::from(header[2] >> 4))
+            .filter(|&&b| b > 0)?;
+    let sample_rate = match ((header[2] >> 2) & 0b11, version) {
+        (i @ 0..=2, 3) => [44100, 48000, 32000][usize::from(i)],
+        (i @ 0..=2, 2) => [22050, 24000, 16000][usize::from(i)],
+        (i @ 0..=2, _) => [11025, 12000, 8000][usize::from(i)],
+        _ => return None,
+    };
+    let padding = usize::from((header[2] >> 1) & 1);
+    let mono = header[3] >> 6 == 0b11;
+
+    let (coefficient, side_info_len) = match (mpeg1, mono) {
+        (true, true) => (144, 17),
+        (true, false) => (144, 32),
+        (false, true) => (72, 9),
+        (false, false) => (72, 17),
+    };
+    Some(FrameHeader {
+        len: (coefficient * bitrate / sample_rate) as usize + padding,
+        side_info_len,
+        bitrate,
+        sample_rate,
+        mono,
+    })
+}
+
+/// Returns the length of the ID3v2 tag at the start of the given MP3, or 0 if there isn't one
+fn id3_len(mp3: &[u8]) -> usize {
+    match mp3.get(..10) {
+        Some(header) if header.starts_with(b"ID3") => {
+            // The size is "syncsafe": 7 bits per byte. A footer adds another 10 bytes.
+            let size = header[6..10]
+                .iter()
+                .fold(0, |acc, &b| (acc << 7) | usize::from(b & 0x7F));
+            let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    }
+}
+
+/// Returns the length of the ID3 tag of the MP3 at the given path, and the header of its first frame
+fn read_first_frame(path: &Path) -> Option<(usize, FrameHeader)> {
+    // The tag's header says how long it is, so only that and the frame header need reading
+    let mut file = fs::File::open(path).ok()?;
+    let mut tag_header = [0u8; 10];
+    file.read_exact(&mut tag_header).ok()?;
+    let audio_start = id3_len(&tag_header);
+    let mut frame_header = [0u8; 4];
+    file.seek(SeekFrom::Start(audio_start as u64)).ok()?;
+    file.read_exact(&mut frame_header).ok()?;
+    Some((audio_start, parse_frame_header(&frame_header)?))
+}
+
+/// Returns how the MP3 at the given path is encoded, going by its first frame. Audio appended to
+/// the MP3 has to be encoded the same way.
+pub fn mp3_encoding(path: &Path) -> Option<EncodingOptions> {
+    let (_, header) = read_first_frame(path)?;
+    Some(EncodingOptions {
+        sample_rate: header.sample_rate,
+        bitrate_kbps: header.bitrate / 1000,
+        stereo: !header.mono,
+    })
+}
+
+/// Returns the length, in seconds, of the MP3 made by `encode_mp3` at the given path. The MP3s are
+/// constant-bitrate, so this is just the size of the audio over the bitrate of the first frame.
+pub fn mp3_duration_secs(path: &Path) -> Option<u64> {
+    let (audio_start, header) = read_first_frame(path)?;
+    let audio_len = fs::metadata(path).ok()?.len() - audio_start as u64;
+    Some(audio_len * 8 / u64::from(header.bitrate))
+}
+
+/// Returns the given MP3 with an Info frame at the start of its audio, replacing the one that's
+/// there, if any. The Info frame says how many frames and bytes the audio has, which lets players
+/// show the duration and seek before they've downloaded the whole file. Returns `None` if there's
+/// no audio.
+fn with_info_frame(mp3: &[u8]) -> Option<Vec<u8>> {
+    let audio_start = id3_len(mp3);
+    let first = parse_frame_header(mp3.get(audio_start..)?)?;
+    let is_info = |frame: &[u8], header: &FrameHeader| {
+        frame
+            .get(4 + header.side_info_len..)
+            .is_some_and(|tag| tag.starts_with(INFO_TAG) || tag.starts_with(b"Xing"))
+    };
+
+    // Skip any existing Info frame, then count the frames of audio
+    let mut frames_start = audio_start;
+    if is_info(&mp3[audio_start..], &first) {
+        frames_start += first.len;
+    }
+    let mut num_frames: u32 = 0;
+    let mut pos = frames_start;
+    while let Some(header) = mp3.get(pos..).and_then(parse_frame_header) {
+        num_frames += 1;
+        pos += header.len;
+    }
+    let audio = &mp3[frames_start..];
+    let first_audio = parse_frame_header(audio)?;
+
+    // The Info frame is a silent frame in the same format as the audio. Its header is the first
+    // audio frame's without padding, so it's the plain length.
+    let mut header = audio[..4].to_vec();
+    header[2] &= !0b10;
+    let info_len = parse_frame_header(&header)?.len;
+    let mut info = vec![0u8; info_len];
+    info[..4].copy_from_slice(&header);
+    let tag_start = 4 + first_audio.side_info_len;
+    info[tag_start..tag_start + 4].copy_from_slice(INFO_TAG);
+    // The flags say the frame and byte counts are present
+    info[tag_start + 4..tag_start + 8].copy_from_slice(&3u32.to_be_bytes());
+    info[tag_start + 8..tag_start + 12].copy_from_slice(&num_frames.to_be_bytes());
+    let num_bytes = (info_len + audio.len()) as u32;
+    info[tag_start + 12..tag_start + 16].copy_from_slice(&num_bytes.to_be_bytes());
+
+    let mut out = Vec::with_capacity(audio_start + info_len + audio.len());
+    out.extend_from_slice(&mp3[..audio_start]);
+    out.extend_from_slice(&info);
+    out.extend_from_slice(audio);
+    Some(out)
+}
+
+/// Writes an Info frame to the MP3 at the given path, replacing the one that's there, if any. This
+/// has to be done again whenever audio is appended.
+pub fn write_info_frame(path: &Path) -> Result<(), AnyError> {
+    let mp3 = fs::read(path)?;
+    let with_info =
+        with_info_frame(&mp3).ok_or_else(|| anyhow!("no MP3 frames in {}", path.display()))?;
+    fs::write(path, with_info)?;
+    Ok(())
+}
+
+#[test]
+fn silence_trimming() {
+    let padding = ms_to_samples(TRIM_PADDING_MS);
+
+    // Speech surrounded by lots of silence gets trimmed down to the padding
+    let mut samples = vec![0i16; 10_000];
+    samples.extend(vec![5000i16; 100]);
+    samples.extend(vec![0i16; 10_000]);
+    let trimmed = trim_silence(&samples);
+    assert_eq!(trimmed.len(), 100 + 2 * padding);
+
+    // Pure silence is trimmed to nothing
+    assert!(trim_silence(&[0i16; 1000]).is_empty());
+
+    // Speech with no silence around it is left alone
+    let samples = vec![-5000i16; 100];
+    assert_eq!(trim_silence(&samples), &samples[..]);
+}
+
+#[test]
+fn chunk_stitching() {
+    // Make a quiet chunk and a loud chunk
+    let quiet = vec![1000i16; 1000];
+    let loud = vec![-20000i16; 1000];
+
+    let gap_ms = 500;
+    let Stitched {
+        samples: stitched,
+        chunk_origins,
+    } = stitch(vec![quiet, vec![0; 1000], loud], gap_ms);
+
+    // The silent chunk is dropped, and there's one gap between the remaining two
+    assert_eq!(stitched.len(), 2000 + ms_to_samples(gap_ms));
+    assert_eq!(
+        chunk_origins,
+        vec![Some(0), None, Some(1000 + ms_to_samples(gap_ms) as i64)]
+    );
+
+    // Both chunks are normalized to the same loudness. Their edges are faded, so look inside them.
+    let first = stitched[500];
+    let last = stitched[stitched.len() - 500];
+    assert_eq!(first.unsigned_abs(), last.unsigned_abs());
+    assert_eq!(first.unsigned_abs(), TARGET_RMS as u16);
+    assert_eq!(stitched[0], 0);
+
+    // Trimming a chunk's leading silence moves its origin back, so times within the chunk still
+    // line up with the joined audio
+    let mut late_start = vec![0i16; 10_000];
+    late_start.extend(vec![5000i16; 100]);
+    let padding = ms_to_samples(TRIM_PADDING_MS);
+    let stitched = stitch(vec![late_start], gap_ms);
+    assert_eq!(stitched.chunk_origins, vec![Some(padding as i64 - 10_000)]);
+}
+
+#[test]
+fn smooth_joins() {
+    // How much the audio jumps between samples, on average. Clicks are big jumps.
+    fn jumpiness(samples: &[i16]) -> f64 {
+        let sum: f64 = samples
+            .windows(2)
+            .map(|w| (w[1] as f64 - w[0] as f64).powi(2))
+            .sum();
+        sum / (samples.len() - 1) as f64
+    }
+
+    // A tone that starts and stops at its peaks, the way an engine cuts off mid-waveform, with a
+    // spike near its start
+    let tone = |len: usize| -> Vec<i16> {
+        let mut samples: Vec<i16> = (0..len)
+            .map(|i| {
+                let t = i as f64 / SAMPLE_RATE as f64;
+                (8000.0 * (2.0 * std::f64::consts::PI * 200.0 * t).cos()) as i16
+            })
+            .collect();
+        samples[200] = samples[200].saturating_add(20000);
+        samples
+    };
+    let len = 4800;
+
+    // With a gap, the chunks fade out to it and in from it
+    let gap_ms = 300;
+    let stitched = stitch(vec![tone(len), tone(len)], gap_ms).samples;
+    let body = jumpiness(&stitched[1000..3000]);
+    let join = len + ms_to_samples(gap_ms);
+    let window = ms_to_samples(2 * FADE_MS);
+    for boundary in [0, len, join, stitched.len()] {
+        let around =
+            &stitched[boundary.saturating_sub(window)..(boundary + window).min(stitched.len())];
+        assert!(
+            jumpiness(around) <= 1.1 * body,
+            "click at sample {boundary}"
+        );
+    }
+    // The spike was smoothed over
+    assert!(stitched[195..205].iter().all(|s| s.unsigned_abs() < 9000));
+
+    // Without a gap, the chunks crossfade, even when the second starts at the opposite peak from
+    // where the first stopped
+    let inverted = tone(len).iter().map(|s| s.saturating_neg()).collect();
+    let Stitched {
+        samples: stitched,
+        chunk_origins,
+    } = stitch(vec![tone(len), inverted], 0);
+    let overlap = ms_to_samples(FADE_MS);
+    assert_eq!(stitched.len(), 2 * len - overlap);
+    assert_eq!(chunk_origins, vec![Some(0), Some((len - overlap) as i64)]);
+    let around = &stitched[len - overlap - window..len + window];
+    // Allow for rounding. A click would be many times the body.
+    assert!(jumpiness(around) <= 1.1 * body);
+}
+
+#[test]
+fn loudness_levels() {
+    // Normalized speech is around the middle, and silence is 0
+    let step = (SAMPLE_RATE as f64 * LOUDNESS_STEP_SECS) as usize;
+    let mut speech: Vec<i16> = (0..step * 2)
+        .map(|i| if i % 2 == 0 { 4000 } else { -4000 })
+        .collect();
+    normalize(&mut speech);
+    let mut samples = silence(1000);
+    samples.extend(&speech);
+    samples.extend(&speech[..step / 2]);
+
+    let levels = loudness(&samples);
+    assert_eq!(levels.len(), 5);
+    assert_eq!(&levels[..2], [0, 0]);
+    assert!(
+        levels[2..].iter().all(|&l| (120..=135).contains(&l)),
+        "{levels:?}"
+    );
+    assert!(loudness(&[]).is_empty());
+}
+
+#[test]
+fn resampling() {
+    // Audio at the working rate is left alone
+    let samples = vec![0i16, 100, 200, 300];
+    assert_eq!(resample(&samples, SAMPLE_RATE), samples);
+
+    // Upsampling 16kHz interpolates between the samples
+    let ramp: Vec<i16> = (0..160).map(|i| i * 10).collect();
+    let up = resample(&ramp, 16000);
+    assert_eq!(up.len(), 240);
+    assert_eq!(&up[..4], &[0, 7, 13, 20]);
+
+    // Raw PCM is little-endian
+    assert_eq!(decode_pcm(&[0x01, 0x02, 0xff], SAMPLE_RATE), vec![0x0201]);
+}
+
+#[test]
+fn info_frames() {
+    // Ten frames of 64kbps mono MPEG-2 audio at 24kHz, which are 192 bytes each, after an empty
+    // ID3 tag
+    let header = [0xFF, 0xF3, 0x84, 0xC0];
+    let frame_len = 192;
+    assert_eq!(
+        parse_frame_header(&header),
+        Some(FrameHeader {
+            len: frame_len,
+            side_info_len: 9,
+            bitrate: 64000,
+            sample_rate: 24000,
+            mono: true,
+        })
+    );
+    let mut mp3 = b"ID3\x04\0\0\0\0\0\0".to_vec();
+    for _ in 0..10 {
+        mp3.extend_from_slice(&header);
+        mp3.extend(vec![0x55; frame_len - 4]);
+    }
+
+    let with_info = with_info_frame(&mp3).unwrap();
+    assert_eq!(with_info.len(), mp3.len() + frame_len);
+    assert_eq!(&with_info[..14], &mp3[..14]);
+    let tag = &with_info[10 + 4 + 9..];
+    assert!(tag.starts_with(b"Info"));
+    assert_eq!(tag[8..12], 10u32.to_be_bytes());
+    assert_eq!(tag[12..16], (11 * frame_len as u32).to_be_bytes());
+
+    // Writing it again replaces the Info frame rather than adding another
+    assert_eq!(with_info_frame(&with_info).unwrap(), with_info);
+
+    // Appending audio updates the count
+    let mut appended = with_info.clone();
+    appended.extend_from_slice(&with_info[10 + frame_len..10 + 2 * frame_len]);
+    let tag = &with_info_frame(&appended).unwrap()[10 + 4 + 9..];
+    assert_eq!(tag[8..12], 11u32.to_be_bytes());
+
+    // Things that aren't MP3s don't get one
+    assert!(with_info_frame(b"not an mp3").is_none());
+}
+
+#[test]
+fn reading_encoding() {
+    // Two minutes of 32kbps mono MPEG-2 audio at 16kHz, which are 144 bytes per frame, after an
+    // ID3 tag with a few bytes in it
+    let header = [0xFF, 0xF3, 0x48, 0xC0];
+    let frame_len = 144;
+    let mut mp3 = b"ID3\x04\0\0\0\0\0\x03abc".to_vec();
+    for _ in 0..(120 * 32000 / 8_usize).div_ceil(frame_len) {
+        mp3.extend_from_slice(&header);
+        mp3.extend(vec![0x55; frame_len - 4]);
+    }
+    let path = std::env::temp_dir().join(format!("rtms-encoding-test-{}.mp3", std::process::id()));
+    fs::write(&path, &mp3).unwrap();
+
+    assert_eq!(
+        mp3_encoding(&path),
+        Some(EncodingOptions {
+            sample_rate: 16000,
+            bitrate_kbps: 32,
+            stereo: false,
+        })
+    );
+    assert_eq!(mp3_duration_secs(&path), Some(120));
+    fs::remove_file(&path).unwrap();
+
+    // Options that an MP3 can't have are moved to the closest ones it can
+    let clamped = EncodingOptions {
+        sample_rate: 23000,
+        bitrate_kbps: 200,
+        stereo: true,
+    }
+    .clamped();
+    assert_eq!((clamped.sample_rate, clamped.bitrate_kbps), (22050, 160));
+    let clamped = EncodingOptions {
+        sample_rate: 48000,
+        bitrate_kbps: 8,
+        ..Default::default()
+    }
+    .clamped();
+    assert_eq!(clamped.bitrate_kbps, 32);
+}
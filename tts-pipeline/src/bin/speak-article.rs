@@ -0,0 +1,80 @@
+//! Reads the article at a URL aloud into an MP3, without a server. Pages are fetched over plain
+//! HTTP, extracted with trafilatura, and spoken by a TTS program on this machine, e.g., Piper.
+
+use std::path::PathBuf;
+
+use anyhow::Error as AnyError;
+use clap::Parser;
+use common::{SpeakingStyle, SynthesisOptions};
+use tts_pipeline::{
+    article::{speak_article, HttpFetcher, Trafilatura},
+    engines::LocalEngine,
+    Voice,
+};
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "speak-article",
+    about = "Reads the article at a URL aloud into an MP3"
+)]
+struct Opt {
+    /// The URL of the article
+    url: String,
+
+    /// Where to write the MP3
+    #[clap(short = 'o', long = "out", default_value = "article.mp3")]
+    out: PathBuf,
+
+    /// The TTS program. It reads text on its stdin and writes audio to its stdout.
+    #[clap(long = "tts-command")]
+    tts_command: PathBuf,
+
+    /// An argument to the TTS program. Can be given more than once.
+    #[clap(long = "tts-arg")]
+    tts_args: Vec<String>,
+
+    /// The sample rate of the TTS program's raw output. Leave it out if it outputs WAV.
+    #[clap(long = "sample-rate")]
+    sample_rate: Option<u32>,
+
+    /// The name of the TTS program's voice, e.g., `en-US-amy`
+    #[clap(long = "voice", default_value = "en-US-local")]
+    voice: String,
+
+    /// The trafilatura CLI
+    #[clap(long = "trafilatura", default_value = "../python_deps/bin/trafilatura")]
+    trafilatura: PathBuf,
+
+    /// Where trafilatura's Python packages are, if they aren't installed system-wide
+    #[clap(long = "python-path", default_value = "../python_deps")]
+    python_path: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), AnyError> {
+    let opt = Opt::parse();
+
+    let fetcher = HttpFetcher::new()?;
+    let extractor = Trafilatura::new(&opt.trafilatura, Some(&opt.python_path));
+    let engine = LocalEngine::new(&opt.tts_command, &opt.tts_args, opt.sample_rate, &opt.voice);
+    let (article, output) = speak_article(
+        &fetcher,
+        &extractor,
+        &engine,
+        &opt.url,
+        Voice::builtin(&opt.voice),
+        SpeakingStyle::default(),
+        SynthesisOptions::default(),
+    )
+    .await?;
+
+    std::fs::write(&opt.out, &output.mp3)?;
+    println!(
+        "Wrote \"{}\" to {:?} ({:.0}s)",
+        article.title, opt.out, output.duration_secs
+    );
+    if let Some((_, e)) = output.unfinished {
+        eprintln!("Only part of the article was spoken: {e}");
+    }
+    Ok(())
+}
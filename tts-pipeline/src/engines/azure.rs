@@ -0,0 +1,97 @@
+//! A barebones client to Azure Speech's text-to-speech REST API
+
+use super::{strip_marks, SynthesizedChunk, TtsEngine};
+use crate::{audio, ssml::escape, tts::TtsRequest};
+
+use anyhow::{Context, Error as AnyError};
+use async_trait::async_trait;
+
+/// The most characters of SSML sent in one request. Azure allows more, but a request also can't
+/// produce more than 10 minutes of audio, and this keeps well clear of that.
+const MAX_CHARS_PER_REQUEST: usize = 5000;
+
+/// The audio format requested. This is a WAV at `audio::SAMPLE_RATE`.
+const OUTPUT_FORMAT: &str = "riff-24khz-16bit-mono-pcm";
+
+/// The voices this engine offers, along with a human-readable description of each. See
+/// https://learn.microsoft.com/azure/cognitive-services/speech-service/language-support for the
+/// full list.
+const VOICES: &[(&str, &str)] = &[
+    ("en-US-JennyNeural", "US English, female (Neural)"),
+    ("en-US-GuyNeural", "US English, male (Neural)"),
+    ("en-US-AriaNeural", "US English, female (Neural)"),
+    ("en-GB-SoniaNeural", "British English, female (Neural)"),
+    ("en-GB-RyanNeural", "British English, male (Neural)"),
+    ("en-AU-NatashaNeural", "Australian English, female (Neural)"),
+    ("ar-SA-ZariyahNeural", "Arabic, female (Neural)"),
+    ("he-IL-HilaNeural", "Hebrew, female (Neural)"),
+    ("zh-CN-XiaoxiaoNeural", "Mandarin Chinese, female (Neural)"),
+    ("ja-JP-NanamiNeural", "Japanese, female (Neural)"),
+    ("ko-KR-SunHiNeural", "Korean, female (Neural)"),
+];
+
+/// Azure Speech
+pub struct AzureEngine {
+    /// The URL of the TTS endpoint of the Speech resource's region
+    endpoint: String,
+    key: String,
+}
+
+impl AzureEngine {
+    pub fn new(region: &str, key: String) -> AzureEngine {
+        AzureEngine {
+            endpoint: format!("https://{region}.tts.speech.microsoft.com/cognitiveservices/v1"),
+            key,
+        }
+    }
+}
+
+/// Returns the SSML sent for the given request. Azure wants the voice in the SSML itself, and the
+/// REST API doesn't report marks, so they're left out.
+fn azure_ssml(req: &TtsRequest) -> String {
+    let ssml = strip_marks(&req.ssml());
+    let body = ssml
+        .strip_prefix("<speak>")
+        .and_then(|s| s.strip_suffix("</speak>"))
+        .unwrap_or(&ssml);
+    format!(
+        "<speak version=\"1.0\" xmlns=\"http://www.w3.org/2001/10/synthesis\" xml:lang=\"{}\">\
+        <voice name=\"{}\">{body}</voice></speak>",
+        escape(&req.voice.language_code),
+        escape(&req.voice.name),
+    )
+}
+
+#[async_trait]
+impl TtsEngine for AzureEngine {
+    fn voices(&self) -> Vec<(&str, &str)> {
+        VOICES.to_vec()
+    }
+
+    fn max_request_len(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+
+    fn request_len(&self, req: &TtsRequest) -> usize {
+        azure_ssml(req).len()
+    }
+
+    async fn synthesize(&self, req: &TtsRequest) -> Result<SynthesizedChunk, AnyError> {
+        let client = reqwest::Client::new();
+        let res = client
+            .post(&self.endpoint)
+            .header("Ocp-Apim-Subscription-Key", &self.key)
+            .header("Content-Type", "application/ssml+xml")
+            .header("X-Microsoft-OutputFormat", OUTPUT_FORMAT)
+            .header("User-Agent", "readtomyshoe")
+            .body(azure_ssml(req))
+            .send()
+            .await
+            .with_context(|| "Couldn't make TTS request")?
+            .error_for_status()
+            .with_context(|| "TTS request failed")?;
+
+        let wav = res.bytes().await?;
+        Ok(SynthesizedChunk::unmarked(audio::decode_wav(&wav)?))
+    }
+}
@@ -0,0 +1,133 @@
+//! A barebones client to the Google Cloud TTS service
+
+use super::{SynthesizedChunk, TtsEngine};
+use crate::{audio, tts::TtsRequest};
+
+use anyhow::{Context, Error as AnyError};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+//const GCP_API_BASE: &str = "https://texttospeech.googleapis.com/v1";
+const GCP_TTS_API: &str = "https://texttospeech.googleapis.com/v1beta1/text:synthesize";
+
+// See https://cloud.google.com/text-to-speech/quotas
+const MAX_CHARS_PER_REQUEST: usize = 5000;
+
+/// The voices this engine offers, along with a human-readable description of each. See
+/// https://cloud.google.com/text-to-speech/docs/voices for the full list.
+const VOICES: &[(&str, &str)] = &[
+    ("en-US-Wavenet-C", "US English, female (WaveNet)"),
+    ("en-US-Wavenet-D", "US English, male (WaveNet)"),
+    ("en-US-Wavenet-F", "US English, female (WaveNet)"),
+    ("en-GB-Wavenet-B", "British English, male (WaveNet)"),
+    ("en-GB-Wavenet-C", "British English, female (WaveNet)"),
+    ("en-AU-Wavenet-C", "Australian English, female (WaveNet)"),
+    ("en-US-Standard-C", "US English, female (Standard)"),
+    ("ar-XA-Wavenet-A", "Arabic, female (WaveNet)"),
+    ("he-IL-Wavenet-A", "Hebrew, female (WaveNet)"),
+    ("cmn-CN-Wavenet-A", "Mandarin Chinese, female (WaveNet)"),
+    ("ja-JP-Wavenet-B", "Japanese, female (WaveNet)"),
+    ("ko-KR-Wavenet-A", "Korean, female (WaveNet)"),
+];
+
+#[derive(Deserialize)]
+struct AudioResponse<'a> {
+    #[serde(borrow, rename = "audioContent")]
+    audio_content: &'a str,
+    /// When each `<mark>` in the SSML was reached
+    #[serde(default)]
+    timepoints: Vec<MarkTimepoint>,
+}
+
+#[derive(Deserialize)]
+struct MarkTimepoint {
+    #[serde(rename = "markName")]
+    mark_name: String,
+    #[serde(rename = "timeSeconds")]
+    time_seconds: f64,
+}
+
+/// Google Cloud TTS
+pub struct GoogleEngine {
+    api_key: String,
+}
+
+impl GoogleEngine {
+    pub fn new(api_key: String) -> GoogleEngine {
+        GoogleEngine { api_key }
+    }
+}
+
+/// Returns the JSON body of the given request
+fn to_json(req: &TtsRequest) -> serde_json::Value {
+    // Custom voices are referred to by their model rather than by name
+    let voice = match &req.voice.custom_model {
+        Some(model) => serde_json::json!({
+            "languageCode": req.voice.language_code,
+            "customVoice": {
+                "model": model,
+                "reportedUsage": "REALTIME",
+            },
+        }),
+        None => serde_json::json!({
+            "languageCode": req.voice.language_code,
+            "name": req.voice.name,
+        }),
+    };
+
+    serde_json::json!({
+        "input": {
+            "ssml": req.ssml()
+        },
+        "voice": voice,
+        "audioConfig":{
+            "audioEncoding": "LINEAR16",
+            "sampleRateHertz": audio::SAMPLE_RATE
+        },
+        "enableTimePointing": ["SSML_MARK"]
+    })
+}
+
+#[async_trait]
+impl TtsEngine for GoogleEngine {
+    fn voices(&self) -> Vec<(&str, &str)> {
+        VOICES.to_vec()
+    }
+
+    fn max_request_len(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+
+    async fn synthesize(&self, req: &TtsRequest) -> Result<SynthesizedChunk, AnyError> {
+        let payload = to_json(req);
+
+        // Do the HTTP request
+        let client = reqwest::Client::new();
+        let url = reqwest::Url::parse_with_params(GCP_TTS_API, &[("key", &self.api_key)])?;
+        let res = client
+            .post(url)
+            .json(&payload)
+            .send()
+            .await
+            .with_context(|| "Couldn't make TTS request")?
+            .error_for_status()
+            .with_context(|| "TTS request failed")?;
+
+        // The resulting JSON response has our WAV data
+        let res_bytes = res.bytes().await?;
+        let audio_response: AudioResponse = serde_json::from_slice(&res_bytes)?;
+        let wav = base64::decode(audio_response.audio_content)?;
+
+        // The marks are named after the offsets of the paragraphs they precede
+        let marks = audio_response
+            .timepoints
+            .iter()
+            .filter_map(|tp| Some((tp.mark_name.parse().ok()?, tp.time_seconds)))
+            .collect();
+
+        Ok(SynthesizedChunk {
+            samples: audio::decode_wav(&wav)?,
+            marks,
+        })
+    }
+}
@@ -0,0 +1,96 @@
+//! Runs a TTS program on this machine, e.g., Piper. This way, articles can be converted without a
+//! cloud service.
+
+use super::{SynthesizedChunk, TtsEngine};
+use crate::{audio, tts::TtsRequest};
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use async_process::Command;
+use async_trait::async_trait;
+use futures::io::AsyncWriteExt;
+
+/// The most characters of text sent to the program at once. There's no limit as such, but smaller
+/// requests let a job be paused or cancelled sooner.
+const MAX_CHARS_PER_REQUEST: usize = 5000;
+
+/// A TTS program that reads text on its stdin and writes audio to its stdout
+pub struct LocalEngine {
+    command: PathBuf,
+    args: Vec<String>,
+    /// The sample rate of the program's raw output, or `None` if it outputs WAV
+    sample_rate: Option<u32>,
+    /// The name the program's voice is listed under
+    voice: String,
+}
+
+impl LocalEngine {
+    pub fn new(
+        command: &Path,
+        args: &[String],
+        sample_rate: Option<u32>,
+        voice: &str,
+    ) -> LocalEngine {
+        LocalEngine {
+            command: command.to_path_buf(),
+            args: args.to_vec(),
+            sample_rate,
+            voice: voice.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl TtsEngine for LocalEngine {
+    fn voices(&self) -> Vec<(&str, &str)> {
+        vec![(&self.voice, "Local voice")]
+    }
+
+    fn max_request_len(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+
+    /// The program is given plain text, so there's no markup to count
+    fn request_len(&self, req: &TtsRequest) -> usize {
+        req.text.len()
+    }
+
+    async fn synthesize(&self, req: &TtsRequest) -> Result<SynthesizedChunk, AnyError> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("IO error running {:?}: {:?}", self.command, e))?;
+
+        // The program may write output as it reads input, so feed stdin and collect stdout at the
+        // same time. Otherwise both pipes can fill up and we deadlock.
+        let mut stdin = child.stdin.take().unwrap();
+        let text = req.text.clone();
+        let write_input = async move {
+            stdin.write_all(text.as_bytes()).await?;
+            stdin.close().await
+        };
+        let (write_res, output) = futures::join!(write_input, child.output());
+
+        let output = output.map_err(|e| anyhow!("IO error running {:?}: {:?}", self.command, e))?;
+        if !output.status.success() {
+            bail!(
+                "TTS program failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        write_res.map_err(|e| anyhow!("IO error writing to {:?}: {:?}", self.command, e))?;
+
+        let samples = match self.sample_rate {
+            Some(rate) => audio::decode_pcm(&output.stdout, rate),
+            None => audio::decode_wav(&output.stdout)?,
+        };
+        Ok(SynthesizedChunk::unmarked(samples))
+    }
+}
@@ -0,0 +1,87 @@
+//! The TTS engines articles can be converted with. The engines all produce the same thing, i.e.,
+//! samples at `audio::SAMPLE_RATE`, so everything after synthesis is the same whichever engine did
+//! it.
+
+mod azure;
+mod google;
+mod local;
+mod polly;
+
+use crate::tts::TtsRequest;
+
+use anyhow::Error as AnyError;
+use async_trait::async_trait;
+
+pub use azure::AzureEngine;
+pub use google::GoogleEngine;
+pub use local::LocalEngine;
+pub use polly::PollyEngine;
+
+/// The audio of a single TTS request
+pub struct SynthesizedChunk {
+    pub samples: Vec<i16>,
+    /// The byte offsets in the request's text where paragraphs start, and when they're spoken in
+    /// `samples`, in seconds
+    pub marks: Vec<(usize, f64)>,
+}
+
+impl SynthesizedChunk {
+    /// Makes a chunk for engines that can't say when each paragraph is spoken. All that's known is
+    /// that the first paragraph starts at the beginning.
+    fn unmarked(samples: Vec<i16>) -> SynthesizedChunk {
+        SynthesizedChunk {
+            samples,
+            marks: vec![(0, 0.0)],
+        }
+    }
+}
+
+/// A text-to-speech service
+#[async_trait]
+pub trait TtsEngine: Send + Sync {
+    /// The voices the engine offers, along with a human-readable description of each. The first one
+    /// is the default.
+    fn voices(&self) -> Vec<(&str, &str)>;
+
+    /// The most characters the input of a single request can have
+    fn max_request_len(&self) -> usize;
+
+    /// The number of characters the given request's input is. By default, requests are sent as
+    /// SSML, so the markup counts too.
+    fn request_len(&self, req: &TtsRequest) -> usize {
+        req.ssml().len()
+    }
+
+    /// Speaks the given request, which is at most `max_request_len` characters, and returns the
+    /// samples and when each paragraph starts
+    async fn synthesize(&self, req: &TtsRequest) -> Result<SynthesizedChunk, AnyError>;
+}
+
+/// Removes the `<mark>`s from the given SSML, for engines that don't support them
+fn strip_marks(ssml: &str) -> String {
+    let mut out = String::with_capacity(ssml.len());
+    let mut rest = ssml;
+    while let Some(start) = rest.find("<mark ") {
+        out.push_str(&rest[..start]);
+        match rest[start..].find("/>") {
+            Some(end) => rest = &rest[start + end + 2..],
+            None => {
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[test]
+fn mark_stripping() {
+    assert_eq!(
+        strip_marks("<speak><mark name=\"0\"/><p>Hi.</p><mark name=\"4\"/><p>Bye.</p></speak>"),
+        "<speak><p>Hi.</p><p>Bye.</p></speak>"
+    );
+    assert_eq!(
+        strip_marks("<speak>No marks</speak>"),
+        "<speak>No marks</speak>"
+    );
+}
@@ -0,0 +1,165 @@
+//! A barebones client to Amazon Polly. Requests are signed with AWS Signature Version 4 by hand,
+//! since that's all this needs from an AWS SDK.
+
+use super::{SynthesizedChunk, TtsEngine};
+use crate::{audio, tts::TtsRequest};
+
+use anyhow::{Context, Error as AnyError};
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// The most characters of SSML sent in one request. Polly allows 6000 in all, of which 3000 can be
+/// billed, i.e., not markup.
+const MAX_CHARS_PER_REQUEST: usize = 3000;
+
+/// The sample rate Polly is asked for. Its raw audio only comes in 8kHz and 16kHz.
+const POLLY_SAMPLE_RATE: u32 = 16000;
+
+/// The voices this engine offers, along with a human-readable description of each. See
+/// https://docs.aws.amazon.com/polly/latest/dg/voicelist.html for the full list.
+const VOICES: &[(&str, &str)] = &[
+    ("Joanna", "US English, female"),
+    ("Matthew", "US English, male"),
+    ("Salli", "US English, female"),
+    ("Amy", "British English, female"),
+    ("Brian", "British English, male"),
+    ("Nicole", "Australian English, female"),
+];
+
+/// Amazon Polly
+pub struct PollyEngine {
+    region: String,
+    access_key_id: String,
+    secret_key: String,
+    neural: bool,
+}
+
+impl PollyEngine {
+    pub fn new(region: &str, access_key_id: &str, secret_key: String, neural: bool) -> PollyEngine {
+        PollyEngine {
+            region: region.to_string(),
+            access_key_id: access_key_id.to_string(),
+            secret_key,
+            neural,
+        }
+    }
+}
+
+/// Returns the HMAC-SHA256 of the given data under the given key
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Returns the given bytes in lowercase hex
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Returns the SHA-256 of the given data, in hex
+fn sha256_hex(data: &str) -> String {
+    hex(&Sha256::digest(data.as_bytes()))
+}
+
+/// Signs the given string-to-sign with a key derived from the secret key, the date (`YYYYMMDD`),
+/// the region, and the service. Returns the signature in hex.
+fn sign(secret_key: &str, date: &str, region: &str, service: &str, string_to_sign: &str) -> String {
+    let key = hmac_sha256(format!("AWS4{secret_key}").as_bytes(), date);
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, service);
+    let key = hmac_sha256(&key, "aws4_request");
+    hex(&hmac_sha256(&key, string_to_sign))
+}
+
+#[async_trait]
+impl TtsEngine for PollyEngine {
+    fn voices(&self) -> Vec<(&str, &str)> {
+        VOICES.to_vec()
+    }
+
+    fn max_request_len(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+
+    async fn synthesize(&self, req: &TtsRequest) -> Result<SynthesizedChunk, AnyError> {
+        let host = format!("polly.{}.amazonaws.com", self.region);
+        let payload = serde_json::json!({
+            "Engine": if self.neural { "neural" } else { "standard" },
+            "OutputFormat": "pcm",
+            "SampleRate": POLLY_SAMPLE_RATE.to_string(),
+            "Text": req.ssml(),
+            "TextType": "ssml",
+            "VoiceId": req.voice.name,
+        })
+        .to_string();
+
+        // Sign the request. See
+        // https://docs.aws.amazon.com/general/latest/gr/sigv4_signing.html
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let signed_headers = "content-type;host;x-amz-date";
+        let canonical_request = format!(
+            "POST\n/v1/speech\n\ncontent-type:application/json\nhost:{host}\n\
+            x-amz-date:{amz_date}\n\n{signed_headers}\n{}",
+            sha256_hex(&payload),
+        );
+        let scope = format!("{date}/{}/polly/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            sha256_hex(&canonical_request)
+        );
+        let signature = sign(
+            &self.secret_key,
+            &date,
+            &self.region,
+            "polly",
+            &string_to_sign,
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+            Signature={signature}",
+            self.access_key_id,
+        );
+
+        let client = reqwest::Client::new();
+        let res = client
+            .post(format!("https://{host}/v1/speech"))
+            .header("Content-Type", "application/json")
+            .header("X-Amz-Date", &amz_date)
+            .header("Authorization", authorization)
+            .body(payload)
+            .send()
+            .await
+            .with_context(|| "Couldn't make TTS request")?
+            .error_for_status()
+            .with_context(|| "TTS request failed")?;
+
+        let pcm = res.bytes().await?;
+        Ok(SynthesizedChunk::unmarked(audio::decode_pcm(
+            &pcm,
+            POLLY_SAMPLE_RATE,
+        )))
+    }
+}
+
+#[test]
+fn signing() {
+    // The example from AWS's documentation of Signature Version 4
+    let string_to_sign =
+        "AWS4-HMAC-SHA256\n20150830T123600Z\n20150830/us-east-1/iam/aws4_request\n\
+        f536975d06c0309214f805bb90ccff089219ecd68b2577efef23edd43b7e1a59";
+    assert_eq!(
+        sign(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20150830",
+            "us-east-1",
+            "iam",
+            string_to_sign,
+        ),
+        "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+    );
+}
@@ -0,0 +1,26 @@
+//! Turns text into an article's audio: the text is broken into requests a TTS engine can take,
+//! every request is synthesized, with retries, and the chunks that come back are cleaned up,
+//! stitched together, and encoded to MP3, along with when each paragraph is spoken.
+//!
+//! The engines are behind the `TtsEngine` trait, and everything that outlives a synthesis is
+//! behind traits too: a `SynthesisJob` hears how it's going and decides when each request can be
+//! made, and a `ChunkStore` keeps the synthesized chunks, so a synthesis that failed partway through
+//! can be finished without paying for the same chunks twice. The server implements them with its
+//! job queue and pending conversions. Anything else can leave them out.
+//!
+//! Articles are read off the web the same way: pages come from a `Fetch` and their text from an
+//! `Extract`. The crate has plain implementations of both, over HTTP and with trafilatura, which
+//! the `speak-article` CLI uses. The server fetches through its page cache instead, and picks
+//! between extractors with its per-site rules and plugins.
+
+pub mod article;
+pub mod audio;
+pub mod engines;
+pub mod ssml;
+pub mod tts;
+pub mod voice;
+
+pub use article::{Extract, ExtractedArticle, Fetch, Page};
+pub use engines::{SynthesizedChunk, TtsEngine};
+pub use tts::{tts, ChunkStore, SynthesisJob, TtsOutput, TtsRequest};
+pub use voice::Voice;
@@ -0,0 +1,217 @@
+//! Builds the SSML that's sent to the TTS engine. This is where speaking styles get mapped to
+//! engine-specific markup.
+
+use crate::voice::Voice;
+use common::{SpeakingStyle, SynthesisOptions};
+
+/// Paragraphs longer than this, in characters, are never considered headings
+const MAX_HEADING_LEN: usize = 100;
+
+/// The number of characters of markup that wrapping a chunk of text in SSML typically adds. Text
+/// chunks should leave this much room under the engine's request limit.
+pub const SSML_OVERHEAD: usize = 200;
+
+/// The markup that asides are wrapped in when they're softened
+const ASIDE_OPEN: &str = "<prosody volume=\"-6dB\" pitch=\"-1st\">";
+const ASIDE_CLOSE: &str = "</prosody>";
+
+/// Returns whether the given character is an invisible bidirectional formatting mark, e.g., the
+/// right-to-left mark. Pages with Arabic or Hebrew use these to lay out mixed-direction text, but
+/// they mean nothing to TTS engines, and some read them as pauses.
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200E}' | '\u{200F}' | '\u{061C}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Pushes the given character to the string, escaping it if it's special in XML. Bidirectional
+/// formatting marks are dropped.
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        c if is_bidi_control(c) => (),
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        '\'' => out.push_str("&apos;"),
+        c => out.push(c),
+    }
+}
+
+/// Escapes the characters that are special in XML
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        push_escaped(&mut out, c);
+    }
+    out
+}
+
+/// Escapes the given paragraph, and reads its parenthetical asides and bracketed footnotes more
+/// quietly and a little lower, so they can be told apart from the main text by ear
+fn escape_with_soft_asides(paragraph: &str) -> String {
+    let mut out = String::with_capacity(paragraph.len());
+    // How many brackets deep we are. Only the outermost aside gets markup.
+    let mut depth = 0usize;
+
+    for c in paragraph.chars() {
+        match c {
+            '(' | '[' => {
+                if depth == 0 {
+                    out.push_str(ASIDE_OPEN);
+                }
+                depth += 1;
+                push_escaped(&mut out, c);
+            }
+            ')' | ']' if depth > 0 => {
+                push_escaped(&mut out, c);
+                depth -= 1;
+                if depth == 0 {
+                    out.push_str(ASIDE_CLOSE);
+                }
+            }
+            c => push_escaped(&mut out, c),
+        }
+    }
+
+    // Close an aside that was never closed in the text
+    if depth > 0 {
+        out.push_str(ASIDE_CLOSE);
+    }
+
+    out
+}
+
+/// Google only supports `<google:style>` on some of its Neural2 voices. Everything else has to
+/// make do with prosody changes.
+fn supports_google_style(voice: &Voice) -> bool {
+    voice.custom_model.is_none() && voice.name.contains("-Neural2-")
+}
+
+/// Guesses whether the given paragraph is a heading. Extracted text has no markup, so we go by
+/// shape: headings are short and don't end like sentences do.
+pub fn is_heading(paragraph: &str) -> bool {
+    let ends_like_sentence = paragraph
+        .chars()
+        .last()
+        .map(|c| ".!?,;:\"'”’)".contains(c))
+        .unwrap_or(true);
+    paragraph.chars().count() <= MAX_HEADING_LEN && !ends_like_sentence
+}
+
+/// Converts the given text to SSML that reads it in the given style, with the pauses given in
+/// `options` between paragraphs and after headings, and with asides softened if `options` says so.
+/// Every paragraph is preceded by a `<mark>` named after the byte offset of the paragraph in
+/// `text`, so the engine can report when each paragraph is spoken.
+pub fn to_ssml(
+    text: &str,
+    style: SpeakingStyle,
+    voice: &Voice,
+    options: &SynthesisOptions,
+) -> String {
+    let mut paragraphs = String::new();
+    let mut prev_was_heading = None;
+    for p in text.split('\n').map(str::trim).filter(|p| !p.is_empty()) {
+        // Pause after the previous paragraph, if there was one
+        if let Some(prev_was_heading) = prev_was_heading {
+            let pause_ms = if prev_was_heading {
+                options.heading_pause_ms
+            } else {
+                options.paragraph_gap_ms
+            };
+            paragraphs.push_str(&format!("<break time=\"{pause_ms}ms\"/>"));
+        }
+
+        let escaped = if options.soften_asides {
+            escape_with_soft_asides(p)
+        } else {
+            escape(p)
+        };
+        // The paragraphs point into the text, so their offsets are just pointer differences
+        let offset = p.as_ptr() as usize - text.as_ptr() as usize;
+        paragraphs.push_str(&format!("<mark name=\"{offset}\"/><p>{escaped}</p>"));
+        prev_was_heading = Some(is_heading(p));
+    }
+
+    let body = match style {
+        SpeakingStyle::Neutral => paragraphs,
+        // Read a bit slower and lower
+        SpeakingStyle::Narration => {
+            format!("<prosody rate=\"95%\" pitch=\"-1st\">{paragraphs}</prosody>")
+        }
+        SpeakingStyle::Newscast => {
+            format!("<prosody rate=\"108%\" pitch=\"-0.5st\">{paragraphs}</prosody>")
+        }
+        SpeakingStyle::Cheerful if supports_google_style(voice) => {
+            format!("<google:style name=\"lively\">{paragraphs}</google:style>")
+        }
+        SpeakingStyle::Cheerful => {
+            format!("<prosody rate=\"105%\" pitch=\"+2st\">{paragraphs}</prosody>")
+        }
+    };
+
+    format!("<speak>{body}</speak>")
+}
+
+#[test]
+fn ssml_styles() {
+    let voice = Voice::default();
+    let text = "Fish & chips.\nThe <end>.";
+
+    let options = SynthesisOptions {
+        paragraph_gap_ms: 600,
+        heading_pause_ms: 1200,
+        ..Default::default()
+    };
+
+    // Text is escaped, paragraphs are separated by the gap, and every paragraph is marked with its
+    // offset
+    let neutral = to_ssml(text, SpeakingStyle::Neutral, &voice, &options);
+    assert_eq!(
+        neutral,
+        "<speak><mark name=\"0\"/><p>Fish &amp; chips.</p><break time=\"600ms\"/>\
+         <mark name=\"14\"/><p>The &lt;end&gt;.</p></speak>"
+    );
+
+    // Headings get a longer pause
+    let with_heading = to_ssml(
+        "A Heading\nFish & chips.",
+        SpeakingStyle::Neutral,
+        &voice,
+        &options,
+    );
+    assert!(with_heading.contains("<p>A Heading</p><break time=\"1200ms\"/>"));
+
+    // Styles wrap the paragraphs
+    let newscast = to_ssml(text, SpeakingStyle::Newscast, &voice, &options);
+    assert!(newscast.starts_with("<speak><prosody"));
+    assert!(newscast.contains("<p>Fish &amp; chips.</p>"));
+
+    // Cheerful uses google:style only where it's supported
+    let cheerful = to_ssml(text, SpeakingStyle::Cheerful, &voice, &options);
+    assert!(!cheerful.contains("google:style"));
+}
+
+#[test]
+fn soft_asides() {
+    // Asides are wrapped, and nested brackets don't open a second aside
+    let text = "Cats (the [1] best) are <great>[2].";
+    assert_eq!(
+        escape_with_soft_asides(text),
+        format!(
+            "Cats {ASIDE_OPEN}(the [1] best){ASIDE_CLOSE} are &lt;great&gt;\
+             {ASIDE_OPEN}[2]{ASIDE_CLOSE}."
+        )
+    );
+
+    // Unbalanced brackets still produce well-formed markup
+    assert_eq!(
+        escape_with_soft_asides("a) b (c"),
+        format!("a) b {ASIDE_OPEN}(c{ASIDE_CLOSE}")
+    );
+}
+
+#[test]
+fn bidi_controls() {
+    // Direction marks are dropped, and the text around them is left alone
+    let text = "\u{202B}שלום\u{202C} means \u{200F}peace\u{200E} & more";
+    assert_eq!(escape(text), "שלום means peace &amp; more");
+}
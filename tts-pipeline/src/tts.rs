@@ -0,0 +1,857 @@
+//! Turns text into audio with the given TTS engine
+
+use crate::{
+    audio::{self, BedMix},
+    engines::{SynthesizedChunk, TtsEngine},
+    ssml,
+    voice::Voice,
+};
+use common::{SpeakingStyle, SynthesisOptions};
+
+use anyhow::{anyhow, bail, Error as AnyError};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+
+use core::iter;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The smallest text chunk we're willing to make when breaking up text
+const MIN_CHUNK_SIZE: usize = 500;
+
+/// The maximum number of TTS requests that are in flight at once for a single article
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// How many times a TTS request is made before it's given up on
+const MAX_ATTEMPTS: u32 = 4;
+
+/// How long to wait before retrying a failed TTS request. Every retry after the first waits twice
+/// as long as the one before.
+#[cfg(not(test))]
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(2);
+#[cfg(test)]
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(1);
+
+/// Where a synthesis says how it's going, and waits its turn to make each TTS request, e.g., a job
+/// in a queue that can be paused and cancelled
+#[async_trait]
+pub trait SynthesisJob: Sync {
+    /// Notes that the text will be spoken by the given voice in the given number of requests,
+    /// which have the given number of characters between them
+    fn set_plan(&self, voice: &str, requests: usize, chars: usize);
+
+    /// Notes that a request of the given number of characters is done
+    fn chunk_done(&self, chars: usize);
+
+    /// Hands over the MP3 of the next chunk, so it can be listened to before the whole text is done
+    fn push_audio(&self, mp3: Bytes);
+
+    /// Returns whether the synthesis was cancelled, in which case nothing's kept
+    fn is_cancelled(&self) -> bool;
+
+    /// Waits until a request can be made, and returns what holds its place until it's done. Fails
+    /// if the synthesis is cancelled while waiting.
+    async fn request_slot(&self) -> Result<Box<dyn Send>, AnyError>;
+}
+
+/// Where the synthesized chunks of a text are kept, so they don't have to be synthesized again
+pub trait ChunkStore: Sync {
+    /// Returns the chunk that the given request was synthesized into, if it's kept
+    fn get(&self, req: &TtsRequest) -> Option<SynthesizedChunk>;
+
+    /// Keeps the chunk that the given request was synthesized into
+    fn put(&self, req: &TtsRequest, chunk: &SynthesizedChunk) -> Result<(), AnyError>;
+}
+
+#[derive(Clone, Debug)]
+pub struct TtsRequest {
+    /// The contents of the request
+    pub text: String,
+    /// The voice to use
+    pub voice: Voice,
+    /// The style to speak in
+    pub style: SpeakingStyle,
+    /// How to produce the audio
+    pub options: SynthesisOptions,
+}
+
+impl TtsRequest {
+    /// Returns the SSML that's sent to the TTS engine
+    pub fn ssml(&self) -> String {
+        ssml::to_ssml(&self.text, self.style, &self.voice, &self.options)
+    }
+}
+
+/// The result of a synthesis that may have failed partway through
+pub struct TtsOutput {
+    /// The MP3 of everything that was successfully synthesized
+    pub mp3: Bytes,
+    /// If synthesis failed partway through, this is the text that's yet to be synthesized, and the
+    /// error that stopped it
+    pub unfinished: Option<(String, AnyError)>,
+    /// The byte offsets in the text where paragraphs start, and when they're spoken in the MP3, in
+    /// seconds. Only paragraphs that were synthesized are included.
+    pub timepoints: Vec<(usize, f64)>,
+    /// The length of the MP3, in seconds
+    pub duration_secs: f64,
+    /// How loud the MP3 is over time, as in `ArticleTranscript::loudness`
+    pub loudness: Vec<u8>,
+}
+
+/// Speaks text string and returns the resulting MP3, with the given ambient bed mixed under it. If
+/// `continues_audio` is set, the output is meant to be appended to existing audio, so it starts with
+/// a paragraph gap.
+///
+/// If a job is given, its progress is reported to it, and every TTS request waits its turn in the
+/// job queue. The job can pause or cancel synthesis between TTS requests. Synthesized chunks are
+/// also handed to the job as they come in, so they can be listened to right away.
+///
+/// Failed TTS requests are retried a few times. If a chunk cache is given, every chunk is saved to
+/// it, and chunks that are already in it aren't synthesized again. If a request fails for good, no
+/// new requests are made, but the ones in flight finish and are saved, for when the synthesis is
+/// finished.
+///
+/// If synthesis fails partway through, the successfully synthesized beginning of the text is kept,
+/// and the rest is reported in the output. Returns an error if nothing could be synthesized, if the
+/// job was cancelled, or if encoding fails.
+pub async fn tts(
+    engine: &dyn TtsEngine,
+    TtsRequest {
+        text,
+        voice,
+        style,
+        options,
+    }: TtsRequest,
+    ambient_bed: Option<BedMix<'_>>,
+    continues_audio: bool,
+    job: Option<&dyn SynthesisJob>,
+    cache: Option<&dyn ChunkStore>,
+) -> Result<TtsOutput, AnyError> {
+    let options = options.clamped();
+
+    // Break up the TTS tasks into smaller ones the engine can take. Each one waits for its job's
+    // turn before making its request.
+    let reqs = break_into_requests(&text, engine, &voice, style, &options)?;
+    let chunk_lens: Vec<usize> = reqs.iter().map(|(_, r)| r.text.len()).collect();
+    if let Some(job) = job {
+        job.set_plan(&voice.name, reqs.len(), chunk_lens.iter().sum());
+    }
+    let stopped = AtomicBool::new(false);
+    let stopped = &stopped;
+    let tts_tasks = reqs.into_iter().map(|(offset, slice_req)| async move {
+        if stopped.load(Ordering::Relaxed) {
+            return Err((offset, anyhow!("Synthesis stopped")));
+        }
+        if let Some(chunk) = cache.and_then(|c| c.get(&slice_req)) {
+            return Ok((offset, chunk));
+        }
+
+        let chunk = synthesize_with_retries(engine, &slice_req, job, stopped)
+            .await
+            .map_err(|e| (offset, e))?;
+        if let Some(cache) = cache {
+            let _ = cache
+                .put(&slice_req, &chunk)
+                .map_err(|e| tracing::error!("Couldn't save chunk: {e}"));
+        }
+        Ok((offset, chunk))
+    });
+
+    // Do a bounded number of tasks at a time, and collect the results in order. Stop making
+    // requests at the first failure. Everything after it is unusable for now, so this prevents us
+    // from wasting API calls.
+    let mut results = stream::iter(tts_tasks).buffered(MAX_CONCURRENT_REQUESTS);
+    let mut chunks = Vec::new();
+    let mut chunk_marks = Vec::new();
+    let mut unfinished = None;
+    while let Some(res) = results.next().await {
+        match res {
+            Ok((offset, chunk)) => {
+                if let Some(job) = job {
+                    stream_chunk(
+                        job,
+                        &chunk.samples,
+                        &options,
+                        continues_audio || !chunks.is_empty(),
+                    )
+                    .await;
+                    job.chunk_done(chunk_lens[chunks.len()]);
+                }
+                chunks.push(chunk.samples);
+                // Make the marks' offsets relative to the whole text
+                let marks: Vec<(usize, f64)> =
+                    chunk.marks.iter().map(|(o, t)| (offset + o, *t)).collect();
+                chunk_marks.push(marks);
+            }
+            Err((offset, e)) => {
+                unfinished = Some((text[offset..].to_string(), e));
+                stopped.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    }
+    // Let the requests in flight finish, so they're saved to the cache
+    if unfinished.is_some() && cache.is_some() {
+        while results.next().await.is_some() {}
+    }
+
+    // A cancelled job keeps nothing
+    if matches!(job, Some(j) if j.is_cancelled()) {
+        bail!("Conversion was cancelled");
+    }
+
+    // If nothing got synthesized, there's nothing to salvage
+    if chunks.is_empty() {
+        match unfinished {
+            Some((_, e)) => return Err(e),
+            None => bail!("There is no text to synthesize"),
+        }
+    }
+
+    // Clean up the chunks and stitch them together. Chunks are broken at paragraphs where
+    // possible, so the gap between chunks is the paragraph gap.
+    let audio::Stitched {
+        mut samples,
+        chunk_origins,
+    } = audio::stitch(chunks, options.paragraph_gap_ms);
+    let mut lead = 0;
+    if continues_audio {
+        let gap = audio::silence(options.paragraph_gap_ms);
+        lead = gap.len() as i64;
+        samples.splice(0..0, gap);
+    }
+    let mp3 =
+        audio::encode_mp3(&samples, ambient_bed, &options.encoding.unwrap_or_default()).await?;
+
+    // Move the marks of every chunk to where the chunk ended up in the stitched audio
+    let sample_rate = audio::SAMPLE_RATE as f64;
+    let timepoints = chunk_marks
+        .into_iter()
+        .zip(chunk_origins)
+        .filter_map(|(marks, origin)| Some((marks, origin?)))
+        .flat_map(|(marks, origin)| {
+            let chunk_start = (lead + origin) as f64 / sample_rate;
+            marks
+                .into_iter()
+                .map(move |(o, t)| (o, (chunk_start + t).max(0.0)))
+        })
+        .collect();
+
+    Ok(TtsOutput {
+        mp3: mp3.into(),
+        unfinished,
+        timepoints,
+        duration_secs: samples.len() as f64 / sample_rate,
+        loudness: audio::loudness(&samples),
+    })
+}
+
+/// Makes the given TTS request, retrying with exponential backoff if it fails. Every attempt waits
+/// for the job's turn, if there's a job. Gives up without retrying if the job is cancelled, or if
+/// `stopped` is set, i.e., if the synthesis has stopped at an earlier request.
+async fn synthesize_with_retries(
+    engine: &dyn TtsEngine,
+    req: &TtsRequest,
+    job: Option<&dyn SynthesisJob>,
+    stopped: &AtomicBool,
+) -> Result<SynthesizedChunk, AnyError> {
+    // The text breaking should ensure the engine's limit is never exceeded
+    if engine.request_len(req) > engine.max_request_len() {
+        bail!("TTS request is too long");
+    }
+
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let res = {
+            let _slot = match job {
+                Some(job) => Some(job.request_slot().await?),
+                None => None,
+            };
+            // Waiting for the slot can take a while
+            if stopped.load(Ordering::Relaxed) {
+                bail!("Synthesis stopped");
+            }
+            engine.synthesize(req).await
+        };
+
+        match res {
+            Ok(chunk) => return Ok(chunk),
+            Err(e) if attempt < MAX_ATTEMPTS && !stopped.load(Ordering::Relaxed) => {
+                tracing::warn!(
+                    "TTS request failed (attempt {attempt} of {MAX_ATTEMPTS}). \
+                    Retrying in {delay:?}: {e:?}"
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.context(format!("TTS request failed {attempt} times"))),
+        }
+    }
+}
+
+/// Encodes the given chunk on its own and hands it to the job, so it can be listened to before the
+/// whole article is done. If `after_gap` is set, the chunk is preceded by a paragraph gap. This is
+/// best-effort: the final audio is encoded separately, so a failure here is only logged.
+async fn stream_chunk(
+    job: &dyn SynthesisJob,
+    chunk: &[i16],
+    options: &SynthesisOptions,
+    after_gap: bool,
+) {
+    let mut samples = audio::clean_chunk(chunk);
+    if samples.is_empty() {
+        return;
+    }
+    if after_gap {
+        samples.splice(0..0, audio::silence(options.paragraph_gap_ms));
+    }
+
+    match audio::encode_mp3(&samples, None, &options.encoding.unwrap_or_default()).await {
+        Ok(mp3) => job.push_audio(mp3.into()),
+        Err(e) => tracing::error!("Couldn't encode chunk for streaming: {:?}", e),
+    }
+}
+
+/// Breaks the given text into TTS requests whose inputs are at most the engine's request limit.
+/// The SSML markup can count towards that limit, so the text chunks may have to be smaller. Every
+/// request is returned with the byte offset of its text in `text`.
+fn break_into_requests(
+    text: &str,
+    engine: &dyn TtsEngine,
+    voice: &Voice,
+    style: SpeakingStyle,
+    options: &SynthesisOptions,
+) -> Result<Vec<(usize, TtsRequest)>, AnyError> {
+    let max_request_len = engine.max_request_len();
+    let mut max_chunk_size = max_request_len.saturating_sub(ssml::SSML_OVERHEAD);
+
+    loop {
+        let reqs: Vec<(usize, TtsRequest)> = break_text(text, max_chunk_size)?
+            .into_iter()
+            .map(|slice| {
+                // The slices point into the text, so their offsets are just pointer differences
+                let offset = slice.as_ptr() as usize - text.as_ptr() as usize;
+                let req = TtsRequest {
+                    text: slice.to_string(),
+                    voice: voice.clone(),
+                    style,
+                    options: options.clone(),
+                };
+                (offset, req)
+            })
+            .collect();
+
+        // Escaping and per-paragraph markup can blow up the size of a chunk. If that pushed any
+        // request over the limit, try again with smaller chunks.
+        if reqs
+            .iter()
+            .all(|(_, r)| engine.request_len(r) <= max_request_len)
+        {
+            return Ok(reqs);
+        }
+        max_chunk_size /= 2;
+        if max_chunk_size < MIN_CHUNK_SIZE {
+            bail!("Couldn't break text into small enough TTS requests");
+        }
+    }
+}
+
+// Helper function that finds the next index i of the delimiter in the text such that txt[0, i]
+// is below the chunk limit. If no such i is found, then the first occurance of the delimiter
+// is returned (and text[0, i] is too big). If no delimiter occurs at all, txt.len() is
+// returned.
+fn next_break(text: &str, delim: char, max_chunk_size: usize) -> usize {
+    // Keep track of the last break that keeps us under the chunk size limit
+    let mut last_candidate_break = None;
+
+    // Find all the delimiters
+    let delim_indices = text.match_indices(delim).map(|(i, _)| i);
+
+    // Make the last breakpoint the EOF, otherwise we'd just be counting span size between
+    // delims (leaving out the span between the final delim and EOF)
+    let eof = text.len();
+
+    for cur_break in delim_indices.chain(iter::once(eof)) {
+        if cur_break != eof {
+            assert_eq!(text.split_at(cur_break).1.chars().next().unwrap(), delim);
+        }
+        if cur_break <= max_chunk_size {
+            last_candidate_break = Some(cur_break);
+        } else {
+            // The current break puts us over the chunk limit. Split at the last break if
+            // possible.
+            return last_candidate_break.unwrap_or(cur_break);
+        }
+    }
+
+    // If we made it to the end, then the text is now small enough to be a chunk in itself, and
+    // we're done.
+    eof
+}
+
+/// Attempts to break the given text into chunks of size at most `max_chunk_size`, making chunks as
+/// large as possible. The only allowed break point is `delim` characters. This is best-effort,
+/// meaning that there may be chunks returned which exceed `max_chunk_size`.
+pub fn break_greedily_at_delim(mut text: &str, delim: char, max_chunk_size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+
+    while !text.is_empty() {
+        // While the text is not fully chunked, find the next break and break it up.
+        let b = next_break(text, delim, max_chunk_size);
+        let (chunk, mut rest) = text.split_at(b);
+
+        // Strip the leading punctuation off of the remainder of the text
+        if rest.starts_with(delim) {
+            rest = &rest[1..];
+        }
+
+        // Save the chunk and truncate the text
+        chunks.push(chunk);
+        text = rest;
+    }
+
+    chunks
+}
+
+/// How good a place a break point is. Breaks between paragraphs are best, and breaks between words
+/// are the last resort before breaking in the middle of one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum BreakStrength {
+    Word,
+    Clause,
+    Sentence,
+    Paragraph,
+}
+
+/// Punctuation that ends a sentence when it's followed by a space or the end of the text. Besides
+/// the Latin marks, this has the Arabic question mark, the Urdu full stop, and the Hebrew sof pasuq.
+const SENTENCE_ENDS: &[char] = &['.', '!', '?', '…', '؟', '۔', '׃'];
+
+/// Punctuation that ends a sentence wherever it is, since CJK text doesn't put spaces between
+/// sentences
+const UNSPACED_SENTENCE_ENDS: &[char] = &['。', '！', '？', '．'];
+
+/// Punctuation that ends a clause when it's followed by a space, including the Arabic comma and
+/// semicolon
+const CLAUSE_ENDS: &[char] = &[',', ';', ':', '،', '؛'];
+
+/// Punctuation that ends a clause wherever it is, e.g., CJK commas and dashes
+const UNSPACED_CLAUSE_ENDS: &[char] = &['、', '，', '；', '：', '—', '–'];
+
+/// Quotes and brackets that can close a sentence or clause after its punctuation, e.g., `"Stop."`
+const CLOSERS: &[char] = &['"', '\'', ')', ']', '”', '’', '」', '』', '）', '》'];
+
+/// Abbreviations whose period doesn't end a sentence, in lowercase and without the period
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "etc", "e.g", "i.e", "no", "fig",
+    "approx", "inc", "ltd", "co", "gen", "gov", "sen", "rep", "mt", "ft",
+];
+
+/// Returns whether the period ending the given word is part of an abbreviation or initial, e.g.,
+/// "Dr." or "J.", rather than the end of a sentence
+fn is_abbreviation(word: &str) -> bool {
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+    let mut chars = word.chars();
+    let is_initial = matches!((chars.next(), chars.next()), (Some(c), None) if c.is_uppercase());
+    is_initial || ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
+
+/// Returns every place the given text can be broken, as the byte index a chunk can end at, and how
+/// good a place it is. Sentences and clauses keep their punctuation and any closing quotes.
+fn break_points(text: &str) -> Vec<(usize, BreakStrength)> {
+    let mut points = Vec::new();
+    let mut word_start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let after = i + c.len_utf8();
+        if c.is_whitespace() {
+            let strength = if c == '\n' {
+                BreakStrength::Paragraph
+            } else {
+                BreakStrength::Word
+            };
+            points.push((after, strength));
+            word_start = after;
+            continue;
+        }
+
+        let is_sentence_end = SENTENCE_ENDS.contains(&c) || UNSPACED_SENTENCE_ENDS.contains(&c);
+        let is_clause_end = CLAUSE_ENDS.contains(&c) || UNSPACED_CLAUSE_ENDS.contains(&c);
+        if !is_sentence_end && !is_clause_end {
+            continue;
+        }
+
+        // The break goes after any closing quotes or brackets
+        let mut end = after;
+        while let Some(&(j, closer)) = chars.peek().filter(|&&(_, c)| CLOSERS.contains(&c)) {
+            end = j + closer.len_utf8();
+            chars.next();
+        }
+
+        // Western punctuation only ends something if a space comes after it. This keeps numbers
+        // like 3.14 and 1,000 together.
+        let needs_space =
+            !UNSPACED_SENTENCE_ENDS.contains(&c) && !UNSPACED_CLAUSE_ENDS.contains(&c);
+        let spaced = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if needs_space && !spaced {
+            continue;
+        }
+
+        let strength = if !is_sentence_end {
+            BreakStrength::Clause
+        } else if c == '.' && is_abbreviation(&text[word_start..i]) {
+            // "Dr. Jones" is one sentence, but it's still fine to break between the words
+            BreakStrength::Word
+        } else {
+            BreakStrength::Sentence
+        };
+        points.push((end, strength));
+    }
+
+    points
+}
+
+/// Picks where the chunk starting at `start` should end, out of the given break points, so that it
+/// ends at or before `limit`. Paragraph breaks are only taken if they make a chunk at least half
+/// full, since packing more in means fewer requests. Otherwise, the furthest sentence break is
+/// taken, and failing that the furthest clause break, then word break. Returns `None` if there's
+/// no break point in range at all.
+fn choose_break(points: &[(usize, BreakStrength)], start: usize, limit: usize) -> Option<usize> {
+    let first = points.partition_point(|&(p, _)| p <= start);
+    let last = points.partition_point(|&(p, _)| p <= limit);
+    let in_range = &points[first..last];
+    let furthest = |strength| {
+        in_range
+            .iter()
+            .rev()
+            .find(|&&(_, s)| s >= strength)
+            .map(|&(p, _)| p)
+    };
+
+    let min_fill = (limit - start) / 2;
+    if let Some(p) = furthest(BreakStrength::Paragraph).filter(|&p| p - start >= min_fill) {
+        return Some(p);
+    }
+    [
+        BreakStrength::Sentence,
+        BreakStrength::Clause,
+        BreakStrength::Word,
+    ]
+    .into_iter()
+    .find_map(furthest)
+}
+
+/// Returns where the chunk starting at `start` should end if it can't end at a break point: after
+/// the last whole grapheme that fits before `limit`. Splitting a grapheme would separate a letter
+/// from its accents or vowel marks, e.g., in Arabic or Hebrew, or break up an emoji. A single
+/// grapheme too big for the chunk is split between characters, as a last resort.
+fn grapheme_break(text: &str, start: usize, limit: usize) -> usize {
+    let last_grapheme_end = text[start..]
+        .grapheme_indices(true)
+        .map(|(i, g)| start + i + g.len())
+        .take_while(|&end| end <= limit)
+        .last();
+    last_grapheme_end.unwrap_or_else(|| {
+        (start + 1..=limit)
+            .rev()
+            .find(|&i| text.is_char_boundary(i))
+            .unwrap_or(start)
+    })
+}
+
+/// Breaks the given text into chunks of at most `max_chunk_size` bytes, each as large as possible
+/// without splitting a sentence, if it can be helped. Chunks are trimmed of the whitespace around
+/// them, and point into the text. A sentence that's too long is split between clauses, then
+/// between words, and a word that's too long, e.g., in CJK text without punctuation, is split
+/// between graphemes.
+fn break_text(text: &str, max_chunk_size: usize) -> Result<Vec<&str>, AnyError> {
+    let points = break_points(text);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    loop {
+        // Chunks don't start with whitespace
+        let rest = &text[start..];
+        start += rest.len() - rest.trim_start().len();
+        if start == text.len() {
+            break;
+        }
+
+        let limit = start + max_chunk_size;
+        let end = if limit >= text.len() {
+            text.len()
+        } else {
+            // If there's no break point, split between the last graphemes that fit
+            choose_break(&points, start, limit)
+                .unwrap_or_else(|| grapheme_break(text, start, limit))
+        };
+        if end == start {
+            bail!("Couldn't break text into chunks of {max_chunk_size} bytes");
+        }
+
+        let chunk = text[start..end].trim_end();
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        start = end;
+    }
+
+    Ok(chunks)
+}
+
+#[test]
+fn text_breaking() {
+    let text = "\
+        Mr James Duffy lived in Chapelizod because he wished to live as far as possible from the \
+        city of which he was a citizen and because he found all the other suburbs of Dublin mean, \
+        modern and pretentious.
+        He lived in an old sombre house and from his windows he could look into the disused \
+        distillery or upwards along the shallow river on which Dublin is built. The lofty walls \
+        of his uncarpeted room were free from pictures. He had himself bought every article of \
+        furniture in the room: a black iron bedstead, an iron washstand, four cane chairs, a \
+        clothes-rack, a coal-scuttle, a fender and irons and a square table on which lay a double \
+        desk. A bookcase had been made in an alcove by means of shelves of white wood. The bed \
+        was clothed with white bedclothes and a black and scarlet rug covered the foot. A little \
+        hand-mirror hung above the washstand and during the day a white-shaded lamp stood as the \
+        sole ornament of the mantelpiece. The books on the white wooden shelves were arranged \
+        from below upwards according to bulk. A complete Wordsworth stood at one end of the \
+        lowest shelf and a copy of the Maynooth Catechism, sewn into the cloth cover of a \
+        notebook, stood at one end of the top shelf. Writing materials were always on the desk. \
+        In the desk lay a manuscript translation of Hauptmann’s Michael Kramer, the stage \
+        directions of which were written in purple ink, and a little sheaf of papers held \
+        together by a brass pin. In these sheets a sentence was inscribed from time to time and, \
+        in an ironical moment, the headline of an advertisement for Bile Beans had been pasted on \
+        to the first sheet. On lifting the lid of the desk a faint fragrance escaped—the \
+        fragrance of new cedarwood pencils or of a bottle of gum or of an overripe apple which \
+        might have been left there and forgotten.\
+    ";
+    let chunk_size = 220;
+    let chunks = break_text(text, chunk_size).unwrap();
+
+    // Nothing but the whitespace between the chunks is lost, and they're all small enough
+    let without_spaces = |s: &str| s.split_whitespace().collect::<String>();
+    assert_eq!(without_spaces(&chunks.concat()), without_spaces(text));
+    assert!(chunks
+        .iter()
+        .all(|c| !c.is_empty() && c.len() <= chunk_size));
+
+    // Every sentence here fits in a chunk, so none of them are split
+    assert!(chunks.iter().all(|c| c.ends_with('.')));
+
+    // With smaller chunks, the long sentence with the list of furniture is split between clauses.
+    // The first sentence has no clause breaks early enough, so it's split between words.
+    let chunks = break_text(text, 150).unwrap();
+    let furniture = chunks.iter().find(|c| c.starts_with("He had")).unwrap();
+    assert!(furniture.ends_with("a clothes-rack,"));
+    assert!(chunks[0].ends_with("found all the"));
+
+    //
+    // Test for a short text sample
+    //
+
+    let text = "\
+        Mr James Duffy lived in Chapelizod because he wished to live as far as possible from the \
+        city of which he was a citizen and because he found all the other suburbs of Dublin mean, \
+        modern and pretentious.\
+    ";
+    let chunks = break_text(text, chunk_size).unwrap();
+    // Make sure there's just one chunk and it's the whole text
+    assert_eq!(chunks, vec![text]);
+}
+
+#[test]
+fn breaking_awkward_text() {
+    let without_spaces = |s: &str| s.split_whitespace().collect::<String>();
+
+    // Whole paragraphs are packed together, so long as that fills chunks at least halfway
+    let paragraph = "This sentence is forty-two bytes long, ok.";
+    let text = [paragraph; 3].join("\n");
+    let chunks = break_text(&text, 100).unwrap();
+    assert_eq!(chunks, vec![[paragraph; 2].join("\n").as_str(), paragraph]);
+
+    // Abbreviations, initials, and numbers don't end sentences
+    let text = "Dr. Jones met J. Smith at 3.14 p.m. on Main St. today. They talked for hours.";
+    let chunks = break_text(text, 60).unwrap();
+    assert_eq!(
+        chunks,
+        vec![
+            "Dr. Jones met J. Smith at 3.14 p.m. on Main St. today.",
+            "They talked for hours."
+        ]
+    );
+
+    // Closing quotes and brackets stay with their sentence
+    let text = "He said, \"Stop.\" (Then he left.) Nobody followed him out the door.";
+    let chunks = break_text(text, 40).unwrap();
+    assert_eq!(
+        chunks,
+        vec![
+            "He said, \"Stop.\" (Then he left.)",
+            "Nobody followed him out the door."
+        ]
+    );
+
+    // A very long sentence without punctuation is split between words
+    let text = "buffalo ".repeat(100);
+    let chunks = break_text(&text, 50).unwrap();
+    assert!(chunks.iter().all(|c| c.len() <= 50));
+    assert!(chunks
+        .iter()
+        .all(|c| c.split(' ').all(|word| word == "buffalo")));
+    assert_eq!(without_spaces(&chunks.concat()), without_spaces(&text));
+
+    // Text without any spaces or punctuation is split wherever it has to be
+    let text = "a".repeat(1000);
+    let chunks = break_text(&text, 64).unwrap();
+    assert!(chunks.iter().all(|c| c.len() <= 64));
+    assert_eq!(chunks.concat(), text);
+
+    // CJK sentences end without spaces, and are kept whole
+    let sentence = "这是一个很短的句子。";
+    let text = sentence.repeat(20);
+    let chunks = break_text(&text, 100).unwrap();
+    assert!(chunks
+        .iter()
+        .all(|c| c.len() <= 100 && c.ends_with('。') && c.len() % sentence.len() == 0));
+    assert_eq!(chunks.concat(), text);
+
+    // CJK clauses are the next best thing, and characters are never split
+    let text = "一二三四五六七八、".repeat(10);
+    let chunks = break_text(&text, 40).unwrap();
+    assert!(chunks.iter().all(|c| c.len() <= 40 && c.ends_with('、')));
+    let text = "漢字".repeat(100);
+    let chunks = break_text(&text, 10).unwrap();
+    assert!(chunks.iter().all(|c| c.len() <= 10));
+    assert_eq!(chunks.concat(), text);
+
+    // Chunks too small for a single character can't be made
+    assert!(break_text("漢字", 2).is_err());
+}
+
+#[test]
+fn breaking_rtl_text() {
+    // Arabic sentences end with Arabic question marks too, and clauses with Arabic commas
+    let text = "هل قرأت الكتاب؟ نعم، قرأته أمس في المساء. كان ممتعا جدا.";
+    let chunks = break_text(text, 40).unwrap();
+    assert_eq!(chunks[0], "هل قرأت الكتاب؟");
+    assert!(chunks.iter().all(|c| c.len() <= 40));
+    let chunks = break_text(text, 30).unwrap();
+    assert_eq!(chunks[..2], ["هل قرأت الكتاب؟", "نعم،"]);
+
+    // Letters aren't split from their vowel points, even without any spaces to break at
+    let word = "שָׁלוֹם";
+    let text = word.repeat(10);
+    let chunks = break_text(&text, 20).unwrap();
+    assert!(chunks.iter().all(|c| c.len() <= 20));
+    assert_eq!(chunks.concat(), text);
+    let graphemes: Vec<&str> = word.graphemes(true).collect();
+    for chunk in chunks {
+        assert!(graphemes.iter().any(|g| chunk.starts_with(g)));
+    }
+
+    // Neither are the parts of an emoji
+    let family = "👨‍👩‍👧";
+    let text = family.repeat(5);
+    let chunks = break_text(&text, 20).unwrap();
+    assert!(chunks.iter().all(|&c| c == family));
+}
+
+#[test]
+fn request_offsets() {
+    // Make a text that has to be broken into a few requests
+    let paragraph = "All work and no play makes Jack a dull boy. ".repeat(40);
+    let text = vec![paragraph; 5].join("\n");
+
+    let reqs = break_into_requests(
+        &text,
+        &crate::engines::GoogleEngine::new(String::new()),
+        &Voice::default(),
+        SpeakingStyle::Neutral,
+        &SynthesisOptions::default(),
+    )
+    .unwrap();
+    assert!(reqs.len() > 1);
+
+    // Every request's offset points to its text, so the unsynthesized rest of an article can be
+    // recovered from the offset of the first failed request
+    for (offset, req) in reqs {
+        assert!(text[offset..].starts_with(&req.text));
+    }
+}
+
+#[tokio::test]
+async fn retrying_requests() {
+    use std::sync::atomic::AtomicU32;
+
+    /// An engine whose first few requests fail
+    struct FlakyEngine {
+        failures_left: AtomicU32,
+        requests: AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl TtsEngine for FlakyEngine {
+        fn voices(&self) -> Vec<(&str, &str)> {
+            Vec::new()
+        }
+
+        fn max_request_len(&self) -> usize {
+            5000
+        }
+
+        async fn synthesize(&self, _req: &TtsRequest) -> Result<SynthesizedChunk, AnyError> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            let failures_left = self.failures_left.load(Ordering::Relaxed);
+            if failures_left > 0 {
+                self.failures_left
+                    .store(failures_left - 1, Ordering::Relaxed);
+                bail!("503 Service Unavailable");
+            }
+            Ok(SynthesizedChunk {
+                samples: vec![1, 2, 3],
+                marks: vec![(0, 0.0)],
+            })
+        }
+    }
+
+    let flaky = |failures| FlakyEngine {
+        failures_left: AtomicU32::new(failures),
+        requests: AtomicU32::new(0),
+    };
+    let req = TtsRequest {
+        text: "Hello".to_string(),
+        voice: Voice::default(),
+        style: SpeakingStyle::default(),
+        options: SynthesisOptions::default(),
+    };
+    let not_stopped = AtomicBool::new(false);
+
+    // A request that fails a couple of times still goes through
+    let engine = flaky(MAX_ATTEMPTS - 1);
+    let chunk = synthesize_with_retries(&engine, &req, None, &not_stopped).await;
+    assert_eq!(chunk.unwrap().samples, vec![1, 2, 3]);
+    assert_eq!(engine.requests.load(Ordering::Relaxed), MAX_ATTEMPTS);
+
+    // One that keeps failing is given up on
+    let engine = flaky(MAX_ATTEMPTS);
+    assert!(synthesize_with_retries(&engine, &req, None, &not_stopped)
+        .await
+        .is_err());
+    assert_eq!(engine.requests.load(Ordering::Relaxed), MAX_ATTEMPTS);
+
+    // Nothing is retried once the synthesis has stopped
+    let engine = flaky(1);
+    let stopped = AtomicBool::new(true);
+    assert!(synthesize_with_retries(&engine, &req, None, &stopped)
+        .await
+        .is_err());
+    assert_eq!(engine.requests.load(Ordering::Relaxed), 0);
+}
@@ -0,0 +1,49 @@
+//! The voices articles are spoken in
+
+/// The voice used in tests. Otherwise, the default is the engine's first voice.
+pub const DEFAULT_VOICE: &str = "en-US-Wavenet-C";
+
+/// A voice that's ready to be used for synthesis
+#[derive(Clone, Debug)]
+pub struct Voice {
+    /// The name of the voice
+    pub name: String,
+    /// The language the voice speaks, e.g., `en-US`
+    pub language_code: String,
+    /// If this is a custom voice, this is the resource name of its model
+    pub custom_model: Option<String>,
+}
+
+impl Default for Voice {
+    fn default() -> Voice {
+        Voice::builtin(DEFAULT_VOICE)
+    }
+}
+
+impl Voice {
+    /// Makes a builtin voice with the given name
+    pub fn builtin(name: &str) -> Voice {
+        Voice {
+            name: name.to_string(),
+            language_code: voice_language_code(name).to_string(),
+            custom_model: None,
+        }
+    }
+}
+
+/// Returns the language code of the given builtin voice. Voice names are of the form
+/// `LANG-REGION-KIND-VARIANT`, e.g., `en-US-Wavenet-C`, so this returns `LANG-REGION`.
+pub fn voice_language_code(voice: &str) -> &str {
+    // Find the second dash and take everything before it
+    match voice.match_indices('-').nth(1) {
+        Some((i, _)) => &voice[..i],
+        None => voice,
+    }
+}
+
+#[test]
+fn language_codes() {
+    assert_eq!(voice_language_code("en-US-Wavenet-C"), "en-US");
+    assert_eq!(voice_language_code("cmn-CN-Standard-A"), "cmn-CN");
+    assert_eq!(voice_language_code("en"), "en");
+}